@@ -0,0 +1,152 @@
+//! Cartridges, loaded from raw dumps of their ROM.
+//!
+//! The 6507 sees 4KB of cartridge, at 0x1000 to 0x1FFF. A 2KB cartridge is repeated to fill it,
+//! and a 4KB one fills it exactly. An 8KB cartridge holds two banks of 4KB, switched by Atari's F8
+//! scheme: reading or writing 0x1FF8 selects the first, and 0x1FF9 the second. Cartridges are told
+//! apart by their size alone, as dumps carry no header.
+
+use emu_core::Error;
+
+/// Bytes of a bank, all the cartridge space there is.
+pub const BANK_SIZE: usize = 0x1000;
+
+/// The addresses that switch to the first and second bank of an F8 cartridge, within a bank.
+const F8_HOTSPOTS: [u16; 2] = [0xFF8, 0xFF9];
+
+/// A cartridge of 2KB, 4KB or 8KB.
+#[derive(Clone, Debug)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    /// The bank in cartridge space.
+    bank: usize,
+}
+
+impl Default for Cartridge {
+    /// A cartridge of 4KB of blank ROM, as the console starts with before one is inserted.
+    fn default() -> Self {
+        Cartridge {
+            rom: vec![0; BANK_SIZE],
+            bank: 0,
+        }
+    }
+}
+
+impl Cartridge {
+    /// Reads the cartridge dumped in `rom`, of 2KB, 4KB, or 8KB for F8, in its last bank.
+    pub fn new(rom: &[u8]) -> Result<Self, Error> {
+        match rom.len() {
+            0x800 | BANK_SIZE | 0x2000 => {
+                let mut cartridge = Cartridge {
+                    rom: rom.to_vec(),
+                    bank: 0,
+                };
+                cartridge.reset();
+                Ok(cartridge)
+            }
+            size => Err(Error::Rom(format!(
+                "a cartridge of {} bytes is not of 2KB, 4KB or 8KB",
+                size
+            ))),
+        }
+    }
+
+    /// Returns the ROM.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Returns how many banks of 4KB the cartridge has, 1 for one of 2KB.
+    pub fn banks(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE)
+    }
+
+    /// Returns the bank in cartridge space.
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    /// Switches to the bank `bank`, ignoring banks the cartridge does not have.
+    pub fn set_bank(&mut self, bank: usize) {
+        if bank < self.banks() {
+            self.bank = bank;
+        }
+    }
+
+    /// Switches to the last bank, which F8 cartridges start in.
+    pub fn reset(&mut self) {
+        self.bank = self.banks() - 1;
+    }
+
+    /// Reads `address` of cartridge space, after switching banks if it is a hotspot.
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.touch(address);
+        let offset = usize::from(address) % BANK_SIZE % self.rom.len();
+        self.rom[self.bank * BANK_SIZE + offset]
+    }
+
+    /// Writes `address` of cartridge space, which only switches banks if it is a hotspot, as
+    /// there is nothing to write to.
+    pub fn write(&mut self, address: u16) {
+        self.touch(address);
+    }
+
+    /// Switches banks if `address` is a hotspot of the cartridge.
+    fn touch(&mut self, address: u16) {
+        if self.banks() == 2 {
+            let address = address & 0xFFF;
+            if let Some(bank) = F8_HOTSPOTS.iter().position(|&hotspot| hotspot == address) {
+                self.bank = bank;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_2k() {
+        let mut rom = vec![0; 0x800];
+        rom[0x7FC] = 0x12;
+        let mut cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(cartridge.banks(), 1);
+        assert_eq!(cartridge.read(0x17FC), 0x12);
+        assert_eq!(cartridge.read(0x1FFC), 0x12);
+    }
+
+    #[test]
+    fn switches_f8_banks() {
+        let mut rom = vec![0; 0x2000];
+        rom[0x0000] = 0xAA;
+        rom[0x1000] = 0xBB;
+        let mut cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(cartridge.bank(), 1);
+        assert_eq!(cartridge.read(0x1000), 0xBB);
+        cartridge.read(0x1FF8);
+        assert_eq!(cartridge.read(0x1000), 0xAA);
+        cartridge.write(0x1FF9);
+        assert_eq!(cartridge.read(0x1000), 0xBB);
+        // The hotspots are within cartridge space wherever it is mirrored.
+        cartridge.read(0xFFF8);
+        assert_eq!(cartridge.bank(), 0);
+    }
+
+    #[test]
+    fn ignores_hotspots_of_4k() {
+        let mut rom = vec![0; BANK_SIZE];
+        rom[0xFF8] = 0x34;
+        let mut cartridge = Cartridge::new(&rom).unwrap();
+        assert_eq!(cartridge.read(0x1FF8), 0x34);
+        assert_eq!(cartridge.bank(), 0);
+        cartridge.set_bank(1);
+        assert_eq!(cartridge.bank(), 0);
+    }
+
+    #[test]
+    fn rejects_other_sizes() {
+        for size in [0, 0x400, 0x3000, 0x4000] {
+            assert!(matches!(Cartridge::new(&vec![0; size]), Err(Error::Rom(_))));
+        }
+    }
+}
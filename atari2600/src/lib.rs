@@ -0,0 +1,590 @@
+//! The Atari 2600, the Video Computer System: a 6507, which is a 6502 with 13 address lines and
+//! no interrupts, the TIA, which draws the picture and reads the fire buttons, the 6532 RIOT,
+//! with 128 bytes of RAM, the joysticks, the console's switches and a timer, and the cartridge.
+//!
+//! The 6507 sees 8KB, repeated over the 64KB of the 6502's addresses:
+//!
+//! | Address         | Chip                                  |
+//! |-----------------|---------------------------------------|
+//! | 0x0000 - 0x007F | the TIA, its registers repeated       |
+//! | 0x0080 - 0x00FF | RAM, again at 0x0180                  |
+//! | 0x0280 - 0x029F | the ports and timer of the RIOT       |
+//! | 0x1000 - 0x1FFF | the cartridge                         |
+//!
+//! The processor and the TIA run in step: the TIA draws three color clocks for every clock cycle
+//! of the processor, on each of its reads and writes, so that the program changes the picture on
+//! the pixel the beam is at. A frame ends when the program starts vertical sync.
+//!
+//! Cartridges are loaded from raw dumps of 2KB, 4KB or 8KB, the last switching banks as Atari's
+//! F8 cartridges do. The sound of the TIA is not emulated, so the console is silent.
+//!
+//! ```
+//! use atari2600::Atari2600;
+//! use emu_core::Emulator;
+//!
+//! let atari = Atari2600::new();
+//! assert_eq!(atari.video().width, 320);
+//! assert_eq!(atari.video().height, 210);
+//! assert_eq!(atari.buttons()[4], "1P Fire");
+//! ```
+
+pub mod cartridge;
+pub mod riot;
+pub mod tia;
+
+use crate::cartridge::Cartridge;
+use crate::riot::Riot;
+use crate::tia::Tia;
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+use mos6502::bus::Bus;
+use mos6502::Cpu;
+
+/// Clock cycles per second of the processor, from the 3.58MHz color clock divided by 3.
+pub const CLOCK_HZ: u64 = 1_193_182;
+
+/// The time a frame of 262 lines of 76 clock cycles takes, as NTSC games draw them, a little over
+/// 1/60 second.
+pub const FRAME_PERIOD: Duration = Duration::from_nanos(16_688_151);
+
+/// Samples per second of the silence the console makes.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// The joysticks of both players, then the console's Reset and Select switches, which spring back.
+pub const BUTTONS: [&str; 12] = [
+    "1P Up", "1P Down", "1P Left", "1P Right", "1P Fire", "2P Up", "2P Down", "2P Left",
+    "2P Right", "2P Fire", "Reset", "Select",
+];
+
+/// Buttons of a joystick.
+const JOYSTICK: usize = 5;
+
+/// The buttons of the console's switches.
+const RESET: usize = 10;
+const SELECT: usize = 11;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"A26\x01";
+
+/// The position of a difficulty switch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    /// The harder, for experts.
+    A,
+    /// The easier, for beginners.
+    B,
+}
+
+/// The console's switches that stay where they are put.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Switches {
+    /// Whether the TV type switch is on Color rather than B/W.
+    pub color: bool,
+    /// The difficulty switch of the first player.
+    pub left_difficulty: Difficulty,
+    /// The difficulty switch of the second player.
+    pub right_difficulty: Difficulty,
+}
+
+impl Default for Switches {
+    /// Color, and both players' difficulties on B, as games expect.
+    fn default() -> Self {
+        Switches {
+            color: true,
+            left_difficulty: Difficulty::B,
+            right_difficulty: Difficulty::B,
+        }
+    }
+}
+
+/// Everything in the console but the processor.
+#[derive(Clone)]
+struct Board {
+    tia: Tia,
+    riot: Riot,
+    cartridge: Cartridge,
+    buttons: [bool; BUTTONS.len()],
+    switches: Switches,
+    /// The last value on the data bus, which the TIA's registers read back in their low 6 bits.
+    open_bus: u8,
+    /// Clock cycles run since the console was turned on.
+    cycles: u64,
+}
+
+impl Board {
+    /// Runs the TIA and the RIOT for a clock cycle of the processor.
+    fn tick(&mut self) {
+        self.cycles += 1;
+        for _ in 0..3 {
+            self.tia.tick();
+        }
+        self.riot.tick();
+    }
+
+    /// Returns the joysticks as port A of the RIOT reads them: right, left, down and up from bit 7
+    /// for the first, then from bit 3 for the second, each low while pushed.
+    fn joysticks(&self) -> u8 {
+        (0..2).fold(0xFF, |port, player| {
+            let shift = if player == 0 { 4 } else { 0 };
+            (0..4)
+                .filter(|&direction| self.buttons[player * JOYSTICK + direction])
+                .fold(port, |port, direction| port & !(1 << (shift + direction)))
+        })
+    }
+
+    /// Returns the console's switches as port B of the RIOT reads them: Reset in bit 0 and Select
+    /// in bit 1, low while pressed, the TV type in bit 3, and the difficulties in bits 6 and 7,
+    /// high for A.
+    fn console_switches(&self) -> u8 {
+        let switches = self.switches;
+        u8::from(!self.buttons[RESET])
+            | u8::from(!self.buttons[SELECT]) << 1
+            | u8::from(switches.color) << 3
+            | u8::from(switches.left_difficulty == Difficulty::A) << 6
+            | u8::from(switches.right_difficulty == Difficulty::A) << 7
+    }
+
+    /// Reads `address` without running a cycle.
+    fn load(&mut self, address: u16) -> u8 {
+        let address = address & 0x1FFF;
+        let value = if address & 0x1000 != 0 {
+            self.cartridge.read(address)
+        } else if address & 0x80 == 0 {
+            self.tia.read(address as u8) | self.open_bus & 0x3F
+        } else {
+            self.riot.inputs = [self.joysticks(), self.console_switches()];
+            self.riot.read(address)
+        };
+        self.open_bus = value;
+        value
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.load(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.open_bus = value;
+        let address = address & 0x1FFF;
+        if address & 0x1000 != 0 {
+            self.cartridge.write(address);
+        } else if address & 0x80 != 0 {
+            self.riot.write(address, value);
+        } else if address & 0x3F == u16::from(tia::WSYNC) {
+            // The TIA holds the processor until the next line starts.
+            while self.tia.clock() != 0 {
+                self.tick();
+            }
+        } else {
+            self.tia.write(address as u8, value);
+        }
+    }
+}
+
+/// The console, with a cartridge.
+#[derive(Clone)]
+pub struct Atari2600 {
+    cpu: Cpu,
+    board: Board,
+    rom_hash: u64,
+    /// Clock cycles since the last sample of sound, times [`SAMPLE_RATE`].
+    clocks: u64,
+    samples: Vec<i16>,
+}
+
+impl Default for Atari2600 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Atari2600 {
+    /// Creates a console with a blank cartridge, turned on.
+    pub fn new() -> Self {
+        let mut atari = Atari2600 {
+            cpu: Cpu::new(),
+            board: Board {
+                tia: Tia::new(),
+                riot: Riot::new(),
+                cartridge: Cartridge::default(),
+                buttons: [false; BUTTONS.len()],
+                switches: Switches::default(),
+                open_bus: 0,
+                cycles: 0,
+            },
+            rom_hash: emu_state::hash(&[]),
+            clocks: 0,
+            samples: Vec::new(),
+        };
+        atari.reset();
+        atari
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns the TIA.
+    pub fn tia(&self) -> &Tia {
+        &self.board.tia
+    }
+
+    /// Returns the RIOT.
+    pub fn riot(&self) -> &Riot {
+        &self.board.riot
+    }
+
+    /// Returns the cartridge.
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.board.cartridge
+    }
+
+    /// Returns the console's RAM, in the RIOT.
+    pub fn ram(&self) -> &[u8; riot::RAM_SIZE] {
+        self.board.riot.ram()
+    }
+
+    /// Returns the console's RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8; riot::RAM_SIZE] {
+        self.board.riot.ram_mut()
+    }
+
+    /// Returns the console's switches.
+    pub fn switches(&self) -> Switches {
+        self.board.switches
+    }
+
+    /// Returns the console's switches, to flip them.
+    pub fn switches_mut(&mut self) -> &mut Switches {
+        &mut self.board.switches
+    }
+
+    /// Returns the clock cycles run since the console was turned on.
+    pub fn cycles(&self) -> u64 {
+        self.board.cycles
+    }
+
+    /// Adds the silence of `cycles` clock cycles to the samples.
+    fn mix(&mut self, cycles: u64) {
+        self.clocks += cycles * u64::from(SAMPLE_RATE);
+        let samples = self.samples.len() + (self.clocks / CLOCK_HZ) as usize;
+        self.samples.resize(samples, 0);
+        self.clocks %= CLOCK_HZ;
+    }
+
+    /// Returns the error of a jammed processor.
+    fn jammed(&self) -> Error {
+        Error::Stopped(format!(
+            "the processor jammed at {:04X}",
+            self.cpu.program_counter().wrapping_sub(1)
+        ))
+    }
+}
+
+impl Emulator for Atari2600 {
+    fn name(&self) -> &'static str {
+        "Atari 2600"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: tia::WIDTH,
+            height: tia::HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Inserts the cartridge dumped in `rom`, of 2KB, 4KB or 8KB, and turns the console on.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.board.cartridge = Cartridge::new(rom)?;
+        self.rom_hash = emu_state::hash(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Turns the console off and on again, clearing its RAM. The switches and buttons stay as they
+    /// are.
+    fn reset(&mut self) {
+        let board = &mut self.board;
+        let fire = [board.buttons[4], board.buttons[9]];
+        board.tia = Tia::new();
+        for (player, &pressed) in fire.iter().enumerate() {
+            board.tia.set_fire(player, pressed);
+        }
+        board.riot = Riot::new();
+        board.cartridge.reset();
+        board.open_bus = 0;
+        board.cycles = 0;
+        self.cpu = Cpu::new();
+        self.cpu.reset(board);
+        self.clocks = 0;
+        self.samples.clear();
+    }
+
+    fn step_frame(&mut self) -> Result<(), Error> {
+        loop {
+            if self.cpu.jammed() {
+                return Err(self.jammed());
+            }
+            let start = self.board.cycles;
+            self.cpu.step(&mut self.board);
+            self.mix(self.board.cycles - start);
+            if self.board.tia.take_frame() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        self.board.tia.framebuffer()
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if button < BUTTONS.len() {
+            self.board.buttons[button] = pressed;
+            if button % JOYSTICK == 4 && button < RESET {
+                self.board.tia.set_fire(button / JOYSTICK, pressed);
+            }
+        }
+    }
+
+    /// Saves the processor, RAM, the TIA, the RIOT and the cartridge's bank, but not the picture,
+    /// which is drawn again by the next frame.
+    fn save_state(&self) -> Vec<u8> {
+        let board = &self.board;
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(board.riot.ram());
+        state.extend_from_slice(&board.riot.save());
+        state.extend_from_slice(&board.tia.save());
+        state.push(board.cartridge.bank() as u8);
+        state.push(board.open_bus);
+        state.extend_from_slice(&board.cycles.to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let size = MAGIC.len()
+            + 8
+            + mos6502::STATE_SIZE
+            + riot::RAM_SIZE
+            + riot::STATE_SIZE
+            + tia::STATE_SIZE
+            + 1
+            + 1
+            + 8;
+        if state.len() != size || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this console".to_string()));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(mos6502::STATE_SIZE);
+        let (ram, rest) = rest.split_at(riot::RAM_SIZE);
+        let (riot, rest) = rest.split_at(riot::STATE_SIZE);
+        let (tia, rest) = rest.split_at(tia::STATE_SIZE);
+        if usize::from(rest[0]) >= self.board.cartridge.banks() {
+            return Err(Error::State("the state is corrupt".to_string()));
+        }
+        let board = &mut self.board;
+        let mut restored = board.riot.clone();
+        restored.restore(<&[u8; riot::STATE_SIZE]>::try_from(riot).unwrap())?;
+        board.tia.restore(tia)?;
+        restored.ram_mut().copy_from_slice(ram);
+        board.riot = restored;
+        board.cartridge.set_bank(usize::from(rest[0]));
+        board.open_bus = rest[1];
+        board.cycles = u64::from_le_bytes(<[u8; 8]>::try_from(&rest[2..]).unwrap());
+        self.cpu
+            .restore(<&[u8; mos6502::STATE_SIZE]>::try_from(cpu).unwrap());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a 4KB cartridge holding `code` at 0xF000, which it starts at.
+    fn rom(code: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; cartridge::BANK_SIZE];
+        rom[..code.len()].copy_from_slice(code);
+        rom[0xFFC..0xFFE].copy_from_slice(&[0x00, 0xF0]);
+        rom
+    }
+
+    fn machine(code: &[u8]) -> Atari2600 {
+        let mut atari = Atari2600::new();
+        atari.load_rom(&rom(code)).unwrap();
+        atari
+    }
+
+    /// A program that draws frames of 258 lines with a yellow background.
+    #[rustfmt::skip]
+    const FRAMES: &[u8] = &[
+        // LDA #$1E; STA COLUBK
+        0xA9, 0x1E, 0x85, 0x09,
+        // LDA #2; STA VSYNC; STA WSYNC three times; LDA #0; STA VSYNC
+        0xA9, 0x02, 0x85, 0x00, 0x85, 0x02, 0x85, 0x02, 0x85, 0x02, 0xA9, 0x00, 0x85, 0x00,
+        // LDX #255; STA WSYNC; DEX; BNE -5
+        0xA2, 0xFF, 0x85, 0x02, 0xCA, 0xD0, 0xFB,
+        // INC $80; JMP $F004
+        0xE6, 0x80, 0x4C, 0x04, 0xF0,
+    ];
+
+    #[test]
+    fn maps_memory() {
+        let mut atari = machine(&[0x42]);
+        let board = &mut atari.board;
+        board.write(0x0080, 0x12);
+        assert_eq!(board.read(0x01FF), 0);
+        assert_eq!(board.read(0x0180), 0x12);
+        assert_eq!(board.read(0xE080), 0x12);
+        assert_eq!(board.read(0xF000), 0x42);
+        assert_eq!(board.read(0x3000), 0x42);
+        board.write(0x0296, 100);
+        assert_eq!(board.read(0x0284), 99);
+        // The TIA's registers repeat every 64 bytes, and set only their top bits.
+        board.write(0x0049, 0x0E);
+        assert_eq!(board.tia.read(tia::CXPPMM), 0);
+        board.open_bus = 0x35;
+        assert_eq!(board.load(0x000C), 0xB5);
+    }
+
+    #[test]
+    fn halts_until_the_next_line() {
+        let mut atari = machine(&[]);
+        let board = &mut atari.board;
+        board.read(0);
+        let (cycles, clock) = (board.cycles, board.tia.clock());
+        assert_ne!(clock, 0);
+        board.write(u16::from(tia::WSYNC), 0);
+        assert_eq!(board.tia.clock(), 0);
+        assert_eq!(
+            board.cycles - cycles,
+            u64::from(tia::CLOCKS_PER_LINE - clock) / 3
+        );
+    }
+
+    #[test]
+    fn runs_and_draws_frames() {
+        let mut atari = machine(FRAMES);
+        let mut ends = Vec::new();
+        for _ in 0..3 {
+            atari.step_frame().unwrap();
+            ends.push(atari.cycles());
+        }
+        assert_eq!(ends[2] - ends[1], 258 * 76);
+        assert_eq!(atari.ram()[0], 2);
+        assert_eq!(atari.tia().line(), 0);
+        atari.step_frame().unwrap();
+        let yellow = tia::rgba(0x1E);
+        assert!(atari.framebuffer().chunks(4).all(|pixel| pixel == yellow));
+
+        let mut samples = Vec::new();
+        atari.pull_audio(&mut samples);
+        let expected = atari.cycles() * u64::from(SAMPLE_RATE) / CLOCK_HZ;
+        assert!((samples.len() as u64).abs_diff(expected) <= 1);
+        assert!(samples.iter().all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn reads_joysticks_and_switches() {
+        let mut atari = machine(&[]);
+        atari.set_button(0, true);
+        atari.set_button(3, true);
+        atari.set_button(7, true);
+        atari.set_button(9, true);
+        atari.set_button(BUTTONS.len(), true);
+        let board = &mut atari.board;
+        assert_eq!(board.load(0x0280), 0b0110_1011);
+        assert_eq!(board.load(0x000D) & 0x80, 0);
+        assert_eq!(board.load(0x000C) & 0x80, 0x80);
+        assert_eq!(board.load(0x0282), 0x0B);
+
+        atari.set_button(RESET, true);
+        atari.switches_mut().left_difficulty = Difficulty::A;
+        atari.switches_mut().color = false;
+        assert_eq!(atari.board.load(0x0282), 0x42);
+    }
+
+    #[test]
+    fn switches_banks() {
+        let mut rom = vec![0; 0x2000];
+        // The second bank starts, switches to the first, and stores what it reads there.
+        // LDA $1FF8, in both banks.
+        rom[0x1000..0x1003].copy_from_slice(&[0xAD, 0xF8, 0x1F]);
+        rom[0x0003..0x0007].copy_from_slice(&[0x85, 0x80, 0xD0, 0xFE]);
+        rom[0x0000..0x0003].copy_from_slice(&[0xAD, 0xF8, 0x1F]);
+        rom[0x1FF8] = 0x77;
+        rom[0x0FF8] = 0x66;
+        rom[0x1FFC..0x1FFE].copy_from_slice(&[0x00, 0xF0]);
+        let mut atari = Atari2600::new();
+        atari.load_rom(&rom).unwrap();
+        assert_eq!(atari.cartridge().bank(), 1);
+        for _ in 0..4 {
+            atari.cpu.step(&mut atari.board);
+        }
+        assert_eq!(atari.cartridge().bank(), 0);
+        assert_eq!(atari.ram()[0], 0x66);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut atari = machine(FRAMES);
+        atari.step_frame().unwrap();
+        let state = atari.save_state();
+        let mut restored = machine(FRAMES);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        atari.step_frame().unwrap();
+        restored.step_frame().unwrap();
+        assert_eq!(restored.save_state(), atari.save_state());
+        assert_eq!(restored.framebuffer(), atari.framebuffer());
+
+        let mut other = machine(&[0xEA]);
+        assert!(matches!(other.load_state(&state), Err(Error::State(_))));
+        assert!(matches!(
+            restored.load_state(&state[1..]),
+            Err(Error::State(_))
+        ));
+    }
+
+    #[test]
+    fn loads_cartridges_of_2k_4k_and_8k() {
+        let mut atari = Atari2600::new();
+        for size in [0x800, 0x1000, 0x2000] {
+            assert!(atari.load_rom(&vec![0; size]).is_ok());
+        }
+        assert!(matches!(atari.load_rom(&[0; 0x1800]), Err(Error::Rom(_))));
+    }
+
+    #[test]
+    fn stops_when_jammed() {
+        // A jam opcode.
+        let mut atari = machine(&[0x02]);
+        assert!(matches!(atari.step_frame(), Err(Error::Stopped(_))));
+    }
+}
@@ -0,0 +1,260 @@
+//! The 6532 RIOT: 128 bytes of RAM, two 8-bit I/O ports, and an interval timer.
+//!
+//! Port A reads the joysticks, the first in the high nibble, and port B the console's switches,
+//! each bit low while its direction is pushed or its switch pressed. The timer counts down once
+//! every 1, 8, 64 or 1024 clock cycles, chosen by the register it is written through, and once it
+//! passes 0 it sets its flag and counts down every cycle until written again, so that the program
+//! can tell how long ago it ran out.
+//!
+//! | Address | Read              | Write                          |
+//! |---------|-------------------|--------------------------------|
+//! | 0x280   | `SWCHA`, port A   | `SWCHA`                        |
+//! | 0x281   | `SWACNT`          | `SWACNT`, the directions of A  |
+//! | 0x282   | `SWCHB`, port B   | `SWCHB`                        |
+//! | 0x283   | `SWBCNT`          | `SWBCNT`, the directions of B  |
+//! | 0x284   | `INTIM`, timer    |                                |
+//! | 0x285   | `TIMINT`, flags   |                                |
+//! | 0x294   |                   | `TIM1T`, every cycle           |
+//! | 0x295   |                   | `TIM8T`, every 8 cycles        |
+//! | 0x296   |                   | `TIM64T`, every 64 cycles      |
+//! | 0x297   |                   | `T1024T`, every 1024 cycles    |
+//!
+//! RAM is at 0x80 to 0xFF, and again at 0x180, where the processor keeps its stack.
+
+use emu_core::Error;
+
+/// Bytes of RAM.
+pub const RAM_SIZE: usize = 128;
+
+/// Length of the state written by [`Riot::save`], without RAM.
+pub const STATE_SIZE: usize = 10;
+
+/// The clock cycles between counts of the timer, for each register it is written through.
+const INTERVALS: [u16; 4] = [1, 8, 64, 1024];
+
+/// The flag of `TIMINT` set when the timer passes 0.
+const TIMER_FLAG: u8 = 0x80;
+
+/// The RIOT.
+#[derive(Clone, Debug)]
+pub struct Riot {
+    ram: [u8; RAM_SIZE],
+    /// What drives the pins of ports A and B: the joysticks and the console's switches.
+    pub inputs: [u8; 2],
+    /// What the program writes to ports A and B, on the pins it makes outputs.
+    outputs: [u8; 2],
+    /// Which pins of ports A and B are outputs, a bit for each.
+    directions: [u8; 2],
+    timer: u8,
+    /// The clock cycles between counts of the timer.
+    interval: u16,
+    /// Clock cycles until the timer next counts.
+    divider: u16,
+    flags: u8,
+}
+
+impl Default for Riot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Riot {
+    /// Creates the chip as it is turned on, its RAM clear, its ports inputs, and its timer at 0
+    /// counting every 1024 cycles.
+    pub fn new() -> Self {
+        Riot {
+            ram: [0; RAM_SIZE],
+            inputs: [0xFF; 2],
+            outputs: [0; 2],
+            directions: [0; 2],
+            timer: 0,
+            interval: 1024,
+            divider: 0,
+            flags: 0,
+        }
+    }
+
+    /// Returns the RAM.
+    pub fn ram(&self) -> &[u8; RAM_SIZE] {
+        &self.ram
+    }
+
+    /// Returns the RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8; RAM_SIZE] {
+        &mut self.ram
+    }
+
+    /// Returns the timer.
+    pub fn timer(&self) -> u8 {
+        self.timer
+    }
+
+    /// Runs the timer for a clock cycle.
+    pub fn tick(&mut self) {
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        let (timer, passed) = self.timer.overflowing_sub(1);
+        self.timer = timer;
+        if passed {
+            self.flags |= TIMER_FLAG;
+            self.interval = 1;
+        }
+        self.divider = self.interval - 1;
+    }
+
+    /// Reads `address`, with A9 choosing between RAM and the registers.
+    pub fn read(&mut self, address: u16) -> u8 {
+        if address & 0x200 == 0 {
+            return self.ram[usize::from(address) % RAM_SIZE];
+        }
+        if address & 0x04 == 0 {
+            let port = usize::from(address >> 1 & 1);
+            return if address & 1 == 0 {
+                self.outputs[port] & self.directions[port]
+                    | self.inputs[port] & !self.directions[port]
+            } else {
+                self.directions[port]
+            };
+        }
+        if address & 1 == 0 {
+            self.flags &= !TIMER_FLAG;
+            self.timer
+        } else {
+            self.flags
+        }
+    }
+
+    /// Writes `value` to `address`, with A9 choosing between RAM and the registers.
+    pub fn write(&mut self, address: u16, value: u8) {
+        if address & 0x200 == 0 {
+            self.ram[usize::from(address) % RAM_SIZE] = value;
+        } else if address & 0x04 == 0 {
+            let port = usize::from(address >> 1 & 1);
+            if address & 1 == 0 {
+                self.outputs[port] = value;
+            } else {
+                self.directions[port] = value;
+            }
+        } else if address & 0x10 != 0 {
+            self.timer = value;
+            self.interval = INTERVALS[usize::from(address & 3)];
+            // The timer counts on the next cycle, then at its interval.
+            self.divider = 0;
+            self.flags &= !TIMER_FLAG;
+        }
+        // The edge detection of pin 7 of port A, which nothing on the console uses, is not
+        // emulated.
+    }
+
+    /// Returns the state of the chip but its RAM, for [`restore`](Riot::restore).
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..2].copy_from_slice(&self.outputs);
+        state[2..4].copy_from_slice(&self.directions);
+        state[4] = self.timer;
+        state[5..7].copy_from_slice(&self.interval.to_le_bytes());
+        state[7..9].copy_from_slice(&self.divider.to_le_bytes());
+        state[9] = self.flags;
+        state
+    }
+
+    /// Restores a state returned by [`save`](Riot::save).
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) -> Result<(), Error> {
+        let interval = u16::from_le_bytes([state[5], state[6]]);
+        let divider = u16::from_le_bytes([state[7], state[8]]);
+        if !INTERVALS.contains(&interval) || divider >= interval {
+            return Err(Error::State("the state of the RIOT is corrupt".to_string()));
+        }
+        self.outputs.copy_from_slice(&state[..2]);
+        self.directions.copy_from_slice(&state[2..4]);
+        self.timer = state[4];
+        self.interval = interval;
+        self.divider = divider;
+        self.flags = state[9];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `value` to the timer through `address` and runs it for `cycles` cycles.
+    fn run(riot: &mut Riot, address: u16, value: u8, cycles: u32) {
+        riot.write(address, value);
+        for _ in 0..cycles {
+            riot.tick();
+        }
+    }
+
+    #[test]
+    fn maps_ram() {
+        let mut riot = Riot::new();
+        riot.write(0x80, 0x12);
+        riot.write(0x1FF, 0x34);
+        assert_eq!(riot.read(0x180), 0x12);
+        assert_eq!(riot.read(0xFF), 0x34);
+        assert_eq!(riot.ram()[0], 0x12);
+    }
+
+    #[test]
+    fn reads_ports() {
+        let mut riot = Riot::new();
+        riot.inputs = [0xEF, 0x0B];
+        assert_eq!(riot.read(0x280), 0xEF);
+        assert_eq!(riot.read(0x282), 0x0B);
+        // Pins made outputs read what was written to them.
+        riot.write(0x281, 0x0F);
+        riot.write(0x280, 0x05);
+        assert_eq!(riot.read(0x281), 0x0F);
+        assert_eq!(riot.read(0x280), 0xE5);
+    }
+
+    #[test]
+    fn counts_at_intervals() {
+        let mut riot = Riot::new();
+        run(&mut riot, 0x296, 10, 1);
+        assert_eq!(riot.read(0x284), 9);
+        run(&mut riot, 0x296, 10, 64);
+        assert_eq!(riot.read(0x284), 9);
+        run(&mut riot, 0x296, 10, 65);
+        assert_eq!(riot.read(0x284), 8);
+        run(&mut riot, 0x297, 2, 1 + 1024);
+        assert_eq!(riot.read(0x284), 0);
+        run(&mut riot, 0x294, 5, 3);
+        assert_eq!(riot.read(0x284), 2);
+    }
+
+    #[test]
+    fn sets_its_flag_and_counts_every_cycle_once_past_0() {
+        let mut riot = Riot::new();
+        run(&mut riot, 0x295, 1, 1 + 7);
+        assert_eq!(riot.read(0x285), 0);
+        run(&mut riot, 0x295, 1, 1 + 8);
+        assert_eq!(riot.read(0x285), TIMER_FLAG);
+        assert_eq!(riot.timer(), 0xFF);
+        riot.tick();
+        riot.tick();
+        assert_eq!(riot.timer(), 0xFD);
+        // Reading the timer clears the flag.
+        assert_eq!(riot.read(0x284), 0xFD);
+        assert_eq!(riot.read(0x285), 0);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut riot = Riot::new();
+        run(&mut riot, 0x296, 100, 30);
+        riot.write(0x283, 0xFF);
+        let state = riot.save();
+        let mut restored = Riot::new();
+        restored.restore(&state).unwrap();
+        assert_eq!(restored.save(), state);
+        let mut corrupt = state;
+        corrupt[5] = 3;
+        assert!(restored.restore(&corrupt).is_err());
+    }
+}
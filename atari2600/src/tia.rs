@@ -0,0 +1,866 @@
+//! The TIA, the Television Interface Adaptor, which makes the picture a color clock at a time as
+//! the beam crosses the screen.
+//!
+//! The TIA has no frame buffer: the program races the beam, rewriting the registers of the five
+//! movable objects and the playfield for each line. A line is 228 color clocks, 3 to each clock
+//! cycle of the processor, the first 68 of them horizontal blank and the other 160 the pixels of
+//! the line. The program writes `WSYNC` to halt the processor until the next line starts, and
+//! turns `VSYNC` on and off to start a frame, which ends the last.
+//!
+//! Each pixel is the color of the first object drawn there, in the order of their priority:
+//!
+//! * the playfield, 20 bits of `PF0`, `PF1` and `PF2` of 4 pixels each, over the left half of the
+//!   line, and repeated or reflected over the right, in `COLUPF`, or in the colors of the players
+//!   in score mode;
+//! * the players, 8 pixels of `GRP0` or `GRP1`, reflected by `REFP0` or `REFP1`, and stretched or
+//!   repeated by `NUSIZ0` or `NUSIZ1`, in `COLUP0` or `COLUP1`;
+//! * the missiles, of 1 to 8 pixels, repeated as their players, in their players' colors;
+//! * the ball, of 1 to 8 pixels, in `COLUPF`;
+//! * and the background, `COLUBK`.
+//!
+//! The players and their missiles come before the playfield and the ball, unless `CTRLPF` gives
+//! the playfield priority. The objects are placed by writing their `RESxx` registers when the
+//! beam is where they should be, then moved a few pixels left or right by `HMOVE`. Wherever two of
+//! them overlap, their collision latch is set, for the program to read in `CXM0P` to `CXPPMM`
+//! until it clears them all with `CXCLR`.
+//!
+//! The TIA also reads the fire buttons of the joysticks in `INPT4` and `INPT5`. Its two channels
+//! of sound are not emulated.
+
+use emu_core::Error;
+
+/// Width of the picture: the 160 pixels of a line, each drawn twice as wide as it is tall, as on
+/// a television.
+pub const WIDTH: usize = 320;
+
+/// Lines of the picture: the 192 lines most games draw, with 9 more above and below for those that
+/// draw more.
+pub const HEIGHT: usize = 210;
+
+/// The line after the start of `VSYNC` at the top of the picture, past 3 lines of vertical sync
+/// and 37 of vertical blank less the 9 lines of margin.
+pub const FIRST_LINE: u16 = 31;
+
+/// Color clocks in a line.
+pub const CLOCKS_PER_LINE: u8 = 228;
+
+/// Color clocks of horizontal blank at the start of a line.
+pub const HBLANK: u8 = 68;
+
+/// Lines after which a frame ends without `VSYNC`, for programs that never start one: more than
+/// the 312 of a PAL frame.
+pub const MAX_LINES: u16 = 320;
+
+/// Length of the state written by [`Tia::save`].
+pub const STATE_SIZE: usize = REGISTERS + OBJECTS + 2 + 1 + 2 + 1 + 2 + 1 + 2;
+
+/// The write registers, at 0x00 to 0x2C.
+pub const VSYNC: u8 = 0x00;
+pub const VBLANK: u8 = 0x01;
+pub const WSYNC: u8 = 0x02;
+pub const RSYNC: u8 = 0x03;
+pub const NUSIZ0: u8 = 0x04;
+pub const NUSIZ1: u8 = 0x05;
+pub const COLUP0: u8 = 0x06;
+pub const COLUP1: u8 = 0x07;
+pub const COLUPF: u8 = 0x08;
+pub const COLUBK: u8 = 0x09;
+pub const CTRLPF: u8 = 0x0A;
+pub const REFP0: u8 = 0x0B;
+pub const REFP1: u8 = 0x0C;
+pub const PF0: u8 = 0x0D;
+pub const PF1: u8 = 0x0E;
+pub const PF2: u8 = 0x0F;
+pub const RESP0: u8 = 0x10;
+pub const RESP1: u8 = 0x11;
+pub const RESM0: u8 = 0x12;
+pub const RESM1: u8 = 0x13;
+pub const RESBL: u8 = 0x14;
+pub const GRP0: u8 = 0x1B;
+pub const GRP1: u8 = 0x1C;
+pub const ENAM0: u8 = 0x1D;
+pub const ENAM1: u8 = 0x1E;
+pub const ENABL: u8 = 0x1F;
+pub const HMP0: u8 = 0x20;
+pub const HMP1: u8 = 0x21;
+pub const HMM0: u8 = 0x22;
+pub const HMM1: u8 = 0x23;
+pub const HMBL: u8 = 0x24;
+pub const VDELP0: u8 = 0x25;
+pub const VDELP1: u8 = 0x26;
+pub const VDELBL: u8 = 0x27;
+pub const RESMP0: u8 = 0x28;
+pub const RESMP1: u8 = 0x29;
+pub const HMOVE: u8 = 0x2A;
+pub const HMCLR: u8 = 0x2B;
+pub const CXCLR: u8 = 0x2C;
+
+/// The read registers, at 0x00 to 0x0D: the collision latches, then the inputs.
+pub const CXM0P: u8 = 0x00;
+pub const CXM1P: u8 = 0x01;
+pub const CXP0FB: u8 = 0x02;
+pub const CXP1FB: u8 = 0x03;
+pub const CXM0FB: u8 = 0x04;
+pub const CXM1FB: u8 = 0x05;
+pub const CXBLPF: u8 = 0x06;
+pub const CXPPMM: u8 = 0x07;
+pub const INPT4: u8 = 0x0C;
+pub const INPT5: u8 = 0x0D;
+
+/// Write registers.
+const REGISTERS: usize = 0x2D;
+
+/// The movable objects, in the order of their `RESxx` and `HMxx` registers.
+const OBJECTS: usize = 5;
+
+/// A bit for each object drawn at a pixel: the five movable ones, then the playfield.
+const P0: u8 = 1 << 0;
+const P1: u8 = 1 << 1;
+const M0: u8 = 1 << 2;
+const M1: u8 = 1 << 3;
+const BL: u8 = 1 << 4;
+const PF: u8 = 1 << 5;
+
+/// The objects whose overlap sets each collision latch: bit 6 then bit 7 of `CXM0P`, and so on to
+/// bit 7 of `CXPPMM`. Bit 6 of `CXBLPF` latches nothing.
+const COLLISIONS: [u8; 16] = [
+    M0 | P0,
+    M0 | P1,
+    M1 | P1,
+    M1 | P0,
+    P0 | BL,
+    P0 | PF,
+    P1 | BL,
+    P1 | PF,
+    M0 | BL,
+    M0 | PF,
+    M1 | BL,
+    M1 | PF,
+    0,
+    BL | PF,
+    M0 | M1,
+    P0 | P1,
+];
+
+/// The pixels after its position where each copy of a player and its missile starts, for each
+/// value of the low 3 bits of `NUSIZ0` or `NUSIZ1`.
+const COPIES: [&[u8]; 8] = [
+    &[0],
+    &[0, 16],
+    &[0, 32],
+    &[0, 16, 32],
+    &[0, 64],
+    &[0],
+    &[0, 32, 64],
+    &[0],
+];
+
+/// The NTSC palette, indexed by the top 7 bits of a color register: 16 hues of 8 luminances.
+#[rustfmt::skip]
+const PALETTE: [u32; 128] = [
+    0x000000, 0x4A4A4A, 0x6F6F6F, 0x8E8E8E, 0xAAAAAA, 0xC0C0C0, 0xD6D6D6, 0xECECEC,
+    0x484800, 0x69690F, 0x86861D, 0xA2A22A, 0xBBBB35, 0xD2D240, 0xE8E84A, 0xFCFC54,
+    0x7C2C00, 0x904811, 0xA26221, 0xB47A30, 0xC3903D, 0xD2A44A, 0xDFB755, 0xECC860,
+    0x901C00, 0xA33915, 0xB55328, 0xC66C3A, 0xD5824A, 0xE39759, 0xF0AA67, 0xFCBC74,
+    0x940000, 0xA71A1A, 0xB83232, 0xC84848, 0xD65C5C, 0xE46F6F, 0xF08080, 0xFC9090,
+    0x840064, 0x97197A, 0xA8308F, 0xB846A2, 0xC659B3, 0xD46CC3, 0xE07CD2, 0xEC8CE0,
+    0x500084, 0x68199A, 0x7D30AD, 0x9246C0, 0xA459D0, 0xB56CE0, 0xC57CEE, 0xD48CFC,
+    0x140090, 0x331AA3, 0x4E32B5, 0x6848C6, 0x7F5CD5, 0x956FE3, 0xA980F0, 0xBC90FC,
+    0x000094, 0x181AA7, 0x2D32B8, 0x4248C8, 0x545CD6, 0x656FE4, 0x7580F0, 0x8490FC,
+    0x001C88, 0x183B9D, 0x2D57B0, 0x4272C2, 0x548AD2, 0x65A0E1, 0x75B5EF, 0x84C8FC,
+    0x003064, 0x185080, 0x2D6D98, 0x4288B0, 0x54A0C5, 0x65B7D9, 0x75CCEB, 0x84E0FC,
+    0x004030, 0x18624E, 0x2D8169, 0x429E82, 0x54B899, 0x65D1AE, 0x75E7C2, 0x84FCD4,
+    0x004400, 0x1A661A, 0x328432, 0x48A048, 0x5CBA5C, 0x6FD26F, 0x80E880, 0x90FC90,
+    0x143C00, 0x355F18, 0x527E2D, 0x6E9C42, 0x87B754, 0x9ED065, 0xB4E775, 0xC8FC84,
+    0x303800, 0x505916, 0x6D762B, 0x88923E, 0xA0AB4F, 0xB7C25F, 0xCCD86E, 0xE0EC7C,
+    0x482C00, 0x694D14, 0x866A26, 0xA28638, 0xBB9F47, 0xD2B656, 0xE8CC63, 0xFCE070,
+];
+
+/// Returns the red, green, blue and alpha of the color register value `color`.
+pub fn rgba(color: u8) -> [u8; 4] {
+    let [_, red, green, blue] = PALETTE[usize::from(color >> 1)].to_be_bytes();
+    [red, green, blue, 0xFF]
+}
+
+/// The TIA.
+#[derive(Clone)]
+pub struct Tia {
+    registers: [u8; REGISTERS],
+    /// The pixel of the line where each movable object starts.
+    positions: [u8; OBJECTS],
+    /// The copies of `GRP0` and `GRP1` the players draw when vertically delayed, taken when the
+    /// other player's register is written.
+    delayed_graphics: [u8; 2],
+    /// The copy of `ENABL` the ball draws when vertically delayed, taken when `GRP1` is written.
+    delayed_ball: bool,
+    /// The collision latches, two bits for each read register from `CXM0P`: bit 6, then bit 7.
+    collisions: u16,
+    /// The color clock of the line the beam is at.
+    clock: u8,
+    /// The line since the start of `VSYNC`.
+    line: u16,
+    /// Whether the first 8 pixels of the line are blank, after `HMOVE` in horizontal blank.
+    hmove_blank: bool,
+    /// Whether each fire button is held down.
+    fire: [bool; 2],
+    /// Whether each fire button was pressed while `VBLANK` latches them.
+    latched: [bool; 2],
+    /// Whether a frame has ended since [`take_frame`](Tia::take_frame) was last called.
+    frame: bool,
+    /// The collision latches set by each combination of objects.
+    collision_table: [u16; 64],
+    framebuffer: Vec<u8>,
+}
+
+impl Default for Tia {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tia {
+    /// Creates the TIA as it is turned on, with every register clear and the picture black.
+    pub fn new() -> Self {
+        let mut collision_table = [0; 64];
+        for (objects, latches) in collision_table.iter_mut().enumerate() {
+            for (bit, &pair) in COLLISIONS.iter().enumerate() {
+                if pair != 0 && objects as u8 & pair == pair {
+                    *latches |= 1 << bit;
+                }
+            }
+        }
+        let mut framebuffer = vec![0; WIDTH * HEIGHT * 4];
+        for pixel in framebuffer.chunks_mut(4) {
+            pixel[3] = 0xFF;
+        }
+        Tia {
+            registers: [0; REGISTERS],
+            positions: [0; OBJECTS],
+            delayed_graphics: [0; 2],
+            delayed_ball: false,
+            collisions: 0,
+            clock: 0,
+            line: 0,
+            hmove_blank: false,
+            fire: [false; 2],
+            latched: [false; 2],
+            frame: false,
+            collision_table,
+            framebuffer,
+        }
+    }
+
+    /// Returns the picture, [`WIDTH`] by [`HEIGHT`] pixels of red, green, blue and alpha.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Returns whether a frame has ended since the last call.
+    pub fn take_frame(&mut self) -> bool {
+        core::mem::replace(&mut self.frame, false)
+    }
+
+    /// Returns the color clock of the line the beam is at, from 0 to 227.
+    pub fn clock(&self) -> u8 {
+        self.clock
+    }
+
+    /// Returns the line the beam is at, counted from the start of `VSYNC`.
+    pub fn line(&self) -> u16 {
+        self.line
+    }
+
+    /// Returns the pixel of the line where the movable object `object` starts: the players, the
+    /// missiles, then the ball.
+    pub fn position(&self, object: usize) -> u8 {
+        self.positions[object]
+    }
+
+    /// Presses or releases the fire button of the joystick `player`.
+    pub fn set_fire(&mut self, player: usize, pressed: bool) {
+        self.fire[player] = pressed;
+        if pressed && self.registers[usize::from(VBLANK)] & 0x40 != 0 {
+            self.latched[player] = true;
+        }
+    }
+
+    /// Reads the register `register`, which sets only bits 7 and 6.
+    pub fn read(&self, register: u8) -> u8 {
+        match register & 0x0F {
+            register @ CXM0P..=CXPPMM => ((self.collisions >> (2 * register)) as u8 & 3) << 6,
+            register @ INPT4..=INPT5 => {
+                let player = usize::from(register - INPT4);
+                if self.fire[player] || self.latched[player] {
+                    0
+                } else {
+                    0x80
+                }
+            }
+            // The paddles, which are not emulated, never charge their capacitors.
+            _ => 0,
+        }
+    }
+
+    /// Writes `value` to the register `register`. `WSYNC` is left to the caller, which halts the
+    /// processor until the next line.
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = register & 0x3F;
+        match register {
+            VSYNC if value & 0x02 != 0 && self.registers[usize::from(VSYNC)] & 0x02 == 0 => {
+                self.line = 0;
+                self.frame = true;
+            }
+            VBLANK if value & 0x40 == 0 => self.latched = [false; 2],
+            VBLANK => {
+                for player in 0..2 {
+                    self.latched[player] |= self.fire[player];
+                }
+            }
+            RESP0..=RESBL => {
+                let object = usize::from(register - RESP0);
+                // Players start 5 pixels after the beam, and the others 4.
+                let delay = if object < 2 { 5 } else { 4 };
+                self.positions[object] = if self.clock < HBLANK {
+                    delay - 2
+                } else {
+                    (self.clock - HBLANK + delay) % 160
+                };
+            }
+            GRP0 => self.delayed_graphics[1] = self.registers[usize::from(GRP1)],
+            GRP1 => {
+                self.delayed_graphics[0] = self.registers[usize::from(GRP0)];
+                self.delayed_ball = self.registers[usize::from(ENABL)] & 0x02 != 0;
+            }
+            RESMP0 | RESMP1 => {
+                let player = usize::from(register - RESMP0);
+                if value & 0x02 == 0 && self.registers[usize::from(register)] & 0x02 != 0 {
+                    // Released from the middle of its player.
+                    let offset = match self.registers[usize::from(NUSIZ0) + player] & 7 {
+                        5 => 6,
+                        7 => 10,
+                        _ => 3,
+                    };
+                    self.positions[2 + player] = (self.positions[player] + offset) % 160;
+                }
+            }
+            HMOVE => {
+                for object in 0..OBJECTS {
+                    // Positive motions move left.
+                    let motion = self.registers[usize::from(HMP0) + object] as i8 >> 4;
+                    let position = i16::from(self.positions[object]) - i16::from(motion);
+                    self.positions[object] = position.rem_euclid(160) as u8;
+                }
+                if self.clock < HBLANK {
+                    self.hmove_blank = true;
+                }
+            }
+            HMCLR => {
+                for register in HMP0..=HMBL {
+                    self.registers[usize::from(register)] = 0;
+                }
+            }
+            CXCLR => self.collisions = 0,
+            _ => {}
+        }
+        if usize::from(register) < REGISTERS {
+            self.registers[usize::from(register)] = value;
+        }
+    }
+
+    /// Runs the TIA for a color clock, drawing a pixel if the beam is past horizontal blank.
+    pub fn tick(&mut self) {
+        if self.clock >= HBLANK {
+            self.draw(self.clock - HBLANK);
+        }
+        self.clock += 1;
+        if self.clock == CLOCKS_PER_LINE {
+            self.clock = 0;
+            self.line += 1;
+            self.hmove_blank = false;
+            if self.line >= MAX_LINES {
+                self.line = 0;
+                self.frame = true;
+            }
+        }
+    }
+
+    /// Draws the pixel `x` of the line, and latches the collisions there.
+    fn draw(&mut self, x: u8) {
+        let color = if self.hmove_blank && x < 8 {
+            // The objects do not move through the extended horizontal blank.
+            None
+        } else {
+            let objects = self.objects(x);
+            self.collisions |= self.collision_table[usize::from(objects)];
+            if self.registers[usize::from(VBLANK)] & 0x02 != 0 {
+                None
+            } else {
+                Some(self.color(objects, x))
+            }
+        };
+        let line = usize::from(self.line.wrapping_sub(FIRST_LINE));
+        if line < HEIGHT {
+            let rgba = color.map_or([0, 0, 0, 0xFF], rgba);
+            let offset = (line * WIDTH + 2 * usize::from(x)) * 4;
+            self.framebuffer[offset..][..4].copy_from_slice(&rgba);
+            self.framebuffer[offset + 4..][..4].copy_from_slice(&rgba);
+        }
+    }
+
+    /// Returns the objects drawn at the pixel `x` of the line, a bit for each.
+    fn objects(&self, x: u8) -> u8 {
+        let mut objects = 0;
+        for player in 0..2 {
+            if self.player(player, x) {
+                objects |= P0 << player;
+            }
+            if self.missile(player, x) {
+                objects |= M0 << player;
+            }
+        }
+        if self.ball(x) {
+            objects |= BL;
+        }
+        if self.playfield(x) {
+            objects |= PF;
+        }
+        objects
+    }
+
+    /// Returns the color register value of the pixel `x` of the line, with `objects` drawn there.
+    fn color(&self, objects: u8, x: u8) -> u8 {
+        let register = |register: u8| self.registers[usize::from(register)];
+        let ctrlpf = register(CTRLPF);
+        let playfield = if ctrlpf & 0x02 != 0 {
+            // Score mode, in the color of the player on each half.
+            if x < 80 {
+                COLUP0
+            } else {
+                COLUP1
+            }
+        } else {
+            COLUPF
+        };
+        let layers = [
+            (P0 | M0, COLUP0),
+            (P1 | M1, COLUP1),
+            (PF, playfield),
+            (BL, COLUPF),
+        ];
+        let order: [usize; 4] = if ctrlpf & 0x04 != 0 {
+            [2, 3, 0, 1]
+        } else {
+            [0, 1, 2, 3]
+        };
+        order
+            .iter()
+            .map(|&layer| layers[layer])
+            .find(|&(mask, _)| objects & mask != 0)
+            .map_or(register(COLUBK), |(_, color)| register(color))
+    }
+
+    /// Returns how many pixels after the start of the movable object `object` the pixel `x` is.
+    fn distance(&self, object: usize, x: u8) -> u8 {
+        ((u16::from(x) + 160 - u16::from(self.positions[object])) % 160) as u8
+    }
+
+    /// Returns whether the player `player` draws at the pixel `x` of the line.
+    fn player(&self, player: usize, x: u8) -> bool {
+        let graphics = if self.registers[usize::from(VDELP0) + player] & 0x01 != 0 {
+            self.delayed_graphics[player]
+        } else {
+            self.registers[usize::from(GRP0) + player]
+        };
+        if graphics == 0 {
+            return false;
+        }
+        let size = self.registers[usize::from(NUSIZ0) + player] & 7;
+        // Stretched players start a pixel later.
+        let (scale, delay) = match size {
+            5 => (2, 1),
+            7 => (4, 1),
+            _ => (1, 0),
+        };
+        let distance = self.distance(player, x);
+        COPIES[usize::from(size)].iter().any(|&copy| {
+            let pixel = distance.wrapping_sub(copy).wrapping_sub(delay);
+            if pixel >= 8 * scale {
+                return false;
+            }
+            let bit = pixel / scale;
+            let reflected = self.registers[usize::from(REFP0) + player] & 0x08 != 0;
+            let bit = if reflected { bit } else { 7 - bit };
+            graphics >> bit & 1 != 0
+        })
+    }
+
+    /// Returns whether the missile of the player `player` draws at the pixel `x` of the line.
+    fn missile(&self, player: usize, x: u8) -> bool {
+        if self.registers[usize::from(ENAM0) + player] & 0x02 == 0
+            || self.registers[usize::from(RESMP0) + player] & 0x02 != 0
+        {
+            return false;
+        }
+        let nusiz = self.registers[usize::from(NUSIZ0) + player];
+        let width = 1 << (nusiz >> 4 & 3);
+        let distance = self.distance(2 + player, x);
+        COPIES[usize::from(nusiz & 7)]
+            .iter()
+            .any(|&copy| distance.wrapping_sub(copy) < width)
+    }
+
+    /// Returns whether the ball draws at the pixel `x` of the line.
+    fn ball(&self, x: u8) -> bool {
+        let enabled = if self.registers[usize::from(VDELBL)] & 0x01 != 0 {
+            self.delayed_ball
+        } else {
+            self.registers[usize::from(ENABL)] & 0x02 != 0
+        };
+        let width = 1 << (self.registers[usize::from(CTRLPF)] >> 4 & 3);
+        enabled && self.distance(4, x) < width
+    }
+
+    /// Returns whether the playfield draws at the pixel `x` of the line.
+    fn playfield(&self, x: u8) -> bool {
+        let mut bit = x % 80 / 4;
+        if x >= 80 && self.registers[usize::from(CTRLPF)] & 0x01 != 0 {
+            bit = 19 - bit;
+        }
+        let register = |register: u8| self.registers[usize::from(register)];
+        let value = match bit {
+            0..=3 => register(PF0) >> (4 + bit),
+            4..=11 => register(PF1) >> (11 - bit),
+            _ => register(PF2) >> (bit - 12),
+        };
+        value & 1 != 0
+    }
+
+    /// Returns the state of the TIA but its picture, for [`restore`](Tia::restore).
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..REGISTERS].copy_from_slice(&self.registers);
+        let rest = &mut state[REGISTERS..];
+        rest[..OBJECTS].copy_from_slice(&self.positions);
+        let rest = &mut rest[OBJECTS..];
+        rest[..2].copy_from_slice(&self.delayed_graphics);
+        rest[2] = u8::from(self.delayed_ball);
+        rest[3..5].copy_from_slice(&self.collisions.to_le_bytes());
+        rest[5] = self.clock;
+        rest[6..8].copy_from_slice(&self.line.to_le_bytes());
+        rest[8] = u8::from(self.hmove_blank);
+        rest[9] = u8::from(self.latched[0]);
+        rest[10] = u8::from(self.latched[1]);
+        state
+    }
+
+    /// Restores a state returned by [`save`](Tia::save).
+    pub fn restore(&mut self, state: &[u8]) -> Result<(), Error> {
+        let corrupt = || Error::State("the state of the TIA is corrupt".to_string());
+        if state.len() != STATE_SIZE {
+            return Err(corrupt());
+        }
+        let (registers, rest) = state.split_at(REGISTERS);
+        let (positions, rest) = rest.split_at(OBJECTS);
+        let line = u16::from_le_bytes([rest[6], rest[7]]);
+        if positions.iter().any(|&position| position >= 160)
+            || rest[5] >= CLOCKS_PER_LINE
+            || line >= MAX_LINES
+        {
+            return Err(corrupt());
+        }
+        self.registers.copy_from_slice(registers);
+        self.positions.copy_from_slice(positions);
+        self.delayed_graphics.copy_from_slice(&rest[..2]);
+        self.delayed_ball = rest[2] != 0;
+        self.collisions = u16::from_le_bytes([rest[3], rest[4]]);
+        self.clock = rest[5];
+        self.line = line;
+        self.hmove_blank = rest[8] != 0;
+        self.latched = [rest[9] != 0, rest[10] != 0];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the TIA to the color clock `clock` of the line `line`.
+    fn run_to(tia: &mut Tia, line: u16, clock: u8) {
+        while tia.line != line || tia.clock != clock {
+            tia.tick();
+        }
+    }
+
+    /// Draws the line [`FIRST_LINE`] and returns which of its 160 pixels are not the background.
+    fn line(tia: &mut Tia) -> Vec<bool> {
+        tia.write(COLUBK, 0x00);
+        run_to(tia, FIRST_LINE, 0);
+        run_to(tia, FIRST_LINE + 1, 0);
+        tia.framebuffer()[..WIDTH * 4]
+            .chunks(8)
+            .map(|pixel| pixel[..3] != [0, 0, 0])
+            .collect()
+    }
+
+    /// Returns the pixels set in `pixels`.
+    fn set(pixels: &[bool]) -> Vec<usize> {
+        (0..pixels.len()).filter(|&x| pixels[x]).collect()
+    }
+
+    /// Places the movable object `object` at the pixel `x`.
+    fn place(tia: &mut Tia, object: usize, x: u8) {
+        tia.positions[object] = x;
+    }
+
+    #[test]
+    fn draws_the_playfield() {
+        let mut tia = Tia::new();
+        tia.write(COLUPF, 0x0E);
+        tia.write(PF0, 0x10);
+        tia.write(PF1, 0x01);
+        tia.write(PF2, 0x80);
+        let pixels = set(&line(&mut tia));
+        let expected: Vec<usize> = (0..4)
+            .chain(44..48)
+            .chain(76..84)
+            .chain(124..128)
+            .chain(156..160)
+            .collect();
+        assert_eq!(pixels, expected);
+
+        // Reflected, the right half is the left backwards.
+        tia.write(CTRLPF, 0x01);
+        tia.line = 0;
+        let pixels = set(&line(&mut tia));
+        let expected: Vec<usize> = (0..4)
+            .chain(44..48)
+            .chain(76..84)
+            .chain(112..116)
+            .chain(156..160)
+            .collect();
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn colors_pixels() {
+        let mut tia = Tia::new();
+        tia.write(COLUBK, 0x80);
+        tia.write(COLUPF, 0x1E);
+        tia.write(COLUP0, 0x44);
+        tia.write(COLUP1, 0xC6);
+        assert_eq!(tia.color(0, 0), 0x80);
+        assert_eq!(tia.color(PF | P0, 10), 0x44);
+        assert_eq!(tia.color(BL | M1, 10), 0xC6);
+        assert_eq!(tia.color(P1 | P0, 10), 0x44);
+        tia.write(CTRLPF, 0x04);
+        assert_eq!(tia.color(PF | P0, 10), 0x1E);
+        // Score mode.
+        tia.write(CTRLPF, 0x02);
+        assert_eq!(tia.color(PF, 10), 0x44);
+        assert_eq!(tia.color(PF, 90), 0xC6);
+        assert_eq!(tia.color(BL, 90), 0x1E);
+        assert_eq!(rgba(0x1E), [0xFC, 0xFC, 0x54, 0xFF]);
+        assert_eq!(rgba(0x1F), rgba(0x1E));
+    }
+
+    #[test]
+    fn draws_players() {
+        let mut tia = Tia::new();
+        tia.write(COLUP0, 0x0E);
+        tia.write(GRP0, 0b1000_0011);
+        place(&mut tia, 0, 10);
+        assert_eq!(set(&line(&mut tia)), [10, 16, 17]);
+        tia.write(REFP0, 0x08);
+        tia.line = 0;
+        assert_eq!(set(&line(&mut tia)), [10, 11, 17]);
+        // Three copies 16 pixels apart, wrapping around the line.
+        tia.write(REFP0, 0);
+        tia.write(NUSIZ0, 3);
+        place(&mut tia, 0, 140);
+        tia.line = 0;
+        assert_eq!(set(&line(&mut tia)), [2, 3, 12, 18, 19, 140, 146, 147, 156]);
+        // Twice as wide, a pixel later.
+        tia.write(NUSIZ0, 5);
+        place(&mut tia, 0, 10);
+        tia.line = 0;
+        assert_eq!(set(&line(&mut tia)), [11, 12, 23, 24, 25, 26]);
+    }
+
+    #[test]
+    fn draws_missiles_and_the_ball() {
+        let mut tia = Tia::new();
+        tia.write(COLUP1, 0x0E);
+        tia.write(COLUPF, 0x0E);
+        tia.write(ENAM1, 0x02);
+        tia.write(NUSIZ1, 0x21);
+        place(&mut tia, 3, 20);
+        tia.write(ENABL, 0x02);
+        tia.write(CTRLPF, 0x10);
+        place(&mut tia, 4, 100);
+        assert_eq!(
+            set(&line(&mut tia)),
+            [20, 21, 22, 23, 36, 37, 38, 39, 100, 101]
+        );
+        // A missile locked to its player is hidden, and released at its middle.
+        place(&mut tia, 1, 50);
+        tia.write(RESMP1, 0x02);
+        tia.write(ENABL, 0);
+        tia.line = 0;
+        assert!(set(&line(&mut tia)).is_empty());
+        tia.write(RESMP1, 0);
+        assert_eq!(tia.position(3), 53);
+    }
+
+    #[test]
+    fn places_and_moves_objects() {
+        let mut tia = Tia::new();
+        run_to(&mut tia, 0, 30);
+        tia.write(RESP0, 0);
+        tia.write(RESBL, 0);
+        assert_eq!((tia.position(0), tia.position(4)), (3, 2));
+        run_to(&mut tia, 0, HBLANK + 100);
+        tia.write(RESP1, 0);
+        tia.write(RESM0, 0);
+        assert_eq!((tia.position(1), tia.position(2)), (105, 104));
+
+        tia.write(HMP0, 0x30);
+        tia.write(HMP1, 0x80);
+        tia.write(HMBL, 0xF0);
+        tia.write(HMOVE, 0);
+        assert_eq!(tia.position(0), 0);
+        assert_eq!(tia.position(1), 113);
+        assert_eq!(tia.position(4), 3);
+        assert!(!tia.hmove_blank);
+        tia.write(HMCLR, 0);
+        run_to(&mut tia, 1, 8);
+        tia.write(HMOVE, 0);
+        assert_eq!(tia.position(0), 0);
+        assert!(tia.hmove_blank);
+    }
+
+    #[test]
+    fn blanks_after_hmove() {
+        let mut tia = Tia::new();
+        tia.write(COLUPF, 0x0E);
+        tia.write(PF0, 0xF0);
+        run_to(&mut tia, FIRST_LINE, 0);
+        tia.write(HMOVE, 0);
+        run_to(&mut tia, FIRST_LINE + 1, 0);
+        let pixels: Vec<usize> = tia.framebuffer()[..WIDTH * 4]
+            .chunks(8)
+            .enumerate()
+            .filter(|(_, pixel)| pixel[..3] != [0, 0, 0])
+            .map(|(x, _)| x)
+            .collect();
+        assert_eq!(pixels, (8..16).chain(80..96).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn delays_players_and_the_ball() {
+        let mut tia = Tia::new();
+        tia.write(VDELP0, 0x01);
+        tia.write(VDELBL, 0x01);
+        tia.write(GRP0, 0xFF);
+        tia.write(ENABL, 0x02);
+        assert!(!tia.player(0, 0));
+        assert!(!tia.ball(0));
+        tia.write(GRP1, 0x00);
+        assert!(tia.player(0, 0));
+        assert!(tia.ball(0));
+        tia.write(GRP0, 0x00);
+        assert!(tia.player(0, 0));
+        assert!(!tia.player(1, 0));
+    }
+
+    #[test]
+    fn latches_collisions() {
+        let mut tia = Tia::new();
+        tia.write(GRP0, 0xFF);
+        tia.write(GRP1, 0xFF);
+        tia.write(ENAM0, 0x02);
+        place(&mut tia, 0, 10);
+        place(&mut tia, 1, 14);
+        place(&mut tia, 2, 15);
+        place(&mut tia, 3, 100);
+        place(&mut tia, 4, 100);
+        tia.write(PF0, 0x10);
+        line(&mut tia);
+        assert_eq!(tia.read(CXPPMM), 0x80);
+        assert_eq!(tia.read(CXM0P), 0xC0);
+        assert_eq!(tia.read(CXM1P), 0);
+        assert_eq!(tia.read(CXP0FB), 0);
+        assert_eq!(tia.read(CXBLPF), 0);
+        // The ball is enabled on the next line, over the playfield.
+        tia.write(ENABL, 0x02);
+        place(&mut tia, 4, 2);
+        run_to(&mut tia, FIRST_LINE + 2, 0);
+        assert_eq!(tia.read(CXBLPF), 0x80);
+        assert_eq!(tia.read(CXP0FB), 0);
+        tia.write(CXCLR, 0);
+        assert_eq!(tia.read(CXPPMM), 0);
+    }
+
+    #[test]
+    fn reads_and_latches_fire_buttons() {
+        let mut tia = Tia::new();
+        assert_eq!(tia.read(INPT4), 0x80);
+        tia.set_fire(1, true);
+        assert_eq!(tia.read(INPT5), 0);
+        tia.set_fire(1, false);
+        assert_eq!(tia.read(INPT5), 0x80);
+        tia.write(VBLANK, 0x40);
+        tia.set_fire(0, true);
+        tia.set_fire(0, false);
+        assert_eq!(tia.read(INPT4), 0);
+        tia.write(VBLANK, 0x00);
+        assert_eq!(tia.read(INPT4), 0x80);
+    }
+
+    #[test]
+    fn ends_frames_at_vsync() {
+        let mut tia = Tia::new();
+        run_to(&mut tia, 100, 0);
+        assert!(!tia.take_frame());
+        tia.write(VSYNC, 0x02);
+        assert_eq!(tia.line(), 0);
+        assert!(tia.take_frame());
+        // Holding VSYNC on does not start another.
+        tia.write(VSYNC, 0x02);
+        tia.write(VSYNC, 0x00);
+        assert!(!tia.take_frame());
+        run_to(&mut tia, MAX_LINES - 1, CLOCKS_PER_LINE - 1);
+        tia.tick();
+        assert!(tia.take_frame());
+        assert_eq!(tia.line(), 0);
+    }
+
+    #[test]
+    fn blanks_during_vblank() {
+        let mut tia = Tia::new();
+        tia.write(COLUBK, 0x0E);
+        tia.write(VBLANK, 0x02);
+        run_to(&mut tia, FIRST_LINE + 1, 0);
+        assert!(tia.framebuffer()[..WIDTH * 4]
+            .chunks(4)
+            .all(|pixel| pixel == [0, 0, 0, 0xFF]));
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut tia = Tia::new();
+        tia.write(COLUP0, 0x44);
+        tia.write(GRP0, 0x81);
+        tia.write(GRP1, 0x18);
+        place(&mut tia, 0, 77);
+        run_to(&mut tia, 40, 100);
+        let state = tia.save();
+        let mut restored = Tia::new();
+        restored.restore(&state).unwrap();
+        assert_eq!(restored.save()[..], state[..]);
+        let mut corrupt = state;
+        corrupt[REGISTERS] = 160;
+        assert!(restored.restore(&corrupt).is_err());
+        assert!(restored.restore(&state[1..]).is_err());
+    }
+}
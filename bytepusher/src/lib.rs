@@ -0,0 +1,387 @@
+//! BytePusher, a virtual machine as simple as a machine with a picture, sound and a keypad can be:
+//! a ByteByteJump processor, whose only instruction copies a byte and jumps, and 16MB of memory,
+//! which holds everything else.
+//!
+//! Each instruction is three 24-bit big-endian addresses, A, B and C: the byte at A is copied to
+//! B, and the program goes on at C. A frame, sixty times a second:
+//!
+//! 1. stores the keys held down in the 16 bits at 0x000000, bit `n` for key `n`;
+//! 2. starts the program at the address in the 3 bytes at 0x000002;
+//! 3. runs 65536 instructions;
+//! 4. draws the picture from the 64KB page the byte at 0x000005 gives, 256 by 256 pixels of a
+//!    byte each, from a cube of 6x6x6 colors;
+//! 5. and plays the 256 signed 8-bit samples of the 256-byte page the 2 bytes at 0x000006 give,
+//!    15360 a second.
+//!
+//! A ROM is an image of memory from address 0, up to 16MB, such as the `.BytePusher` files of the
+//! programs written for it. The keys are those of the CHIP-8 keypad.
+//!
+//! ```
+//! use bytepusher::BytePusher;
+//! use emu_core::Emulator;
+//!
+//! let mut machine = BytePusher::new();
+//! // Jump to 0x000008, which jumps to itself, forever.
+//! machine.load_rom(&[0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 8]).unwrap();
+//! machine.step_frame().unwrap();
+//! assert_eq!(machine.video().width, 256);
+//! assert_eq!(machine.audio().sample_rate, 15360);
+//! ```
+
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+
+/// Bytes of memory: everything 24 bits address.
+pub const MEMORY_SIZE: usize = 0x100_0000;
+
+/// Width and height of the picture.
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 256;
+
+/// Samples per second of the sound: 256 a frame.
+pub const SAMPLE_RATE: u32 = 15_360;
+
+/// The time a frame takes, 1/60 second.
+pub const FRAME_PERIOD: Duration = Duration::from_nanos(16_666_667);
+
+/// Instructions run in a frame.
+pub const INSTRUCTIONS_PER_FRAME: usize = 0x1_0000;
+
+/// The keys of the keypad, numbered as the machine numbers them.
+pub const BUTTONS: [&str; 16] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F",
+];
+
+/// Where in memory the keys, the address of the program, the page of the picture and the page of
+/// the sound are.
+const KEYS: usize = 0;
+const PROGRAM_COUNTER: usize = 2;
+const PICTURE: usize = 5;
+const SOUND: usize = 6;
+
+/// Bytes past the end of memory that instructions at its last addresses read, which are always 0.
+const PADDING: usize = 8;
+
+/// Samples of sound in a frame.
+const SAMPLES_PER_FRAME: usize = 256;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"BPU\x01";
+
+/// Returns the red, green, blue and alpha of the pixel value `value`: values up to 215 are
+/// `red * 36 + green * 6 + blue`, each from 0 to 5, and the rest black.
+pub fn rgba(value: u8) -> [u8; 4] {
+    if value >= 216 {
+        return [0, 0, 0, 0xFF];
+    }
+    [
+        value / 36 * 0x33,
+        value / 6 % 6 * 0x33,
+        value % 6 * 0x33,
+        0xFF,
+    ]
+}
+
+/// The machine, with a ROM.
+#[derive(Clone)]
+pub struct BytePusher {
+    /// Memory, with [`PADDING`] bytes past its end.
+    memory: Vec<u8>,
+    rom: Vec<u8>,
+    rom_hash: u64,
+    /// The keys held down, bit `n` for key `n`.
+    keys: u16,
+    framebuffer: Vec<u8>,
+    samples: Vec<i16>,
+}
+
+impl Default for BytePusher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BytePusher {
+    /// Creates the machine with no ROM, its memory clear.
+    pub fn new() -> Self {
+        let mut framebuffer = vec![0; WIDTH * HEIGHT * 4];
+        for pixel in framebuffer.chunks_mut(4) {
+            pixel[3] = 0xFF;
+        }
+        BytePusher {
+            memory: vec![0; MEMORY_SIZE + PADDING],
+            rom: Vec::new(),
+            rom_hash: emu_state::hash(&[]),
+            keys: 0,
+            framebuffer,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Returns memory.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory[..MEMORY_SIZE]
+    }
+
+    /// Returns memory, to change it directly.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory[..MEMORY_SIZE]
+    }
+
+    /// Returns the keys held down, bit `n` for key `n`.
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+
+    /// Returns the 24-bit big-endian address at `address`.
+    fn address(&self, address: usize) -> usize {
+        let bytes = &self.memory[address..address + 3];
+        usize::from(bytes[0]) << 16 | usize::from(bytes[1]) << 8 | usize::from(bytes[2])
+    }
+
+    /// Runs the instructions of a frame.
+    fn run(&mut self) {
+        self.memory[KEYS..KEYS + 2].copy_from_slice(&self.keys.to_be_bytes());
+        let mut pc = self.address(PROGRAM_COUNTER);
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            let (a, b) = (self.address(pc), self.address(pc + 3));
+            self.memory[b] = self.memory[a];
+            pc = self.address(pc + 6);
+        }
+    }
+
+    /// Draws the page of the picture.
+    fn draw(&mut self) {
+        let page = usize::from(self.memory[PICTURE]) << 16;
+        let pixels = &self.memory[page..page + WIDTH * HEIGHT];
+        for (pixel, &value) in self.framebuffer.chunks_mut(4).zip(pixels) {
+            pixel.copy_from_slice(&rgba(value));
+        }
+    }
+
+    /// Adds the samples of the page of the sound.
+    fn play(&mut self) {
+        let page = usize::from(self.memory[SOUND]) << 16 | usize::from(self.memory[SOUND + 1]) << 8;
+        let samples = &self.memory[page..page + SAMPLES_PER_FRAME];
+        self.samples
+            .extend(samples.iter().map(|&sample| i16::from(sample as i8) << 8));
+    }
+}
+
+impl Emulator for BytePusher {
+    fn name(&self) -> &'static str {
+        "BytePusher"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: WIDTH,
+            height: HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Loads the image of memory `rom`, of up to 16MB, and starts it.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        if rom.len() > MEMORY_SIZE {
+            return Err(Error::Rom(format!(
+                "an image of {} bytes is larger than the 16MB of memory",
+                rom.len()
+            )));
+        }
+        self.rom = rom.to_vec();
+        self.rom_hash = emu_state::hash(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Clears memory and loads the ROM into it again.
+    fn reset(&mut self) {
+        self.memory.iter_mut().for_each(|byte| *byte = 0);
+        self.memory[..self.rom.len()].copy_from_slice(&self.rom);
+        for pixel in self.framebuffer.chunks_mut(4) {
+            pixel.copy_from_slice(&[0, 0, 0, 0xFF]);
+        }
+        self.samples.clear();
+    }
+
+    fn step_frame(&mut self) -> Result<(), Error> {
+        self.run();
+        self.draw();
+        self.play();
+        Ok(())
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if button < BUTTONS.len() {
+            if pressed {
+                self.keys |= 1 << button;
+            } else {
+                self.keys &= !(1 << button);
+            }
+        }
+    }
+
+    /// Saves memory, which is all the state the machine has, but not the picture, which is drawn
+    /// again by the next frame.
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(self.memory());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        if state.len() != MAGIC.len() + 8 + MEMORY_SIZE || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this machine".to_string()));
+        }
+        let (hash, memory) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        self.memory_mut().copy_from_slice(memory);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the bytes of the instruction copying `a` to `b` and jumping to `c`.
+    fn instruction(a: u32, b: u32, c: u32) -> Vec<u8> {
+        [a, b, c]
+            .iter()
+            .flat_map(|address| address.to_be_bytes()[1..].to_vec())
+            .collect()
+    }
+
+    /// Returns a ROM starting at 0x000100 with `program`, drawing page 0x01 and playing page
+    /// 0x0002.
+    fn rom(program: &[Vec<u8>]) -> Vec<u8> {
+        let mut rom = vec![0; 0x100];
+        rom[PROGRAM_COUNTER..PROGRAM_COUNTER + 3].copy_from_slice(&[0x00, 0x01, 0x00]);
+        rom[PICTURE] = 0x01;
+        rom[SOUND..SOUND + 2].copy_from_slice(&[0x00, 0x02]);
+        rom.extend(program.concat());
+        rom
+    }
+
+    fn machine(program: &[Vec<u8>]) -> BytePusher {
+        let mut machine = BytePusher::new();
+        machine.load_rom(&rom(program)).unwrap();
+        machine
+    }
+
+    #[test]
+    fn decodes_colors() {
+        assert_eq!(rgba(0), [0, 0, 0, 0xFF]);
+        assert_eq!(rgba(215), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(rgba(36 + 2 * 6 + 3), [0x33, 0x66, 0x99, 0xFF]);
+        assert_eq!(rgba(216), [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn copies_bytes_and_jumps() {
+        // Copies the keys to 0x000010 and 0x000011, then loops on the second copy.
+        let mut machine = machine(&[
+            instruction(0x000001, 0x000010, 0x000109),
+            instruction(0x000000, 0x000011, 0x000109),
+        ]);
+        machine.set_button(3, true);
+        machine.set_button(0xC, true);
+        machine.set_button(BUTTONS.len(), true);
+        assert_eq!(machine.keys(), 0x1008);
+        machine.step_frame().unwrap();
+        assert_eq!(machine.memory()[..2], [0x10, 0x08]);
+        assert_eq!(machine.memory()[0x10..0x12], [0x08, 0x10]);
+    }
+
+    #[test]
+    fn runs_65536_instructions_a_frame() {
+        // Counts in the low byte of its own A, through a table of successors at 0x002000.
+        let mut machine = machine(&[instruction(0x002005, 0x000102, 0x000100)]);
+        for value in 0..0x100 {
+            machine.memory_mut()[0x2000 + value] = (value + 1) as u8;
+        }
+        machine.step_frame().unwrap();
+        assert_eq!(machine.memory()[0x102], 0x05);
+        // Behind an instruction that jumps to it, it counts once less.
+        machine.memory_mut()[0x100..0x109].copy_from_slice(&instruction(0, 0, 0x000109));
+        machine.memory_mut()[0x109..0x112]
+            .copy_from_slice(&instruction(0x002000, 0x00010B, 0x000109));
+        machine.step_frame().unwrap();
+        assert_eq!(machine.memory()[0x10B], 0xFF);
+    }
+
+    #[test]
+    fn draws_and_plays_pages() {
+        let mut machine = machine(&[instruction(0, 0, 0x000100)]);
+        machine.memory_mut()[0x01_0000] = 5;
+        machine.memory_mut()[0x01_FFFF] = 180;
+        machine.memory_mut()[0x0200] = 0x40;
+        machine.memory_mut()[0x02FF] = 0x80;
+        machine.step_frame().unwrap();
+        let frame = machine.framebuffer();
+        assert_eq!(frame[..4], rgba(5));
+        assert_eq!(frame[frame.len() - 4..], rgba(180));
+        let mut samples = Vec::new();
+        machine.pull_audio(&mut samples);
+        assert_eq!(samples.len(), SAMPLES_PER_FRAME);
+        assert_eq!(samples[0], 0x4000);
+        assert_eq!(samples[1], 0);
+        assert_eq!(samples[255], -0x8000);
+    }
+
+    #[test]
+    fn reads_past_the_end_of_memory_as_0() {
+        // The instruction in the last 4 bytes copies 0x000005 to 0x000000 and jumps to 0.
+        let mut machine = machine(&[]);
+        machine.memory_mut()[PROGRAM_COUNTER..PROGRAM_COUNTER + 3]
+            .copy_from_slice(&[0xFF, 0xFF, 0xFC]);
+        machine.memory_mut()[0xFF_FFFC..].copy_from_slice(&[0x00, 0x00, 0x05, 0x00]);
+        machine.run();
+        assert_eq!(machine.memory()[0], 0x01);
+    }
+
+    #[test]
+    fn saves_state_and_resets() {
+        let mut machine = machine(&[instruction(0x000105, 0x000050, 0x000100)]);
+        machine.step_frame().unwrap();
+        assert_eq!(machine.memory()[0x50], 0x50);
+        let state = machine.save_state();
+        machine.reset();
+        assert_eq!(machine.memory()[0x50], 0);
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.memory()[0x50], 0x50);
+        assert!(matches!(
+            machine.load_state(&state[1..]),
+            Err(Error::State(_))
+        ));
+        let mut other = BytePusher::new();
+        assert!(matches!(other.load_state(&state), Err(Error::State(_))));
+        assert!(matches!(
+            other.load_rom(&vec![0; MEMORY_SIZE + 1]),
+            Err(Error::Rom(_))
+        ));
+    }
+}
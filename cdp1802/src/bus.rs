@@ -0,0 +1,64 @@
+//! What the processor is wired to: memory, the I/O devices selected by the N lines, the four
+//! external flags, and the lines requesting DMA and interrupts.
+
+pub use emu_memory::{Memory, Overflow};
+
+/// A DMA transfer a device asks for, which takes the next machine cycle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dma {
+    /// The device writes a byte to memory at `R0`.
+    In(u8),
+    /// The device reads the byte in memory at `R0`, through [`Bus::dma_out`].
+    Out,
+}
+
+/// The system around the processor.
+///
+/// Only memory has to be provided. The other methods default to a system with no devices:
+/// inputs read 0, outputs go nowhere, the flags are clear and no DMA or interrupt is ever asked
+/// for.
+pub trait Bus {
+    /// Reads the byte at `address`.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes `value` at `address`.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Reads the device selected by `INP`, whose N lines are `port`, from 1 to 7.
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    /// Writes `value` to the device selected by `OUT`, whose N lines are `port`, from 1 to 7.
+    fn output(&mut self, _port: u8, _value: u8) {}
+
+    /// Returns whether the external flag `EF1` to `EF4`, numbered `flag` from 1 to 4, is set.
+    fn flag(&mut self, _flag: u8) -> bool {
+        false
+    }
+
+    /// Returns the DMA transfer a device asks for, checked after every instruction and while
+    /// idle.
+    fn dma(&mut self) -> Option<Dma> {
+        None
+    }
+
+    /// Takes the byte read from memory by a [`Dma::Out`] transfer.
+    fn dma_out(&mut self, _value: u8) {}
+
+    /// Returns whether a device asks for an interrupt, which is only taken while interrupts are
+    /// enabled.
+    fn interrupt(&mut self) -> bool {
+        false
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[address as usize] = value;
+    }
+}
@@ -0,0 +1,162 @@
+//! Disassembling instructions into RCA's mnemonics, such as `LDI #2A`, `BR 3A` and `SEP R3`, for
+//! debuggers and traces.
+//!
+//! ```
+//! use cdp1802::disasm::Instruction;
+//!
+//! let instruction = Instruction::decode(&[0xC0, 0x01, 0x23]).unwrap();
+//! assert_eq!(instruction.to_string(), "LBR 0123");
+//! assert_eq!(instruction.len(), 3);
+//! ```
+
+use core::fmt;
+
+/// The short branches, `30` to `3F`.
+const SHORT: [&str; 16] = [
+    "BR", "BQ", "BZ", "BDF", "B1", "B2", "B3", "B4", "SKP", "BNQ", "BNZ", "BNF", "BN1", "BN2",
+    "BN3", "BN4",
+];
+
+/// `70` to `7F`.
+const CONTROL: [&str; 16] = [
+    "RET", "DIS", "LDXA", "STXD", "ADC", "SDB", "SHRC", "SMB", "SAV", "MARK", "REQ", "SEQ", "ADCI",
+    "SDBI", "SHLC", "SMBI",
+];
+
+/// The long branches and skips, `C0` to `CF`.
+const LONG: [&str; 16] = [
+    "LBR", "LBQ", "LBZ", "LBDF", "NOP", "LSNQ", "LSNZ", "LSNF", "LSKP", "LBNQ", "LBNZ", "LBNF",
+    "LSIE", "LSQ", "LSZ", "LSDF",
+];
+
+/// `F0` to `FF`.
+const ARITHMETIC: [&str; 16] = [
+    "LDX", "OR", "AND", "XOR", "ADD", "SD", "SHR", "SM", "LDI", "ORI", "ANI", "XRI", "ADI", "SDI",
+    "SHL", "SMI",
+];
+
+/// Returns the length in bytes of the instruction starting with `opcode`: 1, 2 for the short
+/// branches and immediate operands, or 3 for the long branches.
+pub fn length(opcode: u8) -> usize {
+    match opcode {
+        0x38 | 0xC8 => 1,
+        0x30..=0x3F | 0x7C | 0x7D | 0x7F | 0xF8..=0xFD | 0xFF => 2,
+        0xC0..=0xC3 | 0xC9..=0xCB => 3,
+        _ => 1,
+    }
+}
+
+/// An instruction with its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u8,
+    /// The byte or, for long branches, the address following the opcode.
+    pub operand: u16,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, or returns `None` if they end before it
+    /// does.
+    pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+        let opcode = *bytes.first()?;
+        let operand = match length(opcode) {
+            1 => 0,
+            2 => u16::from(*bytes.get(1)?),
+            _ => u16::from(*bytes.get(1)?) << 8 | u16::from(*bytes.get(2)?),
+        };
+        Some(Instruction { opcode, operand })
+    }
+
+    /// Returns the length of the instruction in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        length(self.opcode)
+    }
+
+    /// Returns the mnemonic of the instruction, such as `LDI`.
+    pub fn mnemonic(&self) -> &'static str {
+        let n = (self.opcode & 0xF) as usize;
+        match self.opcode >> 4 {
+            0x0 if n == 0 => "IDL",
+            0x0 => "LDN",
+            0x1 => "INC",
+            0x2 => "DEC",
+            0x3 => SHORT[n],
+            0x4 => "LDA",
+            0x5 => "STR",
+            0x6 if n == 0 => "IRX",
+            0x6 if n < 8 => "OUT",
+            0x6 => "INP",
+            0x7 => CONTROL[n],
+            0x8 => "GLO",
+            0x9 => "GHI",
+            0xA => "PLO",
+            0xB => "PHI",
+            0xC => LONG[n],
+            0xD => "SEP",
+            0xE => "SEX",
+            _ => ARITHMETIC[n],
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+        let n = self.opcode & 0xF;
+        match (self.opcode >> 4, self.len()) {
+            (0x3, 2) => write!(f, "{} {:02X}", mnemonic, self.operand),
+            (_, 2) => write!(f, "{} #{:02X}", mnemonic, self.operand),
+            (_, 3) => write!(f, "{} {:04X}", mnemonic, self.operand),
+            (0x0, _) if n == 0 => f.write_str(mnemonic),
+            (0x6, _) if n == 0 => f.write_str(mnemonic),
+            (0x6, _) => write!(f, "{} {}", mnemonic, n & 7),
+            (0x0..=0x2, _) | (0x4 | 0x5, _) | (0x8..=0xB, _) | (0xD | 0xE, _) => {
+                write!(f, "{} R{:X}", mnemonic, n)
+            }
+            _ => f.write_str(mnemonic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    fn disassemble(bytes: &[u8]) -> std::string::String {
+        Instruction::decode(bytes).unwrap().to_string()
+    }
+
+    #[test]
+    fn disassembles() {
+        assert_eq!(disassemble(&[0x00]), "IDL");
+        assert_eq!(disassemble(&[0x05]), "LDN R5");
+        assert_eq!(disassemble(&[0x1F]), "INC RF");
+        assert_eq!(disassemble(&[0x3A, 0x0E]), "BNZ 0E");
+        assert_eq!(disassemble(&[0x38]), "SKP");
+        assert_eq!(disassemble(&[0x60]), "IRX");
+        assert_eq!(disassemble(&[0x62]), "OUT 2");
+        assert_eq!(disassemble(&[0x6C]), "INP 4");
+        assert_eq!(disassemble(&[0x79]), "MARK");
+        assert_eq!(disassemble(&[0x7C, 0x01]), "ADCI #01");
+        assert_eq!(disassemble(&[0xA7]), "PLO R7");
+        assert_eq!(disassemble(&[0xC4]), "NOP");
+        assert_eq!(disassemble(&[0xCA, 0x01, 0x23]), "LBNZ 0123");
+        assert_eq!(disassemble(&[0xD3]), "SEP R3");
+        assert_eq!(disassemble(&[0xE2]), "SEX R2");
+        assert_eq!(disassemble(&[0xF8, 0x2A]), "LDI #2A");
+        assert_eq!(disassemble(&[0xFE]), "SHL");
+        assert_eq!(Instruction::decode(&[0xC0, 0x01]), None);
+        assert_eq!(Instruction::decode(&[]), None);
+    }
+
+    #[test]
+    fn lengths() {
+        let lengths: usize = (0..=255u8).map(length).sum();
+        // 16 short branches less SKP, 10 immediate operands and 7 long branches.
+        assert_eq!(lengths, 256 + 15 + 10 + 7 * 2);
+    }
+}
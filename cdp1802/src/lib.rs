@@ -0,0 +1,919 @@
+//! The RCA CDP1802 COSMAC microprocessor, the processor of the COSMAC VIP, the ETI 660, the
+//! Telmac 1800 and the other machines the first CHIP-8 interpreters ran on.
+//!
+//! The processor has sixteen 16-bit registers, any of which can be the program counter, selected
+//! by `P`, or the pointer to data in memory, selected by `X`. It runs in machine cycles of 8
+//! clock cycles: an instruction takes two, one to fetch it and one to execute it, or three for the
+//! long branches and skips. Between instructions, a device can take a cycle to move a byte
+//! between itself and memory at `R0` by DMA, or interrupt the program, which saves `X` and `P` in
+//! `T` and continues with `R1` as the program counter and `R2` as the stack pointer.
+//!
+//! A [`Cpu`] runs against a [`Bus`], which is the memory and devices of a machine:
+//!
+//! ```
+//! use cdp1802::bus::Memory;
+//! use cdp1802::Cpu;
+//!
+//! // 0x00: LDI 0x2A; PLO R7; IDL
+//! let mut memory = Memory::with_program(0, &[0xF8, 0x2A, 0xA7, 0x00])?;
+//! let mut cpu = Cpu::new();
+//! while !cpu.idle() {
+//!     cpu.step(&mut memory);
+//! }
+//! assert_eq!(cpu.register(7), 0x2A);
+//! assert_eq!(cpu.cycles(), 6);
+//! # Ok::<(), cdp1802::bus::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` and needs no allocator.
+
+#![no_std]
+
+pub mod bus;
+pub mod disasm;
+
+use crate::bus::{Bus, Dma};
+
+/// Clock cycles in a machine cycle.
+pub const CLOCKS_PER_CYCLE: u64 = 8;
+
+/// Length of the state written by [`Cpu::save`].
+pub const STATE_SIZE: usize = 45;
+
+/// The processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    /// The scratchpad registers `R0` to `RF`.
+    registers: [u16; 16],
+    /// Selects the program counter.
+    p: u8,
+    /// Selects the data pointer.
+    x: u8,
+    /// `X` and `P` saved by an interrupt, `X` in the high nibble.
+    t: u8,
+    /// The accumulator.
+    d: u8,
+    /// The carry, which is clear after a subtraction that borrowed.
+    df: bool,
+    /// Whether interrupts are taken.
+    ie: bool,
+    /// The output flip-flop, wired to a speaker on the COSMAC VIP.
+    q: bool,
+    /// Whether the processor is waiting in `IDL` for a DMA transfer or an interrupt.
+    idle: bool,
+    /// Machine cycles run since the processor was created.
+    cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    /// Creates a processor just reset, with every register 0.
+    pub fn new() -> Self {
+        Cpu {
+            registers: [0; 16],
+            p: 0,
+            x: 0,
+            t: 0,
+            d: 0,
+            df: false,
+            ie: true,
+            q: false,
+            idle: false,
+            cycles: 0,
+        }
+    }
+
+    /// Resets the processor as its `CLEAR` line does: `X`, `P`, `Q` and `R0` become 0 and
+    /// interrupts are enabled, so that the program starts at address 0. The other registers keep
+    /// their values.
+    pub fn reset(&mut self) {
+        self.registers[0] = 0;
+        self.p = 0;
+        self.x = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+    }
+
+    /// Returns the register `R0` to `RF` numbered `n`.
+    pub fn register(&self, n: u8) -> u16 {
+        self.registers[n as usize & 0xF]
+    }
+
+    /// Sets the register numbered `n`.
+    pub fn set_register(&mut self, n: u8, value: u16) {
+        self.registers[n as usize & 0xF] = value;
+    }
+
+    /// Returns the number of the register that is the program counter.
+    pub fn p(&self) -> u8 {
+        self.p
+    }
+
+    /// Makes the register numbered `n` the program counter.
+    pub fn set_p(&mut self, n: u8) {
+        self.p = n & 0xF;
+    }
+
+    /// Returns the number of the register that points to data.
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    /// Makes the register numbered `n` the data pointer.
+    pub fn set_x(&mut self, n: u8) {
+        self.x = n & 0xF;
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.register(self.p)
+    }
+
+    /// Returns `X` and `P` as saved by the last interrupt or `MARK`.
+    pub fn t(&self) -> u8 {
+        self.t
+    }
+
+    /// Returns the accumulator.
+    pub fn d(&self) -> u8 {
+        self.d
+    }
+
+    /// Sets the accumulator.
+    pub fn set_d(&mut self, d: u8) {
+        self.d = d;
+    }
+
+    /// Returns the carry flag.
+    pub fn df(&self) -> bool {
+        self.df
+    }
+
+    /// Sets the carry flag.
+    pub fn set_df(&mut self, df: bool) {
+        self.df = df;
+    }
+
+    /// Returns whether interrupts are enabled.
+    pub fn ie(&self) -> bool {
+        self.ie
+    }
+
+    /// Returns the output flip-flop `Q`.
+    pub fn q(&self) -> bool {
+        self.q
+    }
+
+    /// Returns whether the processor is waiting in `IDL` for a DMA transfer or an interrupt.
+    pub fn idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Returns the machine cycles run since the processor was created, each of
+    /// [`CLOCKS_PER_CYCLE`] clock cycles.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs the processor until the next instruction is due, and returns the machine cycles it
+    /// took: one for a DMA transfer, an interrupt or a cycle spent idle, and two or three for an
+    /// instruction.
+    ///
+    /// DMA transfers come first, then interrupts, as on the chip.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        let cycles = if let Some(dma) = bus.dma() {
+            let address = self.registers[0];
+            match dma {
+                Dma::In(value) => bus.write(address, value),
+                Dma::Out => {
+                    let value = bus.read(address);
+                    bus.dma_out(value);
+                }
+            }
+            self.registers[0] = address.wrapping_add(1);
+            self.idle = false;
+            1
+        } else if self.ie && bus.interrupt() {
+            self.t = self.x << 4 | self.p;
+            self.p = 1;
+            self.x = 2;
+            self.ie = false;
+            self.idle = false;
+            1
+        } else if self.idle {
+            1
+        } else {
+            self.execute(bus)
+        };
+        self.cycles += u64::from(cycles);
+        cycles
+    }
+
+    /// Fetches and executes an instruction, returning its machine cycles.
+    fn execute(&mut self, bus: &mut impl Bus) -> u8 {
+        let opcode = self.immediate(bus);
+        let n = opcode & 0xF;
+        let r = n as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.registers[r]),
+            0x1 => self.registers[r] = self.registers[r].wrapping_add(1),
+            0x2 => self.registers[r] = self.registers[r].wrapping_sub(1),
+            0x3 => {
+                let condition = self.condition(n & 7, bus) != (n & 8 != 0);
+                self.short_branch(condition, bus);
+            }
+            0x4 => {
+                self.d = bus.read(self.registers[r]);
+                self.registers[r] = self.registers[r].wrapping_add(1);
+            }
+            0x5 => bus.write(self.registers[r], self.d),
+            0x6 => self.io(n, bus),
+            0x7 => self.control(n, bus),
+            0x8 => self.d = self.registers[r] as u8,
+            0x9 => self.d = (self.registers[r] >> 8) as u8,
+            0xA => self.registers[r] = self.registers[r] & 0xFF00 | u16::from(self.d),
+            0xB => self.registers[r] = self.registers[r] & 0x00FF | u16::from(self.d) << 8,
+            0xC => {
+                self.long(n, bus);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.arithmetic(n, bus),
+        }
+        2
+    }
+
+    /// Returns the condition tested by the branches numbered `n` from 0 to 7: always, `Q`, `D` is
+    /// 0, `DF`, and `EF1` to `EF4`.
+    fn condition(&self, n: u8, bus: &mut impl Bus) -> bool {
+        match n {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            _ => bus.flag(n - 3),
+        }
+    }
+
+    /// Reads the byte at the program counter, and moves past it.
+    fn immediate(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = &mut self.registers[self.p as usize];
+        let value = bus.read(*pc);
+        *pc = pc.wrapping_add(1);
+        value
+    }
+
+    /// Reads the byte the data pointer points to.
+    fn data(&self, bus: &mut impl Bus) -> u8 {
+        bus.read(self.registers[self.x as usize])
+    }
+
+    /// Moves the data pointer by `delta`.
+    fn advance_x(&mut self, delta: i16) {
+        let rx = &mut self.registers[self.x as usize];
+        *rx = rx.wrapping_add(delta as u16);
+    }
+
+    /// Jumps within the page of the operand at the program counter if `condition`, or else skips
+    /// the operand.
+    fn short_branch(&mut self, condition: bool, bus: &mut impl Bus) {
+        let pc = self.registers[self.p as usize];
+        self.registers[self.p as usize] = if condition {
+            pc & 0xFF00 | u16::from(bus.read(pc))
+        } else {
+            pc.wrapping_add(1)
+        };
+    }
+
+    /// Executes the long branches and skips, `C0` to `CF`.
+    fn long(&mut self, n: u8, bus: &mut impl Bus) {
+        let pc = self.registers[self.p as usize];
+        if n & 4 == 0 {
+            // LBR, LBQ, LBZ, LBDF, and when inverted, LSKP, LBNQ, LBNZ, LBNF.
+            let condition = self.condition(n & 3, bus) != (n & 8 != 0);
+            self.registers[self.p as usize] = if condition {
+                u16::from(bus.read(pc)) << 8 | u16::from(bus.read(pc.wrapping_add(1)))
+            } else {
+                pc.wrapping_add(2)
+            };
+        } else {
+            // NOP, LSIE, and the skips on Q, D being 0 and DF, inverted for C5 to C7.
+            let skip = match n {
+                0x4 => false,
+                0xC => self.ie,
+                _ => self.condition(n & 3, bus) == (n & 8 != 0),
+            };
+            if skip {
+                self.registers[self.p as usize] = pc.wrapping_add(2);
+            }
+        }
+    }
+
+    /// Executes `IRX`, `OUT` and `INP`, `60` to `6F`. `68` is undefined on the CDP1802, and reads
+    /// the device on port 0 like an `INP`.
+    fn io(&mut self, n: u8, bus: &mut impl Bus) {
+        match n {
+            0 => self.advance_x(1),
+            1..=7 => {
+                let value = self.data(bus);
+                bus.output(n, value);
+                self.advance_x(1);
+            }
+            _ => {
+                let value = bus.input(n & 7);
+                bus.write(self.registers[self.x as usize], value);
+                self.d = value;
+            }
+        }
+    }
+
+    /// Executes `70` to `7F`: returns, saving `T`, the arithmetic with carry, and `Q`.
+    fn control(&mut self, n: u8, bus: &mut impl Bus) {
+        match n {
+            0x0 | 0x1 => {
+                let xp = self.data(bus);
+                self.advance_x(1);
+                self.x = xp >> 4;
+                self.p = xp & 0xF;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                self.d = self.data(bus);
+                self.advance_x(1);
+            }
+            0x3 => {
+                bus.write(self.registers[self.x as usize], self.d);
+                self.advance_x(-1);
+            }
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 1 != 0;
+                self.d = self.d >> 1 | (carry as u8) << 7;
+            }
+            0x8 => bus.write(self.registers[self.x as usize], self.t),
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.registers[2], self.t);
+                self.x = self.p;
+                self.registers[2] = self.registers[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xE => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | carry as u8;
+            }
+            _ => {
+                // ADC, SDB, SMB, and with an immediate operand, ADCI, SDBI, SMBI.
+                let m = if n < 8 {
+                    self.data(bus)
+                } else {
+                    self.immediate(bus)
+                };
+                self.alu(n & 7, m, self.df);
+            }
+        }
+    }
+
+    /// Executes `F0` to `FF`: loads, logic, arithmetic and shifts on `D`.
+    fn arithmetic(&mut self, n: u8, bus: &mut impl Bus) {
+        match n {
+            0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => {
+                let m = if n < 8 {
+                    self.data(bus)
+                } else {
+                    self.immediate(bus)
+                };
+                match n & 7 {
+                    0 => self.d = m,
+                    1 => self.d |= m,
+                    2 => self.d &= m,
+                    3 => self.d ^= m,
+                    // ADD, SD and SM carry in as if there were no borrow.
+                    op => self.alu(op, m, op != 4),
+                }
+            }
+        }
+    }
+
+    /// Adds `m` to `D` for the operation numbered `op` 4, subtracts `D` from `m` for 5, and `m`
+    /// from `D` for 7, with the carry `carry` coming in. A subtraction adds the complement, so
+    /// that `DF` is clear when it borrows.
+    fn alu(&mut self, op: u8, m: u8, carry: bool) {
+        let (a, b) = match op {
+            4 => (self.d, m),
+            5 => (m, !self.d),
+            _ => (self.d, !m),
+        };
+        let sum = u16::from(a) + u16::from(b) + u16::from(carry);
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// Writes the whole processor, for save states.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        for (bytes, register) in state.chunks_exact_mut(2).zip(self.registers.iter()) {
+            bytes.copy_from_slice(&register.to_be_bytes());
+        }
+        state[32] = self.p;
+        state[33] = self.x;
+        state[34] = self.t;
+        state[35] = self.d;
+        state[36] =
+            self.df as u8 | (self.ie as u8) << 1 | (self.q as u8) << 2 | (self.idle as u8) << 3;
+        state[37..].copy_from_slice(&self.cycles.to_be_bytes());
+        state
+    }
+
+    /// Restores the processor from a state written by [`Cpu::save`].
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        for (register, bytes) in self.registers.iter_mut().zip(state.chunks_exact(2)) {
+            *register = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+        self.p = state[32] & 0xF;
+        self.x = state[33] & 0xF;
+        self.t = state[34];
+        self.d = state[35];
+        let flags = state[36];
+        self.df = flags & 1 != 0;
+        self.ie = flags & 2 != 0;
+        self.q = flags & 4 != 0;
+        self.idle = flags & 8 != 0;
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&state[37..]);
+        self.cycles = u64::from_be_bytes(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Memory;
+
+    /// Memory, with devices driven by the test.
+    struct Machine {
+        memory: Memory,
+        flags: [bool; 4],
+        inputs: [u8; 8],
+        outputs: [Option<u8>; 8],
+        dma: [Option<Dma>; 4],
+        dma_out: [Option<u8>; 4],
+        interrupt: bool,
+    }
+
+    impl Machine {
+        fn new(program: &[u8]) -> Self {
+            Machine {
+                memory: Memory::with_program(0, program).unwrap(),
+                flags: [false; 4],
+                inputs: [0; 8],
+                outputs: [None; 8],
+                dma: [None; 4],
+                dma_out: [None; 4],
+                interrupt: false,
+            }
+        }
+
+        /// Places `bytes` in memory at `address`.
+        fn load(&mut self, address: usize, bytes: &[u8]) {
+            self.memory.0[address..address + bytes.len()].copy_from_slice(bytes);
+        }
+    }
+
+    impl Bus for Machine {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory.write(address, value);
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            self.inputs[port as usize]
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs[port as usize] = Some(value);
+        }
+
+        fn flag(&mut self, flag: u8) -> bool {
+            self.flags[flag as usize - 1]
+        }
+
+        fn dma(&mut self) -> Option<Dma> {
+            let next = self.dma.iter_mut().find(|dma| dma.is_some())?;
+            next.take()
+        }
+
+        fn dma_out(&mut self, value: u8) {
+            if let Some(slot) = self.dma_out.iter_mut().find(|out| out.is_none()) {
+                *slot = Some(value);
+            }
+        }
+
+        fn interrupt(&mut self) -> bool {
+            self.interrupt
+        }
+    }
+
+    /// Runs until the processor idles, failing if it does not within a million steps.
+    fn run_until_idle(cpu: &mut Cpu, bus: &mut impl Bus) {
+        for _ in 0..1_000_000 {
+            if cpu.idle() {
+                return;
+            }
+            cpu.step(bus);
+        }
+        panic!("the program did not reach IDL");
+    }
+
+    #[test]
+    fn multiplies() {
+        // 200 × 123 by shifting and adding, into R7.
+        let mut machine = Machine::new(&[
+            0xF8, 0x80, // 00: LDI 80
+            0xA3, // 02: PLO R3
+            0x43, // 03: LDA R3
+            0xA8, // 04: PLO R8      multiplicand
+            0x03, // 05: LDN R3
+            0xA9, // 06: PLO R9      multiplier
+            0xF8, 0x08, // 07: LDI 8
+            0xAA, // 09: PLO RA      bits left
+            0xF8, 0x90, // 0A: LDI 90
+            0xA2, // 0C: PLO R2      scratch
+            0xE2, // 0D: SEX R2
+            0x89, // 0E: GLO R9
+            0xF6, // 0F: SHR
+            0xA9, // 10: PLO R9
+            0x3B, 0x1E, // 11: BNF 1E
+            0x88, // 13: GLO R8
+            0x52, // 14: STR R2
+            0x87, // 15: GLO R7
+            0xF4, // 16: ADD
+            0xA7, // 17: PLO R7
+            0x98, // 18: GHI R8
+            0x52, // 19: STR R2
+            0x97, // 1A: GHI R7
+            0x74, // 1B: ADC
+            0xB7, // 1C: PHI R7
+            0xC4, // 1D: NOP
+            0x88, // 1E: GLO R8
+            0xFE, // 1F: SHL
+            0xA8, // 20: PLO R8
+            0x98, // 21: GHI R8
+            0x7E, // 22: SHLC
+            0xB8, // 23: PHI R8
+            0x2A, // 24: DEC RA
+            0x8A, // 25: GLO RA
+            0x3A, 0x0E, // 26: BNZ 0E
+            0x00, // 28: IDL
+        ]);
+        machine.load(0x80, &[200, 123]);
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(cpu.register(7), 200 * 123);
+        assert_eq!(cpu.program_counter(), 0x29);
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        // RCA's standard call and return technique: R4 calls the subroutine whose address
+        // follows, pushing R6 on the stack at R2, and R5 returns to R6.
+        let mut machine = Machine::new(&[
+            0xF8, 0xFF, 0xA2, // 00: R2 = 00FF, the stack
+            0xF8, 0x20, 0xA3, // 03: R3 = 0020, the main program
+            0xF8, 0x41, 0xA4, // 06: R4 = 0041, CALL
+            0xF8, 0x52, 0xA5, // 09: R5 = 0052, RETURN
+            0xD3, // 0C: SEP R3
+        ]);
+        machine.load(
+            0x20,
+            &[
+                0xF8, 0x05, 0xA7, // 20: R7 = 5
+                0xD4, 0x00, 0x30, // 23: CALL 0030, doubling R7
+                0xD4, 0x00, 0x30, // 26: CALL 0030
+                0xD4, 0x00, 0x38, // 29: CALL 0038, adding 1 to R7 and doubling it
+                0x00, // 2C: IDL
+            ],
+        );
+        machine.load(0x30, &[0x87, 0xFE, 0xA7, 0xD5]);
+        machine.load(0x38, &[0x87, 0xFC, 0x01, 0xA7, 0xD4, 0x00, 0x30, 0xD5]);
+        machine.load(
+            0x40,
+            &[
+                0xD3, // 40: SEP R3
+                0xE2, 0x96, 0x73, 0x86, 0x73, // 41: SEX R2; push R6
+                0x93, 0xB6, 0x83, 0xA6, // 46: R6 = R3
+                0x46, 0xB3, 0x46, 0xA3, // 4A: R3 = the address after the call
+                0x30, 0x40, // 4E: BR 40
+            ],
+        );
+        machine.load(
+            0x51,
+            &[
+                0xD3, // 51: SEP R3
+                0x96, 0xB3, 0x86, 0xA3, // 52: R3 = R6
+                0xE2, 0x12, 0x72, 0xA6, 0xF0, 0xB6, // 56: pop R6
+                0x30, 0x51, // 5C: BR 51
+            ],
+        );
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(cpu.register(7), 42);
+        assert_eq!(cpu.register(2), 0xFF);
+        assert_eq!(cpu.program_counter(), 0x2D);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut machine = Machine::new(&[
+            0xF8, 0x40, 0xA1, // 00: R1 = 0040, the handler
+            0xF8, 0xFF, 0xA2, // 03: R2 = 00FF, the stack
+            0xF8, 0x55, // 06: LDI 55
+            0x00, // 08: IDL
+            0x17, // 09: INC R7
+            0x30, 0x08, // 0A: BR 08
+        ]);
+        machine.load(
+            0x3E,
+            &[
+                0x72, 0x70, // 3E: LDXA; RET
+                0x22, 0x78, 0x22, 0x52, // 40: DEC R2; SAV; DEC R2; STR R2
+                0x18, // 44: INC R8
+                0xF8, 0xAA, // 45: LDI AA
+                0x30, 0x3E, // 47: BR 3E
+            ],
+        );
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(cpu.step(&mut machine), 1);
+        assert!(cpu.idle());
+
+        machine.interrupt = true;
+        assert_eq!(cpu.step(&mut machine), 1);
+        machine.interrupt = false;
+        assert!(!cpu.idle());
+        assert!(!cpu.ie());
+        assert_eq!((cpu.p(), cpu.x(), cpu.t()), (1, 2, 0x00));
+
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(cpu.register(7), 1);
+        assert_eq!(cpu.register(8), 1);
+        assert_eq!(cpu.d(), 0x55);
+        assert!(cpu.ie());
+        assert_eq!(cpu.register(2), 0xFF);
+        assert_eq!(machine.memory.0[0xFD..0xFF], [0x55, 0x00]);
+
+        // Disabled interrupts are not taken: DIS with X = 0 returns to P = 0 and X = 0.
+        let mut machine = Machine::new(&[0x71, 0x00, 0x00]);
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        machine.interrupt = true;
+        assert_eq!(cpu.step(&mut machine), 1);
+        assert!(cpu.idle());
+        assert!(!cpu.ie());
+        assert_eq!(cpu.p(), 0);
+    }
+
+    #[test]
+    fn dma() {
+        let mut machine = Machine::new(&[
+            0xF8, 0x20, 0xA3, // 00: R3 = 0020
+            0xD3, // 03: SEP R3
+        ]);
+        machine.load(0x20, &[0xF8, 0x80, 0xA0, 0x00, 0x17, 0x00]);
+        machine.load(0x80, &[1, 2, 3]);
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        let cycles = cpu.cycles();
+
+        machine.dma = [Some(Dma::Out), Some(Dma::Out), Some(Dma::In(0xAB)), None];
+        for _ in 0..3 {
+            assert_eq!(cpu.step(&mut machine), 1);
+        }
+        assert_eq!(cpu.cycles(), cycles + 3);
+        assert_eq!(machine.dma_out, [Some(1), Some(2), None, None]);
+        assert_eq!(machine.memory.0[0x82], 0xAB);
+        assert_eq!(cpu.register(0), 0x83);
+        // A transfer ends IDL.
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(cpu.register(7), 1);
+    }
+
+    #[test]
+    fn arithmetic() {
+        // ADCI, SDBI and SMBI for every accumulator, operand and carry, and ADI, SDI and SMI,
+        // which ignore the carry.
+        let mut bus = Memory::default();
+        let mut cpu = Cpu::new();
+        for &opcode in &[0x7C, 0x7D, 0x7F, 0xFC, 0xFD, 0xFF] {
+            for d in 0..=255u8 {
+                for m in 0..=255u8 {
+                    for &carry in &[false, true] {
+                        bus.0[0] = opcode;
+                        bus.0[1] = m;
+                        cpu.set_register(0, 0);
+                        cpu.set_d(d);
+                        cpu.set_df(carry);
+                        cpu.step(&mut bus);
+                        let carry = if opcode & 0x80 == 0 {
+                            carry as i32
+                        } else {
+                            (opcode != 0xFC) as i32
+                        };
+                        let (d, m) = (i32::from(d), i32::from(m));
+                        // Additions carry past 0xFF, and subtractions carry unless they borrow.
+                        let (expected, df) = match opcode & 0xF {
+                            0xC => (d + m + carry, d + m + carry > 0xFF),
+                            0xD => (m - d - 1 + carry, m - d - 1 + carry >= 0),
+                            _ => (d - m - 1 + carry, d - m - 1 + carry >= 0),
+                        };
+                        assert_eq!(
+                            (cpu.d(), cpu.df()),
+                            (expected as u8, df),
+                            "{:02X} with D={:02X}, M={:02X}",
+                            opcode,
+                            d,
+                            m
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn logic_and_shifts() {
+        let mut machine = Machine::new(&[
+            0xF8,
+            0b1100_1010, // LDI
+            0xF9,
+            0b0000_0101, // ORI   -> 1100_1111
+            0xFA,
+            0b0101_1110, // ANI   -> 0100_1110
+            0xFB,
+            0b1111_0000, // XRI   -> 1011_1110
+            0xF6,        // SHR  -> 0101_1111, DF = 0
+            0xFE,        // SHL  -> 1011_1110, DF = 0
+            0x7E,        // SHLC -> 0111_1100, DF = 1
+            0x76,        // SHRC -> 1011_1110, DF = 0
+            0x00,
+        ]);
+        let mut cpu = Cpu::new();
+        let mut trace = [(0, false); 8];
+        for step in trace.iter_mut() {
+            cpu.step(&mut machine);
+            *step = (cpu.d(), cpu.df());
+        }
+        assert_eq!(
+            trace,
+            [
+                (0b1100_1010, false),
+                (0b1100_1111, false),
+                (0b0100_1110, false),
+                (0b1011_1110, false),
+                (0b0101_1111, false),
+                (0b1011_1110, false),
+                (0b0111_1100, true),
+                (0b1011_1110, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn branches() {
+        let mut machine = Machine::new(&[]);
+        let mut cpu = Cpu::new();
+        let mut branch = |program: &[u8], setup: &dyn Fn(&mut Cpu, &mut Machine)| {
+            machine.load(0, program);
+            cpu = Cpu::new();
+            setup(&mut cpu, &mut machine);
+            let cycles = cpu.step(&mut machine);
+            (cpu.program_counter(), cycles)
+        };
+        let none = |_: &mut Cpu, _: &mut Machine| {};
+        assert_eq!(branch(&[0x30, 0x40], &none), (0x40, 2));
+        assert_eq!(branch(&[0x38, 0x40], &none), (0x02, 2));
+        assert_eq!(branch(&[0x32, 0x40], &none), (0x40, 2));
+        assert_eq!(branch(&[0x3A, 0x40], &none), (0x02, 2));
+        assert_eq!(branch(&[0x31, 0x40], &|cpu, _| cpu.q = true), (0x40, 2));
+        assert_eq!(branch(&[0x33, 0x40], &none), (0x02, 2));
+        assert_eq!(
+            branch(&[0x36, 0x40], &|_, bus| bus.flags[2] = true),
+            (0x40, 2)
+        );
+        assert_eq!(
+            branch(&[0x3E, 0x40], &|_, bus| bus.flags[2] = true),
+            (0x02, 2)
+        );
+        assert_eq!(branch(&[0xC0, 0x12, 0x34], &none), (0x1234, 3));
+        assert_eq!(branch(&[0xC8, 0x12, 0x34], &none), (0x03, 3));
+        assert_eq!(branch(&[0xCB, 0x12, 0x34], &none), (0x1234, 3));
+        assert_eq!(branch(&[0xC4, 0x12, 0x34], &none), (0x01, 3));
+        assert_eq!(branch(&[0xCC, 0x12, 0x34], &none), (0x03, 3));
+        assert_eq!(branch(&[0xC6, 0x12, 0x34], &none), (0x01, 3));
+        assert_eq!(branch(&[0xCE, 0x12, 0x34], &none), (0x03, 3));
+        assert_eq!(
+            branch(&[0xCD, 0x12, 0x34], &|cpu, _| cpu.q = true),
+            (0x03, 3)
+        );
+        assert_eq!(
+            branch(&[0xC5, 0x12, 0x34], &|cpu, _| cpu.q = true),
+            (0x01, 3)
+        );
+
+        // A short branch whose operand is on the next page jumps within that page.
+        machine.load(0x1FF, &[0x30, 0x10]);
+        cpu = Cpu::new();
+        cpu.set_register(0, 0x1FF);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x210);
+    }
+
+    #[test]
+    fn memory_and_registers() {
+        let mut machine = Machine::new(&[
+            0xF8, 0x12, 0xB5, // PHI R5
+            0xF8, 0x34, 0xA5, // PLO R5
+            0xF8, 0x99, 0x55, // STR R5
+            0x25, // DEC R5
+            0xE5, // SEX R5
+            0x60, // IRX
+            0x72, // LDXA
+            0xBA, // PHI RA
+            0x73, // STXD
+            0x95, // GHI R5
+            0x7B, // SEQ
+            0x79, // MARK
+            0x7A, // REQ
+            0x00,
+        ]);
+        let mut cpu = Cpu::new();
+        cpu.set_register(2, 0x100);
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(machine.memory.0[0x1234..0x1236], [0x99, 0x99]);
+        assert_eq!(cpu.register(0xA), 0x9900);
+        assert_eq!(cpu.register(5), 0x1234);
+        assert_eq!(cpu.d(), 0x12);
+        // MARK saved X = 5 and P = 0, and made X the program counter's.
+        assert_eq!(cpu.t(), 0x50);
+        assert_eq!(machine.memory.0[0x100], 0x50);
+        assert_eq!((cpu.x(), cpu.register(2)), (0, 0xFF));
+        assert!(!cpu.q());
+    }
+
+    #[test]
+    fn input_and_output() {
+        let mut machine = Machine::new(&[
+            0xF8, 0x80, 0xA2, 0xE2, // R2 = 0080, SEX R2
+            0x6B, // INP 3
+            0x63, // OUT 3
+            0x6D, // INP 5
+            0x61, // OUT 1
+            0x00,
+        ]);
+        machine.inputs[3] = 0x33;
+        machine.inputs[5] = 0x55;
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        assert_eq!(machine.outputs[3], Some(0x33));
+        assert_eq!(machine.outputs[1], Some(0x55));
+        assert_eq!(machine.memory.0[0x80..0x82], [0x33, 0x55]);
+        assert_eq!((cpu.d(), cpu.register(2)), (0x55, 0x82));
+    }
+
+    #[test]
+    fn resets_and_saves() {
+        let mut machine = Machine::new(&[0xF8, 0x12, 0xB7, 0x7B, 0xE5, 0x00]);
+        let mut cpu = Cpu::new();
+        run_until_idle(&mut cpu, &mut machine);
+        let mut restored = Cpu::new();
+        restored.restore(&cpu.save());
+        assert_eq!(restored, cpu);
+
+        cpu.reset();
+        assert_eq!((cpu.p(), cpu.x(), cpu.program_counter()), (0, 0, 0));
+        assert!(restored.q());
+        assert!(cpu.ie() && !cpu.q() && !cpu.idle());
+        assert_eq!(cpu.register(7), 0x1200);
+    }
+}
@@ -0,0 +1,84 @@
+//! Benchmarks of the interpreter's hot path: decoding, executing, drawing and running frames.
+//!
+//! ```text
+//! cargo bench -p chip8-core
+//! ```
+
+use chip8_core::asm;
+use chip8_core::frontend::{Headless, Runner};
+use chip8_core::instruction::Instruction;
+use chip8_core::rom::Rom;
+use chip8_core::CHIP8;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+/// Arithmetic, skips and jumps in a tight loop, without drawing or touching memory.
+const ARITHMETIC: &str = "
+: main
+  v0 := 0
+  v1 := 3
+  loop
+    v0 += 1
+    v2 := v0
+    v2 ^= v1
+    v2 <<= v2
+    v3 += v2
+    if v3 == 0 then v4 += 1
+  again
+";
+
+/// Like a game: each frame clears the screen, draws a row of balls that moves across it, and
+/// waits for the delay timer.
+const BOUNCE: &str = include_str!("../tests/roms/bounce.8o");
+
+/// Returns a machine with `source` assembled and loaded.
+fn load(source: &str) -> CHIP8 {
+    let program = asm::assemble(source).unwrap();
+    let mut chip = CHIP8::builder().seed(0).build().unwrap();
+    chip.load_rom(&Rom::from_bytes(program.bytes).unwrap())
+        .unwrap();
+    chip
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(0x10000));
+    group.bench_function("all opcodes", |b| {
+        b.iter(|| {
+            (0..=u16::MAX)
+                .filter_map(|opcode| Instruction::decode(black_box(opcode)))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(1));
+    let mut chip = load(ARITHMETIC);
+    group.bench_function("arithmetic", |b| b.iter(|| chip.step().unwrap()));
+    group.finish();
+}
+
+fn draw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(1));
+    // Draws the 5 rows of the digit 0 at each position.
+    for &(name, x, y) in &[("aligned", 8, 8), ("unaligned", 13, 8), ("clipped", 60, 29)] {
+        let mut chip = CHIP8::new();
+        // LD F, V2
+        chip.execute(0xF229).unwrap();
+        chip.variable_mut()[0] = x;
+        chip.variable_mut()[1] = y;
+        group.bench_function(name, |b| b.iter(|| chip.execute(0xD015).unwrap()));
+    }
+    group.finish();
+}
+
+fn frame(c: &mut Criterion) {
+    let mut runner = Runner::new(load(BOUNCE), Headless, Headless, Headless);
+    c.bench_function("frame/bounce", |b| b.iter(|| runner.frame().unwrap()));
+}
+
+criterion_group!(benches, decode, step, draw, frame);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+//! Executes arbitrary opcodes one after another, whatever memory holds.
+
+#![no_main]
+
+use chip8_core_fuzz::{machine, Before};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (config, opcodes) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut chip = machine(*config);
+    for pair in opcodes.chunks_exact(2) {
+        let opcode = u16::from_be_bytes([pair[0], pair[1]]);
+        let before = Before::new(&chip);
+        if chip.execute(opcode).is_ok() {
+            before.check(&chip, opcode);
+        }
+    }
+});
@@ -0,0 +1,44 @@
+//! Runs arbitrary ROMs for a bounded number of instructions, with the timers and keypad going.
+
+#![no_main]
+
+use chip8_core::rom::Rom;
+use chip8_core_fuzz::{machine, Before};
+use libfuzzer_sys::fuzz_target;
+
+/// Instructions each ROM runs for at most.
+const STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let (config, rom) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let rom = match Rom::from_bytes(rom.to_vec()) {
+        Ok(rom) => rom,
+        Err(_) => return,
+    };
+    let mut chip = machine(*config);
+    if chip.load_rom(&rom).is_err() {
+        return;
+    }
+    let steps_per_frame = chip.config().steps_per_frame() as usize;
+    for step in 1..=STEPS {
+        let address = chip.program_counter();
+        let opcode = chip.opcode_at(address);
+        let before = Before::new(&chip);
+        if chip.step().is_err() {
+            break;
+        }
+        if let Some(opcode) = opcode {
+            before.check(&chip, opcode);
+        }
+        if step % steps_per_frame == 0 {
+            chip.tick();
+            // Press and release the key the program last read, for programs that wait for one.
+            let key = chip.variable()[0] & 0xF;
+            let pressed = chip.keyboard().is_pressed(key);
+            chip.keyboard_mut().set(key, !pressed);
+        }
+    }
+});
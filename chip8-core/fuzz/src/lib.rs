@@ -0,0 +1,94 @@
+//! The machine the fuzz targets run, and the invariants they check after every instruction.
+
+use chip8_core::builder::MAX_MEMORY_SIZE;
+use chip8_core::host::MemoryFlags;
+use chip8_core::instruction::Instruction;
+use chip8_core::quirks::Quirks;
+use chip8_core::{CHIP8, STACK_DEPTH};
+
+/// Builds a machine from one byte of fuzz input: the low bits pick the memory size and whether
+/// the interpreter area is protected, and the high bits the quirks.
+pub fn machine(config: u8) -> CHIP8 {
+    let memory_size = [MAX_MEMORY_SIZE, 0x800, 0x300, 0x202][(config & 0x03) as usize];
+    CHIP8::builder()
+        .memory_size(memory_size)
+        .protect_interpreter(config & 0x04 != 0)
+        .quirks(Quirks {
+            vf_reset: config & 0x10 != 0,
+            shift_in_place: config & 0x20 != 0,
+            keep_index: config & 0x40 != 0,
+            jump_vx: config & 0x80 != 0,
+        })
+        .seed(0)
+        .flags(MemoryFlags::default())
+        .build()
+        .unwrap()
+}
+
+/// What an instruction may not change beyond, taken before it executes.
+pub struct Before {
+    program_counter: u16,
+    depth: usize,
+    returns_to: Option<u16>,
+    variable: [u8; 16],
+}
+
+impl Before {
+    pub fn new(chip: &CHIP8) -> Self {
+        Before {
+            program_counter: chip.program_counter(),
+            depth: chip.stack().len(),
+            returns_to: chip.stack().last().copied(),
+            variable: *chip.variable(),
+        }
+    }
+
+    /// Checks the machine after `opcode` executed successfully.
+    pub fn check(&self, chip: &CHIP8, opcode: u16) {
+        let instruction = Instruction::decode(opcode).expect("only valid opcodes execute");
+
+        // The stack and the backtrace move together, and by at most one call.
+        assert_eq!(chip.stack().len(), chip.calls().len());
+        let depth = chip.stack().len();
+        assert!(depth + 1 >= self.depth && depth <= self.depth + 1);
+        assert!(depth <= STACK_DEPTH, "stack depth {}", depth);
+
+        // Instructions that do not jump only move past themselves, or past the next instruction
+        // too. Waiting for a key executes the same instruction again.
+        let jumps = matches!(
+            instruction,
+            Instruction::Return
+                | Instruction::Jump { .. }
+                | Instruction::Call { .. }
+                | Instruction::JumpOffset { .. }
+        );
+        let pc = chip.program_counter();
+        let moved = pc.wrapping_sub(self.program_counter);
+        assert!(jumps || [0, 2, 4, 6].contains(&moved), "moved by {}", moved);
+
+        // Jumps go where the instruction says, so the program counter only leaves even addresses
+        // when a program jumps to an odd one.
+        match instruction {
+            Instruction::Jump { nnn } | Instruction::Call { nnn } => assert_eq!(pc, nnn),
+            Instruction::Return => assert_eq!(Some(pc), self.returns_to),
+            Instruction::JumpOffset { nnn } => {
+                let register = if chip.config().quirks.jump_vx {
+                    (nnn >> 8) as usize
+                } else {
+                    0
+                };
+                assert_eq!(pc, nnn + self.variable[register] as u16);
+            }
+            _ if self.program_counter % 2 == 0 => assert_eq!(pc % 2, 0, "pc {:#05X}", pc),
+            _ => {}
+        }
+
+        // Memory is only accessed where there is some.
+        let size = chip.config().memory_size;
+        assert!(chip
+            .accesses()
+            .iter()
+            .all(|access| (access.address as usize) < size));
+        assert_eq!(chip.memory().len(), MAX_MEMORY_SIZE);
+    }
+}
@@ -0,0 +1,304 @@
+//! Static analysis for bugs that span several instructions, which [`Rom::check`] can't see.
+//!
+//! Like the disassembler, the analysis tracks where I points along each straight line of code, so
+//! it only knows about addresses set by `ANNN` and the font.
+//!
+//! [`Rom::check`]: crate::rom::Rom::check
+
+use crate::disasm::{self, Region};
+use crate::instruction::Instruction;
+use crate::rom::{Diagnostic, Rom, Severity, PROGRAM_START};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Fewest instructions in a row that are reported as unreachable code.
+const MIN_UNREACHABLE: usize = 3;
+
+/// What I is known to point at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Index {
+    Unknown,
+    Fixed(u16),
+    /// A font character, set by `FX29` or `FX30`.
+    Font,
+    /// Wherever `FX55` or `FX65` left it, which differs between interpreters.
+    Advanced,
+}
+
+/// Returns the reachable instructions of `rom` in order of address.
+fn code(rom: &Rom) -> Vec<(usize, Instruction)> {
+    let mut code = BTreeMap::new();
+    rom.walk(|address, opcode| {
+        if let Some(instruction) = opcode.and_then(Instruction::decode) {
+            code.insert(address, instruction);
+        }
+    });
+    code.into_iter().collect()
+}
+
+/// Returns `true` if the subroutine at `target` can reach a return, or leaves in a way that can't
+/// be followed.
+fn returns(rom: &Rom, target: u16) -> bool {
+    let mut returns = false;
+    rom.walk_from(target as usize, false, |_, opcode| {
+        match opcode.and_then(Instruction::decode) {
+            Some(Instruction::Return)
+            | Some(Instruction::Exit)
+            | Some(Instruction::JumpOffset { .. }) => returns = true,
+            _ => (),
+        }
+    });
+    returns
+}
+
+/// Analyzes `rom` for unreachable code, subroutines that never return, reads past the end of the
+/// ROM, writes into the font, and code that behaves differently depending on quirks of the
+/// interpreter.
+pub fn analyze(rom: &Rom) -> Vec<Diagnostic> {
+    let mut diagnostics = BTreeSet::new();
+    let mut warn = |address: usize, message: String| {
+        diagnostics.insert(Diagnostic::new(
+            Severity::Warning,
+            Some(address as u16),
+            message,
+        ));
+    };
+    let end = rom.end();
+    let code = code(rom);
+
+    let mut calls = BTreeMap::new();
+    let mut reads = Vec::new();
+    let mut writes: Vec<Range<usize>> = Vec::new();
+    let mut index = Index::Unknown;
+    let mut previous_end = 0;
+    for &(address, instruction) in &code {
+        if address != previous_end {
+            index = Index::Unknown;
+        }
+        previous_end = address + instruction.len() as usize;
+
+        let opcode = rom.opcode_at(address).unwrap_or(0);
+        let uses_index = matches!(
+            instruction,
+            Instruction::Draw { .. }
+                | Instruction::Bcd { .. }
+                | Instruction::Store { .. }
+                | Instruction::Load { .. }
+                | Instruction::AddIndex { .. }
+        );
+        if uses_index && index == Index::Advanced {
+            warn(
+                address,
+                format!(
+                    "{:04X} depends on the load/store quirk: I is advanced past the registers \
+                     by FX55 and FX65 on COSMAC, but left alone by SUPER-CHIP",
+                    opcode
+                ),
+            );
+        }
+
+        let (writing, len) = match instruction {
+            Instruction::Bcd { .. } => (true, 3),
+            Instruction::Store { x } => (true, x as usize + 1),
+            Instruction::Load { x } => (false, x as usize + 1),
+            Instruction::Draw { n, .. } => (false, if n == 0 { 32 } else { n as usize }),
+            _ => (false, 0),
+        };
+        if len > 0 {
+            match index {
+                Index::Fixed(nnn) if writing && nnn < PROGRAM_START => warn(
+                    address,
+                    format!(
+                        "{:04X} writes into interpreter memory at {:#05X}, which holds the font",
+                        opcode, nnn
+                    ),
+                ),
+                Index::Font if writing => warn(
+                    address,
+                    format!(
+                        "{:04X} writes over the font character that I points to",
+                        opcode
+                    ),
+                ),
+                Index::Fixed(nnn) if writing => writes.push(nnn as usize..nnn as usize + len),
+                Index::Fixed(nnn) => {
+                    reads.push((address, opcode, nnn as usize..nnn as usize + len))
+                }
+                _ => (),
+            }
+        }
+
+        index = match instruction {
+            Instruction::SetIndex { nnn } => Index::Fixed(nnn),
+            Instruction::FontChar { .. } | Instruction::BigFontChar { .. } => Index::Font,
+            Instruction::Store { .. } | Instruction::Load { .. } => Index::Advanced,
+            Instruction::AddIndex { .. }
+            | Instruction::LoadLongIndex
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. } => Index::Unknown,
+            _ => index,
+        };
+
+        match instruction {
+            Instruction::Call { nnn } if nnn >= PROGRAM_START => {
+                calls.entry(nnn).or_insert(address);
+            }
+            Instruction::ShiftRight { x, y } | Instruction::ShiftLeft { x, y } if x != y => warn(
+                address,
+                format!(
+                    "{:04X} depends on the shift quirk: V{:X} is shifted into V{:X} on COSMAC, \
+                     but SUPER-CHIP shifts V{:X} in place",
+                    opcode, y, x, x
+                ),
+            ),
+            Instruction::JumpOffset { nnn } if nnn >> 8 != 0 => warn(
+                address,
+                format!(
+                    "{:04X} depends on the jump quirk: COSMAC adds V0 to {:#05X}, but SUPER-CHIP \
+                     adds V{:X}",
+                    opcode,
+                    nnn,
+                    nnn >> 8
+                ),
+            ),
+            _ => (),
+        }
+    }
+
+    for (address, opcode, range) in reads {
+        if range.end > end
+            && !writes
+                .iter()
+                .any(|write| write.start < range.end && range.start < write.end)
+        {
+            warn(
+                address,
+                format!(
+                    "{:04X} reads {} bytes at {:#05X}, past the end of the ROM at {:#05X}, \
+                     from memory the program never writes",
+                    opcode,
+                    range.len(),
+                    range.start,
+                    end
+                ),
+            );
+        }
+    }
+
+    for (target, address) in calls {
+        if !returns(rom, target) {
+            warn(
+                address,
+                format!(
+                    "the subroutine at {:#05X} never returns, so its return address is left on \
+                     the stack",
+                    target
+                ),
+            );
+        }
+    }
+
+    // Look for instructions hidden in what the disassembler considers data.
+    let data = rom.data();
+    let regions = disasm::regions(rom);
+    let mut run: Vec<Instruction> = Vec::new();
+    let mut offset = 0;
+    while offset <= data.len() {
+        let instruction = (offset + 2 <= data.len()
+            && regions[offset] == Region::Data
+            && regions[offset + 1] == Region::Data)
+            .then(|| rom.opcode_at(PROGRAM_START as usize + offset))
+            .flatten()
+            .and_then(Instruction::decode)
+            .filter(|i| {
+                !matches!(
+                    i,
+                    Instruction::MachineCall { .. } | Instruction::LoadLongIndex
+                )
+            });
+        match instruction {
+            Some(instruction) => run.push(instruction),
+            None => {
+                let last = run.last().copied();
+                if run.len() >= MIN_UNREACHABLE
+                    && matches!(
+                        last,
+                        Some(Instruction::Return) | Some(Instruction::Jump { .. })
+                    )
+                {
+                    let start = PROGRAM_START as usize + offset - run.len() * 2;
+                    warn(
+                        start,
+                        format!(
+                            "{} instructions ending in {} look like code, but are never reached",
+                            run.len(),
+                            last.map(|i| i.mnemonic()).unwrap_or_default()
+                        ),
+                    );
+                }
+                run.clear();
+            }
+        }
+        offset += 2;
+    }
+
+    diagnostics.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(data: &[u8]) -> Vec<String> {
+        super::analyze(&Rom::from_bytes(data.to_vec()).unwrap())
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn clean_rom() {
+        // 0x200: LD I, 0x20A; 0x202: DRW V0, V0, 1; 0x204: CALL 0x208; 0x206: JP 0x206;
+        // 0x208: RET; 0x20A: sprite
+        assert!(
+            analyze(&[0xA2, 0x0A, 0xD0, 0x01, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE, 0xFF]).is_empty()
+        );
+    }
+
+    #[test]
+    fn bugs() {
+        // 0x200: CALL 0x20A; 0x202: LD I, 0x080; 0x204: LD B, V0; 0x206: LD I, 0x300;
+        // 0x208: DRW V0, V0, 4; 0x20A: JP 0x20A; 0x20C: SHR V1, V2; 0x20E: ADD V0, 1; 0x210: RET
+        let diagnostics = analyze(&[
+            0x22, 0x0A, 0xA0, 0x80, 0xF0, 0x33, 0xA3, 0x00, 0xD0, 0x04, 0x12, 0x0A, 0x81, 0x26,
+            0x70, 0x01, 0x00, 0xEE,
+        ]);
+        assert_eq!(
+            diagnostics,
+            [
+                "warning: 0x200: the subroutine at 0x20A never returns, so its return address \
+                 is left on the stack",
+                "warning: 0x204: F033 writes into interpreter memory at 0x080, which holds the font",
+                "warning: 0x208: D004 reads 4 bytes at 0x300, past the end of the ROM at 0x212, \
+                 from memory the program never writes",
+                "warning: 0x20C: 3 instructions ending in RET look like code, but are never \
+                 reached",
+            ]
+        );
+    }
+
+    #[test]
+    fn quirks() {
+        // 0x200: SHL V1, V2; 0x202: LD [I], V3; 0x204: DRW V0, V0, 1; 0x206: JP V0, 0x300
+        let diagnostics = analyze(&[0x81, 0x2E, 0xF3, 0x55, 0xD0, 0x01, 0xB3, 0x00]);
+        assert_eq!(diagnostics.len(), 3);
+        assert!(diagnostics[0].contains("shift quirk: V2 is shifted into V1"));
+        assert!(diagnostics[1].contains("load/store quirk"));
+        assert!(
+            diagnostics[2].contains("jump quirk: COSMAC adds V0 to 0x300, but SUPER-CHIP adds V3")
+        );
+    }
+}
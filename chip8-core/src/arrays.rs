@@ -0,0 +1,69 @@
+//! Serializing byte arrays longer than serde supports, such as memory: as a string of bytes in
+//! binary formats, and as a sequence of numbers in formats meant to be read.
+//!
+//! Used with `#[serde(with = "crate::arrays")]`.
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S, const N: usize>(array: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        serializer.collect_seq(array)
+    } else {
+        serializer.serialize_bytes(array)
+    }
+}
+
+pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values = Vec::<u8>::deserialize(deserializer)?;
+    let len = values.len();
+    values
+        .try_into()
+        .map_err(|_| D::Error::custom(format!("expected {} elements, found {}", N, len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rom::Rom;
+    use crate::CHIP8;
+
+    #[test]
+    fn round_trip() {
+        // 0x200: LD V0, 7; LD F, V0; DRW V0, V0, 5; CALL 0x208; JP 0x208
+        let rom = [0x60, 0x07, 0xF0, 0x29, 0xD0, 0x05, 0x22, 0x08, 0x12, 0x08];
+        let mut chip = CHIP8::builder().seed(3).build().unwrap();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        for _ in 0..5 {
+            chip.step().unwrap();
+        }
+        chip.keyboard.set(0xA, true);
+        chip.timer.sound_timer = 4;
+
+        let json = serde_json::to_string(&chip).unwrap();
+        assert!(json.contains(r#""screen":["#));
+        let mut copy: CHIP8 = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.memory[..], chip.memory[..]);
+        assert_eq!(copy.display.text(), chip.display.text());
+        assert_eq!(copy.stack, chip.stack);
+        assert_eq!(copy.calls, chip.calls);
+        assert!(copy.keyboard.is_pressed(0xA));
+        assert_eq!(copy.timer.sound_timer, 4);
+        assert_eq!(copy.config, chip.config);
+        assert_eq!(copy.rng.next_u8(), chip.clone().rng.next_u8());
+
+        let error = serde_json::from_str::<CHIP8>(&json.replacen("[0,", "[", 1))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("expected 4096 elements"));
+    }
+}
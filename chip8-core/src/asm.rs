@@ -0,0 +1,616 @@
+//! Assembler for Octo-style CHIP-8 source.
+//!
+//! Supported syntax:
+//!
+//! * Labels (`: main`), constants (`:const lives 3`), register aliases (`:alias x v2`), macros
+//!   (`:macro add-to r n { r += n }`) and `:org`.
+//! * Statements such as `v0 := 5`, `v1 += v2`, `v3 := random 0xFF`, `i := sprite`,
+//!   `i := hex v0`, `sprite v0 v1 5`, `delay := v0`, `jump main`, `return` and calls by naming a
+//!   label.
+//! * `if v0 == 3 then ...`, `if v0 != v1 begin ... else ... end`, `if v0 key then ...`, and
+//!   `loop ... while v0 != 0 ... again`.
+//! * Bare numbers, which are emitted as bytes for sprites and other data.
+//!
+//! Numbers are decimal, `0x` hexadecimal or `0b` binary, and may be negative.
+
+use crate::rom::PROGRAM_START;
+use crate::symbols::Symbols;
+use std::collections::{HashMap, VecDeque};
+
+/// A word of source code and the line it came from.
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// Splits source into tokens, dropping `#` comments.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    source
+        .lines()
+        .enumerate()
+        .flat_map(|(number, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |text| Token {
+                text: text.to_string(),
+                line: number + 1,
+            })
+        })
+        .collect()
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary number, which may be negative.
+fn parse_literal(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// A user-defined macro.
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// A control structure waiting for its end.
+enum Block {
+    /// `if ... begin`, with the jump taken when the condition is false.
+    If(u16),
+    /// `else`, with the jump over the else branch.
+    Else(u16),
+    /// `loop`, with its start and the jumps out of it made by `while`.
+    Loop(u16, Vec<u16>),
+}
+
+/// The result of assembling a program.
+pub struct Program {
+    /// The program, to be loaded at 0x200.
+    pub bytes: Vec<u8>,
+    /// The address of every label.
+    pub symbols: Symbols,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    /// The line of the last token taken, for errors.
+    line: usize,
+    bytes: Vec<u8>,
+    /// Address of the next byte to be emitted.
+    address: u16,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    /// Addresses of instructions whose low 12 bits are the address of a label not yet defined.
+    fixups: Vec<(u16, String, usize)>,
+    blocks: Vec<Block>,
+}
+
+impl Assembler {
+    fn error<T>(&self, message: String) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line, message))
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of file".to_string()),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected `{}`, found `{}`", expected, token));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn register(&self, text: &str) -> Option<u8> {
+        if let Some(&x) = self.aliases.get(text) {
+            return Some(x);
+        }
+        let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+        match u8::from_str_radix(digit, 16) {
+            Ok(x) if digit.len() == 1 => Some(x),
+            _ => None,
+        }
+    }
+
+    fn next_register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        match self.register(&token) {
+            Some(x) => Ok(x),
+            None => self.error(format!("expected a register, found `{}`", token)),
+        }
+    }
+
+    fn value(&self, text: &str) -> Option<i32> {
+        parse_literal(text).or_else(|| self.constants.get(text).copied())
+    }
+
+    /// Takes a number or constant that must lie in `min..=max`.
+    fn next_value(&mut self, min: i32, max: i32) -> Result<i32, String> {
+        let token = self.next()?;
+        match self.value(&token) {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            Some(_) => self.error(format!("`{}` is out of range", token)),
+            None => self.error(format!("expected a number, found `{}`", token)),
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<u16, String> {
+        Ok(self.next_value(-128, 255)? as u16 & 0xFF)
+    }
+
+    /// Takes an address given as a number, constant or label, which may be defined later.
+    /// `opcode` is the instruction the address is combined with.
+    fn next_address(&mut self, opcode: u16) -> Result<u16, String> {
+        let token = self.next()?;
+        if let Some(value) = self.value(&token) {
+            if !(0..=0xFFF).contains(&value) {
+                return self.error(format!("`{}` is out of range", token));
+            }
+            return Ok(opcode | value as u16);
+        }
+        match self.labels.get(&token) {
+            Some(&address) => Ok(opcode | address),
+            None => {
+                self.fixups.push((self.address, token, self.line));
+                Ok(opcode)
+            }
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), String> {
+        let offset = (self.address - PROGRAM_START) as usize;
+        if offset >= 0x1000 - PROGRAM_START as usize {
+            return self.error("program does not fit in memory".to_string());
+        }
+        if self.bytes.len() <= offset {
+            self.bytes.resize(offset + 1, 0);
+        }
+        self.bytes[offset] = byte;
+        self.address += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), String> {
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    /// Sets the address in the low 12 bits of the instruction at `at`.
+    fn patch(&mut self, at: u16, target: u16) {
+        let offset = (at - PROGRAM_START) as usize;
+        self.bytes[offset] = (self.bytes[offset] & 0xF0) | (target >> 8) as u8;
+        self.bytes[offset + 1] = target as u8;
+    }
+
+    /// Parses a condition, returning the instruction that skips when it is true.
+    fn condition(&mut self) -> Result<u16, String> {
+        let x = self.next_register()? as u16;
+        let op = self.next()?;
+        if op == "key" {
+            return Ok(0xE09E | x << 8);
+        } else if op == "-key" {
+            return Ok(0xE0A1 | x << 8);
+        }
+        let operand = self.next()?;
+        let opcode = match (op.as_str(), self.register(&operand)) {
+            ("==", Some(y)) => 0x5000 | x << 8 | (y as u16) << 4,
+            ("!=", Some(y)) => 0x9000 | x << 8 | (y as u16) << 4,
+            ("==" | "!=", None) => {
+                let value = match self.value(&operand) {
+                    Some(value) if (-128..=255).contains(&value) => value as u16 & 0xFF,
+                    _ => return self.error(format!("expected a byte, found `{}`", operand)),
+                };
+                let base = if op == "==" { 0x3000 } else { 0x4000 };
+                base | x << 8 | value
+            }
+            _ => return self.error(format!("unsupported comparison `{}`", op)),
+        };
+        Ok(opcode)
+    }
+
+    /// Returns the instruction that skips when the condition of `skip` is false.
+    fn invert(skip: u16) -> u16 {
+        match skip & 0xF0FF {
+            0xE09E => skip ^ 0x003F,
+            0xE0A1 => skip ^ 0x003F,
+            _ => match skip >> 12 {
+                0x3 => skip + 0x1000,
+                0x4 => skip - 0x1000,
+                0x5 => skip + 0x4000,
+                _ => skip - 0x4000,
+            },
+        }
+    }
+
+    /// Assembles the statement starting with `token`.
+    fn statement(&mut self, token: String) -> Result<(), String> {
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                if self.labels.insert(name.clone(), self.address).is_some() {
+                    return self.error(format!("label `{}` is defined twice", name));
+                }
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next_value(-0x8000, 0xFFFF)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let x = self.next_register()?;
+                self.aliases.insert(name, x);
+            }
+            ":macro" => {
+                let name = self.next()?;
+                let mut parameters = Vec::new();
+                loop {
+                    match self.next()?.as_str() {
+                        "{" => break,
+                        parameter => parameters.push(parameter.to_string()),
+                    }
+                }
+                let mut body = Vec::new();
+                let mut depth = 1;
+                while let Some(token) = self.tokens.pop_front() {
+                    match token.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => (),
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    body.push(token);
+                }
+                if depth != 0 {
+                    return self.error(format!("macro `{}` is missing `}}`", name));
+                }
+                self.macros.insert(name, Macro { parameters, body });
+            }
+            ":org" => {
+                let address = self.next_value(PROGRAM_START as i32, 0xFFF)? as u16;
+                self.address = address;
+            }
+            ":call" => {
+                let opcode = self.next_address(0x2000)?;
+                self.emit(opcode)?;
+            }
+            "clear" => self.emit(0x00E0)?,
+            "return" | ";" => self.emit(0x00EE)?,
+            "scroll-down" => {
+                let n = self.next_value(0, 15)? as u16;
+                self.emit(0x00C0 | n)?;
+            }
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "jump" => {
+                let opcode = self.next_address(0x1000)?;
+                self.emit(opcode)?;
+            }
+            "jump0" => {
+                let opcode = self.next_address(0xB000)?;
+                self.emit(opcode)?;
+            }
+            "sprite" => {
+                let x = self.next_register()? as u16;
+                let y = self.next_register()? as u16;
+                let n = self.next_value(0, 15)? as u16;
+                self.emit(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "bcd" | "save" | "load" | "saveflags" | "loadflags" => {
+                let x = self.next_register()? as u16;
+                let opcode = match token.as_str() {
+                    "bcd" => 0xF033,
+                    "save" => 0xF055,
+                    "load" => 0xF065,
+                    "saveflags" => 0xF075,
+                    _ => 0xF085,
+                };
+                self.emit(opcode | x << 8)?;
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.next_register()? as u16;
+                let opcode = if token == "delay" { 0xF015 } else { 0xF018 };
+                self.emit(opcode | x << 8)?;
+            }
+            "i" => self.index()?,
+            "if" => {
+                let skip = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit(Assembler::invert(skip))?,
+                    "begin" => {
+                        self.emit(skip)?;
+                        self.blocks.push(Block::If(self.address));
+                        self.emit(0x1000)?;
+                    }
+                    other => {
+                        return self.error(format!("expected `then` or `begin`, found `{}`", other))
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If(jump)) => {
+                    self.blocks.push(Block::Else(self.address));
+                    self.emit(0x1000)?;
+                    self.patch(jump, self.address);
+                }
+                _ => return self.error("`else` without `if ... begin`".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump)) | Some(Block::Else(jump)) => self.patch(jump, self.address),
+                _ => return self.error("`end` without `if ... begin`".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop(self.address, Vec::new())),
+            "while" => {
+                let skip = self.condition()?;
+                self.emit(skip)?;
+                let jump = self.address;
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|b| matches!(b, Block::Loop(..)))
+                {
+                    Some(Block::Loop(_, breaks)) => breaks.push(jump),
+                    _ => return self.error("`while` outside of a loop".to_string()),
+                }
+                self.emit(0x1000)?;
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop(start, breaks)) => {
+                    self.emit(0x1000 | start)?;
+                    for jump in breaks {
+                        self.patch(jump, self.address);
+                    }
+                }
+                _ => return self.error("`again` without `loop`".to_string()),
+            },
+            _ => {
+                if let Some(x) = self.register(&token) {
+                    self.assignment(x as u16)?;
+                } else if let Some(value) = self.value(&token) {
+                    if !(-128..=255).contains(&value) {
+                        return self.error(format!("`{}` does not fit in a byte", token));
+                    }
+                    self.emit_byte(value as u8)?;
+                } else if let Some(called) = self.macros.get(&token) {
+                    let mut arguments = HashMap::new();
+                    for parameter in called.parameters.clone() {
+                        arguments.insert(parameter, self.next()?);
+                    }
+                    let called = &self.macros[&token];
+                    for body_token in called.body.iter().rev() {
+                        let text = arguments.get(&body_token.text).unwrap_or(&body_token.text);
+                        self.tokens.push_front(Token {
+                            text: text.clone(),
+                            line: self.line,
+                        });
+                    }
+                } else if token.starts_with(':') || token.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    return self.error(format!("unknown directive `{}`", token));
+                } else {
+                    // Anything else names a subroutine to call, which may be defined later.
+                    self.tokens.push_front(Token {
+                        text: token,
+                        line: self.line,
+                    });
+                    let opcode = self.next_address(0x2000)?;
+                    self.emit(opcode)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Assembles a statement starting with `i`.
+    fn index(&mut self) -> Result<(), String> {
+        match self.next()?.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let opcode = if self.next()? == "hex" {
+                        0xF029
+                    } else {
+                        0xF030
+                    };
+                    let x = self.next_register()? as u16;
+                    self.emit(opcode | x << 8)
+                }
+                _ => {
+                    let opcode = self.next_address(0xA000)?;
+                    self.emit(opcode)
+                }
+            },
+            "+=" => {
+                let x = self.next_register()? as u16;
+                self.emit(0xF01E | x << 8)
+            }
+            op => self.error(format!("unsupported operator `i {}`", op)),
+        }
+    }
+
+    /// Assembles a statement that assigns to VX.
+    fn assignment(&mut self, x: u16) -> Result<(), String> {
+        let op = self.next()?;
+        let operand = self.next()?;
+        if let Some(y) = self.register(&operand) {
+            let y = y as u16;
+            let low = match op.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("unsupported operator `{}`", op)),
+            };
+            return self.emit(0x8000 | x << 8 | y << 4 | low);
+        }
+        let opcode = match (op.as_str(), operand.as_str()) {
+            (":=", "delay") => 0xF007 | x << 8,
+            (":=", "key") => 0xF00A | x << 8,
+            (":=", "random") => 0xC000 | x << 8 | self.next_byte()?,
+            (":=" | "+=" | "-=", _) => {
+                self.tokens.push_front(Token {
+                    text: operand,
+                    line: self.line,
+                });
+                let value = self.next_byte()?;
+                match op.as_str() {
+                    ":=" => 0x6000 | x << 8 | value,
+                    "+=" => 0x7000 | x << 8 | value,
+                    _ => 0x7000 | x << 8 | (0x100 - value) & 0xFF,
+                }
+            }
+            _ => return self.error(format!("unsupported operator `{}`", op)),
+        };
+        self.emit(opcode)
+    }
+}
+
+/// Assembles Octo-style source code.
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        line: 1,
+        bytes: Vec::new(),
+        address: PROGRAM_START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    while !assembler.tokens.is_empty() {
+        let token = assembler.next()?;
+        assembler.statement(token)?;
+    }
+    if let Some(block) = assembler.blocks.last() {
+        let missing = match block {
+            Block::If(_) | Block::Else(_) => "end",
+            Block::Loop(..) => "again",
+        };
+        return assembler.error(format!("missing `{}`", missing));
+    }
+    for (at, name, line) in std::mem::take(&mut assembler.fixups) {
+        match assembler.labels.get(&name) {
+            Some(&address) => assembler.patch(at, address),
+            None => return Err(format!("line {}: undefined label `{}`", line, name)),
+        }
+    }
+
+    let mut symbols = Symbols::default();
+    for (name, &address) in &assembler.labels {
+        symbols.insert(name, address);
+    }
+    Ok(Program {
+        bytes: assembler.bytes,
+        symbols,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions() {
+        let program = assemble(
+            ": main
+               clear
+               v0 := 5  v1 += -1  v2 := random 0x0F  v3 += v4  v5 =- v6
+               i := sprite  i += v0  i := hex v1  delay := v0  v7 := key
+               sprite v0 v1 2
+               if v0 == 5 then v1 := 0
+               jump main
+             : sprite
+               0b11110000 0x90",
+        )
+        .unwrap();
+        assert_eq!(
+            program.bytes,
+            [
+                0x00, 0xE0, 0x60, 0x05, 0x71, 0xFF, 0xC2, 0x0F, 0x83, 0x44, 0x85, 0x67, 0xA2, 0x1E,
+                0xF0, 0x1E, 0xF1, 0x29, 0xF0, 0x15, 0xF7, 0x0A, 0xD0, 0x12, 0x40, 0x05, 0x61, 0x00,
+                0x12, 0x00, 0xF0, 0x90,
+            ]
+        );
+        assert_eq!(program.symbols.address("sprite"), Some(0x21E));
+    }
+
+    #[test]
+    fn control_flow_and_macros() {
+        let program = assemble(
+            ":const limit 3
+             :alias counter v2
+             :macro bump r { r += 1 }
+             : main
+               loop
+                 bump counter
+                 while counter != limit
+                 if counter key begin
+                   draw
+                 else
+                   ;
+                 end
+               again
+             : draw
+               return",
+        )
+        .unwrap();
+        assert_eq!(
+            program.bytes,
+            [
+                0x72, 0x01, 0x42, 0x03, 0x12, 0x12, 0xE2, 0x9E, 0x12, 0x0E, 0x22, 0x12, 0x12, 0x10,
+                0x00, 0xEE, 0x12, 0x00, 0x00, 0xEE,
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            assemble("v0 := 5\njump nowhere").err(),
+            Some("line 2: undefined label `nowhere`".to_string())
+        );
+        assert_eq!(
+            assemble("v0 := 256").err(),
+            Some("line 1: `256` is out of range".to_string())
+        );
+        assert!(assemble("loop v0 += 1").is_err());
+        assert!(assemble(": a : a").is_err());
+    }
+}
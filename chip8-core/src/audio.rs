@@ -0,0 +1,260 @@
+//! An [`Audio`] frontend that records the tone instead of playing it, so that tests can check
+//! when it plays and what it sounds like without an audio device.
+//!
+//! [`Recording`] keeps an [`Event`] for every frame: whether the tone plays, at what pitch, and
+//! the samples it renders for the frame from the XO-CHIP audio pattern, the way a real frontend
+//! would fill its buffer.
+//!
+//! ```
+//! use chip8_core::audio::Recording;
+//! use chip8_core::frontend::{Headless, Runner};
+//! use chip8_core::rom::Rom;
+//! use chip8_core::CHIP8;
+//!
+//! // 0x200: LD V0, 2; LD ST, V0; 0x204: JP 0x204
+//! let mut chip = CHIP8::new();
+//! chip.load_rom(&Rom::from_bytes(vec![0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]).unwrap())
+//!     .unwrap();
+//! let mut runner = Runner::new(chip, Headless, Recording::new(48_000), Headless);
+//! for _ in 0..4 {
+//!     runner.frame().unwrap();
+//! }
+//! assert_eq!(runner.audio.tones(), [0..2]);
+//! assert_eq!(runner.audio.events()[0].samples.len(), 800);
+//! ```
+
+use crate::frontend::Audio;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Frames per second, at which [`Audio::play`] is called.
+const FRAME_RATE: u64 = 60;
+
+/// The pitch at which the pattern plays at [`BASE_RATE`].
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Bits of the pattern played per second at [`DEFAULT_PITCH`].
+pub const BASE_RATE: f64 = 4000.0;
+
+/// The pattern played unless a program loads its own: a square wave of 250 Hz at the default
+/// pitch.
+pub const DEFAULT_PATTERN: [u8; 16] = [
+    0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00,
+];
+
+/// The level of a sample for a set bit of the pattern, and negated for a clear one.
+pub const AMPLITUDE: i16 = 8192;
+
+/// Returns the bits of the pattern played per second at `pitch`, which doubles every 48 steps.
+pub fn playback_rate(pitch: u8) -> f64 {
+    /// The ratio between two steps of pitch, the 48th root of 2.
+    const STEP: f64 = 1.014_545_334_937_723_5;
+
+    let steps = pitch as i32 - DEFAULT_PITCH as i32;
+    let mut rate = BASE_RATE;
+    for _ in 0..steps.rem_euclid(48) {
+        rate *= STEP;
+    }
+    let octaves = steps.div_euclid(48);
+    if octaves >= 0 {
+        rate * (1u32 << octaves) as f64
+    } else {
+        rate / (1u32 << -octaves) as f64
+    }
+}
+
+/// What the audio did during one frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    /// The frame, counted from 0 when the recording started.
+    pub frame: u64,
+    /// Whether the tone played.
+    pub sounding: bool,
+    /// The pitch it played at.
+    pub pitch: u8,
+    /// The samples rendered for the frame, all 0 while silent.
+    pub samples: Vec<i16>,
+}
+
+/// Records the tone of every frame.
+///
+/// The machine only tells its frontend whether the tone plays, so the pattern and pitch are set
+/// on the recording with [`Recording::set_pattern`] and [`Recording::set_pitch`], as a frontend
+/// for XO-CHIP would when a program executes `F002` and `FX3A`.
+#[derive(Clone, Debug)]
+pub struct Recording {
+    sample_rate: u32,
+    pattern: [u8; 16],
+    pitch: u8,
+    /// Position in the pattern, in bits. It starts over whenever the tone starts.
+    position: f64,
+    /// Frames recorded, including those whose events were drained.
+    frames: u64,
+    events: Vec<Event>,
+}
+
+impl Recording {
+    /// Creates a recording that renders `sample_rate` samples per second.
+    pub fn new(sample_rate: u32) -> Self {
+        Recording {
+            sample_rate,
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            position: 0.0,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets the pattern played from the next frame on.
+    pub fn set_pattern(&mut self, pattern: [u8; 16]) {
+        self.pattern = pattern;
+    }
+
+    /// Sets the pitch played from the next frame on.
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    /// Returns the events recorded so far, one for every frame.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Removes and returns the events recorded so far, for a frontend that writes them out as it
+    /// goes instead of keeping them all. Later frames go on being counted from where they were.
+    pub fn drain(&mut self) -> Vec<Event> {
+        core::mem::take(&mut self.events)
+    }
+
+    /// Returns the frames during which the tone played, as a range for each time it started.
+    pub fn tones(&self) -> Vec<Range<u64>> {
+        let mut tones: Vec<Range<u64>> = Vec::new();
+        for event in self.events.iter().filter(|event| event.sounding) {
+            match tones.last_mut() {
+                Some(tone) if tone.end == event.frame => tone.end += 1,
+                _ => tones.push(event.frame..event.frame + 1),
+            }
+        }
+        tones
+    }
+
+    /// Returns the samples of every frame, one after the other.
+    pub fn samples(&self) -> Vec<i16> {
+        self.events
+            .iter()
+            .flat_map(|event| event.samples.iter().copied())
+            .collect()
+    }
+
+    /// Returns how many samples the frame `frame` lasts, so that every second has exactly
+    /// `sample_rate` of them.
+    fn samples_in(&self, frame: u64) -> usize {
+        let rate = self.sample_rate as u64;
+        ((frame + 1) * rate / FRAME_RATE - frame * rate / FRAME_RATE) as usize
+    }
+}
+
+impl Audio for Recording {
+    fn play(&mut self, sounding: bool) {
+        let frame = self.frames;
+        self.frames += 1;
+        let len = self.samples_in(frame);
+        let samples = if sounding {
+            let step = playback_rate(self.pitch) / self.sample_rate as f64;
+            (0..len)
+                .map(|_| {
+                    let bit = self.position as usize % 128;
+                    self.position = (self.position + step) % 128.0;
+                    if self.pattern[bit / 8] & 0x80 >> (bit % 8) != 0 {
+                        AMPLITUDE
+                    } else {
+                        -AMPLITUDE
+                    }
+                })
+                .collect()
+        } else {
+            self.position = 0.0;
+            alloc::vec![0; len]
+        };
+        self.events.push(Event {
+            frame,
+            sounding,
+            pitch: self.pitch,
+            samples,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pitch() {
+        assert_eq!(playback_rate(DEFAULT_PITCH), BASE_RATE);
+        assert_eq!(playback_rate(DEFAULT_PITCH + 48), 2.0 * BASE_RATE);
+        assert_eq!(playback_rate(DEFAULT_PITCH - 48), BASE_RATE / 2.0);
+        let rate = playback_rate(DEFAULT_PITCH + 24);
+        assert!((rate - 4000.0 * core::f64::consts::SQRT_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn play() {
+        let mut recording = Recording::new(44_100);
+        for &sounding in &[false, true, true, false, true] {
+            recording.play(sounding);
+        }
+        assert_eq!(recording.tones(), [1..3, 4..5]);
+        let lengths: Vec<usize> = recording.events().iter().map(|e| e.samples.len()).collect();
+        assert_eq!(lengths, [735, 735, 735, 735, 735]);
+        // When the samples of a second don't divide into 60 frames, some frames get one more.
+        assert_eq!(Recording::new(1000).samples_in(0), 16);
+        assert_eq!(Recording::new(1000).samples_in(1), 17);
+        assert!(recording.events()[0].samples.iter().all(|&s| s == 0));
+
+        // At 4000 bits a second, each bit of the default pattern lasts 11.025 samples, so the
+        // first 8 set bits last 89 samples. The tone starts over from the first bit after a
+        // silence.
+        let tone = &recording.events()[1].samples;
+        assert!(tone[..89].iter().all(|&s| s == AMPLITUDE));
+        assert_eq!(tone[89], -AMPLITUDE);
+        assert_eq!(recording.events()[4].samples[..12], tone[..12]);
+    }
+
+    #[test]
+    fn drain() {
+        let mut recording = Recording::new(48_000);
+        recording.play(true);
+        recording.play(true);
+        let drained = recording.drain();
+        assert_eq!(drained.len(), 2);
+        assert!(recording.events().is_empty());
+        // The tone carries on where it was, rather than starting over.
+        recording.play(true);
+        let mut whole = Recording::new(48_000);
+        for _ in 0..3 {
+            whole.play(true);
+        }
+        assert_eq!(recording.events()[0].frame, 2);
+        assert_eq!(recording.samples(), whole.samples()[1600..]);
+    }
+
+    #[test]
+    fn pattern() {
+        let mut recording = Recording::new(4000);
+        recording.set_pattern([0x80; 16]);
+        recording.set_pitch(DEFAULT_PITCH + 48);
+        recording.play(true);
+        let event = &recording.events()[0];
+        assert_eq!(event.pitch, DEFAULT_PITCH + 48);
+        // Two bits a sample: every fourth sample starts a byte, and so is set.
+        assert_eq!(
+            event.samples[..8],
+            [
+                AMPLITUDE, -AMPLITUDE, -AMPLITUDE, -AMPLITUDE, AMPLITUDE, -AMPLITUDE, -AMPLITUDE,
+                -AMPLITUDE
+            ]
+        );
+    }
+}
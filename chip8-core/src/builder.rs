@@ -0,0 +1,346 @@
+//! Configuring a machine before it is powered on.
+//!
+//! [`CHIP8::new`] builds the original COSMAC VIP machine. [`CHIP8::builder`] changes any part of
+//! it and checks that the parts fit together:
+//!
+//! ```
+//! use chip8_core::quirks::Quirks;
+//! use chip8_core::CHIP8;
+//!
+//! let chip = CHIP8::builder()
+//!     .quirks(Quirks::SUPER_CHIP)
+//!     .speed(1000)
+//!     .seed(42)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(chip.config().steps_per_frame(), 16);
+//! ```
+
+use crate::font::FONT_SET;
+#[cfg(feature = "std")]
+use crate::host::SystemEntropy;
+use crate::host::{Entropy, FlagStorage};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::rom::PROGRAM_START;
+use crate::variant::Variant;
+use crate::{CHIP8, FONT_START, TIMER_PERIOD};
+use alloc::boxed::Box;
+use core::convert::TryFrom;
+
+/// Largest memory the 12-bit addresses of CHIP-8 instructions can reach.
+pub const MAX_MEMORY_SIZE: usize = 0x1000;
+
+/// How a machine was configured, kept on it for frontends and tools.
+///
+/// A configuration read back with serde, as part of a machine or on its own, is checked as
+/// [`Builder::build`] checks it, so that a machine can't be deserialized into one that doesn't
+/// work.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "RawConfig")]
+pub struct Config {
+    /// The dialect of the programs the machine runs.
+    pub variant: Variant,
+    /// Behaviors that differ between interpreters.
+    pub quirks: Quirks,
+    /// Instructions executed per second.
+    pub speed: u32,
+    /// Bytes of memory, from address 0. Accesses beyond it fail.
+    pub memory_size: usize,
+    /// Address ROMs are loaded at and execution starts from.
+    pub start_address: u16,
+    /// Whether the memory below the start address, which holds the font, is read-only.
+    pub protect_interpreter: bool,
+}
+
+/// A [`Config`] as read, before it is checked.
+#[derive(serde::Deserialize)]
+struct RawConfig {
+    variant: Variant,
+    quirks: Quirks,
+    speed: u32,
+    memory_size: usize,
+    start_address: u16,
+    protect_interpreter: bool,
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = BuildError;
+
+    fn try_from(raw: RawConfig) -> Result<Self, BuildError> {
+        let config = Config {
+            variant: raw.variant,
+            quirks: raw.quirks,
+            speed: raw.speed,
+            memory_size: raw.memory_size,
+            start_address: raw.start_address,
+            protect_interpreter: raw.protect_interpreter,
+        };
+        config.check()?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// Checks that the configuration describes a working machine, with the font where it always
+    /// is.
+    fn check(&self) -> Result<(), BuildError> {
+        if self.variant != Variant::Chip8 {
+            return Err(BuildError::UnsupportedVariant(self.variant));
+        }
+        if self.speed == 0 {
+            return Err(BuildError::ZeroSpeed);
+        }
+        if self.memory_size > MAX_MEMORY_SIZE {
+            return Err(BuildError::MemorySize(self.memory_size));
+        }
+        let font_end = FONT_START as usize + FONT_SET.len();
+        let start = self.start_address as usize;
+        if start < font_end || start >= self.memory_size {
+            return Err(BuildError::StartAddress(self.start_address));
+        }
+        Ok(())
+    }
+
+    /// Returns the instructions executed between two timer updates at this speed.
+    pub fn steps_per_frame(&self) -> u64 {
+        (self.speed as u64 * TIMER_PERIOD.as_micros() as u64 / 1_000_000).max(1)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            variant: Variant::Chip8,
+            quirks: Quirks::CHIP8,
+            speed: 700,
+            memory_size: MAX_MEMORY_SIZE,
+            start_address: PROGRAM_START,
+            protect_interpreter: false,
+        }
+    }
+}
+
+/// Errors from a configuration that does not describe a working machine.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The interpreter does not implement the instructions of the variant.
+    #[error("{0} programs are not supported")]
+    UnsupportedVariant(Variant),
+    /// The speed is 0 instructions per second.
+    #[error("speed must be at least 1 instruction per second")]
+    ZeroSpeed,
+    /// The memory size is larger than the addresses reach.
+    #[error("memory size of {0} bytes is larger than the {MAX_MEMORY_SIZE} bytes addresses reach")]
+    MemorySize(usize),
+    /// The start address overlaps the font, or leaves no memory for a program.
+    #[error("start address {0:#05X} must lie between the end of the font and the end of memory")]
+    StartAddress(u16),
+}
+
+/// Configures a machine, built with [`CHIP8::builder`].
+#[derive(Clone, Debug)]
+pub struct Builder {
+    config: Config,
+    /// Quirks chosen explicitly, rather than those of the variant.
+    quirks: Option<Quirks>,
+    seed: Option<u32>,
+    font: [u8; 80],
+    flags: Option<Box<dyn FlagStorage>>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config::default(),
+            quirks: None,
+            seed: None,
+            font: *FONT_SET,
+            flags: None,
+        }
+    }
+}
+
+impl Builder {
+    /// Sets the dialect of the programs to run, and the quirks they expect unless
+    /// [`Builder::quirks`] is also called.
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.config.variant = variant;
+        self
+    }
+
+    /// Sets the behaviors that differ between interpreters.
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Sets the number of instructions executed per second, 700 by default.
+    pub fn speed(mut self, instructions_per_second: u32) -> Self {
+        self.config.speed = instructions_per_second;
+        self
+    }
+
+    /// Seeds the random number generator, for runs that can be reproduced. Without a seed it is
+    /// seeded from [`SystemEntropy`], or with 0 without the `std`
+    /// feature.
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Seeds the random number generator from `entropy`, such as a hardware generator.
+    pub fn entropy(self, mut entropy: impl Entropy) -> Self {
+        self.seed(entropy.seed())
+    }
+
+    /// Keeps the persistent flags of `FX75` and `FX85` in `storage`. Without storage, those
+    /// instructions are not supported.
+    pub fn flags(mut self, storage: impl FlagStorage + 'static) -> Self {
+        self.flags = Some(Box::new(storage));
+        self
+    }
+
+    /// Sets the bytes of memory, up to [`MAX_MEMORY_SIZE`]. The COSMAC VIP often had only 2KB.
+    pub fn memory_size(mut self, bytes: usize) -> Self {
+        self.config.memory_size = bytes;
+        self
+    }
+
+    /// Replaces the hexadecimal font, 5 bytes for each digit from 0 to F.
+    pub fn font(mut self, font: &[u8; 80]) -> Self {
+        self.font = *font;
+        self
+    }
+
+    /// Sets the address ROMs are loaded at and execution starts from, `0x200` by default. Some
+    /// interpreters, such as the ETI 660's, start at `0x600`.
+    pub fn start_address(mut self, address: u16) -> Self {
+        self.config.start_address = address;
+        self
+    }
+
+    /// Makes the memory below the start address read-only, so that a program overwriting the
+    /// interpreter area or the font fails instead. Off by default, as the original interpreter
+    /// allowed it.
+    pub fn protect_interpreter(mut self, protect: bool) -> Self {
+        self.config.protect_interpreter = protect;
+        self
+    }
+
+    /// Builds the machine, powered on with no ROM loaded.
+    pub fn build(self) -> Result<CHIP8, BuildError> {
+        let mut config = self.config;
+        config.quirks = self
+            .quirks
+            .unwrap_or_else(|| Quirks::for_variant(config.variant));
+        config.check()?;
+
+        let rng = match self.seed {
+            Some(seed) => Rng::new(seed),
+            #[cfg(feature = "std")]
+            None => Rng::new(SystemEntropy.seed()),
+            #[cfg(not(feature = "std"))]
+            None => Rng::new(0),
+        };
+        let mut chip = CHIP8::with_config(config, &self.font, rng);
+        chip.flags = self.flags;
+        Ok(chip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::{Rom, RomError};
+
+    #[test]
+    fn build() {
+        let mut chip = CHIP8::builder()
+            .variant(Variant::Chip8)
+            .memory_size(0x800)
+            .start_address(0x300)
+            .font(&[0xAA; 80])
+            .seed(7)
+            .build()
+            .unwrap();
+        assert_eq!(chip.program_counter, 0x300);
+        assert_eq!(chip.memory[FONT_START as usize], 0xAA);
+        assert_eq!(chip.rng.next_u8(), Rng::new(7).next_u8());
+        assert_eq!(chip.config.quirks, Quirks::CHIP8);
+        assert_eq!(chip.config.steps_per_frame(), 11);
+
+        assert!(matches!(
+            chip.load_rom(&Rom::from_bytes(vec![0; 0x600]).unwrap()),
+            Err(RomError::TooLarge(0x600, 0x500))
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let errors = [
+            (
+                CHIP8::builder().variant(Variant::XoChip),
+                BuildError::UnsupportedVariant(Variant::XoChip),
+            ),
+            (CHIP8::builder().speed(0), BuildError::ZeroSpeed),
+            (
+                CHIP8::builder().memory_size(0x10000),
+                BuildError::MemorySize(0x10000),
+            ),
+            (
+                CHIP8::builder().start_address(0x80),
+                BuildError::StartAddress(0x80),
+            ),
+            (
+                CHIP8::builder().memory_size(0x200),
+                BuildError::StartAddress(0x200),
+            ),
+        ];
+        for (builder, error) in errors {
+            assert_eq!(builder.build().err(), Some(error));
+        }
+    }
+
+    #[test]
+    fn deserialize_invalid() {
+        let json = serde_json::to_string(&CHIP8::new()).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(serde_json::from_value::<CHIP8>(value.clone()).is_ok());
+
+        let changes = [
+            (
+                "memory_size",
+                serde_json::json!(9999),
+                "memory size of 9999",
+            ),
+            (
+                "start_address",
+                serde_json::json!(0x10),
+                "start address 0x010",
+            ),
+            ("speed", serde_json::json!(0), "speed must be at least"),
+        ];
+        for (field, bad, message) in changes {
+            let good = value["config"][field].clone();
+            value["config"][field] = bad;
+            let err = serde_json::from_value::<CHIP8>(value.clone())
+                .err()
+                .unwrap();
+            assert!(err.to_string().contains(message), "{}", err);
+            value["config"][field] = good;
+        }
+    }
+
+    #[test]
+    fn reconfigure() {
+        let mut chip = CHIP8::new();
+        chip.set_quirks(Quirks::SUPER_CHIP);
+        chip.set_speed(1200).unwrap();
+        assert_eq!(chip.config().quirks, Quirks::SUPER_CHIP);
+        assert_eq!(chip.config().steps_per_frame(), 20);
+        assert_eq!(chip.set_speed(0), Err(BuildError::ZeroSpeed));
+        assert_eq!(chip.config().speed, 1200);
+    }
+}
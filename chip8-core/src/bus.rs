@@ -0,0 +1,238 @@
+//! The memory bus, through which instructions reach RAM, read-only regions and devices.
+//!
+//! All of memory is RAM unless a region of the [`MemoryMap`] says otherwise. A region can be
+//! read-only, such as the interpreter area below the start address when the machine is built with
+//! [`Builder::protect_interpreter`](crate::builder::Builder::protect_interpreter), or handled by a
+//! [`Device`] for memory-mapped peripherals:
+//!
+//! ```
+//! use chip8_core::bus::{Bus, Device};
+//! use chip8_core::CHIP8;
+//!
+//! /// Counts the reads of its single byte.
+//! #[derive(Clone)]
+//! struct Counter(u8);
+//!
+//! impl Device for Counter {
+//!     fn read(&mut self, _offset: u16) -> u8 {
+//!         self.0 += 1;
+//!         self.0
+//!     }
+//!
+//!     fn write(&mut self, _offset: u16, value: u8) {
+//!         self.0 = value;
+//!     }
+//! }
+//!
+//! let mut chip = CHIP8::new();
+//! chip.memory_map_mut().map(0xF00..0xF01, Counter(0));
+//! chip.write8(0xF00, 41).unwrap();
+//! assert_eq!(chip.read8(0xF00), Ok(42));
+//! ```
+
+use crate::memory;
+use crate::CHIP8;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+/// Why an access through the bus failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum BusError {
+    /// The address lies past the end of memory.
+    #[error("address out of bounds")]
+    OutOfBounds,
+    /// The address lies in a read-only region.
+    #[error("address is read-only")]
+    ReadOnly,
+}
+
+/// Memory as seen by instructions.
+pub trait Bus {
+    /// Reads the byte at `address`.
+    fn read8(&mut self, address: u16) -> Result<u8, BusError>;
+
+    /// Writes `value` to the byte at `address`.
+    fn write8(&mut self, address: u16, value: u8) -> Result<(), BusError>;
+
+    /// Reads the big-endian word at `address`, such as an opcode.
+    fn read16(&mut self, address: u16) -> Result<u16, BusError> {
+        let next = address.checked_add(1).ok_or(BusError::OutOfBounds)?;
+        Ok(u16::from_be_bytes([
+            self.read8(address)?,
+            self.read8(next)?,
+        ]))
+    }
+}
+
+/// A peripheral that handles the reads and writes of a region of memory.
+///
+/// Devices are `Clone` so that a machine can be, and each copy gets its own devices.
+pub trait Device: Send + DeviceClone {
+    /// Returns the byte at `offset` from the start of the region.
+    fn read(&mut self, offset: u16) -> u8;
+
+    /// Writes `value` to the byte at `offset` from the start of the region.
+    fn write(&mut self, offset: u16, value: u8);
+}
+
+/// Copies a boxed device. Implemented for every device that is `Clone`.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<T: Device + Clone + 'static> DeviceClone for T {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+/// What handles the accesses to a region instead of RAM.
+pub enum Handler {
+    /// Reads come from RAM, and writes fail.
+    ReadOnly,
+    /// A device handles both.
+    Device(Box<dyn Device>),
+}
+
+impl Clone for Handler {
+    fn clone(&self) -> Self {
+        match self {
+            Handler::ReadOnly => Handler::ReadOnly,
+            Handler::Device(device) => Handler::Device(device.clone_box()),
+        }
+    }
+}
+
+impl fmt::Debug for Handler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Handler::ReadOnly => write!(f, "ReadOnly"),
+            Handler::Device(_) => write!(f, "Device"),
+        }
+    }
+}
+
+/// A range of addresses and what handles them.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub range: Range<u16>,
+    pub handler: Handler,
+}
+
+/// The regions of memory that are not plain RAM. Regions mapped later take precedence.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    pub fn new() -> Self {
+        MemoryMap::default()
+    }
+
+    /// Makes the RAM in `range` read-only.
+    pub fn protect(&mut self, range: Range<u16>) {
+        self.regions.push(Region {
+            range,
+            handler: Handler::ReadOnly,
+        });
+    }
+
+    /// Hands the accesses to `range` to `device`.
+    pub fn map(&mut self, range: Range<u16>, device: impl Device + 'static) {
+        self.regions.push(Region {
+            range,
+            handler: Handler::Device(Box::new(device)),
+        });
+    }
+
+    /// Returns the regions, in the order they were mapped.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the handler of `address` and its offset into the region, or `None` for RAM.
+    fn handler(&mut self, address: u16) -> Option<(&mut Handler, u16)> {
+        self.regions
+            .iter_mut()
+            .rev()
+            .find(|region| region.range.contains(&address))
+            .map(|region| (&mut region.handler, address - region.range.start))
+    }
+}
+
+impl Bus for CHIP8 {
+    fn read8(&mut self, address: u16) -> Result<u8, BusError> {
+        let value = memory::byte(self.ram(), address as usize).ok_or(BusError::OutOfBounds)?;
+        match self.memory_map.handler(address) {
+            Some((Handler::Device(device), offset)) => Ok(device.read(offset)),
+            _ => Ok(value),
+        }
+    }
+
+    fn write8(&mut self, address: u16, value: u8) -> Result<(), BusError> {
+        if memory::byte(self.ram(), address as usize).is_none() {
+            return Err(BusError::OutOfBounds);
+        }
+        match self.memory_map.handler(address) {
+            Some((Handler::Device(device), offset)) => device.write(offset, value),
+            Some((Handler::ReadOnly, _)) => return Err(BusError::ReadOnly),
+            None => memory::write(self.ram_mut(), address as usize, &[value])?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ExecError;
+    use crate::rom::Rom;
+
+    /// A device that remembers the last write, offset by its address.
+    #[derive(Clone)]
+    struct Latch(u8);
+
+    impl Device for Latch {
+        fn read(&mut self, offset: u16) -> u8 {
+            self.0 + offset as u8
+        }
+
+        fn write(&mut self, _offset: u16, value: u8) {
+            self.0 = value;
+        }
+    }
+
+    #[test]
+    fn regions() {
+        let mut chip = CHIP8::builder().memory_size(0x800).build().unwrap();
+        chip.memory_map.protect(0x300..0x400);
+        chip.memory_map.map(0x3F0..0x3F2, Latch(0));
+
+        assert_eq!(chip.write8(0x2FF, 1), Ok(()));
+        assert_eq!(chip.write8(0x300, 1), Err(BusError::ReadOnly));
+        assert_eq!(chip.write8(0x3F0, 7), Ok(()));
+        assert_eq!(chip.read16(0x3F0), Ok(0x0708));
+        assert_eq!(chip.memory[0x3F0], 0);
+        assert_eq!(chip.read8(0x800), Err(BusError::OutOfBounds));
+    }
+
+    #[test]
+    fn instructions() {
+        let mut chip = CHIP8::builder().protect_interpreter(true).build().unwrap();
+        // 0x200: LD I, 0x100; LD [I], V0
+        chip.load_rom(&Rom::from_bytes(vec![0xA1, 0x00, 0xF0, 0x55]).unwrap())
+            .unwrap();
+        chip.step().unwrap();
+        assert_eq!(
+            chip.step(),
+            Err(ExecError::ReadOnly {
+                address: 0x202,
+                target: 0x100
+            })
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Shadow call stack, recording where each subroutine was called from.
+
+use crate::CHIP8;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A subroutine call that has not returned yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Frame {
+    /// Address of the call instruction.
+    pub call: u16,
+    /// Address of the called subroutine.
+    pub target: u16,
+}
+
+/// A line of a backtrace: where execution is within a subroutine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub depth: usize,
+    pub address: u16,
+    /// The subroutine containing `address`, or `None` for the program itself.
+    pub subroutine: Option<u16>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:<2} {:#05X} in ", self.depth, self.address)?;
+        match self.subroutine {
+            Some(subroutine) => write!(f, "{:#05X}", subroutine),
+            None => write!(f, "start"),
+        }
+    }
+}
+
+/// Returns how execution reached `address`, innermost subroutine first.
+///
+/// `address` is usually the program counter, or the address of an instruction that failed.
+pub fn backtrace(chip: &CHIP8, address: u16) -> Vec<Location> {
+    let mut locations = Vec::with_capacity(chip.calls.len() + 1);
+    let mut address = address;
+    for frame in chip.calls.iter().rev() {
+        locations.push(Location {
+            depth: locations.len(),
+            address,
+            subroutine: Some(frame.target),
+        });
+        address = frame.call;
+    }
+    locations.push(Location {
+        depth: locations.len(),
+        address,
+        subroutine: None,
+    });
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ExecError;
+    use crate::rom::Rom;
+
+    #[test]
+    fn backtraces() {
+        // 0x200: CALL 0x204; 0x202: JP 0x202; 0x204: CALL 0x208; 0x206: RET; 0x208: RET
+        let rom = Rom::from_bytes(vec![
+            0x22, 0x04, 0x12, 0x02, 0x22, 0x08, 0x00, 0xEE, 0x00, 0xEE,
+        ]);
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom.unwrap()).unwrap();
+        chip.step().unwrap();
+        chip.step().unwrap();
+
+        let lines: Vec<String> = backtrace(&chip, chip.program_counter)
+            .iter()
+            .map(Location::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "#0  0x208 in 0x208",
+                "#1  0x204 in 0x204",
+                "#2  0x200 in start"
+            ]
+        );
+
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(backtrace(&chip, chip.program_counter).len(), 1);
+    }
+
+    #[test]
+    fn overflows() {
+        // 0x200: CALL 0x200
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(vec![0x22, 0x00]).unwrap())
+            .unwrap();
+        for _ in 0..crate::STACK_DEPTH {
+            chip.step().unwrap();
+        }
+        assert_eq!(
+            chip.step(),
+            Err(ExecError::StackOverflow { address: 0x200 })
+        );
+        assert_eq!(chip.stack().len(), crate::STACK_DEPTH);
+        assert_eq!(chip.calls().len(), crate::STACK_DEPTH);
+    }
+}
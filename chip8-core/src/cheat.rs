@@ -0,0 +1,337 @@
+//! Cheats: memory patches given as poke codes, and a RAM search and watch list for finding what to
+//! patch.
+//!
+//! A poke code is a hexadecimal address and the hexadecimal bytes to write there. `300:05` keeps
+//! the byte at 0x300 frozen at 5 while the program runs, while `2A0=1300` writes two bytes once,
+//! when the cheat is applied at load.
+
+use crate::command::parse_byte;
+use crate::memory;
+use crate::rom::PROGRAM_START;
+use crate::symbols::Symbols;
+use crate::CHIP8;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// A patch to memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Whether the bytes are written again before every instruction, rather than once.
+    pub frozen: bool,
+}
+
+impl Cheat {
+    /// Writes the bytes of the cheat to memory, or nothing if they run past its end, which a
+    /// parsed cheat never does.
+    pub fn apply(&self, chip: &mut CHIP8) {
+        let applied = memory::write(&mut chip.memory, self.address as usize, &self.bytes);
+        debug_assert!(
+            applied.is_ok(),
+            "cheat at {:#05X} runs past memory",
+            self.address
+        );
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = String;
+
+    /// Parses a poke code such as `300:05` or `2A0=1300`.
+    fn from_str(code: &str) -> Result<Self, String> {
+        let error = || {
+            format!(
+                "invalid cheat `{}`; expected <addr>:<bytes> or <addr>=<bytes>",
+                code
+            )
+        };
+        let (address, bytes, frozen) = match (code.split_once(':'), code.split_once('=')) {
+            (Some((address, bytes)), None) => (address, bytes, true),
+            (None, Some((address, bytes))) => (address, bytes, false),
+            _ => return Err(error()),
+        };
+        let address = address.trim_start_matches("0x");
+        let address = u16::from_str_radix(address, 16).map_err(|_| error())?;
+        if bytes.is_empty() || !bytes.len().is_multiple_of(2) {
+            return Err(error());
+        }
+        let bytes = (0..bytes.len())
+            .step_by(2)
+            .map(|i| {
+                bytes
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(error)?;
+        // Memory is 4KB, the most that 12-bit addresses can reach.
+        if address as usize + bytes.len() > 0x1000 {
+            return Err(format!("cheat `{}` is out of bounds", code));
+        }
+        Ok(Cheat {
+            address,
+            bytes,
+            frozen,
+        })
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:03X}{}",
+            self.address,
+            if self.frozen { ':' } else { '=' }
+        )?;
+        for byte in &self.bytes {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a cheat file, which has one poke code per line and `#` comments.
+pub fn parse_cheats(text: &str) -> Result<Vec<Cheat>, String> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(number, line)| {
+            let code = line.split('#').next().unwrap_or_default().trim();
+            (!code.is_empty()).then(|| {
+                code.parse()
+                    .map_err(|err| format!("line {}: {}", number + 1, err))
+            })
+        })
+        .collect()
+}
+
+/// Most candidates listed by [`Search::summary`] or pinned to a [`WatchList`] at once.
+const MAX_LISTED: usize = 16;
+
+/// How a value must have changed to remain a candidate in a [`Search`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Equal to a value.
+    Equal(u8),
+    Increased,
+    Decreased,
+    Unchanged,
+    Changed,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parses `+`, `-`, `=`, `!` or a value.
+    fn from_str(text: &str) -> Result<Self, String> {
+        Ok(match text {
+            "+" => Filter::Increased,
+            "-" => Filter::Decreased,
+            "=" => Filter::Unchanged,
+            "!" => Filter::Changed,
+            _ => Filter::Equal(parse_byte(text)?),
+        })
+    }
+}
+
+/// Narrows down which addresses hold a value, such as the number of lives, by comparing memory
+/// between searches.
+#[derive(Default)]
+pub struct Search {
+    /// Addresses that passed every filter so far, or `None` before the search starts.
+    candidates: Option<Vec<u16>>,
+    /// Memory as it was at the last search.
+    previous: Vec<u8>,
+}
+
+impl Search {
+    /// Starts a new search, making every byte of program memory a candidate.
+    pub fn start(&mut self, chip: &CHIP8) {
+        self.candidates = Some((PROGRAM_START..chip.memory.len() as u16).collect());
+        self.previous = chip.memory.to_vec();
+    }
+
+    /// Keeps the candidates that pass `filter`, returning how many are left. Starts a search if
+    /// none is in progress, which only leaves candidates for [`Filter::Equal`].
+    pub fn filter(&mut self, chip: &CHIP8, filter: Filter) -> usize {
+        if self.candidates.is_none() {
+            self.start(chip);
+        }
+        let previous = &self.previous;
+        let candidates = self.candidates.get_or_insert_with(Vec::new);
+        candidates.retain(|&address| {
+            let bytes = (
+                memory::byte(previous, address as usize),
+                memory::byte(&chip.memory, address as usize),
+            );
+            let (old, new) = match bytes {
+                (Some(old), Some(new)) => (old, new),
+                _ => return false,
+            };
+            match filter {
+                Filter::Equal(value) => new == value,
+                Filter::Increased => new > old,
+                Filter::Decreased => new < old,
+                Filter::Unchanged => new == old,
+                Filter::Changed => new != old,
+            }
+        });
+        self.previous = chip.memory.to_vec();
+        candidates.len()
+    }
+
+    /// Returns the remaining candidates, or `None` if no search is in progress.
+    pub fn candidates(&self) -> Option<&[u16]> {
+        self.candidates.as_deref()
+    }
+
+    /// Describes the remaining candidates, listing them with their values if there are few.
+    pub fn summary(&self, chip: &CHIP8) -> String {
+        let candidates = match self.candidates() {
+            Some(candidates) => candidates,
+            None => return "no search in progress".to_string(),
+        };
+        let mut summary = format!("{} candidates", candidates.len());
+        if !candidates.is_empty() && candidates.len() <= MAX_LISTED {
+            let listed: Vec<String> = candidates
+                .iter()
+                .map(
+                    |&address| match memory::byte(&chip.memory, address as usize) {
+                        Some(value) => format!("{:#05X} ({})", address, value),
+                        None => format!("{:#05X} (out of bounds)", address),
+                    },
+                )
+                .collect();
+            summary = format!("{}: {}", summary, listed.join(", "));
+        }
+        summary
+    }
+}
+
+/// Addresses pinned to be shown with their current values, such as those found by a [`Search`].
+#[derive(Default)]
+pub struct WatchList {
+    /// Pinned addresses, with an optional name for each.
+    pinned: BTreeMap<u16, Option<String>>,
+}
+
+impl WatchList {
+    /// Pins `address`, replacing its name if it is already pinned.
+    pub fn pin(&mut self, address: u16, name: Option<String>) {
+        self.pinned.insert(address, name);
+    }
+
+    /// Pins every remaining candidate of `search`, returning how many there were.
+    pub fn pin_candidates(&mut self, search: &Search) -> Result<usize, String> {
+        let candidates = search.candidates().ok_or("no search in progress")?;
+        if candidates.len() > MAX_LISTED {
+            return Err(format!(
+                "{} candidates; narrow the search down to {} first",
+                candidates.len(),
+                MAX_LISTED
+            ));
+        }
+        for &address in candidates {
+            self.pinned.entry(address).or_insert(None);
+        }
+        Ok(candidates.len())
+    }
+
+    /// Unpins `address`, returning `false` if it wasn't pinned.
+    pub fn unpin(&mut self, address: u16) -> bool {
+        self.pinned.remove(&address).is_some()
+    }
+
+    /// Describes each pinned address and its current value, such as `lives 0x300: 03 (3)`.
+    pub fn lines(&self, chip: &CHIP8, symbols: &Symbols) -> Vec<String> {
+        self.pinned
+            .iter()
+            .map(|(&address, name)| {
+                let label = match name {
+                    Some(name) => format!("{} {:#05X}", name, address),
+                    None => symbols.describe(address),
+                };
+                match memory::byte(&chip.memory, address as usize) {
+                    Some(value) => format!("{}: {:02X} ({})", label, value, value),
+                    None => format!("{}: out of bounds", label),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let cheat: Cheat = "300:05".parse().unwrap();
+        assert_eq!(
+            cheat,
+            Cheat {
+                address: 0x300,
+                bytes: vec![5],
+                frozen: true,
+            }
+        );
+        let patch: Cheat = "0x2A0=1300".parse().unwrap();
+        assert_eq!(patch.bytes, [0x13, 0x00]);
+        assert!(!patch.frozen);
+        assert_eq!(patch.to_string(), "2A0=1300");
+        assert!("300".parse::<Cheat>().is_err());
+        assert!("300:5".parse::<Cheat>().is_err());
+        assert!("FFF:0102".parse::<Cheat>().is_err());
+        assert_eq!(
+            parse_cheats("# lives\n300:05\n\n2A0=1300 # skip intro\n")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(parse_cheats("300:05\nlives")
+            .unwrap_err()
+            .starts_with("line 2: "));
+
+        let mut chip = CHIP8::new();
+        patch.apply(&mut chip);
+        assert_eq!(chip.memory[0x2A0..0x2A2], [0x13, 0x00]);
+    }
+
+    #[test]
+    fn search() {
+        let mut chip = CHIP8::new();
+        let mut search = Search::default();
+        assert_eq!(search.candidates(), None);
+        chip.memory[0x300] = 3;
+        chip.memory[0x301] = 3;
+        assert_eq!(search.filter(&chip, Filter::Equal(3)), 2);
+        chip.memory[0x300] = 2;
+        assert_eq!(search.filter(&chip, Filter::Decreased), 1);
+        assert_eq!(search.candidates(), Some(&[0x300][..]));
+        assert_eq!(search.filter(&chip, Filter::Unchanged), 1);
+        assert_eq!(search.summary(&chip), "1 candidates: 0x300 (2)");
+        assert_eq!("!".parse(), Ok(Filter::Changed));
+
+        let mut watch_list = WatchList::default();
+        assert_eq!(watch_list.pin_candidates(&search), Ok(1));
+        watch_list.pin(0x301, Some("lives".to_string()));
+        assert_eq!(
+            watch_list.lines(&chip, &Symbols::default()),
+            ["0x300: 02 (2)", "lives 0x301: 03 (3)"]
+        );
+        assert!(watch_list.unpin(0x300) && !watch_list.unpin(0x300));
+        // Addresses past memory can be pinned from the debugger, but hold nothing.
+        watch_list.pin(0x1000, None);
+        assert_eq!(
+            watch_list.lines(&chip, &Symbols::default())[1],
+            "0x1000: out of bounds"
+        );
+        search.start(&chip);
+        assert!(watch_list.pin_candidates(&search).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Debugger commands shared by the debugger frontends.
+
+use crate::cheat::{Cheat, Filter};
+use crate::debugger::{Register, WatchKind, Watchpoint};
+use crate::expr::Expr;
+use crate::state::SLOTS;
+use crate::symbols::Symbols;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Help text listing every command.
+pub const HELP: &str = "\
+commands:
+  break <addr> [if <cond>]
+                      set a breakpoint (b), stopping only when the condition holds,
+                      e.g. `b 0x204 if V3 == 0x1F && [I] != 0`
+  delete <addr>       clear a breakpoint (d)
+  breakpoints         list breakpoints
+  watch <addr> [len]  stop when memory is written; rwatch for reads, awatch for both
+  unwatch <addr>      clear a watchpoint
+  watchpoints         list watchpoints
+  step [count]        execute instructions (s)
+  next                execute an instruction, stepping over calls (n)
+  continue            run until a breakpoint or Ctrl-C (c)
+  rstep [count]       step backwards through recent instructions (rs)
+  rcontinue           step backwards until a breakpoint or watchpoint (rc)
+  list [count]        disassemble from the program counter (l)
+  regs                show registers (r)
+  set <reg> <value>   change V0-VF, I, PC, DT or ST
+  stack               show the stack
+  backtrace           show how execution reached the program counter (bt)
+  x <addr> [rows]     show memory as a hexdump
+  poke <addr> <byte>...
+                      write bytes to memory
+  heatmap [file]      show how often memory was accessed, or save it as a PNG image
+  search <value|+|-|=|!>
+                      find addresses equal to a value, or that increased, decreased,
+                      stayed the same or changed since the last search
+  search [new]        list the addresses found, or start over
+  pin <addr> [name]   show the value at an address as the program runs; `pin found` pins the
+                      addresses found by a search
+  unpin <addr>        stop showing an address
+  pins                show the pinned addresses and their values
+  save [slot]         save the state of the machine to a slot from 0 to 9
+  load [slot]         restore the state saved in a slot
+  cheat <code>        write memory with a poke code: 300:05 keeps 0x300 at 5, 300=05 writes it once
+  uncheat <addr>      stop keeping an address at a value
+  cheats              list cheats
+  quit                exit the debugger (q)
+numbers are decimal unless prefixed with 0x, and addresses may also be labels from a symbol file;
+an empty line repeats the last command";
+
+/// A command entered by the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Break(u16, Option<Expr>),
+    Delete(u16),
+    Breakpoints,
+    Watch(Watchpoint),
+    Unwatch(u16),
+    Watchpoints,
+    Step(u16),
+    Next,
+    Continue,
+    ReverseStep(u16),
+    ReverseContinue,
+    List(usize),
+    Registers,
+    Set(Register, u16),
+    Stack,
+    Backtrace,
+    Memory(u16, usize),
+    Poke(u16, Vec<u8>),
+    Heatmap(Option<String>),
+    Search(Option<Filter>),
+    NewSearch,
+    Pin(u16, Option<String>),
+    PinFound,
+    Unpin(u16),
+    Pins,
+    SaveState(Option<u8>),
+    LoadState(Option<u8>),
+    Cheat(Cheat),
+    Uncheat(u16),
+    Cheats,
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parses a command line. Addresses may be given as labels from `symbols`.
+    pub fn parse(line: &str, symbols: &Symbols) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.as_slice() {
+            ["break" | "b", address] => Command::Break(symbols.parse_address(address)?, None),
+            ["break" | "b", address, "if", condition @ ..] => Command::Break(
+                symbols.parse_address(address)?,
+                Some(Expr::parse(&condition.join(" "))?),
+            ),
+            ["delete" | "d", address] => Command::Delete(symbols.parse_address(address)?),
+            ["breakpoints"] => Command::Breakpoints,
+            [kind @ ("watch" | "rwatch" | "awatch"), address, len @ ..] if len.len() <= 1 => {
+                let kind = match *kind {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::Access,
+                };
+                let len = match len.first() {
+                    Some(len) => parse_number(len)?.max(1),
+                    None => 1,
+                };
+                Command::Watch(Watchpoint {
+                    address: symbols.parse_address(address)?,
+                    len,
+                    kind,
+                })
+            }
+            ["unwatch", address] => Command::Unwatch(symbols.parse_address(address)?),
+            ["watchpoints"] => Command::Watchpoints,
+            ["step" | "s"] => Command::Step(1),
+            ["step" | "s", count] => Command::Step(parse_number(count)?),
+            ["next" | "n"] => Command::Next,
+            ["continue" | "c"] => Command::Continue,
+            ["rstep" | "rs"] => Command::ReverseStep(1),
+            ["rstep" | "rs", count] => Command::ReverseStep(parse_number(count)?),
+            ["rcontinue" | "rc"] => Command::ReverseContinue,
+            ["list" | "l"] => Command::List(10),
+            ["list" | "l", count] => Command::List(parse_number(count)? as usize),
+            ["regs" | "r"] => Command::Registers,
+            ["set", register, value] => {
+                Command::Set(register.parse()?, symbols.parse_address(value)?)
+            }
+            ["stack"] => Command::Stack,
+            ["backtrace" | "bt"] => Command::Backtrace,
+            ["x", address] => Command::Memory(symbols.parse_address(address)?, 8),
+            ["x", address, rows] => Command::Memory(
+                symbols.parse_address(address)?,
+                parse_number(rows)? as usize,
+            ),
+            ["poke", address, bytes @ ..] if !bytes.is_empty() => {
+                let bytes = bytes
+                    .iter()
+                    .map(|byte| parse_byte(byte))
+                    .collect::<Result<_, String>>()?;
+                Command::Poke(symbols.parse_address(address)?, bytes)
+            }
+            ["heatmap"] => Command::Heatmap(None),
+            ["heatmap", path] => Command::Heatmap(Some(path.to_string())),
+            ["search"] => Command::Search(None),
+            ["search", "new"] => Command::NewSearch,
+            ["search", filter] => Command::Search(Some(filter.parse()?)),
+            ["pin", "found"] => Command::PinFound,
+            ["pin", address] => Command::Pin(symbols.parse_address(address)?, None),
+            ["pin", address, name] => {
+                Command::Pin(symbols.parse_address(address)?, Some(name.to_string()))
+            }
+            ["unpin", address] => Command::Unpin(symbols.parse_address(address)?),
+            ["pins"] => Command::Pins,
+            ["save"] => Command::SaveState(None),
+            ["save", slot] => Command::SaveState(Some(parse_slot(slot)?)),
+            ["load"] => Command::LoadState(None),
+            ["load", slot] => Command::LoadState(Some(parse_slot(slot)?)),
+            ["cheat", code] => Command::Cheat(code.parse()?),
+            ["uncheat", address] => Command::Uncheat(symbols.parse_address(address)?),
+            ["cheats"] => Command::Cheats,
+            ["help" | "h"] => Command::Help,
+            ["quit" | "q"] => Command::Quit,
+            _ => return Err(format!("unknown command `{}`; try `help`", line.trim())),
+        };
+        Ok(command)
+    }
+
+    /// Returns `true` for commands that execute instructions, forwards or backwards.
+    pub fn executes(&self) -> bool {
+        matches!(
+            self,
+            Command::Step(_)
+                | Command::Next
+                | Command::Continue
+                | Command::ReverseStep(_)
+                | Command::ReverseContinue
+        )
+    }
+}
+
+/// Parses the number of a save slot.
+fn parse_slot(text: &str) -> Result<u8, String> {
+    match parse_number(text)? {
+        slot if slot < SLOTS as u16 => Ok(slot as u8),
+        _ => Err(format!("slots are numbered 0 to {}", SLOTS - 1)),
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+pub fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number `{}`", text))
+}
+
+/// Parses a number that must fit in a byte.
+pub fn parse_byte(text: &str) -> Result<u8, String> {
+    match parse_number(text)? {
+        byte @ 0..=0xFF => Ok(byte as u8),
+        _ => Err(format!("`{}` does not fit in a byte", text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("512"), Ok(512));
+        assert_eq!(parse_number("0x200"), Ok(0x200));
+        assert!(parse_number("zz").is_err());
+    }
+
+    #[test]
+    fn commands() {
+        let mut symbols = Symbols::default();
+        symbols.insert("main_loop", 0x204);
+        assert_eq!(
+            Command::parse("b 0x204", &symbols),
+            Ok(Command::Break(0x204, None))
+        );
+        assert_eq!(
+            Command::parse("b 0x204 if V3 == 0x1F", &symbols),
+            Ok(Command::Break(0x204, Expr::parse("V3 == 0x1F").ok()))
+        );
+        assert!(Command::parse("b 0x204 if", &symbols).is_err());
+        assert_eq!(Command::parse("  step  ", &symbols), Ok(Command::Step(1)));
+        assert_eq!(
+            Command::parse("set v3 0x1f", &symbols),
+            Ok(Command::Set(Register::V(3), 0x1F))
+        );
+        assert!(Command::parse("set v3", &symbols).is_err());
+        assert_eq!(
+            Command::parse("poke 0x300 1 0xFF", &symbols),
+            Ok(Command::Poke(0x300, vec![1, 0xFF]))
+        );
+        assert!(Command::parse("poke 0x300 256", &symbols).is_err());
+        assert_eq!(
+            Command::parse("rwatch 0x300 4", &symbols),
+            Ok(Command::Watch(Watchpoint {
+                address: 0x300,
+                len: 4,
+                kind: WatchKind::Read,
+            }))
+        );
+        assert!(Command::parse("watch 0x300 4 5", &symbols).is_err());
+        assert!(Command::parse("frobnicate", &symbols).is_err());
+        assert_eq!(
+            Command::parse("b main_loop", &symbols),
+            Ok(Command::Break(0x204, None))
+        );
+        assert!(Command::parse("b missing", &symbols).is_err());
+        assert_eq!(
+            Command::parse("search -", &symbols),
+            Ok(Command::Search(Some(Filter::Decreased)))
+        );
+        assert_eq!(
+            Command::parse("pin 0x300 lives", &symbols),
+            Ok(Command::Pin(0x300, Some("lives".to_string())))
+        );
+        assert_eq!(Command::parse("pin found", &symbols), Ok(Command::PinFound));
+        assert_eq!(
+            Command::parse("load 9", &symbols),
+            Ok(Command::LoadState(Some(9)))
+        );
+        assert!(Command::parse("save 10", &symbols).is_err());
+        assert_eq!(
+            Command::parse("cheat 300:05", &symbols),
+            Ok(Command::Cheat("300:05".parse().unwrap()))
+        );
+    }
+}
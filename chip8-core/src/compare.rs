@@ -0,0 +1,144 @@
+//! Comparing a run against a reference trace, to find where two emulators disagree.
+
+use crate::debugger::Register;
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::trace::{Change, Record};
+use crate::{CHIP8, STEPS_PER_FRAME};
+use std::io::BufRead;
+
+/// How a comparison ended.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every instruction of the reference was executed the same way.
+    Matched(usize),
+    /// The instruction on `line` of the reference did something different.
+    Diverged {
+        line: usize,
+        expected: Record,
+        actual: Record,
+    },
+    /// The instruction on `line` of the reference failed to execute.
+    Failed {
+        line: usize,
+        expected: Record,
+        error: ExecError,
+    },
+}
+
+/// Returns `true` if two records have the same address, opcode and changes, in any order.
+fn same(expected: &Record, actual: &Record) -> bool {
+    expected.address == actual.address
+        && expected.opcode == actual.opcode
+        && expected.changes.len() == actual.changes.len()
+        && expected
+            .changes
+            .iter()
+            .all(|change| actual.changes.contains(change))
+}
+
+/// Copies the result of an instruction that depends on random numbers or timing from the
+/// reference, since two runs can't be expected to agree on them. `old` is the value VX had before
+/// the instruction.
+fn synchronize(chip: &mut CHIP8, expected: &Record, actual: &mut Record, old: &[u8; 16]) {
+    let x = match actual.instruction {
+        Instruction::Random { x, .. } | Instruction::GetDelay { x } => x,
+        _ => return,
+    };
+    let is_vx = |change: &&Change| matches!(change, Change::Register(Register::V(r), _) if *r == x);
+    actual.changes.retain(|change| !is_vx(&change));
+    // If the reference left VX unchanged, so must we.
+    chip.variable[x as usize] = old[x as usize];
+    if let Some(&change) = expected.changes.iter().find(is_vx) {
+        if let Change::Register(register, value) = change {
+            register.write(chip, value);
+        }
+        actual.changes.push(change);
+    }
+}
+
+/// Runs `chip` alongside the reference trace read from `reference`, stopping at the first
+/// instruction that differs.
+///
+/// The timers are updated every [`STEPS_PER_FRAME`] instructions. Random numbers and reads of
+/// the delay timer are taken from the reference.
+pub fn compare<R: BufRead>(chip: &mut CHIP8, reference: R) -> Result<Outcome, String> {
+    let mut steps = 0;
+    for (number, line) in reference.lines().enumerate() {
+        let line = line.map_err(|err| err.to_string())?;
+        let expected =
+            match Record::parse(&line).map_err(|err| format!("line {}: {}", number + 1, err))? {
+                Some(record) => record,
+                None => continue,
+            };
+
+        let old = chip.variable;
+        let result = Record::execute(chip);
+        steps += 1;
+        if (steps as u64).is_multiple_of(STEPS_PER_FRAME) {
+            chip.tick();
+        }
+        let mut actual = match result {
+            Ok(Some(actual)) => actual,
+            Ok(None) => return Err(format!("line {}: no instruction to execute", number + 1)),
+            Err(error) => {
+                return Ok(Outcome::Failed {
+                    line: number + 1,
+                    expected,
+                    error,
+                })
+            }
+        };
+        synchronize(chip, &expected, &mut actual, &old);
+        if !same(&expected, &actual) {
+            return Ok(Outcome::Diverged {
+                line: number + 1,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(Outcome::Matched(steps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    fn chip() -> CHIP8 {
+        // 0x200: LD V0, 5; 0x202: RND V1, 0xFF; 0x204: LD I, 0x300; 0x206: JP 0x200
+        let rom = Rom::from_bytes(vec![0x60, 0x05, 0xC1, 0xFF, 0xA3, 0x00, 0x12, 0x00]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        chip
+    }
+
+    #[test]
+    fn comparison() {
+        let reference = "0x200  6005  LD V0, 0x05  V0=0x5\n\
+                         0x202  C1FF  RND V1, 0xFF  V1=0x2A\n\
+                         0x204  A300  LD I, 0x300  I=0x300\n";
+        let mut chip = chip();
+        assert_eq!(
+            compare(&mut chip, reference.as_bytes()),
+            Ok(Outcome::Matched(3))
+        );
+        assert_eq!(chip.variable[1], 0x2A);
+
+        let reference = "0x200  6005  LD V0, 0x05  V0=0x5\n0x202  C1FF  RND V1, 0xFF\n\
+                         0x204  A300  LD I, 0x300  I=0x301\n";
+        match compare(&mut self::chip(), reference.as_bytes()) {
+            Ok(Outcome::Diverged {
+                line,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(line, 3);
+                assert_eq!(expected.changes, [Change::Register(Register::I, 0x301)]);
+                assert_eq!(actual.changes, [Change::Register(Register::I, 0x300)]);
+            }
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Code coverage, recording which bytes of a ROM were executed or read.
+
+use crate::disasm::{self, Line};
+use crate::error::ExecError;
+use crate::memory::AccessKind;
+use crate::rom::{Rom, PROGRAM_START};
+use crate::CHIP8;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+/// How a range of bytes was used during a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Usage {
+    Untouched,
+    /// Read as data, but never executed.
+    Read,
+    Executed,
+}
+
+impl Usage {
+    /// Returns a short label for listings.
+    fn label(self) -> &'static str {
+        match self {
+            Usage::Untouched => "----",
+            Usage::Read => "read",
+            Usage::Executed => "exec",
+        }
+    }
+}
+
+/// Records how each byte of memory is used.
+pub struct Coverage {
+    usage: Vec<Usage>,
+}
+
+impl Coverage {
+    /// Creates a coverage map for a machine with `memory_size` bytes of memory.
+    pub fn new(memory_size: usize) -> Self {
+        Coverage {
+            usage: vec![Usage::Untouched; memory_size],
+        }
+    }
+
+    /// Executes one instruction with `step`, recording the bytes it was made of and the bytes it
+    /// read.
+    pub fn step<F>(&mut self, chip: &mut CHIP8, step: F) -> Result<(), ExecError>
+    where
+        F: FnOnce(&mut CHIP8) -> Result<(), ExecError>,
+    {
+        let address = chip.program_counter as usize;
+        step(chip)?;
+
+        // Every instruction that executes successfully is at least two bytes long.
+        for usage in self.usage.iter_mut().skip(address).take(2) {
+            *usage = Usage::Executed;
+        }
+        for access in &chip.accesses {
+            let usage = &mut self.usage[access.address as usize];
+            if access.kind == AccessKind::Read && *usage == Usage::Untouched {
+                *usage = Usage::Read;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how the bytes of `line` were used, taking the most significant use of any byte.
+    fn line_usage(&self, line: &Line) -> Usage {
+        let start = line.address as usize;
+        self.usage
+            .iter()
+            .skip(start)
+            .take(line.bytes.len())
+            .copied()
+            .max()
+            .unwrap_or(Usage::Untouched)
+    }
+
+    /// Returns a summary of how many bytes of `rom` were executed, read and untouched.
+    pub fn summary(&self, rom: &Rom) -> String {
+        let end = rom.end().min(self.usage.len());
+        let bytes = &self.usage[PROGRAM_START as usize..end];
+        let count = |usage| bytes.iter().filter(|&&u| u == usage).count();
+        let percent = |count: usize| count as f64 * 100.0 / bytes.len().max(1) as f64;
+        let (executed, read, untouched) = (
+            count(Usage::Executed),
+            count(Usage::Read),
+            count(Usage::Untouched),
+        );
+        format!(
+            "{} bytes: {} executed ({:.1}%), {} read as data ({:.1}%), {} untouched ({:.1}%)",
+            bytes.len(),
+            executed,
+            percent(executed),
+            read,
+            percent(read),
+            untouched,
+            percent(untouched)
+        )
+    }
+
+    /// Returns a disassembly of `rom` with each line prefixed by how it was used.
+    pub fn annotate(&self, rom: &Rom) -> String {
+        let mut out = format!("; {}\n", self.summary(rom));
+        for line in disasm::disassemble(rom) {
+            let _ = writeln!(out, "{}  {}", self.line_usage(&line).label(), line);
+        }
+        out
+    }
+
+    /// Returns the annotated disassembly of `rom` as a standalone HTML page.
+    pub fn html(&self, rom: &Rom) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Coverage</title>\n\
+             <style>\n\
+             body { font-family: monospace; }\n\
+             .exec { background: #c8f0c8; }\n\
+             .read { background: #c8d8f8; }\n\
+             .untouched { color: #888; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+        let _ = writeln!(out, "<p>{}</p>", escape(&self.summary(rom)));
+        out.push_str(
+            "<p><span class=\"exec\">executed</span> <span class=\"read\">read as data</span> \
+             <span class=\"untouched\">untouched</span></p>\n<pre>\n",
+        );
+        for line in disasm::disassemble(rom) {
+            let class = match self.line_usage(&line) {
+                Usage::Executed => "exec",
+                Usage::Read => "read",
+                Usage::Untouched => "untouched",
+            };
+            let _ = writeln!(
+                out,
+                "<span class=\"{}\">{}</span>",
+                class,
+                escape(&line.to_string())
+            );
+        }
+        out.push_str("</pre>\n</body>\n</html>\n");
+        out
+    }
+}
+
+/// Escapes text for inclusion in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage() {
+        // 0x200: LD I, 0x208; 0x202: LD V1, [I]; 0x204: JP 0x204; 0x206: CLS; 0x208: db 1, 2
+        let rom = Rom::from_bytes(vec![
+            0xA2, 0x08, 0xF1, 0x65, 0x12, 0x04, 0x00, 0xE0, 0x01, 0x02,
+        ])
+        .unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        let mut coverage = Coverage::new(chip.memory.len());
+        for _ in 0..4 {
+            coverage.step(&mut chip, CHIP8::step).unwrap();
+        }
+
+        assert_eq!(
+            coverage.summary(&rom),
+            "10 bytes: 6 executed (60.0%), 2 read as data (20.0%), 2 untouched (20.0%)"
+        );
+        let annotated = coverage.annotate(&rom);
+        let lines: Vec<&str> = annotated.lines().collect();
+        assert!(lines[1].starts_with("exec  0x200"));
+        assert!(lines[4].starts_with("----  0x206"));
+        assert!(lines[5].starts_with("read  0x208"));
+        assert!(coverage.html(&rom).contains("<span class=\"read\">0x208"));
+    }
+}
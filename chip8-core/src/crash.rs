@@ -0,0 +1,120 @@
+//! Crash reports, describing the state of the machine when a program fails.
+
+use crate::callstack;
+use crate::disasm;
+use crate::error::ExecError;
+use crate::inspector::Inspector;
+use crate::symbols::Symbols;
+use crate::trace::Record;
+use crate::CHIP8;
+use std::fmt::Write as _;
+
+/// Instructions kept for the report when no other trace was asked for.
+pub const HISTORY: usize = 32;
+
+/// Instructions disassembled before the one that failed.
+const CONTEXT: u16 = 8;
+
+/// Returns a report of `error` for the ROM at `path`, including the registers, call stack,
+/// disassembly around the failure, `records` of the last instructions, memory at I and the
+/// display.
+pub fn report<'a, I>(
+    path: &str,
+    chip: &CHIP8,
+    error: &ExecError,
+    records: I,
+    symbols: &Symbols,
+) -> String
+where
+    I: IntoIterator<Item = &'a Record>,
+{
+    let mut out = String::new();
+    let _ = writeln!(out, "rom: {}", path);
+    let _ = writeln!(out, "error: {}", error);
+
+    let _ = writeln!(out, "\nregisters:");
+    let _ = writeln!(
+        out,
+        "PC={:#05X} I={:#05X} DT={} ST={} SP={}",
+        chip.program_counter,
+        chip.index,
+        chip.timer.delay_timer,
+        chip.timer.sound_timer,
+        chip.stack.len()
+    );
+    let variables: Vec<String> = (0..16)
+        .map(|x| format!("V{:X}={:02X}", x, chip.variable[x]))
+        .collect();
+    let _ = writeln!(out, "{}", variables.join(" "));
+
+    let _ = writeln!(out, "\nstack:");
+    if chip.stack.is_empty() {
+        let _ = writeln!(out, "empty");
+    }
+    for (depth, address) in chip.stack.iter().enumerate().rev() {
+        let _ = writeln!(out, "#{:<2} {}", depth, symbols.describe(*address));
+    }
+    let _ = writeln!(out, "\ncall stack:");
+    for location in callstack::backtrace(chip, error.address()) {
+        let _ = writeln!(out, "{}", location);
+    }
+
+    let _ = writeln!(out, "\ndisassembly:");
+    let start = error.address().saturating_sub(CONTEXT);
+    for mut line in disasm::disassemble_memory(&chip.memory, start, CONTEXT as usize + 4) {
+        if let Some(name) = symbols.name(line.address) {
+            let _ = writeln!(out, "{}:", name);
+        }
+        line.symbolize(symbols);
+        let marker = if line.address == error.address() {
+            "=>"
+        } else {
+            "  "
+        };
+        let _ = writeln!(out, "{} {}", marker, line);
+    }
+
+    let _ = writeln!(out, "\nlast instructions:");
+    for record in records {
+        let _ = writeln!(out, "{}", record);
+    }
+
+    let _ = writeln!(out, "\nmemory at I:");
+    for row in Inspector::new(chip).rows(chip, chip.index, 4) {
+        let _ = writeln!(out, "{}", row);
+    }
+
+    let _ = writeln!(out, "\ndisplay:");
+    out.push_str(&chip.display.text());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use crate::trace::Tracer;
+
+    #[test]
+    fn crash_report() {
+        // 0x200: LD V0, 5; 0x202: RET
+        let rom = Rom::from_bytes(vec![0x60, 0x05, 0x00, 0xEE]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        let mut tracer = Tracer::ring(HISTORY);
+        tracer.step(&mut chip).unwrap();
+        let error = tracer.step(&mut chip).unwrap_err();
+
+        let report = report(
+            "test.ch8",
+            &chip,
+            &error,
+            tracer.records(),
+            &Symbols::default(),
+        );
+        assert!(report.starts_with("rom: test.ch8\nerror: return with an empty stack at 0x202\n"));
+        assert!(report.contains("\n=> 0x202  00 EE"));
+        assert!(report.contains("last instructions:\n0x200  6005  LD V0, 0x05"));
+        assert!(report.ends_with(&chip.display.text()));
+    }
+}
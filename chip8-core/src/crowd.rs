@@ -0,0 +1,148 @@
+//! Crowd play, where many players share one keypad by voting, as in "Twitch plays".
+//!
+//! Time is cut into voting windows of a number of frames. During a window each voter votes for
+//! a key, a later vote replacing their earlier one, and when it ends the key with the most votes
+//! is held down for the whole of the next window. It is let go for the last frame of a window
+//! longer than one, so that a game waiting for a key to be released sees it. Ties go to the key
+//! voted for first, and a window with no votes holds no key.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+/// Frames in a voting window by default, half a second.
+pub const DEFAULT_WINDOW: u64 = 30;
+
+/// The votes of the window so far, and the key they decided last.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    /// Votes for each key.
+    pub votes: [u32; 16],
+    /// The key held down, decided by the last window.
+    pub held: Option<u8>,
+    /// Frames left until the window ends.
+    pub remaining: u64,
+}
+
+/// The votes of a crowd, turned into keys.
+#[derive(Clone, Debug)]
+pub struct Crowd {
+    window: u64,
+    /// Frames left in the window.
+    remaining: u64,
+    /// Each voter's key, and the order in which it was voted for.
+    votes: BTreeMap<String, (u8, u64)>,
+    /// How many votes were cast, which orders them.
+    cast: u64,
+    held: Option<u8>,
+}
+
+impl Crowd {
+    /// Creates a crowd voting in windows of `window` frames, at least 1.
+    pub fn new(window: u64) -> Self {
+        let window = window.max(1);
+        Crowd {
+            window,
+            remaining: window,
+            votes: BTreeMap::new(),
+            cast: 0,
+            held: None,
+        }
+    }
+
+    /// Counts the vote of `voter` for `key` in this window, replacing any earlier one. Keys past
+    /// `F` are ignored.
+    pub fn vote(&mut self, voter: &str, key: u8) {
+        if key > 0xF {
+            return;
+        }
+        self.votes.insert(String::from(voter), (key, self.cast));
+        self.cast += 1;
+    }
+
+    /// Returns the votes so far and the key held down.
+    pub fn tally(&self) -> Tally {
+        let mut votes = [0; 16];
+        for &(key, _) in self.votes.values() {
+            votes[usize::from(key)] += 1;
+        }
+        Tally {
+            votes,
+            held: self.held,
+            remaining: self.remaining,
+        }
+    }
+
+    /// Returns the key to hold down during the coming frame, if any.
+    pub fn key(&self) -> Option<u8> {
+        if self.remaining == 1 && self.window > 1 {
+            return None;
+        }
+        self.held
+    }
+
+    /// Ends a frame, deciding the key of the next window if it ends this one.
+    pub fn frame(&mut self) {
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            return;
+        }
+        let votes = self.tally().votes;
+        self.held = self
+            .votes
+            .values()
+            .max_by_key(|&&(key, order)| (votes[usize::from(key)], core::cmp::Reverse(order)))
+            .map(|&(key, _)| key);
+        tracing::debug!(key = ?self.held, voters = self.votes.len(), "the window ended");
+        self.votes.clear();
+        self.remaining = self.window;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_the_most_voted_key() {
+        let mut crowd = Crowd::new(3);
+        crowd.vote("ann", 5);
+        crowd.vote("bob", 2);
+        crowd.vote("cat", 2);
+        crowd.vote("ann", 2);
+        crowd.vote("dan", 0x10);
+        assert_eq!(crowd.tally().votes[2], 3);
+        assert_eq!(crowd.tally().votes[5], 0);
+        assert_eq!(crowd.key(), None);
+
+        crowd.frame();
+        crowd.frame();
+        assert_eq!(crowd.tally().remaining, 1);
+        crowd.frame();
+        // Held for the window, but for its last frame.
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            keys.push(crowd.key());
+            crowd.frame();
+        }
+        assert_eq!(keys, [Some(2), Some(2), None]);
+        // Nobody voted in that window.
+        assert_eq!(crowd.key(), None);
+        assert_eq!(crowd.tally().held, None);
+    }
+
+    #[test]
+    fn breaks_ties_by_the_first_vote() {
+        let mut crowd = Crowd::new(1);
+        crowd.vote("ann", 7);
+        crowd.vote("bob", 3);
+        crowd.frame();
+        assert_eq!(crowd.tally().held, Some(7));
+        assert_eq!(crowd.key(), Some(7));
+
+        crowd.vote("ann", 3);
+        crowd.vote("bob", 7);
+        crowd.vote("cat", 3);
+        crowd.frame();
+        assert_eq!(crowd.tally().held, Some(3));
+    }
+}
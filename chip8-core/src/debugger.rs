@@ -0,0 +1,737 @@
+//! Debugger for stepping through programs.
+
+use crate::cheat::{Cheat, Search, WatchList};
+use crate::disasm;
+use crate::error::ExecError;
+use crate::expr::Expr;
+use crate::heatmap::Heatmap;
+use crate::history::{History, Snapshot, DEFAULT_CAPACITY};
+use crate::inspector;
+use crate::instruction::Instruction;
+use crate::memory::Access;
+use crate::symbols::Symbols;
+use crate::CHIP8;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+pub use emu_debug::debugger::{WatchKind, Watchpoint};
+use emu_debug::Kind;
+
+/// A register that can be inspected and modified while paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// One of the variable registers `V0..=VF`.
+    V(u8),
+    /// The index register.
+    I,
+    /// The program counter.
+    PC,
+    /// The delay timer.
+    DT,
+    /// The sound timer.
+    ST,
+}
+
+/// Every register except the program counter, which changes every step. These are the registers
+/// compared to see what an instruction did.
+pub const REGISTERS: [Register; 19] = [
+    Register::V(0x0),
+    Register::V(0x1),
+    Register::V(0x2),
+    Register::V(0x3),
+    Register::V(0x4),
+    Register::V(0x5),
+    Register::V(0x6),
+    Register::V(0x7),
+    Register::V(0x8),
+    Register::V(0x9),
+    Register::V(0xA),
+    Register::V(0xB),
+    Register::V(0xC),
+    Register::V(0xD),
+    Register::V(0xE),
+    Register::V(0xF),
+    Register::I,
+    Register::DT,
+    Register::ST,
+];
+
+impl Register {
+    /// Reads the value of the register.
+    pub fn read(self, chip: &CHIP8) -> u16 {
+        match self {
+            Register::V(x) => chip.variable[x as usize] as u16,
+            Register::I => chip.index,
+            Register::PC => chip.program_counter,
+            Register::DT => chip.timer.delay_timer as u16,
+            Register::ST => chip.timer.sound_timer as u16,
+        }
+    }
+
+    /// Writes `value` to the register, truncating it to the size of the register.
+    pub fn write(self, chip: &mut CHIP8, value: u16) {
+        match self {
+            Register::V(x) => chip.variable[x as usize] = value as u8,
+            Register::I => chip.index = value,
+            Register::PC => chip.program_counter = value,
+            Register::DT => chip.timer.delay_timer = value as u8,
+            Register::ST => chip.timer.sound_timer = value as u8,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    /// Parses a register name such as `v3`, `VF`, `i` or `pc`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let upper = name.to_ascii_uppercase();
+        let register = match upper.as_str() {
+            "I" => Register::I,
+            "PC" => Register::PC,
+            "DT" => Register::DT,
+            "ST" => Register::ST,
+            _ => {
+                let x = upper
+                    .strip_prefix('V')
+                    .filter(|digit| digit.len() == 1)
+                    .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+                    .ok_or_else(|| format!("unknown register `{}`", name))?;
+                Register::V(x)
+            }
+        };
+        Ok(register)
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::PC => write!(f, "PC"),
+            Register::DT => write!(f, "DT"),
+            Register::ST => write!(f, "ST"),
+        }
+    }
+}
+
+/// The registers in the order GDB numbers them: `V0`-`VF` are 0-15, followed by `I`, `PC`, `DT`
+/// and `ST`.
+const TARGET_REGISTERS: [emu_debug::Register; 20] = [
+    emu_debug::Register::new("V0", 1, Kind::Integer),
+    emu_debug::Register::new("V1", 1, Kind::Integer),
+    emu_debug::Register::new("V2", 1, Kind::Integer),
+    emu_debug::Register::new("V3", 1, Kind::Integer),
+    emu_debug::Register::new("V4", 1, Kind::Integer),
+    emu_debug::Register::new("V5", 1, Kind::Integer),
+    emu_debug::Register::new("V6", 1, Kind::Integer),
+    emu_debug::Register::new("V7", 1, Kind::Integer),
+    emu_debug::Register::new("V8", 1, Kind::Integer),
+    emu_debug::Register::new("V9", 1, Kind::Integer),
+    emu_debug::Register::new("VA", 1, Kind::Integer),
+    emu_debug::Register::new("VB", 1, Kind::Integer),
+    emu_debug::Register::new("VC", 1, Kind::Integer),
+    emu_debug::Register::new("VD", 1, Kind::Integer),
+    emu_debug::Register::new("VE", 1, Kind::Integer),
+    emu_debug::Register::new("VF", 1, Kind::Integer),
+    emu_debug::Register::new("I", 2, Kind::Data),
+    emu_debug::Register::new("PC", 2, Kind::Code),
+    emu_debug::Register::new("DT", 1, Kind::Integer),
+    emu_debug::Register::new("ST", 1, Kind::Integer),
+];
+
+/// Returns the register numbered `index` in [`TARGET_REGISTERS`].
+fn numbered(index: usize) -> Register {
+    match index {
+        0..=15 => Register::V(index as u8),
+        16 => Register::I,
+        17 => Register::PC,
+        18 => Register::DT,
+        19 => Register::ST,
+        _ => panic!("no register {}", index),
+    }
+}
+
+/// The machine as a target of the debugging shared by every system, which the GDB stub and the
+/// register and disassembly panels of the frontends are written against.
+impl emu_debug::Target for CHIP8 {
+    fn architecture(&self) -> &'static str {
+        "chip8"
+    }
+
+    fn registers(&self) -> &'static [emu_debug::Register] {
+        &TARGET_REGISTERS
+    }
+
+    fn register(&self, index: usize) -> u16 {
+        numbered(index).read(self)
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) {
+        numbered(index).write(self, value);
+    }
+
+    fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.get(address as usize).copied().unwrap_or(0)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        // Writes past the end of memory go nowhere, as they do on the bus.
+        let _ = inspector::poke(self, address, &[value]);
+    }
+
+    fn step(&mut self, accesses: &mut Vec<emu_debug::Access>) -> Result<(), String> {
+        let result = CHIP8::step(self);
+        accesses.extend(self.accesses.iter().map(|access| emu_debug::Access {
+            kind: access.kind,
+            address: access.address,
+            value: access.new,
+        }));
+        result.map_err(|err| err.to_string())
+    }
+
+    fn disassemble(&self, address: u16) -> emu_debug::disasm::Line {
+        let line = disasm::disassemble_memory(&self.memory, address, 1).pop();
+        emu_debug::disasm::Line {
+            address,
+            bytes: line
+                .as_ref()
+                .map_or_else(|| vec![0], |line| line.bytes.clone()),
+            text: line.map_or_else(|| "db 0x00".to_string(), |line| line.text),
+        }
+    }
+
+    fn is_call(&self, address: u16) -> bool {
+        matches!(
+            self.opcode_at(address).and_then(Instruction::decode),
+            Some(Instruction::Call { .. })
+        )
+    }
+}
+
+/// Why execution stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// A single instruction was executed.
+    Step,
+    /// A breakpoint was reached at the contained address.
+    Breakpoint(u16),
+    /// The instruction at `address` accessed watched memory.
+    Watchpoint { address: u16, access: Access },
+    /// The user interrupted execution.
+    Interrupted,
+    /// The program failed.
+    Error(ExecError),
+    /// Stepping backwards reached the oldest remembered instruction.
+    StartOfHistory,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint(address) => write!(f, "breakpoint at {:#05X}", address),
+            StopReason::Watchpoint { address, access } => {
+                write!(f, "watchpoint: {:#05X} {}", address, access)
+            }
+            StopReason::Interrupted => write!(f, "interrupted"),
+            StopReason::StartOfHistory => write!(f, "reached the start of the history"),
+            StopReason::Error(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+/// Controls the execution of a [`CHIP8`].
+///
+/// The debugger does not own the machine, so it can be inspected and modified directly between
+/// calls.
+pub struct Debugger {
+    /// Breakpoint addresses, with the condition under which each one stops.
+    breakpoints: BTreeMap<u16, Option<Expr>>,
+    watchpoints: Vec<Watchpoint>,
+    /// Memory accesses of every instruction executed under the debugger.
+    heatmap: Heatmap,
+    /// Recently executed instructions, for stepping backwards.
+    history: History,
+    /// Return address and stack depth to stop at when stepping over a call.
+    step_over: Option<(u16, usize)>,
+    /// Labels shown in listings and accepted in place of addresses.
+    symbols: Symbols,
+    /// Frozen cheats, written before every instruction.
+    cheats: Vec<Cheat>,
+    /// The RAM search in progress, if any.
+    search: Search,
+    /// Addresses whose values are shown as the program runs.
+    watch_list: WatchList,
+}
+
+impl Debugger {
+    /// Creates a new `Debugger` with no breakpoints for a machine with `memory_size` bytes of
+    /// memory.
+    pub fn new(memory_size: usize) -> Self {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            watchpoints: Vec::new(),
+            heatmap: Heatmap::new(memory_size),
+            history: History::new(DEFAULT_CAPACITY),
+            step_over: None,
+            symbols: Symbols::default(),
+            cheats: Vec::new(),
+            search: Search::default(),
+            watch_list: WatchList::default(),
+        }
+    }
+
+    /// Replaces the labels used for addresses.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// Returns the labels used for addresses.
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Sets a breakpoint at `address` that stops only when `condition` holds, or always if there
+    /// is no condition.
+    ///
+    /// Returns `false` if a breakpoint was already set, in which case its condition is replaced.
+    pub fn add_breakpoint(&mut self, address: u16, condition: Option<Expr>) -> bool {
+        self.breakpoints.insert(address, condition).is_none()
+    }
+
+    /// Clears the breakpoint at `address`. Returns `false` if there was none.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    /// Returns the addresses of all breakpoints in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// Returns the condition of the breakpoint at `address`, if it has one.
+    pub fn condition(&self, address: u16) -> Option<&Expr> {
+        self.breakpoints.get(&address).and_then(Option::as_ref)
+    }
+
+    /// Sets a watchpoint, replacing any other watchpoint at the same address.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.remove_watchpoint(watchpoint.address);
+        self.watchpoints.push(watchpoint);
+        self.watchpoints
+            .sort_by_key(|watchpoint| watchpoint.address);
+    }
+
+    /// Clears the watchpoint at `address`. Returns `false` if there was none.
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.address != address);
+        self.watchpoints.len() != len
+    }
+
+    /// Returns all watchpoints in ascending order of address.
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> + '_ {
+        self.watchpoints.iter()
+    }
+
+    /// Returns the memory accesses made so far.
+    pub fn heatmap(&self) -> &Heatmap {
+        &self.heatmap
+    }
+
+    /// Applies a cheat, keeping it to apply again before every instruction if it is frozen.
+    /// Replaces any frozen cheat at the same address.
+    pub fn add_cheat(&mut self, chip: &mut CHIP8, cheat: Cheat) {
+        cheat.apply(chip);
+        self.clear_history();
+        if cheat.frozen {
+            self.remove_cheat(cheat.address);
+            self.cheats.push(cheat);
+        }
+    }
+
+    /// Stops applying the frozen cheat at `address`, returning `false` if there was none.
+    pub fn remove_cheat(&mut self, address: u16) -> bool {
+        let len = self.cheats.len();
+        self.cheats.retain(|cheat| cheat.address != address);
+        self.cheats.len() != len
+    }
+
+    /// Returns the frozen cheats.
+    pub fn cheats(&self) -> impl Iterator<Item = &Cheat> + '_ {
+        self.cheats.iter()
+    }
+
+    /// Returns the RAM search.
+    pub fn search(&mut self) -> &mut Search {
+        &mut self.search
+    }
+
+    /// Pins `address` to the watch list.
+    pub fn pin(&mut self, address: u16, name: Option<String>) {
+        self.watch_list.pin(address, name);
+    }
+
+    /// Pins the candidates of the RAM search, returning how many there were.
+    pub fn pin_found(&mut self) -> Result<usize, String> {
+        self.watch_list.pin_candidates(&self.search)
+    }
+
+    /// Removes `address` from the watch list, returning `false` if it wasn't there.
+    pub fn unpin(&mut self, address: u16) -> bool {
+        self.watch_list.unpin(address)
+    }
+
+    /// Returns the watch list.
+    pub fn watch_list(&self) -> &WatchList {
+        &self.watch_list
+    }
+
+    /// Forgets the execution history, for when the user changes the state of the machine.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Executes an instruction, recording it in the heatmap and the history.
+    fn execute(&mut self, chip: &mut CHIP8) -> Result<(), ExecError> {
+        for cheat in &self.cheats {
+            cheat.apply(chip);
+        }
+        let before = Snapshot::take(chip);
+        let result = chip.step();
+        self.heatmap.record(chip);
+        self.history.record(before, chip);
+        result
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self, chip: &mut CHIP8) -> StopReason {
+        let address = chip.program_counter;
+        match self.execute(chip) {
+            Ok(()) => self
+                .check_watchpoints(&chip.accesses, address)
+                .unwrap_or(StopReason::Step),
+            Err(err) => StopReason::Error(err),
+        }
+    }
+
+    /// Begins stepping over the instruction at the program counter.
+    ///
+    /// Ordinary instructions are executed right away and their result is returned. For a call,
+    /// `None` is returned and the caller should keep calling [`Debugger::run_step`] until it
+    /// reports that the subroutine has returned.
+    pub fn start_step_over(&mut self, chip: &mut CHIP8) -> Option<StopReason> {
+        let address = chip.program_counter;
+        match chip.opcode_at(address).and_then(Instruction::decode) {
+            Some(Instruction::Call { .. }) => {
+                self.step_over = Some((address + 2, chip.stack.len()));
+                None
+            }
+            _ => Some(self.step(chip)),
+        }
+    }
+
+    /// Executes one instruction of a continuous run.
+    ///
+    /// Returns why execution should stop, if it should.
+    pub fn run_step(&mut self, chip: &mut CHIP8) -> Option<StopReason> {
+        let address = chip.program_counter;
+        let reason = match self.execute(chip) {
+            Ok(()) => self
+                .check_watchpoints(&chip.accesses, address)
+                .or_else(|| self.check_stop(chip)),
+            Err(err) => Some(StopReason::Error(err)),
+        };
+        if reason.is_some() {
+            self.step_over = None;
+        }
+        reason
+    }
+
+    /// Steps backwards through up to `count` instructions, stopping early if undoing one of them
+    /// touches a watchpoint.
+    pub fn reverse_step(&mut self, chip: &mut CHIP8, count: u16) -> StopReason {
+        for _ in 0..count {
+            let accesses = match self.history.undo(chip) {
+                Some(accesses) => accesses,
+                None => return StopReason::StartOfHistory,
+            };
+            if let Some(reason) = self.check_watchpoints(&accesses, chip.program_counter) {
+                return reason;
+            }
+        }
+        StopReason::Step
+    }
+
+    /// Steps backwards until an instruction that touched a watchpoint is undone, a breakpoint is
+    /// reached, or the history runs out.
+    pub fn reverse_continue(&mut self, chip: &mut CHIP8) -> StopReason {
+        loop {
+            match self.reverse_step(chip, 1) {
+                StopReason::Step if self.at_breakpoint(chip) => {
+                    return StopReason::Breakpoint(chip.program_counter)
+                }
+                StopReason::Step => (),
+                reason => return reason,
+            }
+        }
+    }
+
+    /// Abandons a step over that is in progress, for when the user pauses execution.
+    pub fn interrupt(&mut self) -> StopReason {
+        self.step_over = None;
+        StopReason::Interrupted
+    }
+
+    /// Returns the first of `accesses`, made by the instruction at `address`, that triggers a
+    /// watchpoint.
+    fn check_watchpoints(&self, accesses: &[Access], address: u16) -> Option<StopReason> {
+        accesses
+            .iter()
+            .find(|access| {
+                self.watchpoints
+                    .iter()
+                    .any(|watchpoint| watchpoint.triggers(access.kind, access.address))
+            })
+            .map(|&access| StopReason::Watchpoint { address, access })
+    }
+
+    /// Returns `true` if there is a breakpoint at the program counter whose condition holds.
+    fn at_breakpoint(&self, chip: &CHIP8) -> bool {
+        match self.breakpoints.get(&chip.program_counter) {
+            Some(None) => true,
+            Some(Some(condition)) => condition.is_true(chip),
+            None => false,
+        }
+    }
+
+    /// Returns why execution should stop at the current program counter, if it should.
+    fn check_stop(&self, chip: &CHIP8) -> Option<StopReason> {
+        let pc = chip.program_counter;
+        if self.at_breakpoint(chip) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        match self.step_over {
+            Some((address, depth)) if pc == address && chip.stack.len() <= depth => {
+                Some(StopReason::Step)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::AccessKind;
+    use crate::rom::Rom;
+
+    fn chip(program: &[u8]) -> CHIP8 {
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(program.to_vec()).unwrap())
+            .unwrap();
+        chip
+    }
+
+    /// Runs until the debugger stops, without a clock or a way to interrupt it.
+    fn resume(debugger: &mut Debugger, chip: &mut CHIP8) -> StopReason {
+        loop {
+            if let Some(reason) = debugger.run_step(chip) {
+                return reason;
+            }
+        }
+    }
+
+    fn step_over(debugger: &mut Debugger, chip: &mut CHIP8) -> StopReason {
+        match debugger.start_step_over(chip) {
+            Some(reason) => reason,
+            None => resume(debugger, chip),
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let mut chip = chip(&[0x00, 0xE0]);
+        "v3".parse::<Register>().unwrap().write(&mut chip, 0x1FF);
+        assert_eq!(chip.variable[3], 0xFF);
+        assert_eq!("PC".parse::<Register>().unwrap().read(&chip), 0x200);
+        assert!("v10".parse::<Register>().is_err());
+        assert_eq!(Register::V(0xA).to_string(), "VA");
+    }
+
+    #[test]
+    fn breakpoints_and_step_over() {
+        // 0x200: CALL 0x206; 0x202: LD V0, 1; 0x204: JP 0x204; 0x206: LD V1, 2; 0x208: RET
+        let mut chip = chip(&[0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE]);
+        let mut debugger = Debugger::new(chip.memory.len());
+
+        assert_eq!(step_over(&mut debugger, &mut chip), StopReason::Step);
+        assert_eq!(chip.program_counter, 0x202);
+        assert_eq!(chip.variable[1], 2);
+
+        assert!(debugger.add_breakpoint(0x204, None));
+        assert_eq!(
+            resume(&mut debugger, &mut chip),
+            StopReason::Breakpoint(0x204)
+        );
+        assert_eq!(chip.variable[0], 1);
+
+        assert_eq!(debugger.step(&mut chip), StopReason::Step);
+        assert_eq!(chip.program_counter, 0x204);
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        // 0x200: ADD V0, 1; 0x202: JP 0x200
+        let mut chip = chip(&[0x70, 0x01, 0x12, 0x00]);
+        let mut debugger = Debugger::new(chip.memory.len());
+        let condition = Expr::parse("V0 == 5").unwrap();
+        assert!(debugger.add_breakpoint(0x202, Some(condition.clone())));
+        assert_eq!(debugger.condition(0x202), Some(&condition));
+
+        assert_eq!(
+            resume(&mut debugger, &mut chip),
+            StopReason::Breakpoint(0x202)
+        );
+        assert_eq!(chip.variable[0], 5);
+
+        assert!(!debugger.add_breakpoint(0x202, None));
+        assert_eq!(debugger.condition(0x202), None);
+    }
+
+    #[test]
+    fn reverse_execution() {
+        // 0x200: LD I, 0x300; 0x202: ADD V0, 1; 0x204: LD [I], V0; 0x206: JP 0x202
+        let mut chip = chip(&[0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x02]);
+        let mut debugger = Debugger::new(chip.memory.len());
+        for _ in 0..10 {
+            debugger.step(&mut chip);
+        }
+        assert_eq!(chip.variable[0], 3);
+        assert_eq!(chip.memory[0x302], 3);
+
+        assert_eq!(debugger.reverse_step(&mut chip, 2), StopReason::Step);
+        assert_eq!(chip.program_counter, 0x204);
+        assert_eq!(chip.index, 0x302);
+        assert_eq!(chip.memory[0x302], 0);
+
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x301,
+            len: 1,
+            kind: WatchKind::Write,
+        });
+        match debugger.reverse_continue(&mut chip) {
+            StopReason::Watchpoint { address, access } => {
+                assert_eq!(address, 0x204);
+                assert_eq!(access.new, 2);
+            }
+            reason => panic!("unexpected {:?}", reason),
+        }
+        assert_eq!(chip.program_counter, 0x204);
+        assert_eq!(chip.memory[0x301], 0);
+        assert_eq!(
+            debugger.reverse_continue(&mut chip),
+            StopReason::StartOfHistory
+        );
+        assert_eq!(chip.program_counter, 0x200);
+    }
+
+    #[test]
+    fn target() {
+        use emu_debug::Target;
+
+        // 0x200: CALL 0x206; 0x202: LD V0, 1; 0x204: JP 0x204; 0x206: LD V1, 2; 0x208: RET
+        let mut chip = chip(&[0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE]);
+        let mut debugger = emu_debug::debugger::Debugger::new();
+        assert!(chip.is_call(0x200));
+        assert_eq!(chip.disassemble(0x200).bytes, [0x22, 0x06]);
+        assert_eq!(debugger.start_step_over(&mut chip), None);
+        while debugger.run_step(&mut chip).is_none() {}
+        assert_eq!(chip.program_counter, 0x202);
+        assert_eq!(Target::register(&chip, 1), 2);
+
+        chip.set_register(16, 0x300);
+        assert_eq!(
+            emu_debug::gdb::read_register(&chip, 16),
+            Some("0003".to_string())
+        );
+        assert_eq!(
+            debugger.step(&mut chip),
+            emu_debug::debugger::StopReason::Step
+        );
+        assert_eq!(chip.variable[0], 1);
+    }
+
+    #[test]
+    fn watchpoints() {
+        // 0x200: LD I, 0x300; 0x202: LD V0, 5; 0x204: LD [I], V1; 0x206: LD V1, [I]; 0x208: JP 0x208
+        let mut chip = chip(&[0xA3, 0x00, 0x60, 0x05, 0xF1, 0x55, 0xF1, 0x65, 0x12, 0x08]);
+        let mut debugger = Debugger::new(chip.memory.len());
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x300,
+            len: 1,
+            kind: WatchKind::Write,
+        });
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x301,
+            len: 1,
+            kind: WatchKind::Read,
+        });
+
+        let reason = resume(&mut debugger, &mut chip);
+        assert_eq!(
+            reason,
+            StopReason::Watchpoint {
+                address: 0x204,
+                access: Access {
+                    kind: AccessKind::Write,
+                    address: 0x300,
+                    old: 0x00,
+                    new: 0x05,
+                },
+            }
+        );
+        assert_eq!(
+            reason.to_string(),
+            "watchpoint: 0x204 wrote 0x300 (0x00 -> 0x05)"
+        );
+
+        chip.index = 0x300;
+        match debugger.step(&mut chip) {
+            StopReason::Watchpoint { address, access } => {
+                assert_eq!(address, 0x206);
+                assert_eq!(access.address, 0x301);
+            }
+            reason => panic!("unexpected {:?}", reason),
+        }
+
+        assert!(debugger.remove_watchpoint(0x300));
+        assert!(!debugger.remove_watchpoint(0x300));
+        assert_eq!(debugger.watchpoints().count(), 1);
+    }
+}
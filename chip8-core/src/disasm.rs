@@ -0,0 +1,291 @@
+//! Disassembler.
+
+use crate::instruction::Instruction;
+use crate::rom::{Rom, PROGRAM_START};
+use crate::symbols::Symbols;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// What a range of bytes is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    /// Reachable instructions.
+    Code,
+    /// Bytes drawn as a sprite by `DXYN`.
+    Sprite,
+    /// Anything else.
+    Data,
+}
+
+/// A line of a disassembly listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub region: Region,
+    /// Mnemonic for code, or a `db` directive for sprites and data.
+    pub text: String,
+}
+
+impl Line {
+    /// Replaces the address operand of a jump, call or `LD I` with its label in `symbols`.
+    pub fn symbolize(&mut self, symbols: &Symbols) {
+        let opcode = match self.bytes[..] {
+            [high, low, ..] if self.region == Region::Code => u16::from_be_bytes([high, low]),
+            _ => return,
+        };
+        let target = match Instruction::decode(opcode) {
+            Some(Instruction::Jump { nnn })
+            | Some(Instruction::Call { nnn })
+            | Some(Instruction::SetIndex { nnn })
+            | Some(Instruction::JumpOffset { nnn }) => nnn,
+            _ => return,
+        };
+        if let Some(name) = symbols.name(target) {
+            self.text = self.text.replace(&format!("{:#05X}", target), name);
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(
+            f,
+            "{:#05X}  {:<24}{}",
+            self.address,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+/// Formats bytes as a `db` directive.
+fn data_directive(bytes: &[u8]) -> String {
+    let values: Vec<String> = bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+    format!("db {}", values.join(", "))
+}
+
+/// Formats a sprite row as a `db` directive with a picture of its pixels.
+fn sprite_directive(byte: u8) -> String {
+    let pixels: String = (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect();
+    format!("db {:#04X}  ; {}", byte, pixels)
+}
+
+/// Decodes the instruction at `offset` in `bytes`, returning its length and mnemonic.
+fn decode_at(bytes: &[u8], offset: usize) -> Option<(usize, String)> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let instruction = Instruction::decode(word(offset)?)?;
+    match instruction {
+        Instruction::LoadLongIndex => Some((4, format!("LD I, {:#06X}", word(offset + 2)?))),
+        _ => Some((2, instruction.to_string())),
+    }
+}
+
+/// Returns the address and length of every instruction [`Rom::walk`] can reach, in order.
+fn code(rom: &Rom) -> Vec<(usize, usize)> {
+    let data = rom.data();
+    let mut code = Vec::new();
+    rom.walk(|address, _| {
+        if let Some((len, _)) = decode_at(data, address - PROGRAM_START as usize) {
+            code.push((address, len));
+        }
+    });
+    code.sort_unstable();
+    code
+}
+
+/// Finds the sprites drawn by `code`, as the address and length of each.
+///
+/// Each `DXYN` is paired with the `ANNN` that most recently pointed I somewhere along the same
+/// straight line of code. `DXY0` draws a 16x16 sprite of 32 bytes.
+fn sprites_in(rom: &Rom, code: &[(usize, usize)]) -> Vec<(u16, usize)> {
+    let mut sprites = Vec::new();
+    let mut index = None;
+    let mut previous_end = 0;
+    for &(address, len) in code {
+        if address != previous_end {
+            index = None;
+        }
+        previous_end = address + len;
+
+        let opcode = rom.opcode_at(address).unwrap_or(0);
+        match Instruction::decode(opcode) {
+            Some(Instruction::SetIndex { nnn }) => index = Some(nnn),
+            Some(Instruction::Draw { n, .. }) => {
+                let sprite_len = if n == 0 { 32 } else { n as usize };
+                if let Some(target) = index.filter(|&target| target >= PROGRAM_START) {
+                    sprites.push((target, sprite_len));
+                }
+            }
+            Some(Instruction::AddIndex { .. })
+            | Some(Instruction::FontChar { .. })
+            | Some(Instruction::BigFontChar { .. })
+            | Some(Instruction::LoadLongIndex)
+            | Some(Instruction::Store { .. })
+            | Some(Instruction::Load { .. }) => index = None,
+            _ => (),
+        }
+    }
+    sprites
+}
+
+/// Finds the sprites a ROM draws from fixed addresses, as the address and length of each.
+pub fn sprites(rom: &Rom) -> Vec<(u16, usize)> {
+    sprites_in(rom, &code(rom))
+}
+
+/// Works out which bytes of a ROM are code, sprites, or other data.
+///
+/// Code is whatever [`Rom::walk`] can reach, and sprites are found as described for [`sprites`].
+pub fn regions(rom: &Rom) -> Vec<Region> {
+    let data = rom.data();
+    let start = PROGRAM_START as usize;
+    let mut regions = vec![Region::Data; data.len()];
+
+    let code = code(rom);
+    for &(address, len) in &code {
+        for region in &mut regions[address - start..address - start + len] {
+            *region = Region::Code;
+        }
+    }
+    for (target, len) in sprites_in(rom, &code) {
+        let first = (target as usize - start).min(data.len());
+        let last = (target as usize - start + len).min(data.len());
+        for region in &mut regions[first..last] {
+            if *region == Region::Data {
+                *region = Region::Sprite;
+            }
+        }
+    }
+
+    regions
+}
+
+/// Produces an annotated listing of a whole ROM.
+pub fn disassemble(rom: &Rom) -> Vec<Line> {
+    let data = rom.data();
+    let regions = regions(rom);
+    let mut lines = Vec::new();
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let address = (PROGRAM_START as usize + offset) as u16;
+        let region = regions[offset];
+        let (len, text) = match region {
+            Region::Code => decode_at(data, offset).unwrap_or((2, data_directive(&data[offset..]))),
+            Region::Sprite => (1, sprite_directive(data[offset])),
+            Region::Data => {
+                // Group data into rows of up to eight bytes, aligned to eight byte boundaries.
+                let mut len = 1;
+                while offset + len < data.len()
+                    && regions[offset + len] == Region::Data
+                    && !(address as usize + len).is_multiple_of(8)
+                {
+                    len += 1;
+                }
+                (len, data_directive(&data[offset..offset + len]))
+            }
+        };
+
+        lines.push(Line {
+            address,
+            bytes: data[offset..offset + len].to_vec(),
+            region,
+            text,
+        });
+        offset += len;
+    }
+
+    lines
+}
+
+/// Disassembles `count` instructions of live memory starting at `address`.
+///
+/// Unlike [`disassemble`], everything is treated as code. This is what the debugger shows, since
+/// programs can modify themselves.
+pub fn disassemble_memory(memory: &[u8], address: u16, count: usize) -> Vec<Line> {
+    let mut lines = Vec::with_capacity(count);
+    let mut offset = address as usize;
+
+    while lines.len() < count && offset < memory.len() {
+        let (len, region, text) = match decode_at(memory, offset) {
+            Some((len, text)) => (len, Region::Code, text),
+            None => {
+                let len = 2.min(memory.len() - offset);
+                (
+                    len,
+                    Region::Data,
+                    data_directive(&memory[offset..offset + len]),
+                )
+            }
+        };
+
+        lines.push(Line {
+            address: offset as u16,
+            bytes: memory[offset..offset + len].to_vec(),
+            region,
+            text,
+        });
+        offset += len;
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing() {
+        // 0x200: LD I, 0x208; 0x202: DRW V0, V0, 2; 0x204: JP 0x204; 0x206: data; 0x208: sprite
+        let rom = Rom::from_bytes(vec![
+            0xA2, 0x08, 0xD0, 0x02, 0x12, 0x04, 0xFF, 0xFF, 0xF0, 0x90,
+        ])
+        .unwrap();
+        let lines: Vec<String> = disassemble(&rom).iter().map(|l| l.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "0x200  A2 08                   LD I, 0x208",
+                "0x202  D0 02                   DRW V0, V0, 2",
+                "0x204  12 04                   JP 0x204",
+                "0x206  FF FF                   db 0xFF, 0xFF",
+                "0x208  F0                      db 0xF0  ; ####....",
+                "0x209  90                      db 0x90  ; #..#....",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_instructions() {
+        let memory = [0xF0, 0x00, 0x12, 0x34, 0x00, 0x00];
+        let lines = disassemble_memory(&memory, 0, 3);
+        assert_eq!(lines[0].text, "LD I, 0x1234");
+        assert_eq!(lines[0].bytes.len(), 4);
+        assert_eq!(lines[1].text, "db 0x00, 0x00");
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn labels() {
+        let mut symbols = Symbols::default();
+        symbols.insert("sprite", 0x208);
+        let mut lines = disassemble_memory(&[0xA2, 0x08, 0x12, 0x08], 0, 2);
+        for line in &mut lines {
+            line.symbolize(&symbols);
+        }
+        assert_eq!(lines[0].text, "LD I, sprite");
+        assert_eq!(lines[1].text, "JP sprite");
+    }
+}
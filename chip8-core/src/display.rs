@@ -0,0 +1,259 @@
+//! Display-related structs and methods.
+
+use crate::png;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Width of the display in pixels.
+pub const WIDTH: usize = 64;
+
+/// Height of the display in pixels.
+pub const HEIGHT: usize = 32;
+
+/// Display for the CHIP-8.
+///
+/// The display is 64 pixels wide and 32 pixels tall. Since each pixel is either on or off, it
+/// is stored as a bool.
+///
+/// Formats meant to be read, such as JSON, hold it as a string of `#` and `.` for each row, as in
+/// [`Display::text`]. Binary formats hold it packed eight pixels to a byte, row by row.
+///
+/// TODO: Determine display update rate (Hz).
+#[derive(Clone)]
+pub struct Display {
+    pub(crate) screen: [[bool; WIDTH]; HEIGHT],
+}
+
+/// How a display is serialized in formats meant to be read.
+#[derive(Serialize, Deserialize)]
+struct Rows {
+    screen: Vec<String>,
+}
+
+impl From<&Display> for Rows {
+    fn from(display: &Display) -> Self {
+        Rows {
+            screen: display.text().lines().map(String::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<Rows> for Display {
+    type Error = String;
+
+    fn try_from(rows: Rows) -> Result<Self, String> {
+        if rows.screen.len() != HEIGHT {
+            return Err(format!(
+                "expected {} rows, found {}",
+                HEIGHT,
+                rows.screen.len()
+            ));
+        }
+        let mut display = Display::new();
+        for (row, line) in display.screen.iter_mut().zip(&rows.screen) {
+            if line.chars().count() != WIDTH {
+                return Err(format!("expected rows of {} pixels", WIDTH));
+            }
+            for (pixel, c) in row.iter_mut().zip(line.chars()) {
+                *pixel = match c {
+                    '#' => true,
+                    '.' => false,
+                    _ => return Err(format!("invalid pixel `{}`", c)),
+                };
+            }
+        }
+        Ok(display)
+    }
+}
+
+impl Serialize for Display {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return Rows::from(self).serialize(serializer);
+        }
+        let packed: Vec<u8> = self
+            .screen
+            .iter()
+            .flat_map(|row| row.chunks(8))
+            .map(|pixels| pixels.iter().fold(0u8, |byte, &on| byte << 1 | on as u8))
+            .collect();
+        serializer.serialize_bytes(&packed)
+    }
+}
+
+impl<'de> Deserialize<'de> for Display {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return Display::try_from(Rows::deserialize(deserializer)?).map_err(D::Error::custom);
+        }
+        let packed = Vec::<u8>::deserialize(deserializer)?;
+        if packed.len() != WIDTH * HEIGHT / 8 {
+            return Err(D::Error::invalid_length(packed.len(), &"256 bytes"));
+        }
+        let mut display = Display::new();
+        let pixels = display.screen.iter_mut().flat_map(|row| row.chunks_mut(8));
+        for (pixels, byte) in pixels.zip(packed) {
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = byte & (0x80 >> bit) != 0;
+            }
+        }
+        Ok(display)
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display::new()
+    }
+}
+
+impl Display {
+    /// Creates a new, empty `Display`.
+    pub fn new() -> Self {
+        Display {
+            screen: [[false; WIDTH]; HEIGHT],
+        }
+    }
+
+    /// Returns the pixels, row by row, `true` for lit.
+    pub fn screen(&self) -> &[[bool; WIDTH]; HEIGHT] {
+        &self.screen
+    }
+
+    /// Returns whether the pixel at (`x`, `y`) is lit, or `false` outside the screen.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.screen
+            .get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Lights or clears the pixel at (`x`, `y`), if it is on the screen.
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) {
+        if let Some(pixel) = self.pixel_mut(x, y) {
+            *pixel = lit;
+        }
+    }
+
+    /// Returns the pixel at (`x`, `y`) for changing it, or `None` outside the screen.
+    fn pixel_mut(&mut self, x: usize, y: usize) -> Option<&mut bool> {
+        self.screen.get_mut(y).and_then(|row| row.get_mut(x))
+    }
+
+    /// Clears the screen.
+    ///
+    /// # Examples
+    /// ```
+    /// # use chip8_core::display::Display;
+    /// let mut display: Display = Display::new();
+    /// // Turn on some pixels
+    /// display.set_pixel(5, 5, true);
+    /// display.set_pixel(36, 12, true);
+    /// display.set_pixel(61, 27, true);
+    ///
+    /// display.clear();
+    /// // All pixels are now off
+    /// ```
+    pub fn clear(&mut self) {
+        self.screen = [[false; WIDTH]; HEIGHT];
+    }
+
+    /// Renders the screen as text, one line per row, with `#` for pixels that are on and `.` for
+    /// pixels that are off.
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity((WIDTH + 1) * HEIGHT);
+        for row in &self.screen {
+            text.extend(row.iter().map(|&on| if on { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Renders the screen as a PNG image, white on black, with each pixel `scale` pixels wide and
+    /// tall.
+    pub fn png(&self, scale: usize) -> Vec<u8> {
+        let scale = scale.max(1);
+        let (width, height) = (WIDTH * scale, HEIGHT * scale);
+        let mut rgb = vec![0; width * height * 3];
+        for (y, row) in rgb.chunks_mut(width * 3).enumerate() {
+            for (x, pixel) in row.chunks_mut(3).enumerate() {
+                if self.screen[y / scale][x / scale] {
+                    pixel.fill(0xFF);
+                }
+            }
+        }
+        png::encode(width, height, &rgb)
+    }
+
+    /// Draws a sprite at (`x`, `y`) by XORing it onto the screen.
+    ///
+    /// Each byte of `sprite` is one row of eight pixels. The starting position wraps around the
+    /// screen, but the sprite itself is clipped at the edges. Returns `true` if any pixel was
+    /// turned off.
+    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+        let x = x as usize % WIDTH;
+        let y = y as usize % HEIGHT;
+        let mut collision = false;
+
+        for (row, byte) in sprite.iter().enumerate() {
+            let py = y + row;
+            if py >= HEIGHT {
+                break;
+            }
+            for bit in 0..8 {
+                let px = x + bit;
+                if px >= WIDTH {
+                    break;
+                }
+                if byte & (0x80 >> bit) != 0 {
+                    let pixel = self.pixel_mut(px, py);
+                    debug_assert!(pixel.is_some(), "sprites are clipped to the screen");
+                    if let Some(pixel) = pixel {
+                        collision |= *pixel;
+                        *pixel = !*pixel;
+                    }
+                }
+            }
+        }
+
+        collision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw() {
+        let mut display = Display::new();
+        assert!(!display.draw(62, 0, &[0xC0, 0xFF]));
+        assert!(display.screen[0][62] && display.screen[0][63]);
+        assert!(display.screen[1][63]);
+        // Clipped instead of wrapped.
+        assert!(!display.screen[0][0]);
+
+        assert!(display.draw(126, 32, &[0x80]));
+        assert!(!display.screen[0][62]);
+
+        let text = display.text();
+        assert_eq!(text.lines().count(), HEIGHT);
+        assert!(text.lines().next().unwrap().ends_with(".#"));
+    }
+
+    #[test]
+    fn png() {
+        let mut display = Display::new();
+        display.set_pixel(1, 0, true);
+        let image = display.png(2);
+        assert_eq!(image[..8], *b"\x89PNG\r\n\x1a\n");
+        // The width and the height in the header.
+        assert_eq!(image[16..24], [0, 0, 0, 128, 0, 0, 0, 64]);
+    }
+}
@@ -0,0 +1,300 @@
+//! CHIP-8 as an [`Emulator`], the interface shared by every system of this repository, so that
+//! launchers, debuggers and frontends written against it run CHIP-8 programs too.
+//!
+//! ```
+//! use chip8_core::emulator::System;
+//! use emu_core::Emulator;
+//!
+//! // 0x200: LD V0, 0; LD F, V0; DRW V0, V0, 5; 0x206: JP 0x206
+//! let mut system: Box<dyn Emulator> = Box::new(System::new());
+//! system.load_rom(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+//! system.step_frame().unwrap();
+//! assert_eq!(system.video().width, 64);
+//! // The top-left pixel of the 0 is lit.
+//! assert_eq!(&system.framebuffer()[..4], [0xFF; 4]);
+//! let mut samples = Vec::new();
+//! system.pull_audio(&mut samples);
+//! assert_eq!(samples.len(), 800);
+//! ```
+
+use crate::audio::Recording;
+use crate::builder::{BuildError, Builder};
+use crate::display::{HEIGHT, WIDTH};
+use crate::frontend::Audio;
+use crate::rom::Rom;
+use crate::state;
+use crate::{CHIP8, TIMER_PERIOD};
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+use emu_debug::Target;
+
+/// Samples per second of the tone.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// The keys of the keypad, numbered as the machine numbers them.
+pub const BUTTONS: [&str; 16] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F",
+];
+
+/// The color of a lit pixel.
+const ON: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+/// The color of a dark pixel.
+const OFF: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// A CHIP-8 machine with its ROM, tone and framebuffer.
+pub struct System {
+    /// How the machine is built again whenever a ROM is loaded or it is reset.
+    builder: Builder,
+    chip: CHIP8,
+    rom: Vec<u8>,
+    rom_hash: u64,
+    audio: Recording,
+    framebuffer: Vec<u8>,
+}
+
+impl System {
+    /// Creates the original COSMAC VIP machine, with no ROM loaded.
+    pub fn new() -> Self {
+        Self::with_builder(CHIP8::builder()).expect("the default machine is valid")
+    }
+
+    /// Creates the machine `builder` describes, with no ROM loaded. Fails if
+    /// [`Builder::build`] does.
+    pub fn with_builder(builder: Builder) -> Result<Self, BuildError> {
+        let chip = builder.clone().build()?;
+        let mut system = System {
+            builder,
+            chip,
+            rom: Vec::new(),
+            rom_hash: state::rom_hash(&[]),
+            audio: Recording::new(SAMPLE_RATE),
+            framebuffer: vec![0; WIDTH * HEIGHT * 4],
+        };
+        system.draw();
+        Ok(system)
+    }
+
+    /// Returns the machine, for tools that need more than the [`Emulator`] interface offers.
+    pub fn chip(&self) -> &CHIP8 {
+        &self.chip
+    }
+
+    /// Returns the machine, to change it directly.
+    pub fn chip_mut(&mut self) -> &mut CHIP8 {
+        &mut self.chip
+    }
+
+    /// Copies the display into the framebuffer.
+    fn draw(&mut self) {
+        let pixels = self.chip.display().screen().iter().flatten();
+        for (pixel, &on) in self.framebuffer.chunks_exact_mut(4).zip(pixels) {
+            pixel.copy_from_slice(if on { &ON } else { &OFF });
+        }
+    }
+}
+
+impl Default for System {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emulator for System {
+    fn name(&self) -> &'static str {
+        "CHIP-8"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: WIDTH,
+            height: HEIGHT,
+            frame_period: TIMER_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let parsed = Rom::from_bytes(rom.to_vec()).map_err(|e| Error::Rom(e.to_string()))?;
+        let mut chip = self.builder.clone().build().expect("built once already");
+        chip.load_rom(&parsed)
+            .map_err(|e| Error::Rom(e.to_string()))?;
+        self.chip = chip;
+        self.rom = rom.to_vec();
+        self.rom_hash = state::rom_hash(rom);
+        self.audio = Recording::new(SAMPLE_RATE);
+        self.draw();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        let rom = core::mem::take(&mut self.rom);
+        if rom.is_empty() {
+            self.chip = self.builder.clone().build().expect("built once already");
+            self.audio = Recording::new(SAMPLE_RATE);
+            self.draw();
+        } else if let Err(e) = self.load_rom(&rom) {
+            unreachable!("the ROM loaded before fails to load again: {}", e);
+        }
+    }
+
+    fn step_frame(&mut self) -> Result<(), Error> {
+        let _span = tracing::debug_span!("frame").entered();
+        let steps = self.chip.config().steps_per_frame();
+        let result = (0..steps).try_for_each(|_| self.chip.step());
+        if result.is_ok() {
+            self.audio.play(self.chip.timer().is_sounding());
+            self.chip.tick();
+        } else {
+            self.audio.play(false);
+        }
+        self.draw();
+        result.map_err(|e| Error::Stopped(e.to_string()))
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        for event in self.audio.drain() {
+            samples.extend(event.samples);
+        }
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if button < BUTTONS.len() {
+            self.chip.keyboard_mut().set(button as u8, pressed);
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        state::save(&self.chip.snapshot(), self.rom_hash)
+    }
+
+    fn load_state(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        state::restore(&mut self.chip, bytes, self.rom_hash).map_err(Error::State)?;
+        self.draw();
+        Ok(())
+    }
+
+    fn debug(&mut self) -> Option<Box<dyn Target + '_>> {
+        Some(Box::new(&mut self.chip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0x200: LD V0, 0; LD F, V0; DRW V0, V0, 5; 0x206: LD V1, 3; LD ST, V1;
+    /// 0x20A: SKP V0; JP 0x20A; 0x20E: ADD V2, 1; JP 0x20A
+    const ROM: [u8; 18] = [
+        0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x61, 0x03, 0xF1, 0x18, 0xE0, 0x9E, 0x12, 0x0A, 0x72,
+        0x01, 0x12, 0x0A,
+    ];
+
+    #[test]
+    fn runs_frames() {
+        let mut system = System::new();
+        system.load_rom(&ROM).unwrap();
+        assert_eq!(system.framebuffer().len(), system.video().len());
+        assert_eq!(&system.framebuffer()[..4], OFF);
+        system.step_frame().unwrap();
+        // The top row of the 0 is 0xF0.
+        assert_eq!(&system.framebuffer()[..16], [ON, ON, ON, ON].concat());
+        assert_eq!(&system.framebuffer()[16..20], OFF);
+    }
+
+    #[test]
+    fn pulls_audio() {
+        let mut system = System::new();
+        system.load_rom(&ROM).unwrap();
+        let mut samples = Vec::new();
+        for _ in 0..5 {
+            system.step_frame().unwrap();
+        }
+        system.pull_audio(&mut samples);
+        assert_eq!(samples.len(), 5 * 800);
+        assert!(samples[..2400].iter().any(|&sample| sample != 0));
+        assert!(samples[3200..].iter().all(|&sample| sample == 0));
+        system.pull_audio(&mut samples);
+        assert_eq!(samples.len(), 5 * 800);
+    }
+
+    #[test]
+    fn presses_buttons() {
+        let mut system = System::new();
+        system.load_rom(&ROM).unwrap();
+        system.step_frame().unwrap();
+        assert_eq!(system.chip().variable()[2], 0);
+        system.set_button(0, true);
+        system.set_button(99, true);
+        system.step_frame().unwrap();
+        assert_ne!(system.chip().variable()[2], 0);
+    }
+
+    #[test]
+    fn saves_and_loads_states() {
+        let mut system = System::new();
+        system.load_rom(&ROM).unwrap();
+        system.set_button(0, true);
+        system.step_frame().unwrap();
+        let saved = system.save_state();
+        let counted = system.chip().variable()[2];
+        system.step_frame().unwrap();
+        assert_ne!(system.chip().variable()[2], counted);
+        system.load_state(&saved).unwrap();
+        assert_eq!(system.chip().variable()[2], counted);
+        assert!(matches!(
+            system.load_state(&[1, 2, 3]),
+            Err(Error::State(_))
+        ));
+
+        system.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(matches!(system.load_state(&saved), Err(Error::State(_))));
+    }
+
+    #[test]
+    fn resets() {
+        let mut system = System::new();
+        system.load_rom(&ROM).unwrap();
+        system.step_frame().unwrap();
+        system.reset();
+        assert_eq!(system.chip().program_counter(), 0x200);
+        assert_eq!(&system.framebuffer()[..4], OFF);
+    }
+
+    #[test]
+    fn debugs() {
+        let mut system = System::new();
+        system.load_rom(&ROM).unwrap();
+        let mut target = system.debug().unwrap();
+        assert_eq!(target.architecture(), "chip8");
+        assert_eq!(target.disassemble(0x204).text, "DRW V0, V0, 5");
+        target.step(&mut Vec::new()).unwrap();
+        assert_eq!(target.program_counter(), 0x202);
+    }
+
+    #[test]
+    fn fails() {
+        let mut system: Box<dyn Emulator> = Box::new(System::new());
+        assert!(matches!(system.load_rom(&[]), Err(Error::Rom(_))));
+        assert!(matches!(system.load_rom(&[0; 4096]), Err(Error::Rom(_))));
+        // 0x200: RET
+        system.load_rom(&[0x00, 0xEE]).unwrap();
+        assert!(matches!(system.step_frame(), Err(Error::Stopped(_))));
+    }
+}
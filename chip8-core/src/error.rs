@@ -0,0 +1,54 @@
+//! Error types.
+
+use crate::bus::BusError;
+use crate::instruction::Instruction;
+
+/// Errors that stop the execution of a program.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ExecError {
+    /// The opcode at `address` is not a valid instruction.
+    #[error("invalid opcode {opcode:04X} at {address:#05X}")]
+    InvalidOpcode { address: u16, opcode: u16 },
+    /// The instruction at `address` is valid, but not supported by this interpreter.
+    #[error("unsupported instruction {instruction:?} at {address:#05X}")]
+    Unsupported {
+        address: u16,
+        instruction: Instruction,
+    },
+    /// A return was executed at `address` with an empty stack.
+    #[error("return with an empty stack at {address:#05X}")]
+    StackUnderflow { address: u16 },
+    /// A call was executed at `address` with [`STACK_DEPTH`](crate::STACK_DEPTH) calls already
+    /// on the stack.
+    #[error("call with a full stack at {address:#05X}")]
+    StackOverflow { address: u16 },
+    /// The instruction at `address` accessed memory at `target`, which does not exist.
+    #[error("memory access out of bounds at {address:#05X} (address {target:#X})")]
+    OutOfBounds { address: u16, target: usize },
+    /// The instruction at `address` wrote to memory at `target`, which is read-only.
+    #[error("write to read-only memory at {address:#05X} (address {target:#X})")]
+    ReadOnly { address: u16, target: usize },
+}
+
+impl ExecError {
+    /// Returns the address of the instruction that failed.
+    pub fn address(&self) -> u16 {
+        match *self {
+            ExecError::InvalidOpcode { address, .. }
+            | ExecError::Unsupported { address, .. }
+            | ExecError::StackUnderflow { address }
+            | ExecError::StackOverflow { address }
+            | ExecError::OutOfBounds { address, .. }
+            | ExecError::ReadOnly { address, .. } => address,
+        }
+    }
+
+    /// Converts the failure of an access to `target` by the instruction at `address`.
+    pub fn from_bus(err: BusError, address: u16, target: usize) -> Self {
+        match err {
+            BusError::OutOfBounds => ExecError::OutOfBounds { address, target },
+            BusError::ReadOnly => ExecError::ReadOnly { address, target },
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Events for embedders to react to, rather than polling the machine after every instruction.
+//!
+//! [`Events`] wraps the step of a machine like the other observers, and calls back with each
+//! [`Event`] as it happens. With the `std` feature, [`channel`] sends them to a receiver instead,
+//! for a GUI thread to pick up:
+//!
+//! ```
+//! use chip8_core::events::{self, Event};
+//! use chip8_core::rom::Rom;
+//! use chip8_core::CHIP8;
+//!
+//! // 0x200: CLS; 0x202: JP 0x202
+//! let rom = Rom::from_bytes(vec![0x00, 0xE0, 0x12, 0x02]).unwrap();
+//! let mut chip = CHIP8::new();
+//! chip.load_rom(&rom).unwrap();
+//! let (mut events, receiver) = events::channel();
+//! events.step(&mut chip, CHIP8::step).unwrap();
+//! assert_eq!(receiver.try_recv(), Ok(Event::DisplayUpdated));
+//! ```
+
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::CHIP8;
+#[cfg(feature = "std")]
+use std::sync::mpsc;
+
+/// Something that happened to the machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// An instruction cleared or drew on the display.
+    DisplayUpdated,
+    /// The sound timer started running, so a tone should play.
+    SoundStarted,
+    /// The sound timer ran out.
+    SoundStopped,
+    /// The program started waiting for a key press with the `FX0A` at `address`.
+    KeyWaited { address: u16 },
+    /// The program stopped at `address` on an opcode that is invalid or not supported.
+    IllegalInstruction { address: u16, opcode: u16 },
+    /// The program exited the interpreter with the `00FD` at `address`.
+    RomExited { address: u16 },
+}
+
+/// Reports the events of the instructions it executes to a callback.
+pub struct Events<F> {
+    callback: F,
+    /// Whether the sound timer was running when last checked.
+    sounding: bool,
+    /// Whether the program is waiting for a key, so that the wait is only reported once.
+    waiting: bool,
+}
+
+impl<F: FnMut(Event)> Events<F> {
+    /// Creates an observer that calls `callback` with each event.
+    pub fn new(callback: F) -> Self {
+        Events {
+            callback,
+            sounding: false,
+            waiting: false,
+        }
+    }
+
+    /// Executes one instruction with `step`, reporting the events it causes.
+    pub fn step<S>(&mut self, chip: &mut CHIP8, step: S) -> Result<(), ExecError>
+    where
+        S: FnOnce(&mut CHIP8) -> Result<(), ExecError>,
+    {
+        self.check_sound(chip);
+        let address = chip.program_counter;
+        let instruction = chip.opcode_at(address).and_then(Instruction::decode);
+        let result = step(chip);
+
+        match result {
+            Ok(()) => match instruction {
+                Some(Instruction::Clear) | Some(Instruction::Draw { .. }) => {
+                    (self.callback)(Event::DisplayUpdated)
+                }
+                // The instruction runs again until a key is pressed.
+                Some(Instruction::WaitKey { .. }) if chip.program_counter == address => {
+                    if !self.waiting {
+                        self.waiting = true;
+                        (self.callback)(Event::KeyWaited { address });
+                    }
+                }
+                _ => self.waiting = false,
+            },
+            Err(ExecError::Unsupported {
+                address,
+                instruction: Instruction::Exit,
+            }) => (self.callback)(Event::RomExited { address }),
+            Err(ExecError::InvalidOpcode { address, opcode }) => {
+                (self.callback)(Event::IllegalInstruction { address, opcode })
+            }
+            Err(ExecError::Unsupported { address, .. }) => {
+                let opcode = chip.opcode_at(address).unwrap_or_default();
+                (self.callback)(Event::IllegalInstruction { address, opcode })
+            }
+            Err(_) => (),
+        }
+        self.check_sound(chip);
+        result
+    }
+
+    /// Reports the sound starting or stopping since the last check.
+    ///
+    /// Every step checks this, so it only needs calling after updating the timers, to hear about
+    /// the sound stopping before the next instruction runs.
+    pub fn check_sound(&mut self, chip: &CHIP8) {
+        let sounding = chip.timer.is_sounding();
+        if sounding != self.sounding {
+            self.sounding = sounding;
+            (self.callback)(if sounding {
+                Event::SoundStarted
+            } else {
+                Event::SoundStopped
+            });
+        }
+    }
+}
+
+/// Creates an observer that sends its events to the returned receiver.
+#[cfg(feature = "std")]
+pub fn channel() -> (Events<impl FnMut(Event)>, mpsc::Receiver<Event>) {
+    let (sender, receiver) = mpsc::channel();
+    // A dropped receiver just means nobody is listening any more.
+    let events = Events::new(move |event| {
+        let _ = sender.send(event);
+    });
+    (events, receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use alloc::vec::Vec;
+
+    /// Runs `rom` for `steps` instructions, updating the timers after each one.
+    fn run(rom: &[u8], steps: usize) -> (Vec<Event>, Result<(), ExecError>) {
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        let mut seen = Vec::new();
+        let mut events = Events::new(|event| seen.push(event));
+        let mut result = Ok(());
+        for _ in 0..steps {
+            result = events.step(&mut chip, CHIP8::step);
+            if result.is_err() {
+                break;
+            }
+            chip.tick();
+            events.check_sound(&chip);
+        }
+        (seen, result)
+    }
+
+    #[test]
+    fn events() {
+        // 0x200: LD V0, 1; LD ST, V0; LD F, V0; DRW V0, V0, 5; LD V1, K
+        let (seen, result) = run(
+            &[0x60, 0x01, 0xF0, 0x18, 0xF0, 0x29, 0xD0, 0x05, 0xF1, 0x0A],
+            8,
+        );
+        assert!(result.is_ok());
+        assert_eq!(
+            seen,
+            [
+                Event::SoundStarted,
+                Event::SoundStopped,
+                Event::DisplayUpdated,
+                Event::KeyWaited { address: 0x208 },
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        // 0x200: EXIT
+        let (seen, _) = run(&[0x00, 0xFD], 1);
+        assert_eq!(seen, [Event::RomExited { address: 0x200 }]);
+        // 0x200: an opcode that is not an instruction
+        let (seen, _) = run(&[0x80, 0x0F], 1);
+        assert_eq!(
+            seen,
+            [Event::IllegalInstruction {
+                address: 0x200,
+                opcode: 0x800F
+            }]
+        );
+    }
+}
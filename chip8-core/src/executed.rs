@@ -0,0 +1,190 @@
+//! Running a machine as an iterator over the instructions it executes, for tools such as tracers,
+//! visualizers and fuzzing harnesses.
+//!
+//! [`CHIP8::run_iter`] executes one instruction per item, and tells what each changed:
+//!
+//! ```
+//! use chip8_core::debugger::Register;
+//! use chip8_core::executed::Change;
+//! use chip8_core::rom::Rom;
+//! use chip8_core::CHIP8;
+//!
+//! // 0x200: LD V0, 5; 0x202: JP 0x202
+//! let rom = Rom::from_bytes(vec![0x60, 0x05, 0x12, 0x02]).unwrap();
+//! let mut chip = CHIP8::new();
+//! chip.load_rom(&rom).unwrap();
+//! let first = chip.run_iter().next().unwrap().unwrap();
+//! assert_eq!(first.mnemonic, "LD");
+//! assert!(first.side_effects.contains(&Change::Register(Register::V(0), 5)));
+//! ```
+
+use crate::debugger::{Register, REGISTERS};
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::memory::AccessKind;
+use crate::CHIP8;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A change made by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Register(Register, u16),
+    Memory(u16, u8),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Register(register, value) => write!(f, "{}={:#X}", register, value),
+            Change::Memory(address, value) => write!(f, "[{:#05X}]={:#04X}", address, value),
+        }
+    }
+}
+
+/// An instruction the machine executed, and what it changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutedInstruction {
+    /// Address of the instruction.
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    /// The mnemonic of the instruction, such as `DRW`.
+    pub mnemonic: String,
+    /// The registers that changed, in the order of [`REGISTERS`], then the bytes of memory written.
+    pub side_effects: Vec<Change>,
+}
+
+impl ExecutedInstruction {
+    /// Executes one instruction, recording what it changed.
+    ///
+    /// Returns `None` when RAM does not hold a valid instruction at the program counter, which
+    /// only happens when a device answered for it instead.
+    pub fn execute(chip: &mut CHIP8) -> Result<Option<Self>, ExecError> {
+        let pc = chip.program_counter;
+        let opcode = chip.opcode_at(pc);
+        let before = REGISTERS.map(|register| register.read(chip));
+        chip.step()?;
+
+        let (opcode, instruction) =
+            match opcode.and_then(|opcode| Some((opcode, Instruction::decode(opcode)?))) {
+                Some(decoded) => decoded,
+                None => return Ok(None),
+            };
+
+        let mut side_effects: Vec<Change> = REGISTERS
+            .iter()
+            .zip(before.iter())
+            .filter(|&(register, &old)| register.read(chip) != old)
+            .map(|(&register, _)| Change::Register(register, register.read(chip)))
+            .collect();
+        side_effects.extend(
+            chip.accesses
+                .iter()
+                .filter(|access| access.kind == AccessKind::Write)
+                .map(|access| Change::Memory(access.address, access.new)),
+        );
+        Ok(Some(ExecutedInstruction {
+            pc,
+            opcode,
+            mnemonic: instruction.mnemonic(),
+            instruction,
+            side_effects,
+        }))
+    }
+}
+
+/// Executes the instructions of a machine one per item, created by [`CHIP8::run_iter`].
+///
+/// The timers are updated with [`CHIP8::tick`] after every frame's worth of instructions at the
+/// configured speed, as if the machine ran in real time. The iterator never ends while the
+/// program runs, and ends after yielding the error that stops it.
+pub struct RunIter<'a> {
+    chip: &'a mut CHIP8,
+    /// Instructions executed since the timers were last updated.
+    steps: u64,
+    failed: bool,
+}
+
+impl<'a> RunIter<'a> {
+    pub(crate) fn new(chip: &'a mut CHIP8) -> Self {
+        RunIter {
+            chip,
+            steps: 0,
+            failed: false,
+        }
+    }
+
+    /// Returns the machine, for looking at more than the changes between two items.
+    pub fn chip(&mut self) -> &mut CHIP8 {
+        self.chip
+    }
+}
+
+impl Iterator for RunIter<'_> {
+    type Item = Result<ExecutedInstruction, ExecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            let result = ExecutedInstruction::execute(self.chip);
+            self.steps += 1;
+            if self.steps == self.chip.config.steps_per_frame() {
+                self.steps = 0;
+                self.chip.tick();
+            }
+            match result {
+                Ok(Some(executed)) => return Some(Ok(executed)),
+                // The instruction ran, but cannot be described.
+                Ok(None) => (),
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl core::iter::FusedIterator for RunIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn run_iter() {
+        // 0x200: LD V0, 2; LD DT, V0; LD I, 0x300; LD [I], V0; JP 0x208; 0x302: RET
+        let mut rom = vec![0x60, 0x02, 0xF0, 0x15, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x08];
+        rom.resize(0x102, 0);
+        rom.extend([0x00, 0xEE]);
+        let mut chip = CHIP8::builder().speed(60).build().unwrap();
+        chip.load_rom(&Rom::from_bytes(rom).unwrap()).unwrap();
+
+        let executed: Vec<_> = chip.run_iter().take(4).map(Result::unwrap).collect();
+        assert_eq!(executed[0].pc, 0x200);
+        assert_eq!(executed[0].opcode, 0x6002);
+        assert_eq!(
+            executed[3].side_effects,
+            [
+                Change::Register(Register::I, 0x301),
+                Change::Memory(0x300, 2)
+            ]
+        );
+        // One instruction per frame at 60 instructions per second.
+        assert_eq!(chip.timer().delay(), 0);
+
+        // The program loops until the jump is patched into a jump to the return.
+        let mut iter = chip.run_iter();
+        assert!(iter.next().unwrap().is_ok());
+        iter.chip().memory_mut()[0x208..0x20A].copy_from_slice(&[0x13, 0x02]);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(
+            iter.next(),
+            Some(Err(ExecError::StackUnderflow { address: 0x302 }))
+        ));
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,320 @@
+//! Expressions over emulator state, used for conditional breakpoints.
+//!
+//! Operands are numbers, registers (`V0`-`VF`, `I`, `PC`, `DT`, `ST`) and bytes of memory
+//! (`[0x300]`, `[I + 1]`). From loosest to tightest binding, the operators are `||`, `&&`, the
+//! comparisons `== != < <= > >=`, then `+ - & |`, and finally unary `!`. Arithmetic wraps at 16
+//! bits, and comparisons evaluate to 1 or 0.
+
+use crate::command::parse_number;
+use crate::debugger::Register;
+use crate::CHIP8;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A binary operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+    BitOr,
+}
+
+impl BinOp {
+    /// Returns how tightly the operator binds; higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Or => 0,
+            BinOp::And => 1,
+            BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 2,
+            BinOp::Add | BinOp::Sub | BinOp::BitAnd | BinOp::BitOr => 3,
+        }
+    }
+
+    fn from_token(token: &str) -> Option<BinOp> {
+        let op = match token {
+            "||" => BinOp::Or,
+            "&&" => BinOp::And,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "&" => BinOp::BitAnd,
+            "|" => BinOp::BitOr,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn apply(self, left: u16, right: u16) -> u16 {
+        match self {
+            BinOp::Or => (left != 0 || right != 0) as u16,
+            BinOp::And => (left != 0 && right != 0) as u16,
+            BinOp::Eq => (left == right) as u16,
+            BinOp::Ne => (left != right) as u16,
+            BinOp::Lt => (left < right) as u16,
+            BinOp::Le => (left <= right) as u16,
+            BinOp::Gt => (left > right) as u16,
+            BinOp::Ge => (left >= right) as u16,
+            BinOp::Add => left.wrapping_add(right),
+            BinOp::Sub => left.wrapping_sub(right),
+            BinOp::BitAnd => left & right,
+            BinOp::BitOr => left | right,
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = match self {
+            BinOp::Or => "||",
+            BinOp::And => "&&",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+        };
+        f.write_str(token)
+    }
+}
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Number(u16),
+    Register(Register),
+    /// The byte of memory at an address.
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parses an expression.
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let expr = parser.binary(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected `{}`", token)),
+        }
+    }
+
+    /// Evaluates the expression. Memory outside the address space reads as zero.
+    pub fn eval(&self, chip: &CHIP8) -> u16 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(register) => register.read(chip),
+            Expr::Memory(address) => chip
+                .memory
+                .get(address.eval(chip) as usize)
+                .map_or(0, |&byte| byte as u16),
+            Expr::Not(operand) => (operand.eval(chip) == 0) as u16,
+            Expr::Binary(op, left, right) => op.apply(left.eval(chip), right.eval(chip)),
+        }
+    }
+
+    /// Returns `true` if the expression evaluates to a nonzero value.
+    pub fn is_true(&self, chip: &CHIP8) -> bool {
+        self.eval(chip) != 0
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{:#X}", value),
+            Expr::Register(register) => write!(f, "{}", register),
+            Expr::Memory(address) => write!(f, "[{}]", address),
+            Expr::Not(operand) => match **operand {
+                Expr::Binary(..) => write!(f, "!({})", operand),
+                _ => write!(f, "!{}", operand),
+            },
+            Expr::Binary(op, left, right) => {
+                // Operators are left-associative, so only a right operand of the same precedence
+                // needs parentheses.
+                match **left {
+                    Expr::Binary(inner, ..) if inner.precedence() < op.precedence() => {
+                        write!(f, "({})", left)?
+                    }
+                    _ => write!(f, "{}", left)?,
+                }
+                write!(f, " {} ", op)?;
+                match **right {
+                    Expr::Binary(inner, ..) if inner.precedence() <= op.precedence() => {
+                        write!(f, "({})", right)
+                    }
+                    _ => write!(f, "{}", right),
+                }
+            }
+        }
+    }
+}
+
+/// Splits an expression into words and operators.
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else {
+            chars.next();
+            let token = match (c, chars.peek()) {
+                ('|', Some('|')) | ('&', Some('&')) | ('=', Some('=')) => {
+                    chars.next();
+                    format!("{}{}", c, c)
+                }
+                ('!' | '<' | '>', Some('=')) => {
+                    chars.next();
+                    format!("{}=", c)
+                }
+                ('+' | '-' | '&' | '|' | '<' | '>' | '!' | '(' | ')' | '[' | ']', _) => {
+                    c.to_string()
+                }
+                _ => return Err(format!("unexpected `{}`", c)),
+            };
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+/// A precedence-climbing parser over a list of tokens.
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or("unexpected end of expression")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("expected `{}`, found `{}`", expected, token)),
+        }
+    }
+
+    /// Parses operators that bind at least as tightly as `precedence`.
+    fn binary(&mut self, precedence: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek().and_then(BinOp::from_token) {
+            if op.precedence() < precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(op.precedence() + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                let address = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(address)))
+            }
+            word if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                Ok(Expr::Number(parse_number(word)?))
+            }
+            word if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                Ok(Expr::Register(word.parse()?))
+            }
+            token => Err(format!("unexpected `{}`", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluation() {
+        let mut chip = CHIP8::new();
+        chip.variable[3] = 0x1F;
+        chip.index = 0x300;
+        chip.memory[0x301] = 7;
+
+        let expr = Expr::parse("V3 == 0x1F && I >= 0x300").unwrap();
+        assert!(expr.is_true(&chip));
+        chip.index = 0x2FF;
+        assert!(!expr.is_true(&chip));
+
+        assert_eq!(Expr::parse("[I + 2] + 1").unwrap().eval(&chip), 8);
+        assert_eq!(Expr::parse("0 - 1").unwrap().eval(&chip), 0xFFFF);
+        assert!(Expr::parse("!(v3 == 0 || pc < 0x200)")
+            .unwrap()
+            .is_true(&chip));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expr::parse("V3 ==").is_err());
+        assert!(Expr::parse("(V3").is_err());
+        assert!(Expr::parse("V3 V4").is_err());
+        assert!(Expr::parse("VG == 1").is_err());
+        assert!(Expr::parse("V3 = 1").is_err());
+    }
+
+    #[test]
+    fn display() {
+        let expr = Expr::parse("v3==31&&(i>=0x300||!dt)").unwrap();
+        assert_eq!(expr.to_string(), "V3 == 0x1F && (I >= 0x300 || !DT)");
+        assert_eq!(Expr::parse(&expr.to_string()), Ok(expr));
+        let expr = Expr::parse("1 - (2 - 3)").unwrap();
+        assert_eq!(expr.to_string(), "0x1 - (0x2 - 0x3)");
+    }
+}
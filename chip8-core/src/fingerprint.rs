@@ -0,0 +1,197 @@
+//! ROM fingerprints, for telling which variant an unlabeled ROM was written for.
+
+use crate::instruction::Instruction;
+use crate::rom::{Rom, MAX_CHIP8_SIZE};
+use crate::variant::Variant;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Width in characters of the longest bar in the opcode histogram.
+const BAR_WIDTH: usize = 40;
+
+/// Describes the feature an instruction beyond the original CHIP-8 provides.
+fn feature(instruction: Instruction) -> Option<&'static str> {
+    Some(match instruction {
+        Instruction::ScrollDown { .. } | Instruction::ScrollRight | Instruction::ScrollLeft => {
+            "scrolling"
+        }
+        Instruction::Exit => "exit",
+        Instruction::LowRes | Instruction::HighRes => "high resolution",
+        Instruction::BigFontChar { .. } => "large font",
+        Instruction::SaveFlags { .. } | Instruction::LoadFlags { .. } => "persistent flags",
+        Instruction::Draw { n: 0, .. } => "16x16 sprites",
+        Instruction::ScrollUp { .. } => "scrolling up",
+        Instruction::StoreRange { .. } | Instruction::LoadRange { .. } => "register ranges",
+        Instruction::LoadLongIndex => "16-bit addresses",
+        Instruction::Plane { .. } => "bitplanes",
+        Instruction::Audio | Instruction::Pitch { .. } => "programmable audio",
+        _ => return None,
+    })
+}
+
+/// A feature used by a ROM, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Feature {
+    pub variant: Variant,
+    /// Address of the first instruction using the feature.
+    pub address: u16,
+    pub count: usize,
+}
+
+/// Statistics about a ROM that hint at what it was written for.
+pub struct Fingerprint {
+    pub size: usize,
+    /// Shannon entropy of the bytes of the ROM, in bits per byte.
+    pub entropy: f64,
+    /// Number of reachable instructions for each mnemonic.
+    pub histogram: BTreeMap<String, usize>,
+    /// Bytes taken up by reachable instructions.
+    pub code_size: usize,
+    /// Features beyond the original CHIP-8, by name.
+    pub features: BTreeMap<&'static str, Feature>,
+}
+
+impl Fingerprint {
+    pub fn new(rom: &Rom) -> Self {
+        let data = rom.data();
+        let mut counts = [0usize; 256];
+        for &byte in data {
+            counts[byte as usize] += 1;
+        }
+        let entropy = counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / data.len() as f64;
+                -p * p.log2()
+            })
+            .sum();
+
+        let mut histogram = BTreeMap::new();
+        let mut code_size = 0;
+        let mut features = BTreeMap::new();
+        rom.walk(|address, opcode| {
+            let instruction = match opcode.and_then(Instruction::decode) {
+                Some(instruction) => instruction,
+                None => return,
+            };
+            *histogram.entry(instruction.mnemonic()).or_insert(0) += 1;
+            code_size += instruction.len() as usize;
+            if let Some(name) = feature(instruction) {
+                let feature = features.entry(name).or_insert(Feature {
+                    variant: instruction.variant(),
+                    address: address as u16,
+                    count: 0,
+                });
+                feature.address = feature.address.min(address as u16);
+                feature.count += 1;
+            }
+        });
+
+        Fingerprint {
+            size: data.len(),
+            entropy,
+            histogram,
+            code_size,
+            features,
+        }
+    }
+
+    /// Guesses the variant the ROM targets: the newest one whose features it uses, or XO-CHIP if
+    /// it only fits in XO-CHIP memory.
+    pub fn variant(&self) -> Variant {
+        let size = if self.size > MAX_CHIP8_SIZE {
+            Variant::XoChip
+        } else {
+            Variant::Chip8
+        };
+        self.features
+            .values()
+            .map(|feature| feature.variant)
+            .fold(size, Variant::max)
+    }
+
+    /// Formats the fingerprint as a report.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "size: {} bytes", self.size);
+        let _ = writeln!(
+            out,
+            "code: {} bytes reachable, {} bytes of data",
+            self.code_size,
+            self.size.saturating_sub(self.code_size)
+        );
+        let _ = writeln!(out, "entropy: {:.2} bits per byte", self.entropy);
+
+        let _ = writeln!(out, "\nvariant: {}", self.variant());
+        if self.size > MAX_CHIP8_SIZE {
+            let _ = writeln!(
+                out,
+                "  larger than the {} bytes of CHIP-8 memory",
+                MAX_CHIP8_SIZE
+            );
+        }
+        if self.features.is_empty() {
+            let _ = writeln!(out, "  no instructions beyond the original CHIP-8");
+        }
+        for (name, feature) in &self.features {
+            let _ = writeln!(
+                out,
+                "  {} ({}), {} {} from {:#05X}",
+                name,
+                feature.variant,
+                feature.count,
+                if feature.count == 1 { "use" } else { "uses" },
+                feature.address
+            );
+        }
+
+        let _ = writeln!(out, "\nopcodes:");
+        let mut histogram: Vec<(&String, &usize)> = self.histogram.iter().collect();
+        histogram.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let total: usize = self.histogram.values().sum();
+        let most = histogram.first().map_or(1, |(_, &count)| count);
+        for (mnemonic, &count) in histogram {
+            let _ = writeln!(
+                out,
+                "  {:<6}{:>5}  {:>5.1}%  {}",
+                mnemonic,
+                count,
+                count as f64 * 100.0 / total as f64,
+                "#".repeat((count * BAR_WIDTH).div_ceil(most))
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint() {
+        // 0x200: CLS; 0x202: HIGH; 0x204: DRW V0, V1, 0; 0x206: JP 0x204
+        let rom = Rom::from_bytes(vec![0x00, 0xE0, 0x00, 0xFF, 0xD0, 0x10, 0x12, 0x04]).unwrap();
+        let fingerprint = Fingerprint::new(&rom);
+        assert_eq!(fingerprint.variant(), Variant::SuperChip);
+        assert_eq!(fingerprint.code_size, 8);
+        assert_eq!(fingerprint.histogram["JP"], 1);
+        assert_eq!(
+            fingerprint.features["16x16 sprites"],
+            Feature {
+                variant: Variant::SuperChip,
+                address: 0x204,
+                count: 1,
+            }
+        );
+        // Seven different bytes, one of them twice.
+        assert!((fingerprint.entropy - 2.75).abs() < 1e-9);
+        assert!(fingerprint
+            .report()
+            .contains("high resolution (SUPER-CHIP), 1 use from 0x202"));
+
+        let rom = Rom::from_bytes(vec![0x12, 0x00]).unwrap();
+        assert_eq!(Fingerprint::new(&rom).variant(), Variant::Chip8);
+    }
+}
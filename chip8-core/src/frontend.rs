@@ -0,0 +1,191 @@
+//! The parts of a frontend that depend on the platform, and a driver that runs a machine through
+//! them.
+//!
+//! A platform implements [`Video`] to show the display, [`Audio`] to play the tone and [`Input`]
+//! to read the keypad, and [`Runner`] does the rest: executing instructions at the right speed,
+//! counting down the timers and calling each part once per frame. [`Headless`] implements all
+//! three by doing nothing, for running without a display.
+
+use crate::display::Display;
+use crate::error::ExecError;
+use crate::host::Clock;
+#[cfg(feature = "std")]
+use crate::host::SystemClock;
+use crate::keyboard::Keyboard;
+use crate::CHIP8;
+use crate::TIMER_PERIOD;
+
+/// Shows the display.
+pub trait Video {
+    /// Shows `display`, which is called at the end of every frame.
+    fn draw(&mut self, display: &Display);
+}
+
+/// Plays the tone of the sound timer.
+pub trait Audio {
+    /// Plays the tone for the coming frame if `sounding`, and is silent otherwise.
+    fn play(&mut self, sounding: bool);
+}
+
+/// Reads the keypad.
+pub trait Input {
+    /// Updates `keyboard` with the keys held down, at the start of every frame. Returns `false`
+    /// when the user asks to quit.
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool;
+}
+
+/// A frontend with no display, sound or keypad.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Headless;
+
+impl Video for Headless {
+    fn draw(&mut self, _display: &Display) {}
+}
+
+impl Audio for Headless {
+    fn play(&mut self, _sounding: bool) {}
+}
+
+impl Input for Headless {
+    fn poll(&mut self, _keyboard: &mut Keyboard) -> bool {
+        true
+    }
+}
+
+/// Runs a machine a frame at a time through the video, audio and input of a platform.
+pub struct Runner<V, A, I> {
+    pub chip: CHIP8,
+    pub video: V,
+    pub audio: A,
+    pub input: I,
+}
+
+impl<V: Video, A: Audio, I: Input> Runner<V, A, I> {
+    pub fn new(chip: CHIP8, video: V, audio: A, input: I) -> Self {
+        Runner {
+            chip,
+            video,
+            audio,
+            input,
+        }
+    }
+
+    /// Runs one frame: reads the keypad, executes the instructions of 1/60 second at the speed
+    /// the machine was configured with, counts down the timers and shows the display. Returns
+    /// `false` if the user asked to quit instead.
+    pub fn frame(&mut self) -> Result<bool, ExecError> {
+        self.frame_with(CHIP8::step)
+    }
+
+    /// Runs one frame like [`Runner::frame`], executing each instruction with `step`, which is
+    /// usually a wrapper around [`CHIP8::step`] that observes execution.
+    pub fn frame_with<F>(&mut self, mut step: F) -> Result<bool, ExecError>
+    where
+        F: FnMut(&mut CHIP8) -> Result<(), ExecError>,
+    {
+        if !self.input.poll(&mut self.chip.keyboard) {
+            return Ok(false);
+        }
+        let _span = tracing::debug_span!("frame").entered();
+        let steps = self.chip.config.steps_per_frame();
+        let result = (0..steps).try_for_each(|_| step(&mut self.chip));
+        if result.is_ok() {
+            self.audio.play(self.chip.timer.is_sounding());
+            self.chip.tick();
+        } else {
+            self.audio.play(false);
+        }
+        self.video.draw(&self.chip.display);
+        result.map(|()| true)
+    }
+
+    /// Runs frames in real time, 60 per second, until the user quits or the program fails.
+    #[cfg(feature = "std")]
+    pub fn run(&mut self) -> Result<(), ExecError> {
+        self.run_on(&mut SystemClock::new())
+    }
+
+    /// Runs frames like [`Runner::run`], 60 per second of `clock`.
+    pub fn run_on(&mut self, clock: &mut impl Clock) -> Result<(), ExecError> {
+        let mut next = clock.now();
+        while self.frame()? {
+            next += TIMER_PERIOD;
+            clock.wait_until(next);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::WIDTH;
+    use crate::rom::Rom;
+
+    /// Records what the runner does, and quits after a number of frames.
+    #[derive(Default)]
+    struct Recorder {
+        frames: usize,
+        quit_after: usize,
+        sounds: Vec<bool>,
+        third_row: Vec<bool>,
+    }
+
+    impl Video for Recorder {
+        fn draw(&mut self, display: &Display) {
+            self.third_row = display.screen[2][..WIDTH].to_vec();
+        }
+    }
+
+    impl Input for Recorder {
+        fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+            self.frames += 1;
+            keyboard.set(0x0, self.frames > 1);
+            self.frames <= self.quit_after
+        }
+    }
+
+    impl Audio for Recorder {
+        fn play(&mut self, sounding: bool) {
+            self.sounds.push(sounding);
+        }
+    }
+
+    #[test]
+    fn frames() {
+        // 0x200: LD V0, K; LD V0, 2; LD ST, V0; LD F, V0; DRW V0, V0, 5; JP 0x20A
+        let rom = [
+            0xF0, 0x0A, 0x60, 0x02, 0xF0, 0x18, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x0A,
+        ];
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        let input = Recorder {
+            quit_after: 4,
+            ..Recorder::default()
+        };
+        let mut runner = Runner::new(chip, Recorder::default(), Recorder::default(), input);
+        while runner.frame().unwrap() {}
+
+        assert_eq!(runner.input.frames, 5);
+        assert_eq!(runner.audio.sounds, [false, true, true, false]);
+        // The top row of the font's 2 is 0xF0, drawn at (2, 2).
+        assert_eq!(
+            runner.video.third_row[..7],
+            [false, false, true, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        // 0x200: RET with an empty stack
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(vec![0x00, 0xEE]).unwrap())
+            .unwrap();
+        let mut runner = Runner::new(chip, Headless, Headless, Headless);
+        assert!(matches!(
+            runner.frame(),
+            Err(ExecError::StackUnderflow { address: 0x200 })
+        ));
+    }
+}
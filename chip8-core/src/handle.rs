@@ -0,0 +1,331 @@
+//! A machine running on its own thread, for GUIs and debuggers that must not block on it.
+//!
+//! [`EmulatorHandle::spawn`] moves a machine onto a worker thread, which runs it in real time
+//! while resumed. Every method sends a command over a channel, and the ones that return something
+//! wait for the worker's reply, which comes between two frames. Nothing is shared, so the render
+//! thread can draw the last [`EmulatorHandle::frame`] while the worker carries on.
+
+use crate::debugger::Register;
+use crate::display::Display;
+use crate::error::ExecError;
+use crate::state::State;
+use crate::{CHIP8, TIMER_PERIOD};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// The registers of a machine at one point in time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub variable: [u8; 16],
+    pub index: u16,
+    pub program_counter: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Return addresses, the innermost last.
+    pub stack: Vec<u16>,
+}
+
+impl Registers {
+    /// Reads the registers of `chip`.
+    pub fn of(chip: &CHIP8) -> Self {
+        Registers {
+            variable: chip.variable,
+            index: chip.index,
+            program_counter: chip.program_counter,
+            delay_timer: chip.timer.delay_timer,
+            sound_timer: chip.timer.sound_timer,
+            stack: chip.stack.to_vec(),
+        }
+    }
+}
+
+/// Whether the worker is running the machine, and why it stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub running: bool,
+    /// Frames run since the worker started.
+    pub frames: u64,
+    /// The error that stopped the program, cleared by loading a state.
+    pub error: Option<ExecError>,
+}
+
+/// A request to the worker, with a channel for the reply when there is one.
+enum Command {
+    Pause,
+    Resume,
+    Step(Sender<Result<(), ExecError>>),
+    Key { key: u8, pressed: bool },
+    Write(Register, u16),
+    Registers(Sender<Registers>),
+    Frame(Sender<Display>),
+    SaveState(Sender<State>),
+    LoadState(Box<State>),
+    Status(Sender<Status>),
+}
+
+/// A machine owned by a worker thread.
+pub struct EmulatorHandle {
+    commands: Option<Sender<Command>>,
+    worker: Option<JoinHandle<CHIP8>>,
+}
+
+impl EmulatorHandle {
+    /// Moves `chip` onto a new worker thread. It starts paused, so that the frontend can set it up
+    /// before calling [`EmulatorHandle::resume`].
+    pub fn spawn(chip: CHIP8) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let worker = thread::spawn(move || Worker::new(chip).run(receiver));
+        EmulatorHandle {
+            commands: Some(commands),
+            worker: Some(worker),
+        }
+    }
+
+    fn send(&self, command: Command) {
+        // The worker only exits once the handle is dropped or stopped, and it does not panic.
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(command);
+        }
+    }
+
+    /// Sends a command carrying a reply channel, and waits for the reply.
+    fn ask<T>(&self, command: impl FnOnce(Sender<T>) -> Command) -> T {
+        let (reply, response) = mpsc::channel();
+        self.send(command(reply));
+        response
+            .recv()
+            .expect("the worker replies to every command")
+    }
+
+    /// Stops running the machine after the current frame.
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    /// Runs the machine in real time, 60 frames per second.
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    /// Executes a single instruction, which is mostly useful while paused.
+    pub fn step(&self) -> Result<(), ExecError> {
+        self.ask(Command::Step)
+    }
+
+    /// Presses or releases keypad `key`.
+    pub fn key(&self, key: u8, pressed: bool) {
+        self.send(Command::Key { key, pressed });
+    }
+
+    /// Sets `register` to `value`, as a debugger does.
+    pub fn write_register(&self, register: Register, value: u16) {
+        self.send(Command::Write(register, value));
+    }
+
+    /// Returns the registers as they are between two frames.
+    pub fn registers(&self) -> Registers {
+        self.ask(Command::Registers)
+    }
+
+    /// Returns a copy of the display as it was at the end of the last frame.
+    pub fn frame(&self) -> Display {
+        self.ask(Command::Frame)
+    }
+
+    /// Returns a copy of the whole machine, which [`state::save`](crate::state::save) writes
+    /// out as a save state.
+    pub fn save_state(&self) -> State {
+        self.ask(Command::SaveState)
+    }
+
+    /// Puts the machine back the way it was when `state` was taken, clearing any error.
+    pub fn load_state(&self, state: State) {
+        self.send(Command::LoadState(Box::new(state)));
+    }
+
+    /// Returns whether the machine is running, and why it stopped if it failed.
+    pub fn status(&self) -> Status {
+        self.ask(Command::Status)
+    }
+
+    /// Stops the worker and gives the machine back.
+    pub fn stop(mut self) -> CHIP8 {
+        self.join().expect("the worker is only joined once")
+    }
+
+    /// Closes the channel, which ends the worker, and waits for it.
+    fn join(&mut self) -> Option<CHIP8> {
+        self.commands = None;
+        let worker = self.worker.take()?;
+        Some(worker.join().expect("the worker does not panic"))
+    }
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// The machine and its state on the worker thread.
+struct Worker {
+    chip: CHIP8,
+    running: bool,
+    frames: u64,
+    error: Option<ExecError>,
+}
+
+impl Worker {
+    fn new(chip: CHIP8) -> Self {
+        Worker {
+            chip,
+            running: false,
+            frames: 0,
+            error: None,
+        }
+    }
+
+    /// Runs frames on time while resumed and handles commands in between, until the handle goes
+    /// away.
+    fn run(mut self, commands: Receiver<Command>) -> CHIP8 {
+        let mut next = Instant::now();
+        loop {
+            let command = if self.running {
+                match commands.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                    Ok(command) => Some(command),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => break,
+                }
+            };
+            match command {
+                Some(Command::Resume) if !self.running && self.error.is_none() => {
+                    self.running = true;
+                    next = Instant::now();
+                }
+                Some(command) => self.handle(command),
+                None => {
+                    self.frame();
+                    // Skip frames rather than catching up after falling behind.
+                    next = (next + TIMER_PERIOD).max(Instant::now());
+                }
+            }
+        }
+        self.chip
+    }
+
+    /// Runs one frame, stopping if the program fails.
+    fn frame(&mut self) {
+        for _ in 0..self.chip.config.steps_per_frame() {
+            if let Err(err) = self.chip.step() {
+                self.error = Some(err);
+                self.running = false;
+                return;
+            }
+        }
+        self.chip.tick();
+        self.frames += 1;
+    }
+
+    fn handle(&mut self, command: Command) {
+        // A reply can only fail to send if the handle gave up waiting, which it does not.
+        match command {
+            Command::Pause => self.running = false,
+            Command::Resume => (),
+            Command::Step(reply) => {
+                let result = self.chip.step();
+                if let Err(err) = &result {
+                    self.error = Some(err.clone());
+                    self.running = false;
+                }
+                let _ = reply.send(result);
+            }
+            Command::Key { key, pressed } => self.chip.keyboard.set(key, pressed),
+            Command::Write(register, value) => register.write(&mut self.chip, value),
+            Command::Registers(reply) => {
+                let _ = reply.send(Registers::of(&self.chip));
+            }
+            Command::Frame(reply) => {
+                let _ = reply.send(self.chip.display.clone());
+            }
+            Command::SaveState(reply) => {
+                let _ = reply.send(self.chip.snapshot());
+            }
+            Command::LoadState(state) => {
+                self.chip.restore(&state);
+                self.error = None;
+            }
+            Command::Status(reply) => {
+                let _ = reply.send(Status {
+                    running: self.running,
+                    frames: self.frames,
+                    error: self.error.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use std::time::Duration;
+
+    fn spawn(rom: &[u8]) -> EmulatorHandle {
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        EmulatorHandle::spawn(chip)
+    }
+
+    #[test]
+    fn commands() {
+        // 0x200: ADD V0, 1; LD F, V0; DRW V0, V0, 5; JP 0x200
+        let handle = spawn(&[0x70, 0x01, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x00]);
+        handle.step().unwrap();
+        let saved = handle.save_state();
+        handle.step().unwrap();
+        handle.step().unwrap();
+        assert_eq!(handle.registers().variable[0], 1);
+        assert!(handle.frame().screen[1][3]);
+
+        handle.load_state(saved);
+        handle.write_register(Register::V(0), 5);
+        handle.key(0x3, true);
+        let registers = handle.registers();
+        assert_eq!(registers.variable[0], 5);
+        assert_eq!(registers.program_counter, 0x202);
+        assert!(!handle.frame().screen[1][3]);
+
+        handle.resume();
+        thread::sleep(Duration::from_millis(50));
+        handle.pause();
+        let status = handle.status();
+        assert!(!status.running && status.frames > 0);
+        let chip = handle.stop();
+        assert!(chip.keyboard.is_pressed(0x3));
+    }
+
+    #[test]
+    fn errors() {
+        // 0x200: RET with an empty stack
+        let handle = spawn(&[0x00, 0xEE]);
+        handle.resume();
+        while handle.status().running {
+            thread::yield_now();
+        }
+        assert_eq!(
+            handle.status().error,
+            Some(ExecError::StackUnderflow { address: 0x200 })
+        );
+        // It stays stopped until a state is loaded.
+        handle.resume();
+        assert!(!handle.status().running);
+    }
+}
@@ -0,0 +1,152 @@
+//! Memory access heatmap, showing where a program reads and writes.
+
+use crate::memory::AccessKind;
+#[cfg(feature = "std")]
+use crate::png;
+use crate::CHIP8;
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::fmt::Write as _;
+
+/// Characters used for increasing levels of activity in text heatmaps.
+#[cfg(feature = "std")]
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+/// Addresses per row of a heatmap.
+#[cfg(feature = "std")]
+const COLUMNS: usize = 64;
+
+/// Size in pixels of one address in an exported image.
+#[cfg(feature = "std")]
+const CELL: usize = 8;
+
+/// Counts data reads and writes per address.
+pub struct Heatmap {
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+}
+
+impl Heatmap {
+    /// Creates an empty heatmap for a machine with `memory_size` bytes of memory.
+    pub fn new(memory_size: usize) -> Self {
+        Heatmap {
+            reads: vec![0; memory_size],
+            writes: vec![0; memory_size],
+        }
+    }
+
+    /// Counts the accesses made by the last instruction `chip` executed.
+    pub fn record(&mut self, chip: &CHIP8) {
+        for access in &chip.accesses {
+            let counts = match access.kind {
+                AccessKind::Read => &mut self.reads,
+                AccessKind::Write => &mut self.writes,
+            };
+            if let Some(count) = counts.get_mut(access.address as usize) {
+                *count = count.saturating_add(1);
+            }
+        }
+    }
+}
+
+/// Rendering scales the counts with floating point functions that only `std` provides.
+#[cfg(feature = "std")]
+impl Heatmap {
+    /// Scales `counts` logarithmically to levels from 0.0 to 1.0, relative to the busiest
+    /// address. `None` means the address was never accessed.
+    fn levels(counts: &[u32]) -> Vec<Option<f64>> {
+        let max = (1.0 + counts.iter().copied().max().unwrap_or(0) as f64).ln();
+        counts
+            .iter()
+            .map(|&count| (count > 0).then(|| (1.0 + count as f64).ln() / max))
+            .collect()
+    }
+
+    /// Returns the combined reads and writes of every address as a level from 0 (untouched) to
+    /// `levels - 1`.
+    pub fn heat(&self, levels: usize) -> Vec<usize> {
+        let totals: Vec<u32> = self
+            .reads
+            .iter()
+            .zip(&self.writes)
+            .map(|(reads, writes)| reads.saturating_add(*writes))
+            .collect();
+        Heatmap::levels(&totals)
+            .into_iter()
+            .map(|level| {
+                level.map_or(0, |level| {
+                    1 + (level * (levels - 2) as f64).round() as usize
+                })
+            })
+            .collect()
+    }
+
+    /// Renders the heatmap as text, one row of 64 addresses per line.
+    pub fn text(&self) -> String {
+        let heat = self.heat(SHADES.len());
+        let mut out = String::new();
+        for (row, levels) in heat.chunks(COLUMNS).enumerate() {
+            let _ = write!(out, "{:#05X} ", row * COLUMNS);
+            out.extend(levels.iter().map(|&level| SHADES[level] as char));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Renders the heatmap as a PNG image, with writes in red and reads in green.
+    pub fn png(&self) -> Vec<u8> {
+        let rows = self.reads.len().div_ceil(COLUMNS);
+        let (width, height) = (COLUMNS * CELL, rows * CELL);
+        let mut rgb = vec![0u8; width * height * 3];
+        let (reads, writes) = (Heatmap::levels(&self.reads), Heatmap::levels(&self.writes));
+        let channel = |level: Option<f64>| level.map_or(0, |level| (64.0 + level * 191.0) as u8);
+
+        for address in 0..self.reads.len() {
+            let color = match (reads[address], writes[address]) {
+                // Tint untouched memory so the grid of addresses stays visible.
+                (None, None) => [0, 0, 24],
+                (read, write) => [channel(write), channel(read), 0],
+            };
+            let (column, row) = (address % COLUMNS, address / COLUMNS);
+            for y in row * CELL..(row + 1) * CELL - 1 {
+                for x in column * CELL..(column + 1) * CELL - 1 {
+                    let pixel = (y * width + x) * 3;
+                    rgb[pixel..pixel + 3].copy_from_slice(&color);
+                }
+            }
+        }
+        png::encode(width, height, &rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn counts() {
+        // 0x200: LD I, 0x300; 0x202: LD [I], V1; 0x204: JP 0x200
+        let rom = Rom::from_bytes(vec![0xA3, 0x00, 0xF1, 0x55, 0x12, 0x00]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        let mut heatmap = Heatmap::new(chip.memory.len());
+        for _ in 0..6 {
+            chip.step().unwrap();
+            heatmap.record(&chip);
+        }
+
+        assert_eq!(heatmap.writes[0x300], 2);
+        let heat = heatmap.heat(6);
+        assert_eq!(heat[0x300], 5);
+        assert_eq!(heat[0x302], 0);
+        let text = heatmap.text();
+        assert!(text.lines().nth(12).unwrap().starts_with("0x300 @@ "));
+        assert_eq!(text.lines().count(), 64);
+        // A small image, so that the test runs in reasonable time under Miri.
+        assert!(Heatmap::new(128).png().starts_with(b"\x89PNG"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Execution history, for stepping backwards in the debugger.
+
+use crate::callstack::Frame;
+use crate::display::Display;
+use crate::instruction::Instruction;
+use crate::memory::{self, Access, AccessKind};
+use crate::rng::Rng;
+use crate::CHIP8;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use tinyvec::TinyVec;
+
+/// Number of instructions remembered by default, about 14 seconds at full speed.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Machine state before an instruction ran, apart from memory.
+///
+/// Memory is restored from the old values of the writes the instruction made, and the display
+/// is only saved for instructions that draw.
+pub struct Snapshot {
+    display: Option<Display>,
+    stack: TinyVec<[u16; 16]>,
+    variable: [u8; 16],
+    index: u16,
+    timers: (u8, u8),
+    rng: Rng,
+    program_counter: u16,
+    calls: Vec<Frame>,
+}
+
+impl Snapshot {
+    /// Saves the state of `chip` before it executes its next instruction.
+    pub fn take(chip: &CHIP8) -> Self {
+        let draws = matches!(
+            chip.opcode_at(chip.program_counter)
+                .and_then(Instruction::decode),
+            Some(Instruction::Clear | Instruction::Draw { .. })
+        );
+        Snapshot {
+            display: draws.then(|| chip.display.clone()),
+            stack: chip.stack.clone(),
+            variable: chip.variable,
+            index: chip.index,
+            timers: (chip.timer.delay_timer, chip.timer.sound_timer),
+            rng: chip.rng.clone(),
+            program_counter: chip.program_counter,
+            calls: chip.calls.clone(),
+        }
+    }
+}
+
+/// An executed instruction that can be undone.
+struct Entry {
+    before: Snapshot,
+    accesses: Vec<Access>,
+}
+
+/// The most recent instructions executed, oldest first.
+pub struct History {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+}
+
+impl History {
+    /// Creates an empty history remembering up to `capacity` instructions.
+    pub fn new(capacity: usize) -> Self {
+        History {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Records that `chip` executed an instruction, given its state from before.
+    pub fn record(&mut self, before: Snapshot, chip: &CHIP8) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            before,
+            accesses: chip.accesses.clone(),
+        });
+    }
+
+    /// Undoes the most recent instruction, returning the memory accesses it made, or `None` if
+    /// the history is empty.
+    pub fn undo(&mut self, chip: &mut CHIP8) -> Option<Vec<Access>> {
+        let Entry { before, accesses } = self.entries.pop_back()?;
+        for access in accesses.iter().rev() {
+            if access.kind == AccessKind::Write {
+                let undone =
+                    memory::write(&mut chip.memory, access.address as usize, &[access.old]);
+                debug_assert!(undone.is_ok(), "writes are recorded within memory");
+            }
+        }
+        if let Some(display) = before.display {
+            chip.display = display;
+        }
+        chip.stack = before.stack;
+        chip.variable = before.variable;
+        chip.index = before.index;
+        chip.timer.delay_timer = before.timers.0;
+        chip.timer.sound_timer = before.timers.1;
+        chip.rng = before.rng;
+        chip.program_counter = before.program_counter;
+        chip.calls = before.calls;
+        chip.accesses.clear();
+        Some(accesses)
+    }
+
+    /// Forgets everything, for when the state is changed outside of execution.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn undo() {
+        // 0x200: LD V0, 5; 0x202: LD I, 0x2FF; 0x204: LD [I], V0; 0x206: DRW V0, V0, 1
+        let rom = Rom::from_bytes(vec![0x60, 0x05, 0xA2, 0xFF, 0xF0, 0x55, 0xD0, 0x01]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        chip.memory[0x300] = 0x80;
+        let mut history = History::new(3);
+
+        for _ in 0..4 {
+            let before = Snapshot::take(&chip);
+            chip.step().unwrap();
+            history.record(before, &chip);
+        }
+        assert_eq!(history.entries.len(), 3);
+        assert!(chip.display.screen[5][5]);
+        assert_eq!(chip.memory[0x2FF], 5);
+
+        history.undo(&mut chip).unwrap();
+        assert!(!chip.display.screen[5][5]);
+        let accesses = history.undo(&mut chip).unwrap();
+        assert_eq!(accesses[0].address, 0x2FF);
+        assert_eq!(chip.memory[0x2FF], 0);
+        assert_eq!(chip.index, 0x2FF);
+        history.undo(&mut chip).unwrap();
+        assert_eq!(chip.program_counter, 0x202);
+        assert_eq!(chip.variable[0], 5);
+        assert!(history.undo(&mut chip).is_none());
+    }
+}
@@ -0,0 +1,302 @@
+//! What a machine needs from the host it runs on: randomness, time and somewhere to keep the
+//! persistent flags of `FX75`.
+//!
+//! Each is a small trait supplied at construction, so that the machine itself reads no clock and
+//! touches no file, and a run given a seed, a [`VirtualClock`] and [`MemoryFlags`] is the same on
+//! every platform. With the `std` feature, [`SystemEntropy`], [`SystemClock`] and [`FileFlags`]
+//! use the operating system:
+//!
+//! ```
+//! use chip8_core::frontend::{Headless, Runner};
+//! use chip8_core::host::{Clock, MemoryFlags, VirtualClock};
+//! use chip8_core::rom::Rom;
+//! use chip8_core::CHIP8;
+//!
+//! // 0x200: LD V0, 7; LD R, V0; LD V1, 30; LD DT, V1
+//! // 0x208: LD V2, DT; SE V2, 0; JP 0x208; EXIT
+//! let rom = Rom::from_bytes(vec![
+//!     0x60, 0x07, 0xF0, 0x75, 0x61, 0x1E, 0xF1, 0x15, 0xF2, 0x07, 0x32, 0x00, 0x12, 0x08, 0x00,
+//!     0xFD,
+//! ])
+//! .unwrap();
+//! let flags = MemoryFlags::default();
+//! let mut chip = CHIP8::builder().seed(1).flags(flags.clone()).build().unwrap();
+//! chip.load_rom(&rom).unwrap();
+//!
+//! let mut clock = VirtualClock::default();
+//! let mut runner = Runner::new(chip, Headless, Headless, Headless);
+//! // The program exits with an instruction that is not supported, after waiting half a second
+//! // that took no time at all.
+//! assert!(runner.run_on(&mut clock).is_err());
+//! assert_eq!(flags.get()[0], 7);
+//! assert_eq!(clock.now().as_millis(), 500);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "std")]
+use std::{fs, thread};
+
+/// Number of persistent flags, enough for `FX75` with any register.
+pub const FLAGS: usize = 16;
+
+/// A source of randomness for seeding the machine, such as a hardware generator.
+pub trait Entropy {
+    /// Returns a fresh seed.
+    fn seed(&mut self) -> u32;
+}
+
+/// Seeds from the nanoseconds of the system clock.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemEntropy;
+
+#[cfg(feature = "std")]
+impl Entropy for SystemEntropy {
+    fn seed(&mut self) -> u32 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0)
+    }
+}
+
+/// Time as a frontend runs frames by it.
+pub trait Clock {
+    /// Returns the time since an arbitrary start, which never goes backwards.
+    fn now(&mut self) -> Duration;
+
+    /// Returns once [`Clock::now`] has reached `deadline`.
+    fn wait_until(&mut self, deadline: Duration);
+}
+
+/// The monotonic clock of the operating system, which waits by sleeping.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&mut self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn wait_until(&mut self, deadline: Duration) {
+        thread::sleep(deadline.saturating_sub(self.now()));
+    }
+}
+
+/// A clock that jumps to each deadline instead of waiting, for running as fast as possible and
+/// the same way every time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VirtualClock {
+    now: Duration,
+}
+
+impl Clock for VirtualClock {
+    fn now(&mut self) -> Duration {
+        self.now
+    }
+
+    fn wait_until(&mut self, deadline: Duration) {
+        self.now = self.now.max(deadline);
+    }
+}
+
+/// Where the persistent flags of `FX75` and `FX85` are kept between runs, which was the RPL
+/// user flags of the HP 48 on SUPER-CHIP.
+///
+/// Storages are `Clone` so that a machine can be, and copies may share what they store.
+pub trait FlagStorage: Send + FlagStorageClone {
+    /// Fills `flags` with the stored flags, starting from the first. Flags never saved read as 0.
+    fn load(&mut self, flags: &mut [u8]);
+
+    /// Stores `flags` as the first flags, keeping the rest.
+    fn save(&mut self, flags: &[u8]);
+}
+
+/// Copies a boxed flag storage. Implemented for every storage that is `Clone`.
+pub trait FlagStorageClone {
+    fn clone_box(&self) -> Box<dyn FlagStorage>;
+}
+
+impl<T: FlagStorage + Clone + 'static> FlagStorageClone for T {
+    fn clone_box(&self) -> Box<dyn FlagStorage> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn FlagStorage> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl fmt::Debug for dyn FlagStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FlagStorage")
+    }
+}
+
+/// Flags kept in memory for as long as the program runs. Clones share the same flags, so the
+/// frontend can keep one to look at them or save them elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFlags {
+    flags: Arc<[AtomicU8; FLAGS]>,
+}
+
+impl MemoryFlags {
+    /// Returns the flags as they are now.
+    pub fn get(&self) -> [u8; FLAGS] {
+        let mut flags = [0; FLAGS];
+        self.load_into(&mut flags);
+        flags
+    }
+
+    fn load_into(&self, flags: &mut [u8]) {
+        for (flag, stored) in flags.iter_mut().zip(self.flags.iter()) {
+            *flag = stored.load(Ordering::Relaxed);
+        }
+    }
+}
+
+impl FlagStorage for MemoryFlags {
+    fn load(&mut self, flags: &mut [u8]) {
+        self.load_into(flags);
+    }
+
+    fn save(&mut self, flags: &[u8]) {
+        for (&flag, stored) in flags.iter().zip(self.flags.iter()) {
+            stored.store(flag, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Flags kept in a file, written whenever the program saves them. Failing to read or write it
+/// loses the flags rather than stopping the program, as the HP 48 would never fail to.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct FileFlags {
+    path: PathBuf,
+    /// Serializes the writes of clones.
+    lock: Arc<Mutex<()>>,
+}
+
+#[cfg(feature = "std")]
+impl FileFlags {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileFlags {
+            path: path.into(),
+            lock: Arc::default(),
+        }
+    }
+
+    fn read(&self) -> [u8; FLAGS] {
+        let mut stored = [0; FLAGS];
+        if let Ok(bytes) = fs::read(&self.path) {
+            let len = bytes.len().min(FLAGS);
+            stored[..len].copy_from_slice(&bytes[..len]);
+        }
+        stored
+    }
+}
+
+#[cfg(feature = "std")]
+impl FlagStorage for FileFlags {
+    fn load(&mut self, flags: &mut [u8]) {
+        let _lock = self.lock.lock();
+        flags.copy_from_slice(&self.read()[..flags.len()]);
+    }
+
+    fn save(&mut self, flags: &[u8]) {
+        let _lock = self.lock.lock();
+        let mut stored = self.read();
+        stored[..flags.len()].copy_from_slice(flags);
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(&self.path, stored);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ExecError;
+    use crate::rom::Rom;
+    use crate::CHIP8;
+
+    /// Runs `rom` until it fails, with `flags` if given.
+    fn run(rom: &[u8], flags: Option<MemoryFlags>) -> (CHIP8, ExecError) {
+        let mut builder = CHIP8::builder();
+        if let Some(flags) = flags {
+            builder = builder.flags(flags);
+        }
+        let mut chip = builder.build().unwrap();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        let err = loop {
+            if let Err(err) = chip.step() {
+                break err;
+            }
+        };
+        (chip, err)
+    }
+
+    #[test]
+    fn flags() {
+        // 0x200: LD V0, 1; LD V1, 2; LD R, V1; LD V0, 0; LD V2, R; EXIT
+        let rom = [
+            0x60, 0x01, 0x61, 0x02, 0xF1, 0x75, 0x60, 0x00, 0xF2, 0x85, 0x00, 0xFD,
+        ];
+        let flags = MemoryFlags::default();
+        let (chip, err) = run(&rom, Some(flags.clone()));
+        assert_eq!(err.address(), 0x20A);
+        assert_eq!(chip.variable()[..3], [1, 2, 0]);
+        assert_eq!(flags.get()[..3], [1, 2, 0]);
+
+        // Without storage, the flags are not supported.
+        let (_, err) = run(&rom, None);
+        assert_eq!(err.address(), 0x204);
+    }
+
+    #[test]
+    fn entropy() {
+        struct Dice;
+        impl Entropy for Dice {
+            fn seed(&mut self) -> u32 {
+                4
+            }
+        }
+        let mut chip = CHIP8::builder().entropy(Dice).build().unwrap();
+        let mut seeded = CHIP8::builder().seed(4).build().unwrap();
+        assert_eq!(chip.rng.next_u8(), seeded.rng.next_u8());
+    }
+}
@@ -0,0 +1,271 @@
+//! Memory inspector for the debugger.
+
+use crate::debugger::{Register, REGISTERS};
+use crate::font::FONT_SET;
+use crate::memory;
+use crate::{CHIP8, FONT_START};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Most changed bytes of memory listed by [`State::changes`].
+const MAX_LISTED: usize = 8;
+
+/// Number of updates for which a changed byte counts as recently written.
+const RECENT: u8 = 30;
+
+/// Why a byte of memory stands out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Highlight {
+    None,
+    /// Part of the instruction at the program counter.
+    ProgramCounter,
+    /// The byte the index register points to.
+    Index,
+    /// Part of the built-in font.
+    Font,
+    /// Changed within the last few updates.
+    Recent,
+}
+
+/// A row of a hexdump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    pub address: u16,
+    pub bytes: Vec<(u8, Highlight)>,
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} ", self.address)?;
+        for (column, (byte, _)) in self.bytes.iter().enumerate() {
+            // An extra space splits the row into two groups of eight.
+            let separator = if column == 8 { "  " } else { " " };
+            write!(f, "{}{:02X}", separator, byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows memory as a hexdump, keeping track of which bytes changed recently.
+pub struct Inspector {
+    previous: Vec<u8>,
+    /// Number of updates since each byte last changed.
+    ages: Vec<u8>,
+}
+
+impl Inspector {
+    /// Creates an inspector for `chip`, treating its current memory as unchanged.
+    pub fn new(chip: &CHIP8) -> Self {
+        Inspector {
+            previous: chip.memory.to_vec(),
+            ages: vec![RECENT; chip.memory.len()],
+        }
+    }
+
+    /// Notes which bytes changed since the last update. Call this once per frame or step.
+    pub fn update(&mut self, chip: &CHIP8) {
+        for ((age, previous), &byte) in self
+            .ages
+            .iter_mut()
+            .zip(self.previous.iter_mut())
+            .zip(chip.memory.iter())
+        {
+            if *previous != byte {
+                *previous = byte;
+                *age = 0;
+            } else if *age < RECENT {
+                *age += 1;
+            }
+        }
+    }
+
+    /// Returns how the byte at `address` should be highlighted.
+    pub fn highlight(&self, chip: &CHIP8, address: usize) -> Highlight {
+        let pc = chip.program_counter as usize;
+        let font = FONT_START as usize..FONT_START as usize + FONT_SET.len();
+        if address == pc || address == pc + 1 {
+            Highlight::ProgramCounter
+        } else if address == chip.index as usize {
+            Highlight::Index
+        } else if self.ages.get(address).is_some_and(|&age| age < RECENT) {
+            Highlight::Recent
+        } else if font.contains(&address) {
+            Highlight::Font
+        } else {
+            Highlight::None
+        }
+    }
+
+    /// Returns `count` rows of 16 bytes, starting at the row containing `start`.
+    pub fn rows(&self, chip: &CHIP8, start: u16, count: usize) -> Vec<Row> {
+        let first = start as usize & !0xF;
+        (first..chip.memory.len())
+            .step_by(16)
+            .take(count)
+            .map(|address| Row {
+                address: address as u16,
+                bytes: (address..address + 16)
+                    .filter_map(|address| {
+                        let byte = memory::byte(&chip.memory, address)?;
+                        Some((byte, self.highlight(chip, address)))
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// The registers, stack and memory of a machine when the debugger last stopped, for showing what
+/// changed since then.
+pub struct State {
+    registers: [u16; 19],
+    stack: Vec<u16>,
+    memory: Vec<u8>,
+}
+
+impl State {
+    /// Records the state of `chip`.
+    pub fn take(chip: &CHIP8) -> Self {
+        State {
+            registers: REGISTERS.map(|register| register.read(chip)),
+            stack: chip.stack.to_vec(),
+            memory: chip.memory.to_vec(),
+        }
+    }
+
+    /// Returns `true` if `register` has changed in `chip` since the state was taken.
+    pub fn register_changed(&self, chip: &CHIP8, register: Register) -> bool {
+        REGISTERS
+            .iter()
+            .position(|&r| r == register)
+            .is_some_and(|i| self.registers[i] != register.read(chip))
+    }
+
+    /// Returns `true` if the byte at `address` has changed in `chip` since the state was taken.
+    pub fn memory_changed(&self, chip: &CHIP8, address: usize) -> bool {
+        self.memory.get(address) != chip.memory.get(address)
+    }
+
+    /// Describes each change in `chip` since the state was taken, such as `V3 05->06`,
+    /// `push 0x204` or `[0x300] 00->05`.
+    pub fn changes(&self, chip: &CHIP8) -> Vec<String> {
+        let mut changes: Vec<String> = REGISTERS
+            .iter()
+            .zip(self.registers.iter())
+            .filter(|&(&register, &old)| register.read(chip) != old)
+            .map(|(&register, &old)| match register {
+                Register::I => format!("I {:#05X}->{:#05X}", old, chip.index),
+                _ => format!("{} {:02X}->{:02X}", register, old, register.read(chip)),
+            })
+            .collect();
+
+        let common = self
+            .stack
+            .iter()
+            .zip(chip.stack.iter())
+            .take_while(|(old, new)| old == new)
+            .count();
+        changes.extend(
+            self.stack[common..]
+                .iter()
+                .rev()
+                .map(|a| format!("pop {:#05X}", a)),
+        );
+        changes.extend(
+            chip.stack[common..]
+                .iter()
+                .map(|a| format!("push {:#05X}", a)),
+        );
+
+        let changed: Vec<(usize, u8, u8)> = self
+            .memory
+            .iter()
+            .zip(chip.memory.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(address, (&old, &new))| (address, old, new))
+            .collect();
+        changes.extend(
+            changed
+                .iter()
+                .take(MAX_LISTED)
+                .map(|(address, old, new)| format!("[{:#05X}] {:02X}->{:02X}", address, old, new)),
+        );
+        if changed.len() > MAX_LISTED {
+            changes.push(format!("{} more bytes", changed.len() - MAX_LISTED));
+        }
+        changes
+    }
+}
+
+/// Writes `bytes` to memory starting at `address`.
+pub fn poke(chip: &mut CHIP8, address: u16, bytes: &[u8]) -> Result<(), String> {
+    let start = address as usize;
+    memory::write(&mut chip.memory, start, bytes)
+        .map_err(|_| format!("{:#05X} is out of bounds", start + bytes.len().max(1) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights() {
+        let mut chip = CHIP8::new();
+        chip.index = 0x300;
+        let mut inspector = Inspector::new(&chip);
+
+        poke(&mut chip, 0x400, &[0xAB, 0xCD]).unwrap();
+        inspector.update(&chip);
+        assert_eq!(inspector.highlight(&chip, 0x400), Highlight::Recent);
+        assert_eq!(inspector.highlight(&chip, 0x201), Highlight::ProgramCounter);
+        assert_eq!(inspector.highlight(&chip, 0x300), Highlight::Index);
+        assert_eq!(inspector.highlight(&chip, 0x50), Highlight::Font);
+        assert_eq!(inspector.highlight(&chip, 0x402), Highlight::None);
+
+        for _ in 0..RECENT {
+            inspector.update(&chip);
+        }
+        assert_eq!(inspector.highlight(&chip, 0x400), Highlight::None);
+
+        let rows = inspector.rows(&chip, 0x405, 2);
+        assert_eq!(rows[0].address, 0x400);
+        assert_eq!(rows[0].bytes[1].0, 0xCD);
+        assert_eq!(rows[1].address, 0x410);
+        assert!(rows[0]
+            .to_string()
+            .starts_with("0400  AB CD 00 00 00 00 00 00  00"));
+        assert!(poke(&mut chip, 0xFFF, &[1, 2]).is_err());
+        assert!(poke(&mut chip, 0, &[]).is_ok());
+    }
+
+    #[test]
+    fn changes() {
+        let mut chip = CHIP8::new();
+        chip.stack.push(0x202);
+        let state = State::take(&chip);
+        assert!(state.changes(&chip).is_empty());
+
+        chip.variable[3] = 6;
+        chip.index = 0x302;
+        chip.stack.pop();
+        chip.stack.push(0x206);
+        chip.memory[0x300] = 5;
+        assert_eq!(
+            state.changes(&chip),
+            [
+                "V3 00->06",
+                "I 0x000->0x302",
+                "pop 0x202",
+                "push 0x206",
+                "[0x300] 00->05"
+            ]
+        );
+        assert!(state.register_changed(&chip, Register::V(3)));
+        assert!(!state.register_changed(&chip, Register::PC));
+        assert!(state.memory_changed(&chip, 0x300));
+    }
+}
@@ -0,0 +1,354 @@
+//! Instruction decoding.
+
+use crate::variant::Variant;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+/// A decoded CHIP-8 instruction.
+///
+/// Covers the original CHIP-8 instruction set as well as the SUPER-CHIP and XO-CHIP extensions.
+/// `x` and `y` are register numbers (`0x0..=0xF`), `n` is a 4-bit number, `nn` is an 8-bit
+/// immediate and `nnn` is a 12-bit address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN - Call a machine code routine on the host. Not supported by interpreters.
+    MachineCall { nnn: u16 },
+    /// 00CN - Scroll the display down by n pixels. (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    /// 00DN - Scroll the display up by n pixels. (XO-CHIP)
+    ScrollUp { n: u8 },
+    /// 00E0 - Clear the screen.
+    Clear,
+    /// 00EE - Return from a subroutine.
+    Return,
+    /// 00FB - Scroll the display right by 4 pixels. (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC - Scroll the display left by 4 pixels. (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD - Exit the interpreter. (SUPER-CHIP)
+    Exit,
+    /// 00FE - Switch to low resolution mode. (SUPER-CHIP)
+    LowRes,
+    /// 00FF - Switch to high resolution mode. (SUPER-CHIP)
+    HighRes,
+    /// 1NNN - Jump to nnn.
+    Jump { nnn: u16 },
+    /// 2NNN - Call the subroutine at nnn.
+    Call { nnn: u16 },
+    /// 3XNN - Skip the next instruction if VX == nn.
+    SkipEqImm { x: u8, nn: u8 },
+    /// 4XNN - Skip the next instruction if VX != nn.
+    SkipNeImm { x: u8, nn: u8 },
+    /// 5XY0 - Skip the next instruction if VX == VY.
+    SkipEqReg { x: u8, y: u8 },
+    /// 5XY2 - Store VX..=VY in memory starting at I. (XO-CHIP)
+    StoreRange { x: u8, y: u8 },
+    /// 5XY3 - Load VX..=VY from memory starting at I. (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN - Set VX to nn.
+    SetImm { x: u8, nn: u8 },
+    /// 7XNN - Add nn to VX, without touching the carry flag.
+    AddImm { x: u8, nn: u8 },
+    /// 8XY0 - Set VX to VY.
+    Set { x: u8, y: u8 },
+    /// 8XY1 - Set VX to VX | VY.
+    Or { x: u8, y: u8 },
+    /// 8XY2 - Set VX to VX & VY.
+    And { x: u8, y: u8 },
+    /// 8XY3 - Set VX to VX ^ VY.
+    Xor { x: u8, y: u8 },
+    /// 8XY4 - Set VX to VX + VY. VF is set to the carry.
+    Add { x: u8, y: u8 },
+    /// 8XY5 - Set VX to VX - VY. VF is set to NOT borrow.
+    Sub { x: u8, y: u8 },
+    /// 8XY6 - Shift right by one. VF is set to the bit shifted out.
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7 - Set VX to VY - VX. VF is set to NOT borrow.
+    SubN { x: u8, y: u8 },
+    /// 8XYE - Shift left by one. VF is set to the bit shifted out.
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0 - Skip the next instruction if VX != VY.
+    SkipNeReg { x: u8, y: u8 },
+    /// ANNN - Set the index register I to nnn.
+    SetIndex { nnn: u16 },
+    /// BNNN - Jump to nnn + V0.
+    JumpOffset { nnn: u16 },
+    /// CXNN - Set VX to a random number masked with nn.
+    Random { x: u8, nn: u8 },
+    /// DXYN - Draw an n-byte sprite from I at (VX, VY). VF is set on collision.
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E - Skip the next instruction if the key in VX is pressed.
+    SkipKeyPressed { x: u8 },
+    /// EXA1 - Skip the next instruction if the key in VX is not pressed.
+    SkipKeyNotPressed { x: u8 },
+    /// F000 NNNN - Set I to the 16-bit address in the following word. (XO-CHIP)
+    LoadLongIndex,
+    /// FN01 - Select the drawing planes. (XO-CHIP)
+    Plane { n: u8 },
+    /// F002 - Load 16 bytes of audio pattern from I. (XO-CHIP)
+    Audio,
+    /// FX07 - Set VX to the delay timer.
+    GetDelay { x: u8 },
+    /// FX0A - Wait for a key press and store it in VX.
+    WaitKey { x: u8 },
+    /// FX15 - Set the delay timer to VX.
+    SetDelay { x: u8 },
+    /// FX18 - Set the sound timer to VX.
+    SetSound { x: u8 },
+    /// FX1E - Add VX to I.
+    AddIndex { x: u8 },
+    /// FX29 - Set I to the small font character for the digit in VX.
+    FontChar { x: u8 },
+    /// FX30 - Set I to the large font character for the digit in VX. (SUPER-CHIP)
+    BigFontChar { x: u8 },
+    /// FX33 - Store the BCD representation of VX at I, I+1 and I+2.
+    Bcd { x: u8 },
+    /// FX3A - Set the audio pitch to VX. (XO-CHIP)
+    Pitch { x: u8 },
+    /// FX55 - Store V0..=VX in memory starting at I.
+    Store { x: u8 },
+    /// FX65 - Load V0..=VX from memory starting at I.
+    Load { x: u8 },
+    /// FX75 - Save V0..=VX to the persistent flag registers. (SUPER-CHIP)
+    SaveFlags { x: u8 },
+    /// FX85 - Load V0..=VX from the persistent flag registers. (SUPER-CHIP)
+    LoadFlags { x: u8 },
+}
+
+impl Instruction {
+    /// Decodes a two-byte opcode.
+    ///
+    /// Returns `None` if the opcode is not a valid instruction on any supported variant.
+    ///
+    /// # Examples
+    /// ```
+    /// # use chip8_core::instruction::Instruction;
+    /// assert_eq!(Instruction::decode(0x00E0), Some(Instruction::Clear));
+    /// assert_eq!(Instruction::decode(0x5AB1), None);
+    /// ```
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        // nibble 1. type of instruction.
+        let itype = (opcode >> 12) as u8;
+        // nibble 2. Used to look up one of 16 registers V0-VF.
+        let x = ((opcode >> 8) & 0xF) as u8;
+        // nibble 3. Used to look up one of 16 registers V0-VF.
+        let y = ((opcode >> 4) & 0xF) as u8;
+        // nibble 4. 4-bit number.
+        let n = (opcode & 0xF) as u8;
+        // second byte (nibble 3 and 4). 8-bit immediate number.
+        let nn = (opcode & 0xFF) as u8;
+        // nibble 2, 3, and 4. 12-bit immediate memory address
+        let nnn = opcode & 0xFFF;
+
+        let instruction = match (itype, x, y, n) {
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown { n },
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp { n },
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Clear,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::LowRes,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::HighRes,
+            // 0000 is almost always padding or data rather than a machine code call.
+            (0x0, _, _, _) if nnn != 0 => Instruction::MachineCall { nnn },
+            (0x1, _, _, _) => Instruction::Jump { nnn },
+            (0x2, _, _, _) => Instruction::Call { nnn },
+            (0x3, _, _, _) => Instruction::SkipEqImm { x, nn },
+            (0x4, _, _, _) => Instruction::SkipNeImm { x, nn },
+            (0x5, _, _, 0x0) => Instruction::SkipEqReg { x, y },
+            (0x5, _, _, 0x2) => Instruction::StoreRange { x, y },
+            (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::SetImm { x, nn },
+            (0x7, _, _, _) => Instruction::AddImm { x, nn },
+            (0x8, _, _, 0x0) => Instruction::Set { x, y },
+            (0x8, _, _, 0x1) => Instruction::Or { x, y },
+            (0x8, _, _, 0x2) => Instruction::And { x, y },
+            (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+            (0x8, _, _, 0x4) => Instruction::Add { x, y },
+            (0x8, _, _, 0x5) => Instruction::Sub { x, y },
+            (0x8, _, _, 0x6) => Instruction::ShiftRight { x, y },
+            (0x8, _, _, 0x7) => Instruction::SubN { x, y },
+            (0x8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (0x9, _, _, 0x0) => Instruction::SkipNeReg { x, y },
+            (0xA, _, _, _) => Instruction::SetIndex { nnn },
+            (0xB, _, _, _) => Instruction::JumpOffset { nnn },
+            (0xC, _, _, _) => Instruction::Random { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipKeyPressed { x },
+            (0xE, _, 0xA, 0x1) => Instruction::SkipKeyNotPressed { x },
+            (0xF, 0x0, 0x0, 0x0) => Instruction::LoadLongIndex,
+            (0xF, _, 0x0, 0x1) => Instruction::Plane { n: x },
+            (0xF, 0x0, 0x0, 0x2) => Instruction::Audio,
+            (0xF, _, 0x0, 0x7) => Instruction::GetDelay { x },
+            (0xF, _, 0x0, 0xA) => Instruction::WaitKey { x },
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelay { x },
+            (0xF, _, 0x1, 0x8) => Instruction::SetSound { x },
+            (0xF, _, 0x1, 0xE) => Instruction::AddIndex { x },
+            (0xF, _, 0x2, 0x9) => Instruction::FontChar { x },
+            (0xF, _, 0x3, 0x0) => Instruction::BigFontChar { x },
+            (0xF, _, 0x3, 0x3) => Instruction::Bcd { x },
+            (0xF, _, 0x3, 0xA) => Instruction::Pitch { x },
+            (0xF, _, 0x5, 0x5) => Instruction::Store { x },
+            (0xF, _, 0x6, 0x5) => Instruction::Load { x },
+            (0xF, _, 0x7, 0x5) => Instruction::SaveFlags { x },
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags { x },
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// Returns the length of the instruction in bytes.
+    ///
+    /// Every instruction is two bytes long except for the XO-CHIP `F000 NNNN`, which is four.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u16 {
+        match self {
+            Instruction::LoadLongIndex => 4,
+            _ => 2,
+        }
+    }
+
+    /// Returns the mnemonic of the instruction, such as `DRW` or `CALL`.
+    pub fn mnemonic(&self) -> String {
+        let text = self.to_string();
+        let end = text.find(' ').unwrap_or(text.len());
+        text[..end].to_string()
+    }
+
+    /// Returns the earliest variant that supports this instruction.
+    pub fn variant(&self) -> Variant {
+        match self {
+            Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::BigFontChar { .. }
+            | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } => Variant::SuperChip,
+            Instruction::ScrollUp { .. }
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadLongIndex
+            | Instruction::Plane { .. }
+            | Instruction::Audio
+            | Instruction::Pitch { .. } => Variant::XoChip,
+            Instruction::Draw { n: 0, .. } => Variant::SuperChip,
+            _ => Variant::Chip8,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction as an assembly mnemonic.
+    ///
+    /// The `F000 NNNN` address is not part of the instruction, so it is shown as `long`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::MachineCall { nnn } => write!(f, "SYS {:#05X}", nnn),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump { nnn } => write!(f, "JP {:#05X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "SAVE V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}-V{:X}", x, y),
+            Instruction::SetImm { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Instruction::Set { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetIndex { nnn } => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JumpOffset { nnn } => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongIndex => write!(f, "LD I, long"),
+            Instruction::Plane { n } => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::FontChar { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::BigFontChar { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::Store { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert_eq!(Instruction::decode(0x00E0), Some(Instruction::Clear));
+        assert_eq!(
+            Instruction::decode(0x1ABC),
+            Some(Instruction::Jump { nnn: 0xABC })
+        );
+        assert_eq!(
+            Instruction::decode(0xD12F),
+            Some(Instruction::Draw { x: 1, y: 2, n: 0xF })
+        );
+        assert_eq!(
+            Instruction::decode(0xF000),
+            Some(Instruction::LoadLongIndex)
+        );
+        assert_eq!(Instruction::decode(0x0000), None);
+        assert_eq!(Instruction::decode(0x5121), None);
+        assert_eq!(Instruction::decode(0x8AB8), None);
+        assert_eq!(Instruction::decode(0xE1FF), None);
+    }
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(Instruction::Clear.to_string(), "CLS");
+        assert_eq!(Instruction::Jump { nnn: 0x2A0 }.to_string(), "JP 0x2A0");
+        assert_eq!(
+            Instruction::SkipEqImm { x: 0xA, nn: 0x1F }.to_string(),
+            "SE VA, 0x1F"
+        );
+        assert_eq!(
+            Instruction::Draw { x: 0, y: 1, n: 5 }.to_string(),
+            "DRW V0, V1, 5"
+        );
+        assert_eq!(Instruction::Draw { x: 0, y: 1, n: 5 }.mnemonic(), "DRW");
+        assert_eq!(Instruction::Clear.mnemonic(), "CLS");
+    }
+
+    #[test]
+    fn variant() {
+        assert_eq!(Instruction::Clear.variant(), Variant::Chip8);
+        assert_eq!(Instruction::Exit.variant(), Variant::SuperChip);
+        assert_eq!(Instruction::LoadLongIndex.variant(), Variant::XoChip);
+        assert_eq!(Instruction::LoadLongIndex.len(), 4);
+    }
+}
@@ -0,0 +1,80 @@
+//! Keyboard-related structs and methods.
+
+/// Keypad layout, row by row, as printed on the COSMAC VIP.
+pub const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+/// Host keys mapped onto the keypad, in the same positions as [`LAYOUT`].
+const QWERTY: [[char; 4]; 4] = [
+    ['1', '2', '3', '4'],
+    ['q', 'w', 'e', 'r'],
+    ['a', 's', 'd', 'f'],
+    ['z', 'x', 'c', 'v'],
+];
+
+/// Returns the keypad key for a host key, using the left-hand side of a QWERTY keyboard.
+pub fn key_for_char(c: char) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    QWERTY.iter().zip(LAYOUT.iter()).find_map(|(chars, keys)| {
+        chars
+            .iter()
+            .position(|&mapped| mapped == c)
+            .map(|column| keys[column])
+    })
+}
+
+/// The 16-key hexadecimal keypad of the CHIP-8.
+///
+/// Keys are numbered `0x0..=0xF`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Keyboard {
+    keys: [bool; 16],
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Keyboard::new()
+    }
+}
+
+impl Keyboard {
+    /// Creates a new `Keyboard` with no keys pressed.
+    pub fn new() -> Self {
+        Keyboard { keys: [false; 16] }
+    }
+
+    /// Returns `true` if `key` is currently held down.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0xF) as usize]
+    }
+
+    /// Marks `key` as pressed or released.
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        self.keys[(key & 0xF) as usize] = pressed;
+    }
+
+    /// Returns the lowest numbered key that is currently held down.
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|&pressed| pressed)
+            .map(|key| key as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping() {
+        assert_eq!(key_for_char('1'), Some(0x1));
+        assert_eq!(key_for_char('X'), Some(0x0));
+        assert_eq!(key_for_char('v'), Some(0xF));
+        assert_eq!(key_for_char('p'), None);
+    }
+}
@@ -0,0 +1,811 @@
+//! The CHIP-8 virtual machine and the tools built around it: an assembler and disassembler,
+//! static analysis of ROMs, a debugger, save states, replay movies, tracing and profiling.
+//!
+//! Nothing here prints, touches the terminal or depends on an async runtime, so that the machine
+//! can be embedded in any frontend. A frontend loads a ROM, then calls [`CHIP8::step`] at
+//! [`INSTRUCTION_PERIOD`] and [`CHIP8::tick`] at [`TIMER_PERIOD`], drawing
+//! [`CHIP8::display`] and feeding key presses into [`CHIP8::keyboard_mut`]:
+//!
+//! ```
+//! use chip8_core::rom::Rom;
+//! use chip8_core::{CHIP8, STEPS_PER_FRAME};
+//!
+//! // 0x200: LD V0, 5; 0x202: JP 0x202
+//! let rom = Rom::from_bytes(vec![0x60, 0x05, 0x12, 0x02]).unwrap();
+//! let mut chip = CHIP8::new();
+//! chip.load_rom(&rom).unwrap();
+//! for _ in 0..STEPS_PER_FRAME {
+//!     chip.step().unwrap();
+//! }
+//! chip.tick();
+//! assert_eq!(chip.variable()[0], 5);
+//! ```
+//!
+//! # Features
+//!
+//! The `std` feature, on by default, adds everything that needs an operating system: reading
+//! ROMs, symbols and save state slots from files, seeding the random number generator from the
+//! clock, running a machine on its own thread with [`handle`], and the tools that write to files
+//! and streams, such as [`trace`] and [`profile`]. Without it the crate is `no_std`
+//! and only needs an allocator, so the machine, its debugger and save states can run on a
+//! microcontroller driving an LED matrix.
+//!
+//! The tools of the `chip8` command that other frontends have no use for are behind features of
+//! their own, off by default: `netplay` for playing over a network and streaming to viewers who
+//! vote for keys (the modules `netplay`, `remote` and `crowd`), `testing` for testing games and
+//! interpreters (`playtest`, `suite` and, with `std`, `compare`), `script` for driving ROMs from
+//! Rhai scripts (`script`), and `octo` for importing the save files of the Octo IDE (`octo`).
+//!
+//! The machine reports what it does through [`tracing`](https://docs.rs/tracing): each
+//! instruction runs in an `instruction` span at the `trace` level, which logs its disassembly,
+//! and the frames of [`frontend::Runner`] in `frame` spans at the `debug` level. Nothing is
+//! logged unless the frontend installs a subscriber.
+//!
+//! [`CHIP8`], its parts and [`State`] implement serde's `Serialize` and `Deserialize`, with the
+//! field names as they are in Rust. In formats meant to be read, as in `chip8 state export`,
+//! memory is a sequence of numbers and the display a string of `#` and `.` per row. Binary ones,
+//! such as the CBOR of save states, hold memory as a string of bytes and the display packed eight
+//! pixels to a byte.
+//!
+//! # Stability
+//!
+//! The crate follows semantic versioning. The state of the machine is only reachable through
+//! methods such as [`CHIP8::memory`] and [`CHIP8::variable_mut`], so that its layout can change in
+//! a minor release. Errors and events are `#[non_exhaustive]`, so that matching on them needs a
+//! wildcard arm and new variants are not breaking. The files the crate reads and writes, such as
+//! save states and movies, carry a version of their own.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+mod arrays;
+#[cfg(feature = "std")]
+pub mod asm;
+pub mod audio;
+pub mod builder;
+pub mod bus;
+pub mod callstack;
+pub mod cheat;
+pub mod command;
+#[cfg(all(feature = "std", feature = "testing"))]
+pub mod compare;
+pub mod coverage;
+#[cfg(feature = "std")]
+pub mod crash;
+#[cfg(feature = "netplay")]
+pub mod crowd;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod emulator;
+pub mod error;
+pub mod events;
+pub mod executed;
+mod expr;
+#[cfg(feature = "std")]
+pub mod fingerprint;
+pub mod font;
+pub mod frontend;
+#[cfg(feature = "std")]
+pub mod handle;
+pub mod heatmap;
+mod history;
+pub mod host;
+pub mod inspector;
+pub mod instruction;
+pub mod keyboard;
+pub mod memory;
+pub mod movie;
+#[cfg(feature = "netplay")]
+pub mod netplay;
+#[cfg(feature = "octo")]
+pub mod octo;
+pub mod peripheral;
+#[cfg(feature = "testing")]
+pub mod playtest;
+mod png;
+pub mod print;
+#[cfg(feature = "std")]
+pub mod profile;
+pub mod quirks;
+#[cfg(feature = "netplay")]
+pub mod remote;
+pub mod rewind;
+pub mod rng;
+pub mod rom;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod sprites;
+pub mod state;
+#[cfg(feature = "testing")]
+pub mod suite;
+pub mod symbols;
+pub mod timer;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod variant;
+
+use crate::builder::{BuildError, Builder, Config};
+use crate::bus::{Bus, MemoryMap};
+use crate::callstack::Frame;
+use crate::display::Display;
+use crate::error::ExecError;
+use crate::executed::RunIter;
+use crate::host::FlagStorage;
+use crate::instruction::Instruction;
+use crate::keyboard::Keyboard;
+use crate::memory::{Access, AccessKind};
+use crate::peripheral::Peripheral;
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::rom::{Rom, RomError};
+use crate::state::State;
+use crate::timer::Timer;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::time::Duration;
+use tinyvec::*;
+
+/// Address at which the font set is stored.
+pub const FONT_START: u16 = 0x50;
+
+/// Calls the stack holds at most, as on the COSMAC VIP.
+pub const STACK_DEPTH: usize = 16;
+
+/// Time between two instructions, for a speed of 700 instructions per second.
+pub const INSTRUCTION_PERIOD: Duration = Duration::from_micros(1429);
+
+/// Time between two timer updates, for a rate of 60 Hz.
+pub const TIMER_PERIOD: Duration = Duration::from_micros(16667);
+
+/// Instructions executed between two timer updates when running without a clock, such as in
+/// scripts.
+pub const STEPS_PER_FRAME: u64 = (TIMER_PERIOD.as_micros() / INSTRUCTION_PERIOD.as_micros()) as u64;
+
+/// CHIP-8 implementation in Rust.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct CHIP8 {
+    /// Memory for the CHIP-8.
+    ///
+    /// All memory is considered RAM and therefore writable. We use 4KB
+    /// (4096 bytes) because the index register and program counter can only address 12 bits (4096
+    /// addresses). A machine configured with less memory fails on accesses past
+    /// [`Config::memory_size`].
+    ///
+    /// The first 512 bits are reserved (`0x0..0x200`).
+    #[serde(with = "crate::arrays")]
+    pub(crate) memory: [u8; 4096],
+
+    /// Display for the CHIP-8.
+    pub(crate) display: Display,
+
+    /// Stack for the CHIP-8.
+    ///
+    /// The stack is comprised of [`STACK_DEPTH`] two-byte entries holding return addresses. A
+    /// call with a full stack fails with [`ExecError::StackOverflow`].
+    pub(crate) stack: TinyVec<[u16; 16]>,
+
+    /// Variable register for the CHIP-8.
+    ///
+    /// These are numbered `0x0..=0xF` and referenced as `V0..=VF`. `VF` is also used as a flag register
+    /// based on some rule.
+    pub(crate) variable: [u8; 16],
+
+    /// Index register for the CHIP-8.
+    ///
+    /// The index register points to locations in memory.
+    pub(crate) index: u16,
+
+    /// Timers for the CHIP-8.
+    pub(crate) timer: Timer,
+
+    /// Keyboard for the CHIP-8.
+    pub(crate) keyboard: Keyboard,
+
+    /// Random number generator used by `CXNN`.
+    pub(crate) rng: Rng,
+
+    /// Program Counter for the CHIP-8.
+    ///
+    /// The program counter points to the current instruction in memory.
+    pub(crate) program_counter: u16,
+
+    /// Data accesses made by the last instruction, for watchpoints.
+    pub(crate) accesses: Vec<Access>,
+
+    /// Subroutine calls that have not returned, alongside the stack, for backtraces.
+    pub(crate) calls: Vec<Frame>,
+
+    /// How the machine was configured with [`CHIP8::builder`].
+    pub(crate) config: Config,
+
+    /// Regions of memory that are read-only or handled by devices rather than RAM.
+    ///
+    /// Devices are not serialized, and a deserialized machine has plain RAM until they are mapped
+    /// again.
+    #[serde(skip)]
+    pub(crate) memory_map: MemoryMap,
+
+    /// Extensions told about each instruction and frame, which are not serialized either.
+    #[serde(skip)]
+    pub(crate) peripherals: Vec<Box<dyn Peripheral>>,
+
+    /// Where `FX75` keeps the persistent flags, if the host gave somewhere.
+    #[serde(skip)]
+    pub(crate) flags: Option<Box<dyn FlagStorage>>,
+}
+
+impl Default for CHIP8 {
+    fn default() -> Self {
+        CHIP8::new()
+    }
+}
+
+impl CHIP8 {
+    /// Constructs a new, empty `CHIP8` as the original COSMAC VIP interpreter, with 4KB of memory
+    /// and programs starting at `0x200`. Use [`CHIP8::builder`] to configure it differently.
+    ///
+    /// The random number generator is seeded from the clock, or with a fixed seed without the
+    /// `std` feature, in which case a seed from the hardware can be given with
+    /// [`Builder::seed`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use chip8_core::CHIP8;
+    /// let mut chip: CHIP8 = CHIP8::new();
+    /// ```
+    pub fn new() -> Self {
+        CHIP8::builder()
+            .build()
+            .expect("the default configuration is valid")
+    }
+
+    /// Returns a builder to configure the variant, quirks, speed, memory and so on of a machine.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Constructs a machine from a configuration checked by [`Builder::build`].
+    fn with_config(config: Config, font: &[u8; 80], rng: Rng) -> Self {
+        let mut memory: [u8; 4096] = [0; 4096];
+        // Insert fonts into address range 0x50..=0x9F.
+        memory::write(&mut memory, FONT_START as usize, font).expect("the font fits in memory");
+
+        let display: Display = Display::new();
+
+        let stack: TinyVec<[u16; 16]> = tiny_vec!();
+
+        let variable: [u8; 16] = [0; 16];
+
+        let index: u16 = 0;
+
+        let timer: Timer = Timer::new();
+
+        let keyboard: Keyboard = Keyboard::new();
+
+        let program_counter: u16 = config.start_address;
+
+        let mut memory_map = MemoryMap::new();
+        if config.protect_interpreter {
+            memory_map.protect(0..config.start_address);
+        }
+
+        CHIP8 {
+            memory,
+            display,
+            stack,
+            variable,
+            index,
+            timer,
+            keyboard,
+            rng,
+            program_counter,
+            accesses: Vec::new(),
+            calls: Vec::new(),
+            config,
+            memory_map,
+            peripherals: Vec::new(),
+            flags: None,
+        }
+    }
+
+    /// Returns all 4KB of memory, including any past [`Config::memory_size`].
+    pub fn memory(&self) -> &[u8; 4096] {
+        &self.memory
+    }
+
+    /// Returns memory for changing it directly, bypassing the [`MemoryMap`], as a debugger or
+    /// cheat does.
+    pub fn memory_mut(&mut self) -> &mut [u8; 4096] {
+        &mut self.memory
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
+
+    /// Returns the return addresses on the stack, the innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Returns the variable registers `V0..=VF`.
+    pub fn variable(&self) -> &[u8; 16] {
+        &self.variable
+    }
+
+    pub fn variable_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.variable
+    }
+
+    /// Returns the index register.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn set_index(&mut self, index: u16) {
+        self.index = index;
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    pub fn timer_mut(&mut self) -> &mut Timer {
+        &mut self.timer
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
+    }
+
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    /// Replaces the random number generator, such as with one seeded for a reproducible run.
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// Returns the data accesses made by the last instruction, for watchpoints.
+    pub fn accesses(&self) -> &[Access] {
+        &self.accesses
+    }
+
+    /// Returns the subroutine calls that have not returned, outermost first, for backtraces.
+    pub fn calls(&self) -> &[Frame] {
+        &self.calls
+    }
+
+    /// Returns how the machine was configured with [`CHIP8::builder`].
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Changes the quirks of the running machine, from the next instruction on.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.config.quirks = quirks;
+    }
+
+    /// Changes how many instructions the running machine executes per second, from the next
+    /// frame on. Fails for 0, as [`Builder::build`] does.
+    pub fn set_speed(&mut self, speed: u32) -> Result<(), BuildError> {
+        if speed == 0 {
+            return Err(BuildError::ZeroSpeed);
+        }
+        self.config.speed = speed;
+        Ok(())
+    }
+
+    /// Returns the regions of memory that are read-only or handled by devices.
+    pub fn memory_map(&self) -> &MemoryMap {
+        &self.memory_map
+    }
+
+    /// Returns the memory map, to protect regions or map devices into them.
+    pub fn memory_map_mut(&mut self) -> &mut MemoryMap {
+        &mut self.memory_map
+    }
+
+    /// Returns an iterator that executes one instruction per item, describing what each changed.
+    pub fn run_iter(&mut self) -> RunIter<'_> {
+        RunIter::new(self)
+    }
+
+    /// Attaches `peripheral`, which from then on hears about every instruction and frame.
+    pub fn attach(&mut self, peripheral: impl Peripheral + 'static) {
+        self.peripherals.push(Box::new(peripheral));
+    }
+
+    /// Updates the timers and ticks the peripherals. Frontends call this 60 times per second,
+    /// every [`TIMER_PERIOD`].
+    pub fn tick(&mut self) {
+        self.timer.cycle();
+        self.with_peripherals(|peripheral, chip| peripheral.tick(chip));
+    }
+
+    /// Calls `f` with each peripheral and the machine. Peripherals attached by `f` are kept.
+    fn with_peripherals(&mut self, mut f: impl FnMut(&mut dyn Peripheral, &mut CHIP8)) {
+        if self.peripherals.is_empty() {
+            return;
+        }
+        let mut peripherals = core::mem::take(&mut self.peripherals);
+        for peripheral in &mut peripherals {
+            f(peripheral.as_mut(), self);
+        }
+        peripherals.append(&mut self.peripherals);
+        self.peripherals = peripherals;
+    }
+
+    /// Copies the whole machine, apart from the accesses made by the last instruction, without
+    /// serializing it.
+    pub fn snapshot(&self) -> State {
+        State {
+            memory: self.memory,
+            display: self.display.clone(),
+            stack: self.stack.clone(),
+            variable: self.variable,
+            index: self.index,
+            timer: self.timer.clone(),
+            keyboard: self.keyboard.clone(),
+            rng: self.rng.clone(),
+            program_counter: self.program_counter,
+            calls: self.calls.clone(),
+        }
+    }
+
+    /// Puts the machine back the way it was when `state` was taken.
+    pub fn restore(&mut self, state: &State) {
+        self.memory = state.memory;
+        self.display = state.display.clone();
+        self.stack = state.stack.clone();
+        self.variable = state.variable;
+        self.index = state.index;
+        self.timer = state.timer.clone();
+        self.keyboard = state.keyboard.clone();
+        self.rng = state.rng.clone();
+        self.program_counter = state.program_counter;
+        self.calls = state.calls.clone();
+        self.accesses.clear();
+    }
+
+    /// Loads a ROM into memory at the start address, `0x200` unless configured otherwise, and points
+    /// the program counter at it.
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), RomError> {
+        let start = self.config.start_address as usize;
+        let available = self.config.memory_size.saturating_sub(start);
+        memory::write(self.ram_mut(), start, rom.data())
+            .map_err(|_| RomError::TooLarge(rom.data().len(), available))?;
+        self.program_counter = self.config.start_address;
+        tracing::debug!(bytes = rom.data().len(), "loaded a ROM");
+        Ok(())
+    }
+
+    /// Fetches an instruction from the current program counter.
+    fn fetch(&mut self) -> Result<u16, ExecError> {
+        let address = self.program_counter;
+        let opcode = self
+            .read16(address)
+            .map_err(|err| ExecError::from_bus(err, address, address as usize + 1))?;
+        self.program_counter += 2;
+
+        Ok(opcode)
+    }
+
+    /// Reads the opcode at `address` from RAM, if it lies within memory, without going through
+    /// the devices of the memory map.
+    pub fn opcode_at(&self, address: u16) -> Option<u16> {
+        let bytes = memory::read(self.ram(), address as usize, 2).ok()?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Returns the RAM the machine was configured with, which may be less than the 4KB it holds.
+    fn ram(&self) -> &[u8] {
+        debug_assert!(self.config.memory_size <= self.memory.len());
+        &self.memory[..self.config.memory_size.min(self.memory.len())]
+    }
+
+    /// Returns the RAM the machine was configured with for changing it.
+    fn ram_mut(&mut self) -> &mut [u8] {
+        debug_assert!(self.config.memory_size <= self.memory.len());
+        let size = self.config.memory_size.min(self.memory.len());
+        &mut self.memory[..size]
+    }
+
+    /// Returns the range of `len` bytes of memory starting at the index register.
+    ///
+    /// `address` is the address of the instruction performing the access, for error reporting.
+    fn index_range(&self, address: u16, len: usize) -> Result<Range<usize>, ExecError> {
+        let start = self.index as usize;
+        memory::span(start, len, self.config.memory_size).ok_or(ExecError::OutOfBounds {
+            address,
+            target: start + len.max(1) - 1,
+        })
+    }
+
+    /// Fills `values` with memory starting at the index register, recording the accesses.
+    fn read_index(&mut self, address: u16, values: &mut [u8]) -> Result<(), ExecError> {
+        let range = self.index_range(address, values.len())?;
+        for (target, value) in range.zip(values) {
+            *value = self
+                .read8(target as u16)
+                .map_err(|err| ExecError::from_bus(err, address, target))?;
+            self.accesses.push(Access {
+                kind: AccessKind::Read,
+                address: target as u16,
+                old: *value,
+                new: *value,
+            });
+        }
+        Ok(())
+    }
+
+    /// Writes `values` to memory starting at the index register, recording the accesses.
+    fn write_index(&mut self, address: u16, values: &[u8]) -> Result<(), ExecError> {
+        let range = self.index_range(address, values.len())?;
+        for (target, &value) in range.zip(values) {
+            let old = memory::byte(self.ram(), target)
+                .ok_or(ExecError::OutOfBounds { address, target })?;
+            self.write8(target as u16, value)
+                .map_err(|err| ExecError::from_bus(err, address, target))?;
+            self.accesses.push(Access {
+                kind: AccessKind::Write,
+                address: target as u16,
+                old,
+                new: value,
+            });
+        }
+        Ok(())
+    }
+
+    /// Decodes and executes the given instruction.
+    fn decode_execute(&mut self, opcode: u16) -> Result<(), ExecError> {
+        // The program counter has already moved past the instruction.
+        let address = self.program_counter.wrapping_sub(2);
+        let instruction =
+            Instruction::decode(opcode).ok_or(ExecError::InvalidOpcode { address, opcode })?;
+
+        match instruction {
+            Instruction::Clear => self.display.clear(),
+            Instruction::Return => {
+                self.program_counter = self
+                    .stack
+                    .pop()
+                    .ok_or(ExecError::StackUnderflow { address })?;
+                self.calls.pop();
+            }
+            Instruction::Jump { nnn } => self.program_counter = nnn,
+            Instruction::Call { nnn } => {
+                if self.stack.len() >= STACK_DEPTH {
+                    return Err(ExecError::StackOverflow { address });
+                }
+                self.stack.push(self.program_counter);
+                self.calls.push(Frame {
+                    call: address,
+                    target: nnn,
+                });
+                self.program_counter = nnn;
+            }
+            Instruction::SkipEqImm { x, nn } => self.skip_if(self.variable[x as usize] == nn),
+            Instruction::SkipNeImm { x, nn } => self.skip_if(self.variable[x as usize] != nn),
+            Instruction::SkipEqReg { x, y } => {
+                self.skip_if(self.variable[x as usize] == self.variable[y as usize])
+            }
+            Instruction::SkipNeReg { x, y } => {
+                self.skip_if(self.variable[x as usize] != self.variable[y as usize])
+            }
+            Instruction::SetImm { x, nn } => self.variable[x as usize] = nn,
+            Instruction::AddImm { x, nn } => {
+                self.variable[x as usize] = self.variable[x as usize].wrapping_add(nn)
+            }
+            Instruction::Set { x, y } => self.variable[x as usize] = self.variable[y as usize],
+            // The logical operations reset VF on the original interpreter.
+            Instruction::Or { x, y } => {
+                self.variable[x as usize] |= self.variable[y as usize];
+                self.reset_flag();
+            }
+            Instruction::And { x, y } => {
+                self.variable[x as usize] &= self.variable[y as usize];
+                self.reset_flag();
+            }
+            Instruction::Xor { x, y } => {
+                self.variable[x as usize] ^= self.variable[y as usize];
+                self.reset_flag();
+            }
+            // VF is written last so that it holds the flag even when it is also the operand.
+            Instruction::Add { x, y } => {
+                let (result, carry) =
+                    self.variable[x as usize].overflowing_add(self.variable[y as usize]);
+                self.variable[x as usize] = result;
+                self.variable[0xF] = carry as u8;
+            }
+            Instruction::Sub { x, y } => {
+                let (result, borrow) =
+                    self.variable[x as usize].overflowing_sub(self.variable[y as usize]);
+                self.variable[x as usize] = result;
+                self.variable[0xF] = !borrow as u8;
+            }
+            Instruction::SubN { x, y } => {
+                let (result, borrow) =
+                    self.variable[y as usize].overflowing_sub(self.variable[x as usize]);
+                self.variable[x as usize] = result;
+                self.variable[0xF] = !borrow as u8;
+            }
+            Instruction::ShiftRight { x, y } => {
+                let value = self.variable[self.shift_source(x, y)];
+                self.variable[x as usize] = value >> 1;
+                self.variable[0xF] = value & 0x1;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let value = self.variable[self.shift_source(x, y)];
+                self.variable[x as usize] = value << 1;
+                self.variable[0xF] = value >> 7;
+            }
+            Instruction::SetIndex { nnn } => self.index = nnn,
+            Instruction::JumpOffset { nnn } => {
+                let register = if self.config.quirks.jump_vx {
+                    (nnn >> 8) as usize
+                } else {
+                    0
+                };
+                self.program_counter = nnn + self.variable[register] as u16;
+            }
+            Instruction::Random { x, nn } => self.variable[x as usize] = self.rng.next_u8() & nn,
+            Instruction::Draw { x, y, n } => {
+                let (px, py) = (self.variable[x as usize], self.variable[y as usize]);
+                let mut sprite = [0; 15];
+                let sprite = &mut sprite[..n as usize];
+                self.read_index(address, sprite)?;
+                self.variable[0xF] = self.display.draw(px, py, sprite) as u8;
+            }
+            Instruction::SkipKeyPressed { x } => {
+                self.skip_if(self.keyboard.is_pressed(self.variable[x as usize]))
+            }
+            Instruction::SkipKeyNotPressed { x } => {
+                self.skip_if(!self.keyboard.is_pressed(self.variable[x as usize]))
+            }
+            Instruction::GetDelay { x } => self.variable[x as usize] = self.timer.delay_timer,
+            Instruction::WaitKey { x } => match self.keyboard.first_pressed() {
+                Some(key) => self.variable[x as usize] = key,
+                // Execute this instruction again until a key is pressed.
+                None => self.program_counter = address,
+            },
+            Instruction::SetDelay { x } => self.timer.delay_timer = self.variable[x as usize],
+            Instruction::SetSound { x } => self.timer.sound_timer = self.variable[x as usize],
+            Instruction::AddIndex { x } => {
+                self.index = self.index.wrapping_add(self.variable[x as usize] as u16)
+            }
+            Instruction::FontChar { x } => {
+                self.index = FONT_START + (self.variable[x as usize] & 0xF) as u16 * 5;
+            }
+            Instruction::Bcd { x } => {
+                let value = self.variable[x as usize];
+                self.write_index(address, &[value / 100, value / 10 % 10, value % 10])?;
+            }
+            // The original interpreter leaves I pointing past the last register.
+            Instruction::Store { x } => {
+                let count = x as usize + 1;
+                let registers = self.variable;
+                self.write_index(address, &registers[..count])?;
+                if !self.config.quirks.keep_index {
+                    self.index += count as u16;
+                }
+            }
+            Instruction::Load { x } => {
+                let count = x as usize + 1;
+                let mut registers = [0; 16];
+                self.read_index(address, &mut registers[..count])?;
+                self.variable[..count].copy_from_slice(&registers[..count]);
+                if !self.config.quirks.keep_index {
+                    self.index += count as u16;
+                }
+            }
+            Instruction::SaveFlags { x } if self.flags.is_some() => {
+                if let Some(flags) = &mut self.flags {
+                    flags.save(&self.variable[..=x as usize]);
+                }
+            }
+            Instruction::LoadFlags { x } if self.flags.is_some() => {
+                if let Some(flags) = &mut self.flags {
+                    flags.load(&mut self.variable[..=x as usize]);
+                }
+            }
+            _ => {
+                return Err(ExecError::Unsupported {
+                    address,
+                    instruction,
+                })
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Resets VF after a logical operation, if the quirks say so.
+    fn reset_flag(&mut self) {
+        if self.config.quirks.vf_reset {
+            self.variable[0xF] = 0;
+        }
+    }
+
+    /// Returns the register a shift reads from, which depends on the quirks.
+    fn shift_source(&self, x: u8, y: u8) -> usize {
+        if self.config.quirks.shift_in_place {
+            x as usize
+        } else {
+            y as usize
+        }
+    }
+
+    /// Skips the next instruction if `condition` is true.
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.program_counter += 2;
+        }
+    }
+
+    /// Fetches, decodes and executes a single instruction.
+    pub fn step(&mut self) -> Result<(), ExecError> {
+        self.accesses.clear();
+        let opcode = self.fetch()?;
+        self.execute_fetched(opcode)
+    }
+
+    /// Executes `opcode` as if it had been fetched from the program counter, which moves past it
+    /// first, whatever memory holds there. For trying out instructions, and fuzzing the executor.
+    pub fn execute(&mut self, opcode: u16) -> Result<(), ExecError> {
+        self.accesses.clear();
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.execute_fetched(opcode)
+    }
+
+    /// Executes an instruction the program counter has moved past, and tells the peripherals.
+    fn execute_fetched(&mut self, opcode: u16) -> Result<(), ExecError> {
+        let address = self.program_counter.wrapping_sub(2);
+        let _span = tracing::trace_span!(
+            "instruction",
+            address = format_args!("{:#05X}", address),
+            opcode = format_args!("{:04X}", opcode)
+        )
+        .entered();
+        if let Some(instruction) = Instruction::decode(opcode) {
+            tracing::trace!("{}", instruction);
+        }
+        if let Err(err) = self.decode_execute(opcode) {
+            tracing::debug!("{}", err);
+            return Err(err);
+        }
+        if self.peripherals.is_empty() {
+            return Ok(());
+        }
+        if let Some(instruction) = Instruction::decode(opcode) {
+            self.with_peripherals(|peripheral, chip| {
+                for access in &chip.accesses {
+                    peripheral.on_memory_access(access);
+                }
+                peripheral.on_instruction(chip, address, instruction);
+            });
+        }
+        Ok(())
+    }
+}
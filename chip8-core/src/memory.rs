@@ -0,0 +1,101 @@
+//! Memory access tracking, and checked access to memory for everything that indexes it.
+//!
+//! Addresses come from programs, debugger commands, cheats and settings files, so code that
+//! reads or writes memory directly, bypassing the [`Bus`](crate::bus::Bus), goes through these
+//! helpers, which report an address past the end instead of panicking:
+//!
+//! ```
+//! use chip8_core::bus::BusError;
+//! use chip8_core::memory;
+//!
+//! let mut memory = [0; 0x1000];
+//! assert_eq!(memory::write(&mut memory, 0xFFE, &[1, 2]), Ok(()));
+//! assert_eq!(memory::read(&memory, 0xFFE, 2), Ok(&[1, 2][..]));
+//! assert_eq!(memory::read(&memory, 0xFFF, 2), Err(BusError::OutOfBounds));
+//! ```
+
+use crate::bus::BusError;
+use core::fmt;
+use core::ops::Range;
+
+/// Whether memory was read or written, as the debuggers of every system tell.
+pub use emu_debug::AccessKind;
+
+/// A single byte of memory accessed by an instruction.
+///
+/// Instruction fetches are not recorded, only the data accesses made by `DXYN`, `FX33`, `FX55`
+/// and `FX65`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u16,
+    /// Value before the access.
+    pub old: u8,
+    /// Value after the access. The same as `old` for reads.
+    pub new: u8,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            AccessKind::Read => write!(f, "read {:#05X} ({:#04X})", self.address, self.old),
+            AccessKind::Write => write!(
+                f,
+                "wrote {:#05X} ({:#04X} -> {:#04X})",
+                self.address, self.old, self.new
+            ),
+        }
+    }
+}
+
+/// Returns the addresses of `len` bytes from `start`, or `None` if any lies at or past `size`.
+pub fn span(start: usize, len: usize, size: usize) -> Option<Range<usize>> {
+    let end = start.checked_add(len)?;
+    if end > size {
+        return None;
+    }
+    Some(start..end)
+}
+
+/// Returns the byte at `address`, or `None` past the end of `memory`.
+pub fn byte(memory: &[u8], address: usize) -> Option<u8> {
+    memory.get(address).copied()
+}
+
+/// Returns the `len` bytes from `start`.
+pub fn read(memory: &[u8], start: usize, len: usize) -> Result<&[u8], BusError> {
+    let range = span(start, len, memory.len()).ok_or(BusError::OutOfBounds)?;
+    Ok(&memory[range])
+}
+
+/// Returns the `len` bytes from `start` for changing them.
+pub fn read_mut(memory: &mut [u8], start: usize, len: usize) -> Result<&mut [u8], BusError> {
+    let range = span(start, len, memory.len()).ok_or(BusError::OutOfBounds)?;
+    Ok(&mut memory[range])
+}
+
+/// Copies `bytes` to memory from `start`, changing nothing if they do not all fit.
+pub fn write(memory: &mut [u8], start: usize, bytes: &[u8]) -> Result<(), BusError> {
+    read_mut(memory, start, bytes.len())?.copy_from_slice(bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds() {
+        assert_eq!(span(0xFFE, 2, 0x1000), Some(0xFFE..0x1000));
+        assert_eq!(span(0xFFE, 3, 0x1000), None);
+        assert_eq!(span(usize::MAX, 1, usize::MAX), None);
+        assert_eq!(span(0x1000, 0, 0x1000), Some(0x1000..0x1000));
+
+        let mut memory = [0; 16];
+        assert_eq!(write(&mut memory, 15, &[1, 2]), Err(BusError::OutOfBounds));
+        assert_eq!(memory, [0; 16]);
+        assert_eq!(byte(&memory, 16), None);
+        read_mut(&mut memory, 14, 2).unwrap()[1] = 7;
+        assert_eq!(byte(&memory, 15), Some(7));
+    }
+}
@@ -0,0 +1,380 @@
+//! Replay movies, for sharing speedruns and for regression tests that play a whole game.
+//!
+//! A movie is a text file describing a run from power-on, one item per line, with `#` starting a
+//! comment:
+//!
+//! ```text
+//! version 1
+//! rom 3f2a9c0d11e4b7a5    # hash of the ROM
+//! variant CHIP-8
+//! seed 2545f491           # starting state of the random number generator
+//! length 900              # frames in the run
+//! 120 press 5             # frame, then what happened at its start
+//! 131 release 5
+//! 180 checksum 8c1e0f7a2b9d3c46
+//! ```
+//!
+//! Playback is deterministic because the machine runs in whole frames of [`STEPS_PER_FRAME`]
+//! instructions followed by a timer update, and keys only change between frames. The checksums
+//! of the whole machine, taken at the end of a frame, show where a replay went out of step: when
+//! one differs, playback stops and lists what changed in the machine since the last checksum that
+//! matched. Recording with a checksum every frame pins this down to the exact frame.
+
+use crate::error::ExecError;
+use crate::rng::Rng;
+use crate::state::{self, State};
+use crate::variant::Variant;
+use crate::{CHIP8, STEPS_PER_FRAME};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// Version of the format, increased whenever its meaning changes.
+const VERSION: u32 = 1;
+
+/// Frames between two checksums written while recording by default, one second.
+pub const CHECKSUM_FRAMES: u64 = 60;
+
+/// The variant whose behavior the emulator implements, which movies are recorded against.
+const VARIANT: Variant = Variant::Chip8;
+
+/// Something that happens at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Press(u8),
+    Release(u8),
+    /// The checksum of the machine at the end of the previous frame.
+    Checksum(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub frame: u64,
+    pub input: Input,
+}
+
+/// A recorded run of a ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub variant: Variant,
+    pub seed: u32,
+    /// Number of frames in the run.
+    pub length: u64,
+    /// Events in order of frame.
+    pub events: Vec<Event>,
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut version = None;
+        let mut rom_hash = None;
+        let mut variant = None;
+        let mut seed = None;
+        let mut length = None;
+        let mut events: Vec<Event> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let hex = |text: &str| {
+                u64::from_str_radix(text, 16)
+                    .map_err(|_| error(format!("invalid hexadecimal number `{}`", text)))
+            };
+            let decimal = |text: &str| {
+                text.parse::<u64>()
+                    .map_err(|_| error(format!("invalid number `{}`", text)))
+            };
+            let key = |text: &str| match u8::from_str_radix(text, 16) {
+                Ok(key @ 0..=0xF) => Ok(key),
+                _ => Err(error(format!("invalid key `{}`", text))),
+            };
+            match words.as_slice() {
+                [] => (),
+                ["version", value] => version = Some(decimal(value)?),
+                ["rom", value] => rom_hash = Some(hex(value)?),
+                ["variant", name] => {
+                    let found = [Variant::Chip8, Variant::SuperChip, Variant::XoChip]
+                        .iter()
+                        .copied()
+                        .find(|variant| variant.to_string() == *name);
+                    variant =
+                        Some(found.ok_or_else(|| error(format!("unknown variant `{}`", name)))?);
+                }
+                ["seed", value] => seed = Some(hex(value)? as u32),
+                ["length", value] => length = Some(decimal(value)?),
+                [frame, kind, value] => {
+                    let frame = decimal(frame)?;
+                    let input = match *kind {
+                        "press" => Input::Press(key(value)?),
+                        "release" => Input::Release(key(value)?),
+                        "checksum" => Input::Checksum(hex(value)?),
+                        _ => return Err(error(format!("unknown event `{}`", kind))),
+                    };
+                    if events.last().is_some_and(|last| last.frame > frame) {
+                        return Err(error("events are out of order".to_string()));
+                    }
+                    events.push(Event { frame, input });
+                }
+                _ => return Err(error(format!("invalid line `{}`", line.trim()))),
+            }
+        }
+
+        match version {
+            Some(version) if version == VERSION as u64 => (),
+            Some(version) => return Err(format!("movie version {} is not supported", version)),
+            None => return Err("missing `version`".to_string()),
+        }
+        let missing = |name: &str| format!("missing `{}`", name);
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            variant: variant.ok_or_else(|| missing("variant"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            length: length.ok_or_else(|| missing("length"))?,
+            events,
+        })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version {}", VERSION)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "variant {}", self.variant)?;
+        writeln!(f, "seed {:08x}", self.seed)?;
+        writeln!(f, "length {}", self.length)?;
+        for event in &self.events {
+            match event.input {
+                Input::Press(key) => writeln!(f, "{} press {:X}", event.frame, key)?,
+                Input::Release(key) => writeln!(f, "{} release {:X}", event.frame, key)?,
+                Input::Checksum(sum) => writeln!(f, "{} checksum {:016x}", event.frame, sum)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs one frame of `chip`: [`STEPS_PER_FRAME`] instructions, then a timer update.
+fn frame(chip: &mut CHIP8) -> Result<(), ExecError> {
+    for _ in 0..STEPS_PER_FRAME {
+        chip.step()?;
+    }
+    chip.tick();
+    Ok(())
+}
+
+/// Records a movie of a ROM as it is played, one frame at a time.
+pub struct Recorder {
+    movie: Movie,
+    /// The keys held down at the start of the last frame, one bit per key.
+    keys: u16,
+    /// Frames between two checksums.
+    checksum_frames: u64,
+}
+
+impl Recorder {
+    /// Starts recording `chip`, which must have just loaded the ROM with hash `rom_hash`, with a
+    /// checksum every `checksum_frames` frames.
+    pub fn new(chip: &CHIP8, rom_hash: u64, checksum_frames: u64) -> Self {
+        Recorder {
+            movie: Movie {
+                rom_hash,
+                variant: VARIANT,
+                seed: chip.rng.state(),
+                length: 0,
+                events: Vec::new(),
+            },
+            keys: 0,
+            checksum_frames: checksum_frames.max(1),
+        }
+    }
+
+    /// Records the keys that changed since the last frame, then runs a frame of `chip`.
+    pub fn frame(&mut self, chip: &mut CHIP8) -> Result<(), ExecError> {
+        let number = self.movie.length;
+        for key in 0..16 {
+            let pressed = chip.keyboard.is_pressed(key);
+            if pressed != (self.keys >> key & 1 != 0) {
+                self.keys ^= 1 << key;
+                let input = if pressed {
+                    Input::Press(key)
+                } else {
+                    Input::Release(key)
+                };
+                self.movie.events.push(Event {
+                    frame: number,
+                    input,
+                });
+            }
+        }
+        frame(chip)?;
+        self.movie.length += 1;
+        if self.movie.length.is_multiple_of(self.checksum_frames) {
+            self.movie.events.push(Event {
+                frame: self.movie.length,
+                input: Input::Checksum(state::checksum(chip)),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+/// Plays `movie` on `chip`, which must have just loaded the ROM with hash `rom_hash`. Returns the
+/// number of checksums that matched, or where the replay went out of step and what changed in the
+/// machine since the last checksum that matched.
+pub fn play(movie: &Movie, chip: &mut CHIP8, rom_hash: u64) -> Result<usize, String> {
+    if movie.rom_hash != rom_hash {
+        return Err("movie is for a different ROM".to_string());
+    }
+    if movie.variant != VARIANT {
+        return Err(format!(
+            "movie is for {}, which is not supported",
+            movie.variant
+        ));
+    }
+    chip.rng = Rng::new(movie.seed);
+    let mut events = movie.events.iter().peekable();
+    let mut matched = 0;
+    // The machine when the last checksum matched, and the frame it was taken at.
+    let mut last: (u64, State) = (0, chip.snapshot());
+    for number in 0..=movie.length {
+        while let Some(event) = events.next_if(|event| event.frame == number) {
+            match event.input {
+                Input::Press(key) => chip.keyboard.set(key, true),
+                Input::Release(key) => chip.keyboard.set(key, false),
+                Input::Checksum(expected) => {
+                    let actual = state::checksum(chip);
+                    if actual != expected {
+                        let changes = state::differences(&last.1, &chip.snapshot());
+                        return Err(format!(
+                            "frame {}: checksum {:016x} does not match {:016x} from the \
+                             recording\nthe replay went out of step after frame {}, since when:\n  {}",
+                            number,
+                            actual,
+                            expected,
+                            last.0,
+                            changes.join("\n  ")
+                        ));
+                    }
+                    matched += 1;
+                    last = (number, chip.snapshot());
+                }
+            }
+        }
+        if number < movie.length {
+            frame(chip).map_err(|err| format!("frame {}: {}", number, err))?;
+        }
+    }
+    if let Some(event) = events.next() {
+        return Err(format!(
+            "event at frame {} is past the end of the movie",
+            event.frame
+        ));
+    }
+    Ok(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    /// Returns a machine that has loaded a ROM which adds a random number to V1 while key 5 is
+    /// held: 0x200: SKNP V0; 0x202: JP 0x206; 0x204: JP 0x200; 0x206: RND V2, 0xFF;
+    /// 0x208: ADD V1, V2; 0x20A: JP 0x200
+    fn load() -> CHIP8 {
+        let rom = Rom::from_bytes(vec![
+            0xE0, 0xA1, 0x12, 0x06, 0x12, 0x00, 0xC2, 0xFF, 0x81, 0x24, 0x12, 0x00,
+        ])
+        .unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        chip.variable[0] = 5;
+        chip
+    }
+
+    #[test]
+    fn record_and_play() {
+        let mut chip = load();
+        let mut recorder = Recorder::new(&chip, 1, CHECKSUM_FRAMES);
+        for number in 0..130 {
+            chip.keyboard.set(5, (10..70).contains(&number));
+            recorder.frame(&mut chip).unwrap();
+        }
+        let movie: Movie = recorder.movie().to_string().parse().unwrap();
+        assert_eq!(&movie, recorder.movie());
+        assert_eq!(movie.length, 130);
+        assert_eq!(
+            movie.events[0],
+            Event {
+                frame: 10,
+                input: Input::Press(5),
+            }
+        );
+        assert!(matches!(movie.events[1].input, Input::Checksum(_)));
+        assert_eq!(movie.events[2].frame, 70);
+
+        let mut replay = load();
+        replay.rng = Rng::new(12345);
+        assert_eq!(play(&movie, &mut replay, 1), Ok(2));
+        assert_eq!(replay.variable, chip.variable);
+        assert_eq!(
+            play(&movie, &mut replay, 2),
+            Err("movie is for a different ROM".to_string())
+        );
+
+        // Holding the key a frame longer changes the random numbers added to V1.
+        let mut desynced = movie.clone();
+        desynced.events[2].frame += 1;
+        let error = play(&desynced, &mut load(), 1).unwrap_err();
+        assert!(error.starts_with("frame 120: checksum"), "{}", error);
+        assert!(error.contains("out of step after frame 60"), "{}", error);
+        assert!(error.contains("\n  V1: "), "{}", error);
+
+        // With a checksum every frame, the replay stops on the frame it went out of step.
+        let mut chip = load();
+        let mut recorder = Recorder::new(&chip, 1, 1);
+        for number in 0..20 {
+            chip.keyboard.set(5, (5..10).contains(&number));
+            recorder.frame(&mut chip).unwrap();
+        }
+        let mut desynced = recorder.movie().clone();
+        let release = desynced
+            .events
+            .iter_mut()
+            .find(|event| event.input == Input::Release(5))
+            .unwrap();
+        release.frame += 1;
+        let error = play(&desynced, &mut load(), 1).unwrap_err();
+        assert!(error.starts_with("frame 11: checksum"), "{}", error);
+        assert!(error.contains("out of step after frame 10"), "{}", error);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "version 1\nrom 1\nvariant CHIP-8\nseed 1\nlength 2\n2 jump 5".parse::<Movie>(),
+            Err("line 6: unknown event `jump`".to_string())
+        );
+        assert_eq!(
+            "version 2".parse::<Movie>(),
+            Err("movie version 2 is not supported".to_string())
+        );
+        assert_eq!(
+            "version 1\nrom 1".parse::<Movie>(),
+            Err("missing `variant`".to_string())
+        );
+    }
+}
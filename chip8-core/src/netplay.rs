@@ -0,0 +1,629 @@
+//! Lockstep netplay, for two players on two machines to play games such as Pong and Tank
+//! together.
+//!
+//! Both machines run the same ROM from power-on with the same seed, and run a frame only once
+//! they know the keys both players held for it, so that they stay in step without sending any
+//! more than the keys. The keys of the two players are merged: a key is down if either holds it,
+//! as if both pressed the one keypad, so that each plays with the keys of their side of the game.
+//!
+//! The keys read at a frame are played a few frames later, the input delay, so that they reach
+//! the other machine before it needs them and neither waits on the network while the delay covers
+//! its round trip. Every so often the machines compare checksums of their whole state, and stop
+//! with [`NetplayError::Desync`] if they went out of step.
+//!
+//! A [`Session`] does no input or output of its own: the frontend carries its [`Message`]s, one
+//! per line, over a connection such as TCP. One player hosts, which sends the seed and the
+//! delay, and the other joins:
+//!
+//! ```text
+//! hello 1 3f2a9c0d11e4b7a5 2545f491 3 60    # version, ROM hash, seed, delay, checksum frames
+//! input 3 0012                              # frame, then the keys held down, one bit per key
+//! checksum 60 8c1e0f7a2b9d3c46              # frame, then the checksum taken at its start
+//! ```
+
+use crate::error::ExecError;
+use crate::rng::Rng;
+use crate::state;
+use crate::CHIP8;
+use alloc::collections::btree_map::{BTreeMap, Entry};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+/// Version of the protocol, increased whenever its meaning changes.
+const VERSION: u32 = 1;
+
+/// Frames between the reading of keys and their playing by default, 50 ms.
+pub const DEFAULT_DELAY: u64 = 3;
+
+/// The longest input delay either player accepts, one second.
+pub const MAX_DELAY: u64 = 60;
+
+/// Frames between two checksums by default, one second.
+pub const CHECKSUM_FRAMES: u64 = 60;
+
+/// Why netplay stopped.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum NetplayError {
+    /// The program failed while running.
+    #[error(transparent)]
+    Exec(#[from] ExecError),
+    /// The two machines are no longer in step.
+    #[error("out of step with the other player at frame {frame}: checksum {local:016x} here, {remote:016x} there")]
+    Desync { frame: u64, local: u64, remote: u64 },
+    /// The other player sent something that does not make sense, or cannot play with this one.
+    #[error("{0}")]
+    Protocol(String),
+}
+
+/// What one machine tells the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// Sent by the host when it starts: what the player joining must run to stay in step.
+    Hello {
+        version: u32,
+        rom_hash: u64,
+        seed: u32,
+        delay: u64,
+        checksum_frames: u64,
+    },
+    /// The keys the player holds at `frame`, one bit per key.
+    Input { frame: u64, keys: u16 },
+    /// The checksum of the machine at the start of `frame`.
+    Checksum { frame: u64, checksum: u64 },
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Message::Hello {
+                version,
+                rom_hash,
+                seed,
+                delay,
+                checksum_frames,
+            } => write!(
+                f,
+                "hello {} {:016x} {:08x} {} {}",
+                version, rom_hash, seed, delay, checksum_frames
+            ),
+            Message::Input { frame, keys } => write!(f, "input {} {:04x}", frame, keys),
+            Message::Checksum { frame, checksum } => {
+                write!(f, "checksum {} {:016x}", frame, checksum)
+            }
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["hello", version, rom_hash, seed, delay, checksum_frames] => Ok(Message::Hello {
+                version: decimal(version)?,
+                rom_hash: hex(rom_hash)?,
+                seed: hex(seed)?,
+                delay: match decimal(delay)? {
+                    delay if delay > MAX_DELAY => {
+                        return Err(format!(
+                            "input delay of {} frames, more than {}",
+                            delay, MAX_DELAY
+                        ))
+                    }
+                    delay => delay,
+                },
+                checksum_frames: match decimal(checksum_frames)? {
+                    0 => return Err("checksums every 0 frames".to_string()),
+                    frames => frames,
+                },
+            }),
+            ["input", frame, keys] => Ok(Message::Input {
+                frame: decimal(frame)?,
+                keys: hex(keys)?,
+            }),
+            ["checksum", frame, checksum] => Ok(Message::Checksum {
+                frame: decimal(frame)?,
+                checksum: hex(checksum)?,
+            }),
+            _ => Err(format!("invalid message `{}`", line.trim())),
+        }
+    }
+}
+
+/// Parses a field of a message written in hexadecimal, which must fit in a `T`.
+fn hex<T: TryFrom<u64>>(text: &str) -> Result<T, String> {
+    let value = u64::from_str_radix(text, 16)
+        .map_err(|_| format!("invalid hexadecimal number `{}`", text))?;
+    T::try_from(value).map_err(|_| format!("number out of range `{}`", text))
+}
+
+/// Parses a field of a message written in decimal, which must fit in a `T`.
+fn decimal<T: TryFrom<u64>>(text: &str) -> Result<T, String> {
+    let value = text
+        .parse::<u64>()
+        .map_err(|_| format!("invalid number `{}`", text))?;
+    T::try_from(value).map_err(|_| format!("number out of range `{}`", text))
+}
+
+/// Returns the keys held down on `chip`'s keypad, one bit per key.
+pub fn keys(chip: &CHIP8) -> u16 {
+    (0..16).fold(0, |keys, key| {
+        keys | u16::from(chip.keyboard.is_pressed(key)) << key
+    })
+}
+
+/// A game played with another machine.
+pub struct Session {
+    rom_hash: u64,
+    /// The seed to start the machine with, until it starts.
+    seed: Option<u32>,
+    /// Frames between the reading of keys and their playing, once known.
+    delay: Option<u64>,
+    checksum_frames: u64,
+    /// The next frame to run.
+    frame: u64,
+    /// The keys of each player for the frames not yet run.
+    local: BTreeMap<u64, u16>,
+    remote: BTreeMap<u64, u16>,
+    /// The checksums of each machine not yet compared.
+    local_checksums: BTreeMap<u64, u64>,
+    remote_checksums: BTreeMap<u64, u64>,
+    outbox: Vec<Message>,
+}
+
+impl Session {
+    /// Hosts a game of `chip`, which has just loaded the ROM with hash `rom_hash`, playing keys
+    /// `delay` frames after they are read and comparing checksums every `checksum_frames`. The
+    /// delay is at most [`MAX_DELAY`].
+    pub fn host(chip: &CHIP8, rom_hash: u64, delay: u64, checksum_frames: u64) -> Self {
+        let delay = delay.min(MAX_DELAY);
+        let checksum_frames = checksum_frames.max(1);
+        let mut session = Session::new(rom_hash, checksum_frames);
+        session.seed = Some(chip.rng.state());
+        session.start(delay);
+        session.outbox.push(Message::Hello {
+            version: VERSION,
+            rom_hash,
+            seed: chip.rng.state(),
+            delay,
+            checksum_frames,
+        });
+        session
+    }
+
+    /// Joins the game of the host, on a machine that has just loaded the ROM with hash
+    /// `rom_hash`. Nothing runs until the host's [`Message::Hello`] arrives.
+    pub fn join(rom_hash: u64) -> Self {
+        Session::new(rom_hash, CHECKSUM_FRAMES)
+    }
+
+    fn new(rom_hash: u64, checksum_frames: u64) -> Self {
+        Session {
+            rom_hash,
+            seed: None,
+            delay: None,
+            checksum_frames,
+            frame: 0,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            local_checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            outbox: Vec::new(),
+        }
+    }
+
+    /// Plays no keys for the frames before the first ones read, which are played after `delay`.
+    fn start(&mut self, delay: u64) {
+        self.delay = Some(delay);
+        for frame in 0..delay {
+            self.local.insert(frame, 0);
+            self.remote.insert(frame, 0);
+        }
+    }
+
+    /// Returns the next frame to run.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Returns the input delay, or `None` if the host has not said yet.
+    pub fn delay(&self) -> Option<u64> {
+        self.delay
+    }
+
+    /// Takes a message from the other machine.
+    pub fn receive(&mut self, message: Message) -> Result<(), NetplayError> {
+        match message {
+            Message::Hello {
+                version,
+                rom_hash,
+                seed,
+                delay,
+                checksum_frames,
+            } => {
+                if self.delay.is_some() {
+                    return Err(NetplayError::Protocol(
+                        "both players are hosting".to_string(),
+                    ));
+                }
+                if version != VERSION {
+                    return Err(NetplayError::Protocol(format!(
+                        "the other player speaks version {} of netplay, not {}",
+                        version, VERSION
+                    )));
+                }
+                if rom_hash != self.rom_hash {
+                    return Err(NetplayError::Protocol(
+                        "the other player runs a different ROM".to_string(),
+                    ));
+                }
+                if delay > MAX_DELAY {
+                    return Err(NetplayError::Protocol(format!(
+                        "the other player asks for an input delay of {} frames, more than {}",
+                        delay, MAX_DELAY
+                    )));
+                }
+                self.seed = Some(seed);
+                self.checksum_frames = checksum_frames.max(1);
+                self.start(delay);
+            }
+            Message::Input { frame, keys } => {
+                if frame < self.frame {
+                    return Err(NetplayError::Protocol(format!(
+                        "keys for frame {}, which has already run",
+                        frame
+                    )));
+                }
+                // The other machine runs at most a delay ahead of this one, as it needs the keys
+                // read here, and reads its own keys a delay ahead of the frame after that.
+                let delay = self.known_delay()?;
+                if frame > self.frame + 2 * delay + 1 {
+                    return Err(NetplayError::Protocol(format!(
+                        "keys for frame {}, too far ahead of frame {}",
+                        frame, self.frame
+                    )));
+                }
+                self.remote.insert(frame, keys);
+            }
+            Message::Checksum { frame, checksum } => {
+                // A checksum is taken at the start of a frame, so it is for one that the other
+                // machine has run, and was compared already if it ran here.
+                let delay = self.known_delay()?;
+                let compared = frame < self.frame && !self.local_checksums.contains_key(&frame);
+                if !frame.is_multiple_of(self.checksum_frames)
+                    || frame > self.frame + delay
+                    || compared
+                {
+                    return Err(NetplayError::Protocol(format!(
+                        "unexpected checksum for frame {}",
+                        frame
+                    )));
+                }
+                self.remote_checksums.insert(frame, checksum);
+                self.compare(frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the input delay, which the host must have sent before anything else.
+    fn known_delay(&self) -> Result<u64, NetplayError> {
+        self.delay.ok_or_else(|| {
+            NetplayError::Protocol("the host sent a message before saying hello".to_string())
+        })
+    }
+
+    /// Moves the messages for the other machine to the end of `messages`.
+    pub fn take_messages(&mut self, messages: &mut Vec<Message>) {
+        messages.append(&mut self.outbox);
+    }
+
+    /// Reads `keys`, the keys held down now, and runs the next frame of `chip` with `run` if the
+    /// keys of both players for it are known. Returns `false` if it waits for the other player.
+    ///
+    /// The keys are played after the input delay, and only the first read for each frame count,
+    /// so that this can be called again and again while it waits.
+    pub fn advance<F>(&mut self, chip: &mut CHIP8, keys: u16, run: F) -> Result<bool, NetplayError>
+    where
+        F: FnOnce(&mut CHIP8) -> Result<(), ExecError>,
+    {
+        let delay = match self.delay {
+            Some(delay) => delay,
+            None => return Ok(false),
+        };
+        if let Some(seed) = self.seed.take() {
+            chip.rng = Rng::new(seed);
+        }
+        let at = self.frame + delay;
+        if let Entry::Vacant(entry) = self.local.entry(at) {
+            entry.insert(keys);
+            self.outbox.push(Message::Input { frame: at, keys });
+        }
+        let remote = match self.remote.get(&self.frame) {
+            Some(&remote) => remote,
+            None => return Ok(false),
+        };
+        let local = self.local.remove(&self.frame).unwrap_or_default();
+        self.remote.remove(&self.frame);
+
+        if self.frame.is_multiple_of(self.checksum_frames) {
+            let checksum = state::checksum(chip);
+            self.local_checksums.insert(self.frame, checksum);
+            self.outbox.push(Message::Checksum {
+                frame: self.frame,
+                checksum,
+            });
+            self.compare(self.frame)?;
+        }
+        let held = local | remote;
+        for key in 0..16 {
+            chip.keyboard.set(key, held >> key & 1 != 0);
+        }
+        self.frame += 1;
+        run(chip)?;
+        Ok(true)
+    }
+
+    /// Compares the checksums of the two machines at `frame`, once both are known.
+    fn compare(&mut self, frame: u64) -> Result<(), NetplayError> {
+        let (local, remote) = match (
+            self.local_checksums.get(&frame),
+            self.remote_checksums.get(&frame),
+        ) {
+            (Some(&local), Some(&remote)) => (local, remote),
+            _ => return Ok(()),
+        };
+        self.local_checksums.remove(&frame);
+        self.remote_checksums.remove(&frame);
+        if local != remote {
+            return Err(NetplayError::Desync {
+                frame,
+                local,
+                remote,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use crate::STEPS_PER_FRAME;
+
+    /// Returns a machine that has loaded a ROM which adds a random number to V1 while key 5 is
+    /// held, and to V3 while key 8 is: 0x200: SKNP V0; 0x202: CALL 0x20E; 0x204: SKNP V4;
+    /// 0x206: CALL 0x214; 0x208: JP 0x200; 0x20E: RND V2, 0xFF; ADD V1, V2; RET;
+    /// 0x214: RND V2, 0xFF; ADD V3, V2; RET. The seed is fixed, so that the sums aren't 0 by
+    /// chance.
+    fn load() -> CHIP8 {
+        let rom = Rom::from_bytes(vec![
+            0xE0, 0xA1, 0x22, 0x0E, 0xE4, 0xA1, 0x22, 0x14, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xC2, 0xFF, 0x81, 0x24, 0x00, 0xEE, 0xC2, 0xFF, 0x83, 0x24, 0x00, 0xEE,
+        ])
+        .unwrap();
+        let mut chip = CHIP8::new();
+        chip.set_rng(Rng::new(0x2545_f491));
+        chip.load_rom(&rom).unwrap();
+        chip.variable_mut()[0] = 5;
+        chip.variable_mut()[4] = 8;
+        chip
+    }
+
+    fn run(chip: &mut CHIP8) -> Result<(), ExecError> {
+        for _ in 0..STEPS_PER_FRAME {
+            chip.step()?;
+        }
+        chip.tick();
+        Ok(())
+    }
+
+    /// Passes the messages of `from` to `to`.
+    fn deliver(from: &mut Session, to: &mut Session) -> Result<(), NetplayError> {
+        let mut messages = Vec::new();
+        from.take_messages(&mut messages);
+        messages.into_iter().try_for_each(|message| {
+            let line = message.to_string();
+            to.receive(line.parse().unwrap())
+        })
+    }
+
+    #[test]
+    fn plays_in_step() {
+        let mut host_chip = load();
+        let mut guest_chip = load();
+        let hash = 0x1234;
+        let mut host = Session::host(&host_chip, hash, 2, 4);
+        let mut guest = Session::join(hash);
+        assert!(!guest.advance(&mut guest_chip, 0, run).unwrap());
+
+        deliver(&mut host, &mut guest).unwrap();
+        assert_eq!(guest.delay(), Some(2));
+        let mut stalls = 0;
+        for frame in 0..40u64 {
+            // The host holds 5 for a while, and the guest 8 later.
+            let host_keys = if (5..15).contains(&frame) { 1 << 5 } else { 0 };
+            let guest_keys = if (20..30).contains(&frame) { 1 << 8 } else { 0 };
+            let target = frame + 1;
+            while host.frame() < target || guest.frame() < target {
+                let ran = host.advance(&mut host_chip, host_keys, run).unwrap()
+                    | guest.advance(&mut guest_chip, guest_keys, run).unwrap();
+                stalls += usize::from(!ran);
+                deliver(&mut host, &mut guest).unwrap();
+                deliver(&mut guest, &mut host).unwrap();
+            }
+        }
+        assert_eq!(stalls, 0);
+        assert_eq!(state::checksum(&host_chip), state::checksum(&guest_chip));
+        assert_ne!(host_chip.variable()[1], 0);
+        assert_ne!(host_chip.variable()[3], 0);
+        assert_eq!(keys(&host_chip), 0);
+
+        // A machine that went out of step is caught at the next checksum.
+        guest_chip.variable_mut()[7] = 1;
+        let result = (0..8).try_for_each(|_| {
+            host.advance(&mut host_chip, 0, run)?;
+            guest.advance(&mut guest_chip, 0, run)?;
+            deliver(&mut host, &mut guest)?;
+            deliver(&mut guest, &mut host)
+        });
+        assert!(matches!(
+            result,
+            Err(NetplayError::Desync { frame: 40, .. })
+        ));
+    }
+
+    #[test]
+    fn waits_for_the_other_player() {
+        let mut chip = load();
+        let mut host = Session::host(&chip, 1, 1, CHECKSUM_FRAMES);
+        assert!(host.advance(&mut chip, 1 << 5, run).unwrap());
+        // The keys for frame 1 have not come.
+        assert!(!host.advance(&mut chip, 0, run).unwrap());
+        assert!(!host.advance(&mut chip, 0, run).unwrap());
+        let mut messages = Vec::new();
+        host.take_messages(&mut messages);
+        let inputs: Vec<&Message> = messages
+            .iter()
+            .filter(|message| matches!(message, Message::Input { .. }))
+            .collect();
+        // The keys of frame 2 are read once, while waiting.
+        assert_eq!(
+            inputs,
+            [
+                &Message::Input {
+                    frame: 1,
+                    keys: 0x20
+                },
+                &Message::Input { frame: 2, keys: 0 }
+            ]
+        );
+
+        host.receive(Message::Input {
+            frame: 1,
+            keys: 1 << 8,
+        })
+        .unwrap();
+        assert!(host.advance(&mut chip, 0, run).unwrap());
+        assert_eq!(keys(&chip), 1 << 5 | 1 << 8);
+        assert!(host.receive(Message::Input { frame: 0, keys: 0 }).is_err());
+    }
+
+    #[test]
+    fn refuses_what_the_other_player_cannot_send() {
+        let chip = load();
+        assert_eq!(
+            "hello 1 0 0 18446744073709551615 60".parse::<Message>(),
+            Err("input delay of 18446744073709551615 frames, more than 60".to_string())
+        );
+        let mut guest = Session::join(1);
+        assert!(guest
+            .receive(Message::Hello {
+                version: VERSION,
+                rom_hash: 1,
+                seed: 0,
+                delay: MAX_DELAY + 1,
+                checksum_frames: CHECKSUM_FRAMES,
+            })
+            .is_err());
+        assert_eq!(
+            guest.receive(Message::Input { frame: 0, keys: 0 }),
+            Err(NetplayError::Protocol(
+                "the host sent a message before saying hello".to_string()
+            ))
+        );
+        assert_eq!(
+            Session::host(&chip, 1, u64::MAX, 1).delay(),
+            Some(MAX_DELAY)
+        );
+
+        // With a delay of 2, the other machine can have run frames 0 to 2, and read keys for
+        // frame 5 on its way to frame 3.
+        let mut host = Session::host(&chip, 1, 2, 2);
+        host.receive(Message::Input { frame: 5, keys: 0 }).unwrap();
+        assert!(host.receive(Message::Input { frame: 6, keys: 0 }).is_err());
+        host.receive(Message::Checksum {
+            frame: 2,
+            checksum: 0,
+        })
+        .unwrap();
+        for frame in [1, 4] {
+            assert_eq!(
+                host.receive(Message::Checksum { frame, checksum: 0 }),
+                Err(NetplayError::Protocol(format!(
+                    "unexpected checksum for frame {}",
+                    frame
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_games() {
+        let chip = load();
+        let mut host = Session::host(&chip, 1, DEFAULT_DELAY, CHECKSUM_FRAMES);
+        let mut other = Session::join(2);
+        assert_eq!(
+            deliver(&mut host, &mut other),
+            Err(NetplayError::Protocol(
+                "the other player runs a different ROM".to_string()
+            ))
+        );
+        let mut host_again = Session::host(&chip, 1, DEFAULT_DELAY, CHECKSUM_FRAMES);
+        assert!(deliver(&mut host_again, &mut host).is_err());
+    }
+
+    #[test]
+    fn parses_messages() {
+        let messages = [
+            Message::Hello {
+                version: 1,
+                rom_hash: 0x3f2a_9c0d_11e4_b7a5,
+                seed: 0x2545_f491,
+                delay: 3,
+                checksum_frames: 60,
+            },
+            Message::Input {
+                frame: 3,
+                keys: 0x0012,
+            },
+            Message::Checksum {
+                frame: 60,
+                checksum: 0x8c1e_0f7a_2b9d_3c46,
+            },
+        ];
+        let lines: Vec<String> = messages.iter().map(Message::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "hello 1 3f2a9c0d11e4b7a5 2545f491 3 60",
+                "input 3 0012",
+                "checksum 60 8c1e0f7a2b9d3c46"
+            ]
+        );
+        for (line, message) in lines.iter().zip(&messages) {
+            assert_eq!(line.parse::<Message>().as_ref(), Ok(message));
+        }
+        assert_eq!(
+            "input x 0".parse::<Message>(),
+            Err("invalid number `x`".to_string())
+        );
+        assert_eq!(
+            "input 3 10000".parse::<Message>(),
+            Err("number out of range `10000`".to_string())
+        );
+        assert_eq!(
+            "hello 4294967296 0 0 3 60".parse::<Message>(),
+            Err("number out of range `4294967296`".to_string())
+        );
+        assert!("hello 1 0 0 3 0".parse::<Message>().is_err());
+        assert!("goodbye".parse::<Message>().is_err());
+    }
+}
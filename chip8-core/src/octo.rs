@@ -0,0 +1,229 @@
+//! Importing the state of Octo's emulator, so that games played there can carry on here.
+//!
+//! Octo has no save state files of its own, but its emulator keeps the whole machine in plain
+//! fields, which `JSON.stringify` turns into an object such as:
+//!
+//! ```text
+//! {"m": [0, 0, ...], "v": [0, 5, ...], "r": [516], "pc": 530, "i": 768, "dt": 0, "st": 0,
+//!  "p": [[0, 1, ...], [0, 0, ...]], "hires": false, "waiting": false, ...}
+//! ```
+//!
+//! `m` is memory, `v` the registers, `r` the return addresses on the stack, and `p` the two
+//! drawing planes, one number per pixel from left to right and top to bottom. Other fields, such
+//! as the SUPER-CHIP flags and the XO-CHIP audio pattern, have no counterpart here and are
+//! ignored.
+
+use crate::callstack::Frame;
+use crate::state;
+use crate::{CHIP8, STACK_DEPTH};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// The fields of Octo's emulator that have a counterpart here. Numbers are read as they are and
+/// checked afterwards, to say which field is out of range.
+#[derive(serde::Deserialize)]
+struct Octo {
+    m: Vec<u64>,
+    v: Vec<u64>,
+    r: Vec<u64>,
+    pc: u64,
+    i: u64,
+    dt: u64,
+    st: u64,
+    p: Vec<Vec<Pixel>>,
+    #[serde(default)]
+    hires: bool,
+    #[serde(default)]
+    waiting: bool,
+}
+
+/// A pixel of a drawing plane, which Octo writes as a number or, in older versions, a bool.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Pixel {
+    Number(u64),
+    Lit(bool),
+}
+
+/// Returns `value` if it is at most `max`.
+fn number(value: u64, name: &str, max: u64) -> Result<u64, String> {
+    if value <= max {
+        Ok(value)
+    } else {
+        Err(format!("`{}` must be a number up to {:#X}", name, max))
+    }
+}
+
+/// Converts the state of Octo's emulator, for the ROM with hash `rom_hash`, to a save state.
+pub fn import(text: &str, rom_hash: u64) -> Result<Vec<u8>, String> {
+    let octo: Octo = serde_json::from_str(text).map_err(|err| err.to_string())?;
+    let numbers = |values: &[u64], name: &str, max: u64| -> Result<Vec<u64>, String> {
+        values
+            .iter()
+            .map(|&value| number(value, name, max))
+            .collect()
+    };
+
+    if octo.hires {
+        return Err("the program is in SUPER-CHIP's high resolution mode".to_string());
+    }
+    let mut state = CHIP8::new().snapshot();
+
+    let memory = numbers(&octo.m, "m", 0xFF)?;
+    if memory.len() < state.memory.len() {
+        return Err(format!("`m` must hold {} bytes", state.memory.len()));
+    }
+    if memory[state.memory.len()..].iter().any(|&byte| byte != 0) {
+        return Err("the program uses XO-CHIP's 64 KB of memory".to_string());
+    }
+    for (byte, &value) in state.memory.iter_mut().zip(&memory) {
+        *byte = value as u8;
+    }
+
+    let variables = numbers(&octo.v, "v", 0xFF)?;
+    if variables.len() != state.variable.len() {
+        return Err("`v` must hold 16 registers".to_string());
+    }
+    for (register, &value) in state.variable.iter_mut().zip(&variables) {
+        *register = value as u8;
+    }
+
+    state.program_counter = number(octo.pc, "pc", 0xFFF)? as u16;
+    // Octo moves past `FX0A` while it waits for a key, where this emulator runs it again.
+    if octo.waiting {
+        state.program_counter = state.program_counter.saturating_sub(2);
+    }
+    state.index = number(octo.i, "i", 0xFFFF)? as u16;
+    state.timer.delay_timer = number(octo.dt, "dt", 0xFF)? as u8;
+    state.timer.sound_timer = number(octo.st, "st", 0xFF)? as u8;
+
+    if octo.r.len() > STACK_DEPTH {
+        return Err(format!("`r` must hold at most {} addresses", STACK_DEPTH));
+    }
+    for address in numbers(&octo.r, "r", 0xFFF)? {
+        let address = address as u16;
+        state.stack.push(address);
+        // Octo does not keep where each subroutine starts, but the call that returns here does.
+        let call = address.wrapping_sub(2);
+        let opcode = state
+            .memory
+            .get(call as usize..call as usize + 2)
+            .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+        let target = if opcode & 0xF000 == 0x2000 {
+            opcode & 0xFFF
+        } else {
+            call
+        };
+        state.calls.push(Frame { call, target });
+    }
+
+    let plane = |index: usize| -> Result<Vec<u64>, String> {
+        let pixels = octo.p.get(index).map_or(&[][..], Vec::as_slice);
+        pixels
+            .iter()
+            .map(|pixel| match pixel {
+                Pixel::Lit(lit) => Ok(*lit as u64),
+                Pixel::Number(value) => number(*value, "p", 1),
+            })
+            .collect()
+    };
+    if plane(1)?.iter().any(|&pixel| pixel != 0) {
+        return Err("the program draws to XO-CHIP's second plane".to_string());
+    }
+    let pixels = plane(0)?;
+    let (width, height) = (state.display.screen[0].len(), state.display.screen.len());
+    if pixels.len() != width * height {
+        return Err(format!(
+            "the first plane of `p` must hold {} pixels",
+            width * height
+        ));
+    }
+    for (row, pixels) in state.display.screen.iter_mut().zip(pixels.chunks(width)) {
+        for (pixel, &lit) in row.iter_mut().zip(pixels) {
+            *pixel = lit != 0;
+        }
+    }
+    Ok(state::save(&state, rom_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns Octo's state of a machine that has just loaded a ROM, with some fields changed.
+    fn octo(changes: &[(&str, String)]) -> String {
+        let zeros = |len: usize| format!("[{}]", vec!["0"; len].join(", "));
+        let fields = [
+            ("m", zeros(4096)),
+            ("v", zeros(16)),
+            ("r", "[]".to_string()),
+            ("pc", "512".to_string()),
+            ("i", "0".to_string()),
+            ("dt", "0".to_string()),
+            ("st", "0".to_string()),
+            ("p", format!("[{}, {}]", zeros(64 * 32), zeros(64 * 32))),
+            ("hires", "false".to_string()),
+            ("flags", "[]".to_string()),
+            ("waiting", "false".to_string()),
+        ];
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| {
+                let change = changes.iter().find(|(changed, _)| changed == name);
+                format!("\"{}\": {}", name, change.map_or(value, |(_, value)| value))
+            })
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    #[test]
+    fn import_octo() {
+        // 0x200: CALL 0x300, which has returned to 0x202, and then waits for a key at 0x312.
+        let mut memory = vec!["0"; 4096];
+        memory[0x200] = "35";
+        let mut pixels = vec!["0"; 64 * 32];
+        pixels[0] = "1";
+        let text = octo(&[
+            ("m", format!("[{}]", memory.join(", "))),
+            ("v", format!("[0, 7, {}]", vec!["0"; 14].join(", "))),
+            ("r", "[514]".to_string()),
+            ("pc", "788".to_string()),
+            ("i", "1024".to_string()),
+            ("dt", "9".to_string()),
+            ("p", format!("[[{}], []]", pixels.join(", "))),
+            ("waiting", "true".to_string()),
+        ]);
+
+        let mut chip = CHIP8::new();
+        state::restore(&mut chip, &import(&text, 3).unwrap(), 3).unwrap();
+        assert_eq!(chip.memory[0x200], 0x23);
+        assert_eq!(
+            (chip.variable[1], chip.index, chip.program_counter),
+            (7, 0x400, 0x312)
+        );
+        assert_eq!(chip.timer.delay_timer, 9);
+        assert_eq!(chip.stack[..], [0x202]);
+        assert_eq!(
+            chip.calls,
+            [Frame {
+                call: 0x200,
+                target: 0x300
+            }]
+        );
+        assert!(chip.display.screen[0][0] && !chip.display.screen[0][1]);
+
+        assert_eq!(
+            import(&octo(&[("hires", "true".to_string())]), 3),
+            Err("the program is in SUPER-CHIP's high resolution mode".to_string())
+        );
+        assert_eq!(
+            import(&octo(&[("dt", "300".to_string())]), 3),
+            Err("`dt` must be a number up to 0xFF".to_string())
+        );
+        assert_eq!(
+            import(&octo(&[("p", "[[], [1]]".to_string())]), 3),
+            Err("the program draws to XO-CHIP's second plane".to_string())
+        );
+    }
+}
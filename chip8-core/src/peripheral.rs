@@ -0,0 +1,135 @@
+//! Extensions that run alongside the interpreter, such as printers, serial loggers or custom I/O.
+//!
+//! A [`Peripheral`] is attached to a machine with [`CHIP8::attach`], and hears about every
+//! instruction it executes, every data access those instructions make, and every frame through
+//! [`CHIP8::tick`]. Unlike a [`Device`](crate::bus::Device), it does not take over any memory, so
+//! it can watch a region that the program also uses as RAM:
+//!
+//! ```
+//! use chip8_core::memory::{Access, AccessKind};
+//! use chip8_core::peripheral::Peripheral;
+//! use chip8_core::rom::Rom;
+//! use chip8_core::CHIP8;
+//! use std::sync::mpsc::{self, Sender};
+//!
+//! /// Prints each byte the program writes to `0xF00`.
+//! #[derive(Clone)]
+//! struct Printer(Sender<u8>);
+//!
+//! impl Peripheral for Printer {
+//!     fn on_memory_access(&mut self, access: &Access) {
+//!         if access.kind == AccessKind::Write && access.address == 0xF00 {
+//!             let _ = self.0.send(access.new);
+//!         }
+//!     }
+//! }
+//!
+//! // 0x200: LD V0, 0x41; LD I, 0xF00; LD [I], V0
+//! let rom = Rom::from_bytes(vec![0x60, 0x41, 0xAF, 0x00, 0xF0, 0x55]).unwrap();
+//! let mut chip = CHIP8::new();
+//! chip.load_rom(&rom).unwrap();
+//! let (sender, printed) = mpsc::channel();
+//! chip.attach(Printer(sender));
+//! for _ in 0..3 {
+//!     chip.step().unwrap();
+//! }
+//! assert_eq!(printed.try_recv(), Ok(b'A'));
+//! ```
+
+use crate::instruction::Instruction;
+use crate::memory::Access;
+use crate::CHIP8;
+use alloc::boxed::Box;
+
+/// An extension attached to a machine. Every method does nothing unless implemented.
+///
+/// Peripherals are `Clone` so that a machine can be, and each copy gets its own peripherals.
+pub trait Peripheral: Send + PeripheralClone {
+    /// Called once per frame by [`CHIP8::tick`], after the timers are updated.
+    fn tick(&mut self, _chip: &mut CHIP8) {}
+
+    /// Called with each byte of memory an instruction read or wrote, once it has executed.
+    fn on_memory_access(&mut self, _access: &Access) {}
+
+    /// Called after `instruction` at `address` executed successfully.
+    fn on_instruction(&mut self, _chip: &mut CHIP8, _address: u16, _instruction: Instruction) {}
+}
+
+/// Copies a boxed peripheral. Implemented for every peripheral that is `Clone`.
+pub trait PeripheralClone {
+    fn clone_box(&self) -> Box<dyn Peripheral>;
+}
+
+impl<T: Peripheral + Clone + 'static> PeripheralClone for T {
+    fn clone_box(&self) -> Box<dyn Peripheral> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Peripheral> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::AccessKind;
+    use crate::rom::Rom;
+    use std::sync::mpsc::{self, Sender};
+
+    /// Sends what it hears about, and sets V1 to the number of frames.
+    #[derive(Clone)]
+    struct Recorder {
+        heard: Sender<(u16, Option<Instruction>)>,
+        frames: u8,
+    }
+
+    impl Peripheral for Recorder {
+        fn tick(&mut self, chip: &mut CHIP8) {
+            self.frames += 1;
+            chip.variable_mut()[1] = self.frames;
+        }
+
+        fn on_memory_access(&mut self, access: &Access) {
+            assert_eq!(access.kind, AccessKind::Read);
+            self.heard.send((access.address, None)).unwrap();
+        }
+
+        fn on_instruction(&mut self, _chip: &mut CHIP8, address: u16, instruction: Instruction) {
+            self.heard.send((address, Some(instruction))).unwrap();
+        }
+    }
+
+    #[test]
+    fn hooks() {
+        // 0x200: LD I, 0x300; LD V0, [I]
+        let rom = [0xA3, 0x00, 0xF0, 0x65];
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        let (heard, receiver) = mpsc::channel();
+        chip.attach(Recorder { heard, frames: 0 });
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [
+                (0x200, Some(Instruction::SetIndex { nnn: 0x300 })),
+                (0x300, None),
+                (0x202, Some(Instruction::Load { x: 0 })),
+            ]
+        );
+
+        chip.tick();
+        chip.tick();
+        assert_eq!(chip.variable()[1], 2);
+        // A copy of the machine gets a copy of the peripheral.
+        let mut copy = chip.clone();
+        copy.tick();
+        assert_eq!(copy.variable()[1], 3);
+        chip.tick();
+        assert_eq!(chip.variable()[1], 3);
+    }
+}
@@ -0,0 +1,480 @@
+//! Gameplay tests: a ROM played with keys pressed at given frames, and checks of memory and the
+//! screen at others, described in a TOML file so that game developers can run them in continuous
+//! integration.
+//!
+//! ```toml
+//! rom = "pong.ch8"       # relative to the test file
+//! seed = 1               # for CXNN, 0 by default
+//! quirks = "chip8"       # or "schip" or "xochip"
+//!
+//! [[key]]
+//! key = 0x1
+//! press = 10
+//! release = 40           # held until the end without it
+//!
+//! [[check]]
+//! name = "the paddle moved up"
+//! frame = 60
+//! address = 0x3F0
+//! bytes = [0, 3]
+//!
+//! [[check]]
+//! frame = 60
+//! x = 0
+//! y = 10
+//! screen = [
+//!   "#.",
+//!   "#.",                # `#` is lit, `.` is not and `?` is either
+//! ]
+//! ```
+//!
+//! A check runs after the number of frames it names, so frame 0 is before the ROM starts.
+
+use crate::builder::Builder;
+use crate::display::{HEIGHT, WIDTH};
+use crate::error::ExecError;
+use crate::frontend::{Headless, Input, Runner};
+use crate::keyboard::Keyboard;
+use crate::quirks::Quirks;
+use crate::CHIP8;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use serde::Deserialize;
+
+/// A key pressed or released at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// What a check expects to find.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expectation {
+    /// `bytes` in memory from `address`.
+    Memory { address: u16, bytes: Vec<u8> },
+    /// Pixels from `x`, `y` on the screen, a row of `#`, `.` and `?` for each line.
+    Screen {
+        x: usize,
+        y: usize,
+        rows: Vec<String>,
+    },
+}
+
+/// Expectations about the machine after a number of frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub frame: u32,
+    pub expectations: Vec<Expectation>,
+}
+
+/// A gameplay test.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Playtest {
+    /// Path of the ROM, relative to the test.
+    pub rom: String,
+    pub seed: u32,
+    /// Instructions per second, or the default speed if `None`.
+    pub speed: Option<u32>,
+    /// The quirks to run with, or those of the original CHIP-8 if `None`.
+    pub quirks: Option<Quirks>,
+    /// Frames to run for at least, even after the last check.
+    pub frames: u32,
+    pub keys: Vec<KeyEvent>,
+    pub checks: Vec<Check>,
+}
+
+/// The outcome of one check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub name: String,
+    /// What did not match, empty if the check passed.
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// A test as its file is written, before the checks that serde cannot do.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    rom: String,
+    #[serde(default)]
+    seed: u32,
+    speed: Option<u32>,
+    quirks: Option<QuirksName>,
+    #[serde(default)]
+    frames: u32,
+    #[serde(default)]
+    key: Vec<KeyFile>,
+    #[serde(default)]
+    check: Vec<CheckFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum QuirksName {
+    Chip8,
+    Schip,
+    Xochip,
+}
+
+/// A `[[key]]` table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    key: u8,
+    press: u32,
+    release: Option<u32>,
+}
+
+/// A `[[check]]` table.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckFile {
+    name: Option<String>,
+    frame: u32,
+    address: Option<u16>,
+    bytes: Option<Vec<u8>>,
+    x: Option<usize>,
+    y: Option<usize>,
+    screen: Option<Vec<String>>,
+}
+
+impl Playtest {
+    /// Parses a test from TOML.
+    pub fn parse(text: &str) -> Result<Playtest, String> {
+        let file: File = toml::from_str(text).map_err(|err| {
+            let start = err.span().map_or(0, |span| span.start);
+            let line = text[..start].matches('\n').count() + 1;
+            format!("line {}: {}", line, err.message())
+        })?;
+
+        let mut keys = Vec::new();
+        for (n, key) in file.key.into_iter().enumerate() {
+            if key.key > 0xF {
+                return Err(format!("key {}: `key` is not between 0 and 0xF", n + 1));
+            }
+            keys.push(KeyEvent {
+                frame: key.press,
+                key: key.key,
+                pressed: true,
+            });
+            if let Some(release) = key.release {
+                if release <= key.press {
+                    return Err(format!("key {}: released before it is pressed", n + 1));
+                }
+                keys.push(KeyEvent {
+                    frame: release,
+                    key: key.key,
+                    pressed: false,
+                });
+            }
+        }
+
+        let checks = file
+            .check
+            .into_iter()
+            .enumerate()
+            .map(|(n, check)| Check::new(check, &format!("check {}: ", n + 1)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Playtest {
+            rom: file.rom,
+            seed: file.seed,
+            speed: file.speed,
+            quirks: file.quirks.map(|quirks| match quirks {
+                QuirksName::Chip8 => Quirks::CHIP8,
+                QuirksName::Schip => Quirks::SUPER_CHIP,
+                QuirksName::Xochip => Quirks::XO_CHIP,
+            }),
+            frames: file.frames,
+            keys,
+            checks,
+        })
+    }
+
+    /// Returns a builder for a machine configured as the test says.
+    pub fn builder(&self) -> Builder {
+        let mut builder = CHIP8::builder().seed(self.seed);
+        if let Some(speed) = self.speed {
+            builder = builder.speed(speed);
+        }
+        if let Some(quirks) = self.quirks {
+            builder = builder.quirks(quirks);
+        }
+        builder
+    }
+
+    /// Plays `chip`, with the ROM loaded, pressing the keys and running the checks.
+    ///
+    /// If the program fails, the checks it did not reach fail with its error.
+    pub fn run(&self, chip: CHIP8) -> Vec<Outcome> {
+        let last = self.checks.iter().map(|check| check.frame).max();
+        let frames = self.frames.max(last.unwrap_or(0));
+        let script = Script {
+            keys: &self.keys,
+            frame: 0,
+        };
+        let mut runner = Runner::new(chip, Headless, Headless, script);
+        let mut outcomes: Vec<Option<Outcome>> = self.checks.iter().map(|_| None).collect();
+        let mut error: Option<ExecError> = None;
+        for frame in 0..=frames {
+            for (check, outcome) in self.checks.iter().zip(&mut outcomes) {
+                if check.frame == frame {
+                    *outcome = Some(check.run(&runner.chip));
+                }
+            }
+            if frame == frames {
+                break;
+            }
+            if let Err(err) = runner.frame() {
+                error = Some(err);
+                break;
+            }
+        }
+        self.checks
+            .iter()
+            .zip(outcomes)
+            .map(|(check, outcome)| {
+                outcome.unwrap_or_else(|| Outcome {
+                    name: check.name.clone(),
+                    failures: alloc::vec![match &error {
+                        Some(err) =>
+                            format!("the program failed before frame {}: {}", check.frame, err),
+                        None => format!("frame {} was not reached", check.frame),
+                    }],
+                })
+            })
+            .collect()
+    }
+}
+
+impl Check {
+    fn new(file: CheckFile, context: &str) -> Result<Check, String> {
+        let frame = file.frame;
+        let mut expectations = Vec::new();
+        match (file.bytes, file.address) {
+            (Some(bytes), Some(address)) => {
+                expectations.push(Expectation::Memory { address, bytes })
+            }
+            (Some(_), None) => return Err(format!("{}`address` is missing", context)),
+            (None, Some(_)) => return Err(format!("{}`address` without `bytes`", context)),
+            (None, None) => (),
+        }
+        match file.screen {
+            Some(rows) => {
+                if !rows
+                    .iter()
+                    .all(|row| row.chars().all(|c| "#.?".contains(c)))
+                {
+                    return Err(format!(
+                        "{}`screen` rows may only hold `#`, `.` and `?`",
+                        context
+                    ));
+                }
+                let x = file.x.unwrap_or(0);
+                let y = file.y.unwrap_or(0);
+                let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+                if x.saturating_add(width) > WIDTH || y.saturating_add(rows.len()) > HEIGHT {
+                    return Err(format!("{}`screen` runs off the screen", context));
+                }
+                expectations.push(Expectation::Screen { x, y, rows });
+            }
+            None if file.x.is_some() || file.y.is_some() => {
+                return Err(format!("{}`x` or `y` without `screen`", context));
+            }
+            None => (),
+        }
+        if expectations.is_empty() {
+            return Err(format!("{}nothing to check", context));
+        }
+        Ok(Check {
+            name: file.name.unwrap_or_else(|| format!("frame {}", frame)),
+            frame,
+            expectations,
+        })
+    }
+
+    /// Checks `chip` against the expectations.
+    pub fn run(&self, chip: &CHIP8) -> Outcome {
+        let mut failures = Vec::new();
+        for expectation in &self.expectations {
+            match expectation {
+                Expectation::Memory { address, bytes } => {
+                    let start = *address as usize;
+                    let found = chip.memory().get(start..start + bytes.len());
+                    if found != Some(&bytes[..]) {
+                        failures.push(format!(
+                            "memory at {:#05X}: expected {}, found {}",
+                            address,
+                            hex(bytes),
+                            found.map_or("nothing".to_string(), hex)
+                        ));
+                    }
+                }
+                Expectation::Screen { x, y, rows } => {
+                    let display = chip.display();
+                    let matches = rows.iter().enumerate().all(|(row, pixels)| {
+                        pixels.chars().enumerate().all(|(column, c)| {
+                            let lit = display.pixel(x + column, y + row);
+                            c == '?' || lit == (c == '#')
+                        })
+                    });
+                    if !matches {
+                        let mut failure =
+                            format!("screen at ({}, {}): expected, then found:", x, y);
+                        for row in rows {
+                            let _ = write!(failure, "\n  {}", row);
+                        }
+                        for (row, pixels) in rows.iter().enumerate() {
+                            failure.push_str("\n  ");
+                            for column in 0..pixels.len() {
+                                let lit = display.pixel(x + column, y + row);
+                                failure.push(if lit { '#' } else { '.' });
+                            }
+                        }
+                        failures.push(failure);
+                    }
+                }
+            }
+        }
+        Outcome {
+            name: self.name.clone(),
+            failures,
+        }
+    }
+}
+
+/// Writes bytes as hexadecimal, separated by spaces.
+fn hex(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    hex.join(" ")
+}
+
+/// Presses the keys of a test at the frames it says.
+struct Script<'a> {
+    keys: &'a [KeyEvent],
+    frame: u32,
+}
+
+impl Input for Script<'_> {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        for event in self.keys {
+            if event.frame == self.frame {
+                keyboard.set(event.key, event.pressed);
+            }
+        }
+        self.frame += 1;
+        true
+    }
+}
+
+/// Reports `outcomes` in the Test Anything Protocol, which continuous integration services read.
+pub fn tap(outcomes: &[Outcome]) -> String {
+    let mut out = format!("1..{}\n", outcomes.len());
+    for (n, outcome) in outcomes.iter().enumerate() {
+        let status = if outcome.passed() { "ok" } else { "not ok" };
+        let _ = writeln!(out, "{} {} - {}", status, n + 1, outcome.name);
+        for line in outcome.failures.iter().flat_map(|failure| failure.lines()) {
+            let _ = writeln!(out, "# {}", line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    /// Waits for key 5 and draws the digit pressed at the top left, storing it at 0x300.
+    ///
+    /// 0x200: LD V0, K; LD F, V0; DRW V1, V1, 5; LD I, 0x300; LD [I], V0; 0x20A: JP 0x20A
+    const ROM: [u8; 12] = [
+        0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x0A,
+    ];
+
+    const TEST: &str = r#####"
+rom = "wait.ch8"
+
+[[key]]
+key = 5
+press = 2
+release = 3
+
+[[check]]
+name = "nothing drawn before the key"
+frame = 1
+screen = ["....", "...."]
+
+[[check]]
+name = "the key is stored"
+frame = 5
+address = 0x300
+bytes = [5]
+
+[[check]]
+frame = 5
+screen = [
+  "####",
+  "#...",
+  "####",
+  "...#",
+  "####",
+]
+"#####;
+
+    fn play(test: &Playtest) -> Vec<Outcome> {
+        let mut chip = test.builder().build().unwrap();
+        chip.load_rom(&Rom::from_bytes(ROM.to_vec()).unwrap())
+            .unwrap();
+        test.run(chip)
+    }
+
+    #[test]
+    fn parse() {
+        let test = Playtest::parse(TEST).unwrap();
+        assert_eq!(test.rom, "wait.ch8");
+        assert_eq!(test.keys.len(), 2);
+        assert_eq!(test.checks[2].name, "frame 5");
+        assert!(Playtest::parse("rom = \"a\"\nsped = 1")
+            .unwrap_err()
+            .starts_with("line 2: unknown field `sped`"));
+        assert!(Playtest::parse("rom = \"a\"\nquirks = \"chip9\"")
+            .unwrap_err()
+            .starts_with("line 2: unknown variant `chip9`"));
+        assert_eq!(
+            Playtest::parse("rom = \"a\"\n[[check]]\nframe = 1").unwrap_err(),
+            "check 1: nothing to check"
+        );
+        assert_eq!(
+            Playtest::parse("rom = \"a\"\n[[key]]\nkey = 16\npress = 1").unwrap_err(),
+            "key 1: `key` is not between 0 and 0xF"
+        );
+    }
+
+    #[test]
+    fn run() {
+        let outcomes = play(&Playtest::parse(TEST).unwrap());
+        assert!(outcomes.iter().all(Outcome::passed), "{:?}", outcomes);
+
+        let failing = TEST.replace("bytes = [5]", "bytes = [6, 0]");
+        let outcomes = play(&Playtest::parse(&failing).unwrap());
+        assert_eq!(
+            outcomes[1].failures,
+            ["memory at 0x300: expected 06 00, found 05 00"]
+        );
+        let report = tap(&outcomes);
+        assert!(report.starts_with("1..3\nok 1 - nothing drawn before the key\nnot ok 2 -"));
+        assert!(report.contains("# memory at 0x300"));
+    }
+}
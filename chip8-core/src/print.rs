@@ -0,0 +1,116 @@
+//! Debug output for ROM developers, through machine code calls that no real machine would make.
+//!
+//! When enabled, these `0NNN` opcodes print instead of failing:
+//!
+//! * `0F0X` prints the value of VX.
+//! * `0F10` prints the zero-terminated ASCII string at I.
+//! * `0F11` prints I and every variable register.
+
+#[cfg(feature = "std")]
+use crate::error::ExecError;
+#[cfg(feature = "std")]
+use crate::CHIP8;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+/// Longest string printed by `0F10`, in case the terminator is missing.
+#[cfg(feature = "std")]
+const MAX_STRING: usize = 64;
+
+/// What a debug print opcode prints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Print {
+    Register(u8),
+    String,
+    Registers,
+}
+
+impl Print {
+    /// Recognizes the address of a machine code call as a debug print.
+    fn decode(nnn: u16) -> Option<Print> {
+        match nnn {
+            0xF00..=0xF0F => Some(Print::Register((nnn & 0xF) as u8)),
+            0xF10 => Some(Print::String),
+            0xF11 => Some(Print::Registers),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `true` if a machine code call to `nnn` is a debug print.
+pub fn is_print(nnn: u16) -> bool {
+    Print::decode(nnn).is_some()
+}
+
+/// Executes one instruction, writing to `out` instead if it is a debug print.
+#[cfg(feature = "std")]
+pub fn step<W, F>(chip: &mut CHIP8, out: &mut W, step: F) -> Result<(), ExecError>
+where
+    W: Write,
+    F: FnOnce(&mut CHIP8) -> Result<(), ExecError>,
+{
+    let address = chip.program_counter;
+    let print = match chip.opcode_at(address) {
+        Some(opcode) if opcode & 0xF000 == 0 => Print::decode(opcode),
+        _ => None,
+    };
+    let print = match print {
+        Some(print) => print,
+        None => return step(chip),
+    };
+
+    let text = match print {
+        Print::Register(x) => {
+            let value = chip.variable[x as usize];
+            format!("V{:X} = {:#04X} ({})", x, value, value)
+        }
+        Print::String => chip
+            .memory
+            .iter()
+            .skip(chip.index as usize)
+            .take(MAX_STRING)
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| byte as char)
+            .collect(),
+        Print::Registers => {
+            let variables: Vec<String> = (0..16)
+                .map(|x| format!("V{:X}={:02X}", x, chip.variable[x]))
+                .collect();
+            format!("I={:#05X} {}", chip.index, variables.join(" "))
+        }
+    };
+    // Debug output is best effort, and must not stop the program.
+    let _ = writeln!(out, "{:#05X}: {}", address, text);
+    chip.accesses.clear();
+    chip.program_counter += 2;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn prints() {
+        // 0x200: print V3; 0x202: print string at I; 0x204: print registers; 0x206: SYS 0x123
+        let rom = Rom::from_bytes(vec![0x0F, 0x03, 0x0F, 0x10, 0x0F, 0x11, 0x01, 0x23]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        chip.variable[3] = 31;
+        chip.index = 0x300;
+        chip.memory[0x300..0x303].copy_from_slice(b"hi\0");
+
+        let mut out = Vec::new();
+        for _ in 0..3 {
+            step(&mut chip, &mut out, CHIP8::step).unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "0x200: V3 = 0x1F (31)");
+        assert_eq!(lines[1], "0x202: hi");
+        assert!(lines[2].starts_with("0x204: I=0x300 V0=00"));
+        assert!(step(&mut chip, &mut Vec::new(), CHIP8::step).is_err());
+        assert!(is_print(0xF0A) && !is_print(0x123));
+    }
+}
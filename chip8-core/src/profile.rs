@@ -0,0 +1,169 @@
+//! Profiler counting how often, and for how long, each instruction runs.
+
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::CHIP8;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Number of rows in each table of the report.
+const REPORT_ROWS: usize = 20;
+
+/// Executions of an instruction and the host time they took.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub count: u64,
+    pub time: Duration,
+}
+
+impl Stat {
+    fn add(&mut self, time: Duration) {
+        self.count += 1;
+        self.time += time;
+    }
+}
+
+/// Collects execution statistics per address, per mnemonic and per call stack.
+pub struct Profiler {
+    addresses: Vec<Stat>,
+    mnemonics: BTreeMap<String, Stat>,
+    /// Instructions executed in each chain of subroutines, for flame graphs.
+    stacks: HashMap<Vec<u16>, u64>,
+}
+
+impl Profiler {
+    /// Creates an empty profiler for a machine with `memory_size` bytes of memory.
+    pub fn new(memory_size: usize) -> Self {
+        Profiler {
+            addresses: vec![Stat::default(); memory_size],
+            mnemonics: BTreeMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    /// Executes one instruction with `step`, recording it if it succeeds.
+    pub fn step<F>(&mut self, chip: &mut CHIP8, step: F) -> Result<(), ExecError>
+    where
+        F: FnOnce(&mut CHIP8) -> Result<(), ExecError>,
+    {
+        let address = chip.program_counter;
+        let instruction = chip.opcode_at(address).and_then(Instruction::decode);
+        let stack: Vec<u16> = chip.calls.iter().map(|frame| frame.target).collect();
+
+        let start = Instant::now();
+        step(chip)?;
+        let time = start.elapsed();
+
+        if let Some(stat) = self.addresses.get_mut(address as usize) {
+            stat.add(time);
+        }
+        if let Some(instruction) = instruction {
+            self.mnemonics
+                .entry(instruction.mnemonic())
+                .or_default()
+                .add(time);
+        }
+        *self.stacks.entry(stack).or_default() += 1;
+        Ok(())
+    }
+
+    /// Returns a report of the most executed addresses and mnemonics.
+    ///
+    /// `chip` is used to disassemble the hot addresses.
+    pub fn report(&self, chip: &CHIP8) -> String {
+        let total: u64 = self.mnemonics.values().map(|stat| stat.count).sum();
+        let percent = |count: u64| count as f64 * 100.0 / total.max(1) as f64;
+        let mut out = String::new();
+
+        let mut addresses: Vec<(usize, &Stat)> = self
+            .addresses
+            .iter()
+            .enumerate()
+            .filter(|(_, stat)| stat.count > 0)
+            .collect();
+        addresses.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(&b.0)));
+        let _ = writeln!(out, "{} instructions executed", total);
+        let _ = writeln!(out);
+        let _ = writeln!(out, "address      count       %     time  instruction");
+        for (address, stat) in addresses.iter().take(REPORT_ROWS) {
+            let instruction = chip
+                .opcode_at(*address as u16)
+                .and_then(Instruction::decode)
+                .map(|instruction| instruction.to_string())
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{:#05X}  {:>11} {:>6.2}% {:>6}µs  {}",
+                address,
+                stat.count,
+                percent(stat.count),
+                stat.time.as_micros(),
+                instruction
+            );
+        }
+
+        let mut mnemonics: Vec<(&String, &Stat)> = self.mnemonics.iter().collect();
+        mnemonics.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        let _ = writeln!(out);
+        let _ = writeln!(out, "mnemonic     count       %     time");
+        for (mnemonic, stat) in mnemonics.iter().take(REPORT_ROWS) {
+            let _ = writeln!(
+                out,
+                "{:<6} {:>11} {:>6.2}% {:>6}µs",
+                mnemonic,
+                stat.count,
+                percent(stat.count),
+                stat.time.as_micros()
+            );
+        }
+        out
+    }
+
+    /// Writes instruction counts per call stack in the folded format read by flame graph tools,
+    /// such as `start;0x300;0x340 1234`.
+    pub fn write_folded<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let mut stacks: Vec<(&Vec<u16>, &u64)> = self.stacks.iter().collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            let mut line = String::from("start");
+            for target in stack {
+                let _ = write!(line, ";{:#05X}", target);
+            }
+            writeln!(out, "{} {}", line, count)?;
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn counts() {
+        // 0x200: CALL 0x204; 0x202: JP 0x200; 0x204: ADD V0, 1; 0x206: RET
+        let rom = Rom::from_bytes(vec![0x22, 0x04, 0x12, 0x00, 0x70, 0x01, 0x00, 0xEE]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        let mut profiler = Profiler::new(chip.memory.len());
+        for _ in 0..8 {
+            profiler.step(&mut chip, CHIP8::step).unwrap();
+        }
+
+        assert_eq!(profiler.addresses[0x204].count, 2);
+        assert_eq!(profiler.addresses[0x208].count, 0);
+        let report = profiler.report(&chip);
+        assert!(report.starts_with("8 instructions executed"));
+        assert!(report.contains("ADD V0, 0x01"));
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            "start 4\nstart;0x204 4\n"
+        );
+    }
+}
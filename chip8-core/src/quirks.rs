@@ -0,0 +1,99 @@
+//! Behaviors that differ between interpreters of the same instructions.
+
+use crate::variant::Variant;
+
+/// Which of the behaviors that interpreters disagree on the machine follows.
+///
+/// Each flag is off on the original COSMAC VIP interpreter, apart from `vf_reset`, which only the
+/// original does. Programs written for SUPER-CHIP often rely on its quirks even when they only use
+/// CHIP-8 instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Quirks {
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0.
+    pub vf_reset: bool,
+    /// `8XY6` and `8XYE` shift VX in place instead of shifting VY into VX.
+    pub shift_in_place: bool,
+    /// `FX55` and `FX65` leave I unchanged instead of pointing it past the last register.
+    pub keep_index: bool,
+    /// `BNNN` jumps to NNN plus VX, where X is the first digit of NNN, instead of plus V0.
+    pub jump_vx: bool,
+}
+
+impl Quirks {
+    /// The behavior of the original COSMAC VIP interpreter.
+    pub const CHIP8: Quirks = Quirks {
+        vf_reset: true,
+        shift_in_place: false,
+        keep_index: false,
+        jump_vx: false,
+    };
+
+    /// The behavior of SUPER-CHIP 1.1 on the HP 48.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_in_place: true,
+        keep_index: true,
+        jump_vx: true,
+    };
+
+    /// The behavior of XO-CHIP, as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_in_place: false,
+        keep_index: false,
+        jump_vx: false,
+    };
+
+    /// Returns the quirks programs written for `variant` expect.
+    pub fn for_variant(variant: Variant) -> Quirks {
+        match variant {
+            Variant::Chip8 => Quirks::CHIP8,
+            Variant::SuperChip => Quirks::SUPER_CHIP,
+            Variant::XoChip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::CHIP8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use crate::CHIP8;
+
+    /// Runs `rom` for `steps` instructions with `quirks`.
+    fn run(quirks: Quirks, rom: &[u8], steps: usize) -> CHIP8 {
+        let mut chip = CHIP8::builder().quirks(quirks).build().unwrap();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        for _ in 0..steps {
+            chip.step().unwrap();
+        }
+        chip
+    }
+
+    #[test]
+    fn quirks() {
+        // 0x200: LD VF, 1; LD V1, 3; LD V2, 8; OR V1, V1; SHR V1, V2; LD I, 0x300; LD [I], V1
+        let rom = [
+            0x6F, 0x01, 0x61, 0x03, 0x62, 0x08, 0x81, 0x11, 0x81, 0x26, 0xA3, 0x00, 0xF1, 0x55,
+        ];
+        let chip = run(Quirks::CHIP8, &rom, 7);
+        assert_eq!(chip.variable[1], 4);
+        assert_eq!(chip.index, 0x302);
+        let chip = run(Quirks::SUPER_CHIP, &rom, 7);
+        assert_eq!(chip.variable[1], 1);
+        assert_eq!(chip.variable[0xF], 1);
+        assert_eq!(chip.index, 0x300);
+
+        // 0x200: LD V0, 0; LD V3, 4; JP V0, 0x300
+        let rom = [0x60, 0x00, 0x63, 0x04, 0xB3, 0x00];
+        assert_eq!(run(Quirks::CHIP8, &rom, 3).program_counter, 0x300);
+        assert_eq!(run(Quirks::SUPER_CHIP, &rom, 3).program_counter, 0x304);
+    }
+}
@@ -0,0 +1,406 @@
+//! Remote display and control: the messages that stream the display to a viewer on another
+//! machine, such as a browser, and carry its key presses back.
+//!
+//! An [`Encoder`] turns each frame into a small binary message, or none if nothing changed. The
+//! pixels are listed row by row as runs that alternate between pixels left alone and pixels
+//! flipped, starting with pixels left alone, so that a frame which moved one paddle is a few bytes:
+//!
+//! ```text
+//! 00 40 20 <runs>    keyframe: width, height, then runs flipping the pixels of a blank screen
+//! 01 40 20 <runs>    change: runs flipping the pixels of the last frame
+//! 02 01              sound: 1 while the tone plays, 0 once it stops
+//! 03 05 <numbers>    crowd play: the key held (FF for none), then the frames left to vote and
+//!                    the votes for each of the 16 keys
+//! ```
+//!
+//! Each run is a LEB128 number: seven bits at a time, lowest first, with the top bit set on all
+//! but the last byte, as are the numbers of a tally. The pixels after the last run are left
+//! alone. A [`Decoder`] reads the messages back. Tallies are only sent in crowd play, described
+//! in [`crowd`](crate::crowd).
+//!
+//! The viewer sends [`Event`]s as text, one per message: `down 5` when key 5 is pressed and
+//! `up 5` when it is released, with the key in hexadecimal.
+
+use crate::crowd::Tally;
+use crate::display::{Display, HEIGHT, WIDTH};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// The first byte of a message with a whole frame.
+pub const KEYFRAME: u8 = 0;
+/// The first byte of a message with the pixels that changed since the last frame.
+pub const CHANGE: u8 = 1;
+/// The first byte of a message saying whether the tone plays.
+pub const SOUND: u8 = 2;
+/// The first byte of a message with the votes of crowd play.
+pub const TALLY: u8 = 3;
+
+/// The pixels of a display, row by row.
+type Pixels = [[bool; WIDTH]; HEIGHT];
+
+const BLANK: Pixels = [[false; WIDTH]; HEIGHT];
+
+/// Turns frames into messages for one viewer, sending only what it has not seen.
+#[derive(Clone, Debug, Default)]
+pub struct Encoder {
+    /// The last frame sent, if any.
+    sent: Option<Pixels>,
+    /// Whether the tone played at the last frame sent.
+    sounding: bool,
+    /// The last tally sent, if any.
+    tally: Option<Tally>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder::default()
+    }
+
+    /// Returns the message that brings the viewer up to date with `display`, or `None` if it
+    /// already shows it. The first message is a keyframe, and later ones are changes unless a
+    /// keyframe is shorter, such as after the screen is cleared.
+    pub fn frame(&mut self, display: &Display) -> Option<Vec<u8>> {
+        let screen = display.screen();
+        let keyframe = encode(KEYFRAME, &BLANK, screen);
+        let message = match &self.sent {
+            Some(sent) if sent == screen => return None,
+            Some(sent) => {
+                let change = encode(CHANGE, sent, screen);
+                if change.len() <= keyframe.len() {
+                    change
+                } else {
+                    keyframe
+                }
+            }
+            None => keyframe,
+        };
+        self.sent = Some(*screen);
+        Some(message)
+    }
+
+    /// Returns the message telling the viewer that the tone plays or stopped, or `None` if it
+    /// already knows.
+    pub fn sound(&mut self, sounding: bool) -> Option<Vec<u8>> {
+        if sounding == self.sounding {
+            return None;
+        }
+        self.sounding = sounding;
+        Some(vec![SOUND, u8::from(sounding)])
+    }
+
+    /// Returns the message with the votes of crowd play, or `None` if the viewer already has it.
+    pub fn tally(&mut self, tally: &Tally) -> Option<Vec<u8>> {
+        if self.tally.as_ref() == Some(tally) {
+            return None;
+        }
+        self.tally = Some(tally.clone());
+        let mut message = vec![TALLY, tally.held.unwrap_or(0xFF)];
+        push_number(&mut message, tally.remaining as usize);
+        for &votes in &tally.votes {
+            push_number(&mut message, votes as usize);
+        }
+        Some(message)
+    }
+}
+
+/// Returns the message of kind `kind` that turns `from` into `to`.
+fn encode(kind: u8, from: &Pixels, to: &Pixels) -> Vec<u8> {
+    let mut message = vec![kind, WIDTH as u8, HEIGHT as u8];
+    let pixels = from
+        .iter()
+        .flatten()
+        .zip(to.iter().flatten())
+        .map(|(from, to)| from != to);
+    let (mut flipping, mut run) = (false, 0);
+    for flipped in pixels {
+        if flipped != flipping {
+            push_number(&mut message, run);
+            flipping = flipped;
+            run = 0;
+        }
+        run += 1;
+    }
+    if flipping {
+        push_number(&mut message, run);
+    }
+    message
+}
+
+/// Appends `number` to `message` in LEB128.
+fn push_number(message: &mut Vec<u8>, mut number: usize) {
+    while number >= 0x80 {
+        message.push(number as u8 | 0x80);
+        number >>= 7;
+    }
+    message.push(number as u8);
+}
+
+/// Shows the frames of an [`Encoder`], as a viewer does.
+#[derive(Clone)]
+pub struct Decoder {
+    display: Display,
+    sounding: bool,
+    tally: Option<Tally>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            display: Display::new(),
+            sounding: false,
+            tally: None,
+        }
+    }
+
+    /// Returns the display as of the last message.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Returns `true` while the tone plays.
+    pub fn is_sounding(&self) -> bool {
+        self.sounding
+    }
+
+    /// Returns the votes of crowd play, once any were sent.
+    pub fn tally(&self) -> Option<&Tally> {
+        self.tally.as_ref()
+    }
+
+    /// Applies `message`.
+    pub fn decode(&mut self, message: &[u8]) -> Result<(), String> {
+        match message {
+            [SOUND, sounding] => {
+                self.sounding = *sounding != 0;
+                Ok(())
+            }
+            [TALLY, held, numbers @ ..] => {
+                let mut numbers = numbers;
+                let mut tally = Tally {
+                    held: (*held <= 0xF).then_some(*held),
+                    remaining: read_number(&mut numbers)? as u64,
+                    ..Tally::default()
+                };
+                for votes in &mut tally.votes {
+                    *votes = read_number(&mut numbers)? as u32;
+                }
+                if !numbers.is_empty() {
+                    return Err("malformed message".to_string());
+                }
+                self.tally = Some(tally);
+                Ok(())
+            }
+            [kind @ (KEYFRAME | CHANGE), width, height, runs @ ..] => {
+                if (usize::from(*width), usize::from(*height)) != (WIDTH, HEIGHT) {
+                    return Err(format!("unsupported display size {}x{}", width, height));
+                }
+                if *kind == KEYFRAME {
+                    self.display.clear();
+                }
+                self.flip(runs)
+            }
+            _ => Err("malformed message".to_string()),
+        }
+    }
+
+    /// Flips the pixels of the runs in `bytes`.
+    fn flip(&mut self, mut bytes: &[u8]) -> Result<(), String> {
+        let (mut pixel, mut flipping) = (0usize, false);
+        while !bytes.is_empty() {
+            let run = read_number(&mut bytes)?;
+            let end = pixel
+                .checked_add(run)
+                .filter(|&end| end <= WIDTH * HEIGHT)
+                .ok_or_else(|| "runs past the end of the display".to_string())?;
+            if flipping {
+                for pixel in pixel..end {
+                    let (x, y) = (pixel % WIDTH, pixel / WIDTH);
+                    let lit = self.display.pixel(x, y);
+                    self.display.set_pixel(x, y, !lit);
+                }
+            }
+            pixel = end;
+            flipping = !flipping;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a LEB128 number from the front of `bytes`.
+fn read_number(bytes: &mut &[u8]) -> Result<usize, String> {
+    let mut number = 0;
+    for shift in (0..usize::BITS).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| "message ends within a run".to_string())?;
+        *bytes = rest;
+        number |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(number);
+        }
+    }
+    Err("run too long".to_string())
+}
+
+/// What a viewer tells the machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A key of the keypad was pressed or released.
+    Key { key: u8, pressed: bool },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Key { key, pressed } => {
+                write!(f, "{} {:x}", if *pressed { "down" } else { "up" }, key)
+            }
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid event `{}`", text.trim());
+        let mut words = text.split_whitespace();
+        let pressed = match words.next() {
+            Some("down") => true,
+            Some("up") => false,
+            _ => return Err(invalid()),
+        };
+        let key = match (words.next(), words.next()) {
+            (Some(key), None) => u8::from_str_radix(key, 16).map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+        if key > 0xF {
+            return Err(format!("no key {:X} on the keypad", key));
+        }
+        Ok(Event::Key { key, pressed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_frames() {
+        let mut display = Display::new();
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+
+        let blank = encoder.frame(&display).unwrap();
+        assert_eq!(blank, [KEYFRAME, 64, 32]);
+        assert_eq!(encoder.frame(&display), None);
+
+        display.draw(10, 5, &[0xF0, 0x90, 0xF0]);
+        let drawn = encoder.frame(&display).unwrap();
+        assert_eq!(drawn[0], CHANGE);
+        assert!(drawn.len() < 20, "{:?}", drawn);
+        decoder.decode(&blank).unwrap();
+        decoder.decode(&drawn).unwrap();
+        assert_eq!(decoder.display().text(), display.text());
+
+        // Moving the sprite sends only the pixels that differ.
+        display.draw(10, 5, &[0xF0, 0x90, 0xF0]);
+        display.draw(11, 5, &[0xF0, 0x90, 0xF0]);
+        decoder.decode(&encoder.frame(&display).unwrap()).unwrap();
+        assert_eq!(decoder.display().text(), display.text());
+
+        // A viewer joining late starts from a keyframe.
+        let mut late = Decoder::new();
+        let keyframe = Encoder::new().frame(&display).unwrap();
+        assert_eq!(keyframe[0], KEYFRAME);
+        late.decode(&keyframe).unwrap();
+        assert_eq!(late.display().text(), display.text());
+
+        // Whole rows make runs longer than a byte holds.
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                display.set_pixel(x, y, y >= 8);
+            }
+        }
+        decoder.decode(&encoder.frame(&display).unwrap()).unwrap();
+        assert_eq!(decoder.display().text(), display.text());
+    }
+
+    #[test]
+    fn streams_sound() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+        assert_eq!(encoder.sound(false), None);
+        decoder.decode(&encoder.sound(true).unwrap()).unwrap();
+        assert!(decoder.is_sounding());
+        assert_eq!(encoder.sound(true), None);
+        decoder.decode(&encoder.sound(false).unwrap()).unwrap();
+        assert!(!decoder.is_sounding());
+    }
+
+    #[test]
+    fn streams_tallies() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+        let mut tally = Tally {
+            held: Some(0xA),
+            remaining: 300,
+            ..Tally::default()
+        };
+        tally.votes[2] = 1000;
+        decoder.decode(&encoder.tally(&tally).unwrap()).unwrap();
+        assert_eq!(decoder.tally(), Some(&tally));
+        assert_eq!(encoder.tally(&tally), None);
+
+        tally.held = None;
+        decoder.decode(&encoder.tally(&tally).unwrap()).unwrap();
+        assert_eq!(decoder.tally(), Some(&tally));
+        assert!(decoder.decode(&[TALLY, 0xFF, 30, 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let mut decoder = Decoder::new();
+        assert!(decoder.decode(&[]).is_err());
+        assert!(decoder.decode(&[KEYFRAME, 128, 64]).is_err());
+        assert!(decoder.decode(&[CHANGE, 64, 32, 0x80]).is_err());
+        assert!(decoder.decode(&[CHANGE, 64, 32, 0x80, 0x80, 0x01]).is_err());
+        assert!(decoder.decode(&[9, 1]).is_err());
+    }
+
+    #[test]
+    fn parses_events() {
+        for event in [
+            Event::Key {
+                key: 0xA,
+                pressed: true,
+            },
+            Event::Key {
+                key: 0,
+                pressed: false,
+            },
+        ] {
+            assert_eq!(event.to_string().parse(), Ok(event));
+        }
+        assert_eq!(
+            "down a".parse(),
+            Ok(Event::Key {
+                key: 0xA,
+                pressed: true
+            })
+        );
+        assert!("down 10".parse::<Event>().is_err());
+        assert!("press 1".parse::<Event>().is_err());
+        assert!("up".parse::<Event>().is_err());
+    }
+}
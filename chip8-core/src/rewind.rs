@@ -0,0 +1,87 @@
+//! Rewinding, for scrubbing back through the last few seconds of a game.
+//!
+//! The machine is saved with [`state::save`] once per frame, into the buffer of
+//! [`emu_state::rewind`] that rewinds every system, which keeps all but the newest frame as the
+//! difference from the frame after it.
+
+use crate::state;
+use crate::CHIP8;
+
+pub use emu_state::rewind::DEFAULT_FRAMES;
+
+/// The most recent frames of a running game, newest last.
+pub struct Rewind {
+    frames: emu_state::rewind::Rewind,
+}
+
+impl Rewind {
+    /// Creates an empty buffer remembering up to `capacity` frames.
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            frames: emu_state::rewind::Rewind::new(capacity),
+        }
+    }
+
+    /// Returns the number of frames that can be rewound.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns whether no frames are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Remembers the current frame of `chip`, forgetting the oldest one if the buffer is full.
+    pub fn record(&mut self, chip: &CHIP8) {
+        self.frames.push(state::save(&chip.snapshot(), 0));
+    }
+
+    /// Restores `chip` to the newest frame and forgets it, returning `false` if there are no
+    /// frames left. The keys held down are kept, since they are still being held.
+    pub fn rewind(&mut self, chip: &mut CHIP8) -> bool {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        let keyboard = core::mem::take(&mut chip.keyboard);
+        // The frames were saved by `record`, so they are always valid.
+        let _ = state::restore(chip, &frame, 0);
+        chip.keyboard = keyboard;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn rewind() {
+        // 0x200: ADD V0, 1; 0x202: LD I, 0x300; 0x204: LD [I], V0; 0x206: JP 0x200
+        let rom = Rom::from_bytes(vec![0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        let mut rewind = Rewind::new(3);
+
+        for _ in 0..5 {
+            rewind.record(&chip);
+            for _ in 0..4 {
+                chip.step().unwrap();
+            }
+        }
+        assert_eq!(rewind.len(), 3);
+        // Only V0, I and the byte stored change between frames.
+        assert!(rewind.frames.size() < state::save(&chip.snapshot(), 0).len() + 2 * 64);
+
+        chip.keyboard.set(0xA, true);
+        for v0 in [4, 3, 2] {
+            assert!(rewind.rewind(&mut chip));
+            assert_eq!((chip.variable[0], chip.memory[0x300]), (v0, v0));
+            assert_eq!(chip.program_counter, 0x200);
+        }
+        assert!(chip.keyboard.is_pressed(0xA));
+        assert!(!rewind.rewind(&mut chip));
+    }
+}
@@ -0,0 +1,46 @@
+//! Random number generation.
+
+#[cfg(feature = "std")]
+use crate::host::{Entropy, SystemEntropy};
+
+/// Small xorshift random number generator used by `CXNN`.
+///
+/// CHIP-8 programs only need a byte of randomness at a time, so there is no need for anything
+/// stronger. Keeping the state explicit makes runs reproducible from a seed.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    /// Creates a generator from `seed`. A zero seed is replaced, since xorshift would get stuck.
+    pub fn new(seed: u32) -> Self {
+        Rng {
+            state: if seed == 0 { 0x2545_F491 } else { seed },
+        }
+    }
+
+    /// Creates a generator seeded from the system clock.
+    #[cfg(feature = "std")]
+    pub fn from_time() -> Self {
+        Rng::new(SystemEntropy.seed())
+    }
+
+    /// Returns the internal state, from which [`Rng::set_state`] continues the same sequence.
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+
+    /// Restores a state returned by [`Rng::state`].
+    pub fn set_state(&mut self, state: u32) {
+        *self = Rng::new(state);
+    }
+
+    /// Returns the next random byte.
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 24) as u8
+    }
+}
@@ -0,0 +1,369 @@
+//! ROM loading and validation.
+
+use crate::instruction::Instruction;
+use crate::print;
+use crate::variant::Variant;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+/// Address at which programs are loaded. Everything below it is reserved for the interpreter.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// Largest ROM that fits in the 4KB of memory of the original CHIP-8.
+pub const MAX_CHIP8_SIZE: usize = 0x1000 - PROGRAM_START as usize;
+
+/// Largest ROM that fits in the 64KB of memory of XO-CHIP.
+pub const MAX_SIZE: usize = 0x10000 - PROGRAM_START as usize;
+
+/// Errors that can occur while loading a ROM.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum RomError {
+    /// The ROM file could not be read.
+    #[cfg(feature = "std")]
+    #[error("could not read ROM: {0}")]
+    Io(#[from] io::Error),
+    /// The ROM contains no data.
+    #[error("ROM is empty")]
+    Empty,
+    /// The ROM does not fit in memory. Contains the size of the ROM and the space available.
+    #[error("ROM is {0} bytes but only {1} bytes are available")]
+    TooLarge(usize, usize),
+}
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Something suspicious that may still work.
+    Warning,
+    /// Something that will fail at runtime if it is reached.
+    Error,
+}
+
+/// A problem found while checking a ROM.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    /// Address of the offending instruction, if the problem is tied to one.
+    pub address: Option<u16>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, address: Option<u16>, message: String) -> Self {
+        Diagnostic {
+            address,
+            severity,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.address {
+            Some(address) => write!(f, "{}: {:#05X}: {}", severity, address, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// A CHIP-8 program.
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    /// Reads a ROM from a file.
+    #[cfg(feature = "std")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, RomError> {
+        Rom::from_bytes(fs::read(path)?)
+    }
+
+    /// Creates a ROM from raw bytes.
+    ///
+    /// Fails if the ROM is empty or too large to fit in the memory of any variant.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, RomError> {
+        if data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if data.len() > MAX_SIZE {
+            return Err(RomError::TooLarge(data.len(), MAX_SIZE));
+        }
+        Ok(Rom { data })
+    }
+
+    /// Returns the program bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the address one past the last byte of the ROM once loaded.
+    pub fn end(&self) -> usize {
+        PROGRAM_START as usize + self.data.len()
+    }
+
+    /// Reads the opcode at `address`, if both of its bytes lie within the ROM.
+    pub fn opcode_at(&self, address: usize) -> Option<u16> {
+        let offset = address.checked_sub(PROGRAM_START as usize)?;
+        let bytes = self.data.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Follows every path of execution from the entry point.
+    ///
+    /// `visit` is called once for each reachable instruction address with the opcode found there,
+    /// or `None` if execution can run past the end of the ROM. Paths are not followed past invalid
+    /// opcodes, computed jumps, or jumps into reserved memory.
+    pub fn walk<F: FnMut(usize, Option<u16>)>(&self, visit: F) {
+        self.walk_from(PROGRAM_START as usize, true, visit);
+    }
+
+    /// Follows every path of execution from `start`, like [`Rom::walk`]. If `into_calls` is
+    /// `false`, subroutines are stepped over rather than followed.
+    pub fn walk_from<F>(&self, start: usize, into_calls: bool, mut visit: F)
+    where
+        F: FnMut(usize, Option<u16>),
+    {
+        let mut visited = vec![false; 0x10000];
+        let mut pending = vec![start];
+        while let Some(address) = pending.pop() {
+            if address >= visited.len() || visited[address] {
+                continue;
+            }
+            visited[address] = true;
+
+            let opcode = self.opcode_at(address);
+            visit(address, opcode);
+            let instruction = match opcode.and_then(Instruction::decode) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+
+            let next = address + instruction.len() as usize;
+            match instruction {
+                Instruction::Jump { nnn } if nnn < PROGRAM_START => (),
+                Instruction::Jump { nnn } => pending.push(nnn as usize),
+                Instruction::Call { nnn } => {
+                    if into_calls && nnn >= PROGRAM_START {
+                        pending.push(nnn as usize);
+                    }
+                    pending.push(next);
+                }
+                Instruction::Return
+                | Instruction::Exit
+                | Instruction::JumpOffset { .. }
+                | Instruction::MachineCall { .. } => (),
+                Instruction::SkipEqImm { .. }
+                | Instruction::SkipNeImm { .. }
+                | Instruction::SkipEqReg { .. }
+                | Instruction::SkipNeReg { .. }
+                | Instruction::SkipKeyPressed { .. }
+                | Instruction::SkipKeyNotPressed { .. } => {
+                    pending.push(next);
+                    pending.push(next + 2);
+                    // XO-CHIP skips over both words of F000 NNNN.
+                    if self.opcode_at(next) == Some(0xF000) {
+                        pending.push(next + 4);
+                    }
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    /// Checks the ROM for problems that would otherwise only show up at runtime.
+    ///
+    /// Code is found with [`Rom::walk`], so data mixed in with the code is not mistaken for
+    /// invalid instructions.
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = BTreeSet::new();
+        let end = self.end();
+
+        if self.data.len() > MAX_CHIP8_SIZE {
+            diagnostics.insert(Diagnostic::new(
+                Severity::Warning,
+                None,
+                format!(
+                    "ROM is {} bytes and only fits in XO-CHIP memory",
+                    self.data.len()
+                ),
+            ));
+        }
+        if !self.data.len().is_multiple_of(2) {
+            diagnostics.insert(Diagnostic::new(
+                Severity::Warning,
+                None,
+                format!(
+                    "ROM has an odd length ({} bytes); the last byte is not a full instruction",
+                    self.data.len()
+                ),
+            ));
+        }
+
+        let mut visited = BTreeSet::new();
+        self.walk(|address, opcode| {
+            visited.insert(address);
+            let opcode = match opcode {
+                Some(opcode) => opcode,
+                None => {
+                    diagnostics.insert(Diagnostic::new(
+                        Severity::Warning,
+                        Some(address as u16),
+                        "execution can run past the end of the ROM".to_string(),
+                    ));
+                    return;
+                }
+            };
+            let instruction = match Instruction::decode(opcode) {
+                Some(instruction) => instruction,
+                None => {
+                    diagnostics.insert(Diagnostic::new(
+                        Severity::Error,
+                        Some(address as u16),
+                        format!("invalid opcode {:04X} is reachable", opcode),
+                    ));
+                    return;
+                }
+            };
+
+            if instruction.variant() > Variant::Chip8 {
+                diagnostics.insert(Diagnostic::new(
+                    Severity::Warning,
+                    Some(address as u16),
+                    format!("{:04X} requires {}", opcode, instruction.variant()),
+                ));
+            }
+            match instruction {
+                Instruction::MachineCall { nnn } if print::is_print(nnn) => {
+                    diagnostics.insert(Diagnostic::new(
+                        Severity::Warning,
+                        Some(address as u16),
+                        format!("{:04X} is a debug print, which needs --debug-print", opcode),
+                    ));
+                }
+                Instruction::MachineCall { nnn } => {
+                    diagnostics.insert(Diagnostic::new(
+                        Severity::Error,
+                        Some(address as u16),
+                        format!("machine code call to {:#05X} is not supported", nnn),
+                    ));
+                }
+                Instruction::LoadLongIndex if address + 4 > end => {
+                    diagnostics.insert(Diagnostic::new(
+                        Severity::Error,
+                        Some(address as u16),
+                        "F000 is truncated; the ROM ends before its 16-bit address".to_string(),
+                    ));
+                }
+                Instruction::Jump { nnn }
+                | Instruction::Call { nnn }
+                | Instruction::JumpOffset { nnn } => {
+                    if nnn < PROGRAM_START {
+                        diagnostics.insert(Diagnostic::new(
+                            Severity::Error,
+                            Some(address as u16),
+                            format!("jump into reserved memory at {:#05X}", nnn),
+                        ));
+                    } else if nnn as usize >= end {
+                        diagnostics.insert(Diagnostic::new(
+                            Severity::Warning,
+                            Some(address as u16),
+                            format!("jump past the end of the ROM to {:#05X}", nnn),
+                        ));
+                    }
+                }
+                _ => (),
+            }
+        });
+
+        // The final word can still be a truncated F000 even if no path reaches it.
+        let last = end - self.data.len() % 2 - 2;
+        if last >= PROGRAM_START as usize
+            && !visited.contains(&last)
+            && self.opcode_at(last) == Some(0xF000)
+        {
+            diagnostics.insert(Diagnostic::new(
+                Severity::Error,
+                Some(last as u16),
+                "F000 is truncated; the ROM ends before its 16-bit address".to_string(),
+            ));
+        }
+
+        diagnostics.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(data: &[u8]) -> Vec<String> {
+        Rom::from_bytes(data.to_vec())
+            .unwrap()
+            .check()
+            .iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn from_bytes() {
+        assert!(matches!(Rom::from_bytes(vec![]), Err(RomError::Empty)));
+        assert!(matches!(
+            Rom::from_bytes(vec![0; MAX_SIZE + 1]),
+            Err(RomError::TooLarge(_, _))
+        ));
+    }
+
+    #[test]
+    fn clean_rom() {
+        // 0x200: CLS; 0x202: JP 0x202
+        assert!(check(&[0x00, 0xE0, 0x12, 0x02]).is_empty());
+    }
+
+    #[test]
+    fn odd_length_and_truncated_long_instruction() {
+        let diagnostics = check(&[0x12, 0x00, 0xF0, 0x00, 0xAA]);
+        assert_eq!(
+            diagnostics,
+            vec![
+                "warning: ROM has an odd length (5 bytes); the last byte is not a full instruction",
+                "error: 0x202: F000 is truncated; the ROM ends before its 16-bit address",
+            ]
+        );
+    }
+
+    #[test]
+    fn reachable_problems() {
+        // 0x200: SE V0, 0; 0x202: JP 0x100; 0x204: invalid 5121
+        let diagnostics = check(&[0x30, 0x00, 0x11, 0x00, 0x51, 0x21]);
+        assert_eq!(
+            diagnostics,
+            vec![
+                "error: 0x202: jump into reserved memory at 0x100",
+                "error: 0x204: invalid opcode 5121 is reachable",
+            ]
+        );
+    }
+
+    #[test]
+    fn data_is_not_code() {
+        // 0x200: JP 0x200; 0x202: data that would be an invalid opcode.
+        assert!(check(&[0x12, 0x00, 0xFF, 0xFF]).is_empty());
+    }
+}
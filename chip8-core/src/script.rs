@@ -0,0 +1,366 @@
+//! Scripts that drive a ROM without a display, for automated tests, cheats and input replays,
+//! written in [Rhai](https://rhai.rs) with functions to run and inspect the machine:
+//!
+//! ```text
+//! frames(60);                          // run for one second
+//! press(5);                            // hold a key for a frame
+//! frames(1);
+//! release(5);
+//! run_until(|| reg("V3") == 2);        // run until a condition holds
+//! hook(0x2A4, || set_reg("V7", 3));    // freeze a register whenever 0x2A4 is reached
+//! assert(peek(0x300) != 0 && reg("I") == label("sprite"));
+//! print(`V1 = ${reg("V1")}`);
+//! ```
+
+use crate::debugger::Register;
+use crate::inspector;
+use crate::symbols::Symbols;
+use crate::{CHIP8, STEPS_PER_FRAME};
+use rhai::{Array, Engine, EvalAltResult, FnPtr, NativeCallContext, ParseError, AST, INT};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::mem;
+use std::rc::Rc;
+
+/// Instructions `run_until` executes before giving up, an hour of emulated time.
+const UNTIL_LIMIT: u64 = STEPS_PER_FRAME * 60 * 60 * 60;
+
+/// What the functions of a script return when they fail.
+type Failure = Box<EvalAltResult>;
+
+/// A parsed script.
+#[derive(Debug)]
+pub struct Script {
+    ast: AST,
+    symbols: Symbols,
+}
+
+/// The machine as the functions of a running script share it.
+struct Machine {
+    chip: CHIP8,
+    /// Functions to call when the program counter reaches an address.
+    hooks: Vec<(u16, FnPtr)>,
+    /// Instructions executed, for updating the timers.
+    steps: u64,
+    /// Whether a hook is running, which must not execute instructions or add hooks.
+    in_hook: bool,
+}
+
+type Shared = Rc<RefCell<Machine>>;
+
+impl Machine {
+    /// Fails if a hook is running, for the functions named `name` that hooks must not call.
+    fn check_not_in_hook(&self, name: &str) -> Result<(), Failure> {
+        match self.in_hook {
+            true => Err(format!("`{}` cannot be used in a hook", name).into()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Executes one instruction, calling any hooks at the program counter first.
+fn step(context: &NativeCallContext, machine: &Shared) -> Result<(), Failure> {
+    let hooks: Vec<FnPtr> = {
+        let machine = machine.borrow();
+        let pc = machine.chip.program_counter;
+        machine
+            .hooks
+            .iter()
+            .filter(|(address, _)| *address == pc)
+            .map(|(_, hook)| hook.clone())
+            .collect()
+    };
+    for hook in hooks {
+        machine.borrow_mut().in_hook = true;
+        let result = hook.call_within_context::<()>(context, ());
+        machine.borrow_mut().in_hook = false;
+        result?;
+    }
+    let mut machine = machine.borrow_mut();
+    machine.chip.step().map_err(|err| err.to_string())?;
+    machine.steps += 1;
+    if machine.steps.is_multiple_of(STEPS_PER_FRAME) {
+        machine.chip.tick();
+    }
+    Ok(())
+}
+
+/// Converts a number of a script to what a function takes, such as an address or a key.
+fn convert<T: TryFrom<INT>>(value: INT, what: &str) -> Result<T, Failure> {
+    T::try_from(value).map_err(|_| format!("invalid {} `{}`", what, value).into())
+}
+
+fn key(value: INT) -> Result<u8, Failure> {
+    match convert(value, "key")? {
+        key @ 0..=0xF => Ok(key),
+        _ => Err(format!("invalid key `{}`", value).into()),
+    }
+}
+
+fn register(name: &str) -> Result<Register, Failure> {
+    name.parse().map_err(Into::into)
+}
+
+/// Returns an engine whose functions run `machine`, and which prints to `out`.
+fn engine(machine: &Shared, symbols: Symbols, out: impl Write + 'static) -> Engine {
+    let mut engine = Engine::new();
+    let out = Rc::new(RefCell::new(out));
+    let print = Rc::clone(&out);
+    engine.on_print(move |text| {
+        let _ = writeln!(print.borrow_mut(), "{}", text);
+    });
+
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "steps",
+        move |context: NativeCallContext, count: INT| -> Result<(), Failure> {
+            shared.borrow().check_not_in_hook("steps")?;
+            for _ in 0..convert::<u64>(count, "count")? {
+                step(&context, &shared)?;
+            }
+            Ok(())
+        },
+    );
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "frames",
+        move |context: NativeCallContext, count: INT| -> Result<(), Failure> {
+            shared.borrow().check_not_in_hook("frames")?;
+            for _ in 0..convert::<u64>(count, "count")? * STEPS_PER_FRAME {
+                step(&context, &shared)?;
+            }
+            Ok(())
+        },
+    );
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "run_until",
+        move |context: NativeCallContext, condition: FnPtr| -> Result<(), Failure> {
+            shared.borrow().check_not_in_hook("run_until")?;
+            let mut steps = 0;
+            while !condition.call_within_context::<bool>(&context, ())? {
+                if steps == UNTIL_LIMIT {
+                    return Err("timed out waiting for the condition".into());
+                }
+                step(&context, &shared)?;
+                steps += 1;
+            }
+            Ok(())
+        },
+    );
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "hook",
+        move |address: INT, hook: FnPtr| -> Result<(), Failure> {
+            let mut machine = shared.borrow_mut();
+            machine.check_not_in_hook("hook")?;
+            machine.hooks.push((convert(address, "address")?, hook));
+            Ok(())
+        },
+    );
+
+    for (name, pressed) in [("press", true), ("release", false)] {
+        let shared = Rc::clone(machine);
+        engine.register_fn(name, move |value: INT| -> Result<(), Failure> {
+            shared.borrow_mut().chip.keyboard.set(key(value)?, pressed);
+            Ok(())
+        });
+    }
+    let shared = Rc::clone(machine);
+    engine.register_fn("reg", move |name: &str| -> Result<INT, Failure> {
+        Ok(INT::from(register(name)?.read(&shared.borrow().chip)))
+    });
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "set_reg",
+        move |name: &str, value: INT| -> Result<(), Failure> {
+            let register = register(name)?;
+            register.write(&mut shared.borrow_mut().chip, convert(value, "value")?);
+            Ok(())
+        },
+    );
+    let shared = Rc::clone(machine);
+    engine.register_fn("peek", move |address: INT| -> Result<INT, Failure> {
+        let address: usize = convert(address, "address")?;
+        match shared.borrow().chip.memory.get(address) {
+            Some(&byte) => Ok(INT::from(byte)),
+            None => Err(format!("{:#05X} is out of bounds", address).into()),
+        }
+    });
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "poke",
+        move |address: INT, bytes: Array| -> Result<(), Failure> {
+            let bytes = bytes
+                .into_iter()
+                .map(|byte| convert(byte.as_int()?, "byte"))
+                .collect::<Result<Vec<u8>, _>>()?;
+            let address = convert(address, "address")?;
+            Ok(inspector::poke(
+                &mut shared.borrow_mut().chip,
+                address,
+                &bytes,
+            )?)
+        },
+    );
+    let shared = Rc::clone(machine);
+    engine.register_fn(
+        "poke",
+        move |address: INT, byte: INT| -> Result<(), Failure> {
+            let (address, byte) = (convert(address, "address")?, convert(byte, "byte")?);
+            Ok(inspector::poke(
+                &mut shared.borrow_mut().chip,
+                address,
+                &[byte],
+            )?)
+        },
+    );
+    engine.register_fn("label", move |name: &str| -> Result<INT, Failure> {
+        match symbols.address(name) {
+            Some(address) => Ok(INT::from(address)),
+            None => Err(format!("no label `{}`", name).into()),
+        }
+    });
+
+    engine.register_fn("assert", |condition: bool| -> Result<(), Failure> {
+        match condition {
+            true => Ok(()),
+            false => Err("assertion failed".into()),
+        }
+    });
+    engine.register_fn(
+        "assert",
+        |condition: bool, message: &str| -> Result<(), Failure> {
+            match condition {
+                true => Ok(()),
+                false => Err(format!("assertion failed: {}", message).into()),
+            }
+        },
+    );
+    let shared = Rc::clone(machine);
+    engine.register_fn("screen", move || -> Result<(), Failure> {
+        let text = shared.borrow().chip.display.text();
+        write!(out.borrow_mut(), "{}", text).map_err(|err| err.to_string().into())
+    });
+    engine
+}
+
+/// Returns the message of a failure, saying on which line it happened.
+fn located(err: &EvalAltResult) -> String {
+    // Failures inside functions of the script are wrapped in the calls that led to them.
+    let inner = err.unwrap_inner();
+    let message = match inner {
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        inner => {
+            let text = inner.to_string();
+            let position = format!(" ({})", inner.position());
+            text.strip_suffix(&position).unwrap_or(&text).to_string()
+        }
+    };
+    match inner.position().line().or_else(|| err.position().line()) {
+        Some(line) => format!("line {}: {}", line, message),
+        None => message,
+    }
+}
+
+impl Script {
+    /// Parses a script, whose `label` function looks labels up in `symbols`.
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        let ast =
+            Engine::new()
+                .compile(text)
+                .map_err(|ParseError(err, position)| match position.line() {
+                    Some(line) => format!("line {}: {}", line, err),
+                    None => err.to_string(),
+                })?;
+        Ok(Script {
+            ast,
+            symbols: symbols.clone(),
+        })
+    }
+
+    /// Runs the script against `chip`, writing what it prints and the screens it shows to `out`.
+    pub fn run(&self, chip: &mut CHIP8, out: impl Write + 'static) -> Result<(), String> {
+        let machine = Rc::new(RefCell::new(Machine {
+            chip: mem::take(chip),
+            hooks: Vec::new(),
+            steps: 0,
+            in_hook: false,
+        }));
+        let result = engine(&machine, self.symbols.clone(), out)
+            .run_ast(&self.ast)
+            .map_err(|err| located(&err));
+        // The engine, and the functions sharing the machine with it, are gone.
+        let machine = match Rc::try_unwrap(machine) {
+            Ok(machine) => machine.into_inner(),
+            Err(_) => unreachable!("the engine was dropped"),
+        };
+        *chip = machine.chip;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    /// Output that the test can read once the script is done with it.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn scripts() {
+        // 0x200: ADD V0, 1; 0x202: JP 0x200
+        let rom = Rom::from_bytes(vec![0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        let mut symbols = Symbols::default();
+        symbols.insert("loop", 0x202);
+
+        let script = Script::parse(
+            "steps(4); // two loops\n\
+             assert(reg(\"V0\") == 2);\n\
+             hook(label(\"loop\"), || set_reg(\"V1\", 7));\n\
+             run_until(|| reg(\"V0\") == 5);\n\
+             print(`V1 = ${reg(\"V1\")}`);\n\
+             assert(reg(\"V0\") == 6, \"V0 is 6\");",
+            &symbols,
+        )
+        .unwrap();
+        let out = Output::default();
+        assert_eq!(
+            script.run(&mut chip, out.clone()),
+            Err("line 6: assertion failed: V0 is 6".to_string())
+        );
+        assert_eq!(String::from_utf8(out.0.take()).unwrap(), "V1 = 7\n");
+        // The machine is given back as the script left it.
+        assert_eq!(chip.variable()[0], 5);
+        assert_eq!(chip.variable()[1], 7);
+
+        let script = Script::parse("steps(1);\nhook(0x200, || frames(1));\nsteps(2);", &symbols);
+        assert_eq!(
+            script.unwrap().run(&mut chip, Output::default()),
+            Err("line 2: `frames` cannot be used in a hook".to_string())
+        );
+        let script = Script::parse("press(16);", &symbols).unwrap();
+        assert_eq!(
+            script.run(&mut chip, Output::default()),
+            Err("line 1: invalid key `16`".to_string())
+        );
+        assert!(Script::parse("steps(", &symbols)
+            .unwrap_err()
+            .starts_with("line 1: "));
+    }
+}
@@ -0,0 +1,204 @@
+//! Sprite extraction, for documenting the graphics of a ROM or editing them as source.
+
+use crate::disasm;
+use crate::error::ExecError;
+use crate::instruction::Instruction;
+use crate::png;
+use crate::rom::{Rom, PROGRAM_START};
+use crate::CHIP8;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+/// Sprites per row of a sprite sheet.
+const COLUMNS: usize = 8;
+
+/// Size in pixels of one sprite pixel in a sprite sheet.
+const SCALE: usize = 4;
+
+/// Sprite pixels between sprites in a sprite sheet.
+const GAP: usize = 2;
+
+/// The bytes of a sprite, one row of pixels per byte, or per two bytes for a 16x16 sprite.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sprite {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Sprite {
+    /// Returns the width in pixels, which is 16 for the 32-byte sprites drawn by `DXY0`.
+    pub fn width(&self) -> usize {
+        if self.bytes.len() == 32 {
+            16
+        } else {
+            8
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.bytes.len() * 8 / self.width()
+    }
+
+    /// Returns whether the pixel at (`x`, `y`) is set.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let byte = self.bytes[y * self.width() / 8 + x / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+/// Collects the addresses and lengths of sprites, both from the code of a ROM and from the
+/// instructions drawn while it runs, which also finds sprites whose address is computed.
+#[derive(Default)]
+pub struct Finder {
+    found: BTreeMap<u16, usize>,
+}
+
+impl Finder {
+    /// Adds a sprite, keeping the longest one seen at each address. Addresses outside the program,
+    /// such as the font, are ignored.
+    pub fn add(&mut self, address: u16, len: usize) {
+        if address >= PROGRAM_START {
+            let longest = self.found.entry(address).or_insert(len);
+            *longest = (*longest).max(len);
+        }
+    }
+
+    /// Adds the sprites that `rom` draws from fixed addresses.
+    pub fn scan(&mut self, rom: &Rom) {
+        for (address, len) in disasm::sprites(rom) {
+            self.add(address, len);
+        }
+    }
+
+    /// Executes one instruction with `step`, adding the sprite it draws, if any.
+    pub fn step<F>(&mut self, chip: &mut CHIP8, step: F) -> Result<(), ExecError>
+    where
+        F: FnOnce(&mut CHIP8) -> Result<(), ExecError>,
+    {
+        let instruction = chip
+            .opcode_at(chip.program_counter)
+            .and_then(Instruction::decode);
+        if let Some(Instruction::Draw { n, .. }) = instruction {
+            self.add(chip.index, if n == 0 { 32 } else { n as usize });
+        }
+        step(chip)
+    }
+
+    /// Returns the sprites found, with their bytes taken from `memory`.
+    pub fn sprites(&self, memory: &[u8]) -> Vec<Sprite> {
+        self.found
+            .iter()
+            .filter_map(|(&address, &len)| {
+                let bytes = memory.get(address as usize..address as usize + len)?;
+                Some(Sprite {
+                    address,
+                    bytes: bytes.to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Formats sprites as source for `chip8 asm` or Octo, with a label and one line of binary
+/// literals per row.
+pub fn source(sprites: &[Sprite]) -> String {
+    let mut out = String::new();
+    for sprite in sprites {
+        let _ = writeln!(
+            out,
+            ": sprite_{:03X}  # {}x{} at {:#05X}",
+            sprite.address,
+            sprite.width(),
+            sprite.height(),
+            sprite.address
+        );
+        for row in sprite.bytes.chunks(sprite.width() / 8) {
+            let literals: Vec<String> = row.iter().map(|byte| format!("0b{:08b}", byte)).collect();
+            let _ = writeln!(out, "  {}", literals.join(" "));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders sprites as a PNG sprite sheet, in rows of up to 8 sprites in order of address.
+pub fn sheet(sprites: &[Sprite]) -> Vec<u8> {
+    let cell = 16 + GAP;
+    let columns = sprites.len().clamp(1, COLUMNS);
+    let heights: Vec<usize> = sprites
+        .chunks(COLUMNS)
+        .map(|row| row.iter().map(Sprite::height).max().unwrap_or(0) + GAP)
+        .collect();
+    let width = (GAP + columns * cell) * SCALE;
+    let height = (GAP + heights.iter().sum::<usize>()) * SCALE;
+    // Tint the background so that the unset pixels of each sprite stand out.
+    let mut rgb: Vec<u8> = [0, 0, 24].repeat(width * height);
+
+    let mut top = GAP;
+    for (row, height) in sprites.chunks(COLUMNS).zip(heights) {
+        for (column, sprite) in row.iter().enumerate() {
+            let left = GAP + column * cell;
+            for y in 0..sprite.height() {
+                for x in 0..sprite.width() {
+                    let color = if sprite.pixel(x, y) { 255 } else { 0 };
+                    for py in (top + y) * SCALE..(top + y + 1) * SCALE {
+                        for px in (left + x) * SCALE..(left + x + 1) * SCALE {
+                            let pixel = (py * width + px) * 3;
+                            rgb[pixel..pixel + 3].fill(color);
+                        }
+                    }
+                }
+            }
+        }
+        top += height;
+    }
+    png::encode(width, height, &rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finding() {
+        // 0x200: LD I, 0x20A; 0x202: DRW V0, V0, 2; 0x204: ADD I, V0; 0x206: DRW V0, V0, 1;
+        // 0x208: JP 0x208; 0x20A: sprite
+        let rom = Rom::from_bytes(vec![
+            0xA2, 0x0A, 0xD0, 0x02, 0xF0, 0x1E, 0xD0, 0x01, 0x12, 0x08, 0xF0, 0x90, 0x60,
+        ])
+        .unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        chip.variable[0] = 2;
+
+        let mut finder = Finder::default();
+        finder.scan(&rom);
+        assert_eq!(finder.found.len(), 1);
+        for _ in 0..4 {
+            finder.step(&mut chip, CHIP8::step).unwrap();
+        }
+        let sprites = finder.sprites(&chip.memory);
+        assert_eq!(
+            sprites,
+            [
+                Sprite {
+                    address: 0x20A,
+                    bytes: vec![0xF0, 0x90],
+                },
+                Sprite {
+                    address: 0x20C,
+                    bytes: vec![0x60],
+                },
+            ]
+        );
+        assert!(sprites[0].pixel(0, 1) && !sprites[0].pixel(1, 1));
+        assert_eq!(
+            source(&sprites[..1]),
+            ": sprite_20A  # 8x2 at 0x20A\n  0b11110000\n  0b10010000\n\n"
+        );
+        assert!(sheet(&sprites).starts_with(b"\x89PNG"));
+    }
+}
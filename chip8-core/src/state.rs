@@ -0,0 +1,1406 @@
+//! Save states: snapshots of the whole machine, kept in numbered slots for each ROM.
+//!
+//! A save state is a small binary file starting with a magic number and a format version,
+//! followed by a hash of the ROM it was saved from and then the machine itself: a [`State`]
+//! encoded as CBOR through serde. CBOR describes itself, so `chip8 state inspect` and other tools
+//! can read a state without knowing the layout of the version that wrote it.
+//!
+//! Whenever the layout changes, the version goes up and a migration is added that upgrades the
+//! previous layout, so states written by older releases keep loading. Versions 1 and 2 wrote the
+//! fields by hand, one after the other and then in tagged sections.
+//!
+//! Besides the numbered slots, each ROM has an auto-save slot written by `chip8 run`, and a
+//! settings file controlling it:
+//!
+//! ```text
+//! autosave = yes           # or no
+//! resume = ask             # or always, never
+//! battery = 0x300-0x33F    # memory kept between runs, like a cartridge's battery-backed RAM
+//! ```
+
+use crate::callstack::Frame;
+use crate::command;
+use crate::display::Display;
+#[cfg(feature = "std")]
+use crate::host::FileFlags;
+use crate::inspector::Inspector;
+use crate::keyboard::Keyboard;
+#[cfg(feature = "std")]
+use crate::memory;
+use crate::rng::Rng;
+#[cfg(feature = "std")]
+use crate::rom::Rom;
+use crate::timer::Timer;
+use crate::{CHIP8, STACK_DEPTH};
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
+use core::ops::Range;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+use tinyvec::TinyVec;
+
+/// Identifies save state files.
+const MAGIC: &[u8; 4] = b"C8SS";
+
+/// Version of the format, increased whenever the layout changes.
+const VERSION: u16 = 3;
+
+/// Upgrades the body of a state, everything after the hash of the ROM, to the next version.
+type Migration = fn(&[u8]) -> Result<Vec<u8>, String>;
+
+/// Migrations from each version to the next, starting from version 1. States are upgraded one
+/// version at a time.
+const MIGRATIONS: [Migration; VERSION as usize - 1] = [v1_to_v2, v2_to_v3];
+
+/// Number of save slots per ROM.
+pub const SLOTS: u8 = 10;
+
+/// Hashes ROM data with 64-bit FNV-1a, to check that a state is loaded into the same ROM.
+pub fn rom_hash(data: &[u8]) -> u64 {
+    emu_state::hash(data)
+}
+
+/// Hashes the whole state of `chip`, to check that two runs of a ROM are still in step.
+pub fn checksum(chip: &CHIP8) -> u64 {
+    rom_hash(&save(&chip.snapshot(), 0))
+}
+
+/// A copy of the whole machine, taken by [`CHIP8::snapshot`] and put back by [`CHIP8::restore`].
+///
+/// Nothing is serialized, so taking and restoring one is cheap enough to do every frame, for
+/// run-ahead, rewinding or undo. Save states are written from and read into a `State`.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    #[serde(with = "crate::arrays")]
+    pub(crate) memory: [u8; 4096],
+    pub(crate) display: Display,
+    pub(crate) stack: TinyVec<[u16; 16]>,
+    pub(crate) variable: [u8; 16],
+    pub(crate) index: u16,
+    pub(crate) timer: Timer,
+    pub(crate) keyboard: Keyboard,
+    pub(crate) rng: Rng,
+    pub(crate) program_counter: u16,
+    pub(crate) calls: Vec<Frame>,
+}
+
+impl State {
+    /// Returns all 4KB of memory.
+    pub fn memory(&self) -> &[u8; 4096] {
+        &self.memory
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Returns the return addresses on the stack, the innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Returns the variable registers `V0..=VF`.
+    pub fn variable(&self) -> &[u8; 16] {
+        &self.variable
+    }
+
+    /// Returns the index register.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Moves the program counter, such as back to an instruction to run it again once restored.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    /// Returns the subroutine calls that have not returned, outermost first.
+    pub fn calls(&self) -> &[Frame] {
+        &self.calls
+    }
+
+    /// Checks for what the machine itself never gets into: more than [`STACK_DEPTH`] calls, a
+    /// return address without the call it returns from, or an odd program counter.
+    fn check(&self) -> Result<(), String> {
+        if self.stack.len() > STACK_DEPTH {
+            return Err(format!(
+                "the stack holds {} addresses, more than {}",
+                self.stack.len(),
+                STACK_DEPTH
+            ));
+        }
+        if self.calls.len() != self.stack.len() {
+            return Err(format!(
+                "the stack and the calls differ in depth, {} and {}",
+                self.stack.len(),
+                self.calls.len()
+            ));
+        }
+        if !self.program_counter.is_multiple_of(2) {
+            return Err(format!(
+                "the program counter {:#05X} is not at an even address",
+                self.program_counter
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Serializes `chip`, a state of the ROM with hash `rom_hash`.
+pub fn save(chip: &State, rom_hash: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(chip.memory.len() + 512);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&rom_hash.to_le_bytes());
+    ciborium::into_writer(chip, &mut out).expect("writing to a vector does not fail");
+    out
+}
+
+/// Returns the size of the largest save state, with every register and return address at its
+/// largest, for hosts that set aside a fixed amount of space for one.
+pub fn max_size() -> usize {
+    let mut chip = CHIP8::new().snapshot();
+    chip.variable = [0xFF; 16];
+    chip.index = 0xFFFF;
+    chip.program_counter = 0xFFE;
+    chip.timer.delay_timer = 0xFF;
+    chip.timer.sound_timer = 0xFF;
+    chip.rng.set_state(u32::MAX);
+    for _ in 0..STACK_DEPTH {
+        chip.stack.push(0xFFF);
+        chip.calls.push(Frame {
+            call: 0xFFF,
+            target: 0xFFF,
+        });
+    }
+    save(&chip, u64::MAX).len()
+}
+
+/// Appends a section of a version 2 state to `out`: its tag, the length of its data, then the
+/// data.
+fn section(out: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Reads values from a save state, failing if it ends too soon.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("save state is truncated".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Fails if anything is left, as the data would be from a different layout.
+    fn finish(&self) -> Result<(), String> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err("save state has trailing data".to_string())
+        }
+    }
+}
+
+/// Upgrades the body of a version 1 state, which held the same values as version 2 one after
+/// the other, to sections.
+fn v1_to_v2(body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader { bytes: body };
+    let mut out = Vec::with_capacity(body.len() + 64);
+    section(&mut out, b"MEM ", reader.take(0x1000)?);
+    section(&mut out, b"REGS", reader.take(20)?);
+    let count = reader.u8()?;
+    let stack = [&[count][..], reader.take(count as usize * 2)?].concat();
+    section(&mut out, b"STAK", &stack);
+    let count = reader.u8()?;
+    let calls = [&[count][..], reader.take(count as usize * 4)?].concat();
+    section(&mut out, b"CALL", &calls);
+    section(&mut out, b"TIME", reader.take(2)?);
+    section(&mut out, b"DISP", reader.take(256)?);
+    section(&mut out, b"RNG ", reader.take(4)?);
+    section(&mut out, b"KEYS", reader.take(2)?);
+    reader.finish()?;
+    Ok(out)
+}
+
+/// Upgrades the body of a version 2 state, in tagged sections, to the CBOR of version 3.
+fn v2_to_v3(body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader { bytes: body };
+    let mut sections = Vec::new();
+    while !reader.bytes.is_empty() {
+        let mut tag = [0; 4];
+        tag.copy_from_slice(reader.take(4)?);
+        let len = reader.u32()? as usize;
+        sections.push((tag, reader.take(len)?));
+    }
+    let section = |tag: &[u8; 4]| {
+        sections
+            .iter()
+            .find(|(found, _)| found == tag)
+            .map(|&(_, data)| Reader { bytes: data })
+            .ok_or_else(|| {
+                format!(
+                    "save state has no `{}` section",
+                    String::from_utf8_lossy(tag).trim()
+                )
+            })
+    };
+
+    let mut chip = CHIP8::new().snapshot();
+    let mut memory = section(b"MEM ")?;
+    let len = chip.memory.len();
+    chip.memory.copy_from_slice(memory.take(len)?);
+    memory.finish()?;
+
+    let mut registers = section(b"REGS")?;
+    chip.variable.copy_from_slice(registers.take(16)?);
+    chip.index = registers.u16()?;
+    chip.program_counter = registers.u16()?;
+    registers.finish()?;
+
+    let mut stack = section(b"STAK")?;
+    for _ in 0..stack.u8()? {
+        chip.stack.push(stack.u16()?);
+    }
+    stack.finish()?;
+
+    let mut calls = section(b"CALL")?;
+    for _ in 0..calls.u8()? {
+        let call = calls.u16()?;
+        let target = calls.u16()?;
+        chip.calls.push(Frame { call, target });
+    }
+    calls.finish()?;
+
+    let mut timers = section(b"TIME")?;
+    chip.timer.delay_timer = timers.u8()?;
+    chip.timer.sound_timer = timers.u8()?;
+    timers.finish()?;
+
+    let mut display = section(b"DISP")?;
+    for row in chip.display.screen.iter_mut() {
+        for pixels in row.chunks_mut(8) {
+            let byte = display.u8()?;
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = byte & (0x80 >> bit) != 0;
+            }
+        }
+    }
+    display.finish()?;
+
+    let mut rng = section(b"RNG ")?;
+    chip.rng.set_state(rng.u32()?);
+    rng.finish()?;
+
+    let mut keyboard = section(b"KEYS")?;
+    let keys = keyboard.u16()?;
+    for key in 0..16 {
+        chip.keyboard.set(key, keys & (1 << key) != 0);
+    }
+    keyboard.finish()?;
+
+    let mut out = Vec::with_capacity(body.len());
+    ciborium::into_writer(&chip, &mut out).expect("writing to a vector does not fail");
+    Ok(out)
+}
+
+/// A save state read from a file, upgraded to the current version.
+struct Decoded {
+    /// The version the state was written with.
+    version: u16,
+    rom_hash: u64,
+    /// The machine, encoded as CBOR.
+    body: Vec<u8>,
+}
+
+impl Decoded {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err("not a save state".to_string());
+        }
+        let version = reader.u16()?;
+        if version == 0 || version > VERSION {
+            return Err(format!(
+                "save state version {} is not supported, only versions up to {}",
+                version, VERSION
+            ));
+        }
+        let rom_hash = reader.u64()?;
+        let mut body = reader.bytes.to_vec();
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            body = migrate(&body)?;
+        }
+        Ok(Decoded {
+            version,
+            rom_hash,
+            body,
+        })
+    }
+
+    /// Decodes the machine the state describes, checking that it could have got there by running
+    /// a program.
+    fn state(&self) -> Result<State, String> {
+        let mut reader = &self.body[..];
+        let state: State = ciborium::from_reader(&mut reader).map_err(|err| match err {
+            ciborium::de::Error::Io(_) => "save state is truncated".to_string(),
+            err => format!("save state is invalid: {}", err),
+        })?;
+        if !reader.is_empty() {
+            return Err("save state has trailing data".to_string());
+        }
+        state.check()?;
+        Ok(state)
+    }
+}
+
+/// Restores `chip` from a save state, which must have been saved from the ROM with hash
+/// `rom_hash`. States written by older versions are upgraded first. `chip` is left untouched if
+/// the state is invalid.
+pub fn restore(chip: &mut CHIP8, bytes: &[u8], rom_hash: u64) -> Result<(), String> {
+    let decoded = Decoded::parse(bytes)?;
+    if decoded.rom_hash != rom_hash {
+        return Err("save state is for a different ROM".to_string());
+    }
+    chip.restore(&decoded.state()?);
+    Ok(())
+}
+
+/// Describes a save state for debugging: its version and sections, then the machine it holds,
+/// with the rows of memory that are all zero left out.
+pub fn inspect(bytes: &[u8]) -> Result<String, String> {
+    let decoded = Decoded::parse(bytes)?;
+    let chip = decoded.state()?;
+    let mut out = String::new();
+    let _ = write!(out, "version: {}", decoded.version);
+    if decoded.version != VERSION {
+        let _ = write!(out, ", upgraded to {}", VERSION);
+    }
+    let _ = writeln!(out, "\nrom hash: {:016x}", decoded.rom_hash);
+    let _ = writeln!(out, "machine: {} bytes of CBOR", decoded.body.len());
+
+    let _ = writeln!(
+        out,
+        "\nPC={:#05X} I={:#05X} DT={} ST={}",
+        chip.program_counter, chip.index, chip.timer.delay_timer, chip.timer.sound_timer
+    );
+    let variables: Vec<String> = (0..16)
+        .map(|x| format!("V{:X}={:02X}", x, chip.variable[x]))
+        .collect();
+    let _ = writeln!(out, "{}", variables.join(" "));
+    let stack: Vec<String> = chip.stack.iter().map(|a| format!("{:#05X}", a)).collect();
+    let list = |items: Vec<String>, separator: &str| {
+        if items.is_empty() {
+            "none".to_string()
+        } else {
+            items.join(separator)
+        }
+    };
+    let _ = writeln!(out, "stack: {}", list(stack, " "));
+    let calls: Vec<String> = chip
+        .calls
+        .iter()
+        .map(|frame| format!("{:#05X} -> {:#05X}", frame.call, frame.target))
+        .collect();
+    let _ = writeln!(out, "calls: {}", list(calls, ", "));
+    let keys: Vec<String> = (0..16)
+        .filter(|&key| chip.keyboard.is_pressed(key))
+        .map(|key| format!("{:X}", key))
+        .collect();
+    let _ = writeln!(out, "keys held: {}", list(keys, " "));
+    let _ = writeln!(out, "random state: {:#010X}", chip.rng.state());
+
+    let _ = writeln!(out, "\ndisplay:\n{}", chip.display.text());
+    let _ = writeln!(out, "memory:");
+    let mut machine = CHIP8::new();
+    machine.restore(&chip);
+    let rows = Inspector::new(&machine).rows(&machine, 0, machine.memory.len() / 16);
+    for row in rows
+        .iter()
+        .filter(|row| row.bytes.iter().any(|&(byte, _)| byte != 0))
+    {
+        let _ = writeln!(out, "{}", row);
+    }
+    Ok(out)
+}
+
+/// Lists what differs between two states of the machine, one line per register, stack, range of
+/// memory and so on, as `name: before -> after`.
+pub fn differences(before: &State, after: &State) -> Vec<String> {
+    let mut out = register_differences(before, after);
+    for range in changed_ranges(&before.memory, &after.memory) {
+        let name = if range.len() == 1 {
+            format!("[{:#05X}]", range.start)
+        } else {
+            format!("[{:#05X}-{:#05X}]", range.start, range.end - 1)
+        };
+        if range.len() <= 8 {
+            let bytes = |memory: &[u8]| hex_bytes(&memory[range.clone()]);
+            out.push(format!(
+                "{}: {} -> {}",
+                name,
+                bytes(&before.memory),
+                bytes(&after.memory)
+            ));
+        } else {
+            out.push(format!("{}: {} bytes changed", name, range.len()));
+        }
+    }
+    let pixels = changed_pixels(&before.display, &after.display);
+    if pixels > 0 {
+        out.push(format!(
+            "display: {} {} changed",
+            pixels,
+            if pixels == 1 { "pixel" } else { "pixels" }
+        ));
+    }
+    out
+}
+
+/// Writes bytes as two hexadecimal digits each, separated by spaces.
+fn hex_bytes(bytes: &[u8]) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    bytes.join(" ")
+}
+
+/// Returns the ranges of addresses whose bytes differ, joining changes less than a row of 16
+/// bytes apart.
+fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let changed = before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new);
+    for (address, _) in changed {
+        match ranges.last_mut() {
+            Some(range) if address - range.end < 16 => range.end = address + 1,
+            _ => ranges.push(address..address + 1),
+        }
+    }
+    ranges
+}
+
+/// Counts the pixels that differ between two displays.
+fn changed_pixels(before: &Display, after: &Display) -> usize {
+    before
+        .screen
+        .iter()
+        .flatten()
+        .zip(after.screen.iter().flatten())
+        .filter(|(old, new)| old != new)
+        .count()
+}
+
+/// Lists what differs between two states apart from memory and the display.
+fn register_differences(before: &State, after: &State) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut compare = |name: &str, before: String, after: String| {
+        if before != after {
+            out.push(format!("{}: {} -> {}", name, before, after));
+        }
+    };
+    let address = |address: u16| format!("{:#05X}", address);
+    compare(
+        "PC",
+        address(before.program_counter),
+        address(after.program_counter),
+    );
+    compare("I", address(before.index), address(after.index));
+    for x in 0..16 {
+        compare(
+            &format!("V{:X}", x),
+            format!("{:#04X}", before.variable[x]),
+            format!("{:#04X}", after.variable[x]),
+        );
+    }
+    compare(
+        "DT",
+        before.timer.delay_timer.to_string(),
+        after.timer.delay_timer.to_string(),
+    );
+    compare(
+        "ST",
+        before.timer.sound_timer.to_string(),
+        after.timer.sound_timer.to_string(),
+    );
+    let list = |items: Vec<String>| {
+        if items.is_empty() {
+            "none".to_string()
+        } else {
+            items.join(" ")
+        }
+    };
+    let stack = |state: &State| list(state.stack.iter().copied().map(address).collect());
+    compare("stack", stack(before), stack(after));
+    let calls = |state: &State| {
+        let calls = state.calls.iter();
+        list(
+            calls
+                .map(|frame| format!("{}->{}", address(frame.call), address(frame.target)))
+                .collect(),
+        )
+    };
+    compare("calls", calls(before), calls(after));
+    let keys = |state: &State| {
+        let held = (0..16).filter(|&key| state.keyboard.is_pressed(key));
+        list(held.map(|key| format!("{:X}", key)).collect())
+    };
+    compare("keys held", keys(before), keys(after));
+    compare(
+        "random state",
+        format!("{:#010X}", before.rng.state()),
+        format!("{:#010X}", after.rng.state()),
+    );
+    out
+}
+
+/// Compares two save states, for chasing down why two runs of a ROM behave differently. Lists the
+/// registers that differ, hexdumps each range of memory that differs from both states, and draws
+/// the pixels lit in only one of the displays. Fails with the index of the state that is not
+/// valid, 0 or 1, and why.
+pub fn diff(a: &[u8], b: &[u8]) -> Result<String, (usize, String)> {
+    let decode = |index: usize, bytes: &[u8]| {
+        Decoded::parse(bytes)
+            .and_then(|decoded| Ok((decoded.rom_hash, decoded.state()?)))
+            .map_err(|err| (index, err))
+    };
+    let ((a_hash, a), (b_hash, b)) = (decode(0, a)?, decode(1, b)?);
+    let mut out = String::new();
+    if a_hash != b_hash {
+        let _ = writeln!(
+            out,
+            "rom hash: {:016x} -> {:016x}, the states are of different ROMs\n",
+            a_hash, b_hash
+        );
+    }
+
+    let registers = register_differences(&a, &b);
+    if registers.is_empty() {
+        let _ = writeln!(out, "registers: same");
+    } else {
+        let _ = writeln!(out, "registers:");
+        for line in &registers {
+            let _ = writeln!(out, "  {}", line);
+        }
+    }
+
+    let ranges = changed_ranges(&a.memory, &b.memory);
+    if ranges.is_empty() {
+        let _ = writeln!(out, "memory: same");
+    }
+    for range in ranges {
+        let changed = range
+            .clone()
+            .filter(|&address| a.memory[address] != b.memory[address])
+            .count();
+        let _ = writeln!(
+            out,
+            "memory {:#05X}-{:#05X}: {} {} changed",
+            range.start,
+            range.end - 1,
+            changed,
+            if changed == 1 { "byte" } else { "bytes" }
+        );
+        for row in (range.start & !0xF..range.end).step_by(16) {
+            for (sign, memory) in [("-", &a.memory), ("+", &b.memory)].iter() {
+                let _ = writeln!(
+                    out,
+                    "  {} {:#05X}  {}",
+                    sign,
+                    row,
+                    hex_bytes(&memory[row..row + 16])
+                );
+            }
+        }
+    }
+
+    let pixels = changed_pixels(&a.display, &b.display);
+    if pixels == 0 {
+        let _ = writeln!(out, "display: same");
+    } else {
+        let _ = writeln!(
+            out,
+            "display: {} {} changed, - lit only in the first, + lit only in the second",
+            pixels,
+            if pixels == 1 { "pixel" } else { "pixels" }
+        );
+        for (old, new) in a.display.screen.iter().zip(b.display.screen.iter()) {
+            let row: String = old
+                .iter()
+                .zip(new.iter())
+                .map(|pixels| match pixels {
+                    (true, false) => '-',
+                    (false, true) => '+',
+                    _ => '.',
+                })
+                .collect();
+            let _ = writeln!(out, "  {}", row);
+        }
+    }
+    Ok(out)
+}
+
+/// A save state as JSON, written by [`to_json`] and read back by [`from_json`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Json {
+    version: u16,
+    rom_hash: String,
+    pc: String,
+    i: String,
+    v: [u8; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    stack: Vec<String>,
+    calls: Vec<JsonFrame>,
+    random_state: String,
+    keys_held: Vec<String>,
+    display: Vec<String>,
+    /// Rows of 16 bytes keyed by the address of their first byte.
+    memory: BTreeMap<String, String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JsonFrame {
+    call: String,
+    target: String,
+}
+
+/// Parses an address written as `0x` and hexadecimal digits, which must be at most `max`.
+fn parse_address(text: &str, name: &str, max: u16) -> Result<u16, String> {
+    command::parse_number(text)
+        .ok()
+        .filter(|&address| address <= max)
+        .ok_or_else(|| format!("`{}` must be an address up to {:#X}", name, max))
+}
+
+/// Converts a save state to JSON, for diffing against other emulators, bug reports and editing
+/// by hand. Addresses are written as hexadecimal strings, the display as rows of `#` and `.`, and
+/// memory as rows of 16 bytes keyed by address, leaving out the rows that are all zero.
+pub fn to_json(bytes: &[u8]) -> Result<String, String> {
+    let decoded = Decoded::parse(bytes)?;
+    let state = decoded.state()?;
+    let address = |address: u16| format!("{:#05X}", address);
+    let memory = state
+        .memory
+        .chunks(16)
+        .enumerate()
+        .filter(|(_, row)| row.iter().any(|&byte| byte != 0))
+        .map(|(row, bytes)| (address(row as u16 * 16), hex_bytes(bytes)))
+        .collect();
+    let json = Json {
+        version: VERSION,
+        rom_hash: format!("{:016x}", decoded.rom_hash),
+        pc: address(state.program_counter),
+        i: address(state.index),
+        v: state.variable,
+        delay_timer: state.timer.delay_timer,
+        sound_timer: state.timer.sound_timer,
+        stack: state.stack.iter().copied().map(address).collect(),
+        calls: state
+            .calls
+            .iter()
+            .map(|frame| JsonFrame {
+                call: address(frame.call),
+                target: address(frame.target),
+            })
+            .collect(),
+        random_state: format!("{:#010x}", state.rng.state()),
+        keys_held: (0..16)
+            .filter(|&key| state.keyboard.is_pressed(key))
+            .map(|key| format!("{:X}", key))
+            .collect(),
+        display: state.display.text().lines().map(String::from).collect(),
+        memory,
+    };
+    let mut text = serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?;
+    text.push('\n');
+    Ok(text)
+}
+
+/// Converts JSON written by [`to_json`], perhaps edited since, back to a save state.
+pub fn from_json(text: &str) -> Result<Vec<u8>, String> {
+    let json: Json = serde_json::from_str(text).map_err(|err| err.to_string())?;
+    if json.version != VERSION {
+        return Err(format!(
+            "JSON save state version {} is not supported, only version {}",
+            json.version, VERSION
+        ));
+    }
+    let rom_hash = u64::from_str_radix(&json.rom_hash, 16)
+        .map_err(|_| "`rom_hash` must be a hexadecimal string")?;
+    let random_state = json.random_state.trim_start_matches("0x");
+    let random_state = u32::from_str_radix(random_state, 16)
+        .map_err(|_| "`random_state` must be a hexadecimal string")?;
+
+    let mut state = CHIP8::new().snapshot();
+    state.memory = [0; 4096];
+    state.program_counter = parse_address(&json.pc, "pc", 0xFFF)?;
+    state.index = parse_address(&json.i, "i", 0xFFFF)?;
+    state.variable = json.v;
+    state.timer.delay_timer = json.delay_timer;
+    state.timer.sound_timer = json.sound_timer;
+    if json.stack.len() > STACK_DEPTH {
+        return Err(format!(
+            "`stack` must hold at most {} addresses",
+            STACK_DEPTH
+        ));
+    }
+    for address in &json.stack {
+        state.stack.push(parse_address(address, "stack", 0xFFF)?);
+    }
+    for frame in &json.calls {
+        state.calls.push(Frame {
+            call: parse_address(&frame.call, "call", 0xFFF)?,
+            target: parse_address(&frame.target, "target", 0xFFF)?,
+        });
+    }
+    state.rng.set_state(random_state);
+    for key in &json.keys_held {
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|&key| key <= 0xF)
+            .ok_or("`keys_held` must hold hexadecimal digits")?;
+        state.keyboard.set(key, true);
+    }
+
+    if json.display.len() != state.display.screen.len() {
+        return Err(format!(
+            "`display` must have {} rows",
+            state.display.screen.len()
+        ));
+    }
+    for (y, (row, text)) in state
+        .display
+        .screen
+        .iter_mut()
+        .zip(&json.display)
+        .enumerate()
+    {
+        if text.len() != row.len() || !text.chars().all(|c| c == '#' || c == '.') {
+            return Err(format!(
+                "row {} of `display` must be {} characters of `#` and `.`",
+                y,
+                row.len()
+            ));
+        }
+        for (pixel, c) in row.iter_mut().zip(text.chars()) {
+            *pixel = c == '#';
+        }
+    }
+
+    for (address, row) in &json.memory {
+        let invalid = || format!("invalid row of `memory` at `{}`", address);
+        let start = command::parse_number(address).map_err(|_| invalid())? as usize;
+        let bytes = row
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        let end = start.checked_add(bytes.len()).ok_or_else(invalid)?;
+        let row = state.memory.get_mut(start..end).ok_or_else(invalid)?;
+        row.copy_from_slice(&bytes);
+    }
+    state.check()?;
+    Ok(save(&state, rom_hash))
+}
+
+/// Whether to resume from the auto-save when a ROM is run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    Ask,
+    Always,
+    Never,
+}
+
+/// Per-ROM settings for auto-saving and battery-backed memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
+    /// Whether the state is saved when the program exits, and periodically while it runs.
+    pub autosave: bool,
+    pub resume: Resume,
+    /// The first and last address of memory that is kept between runs, for games that keep high
+    /// scores or progress there.
+    pub battery: Option<(u16, u16)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            autosave: true,
+            resume: Resume::Ask,
+            battery: None,
+        }
+    }
+}
+
+impl FromStr for Settings {
+    type Err = String;
+
+    /// Parses a settings file of `key = value` lines, where `#` starts a comment.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut settings = Settings::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || format!("line {}: invalid setting `{}`", number + 1, line);
+            let (key, value) = line.split_once('=').ok_or_else(error)?;
+            match (key.trim(), value.trim()) {
+                ("autosave", "yes") => settings.autosave = true,
+                ("autosave", "no") => settings.autosave = false,
+                ("resume", "ask") => settings.resume = Resume::Ask,
+                ("resume", "always") => settings.resume = Resume::Always,
+                ("resume", "never") => settings.resume = Resume::Never,
+                ("battery", "none") => settings.battery = None,
+                ("battery", range) => {
+                    let (start, end) = range.split_once('-').ok_or_else(error)?;
+                    let start = command::parse_number(start.trim()).map_err(|_| error())?;
+                    let end = command::parse_number(end.trim()).map_err(|_| error())?;
+                    if start > end || end > 0xFFF {
+                        return Err(error());
+                    }
+                    settings.battery = Some((start, end));
+                }
+                _ => return Err(error()),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resume = match self.resume {
+            Resume::Ask => "ask",
+            Resume::Always => "always",
+            Resume::Never => "never",
+        };
+        writeln!(f, "autosave = {}", if self.autosave { "yes" } else { "no" })?;
+        writeln!(f, "resume = {}", resume)?;
+        match self.battery {
+            Some((start, end)) => writeln!(f, "battery = {:#05X}-{:#05X}", start, end),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the directory that holds the save states of every ROM.
+#[cfg(feature = "std")]
+fn states_dir() -> PathBuf {
+    if let Some(data) = env::var_os("XDG_DATA_HOME") {
+        return Path::new(&data).join("chip8").join("states");
+    }
+    match env::var_os("HOME") {
+        Some(home) => Path::new(&home).join(".local/share/chip8/states"),
+        None => PathBuf::from("chip8-states"),
+    }
+}
+
+/// The save slots of one ROM, with one of them selected.
+#[cfg(feature = "std")]
+pub struct Slots {
+    /// Directory holding the slots, named after the ROM and its hash.
+    dir: PathBuf,
+    rom_hash: u64,
+    /// The slot used when none is given.
+    pub selected: u8,
+}
+
+#[cfg(feature = "std")]
+impl Slots {
+    /// Returns the slots of the ROM at `path`.
+    pub fn new(path: &str, rom: &Rom) -> Self {
+        let rom_hash = rom_hash(rom.data());
+        let stem = Path::new(path).file_stem().unwrap_or_default();
+        Slots {
+            dir: states_dir().join(format!("{}-{:016x}", stem.to_string_lossy(), rom_hash)),
+            rom_hash,
+            selected: 0,
+        }
+    }
+
+    fn path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("slot{}.state", slot))
+    }
+
+    fn auto_path(&self) -> PathBuf {
+        self.dir.join("auto.state")
+    }
+
+    fn settings_path(&self) -> PathBuf {
+        self.dir.join("settings")
+    }
+
+    fn battery_path(&self) -> PathBuf {
+        self.dir.join("battery")
+    }
+
+    /// Returns storage for the persistent flags of `FX75` in the directory of the slots, so that
+    /// they are kept between runs of the ROM.
+    pub fn flags(&self) -> FileFlags {
+        FileFlags::new(self.dir.join("flags"))
+    }
+
+    /// Writes `contents` to `path` in the directory of the slots, creating it if needed.
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(path, contents))
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Restores `chip` from the state at `path`. `missing` is the error if there is none.
+    fn read(&self, chip: &mut CHIP8, path: &Path, missing: String) -> Result<(), String> {
+        let bytes = fs::read(path).map_err(|_| missing)?;
+        restore(chip, &bytes, self.rom_hash).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Saves `chip` to `slot`, or to the selected slot, and selects it.
+    pub fn save(&mut self, chip: &CHIP8, slot: Option<u8>) -> Result<String, String> {
+        let slot = slot.unwrap_or(self.selected);
+        self.write(&self.path(slot), &save(&chip.snapshot(), self.rom_hash))?;
+        self.selected = slot;
+        Ok(format!("saved state to slot {}", slot))
+    }
+
+    /// Restores `chip` from `slot`, or from the selected slot, and selects it.
+    pub fn load(&mut self, chip: &mut CHIP8, slot: Option<u8>) -> Result<String, String> {
+        let slot = slot.unwrap_or(self.selected);
+        self.read(chip, &self.path(slot), format!("slot {} is empty", slot))?;
+        self.selected = slot;
+        Ok(format!("loaded state from slot {}", slot))
+    }
+
+    /// Saves `chip` to the auto-save slot.
+    pub fn save_auto(&self, chip: &CHIP8) -> Result<(), String> {
+        self.write(&self.auto_path(), &save(&chip.snapshot(), self.rom_hash))
+    }
+
+    /// Returns `true` if there is an auto-save to resume from.
+    pub fn has_auto(&self) -> bool {
+        self.auto_path().exists()
+    }
+
+    /// Restores `chip` from the auto-save slot.
+    pub fn load_auto(&self, chip: &mut CHIP8) -> Result<(), String> {
+        self.read(chip, &self.auto_path(), "there is no auto-save".to_string())
+    }
+
+    /// Reads the settings of the ROM, which are the defaults if they were never saved.
+    pub fn settings(&self) -> Result<Settings, String> {
+        let path = self.settings_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => text
+                .parse()
+                .map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    pub fn save_settings(&self, settings: &Settings) -> Result<(), String> {
+        self.write(&self.settings_path(), settings.to_string().as_bytes())
+    }
+
+    /// Writes the battery-backed memory of `chip`, from `start` to `end`.
+    pub fn save_battery(&self, chip: &CHIP8, (start, end): (u16, u16)) -> Result<(), String> {
+        let bytes = memory::read(&chip.memory, start as usize, battery_len(start, end))
+            .map_err(|_| format!("battery {:#05X}-{:#05X} is out of bounds", start, end))?;
+        self.write(&self.battery_path(), bytes)
+    }
+
+    /// Restores the battery-backed memory of `chip`, from `start` to `end`, if it was saved.
+    /// Returns `false` if there was nothing to restore.
+    pub fn load_battery(&self, chip: &mut CHIP8, (start, end): (u16, u16)) -> Result<bool, String> {
+        let path = self.battery_path();
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        };
+        let len = battery_len(start, end);
+        if bytes.len() != len {
+            return Err(format!(
+                "{}: holds {} bytes, but the battery is {} bytes",
+                path.display(),
+                bytes.len(),
+                len
+            ));
+        }
+        memory::write(&mut chip.memory, start as usize, &bytes)
+            .map_err(|_| format!("battery {:#05X}-{:#05X} is out of bounds", start, end))?;
+        Ok(true)
+    }
+}
+
+/// Returns the bytes of battery-backed memory from `start` to `end`, inclusive.
+#[cfg(feature = "std")]
+fn battery_len(start: u16, end: u16) -> usize {
+    (end as usize + 1).saturating_sub(start as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut chip = CHIP8::new();
+        chip.memory[0x300] = 0xAB;
+        chip.variable[3] = 7;
+        chip.index = 0x123;
+        chip.program_counter = 0x246;
+        chip.stack.push(0x204);
+        chip.calls.push(Frame {
+            call: 0x202,
+            target: 0x240,
+        });
+        chip.timer.delay_timer = 30;
+        chip.display.screen[31][63] = true;
+        chip.keyboard.set(0xA, true);
+        let bytes = save(&chip.snapshot(), 42);
+
+        let mut restored = CHIP8::new();
+        restore(&mut restored, &bytes, 42).unwrap();
+        assert_eq!(restored.memory[..], chip.memory[..]);
+        assert_eq!(restored.variable, chip.variable);
+        assert_eq!((restored.index, restored.program_counter), (0x123, 0x246));
+        assert_eq!(restored.stack[..], [0x204]);
+        assert_eq!(restored.calls[0].target, 0x240);
+        assert_eq!(restored.timer.delay_timer, 30);
+        assert_eq!(restored.display.text(), chip.display.text());
+        assert!(restored.keyboard.is_pressed(0xA) && !restored.keyboard.is_pressed(0xB));
+        assert_eq!(restored.rng.next_u8(), chip.rng.next_u8());
+
+        assert_eq!(
+            restore(&mut restored, &bytes, 43),
+            Err("save state is for a different ROM".to_string())
+        );
+        assert_eq!(
+            restore(&mut restored, &bytes[..100], 42),
+            Err("save state is truncated".to_string())
+        );
+        let mut future = bytes.clone();
+        future[4] = VERSION as u8 + 1;
+        assert!(restore(&mut restored, &future, 42).is_err());
+        assert_eq!(rom_hash(b""), 0xCBF2_9CE4_8422_2325);
+    }
+
+    #[test]
+    fn snapshot() {
+        // 0x200: CALL 0x204; 0x202: JP 0x202; 0x204: ADD V0, 1; 0x206: LD [I], V0
+        let rom = Rom::from_bytes(vec![0x22, 0x04, 0x12, 0x02, 0x70, 0x01, 0xF0, 0x55]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+        chip.index = 0x300;
+        let state = chip.snapshot();
+        for _ in 0..3 {
+            chip.step().unwrap();
+        }
+        assert_eq!((chip.memory[0x300], chip.calls.len()), (1, 1));
+
+        chip.restore(&state);
+        assert_eq!((chip.memory[0x300], chip.variable[0]), (0, 0));
+        assert_eq!((chip.index, chip.program_counter), (0x300, 0x200));
+        assert!(chip.stack.is_empty() && chip.calls.is_empty() && chip.accesses.is_empty());
+    }
+
+    #[test]
+    fn diff() {
+        let mut chip = CHIP8::new();
+        let a = chip.snapshot();
+        chip.variable[1] = 0x20;
+        chip.memory[0x300] = 1;
+        chip.memory[0x30A] = 2;
+        chip.memory[0x800] = 3;
+        chip.display.screen[0][1] = true;
+        chip.stack.push(0x204);
+        chip.calls.push(Frame {
+            call: 0x202,
+            target: 0x300,
+        });
+        let b = chip.snapshot();
+
+        assert_eq!(
+            differences(&a, &b),
+            [
+                "V1: 0x00 -> 0x20",
+                "stack: none -> 0x204",
+                "calls: none -> 0x202->0x300",
+                "[0x300-0x30A]: 11 bytes changed",
+                "[0x800]: 00 -> 03",
+                "display: 1 pixel changed",
+            ]
+        );
+
+        let report = super::diff(&save(&a, 1), &save(&b, 1)).unwrap();
+        assert!(
+            report.starts_with("registers:\n  V1: 0x00 -> 0x20\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains("memory 0x300-0x30A: 2 bytes changed\n  - 0x300  00 00"));
+        assert!(report.contains("\n  + 0x800  03 00 00"));
+        assert!(report.contains("\n  .+......"));
+        assert_eq!(
+            super::diff(&save(&a, 1), b"C8"),
+            Err((1, "not a save state".to_string()))
+        );
+    }
+
+    #[test]
+    fn json() {
+        let mut chip = CHIP8::new();
+        chip.variable[2] = 200;
+        chip.stack.push(0x204);
+        chip.calls.push(Frame {
+            call: 0x202,
+            target: 0x240,
+        });
+        chip.keyboard.set(0xC, true);
+        chip.display.screen[0][1] = true;
+        chip.memory[0xFFF] = 0x7E;
+        let bytes = save(&chip.snapshot(), 42);
+
+        let json = to_json(&bytes).unwrap();
+        assert!(json.contains("  \"pc\": \"0x200\",\n"));
+        assert!(json.contains("  \"v\": [\n    0,\n    0,\n    200,\n"));
+        assert!(json.contains("    \".#......"));
+        assert!(
+            json.contains("    \"0xFF0\": \"00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 7E\"\n")
+        );
+        assert_eq!(from_json(&json), Ok(bytes));
+
+        let edited = from_json(&json.replace("\"0x200\"", "\"0x2A0\"")).unwrap();
+        let mut restored = CHIP8::new();
+        restore(&mut restored, &edited, 42).unwrap();
+        assert_eq!(restored.program_counter, 0x2A0);
+        assert_eq!(
+            from_json(&json.replace("\"0x200\"", "\"0x2000\"")),
+            Err("`pc` must be an address up to 0xFFF".to_string())
+        );
+        assert_eq!(
+            from_json(&json.replace("\"0xFF0\"", "\"0xFFFFFFFFFFFFFFFF\"")),
+            Err("invalid row of `memory` at `0xFFFFFFFFFFFFFFFF`".to_string())
+        );
+        let deep = format!("\"stack\": [{}]", vec!["\"0x204\""; 40].join(", "));
+        let stack = json.find("\"stack\"").unwrap();
+        let end = stack + json[stack..].find(']').unwrap() + 1;
+        assert_eq!(
+            from_json(&[&json[..stack], &deep, &json[end..]].concat()),
+            Err("`stack` must hold at most 16 addresses".to_string())
+        );
+        assert_eq!(
+            from_json(&json.replace("\"0x204\"", "\"0x204\", \"0x206\"")),
+            Err("the stack and the calls differ in depth, 2 and 1".to_string())
+        );
+    }
+
+    /// Writes the sections of `chip` as version 2 did.
+    fn v2_sections(chip: &State) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut registers = chip.variable.to_vec();
+        registers.extend_from_slice(&chip.index.to_le_bytes());
+        registers.extend_from_slice(&chip.program_counter.to_le_bytes());
+        let mut stack = vec![chip.stack.len() as u8];
+        for address in &chip.stack {
+            stack.extend_from_slice(&address.to_le_bytes());
+        }
+        let mut calls = vec![chip.calls.len() as u8];
+        for frame in &chip.calls {
+            calls.extend_from_slice(&frame.call.to_le_bytes());
+            calls.extend_from_slice(&frame.target.to_le_bytes());
+        }
+        let display = chip
+            .display
+            .screen
+            .iter()
+            .flat_map(|row| row.chunks(8))
+            .map(|pixels| pixels.iter().fold(0, |byte, &on| byte << 1 | on as u8))
+            .collect();
+        let keys = (0..16).fold(0u16, |keys, key| {
+            keys | (chip.keyboard.is_pressed(key) as u16) << key
+        });
+        vec![
+            (*b"MEM ", chip.memory.to_vec()),
+            (*b"REGS", registers),
+            (*b"STAK", stack),
+            (*b"CALL", calls),
+            (
+                *b"TIME",
+                vec![chip.timer.delay_timer, chip.timer.sound_timer],
+            ),
+            (*b"DISP", display),
+            (*b"RNG ", chip.rng.state().to_le_bytes().to_vec()),
+            (*b"KEYS", keys.to_le_bytes().to_vec()),
+        ]
+    }
+
+    /// Writes `chip` as `version` 1 or 2 did.
+    fn save_old(chip: &State, version: u16, rom_hash: u64) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&version.to_le_bytes());
+        out.extend_from_slice(&rom_hash.to_le_bytes());
+        for (tag, data) in v2_sections(chip) {
+            match version {
+                1 => out.extend_from_slice(&data),
+                _ => section(&mut out, &tag, &data),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn migration() {
+        let mut chip = CHIP8::new();
+        chip.variable[0xF] = 1;
+        chip.stack.push(0x208);
+        chip.calls.push(Frame {
+            call: 0x206,
+            target: 0x300,
+        });
+        chip.display.screen[31][63] = true;
+        let current = save(&chip.snapshot(), 42);
+
+        for version in [1, 2] {
+            let old = save_old(&chip.snapshot(), version, 42);
+            let mut restored = CHIP8::new();
+            restore(&mut restored, &old, 42).unwrap();
+            assert_eq!(save(&restored.snapshot(), 42), current);
+        }
+
+        let report = inspect(&save_old(&chip.snapshot(), 1, 42)).unwrap();
+        assert!(report.starts_with("version: 1, upgraded to 3\nrom hash: 000000000000002a\n"));
+        assert!(report.contains("VF=01"));
+        assert!(report.contains("stack: 0x208\n"));
+        assert!(report.contains("calls: 0x206 -> 0x300\n"));
+    }
+
+    #[test]
+    fn invalid_states() {
+        // Version 2 counted the stack in a byte, and read back as many addresses as it said.
+        let mut chip = CHIP8::new().snapshot();
+        for _ in 0..40 {
+            chip.stack.push(0x202);
+            chip.calls.push(Frame {
+                call: 0x200,
+                target: 0x200,
+            });
+        }
+        let mut restored = CHIP8::new();
+        let expected = Err("the stack holds 40 addresses, more than 16".to_string());
+        assert_eq!(restore(&mut restored, &save_old(&chip, 2, 0), 0), expected);
+        assert_eq!(restore(&mut restored, &save(&chip, 0), 0), expected);
+        assert!(restored.stack.is_empty());
+
+        chip.calls.clear();
+        chip.stack.truncate(3);
+        assert_eq!(
+            restore(&mut restored, &save(&chip, 0), 0),
+            Err("the stack and the calls differ in depth, 3 and 0".to_string())
+        );
+
+        let mut chip = CHIP8::new().snapshot();
+        chip.program_counter = 0x201;
+        assert_eq!(
+            restore(&mut restored, &save(&chip, 0), 0),
+            Err("the program counter 0x201 is not at an even address".to_string())
+        );
+        let mut trailing = save(&CHIP8::new().snapshot(), 0);
+        trailing.push(0);
+        assert_eq!(
+            restore(&mut restored, &trailing, 0),
+            Err("save state has trailing data".to_string())
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        let mut chip = CHIP8::new();
+        let empty = save(&chip.snapshot(), 0).len();
+        chip.memory = [0xFF; 4096];
+        chip.variable = [0x80; 16];
+        assert_eq!(save(&chip.snapshot(), 0).len(), empty + 16);
+        assert!(max_size() > empty + 16);
+    }
+
+    #[test]
+    fn settings() {
+        let settings: Settings =
+            "autosave = no  # not for this one\n\nresume=always\nbattery = 0x300-0x33F\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                autosave: false,
+                resume: Resume::Always,
+                battery: Some((0x300, 0x33F)),
+            }
+        );
+        assert_eq!(settings.to_string().parse(), Ok(settings));
+        assert_eq!("".parse(), Ok(Settings::default()));
+        assert_eq!(
+            "resume = sometimes".parse::<Settings>(),
+            Err("line 1: invalid setting `resume = sometimes`".to_string())
+        );
+        assert_eq!(
+            "battery = 0x300-0x1000".parse::<Settings>(),
+            Err("line 1: invalid setting `battery = 0x300-0x1000`".to_string())
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! The community test suite for CHIP-8 interpreters, run without a display and scored by reading
+//! the marks its ROMs draw next to each check.
+//!
+//! The ROMs of Timendus' `chip8-test-suite` are not distributed with this crate. Each [`Test`]
+//! knows how to run one of them unattended, picking its platform or sub-test through the byte at
+//! `0x1FF` and pressing keys for the keypad test, and [`Verdict::read`] counts the marks for
+//! passed and failed checks on the screen once it has run:
+//!
+//! ```
+//! use chip8_core::display::Display;
+//! use chip8_core::suite::{Verdict, OK};
+//!
+//! let mut display = Display::new();
+//! display.draw(8, 4, OK);
+//! assert_eq!(Verdict::read(&display), Verdict { passed: 1, failed: 0 });
+//! ```
+
+use crate::display::{Display, HEIGHT, WIDTH};
+use crate::error::ExecError;
+use crate::frontend::{Headless, Input, Runner};
+use crate::keyboard::Keyboard;
+use crate::CHIP8;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// The `ok` the opcode test of corax89 draws next to a check that passed.
+pub const OK: &[u8] = &[0xEA, 0xAC, 0xAA, 0xEA];
+/// The `no` it draws next to a check that failed.
+pub const NO: &[u8] = &[0xCE, 0xAA, 0xAA, 0xAE];
+/// The check mark the later tests of the suite draw for a check that passed.
+pub const CHECK: &[u8] = &[0x01, 0x02, 0x84, 0x48, 0x30];
+/// The cross they draw for a check that failed.
+pub const CROSS: &[u8] = &[0x88, 0x50, 0x20, 0x50, 0x88];
+
+/// Frames each test runs for, which is long enough for every test to finish at the default speed.
+pub const FRAMES: u32 = 180;
+
+/// The address of the byte the suite reads to skip its menus.
+const CHOICE: usize = 0x1FF;
+
+/// A ROM of the suite, and how to run it without anyone at the keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Test {
+    /// Name of the test, which the file name of its ROM contains.
+    pub name: &'static str,
+    /// The platform or sub-test picked from the menu, written to `0x1FF` before it runs.
+    pub choice: Option<u8>,
+    /// Keys pressed and released while it runs, as the frame, the key and whether it is held.
+    pub keys: &'static [(u32, u8, bool)],
+}
+
+/// The tests of the suite that check an original CHIP-8 and report their results on screen.
+pub const TESTS: [Test; 4] = [
+    Test {
+        name: "corax",
+        choice: None,
+        keys: &[],
+    },
+    Test {
+        name: "flags",
+        choice: None,
+        keys: &[],
+    },
+    Test {
+        name: "quirks",
+        choice: Some(1),
+        keys: &[],
+    },
+    // The `FX0A` test, which waits for key 5 to be pressed and released.
+    Test {
+        name: "keypad",
+        choice: Some(3),
+        keys: &[(30, 5, true), (40, 5, false)],
+    },
+];
+
+impl Test {
+    /// Returns the test whose ROM is called `file_name`, if any.
+    pub fn find(file_name: &str) -> Option<Test> {
+        TESTS
+            .iter()
+            .copied()
+            .find(|test| file_name.contains(test.name))
+    }
+
+    /// Runs `chip`, with the ROM of the test loaded, for [`FRAMES`] frames and reads the verdict
+    /// from its screen.
+    pub fn run(&self, mut chip: CHIP8) -> Result<Verdict, ExecError> {
+        if let Some(choice) = self.choice {
+            chip.memory_mut()[CHOICE] = choice;
+        }
+        let script = Script {
+            keys: self.keys,
+            frame: 0,
+        };
+        let mut runner = Runner::new(chip, Headless, Headless, script);
+        for _ in 0..FRAMES {
+            runner.frame()?;
+        }
+        Ok(Verdict::read(runner.chip.display()))
+    }
+}
+
+/// Presses the keys of a test at the frames it says.
+struct Script {
+    keys: &'static [(u32, u8, bool)],
+    frame: u32,
+}
+
+impl Input for Script {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        for &(frame, key, pressed) in self.keys {
+            if frame == self.frame {
+                keyboard.set(key, pressed);
+            }
+        }
+        self.frame += 1;
+        true
+    }
+}
+
+/// The checks a test reported on screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Verdict {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl Verdict {
+    /// Counts the marks for passed and failed checks on `display`.
+    pub fn read(display: &Display) -> Self {
+        let count = |marks: &[&[u8]]| marks.iter().map(|mark| occurrences(display, mark)).sum();
+        Verdict {
+            passed: count(&[OK, CHECK]),
+            failed: count(&[NO, CROSS]),
+        }
+    }
+
+    /// Describes the verdict as `pass`, `fail`, or `unknown` when no marks were found at all.
+    pub fn result(&self) -> &'static str {
+        match (self.passed, self.failed) {
+            (0, 0) => "unknown",
+            (_, 0) => "pass",
+            _ => "fail",
+        }
+    }
+}
+
+/// Counts where `mark`, eight pixels wide, is drawn on `display` with nothing else lit around it
+/// within its width.
+fn occurrences(display: &Display, mark: &[u8]) -> usize {
+    let matches = |x: usize, y: usize| {
+        mark.iter().enumerate().all(|(row, &bits)| {
+            (0..8).all(|bit| display.pixel(x + bit, y + row) == (bits & 0x80 >> bit != 0))
+        })
+    };
+    (0..=HEIGHT - mark.len())
+        .flat_map(|y| (0..=WIDTH - 8).map(move |x| (x, y)))
+        .filter(|&(x, y)| matches(x, y))
+        .count()
+}
+
+/// What became of one test of the suite.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub test: Test,
+    /// Path of the ROM, or `None` if it was not found.
+    pub rom: Option<String>,
+    /// The verdict, or the error the ROM stopped with. `None` if it did not run.
+    pub result: Option<Result<Verdict, ExecError>>,
+}
+
+impl Report {
+    /// Describes the report as the result of its verdict, `error` or `missing`.
+    pub fn result(&self) -> &'static str {
+        match &self.result {
+            Some(Ok(verdict)) => verdict.result(),
+            Some(Err(_)) => "error",
+            None => "missing",
+        }
+    }
+}
+
+/// Returns whether every test that ran passed, and at least one did.
+pub fn passed(reports: &[Report]) -> bool {
+    let mut ran = reports
+        .iter()
+        .filter(|report| report.result.is_some())
+        .peekable();
+    ran.peek().is_some() && ran.all(|report| report.result() == "pass")
+}
+
+/// The JSON document written by [`summary`].
+#[derive(serde::Serialize)]
+struct Summary<'a> {
+    passed: bool,
+    tests: Vec<TestSummary<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct TestSummary<'a> {
+    name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rom: Option<&'a str>,
+    result: &'static str,
+    #[serde(flatten)]
+    verdict: Option<&'a Verdict>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Summarizes `reports` as a JSON document, for scripts and continuous integration.
+pub fn summary(reports: &[Report]) -> String {
+    let tests = reports
+        .iter()
+        .map(|report| TestSummary {
+            name: report.test.name,
+            rom: report.rom.as_deref(),
+            result: report.result(),
+            verdict: report
+                .result
+                .as_ref()
+                .and_then(|result| result.as_ref().ok()),
+            error: match &report.result {
+                Some(Err(err)) => Some(err.to_string()),
+                _ => None,
+            },
+        })
+        .collect();
+    let summary = Summary {
+        passed: passed(reports),
+        tests,
+    };
+    serde_json::to_string_pretty(&summary).expect("the summary is valid JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    /// Returns a machine running `rom`.
+    fn load(rom: &[u8]) -> CHIP8 {
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(rom.to_vec()).unwrap())
+            .unwrap();
+        chip
+    }
+
+    #[test]
+    fn read() {
+        let mut display = Display::new();
+        display.draw(0, 0, OK);
+        display.draw(20, 10, OK);
+        display.draw(56, 27, CHECK);
+        display.draw(30, 20, NO);
+        assert_eq!(
+            Verdict::read(&display),
+            Verdict {
+                passed: 3,
+                failed: 1
+            }
+        );
+        // A mark that something else is drawn next to is not one.
+        display.set_pixel(31, 21, true);
+        assert_eq!(Verdict::read(&display).failed, 0);
+        assert_eq!(Verdict::read(&Display::new()).result(), "unknown");
+    }
+
+    #[test]
+    fn choice() {
+        // 0x200: LD I, 0x1FF; LD V0, [I]; LD I, 0x212; SE V0, 1; LD I, 0x216; DRW V1, V1, 4
+        // 0x20C: JP 0x20C; 0x212: ok; 0x216: no
+        let mut rom = vec![
+            0xA1, 0xFF, 0xF0, 0x65, 0xA2, 0x12, 0x30, 0x01, 0xA2, 0x16, 0xD1, 0x14, 0x12, 0x0C, 0,
+            0, 0, 0,
+        ];
+        rom.extend(OK);
+        rom.extend(NO);
+        let test = Test::find("5-quirks.ch8").unwrap();
+        assert_eq!(test.run(load(&rom)).unwrap().result(), "pass");
+        let test = Test::find("3-corax+.ch8").unwrap();
+        assert_eq!(test.run(load(&rom)).unwrap().result(), "fail");
+        assert_eq!(Test::find("pong.ch8"), None);
+    }
+
+    #[test]
+    fn keys() {
+        // 0x200: LD V0, K; LD I, 0x208; DRW V1, V1, 4; 0x206: JP 0x206; 0x208: ok
+        let mut rom = vec![0xF0, 0x0A, 0xA2, 0x08, 0xD1, 0x14, 0x12, 0x06];
+        rom.extend(OK);
+        let keypad = Test::find("6-keypad.ch8").unwrap();
+        assert_eq!(keypad.run(load(&rom)).unwrap().passed, 1);
+
+        let reports = [
+            Report {
+                test: keypad,
+                rom: Some("6-keypad.ch8".to_string()),
+                result: Some(keypad.run(load(&rom))),
+            },
+            Report {
+                test: TESTS[1],
+                rom: None,
+                result: None,
+            },
+        ];
+        assert!(passed(&reports));
+        let summary: serde_json::Value = serde_json::from_str(&summary(&reports)).unwrap();
+        assert_eq!(summary["passed"], true);
+        assert_eq!(summary["tests"][0]["passed"], 1);
+        assert_eq!(summary["tests"][1]["result"], "missing");
+        assert!(summary["tests"][1].get("rom").is_none());
+    }
+}
@@ -0,0 +1,145 @@
+//! Labels for addresses, loaded from symbol files written by assemblers such as Octo.
+//!
+//! A symbol file has one label per line, as a name and an address in either order, optionally
+//! separated by `=` and preceded by a directive such as `:label`. Addresses are decimal or
+//! `0x`-prefixed hexadecimal, and `#` or `;` start a comment:
+//!
+//! ```text
+//! main_loop = 0x202
+//! 0x2A0 draw_player
+//! :label sprite_data 0x300  # the player sprite
+//! ```
+
+use crate::command::parse_number;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+/// A two-way mapping between addresses and labels.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+    addresses: BTreeMap<String, u16>,
+}
+
+/// Returns `true` if `word` can be used as a label.
+fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.'))
+}
+
+impl Symbols {
+    /// Parses the contents of a symbol file.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut symbols = Symbols::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let words: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|word| !word.is_empty() && !word.starts_with(':'))
+                .collect();
+            let error = || format!("line {}: expected a label and an address", number + 1);
+            let (name, address) = match words[..] {
+                [] => continue,
+                [a, b] if is_name(a) => (a, b),
+                [a, b] if is_name(b) => (b, a),
+                _ => return Err(error()),
+            };
+            let address =
+                parse_number(address).map_err(|err| format!("line {}: {}", number + 1, err))?;
+            symbols.insert(name, address);
+        }
+        Ok(symbols)
+    }
+
+    /// Reads a symbol file.
+    #[cfg(feature = "std")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Symbols::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Adds a label, replacing any other label for the same address.
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if let Some(old) = self.names.insert(address, name.to_string()) {
+            self.addresses.remove(&old);
+        }
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    /// Returns the label for `address`.
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// Returns the address of the label `name`.
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Formats `address` as hexadecimal, followed by its label if it has one.
+    pub fn describe(&self, address: u16) -> String {
+        match self.name(address) {
+            Some(name) => format!("{:#05X} <{}>", address, name),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    /// Parses an address given either as a label or as a number.
+    pub fn parse_address(&self, text: &str) -> Result<u16, String> {
+        match self.address(text) {
+            Some(address) => Ok(address),
+            None if is_name(text) => Err(format!("unknown label `{}`", text)),
+            None => parse_number(text),
+        }
+    }
+}
+
+impl fmt::Display for Symbols {
+    /// Formats the labels as a symbol file, in order of address.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (address, name) in &self.names {
+            writeln!(f, "{} = {:#05X}", name, address)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let symbols =
+            Symbols::parse("main_loop = 0x202\n\n0x2A0 draw\n:label sprite 768 # comment\n")
+                .unwrap();
+        assert_eq!(symbols.address("main_loop"), Some(0x202));
+        assert_eq!(symbols.name(0x2A0), Some("draw"));
+        assert_eq!(symbols.parse_address("sprite"), Ok(0x300));
+        assert_eq!(symbols.parse_address("0x400"), Ok(0x400));
+        assert!(symbols.parse_address("missing").is_err());
+        assert_eq!(symbols.describe(0x202), "0x202 <main_loop>");
+        assert_eq!(
+            symbols.to_string(),
+            "main_loop = 0x202\ndraw = 0x2A0\nsprite = 0x300\n"
+        );
+
+        assert!(Symbols::parse("main_loop").is_err());
+        assert_eq!(
+            Symbols::parse("a b").unwrap_err(),
+            "line 1: invalid number `b`"
+        );
+    }
+}
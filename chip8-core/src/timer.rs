@@ -1,15 +1,22 @@
 //! Timer-related structs and methods.
 
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Timer {
     /// Delay timer for the CHIP-8.
     ///
     /// The delay timer is one byte and decremented by one 60 times per second if its value is > 0.
-    pub delay_timer: u8,
+    pub(crate) delay_timer: u8,
 
     /// Sound timer for the CHIP-8.
     ///
     /// The sound timer is one byte and decremented by one 60 times per second if its value is > 0.
-    pub sound_timer: u8,
+    pub(crate) sound_timer: u8,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
 }
 
 impl Timer {
@@ -24,20 +31,38 @@ impl Timer {
 
     /// Checks if `delay_timer` and `sound_timer` are greater than 0.
     ///
-    /// If the values are greater than 0, decrement them by one. Makes a sound if `sound_timer` is
-    /// greater than 0.
+    /// If the values are greater than 0, decrement them by one. The frontend should make a sound
+    /// while [`Timer::is_sounding`] before the update.
     pub fn cycle(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
         if self.sound_timer > 0 {
-            self.beep();
             self.sound_timer -= 1;
         }
     }
 
-    fn beep(&self) {
-        println!("BEEP");
+    /// Returns the value of the delay timer.
+    pub fn delay(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    /// Returns the value of the sound timer.
+    pub fn sound(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Returns `true` while the sound timer is running, when a tone should be playing.
+    pub fn is_sounding(&self) -> bool {
+        self.sound_timer > 0
     }
 }
 
@@ -0,0 +1,249 @@
+//! Instruction tracing, for comparing runs against other emulators.
+
+use crate::command::parse_number;
+use crate::error::ExecError;
+pub use crate::executed::Change;
+use crate::executed::ExecutedInstruction;
+use crate::instruction::Instruction;
+use crate::symbols::Symbols;
+use crate::CHIP8;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+/// One executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub address: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub changes: Vec<Change>,
+}
+
+impl Record {
+    /// Executes one instruction, recording what it changed.
+    pub fn execute(chip: &mut CHIP8) -> Result<Option<Record>, ExecError> {
+        Ok(ExecutedInstruction::execute(chip)?.map(|executed| Record {
+            address: executed.pc,
+            opcode: executed.opcode,
+            instruction: executed.instruction,
+            changes: executed.side_effects,
+        }))
+    }
+
+    /// Parses a line written by a [`Tracer`]. Returns `None` for lines without a record, such as
+    /// labels.
+    pub fn parse(line: &str) -> Result<Option<Record>, String> {
+        let mut words = line.split_whitespace();
+        let address = match words.next() {
+            Some(word) if !word.ends_with(':') => parse_number(word)?,
+            _ => return Ok(None),
+        };
+        let opcode = words
+            .next()
+            .and_then(|word| u16::from_str_radix(word, 16).ok())
+            .ok_or_else(|| format!("missing opcode in `{}`", line.trim()))?;
+        let instruction = Instruction::decode(opcode)
+            .ok_or_else(|| format!("invalid opcode {:04X} in `{}`", opcode, line.trim()))?;
+
+        // The mnemonic is skipped, leaving changes such as `V0=0x5` and `[0x300]=0x05`.
+        let mut changes = Vec::new();
+        for word in words {
+            let (target, value) = match word.split_once('=') {
+                Some(change) => change,
+                None => continue,
+            };
+            let value = parse_number(value)?;
+            let change = match target.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+                Some(address) => Change::Memory(parse_number(address)?, value as u8),
+                None => Change::Register(target.parse()?, value),
+            };
+            changes.push(change);
+        }
+        Ok(Some(Record {
+            address,
+            opcode,
+            instruction,
+            changes,
+        }))
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes: Vec<String> = self.changes.iter().map(Change::to_string).collect();
+        let line = format!(
+            "{:#05X}  {:04X}  {:<20}{}",
+            self.address,
+            self.opcode,
+            self.instruction.to_string(),
+            changes.join(" ")
+        );
+        f.write_str(line.trim_end())
+    }
+}
+
+/// Where trace records go.
+enum Sink {
+    File(BufWriter<File>),
+    /// The most recent records, up to a capacity.
+    Ring(VecDeque<Record>, usize),
+}
+
+/// Records executed instructions that pass its filters.
+///
+/// Instructions are traced if they lie in any of the address ranges and their mnemonic is any of
+/// the chosen ones. An empty list of ranges or mnemonics matches everything.
+pub struct Tracer {
+    sink: Sink,
+    ranges: Vec<RangeInclusive<u16>>,
+    mnemonics: Vec<String>,
+    /// Labels written before the instructions they point at.
+    symbols: Symbols,
+    /// The first error writing to the file, reported by [`Tracer::finish`].
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    fn new(sink: Sink) -> Self {
+        Tracer {
+            sink,
+            ranges: Vec::new(),
+            mnemonics: Vec::new(),
+            symbols: Symbols::default(),
+            error: None,
+        }
+    }
+
+    /// Creates a tracer that writes one line per instruction to `file`.
+    pub fn to_file(file: File) -> Self {
+        Tracer::new(Sink::File(BufWriter::new(file)))
+    }
+
+    /// Creates a tracer that keeps the last `capacity` instructions in memory.
+    pub fn ring(capacity: usize) -> Self {
+        Tracer::new(Sink::Ring(VecDeque::with_capacity(capacity), capacity))
+    }
+
+    /// Only traces instructions in `range`, in addition to any other ranges.
+    pub fn add_range(&mut self, range: RangeInclusive<u16>) {
+        self.ranges.push(range);
+    }
+
+    /// Only traces instructions with the mnemonic `mnemonic`, such as `DRW` or `CALL`, in
+    /// addition to any other mnemonics.
+    pub fn add_mnemonic(&mut self, mnemonic: &str) {
+        self.mnemonics.push(mnemonic.to_ascii_uppercase());
+    }
+
+    /// Writes a `label:` line before each traced instruction that has a label in `symbols`.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// Returns the records kept in memory, oldest first. Empty when tracing to a file.
+    pub fn records(&self) -> impl Iterator<Item = &Record> + '_ {
+        let records = match &self.sink {
+            Sink::Ring(records, _) => Some(records.iter()),
+            Sink::File(_) => None,
+        };
+        records.into_iter().flatten()
+    }
+
+    /// Executes one instruction, tracing it if it passes the filters.
+    pub fn step(&mut self, chip: &mut CHIP8) -> Result<(), ExecError> {
+        if let Some(record) = Record::execute(chip)? {
+            if self.matches(record.address, &record.instruction) {
+                self.record(record);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the trace file, returning the first error that occurred while writing it.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        match &mut self.sink {
+            Sink::File(file) => file.flush(),
+            Sink::Ring(..) => Ok(()),
+        }
+    }
+
+    fn matches(&self, address: u16, instruction: &Instruction) -> bool {
+        let in_range =
+            self.ranges.is_empty() || self.ranges.iter().any(|range| range.contains(&address));
+        let has_mnemonic =
+            self.mnemonics.is_empty() || self.mnemonics.contains(&instruction.mnemonic());
+        in_range && has_mnemonic
+    }
+
+    fn record(&mut self, record: Record) {
+        match &mut self.sink {
+            Sink::File(file) => {
+                if self.error.is_none() {
+                    let result = match self.symbols.name(record.address) {
+                        Some(name) => writeln!(file, "{}:\n{}", name, record),
+                        None => writeln!(file, "{}", record),
+                    };
+                    if let Err(err) = result {
+                        self.error = Some(err);
+                    }
+                }
+            }
+            Sink::Ring(records, capacity) => {
+                if records.len() == *capacity {
+                    records.pop_front();
+                }
+                if *capacity > 0 {
+                    records.push_back(record);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    #[test]
+    fn ring_buffer_and_filters() {
+        // 0x200: LD V0, 5; 0x202: LD I, 0x300; 0x204: LD B, V0; 0x206: JP 0x200
+        let rom = Rom::from_bytes(vec![0x60, 0x05, 0xA3, 0x00, 0xF0, 0x33, 0x12, 0x00]).unwrap();
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).unwrap();
+
+        let mut tracer = Tracer::ring(2);
+        for _ in 0..3 {
+            tracer.step(&mut chip).unwrap();
+        }
+        let lines: Vec<String> = tracer.records().map(Record::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "0x202  A300  LD I, 0x300         I=0x300",
+                "0x204  F033  LD B, V0            [0x300]=0x00 [0x301]=0x00 [0x302]=0x05",
+            ]
+        );
+
+        let mut tracer = Tracer::ring(10);
+        tracer.add_mnemonic("jp");
+        tracer.add_range(0x204..=0x206);
+        for _ in 0..4 {
+            tracer.step(&mut chip).unwrap();
+        }
+        let addresses: Vec<u16> = tracer.records().map(|record| record.address).collect();
+        assert_eq!(addresses, [0x206]);
+
+        for line in lines {
+            let record = Record::parse(&line).unwrap().unwrap();
+            assert_eq!(record.to_string(), line);
+        }
+        assert_eq!(Record::parse("main_loop:"), Ok(None));
+    }
+}
@@ -0,0 +1,29 @@
+//! CHIP-8 variant definitions.
+
+use core::fmt;
+
+/// A CHIP-8 dialect.
+///
+/// Variants are ordered so that each one is a superset of the previous one.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum Variant {
+    /// The original COSMAC VIP CHIP-8.
+    Chip8,
+    /// SUPER-CHIP 1.1, adding high resolution mode, scrolling and persistent flags.
+    SuperChip,
+    /// XO-CHIP, adding 64KB of memory, bitplanes and programmable audio.
+    XoChip,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
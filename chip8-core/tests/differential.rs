@@ -0,0 +1,169 @@
+//! Runs random ROMs on the core and on the reference interpreter in `tests/reference` side by
+//! side, pressing random keys, and checks after every instruction that both machines are in the
+//! same state.
+//!
+//! A few ROMs run by default. Set `CHIP8_DIFFERENTIAL` to the number of ROMs to run more, and
+//! `CHIP8_DIFFERENTIAL_SEED` to vary them:
+//!
+//! ```text
+//! CHIP8_DIFFERENTIAL=100000 cargo test --release -p chip8-core --test differential
+//! ```
+
+mod reference;
+
+use chip8_core::font::FONT_SET;
+use chip8_core::quirks::Quirks;
+use chip8_core::rom::Rom;
+use chip8_core::CHIP8;
+use reference::Reference;
+use std::env;
+
+/// ROMs run unless `CHIP8_DIFFERENTIAL` says otherwise, fewer under Miri, which is much slower.
+const ROMS: u64 = if cfg!(miri) { 10 } else { 200 };
+/// Bytes in each ROM.
+const ROM_SIZE: usize = 256;
+/// Instructions each ROM runs for at most.
+const STEPS: u64 = 2000;
+
+/// Generates the ROMs, quirks and keys.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    /// Returns a number below `limit`.
+    fn below(&mut self, limit: u32) -> u16 {
+        (self.next() % limit) as u16
+    }
+
+    /// Returns an opcode, mostly valid CHIP-8 instructions that stay within the ROM, so that
+    /// programs run for a while before failing.
+    fn opcode(&mut self) -> u16 {
+        let x = self.below(16) << 8;
+        let y = self.below(16) << 4;
+        let target = 0x200 + self.below(ROM_SIZE as u32 / 2) * 2;
+        match self.below(24) {
+            // Returning with an empty stack ends the program, so return rarely.
+            0 => [0x00E0, 0x00E0, 0x00E0, 0x00EE][self.below(4) as usize],
+            1 => 0x1000 | target,
+            2 => 0x2000 | target,
+            3 => 0x3000 | x | self.below(4),
+            4 => 0x4000 | x | self.below(4),
+            5 => 0x5000 | x | y,
+            6 | 7 => 0x6000 | x | self.below(256),
+            8 => 0x7000 | x | self.below(256),
+            9 | 10 => 0x8000 | x | y | [0, 1, 2, 3, 4, 5, 6, 7, 0xE][self.below(9) as usize],
+            11 => 0x9000 | x | y,
+            12 => 0xA000 | (0x200 + self.below(0xE00)),
+            13 => 0xB000 | target,
+            14 => 0xC000 | x | self.below(256),
+            15 | 16 => 0xD000 | x | y | self.below(16),
+            17 => 0xE000 | x | [0x9E, 0xA1][self.below(2) as usize],
+            18 => {
+                let low = [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65];
+                0xF000 | x | low[self.below(low.len() as u32) as usize]
+            }
+            19 => self.next() as u16,
+            _ => 0x7000 | x | self.below(256),
+        }
+    }
+
+    fn quirks(&mut self) -> Quirks {
+        let bits = self.next();
+        Quirks {
+            vf_reset: bits & 1 != 0,
+            shift_in_place: bits & 2 != 0,
+            keep_index: bits & 4 != 0,
+            jump_vx: bits & 8 != 0,
+        }
+    }
+}
+
+/// Returns what differs between the two machines, if anything.
+fn difference(chip: &CHIP8, reference: &Reference) -> Option<&'static str> {
+    let screen = chip.display().screen();
+    let timer = chip.timer();
+    [
+        (chip.program_counter() == reference.pc, "program counter"),
+        (chip.index() == reference.i, "index register"),
+        (chip.variable() == &reference.v, "registers"),
+        (chip.stack() == reference.stack.as_slice(), "stack"),
+        (timer.delay() == reference.delay, "delay timer"),
+        (timer.sound() == reference.sound, "sound timer"),
+        (chip.memory()[..] == reference.memory[..], "memory"),
+        (screen == &reference.screen, "screen"),
+    ]
+    .iter()
+    .find(|(same, _)| !same)
+    .map(|&(_, what)| what)
+}
+
+/// Runs one random ROM on both machines, and describes the first difference.
+fn run(random: &mut Random, number: u64) -> Result<(), String> {
+    let rom: Vec<u8> = (0..ROM_SIZE / 2)
+        .flat_map(|_| random.opcode().to_be_bytes())
+        .collect();
+    let quirks = random.quirks();
+    let seed = random.next();
+
+    let mut chip = CHIP8::builder().quirks(quirks).seed(seed).build().unwrap();
+    chip.load_rom(&Rom::from_bytes(rom.clone()).unwrap())
+        .unwrap();
+    let mut reference = Reference::new(FONT_SET, &rom, seed, quirks);
+
+    let steps_per_frame = chip.config().steps_per_frame();
+    for step in 1..=STEPS {
+        let pc = chip.program_counter();
+        let core = chip.step().is_ok();
+        let same = reference.step().is_some();
+        let failure = |what: &str| {
+            Err(format!(
+                "ROM {} ({:02X?}, {:?}, seed {}): {} at step {}, address {:#05X}",
+                number, rom, quirks, seed, what, step, pc
+            ))
+        };
+        if core != same {
+            return failure(if core {
+                "only the reference failed"
+            } else {
+                "only the core failed"
+            });
+        }
+        if !core {
+            return Ok(());
+        }
+        if let Some(what) = difference(&chip, &reference) {
+            return failure(&format!("the machines differ in the {}", what));
+        }
+        if step % steps_per_frame == 0 {
+            chip.tick();
+            reference.tick();
+            let key = random.below(16) as u8;
+            let pressed = random.below(2) == 0;
+            chip.keyboard_mut().set(key, pressed);
+            reference.keys[key as usize] = pressed;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn differential() {
+    let number = |name: &str, default: u64| {
+        env::var(name)
+            .map(|value| value.parse().expect(name))
+            .unwrap_or(default)
+    };
+    let roms = number("CHIP8_DIFFERENTIAL", ROMS);
+    let mut random = Random(number("CHIP8_DIFFERENTIAL_SEED", 1).max(1));
+    for number in 0..roms {
+        if let Err(failure) = run(&mut random, number) {
+            panic!("{}", failure);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! A CHIP-8 interpreter written to be obviously correct rather than fast or useful: one flat
+//! match on the digits of each opcode, with no memory map, devices, peripherals or bookkeeping.
+//! It follows the same decisions as the core wherever interpreters disagree, such as clipping
+//! sprites at the edges and the quirks.
+
+use chip8_core::quirks::Quirks;
+
+/// Bytes of memory.
+pub const MEMORY: usize = 0x1000;
+/// Calls the stack holds.
+const STACK: usize = 16;
+/// Where the font starts.
+const FONT: usize = 0x50;
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+pub struct Reference {
+    pub memory: [u8; MEMORY],
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    pub screen: [[bool; WIDTH]; HEIGHT],
+    pub keys: [bool; 16],
+    rng: u32,
+    quirks: Quirks,
+}
+
+impl Reference {
+    pub fn new(font: &[u8], rom: &[u8], seed: u32, quirks: Quirks) -> Self {
+        let mut memory = [0; MEMORY];
+        memory[FONT..FONT + font.len()].copy_from_slice(font);
+        memory[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        Reference {
+            memory,
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            screen: [[false; WIDTH]; HEIGHT],
+            keys: [false; 16],
+            rng: if seed == 0 { 0x2545_F491 } else { seed },
+            quirks,
+        }
+    }
+
+    /// Counts down the timers, once a frame.
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    fn random(&mut self) -> u8 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 24) as u8
+    }
+
+    /// Returns the addresses of `len` bytes from I, or `None` if they run past memory.
+    fn at_index(&self, len: usize) -> Option<std::ops::Range<usize>> {
+        let start = self.i as usize;
+        if start + len > MEMORY {
+            None
+        } else {
+            Some(start..start + len)
+        }
+    }
+
+    /// Executes one instruction. Returns `None` if it fails.
+    pub fn step(&mut self) -> Option<()> {
+        let pc = self.pc as usize;
+        if pc + 1 >= MEMORY {
+            return None;
+        }
+        let opcode = u16::from_be_bytes([self.memory[pc], self.memory[pc + 1]]);
+        self.pc += 2;
+
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        match (opcode >> 12, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => self.screen = [[false; WIDTH]; HEIGHT],
+            (0x0, 0x0, 0xE, 0xE) => self.pc = self.stack.pop()?,
+            (0x1, _, _, _) => self.pc = nnn,
+            (0x2, _, _, _) => {
+                if self.stack.len() == STACK {
+                    return None;
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            (0x3, _, _, _) => {
+                if self.v[x] == nn {
+                    self.pc += 2;
+                }
+            }
+            (0x4, _, _, _) => {
+                if self.v[x] != nn {
+                    self.pc += 2;
+                }
+            }
+            (0x5, _, _, 0x0) => {
+                if self.v[x] == self.v[y] {
+                    self.pc += 2;
+                }
+            }
+            (0x6, _, _, _) => self.v[x] = nn,
+            (0x7, _, _, _) => self.v[x] = self.v[x].wrapping_add(nn),
+            (0x8, _, _, 0x0) => self.v[x] = self.v[y],
+            (0x8, _, _, 0x1) => {
+                self.v[x] |= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            (0x8, _, _, 0x2) => {
+                self.v[x] &= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            (0x8, _, _, 0x3) => {
+                self.v[x] ^= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            (0x8, _, _, 0x4) => {
+                let sum = self.v[x] as u16 + self.v[y] as u16;
+                self.v[x] = sum as u8;
+                self.v[0xF] = (sum > 0xFF) as u8;
+            }
+            (0x8, _, _, 0x5) => {
+                let no_borrow = self.v[x] >= self.v[y];
+                self.v[x] = self.v[x].wrapping_sub(self.v[y]);
+                self.v[0xF] = no_borrow as u8;
+            }
+            (0x8, _, _, 0x6) => {
+                let value = if self.quirks.shift_in_place {
+                    self.v[x]
+                } else {
+                    self.v[y]
+                };
+                self.v[x] = value >> 1;
+                self.v[0xF] = value & 1;
+            }
+            (0x8, _, _, 0x7) => {
+                let no_borrow = self.v[y] >= self.v[x];
+                self.v[x] = self.v[y].wrapping_sub(self.v[x]);
+                self.v[0xF] = no_borrow as u8;
+            }
+            (0x8, _, _, 0xE) => {
+                let value = if self.quirks.shift_in_place {
+                    self.v[x]
+                } else {
+                    self.v[y]
+                };
+                self.v[x] = value << 1;
+                self.v[0xF] = value >> 7;
+            }
+            (0x9, _, _, 0x0) => {
+                if self.v[x] != self.v[y] {
+                    self.pc += 2;
+                }
+            }
+            (0xA, _, _, _) => self.i = nnn,
+            (0xB, _, _, _) => {
+                let offset = if self.quirks.jump_vx {
+                    self.v[x]
+                } else {
+                    self.v[0]
+                };
+                self.pc = nnn + offset as u16;
+            }
+            (0xC, _, _, _) => self.v[x] = self.random() & nn,
+            (0xD, _, _, _) => {
+                let rows = self.at_index(n)?;
+                let left = self.v[x] as usize % WIDTH;
+                let top = self.v[y] as usize % HEIGHT;
+                self.v[0xF] = 0;
+                for (row, address) in rows.enumerate() {
+                    for column in 0..8 {
+                        let (px, py) = (left + column, top + row);
+                        let lit = self.memory[address] & (0x80 >> column) != 0;
+                        if lit && px < WIDTH && py < HEIGHT {
+                            if self.screen[py][px] {
+                                self.v[0xF] = 1;
+                            }
+                            self.screen[py][px] = !self.screen[py][px];
+                        }
+                    }
+                }
+            }
+            (0xE, _, 0x9, 0xE) => {
+                if self.keys[(self.v[x] & 0xF) as usize] {
+                    self.pc += 2;
+                }
+            }
+            (0xE, _, 0xA, 0x1) => {
+                if !self.keys[(self.v[x] & 0xF) as usize] {
+                    self.pc += 2;
+                }
+            }
+            (0xF, _, 0x0, 0x7) => self.v[x] = self.delay,
+            (0xF, _, 0x0, 0xA) => match self.keys.iter().position(|&pressed| pressed) {
+                Some(key) => self.v[x] = key as u8,
+                None => self.pc -= 2,
+            },
+            (0xF, _, 0x1, 0x5) => self.delay = self.v[x],
+            (0xF, _, 0x1, 0x8) => self.sound = self.v[x],
+            (0xF, _, 0x1, 0xE) => self.i = self.i.wrapping_add(self.v[x] as u16),
+            (0xF, _, 0x2, 0x9) => self.i = (FONT + (self.v[x] & 0xF) as usize * 5) as u16,
+            (0xF, _, 0x3, 0x3) => {
+                let digits = self.at_index(3)?;
+                let value = self.v[x];
+                self.memory[digits].copy_from_slice(&[value / 100, value / 10 % 10, value % 10]);
+            }
+            (0xF, _, 0x5, 0x5) => {
+                let registers = self.at_index(x + 1)?;
+                self.memory[registers].copy_from_slice(&self.v[..=x]);
+                if !self.quirks.keep_index {
+                    self.i += x as u16 + 1;
+                }
+            }
+            (0xF, _, 0x6, 0x5) => {
+                let registers = self.at_index(x + 1)?;
+                self.v[..=x].copy_from_slice(&self.memory[registers]);
+                if !self.quirks.keep_index {
+                    self.i += x as u16 + 1;
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
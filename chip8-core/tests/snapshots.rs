@@ -0,0 +1,97 @@
+//! Runs the test ROMs in `tests/roms` without a display, and compares the screen each leaves with
+//! its snapshot in `tests/snapshots`, so that a change to drawing, timing or a quirk shows up as a
+//! failing test.
+//!
+//! The ROMs are assembly sources, run for [`FRAMES`] frames unless they start with a line such as
+//! `# frames: 30`. After checking that a change to the screen is intended, update the snapshots
+//! with:
+//!
+//! ```text
+//! CHIP8_BLESS=1 cargo test -p chip8-core --test snapshots
+//! ```
+
+#![cfg(feature = "std")]
+
+use chip8_core::asm;
+use chip8_core::frontend::{Headless, Runner};
+use chip8_core::rom::Rom;
+use chip8_core::CHIP8;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Frames a ROM runs for unless it says otherwise.
+const FRAMES: usize = 60;
+
+/// Set to write the snapshots instead of comparing with them.
+const BLESS: &str = "CHIP8_BLESS";
+
+fn dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+/// Returns the frames `source` asks to run for.
+fn frames(source: &str) -> usize {
+    source
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("# frames:"))
+        .map(|frames| frames.trim().parse().expect("frame count"))
+        .unwrap_or(FRAMES)
+}
+
+/// Assembles and runs `source`, and returns the screen it leaves.
+fn run(source: &str) -> Result<String, String> {
+    let program = asm::assemble(source)?;
+    let rom = Rom::from_bytes(program.bytes).map_err(|err| err.to_string())?;
+    let mut chip = CHIP8::builder()
+        .seed(0)
+        .build()
+        .map_err(|err| err.to_string())?;
+    chip.load_rom(&rom).map_err(|err| err.to_string())?;
+    let mut runner = Runner::new(chip, Headless, Headless, Headless);
+    for _ in 0..frames(source) {
+        runner.frame().map_err(|err| err.to_string())?;
+    }
+    Ok(runner.chip.display().text())
+}
+
+#[test]
+fn snapshots() {
+    let bless = env::var_os(BLESS).is_some();
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir("roms"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "8o"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty(), "no test ROMs");
+
+    let mut failures = Vec::new();
+    for rom in &roms {
+        let name = rom.file_stem().unwrap().to_string_lossy();
+        let snapshot = dir("snapshots").join(format!("{}.txt", name));
+        let screen = match run(&fs::read_to_string(rom).unwrap()) {
+            Ok(screen) => screen,
+            Err(err) => {
+                failures.push(format!("{}: {}", name, err));
+                continue;
+            }
+        };
+        if bless {
+            fs::write(&snapshot, &screen).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == screen => (),
+            Ok(expected) => failures.push(format!(
+                "{}: the screen differs from the snapshot\nexpected:\n{}actual:\n{}",
+                name, expected, screen
+            )),
+            Err(_) => failures.push(format!("{}: no snapshot, run with {}=1", name, BLESS)),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,46 @@
+//! Runs the ROMs of the community test suite, which are not distributed with the crate. The test is
+//! ignored unless asked for, and then needs `CHIP8_TEST_SUITE` to name the directory holding them:
+//!
+//! ```text
+//! CHIP8_TEST_SUITE=~/chip8-test-suite/bin cargo test -p chip8-core --features testing --test suite -- --ignored
+//! ```
+
+#![cfg(feature = "std")]
+
+use chip8_core::rom::Rom;
+use chip8_core::suite::{self, Report, Test, TESTS};
+use chip8_core::CHIP8;
+use std::env;
+use std::fs;
+
+#[test]
+#[ignore = "needs $CHIP8_TEST_SUITE"]
+fn suite() {
+    let dir = env::var_os("CHIP8_TEST_SUITE").expect("CHIP8_TEST_SUITE is not set");
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+
+    let reports: Vec<Report> = TESTS
+        .iter()
+        .map(|test| {
+            let path = files.iter().find(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.ends_with(".ch8") && Test::find(&name) == Some(*test)
+            });
+            let result = path.map(|path| {
+                let mut chip = CHIP8::new();
+                chip.load_rom(&Rom::from_file(path).unwrap()).unwrap();
+                test.run(chip)
+            });
+            Report {
+                test: *test,
+                rom: path.map(|path| path.display().to_string()),
+                result,
+            }
+        })
+        .collect();
+    assert!(suite::passed(&reports), "{}", suite::summary(&reports));
+}
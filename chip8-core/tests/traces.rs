@@ -0,0 +1,158 @@
+//! Runs the tests of Timendus' CHIP-8 test suite in `tests/test-suite` and compares every
+//! instruction executed, its address, opcode and what it changed, with the traces in
+//! `tests/traces`, so that a refactoring that changes behavior in any way shows up as a failing
+//! test.
+//!
+//! A test is traced under the quirks of each variant that changes what it does, and must run
+//! exactly as under the first of them under the others. A trace ends after [`STEPS`]
+//! instructions, or when the program counter stops moving, as it does once a test has drawn its
+//! results and waits for a key. After checking that a change in behavior is intended, update the
+//! traces with:
+//!
+//! ```text
+//! CHIP8_BLESS=1 cargo test -p chip8-core --test traces
+//! ```
+
+#![cfg(feature = "std")]
+
+use chip8_core::quirks::Quirks;
+use chip8_core::rom::Rom;
+use chip8_core::trace::Record;
+use chip8_core::CHIP8;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Instructions a trace runs for at most.
+const STEPS: u64 = 20_000;
+
+/// Set to write the traces instead of comparing with them.
+const BLESS: &str = "CHIP8_BLESS";
+
+/// The address of the byte the suite reads to pick a test, skipping its menu.
+const TEST: usize = 0x1FF;
+
+/// The address of the byte the quirks test reads to pick the platform it expects.
+const PLATFORM: usize = 0x1FE;
+
+/// The quirks of each variant, by the name their traces end with, and the platform the quirks
+/// test is told to expect under them.
+const PRESETS: [(&str, Quirks, u8); 3] = [
+    ("chip8", Quirks::CHIP8, 1),
+    ("schip", Quirks::SUPER_CHIP, 2),
+    ("xochip", Quirks::XO_CHIP, 3),
+];
+
+/// The tests traced, by name: the number the suite picks it by, and the presets that get a trace
+/// of their own. The logo test only draws, and the keypad test waits for someone at the keypad,
+/// so both are left out.
+const TESTS: [(&str, u8, &[&str]); 3] = [
+    ("corax", 2, &["chip8", "schip"]),
+    ("flags", 3, &["chip8", "schip", "xochip"]),
+    ("quirks", 4, &["chip8", "schip", "xochip"]),
+];
+
+fn dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+/// Runs test `test` of the suite in `rom` with the quirks and platform of `preset`, and returns
+/// the trace of what it executed.
+fn trace(rom: &Rom, test: u8, (_, quirks, platform): (&str, Quirks, u8)) -> Result<String, String> {
+    let mut chip = CHIP8::builder()
+        .quirks(quirks)
+        .seed(0)
+        .build()
+        .map_err(|err| err.to_string())?;
+    chip.load_rom(rom).map_err(|err| err.to_string())?;
+    chip.memory_mut()[TEST] = test;
+    chip.memory_mut()[PLATFORM] = platform;
+
+    let steps_per_frame = chip.config().steps_per_frame();
+    let mut trace = String::new();
+    for step in 1..=STEPS {
+        let record = Record::execute(&mut chip)
+            .map_err(|err| err.to_string())?
+            .ok_or("no instruction to execute")?;
+        if step % steps_per_frame == 0 {
+            chip.tick();
+        }
+        writeln!(trace, "{}", record).unwrap();
+        if chip.program_counter() == record.address {
+            break;
+        }
+    }
+    Ok(trace)
+}
+
+/// Describes the first line where `actual` differs from `expected`.
+fn difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => (),
+            (expected, actual) => {
+                return format!(
+                    "line {}:\n  expected: {}\n  actual:   {}",
+                    line,
+                    expected.unwrap_or("the end of the trace"),
+                    actual.unwrap_or("the end of the trace")
+                )
+            }
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn traces() {
+    let bless = env::var_os(BLESS).is_some();
+    let rom = Rom::from_file(dir("test-suite").join("chip8-test-suite.ch8")).unwrap();
+
+    let mut failures = Vec::new();
+    for (test, number, traced) in TESTS.iter() {
+        let mut first: Option<(&str, String)> = None;
+        for preset in PRESETS.iter() {
+            let name = format!("{}.{}", test, preset.0);
+            let actual = match trace(&rom, *number, *preset) {
+                Ok(trace) => trace,
+                Err(err) => {
+                    failures.push(format!("{}: {}", name, err));
+                    continue;
+                }
+            };
+            if !traced.contains(&preset.0) {
+                // Without a trace of its own, the test must not notice the preset.
+                match &first {
+                    Some((first, expected)) if *expected != actual => failures.push(format!(
+                        "{}: differs from {}.{}, give it a trace: {}",
+                        name,
+                        test,
+                        first,
+                        difference(expected, &actual)
+                    )),
+                    _ => (),
+                }
+                continue;
+            }
+            let path = dir("traces").join(format!("{}.txt", name));
+            if bless {
+                fs::write(&path, &actual).unwrap();
+            } else {
+                match fs::read_to_string(&path) {
+                    Ok(expected) if expected == actual => (),
+                    Ok(expected) => {
+                        failures.push(format!("{}: {}", name, difference(&expected, &actual)))
+                    }
+                    Err(_) => failures.push(format!("{}: no trace, run with {}=1", name, BLESS)),
+                }
+            }
+            first.get_or_insert((preset.0, actual));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,58 @@
+/*
+ * Runs a ROM for a number of frames without a display and prints the screen.
+ *
+ *   cargo build -p chip8-ffi --release
+ *   cc -Iinclude examples/headless.c ../target/release/libchip8_ffi.a -lpthread -ldl -lm -o headless
+ *   ./headless <rom> [<frames>]
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "chip8.h"
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s <rom> [<frames>]\n", argv[0]);
+        return 1;
+    }
+    if (chip8_api_version() != CHIP8_API_VERSION) {
+        fprintf(stderr, "error: the library does not match chip8.h\n");
+        return 1;
+    }
+
+    FILE *file = fopen(argv[1], "rb");
+    if (!file) {
+        perror(argv[1]);
+        return 1;
+    }
+    static uint8_t rom[0x10000];
+    size_t len = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+
+    Chip8 *chip = chip8_new();
+    chip8_set_seed(chip, 1);
+    if (!chip8_load_rom(chip, rom, len)) {
+        fprintf(stderr, "error: %s: %s\n", argv[1], chip8_last_error(chip));
+        chip8_free(chip);
+        return 1;
+    }
+
+    int frames = argc > 2 ? atoi(argv[2]) : 60;
+    for (int frame = 0; frame < frames; frame++) {
+        if (!chip8_step_frame(chip)) {
+            fprintf(stderr, "error: frame %d: %s\n", frame, chip8_last_error(chip));
+            break;
+        }
+    }
+
+    const uint8_t *pixels = chip8_framebuffer(chip);
+    for (int y = 0; y < CHIP8_HEIGHT; y++) {
+        for (int x = 0; x < CHIP8_WIDTH; x++) {
+            putchar(pixels[y * CHIP8_WIDTH + x] ? '#' : '.');
+        }
+        putchar('\n');
+    }
+    chip8_free(chip);
+    return 0;
+}
@@ -0,0 +1,144 @@
+/* Generated by cbindgen from chip8-ffi. Do not edit by hand. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Version of the API, increased whenever functions are added.
+ */
+#define CHIP8_API_VERSION 1
+
+/**
+ * Width of the display in pixels.
+ */
+#define CHIP8_WIDTH 64
+
+/**
+ * Height of the display in pixels.
+ */
+#define CHIP8_HEIGHT 32
+
+/**
+ * A machine and the buffers shared with the caller.
+ */
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns [`CHIP8_API_VERSION`] as the library was built, to check it against the header.
+ */
+uint32_t chip8_api_version(void);
+
+/**
+ * Creates a machine with no ROM loaded, seeding its random number generator from the clock.
+ */
+Chip8 *chip8_new(void);
+
+/**
+ * Frees a machine.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`] and not be used afterwards. It may be null.
+ */
+void chip8_free(Chip8 *chip);
+
+/**
+ * Powers the machine on with the `len` bytes of ROM at `data`.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`], and `data` must point to `len` readable bytes.
+ */
+bool chip8_load_rom(Chip8 *chip, const uint8_t *data, size_t len);
+
+/**
+ * Seeds the random number generator, for runs that can be reproduced. Takes effect for the ROM
+ * loaded afterwards, or the one running.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`].
+ */
+void chip8_set_seed(Chip8 *chip, uint32_t seed);
+
+/**
+ * Runs the program for one frame, 1/60 second, and updates the timers and the framebuffer.
+ * Returns `false` once the program has stopped.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`].
+ */
+bool chip8_step_frame(Chip8 *chip);
+
+/**
+ * Returns the framebuffer: [`CHIP8_WIDTH`] × [`CHIP8_HEIGHT`] bytes, row by row, 1 for a lit
+ * pixel. It stays valid until the machine is freed.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`].
+ */
+const uint8_t *chip8_framebuffer(const Chip8 *chip);
+
+/**
+ * Presses or releases keypad `key`, from 0x0 to 0xF.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`].
+ */
+void chip8_key_event(Chip8 *chip, uint8_t key, bool pressed);
+
+/**
+ * Returns `true` while the sound timer runs and a tone should play.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`].
+ */
+bool chip8_sounding(const Chip8 *chip);
+
+/**
+ * Saves the state of the machine to `buffer`, if it holds the `len` bytes needed. Returns the
+ * size of the state, so that a first call with a null buffer gives the size to allocate.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`], and `buffer` must be null or point to `len` writable
+ * bytes.
+ */
+size_t chip8_save_state(const Chip8 *chip, uint8_t *buffer, size_t len);
+
+/**
+ * Restores a state saved by [`chip8_save_state`], or by `chip8`, with the same ROM loaded.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`], and `data` must point to `len` readable bytes.
+ */
+bool chip8_load_state(Chip8 *chip, const uint8_t *data, size_t len);
+
+/**
+ * Returns why the last call failed as a null-terminated string, or null if it did not. It stays
+ * valid until the next call with the same machine.
+ *
+ * # Safety
+ *
+ * `chip` must come from [`chip8_new`].
+ */
+const char *chip8_last_error(const Chip8 *chip);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif // __cplusplus
+
+#endif /* CHIP8_H */
@@ -0,0 +1,339 @@
+//! A C API for embedding the emulator in programs written in C, C++ or any language that can call
+//! C, built as a shared or static library.
+//!
+//! The declarations are in `include/chip8.h`, generated with cbindgen. Every function takes a
+//! machine created by [`chip8_new`], which is freed with [`chip8_free`]. A frontend loads a ROM,
+//! then once per frame feeds in key presses, calls [`chip8_step_frame`], draws
+//! [`chip8_framebuffer`] and plays a tone while [`chip8_sounding`] is true.
+//!
+//! Functions that can fail return `false`, with the reason in [`chip8_last_error`]. The API only
+//! grows: [`CHIP8_API_VERSION`] goes up when functions are added, and existing functions keep
+//! their signatures and behavior.
+
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::rng::Rng;
+use chip8_core::rom::Rom;
+use chip8_core::state;
+use chip8_core::CHIP8;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+/// Version of the API, increased whenever functions are added.
+pub const CHIP8_API_VERSION: u32 = 1;
+
+/// Width of the display in pixels.
+pub const CHIP8_WIDTH: usize = 64;
+
+/// Height of the display in pixels.
+pub const CHIP8_HEIGHT: usize = 32;
+
+// Spelled out above so that they appear as numbers in the header.
+const _: () = assert!(CHIP8_WIDTH == WIDTH && CHIP8_HEIGHT == HEIGHT);
+
+/// A machine and the buffers shared with the caller.
+pub struct Chip8 {
+    chip: CHIP8,
+    /// Hash of the loaded ROM, which save states are checked against.
+    rom_hash: u64,
+    /// One byte per pixel, 1 for lit, row by row.
+    framebuffer: [u8; WIDTH * HEIGHT],
+    /// Set once the program fails, after which frames do nothing.
+    stopped: bool,
+    /// Why the last call failed.
+    error: Option<CString>,
+}
+
+impl Chip8 {
+    /// Records why a call failed and returns `false`.
+    fn fail(&mut self, error: impl ToString) -> bool {
+        self.error = CString::new(error.to_string()).ok();
+        false
+    }
+
+    /// Copies the display into the framebuffer.
+    fn update_framebuffer(&mut self) {
+        let pixels = self.chip.display().screen().iter().flatten();
+        for (byte, &lit) in self.framebuffer.iter_mut().zip(pixels) {
+            *byte = lit as u8;
+        }
+    }
+}
+
+/// Returns [`CHIP8_API_VERSION`] as the library was built, to check it against the header.
+#[no_mangle]
+pub extern "C" fn chip8_api_version() -> u32 {
+    CHIP8_API_VERSION
+}
+
+/// Creates a machine with no ROM loaded, seeding its random number generator from the clock.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    Box::into_raw(Box::new(Chip8 {
+        chip: CHIP8::new(),
+        rom_hash: 0,
+        framebuffer: [0; WIDTH * HEIGHT],
+        stopped: false,
+        error: None,
+    }))
+}
+
+/// Frees a machine.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`] and not be used afterwards. It may be null.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip: *mut Chip8) {
+    if !chip.is_null() {
+        drop(Box::from_raw(chip));
+    }
+}
+
+/// Powers the machine on with the `len` bytes of ROM at `data`.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`], and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip: *mut Chip8, data: *const u8, len: usize) -> bool {
+    let chip = match chip.as_mut() {
+        Some(chip) => chip,
+        None => return false,
+    };
+    if data.is_null() {
+        return chip.fail("no ROM given");
+    }
+    let data = slice::from_raw_parts(data, len).to_vec();
+    let rom_hash = state::rom_hash(&data);
+    let mut machine = CHIP8::new();
+    machine.set_rng(chip.chip.rng().clone());
+    if let Err(err) = Rom::from_bytes(data).and_then(|rom| machine.load_rom(&rom)) {
+        return chip.fail(err);
+    }
+    chip.chip = machine;
+    chip.rom_hash = rom_hash;
+    chip.stopped = false;
+    chip.error = None;
+    chip.update_framebuffer();
+    true
+}
+
+/// Seeds the random number generator, for runs that can be reproduced. Takes effect for the ROM
+/// loaded afterwards, or the one running.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`].
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_seed(chip: *mut Chip8, seed: u32) {
+    if let Some(chip) = chip.as_mut() {
+        chip.chip.set_rng(Rng::new(seed));
+    }
+}
+
+/// Runs the program for one frame, 1/60 second, and updates the timers and the framebuffer.
+/// Returns `false` once the program has stopped.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`].
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step_frame(chip: *mut Chip8) -> bool {
+    let chip = match chip.as_mut() {
+        Some(chip) => chip,
+        None => return false,
+    };
+    if chip.stopped {
+        return false;
+    }
+    for _ in 0..chip.chip.config().steps_per_frame() {
+        if let Err(err) = chip.chip.step() {
+            chip.stopped = true;
+            chip.update_framebuffer();
+            return chip.fail(err);
+        }
+    }
+    chip.chip.tick();
+    chip.update_framebuffer();
+    true
+}
+
+/// Returns the framebuffer: [`CHIP8_WIDTH`] × [`CHIP8_HEIGHT`] bytes, row by row, 1 for a lit
+/// pixel. It stays valid until the machine is freed.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`].
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(chip: *const Chip8) -> *const u8 {
+    match chip.as_ref() {
+        Some(chip) => chip.framebuffer.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Presses or releases keypad `key`, from 0x0 to 0xF.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`].
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_event(chip: *mut Chip8, key: u8, pressed: bool) {
+    if let Some(chip) = chip.as_mut() {
+        chip.chip.keyboard_mut().set(key, pressed);
+    }
+}
+
+/// Returns `true` while the sound timer runs and a tone should play.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`].
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sounding(chip: *const Chip8) -> bool {
+    chip.as_ref()
+        .is_some_and(|chip| !chip.stopped && chip.chip.timer().is_sounding())
+}
+
+/// Saves the state of the machine to `buffer`, if it holds the `len` bytes needed. Returns the
+/// size of the state, so that a first call with a null buffer gives the size to allocate.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`], and `buffer` must be null or point to `len` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_save_state(
+    chip: *const Chip8,
+    buffer: *mut u8,
+    len: usize,
+) -> usize {
+    let chip = match chip.as_ref() {
+        Some(chip) => chip,
+        None => return 0,
+    };
+    let bytes = state::save(&chip.chip.snapshot(), chip.rom_hash);
+    if !buffer.is_null() && len >= bytes.len() {
+        slice::from_raw_parts_mut(buffer, bytes.len()).copy_from_slice(&bytes);
+    }
+    bytes.len()
+}
+
+/// Restores a state saved by [`chip8_save_state`], or by `chip8`, with the same ROM loaded.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`], and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_state(chip: *mut Chip8, data: *const u8, len: usize) -> bool {
+    let chip = match chip.as_mut() {
+        Some(chip) => chip,
+        None => return false,
+    };
+    if data.is_null() {
+        return chip.fail("no state given");
+    }
+    let bytes = slice::from_raw_parts(data, len);
+    if let Err(err) = state::restore(&mut chip.chip, bytes, chip.rom_hash) {
+        return chip.fail(err);
+    }
+    chip.stopped = false;
+    chip.error = None;
+    chip.update_framebuffer();
+    true
+}
+
+/// Returns why the last call failed as a null-terminated string, or null if it did not. It stays
+/// valid until the next call with the same machine.
+///
+/// # Safety
+///
+/// `chip` must come from [`chip8_new`].
+#[no_mangle]
+pub unsafe extern "C" fn chip8_last_error(chip: *const Chip8) -> *const c_char {
+    match chip.as_ref().and_then(|chip| chip.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn load(chip: *mut Chip8, rom: &[u8]) -> bool {
+        unsafe { chip8_load_rom(chip, rom.as_ptr(), rom.len()) }
+    }
+
+    fn error(chip: *const Chip8) -> String {
+        let error = unsafe { chip8_last_error(chip) };
+        assert!(!error.is_null());
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn frames_and_keys() {
+        let chip = chip8_new();
+        // 0x200: LD V0, K; LD ST, V0; LD F, V0; DRW V0, V0, 5; JP 0x208
+        assert!(load(
+            chip,
+            &[0xF0, 0x0A, 0xF0, 0x18, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x08]
+        ));
+        assert!(unsafe { chip8_step_frame(chip) });
+        assert!(!unsafe { chip8_sounding(chip) });
+
+        unsafe { chip8_key_event(chip, 0x5, true) };
+        assert!(unsafe { chip8_step_frame(chip) });
+        assert!(unsafe { chip8_sounding(chip) });
+        let pixels = unsafe { slice::from_raw_parts(chip8_framebuffer(chip), WIDTH * HEIGHT) };
+        // The top row of the font's 5 is 0xF0, drawn at (5, 5).
+        assert_eq!(pixels[5 * WIDTH + 4..5 * WIDTH + 10], [0, 1, 1, 1, 1, 0]);
+        assert!(unsafe { chip8_last_error(chip) }.is_null());
+        unsafe { chip8_free(chip) };
+    }
+
+    #[test]
+    fn states() {
+        let chip = chip8_new();
+        // 0x200: ADD V0, 1; JP 0x200
+        assert!(load(chip, &[0x70, 0x01, 0x12, 0x00]));
+        assert!(unsafe { chip8_step_frame(chip) });
+        let len = unsafe { chip8_save_state(chip, ptr::null_mut(), 0) };
+        let mut saved = vec![0; len];
+        assert_eq!(
+            unsafe { chip8_save_state(chip, saved.as_mut_ptr(), len) },
+            len
+        );
+        let v0 = unsafe { &*chip }.chip.variable()[0];
+
+        assert!(unsafe { chip8_step_frame(chip) });
+        assert!(unsafe { chip8_load_state(chip, saved.as_ptr(), len) });
+        assert_eq!(unsafe { &*chip }.chip.variable()[0], v0);
+
+        assert!(!unsafe { chip8_load_state(chip, saved.as_ptr(), 3) });
+        assert!(!error(chip).is_empty());
+        unsafe { chip8_free(chip) };
+    }
+
+    #[test]
+    fn errors() {
+        let chip = chip8_new();
+        assert!(!load(chip, &[]));
+        assert_eq!(error(chip), "ROM is empty");
+
+        // 0x200: RET with an empty stack
+        assert!(load(chip, &[0x00, 0xEE]));
+        assert!(!unsafe { chip8_step_frame(chip) });
+        assert!(error(chip).contains("0x200"));
+        assert!(!unsafe { chip8_step_frame(chip) });
+        unsafe { chip8_free(chip) };
+
+        assert!(!load(ptr::null_mut(), &[0x00, 0xE0]));
+        assert!(unsafe { chip8_framebuffer(ptr::null()) }.is_null());
+    }
+}
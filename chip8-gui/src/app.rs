@@ -0,0 +1,754 @@
+//! The application: a menu bar, the dockable panels, a status bar, and the machine running in real
+//! time behind them.
+
+use crate::frontend::{Keypad, Screen, Tone};
+use crate::panels::{self, Panels, Tab, TABS};
+use crate::settings::{self, Settings};
+#[cfg(feature = "video")]
+use crate::video::{self, Recorder};
+use chip8_core::error::ExecError;
+use chip8_core::frontend::{Runner, Video};
+use chip8_core::inspector::Inspector;
+use chip8_core::memory::AccessKind;
+use chip8_core::rom::Rom;
+use chip8_core::state::{self, Resume, Slots};
+use chip8_core::{CHIP8, TIMER_PERIOD};
+use eframe::egui::ViewportCommand;
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers, TextureHandle, TextureOptions};
+use eframe::egui_wgpu::RenderState;
+use egui_dock::{DockArea, DockState, Style};
+use emu_debug::log::Log;
+use emu_frontend::osd;
+use emu_frontend::shaders::{self, Library};
+use emu_frontend::window::toggle_fullscreen;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How far behind real time the machine may fall before it skips ahead instead of catching up,
+/// such as after the window was hidden.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Time between two auto-saves while a ROM runs, in case the emulator crashes, as in `chip8 run`.
+const AUTOSAVE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Number of save slots offered in the File menu.
+const SLOTS: u8 = 10;
+
+/// Extensions of the ROMs the Open dialog shows first.
+const ROM_EXTENSIONS: [&str; 2] = ["ch8", "c8"];
+
+/// Keyboard shortcuts of the menu items, so that everything can be done without a mouse.
+const OPEN: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::O);
+const RESET: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::R);
+const SAVE_STATE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::S);
+const LOAD_STATE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::L);
+#[cfg(feature = "video")]
+const RECORD: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F9);
+const SETTINGS: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Comma);
+const QUIT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Q);
+const PAUSE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F5);
+const STEP: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F11);
+const STEP_FRAME: KeyboardShortcut = KeyboardShortcut::new(Modifiers::SHIFT, Key::F11);
+const FULLSCREEN: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Enter);
+
+/// The key that moves the keyboard focus to the menu bar, as in most desktop applications.
+const MENU_KEY: Key = Key::F10;
+
+/// A ROM running on a machine.
+pub struct Game {
+    pub path: String,
+    pub runner: Runner<Screen, Tone, Keypad>,
+    pub inspector: Inspector,
+    slots: Slots,
+    /// The ROM's own settings for auto-saving and battery-backed memory, shared with `chip8 run`.
+    saving: state::Settings,
+    /// Whether the File menu offers to resume from the auto-save.
+    resumable: bool,
+    /// Why the program stopped, if it failed.
+    pub error: Option<String>,
+    /// Whether the machine is stopped in the debugger, running only when stepped.
+    pub paused: bool,
+    /// The video being recorded of every frame, if any.
+    #[cfg(feature = "video")]
+    pub recorder: Option<Recorder>,
+    /// When the next frame is due.
+    next_frame: Instant,
+    /// When the state is next auto-saved.
+    next_autosave: Instant,
+}
+
+impl Game {
+    /// Loads the ROM at `path` into a machine configured by `settings`.
+    fn load(path: &str, settings: &Settings) -> Result<Game, String> {
+        let rom = Rom::from_file(path).map_err(|err| format!("{}: {}", path, err))?;
+        let slots = Slots::new(path, &rom);
+        let mut chip = CHIP8::builder()
+            .quirks(settings.quirks)
+            .speed(settings.speed)
+            .flags(slots.flags())
+            .build()
+            .map_err(|err| err.to_string())?;
+        chip.load_rom(&rom)
+            .map_err(|err| format!("{}: {}", path, err))?;
+        let mut screen = Screen::new(settings);
+        screen.draw(chip.display());
+        let now = Instant::now();
+        Ok(Game {
+            path: path.to_string(),
+            inspector: Inspector::new(&chip),
+            runner: Runner::new(chip, screen, Tone::default(), Keypad::default()),
+            slots,
+            saving: state::Settings::default(),
+            resumable: false,
+            error: None,
+            paused: false,
+            #[cfg(feature = "video")]
+            recorder: None,
+            next_frame: now,
+            next_autosave: now + AUTOSAVE_PERIOD,
+        })
+    }
+
+    /// Applies the ROM's own settings: restores its battery-backed memory, and if `resume`,
+    /// resumes from its auto-save or offers to. Returns what happened, for the status bar.
+    fn start(&mut self, resume: bool) -> String {
+        let mut notes = vec![format!("loaded {}", self.path)];
+        self.saving = self.slots.settings().unwrap_or_else(|err| {
+            notes.push(err);
+            state::Settings::default()
+        });
+        let chip = &mut self.runner.chip;
+        if let Some(battery) = self.saving.battery {
+            match self.slots.load_battery(chip, battery) {
+                Ok(true) => notes.push("restored battery-backed memory".to_string()),
+                Ok(false) => (),
+                Err(err) => notes.push(err),
+            }
+        }
+        if resume && self.saving.autosave && self.slots.has_auto() {
+            match self.saving.resume {
+                Resume::Always => match self.slots.load_auto(chip) {
+                    Ok(()) => notes.push("resumed from the auto-save".to_string()),
+                    Err(err) => notes.push(err),
+                },
+                Resume::Ask => {
+                    self.resumable = true;
+                    notes.push("File → Resume Auto-Save picks up where you left off".to_string());
+                }
+                Resume::Never => (),
+            }
+        }
+        self.inspector = Inspector::new(chip);
+        self.redraw();
+        notes.join("; ")
+    }
+
+    /// Restores the state of the auto-save, as offered by [`Game::start`].
+    fn resume(&mut self) -> String {
+        self.resumable = false;
+        match self.slots.load_auto(&mut self.runner.chip) {
+            Ok(()) => {
+                self.error = None;
+                self.redraw();
+                "resumed from the auto-save".to_string()
+            }
+            Err(err) => err,
+        }
+    }
+
+    /// Auto-saves the game, if its settings say to, before it is replaced or the window closes.
+    /// A program that failed is not saved, as resuming into the failure would not help.
+    fn close(&self) {
+        if self.saving.autosave && self.error.is_none() {
+            if let Err(err) = self.slots.save_auto(&self.runner.chip) {
+                tracing::warn!("{}", err);
+            }
+        }
+    }
+
+    /// Runs the frames due by `now`, unless the program is paused or has stopped.
+    fn run(&mut self, now: Instant) {
+        if self.error.is_some() || self.paused {
+            return;
+        }
+        if now.duration_since(self.next_frame) > MAX_LAG {
+            self.next_frame = now;
+        }
+        while self.next_frame <= now {
+            if !self.frame() {
+                return;
+            }
+            self.next_frame += TIMER_PERIOD;
+        }
+        if self.saving.autosave && self.next_autosave <= now {
+            if let Err(err) = self.slots.save_auto(&self.runner.chip) {
+                tracing::warn!("{}", err);
+            }
+            self.next_autosave = now + AUTOSAVE_PERIOD;
+        }
+    }
+
+    /// Runs one frame, returning `false` if the program failed.
+    fn frame(&mut self) -> bool {
+        let (slots, battery) = (&self.slots, self.saving.battery);
+        let result = self.runner.frame_with(|chip| execute(chip, slots, battery));
+        self.inspector.update(&self.runner.chip);
+        #[cfg(feature = "video")]
+        if let Some(recorder) = &mut self.recorder {
+            recorder.frame(&self.runner.video.image, self.runner.audio.sounding);
+        }
+        match result {
+            Ok(_) => true,
+            Err(err) => {
+                self.error = Some(err.to_string());
+                false
+            }
+        }
+    }
+
+    /// Pauses or continues the program. On continuing, it picks up from `now` rather than
+    /// catching up on the time it was paused.
+    pub fn set_paused(&mut self, paused: bool, now: Instant) {
+        self.paused = paused;
+        self.next_frame = now;
+    }
+
+    /// Executes a single instruction of the paused program. The timers and the display are
+    /// left as they are, as they only change at the end of a frame.
+    fn step(&mut self) {
+        let result = execute(&mut self.runner.chip, &self.slots, self.saving.battery);
+        self.inspector.update(&self.runner.chip);
+        if let Err(err) = result {
+            self.error = Some(err.to_string());
+        }
+        self.redraw();
+    }
+
+    /// Shows the display again, for after it changed outside of a frame.
+    fn redraw(&mut self) {
+        self.runner.video.draw(self.runner.chip.display());
+    }
+}
+
+/// Executes an instruction, saving the ROM's `battery`-backed memory, if it has any, when the instruction changes it.
+fn execute(chip: &mut CHIP8, slots: &Slots, battery: Option<(u16, u16)>) -> Result<(), ExecError> {
+    chip.step()?;
+    if let Some((start, end)) = battery {
+        let changed = chip.accesses().iter().any(|access| {
+            access.kind == AccessKind::Write
+                && (start..=end).contains(&access.address)
+                && access.old != access.new
+        });
+        if changed {
+            if let Err(err) = slots.save_battery(chip, (start, end)) {
+                tracing::warn!("{}", err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The window and everything in it.
+pub struct App {
+    game: Option<Game>,
+    settings: Settings,
+    dialog: settings::Dialog,
+    dock: DockState<Tab>,
+    /// The game as shown by the game panel.
+    texture: TextureHandle,
+    /// What last happened, shown in the status bar.
+    status: String,
+    /// The shaders, if the window can use them.
+    shaders: Option<Library>,
+    /// The kind of file videos are recorded to.
+    #[cfg(feature = "video")]
+    video_format: video::Format,
+    /// The events logged, which the log panel shows.
+    log: Log,
+}
+
+impl App {
+    /// Creates the application, drawing with `render` if shaders can be used, showing `log` and
+    /// running the ROM at `path` if one is given.
+    pub fn new(
+        ctx: &egui::Context,
+        render: Option<&RenderState>,
+        log: Log,
+        path: Option<&str>,
+    ) -> Self {
+        let (settings, status) = match Settings::load() {
+            Ok(settings) => (settings, String::new()),
+            Err(err) => (Settings::default(), err),
+        };
+        let screen = Screen::new(&settings);
+        let mut app = App {
+            game: None,
+            settings,
+            dialog: settings::Dialog::default(),
+            dock: panels::layout(),
+            texture: ctx.load_texture("screen", screen.image, TextureOptions::NEAREST),
+            status,
+            shaders: render.map(|render| Library::new(render, settings::shaders_dir())),
+            #[cfg(feature = "video")]
+            video_format: video::Format::Mp4,
+            log,
+        };
+        if let Some(path) = path {
+            app.open(path);
+        }
+        app
+    }
+
+    /// Runs the ROM at `path` in place of the current one, with the ROM's own settings. Shows
+    /// why in the status bar if it could not be loaded, and keeps the current one.
+    fn open(&mut self, path: &str) {
+        self.replace(path, true);
+    }
+
+    /// Starts the current ROM over, without resuming from its auto-save.
+    fn reset(&mut self) {
+        if let Some(path) = self.game.as_ref().map(|game| game.path.clone()) {
+            self.replace(&path, false);
+        }
+    }
+
+    /// Replaces the game with the ROM at `path`, resuming it from its auto-save if `resume`.
+    /// Unless it is started over, the old game is auto-saved first. A video being recorded goes
+    /// on with the new game.
+    fn replace(&mut self, path: &str, resume: bool) {
+        match Game::load(path, &self.settings) {
+            Ok(mut game) => {
+                if let (true, Some(old)) = (resume, &self.game) {
+                    old.close();
+                }
+                #[cfg(feature = "video")]
+                if let Some(old) = &mut self.game {
+                    game.recorder = old.recorder.take();
+                }
+                self.status = game.start(resume);
+                self.game = Some(game);
+            }
+            Err(err) => self.status = err,
+        }
+    }
+
+    /// Asks for a ROM with the system's file dialog, and runs it.
+    fn choose(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Open ROM")
+            .add_filter("CHIP-8 ROMs", &ROM_EXTENSIONS)
+            .add_filter("All files", &["*"]);
+        if let Some(dir) = self
+            .game
+            .as_ref()
+            .and_then(|game| Path::new(&game.path).parent())
+        {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(path) = dialog.pick_file() {
+            self.open(&path.to_string_lossy());
+        }
+    }
+
+    /// Runs a ROM dropped onto the window, and shows where it can be dropped while one is
+    /// dragged over it.
+    fn drop_target(&mut self, ctx: &egui::Context) {
+        let (hovering, dropped) = ctx.input(|input| {
+            let dropped = input
+                .raw
+                .dropped_files
+                .iter()
+                .find_map(|file| file.path.clone());
+            (!input.raw.hovered_files.is_empty(), dropped)
+        });
+        if let Some(path) = dropped {
+            self.open(&path.to_string_lossy());
+        }
+        if hovering {
+            osd::cover(ctx, "Drop a ROM to run it", self.settings.large_text);
+        }
+    }
+
+    /// Uses `settings` from now on, on the running game too, and keeps them for next time.
+    fn apply(&mut self, settings: Settings) {
+        self.settings = settings;
+        if let Some(game) = &mut self.game {
+            let chip = &mut game.runner.chip;
+            chip.set_quirks(self.settings.quirks);
+            if let Err(err) = chip.set_speed(self.settings.speed) {
+                self.status = err.to_string();
+            }
+            game.runner.video.palette = self.settings.game_palette();
+            game.runner.video.blend = self.settings.reduce_flicker;
+            game.redraw();
+            #[cfg(feature = "video")]
+            if let Some(recorder) = &mut game.recorder {
+                recorder.set_volume(self.settings.volume);
+            }
+        }
+        if let Err(err) = self.settings.save() {
+            self.status = err;
+        }
+    }
+
+    /// Saves or loads the state of the game in the selected slot.
+    fn state(&mut self, save: bool) {
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+        let result = if save {
+            game.slots.save(&game.runner.chip, None)
+        } else {
+            let loaded = game.slots.load(&mut game.runner.chip, None);
+            if loaded.is_ok() {
+                game.error = None;
+                game.redraw();
+            }
+            loaded
+        };
+        self.status = result.unwrap_or_else(|err| err);
+    }
+
+    /// Pauses the running game, or continues the paused one.
+    fn toggle_pause(&mut self) {
+        if let Some(game) = &mut self.game {
+            let paused = !game.paused;
+            game.set_paused(paused, Instant::now());
+        }
+    }
+
+    /// Pauses the game and executes its next instruction, or its next frame if `frame`.
+    fn step(&mut self, frame: bool) {
+        let game = match &mut self.game {
+            Some(game) if game.error.is_none() => game,
+            _ => return,
+        };
+        game.set_paused(true, Instant::now());
+        if frame {
+            game.frame();
+        } else {
+            game.step();
+        }
+    }
+
+    /// Starts recording a video of the game, or stops and saves the one being recorded.
+    #[cfg(feature = "video")]
+    fn toggle_recording(&mut self) {
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+        self.status = match game.recorder.take() {
+            Some(recorder) => recorder.finish().unwrap_or_else(|err| err),
+            None => match Recorder::start(&game.path, self.video_format) {
+                Ok(mut recorder) => {
+                    recorder.set_volume(self.settings.volume);
+                    game.recorder = Some(recorder);
+                    "recording a video; F9 stops".to_string()
+                }
+                Err(err) => err,
+            },
+        };
+    }
+
+    #[cfg(feature = "video")]
+    fn video_menu(&mut self, ui: &mut egui::Ui) {
+        let recording = self.game.as_ref().map(|game| game.recorder.is_some());
+        let label = if recording == Some(true) {
+            "Stop Recording"
+        } else {
+            "Record Video"
+        };
+        if ui
+            .add_enabled(recording.is_some(), item(ui, label, &RECORD))
+            .clicked()
+        {
+            self.toggle_recording();
+            ui.close_menu();
+        }
+        ui.menu_button("Video Format", |ui| {
+            for format in video::FORMATS.iter() {
+                ui.radio_value(&mut self.video_format, *format, format.to_string());
+            }
+        });
+    }
+
+    fn menu_bar(&mut self, ui: &mut egui::Ui) {
+        egui::menu::bar(ui, |ui| {
+            let file = ui.menu_button("File", |ui| {
+                if ui.add(item(ui, "Open ROM…", &OPEN)).clicked() {
+                    ui.close_menu();
+                    self.choose();
+                }
+                let loaded = self.game.is_some();
+                if ui.add_enabled(loaded, item(ui, "Reset", &RESET)).clicked() {
+                    self.reset();
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui
+                    .add_enabled(loaded, item(ui, "Save State", &SAVE_STATE))
+                    .clicked()
+                {
+                    self.state(true);
+                    ui.close_menu();
+                }
+                if ui
+                    .add_enabled(loaded, item(ui, "Load State", &LOAD_STATE))
+                    .clicked()
+                {
+                    self.state(false);
+                    ui.close_menu();
+                }
+                let resumable = self.game.as_ref().is_some_and(|game| game.resumable);
+                if ui
+                    .add_enabled(resumable, egui::Button::new("Resume Auto-Save"))
+                    .clicked()
+                {
+                    if let Some(game) = &mut self.game {
+                        self.status = game.resume();
+                    }
+                    ui.close_menu();
+                }
+                if let Some(game) = &mut self.game {
+                    ui.menu_button("Slot", |ui| {
+                        for slot in 0..SLOTS {
+                            ui.radio_value(&mut game.slots.selected, slot, slot.to_string());
+                        }
+                    });
+                }
+                #[cfg(feature = "video")]
+                {
+                    ui.separator();
+                    self.video_menu(ui);
+                }
+                ui.separator();
+                if ui.add(item(ui, "Settings…", &SETTINGS)).clicked() {
+                    self.dialog.open(&self.settings);
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui.add(item(ui, "Quit", &QUIT)).clicked() {
+                    ui.ctx().send_viewport_cmd(ViewportCommand::Close);
+                }
+            });
+            if ui.input_mut(|input| input.consume_key(Modifiers::NONE, MENU_KEY)) {
+                file.response.request_focus();
+            }
+            ui.menu_button("Debug", |ui| {
+                let paused = self.game.as_ref().map(|game| game.paused);
+                let label = if paused == Some(true) {
+                    "Continue"
+                } else {
+                    "Pause"
+                };
+                if ui
+                    .add_enabled(paused.is_some(), item(ui, label, &PAUSE))
+                    .clicked()
+                {
+                    self.toggle_pause();
+                    ui.close_menu();
+                }
+                let runnable = self.game.as_ref().is_some_and(|game| game.error.is_none());
+                if ui
+                    .add_enabled(runnable, item(ui, "Step Instruction", &STEP))
+                    .clicked()
+                {
+                    self.step(false);
+                    ui.close_menu();
+                }
+                if ui
+                    .add_enabled(runnable, item(ui, "Step Frame", &STEP_FRAME))
+                    .clicked()
+                {
+                    self.step(true);
+                    ui.close_menu();
+                }
+            });
+            ui.menu_button("View", |ui| {
+                for tab in TABS.iter() {
+                    let mut shown = self.dock.find_tab(tab).is_some();
+                    if *tab == Tab::Game || !ui.checkbox(&mut shown, tab.to_string()).changed() {
+                        continue;
+                    }
+                    match self.dock.find_tab(tab) {
+                        Some(location) => {
+                            self.dock.remove_tab(location);
+                        }
+                        None => self.dock.push_to_focused_leaf(*tab),
+                    }
+                }
+                ui.separator();
+                let fullscreen = ui.input(|input| input.viewport().fullscreen == Some(true));
+                let label = if fullscreen {
+                    "Leave Full Screen"
+                } else {
+                    "Full Screen"
+                };
+                if ui.add(item(ui, label, &FULLSCREEN)).clicked() {
+                    toggle_fullscreen(ui.ctx());
+                    ui.close_menu();
+                }
+                egui::gui_zoom::zoom_menu_buttons(ui);
+            });
+        });
+    }
+
+    fn status_bar(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| match &self.game {
+            Some(Game {
+                error: Some(err), ..
+            }) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("stopped: {}", err));
+            }
+            Some(Game { paused: true, .. }) => {
+                ui.strong("paused");
+                ui.label(&self.status);
+            }
+            _ => {
+                ui.label(&self.status);
+            }
+        });
+    }
+
+    /// Does what the keyboard shortcuts pressed since the last frame ask for.
+    fn shortcuts(&mut self, ctx: &egui::Context) {
+        let pressed =
+            |shortcut: &KeyboardShortcut| ctx.input_mut(|input| input.consume_shortcut(shortcut));
+        let loaded = self.game.is_some();
+        if pressed(&OPEN) {
+            self.choose();
+        }
+        if pressed(&RESET) {
+            self.reset();
+        }
+        if pressed(&SAVE_STATE) && loaded {
+            self.state(true);
+        }
+        if pressed(&LOAD_STATE) && loaded {
+            self.state(false);
+        }
+        #[cfg(feature = "video")]
+        if pressed(&RECORD) {
+            self.toggle_recording();
+        }
+        if pressed(&SETTINGS) {
+            self.dialog.open(&self.settings);
+        }
+        if pressed(&QUIT) {
+            ctx.send_viewport_cmd(ViewportCommand::Close);
+        }
+        if pressed(&PAUSE) {
+            self.toggle_pause();
+        }
+        // Shift+F11 before F11, which would take it otherwise.
+        if pressed(&STEP_FRAME) {
+            self.step(true);
+        }
+        if pressed(&STEP) {
+            self.step(false);
+        }
+        if pressed(&FULLSCREEN) {
+            toggle_fullscreen(ctx);
+        }
+    }
+
+    /// Runs the frames that are due and shows everything.
+    fn show(&mut self, ctx: &egui::Context) {
+        let names = self.shaders.as_ref().map_or_else(Vec::new, Library::names);
+        if let Some(settings) = self.dialog.show(ctx, &names) {
+            self.apply(settings);
+        }
+        self.drop_target(ctx);
+
+        // The shortcuts only see the keyboard while no text field or key binding has it, and
+        // the keypad while no control has it either, such as a menu reached with F10.
+        if !ctx.wants_keyboard_input() && !self.dialog.is_binding() {
+            self.shortcuts(ctx);
+        }
+        let typing = ctx.memory(|memory| memory.focused().is_some()) || self.dialog.is_binding();
+
+        let now = Instant::now();
+        if let Some(library) = &mut self.shaders {
+            let notes = library.scan(now);
+            if !notes.is_empty() {
+                self.status = notes.join("; ");
+            }
+            // Shaders are reloaded while the game is paused too.
+            if !self.settings.shaders.is_empty() {
+                ctx.request_repaint_after(shaders::SCAN_PERIOD);
+            }
+        }
+        if let Some(game) = &mut self.game {
+            if typing {
+                game.runner.input.release();
+            } else {
+                let settings = &self.settings;
+                ctx.input(|input| game.runner.input.read(input, settings));
+            }
+            game.run(now);
+            #[cfg(feature = "video")]
+            if game.recorder.as_ref().is_some_and(Recorder::failed) {
+                if let Some(recorder) = game.recorder.take() {
+                    self.status = recorder.finish().unwrap_or_else(|err| err);
+                }
+            }
+            self.texture
+                .set(game.runner.video.image.clone(), TextureOptions::NEAREST);
+            if game.error.is_none() && !game.paused {
+                ctx.request_repaint_after(game.next_frame.saturating_duration_since(now));
+            }
+        }
+
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| self.menu_bar(ui));
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| self.status_bar(ui));
+        #[cfg(feature = "video")]
+        let recording = self
+            .game
+            .as_ref()
+            .and_then(|game| game.recorder.as_ref())
+            .map(Recorder::duration);
+        #[cfg(not(feature = "video"))]
+        let recording = None;
+        let mut panels = Panels {
+            game: self.game.as_ref(),
+            texture: &self.texture,
+            recording,
+            passes: self
+                .shaders
+                .as_ref()
+                .map_or_else(Vec::new, |library| library.passes(&self.settings.shaders)),
+            time: self.shaders.as_ref().map_or(0.0, Library::time),
+            large_text: self.settings.large_text,
+            log: &self.log,
+        };
+        DockArea::new(&mut self.dock)
+            .style(Style::from_egui(ctx.style().as_ref()))
+            .show(ctx, &mut panels);
+    }
+}
+
+/// A menu item, showing its keyboard shortcut.
+fn item(ui: &egui::Ui, text: &str, shortcut: &KeyboardShortcut) -> egui::Button<'static> {
+    egui::Button::new(text.to_string()).shortcut_text(ui.ctx().format_shortcut(shortcut))
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.show(ctx);
+    }
+
+    fn on_exit(&mut self) {
+        if let Some(game) = &mut self.game {
+            game.close();
+            #[cfg(feature = "video")]
+            if let Some(recorder) = game.recorder.take() {
+                match recorder.finish() {
+                    Ok(status) => tracing::info!("{}", status),
+                    Err(err) => tracing::warn!("{}", err),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! The window as a frontend: the game is drawn into an image for the game panel to show as a
+//! texture, the tone is noted for video recordings, and the keypad is read from the keys egui
+//! sees held down.
+
+use crate::settings::{Palette, Settings};
+use chip8_core::display::{Display, HEIGHT, WIDTH};
+use chip8_core::frontend::{Audio, Input, Video};
+use chip8_core::keyboard::Keyboard;
+use eframe::egui::{ColorImage, InputState};
+use emu_frontend::input;
+
+/// Draws the display in the colors of a palette.
+pub struct Screen {
+    pub palette: Palette,
+    /// Whether pixels lit in only one of the last two frames are drawn halfway between the
+    /// colors, for games that flicker by erasing their sprites and drawing them again.
+    pub blend: bool,
+    pub image: ColorImage,
+    /// The pixels lit in the frame drawn before.
+    previous: Vec<bool>,
+}
+
+impl Screen {
+    /// Creates a blank screen drawn as `settings` say.
+    pub fn new(settings: &Settings) -> Self {
+        let palette = settings.game_palette();
+        Screen {
+            palette,
+            blend: settings.reduce_flicker,
+            image: ColorImage::new([WIDTH, HEIGHT], palette.off),
+            previous: vec![false; WIDTH * HEIGHT],
+        }
+    }
+}
+
+impl Video for Screen {
+    fn draw(&mut self, display: &Display) {
+        let half = self.palette.off.lerp_to_gamma(self.palette.on, 0.5);
+        let pixels = display.screen().iter().flatten();
+        let previous = self.previous.iter_mut();
+        for ((color, &lit), was_lit) in self.image.pixels.iter_mut().zip(pixels).zip(previous) {
+            *color = match (lit, self.blend && lit != *was_lit) {
+                (_, true) => half,
+                (true, false) => self.palette.on,
+                (false, false) => self.palette.off,
+            };
+            *was_lit = lit;
+        }
+    }
+}
+
+/// Whether the tone plays. The window makes no sound of its own, but its video recordings do.
+#[derive(Default)]
+pub struct Tone {
+    pub sounding: bool,
+}
+
+impl Audio for Tone {
+    fn play(&mut self, sounding: bool) {
+        self.sounding = sounding;
+    }
+}
+
+/// The keypad keys held down when the window last saw the keyboard.
+#[derive(Default)]
+pub struct Keypad([bool; 16]);
+
+impl Keypad {
+    /// Reads which keypad keys are held down from `state`, with the keys of `settings`. Keys
+    /// held with Ctrl or Alt are for the shortcuts of the menus, and the game does not see them.
+    pub fn read(&mut self, state: &InputState, settings: &Settings) {
+        input::read(&settings.keys, state, &mut self.0);
+    }
+
+    /// Releases every key, for when the keyboard is busy with something else.
+    pub fn release(&mut self) {
+        self.0 = [false; 16];
+    }
+}
+
+impl Input for Keypad {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        for (key, &held) in self.0.iter().enumerate() {
+            keyboard.set(key as u8, held);
+        }
+        true
+    }
+}
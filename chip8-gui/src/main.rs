@@ -0,0 +1,56 @@
+//! CHIP-8 emulator in a desktop window, with menus, settings and the debugger's views in dockable
+//! panels.
+
+mod app;
+mod frontend;
+mod panels;
+mod settings;
+#[cfg(feature = "video")]
+mod video;
+
+use crate::app::App;
+use emu_debug::log;
+use emu_frontend::window;
+use std::env;
+use std::process;
+
+/// Exit status of a command line the emulator does not understand, as for `chip8`.
+const EXIT_USAGE: i32 = 2;
+
+/// Exit status when the window could not be opened.
+const EXIT_IO: i32 = 4;
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let (filter, format) = log::parse_args(&mut args).unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        process::exit(EXIT_USAGE);
+    });
+    let path = match &args[..] {
+        [] => None,
+        [path] if !path.starts_with('-') => Some(path.clone()),
+        _ => {
+            eprintln!("usage: chip8-gui [--log <filter>] [--log-json] [<rom>]");
+            process::exit(EXIT_USAGE);
+        }
+    };
+    let log = log::init(filter, format);
+    let options = window::options("chip8", [960.0, 640.0], settings::window_path());
+    let result = eframe::run_native(
+        "chip8",
+        options,
+        Box::new(move |cc| {
+            let render = cc.wgpu_render_state.as_ref();
+            Ok(Box::new(App::new(
+                &cc.egui_ctx,
+                render,
+                log,
+                path.as_deref(),
+            )))
+        }),
+    );
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(EXIT_IO);
+    }
+}
@@ -0,0 +1,251 @@
+//! The dockable panels: the game itself, the views of the debugger on the running machine, and
+//! the log.
+
+use crate::app::Game;
+use chip8_core::callstack;
+use chip8_core::debugger::{Register, REGISTERS};
+use chip8_core::disasm;
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::inspector::Highlight;
+use eframe::egui::WidgetText;
+use eframe::egui::{self, Color32, RichText, Sense, TextureHandle, Vec2};
+use egui_dock::{DockState, NodeIndex, TabViewer};
+use emu_debug::log::Log;
+use emu_frontend::osd;
+use emu_frontend::shaders::{Chain, Pass};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Level;
+
+/// Instructions shown by the disassembly panel, from the program counter on.
+const DISASSEMBLY_LINES: usize = 32;
+
+/// A dockable panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tab {
+    Game,
+    Registers,
+    Stack,
+    Disassembly,
+    Memory,
+    Log,
+}
+
+/// Every panel, in the order the View menu lists them.
+pub const TABS: [Tab; 6] = [
+    Tab::Game,
+    Tab::Registers,
+    Tab::Stack,
+    Tab::Disassembly,
+    Tab::Memory,
+    Tab::Log,
+];
+
+impl fmt::Display for Tab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Tab::Game => "Game",
+            Tab::Registers => "Registers",
+            Tab::Stack => "Stack",
+            Tab::Disassembly => "Disassembly",
+            Tab::Memory => "Memory",
+            Tab::Log => "Log",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The panels as they are first laid out: the game on the left, the registers and the stack
+/// sharing a place on its right above the disassembly, and memory and the log below.
+pub fn layout() -> DockState<Tab> {
+    let mut dock = DockState::new(vec![Tab::Game]);
+    let surface = dock.main_surface_mut();
+    let [game, _] = surface.split_below(NodeIndex::root(), 0.7, vec![Tab::Memory, Tab::Log]);
+    let [_, registers] = surface.split_right(game, 0.6, vec![Tab::Registers, Tab::Stack]);
+    surface.split_below(registers, 0.4, vec![Tab::Disassembly]);
+    dock
+}
+
+/// Shows the panels for the loaded game, if there is one.
+pub struct Panels<'a> {
+    pub game: Option<&'a Game>,
+    pub texture: &'a TextureHandle,
+    /// How long the video being recorded is, if one is.
+    pub recording: Option<Duration>,
+    /// The shaders the game is drawn through, if any.
+    pub passes: Vec<Arc<Pass>>,
+    /// The time the shaders see, in seconds.
+    pub time: f32,
+    /// Whether the text over the game is larger.
+    pub large_text: bool,
+    /// The events logged, shown by the log panel.
+    pub log: &'a Log,
+}
+
+impl TabViewer for Panels<'_> {
+    type Tab = Tab;
+
+    fn title(&mut self, tab: &mut Tab) -> WidgetText {
+        tab.to_string().into()
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Tab) {
+        let game = match (self.game, *tab) {
+            (_, Tab::Game) => return self.screen(ui),
+            (_, Tab::Log) => return log(ui, self.log),
+            (Some(game), _) => game,
+            (None, _) => {
+                ui.weak("No ROM loaded");
+                return;
+            }
+        };
+        match tab {
+            Tab::Game | Tab::Log => unreachable!("the panel is shown above"),
+            Tab::Registers => registers(ui, game),
+            Tab::Stack => stack(ui, game),
+            Tab::Disassembly => disassembly(ui, game),
+            Tab::Memory => memory(ui, game),
+        }
+    }
+
+    /// The game stays, as there would be nothing to play otherwise.
+    fn closeable(&mut self, tab: &mut Tab) -> bool {
+        *tab != Tab::Game
+    }
+}
+
+impl Panels<'_> {
+    /// Shows the game as large as fits, at a whole multiple of its size when it can be, through
+    /// the shaders, and marked while a video of it is recorded.
+    fn screen(&self, ui: &mut egui::Ui) {
+        let available = ui.available_size();
+        let scale = (available.x / WIDTH as f32).min(available.y / HEIGHT as f32);
+        let scale = if scale >= 1.0 { scale.floor() } else { scale };
+        let size = Vec2::new(WIDTH as f32, HEIGHT as f32) * scale;
+        let image = ui
+            .centered_and_justified(|ui| match self.game {
+                Some(game) if !self.passes.is_empty() => {
+                    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+                    let pixels = game.runner.video.image.pixels.iter();
+                    let output = rect.size() * ui.ctx().pixels_per_point();
+                    let chain = Chain {
+                        pixels: pixels.flat_map(|color| color.to_array()).collect(),
+                        game_size: [WIDTH as u32, HEIGHT as u32],
+                        output_size: [output.x.round() as u32, output.y.round() as u32],
+                        passes: self.passes.clone(),
+                        time: self.time,
+                    };
+                    ui.painter().add(chain.callback(rect));
+                    response
+                }
+                _ => ui.add(egui::Image::new(self.texture).fit_to_exact_size(size)),
+            })
+            .inner;
+        if let Some(duration) = self.recording {
+            osd::recording(
+                ui.painter(),
+                image.rect.left_top(),
+                duration,
+                self.large_text,
+            );
+        }
+    }
+}
+
+fn registers(ui: &mut egui::Ui, game: &Game) {
+    let chip = &game.runner.chip;
+    egui::Grid::new("registers").striped(true).show(ui, |ui| {
+        let pc = Register::PC;
+        ui.monospace(pc.to_string());
+        ui.monospace(format!("{:04X}", pc.read(chip)));
+        ui.end_row();
+        for register in REGISTERS.iter() {
+            ui.monospace(register.to_string());
+            ui.monospace(match register {
+                Register::I => format!("{:04X}", register.read(chip)),
+                _ => format!("{:02X}", register.read(chip)),
+            });
+            ui.end_row();
+        }
+        ui.monospace("SP");
+        ui.monospace(chip.stack().len().to_string());
+        ui.end_row();
+    });
+}
+
+/// Shows where the program is and the calls that led there, innermost first.
+fn stack(ui: &mut egui::Ui, game: &Game) {
+    let chip = &game.runner.chip;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for location in callstack::backtrace(chip, chip.program_counter()) {
+            ui.monospace(location.to_string());
+        }
+    });
+}
+
+fn disassembly(ui: &mut egui::Ui, game: &Game) {
+    let chip = &game.runner.chip;
+    let pc = chip.program_counter();
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for line in disasm::disassemble_memory(chip.memory(), pc, DISASSEMBLY_LINES) {
+            let text = RichText::new(line.to_string()).monospace();
+            if line.address == pc {
+                ui.label(text.strong());
+            } else {
+                ui.label(text);
+            }
+        }
+    });
+}
+
+fn memory(ui: &mut egui::Ui, game: &Game) {
+    let chip = &game.runner.chip;
+    let rows = chip.memory().len() / 16;
+    let height = ui.text_style_height(&egui::TextStyle::Monospace);
+    egui::ScrollArea::vertical().show_rows(ui, height, rows, |ui, range| {
+        let rows = game
+            .inspector
+            .rows(chip, (range.start * 16) as u16, range.len());
+        for row in rows {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 0.0;
+                ui.monospace(format!("{:04X} ", row.address));
+                for (column, &(byte, highlight)) in row.bytes.iter().enumerate() {
+                    let separator = if column == 8 { "  " } else { " " };
+                    ui.monospace(separator);
+                    let text = RichText::new(format!("{:02X}", byte)).monospace();
+                    ui.label(match highlight {
+                        Highlight::ProgramCounter => text.color(Color32::LIGHT_GREEN),
+                        Highlight::Index => text.color(Color32::LIGHT_BLUE),
+                        Highlight::Recent => text.color(Color32::YELLOW),
+                        Highlight::Font => text.weak(),
+                        Highlight::None => text,
+                    });
+                }
+            });
+        }
+    });
+}
+
+/// Shows the events logged, oldest first, following the newest as they come in.
+fn log(ui: &mut egui::Ui, log: &Log) {
+    if ui.button("Clear").clicked() {
+        log.clear();
+    }
+    let height = ui.text_style_height(&egui::TextStyle::Monospace);
+    egui::ScrollArea::both()
+        .stick_to_bottom(true)
+        .auto_shrink(false)
+        .show_rows(ui, height, log.len(), |ui, range| {
+            for record in log.range(range) {
+                let text = RichText::new(record.to_string()).monospace();
+                ui.label(match record.level {
+                    Level::ERROR => text.color(Color32::LIGHT_RED),
+                    Level::WARN => text.color(Color32::YELLOW),
+                    Level::INFO => text,
+                    _ => text.weak(),
+                });
+            }
+        });
+}
@@ -0,0 +1,711 @@
+//! What the user can change in the settings dialog: how the machine behaves, how the game looks,
+//! sounds and is played, and how easy it is to see.
+//!
+//! The settings take effect as soon as they change, and are kept for next time in `gui.toml` in
+//! the configuration directory, `$XDG_CONFIG_HOME/chip8` or `~/.config/chip8`:
+//!
+//! ```toml
+//! speed = 700
+//! vf_reset = true
+//! shift_in_place = false
+//! keep_index = false
+//! jump_vx = false
+//! lit = 0xFFFFFF
+//! unlit = 0x000000
+//! volume = 50
+//! keys = ["X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V"]
+//! shaders = ["lcd", "bloom"]
+//! high_contrast = false
+//! reduce_flicker = false
+//! large_text = false
+//! ```
+//!
+//! `keys` holds the host key of each keypad key from 0 to F, by egui's names for them, and
+//! `shaders` the post-processing shaders, in the order they run. Settings left out keep their
+//! defaults.
+
+use chip8_core::builder::Config;
+use chip8_core::keyboard::LAYOUT;
+use chip8_core::quirks::Quirks;
+use eframe::egui::{self, Color32, Key};
+use emu_frontend::config::{self, parse_color, parse_strings};
+use emu_frontend::input;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use toml::Value;
+
+/// Host keys mapped onto the keypad by default, in the same positions as [`LAYOUT`]: the
+/// left-hand side of a QWERTY keyboard, as in `chip8`.
+const QWERTY: [[Key; 4]; 4] = [
+    [Key::Num1, Key::Num2, Key::Num3, Key::Num4],
+    [Key::Q, Key::W, Key::E, Key::R],
+    [Key::A, Key::S, Key::D, Key::F],
+    [Key::Z, Key::X, Key::C, Key::V],
+];
+
+/// The quirks offered as a starting point, by the interpreter they come from.
+const PRESETS: [(&str, Quirks); 3] = [
+    ("CHIP-8", Quirks::CHIP8),
+    ("SUPER-CHIP", Quirks::SUPER_CHIP),
+    ("XO-CHIP", Quirks::XO_CHIP),
+];
+
+/// The least contrast between lit and unlit pixels with the high contrast setting: 7:1, the
+/// ratio WCAG asks of text for its highest level of accessibility.
+pub const MIN_CONTRAST: f32 = 7.0;
+
+/// The colors of lit and unlit pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub on: Color32,
+    pub off: Color32,
+}
+
+impl Palette {
+    /// Returns the contrast ratio between lit and unlit pixels, as WCAG defines it: from 1 for
+    /// the same color to 21 for black and white.
+    pub fn contrast(&self) -> f32 {
+        let (on, off) = (luminance(self.on), luminance(self.off));
+        (on.max(off) + 0.05) / (on.min(off) + 0.05)
+    }
+
+    /// Returns the palette if it has a contrast of at least `min`, or else one that does: unlit
+    /// pixels become black or white, whichever stands out more from lit ones, and if that is not
+    /// enough, lit pixels become the other.
+    pub fn with_contrast(self, min: f32) -> Palette {
+        if self.contrast() >= min {
+            return self;
+        }
+        let (dark, light) = (Color32::BLACK, Color32::WHITE);
+        let on_dark = Palette { off: dark, ..self };
+        let on_light = Palette { off: light, ..self };
+        let palette = if on_dark.contrast() >= on_light.contrast() {
+            on_dark
+        } else {
+            on_light
+        };
+        match palette.contrast() >= min {
+            true => palette,
+            false if palette.off == dark => Palette {
+                on: light,
+                ..palette
+            },
+            false => Palette {
+                on: dark,
+                ..palette
+            },
+        }
+    }
+}
+
+/// Returns the relative luminance of `color`, from 0 for black to 1 for white.
+fn luminance(color: Color32) -> f32 {
+    let linear = |channel: u8| {
+        let c = f32::from(channel) / 255.0;
+        if c <= 0.039_28 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r()) + 0.7152 * linear(color.g()) + 0.0722 * linear(color.b())
+}
+
+/// The palettes offered by name, the first of which is the default.
+pub const PALETTES: [(&str, Palette); 4] = [
+    (
+        "Classic",
+        Palette {
+            on: Color32::WHITE,
+            off: Color32::BLACK,
+        },
+    ),
+    (
+        "Amber",
+        Palette {
+            on: Color32::from_rgb(0xFF, 0xB0, 0x00),
+            off: Color32::from_rgb(0x1A, 0x10, 0x00),
+        },
+    ),
+    (
+        "Phosphor",
+        Palette {
+            on: Color32::from_rgb(0x33, 0xFF, 0x66),
+            off: Color32::from_rgb(0x00, 0x1A, 0x08),
+        },
+    ),
+    (
+        "LCD",
+        Palette {
+            on: Color32::from_rgb(0x0F, 0x38, 0x0F),
+            off: Color32::from_rgb(0x9B, 0xBC, 0x0F),
+        },
+    ),
+];
+
+/// Everything the settings dialog changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub quirks: Quirks,
+    /// Instructions executed per second.
+    pub speed: u32,
+    pub palette: Palette,
+    /// The host key of each keypad key, indexed by keypad key.
+    pub keys: [Key; 16],
+    /// Volume of the tone in recorded videos, in percent.
+    pub volume: u8,
+    /// The names of the shaders the game is drawn through, in order.
+    pub shaders: Vec<String>,
+    /// Whether the game is drawn with at least [`MIN_CONTRAST`], whatever the palette.
+    pub high_contrast: bool,
+    /// Whether each frame of the game is blended with the one before, so that sprites erased and
+    /// drawn again from one frame to the next do not flicker.
+    pub reduce_flicker: bool,
+    /// Whether the text shown over the game is larger.
+    pub large_text: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let mut keys = [Key::Num0; 16];
+        for (row, host) in LAYOUT.iter().zip(QWERTY.iter()) {
+            for (&key, &host) in row.iter().zip(host.iter()) {
+                keys[key as usize] = host;
+            }
+        }
+        let config = Config::default();
+        Settings {
+            quirks: config.quirks,
+            speed: config.speed,
+            palette: PALETTES[0].1,
+            keys,
+            volume: 50,
+            shaders: Vec::new(),
+            high_contrast: false,
+            reduce_flicker: false,
+            large_text: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the colors the game is drawn in: those of the palette, with enough contrast if
+    /// the settings ask for it.
+    pub fn game_palette(&self) -> Palette {
+        if self.high_contrast {
+            self.palette.with_contrast(MIN_CONTRAST)
+        } else {
+            self.palette
+        }
+    }
+
+    /// Maps the host key `key` to keypad key `keypad`. The keypad key that had it before takes
+    /// the old key of `keypad` instead, so that no host key presses two keypad keys.
+    pub fn bind(&mut self, keypad: u8, key: Key) {
+        input::bind(&mut self.keys, keypad as usize & 0xF, key);
+    }
+
+    /// Reads the settings kept from last time, or the defaults if there are none.
+    pub fn load() -> Result<Settings, String> {
+        let path = config_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => text
+                .parse()
+                .map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    /// Keeps the settings for next time.
+    pub fn save(&self) -> Result<(), String> {
+        let path = config_path();
+        let error = |err: io::Error| format!("{}: {}", path.display(), err);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(error)?;
+        }
+        fs::write(&path, self.to_string()).map_err(error)
+    }
+}
+
+/// Returns the directory holding the settings and the user's shaders.
+pub fn config_dir() -> PathBuf {
+    config::config_dir("chip8")
+}
+
+/// Returns the directory holding the user's shaders.
+pub fn shaders_dir() -> PathBuf {
+    config_dir().join("shaders")
+}
+
+/// Returns where the settings are kept.
+fn config_path() -> PathBuf {
+    config_dir().join("gui.toml")
+}
+
+/// Returns where the window's size, position, full screen state and zoom are kept, which the
+/// window saves itself.
+pub fn window_path() -> PathBuf {
+    config_dir().join("gui-window.ron")
+}
+
+/// Parses an array of the names of 16 keys, such as `["X", "1", …]`.
+fn parse_keys(value: &Value) -> Option<[Key; 16]> {
+    let keys = input::parse_keys(value, 16)?;
+    <[Key; 16]>::try_from(keys).ok()
+}
+
+impl FromStr for Settings {
+    type Err = String;
+
+    /// Parses `gui.toml`.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut settings = Settings::default();
+        for (key, value) in config::parse(text)? {
+            let error = || config::invalid(&key, &value);
+            let flag = || value.as_bool().ok_or_else(error);
+            let quirks = &mut settings.quirks;
+            match key.as_str() {
+                "speed" => {
+                    settings.speed = value
+                        .as_integer()
+                        .and_then(|speed| u32::try_from(speed).ok())
+                        .filter(|&speed| speed > 0)
+                        .ok_or_else(error)?
+                }
+                "vf_reset" => quirks.vf_reset = flag()?,
+                "shift_in_place" => quirks.shift_in_place = flag()?,
+                "keep_index" => quirks.keep_index = flag()?,
+                "jump_vx" => quirks.jump_vx = flag()?,
+                "lit" => settings.palette.on = parse_color(&value).ok_or_else(error)?,
+                "unlit" => settings.palette.off = parse_color(&value).ok_or_else(error)?,
+                "volume" => {
+                    settings.volume = value
+                        .as_integer()
+                        .and_then(|volume| u8::try_from(volume).ok())
+                        .filter(|&volume| volume <= 100)
+                        .ok_or_else(error)?
+                }
+                "keys" => settings.keys = parse_keys(&value).ok_or_else(error)?,
+                "shaders" => {
+                    let names = parse_strings(&value).ok_or_else(error)?;
+                    settings.shaders = names.into_iter().map(String::from).collect();
+                }
+                "high_contrast" => settings.high_contrast = flag()?,
+                "reduce_flicker" => settings.reduce_flicker = flag()?,
+                "large_text" => settings.large_text = flag()?,
+                _ => return Err(error()),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let color = config::color;
+        writeln!(f, "# Settings of chip8-gui, written whenever they change.")?;
+        writeln!(f, "speed = {}", self.speed)?;
+        writeln!(f, "vf_reset = {}", self.quirks.vf_reset)?;
+        writeln!(f, "shift_in_place = {}", self.quirks.shift_in_place)?;
+        writeln!(f, "keep_index = {}", self.quirks.keep_index)?;
+        writeln!(f, "jump_vx = {}", self.quirks.jump_vx)?;
+        writeln!(f, "lit = {}", color(self.palette.on))?;
+        writeln!(f, "unlit = {}", color(self.palette.off))?;
+        writeln!(f, "volume = {}", self.volume)?;
+        writeln!(f, "keys = {}", input::key_names(&self.keys))?;
+        writeln!(
+            f,
+            "shaders = {}",
+            config::strings(self.shaders.iter().map(String::as_str))
+        )?;
+        writeln!(f, "high_contrast = {}", self.high_contrast)?;
+        writeln!(f, "reduce_flicker = {}", self.reduce_flicker)?;
+        writeln!(f, "large_text = {}", self.large_text)
+    }
+}
+
+/// The settings dialog, whose changes take effect as they are made.
+#[derive(Default)]
+pub struct Dialog {
+    pub open: bool,
+    draft: Settings,
+    /// The settings when the dialog was opened, to go back to.
+    original: Settings,
+    /// The keypad key waiting for a host key to be pressed.
+    binding: Option<u8>,
+}
+
+impl Dialog {
+    /// Opens the dialog on `settings`.
+    pub fn open(&mut self, settings: &Settings) {
+        self.open = true;
+        self.draft = settings.clone();
+        self.original = settings.clone();
+        self.binding = None;
+    }
+
+    /// Returns `true` while the dialog waits for a key to bind, so the game should not see it.
+    pub fn is_binding(&self) -> bool {
+        self.open && self.binding.is_some()
+    }
+
+    /// Shows the dialog if it is open, offering the shaders named in `shaders`. Returns the new
+    /// settings whenever the user changes them.
+    pub fn show(&mut self, ctx: &egui::Context, shaders: &[String]) -> Option<Settings> {
+        if !self.open {
+            return None;
+        }
+        let before = self.draft.clone();
+        if let Some(keypad) = self.binding {
+            let pressed = ctx.input(|input| {
+                input.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key, pressed: true, ..
+                    } => Some(*key),
+                    _ => None,
+                })
+            });
+            match pressed {
+                Some(Key::Escape) => self.binding = None,
+                Some(key) => {
+                    self.draft.bind(keypad, key);
+                    self.binding = None;
+                }
+                None => (),
+            }
+        }
+
+        let mut open = true;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label("Changes take effect right away, and are kept for next time.");
+                ui.separator();
+                self.machine(ui);
+                ui.separator();
+                self.palette(ui);
+                ui.separator();
+                self.shaders(ui, shaders);
+                #[cfg(feature = "video")]
+                {
+                    ui.separator();
+                    self.sound(ui);
+                }
+                ui.separator();
+                self.keys(ui);
+                ui.separator();
+                self.accessibility(ui);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Close").clicked() {
+                        self.open = false;
+                    }
+                    if ui.button("Revert").clicked() {
+                        self.draft = self.original.clone();
+                    }
+                    if ui.button("Defaults").clicked() {
+                        self.draft = Settings::default();
+                    }
+                });
+            });
+        self.open &= open;
+        if self.draft != before {
+            Some(self.draft.clone())
+        } else {
+            None
+        }
+    }
+
+    fn machine(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Machine");
+        let preset = PRESETS
+            .iter()
+            .find(|(_, quirks)| *quirks == self.draft.quirks)
+            .map_or("Custom", |(name, _)| name);
+        egui::ComboBox::from_label("Quirks")
+            .selected_text(preset)
+            .show_ui(ui, |ui| {
+                for (name, quirks) in PRESETS.iter() {
+                    ui.selectable_value(&mut self.draft.quirks, *quirks, *name);
+                }
+            });
+        let quirks = &mut self.draft.quirks;
+        ui.checkbox(&mut quirks.vf_reset, "AND, OR and XOR reset VF");
+        ui.checkbox(&mut quirks.shift_in_place, "Shifts change VX in place");
+        ui.checkbox(&mut quirks.keep_index, "Loads and stores leave I unchanged");
+        ui.checkbox(&mut quirks.jump_vx, "BNNN jumps relative to VX");
+        ui.add(
+            egui::Slider::new(&mut self.draft.speed, 1..=100_000)
+                .logarithmic(true)
+                .text("instructions per second"),
+        );
+    }
+
+    fn palette(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Palette");
+        let palette = &mut self.draft.palette;
+        let name = PALETTES
+            .iter()
+            .find(|(_, colors)| colors == palette)
+            .map_or("Custom", |(name, _)| name);
+        egui::ComboBox::from_label("Colors")
+            .selected_text(name)
+            .show_ui(ui, |ui| {
+                for (name, colors) in PALETTES.iter() {
+                    ui.selectable_value(palette, *colors, *name);
+                }
+            });
+        ui.horizontal(|ui| {
+            egui::color_picker::color_edit_button_srgba(
+                ui,
+                &mut palette.on,
+                egui::color_picker::Alpha::Opaque,
+            );
+            ui.label("Lit");
+            egui::color_picker::color_edit_button_srgba(
+                ui,
+                &mut palette.off,
+                egui::color_picker::Alpha::Opaque,
+            );
+            ui.label("Unlit");
+        });
+        ui.label(format!("Contrast {:.1}:1", palette.contrast()));
+    }
+
+    /// The shaders the game is drawn through, chosen from `available` and put in order.
+    fn shaders(&mut self, ui: &mut egui::Ui, available: &[String]) {
+        ui.strong("Shaders");
+        if available.is_empty() {
+            ui.label("Shaders need a graphics card that wgpu can use.");
+            return;
+        }
+        let chosen = &mut self.draft.shaders;
+        let count = chosen.len();
+        let mut moved = None;
+        let mut removed = None;
+        for (index, name) in chosen.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}. {}", index + 1, name));
+                if ui
+                    .add_enabled(index > 0, egui::Button::new("Up").small())
+                    .clicked()
+                {
+                    moved = Some(index - 1);
+                }
+                if ui
+                    .add_enabled(index + 1 < count, egui::Button::new("Down").small())
+                    .clicked()
+                {
+                    moved = Some(index);
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(index);
+                }
+            });
+        }
+        if let Some(index) = moved {
+            chosen.swap(index, index + 1);
+        }
+        if let Some(index) = removed {
+            chosen.remove(index);
+        }
+        egui::ComboBox::from_label("Add a shader")
+            .selected_text("")
+            .show_ui(ui, |ui| {
+                let mut added = None;
+                for name in available.iter().filter(|name| !chosen.contains(name)) {
+                    if ui.selectable_label(false, name).clicked() {
+                        added = Some(name.clone());
+                    }
+                }
+                chosen.extend(added);
+            });
+        ui.label(format!(
+            "Your own are the .wgsl files in {}.",
+            shaders_dir().display()
+        ));
+    }
+
+    /// The window makes no sound, so the volume is that of the videos it records.
+    #[cfg(feature = "video")]
+    fn sound(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Sound");
+        ui.add(
+            egui::Slider::new(&mut self.draft.volume, 0..=100)
+                .suffix("%")
+                .text("Volume of recorded videos"),
+        );
+    }
+
+    fn keys(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Keys");
+        ui.label("Click a key, then press the host key for it.");
+        egui::Grid::new("keys").show(ui, |ui| {
+            for row in LAYOUT.iter() {
+                for &keypad in row.iter() {
+                    let text = if self.binding == Some(keypad) {
+                        format!("{:X}: …", keypad)
+                    } else {
+                        format!("{:X}: {}", keypad, self.draft.keys[keypad as usize].name())
+                    };
+                    if ui.button(text).clicked() {
+                        self.binding = Some(keypad);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn accessibility(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Accessibility");
+        let contrast = format!(
+            "Draw the game with a contrast of at least {}:1",
+            MIN_CONTRAST
+        );
+        ui.checkbox(&mut self.draft.high_contrast, contrast);
+        ui.checkbox(
+            &mut self.draft.reduce_flicker,
+            "Reduce flicker by blending each frame with the one before",
+        );
+        ui.checkbox(&mut self.draft.large_text, "Larger text over the game");
+        ui.label(
+            "F10 moves to the menu bar. Tab and the arrow keys move between controls, Space or \
+             Enter uses one, and Escape leaves them for the game.",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::keyboard;
+
+    #[test]
+    fn keys() {
+        let mut settings = Settings::default();
+        let keypad_key = |settings: &Settings, key| {
+            input::button_for(&settings.keys, key).map(|keypad| keypad as u8)
+        };
+        // The default keys are those of `chip8`.
+        for (host, c) in [
+            (Key::Num1, '1'),
+            (Key::X, 'x'),
+            (Key::V, 'v'),
+            (Key::E, 'e'),
+        ] {
+            assert_eq!(keypad_key(&settings, host), keyboard::key_for_char(c));
+        }
+        assert_eq!(keypad_key(&settings, Key::P), None);
+
+        settings.bind(0x5, Key::P);
+        assert_eq!(keypad_key(&settings, Key::P), Some(0x5));
+        assert_eq!(keypad_key(&settings, Key::W), None);
+        // Taking the key of another keypad key swaps them.
+        settings.bind(0x5, Key::X);
+        assert_eq!(keypad_key(&settings, Key::X), Some(0x5));
+        assert_eq!(keypad_key(&settings, Key::P), Some(0x0));
+    }
+
+    #[test]
+    fn file() {
+        let mut settings = Settings {
+            quirks: Quirks::SUPER_CHIP,
+            speed: 100_000,
+            palette: PALETTES[3].1,
+            volume: 0,
+            reduce_flicker: true,
+            ..Settings::default()
+        };
+        settings.bind(0x5, Key::ArrowUp);
+        assert_eq!(settings.to_string().parse(), Ok(settings.clone()));
+
+        let text = "speed = 1000  # fast\n\nlit = 0xFF0000\n";
+        let parsed: Settings = text.parse().unwrap();
+        assert_eq!(parsed.speed, 1000);
+        assert_eq!(parsed.palette.on, Color32::RED);
+        assert_eq!(parsed.palette.off, Settings::default().palette.off);
+
+        // A `#` in a string does not start a comment.
+        let shaders = Settings {
+            shaders: vec!["#1".to_string()],
+            ..Settings::default()
+        };
+        assert_eq!(shaders.to_string().parse(), Ok(shaders));
+
+        assert!("\nvf_reset = yes"
+            .parse::<Settings>()
+            .unwrap_err()
+            .starts_with("line 2:"));
+        for text in [
+            "speed = 0",
+            "lit = 0x1000000",
+            "volume = 101",
+            "keys = [\"X\", \"1\"]",
+            "keys = [\"X\", \"1\", \"2\", \"3\", \"Q\", \"W\", \"E\", \"A\", \"S\", \"D\", \"Z\", \"C\", \"4\", \"R\", \"F\", \"Nope\"]",
+            "colour = 0x000000",
+        ] {
+            let err = text.parse::<Settings>().unwrap_err();
+            assert!(err.starts_with("invalid setting `"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn contrast() {
+        let classic = PALETTES[0].1;
+        assert!((classic.contrast() - 21.0).abs() < 0.01);
+        assert_eq!(classic.with_contrast(MIN_CONTRAST), classic);
+        let gray = Color32::from_gray(0x77);
+        assert!(
+            (Palette {
+                on: gray,
+                off: gray
+            }
+            .contrast()
+                - 1.0)
+                .abs()
+                < 0.01
+        );
+
+        for (_, palette) in PALETTES.iter() {
+            let raised = palette.with_contrast(MIN_CONTRAST);
+            assert!(raised.contrast() >= MIN_CONTRAST, "{:?}", raised);
+        }
+        // Unlit pixels go first, to whichever of black and white suits the lit color.
+        let dim = Palette {
+            on: Color32::from_rgb(0x00, 0x00, 0x99),
+            off: Color32::from_rgb(0x00, 0x00, 0x66),
+        };
+        assert_eq!(
+            dim.with_contrast(MIN_CONTRAST),
+            Palette {
+                off: Color32::WHITE,
+                ..dim
+            }
+        );
+        // A gray too dull for either changes too.
+        let dull = Palette {
+            on: gray,
+            off: gray,
+        };
+        assert!(dull.with_contrast(MIN_CONTRAST).contrast() > 20.9);
+
+        let settings = Settings {
+            palette: dull,
+            ..Settings::default()
+        };
+        assert_eq!(settings.game_palette(), dull);
+        let settings = Settings {
+            high_contrast: true,
+            ..settings
+        };
+        assert!(settings.game_palette().contrast() > 20.9);
+    }
+}
@@ -0,0 +1,70 @@
+//! Recording the game as a video with `ffmpeg`, through `emu-frontend`, with the tone rendered
+//! into the sound track.
+
+use chip8_core::audio::Recording;
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::frontend::Audio;
+use chip8_core::TIMER_PERIOD;
+use eframe::egui::ColorImage;
+use emu_frontend::video::{self, Stream};
+use std::time::Duration;
+
+pub use emu_frontend::video::{Format, FORMATS};
+
+/// Samples of the sound track per second.
+const SAMPLE_RATE: u32 = 48_000;
+
+/// A video of the game being recorded.
+pub struct Recorder {
+    video: video::Recorder,
+    tone: Recording,
+}
+
+impl Recorder {
+    /// Starts recording a video named after the ROM at `rom`, beside it, such as `pong-1.mp4`
+    /// for `pong.ch8`.
+    pub fn start(rom: &str, format: Format) -> Result<Recorder, String> {
+        let stream = Stream {
+            size: [WIDTH, HEIGHT],
+            frame_rate: (1.0 / TIMER_PERIOD.as_secs_f64()).round() as u32,
+            sample_rate: SAMPLE_RATE,
+        };
+        Ok(Recorder {
+            video: video::Recorder::start(rom, format, stream)?,
+            tone: Recording::new(SAMPLE_RATE),
+        })
+    }
+
+    /// Returns how long the video is so far.
+    pub fn duration(&self) -> Duration {
+        self.video.duration()
+    }
+
+    /// Sets how loud the tone is from now on, in percent.
+    pub fn set_volume(&mut self, percent: u8) {
+        self.video.set_volume(percent);
+    }
+
+    /// Returns `true` if recording failed, and the recorder should be finished to find out why.
+    pub fn failed(&self) -> bool {
+        self.video.failed()
+    }
+
+    /// Adds a frame showing `image`, with the tone if `sounding`.
+    pub fn frame(&mut self, image: &ColorImage, sounding: bool) {
+        self.tone.play(sounding);
+        let samples: Vec<i16> = self
+            .tone
+            .drain()
+            .into_iter()
+            .flat_map(|event| event.samples)
+            .collect();
+        self.video.frame(image, &samples);
+    }
+
+    /// Stops recording and joins the frames with the sound track. Returns what happened, for
+    /// the status bar.
+    pub fn finish(self) -> Result<String, String> {
+        self.video.finish()
+    }
+}
@@ -0,0 +1,583 @@
+//! A libretro core, so that RetroArch and other libretro frontends can run CHIP-8 ROMs with their
+//! own video, audio, input, save states, rewind, netplay and cheats.
+//!
+//! The keypad can be played from a keyboard, on the left-hand side of a QWERTY layout as in
+//! `chip8`, or from a gamepad, where the directions are 2, 4, 6 and 8, which most games use for
+//! movement. Save states are the same as `chip8`'s, padded to the fixed size libretro expects, and
+//! the 4 KB of memory is exposed as system RAM for cheat searches and achievements. Cheats are
+//! `chip8` poke codes such as `300:05`, several of which can be joined with `+`.
+
+mod retro;
+
+use crate::retro::*;
+use chip8_core::cheat::Cheat;
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::keyboard;
+use chip8_core::rom::Rom;
+use chip8_core::state;
+use chip8_core::CHIP8;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+
+/// Audio samples per second.
+const SAMPLE_RATE: usize = 44100;
+
+/// Frequency of the tone played while the sound timer runs, in hertz.
+const TONE: usize = 440;
+
+/// Amplitude of the tone.
+const VOLUME: i16 = 0x1000;
+
+/// Host keys for the keypad, in the order of [`keyboard::key_for_char`]'s layout. libretro's key
+/// codes for letters and digits are their lowercase ASCII codes.
+const KEYS: &str = "1234qwerasdfzxcv";
+
+/// Gamepad buttons and the keypad keys they press.
+const BUTTONS: [(c_uint, u8); 10] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x1),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x3),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xF),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xE),
+];
+
+/// Callbacks given by the frontend.
+#[derive(Clone, Copy)]
+struct Frontend {
+    environment: Option<retro_environment_t>,
+    video_refresh: Option<retro_video_refresh_t>,
+    audio_sample_batch: Option<retro_audio_sample_batch_t>,
+    input_poll: Option<retro_input_poll_t>,
+    input_state: Option<retro_input_state_t>,
+}
+
+static FRONTEND: Mutex<Frontend> = Mutex::new(Frontend {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+/// The loaded game.
+struct Core {
+    chip: CHIP8,
+    rom: Rom,
+    rom_hash: u64,
+    /// Set once the program fails, after which the last frame stays on screen.
+    stopped: bool,
+    /// Cheats by the index the frontend gave them.
+    cheats: BTreeMap<c_uint, Vec<Cheat>>,
+    /// One XRGB8888 pixel per pixel of the display.
+    framebuffer: [u32; WIDTH * HEIGHT],
+    /// Samples played of the tone, so that it carries on smoothly from one frame to the next.
+    phase: usize,
+    /// The memory the frontend reads and writes between calls. A pointer into the machine's own
+    /// memory would be invalidated as soon as the machine changed it, so this copy is handed out
+    /// instead, and synchronized with the machine around everything that uses memory.
+    ram: Box<[Cell<u8>]>,
+}
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+impl Core {
+    fn new(rom: Rom) -> Option<Self> {
+        let mut chip = CHIP8::new();
+        chip.load_rom(&rom).ok()?;
+        Some(Core {
+            rom_hash: state::rom_hash(rom.data()),
+            rom,
+            stopped: false,
+            cheats: BTreeMap::new(),
+            framebuffer: [0; WIDTH * HEIGHT],
+            phase: 0,
+            ram: chip.memory().iter().map(|&byte| Cell::new(byte)).collect(),
+            chip,
+        })
+    }
+
+    /// Takes the changes the frontend made to memory.
+    fn take_ram(&mut self) {
+        for (byte, cell) in self.chip.memory_mut().iter_mut().zip(self.ram.iter()) {
+            *byte = cell.get();
+        }
+    }
+
+    /// Shows the frontend memory as the machine left it.
+    fn show_ram(&self) {
+        for (cell, &byte) in self.ram.iter().zip(self.chip.memory().iter()) {
+            cell.set(byte);
+        }
+    }
+
+    /// Powers the machine off and on again, writing the one-off cheats again.
+    fn reset(&mut self) {
+        let mut chip = CHIP8::new();
+        if chip.load_rom(&self.rom).is_ok() {
+            self.chip = chip;
+            self.stopped = false;
+            for cheat in self.cheats.values().flatten() {
+                cheat.apply(&mut self.chip);
+            }
+        }
+    }
+
+    /// Sets the keypad from the keyboard and the gamepad in the first port.
+    fn read_keys(&mut self, input_state: retro_input_state_t) {
+        let mut pressed = [false; 16];
+        for c in KEYS.chars() {
+            if input_state(0, RETRO_DEVICE_KEYBOARD, 0, c as c_uint) != 0 {
+                if let Some(key) = keyboard::key_for_char(c) {
+                    pressed[key as usize] = true;
+                }
+            }
+        }
+        for &(button, key) in BUTTONS.iter() {
+            if input_state(0, RETRO_DEVICE_JOYPAD, 0, button) != 0 {
+                pressed[key as usize] = true;
+            }
+        }
+        for (key, &pressed) in pressed.iter().enumerate() {
+            self.chip.keyboard_mut().set(key as u8, pressed);
+        }
+    }
+
+    /// Runs the program for one frame and updates the timers and the framebuffer.
+    fn run_frame(&mut self) {
+        if !self.stopped {
+            for _ in 0..self.chip.config().steps_per_frame() {
+                for cheat in self.cheats.values().flatten().filter(|cheat| cheat.frozen) {
+                    cheat.apply(&mut self.chip);
+                }
+                if self.chip.step().is_err() {
+                    self.stopped = true;
+                    break;
+                }
+            }
+            self.chip.tick();
+        }
+        let pixels = self.chip.display().screen().iter().flatten();
+        for (pixel, &lit) in self.framebuffer.iter_mut().zip(pixels) {
+            *pixel = if lit { 0x00FF_FFFF } else { 0 };
+        }
+    }
+
+    /// Returns a frame of stereo samples, playing the tone while the sound timer runs.
+    fn audio_frame(&mut self) -> Vec<i16> {
+        let frames = SAMPLE_RATE / 60;
+        let sounding = !self.stopped && self.chip.timer().is_sounding();
+        let mut samples = Vec::with_capacity(frames * 2);
+        for _ in 0..frames {
+            let sample = match sounding {
+                true if (self.phase * TONE * 2 / SAMPLE_RATE).is_multiple_of(2) => VOLUME,
+                true => -VOLUME,
+                false => 0,
+            };
+            samples.extend_from_slice(&[sample, sample]);
+            self.phase = (self.phase + 1) % SAMPLE_RATE;
+        }
+        samples
+    }
+}
+
+/// Locks the callbacks given by the frontend.
+fn frontend() -> MutexGuard<'static, Frontend> {
+    FRONTEND.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Locks the loaded game.
+fn core() -> MutexGuard<'static, Option<Core>> {
+    CORE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *core() = None;
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_info` to fill in.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info) {
+    *info = retro_system_info {
+        library_name: b"chip8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a `retro_system_av_info` to fill in.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info) {
+    *info = retro_system_av_info {
+        geometry: retro_game_geometry {
+            base_width: WIDTH as c_uint,
+            base_height: HEIGHT as c_uint,
+            max_width: WIDTH as c_uint,
+            max_height: HEIGHT as c_uint,
+            aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        },
+        timing: retro_system_timing {
+            fps: 60.0,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: retro_environment_t) {
+    frontend().environment = Some(environment);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: retro_video_refresh_t) {
+    frontend().video_refresh = Some(video_refresh);
+}
+
+/// Single samples are not used, since a whole frame of audio is sent at once.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_: retro_audio_sample_t) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: retro_audio_sample_batch_t) {
+    frontend().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: retro_input_poll_t) {
+    frontend().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: retro_input_state_t) {
+    frontend().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = core().as_mut() {
+        core.reset();
+        core.show_ram();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let frontend = *frontend();
+    if let Some(input_poll) = frontend.input_poll {
+        input_poll();
+    }
+    let mut core = core();
+    let core = match core.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+    if let Some(input_state) = frontend.input_state {
+        core.read_keys(input_state);
+    }
+    core.take_ram();
+    core.run_frame();
+    core.show_ram();
+    if let Some(video_refresh) = frontend.video_refresh {
+        video_refresh(
+            core.framebuffer.as_ptr() as *const c_void,
+            WIDTH as c_uint,
+            HEIGHT as c_uint,
+            WIDTH * 4,
+        );
+    }
+    let samples = core.audio_frame();
+    if let Some(audio_sample_batch) = frontend.audio_sample_batch {
+        audio_sample_batch(samples.as_ptr(), samples.len() / 2);
+    }
+}
+
+/// Returns the size of every save state: a length, then the state, padded to the largest one.
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    4 + state::max_size()
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let mut core = core();
+    let core = match core.as_mut() {
+        Some(core) => core,
+        None => return false,
+    };
+    core.take_ram();
+    let bytes = state::save(&core.chip.snapshot(), core.rom_hash);
+    if 4 + bytes.len() > size {
+        return false;
+    }
+    let out = slice::from_raw_parts_mut(data as *mut u8, size);
+    out[..4].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out[4..4 + bytes.len()].copy_from_slice(&bytes);
+    out[4 + bytes.len()..].iter_mut().for_each(|byte| *byte = 0);
+    true
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core = core();
+    let core = match core.as_mut() {
+        Some(core) => core,
+        None => return false,
+    };
+    let bytes = slice::from_raw_parts(data as *const u8, size);
+    let len = match bytes.get(..4) {
+        Some(len) => u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize,
+        None => return false,
+    };
+    match bytes.get(4..4 + len) {
+        Some(bytes) if state::restore(&mut core.chip, bytes, core.rom_hash).is_ok() => {
+            core.stopped = false;
+            core.show_ram();
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    if let Some(core) = core().as_mut() {
+        core.cheats.clear();
+    }
+}
+
+/// # Safety
+///
+/// `code` must be a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    let mut core = core();
+    let core = match core.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+    core.cheats.remove(&index);
+    if !enabled {
+        return;
+    }
+    let code = CStr::from_ptr(code).to_string_lossy();
+    let cheats: Result<Vec<Cheat>, _> = code.split('+').map(|code| code.trim().parse()).collect();
+    if let Ok(cheats) = cheats {
+        core.take_ram();
+        for cheat in &cheats {
+            cheat.apply(&mut core.chip);
+        }
+        core.show_ram();
+        core.cheats.insert(index, cheats);
+    }
+}
+
+/// # Safety
+///
+/// `game` must point to a `retro_game_info` whose data is `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const retro_game_info) -> bool {
+    let game = match game.as_ref() {
+        Some(game) if !game.data.is_null() => game,
+        _ => return false,
+    };
+    let data = slice::from_raw_parts(game.data as *const u8, game.size).to_vec();
+    let loaded = match Rom::from_bytes(data).ok().and_then(Core::new) {
+        Some(core) => core,
+        None => return false,
+    };
+    let environment = frontend().environment;
+    if let Some(environment) = environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut c_uint as *mut c_void,
+        ) {
+            return false;
+        }
+    }
+    *core() = Some(loaded);
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _type: c_uint,
+    _info: *const retro_game_info,
+    _num: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match core().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.ram.as_ptr() as *mut c_void,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match core().as_ref() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.ram.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Serializes the tests, which share the loaded game.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    static FRAME: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+    static SAMPLES: AtomicUsize = AtomicUsize::new(0);
+    static PRESS_A: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn environment(_cmd: c_uint, _data: *mut c_void) -> bool {
+        true
+    }
+
+    extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        assert_eq!(pitch, width as usize * 4);
+        let pixels =
+            unsafe { slice::from_raw_parts(data as *const u32, (width * height) as usize) };
+        *FRAME.lock().unwrap() = pixels.to_vec();
+    }
+
+    extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+        let samples = unsafe { slice::from_raw_parts(data, frames * 2) };
+        let loud = samples.iter().filter(|&&sample| sample != 0).count();
+        SAMPLES.store(loud, Ordering::SeqCst);
+        frames
+    }
+
+    extern "C" fn input_poll() {}
+
+    extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+        let pressed = port == 0
+            && device == RETRO_DEVICE_JOYPAD
+            && id == RETRO_DEVICE_ID_JOYPAD_A
+            && PRESS_A.load(Ordering::SeqCst);
+        pressed as i16
+    }
+
+    /// Loads `rom` with every callback set.
+    fn load(rom: &[u8]) -> MutexGuard<'static, ()> {
+        let lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        retro_set_environment(environment);
+        retro_set_video_refresh(video_refresh);
+        retro_set_audio_sample_batch(audio_sample_batch);
+        retro_set_input_poll(input_poll);
+        retro_set_input_state(input_state);
+        PRESS_A.store(false, Ordering::SeqCst);
+        let game = retro_game_info {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        assert!(unsafe { retro_load_game(&game) });
+        lock
+    }
+
+    #[test]
+    fn run() {
+        // 0x200: LD V0, K; LD ST, V0; LD F, V0; DRW V0, V0, 5; JP 0x208
+        let _lock = load(&[0xF0, 0x0A, 0xF0, 0x18, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x08]);
+        retro_run();
+        assert!(FRAME.lock().unwrap().iter().all(|&pixel| pixel == 0));
+        assert_eq!(SAMPLES.load(Ordering::SeqCst), 0);
+
+        // A on the gamepad is 5, which draws the font's 5 at (5, 5) and beeps for 5 frames.
+        PRESS_A.store(true, Ordering::SeqCst);
+        retro_run();
+        let frame = FRAME.lock().unwrap().clone();
+        assert_eq!(frame.len(), WIDTH * HEIGHT);
+        assert_eq!(frame[5 * WIDTH + 5..5 * WIDTH + 9], [0x00FF_FFFF; 4]);
+        assert_eq!(SAMPLES.load(Ordering::SeqCst), SAMPLE_RATE / 60 * 2);
+        retro_unload_game();
+    }
+
+    #[test]
+    fn serialize() {
+        // 0x200: ADD V0, 1; JP 0x200
+        let _lock = load(&[0x70, 0x01, 0x12, 0x00]);
+        let size = retro_serialize_size();
+        let mut saved = vec![0xAAu8; size];
+        retro_run();
+        assert!(unsafe { retro_serialize(saved.as_mut_ptr() as *mut c_void, size) });
+        let v0 = core().as_ref().unwrap().chip.variable()[0];
+        retro_run();
+        assert_ne!(core().as_ref().unwrap().chip.variable()[0], v0);
+        assert!(unsafe { retro_unserialize(saved.as_ptr() as *const c_void, size) });
+        assert_eq!(core().as_ref().unwrap().chip.variable()[0], v0);
+        assert!(!unsafe { retro_unserialize(saved.as_ptr() as *const c_void, 3) });
+        retro_unload_game();
+    }
+
+    #[test]
+    fn cheats_and_memory() {
+        // 0x200: JP 0x200
+        let _lock = load(&[0x12, 0x00]);
+        assert_eq!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM), 4096);
+        let code = CStr::from_bytes_with_nul(b"300:05+302=0102\0").unwrap();
+        unsafe { retro_cheat_set(0, true, code.as_ptr()) };
+        let memory = retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM) as *mut u8;
+        unsafe { *memory.add(0x300) = 0 };
+        retro_run();
+        let memory = unsafe { slice::from_raw_parts(memory, 4096) };
+        assert_eq!(memory[0x300..0x304], [0x05, 0x00, 0x01, 0x02]);
+
+        retro_cheat_reset();
+        retro_reset();
+        assert_eq!(core().as_ref().unwrap().chip.memory()[0x300..0x304], [0; 4]);
+        retro_unload_game();
+        assert!(retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM).is_null());
+    }
+}
@@ -0,0 +1,150 @@
+//! Python bindings for the emulator, built with PyO3 into the `chip8` extension module.
+//!
+//! The module has one class, `Chip8`, which runs a ROM a frame at a time for scripts, bots and
+//! reinforcement learning agents, and the `Chip8Error` exception it raises when a ROM or save
+//! state can't be loaded or the program stops:
+//!
+//! ```python
+//! from chip8 import Chip8
+//! chip = Chip8(open("brix.ch8", "rb").read(), seed=1)
+//! chip.press(0x4)
+//! chip.step_frame()
+//! chip.framebuffer.shape  # (32, 64), with numpy installed
+//! ```
+//!
+//! Wheels are built with maturin, as described in the README.
+
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::rng::Rng;
+use chip8_core::rom::Rom;
+use chip8_core::state;
+use chip8_core::CHIP8;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+create_exception!(
+    chip8,
+    Chip8Error,
+    PyException,
+    "A ROM or save state could not be loaded, or the program stopped."
+);
+
+fn error(err: impl ToString) -> PyErr {
+    Chip8Error::new_err(err.to_string())
+}
+
+/// A CHIP-8 machine running a ROM.
+///
+/// ``seed`` seeds the random number generator, so that runs can be reproduced; without one it is
+/// seeded from the clock.
+#[pyclass(module = "chip8", unsendable)]
+struct Chip8 {
+    chip: CHIP8,
+    /// Hash of the ROM, which save states are checked against.
+    rom_hash: u64,
+    /// Why the program stopped, after which frames raise it again.
+    stopped: Option<String>,
+}
+
+#[pymethods]
+impl Chip8 {
+    #[new]
+    #[pyo3(signature = (rom, seed = None))]
+    fn new(rom: Vec<u8>, seed: Option<u32>) -> PyResult<Self> {
+        let mut chip = CHIP8::new();
+        if let Some(seed) = seed {
+            chip.set_rng(Rng::new(seed));
+        }
+        let rom_hash = state::rom_hash(&rom);
+        Rom::from_bytes(rom)
+            .and_then(|rom| chip.load_rom(&rom))
+            .map_err(error)?;
+        Ok(Chip8 {
+            chip,
+            rom_hash,
+            stopped: None,
+        })
+    }
+
+    /// Runs the program for ``frames`` frames of 1/60 second, raising Chip8Error if it stops.
+    #[pyo3(signature = (frames = 1))]
+    fn step_frame(&mut self, frames: u32) -> PyResult<()> {
+        for _ in 0..frames {
+            if let Some(err) = &self.stopped {
+                return Err(error(err));
+            }
+            for _ in 0..self.chip.config().steps_per_frame() {
+                if let Err(err) = self.chip.step() {
+                    self.stopped = Some(err.to_string());
+                    return Err(error(err));
+                }
+            }
+            self.chip.tick();
+        }
+        Ok(())
+    }
+
+    /// Holds down keypad ``key``, from 0x0 to 0xF.
+    fn press(&mut self, key: u8) {
+        self.chip.keyboard_mut().set(key, true);
+    }
+
+    /// Lets go of keypad ``key``.
+    fn release(&mut self, key: u8) {
+        self.chip.keyboard_mut().set(key, false);
+    }
+
+    /// Whether the sound timer is running and a tone should play.
+    #[getter]
+    fn sounding(&self) -> bool {
+        self.stopped.is_none() && self.chip.timer().is_sounding()
+    }
+
+    /// Returns the display as 64×32 bytes, row by row, 1 for a lit pixel.
+    fn screen<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let pixels: Vec<u8> = self
+            .chip
+            .display()
+            .screen()
+            .iter()
+            .flatten()
+            .map(|&lit| lit as u8)
+            .collect();
+        PyBytes::new(py, &pixels)
+    }
+
+    /// The display as a 32×64 numpy array of ``uint8``, 1 for a lit pixel.
+    ///
+    /// The array is a copy, so it can be kept as an observation while the machine runs on.
+    #[getter]
+    fn framebuffer<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let numpy = py.import("numpy")?;
+        numpy
+            .call_method1("frombuffer", (self.screen(py), numpy.getattr("uint8")?))?
+            .call_method1("reshape", (HEIGHT, WIDTH))
+    }
+
+    /// Returns the state of the machine, in the same format as chip8's save states.
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &state::save(&self.chip.snapshot(), self.rom_hash))
+    }
+
+    /// Restores a state from save_state, raising Chip8Error if it is not of this ROM.
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        state::restore(&mut self.chip, state, self.rom_hash).map_err(error)?;
+        self.stopped = None;
+        Ok(())
+    }
+}
+
+/// Python bindings for the chip8-core CHIP-8 emulator.
+#[pymodule]
+fn chip8(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Chip8>()?;
+    m.add("Chip8Error", m.py().get_type::<Chip8Error>())?;
+    m.add("WIDTH", WIDTH)?;
+    m.add("HEIGHT", HEIGHT)?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+import unittest
+
+from chip8 import HEIGHT, WIDTH, Chip8, Chip8Error
+
+try:
+    import numpy
+except ImportError:
+    numpy = None
+
+# 0x200: LD V0, K; LD ST, V0; LD F, V0; DRW V0, V0, 5; JP 0x208
+DRAW_KEY = bytes([0xF0, 0x0A, 0xF0, 0x18, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x08])
+
+# 0x200: ADD V0, 1; JP 0x200
+COUNT = bytes([0x70, 0x01, 0x12, 0x00])
+
+
+class Chip8Test(unittest.TestCase):
+    def test_frames_and_keys(self):
+        chip = Chip8(DRAW_KEY, seed=1)
+        chip.step_frame()
+        self.assertFalse(chip.sounding)
+        self.assertEqual(chip.screen(), bytes(WIDTH * HEIGHT))
+
+        chip.press(0x5)
+        chip.step_frame()
+        self.assertTrue(chip.sounding)
+        # The top row of the font's 5 is 0xF0, drawn at (5, 5).
+        row = chip.screen()[5 * WIDTH : 6 * WIDTH]
+        self.assertEqual(row[4:10], bytes([0, 1, 1, 1, 1, 0]))
+
+    @unittest.skipIf(numpy is None, "numpy is not installed")
+    def test_framebuffer(self):
+        chip = Chip8(DRAW_KEY)
+        chip.press(0x0)
+        chip.step_frame(2)
+        self.assertEqual(chip.framebuffer.shape, (HEIGHT, WIDTH))
+        self.assertEqual(chip.framebuffer.sum(), 14)
+
+    def test_states(self):
+        chip = Chip8(COUNT)
+        chip.step_frame()
+        state = chip.save_state()
+        chip.step_frame()
+        chip.load_state(state)
+        self.assertEqual(chip.save_state(), state)
+        with self.assertRaises(Chip8Error):
+            chip.load_state(state[:3])
+
+    def test_errors(self):
+        with self.assertRaisesRegex(Chip8Error, "ROM is empty"):
+            Chip8(b"")
+        # 0x200: RET with an empty stack
+        chip = Chip8(bytes([0x00, 0xEE]))
+        with self.assertRaises(Chip8Error):
+            chip.step_frame()
+        # It stays stopped, with the same error.
+        with self.assertRaises(Chip8Error):
+            chip.step_frame()
+        self.assertFalse(chip.sounding)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,197 @@
+//! WebAssembly bindings for running the emulator in a browser.
+//!
+//! The module exports an [`Emulator`] class through `wasm-bindgen`, for a page to drive with a few
+//! lines of JavaScript (see `www/index.html`):
+//!
+//! 1. Create an `Emulator`, passing a function to call with `true` when the tone should start and
+//!    `false` when it should stop.
+//! 2. Load a ROM with `load`, which throws if it cannot be loaded.
+//! 3. Call `frame` 60 times per second, which throws once the program stops, then draw the 64×32
+//!    bytes of `framebuffer`.
+//! 4. Report keypad presses and releases with `keyEvent`.
+//!
+//! Unlike `chip8-web`, which is a whole frontend, this leaves the page to the embedder. The core
+//! is built without `std`, since there is no clock to seed the random number generator from; the
+//! page passes a seed to `load` instead.
+
+use chip8_core::display::{Display, HEIGHT, WIDTH};
+use chip8_core::frontend::{Audio, Input, Runner, Video};
+use chip8_core::keyboard::Keyboard;
+use chip8_core::rng::Rng;
+use chip8_core::rom::Rom;
+use chip8_core::CHIP8;
+use js_sys::Function;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{JsError, JsValue};
+
+/// The display as shared with the page: one byte per pixel, 1 for lit, row by row.
+struct Framebuffer([u8; WIDTH * HEIGHT]);
+
+impl Video for Framebuffer {
+    fn draw(&mut self, display: &Display) {
+        let pixels = display.screen().iter().flatten();
+        for (byte, &lit) in self.0.iter_mut().zip(pixels) {
+            *byte = lit as u8;
+        }
+    }
+}
+
+/// Tells the page when the tone starts and stops.
+struct Speaker {
+    /// Called with whether the tone plays, each time that changes.
+    on_sound: Option<Function>,
+    playing: bool,
+}
+
+impl Audio for Speaker {
+    fn play(&mut self, sounding: bool) {
+        if sounding == self.playing {
+            return;
+        }
+        self.playing = sounding;
+        if let Some(on_sound) = &self.on_sound {
+            // An exception thrown by the page's function is its own business.
+            let _ = on_sound.call1(&JsValue::NULL, &JsValue::from_bool(sounding));
+        }
+    }
+}
+
+/// The keys held down on the page, reported by `keyEvent`.
+struct Keypad([bool; 16]);
+
+impl Input for Keypad {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        for (key, &pressed) in self.0.iter().enumerate() {
+            keyboard.set(key as u8, pressed);
+        }
+        true
+    }
+}
+
+/// A CHIP-8 machine for a page to run.
+#[wasm_bindgen]
+pub struct Emulator {
+    runner: Runner<Framebuffer, Speaker, Keypad>,
+    /// Why the last ROM failed to load or the program stopped.
+    error: Option<String>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Returns a machine with no ROM loaded, which calls `on_sound` with `true` when the tone
+    /// should start and with `false` when it should stop.
+    #[wasm_bindgen(constructor)]
+    pub fn new(on_sound: Option<Function>) -> Emulator {
+        Emulator {
+            runner: Runner::new(
+                CHIP8::new(),
+                Framebuffer([0; WIDTH * HEIGHT]),
+                Speaker {
+                    on_sound,
+                    playing: false,
+                },
+                Keypad([false; 16]),
+            ),
+            error: Some("no ROM loaded".to_string()),
+        }
+    }
+
+    /// Powers on the machine with `rom` and the random number generator seeded with `seed`.
+    pub fn load(&mut self, rom: &[u8], seed: u32) -> Result<(), JsError> {
+        self.try_load(rom, seed).map_err(|err| JsError::new(&err))
+    }
+
+    /// Runs the program for one frame, 1/60 second, and updates the timers and the framebuffer.
+    /// Throws once the program has stopped, with the reason.
+    pub fn frame(&mut self) -> Result<(), JsError> {
+        self.try_frame().map_err(|err| JsError::new(&err))
+    }
+
+    /// Returns the display: 64×32 bytes, row by row, 1 for a lit pixel.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.runner.video.0.to_vec()
+    }
+
+    /// Presses or releases keypad `key`, from 0x0 to 0xF.
+    #[wasm_bindgen(js_name = keyEvent)]
+    pub fn key_event(&mut self, key: u8, pressed: bool) {
+        if let Some(held) = self.runner.input.0.get_mut(key as usize) {
+            *held = pressed;
+        }
+    }
+}
+
+impl Emulator {
+    fn try_load(&mut self, rom: &[u8], seed: u32) -> Result<(), String> {
+        let mut chip = CHIP8::new();
+        chip.set_rng(Rng::new(seed));
+        let loaded = Rom::from_bytes(rom.to_vec()).and_then(|rom| chip.load_rom(&rom));
+        let runner = &mut self.runner;
+        runner.chip = chip;
+        runner.video.draw(runner.chip.display());
+        runner.audio.play(false);
+        self.error = loaded.err().map(|err| err.to_string());
+        self.error.clone().map_or(Ok(()), Err)
+    }
+
+    fn try_frame(&mut self) -> Result<(), String> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        if let Err(err) = self.runner.frame() {
+            self.error = Some(err.to_string());
+            self.runner.audio.play(false);
+        }
+        self.error.clone().map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames() {
+        let mut emulator = Emulator::new(None);
+        // 0x200: LD V0, 0; LD F, V0; DRW V0, V0, 5; JP 0x206
+        emulator
+            .try_load(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06], 1)
+            .unwrap();
+        emulator.try_frame().unwrap();
+        let pixels = emulator.framebuffer();
+        // The top row of the font's 0 is 0xF0.
+        assert_eq!(&pixels[..5], &[1, 1, 1, 1, 0]);
+        assert_eq!(pixels.iter().filter(|&&pixel| pixel == 1).count(), 14);
+        assert!(!emulator.runner.audio.playing);
+    }
+
+    #[test]
+    fn keys_and_sound() {
+        let mut emulator = Emulator::new(None);
+        // 0x200: LD V0, K; LD ST, V0; JP 0x204
+        emulator
+            .try_load(&[0xF0, 0x0A, 0xF0, 0x18, 0x12, 0x04], 1)
+            .unwrap();
+        emulator.try_frame().unwrap();
+        assert!(!emulator.runner.audio.playing);
+        emulator.key_event(0x5, true);
+        emulator.try_frame().unwrap();
+        assert!(emulator.runner.audio.playing);
+        emulator.try_load(&[0x12, 0x00], 1).unwrap();
+        assert!(!emulator.runner.audio.playing);
+    }
+
+    #[test]
+    fn errors() {
+        let mut emulator = Emulator::new(None);
+        assert_eq!(emulator.try_frame().unwrap_err(), "no ROM loaded");
+        assert_eq!(emulator.try_load(&[], 1).unwrap_err(), "ROM is empty");
+        assert!(emulator.try_frame().is_err());
+
+        // 0x200: RET with an empty stack
+        emulator.try_load(&[0x00, 0xEE], 1).unwrap();
+        let err = emulator.try_frame().unwrap_err();
+        assert!(!err.is_empty());
+        assert_eq!(emulator.try_frame().unwrap_err(), err);
+    }
+}
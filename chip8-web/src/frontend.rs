@@ -0,0 +1,134 @@
+//! The page as a frontend: the game is drawn on a canvas, the tone is played with Web Audio, and
+//! the keypad is read from the keyboard, gamepads and the on-screen keypad.
+
+use crate::keys::{Held, GAMEPAD};
+use chip8_core::display::{Display, HEIGHT, WIDTH};
+use chip8_core::frontend::{Audio, Input, Video};
+use chip8_core::keyboard::Keyboard;
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{AudioContext, CanvasRenderingContext2d, GainNode, Gamepad, GamepadButton};
+use web_sys::{GamepadMappingType, ImageData, OscillatorType};
+
+/// Colors of lit and unlit pixels, as RGBA.
+const LIT: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const UNLIT: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// Frequency of the tone, in Hz.
+const FREQUENCY: f32 = 440.0;
+
+/// Volume of the tone, from 0 to 1. A square wave is loud.
+const VOLUME: f32 = 0.1;
+
+/// Draws the display on a canvas of 64×32 pixels, which the page scales up.
+pub struct Canvas {
+    context: CanvasRenderingContext2d,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(context: CanvasRenderingContext2d) -> Self {
+        Canvas {
+            context,
+            pixels: vec![0; WIDTH * HEIGHT * 4],
+        }
+    }
+}
+
+impl Video for Canvas {
+    fn draw(&mut self, display: &Display) {
+        let lit = display.screen().iter().flatten();
+        for (pixel, &lit) in self.pixels.chunks_exact_mut(4).zip(lit) {
+            pixel.copy_from_slice(if lit { &LIT } else { &UNLIT });
+        }
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&self.pixels),
+            WIDTH as u32,
+            HEIGHT as u32,
+        );
+        if let Ok(image) = image {
+            let _ = self.context.put_image_data(&image, 0.0, 0.0);
+        }
+    }
+}
+
+/// Plays the tone as a square wave, once the user has interacted with the page.
+#[derive(Default)]
+pub struct Buzzer {
+    /// The running tone, and the gain that mutes it while the sound timer is stopped.
+    tone: Option<(AudioContext, GainNode)>,
+}
+
+impl Buzzer {
+    /// Starts the audio, which browsers only allow in response to the user, such as a key
+    /// press. Does nothing if it has already started or the browser has no Web Audio.
+    pub fn unlock(&mut self) {
+        if self.tone.is_none() {
+            self.tone = tone().ok();
+        }
+    }
+}
+
+/// Starts a silent square wave.
+fn tone() -> Result<(AudioContext, GainNode), JsValue> {
+    let audio = AudioContext::new()?;
+    let oscillator = audio.create_oscillator()?;
+    oscillator.set_type(OscillatorType::Square);
+    oscillator.frequency().set_value(FREQUENCY);
+    let gain = audio.create_gain()?;
+    gain.gain().set_value(0.0);
+    oscillator.connect_with_audio_node(&gain)?;
+    gain.connect_with_audio_node(&audio.destination())?;
+    oscillator.start()?;
+    Ok((audio, gain))
+}
+
+impl Audio for Buzzer {
+    fn play(&mut self, sounding: bool) {
+        if let Some((_, gain)) = &self.tone {
+            gain.gain().set_value(if sounding { VOLUME } else { 0.0 });
+        }
+    }
+}
+
+/// The keypad, from the keys the page's event handlers saw held down and the gamepads, which
+/// are read at the start of every frame.
+#[derive(Default)]
+pub struct Keypad {
+    pub held: Held,
+}
+
+impl Keypad {
+    /// Reads the buttons of every connected gamepad with the standard mapping.
+    fn read_gamepads(&mut self) {
+        self.held.gamepad = [false; 16];
+        let gamepads = match web_sys::window().map(|window| window.navigator().get_gamepads()) {
+            Some(Ok(gamepads)) => gamepads,
+            _ => return,
+        };
+        for gamepad in gamepads.iter() {
+            let gamepad = match gamepad.dyn_into::<Gamepad>() {
+                Ok(gamepad) if gamepad.mapping() == GamepadMappingType::Standard => gamepad,
+                // Disconnected gamepads are null, and others have buttons in no known order.
+                _ => continue,
+            };
+            let buttons = gamepad.buttons();
+            for &(button, key) in GAMEPAD.iter() {
+                let pressed = buttons
+                    .get(button)
+                    .dyn_into::<GamepadButton>()
+                    .is_ok_and(|button| button.pressed());
+                self.held.gamepad[key as usize] |= pressed;
+            }
+        }
+    }
+}
+
+impl Input for Keypad {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        self.read_gamepads();
+        for key in 0..16 {
+            keyboard.set(key, self.held.is_held(key));
+        }
+        true
+    }
+}
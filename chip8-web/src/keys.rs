@@ -0,0 +1,171 @@
+//! Which keypad keys the keyboard, gamepads and the on-screen keypad press.
+
+use chip8_core::keyboard::{self, LAYOUT};
+use std::fmt;
+use std::str::FromStr;
+
+/// Buttons of a gamepad with the standard mapping, by index, and the keypad keys they press. The
+/// directions are 2, 4, 6 and 8, which most games use for moving, and the face buttons are placed
+/// as on the RetroPad of `chip8-libretro`.
+pub const GAMEPAD: [(u32, u8); 10] = [
+    (12, 0x2), // up
+    (14, 0x4), // left
+    (15, 0x6), // right
+    (13, 0x8), // down
+    (1, 0x5),  // right face button, A on a RetroPad
+    (0, 0x0),  // bottom face button, B
+    (3, 0x1),  // top face button, X
+    (2, 0x3),  // left face button, Y
+    (9, 0xF),  // start
+    (8, 0xE),  // select
+];
+
+/// Returns the keypad key for the `code` of a keyboard event, which names a key by where it is
+/// rather than what it types. The keypad is the left-hand side of the keyboard, `1`-`4` down to
+/// `Z`-`V` on QWERTY, as in `chip8`, and keeps those positions on other layouts.
+pub fn for_code(code: &str) -> Option<u8> {
+    let name = code
+        .strip_prefix("Key")
+        .or_else(|| code.strip_prefix("Digit"))?;
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => keyboard::key_for_char(c),
+        _ => None,
+    }
+}
+
+/// The keypad keys held down by each source of input. A key is held while any of them holds it,
+/// so that letting go of it on one does not release it on another.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Held {
+    pub keyboard: [bool; 16],
+    /// The on-screen keypad, pressed with a finger or the mouse.
+    pub pointer: [bool; 16],
+    pub gamepad: [bool; 16],
+}
+
+impl Held {
+    /// Returns `true` if `key` is held down by any source.
+    pub fn is_held(&self, key: u8) -> bool {
+        let key = key as usize & 0xF;
+        self.keyboard[key] || self.pointer[key] || self.gamepad[key]
+    }
+}
+
+/// The keys of the on-screen keypad, in the order its buttons are laid out, row by row. Each game
+/// can have its own, so that the keys it uses are where the thumbs are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arrangement {
+    pub keys: [u8; 16],
+}
+
+impl Default for Arrangement {
+    /// The layout of the COSMAC VIP's keypad.
+    fn default() -> Self {
+        let mut keys = [0; 16];
+        for (key, &layout) in keys.iter_mut().zip(LAYOUT.iter().flatten()) {
+            *key = layout;
+        }
+        Arrangement { keys }
+    }
+}
+
+impl Arrangement {
+    /// Returns the name the arrangement of the ROM with hash `rom_hash` is stored under in the
+    /// browser's local storage.
+    pub fn storage_key(rom_hash: u64) -> String {
+        format!("chip8-keypad-{:016x}", rom_hash)
+    }
+
+    /// Swaps the keys of the buttons at `a` and `b`.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.keys.swap(a, b);
+    }
+}
+
+/// Reads an arrangement written by its `Display`: the 16 keys as hexadecimal digits, each once.
+impl FromStr for Arrangement {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut keys = [0; 16];
+        let mut seen = [false; 16];
+        let mut digits = s.chars();
+        for key in keys.iter_mut() {
+            let digit = digits.next().and_then(|c| c.to_digit(16)).ok_or(())? as u8;
+            if seen[digit as usize] {
+                return Err(());
+            }
+            seen[digit as usize] = true;
+            *key = digit;
+        }
+        match digits.next() {
+            None => Ok(Arrangement { keys }),
+            Some(_) => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Arrangement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.keys.iter().try_for_each(|key| write!(f, "{:X}", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        assert_eq!(for_code("Digit1"), Some(0x1));
+        assert_eq!(for_code("KeyX"), Some(0x0));
+        assert_eq!(for_code("KeyV"), Some(0xF));
+        assert_eq!(for_code("KeyP"), None);
+        assert_eq!(for_code("Digit9"), None);
+        assert_eq!(for_code("Numpad1"), None);
+        assert_eq!(for_code("Key"), None);
+    }
+
+    #[test]
+    fn held() {
+        let mut held = Held::default();
+        held.keyboard[0x5] = true;
+        held.gamepad[0x5] = true;
+        held.keyboard[0x5] = false;
+        assert!(held.is_held(0x5));
+        held.gamepad[0x5] = false;
+        assert!(!held.is_held(0x5));
+
+        let keys: Vec<u8> = GAMEPAD.iter().map(|&(_, key)| key).collect();
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(
+            sorted.len(),
+            keys.len(),
+            "no two buttons press the same key"
+        );
+    }
+
+    #[test]
+    fn arrangement() {
+        let default = Arrangement::default();
+        assert_eq!(default.to_string(), "123C456D789EA0BF");
+        assert_eq!("123C456D789EA0BF".parse(), Ok(default));
+
+        let mut swapped = default;
+        swapped.swap(0, 15);
+        assert_eq!(swapped.to_string(), "F23C456D789EA0B1");
+        assert_eq!(swapped.to_string().parse(), Ok(swapped));
+
+        assert_eq!("123C456D789EA0B".parse::<Arrangement>(), Err(()));
+        assert_eq!("123C456D789EA0BF0".parse::<Arrangement>(), Err(()));
+        assert_eq!("113C456D789EA0BF".parse::<Arrangement>(), Err(()));
+        assert_eq!("123C456D789EA0BG".parse::<Arrangement>(), Err(()));
+        assert_eq!(
+            Arrangement::storage_key(0xAB),
+            "chip8-keypad-00000000000000ab"
+        );
+    }
+}
@@ -0,0 +1,420 @@
+//! A web page for playing CHIP-8 games, built with [trunk](https://trunkrs.dev) from `index.html`.
+//!
+//! Unlike `chip8-wasm`, which exports an emulator class for a page to drive, this crate is the
+//! whole frontend, written against the browser's APIs with `wasm-bindgen`: it draws on the page's
+//! canvas, plays the tone with Web Audio, reads the keyboard, gamepads and an on-screen keypad for
+//! touch screens, and loads ROMs chosen with the file input, dropped on the page, or named by the
+//! `rom` parameter of the page's URL. The keys of the on-screen keypad can be rearranged for each
+//! game, and are kept in the browser's local storage.
+
+mod frontend;
+mod keys;
+
+use crate::frontend::{Buzzer, Canvas, Keypad};
+use crate::keys::Arrangement;
+use chip8_core::frontend::{Audio, Runner, Video};
+use chip8_core::rom::Rom;
+use chip8_core::state::rom_hash;
+use chip8_core::{CHIP8, TIMER_PERIOD};
+use js_sys::{Math, Promise, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{CanvasRenderingContext2d, Document, DragEvent, Element, Event, EventTarget};
+use web_sys::{HtmlCanvasElement, HtmlElement, HtmlInputElement, KeyboardEvent, PointerEvent};
+use web_sys::{Response, Storage, UrlSearchParams};
+
+/// How far behind real time the game may fall, in milliseconds, before it skips ahead instead of
+/// catching up, such as after the tab was in the background.
+const MAX_LAG: f64 = 100.0;
+
+/// How long a phone vibrates when a key of the on-screen keypad is touched, in milliseconds.
+const TAP_VIBRATION: u32 = 15;
+
+/// The machine and what the page shows of it.
+struct Page {
+    runner: Runner<Canvas, Buzzer, Keypad>,
+    /// Whether a program is loaded and has not stopped.
+    running: bool,
+    /// When the next frame is due, in milliseconds on the clock of `requestAnimationFrame`.
+    next_frame: f64,
+    status: HtmlElement,
+    /// The hash of the loaded ROM, under which its keypad arrangement is stored.
+    rom_hash: Option<u64>,
+    arrangement: Arrangement,
+    /// The buttons of the on-screen keypad, in the order of the arrangement.
+    buttons: Vec<Element>,
+    /// Whether taps on the on-screen keypad rearrange it instead of pressing keys, and the
+    /// button tapped first, which the next tap swaps with.
+    arranging: Option<Option<usize>>,
+}
+
+impl Page {
+    fn set_status(&self, text: &str) {
+        self.status.set_text_content(Some(text));
+    }
+
+    /// Powers on the machine with `rom`, named `name` in the status.
+    fn load(&mut self, rom: Vec<u8>, name: &str) {
+        let hash = rom_hash(&rom);
+        let seed = (Math::random() * u32::MAX as f64) as u32;
+        let chip = Rom::from_bytes(rom)
+            .map_err(|err| err.to_string())
+            .and_then(|rom| {
+                let mut chip = CHIP8::builder()
+                    .seed(seed)
+                    .build()
+                    .map_err(|err| err.to_string())?;
+                chip.load_rom(&rom).map_err(|err| err.to_string())?;
+                Ok(chip)
+            });
+        match chip {
+            Ok(chip) => {
+                self.runner.chip = chip;
+                self.runner.video.draw(self.runner.chip.display());
+                self.running = true;
+                self.set_status(&format!("Playing {}.", name));
+                self.rom_hash = Some(hash);
+                let stored = local_storage()
+                    .and_then(|storage| storage.get_item(&Arrangement::storage_key(hash)).ok()?);
+                self.arrange(
+                    stored
+                        .and_then(|keys| keys.parse().ok())
+                        .unwrap_or_default(),
+                );
+            }
+            Err(err) => self.set_status(&format!("Could not load {}: {}", name, err)),
+        }
+    }
+
+    /// Lays out the on-screen keypad as `arrangement`.
+    fn arrange(&mut self, arrangement: Arrangement) {
+        self.arrangement = arrangement;
+        for (button, key) in self.buttons.iter().zip(arrangement.keys.iter()) {
+            button.set_text_content(Some(&format!("{:X}", key)));
+        }
+    }
+
+    /// Keeps the arrangement of the on-screen keypad for the loaded ROM, forgetting it when it is
+    /// the default one.
+    fn save_arrangement(&self) {
+        let (hash, storage) = match (self.rom_hash, local_storage()) {
+            (Some(hash), Some(storage)) => (hash, storage),
+            _ => return,
+        };
+        let key = Arrangement::storage_key(hash);
+        let _ = if self.arrangement == Arrangement::default() {
+            storage.remove_item(&key)
+        } else {
+            storage.set_item(&key, &self.arrangement.to_string())
+        };
+    }
+
+    /// Starts or stops rearranging the on-screen keypad.
+    fn toggle_arranging(&mut self) {
+        if let Some(Some(picked)) = self.arranging {
+            let _ = self.buttons[picked].class_list().remove_1("picked");
+        }
+        self.arranging = match self.arranging {
+            Some(_) => None,
+            None => Some(None),
+        };
+        // Keys held when rearranging starts would be released on other buttons.
+        self.runner.input.held.pointer = [false; 16];
+    }
+
+    /// Taps the button at `index` while rearranging the keypad: the first of two taps picks a
+    /// button, and the second swaps it with the one picked.
+    fn pick(&mut self, index: usize) {
+        match self.arranging {
+            Some(None) => {
+                let _ = self.buttons[index].class_list().add_1("picked");
+                self.arranging = Some(Some(index));
+            }
+            Some(Some(picked)) => {
+                let _ = self.buttons[picked].class_list().remove_1("picked");
+                self.arranging = Some(None);
+                let mut arrangement = self.arrangement;
+                arrangement.swap(picked, index);
+                self.arrange(arrangement);
+                self.save_arrangement();
+            }
+            None => {}
+        }
+    }
+
+    /// Runs the frames due by `now`, in milliseconds.
+    fn frames(&mut self, now: f64) {
+        if !self.running {
+            return;
+        }
+        if now - self.next_frame > MAX_LAG {
+            self.next_frame = now;
+        }
+        while self.next_frame <= now {
+            if let Err(err) = self.runner.frame() {
+                self.running = false;
+                self.runner.audio.play(false);
+                self.set_status(&format!("Stopped: {}", err));
+                return;
+            }
+            self.next_frame += TIMER_PERIOD.as_secs_f64() * 1000.0;
+        }
+    }
+}
+
+/// Describes an exception from the browser.
+fn describe(err: JsValue) -> String {
+    err.as_string()
+        .or_else(|| {
+            err.dyn_ref::<js_sys::Error>()
+                .map(|err| String::from(err.message()))
+        })
+        .unwrap_or_else(|| format!("{:?}", err))
+}
+
+/// Returns the element of the page with the id `id`.
+fn element<T: JsCast>(document: &Document, id: &str) -> Result<T, JsValue> {
+    document
+        .get_element_by_id(id)
+        .ok_or_else(|| format!("the page has no #{}", id))?
+        .dyn_into::<T>()
+        .map_err(|_| format!("#{} is the wrong kind of element", id).into())
+}
+
+/// Calls `handler` with every `name` event of type `E` on `target`, for as long as the page is
+/// open.
+fn listen<E: JsCast>(
+    target: &EventTarget,
+    name: &str,
+    mut handler: impl FnMut(E) + 'static,
+) -> Result<(), JsValue> {
+    let closure = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+        if let Ok(event) = event.dyn_into::<E>() {
+            handler(event);
+        }
+    });
+    target.add_event_listener_with_callback(name, closure.as_ref().unchecked_ref())?;
+    closure.forget();
+    Ok(())
+}
+
+/// Loads the ROM that `bytes` resolves to, as an `ArrayBuffer`, once it is read.
+fn load_later(page: &Rc<RefCell<Page>>, name: String, bytes: Promise) {
+    let page = page.clone();
+    page.borrow().set_status(&format!("Loading {}…", name));
+    spawn_local(async move {
+        match JsFuture::from(bytes).await {
+            Ok(buffer) => page
+                .borrow_mut()
+                .load(Uint8Array::new(&buffer).to_vec(), &name),
+            Err(err) => {
+                page.borrow()
+                    .set_status(&format!("Could not read {}: {}", name, describe(err)))
+            }
+        }
+    });
+}
+
+/// Returns the browser's local storage, unless the page may not use it.
+fn local_storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// Downloads the ROM at `url`, returning its bytes as an `ArrayBuffer`.
+async fn fetch(url: String) -> Result<JsValue, JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    let response: Response = JsFuture::from(window.fetch_with_str(&url))
+        .await?
+        .dyn_into()?;
+    if !response.ok() {
+        return Err(format!("HTTP {} {}", response.status(), response.status_text()).into());
+    }
+    JsFuture::from(response.array_buffer()?).await
+}
+
+/// Builds the on-screen keypad in `keypad`, pressed with a finger or the mouse, and rearranged
+/// with the `arrange` and `default-keys` buttons. Touching a key makes the phone vibrate, where
+/// browsers allow it.
+fn keypad(
+    page: &Rc<RefCell<Page>>,
+    document: &Document,
+    keypad: &HtmlElement,
+) -> Result<(), JsValue> {
+    for index in 0..16 {
+        let button = document.create_element("button")?;
+        keypad.append_child(&button)?;
+        page.borrow_mut().buttons.push(button.clone());
+        for &(name, pressed) in [
+            ("pointerdown", true),
+            ("pointerup", false),
+            ("pointerleave", false),
+            ("pointercancel", false),
+        ]
+        .iter()
+        {
+            let page = page.clone();
+            listen(&button, name, move |event: PointerEvent| {
+                event.prevent_default();
+                let mut page = page.borrow_mut();
+                if page.arranging.is_some() {
+                    if pressed {
+                        page.pick(index);
+                    }
+                    return;
+                }
+                let key = page.arrangement.keys[index];
+                page.runner.input.held.pointer[key as usize] = pressed;
+                page.runner.audio.unlock();
+                if pressed && event.pointer_type() == "touch" {
+                    if let Some(window) = web_sys::window() {
+                        window.navigator().vibrate_with_duration(TAP_VIBRATION);
+                    }
+                }
+            })?;
+        }
+    }
+    let arrangement = page.borrow().arrangement;
+    page.borrow_mut().arrange(arrangement);
+
+    let arrange: HtmlElement = element(document, "arrange")?;
+    let reset: HtmlElement = element(document, "default-keys")?;
+    let body = document.body().ok_or("the page has no body")?;
+    let (toggled, label) = (page.clone(), arrange.clone());
+    listen(&arrange, "click", move |_: Event| {
+        let mut page = toggled.borrow_mut();
+        page.toggle_arranging();
+        let arranging = page.arranging.is_some();
+        let _ = body.class_list().toggle_with_force("arranging", arranging);
+        label.set_text_content(Some(if arranging { "Done" } else { "Arrange keys" }));
+    })?;
+    let page = page.clone();
+    listen(&reset, "click", move |_: Event| {
+        let mut page = page.borrow_mut();
+        page.arrange(Arrangement::default());
+        page.save_arrangement();
+    })
+}
+
+/// Listens for the keyboard, ROMs chosen or dropped, and the on-screen keypad.
+fn listen_all(page: &Rc<RefCell<Page>>, document: &Document) -> Result<(), JsValue> {
+    for &(name, pressed) in [("keydown", true), ("keyup", false)].iter() {
+        let page = page.clone();
+        listen(document, name, move |event: KeyboardEvent| {
+            if event.ctrl_key() || event.meta_key() || event.alt_key() {
+                return;
+            }
+            if let Some(key) = keys::for_code(&event.code()) {
+                event.prevent_default();
+                let mut page = page.borrow_mut();
+                page.runner.input.held.keyboard[key as usize] = pressed;
+                page.runner.audio.unlock();
+            }
+        })?;
+    }
+    // Keys released while the page is in the background would stay held otherwise.
+    let window = web_sys::window().ok_or("no window")?;
+    let blurred = page.clone();
+    listen(&window, "blur", move |_: Event| {
+        blurred.borrow_mut().runner.input.held.keyboard = [false; 16];
+    })?;
+
+    let input: HtmlInputElement = element(document, "rom")?;
+    let (chosen, files) = (page.clone(), input.clone());
+    listen(&input, "change", move |_: Event| {
+        if let Some(file) = files.files().and_then(|files| files.get(0)) {
+            chosen.borrow_mut().runner.audio.unlock();
+            load_later(&chosen, file.name(), file.array_buffer());
+        }
+    })?;
+
+    // The page must cancel `dragover` for the browser to let files be dropped on it.
+    let body = document.body().ok_or("the page has no body")?;
+    let dragged = body.clone();
+    listen(document, "dragover", move |event: DragEvent| {
+        event.prevent_default();
+        let _ = dragged.class_list().add_1("dragging");
+    })?;
+    let left = body.clone();
+    listen(document, "dragleave", move |_: DragEvent| {
+        let _ = left.class_list().remove_1("dragging");
+    })?;
+    let dropped = page.clone();
+    listen(document, "drop", move |event: DragEvent| {
+        event.prevent_default();
+        let _ = body.class_list().remove_1("dragging");
+        let file = event
+            .data_transfer()
+            .and_then(|transfer| transfer.files())
+            .and_then(|files| files.get(0));
+        if let Some(file) = file {
+            dropped.borrow_mut().runner.audio.unlock();
+            load_later(&dropped, file.name(), file.array_buffer());
+        }
+    })?;
+
+    keypad(page, document, &element(document, "keypad")?)
+}
+
+/// Runs the game at every animation frame of the browser.
+fn animate(page: Rc<RefCell<Page>>) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    // The callback asks for the next frame with itself, so it holds a reference to where it is
+    // stored once created.
+    let callback = Rc::new(RefCell::new(None::<Closure<dyn FnMut(f64)>>));
+    let next = callback.clone();
+    let again = window.clone();
+    *callback.borrow_mut() = Some(Closure::new(move |now: f64| {
+        page.borrow_mut().frames(now);
+        if let Some(callback) = &*next.borrow() {
+            let _ = again.request_animation_frame(callback.as_ref().unchecked_ref());
+        }
+    }));
+    if let Some(callback) = &*callback.borrow() {
+        window.request_animation_frame(callback.as_ref().unchecked_ref())?;
+    }
+    Ok(())
+}
+
+/// Sets up the page when the module is loaded, and starts the ROM named by the `rom` parameter
+/// of its URL, if there is one.
+#[wasm_bindgen(start)]
+pub fn start() -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or("no window")?;
+    let document = window.document().ok_or("no document")?;
+    let canvas: HtmlCanvasElement = element(&document, "screen")?;
+    let context: CanvasRenderingContext2d = canvas
+        .get_context("2d")?
+        .ok_or("the canvas has no 2D context")?
+        .dyn_into()?;
+    let page = Rc::new(RefCell::new(Page {
+        runner: Runner::new(
+            CHIP8::new(),
+            Canvas::new(context),
+            Buzzer::default(),
+            Keypad::default(),
+        ),
+        running: false,
+        next_frame: 0.0,
+        status: element(&document, "status")?,
+        rom_hash: None,
+        arrangement: Arrangement::default(),
+        buttons: Vec::new(),
+        arranging: None,
+    }));
+    listen_all(&page, &document)?;
+
+    let params = UrlSearchParams::new_with_str(&window.location().search()?)?;
+    if let Some(url) = params.get("rom") {
+        let name = url.rsplit('/').next().unwrap_or(&url).to_string();
+        load_later(
+            &page,
+            name,
+            wasm_bindgen_futures::future_to_promise(fetch(url)),
+        );
+    }
+    animate(page)
+}
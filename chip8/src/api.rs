@@ -0,0 +1,519 @@
+//! The HTTP control API of `chip8 serve --api`, for tools, test rigs and chat bots to drive the
+//! emulator. Requests go to the machine between frames, and answers are JSON unless said
+//! otherwise:
+//!
+//! ```text
+//! GET  /api/status                   whether a ROM is loaded and paused, and the frames run
+//! GET  /api/registers                V0-VF, I, PC, the timers and the stack
+//! GET  /api/memory?address=&length=  bytes of memory, 16 from 0x200 by default
+//! GET  /api/screenshot.png?scale=    the display as a PNG image, 8 times its size by default
+//! POST /api/load                     restarts with the ROM in the body
+//! POST /api/pause                    stops running frames
+//! POST /api/resume                   runs frames again
+//! POST /api/advance?frames=          runs frames while paused, 1 by default
+//! POST /api/press?key=&frames=       holds a key for some frames, 6 by default
+//! POST /api/hold?key=                holds a key until it is released
+//! POST /api/release?key=             releases a key
+//! GET  /api/votes                    in crowd play, the votes of the window and the key held
+//! POST /api/vote?key=&voter=         in crowd play, votes for a key as a voter such as a chat user
+//! ```
+//!
+//! Numbers are decimal or `0x`-prefixed hexadecimal, keys are hexadecimal digits, and query
+//! values are percent-encoded as in any URL. A request that cannot be done is answered with a 4xx
+//! status and `{"error":"..."}`.
+//!
+//! In crowd play, described in [`chip8_core::crowd`], the keys are only pressed by votes, and
+//! `press`, `hold` and `release` fail. The `voter` of `/api/vote` is taken at its word, so that one
+//! client can vote as any number of voters: it can only be trusted when the API needs a token, and
+//! only the chat bot or other relay holding it votes.
+
+use chip8_core::command::parse_number;
+use chip8_core::crowd::{Crowd, Tally};
+use chip8_core::error::ExecError;
+use chip8_core::rom::Rom;
+use chip8_core::CHIP8;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Frames a key pressed with `/api/press` is held by default, a tenth of a second.
+const PRESS_FRAMES: u64 = 6;
+
+/// How many times its size a screenshot is by default.
+const SCREENSHOT_SCALE: usize = 8;
+
+/// The paths of the endpoints.
+const ENDPOINTS: [&str; 13] = [
+    "/api/status",
+    "/api/registers",
+    "/api/memory",
+    "/api/screenshot.png",
+    "/api/load",
+    "/api/pause",
+    "/api/resume",
+    "/api/advance",
+    "/api/press",
+    "/api/hold",
+    "/api/release",
+    "/api/votes",
+    "/api/vote",
+];
+
+/// The answer to `/api/status` and to the requests that change the state of the machine.
+#[derive(Serialize)]
+struct Status {
+    loaded: bool,
+    paused: bool,
+    frame: u64,
+}
+
+/// The answer to `/api/registers`.
+#[derive(Serialize)]
+struct Registers<'a> {
+    v: &'a [u8],
+    i: u16,
+    pc: u16,
+    dt: u8,
+    st: u8,
+    stack: &'a [u16],
+}
+
+/// The answer to `/api/memory`.
+#[derive(Serialize)]
+struct Memory<'a> {
+    address: usize,
+    bytes: &'a [u8],
+    hex: String,
+}
+
+/// The answer to `/api/votes` and `/api/vote`.
+#[derive(Serialize)]
+struct Votes {
+    held: Option<u8>,
+    remaining: u64,
+    votes: [u32; 16],
+}
+
+/// The answer to a request that cannot be done.
+#[derive(Serialize)]
+struct Failure<'a> {
+    error: &'a str,
+}
+
+/// The answer to a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: &'static str, value: &impl Serialize) -> Self {
+        let mut body = serde_json::to_vec(value).expect("the answers are all JSON");
+        body.push(b'\n');
+        Response {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn ok(value: &impl Serialize) -> Self {
+        Response::json("200 OK", value)
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Response::json(status, &Failure { error: message })
+    }
+}
+
+/// The machine as `chip8 serve` runs it, which the API pauses and presses the keys of.
+pub struct Machine {
+    pub chip: CHIP8,
+    paused: bool,
+    /// Whether a ROM was loaded, without which nothing runs.
+    loaded: bool,
+    /// Frames run since the ROM was loaded.
+    frame: u64,
+    /// The frames each key pressed with `/api/press` is held for yet.
+    pressed: [u64; 16],
+    /// The votes for the keys, in crowd play.
+    crowd: Option<Crowd>,
+}
+
+impl Machine {
+    pub fn new(chip: CHIP8) -> Self {
+        Machine {
+            chip,
+            paused: false,
+            loaded: true,
+            frame: 0,
+            pressed: [0; 16],
+            crowd: None,
+        }
+    }
+
+    /// Returns a machine waiting for a ROM to be loaded through the API.
+    pub fn empty() -> Self {
+        Machine {
+            loaded: false,
+            ..Machine::new(crate::machine(None, None).expect("there is no ROM to fail"))
+        }
+    }
+
+    /// Turns on crowd play, voting in windows of `window` frames.
+    pub fn set_crowd(&mut self, window: u64) {
+        self.crowd = Some(Crowd::new(window));
+    }
+
+    /// Returns the votes of crowd play, if it is on.
+    pub fn tally(&self) -> Option<Tally> {
+        self.crowd.as_ref().map(Crowd::tally)
+    }
+
+    /// Presses or releases `key` for a viewer, which votes for it in crowd play.
+    pub fn key(&mut self, voter: &str, key: u8, pressed: bool) {
+        match &mut self.crowd {
+            Some(crowd) if pressed => crowd.vote(voter, key),
+            Some(_) => (),
+            None => self.chip.keyboard_mut().set(key, pressed),
+        }
+    }
+
+    /// Runs a frame unless paused or waiting for a ROM. Returns whether the tone plays.
+    pub fn frame(&mut self) -> Result<bool, ExecError> {
+        if self.paused || !self.loaded {
+            return Ok(false);
+        }
+        self.run()
+    }
+
+    fn run(&mut self) -> Result<bool, ExecError> {
+        let _span = tracing::debug_span!("frame", number = self.frame).entered();
+        if let Some(crowd) = &self.crowd {
+            let held = crowd.key();
+            for key in 0..16 {
+                self.chip.keyboard_mut().set(key, held == Some(key));
+            }
+        }
+        for _ in 0..self.chip.config().steps_per_frame() {
+            self.chip.step()?;
+        }
+        let sounding = self.chip.timer().is_sounding();
+        self.chip.tick();
+        self.frame += 1;
+        if let Some(crowd) = &mut self.crowd {
+            crowd.frame();
+        }
+        for (key, frames) in self.pressed.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    self.chip.keyboard_mut().set(key as u8, false);
+                }
+            }
+        }
+        Ok(sounding)
+    }
+
+    /// Answers a request for `target`, a path under `/api/` with its query.
+    pub fn handle(&mut self, method: &str, target: &str, body: &[u8]) -> Response {
+        tracing::debug!(method, target, body = body.len(), "API request");
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let get = |name: &str| {
+            query
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let result = match (method, path) {
+            ("GET", "/api/status") => Ok(self.status()),
+            ("GET", "/api/registers") => Ok(self.registers()),
+            ("GET", "/api/memory") => self.memory(get("address"), get("length")),
+            ("GET", "/api/screenshot.png") => return self.screenshot(get("scale")),
+            ("POST", "/api/load") => self.load(body),
+            ("POST", "/api/pause") => {
+                self.paused = true;
+                Ok(self.status())
+            }
+            ("POST", "/api/resume") => {
+                self.paused = false;
+                Ok(self.status())
+            }
+            ("POST", "/api/advance") => self.advance(get("frames")),
+            ("POST", "/api/press") => self.press(get("key"), get("frames")),
+            ("POST", "/api/hold") => self.hold(get("key"), true),
+            ("POST", "/api/release") => self.hold(get("key"), false),
+            ("GET", "/api/votes") => self.votes(),
+            ("POST", "/api/vote") => self.vote(get("key"), get("voter")),
+            _ if ENDPOINTS.contains(&path) => {
+                return Response::error("405 Method Not Allowed", "wrong method")
+            }
+            _ => return Response::error("404 Not Found", "no such endpoint"),
+        };
+        match result {
+            Ok(response) => response,
+            Err(message) => Response::error("400 Bad Request", &message),
+        }
+    }
+
+    fn status(&self) -> Response {
+        Response::ok(&Status {
+            loaded: self.loaded,
+            paused: self.paused,
+            frame: self.frame,
+        })
+    }
+
+    fn registers(&self) -> Response {
+        let chip = &self.chip;
+        Response::ok(&Registers {
+            v: chip.variable(),
+            i: chip.index(),
+            pc: chip.program_counter(),
+            dt: chip.timer().delay(),
+            st: chip.timer().sound(),
+            stack: chip.stack(),
+        })
+    }
+
+    fn memory(&self, address: Option<&str>, length: Option<&str>) -> Result<Response, String> {
+        let address = usize::from(parse_number(address.unwrap_or("0x200"))?);
+        let length = usize::from(parse_number(length.unwrap_or("16"))?);
+        let memory = self.chip.memory();
+        let bytes = address
+            .checked_add(length)
+            .and_then(|end| memory.get(address..end))
+            .ok_or_else(|| {
+                format!(
+                    "{} bytes from {:#05X} are not all in memory",
+                    length, address
+                )
+            })?;
+        let mut hex = String::new();
+        for byte in bytes {
+            let _ = write!(hex, "{:02x}", byte);
+        }
+        Ok(Response::ok(&Memory {
+            address,
+            bytes,
+            hex,
+        }))
+    }
+
+    fn screenshot(&self, scale: Option<&str>) -> Response {
+        let scale = match scale.map(parse_number) {
+            None => SCREENSHOT_SCALE,
+            Some(Ok(scale @ 1..=64)) => usize::from(scale),
+            Some(_) => return Response::error("400 Bad Request", "scale must be from 1 to 64"),
+        };
+        Response {
+            status: "200 OK",
+            content_type: "image/png",
+            body: self.chip.display().png(scale),
+        }
+    }
+
+    fn load(&mut self, body: &[u8]) -> Result<Response, String> {
+        let rom = Rom::from_bytes(body.to_vec()).map_err(|err| err.to_string())?;
+        let chip = crate::machine(Some(&rom), None).map_err(|err| err.to_string())?;
+        *self = Machine {
+            paused: self.paused,
+            crowd: self.crowd.take(),
+            ..Machine::new(chip)
+        };
+        Ok(self.status())
+    }
+
+    fn advance(&mut self, frames: Option<&str>) -> Result<Response, String> {
+        let frames = parse_number(frames.unwrap_or("1"))?;
+        if !self.loaded {
+            return Err("no ROM loaded".to_string());
+        }
+        for _ in 0..frames {
+            self.run().map_err(|err| err.to_string())?;
+        }
+        Ok(self.status())
+    }
+
+    /// Fails in crowd play, where keys are only pressed by votes.
+    fn check_not_crowd(&self) -> Result<(), String> {
+        match self.crowd {
+            Some(_) => Err("keys are voted for in crowd play, with /api/vote".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn press(&mut self, key: Option<&str>, frames: Option<&str>) -> Result<Response, String> {
+        self.check_not_crowd()?;
+        let key = parse_key(key)?;
+        let frames = match frames {
+            Some(frames) => u64::from(parse_number(frames)?.max(1)),
+            None => PRESS_FRAMES,
+        };
+        self.chip.keyboard_mut().set(key, true);
+        self.pressed[usize::from(key)] = frames;
+        Ok(self.status())
+    }
+
+    fn hold(&mut self, key: Option<&str>, held: bool) -> Result<Response, String> {
+        self.check_not_crowd()?;
+        let key = parse_key(key)?;
+        self.chip.keyboard_mut().set(key, held);
+        self.pressed[usize::from(key)] = 0;
+        Ok(self.status())
+    }
+
+    fn votes(&self) -> Result<Response, String> {
+        let tally = self.tally().ok_or("crowd play is off")?;
+        Ok(Response::ok(&Votes {
+            held: tally.held,
+            remaining: tally.remaining,
+            votes: tally.votes,
+        }))
+    }
+
+    fn vote(&mut self, key: Option<&str>, voter: Option<&str>) -> Result<Response, String> {
+        let key = parse_key(key)?;
+        let voter = voter
+            .filter(|voter| !voter.is_empty())
+            .ok_or("missing voter")?;
+        let crowd = self.crowd.as_mut().ok_or("crowd play is off")?;
+        crowd.vote(voter, key);
+        self.votes()
+    }
+}
+
+/// Parses a key of the keypad, a hexadecimal digit.
+fn parse_key(key: Option<&str>) -> Result<u8, String> {
+    let key = key.ok_or("missing key")?;
+    match u8::from_str_radix(key, 16) {
+        Ok(key @ 0..=0xF) => Ok(key),
+        _ => Err(format!("no key `{}` on the keypad", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0x200: LD V0, 5; 0x202: SKNP V1 (key 0); 0x204: ADD V0, 1; 0x206: JP 0x202
+    const ROM: [u8; 8] = [0x60, 0x05, 0xE1, 0xA1, 0x70, 0x01, 0x12, 0x02];
+
+    fn text(response: &Response) -> &str {
+        std::str::from_utf8(&response.body).unwrap()
+    }
+
+    #[test]
+    fn drives_the_machine() {
+        let mut machine = Machine::empty();
+        machine.frame().unwrap();
+        let advance = machine.handle("POST", "/api/advance", b"");
+        assert_eq!(text(&advance), "{\"error\":\"no ROM loaded\"}\n");
+        let response = machine.handle("POST", "/api/load", &ROM);
+        assert_eq!(response.status, "200 OK", "{}", text(&response));
+        machine.handle("POST", "/api/pause", b"");
+        machine.frame().unwrap();
+        let status = machine.handle("GET", "/api/status", b"");
+        assert_eq!(
+            text(&status),
+            "{\"loaded\":true,\"paused\":true,\"frame\":0}\n"
+        );
+
+        machine.handle("POST", "/api/advance?frames=2", b"");
+        let registers = machine.handle("GET", "/api/registers", b"");
+        assert!(
+            text(&registers).starts_with("{\"v\":[5,0,"),
+            "{}",
+            text(&registers)
+        );
+
+        // The key counts while it is pressed, then is let go.
+        machine.handle("POST", "/api/press?key=0&frames=2", b"");
+        machine.handle("POST", "/api/advance?frames=3", b"");
+        assert!(machine.chip.variable()[0] > 5);
+        assert!(!machine.chip.keyboard().is_pressed(0));
+        machine.handle("POST", "/api/hold?key=0", b"");
+        machine.handle("POST", "/api/advance?frames=3", b"");
+        assert!(machine.chip.keyboard().is_pressed(0));
+
+        let memory = machine.handle("GET", "/api/memory?address=0x200&length=2", b"");
+        assert_eq!(
+            text(&memory),
+            "{\"address\":512,\"bytes\":[96,5],\"hex\":\"6005\"}\n"
+        );
+        let screenshot = machine.handle("GET", "/api/screenshot.png?scale=1", b"");
+        assert_eq!(screenshot.content_type, "image/png");
+        assert_eq!(screenshot.body[..4], *b"\x89PNG");
+    }
+
+    #[test]
+    fn counts_votes() {
+        let mut machine = Machine::new(CHIP8::new());
+        machine.handle("POST", "/api/load", &ROM);
+        assert!(machine
+            .handle("GET", "/api/votes", b"")
+            .status
+            .starts_with("400"));
+        machine.set_crowd(2);
+        machine.handle("POST", "/api/vote?key=0&voter=ann", b"");
+        machine.key("viewer", 0, true);
+        let votes = machine.handle("POST", "/api/vote?key=3&voter=bob", b"");
+        assert_eq!(
+            text(&votes),
+            "{\"held\":null,\"remaining\":2,\"votes\":[2,0,0,1,0,0,0,0,0,0,0,0,0,0,0,0]}\n"
+        );
+        let press = machine.handle("POST", "/api/press?key=0", b"");
+        assert!(text(&press).contains("crowd play"));
+
+        // Key 0 wins the window and is held for the next one, but for its last frame.
+        machine.handle("POST", "/api/advance?frames=3", b"");
+        assert!(machine.chip.keyboard().is_pressed(0));
+        assert!(machine.chip.variable()[0] > 5);
+        machine.handle("POST", "/api/advance", b"");
+        assert!(!machine.chip.keyboard().is_pressed(0));
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let mut machine = Machine::empty();
+        let status = |response: Response| response.status;
+        assert_eq!(
+            status(machine.handle("GET", "/api/nothing", b"")),
+            "404 Not Found"
+        );
+        assert_eq!(
+            status(machine.handle("GET", "/api/pause", b"")),
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            status(machine.handle("POST", "/api/press?key=10", b"")),
+            "400 Bad Request"
+        );
+        assert_eq!(
+            status(machine.handle("GET", "/api/memory?address=0xFFF&length=2", b"")),
+            "400 Bad Request"
+        );
+        let response = machine.handle("POST", "/api/load", b"");
+        assert_eq!(text(&response), "{\"error\":\"ROM is empty\"}\n");
+    }
+
+    #[test]
+    fn decodes_queries() {
+        let mut machine = Machine::new(CHIP8::new());
+        machine.handle("POST", "/api/load", &ROM);
+        let memory = machine.handle("GET", "/api/memory?address=%30x200&length=%32", b"");
+        assert_eq!(
+            text(&memory),
+            "{\"address\":512,\"bytes\":[96,5],\"hex\":\"6005\"}\n"
+        );
+        let key = machine.handle("POST", "/api/press?key=%22%5C%0A", b"");
+        assert_eq!(
+            text(&key),
+            "{\"error\":\"no key `\\\"\\\\\\n` on the keypad\"}\n"
+        );
+    }
+}
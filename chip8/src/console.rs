@@ -0,0 +1,321 @@
+//! Command-line frontend for the debugger.
+
+use crate::terminal;
+use chip8_core::callstack;
+use chip8_core::command::{Command, HELP};
+use chip8_core::debugger::{Debugger, StopReason};
+use chip8_core::disasm;
+use chip8_core::inspector::{self, Inspector, State};
+use chip8_core::state::Slots;
+use chip8_core::symbols::Symbols;
+use chip8_core::{CHIP8, INSTRUCTION_PERIOD, TIMER_PERIOD};
+use std::fs;
+use std::io::Write;
+use tokio::io::{self, AsyncBufReadExt, BufReader};
+use tokio::{signal, time};
+
+/// What to do after a command has run.
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Disassembles `count` instructions starting at the program counter, using the debugger's
+/// labels.
+fn listing(debugger: &Debugger, chip: &CHIP8, count: usize) -> Vec<disasm::Line> {
+    let mut lines = disasm::disassemble_memory(chip.memory(), chip.program_counter(), count);
+    for line in &mut lines {
+        line.symbolize(debugger.symbols());
+    }
+    lines
+}
+
+/// Prints the label of `address`, if it has one.
+fn print_label(debugger: &Debugger, address: u16) {
+    if let Some(name) = debugger.symbols().name(address) {
+        println!("{}:", name);
+    }
+}
+
+/// Prints the instruction at the program counter.
+fn print_location(debugger: &Debugger, chip: &CHIP8) {
+    print_label(debugger, chip.program_counter());
+    match listing(debugger, chip, 1).first() {
+        Some(line) => println!("{}", line),
+        None => println!("{:#05X}  <out of bounds>", chip.program_counter()),
+    }
+}
+
+/// Prints `count` instructions starting at the program counter, marking breakpoints with `*`.
+fn print_listing(debugger: &Debugger, chip: &CHIP8, count: usize) {
+    let breakpoints: Vec<u16> = debugger.breakpoints().collect();
+    for line in listing(debugger, chip, count) {
+        print_label(debugger, line.address);
+        let marker = if line.address == chip.program_counter() {
+            "=>"
+        } else if breakpoints.contains(&line.address) {
+            " *"
+        } else {
+            "  "
+        };
+        println!("{} {}", marker, line);
+    }
+}
+
+fn print_registers(chip: &CHIP8) {
+    println!(
+        "PC={:#05X} I={:#05X} DT={} ST={} SP={}",
+        chip.program_counter(),
+        chip.index(),
+        chip.timer().delay(),
+        chip.timer().sound(),
+        chip.stack().len()
+    );
+    let variables: Vec<String> = (0..16)
+        .map(|x| format!("V{:X}={:02X}", x, chip.variable()[x]))
+        .collect();
+    println!("{}", variables.join(" "));
+}
+
+fn print_stack(chip: &CHIP8) {
+    if chip.stack().is_empty() {
+        println!("stack is empty");
+    }
+    for (depth, address) in chip.stack().iter().enumerate().rev() {
+        println!("#{:<2} {:#05X}", depth, address);
+    }
+}
+
+fn print_backtrace(chip: &CHIP8, address: u16) {
+    for location in callstack::backtrace(chip, address) {
+        println!("{}", location);
+    }
+}
+
+fn print_watch_list(debugger: &Debugger, chip: &CHIP8) {
+    for line in debugger.watch_list().lines(chip, debugger.symbols()) {
+        println!("{}", line);
+    }
+}
+
+/// Runs at full speed until a breakpoint is reached, the program fails, or the user presses
+/// Ctrl-C.
+///
+/// The instruction at the program counter is always executed, so resuming from a breakpoint
+/// does not stop at it again.
+async fn resume(debugger: &mut Debugger, chip: &mut CHIP8) -> StopReason {
+    let mut instructions = time::interval(INSTRUCTION_PERIOD);
+    let mut timers = time::interval(TIMER_PERIOD);
+    let interrupt = signal::ctrl_c();
+    tokio::pin!(interrupt);
+
+    loop {
+        tokio::select! {
+            _ = instructions.tick() => {
+                if let Some(reason) = debugger.run_step(chip) {
+                    return reason;
+                }
+            }
+            _ = timers.tick() => terminal::cycle_timers(chip),
+            _ = &mut interrupt => return debugger.interrupt(),
+        }
+    }
+}
+
+/// Executes a single instruction, running a called subroutine to completion.
+///
+/// Execution stops early if a breakpoint is reached inside the subroutine.
+async fn step_over(debugger: &mut Debugger, chip: &mut CHIP8) -> StopReason {
+    match debugger.start_step_over(chip) {
+        Some(reason) => reason,
+        None => resume(debugger, chip).await,
+    }
+}
+
+/// Reports why execution stopped, unless it was an uneventful step.
+fn report(reason: &StopReason, debugger: &Debugger, chip: &CHIP8) {
+    if *reason != StopReason::Step {
+        println!("{}", reason);
+    }
+    if let StopReason::Error(err) = reason {
+        print_backtrace(chip, err.address());
+    }
+    print_location(debugger, chip);
+}
+
+async fn execute(
+    debugger: &mut Debugger,
+    chip: &mut CHIP8,
+    slots: &mut Slots,
+    command: Command,
+) -> Result<Flow, String> {
+    match command {
+        Command::Break(address, condition) => {
+            if !debugger.add_breakpoint(address, condition) {
+                println!("updated breakpoint at {:#05X}", address);
+            }
+        }
+        Command::Delete(address) => {
+            if !debugger.remove_breakpoint(address) {
+                return Err(format!("no breakpoint at {:#05X}", address));
+            }
+        }
+        Command::Breakpoints => {
+            for address in debugger.breakpoints() {
+                match debugger.condition(address) {
+                    Some(condition) => {
+                        println!("{} if {}", debugger.symbols().describe(address), condition)
+                    }
+                    None => println!("{}", debugger.symbols().describe(address)),
+                }
+            }
+        }
+        Command::Watch(watchpoint) => debugger.add_watchpoint(watchpoint),
+        Command::Unwatch(address) => {
+            if !debugger.remove_watchpoint(address) {
+                return Err(format!("no watchpoint at {:#05X}", address));
+            }
+        }
+        Command::Watchpoints => {
+            for watchpoint in debugger.watchpoints() {
+                println!("{}", watchpoint);
+            }
+        }
+        Command::Step(count) => {
+            for _ in 0..count {
+                let reason = debugger.step(chip);
+                if reason != StopReason::Step {
+                    report(&reason, debugger, chip);
+                    return Ok(Flow::Continue);
+                }
+            }
+            print_location(debugger, chip);
+        }
+        Command::Next => {
+            let reason = step_over(debugger, chip).await;
+            report(&reason, debugger, chip)
+        }
+        Command::Continue => {
+            let reason = resume(debugger, chip).await;
+            report(&reason, debugger, chip)
+        }
+        Command::ReverseStep(count) => {
+            let reason = debugger.reverse_step(chip, count);
+            report(&reason, debugger, chip)
+        }
+        Command::ReverseContinue => {
+            let reason = debugger.reverse_continue(chip);
+            report(&reason, debugger, chip)
+        }
+        Command::List(count) => print_listing(debugger, chip, count),
+        Command::Registers => print_registers(chip),
+        Command::Set(register, value) => {
+            register.write(chip, value);
+            debugger.clear_history();
+            println!("{} = {:#X}", register, register.read(chip));
+        }
+        Command::Stack => print_stack(chip),
+        Command::Backtrace => print_backtrace(chip, chip.program_counter()),
+        Command::Memory(address, rows) => {
+            for row in Inspector::new(chip).rows(chip, address, rows) {
+                println!("{}", row);
+            }
+        }
+        Command::Poke(address, bytes) => {
+            inspector::poke(chip, address, &bytes)?;
+            debugger.clear_history();
+        }
+        Command::Heatmap(None) => print!("{}", debugger.heatmap().text()),
+        Command::Heatmap(Some(path)) => {
+            fs::write(&path, debugger.heatmap().png()).map_err(|err| err.to_string())?;
+            println!("wrote {}", path);
+        }
+        Command::Search(filter) => {
+            if let Some(filter) = filter {
+                debugger.search().filter(chip, filter);
+            }
+            println!("{}", debugger.search().summary(chip));
+        }
+        Command::NewSearch => {
+            debugger.search().start(chip);
+            println!("{}", debugger.search().summary(chip));
+        }
+        Command::Pin(address, name) => debugger.pin(address, name),
+        Command::PinFound => println!("pinned {} addresses", debugger.pin_found()?),
+        Command::Unpin(address) => {
+            if !debugger.unpin(address) {
+                return Err(format!("{:#05X} is not pinned", address));
+            }
+        }
+        Command::Pins => print_watch_list(debugger, chip),
+        Command::SaveState(slot) => println!("{}", slots.save(chip, slot)?),
+        Command::LoadState(slot) => {
+            println!("{}", slots.load(chip, slot)?);
+            debugger.clear_history();
+            print_location(debugger, chip);
+        }
+        Command::Cheat(cheat) => debugger.add_cheat(chip, cheat),
+        Command::Uncheat(address) => {
+            if !debugger.remove_cheat(address) {
+                return Err(format!("no cheat at {:#05X}", address));
+            }
+        }
+        Command::Cheats => {
+            for cheat in debugger.cheats() {
+                println!("{}", cheat);
+            }
+        }
+        Command::Help => println!("{}", HELP),
+        Command::Quit => return Ok(Flow::Quit),
+    }
+    Ok(Flow::Continue)
+}
+
+/// Runs an interactive debugging session on standard input until the user quits. `slots` holds
+/// the save states of the ROM.
+pub async fn run(chip: &mut CHIP8, symbols: Symbols, mut slots: Slots) {
+    let mut debugger = Debugger::new(chip.memory().len());
+    debugger.set_symbols(symbols);
+    let mut lines = BufReader::new(io::stdin()).lines();
+    let mut last = String::new();
+
+    print_location(&debugger, chip);
+    loop {
+        print!("(chip8) ");
+        let _ = std::io::stdout().flush();
+
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            _ => break,
+        };
+        if !line.trim().is_empty() {
+            last = line;
+        }
+
+        if last.is_empty() {
+            continue;
+        }
+        let result = match Command::parse(&last, debugger.symbols()) {
+            Ok(command) => {
+                let state = command.executes().then(|| State::take(chip));
+                let result = execute(&mut debugger, chip, &mut slots, command).await;
+                if let Some(changes) = state.map(|state| state.changes(chip)) {
+                    if !changes.is_empty() {
+                        println!("changed: {}", changes.join(", "));
+                    }
+                    let watch_list = debugger.watch_list().lines(chip, debugger.symbols());
+                    if !watch_list.is_empty() {
+                        println!("watch: {}", watch_list.join(", "));
+                    }
+                }
+                result
+            }
+            Err(message) => Err(message),
+        };
+        match result {
+            Ok(Flow::Continue) => (),
+            Ok(Flow::Quit) => break,
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+}
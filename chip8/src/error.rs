@@ -0,0 +1,130 @@
+//! Errors that end a command, and the exit status each ends the emulator with.
+
+use chip8_core::error::ExecError;
+use chip8_core::netplay::NetplayError;
+use chip8_core::rom::RomError;
+use std::io;
+use std::path::Path;
+
+/// Exit status of a program that failed, or of a check or comparison that found problems.
+pub const EXIT_FAILED: i32 = 1;
+/// Exit status of a command line the emulator does not understand.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit status of a file that could not be read, written or understood.
+pub const EXIT_INPUT: i32 = 3;
+/// Exit status of a terminal or connection that failed.
+pub const EXIT_IO: i32 = 4;
+
+/// Why a command failed.
+#[derive(Debug, thiserror::Error)]
+pub enum FrontendError {
+    /// The arguments are not ones the command takes.
+    #[error("{0}")]
+    Usage(String),
+    /// A file could not be read or written.
+    #[error("{path}: {source}")]
+    File {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// A ROM could not be read or does not fit in memory.
+    #[error("{path}: {source}")]
+    Rom {
+        path: String,
+        #[source]
+        source: RomError,
+    },
+    /// A file such as a script or a movie is malformed. Messages about a line start with
+    /// `line N:`, which is shown after the path like a compiler does.
+    #[error("{path}:{}", located(.message))]
+    Parse { path: String, message: String },
+    /// The program failed while running.
+    #[error(transparent)]
+    Exec(#[from] ExecError),
+    /// The terminal, the window or a connection failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A game played with another machine went out of step or received nonsense.
+    #[error(transparent)]
+    Netplay(NetplayError),
+    /// A check, comparison or script found problems, which it has already reported.
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Formats `message` to follow a path and a colon.
+fn located(message: &str) -> String {
+    match message.strip_prefix("line ") {
+        Some(message) => message.to_string(),
+        None => format!(" {}", message),
+    }
+}
+
+impl From<NetplayError> for FrontendError {
+    fn from(err: NetplayError) -> Self {
+        match err {
+            NetplayError::Exec(err) => FrontendError::Exec(err),
+            err => FrontendError::Netplay(err),
+        }
+    }
+}
+
+impl FrontendError {
+    /// Returns a function that wraps the failure to read or write `path`, for `map_err`.
+    pub fn file(path: impl AsRef<Path>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.as_ref().display().to_string();
+        move |source| FrontendError::File { path, source }
+    }
+
+    /// Returns a function that wraps the failure to load the ROM at `path`, for `map_err`.
+    pub fn rom(path: &str) -> impl FnOnce(RomError) -> Self + '_ {
+        move |source| FrontendError::Rom {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    /// Returns a function that wraps a message about the contents of `path`, for `map_err`.
+    pub fn parse(path: &str) -> impl FnOnce(String) -> Self + '_ {
+        move |message| FrontendError::Parse {
+            path: path.to_string(),
+            message,
+        }
+    }
+
+    /// Returns the status the emulator exits with.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FrontendError::Exec(_) | FrontendError::Netplay(_) | FrontendError::Failed(_) => {
+                EXIT_FAILED
+            }
+            FrontendError::Usage(_) => EXIT_USAGE,
+            FrontendError::File { .. }
+            | FrontendError::Rom { .. }
+            | FrontendError::Parse { .. } => EXIT_INPUT,
+            FrontendError::Io(_) => EXIT_IO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let parse = FrontendError::parse("game.txt");
+        let err = parse("line 3: unknown command `jump`".to_string());
+        assert_eq!(err.to_string(), "game.txt:3: unknown command `jump`");
+        assert_eq!(err.exit_code(), EXIT_INPUT);
+        let err = FrontendError::parse("game.json")("not an object".to_string());
+        assert_eq!(err.to_string(), "game.json: not an object");
+
+        let err = FrontendError::rom("game.ch8")(RomError::Empty);
+        assert_eq!(err.to_string(), "game.ch8: ROM is empty");
+        let err = FrontendError::from(ExecError::StackUnderflow { address: 0x200 });
+        assert_eq!(err.to_string(), "return with an empty stack at 0x200");
+        assert_eq!(err.exit_code(), EXIT_FAILED);
+    }
+}
@@ -0,0 +1,109 @@
+//! The terminal as a frontend for `chip8 run`: the game is drawn with half blocks in a pane of its
+//! own, the bell rings while the sound timer runs, and the left-hand side of the keyboard is the
+//! keypad.
+
+use crate::terminal::{self, FullScreen, Key, KEY_HOLD};
+use chip8_core::display::{Display, HEIGHT, WIDTH};
+use chip8_core::frontend::{Audio, Input, Video};
+use chip8_core::keyboard::{self, Keyboard};
+use emu_debug::log::{Hold, Log};
+use ratatui::layout::{Constraint, Flex, Layout};
+use ratatui::text::Line;
+use ratatui::widgets::Paragraph;
+use std::io;
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// Draws the game in a pane in the middle of the terminal, which is in raw mode until this is
+/// dropped. Meanwhile the log is held, and its events are written once the terminal is back to
+/// normal.
+pub struct Screen {
+    full_screen: FullScreen,
+    _hold: Hold,
+    title: String,
+}
+
+impl Screen {
+    pub fn new(title: &str, log: &Log) -> io::Result<Self> {
+        Ok(Screen {
+            // Dropped before the log is let go of, which is declared after.
+            full_screen: FullScreen::enable()?,
+            _hold: log.hold(),
+            title: title.to_string(),
+        })
+    }
+}
+
+impl Video for Screen {
+    fn draw(&mut self, display: &Display) {
+        let lines: Vec<Line> = terminal::half_blocks(display)
+            .into_iter()
+            .map(Line::from)
+            .collect();
+        let title = &self.title;
+        let drawn = self.full_screen.draw(|frame| {
+            let [area] = Layout::horizontal([Constraint::Length(WIDTH as u16 + 2)])
+                .flex(Flex::Center)
+                .areas(frame.area());
+            let [area] = Layout::vertical([Constraint::Length(HEIGHT as u16 / 2 + 2)])
+                .flex(Flex::Center)
+                .areas(area);
+            frame.render_widget(Paragraph::new(lines).block(terminal::pane(title)), area);
+        });
+        if let Err(err) = drawn {
+            tracing::warn!("drawing the game: {}", err);
+        }
+    }
+}
+
+/// Rings the terminal bell for every frame the tone plays.
+pub struct Bell;
+
+impl Audio for Bell {
+    fn play(&mut self, sounding: bool) {
+        if sounding {
+            terminal::bell();
+        }
+    }
+}
+
+/// Reads the keypad from the terminal. Ctrl-C or Ctrl-Q quits.
+pub struct Keys {
+    input: mpsc::UnboundedReceiver<Key>,
+    /// When each keypad key is released, as the terminal does not report it.
+    releases: [Option<Instant>; 16],
+}
+
+impl Keys {
+    pub fn new() -> Self {
+        Keys {
+            input: terminal::spawn_reader(),
+            releases: [None; 16],
+        }
+    }
+}
+
+impl Input for Keys {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        let now = Instant::now();
+        while let Ok(key) = self.input.try_recv() {
+            match key {
+                Key::Ctrl('c') | Key::Ctrl('q') => return false,
+                Key::Char(c) => {
+                    if let Some(key) = keyboard::key_for_char(c) {
+                        keyboard.set(key, true);
+                        self.releases[key as usize] = Some(now + KEY_HOLD);
+                    }
+                }
+                _ => (),
+            }
+        }
+        for (key, release) in self.releases.iter_mut().enumerate() {
+            if release.is_some_and(|at| at <= now) {
+                keyboard.set(key as u8, false);
+                *release = None;
+            }
+        }
+        true
+    }
+}
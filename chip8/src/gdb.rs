@@ -0,0 +1,299 @@
+//! Stub for the GDB remote serial protocol, so programs can be debugged from GDB or an IDE.
+//!
+//! The protocol is that of [`emu_debug::gdb`], shared with the processors of the other systems;
+//! this stub adds stepping and continuing backwards through the history of the CHIP-8 debugger,
+//! and runs the program at its own speed, with its timers.
+//!
+//! Registers are numbered as in the target description sent to GDB: `V0`-`VF` are 0-15, followed
+//! by `I`, `PC`, `DT` and `ST`. Multi-byte registers are little-endian.
+
+use crate::terminal;
+use chip8_core::debugger::{Debugger, StopReason};
+use chip8_core::{CHIP8, INSTRUCTION_PERIOD, TIMER_PERIOD};
+use emu_debug::gdb::{self, Command, Input, Point, INTERRUPT, PACKET_SIZE};
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+/// What to do after handling a packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Action {
+    Reply(String),
+    /// Run until something stops the program, then send a stop reply.
+    Continue,
+    /// Execute one instruction, then send a stop reply.
+    Step,
+    /// Send a stop reply for something that already happened.
+    Stop(StopReason),
+    /// Close the connection, replying first if there is a reply.
+    Detach(Option<String>),
+}
+
+/// A debugging session with one GDB client.
+struct Session {
+    debugger: Debugger,
+    /// The target description.
+    xml: String,
+    /// Whether packets are acknowledged, which GDB may turn off.
+    ack: bool,
+}
+
+impl Session {
+    fn new(chip: &CHIP8) -> Self {
+        Session {
+            debugger: Debugger::new(chip.memory().len()),
+            xml: gdb::target_xml(chip),
+            ack: true,
+        }
+    }
+
+    /// Handles one packet.
+    fn handle(&mut self, chip: &mut CHIP8, packet: &str) -> Action {
+        let reply = |body: &str| Action::Reply(body.to_string());
+        let ok = |done: bool| reply(if done { "OK" } else { "E01" });
+
+        let command = match gdb::parse(packet) {
+            Some(command) => command,
+            None => return reply("E01"),
+        };
+        // Undoing instructions would clobber changes made by the client.
+        if matches!(
+            command,
+            Command::WriteRegisters(_) | Command::WriteRegister(..) | Command::WriteMemory { .. }
+        ) {
+            self.debugger.clear_history();
+        }
+        match command {
+            Command::Halted => reply("S05"),
+            Command::ReadRegisters => Action::Reply(gdb::read_registers(chip)),
+            Command::WriteRegisters(hex) => ok(gdb::write_registers(chip, hex)),
+            Command::ReadRegister(number) => match gdb::read_register(chip, number) {
+                Some(hex) => Action::Reply(hex),
+                None => reply("E01"),
+            },
+            Command::WriteRegister(number, hex) => ok(gdb::write_register(chip, number, hex)),
+            Command::ReadMemory { address, len } => match gdb::read_memory(chip, address, len) {
+                Some(hex) => Action::Reply(hex),
+                None => reply("E01"),
+            },
+            Command::WriteMemory { address, data } => ok(gdb::write_memory(chip, address, &data)),
+            Command::Continue(address) | Command::Step(address) => {
+                if let Some(address) = address {
+                    chip.set_program_counter(address);
+                }
+                if matches!(command, Command::Continue(_)) {
+                    Action::Continue
+                } else {
+                    Action::Step
+                }
+            }
+            Command::ReverseStep => Action::Stop(self.debugger.reverse_step(chip, 1)),
+            Command::ReverseContinue => Action::Stop(self.debugger.reverse_continue(chip)),
+            Command::Insert(Point::Breakpoint(address)) => {
+                self.debugger.add_breakpoint(address, None);
+                reply("OK")
+            }
+            Command::Insert(Point::Watchpoint(watchpoint)) => {
+                self.debugger.add_watchpoint(watchpoint);
+                reply("OK")
+            }
+            Command::Remove(Point::Breakpoint(address)) => {
+                self.debugger.remove_breakpoint(address);
+                reply("OK")
+            }
+            Command::Remove(Point::Watchpoint(watchpoint)) => {
+                self.debugger.remove_watchpoint(watchpoint.address);
+                reply("OK")
+            }
+            Command::Insert(Point::Unsupported) | Command::Remove(Point::Unsupported) => reply(""),
+            Command::Detach => Action::Detach(Some("OK".to_string())),
+            Command::Kill => Action::Detach(None),
+            Command::SetThread => reply("OK"),
+            Command::Other(packet) => Action::Reply(gdb::query(
+                packet,
+                &self.xml,
+                ";ReverseStep+;ReverseContinue+",
+                &mut self.ack,
+            )),
+        }
+    }
+
+    async fn send(&self, stream: &mut TcpStream, body: &str) -> io::Result<()> {
+        stream.write_all(gdb::frame(body).as_bytes()).await
+    }
+
+    /// Runs the program until it stops, watching the connection for an interrupt.
+    async fn resume(
+        &mut self,
+        chip: &mut CHIP8,
+        stream: &mut TcpStream,
+        buffer: &mut Vec<u8>,
+    ) -> io::Result<StopReason> {
+        let mut instructions = time::interval(INSTRUCTION_PERIOD);
+        let mut timers = time::interval(TIMER_PERIOD);
+        let mut chunk = [0u8; 256];
+
+        loop {
+            tokio::select! {
+                _ = instructions.tick() => {
+                    if let Some(reason) = self.debugger.run_step(chip) {
+                        return Ok(reason);
+                    }
+                }
+                _ = timers.tick() => terminal::cycle_timers(chip),
+                read = stream.read(&mut chunk) => {
+                    let count = read?;
+                    if count == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    buffer.extend_from_slice(&chunk[..count]);
+                    if let Some(position) = buffer.iter().position(|&byte| byte == INTERRUPT) {
+                        buffer.remove(position);
+                        return Ok(self.debugger.interrupt());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Serves a connection until GDB detaches.
+    async fn serve(&mut self, chip: &mut CHIP8, stream: &mut TcpStream) -> io::Result<()> {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; PACKET_SIZE];
+
+        loop {
+            let input = match gdb::next_input(&mut buffer) {
+                Some(input) => input,
+                None => {
+                    let count = stream.read(&mut chunk).await?;
+                    if count == 0 {
+                        return Ok(());
+                    }
+                    buffer.extend_from_slice(&chunk[..count]);
+                    continue;
+                }
+            };
+
+            let packet = match input {
+                Input::Packet(packet) => packet,
+                Input::Corrupt => {
+                    stream.write_all(b"-").await?;
+                    continue;
+                }
+                // The program is already stopped.
+                Input::Interrupt => {
+                    self.send(stream, "S02").await?;
+                    continue;
+                }
+            };
+            if self.ack {
+                stream.write_all(b"+").await?;
+            }
+
+            let reason = match self.handle(chip, &packet) {
+                Action::Reply(body) => {
+                    self.send(stream, &body).await?;
+                    continue;
+                }
+                Action::Continue => self.resume(chip, stream, &mut buffer).await?,
+                Action::Step => self.debugger.step(chip),
+                Action::Stop(reason) => reason,
+                Action::Detach(reply) => {
+                    if let Some(body) = reply {
+                        self.send(stream, &body).await?;
+                    }
+                    return Ok(());
+                }
+            };
+            self.send(stream, &stop_reply(&reason)).await?;
+        }
+    }
+}
+
+/// Returns the stop reply packet for `reason`.
+fn stop_reply(reason: &StopReason) -> String {
+    match reason {
+        StopReason::Step => "S05".to_string(),
+        StopReason::Breakpoint(_) => "T05swbreak:;".to_string(),
+        StopReason::Watchpoint { access, .. } => gdb::watch_reply(access.kind, access.address),
+        StopReason::Interrupted => "S02".to_string(),
+        // Report failures as an illegal instruction.
+        StopReason::Error(_) => "S04".to_string(),
+        StopReason::StartOfHistory => "T05replaylog:begin;".to_string(),
+        _ => "S05".to_string(),
+    }
+}
+
+/// Waits for GDB to connect on `port`, then lets it control `chip` until it detaches.
+pub async fn serve(chip: &mut CHIP8, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    tracing::info!(
+        "waiting for gdb on port {}; use `target remote :{}`",
+        port,
+        port
+    );
+    let (mut stream, address) = listener.accept().await?;
+    tracing::info!("connected to {}", address);
+    stream.set_nodelay(true)?;
+    Session::new(chip).serve(chip, &mut stream).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::rom::Rom;
+
+    fn chip() -> CHIP8 {
+        let mut chip = CHIP8::new();
+        // 0x200: LD V0, 5; 0x202: JP 0x202
+        chip.load_rom(&Rom::from_bytes(vec![0x60, 0x05, 0x12, 0x02]).unwrap())
+            .unwrap();
+        chip
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut chip = chip();
+        let mut session = Session::new(&chip);
+        chip.set_index(0x1234);
+
+        let reply =
+            |session: &mut Session, chip: &mut CHIP8, packet| match session.handle(chip, packet) {
+                Action::Reply(body) => body,
+                action => panic!("unexpected {:?}", action),
+            };
+        let registers = reply(&mut session, &mut chip, "g");
+        assert_eq!(&registers[32..], "34120002 0000".replace(' ', ""));
+        assert_eq!(reply(&mut session, &mut chip, "P3=7f"), "OK");
+        assert_eq!(chip.variable()[3], 0x7F);
+        assert_eq!(reply(&mut session, &mut chip, "p11"), "0002");
+        assert_eq!(reply(&mut session, &mut chip, "m200,4"), "60051202");
+        assert_eq!(reply(&mut session, &mut chip, "M300,2:abcd"), "OK");
+        assert_eq!(chip.memory()[0x301], 0xCD);
+        assert_eq!(reply(&mut session, &mut chip, "m1000,1"), "E01");
+        assert!(reply(
+            &mut session,
+            &mut chip,
+            "qXfer:features:read:target.xml:0,10"
+        )
+        .starts_with("m<?xml"));
+        assert_eq!(reply(&mut session, &mut chip, "vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut chip = chip();
+        let mut session = Session::new(&chip);
+        assert_eq!(
+            session.handle(&mut chip, "Z0,202,2"),
+            Action::Reply("OK".to_string())
+        );
+        assert_eq!(session.handle(&mut chip, "c"), Action::Continue);
+        assert_eq!(
+            session.debugger.run_step(&mut chip),
+            Some(StopReason::Breakpoint(0x202))
+        );
+        assert_eq!(stop_reply(&StopReason::Breakpoint(0x202)), "T05swbreak:;");
+    }
+}
@@ -1,9 +0,0 @@
-//! Keyboard-related structs and methods.
-
-pub struct Keyboard {}
-
-impl Keyboard {
-    pub fn new() -> Self {
-        Keyboard {}
-    }
-}
@@ -1,173 +1,1168 @@
 //! CHIP-8 emulator in pure Rust.
 
-mod display;
-mod font;
-mod keyboard;
-mod timer;
-
-use crate::display::Display;
-use crate::font::FONT_SET;
-use crate::keyboard::Keyboard;
-use crate::timer::Timer;
+mod api;
+mod console;
+mod error;
+mod frontend;
+mod gdb;
+mod netplay;
+mod remote;
+#[cfg(feature = "sdl")]
+mod sdl;
+mod terminal;
+mod tui;
+mod websocket;
+
+use crate::api::Machine;
+use crate::error::FrontendError;
+use chip8_core::cheat::Cheat;
+use chip8_core::compare::Outcome;
+use chip8_core::coverage::Coverage;
+use chip8_core::crowd::DEFAULT_WINDOW;
+use chip8_core::error::ExecError;
+use chip8_core::fingerprint::Fingerprint;
+use chip8_core::frontend::{Audio, Headless, Input, Runner, Video};
+use chip8_core::heatmap::Heatmap;
+use chip8_core::memory::AccessKind;
+use chip8_core::movie::{Movie, Recorder};
+use chip8_core::netplay::{Session, CHECKSUM_FRAMES, DEFAULT_DELAY, MAX_DELAY};
+use chip8_core::playtest::Playtest;
+use chip8_core::profile::Profiler;
+use chip8_core::rom::{Rom, RomError, Severity};
+use chip8_core::script::Script;
+use chip8_core::sprites::Finder;
+use chip8_core::state::{Resume, Settings, Slots};
+use chip8_core::suite::{self, Report, Test};
+use chip8_core::symbols::Symbols;
+use chip8_core::trace::Tracer;
+use chip8_core::{
+    analysis, asm, callstack, cheat, command, compare, crash, disasm, movie, octo, playtest, print,
+    sprites, state, CHIP8, INSTRUCTION_PERIOD, STEPS_PER_FRAME, TIMER_PERIOD,
+};
+use emu_debug::log::{self, Log};
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::process;
 use std::time::Duration;
-use tinyvec::*;
 use tokio::time;
 
-/// CHIP-8 implementation in Rust.
-struct CHIP8 {
-    /// Memory for the CHIP-8.
-    ///
-    /// All memory is considered RAM and therefore writable. We use 4KB
-    /// (4096 bytes) because the index register and program counter can only address 12 bits (4096
-    /// addresses).
-    ///
-    /// The first 512 bits are reserved (`0x0..0x200`).
-    pub memory: [u8; 4096],
-
-    /// Display for the CHIP-8.
-    pub display: Display,
-
-    /// Stack for the CHIP-8.
-    ///
-    /// The stack is comprised of 16 two-byte entries. In case of overflow, data will
-    /// be allocated on the heap.
-    pub stack: TinyVec<[(u8, u8); 16]>,
-
-    /// Variable register for the CHIP-8.
-    ///
-    /// These are numbered `0x0..=0xF` and referenced as `V0..=VF`. `VF` is also used as a flag register
-    /// based on some rule.
-    pub variable: [u8; 16],
-
-    /// Index register for the CHIP-8.
-    ///
-    /// The index register points to locations in memory.
-    pub index: u16,
-
-    /// Timers for the CHIP-8.
-    pub timer: Timer,
-
-    /// Keyboard for the CHIP-8.
-    ///
-    /// TODO: Implement keypad somewhere here.
-    pub keyboard: Keyboard,
-
-    /// Program Counter for the CHIP-8.
-    ///
-    /// The program counter points to the current instruction in memory.
-    pub program_counter: u16,
-}
-
-impl CHIP8 {
-    /// Constructs a new, empty `CHIP8`.
-    ///
-    /// # Examples
-    /// ```
-    /// let mut chip: CHIP8 = CHIP8::new();
-    /// ```
-    pub fn new() -> Self {
-        let mut memory: [u8; 4096] = [0; 4096];
-        // Insert fonts into address range 0x50..=0x9F.
-        for address in 0x50..=0x9F {
-            let font_idx = address - 0x50;
-            memory[address] = FONT_SET[font_idx];
-        }
-
-        let display: Display = Display::new();
-
-        let stack: TinyVec<[(u8, u8); 16]> = tiny_vec!();
-
-        let variable: [u8; 16] = [0; 16];
-
-        let index: u16 = 0;
-
-        let timer: Timer = Timer::new();
-
-        let keyboard: Keyboard = Keyboard::new();
-
-        let program_counter: u16 = 0;
-
-        CHIP8 {
-            memory,
-            display,
-            stack,
-            variable,
-            index,
-            timer,
-            keyboard,
-            program_counter,
-        }
-    }
-
-    /// Fetches an instruction from the current program counter.
-    fn fetch(&mut self) -> (u8, u8) {
-        let instr_one = self.memory[self.program_counter as usize];
-        self.program_counter += 1;
-
-        let instr_two = self.memory[self.program_counter as usize];
-        self.program_counter += 1;
-
-        (instr_one, instr_two)
-    }
-
-    /// Decodes and executes the given instruction.
-    fn decode_execute(&mut self, instruction: (u8, u8)) {
-        // nibble 1. type of instruction.
-        let itype: u8 = (instruction.0 & 0xF0) >> 4;
-        // nibble 2. Used to look up one of 16 registers V0-VF.
-        let x: u8 = instruction.0 & 0x0F;
-        // nibble 3. Used to look up one of 16 registers V0-VF.
-        let y: u8 = (instruction.1 & 0xF0) >> 4;
-        // nibble 4. 4-bit number.
-        let n: u8 = instruction.1 & 0x0F;
-        // second byte (nibble 3 and 4). 8-bit immediate number.
-        let nn: u8 = instruction.1;
-        // nibble 2, 3, and 4. 12-bit immediate memory address
-        let nnn: u16 = (x as u16) << 8 | (y as u16) << 4 | (n as u16);
-
-        match itype {
-            // 00E0 - Clear screen.
-            0x0 => self.display.clear(),
-            // 1NNN - Jump. Set the program counter to nnn.
-            0x1 => match nnn {
-                _ => self.program_counter = nnn,
-            },
-            // 6XNN - Set register VX to nn.
-            0x6 => self.variable[x as usize] = nn,
-            // 7XNN - Add value nn to VX.
-            0x7 => {
-                // TODO: Overflow???
-                if self.variable[0] == 0xFF {
-                    self.variable[x as usize] += nn;
+/// Time between two auto-saves while a ROM runs, in case the emulator crashes.
+const AUTOSAVE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Runs frames in real time through `runner` until the user quits or an error occurs, executing
+/// each instruction with `step`.
+///
+/// `step` is usually [`CHIP8::step`], or a wrapper around it that observes execution. The
+/// emulator runs at the speed of the machine's configuration, 700 instructions per second by
+/// default, while the timers count down at 60 Hz.
+async fn run_in_real_time<V, A, I, F>(
+    runner: &mut Runner<V, A, I>,
+    mut step: F,
+) -> Result<(), ExecError>
+where
+    V: Video,
+    A: Audio,
+    I: Input,
+    F: FnMut(&mut CHIP8) -> Result<(), ExecError>,
+{
+    let mut frames = time::interval(TIMER_PERIOD);
+    loop {
+        frames.tick().await;
+        if !runner.frame_with(&mut step)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs `runner` like [`run_in_real_time`], also stopping cleanly on Ctrl-C so that the reports
+/// are written, and gives the machine back.
+async fn run_until_stopped<V, A, I, F>(
+    mut runner: Runner<V, A, I>,
+    step: F,
+) -> (CHIP8, Result<(), ExecError>)
+where
+    V: Video,
+    A: Audio,
+    I: Input,
+    F: FnMut(&mut CHIP8) -> Result<(), ExecError>,
+{
+    let result = tokio::select! {
+        result = run_in_real_time(&mut runner, step) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    (runner.chip, result)
+}
+
+/// Prints how to use the emulator.
+fn usage() {
+    eprintln!("usage: chip8 [run] [options] <rom>");
+    eprintln!("       chip8 check <rom>");
+    eprintln!("       chip8 identify <rom>");
+    eprintln!("       chip8 debug [--symbols <file>] <rom>");
+    eprintln!("       chip8 disasm [--symbols <file>] <rom>");
+    eprintln!("       chip8 gdb [--port <port>] <rom>");
+    eprintln!("       chip8 netplay --host <port> [--bind <address>] [--delay <frames>] <rom>");
+    eprintln!("       chip8 netplay --connect <address> <rom>");
+    eprintln!("       chip8 serve [--port <port>] [--bind <address>]");
+    eprintln!("                   [--crowd [--window <frames>]] <rom>");
+    eprintln!("       chip8 serve --api [--token <token>] [--port <port>] [--bind <address>]");
+    eprintln!("                   [--crowd [--window <frames>]] [<rom>]");
+    eprintln!("       chip8 script [--symbols <file>] <script> <rom>");
+    eprintln!("       chip8 compare <trace> <rom>");
+    eprintln!("       chip8 selftest <dir>");
+    eprintln!("       chip8 test <script.toml>");
+    eprintln!("       chip8 asm <source> [<rom>]");
+    eprintln!("       chip8 sprites [--frames <count>] <rom>");
+    eprintln!("       chip8 record [--checksum-frames <count>] <movie> <rom>");
+    eprintln!("       chip8 play <movie> <rom>");
+    eprintln!("       chip8 state inspect <file>");
+    eprintln!("       chip8 state diff <file> <file>");
+    eprintln!("       chip8 state export <file> [<json>]");
+    eprintln!("       chip8 state import [--format json] <json> <file>");
+    eprintln!("       chip8 state import --format octo <json> <file> <rom>");
+    eprintln!();
+    eprintln!("run options:");
+    eprintln!("  --sdl                   show the game in an SDL2 window instead of the terminal");
+    eprintln!("  --symbols <file>        label addresses in traces using a symbol file");
+    eprintln!("  --cheat <code>          patch memory with a poke code such as 300:05");
+    eprintln!("  --cheats <file>         patch memory with the poke codes in a file");
+    eprintln!(
+        "  --debug-print           treat 0F0X, 0F10 and 0F11 as prints of VX, the string at I"
+    );
+    eprintln!("                          and the registers");
+    eprintln!("  --trace <file>          log every instruction to a file");
+    eprintln!("  --trace-last <count>    print the last instructions if the program fails");
+    eprintln!("  --trace-range <a>-<b>   only trace instructions between two addresses");
+    eprintln!("  --trace-op <mnemonic>   only trace instructions such as DRW or CALL");
+    eprintln!("  --profile               print the most executed instructions on exit");
+    eprintln!("  --profile-folded <file> write call stacks in the folded flame graph format");
+    eprintln!("  --coverage <file>       write a disassembly showing which bytes were used");
+    eprintln!("  --coverage-html <file>  write the same disassembly as an HTML page");
+    eprintln!("  --heatmap <file>        write a PNG image of memory reads and writes");
+    eprintln!("  --crash-report <file>   where to describe the machine if the program fails");
+    eprintln!("                          (default: <rom name>.crash.txt)");
+    eprintln!();
+    eprintln!("options of every command:");
+    eprintln!("  --log <filter>          what to log, such as `debug` or `warn,chip8_core=trace`");
+    eprintln!("                          (default: $RUST_LOG, or `info`)");
+    eprintln!("  --log-json              log JSON lines instead of text");
+}
+
+/// Reads a ROM.
+fn read_rom(path: &str) -> Result<Rom, FrontendError> {
+    Rom::from_file(path).map_err(FrontendError::rom(path))
+}
+
+/// Reads a text file.
+fn read_text(path: &str) -> Result<String, FrontendError> {
+    std::fs::read_to_string(path).map_err(FrontendError::file(path))
+}
+
+/// Writes `contents` to a file.
+fn write_file(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<(), FrontendError> {
+    std::fs::write(&path, contents).map_err(FrontendError::file(path))
+}
+
+/// Checks a ROM for problems without running it.
+///
+/// Fails if any errors were found.
+fn check(path: &str) -> Result<(), FrontendError> {
+    let rom = read_rom(path)?;
+    let mut diagnostics = rom.check();
+    diagnostics.extend(analysis::analyze(&rom));
+    diagnostics.sort();
+    if diagnostics.is_empty() {
+        println!("{}: no problems found", path);
+        return Ok(());
+    }
+
+    for diagnostic in &diagnostics {
+        println!("{}: {}", path, diagnostic);
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(FrontendError::Failed(format!(
+            "{}: {} errors found",
+            path, errors
+        )));
+    }
+    Ok(())
+}
+
+/// Runs the ROMs of the community test suite found in `dir` and prints a JSON summary of what
+/// they report.
+fn selftest(dir: &str) -> Result<(), FrontendError> {
+    let mut files: Vec<String> = std::fs::read_dir(dir)
+        .map_err(FrontendError::file(dir))?
+        .filter_map(|entry| Some(entry.ok()?.path().to_str()?.to_string()))
+        .filter(|path| path.ends_with(".ch8"))
+        .collect();
+    files.sort();
+
+    let mut reports = Vec::new();
+    for test in suite::TESTS.iter() {
+        let path = files.iter().find(|path| {
+            let name = Path::new(path).file_name().unwrap_or_default();
+            Test::find(&name.to_string_lossy()) == Some(*test)
+        });
+        let result = match path {
+            Some(path) => {
+                let mut chip = CHIP8::new();
+                chip.load_rom(&read_rom(path)?)
+                    .map_err(FrontendError::rom(path))?;
+                Some(test.run(chip))
+            }
+            None => None,
+        };
+        reports.push(Report {
+            test: *test,
+            rom: path.cloned(),
+            result,
+        });
+    }
+
+    if reports.iter().all(|report| report.result.is_none()) {
+        return Err(FrontendError::Failed(format!(
+            "{}: no test suite ROMs found",
+            dir
+        )));
+    }
+    println!("{}", suite::summary(&reports));
+    if !suite::passed(&reports) {
+        return Err(FrontendError::Failed("the test suite failed".to_string()));
+    }
+    Ok(())
+}
+
+/// Plays the ROM of a gameplay test with the keys it presses, and prints the results of its checks
+/// in the Test Anything Protocol.
+///
+/// Fails if any check failed.
+fn playtest(path: &str) -> Result<(), FrontendError> {
+    let test = Playtest::parse(&read_text(path)?).map_err(FrontendError::parse(path))?;
+    let rom_path = Path::new(path).with_file_name(&test.rom);
+    let rom_path = rom_path.to_string_lossy();
+    let mut chip = test
+        .builder()
+        .build()
+        .map_err(|err| FrontendError::Failed(format!("{}: {}", path, err)))?;
+    chip.load_rom(&read_rom(&rom_path)?)
+        .map_err(FrontendError::rom(&rom_path))?;
+
+    let outcomes = test.run(chip);
+    print!("{}", playtest::tap(&outcomes));
+    let failed = outcomes.iter().filter(|outcome| !outcome.passed()).count();
+    if failed > 0 {
+        return Err(FrontendError::Failed(format!(
+            "{}: {} of {} checks failed",
+            path,
+            failed,
+            outcomes.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Prints a fingerprint of a ROM and a guess at the variant it targets.
+fn identify(path: &str) -> Result<(), FrontendError> {
+    let rom = read_rom(path)?;
+    print!("{}", Fingerprint::new(&rom).report());
+    Ok(())
+}
+
+/// Reads a symbol file, or returns no symbols without one.
+fn read_symbols(path: Option<&str>) -> Result<Symbols, FrontendError> {
+    match path {
+        Some(path) => Symbols::parse(&read_text(path)?).map_err(FrontendError::parse(path)),
+        None => Ok(Symbols::default()),
+    }
+}
+
+/// Prints an annotated disassembly of a ROM, with labels from `symbols`.
+fn disasm(path: &str, symbols: Option<&str>) -> Result<(), FrontendError> {
+    let rom = read_rom(path)?;
+    let symbols = read_symbols(symbols)?;
+    for mut line in disasm::disassemble(&rom) {
+        if let Some(name) = symbols.name(line.address) {
+            println!("{}:", name);
+        }
+        line.symbolize(&symbols);
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Logs the problems found in a ROM as it loads, errors as errors and the rest as warnings.
+fn log_diagnostics(path: &str, rom: &Rom) {
+    for diagnostic in rom.check() {
+        let location = match diagnostic.address {
+            Some(address) => format!("{}: {:#05X}", path, address),
+            None => path.to_string(),
+        };
+        match diagnostic.severity {
+            Severity::Error => tracing::error!("{}: {}", location, diagnostic.message),
+            Severity::Warning => tracing::warn!("{}: {}", location, diagnostic.message),
+        }
+    }
+}
+
+/// Creates a `CHIP8` with a ROM loaded, reporting any problems found in the ROM.
+fn load(path: &str) -> Result<CHIP8, FrontendError> {
+    let rom = read_rom(path)?;
+    log_diagnostics(path, &rom);
+
+    machine(Some(&rom), Some(path)).map_err(FrontendError::rom(path))
+}
+
+/// Returns the machine every command runs, so that they all configure it alike, with `rom`
+/// loaded if there is one. With the `path` the ROM was read from, the persistent flags of `FX75`
+/// are kept with its save slots; otherwise, as for netplay, where both machines must start alike,
+/// and for ROMs sent to the API, they only last the run.
+pub fn machine(rom: Option<&Rom>, path: Option<&str>) -> Result<CHIP8, RomError> {
+    let mut builder = CHIP8::builder();
+    if let (Some(rom), Some(path)) = (rom, path) {
+        builder = builder.flags(Slots::new(path, rom).flags());
+    }
+    let mut chip = builder.build().expect("the default configuration is valid");
+    if let Some(rom) = rom {
+        chip.load_rom(rom)?;
+    }
+    Ok(chip)
+}
+
+/// Debugs a ROM, in the full-screen debugger if standard input is a terminal, or with the
+/// line-based console otherwise.
+async fn debug(path: &str, symbols: Option<&str>, log: &Log) -> Result<(), FrontendError> {
+    let symbols = read_symbols(symbols)?;
+    let mut chip = load(path)?;
+    let slots = Slots::new(path, &read_rom(path)?);
+    if !io::stdin().is_terminal() {
+        console::run(&mut chip, symbols, slots).await;
+        return Ok(());
+    }
+    tui::run(&mut chip, path, symbols, slots, None, log).await?;
+    Ok(())
+}
+
+/// Parses a number of frames, which must be at least 1.
+fn parse_frames(frames: &str) -> Result<u64, FrontendError> {
+    match frames.parse() {
+        Ok(frames) if frames > 0 => Ok(frames),
+        _ => Err(FrontendError::Usage(format!(
+            "invalid frame count `{}`",
+            frames
+        ))),
+    }
+}
+
+/// Records a movie of a ROM being played in the full-screen debugger.
+async fn record(
+    movie: &str,
+    path: &str,
+    checksum_frames: &str,
+    log: &Log,
+) -> Result<(), FrontendError> {
+    let checksum_frames = parse_frames(checksum_frames)?;
+    let rom = read_rom(path)?;
+    let mut chip = load(path)?;
+    let mut recorder = Recorder::new(&chip, state::rom_hash(rom.data()), checksum_frames);
+    if !io::stdin().is_terminal() {
+        return Err(FrontendError::Usage(
+            "recording a movie needs a terminal".to_string(),
+        ));
+    }
+    let slots = Slots::new(path, &rom);
+    tui::run(
+        &mut chip,
+        path,
+        Symbols::default(),
+        slots,
+        Some(&mut recorder),
+        log,
+    )
+    .await?;
+    let movie_data = recorder.movie();
+    write_file(movie, movie_data.to_string())?;
+    tracing::info!("recorded {} frames to {}", movie_data.length, movie);
+    Ok(())
+}
+
+/// Plays a movie of a ROM without a display, checking that it stays in step with the recording,
+/// and prints the final screen.
+fn play(movie: &str, path: &str) -> Result<(), FrontendError> {
+    let parsed: Movie = read_text(movie)?
+        .parse()
+        .map_err(FrontendError::parse(movie))?;
+    let rom = read_rom(path)?;
+    let mut chip = load(path)?;
+    let matched = movie::play(&parsed, &mut chip, state::rom_hash(rom.data()))
+        .map_err(|err| FrontendError::Failed(format!("{}: {}", movie, err)))?;
+    print!("{}", chip.display().text());
+    println!(
+        "played {} frames, {} checksums matched",
+        parsed.length, matched
+    );
+    Ok(())
+}
+
+/// Reads a save state.
+fn read_state(file: &str) -> Result<Vec<u8>, FrontendError> {
+    std::fs::read(file).map_err(FrontendError::file(file))
+}
+
+/// Prints the contents of a save state.
+fn inspect_state(file: &str) -> Result<(), FrontendError> {
+    let report = state::inspect(&read_state(file)?).map_err(FrontendError::parse(file))?;
+    print!("{}", report);
+    Ok(())
+}
+
+/// Prints what differs between two save states.
+fn diff_states(files: [&str; 2]) -> Result<(), FrontendError> {
+    let states = [read_state(files[0])?, read_state(files[1])?];
+    let report = state::diff(&states[0], &states[1])
+        .map_err(|(index, err)| FrontendError::parse(files[index])(err))?;
+    print!("{}", report);
+    Ok(())
+}
+
+/// Converts a save state to JSON, written to `output` or printed.
+fn export_state(file: &str, output: Option<&str>) -> Result<(), FrontendError> {
+    let json = state::to_json(&read_state(file)?).map_err(FrontendError::parse(file))?;
+    match output {
+        Some(output) => write_file(output, json)?,
+        None => print!("{}", json),
+    }
+    Ok(())
+}
+
+/// Converts JSON to a save state. `format` is `json` for JSON written by `chip8 state export`, or
+/// `octo` for the state of Octo's emulator, which needs the ROM it is of.
+fn import_state(
+    format: &str,
+    json: &str,
+    file: &str,
+    rom: Option<&str>,
+) -> Result<(), FrontendError> {
+    let text = read_text(json)?;
+    let bytes = match (format, rom) {
+        ("json", None) => state::from_json(&text),
+        ("octo", Some(rom)) => octo::import(&text, state::rom_hash(read_rom(rom)?.data())),
+        ("octo", None) => {
+            return Err(FrontendError::Usage(
+                "importing from Octo needs the ROM, which its states do not hold".to_string(),
+            ))
+        }
+        ("json", Some(_)) => {
+            return Err(FrontendError::Usage(
+                "importing JSON does not need a ROM".to_string(),
+            ))
+        }
+        _ => {
+            return Err(FrontendError::Usage(format!(
+                "unknown format `{}`, expected `json` or `octo`",
+                format
+            )))
+        }
+    }
+    .map_err(FrontendError::parse(json))?;
+    write_file(file, bytes)
+}
+
+/// Serves a ROM to GDB.
+async fn gdb(path: &str, port: &str) -> Result<(), FrontendError> {
+    let port = command::parse_number(port).map_err(FrontendError::Usage)?;
+    let mut chip = load(path)?;
+    gdb::serve(&mut chip, port).await?;
+    Ok(())
+}
+
+/// Loads a ROM for netplay, with the defaults rather than the settings saved for it so that both
+/// machines start alike, and returns its hash.
+fn load_for_netplay(path: &str) -> Result<(CHIP8, u64), FrontendError> {
+    let rom = read_rom(path)?;
+    log_diagnostics(path, &rom);
+    let chip = machine(Some(&rom), None).map_err(FrontendError::rom(path))?;
+    Ok((chip, state::rom_hash(rom.data())))
+}
+
+/// Hosts a two-player game of a ROM on a port of every address of this machine, or of the one
+/// given with `--bind`, playing keys `--delay` frames after they are read.
+async fn host(port: &str, args: &[&str], log: &Log) -> Result<(), FrontendError> {
+    let port = command::parse_number(port).map_err(FrontendError::Usage)?;
+    let mut bind = IpAddr::from(Ipv4Addr::UNSPECIFIED);
+    let mut delay = DEFAULT_DELAY;
+    let path = match args {
+        [options @ .., path] if !path.starts_with("--") => {
+            let mut options = options.iter();
+            while let Some(&option) = options.next() {
+                let value = *options
+                    .next()
+                    .ok_or_else(|| FrontendError::Usage(format!("{} needs a value", option)))?;
+                match option {
+                    "--bind" => {
+                        bind = value.parse().map_err(|_| {
+                            FrontendError::Usage(format!("invalid address `{}`", value))
+                        })?
+                    }
+                    "--delay" => {
+                        delay = value
+                            .parse()
+                            .ok()
+                            .filter(|&delay| delay <= MAX_DELAY)
+                            .ok_or_else(|| {
+                                FrontendError::Usage(format!(
+                                    "the delay must be a frame count up to {}",
+                                    MAX_DELAY
+                                ))
+                            })?
+                    }
+                    _ => return Err(FrontendError::Usage(format!("unknown option `{}`", option))),
                 }
             }
-            // ANNN - Set index register I to nnn.
-            0xA => self.index = nnn,
-            // DXYN - Display/Draw
-            // TODO: Call a method in Display to handle this.
-            0xD => println!("D"),
-            _ => panic!("error: unknown instruction {:x}", itype),
+            *path
+        }
+        _ => return Err(FrontendError::Usage("no ROM given".to_string())),
+    };
+    let (chip, rom_hash) = load_for_netplay(path)?;
+    let session = Session::host(&chip, rom_hash, delay, CHECKSUM_FRAMES);
+    netplay::host(chip, session, SocketAddr::new(bind, port), path, log).await
+}
+
+/// Joins a two-player game of a ROM hosted at an address.
+async fn join(path: &str, address: &str, log: &Log) -> Result<(), FrontendError> {
+    let (chip, rom_hash) = load_for_netplay(path)?;
+    netplay::join(chip, Session::join(rom_hash), address, path, log).await
+}
+
+/// Serves a ROM to browsers, which show it and play it over a WebSocket, on the loopback address
+/// unless `--bind` gives another. With `--api`, also serves the control API, through which the
+/// ROM may be loaded later, to those with the `--token` if one is given. With `--crowd`, the keys
+/// are voted for.
+async fn serve(args: &[&str]) -> Result<(), FrontendError> {
+    let mut port = 8080;
+    let mut bind = IpAddr::from(Ipv4Addr::LOCALHOST);
+    let mut api = false;
+    let mut token = None;
+    let mut crowd = false;
+    let mut window = None;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .copied()
+                .ok_or_else(|| FrontendError::Usage(format!("{} needs a value", arg)))
+        };
+        match arg {
+            "--port" => port = command::parse_number(value()?).map_err(FrontendError::Usage)?,
+            "--bind" => {
+                let address = value()?;
+                bind = address
+                    .parse()
+                    .map_err(|_| FrontendError::Usage(format!("invalid address `{}`", address)))?;
+            }
+            "--api" => api = true,
+            "--token" => token = Some(value()?.to_string()),
+            "--crowd" => crowd = true,
+            "--window" => window = Some(parse_frames(value()?)?),
+            _ if arg.starts_with("--") => {
+                return Err(FrontendError::Usage(format!("unknown option `{}`", arg)))
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                return Err(FrontendError::Usage(format!(
+                    "unexpected argument `{}`",
+                    arg
+                )))
+            }
+        }
+    }
+    if window.is_some() && !crowd {
+        return Err(FrontendError::Usage("--window needs --crowd".to_string()));
+    }
+    if token.is_some() && !api {
+        return Err(FrontendError::Usage("--token needs --api".to_string()));
+    }
+    let mut machine = match path {
+        Some(path) => Machine::new(load(path)?),
+        None if api => Machine::empty(),
+        None => return Err(FrontendError::Usage("no ROM given".to_string())),
+    };
+    if crowd {
+        machine.set_crowd(window.unwrap_or(DEFAULT_WINDOW));
+    }
+    remote::serve(machine, SocketAddr::new(bind, port), api, token).await
+}
+
+/// Runs a script against a ROM without a display, failing if an assertion does.
+fn script(script: &str, path: &str, symbols: Option<&str>) -> Result<(), FrontendError> {
+    let symbols = read_symbols(symbols)?;
+    let parsed =
+        Script::parse(&read_text(script)?, &symbols).map_err(FrontendError::parse(script))?;
+    let mut chip = load(path)?;
+    parsed.run(&mut chip, io::stdout()).map_err(|message| {
+        FrontendError::Failed(format!(
+            "{}:{}",
+            script,
+            message.trim_start_matches("line ")
+        ))
+    })
+}
+
+/// Assembles Octo-style source into a ROM at `output`, which defaults to the source file with a
+/// `.ch8` extension, and writes the labels to a symbol file with a `.sym` extension beside it.
+fn assemble(source: &str, output: Option<&str>) -> Result<(), FrontendError> {
+    let program = asm::assemble(&read_text(source)?).map_err(FrontendError::parse(source))?;
+    let output = match output {
+        Some(output) => Path::new(output).to_path_buf(),
+        None => Path::new(source).with_extension("ch8"),
+    };
+    let symbols = output.with_extension("sym");
+    write_file(&output, &program.bytes)?;
+    write_file(&symbols, program.symbols.to_string())?;
+    println!(
+        "{} bytes written to {}, labels to {}",
+        program.bytes.len(),
+        output.display(),
+        symbols.display()
+    );
+    Ok(())
+}
+
+/// Frames a ROM is run for by `chip8 sprites` to find sprites whose address is computed.
+const SPRITE_FRAMES: u64 = 600;
+
+/// Finds the sprites in a ROM, from its code and from running it without input for `frames`
+/// frames, and writes them as a PNG sprite sheet and as assembler source beside the ROM.
+fn sprites(path: &str, frames: &str) -> Result<(), FrontendError> {
+    let frames: u64 = frames
+        .parse()
+        .map_err(|_| FrontendError::Usage(format!("invalid frame count `{}`", frames)))?;
+    let rom = read_rom(path)?;
+    let mut chip = load(path)?;
+    let mut finder = Finder::default();
+    finder.scan(&rom);
+    for step in 0..frames * STEPS_PER_FRAME {
+        if let Err(err) = finder.step(&mut chip, CHIP8::step) {
+            tracing::warn!(
+                "stopped running after {} frames: {}",
+                step / STEPS_PER_FRAME,
+                err
+            );
+            break;
+        }
+        if (step + 1).is_multiple_of(STEPS_PER_FRAME) {
+            chip.tick();
+        }
+    }
+
+    let sprites = finder.sprites(chip.memory());
+    if sprites.is_empty() {
+        println!("no sprites found");
+        return Ok(());
+    }
+    let sheet = Path::new(path).with_extension("sprites.png");
+    let source = Path::new(path).with_extension("sprites.8o");
+    write_file(&sheet, sprites::sheet(&sprites))?;
+    write_file(&source, sprites::source(&sprites))?;
+    println!(
+        "{} sprites written to {} and {}",
+        sprites.len(),
+        sheet.display(),
+        source.display()
+    );
+    Ok(())
+}
+
+/// Runs a ROM against a reference trace, printing where the two first disagree.
+///
+/// Fails if they do.
+fn compare(trace: &str, path: &str) -> Result<(), FrontendError> {
+    let reference = File::open(trace).map_err(FrontendError::file(trace))?;
+    let mut chip = load(path)?;
+    let outcome = compare::compare(&mut chip, io::BufReader::new(reference))
+        .map_err(FrontendError::parse(trace))?;
+    let (line, expected) = match outcome {
+        Outcome::Matched(count) => {
+            println!("{} instructions matched", count);
+            return Ok(());
+        }
+        Outcome::Diverged {
+            line,
+            expected,
+            actual,
+        } => {
+            println!("{}:{}: diverged from the reference", trace, line);
+            println!("expected: {}", expected);
+            println!("actual:   {}", actual);
+            (line, expected)
+        }
+        Outcome::Failed {
+            line,
+            expected,
+            error,
+        } => {
+            println!("{}:{}: {}", trace, line, error);
+            println!("expected: {}", expected);
+            (line, expected)
+        }
+    };
+    let variables: Vec<String> = (0..16)
+        .map(|x| format!("V{:X}={:02X}", x, chip.variable()[x]))
+        .collect();
+    println!(
+        "state after line {}: PC={:#05X} I={:#05X} DT={} ST={} {}",
+        line,
+        chip.program_counter(),
+        chip.index(),
+        chip.timer().delay(),
+        chip.timer().sound(),
+        variables.join(" ")
+    );
+    for location in callstack::backtrace(&chip, expected.address) {
+        println!("{}", location);
+    }
+    Err(FrontendError::Failed(format!(
+        "{}: diverged at line {}",
+        trace, line
+    )))
+}
+
+/// Options of the `run` command.
+struct RunOptions {
+    path: String,
+    /// Whether to show the game in an SDL2 window.
+    sdl: bool,
+    /// Labels for addresses in traces.
+    symbols: Symbols,
+    cheats: Vec<Cheat>,
+    /// Whether debug print opcodes are enabled.
+    debug_print: bool,
+    tracer: Option<Tracer>,
+    /// Whether to print a profile on exit.
+    profile: bool,
+    /// Where to write folded call stacks on exit.
+    folded: Option<File>,
+    /// Where to write coverage as text and as HTML on exit.
+    coverage: Option<File>,
+    coverage_html: Option<File>,
+    /// Where to write a memory access heatmap on exit.
+    heatmap: Option<File>,
+    /// Where to write a crash report if the program fails.
+    crash_report: String,
+}
+
+/// Parses the options of the `run` command.
+fn parse_run_options(args: &[&str]) -> Result<RunOptions, String> {
+    let mut path = None;
+    let mut sdl = false;
+    let mut symbols = None;
+    let mut cheats = Vec::new();
+    let mut debug_print = false;
+    let mut tracer = None;
+    let mut ranges = Vec::new();
+    let mut mnemonics = Vec::new();
+    let mut profile = false;
+    let mut folded = None;
+    let mut coverage = None;
+    let mut coverage_html = None;
+    let mut heatmap = None;
+    let mut crash_report = None;
+    let create = |file: &str| File::create(file).map_err(|err| format!("{}: {}", file, err));
+
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .copied()
+                .ok_or_else(|| format!("{} needs a value", arg))
         };
+        match arg {
+            "--sdl" if cfg!(feature = "sdl") => sdl = true,
+            "--sdl" => return Err("--sdl needs chip8 built with `--features sdl`".to_string()),
+            "--symbols" => symbols = Some(value()?),
+            "--cheat" => cheats.push(value()?.parse()?),
+            "--cheats" => {
+                let file = value()?;
+                let text =
+                    std::fs::read_to_string(file).map_err(|err| format!("{}: {}", file, err))?;
+                cheats.extend(
+                    cheat::parse_cheats(&text).map_err(|err| format!("{}: {}", file, err))?,
+                );
+            }
+            "--debug-print" => debug_print = true,
+            "--trace" => tracer = Some(Tracer::to_file(create(value()?)?)),
+            "--trace-last" => {
+                tracer = Some(Tracer::ring(command::parse_number(value()?)? as usize));
+            }
+            "--trace-range" => {
+                let range = value()?;
+                ranges.push(
+                    range
+                        .split_once('-')
+                        .ok_or_else(|| format!("invalid range `{}`", range))?,
+                );
+            }
+            "--trace-op" => mnemonics.push(value()?),
+            "--profile" => profile = true,
+            "--profile-folded" => folded = Some(create(value()?)?),
+            "--coverage" => coverage = Some(create(value()?)?),
+            "--coverage-html" => coverage_html = Some(create(value()?)?),
+            "--heatmap" => heatmap = Some(create(value()?)?),
+            "--crash-report" => crash_report = Some(value()?.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            _ if path.is_none() => path = Some(arg.to_string()),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
     }
 
-    /// Runs the emulator.
-    ///
-    /// The emulator runs at a speed of 700 instructions per second (700 Hz).
-    pub async fn run(&mut self) {
-        // 700 instructions per second
-        let interval = time::interval(Duration::from_micros(1429));
-        tokio::pin!(interval);
+    let path: String = path.ok_or("no ROM given")?;
+    let crash_report = crash_report.unwrap_or_else(|| {
+        let name = Path::new(&path).file_stem().unwrap_or_default();
+        format!("{}.crash.txt", name.to_string_lossy())
+    });
+    let symbols = match symbols {
+        Some(symbols) => Symbols::from_file(symbols)?,
+        None => Symbols::default(),
+    };
+    if tracer.is_none() && !(ranges.is_empty() && mnemonics.is_empty()) {
+        return Err("trace filters need --trace or --trace-last".to_string());
+    }
+    if let Some(tracer) = &mut tracer {
+        tracer.set_symbols(symbols.clone());
+        for (start, end) in ranges {
+            tracer.add_range(symbols.parse_address(start)?..=symbols.parse_address(end)?);
+        }
+        for mnemonic in mnemonics {
+            tracer.add_mnemonic(mnemonic);
+        }
+    }
+    Ok(RunOptions {
+        path,
+        sdl,
+        symbols,
+        cheats,
+        debug_print,
+        tracer,
+        profile,
+        folded,
+        coverage,
+        coverage_html,
+        heatmap,
+        crash_report,
+    })
+}
 
-        loop {
-            interval.as_mut().tick().await;
-            self.timer.cycle();
+/// Resumes `chip` from the auto-save of its ROM, if there is one and the settings of the ROM say
+/// to, asking first if they say so and there is someone to ask. Returns the settings.
+fn resume(chip: &mut CHIP8, slots: &Slots) -> Settings {
+    let mut settings = slots.settings().unwrap_or_else(|err| {
+        tracing::warn!("{}", err);
+        Default::default()
+    });
+    if !settings.autosave || !slots.has_auto() {
+        return settings;
+    }
+    let resume = match settings.resume {
+        Resume::Always => true,
+        Resume::Never => false,
+        Resume::Ask if !io::stdin().is_terminal() => false,
+        Resume::Ask => {
+            eprint!("resume from the auto-save? [y]es, [n]o, [a]lways, ne[v]er: ");
+            let mut answer = String::new();
+            let _ = io::stdin().read_line(&mut answer);
+            let remembered = match answer.trim() {
+                "a" | "always" => Some(Resume::Always),
+                "v" | "never" => Some(Resume::Never),
+                _ => None,
+            };
+            if let Some(resume) = remembered {
+                settings.resume = resume;
+                if let Err(err) = slots.save_settings(&settings) {
+                    tracing::warn!("{}", err);
+                }
+            }
+            matches!(answer.trim(), "y" | "yes" | "a" | "always")
+        }
+    };
+    if resume {
+        if let Err(err) = slots.load_auto(chip) {
+            tracing::warn!("{}", err);
         }
     }
+    settings
 }
 
-/// Emulator entry-point.
+/// Loads and runs a ROM.
+async fn run(args: &[&str], log: &Log) -> Result<(), FrontendError> {
+    let mut options = parse_run_options(args).map_err(FrontendError::Usage)?;
+    let mut chip = load(&options.path)?;
+    let slots = Slots::new(&options.path, &read_rom(&options.path)?);
+    let settings = resume(&mut chip, &slots);
+    let autosave = settings.autosave;
+    if let Some(battery) = settings.battery {
+        if let Err(err) = slots.load_battery(&mut chip, battery) {
+            tracing::warn!("{}", err);
+        }
+    }
+    for cheat in &options.cheats {
+        cheat.apply(&mut chip);
+    }
+    // Keep the last few instructions for a crash report, unless they are already being traced.
+    let print_last = options.tracer.is_some();
+    let tracer = options
+        .tracer
+        .get_or_insert_with(|| Tracer::ring(crash::HISTORY));
+    let mut profiler =
+        (options.profile || options.folded.is_some()).then(|| Profiler::new(chip.memory().len()));
+
+    let mut coverage = (options.coverage.is_some() || options.coverage_html.is_some())
+        .then(|| Coverage::new(chip.memory().len()));
+
+    // Each observer wraps the step of the one before it.
+    let frozen: Vec<&Cheat> = options.cheats.iter().filter(|cheat| cheat.frozen).collect();
+    let mut step = |chip: &mut CHIP8| {
+        for cheat in &frozen {
+            cheat.apply(chip);
+        }
+        tracer.step(chip)
+    };
+    let debug_print = options.debug_print;
+    let mut step = |chip: &mut CHIP8| {
+        if !debug_print {
+            return step(chip);
+        }
+        // The prints go to the log, which holds them while the terminal shows the game instead of
+        // letting them write over it.
+        let mut out = Vec::new();
+        let result = print::step(chip, &mut out, &mut step);
+        for line in String::from_utf8_lossy(&out).lines() {
+            tracing::info!("{}", line);
+        }
+        result
+    };
+    let mut heatmap = options
+        .heatmap
+        .is_some()
+        .then(|| Heatmap::new(chip.memory().len()));
+    let mut step = |chip: &mut CHIP8| {
+        step(chip)?;
+        if let Some(heatmap) = &mut heatmap {
+            heatmap.record(chip);
+        }
+        Ok(())
+    };
+    let mut step = |chip: &mut CHIP8| match &mut coverage {
+        Some(coverage) => coverage.step(chip, &mut step),
+        None => step(chip),
+    };
+    let mut step = |chip: &mut CHIP8| match &mut profiler {
+        Some(profiler) => profiler.step(chip, &mut step),
+        None => step(chip),
+    };
+    let autosave_steps = (AUTOSAVE_PERIOD.as_micros() / INSTRUCTION_PERIOD.as_micros()) as u64;
+    let mut steps = 0u64;
+    let step = |chip: &mut CHIP8| {
+        step(chip)?;
+        steps += 1;
+        if autosave && steps.is_multiple_of(autosave_steps) {
+            if let Err(err) = slots.save_auto(chip) {
+                tracing::warn!("{}", err);
+            }
+        }
+        if let Some((start, end)) = settings.battery {
+            let changed = chip.accesses().iter().any(|access| {
+                access.kind == AccessKind::Write
+                    && (start..=end).contains(&access.address)
+                    && access.old != access.new
+            });
+            if changed {
+                if let Err(err) = slots.save_battery(chip, (start, end)) {
+                    tracing::warn!("{}", err);
+                }
+            }
+        }
+        Ok(())
+    };
+    // Show the game in a window if asked to, or else when there is a terminal to show it in, and
+    // run without a display otherwise.
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let screen = if interactive && !options.sdl {
+        frontend::Screen::new("chip8 — Ctrl-C quits", log)
+            .map_err(|err| tracing::warn!("running without a display: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    let (chip, result) = match screen {
+        #[cfg(feature = "sdl")]
+        None if options.sdl => {
+            let (window, beeper, keys) = sdl::open("chip8 — Escape quits")
+                .map_err(|err| io::Error::other(format!("SDL2: {}", err)))?;
+            run_until_stopped(Runner::new(chip, window, beeper, keys), step).await
+        }
+        Some(screen) => {
+            let runner = Runner::new(chip, screen, frontend::Bell, frontend::Keys::new());
+            run_until_stopped(runner, step).await
+        }
+        None => run_until_stopped(Runner::new(chip, Headless, Headless, Headless), step).await,
+    };
+    // Only a clean exit is auto-saved, as resuming into a crash would not help.
+    if autosave && result.is_ok() {
+        match slots.save_auto(&chip) {
+            Ok(()) => tracing::info!("auto-saved, resume with `chip8 run {}`", options.path),
+            Err(err) => tracing::warn!("{}", err),
+        }
+    }
+
+    if let Some(tracer) = &mut options.tracer {
+        if let Err(err) = tracer.finish() {
+            tracing::error!("writing trace: {}", err);
+        }
+        if print_last && result.is_err() && tracer.records().next().is_some() {
+            eprintln!("last instructions:");
+            for record in tracer.records() {
+                if let Some(name) = options.symbols.name(record.address) {
+                    eprintln!("{}:", name);
+                }
+                eprintln!("{}", record);
+            }
+        }
+    }
+    if let Some(profiler) = &profiler {
+        if options.profile {
+            eprint!("{}", profiler.report(&chip));
+        }
+        if let Some(file) = options.folded.take() {
+            if let Err(err) = profiler.write_folded(io::BufWriter::new(file)) {
+                tracing::error!("writing profile: {}", err);
+            }
+        }
+    }
+    if let Some(coverage) = &coverage {
+        let rom = read_rom(&options.path)?;
+        let reports = [
+            (options.coverage.take(), coverage.annotate(&rom)),
+            (options.coverage_html.take(), coverage.html(&rom)),
+        ];
+        for (file, report) in reports.iter() {
+            if let Some(mut file) = file.as_ref() {
+                if let Err(err) = file.write_all(report.as_bytes()) {
+                    tracing::error!("writing coverage: {}", err);
+                }
+            }
+        }
+    }
+    if let (Some(heatmap), Some(mut file)) = (&heatmap, options.heatmap.take()) {
+        if let Err(err) = file.write_all(&heatmap.png()) {
+            tracing::error!("writing heatmap: {}", err);
+        }
+    }
+    if let Err(err) = result {
+        for location in callstack::backtrace(&chip, err.address()) {
+            eprintln!("{}", location);
+        }
+        let records = options.tracer.iter().flat_map(Tracer::records);
+        let report = crash::report(&options.path, &chip, &err, records, &options.symbols);
+        match std::fs::write(&options.crash_report, report) {
+            Ok(()) => tracing::info!("crash report written to {}", options.crash_report),
+            Err(err) => tracing::error!("writing crash report: {}", err),
+        }
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Runs the command given on the command line.
+async fn command(args: &[&str], log: &Log) -> Result<(), FrontendError> {
+    match args {
+        ["check", path] => check(path),
+        ["identify", path] => identify(path),
+        ["debug", path] => debug(path, None, log).await,
+        ["debug", "--symbols", symbols, path] => debug(path, Some(symbols), log).await,
+        ["disasm", path] => disasm(path, None),
+        ["disasm", "--symbols", symbols, path] => disasm(path, Some(symbols)),
+        ["gdb", path] => gdb(path, "1234").await,
+        ["gdb", "--port", port, path] => gdb(path, port).await,
+        ["netplay", "--host", port, args @ ..] => host(port, args, log).await,
+        ["netplay", "--connect", address, path] => join(path, address, log).await,
+        ["serve", args @ ..] => serve(args).await,
+        ["script", script_path, path] => script(script_path, path, None),
+        ["script", "--symbols", symbols, script_path, path] => {
+            script(script_path, path, Some(symbols))
+        }
+        ["compare", trace, path] => compare(trace, path),
+        ["selftest", dir] => selftest(dir),
+        ["test", script] => playtest(script),
+        ["asm", source] => assemble(source, None),
+        ["asm", source, output] => assemble(source, Some(output)),
+        ["sprites", path] => sprites(path, &SPRITE_FRAMES.to_string()),
+        ["sprites", "--frames", frames, path] => sprites(path, frames),
+        ["record", movie, path] => {
+            record(movie, path, &movie::CHECKSUM_FRAMES.to_string(), log).await
+        }
+        ["record", "--checksum-frames", frames, movie, path] => {
+            record(movie, path, frames, log).await
+        }
+        ["play", movie, path] => play(movie, path),
+        ["state", "inspect", file] => inspect_state(file),
+        ["state", "diff", a, b] => diff_states([a, b]),
+        ["state", "export", file] => export_state(file, None),
+        ["state", "export", file, json] => export_state(file, Some(json)),
+        ["state", "import", json, file] => import_state("json", json, file, None),
+        ["state", "import", "--format", format, json, file] => {
+            import_state(format, json, file, None)
+        }
+        ["state", "import", "--format", format, json, file, rom] => {
+            import_state(format, json, file, Some(rom))
+        }
+        [command @ "check", ..]
+        | [command @ "identify", ..]
+        | [command @ "debug", ..]
+        | [command @ "disasm", ..]
+        | [command @ "gdb", ..]
+        | [command @ "script", ..]
+        | [command @ "compare", ..]
+        | [command @ "selftest", ..]
+        | [command @ "test", ..]
+        | [command @ "asm", ..]
+        | [command @ "sprites", ..]
+        | [command @ "record", ..]
+        | [command @ "play", ..]
+        | [command @ "state", ..] => Err(FrontendError::Usage(format!(
+            "wrong arguments for `{}`",
+            command
+        ))),
+        [] => Err(FrontendError::Usage("no ROM given".to_string())),
+        ["run", args @ ..] | args => run(args, log).await,
+    }
+}
+
+/// Emulator entry-point, which starts logging, reports a failed command and exits with its
+/// status.
 #[tokio::main]
 async fn main() {
-    let mut chip = CHIP8::new();
-    chip.run().await;
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let result = match log::parse_args(&mut args) {
+        Ok((filter, format)) => {
+            let log = log::init(filter, format);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            command(&args, &log).await
+        }
+        Err(message) => Err(FrontendError::Usage(message)),
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        if let FrontendError::Usage(_) = err {
+            usage();
+        }
+        process::exit(err.exit_code());
+    }
 }
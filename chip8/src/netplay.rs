@@ -0,0 +1,111 @@
+//! `chip8 netplay`: plays a two-player game with another machine over TCP, in lockstep.
+//!
+//! The game runs in the terminal as with `chip8 run`, and the messages of
+//! [`chip8_core::netplay`] go over the connection one per line.
+
+use crate::error::FrontendError;
+use crate::frontend::{Bell, Keys, Screen};
+use chip8_core::frontend::{Audio, Input, Video};
+use chip8_core::keyboard::Keyboard;
+use chip8_core::netplay::{Message, Session};
+use chip8_core::{CHIP8, TIMER_PERIOD};
+use emu_debug::log::Log;
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time;
+
+/// Waits at `address` for the other player to connect, then hosts the game.
+pub async fn host(
+    chip: CHIP8,
+    mut session: Session,
+    address: SocketAddr,
+    title: &str,
+    log: &Log,
+) -> Result<(), FrontendError> {
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!("waiting for the other player on {}", address);
+    let (stream, address) = listener.accept().await?;
+    tracing::info!("{} joined", address);
+    play(chip, &mut session, stream, title, log).await
+}
+
+/// Joins the game hosted at `address`.
+pub async fn join(
+    chip: CHIP8,
+    mut session: Session,
+    address: &str,
+    title: &str,
+    log: &Log,
+) -> Result<(), FrontendError> {
+    let stream = TcpStream::connect(address).await?;
+    play(chip, &mut session, stream, title, log).await
+}
+
+/// Plays until the player quits, the other player leaves or the machines go out of step.
+async fn play(
+    mut chip: CHIP8,
+    session: &mut Session,
+    stream: TcpStream,
+    title: &str,
+    log: &Log,
+) -> Result<(), FrontendError> {
+    stream.set_nodelay(true)?;
+    let (reader, mut writer) = stream.into_split();
+    // Lines are read on their own task, so that a frame never waits for the network.
+    let (sender, mut lines) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = reader.next_line().await {
+            if sender.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut screen = Screen::new(title, log)?;
+    let mut bell = Bell;
+    let mut keys = Keys::new();
+    let mut keyboard = Keyboard::new();
+    let mut messages = Vec::new();
+    let mut frames = time::interval(TIMER_PERIOD);
+    loop {
+        frames.tick().await;
+        loop {
+            match lines.try_recv() {
+                Ok(line) => {
+                    let message: Message = line.parse().map_err(FrontendError::Failed)?;
+                    session.receive(message)?;
+                }
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    return Err(FrontendError::Failed("the other player left".to_string()))
+                }
+            }
+        }
+        if !keys.poll(&mut keyboard) {
+            return Ok(());
+        }
+        let held = (0..16).fold(0, |held, key| {
+            held | u16::from(keyboard.is_pressed(key)) << key
+        });
+        let ran = session.advance(&mut chip, held, |chip| {
+            for _ in 0..chip.config().steps_per_frame() {
+                chip.step()?;
+            }
+            bell.play(chip.timer().is_sounding());
+            chip.tick();
+            Ok(())
+        })?;
+        session.take_messages(&mut messages);
+        for message in messages.drain(..) {
+            writer
+                .write_all(format!("{}\n", message).as_bytes())
+                .await?;
+        }
+        if ran {
+            screen.draw(chip.display());
+        }
+    }
+}
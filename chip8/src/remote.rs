@@ -0,0 +1,520 @@
+//! `chip8 serve`: runs a ROM without a display and plays it from a browser, on this machine or
+//! another.
+//!
+//! One port serves both the viewer, a page built into the emulator, and the WebSocket it talks
+//! to. Each viewer is sent the frames it has not seen, as the messages of
+//! [`chip8_core::remote`], and its key presses go to the machine. Any number of viewers may
+//! watch and play; a viewer that falls behind skips frames rather than slowing the machine.
+//!
+//! With `--api`, the port also serves the HTTP control API of [`crate::api`], to those who send
+//! its token if it has one. Neither the API nor the WebSocket is open to pages of other sites,
+//! which browsers would otherwise let send requests and open WebSockets to the port. In crowd play, the key presses of the viewers are votes, and each
+//! viewer is also sent the tally, which the page shows over the game.
+//!
+//! The port is only open to this machine unless another address is given to bind to.
+
+use crate::api::{Machine, Response};
+use crate::error::FrontendError;
+use crate::websocket::{self, Message, Reader, Request};
+use chip8_core::crowd::Tally;
+use chip8_core::display::Display;
+use chip8_core::error::ExecError;
+use chip8_core::remote::{Encoder, Event};
+use chip8_core::TIMER_PERIOD;
+use std::hint;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time;
+
+/// The viewer, served at `/`.
+const VIEWER: &str = include_str!("viewer.html");
+
+/// The longest body of a request to the API, more than any ROM.
+const MAX_BODY: usize = 64 * 1024;
+
+/// What the viewers are shown at the end of a frame.
+#[derive(Clone)]
+struct Frame {
+    display: Display,
+    sounding: bool,
+    /// The votes, in crowd play.
+    tally: Option<Tally>,
+}
+
+/// What a connection asks of the machine.
+enum Control {
+    /// The viewer voting as `voter` pressed or released a key.
+    Event { voter: String, event: Event },
+    /// A request to the API, to be answered through `reply`.
+    Api {
+        method: String,
+        target: String,
+        body: Vec<u8>,
+        reply: oneshot::Sender<Response>,
+    },
+}
+
+/// What the connections share.
+#[derive(Clone)]
+struct Server {
+    frames: watch::Receiver<Frame>,
+    controls: mpsc::UnboundedSender<Control>,
+    api: bool,
+    /// What requests to the API must send as `Authorization: Bearer <token>`, if anything.
+    token: Option<String>,
+    /// The address served at.
+    address: SocketAddr,
+}
+
+/// Serves `machine` at `address` until Ctrl-C or the program fails, with the control API if
+/// `api`, which only answers requests bearing `token` if one is given.
+pub async fn serve(
+    machine: Machine,
+    address: SocketAddr,
+    api: bool,
+    token: Option<String>,
+) -> Result<(), FrontendError> {
+    let listener = TcpListener::bind(address).await?;
+    let address = listener.local_addr()?;
+    // A browser on this machine reaches an unspecified address through the loopback one.
+    let shown = if address.ip().is_unspecified() {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, address.port()))
+    } else {
+        address
+    };
+    tracing::info!(
+        "serving on {}; open http://{}/ in a browser",
+        address,
+        shown
+    );
+    if api {
+        tracing::info!("the control API is at http://{}/api/", shown);
+        if token.is_none() && !address.ip().is_loopback() {
+            tracing::warn!(
+                "anyone who can reach {} can control the emulator; give a --token",
+                address
+            );
+        }
+    }
+    let (frames, viewers) = watch::channel(Frame {
+        display: machine.chip.display().clone(),
+        sounding: false,
+        tally: machine.tally(),
+    });
+    let (controls, received) = mpsc::unbounded_channel();
+    let server = Server {
+        frames: viewers,
+        controls,
+        api,
+        token,
+        address,
+    };
+    let accepting = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, address)) => {
+                    tokio::spawn(view(stream, address, server.clone()));
+                }
+                Err(err) => tracing::warn!("accept: {}", err),
+            }
+        }
+    });
+    let result = tokio::select! {
+        result = run(machine, &frames, received) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+    accepting.abort();
+    Ok(result?)
+}
+
+/// Runs `machine` in real time, doing what the connections ask between frames and showing the
+/// viewers every frame.
+async fn run(
+    mut machine: Machine,
+    frames: &watch::Sender<Frame>,
+    mut controls: mpsc::UnboundedReceiver<Control>,
+) -> Result<(), ExecError> {
+    let mut ticks = time::interval(TIMER_PERIOD);
+    let mut sounding = false;
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => sounding = machine.frame()?,
+            Some(control) = controls.recv() => match control {
+                Control::Event { voter, event: Event::Key { key, pressed } } => {
+                    machine.key(&voter, key, pressed);
+                }
+                Control::Api { method, target, body, reply } => {
+                    let _ = reply.send(machine.handle(&method, &target, &body));
+                }
+            },
+        }
+        let tally = machine.tally();
+        frames.send_if_modified(|frame| {
+            let changed = frame.sounding != sounding
+                || frame.tally != tally
+                || frame.display.screen() != machine.chip.display().screen();
+            if changed {
+                frame.display = machine.chip.display().clone();
+                frame.sounding = sounding;
+                frame.tally = tally;
+            }
+            changed
+        });
+    }
+}
+
+/// Answers one connection: the viewer page, the WebSocket of a viewer or a request to the API.
+async fn view(stream: TcpStream, address: SocketAddr, server: Server) {
+    if let Err(err) = answer(stream, address, server).await {
+        tracing::warn!(%address, "{}", err);
+    }
+}
+
+async fn answer(stream: TcpStream, address: SocketAddr, server: Server) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let request = match websocket::read_request(&mut reader).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    if server.api && request.path.starts_with("/api/") {
+        if is_foreign(&request, server.address) {
+            tracing::warn!(%address, "a request to the API from another site");
+            let body = b"requests from other sites are refused";
+            return websocket::respond(&mut writer, "403 Forbidden", "text/plain", body).await;
+        }
+        if let Some(token) = &server.token {
+            let bearer = request
+                .header("authorization")
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if !bearer.is_some_and(|bearer| same_token(bearer, token)) {
+                tracing::warn!(%address, "a request to the API without the token");
+                let body = b"missing or wrong token";
+                return websocket::respond(&mut writer, "401 Unauthorized", "text/plain", body)
+                    .await;
+            }
+        }
+        let body = websocket::read_body(&mut reader, &request, MAX_BODY).await?;
+        let (reply, response) = oneshot::channel();
+        let control = Control::Api {
+            method: request.method,
+            target: request.path,
+            body,
+            reply,
+        };
+        let response = match server.controls.send(control) {
+            Ok(()) => response.await.ok(),
+            Err(_) => None,
+        };
+        let response = response.unwrap_or_else(|| Response {
+            status: "503 Service Unavailable",
+            content_type: "text/plain",
+            body: b"the machine stopped".to_vec(),
+        });
+        let Response {
+            status,
+            content_type,
+            body,
+        } = response;
+        return websocket::respond(&mut writer, status, content_type, &body).await;
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/socket") => {
+            let key = match request.websocket_key() {
+                Some(key) => key,
+                None => {
+                    let body = b"expected a WebSocket";
+                    return websocket::respond(&mut writer, "400 Bad Request", "text/plain", body)
+                        .await;
+                }
+            };
+            if is_foreign(&request, server.address) {
+                tracing::warn!(%address, "a WebSocket opened by another site");
+                let body = b"pages of other sites cannot watch or play";
+                return websocket::respond(&mut writer, "403 Forbidden", "text/plain", body).await;
+            }
+            websocket::upgrade(&mut writer, key).await?;
+            tracing::info!(%address, "a viewer is watching");
+            let result = play(Reader::new(reader), &mut writer, address, server).await;
+            tracing::info!(%address, "the viewer left");
+            result
+        }
+        ("GET", "/") | ("GET", "/index.html") => {
+            let content_type = "text/html; charset=utf-8";
+            websocket::respond(&mut writer, "200 OK", content_type, VIEWER.as_bytes()).await
+        }
+        _ => websocket::respond(&mut writer, "404 Not Found", "text/plain", b"not found").await,
+    }
+}
+
+/// Returns whether `request` may come from a page of another site than the one served at
+/// `address`, which browsers let send simple requests and open WebSockets without asking.
+///
+/// A browser says which page a request comes from in `Origin`, which must then be this server as
+/// named in `Host`, over HTTP or, behind a proxy, HTTPS. Served at a loopback address, `Host` must also name this machine, so that a
+/// site whose name was rebound to the loopback address can't pass for it.
+fn is_foreign(request: &Request, address: SocketAddr) -> bool {
+    let host = match request.header("host") {
+        Some(host) => host,
+        None => return request.header("origin").is_some(),
+    };
+    if address.ip().is_loopback() {
+        // The name without the port, and an IPv6 address without its brackets.
+        let name = match host.strip_prefix('[') {
+            Some(rest) => rest.split(']').next().unwrap_or(rest),
+            None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+        };
+        let local = name.eq_ignore_ascii_case("localhost")
+            || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+        if !local {
+            return true;
+        }
+    }
+    // Behind a proxy adding TLS, the page is served over HTTPS.
+    match request.header("origin") {
+        Some(origin) => !["http://", "https://"]
+            .iter()
+            .any(|scheme| origin.eq_ignore_ascii_case(&format!("{}{}", scheme, host))),
+        None => false,
+    }
+}
+
+/// Returns whether `given` is `token`, taking as long whichever byte differs, so that the time
+/// taken doesn't tell how much of a guess was right.
+fn same_token(given: &str, token: &str) -> bool {
+    let difference = given
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |difference, (a, b)| difference | hint::black_box(a ^ b));
+    given.len() == token.len() && difference == 0
+}
+
+/// Returns who a viewer at `address` votes as in crowd play. Viewers are told apart by their IP
+/// address alone, so that the tabs or connections of one player share a single vote.
+fn voter(address: SocketAddr) -> String {
+    address.ip().to_string()
+}
+
+/// Streams the frames to a viewer and passes on its key presses, until it leaves. The keys it
+/// holds are released then, so that none stays stuck.
+async fn play<R, W>(
+    mut reader: Reader<R>,
+    writer: &mut W,
+    address: SocketAddr,
+    server: Server,
+) -> io::Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    // Messages are read on their own task, as reading one cannot be abandoned halfway.
+    let (sender, mut incoming) = mpsc::unbounded_channel();
+    let reading = tokio::spawn(async move {
+        loop {
+            let message = reader.read().await.ok().flatten();
+            let closed = matches!(message, None | Some(Message::Close));
+            if sender.send(message).is_err() || closed {
+                return;
+            }
+        }
+    });
+
+    let Server {
+        mut frames,
+        controls,
+        ..
+    } = server;
+    let voter = voter(address);
+    let send = |key, pressed| {
+        let event = Event::Key { key, pressed };
+        let _ = controls.send(Control::Event {
+            voter: voter.clone(),
+            event,
+        });
+    };
+    let mut encoder = Encoder::new();
+    let mut held = [false; 16];
+    frames.mark_changed();
+    let result = loop {
+        tokio::select! {
+            changed = frames.changed() => {
+                if changed.is_err() {
+                    break Ok(());
+                }
+                let frame = frames.borrow_and_update().clone();
+                let messages = encoder.frame(&frame.display).into_iter()
+                    .chain(encoder.sound(frame.sounding))
+                    .chain(frame.tally.and_then(|tally| encoder.tally(&tally)));
+                for message in messages {
+                    websocket::write_message(writer, &Message::Binary(message)).await?;
+                }
+            }
+            message = incoming.recv() => match message {
+                Some(Some(Message::Text(text))) => match text.parse() {
+                    Ok(Event::Key { key, pressed }) => {
+                        held[usize::from(key)] = pressed;
+                        send(key, pressed);
+                    }
+                    Err(err) => tracing::warn!(%address, "{}", err),
+                },
+                Some(Some(Message::Ping(data))) => {
+                    websocket::write_message(writer, &Message::Pong(data)).await?;
+                }
+                Some(Some(Message::Close)) => {
+                    break websocket::write_message(writer, &Message::Close).await;
+                }
+                Some(Some(_)) => (),
+                Some(None) | None => break Ok(()),
+            },
+        }
+    };
+    reading.abort();
+    for (key, _) in held.iter().enumerate().filter(|(_, &held)| held) {
+        send(key as u8, false);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::rom::Rom;
+    use chip8_core::CHIP8;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/api/load".to_string(),
+            headers: headers
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn refuses_other_sites() {
+        let loopback: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let everywhere: SocketAddr = "0.0.0.0:8080".parse().unwrap();
+        let foreign = |headers: &[(&str, &str)], address| is_foreign(&request(headers), address);
+        // curl and scripts send no origin.
+        assert!(!foreign(&[("host", "localhost:8080")], loopback));
+        assert!(!foreign(&[], loopback));
+        assert!(!foreign(
+            &[
+                ("host", "127.0.0.1:8080"),
+                ("origin", "http://127.0.0.1:8080")
+            ],
+            loopback
+        ));
+        assert!(!foreign(
+            &[("host", "[::1]:8080"), ("origin", "http://[::1]:8080")],
+            loopback
+        ));
+        assert!(foreign(
+            &[
+                ("host", "localhost:8080"),
+                ("origin", "https://example.com")
+            ],
+            loopback
+        ));
+        assert!(foreign(
+            &[("host", "localhost:8080"), ("origin", "null")],
+            loopback
+        ));
+        assert!(foreign(&[("origin", "http://localhost:8080")], loopback));
+        // A site rebound to the loopback address.
+        assert!(foreign(&[("host", "rebound.example:8080")], loopback));
+        assert!(!foreign(
+            &[
+                ("host", "emulator.example:8080"),
+                ("origin", "http://emulator.example:8080")
+            ],
+            everywhere
+        ));
+        assert!(foreign(
+            &[
+                ("host", "emulator.example:8080"),
+                ("origin", "http://example.com")
+            ],
+            everywhere
+        ));
+    }
+
+    /// Sends the handshake of a WebSocket to a server on the loopback address, from a page at
+    /// the origin `origin` gives for the server's address, and returns the status line of the
+    /// answer.
+    async fn handshake(origin: impl FnOnce(SocketAddr) -> String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (_frames, viewers) = watch::channel(Frame {
+            display: Display::new(),
+            sounding: false,
+            tally: None,
+        });
+        let (controls, _received) = mpsc::unbounded_channel();
+        let server = Server {
+            frames: viewers,
+            controls,
+            api: false,
+            token: None,
+            address,
+        };
+        tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            answer(stream, peer, server).await
+        });
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let head = format!(
+            "GET /socket HTTP/1.1\r\nHost: {}\r\nOrigin: {}\r\nUpgrade: websocket\r\n\
+             Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            address,
+            origin(address)
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).await.unwrap();
+        status.trim_end().to_string()
+    }
+
+    #[tokio::test]
+    async fn refuses_sockets_of_other_sites() {
+        let foreign = handshake(|_| "https://example.com".to_string()).await;
+        assert_eq!(foreign, "HTTP/1.1 403 Forbidden");
+        let viewer = handshake(|address| format!("http://{}", address)).await;
+        assert_eq!(viewer, "HTTP/1.1 101 Switching Protocols");
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(same_token("s3cret", "s3cret"));
+        assert!(!same_token("s3creT", "s3cret"));
+        assert!(!same_token("s3cre", "s3cret"));
+        assert!(!same_token("s3cret!", "s3cret"));
+        assert!(!same_token("", "s3cret"));
+    }
+
+    #[test]
+    fn one_vote_per_address() {
+        // 0x200: JP 0x200
+        let mut chip = CHIP8::new();
+        chip.load_rom(&Rom::from_bytes(vec![0x12, 0x00]).unwrap())
+            .unwrap();
+        let mut machine = Machine::new(chip);
+        machine.set_crowd(30);
+        // Two tabs of one player, and another player.
+        let tabs = ["192.0.2.1:50000", "192.0.2.1:50001"];
+        for (address, key) in tabs.iter().zip([1, 2]) {
+            machine.key(&voter(address.parse().unwrap()), key, true);
+        }
+        machine.key(&voter("192.0.2.2:50000".parse().unwrap()), 1, true);
+        let votes = machine.tally().unwrap().votes;
+        assert_eq!((votes[1], votes[2]), (1, 1));
+        assert_eq!(votes.iter().sum::<u32>(), 2);
+    }
+}
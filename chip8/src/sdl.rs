@@ -0,0 +1,161 @@
+//! An SDL2 window as a frontend for `chip8 run --sdl`: the game is drawn scaled up in a window of
+//! its own, the tone is a square wave, and the left-hand side of the keyboard is the keypad.
+//!
+//! Only built with the `sdl` feature, as it needs the SDL2 libraries of the system.
+
+use chip8_core::display::{Display, HEIGHT, WIDTH};
+use chip8_core::frontend::{Audio, Input, Video};
+use chip8_core::keyboard::{self, Keyboard};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::EventPump;
+use std::convert::TryFrom;
+
+/// How many times its size the game is drawn.
+const SCALE: u32 = 10;
+
+/// The pitch of the tone, in Hz.
+const PITCH: f32 = 440.0;
+
+/// Opens a window titled `title`, with the sound and keypad that go with it.
+pub fn open(title: &str) -> Result<(Window, Beeper, Keys), String> {
+    let sdl = sdl2::init()?;
+    let window = sdl
+        .video()?
+        .window(title, WIDTH as u32 * SCALE, HEIGHT as u32 * SCALE)
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|err| err.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .present_vsync()
+        .build()
+        .map_err(|err| err.to_string())?;
+    // The game keeps its proportions, with black bars around it, when the window is resized.
+    canvas
+        .set_logical_size(WIDTH as u32, HEIGHT as u32)
+        .map_err(|err| err.to_string())?;
+
+    let spec = AudioSpecDesired {
+        freq: Some(44_100),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = sdl.audio()?.open_playback(None, &spec, |spec| SquareWave {
+        step: PITCH / spec.freq as f32,
+        phase: 0.0,
+        volume: 0.1,
+    })?;
+
+    Ok((
+        Window { canvas },
+        Beeper { device },
+        Keys {
+            events: sdl.event_pump()?,
+        },
+    ))
+}
+
+/// Draws the game, lit pixels white on black.
+pub struct Window {
+    canvas: Canvas<sdl2::video::Window>,
+}
+
+impl Video for Window {
+    fn draw(&mut self, display: &Display) {
+        self.canvas.set_draw_color(Color::BLACK);
+        self.canvas.clear();
+        self.canvas.set_draw_color(Color::WHITE);
+        let lit: Vec<Rect> = (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| display.pixel(x, y))
+            .map(|(x, y)| Rect::new(x as i32, y as i32, 1, 1))
+            .collect();
+        if let Err(err) = self.canvas.fill_rects(&lit) {
+            tracing::warn!("drawing the game: {}", err);
+        }
+        self.canvas.present();
+    }
+}
+
+/// A square wave, which SDL asks for samples of as it plays.
+struct SquareWave {
+    /// The part of a period each sample moves on by.
+    step: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out {
+            *sample = if self.phase < 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+/// Plays the tone while the sound timer runs.
+pub struct Beeper {
+    device: AudioDevice<SquareWave>,
+}
+
+impl Audio for Beeper {
+    fn play(&mut self, sounding: bool) {
+        if sounding {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+}
+
+/// Reads the keypad from the keys held down in the window. Closing it, Escape or Ctrl-Q quits.
+pub struct Keys {
+    events: EventPump,
+}
+
+impl Input for Keys {
+    fn poll(&mut self, keyboard: &mut Keyboard) -> bool {
+        for event in self.events.poll_iter() {
+            let (keycode, keymod, pressed) = match event {
+                Event::Quit { .. } => return false,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } => (keycode, keymod, true),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } => (keycode, keymod, false),
+                _ => continue,
+            };
+            let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+            if pressed && (keycode == Keycode::ESCAPE || (ctrl && keycode == Keycode::Q)) {
+                return false;
+            }
+            // The keycodes of printable keys are the characters they type.
+            let key = u32::try_from(keycode.into_i32())
+                .ok()
+                .and_then(char::from_u32)
+                .and_then(keyboard::key_for_char);
+            if let Some(key) = key {
+                keyboard.set(key, pressed);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,216 @@
+//! Terminal handling for the text user interface, which is drawn with ratatui on crossterm.
+
+use chip8_core::display::{Display, HEIGHT, WIDTH};
+use chip8_core::CHIP8;
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self as term, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Span;
+use ratatui::widgets::{Block, Borders};
+use ratatui::Frame;
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long a keypad key stays down after a key press.
+///
+/// Terminals do not report key releases, so keys are released once the terminal stops repeating
+/// them.
+pub const KEY_HOLD: Duration = Duration::from_millis(150);
+
+/// Updates the timers of `chip`, ringing the terminal bell while the sound timer runs.
+pub fn cycle_timers(chip: &mut CHIP8) {
+    if chip.timer().is_sounding() {
+        bell();
+    }
+    chip.tick();
+}
+
+/// Rings the terminal bell. Unlike printing a message, this does not disturb a full-screen
+/// interface.
+pub fn bell() {
+    print!("\x07");
+    let _ = io::stdout().flush();
+}
+
+/// Returns the lines of text that draw `display`, two rows of pixels per line using half blocks.
+pub fn half_blocks(display: &Display) -> Vec<String> {
+    let screen = &display.screen();
+    (0..HEIGHT / 2)
+        .map(|row| {
+            (0..WIDTH)
+                .map(
+                    |column| match (screen[row * 2][column], screen[row * 2 + 1][column]) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns a pane with a dim border and `title` in bold in its top border.
+pub fn pane(title: &str) -> Block<'_> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().add_modifier(Modifier::DIM))
+        .title(Span::styled(
+            format!(" {} ", title),
+            Style::default().add_modifier(Modifier::BOLD),
+        ))
+}
+
+/// The terminal in raw mode on an alternate screen, restored when dropped.
+///
+/// In raw mode input is delivered key by key without echo, and Ctrl-C arrives as a key instead of
+/// a signal.
+pub struct FullScreen {
+    terminal: ratatui::Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl FullScreen {
+    /// Switches the terminal to raw mode and the alternate screen.
+    pub fn enable() -> io::Result<Self> {
+        term::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        if let Err(err) = execute!(stdout, EnterAlternateScreen, cursor::Hide) {
+            let _ = term::disable_raw_mode();
+            return Err(err);
+        }
+        Ok(FullScreen {
+            terminal: ratatui::Terminal::new(CrosstermBackend::new(stdout))?,
+        })
+    }
+
+    /// Redraws the screen with `render`. Only the cells that changed since the last time are
+    /// written, which avoids flicker.
+    pub fn draw(&mut self, render: impl FnOnce(&mut Frame)) -> io::Result<()> {
+        self.terminal.draw(render).map(|_| ())
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.terminal.backend_mut(),
+            LeaveAlternateScreen,
+            cursor::Show
+        );
+        let _ = term::disable_raw_mode();
+    }
+}
+
+/// Reads key presses on a background thread, forwarding them as they arrive.
+///
+/// A plain thread is used rather than tokio's blocking pool, whose reads would keep the runtime
+/// from shutting down.
+pub fn spawn_reader() -> mpsc::UnboundedReceiver<Key> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            let key = match event {
+                Event::Key(event) => Key::from_event(event),
+                _ => continue,
+            };
+            if let Some(key) = key {
+                if sender.send(key).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// A key press read from the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Backspace,
+    Tab,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    /// A function key, `F(1)` to `F(12)`.
+    F(u8),
+}
+
+impl Key {
+    /// Returns the key a crossterm event presses, if any. Releases, which only some terminals
+    /// report, and keys the interface has no use for give `None`.
+    fn from_event(event: KeyEvent) -> Option<Key> {
+        if event.kind == KeyEventKind::Release {
+            return None;
+        }
+        let key = match event.code {
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                Key::Ctrl(c.to_ascii_lowercase())
+            }
+            KeyCode::Char(c) => Key::Char(c),
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::Tab => Key::Tab,
+            KeyCode::Esc => Key::Escape,
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::Left => Key::Left,
+            KeyCode::Right => Key::Right,
+            KeyCode::PageUp => Key::PageUp,
+            KeyCode::PageDown => Key::PageDown,
+            KeyCode::F(n @ 1..=12) => Key::F(n),
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let key = |code, modifiers| Key::from_event(KeyEvent::new(code, modifiers));
+        assert_eq!(
+            key(KeyCode::Char('a'), KeyModifiers::NONE),
+            Some(Key::Char('a'))
+        );
+        assert_eq!(
+            key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Key::Ctrl('c'))
+        );
+        assert_eq!(
+            key(KeyCode::Char('é'), KeyModifiers::NONE),
+            Some(Key::Char('é'))
+        );
+        assert_eq!(key(KeyCode::F(5), KeyModifiers::NONE), Some(Key::F(5)));
+        assert_eq!(key(KeyCode::Esc, KeyModifiers::NONE), Some(Key::Escape));
+        assert_eq!(key(KeyCode::Home, KeyModifiers::NONE), None);
+        let mut release = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE);
+        release.kind = KeyEventKind::Release;
+        assert_eq!(Key::from_event(release), None);
+    }
+
+    #[test]
+    fn blocks() {
+        let mut display = Display::new();
+        display.set_pixel(0, 0, true);
+        display.set_pixel(1, 1, true);
+        let lines = half_blocks(&display);
+        assert_eq!(lines.len(), HEIGHT / 2);
+        assert!(lines[0].starts_with("▀▄ "));
+    }
+}
@@ -0,0 +1,886 @@
+//! Full-screen terminal debugger.
+//!
+//! The screen, drawn with ratatui, is split into panes for the game, a memory hexdump, the
+//! disassembly around the program counter, registers, the keypad and the stack, with a command line
+//! at the bottom. The game keeps running while the panes update live. The latest events of the log have a pane of
+//! their own, as they cannot be written over the screen.
+//!
+//! Resetting, whether with F4 or by the program exiting with `00FD`, keeps the machine as it was
+//! for a few seconds, so that an accidental reset can be undone with Ctrl-Z.
+
+use crate::terminal::{self, FullScreen, Key, KEY_HOLD};
+use chip8_core::callstack;
+use chip8_core::cheat::Cheat;
+use chip8_core::command::Command;
+use chip8_core::debugger::{Debugger, Register, StopReason};
+use chip8_core::disasm;
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::error::ExecError;
+use chip8_core::inspector::{self, Highlight, Inspector, State};
+use chip8_core::instruction::Instruction;
+use chip8_core::keyboard::{self, LAYOUT};
+use chip8_core::movie::Recorder;
+use chip8_core::rewind::{self, Rewind};
+use chip8_core::state::{self, Slots, SLOTS};
+use chip8_core::symbols::Symbols;
+use chip8_core::{CHIP8, INSTRUCTION_PERIOD, TIMER_PERIOD};
+use emu_debug::log::Log;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::time;
+use tracing::Level;
+
+/// Time between two redraws of the screen.
+const FRAME_PERIOD: Duration = Duration::from_millis(33);
+
+/// How long a reset can be undone for.
+const UNDO_PERIOD: Duration = Duration::from_secs(10);
+
+/// Smallest terminal the layout fits in.
+const MIN_SIZE: (u16, u16) = (103, 36);
+
+/// Width of the left column, which holds the game and the memory pane.
+const LEFT_WIDTH: u16 = WIDTH as u16 + 2;
+
+/// Height of the game pane, and of the disassembly beside it.
+const GAME_HEIGHT: u16 = HEIGHT as u16 / 2 + 2;
+
+const HELP: &str = "F5 run/pause  F6 rewind  F7 back  F10 next  F11 step  Tab game/command  \
+                    PgUp/PgDn memory  F2/F3 save/load  F8 slot  F4 reset  Ctrl-Q quit";
+
+const RECORDING_HELP: &str = "recording a movie  1-4 Q-R A-F Z-V keypad  F5 pause  Ctrl-Q stop";
+
+/// Where typed characters go.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    /// Keys are mapped onto the keypad.
+    Game,
+    /// Keys are typed into the command line.
+    Command,
+}
+
+struct Tui<'a> {
+    title: &'a str,
+    debugger: Debugger,
+    running: bool,
+    focus: Focus,
+    command: String,
+    last_command: String,
+    message: String,
+    inspector: Inspector,
+    /// First address shown in the memory pane.
+    memory_start: u16,
+    /// Whether the memory pane is colored by how often each byte was accessed.
+    heatmap: bool,
+    /// When each keypad key should be released.
+    releases: [Option<Instant>; 16],
+    /// The state of the machine when execution last stopped, and when it stopped before that.
+    /// What changed in between is highlighted.
+    stopped_at: State,
+    changed_from: State,
+    /// Save states of the ROM.
+    slots: Slots,
+    /// The last few seconds of the game, one frame per timer update.
+    rewind: Rewind,
+    /// When the rewind key should be released, while it is held.
+    rewinding: Option<Instant>,
+    /// The machine as the ROM was loaded, which resetting goes back to.
+    start: state::State,
+    /// The machine before the last reset, and until when the reset can be undone.
+    undo: Option<(Instant, state::State)>,
+    /// The movie being recorded, in which case the game runs a whole frame at a time and only
+    /// the keypad can change it.
+    recorder: Option<&'a mut Recorder>,
+    /// The events logged, held back from the terminal while the debugger runs.
+    log: Log,
+}
+
+impl<'a> Tui<'a> {
+    fn new(
+        title: &'a str,
+        chip: &CHIP8,
+        symbols: Symbols,
+        slots: Slots,
+        recorder: Option<&'a mut Recorder>,
+        log: Log,
+    ) -> Self {
+        let mut debugger = Debugger::new(chip.memory().len());
+        debugger.set_symbols(symbols);
+        Tui {
+            inspector: Inspector::new(chip),
+            title,
+            debugger,
+            running: false,
+            focus: Focus::Command,
+            command: String::new(),
+            last_command: String::new(),
+            message: HELP.to_string(),
+            memory_start: 0x200,
+            heatmap: false,
+            releases: [None; 16],
+            stopped_at: State::take(chip),
+            changed_from: State::take(chip),
+            slots,
+            rewind: Rewind::new(rewind::DEFAULT_FRAMES),
+            rewinding: None,
+            start: chip.snapshot(),
+            undo: None,
+            recorder,
+            log,
+        }
+    }
+
+    /// Stops running and shows why.
+    fn stop(&mut self, chip: &CHIP8, reason: StopReason) {
+        self.running = false;
+        self.changed_from = std::mem::replace(&mut self.stopped_at, State::take(chip));
+        let changes = self.changed_from.changes(chip);
+        self.message = if reason == StopReason::Step && !changes.is_empty() {
+            changes.join("  ")
+        } else {
+            reason.to_string()
+        };
+    }
+
+    fn toggle_running(&mut self, chip: &CHIP8) {
+        if self.running {
+            let reason = self.debugger.interrupt();
+            self.stop(chip, reason);
+        } else {
+            self.running = true;
+            self.message.clear();
+        }
+    }
+
+    fn step_over(&mut self, chip: &mut CHIP8) {
+        if self.running {
+            return;
+        }
+        match self.debugger.start_step_over(chip) {
+            Some(reason) => self.stop(chip, reason),
+            None => self.running = true,
+        }
+    }
+
+    fn step(&mut self, chip: &mut CHIP8, count: u16) {
+        if self.running {
+            return;
+        }
+        for _ in 0..count {
+            let reason = self.debugger.step(chip);
+            if reason != StopReason::Step {
+                self.stop(chip, reason);
+                return;
+            }
+        }
+        self.stop(chip, StopReason::Step);
+    }
+
+    fn reverse_step(&mut self, chip: &mut CHIP8, count: u16) {
+        if !self.running {
+            let reason = self.debugger.reverse_step(chip, count);
+            self.stop(chip, reason);
+        }
+    }
+
+    fn save_state(&mut self, chip: &CHIP8, slot: Option<u8>) {
+        self.message = match self.slots.save(chip, slot) {
+            Ok(message) | Err(message) => message,
+        };
+    }
+
+    /// Restores a save state, forgetting the history and changes of the state it replaces.
+    fn load_state(&mut self, chip: &mut CHIP8, slot: Option<u8>) {
+        self.message = match self.slots.load(chip, slot) {
+            Ok(message) => {
+                self.debugger.clear_history();
+                self.stopped_at = State::take(chip);
+                self.changed_from = State::take(chip);
+                message
+            }
+            Err(message) => message,
+        };
+    }
+
+    /// Puts the machine back to how the ROM was loaded, keeping its current state so that the reset
+    /// can be undone for a while. `why` says what caused it.
+    fn reset(&mut self, chip: &mut CHIP8, before: state::State, why: &str) {
+        chip.restore(&self.start);
+        self.debugger.clear_history();
+        self.stopped_at = State::take(chip);
+        self.changed_from = State::take(chip);
+        self.undo = Some((Instant::now() + UNDO_PERIOD, before));
+        self.message = why.to_string();
+    }
+
+    /// Undoes the last reset, if it was recent enough, and pauses so that the player can get
+    /// ready to carry on. The keys held down are kept, since they are still being held.
+    fn undo_reset(&mut self, chip: &mut CHIP8) {
+        let before = match self.undo.take() {
+            Some((until, before)) if until > Instant::now() => before,
+            _ => {
+                self.message = "nothing to undo".to_string();
+                return;
+            }
+        };
+        let keyboard = chip.keyboard().clone();
+        chip.restore(&before);
+        *chip.keyboard_mut() = keyboard;
+        self.debugger.clear_history();
+        self.running = false;
+        self.stopped_at = State::take(chip);
+        self.changed_from = State::take(chip);
+        self.message = "undid the reset; F5 to carry on".to_string();
+    }
+
+    /// Stops running and shows why, unless the program exited with `00FD`, which resets it.
+    fn stop_or_exit(&mut self, chip: &mut CHIP8, reason: StopReason) {
+        match reason {
+            StopReason::Error(ExecError::Unsupported {
+                address,
+                instruction: Instruction::Exit,
+            }) => {
+                let mut before = chip.snapshot();
+                before.set_program_counter(address);
+                self.reset(
+                    chip,
+                    before,
+                    &format!("the program exited at {:#05X} and was reset", address),
+                );
+            }
+            reason => self.stop(chip, reason),
+        }
+    }
+
+    /// Scrolls the memory pane by `rows` rows of 16 bytes.
+    fn scroll_memory(&mut self, rows: i32) {
+        let start = self.memory_start as i32 + rows * 16;
+        self.memory_start = start.clamp(0, 0x1000 - 16) as u16;
+    }
+
+    /// Advances the game by a frame, or while the rewind key is held, takes it back by one.
+    fn frame(&mut self, chip: &mut CHIP8) {
+        match self.rewinding {
+            Some(release) if release > Instant::now() => {
+                self.message = if self.rewind.rewind(chip) {
+                    self.debugger.clear_history();
+                    format!(
+                        "rewinding, {:.1} s left",
+                        self.rewind.len() as f64 * TIMER_PERIOD.as_secs_f64()
+                    )
+                } else {
+                    "reached the start of the rewind buffer".to_string()
+                };
+            }
+            Some(_) => {
+                // Carry on from the frame rewound to, highlighting what rewinding changed.
+                self.rewinding = None;
+                self.changed_from = std::mem::replace(&mut self.stopped_at, State::take(chip));
+            }
+            None if self.running => match &mut self.recorder {
+                Some(recorder) => {
+                    if chip.timer().is_sounding() {
+                        terminal::bell();
+                    }
+                    if let Err(err) = recorder.frame(chip) {
+                        self.running = false;
+                        self.message = err.to_string();
+                    }
+                }
+                None => {
+                    terminal::cycle_timers(chip);
+                    self.rewind.record(chip);
+                }
+            },
+            None => (),
+        }
+    }
+
+    fn press(&mut self, key: u8, chip: &mut CHIP8) {
+        chip.keyboard_mut().set(key, true);
+        self.releases[key as usize] = Some(Instant::now() + KEY_HOLD);
+    }
+
+    fn release_keys(&mut self, chip: &mut CHIP8) {
+        let now = Instant::now();
+        for (key, release) in self.releases.iter_mut().enumerate() {
+            if release.is_some_and(|at| at <= now) {
+                chip.keyboard_mut().set(key as u8, false);
+                *release = None;
+            }
+        }
+    }
+
+    /// Runs the command typed into the command line. Returns `false` to quit.
+    fn execute(&mut self, chip: &mut CHIP8) -> bool {
+        let line = std::mem::take(&mut self.command);
+        if !line.trim().is_empty() {
+            self.last_command = line;
+        }
+        if self.last_command.trim().is_empty() {
+            return true;
+        }
+
+        let command = match Command::parse(&self.last_command, self.debugger.symbols()) {
+            Ok(command) => command,
+            Err(message) => {
+                self.message = message;
+                return true;
+            }
+        };
+        self.message.clear();
+        match command {
+            Command::Break(address, condition) => {
+                if !self.debugger.add_breakpoint(address, condition) {
+                    self.message = format!("updated breakpoint at {:#05X}", address);
+                }
+            }
+            Command::Delete(address) => {
+                if !self.debugger.remove_breakpoint(address) {
+                    self.message = format!("no breakpoint at {:#05X}", address);
+                }
+            }
+            Command::Breakpoints => {
+                let addresses: Vec<String> = self
+                    .debugger
+                    .breakpoints()
+                    .map(|address| match self.debugger.condition(address) {
+                        Some(condition) => format!(
+                            "{} if {}",
+                            self.debugger.symbols().describe(address),
+                            condition
+                        ),
+                        None => self.debugger.symbols().describe(address),
+                    })
+                    .collect();
+                self.message = format!("breakpoints: {}", addresses.join(", "));
+            }
+            Command::Watch(watchpoint) => self.debugger.add_watchpoint(watchpoint),
+            Command::Unwatch(address) => {
+                if !self.debugger.remove_watchpoint(address) {
+                    self.message = format!("no watchpoint at {:#05X}", address);
+                }
+            }
+            Command::Watchpoints => {
+                let watchpoints: Vec<String> = self
+                    .debugger
+                    .watchpoints()
+                    .map(|watchpoint| watchpoint.to_string())
+                    .collect();
+                self.message = format!("watchpoints: {}", watchpoints.join(", "));
+            }
+            Command::Step(count) => self.step(chip, count),
+            Command::Next => self.step_over(chip),
+            Command::Continue => self.running = true,
+            Command::ReverseStep(count) => self.reverse_step(chip, count),
+            Command::ReverseContinue if !self.running => {
+                let reason = self.debugger.reverse_continue(chip);
+                self.stop(chip, reason);
+            }
+            Command::ReverseContinue => (),
+            Command::Set(register, value) => {
+                register.write(chip, value);
+                self.debugger.clear_history();
+            }
+            Command::List(_) | Command::Registers | Command::Stack | Command::Backtrace => {
+                self.message = "shown in the panes above".to_string();
+            }
+            Command::Memory(address, _) => self.memory_start = address & !0xF,
+            Command::Poke(address, bytes) => {
+                if self.running {
+                    self.message = "pause before poking memory".to_string();
+                } else if let Err(message) = inspector::poke(chip, address, &bytes) {
+                    self.message = message;
+                } else {
+                    self.debugger.clear_history();
+                }
+            }
+            Command::Heatmap(None) => self.heatmap = !self.heatmap,
+            Command::Heatmap(Some(path)) => {
+                self.message = match fs::write(&path, self.debugger.heatmap().png()) {
+                    Ok(()) => format!("wrote {}", path),
+                    Err(err) => err.to_string(),
+                }
+            }
+            Command::Search(filter) => {
+                if let Some(filter) = filter {
+                    self.debugger.search().filter(chip, filter);
+                }
+                self.message = self.debugger.search().summary(chip);
+            }
+            Command::NewSearch => {
+                self.debugger.search().start(chip);
+                self.message = self.debugger.search().summary(chip);
+            }
+            Command::Pin(address, name) => self.debugger.pin(address, name),
+            Command::PinFound => {
+                self.message = match self.debugger.pin_found() {
+                    Ok(count) => format!("pinned {} addresses", count),
+                    Err(message) => message,
+                }
+            }
+            Command::Unpin(address) => {
+                if !self.debugger.unpin(address) {
+                    self.message = format!("{:#05X} is not pinned", address);
+                }
+            }
+            Command::Pins => self.message = "shown in the watch pane".to_string(),
+            Command::SaveState(slot) => self.save_state(chip, slot),
+            Command::LoadState(slot) => self.load_state(chip, slot),
+            Command::Cheat(cheat) => self.debugger.add_cheat(chip, cheat),
+            Command::Uncheat(address) => {
+                if !self.debugger.remove_cheat(address) {
+                    self.message = format!("no cheat at {:#05X}", address);
+                }
+            }
+            Command::Cheats => {
+                let cheats: Vec<String> = self.debugger.cheats().map(Cheat::to_string).collect();
+                self.message = format!("cheats: {}", cheats.join(", "));
+            }
+            Command::Help => self.message = HELP.to_string(),
+            Command::Quit => return false,
+        }
+        true
+    }
+
+    /// Handles a key press. Returns `false` to quit.
+    fn handle_key(&mut self, key: Key, chip: &mut CHIP8) -> bool {
+        if self.recorder.is_some() {
+            return self.handle_recording_key(key, chip);
+        }
+        match (key, self.focus) {
+            (Key::Ctrl('q'), _) => return false,
+            (Key::Ctrl('c'), _) if self.running => self.toggle_running(chip),
+            (Key::F(2), _) => self.save_state(chip, None),
+            (Key::F(3), _) => self.load_state(chip, None),
+            (Key::F(4), _) => {
+                let before = chip.snapshot();
+                self.reset(chip, before, "reset");
+            }
+            (Key::Ctrl('z'), _) => self.undo_reset(chip),
+            (Key::F(8), _) => {
+                self.slots.selected = (self.slots.selected + 1) % SLOTS;
+                self.message = format!("selected slot {}", self.slots.selected);
+            }
+            (Key::F(5), _) => self.toggle_running(chip),
+            (Key::F(6), _) => self.rewinding = Some(Instant::now() + KEY_HOLD),
+            (Key::F(7), _) => self.reverse_step(chip, 1),
+            (Key::F(10), _) => self.step_over(chip),
+            (Key::F(11), _) => self.step(chip, 1),
+            (Key::Tab, _) => {
+                self.focus = match self.focus {
+                    Focus::Game => Focus::Command,
+                    Focus::Command => Focus::Game,
+                }
+            }
+            (Key::PageUp, _) => self.scroll_memory(-8),
+            (Key::PageDown, _) => self.scroll_memory(8),
+            (Key::Up, _) => self.scroll_memory(-1),
+            (Key::Down, _) => self.scroll_memory(1),
+            (Key::Char(c), Focus::Game) => {
+                if let Some(key) = keyboard::key_for_char(c) {
+                    self.press(key, chip);
+                }
+            }
+            (Key::Char(c), Focus::Command) => self.command.push(c),
+            (Key::Backspace, Focus::Command) => {
+                self.command.pop();
+            }
+            (Key::Escape, Focus::Command) => self.command.clear(),
+            (Key::Enter, Focus::Command) => return self.execute(chip),
+            _ => (),
+        }
+        true
+    }
+
+    /// Handles a key press while recording a movie, where anything but the keypad would make the
+    /// movie play differently. Returns `false` to quit.
+    fn handle_recording_key(&mut self, key: Key, chip: &mut CHIP8) -> bool {
+        match key {
+            Key::Ctrl('q') => return false,
+            Key::Ctrl('c') if self.running => self.toggle_running(chip),
+            Key::F(5) => self.toggle_running(chip),
+            Key::Char(c) => {
+                if let Some(key) = keyboard::key_for_char(c) {
+                    self.press(key, chip);
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn draw(&self, frame: &mut Frame, chip: &CHIP8) {
+        let area = frame.area();
+        if area.width < MIN_SIZE.0 || area.height < MIN_SIZE.1 {
+            let message = format!("terminal too small; need {}x{}", MIN_SIZE.0, MIN_SIZE.1);
+            frame.render_widget(Paragraph::new(message).style(bold()), area);
+            return;
+        }
+
+        let state = if self.rewinding.is_some() {
+            "REWINDING"
+        } else if self.running && self.recorder.is_some() {
+            "RECORDING"
+        } else if self.running {
+            "RUNNING"
+        } else {
+            "PAUSED"
+        };
+        let focus = match self.focus {
+            Focus::Game => "game",
+            Focus::Command => "command",
+        };
+        let mut title = format!(
+            " chip8 debug: {}  [{}]  input: {}  slot: {}",
+            self.title, state, focus, self.slots.selected
+        );
+        if let Some((until, _)) = &self.undo {
+            let left = until.saturating_duration_since(Instant::now());
+            if !left.is_zero() {
+                title += &format!("  Ctrl-Z undoes the reset ({} s)", left.as_secs() + 1);
+            }
+        }
+
+        let [title_bar, panes, message, prompt] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let reversed = Style::default().add_modifier(Modifier::REVERSED);
+        frame.render_widget(Paragraph::new(title).style(reversed), title_bar);
+
+        let [left, right] =
+            Layout::horizontal([Constraint::Length(LEFT_WIDTH), Constraint::Min(0)])
+                .spacing(1)
+                .areas(panes);
+        let [game, memory] =
+            Layout::vertical([Constraint::Length(GAME_HEIGHT), Constraint::Min(0)]).areas(left);
+        self.draw_game(frame, chip, game);
+        self.draw_memory(frame, chip, memory);
+
+        let [disassembly, registers, below] = Layout::vertical([
+            Constraint::Length(GAME_HEIGHT),
+            Constraint::Length(8),
+            Constraint::Min(0),
+        ])
+        .areas(right);
+        self.draw_disassembly(frame, chip, disassembly);
+        let [registers, keypad] =
+            Layout::horizontal([Constraint::Length(25), Constraint::Length(11)]).areas(registers);
+        self.draw_registers(frame, chip, registers);
+        let [keypad, _] =
+            Layout::vertical([Constraint::Length(6), Constraint::Min(0)]).areas(keypad);
+        self.draw_keypad(frame, chip, keypad);
+
+        // The watch list takes up to half of the space below the registers, and the log up to
+        // half of what is left, below the stack.
+        let watch_list = self
+            .debugger
+            .watch_list()
+            .lines(chip, self.debugger.symbols());
+        let watch_height = match watch_list.len() {
+            0 => 0,
+            lines => (lines as u16 + 2).min(below.height / 2),
+        };
+        let log_height = match self.log.len() {
+            0 => 0,
+            events => (events as u16 + 2).min((below.height - watch_height) / 2),
+        };
+        let [watch, stack, log] = Layout::vertical([
+            Constraint::Length(watch_height),
+            Constraint::Min(0),
+            Constraint::Length(log_height),
+        ])
+        .areas(below);
+        if watch_height > 0 {
+            self.draw_watch_list(frame, watch_list, watch);
+        }
+        self.draw_stack(frame, chip, stack);
+        if log_height >= 3 {
+            self.draw_log(frame, log);
+        }
+
+        frame.render_widget(Paragraph::new(self.message.as_str()), message);
+        let mut command = vec![Span::styled(format!("> {}", self.command), bold())];
+        if self.focus == Focus::Command {
+            command.push(Span::styled(" ", reversed));
+        }
+        frame.render_widget(Paragraph::new(Line::from(command)), prompt);
+    }
+
+    /// Draws the screen using half blocks, so that each character holds two rows of pixels.
+    fn draw_game(&self, frame: &mut Frame, chip: &CHIP8, area: Rect) {
+        let lines: Vec<Line> = terminal::half_blocks(chip.display())
+            .into_iter()
+            .map(Line::from)
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(terminal::pane("game")), area);
+    }
+
+    /// Draws the hexdump. The bytes at the program counter are reversed, the byte I points to is
+    /// underlined, recently written bytes are bold and the font is dimmed.
+    fn draw_memory(&self, frame: &mut Frame, chip: &CHIP8, area: Rect) {
+        let title = if self.heatmap {
+            "memory (heatmap)"
+        } else {
+            "memory"
+        };
+        let rows = self.inspector.rows(
+            chip,
+            self.memory_start,
+            area.height.saturating_sub(2) as usize,
+        );
+        // Level 0 means untouched, and is drawn without a background.
+        let heat = if self.heatmap {
+            self.debugger.heatmap().heat(6)
+        } else {
+            Vec::new()
+        };
+        let lines: Vec<Line> = rows
+            .iter()
+            .map(|line| {
+                let mut spans = vec![Span::raw(format!(" {:04X}  ", line.address))];
+                for (column, &(byte, highlight)) in line.bytes.iter().enumerate() {
+                    // A gap after every byte, and a wider one between the two halves of the row.
+                    match column {
+                        0 => (),
+                        8 => spans.push(Span::raw("  ")),
+                        _ => spans.push(Span::raw(" ")),
+                    }
+                    let address = line.address as usize + column;
+                    let style = match (heat.get(address), highlight) {
+                        (Some(&level), _) if level > 0 => Style::default().bg(heat_color(level)),
+                        (Some(_), _) => Style::default(),
+                        (None, Highlight::ProgramCounter) => {
+                            Style::default().add_modifier(Modifier::REVERSED)
+                        }
+                        (None, Highlight::Index) => {
+                            Style::default().add_modifier(Modifier::UNDERLINED)
+                        }
+                        (None, _)
+                            if !self.running && self.changed_from.memory_changed(chip, address) =>
+                        {
+                            bold()
+                        }
+                        (None, Highlight::Recent) => bold(),
+                        (None, Highlight::Font) => Style::default().add_modifier(Modifier::DIM),
+                        (None, Highlight::None) => Style::default(),
+                    };
+                    spans.push(Span::styled(format!("{:02X}", byte), style));
+                }
+                Line::from(spans)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(terminal::pane(title)), area);
+    }
+
+    fn draw_disassembly(&self, frame: &mut Frame, chip: &CHIP8, area: Rect) {
+        let pc = chip.program_counter();
+        // Show a few instructions before the program counter, assuming they are aligned with it.
+        let start = pc.saturating_sub(8);
+        let breakpoints: Vec<u16> = self.debugger.breakpoints().collect();
+        let mut listing =
+            disasm::disassemble_memory(chip.memory(), start, area.height as usize - 2);
+        let lines: Vec<Line> = listing
+            .iter_mut()
+            .map(|line| {
+                line.symbolize(self.debugger.symbols());
+                let marker = if breakpoints.contains(&line.address) {
+                    '*'
+                } else {
+                    ' '
+                };
+                let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                let text = format!(
+                    "{}{:04X}  {:<11} {}",
+                    marker,
+                    line.address,
+                    bytes.join(" "),
+                    line.text
+                );
+                if line.address == pc {
+                    Line::styled(text, Style::default().add_modifier(Modifier::REVERSED))
+                } else {
+                    Line::raw(text)
+                }
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(terminal::pane("disassembly")),
+            area,
+        );
+    }
+
+    fn draw_registers(&self, frame: &mut Frame, chip: &CHIP8, area: Rect) {
+        // Registers that changed when execution last stopped are shown in bold.
+        let style = |register| {
+            if !self.running && self.changed_from.register_changed(chip, register) {
+                bold()
+            } else {
+                Style::default()
+            }
+        };
+        let mut lines = vec![
+            Line::from(vec![
+                Span::raw(format!("PC {:04X}  ", chip.program_counter())),
+                Span::styled(format!("I {:04X}", chip.index()), style(Register::I)),
+            ]),
+            Line::from(vec![
+                Span::styled(
+                    format!("DT {:02X}", chip.timer().delay()),
+                    style(Register::DT),
+                ),
+                Span::raw("    "),
+                Span::styled(
+                    format!("ST {:02X}", chip.timer().sound()),
+                    style(Register::ST),
+                ),
+            ]),
+        ];
+        for row in 0..4 {
+            let mut spans = Vec::new();
+            for register in row * 4..row * 4 + 4 {
+                if register % 4 > 0 {
+                    spans.push(Span::raw(" "));
+                }
+                spans.push(Span::styled(
+                    format!("V{:X} {:02X}", register, chip.variable()[register]),
+                    style(Register::V(register as u8)),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(terminal::pane("registers")),
+            area,
+        );
+    }
+
+    fn draw_keypad(&self, frame: &mut Frame, chip: &CHIP8, area: Rect) {
+        let lines: Vec<Line> = LAYOUT
+            .iter()
+            .map(|keys| {
+                let mut spans = Vec::new();
+                for &key in keys {
+                    let style = if chip.keyboard().is_pressed(key) {
+                        Style::default().add_modifier(Modifier::REVERSED)
+                    } else {
+                        Style::default()
+                    };
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(format!("{:X}", key), style));
+                }
+                Line::from(spans)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(terminal::pane("keys")), area);
+    }
+
+    fn draw_watch_list(&self, frame: &mut Frame, lines: Vec<String>, area: Rect) {
+        let lines: Vec<Line> = lines.into_iter().map(Line::from).collect();
+        frame.render_widget(Paragraph::new(lines).block(terminal::pane("watch")), area);
+    }
+
+    fn draw_stack(&self, frame: &mut Frame, chip: &CHIP8, area: Rect) {
+        let lines: Vec<Line> = callstack::backtrace(chip, chip.program_counter())
+            .iter()
+            .map(|location| Line::from(location.to_string()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(terminal::pane("call stack")),
+            area,
+        );
+    }
+
+    /// Draws the latest events of the log, warnings and errors in bold and the details dimmed.
+    fn draw_log(&self, frame: &mut Frame, area: Rect) {
+        let events = self.log.len();
+        let shown = area.height as usize - 2;
+        let lines: Vec<Line> = self
+            .log
+            .range(events.saturating_sub(shown)..events)
+            .iter()
+            .map(|record| {
+                let style = match record.level {
+                    Level::ERROR | Level::WARN => bold(),
+                    Level::INFO => Style::default(),
+                    _ => Style::default().add_modifier(Modifier::DIM),
+                };
+                Line::styled(record.to_string(), style)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(terminal::pane("log")), area);
+    }
+}
+
+fn bold() -> Style {
+    Style::default().add_modifier(Modifier::BOLD)
+}
+
+/// Returns the background color for a level of the heatmap, from 1 for cold to 5 and up for hot,
+/// in the 256-color palette.
+fn heat_color(level: usize) -> Color {
+    const HEAT: [u8; 5] = [17, 22, 58, 130, 160];
+    Color::Indexed(HEAT[(level - 1).min(HEAT.len() - 1)])
+}
+
+/// Runs the full-screen debugger until the user quits. `title` is shown in the title bar, and
+/// `slots` holds the save states of the ROM. With a `recorder`, the game starts running right
+/// away and is recorded as a movie. The events of `log` are shown in a pane, and written once
+/// the terminal is back to normal.
+pub async fn run(
+    chip: &mut CHIP8,
+    title: &str,
+    symbols: Symbols,
+    slots: Slots,
+    recorder: Option<&mut Recorder>,
+    log: &Log,
+) -> io::Result<()> {
+    // Let go of after the full screen, which is dropped first.
+    let _hold = log.hold();
+    let mut full_screen = FullScreen::enable()?;
+    let mut input = terminal::spawn_reader();
+    let mut tui = Tui::new(title, chip, symbols, slots, recorder, log.clone());
+    if tui.recorder.is_some() {
+        tui.running = true;
+        tui.focus = Focus::Game;
+        tui.message = RECORDING_HELP.to_string();
+    }
+
+    let mut instructions = time::interval(INSTRUCTION_PERIOD);
+    let mut timers = time::interval(TIMER_PERIOD);
+    let mut frames = time::interval(FRAME_PERIOD);
+
+    loop {
+        tokio::select! {
+            _ = instructions.tick(), if tui.running && tui.rewinding.is_none() && tui.recorder.is_none() => {
+                if let Some(reason) = tui.debugger.run_step(chip) {
+                    tui.stop_or_exit(chip, reason);
+                }
+            }
+            _ = timers.tick() => {
+                tui.frame(chip);
+                tui.release_keys(chip);
+            }
+            _ = frames.tick() => {
+                tui.inspector.update(chip);
+                full_screen.draw(|frame| tui.draw(frame, chip))?;
+            }
+            key = input.recv() => {
+                match key {
+                    Some(key) if tui.handle_key(key, chip) => (),
+                    _ => return Ok(()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Just enough HTTP and WebSocket (RFC 6455) to serve a page and talk to it: reading a request
+//! and its body, answering it or upgrading it, and reading and writing messages.
+//!
+//! Messages from the browser are masked and may be split into fragments, which are put back
+//! together; messages to it are sent whole and unmasked.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::io;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+
+/// Appended to the key of a handshake before hashing it, as the protocol says.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The longest request head read, so that a client cannot make the server buffer forever.
+const MAX_HEAD: usize = 8 * 1024;
+
+/// The longest message read, far more than any the viewer sends.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// The head of an HTTP request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// The headers, with their names in lower case.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the value of the header `name`, given in lower case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the key of the WebSocket handshake if the request asks to upgrade to one.
+    pub fn websocket_key(&self) -> Option<&str> {
+        let upgrade = self.header("upgrade")?;
+        if !upgrade.eq_ignore_ascii_case("websocket") {
+            return None;
+        }
+        self.header("sec-websocket-key")
+    }
+}
+
+/// Reads the head of a request, or returns `None` if the client hung up first.
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<Request>> {
+    let mut lines = Vec::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        let read = (&mut *reader)
+            .take((MAX_HEAD - length) as u64)
+            .read_line(&mut line)
+            .await?;
+        if read == 0 {
+            if lines.is_empty() {
+                return Ok(None);
+            }
+            return Err(invalid("request head too long or cut short"));
+        }
+        length += read;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+    let mut lines = lines.into_iter();
+    let first = lines.next().unwrap_or_default();
+    let mut words = first.split(' ');
+    let (method, path) = match (words.next(), words.next(), words.next()) {
+        (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/") => {
+            (method.to_string(), path.to_string())
+        }
+        _ => return Err(invalid("malformed request line")),
+    };
+    let headers = lines
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) => Ok((name.trim().to_ascii_lowercase(), value.trim().to_string())),
+            None => Err(invalid("malformed header")),
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+    }))
+}
+
+/// Reads the body of `request`, as long as its `Content-Length` says, up to `max` bytes.
+pub async fn read_body<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    request: &Request,
+    max: usize,
+) -> io::Result<Vec<u8>> {
+    let length = match request.header("content-length") {
+        Some(length) => length
+            .parse()
+            .map_err(|_| invalid("malformed Content-Length"))?,
+        None => 0,
+    };
+    if length > max {
+        return Err(invalid("body too long"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+/// Writes a whole response with a body.
+pub async fn respond<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await
+}
+
+/// Accepts the WebSocket handshake with `key`, after which messages go both ways.
+pub async fn upgrade<W: AsyncWrite + Unpin>(writer: &mut W, key: &str) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Returns the answer to the key of a handshake.
+fn accept_key(key: &str) -> String {
+    let mut sha1 = sha1_smol::Sha1::new();
+    sha1.update(key.as_bytes());
+    sha1.update(GUID.as_bytes());
+    STANDARD.encode(sha1.digest().bytes())
+}
+
+/// A message of a WebSocket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close,
+}
+
+/// Reads the messages of a WebSocket.
+pub struct Reader<R> {
+    inner: R,
+    /// The opcode and the data of the fragments read so far of a message split into several.
+    fragments: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Reader {
+            inner,
+            fragments: None,
+        }
+    }
+
+    /// Reads the next message, or returns `None` once the other end hung up without closing.
+    pub async fn read(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            let mut head = [0; 2];
+            match self.inner.read_exact(&mut head).await {
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            }
+            let fin = head[0] & 0x80 != 0;
+            let opcode = head[0] & 0x0F;
+            let length = match head[1] & 0x7F {
+                126 => u64::from(self.inner.read_u16().await?),
+                127 => self.inner.read_u64().await?,
+                length => u64::from(length),
+            };
+            let read = self.fragments.as_ref().map_or(0, |(_, data)| data.len());
+            if length > (MAX_MESSAGE - read) as u64 {
+                return Err(invalid("message too long"));
+            }
+            let mut mask = [0; 4];
+            if head[1] & 0x80 != 0 {
+                self.inner.read_exact(&mut mask).await?;
+            }
+            let mut payload = vec![0; length as usize];
+            self.inner.read_exact(&mut payload).await?;
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+
+            // Control messages may come between the fragments of another.
+            let (opcode, payload) = match (opcode, self.fragments.take()) {
+                (CLOSE, _) => return Ok(Some(Message::Close)),
+                (PING, fragments) => {
+                    self.fragments = fragments;
+                    return Ok(Some(Message::Ping(payload)));
+                }
+                (PONG, fragments) => {
+                    self.fragments = fragments;
+                    return Ok(Some(Message::Pong(payload)));
+                }
+                (CONTINUATION, Some((opcode, mut data))) => {
+                    data.extend(payload);
+                    (opcode, data)
+                }
+                (TEXT | BINARY, None) => (opcode, payload),
+                _ => return Err(invalid("unexpected fragment")),
+            };
+            if !fin {
+                self.fragments = Some((opcode, payload));
+                continue;
+            }
+            return match opcode {
+                TEXT => String::from_utf8(payload)
+                    .map(|text| Some(Message::Text(text)))
+                    .map_err(|_| invalid("text message is not UTF-8")),
+                _ => Ok(Some(Message::Binary(payload))),
+            };
+        }
+    }
+}
+
+/// Writes `message` whole.
+pub async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &Message,
+) -> io::Result<()> {
+    let (opcode, payload) = match message {
+        Message::Text(text) => (TEXT, text.as_bytes()),
+        Message::Binary(data) => (BINARY, data.as_slice()),
+        Message::Ping(data) => (PING, data.as_slice()),
+        Message::Pong(data) => (PONG, data.as_slice()),
+        Message::Close => (CLOSE, &[][..]),
+    };
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_requests() {
+        let mut head: &[u8] =
+            b"GET /socket HTTP/1.1\r\nHost: localhost:8080\r\nUpgrade: websocket\r\n\
+            Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let request = read_request(&mut head).await.unwrap().unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.path.as_str()),
+            ("GET", "/socket")
+        );
+        assert_eq!(request.header("host"), Some("localhost:8080"));
+        assert_eq!(request.websocket_key(), Some("dGhlIHNhbXBsZSBub25jZQ=="));
+
+        let mut post: &[u8] = b"POST /api/load HTTP/1.1\r\nContent-Length: 3\r\n\r\nROM!";
+        let request = read_request(&mut post).await.unwrap().unwrap();
+        assert_eq!(read_body(&mut post, &request, 16).await.unwrap(), b"ROM");
+        assert!(read_body(&mut &b"ROM"[..], &request, 2).await.is_err());
+
+        let mut empty: &[u8] = b"";
+        assert_eq!(read_request(&mut empty).await.unwrap(), None);
+        let mut garbage: &[u8] = b"hello\r\n\r\n";
+        assert!(read_request(&mut garbage).await.is_err());
+    }
+
+    #[test]
+    fn answers_handshakes() {
+        // The example of RFC 6455.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_and_writes_messages() {
+        // "Hel" and "lo" in two masked fragments, with a ping between them, as in RFC 6455.
+        let mut reader = Reader::new(
+            &[
+                0x01, 0x83, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, //
+                0x89, 0x00, //
+                0x80, 0x82, 0x37, 0xfa, 0x21, 0x3d, 0x5b, 0x95, //
+                0x88, 0x00,
+            ][..],
+        );
+        assert_eq!(
+            reader.read().await.unwrap(),
+            Some(Message::Ping(Vec::new()))
+        );
+        assert_eq!(
+            reader.read().await.unwrap(),
+            Some(Message::Text("Hello".to_string()))
+        );
+        assert_eq!(reader.read().await.unwrap(), Some(Message::Close));
+        assert_eq!(reader.read().await.unwrap(), None);
+
+        let mut written = Vec::new();
+        write_message(&mut written, &Message::Binary(vec![7; 200]))
+            .await
+            .unwrap();
+        assert_eq!(written[..4], [0x82, 126, 0, 200]);
+        let mut reader = Reader::new(written.as_slice());
+        assert_eq!(
+            reader.read().await.unwrap(),
+            Some(Message::Binary(vec![7; 200]))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Running a CHIP-8 program on the original interpreter and on [`chip8_core`] side by side, and
+//! comparing the screens they leave, to check the high-level interpreter against the machine it
+//! imitates.
+//!
+//! The interpreter keeps its 64x32 screen in the last page of RAM, a bit per pixel, so the VIP's
+//! screen is read from there rather than from the picture, which shows it 4 lines to a row.
+
+use crate::{Config, Vip, ROM_SIZE};
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::emulator::System;
+use emu_core::{Emulator, Error};
+
+/// A CHIP-8 screen.
+pub type Screen = [[bool; WIDTH]; HEIGHT];
+
+/// The screens a program left on both machines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    /// The screen of the VIP, running the original interpreter.
+    pub vip: Screen,
+    /// The screen of [`chip8_core`].
+    pub chip8: Screen,
+}
+
+impl Comparison {
+    /// Returns how many pixels differ.
+    pub fn differences(&self) -> usize {
+        let rows = self.vip.iter().zip(&self.chip8);
+        rows.map(|(vip, chip8)| vip.iter().zip(chip8).filter(|(a, b)| a != b).count())
+            .sum()
+    }
+
+    /// Returns the screens as text, a line per row: `#` where both are lit and `.` where neither
+    /// is, and `V` or `C` where only the VIP or only [`chip8_core`] is.
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity((WIDTH + 1) * HEIGHT);
+        for (vip, chip8) in self.vip.iter().zip(&self.chip8) {
+            text.extend(vip.iter().zip(chip8).map(|pixels| match pixels {
+                (true, true) => '#',
+                (false, false) => '.',
+                (true, false) => 'V',
+                (false, true) => 'C',
+            }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Returns the screen the interpreter running on `vip` keeps in the last page of RAM.
+pub fn screen(vip: &Vip) -> Screen {
+    let page = &vip.ram()[vip.ram().len() - WIDTH * HEIGHT / 8..];
+    let mut screen = [[false; WIDTH]; HEIGHT];
+    for (y, row) in screen.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = page[y * WIDTH / 8 + x / 8] & 0x80 >> (x % 8) != 0;
+        }
+    }
+    screen
+}
+
+/// Runs `program` for `frames` frames on a 4KB VIP with `interpreter` and on the original
+/// machine of [`chip8_core`], and returns the screens it left. Fails if either machine cannot
+/// load or run it.
+pub fn compare(
+    interpreter: [u8; ROM_SIZE],
+    program: &[u8],
+    frames: usize,
+) -> Result<Comparison, Error> {
+    let mut vip = Vip::new(Config {
+        interpreter: Some(interpreter),
+        ..Config::default()
+    });
+    let mut chip8 = System::new();
+    vip.load_rom(program)?;
+    chip8.load_rom(program)?;
+    for _ in 0..frames {
+        vip.step_frame()?;
+        chip8.step_frame()?;
+    }
+    Ok(Comparison {
+        vip: screen(&vip),
+        chip8: *chip8.chip().display().screen(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares() {
+        let mut vip = Vip::new(Config::default());
+        let len = vip.ram().len();
+        vip.ram_mut()[len - 256] = 0x81;
+        vip.ram_mut()[len - 1] = 0x01;
+        let mut chip8 = [[false; WIDTH]; HEIGHT];
+        chip8[0][0] = true;
+        chip8[0][1] = true;
+        let comparison = Comparison {
+            vip: screen(&vip),
+            chip8,
+        };
+        assert_eq!(comparison.differences(), 3);
+        let text = comparison.text();
+        assert!(text.starts_with("#C.....V."));
+        assert!(text.ends_with(".V\n"));
+        assert_eq!(text.lines().count(), HEIGHT);
+    }
+
+    #[test]
+    fn fails_to_load() {
+        let comparison = compare([0; ROM_SIZE], &[0; 0xE00], 1);
+        assert!(matches!(comparison, Err(Error::Rom(_))));
+    }
+}
@@ -0,0 +1,624 @@
+//! The RCA COSMAC VIP, the machine CHIP-8 was written for, emulated a machine cycle at a time so
+//! that CHIP-8 programs run on the original interpreter as they did in 1977.
+//!
+//! The VIP is a [CDP1802](cdp1802) with 2KB or 4KB of RAM, a 512-byte monitor ROM, a
+//! [CDP1861](pixie) drawing a 64x128 picture from memory by DMA, a hexadecimal keypad and a tone
+//! switched by the processor's `Q` output. CHIP-8 itself is a 512-byte program loaded at address 0,
+//! which runs the CHIP-8 program after it at 0x200.
+//!
+//! Neither the monitor nor the interpreter comes with this crate, as RCA's copyright on them still
+//! stands: a [`Config`] takes images of them read from elsewhere. Without an interpreter, a ROM is
+//! an 1802 program loaded at address 0. Without a monitor, the machine starts at address 0 with
+//! `R1` pointing at the end of RAM, as the monitor leaves it.
+//!
+//! ```
+//! use cosmac_vip::{Config, Vip};
+//! use emu_core::Emulator;
+//!
+//! // 0x00: SEQ; IDL
+//! let mut vip = Vip::new(Config::default());
+//! vip.load_rom(&[0x7B, 0x00]).unwrap();
+//! vip.step_frame().unwrap();
+//! assert!(vip.cpu().q());
+//! let mut samples = Vec::new();
+//! vip.pull_audio(&mut samples);
+//! assert!(samples.iter().any(|&sample| sample != 0));
+//! ```
+
+pub mod compare;
+pub mod pixie;
+
+use crate::pixie::Pixie;
+use cdp1802::bus::{Bus, Dma};
+use cdp1802::{Cpu, CLOCKS_PER_CYCLE, STATE_SIZE};
+use chip8_core::emulator::BUTTONS;
+use chip8_core::state::rom_hash;
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+
+/// Clock cycles per second of the processor, from the VIP's 3.52128MHz crystal halved.
+pub const CLOCK_HZ: u64 = 1_760_640;
+
+/// The time a frame of the picture takes.
+pub const FRAME_PERIOD: Duration = Duration::from_nanos(
+    pixie::CYCLES_PER_FRAME as u64 * CLOCKS_PER_CYCLE * 1_000_000_000 / CLOCK_HZ,
+);
+
+/// Bytes in the monitor ROM and in the CHIP-8 interpreter.
+pub const ROM_SIZE: usize = 512;
+
+/// Where the interpreter loads CHIP-8 programs.
+pub const PROGRAM_START: usize = 0x200;
+
+/// Bytes at the end of RAM the interpreter keeps for its stack, its variables and the screen.
+pub const RESERVED: usize = 0x160;
+
+/// Samples per second of the tone.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Pitch of the tone. The VIP makes it with an RC oscillator, so this is only near the real one,
+/// which varied from machine to machine.
+pub const TONE_HZ: u32 = 1_400;
+
+/// Pixels in the framebuffer for each dot of the picture across, which is about as wide as a dot
+/// looked on a TV.
+pub const DOT_WIDTH: usize = 3;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"VIP\x01";
+
+/// Loudness of the tone.
+const AMPLITUDE: i16 = 0x1000;
+
+/// The color of a lit pixel.
+const ON: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+/// The color of a dark pixel.
+const OFF: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// What a machine is built with.
+#[derive(Clone)]
+pub struct Config {
+    /// Bytes of RAM, a power of two from 1KB to 32KB. The VIP came with 2KB and took 4KB on the
+    /// board.
+    pub ram: usize,
+    /// The monitor ROM, which runs first when present.
+    pub monitor: Option<[u8; ROM_SIZE]>,
+    /// The CHIP-8 interpreter, loaded at address 0 under every ROM when present.
+    pub interpreter: Option<[u8; ROM_SIZE]>,
+}
+
+impl Default for Config {
+    /// 4KB of RAM, with no monitor and no interpreter.
+    fn default() -> Self {
+        Config {
+            ram: 0x1000,
+            monitor: None,
+            interpreter: None,
+        }
+    }
+}
+
+/// Everything on the board but the processor.
+#[derive(Clone)]
+struct Board {
+    ram: Vec<u8>,
+    monitor: Option<[u8; ROM_SIZE]>,
+    /// Whether reads from the bottom of memory come from the monitor, as they do after a reset
+    /// until an address with A15 set is used.
+    monitor_at_zero: bool,
+    pixie: Pixie,
+    /// The key the keypad is asked about, by `OUT 2`.
+    key: u8,
+    keys: [bool; 16],
+}
+
+impl Board {
+    /// Returns the monitor byte at `address`, or the open bus if there is no monitor.
+    fn monitor(&self, address: u16) -> u8 {
+        self.monitor
+            .map_or(0xFF, |monitor| monitor[address as usize % ROM_SIZE])
+    }
+
+    /// Returns the RAM offset of `address`, which repeats over the bottom 32KB.
+    fn offset(&self, address: u16) -> usize {
+        address as usize & (self.ram.len() - 1)
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.monitor_at_zero = false;
+            self.monitor(address)
+        } else if self.monitor_at_zero {
+            self.monitor(address)
+        } else {
+            self.ram[self.offset(address)]
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address & 0x8000 != 0 {
+            self.monitor_at_zero = false;
+        } else {
+            let offset = self.offset(address);
+            self.ram[offset] = value;
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.pixie.on = true;
+        }
+        0
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.pixie.on = false,
+            2 => self.key = value & 0xF,
+            _ => {}
+        }
+    }
+
+    fn flag(&mut self, flag: u8) -> bool {
+        match flag {
+            1 => self.pixie.ef1(),
+            3 => self.keys[self.key as usize],
+            _ => false,
+        }
+    }
+
+    fn dma(&mut self) -> Option<Dma> {
+        self.pixie.dma()
+    }
+
+    fn dma_out(&mut self, value: u8) {
+        self.pixie.dma_out(value);
+    }
+
+    fn interrupt(&mut self) -> bool {
+        self.pixie.interrupt()
+    }
+}
+
+/// The tone, a square wave while `Q` is set.
+#[derive(Clone, Default)]
+struct Tone {
+    /// Clock cycles since the last sample, times [`SAMPLE_RATE`].
+    clocks: u64,
+    /// Where the wave is within a cycle, in [`SAMPLE_RATE`]ths.
+    phase: u32,
+    samples: Vec<i16>,
+}
+
+impl Tone {
+    /// Plays `cycles` machine cycles of the tone, sounding if `on`.
+    fn play(&mut self, cycles: u8, on: bool) {
+        self.clocks += u64::from(cycles) * CLOCKS_PER_CYCLE * u64::from(SAMPLE_RATE);
+        while self.clocks >= CLOCK_HZ {
+            self.clocks -= CLOCK_HZ;
+            self.phase = (self.phase + TONE_HZ) % SAMPLE_RATE;
+            let sample = match (on, self.phase < SAMPLE_RATE / 2) {
+                (false, _) => 0,
+                (true, true) => AMPLITUDE,
+                (true, false) => -AMPLITUDE,
+            };
+            self.samples.push(sample);
+        }
+    }
+}
+
+/// A COSMAC VIP, with the ROM it runs.
+#[derive(Clone)]
+pub struct Vip {
+    config: Config,
+    cpu: Cpu,
+    board: Board,
+    rom: Vec<u8>,
+    rom_hash: u64,
+    tone: Tone,
+    framebuffer: Vec<u8>,
+}
+
+impl Vip {
+    /// Creates the machine `config` describes, with no ROM loaded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.ram` is not a power of two from 1KB to 32KB.
+    pub fn new(config: Config) -> Self {
+        assert!(
+            config.ram.is_power_of_two() && (0x400..=0x8000).contains(&config.ram),
+            "RAM must be a power of two from 1KB to 32KB, not {} bytes",
+            config.ram
+        );
+        let board = Board {
+            ram: vec![0; config.ram],
+            monitor: config.monitor,
+            monitor_at_zero: false,
+            pixie: Pixie::default(),
+            key: 0,
+            keys: [false; 16],
+        };
+        let mut vip = Vip {
+            config,
+            cpu: Cpu::new(),
+            board,
+            rom: Vec::new(),
+            rom_hash: rom_hash(&[]),
+            tone: Tone::default(),
+            framebuffer: vec![0; pixie::WIDTH * DOT_WIDTH * pixie::HEIGHT * 4],
+        };
+        vip.start();
+        vip
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns the processor, to change it directly.
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// Returns RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.board.ram
+    }
+
+    /// Returns RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.board.ram
+    }
+
+    /// Returns whether the dot at `x` on line `y` of the last frame was lit, with `x` below 64 and
+    /// `y` below 128.
+    pub fn dot(&self, x: usize, y: usize) -> bool {
+        self.board.pixie.pixel(x, y)
+    }
+
+    /// Resets the processor and the devices, keeping RAM, as the reset switch does.
+    fn start(&mut self) {
+        self.cpu.reset();
+        self.board.pixie = Pixie::default();
+        self.board.key = 0;
+        self.board.monitor_at_zero = self.board.monitor.is_some();
+        if self.board.monitor.is_none() {
+            self.cpu.set_register(1, self.config.ram as u16 - 1);
+        }
+        self.tone = Tone::default();
+        self.draw();
+    }
+
+    /// Copies the last frame into the framebuffer.
+    fn draw(&mut self) {
+        let width = pixie::WIDTH * DOT_WIDTH;
+        for (i, pixel) in self.framebuffer.chunks_exact_mut(4).enumerate() {
+            let (x, y) = (i % width / DOT_WIDTH, i / width);
+            let on = self.board.pixie.pixel(x, y);
+            pixel.copy_from_slice(if on { &ON } else { &OFF });
+        }
+    }
+}
+
+impl Emulator for Vip {
+    fn name(&self) -> &'static str {
+        "COSMAC VIP"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: pixie::WIDTH * DOT_WIDTH,
+            height: pixie::HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Loads a CHIP-8 program at 0x200 under the interpreter, or without one, an 1802 program at
+    /// address 0, and resets the machine.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let (start, space) = match &self.config.interpreter {
+            Some(_) => (PROGRAM_START, self.config.ram - PROGRAM_START - RESERVED),
+            None => (0, self.config.ram),
+        };
+        if rom.is_empty() {
+            return Err(Error::Rom("the ROM is empty".to_string()));
+        }
+        if rom.len() > space {
+            return Err(Error::Rom(format!(
+                "the ROM is {} bytes, but only {} fit",
+                rom.len(),
+                space
+            )));
+        }
+        let ram = &mut self.board.ram;
+        ram.iter_mut().for_each(|byte| *byte = 0);
+        if let Some(interpreter) = &self.config.interpreter {
+            ram[..ROM_SIZE].copy_from_slice(interpreter);
+        }
+        ram[start..start + rom.len()].copy_from_slice(rom);
+        self.rom = rom.to_vec();
+        self.rom_hash = rom_hash(rom);
+        self.start();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        let rom = core::mem::take(&mut self.rom);
+        if rom.is_empty() {
+            self.board.ram.iter_mut().for_each(|byte| *byte = 0);
+            self.start();
+        } else if let Err(e) = self.load_rom(&rom) {
+            unreachable!("the ROM loaded before fails to load again: {}", e);
+        }
+    }
+
+    /// Runs the machine until the beam is back at the top of the picture.
+    fn step_frame(&mut self) -> Result<(), Error> {
+        loop {
+            let cycles = self.cpu.step(&mut self.board);
+            self.tone.play(cycles, self.cpu.q());
+            if self.board.pixie.advance(u32::from(cycles)) {
+                break;
+            }
+        }
+        self.draw();
+        Ok(())
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.tone.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if let Some(key) = self.board.keys.get_mut(button) {
+            *key = pressed;
+        }
+    }
+
+    /// Saves the processor, RAM and where the beam is, but not the picture, which is drawn again
+    /// within a frame.
+    fn save_state(&self) -> Vec<u8> {
+        let board = &self.board;
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(&board.ram);
+        state.extend_from_slice(&[
+            board.monitor_at_zero as u8,
+            board.pixie.on as u8,
+            board.key,
+            board.pixie.column as u8,
+        ]);
+        state.extend_from_slice(&board.pixie.cycle.to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let ram = self.config.ram;
+        if state.len() != MAGIC.len() + 8 + STATE_SIZE + ram + 8 || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this machine".to_string()));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(STATE_SIZE);
+        let (memory, rest) = rest.split_at(ram);
+        let cycle = u32::from_le_bytes(<[u8; 4]>::try_from(&rest[4..]).unwrap());
+        if cycle >= pixie::CYCLES_PER_FRAME || rest[2] > 0xF {
+            return Err(Error::State("the state is corrupt".to_string()));
+        }
+        self.cpu
+            .restore(<&[u8; STATE_SIZE]>::try_from(cpu).unwrap());
+        let board = &mut self.board;
+        board.ram.copy_from_slice(memory);
+        board.monitor_at_zero = rest[0] != 0;
+        board.pixie.on = rest[1] != 0;
+        board.key = rest[2];
+        board.pixie.column = rest[3] as usize;
+        board.pixie.cycle = cycle;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shows the last page of RAM, as the interpreter does: the main program turns the display on
+    /// and loops, and the interrupt routine at 0x40 points `R0` at the page and has each 8 bytes
+    /// drawn on 4 lines, moving `R0` back before the next, with `DEC` first for the last row.
+    fn display() -> Vec<u8> {
+        let mut program = vec![0xC4; 0x5C];
+        program[..0x10].copy_from_slice(&[
+            0xF8, 0x00, 0xB1, 0xF8, 0x40, 0xA1, // R1 = 0x0040
+            0xF8, 0x0E, 0xB2, 0xF8, 0xFF, 0xA2, // R2 = 0x0EFF
+            0xF8, 0x20, 0xA3, 0xD3, // R3 = 0x0020; SEP R3
+        ]);
+        // 0x20: SEX R2; INP 1; BR 0x22
+        program[0x20..0x24].copy_from_slice(&[0xE2, 0x69, 0x30, 0x22]);
+        program[0x3E..].copy_from_slice(&[
+            0x72, 0x70, // 0x3E: LDXA; RET
+            0x22, 0x78, 0x22, 0x52, // 0x40: save T and D on the stack
+            0xC4, 0xE2,
+            0xE2, // NOP; SEX R2; SEX R2, to start the loop 4 cycles before line 80
+            0xF8, 0x0F, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0x0F00
+            0x80, 0xE2, 0xE2, // 0x4D: GLO R0; the first line
+            0x20, 0xA0, 0xE2, // DEC R0; PLO R0; the line again
+            0x20, 0xA0, 0xE2, // and again
+            0x20, 0xA0, 0x3C, 0x4D, // and again; BN1 0x4D
+            0x30, 0x3E, // BR 0x3E
+        ]);
+        program
+    }
+
+    /// 0x00: OUT 2, 5; BN3 0x00; SEQ; IDL
+    const KEYPAD: [u8; 6] = [0x62, 0x05, 0x3E, 0x00, 0x7B, 0x00];
+
+    fn displaying() -> Vip {
+        let mut vip = Vip::new(Config::default());
+        vip.load_rom(&display()).unwrap();
+        for (i, byte) in vip.ram_mut()[0xF00..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        vip
+    }
+
+    #[test]
+    fn displays() {
+        let mut vip = displaying();
+        assert_eq!(vip.framebuffer().len(), vip.video().len());
+        for _ in 0..3 {
+            vip.step_frame().unwrap();
+        }
+        for y in 0..pixie::HEIGHT {
+            for x in 0..pixie::WIDTH {
+                let byte = (y / 4 * 8 + x / 8) as u8;
+                assert_eq!(vip.dot(x, y), byte & 0x80 >> (x % 8) != 0, "{}, {}", x, y);
+            }
+        }
+        // The byte 0x08 is first drawn on line 4, from x = 4.
+        let width = pixie::WIDTH * DOT_WIDTH;
+        let at = |x: usize, y: usize| &vip.framebuffer()[(y * width + x) * 4..][..4];
+        assert_eq!(at(4 * DOT_WIDTH, 4), ON);
+        assert_eq!(at(5 * DOT_WIDTH - 1, 7), ON);
+        assert_eq!(at(5 * DOT_WIDTH, 4), OFF);
+        assert_eq!(at(4 * DOT_WIDTH, 3), OFF);
+        // The interrupt routine returned to the main program with the stack as it was.
+        assert_eq!(vip.cpu().p(), 3);
+        assert_eq!(vip.cpu().register(2), 0x0EFF);
+    }
+
+    #[test]
+    fn runs_at_60_hz() {
+        let mut vip = displaying();
+        vip.step_frame().unwrap();
+        let cycles = vip.cpu().cycles();
+        vip.step_frame().unwrap();
+        let frame = vip.cpu().cycles() - cycles;
+        assert!((frame as i64 - i64::from(pixie::CYCLES_PER_FRAME)).abs() < 3);
+        assert_eq!(FRAME_PERIOD.as_micros(), 16_666);
+    }
+
+    #[test]
+    fn reads_the_keypad_and_sounds() {
+        let mut vip = Vip::new(Config::default());
+        vip.load_rom(&KEYPAD).unwrap();
+        let mut samples = Vec::new();
+        vip.set_button(4, true);
+        vip.set_button(99, true);
+        vip.step_frame().unwrap();
+        assert!(!vip.cpu().q());
+        vip.pull_audio(&mut samples);
+        assert!((799..=801).contains(&samples.len()));
+        assert!(samples.iter().all(|&sample| sample == 0));
+
+        vip.set_button(5, true);
+        vip.step_frame().unwrap();
+        assert!(vip.cpu().q());
+        assert!(vip.cpu().idle());
+        vip.pull_audio(&mut samples);
+        samples.clear();
+        vip.step_frame().unwrap();
+        vip.pull_audio(&mut samples);
+        assert!(samples.contains(&AMPLITUDE) && samples.contains(&-AMPLITUDE));
+        // About 1400Hz: 23 samples up, 11 or 12 at a time.
+        let rises = samples.windows(2).filter(|w| w[0] < w[1]).count();
+        assert!((22..=25).contains(&rises), "{}", rises);
+    }
+
+    #[test]
+    fn saves_and_loads_states() {
+        let mut vip = displaying();
+        vip.step_frame().unwrap();
+        let saved = vip.save_state();
+        let cpu = vip.cpu().clone();
+        vip.ram_mut()[0xF00] = 0xFF;
+        vip.step_frame().unwrap();
+        assert_ne!(vip.cpu(), &cpu);
+        vip.load_state(&saved).unwrap();
+        assert_eq!(vip.cpu(), &cpu);
+        assert_eq!(vip.ram()[0xF00], 0);
+        vip.step_frame().unwrap();
+        vip.step_frame().unwrap();
+        assert!(!vip.dot(0, 0));
+
+        assert!(matches!(vip.load_state(&[1, 2, 3]), Err(Error::State(_))));
+        let mut corrupt = saved.clone();
+        *corrupt.last_mut().unwrap() = 0xFF;
+        assert!(matches!(vip.load_state(&corrupt), Err(Error::State(_))));
+        vip.load_rom(&KEYPAD).unwrap();
+        assert!(matches!(vip.load_state(&saved), Err(Error::State(_))));
+    }
+
+    #[test]
+    fn loads_under_the_interpreter() {
+        // 0x000: an interpreter that stops at once.
+        let config = Config {
+            interpreter: Some([0x00; ROM_SIZE]),
+            ..Config::default()
+        };
+        let mut vip = Vip::new(config);
+        vip.load_rom(&[0x12, 0x00]).unwrap();
+        assert_eq!(vip.ram()[PROGRAM_START..][..3], [0x12, 0x00, 0x00]);
+        assert_eq!(vip.cpu().register(1), 0x0FFF);
+        vip.step_frame().unwrap();
+        assert_eq!(vip.cpu().program_counter(), 1);
+
+        let largest = vec![0x12; 0x1000 - PROGRAM_START - RESERVED];
+        vip.load_rom(&largest).unwrap();
+        let too_large = vec![0x12; largest.len() + 1];
+        assert!(matches!(vip.load_rom(&too_large), Err(Error::Rom(_))));
+        assert!(matches!(vip.load_rom(&[]), Err(Error::Rom(_))));
+        vip.reset();
+        assert_eq!(vip.ram()[0x200..0xEA0], largest[..]);
+    }
+
+    #[test]
+    fn starts_in_the_monitor() {
+        // 0x0000: LBR 0x8003; 0x8003: LDI 0x2A; PLO R7; LBR 0x0010
+        let mut monitor = [0x00; ROM_SIZE];
+        monitor[..9].copy_from_slice(&[0xC0, 0x80, 0x03, 0xF8, 0x2A, 0xA7, 0xC0, 0x00, 0x10]);
+        let mut vip = Vip::new(Config {
+            monitor: Some(monitor),
+            ..Config::default()
+        });
+        // 0x0000: SEQ, which the monitor hides; 0x0010: IDL
+        vip.load_rom(&[0x7B]).unwrap();
+        vip.step_frame().unwrap();
+        assert!(!vip.cpu().q());
+        assert_eq!(vip.cpu().register(7), 0x2A);
+        assert_eq!(vip.cpu().program_counter(), 0x11);
+        // RAM repeats up to 0x7FFF.
+        assert_eq!(vip.board.read(0x1000), 0x7B);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn needs_ram() {
+        Vip::new(Config {
+            ram: 3000,
+            ..Config::default()
+        });
+    }
+}
@@ -0,0 +1,186 @@
+//! The CDP1861 video display controller, known as the Pixie, which draws a TV picture from memory
+//! by DMA while the processor runs.
+//!
+//! A frame of the NTSC picture has 262 lines of 14 machine cycles each. Of those, the 128 lines
+//! from line 80 on show 64 pixels each, which the Pixie reads as 8 bytes from memory at `R0` by
+//! DMA at the start of the line. It asks for an interrupt 29 cycles before the first of them, so
+//! that the program can point `R0` at what to show, and sets `EF1` during the 4 lines before the
+//! picture starts and the last 4 lines of it, so that the program can tell where the beam is.
+//!
+//! The Pixie is turned on by `INP 1` and off by `OUT 1`. While off, it neither interrupts nor
+//! reads memory, and the picture is black.
+
+use cdp1802::bus::Dma;
+
+/// Machine cycles in a line of the picture.
+pub const CYCLES_PER_LINE: u32 = 14;
+
+/// Lines in a frame.
+pub const LINES: u32 = 262;
+
+/// Machine cycles in a frame.
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES;
+
+/// The first line showing pixels.
+pub const FIRST_LINE: u32 = 80;
+
+/// Lines showing pixels.
+pub const HEIGHT: usize = 128;
+
+/// Pixels in a line.
+pub const WIDTH: usize = 64;
+
+/// Bytes read by DMA for a line.
+const BYTES_PER_LINE: usize = WIDTH / 8;
+
+/// Machine cycles between the interrupt and the first DMA of a frame.
+const INTERRUPT_LEAD: u32 = 29;
+
+/// Lines before the picture, and at its end, during which `EF1` is set.
+const EF1_LINES: u32 = 4;
+
+/// The video display controller.
+#[derive(Clone)]
+pub struct Pixie {
+    /// Whether the display is on.
+    pub(crate) on: bool,
+    /// The machine cycle within the frame.
+    pub(crate) cycle: u32,
+    /// The bytes of the current line read so far.
+    pub(crate) column: usize,
+    /// The picture being drawn, a byte per 8 pixels.
+    drawing: [[u8; BYTES_PER_LINE]; HEIGHT],
+    /// The picture of the last whole frame.
+    shown: [[u8; BYTES_PER_LINE]; HEIGHT],
+}
+
+impl Default for Pixie {
+    fn default() -> Self {
+        Pixie {
+            on: false,
+            cycle: 0,
+            column: 0,
+            drawing: [[0; BYTES_PER_LINE]; HEIGHT],
+            shown: [[0; BYTES_PER_LINE]; HEIGHT],
+        }
+    }
+}
+
+impl Pixie {
+    /// Returns the line the beam is on.
+    fn line(&self) -> u32 {
+        self.cycle / CYCLES_PER_LINE
+    }
+
+    /// Returns the line of the picture the beam is on, if it is on one.
+    fn picture_line(&self) -> Option<usize> {
+        let line = self.line().checked_sub(FIRST_LINE)? as usize;
+        (line < HEIGHT).then_some(line)
+    }
+
+    /// Moves the beam on by `cycles` machine cycles. Returns `true` if a frame ended, when the
+    /// picture drawn during it is shown.
+    pub fn advance(&mut self, cycles: u32) -> bool {
+        let line = self.line();
+        self.cycle += cycles;
+        let ended = self.cycle >= CYCLES_PER_FRAME;
+        if ended {
+            self.cycle -= CYCLES_PER_FRAME;
+            self.shown = self.drawing;
+            self.drawing = [[0; BYTES_PER_LINE]; HEIGHT];
+        }
+        if self.line() != line {
+            self.column = 0;
+        }
+        ended
+    }
+
+    /// Returns the DMA transfer the Pixie asks for: a byte of the line the beam is on, until it
+    /// has all 8.
+    pub fn dma(&self) -> Option<Dma> {
+        let reading = self.on && self.picture_line().is_some() && self.column < BYTES_PER_LINE;
+        reading.then_some(Dma::Out)
+    }
+
+    /// Takes the next byte of the line the beam is on.
+    pub fn dma_out(&mut self, value: u8) {
+        if let Some(line) = self.picture_line() {
+            if let Some(byte) = self.drawing[line].get_mut(self.column) {
+                *byte = value;
+            }
+        }
+        self.column += 1;
+    }
+
+    /// Returns whether the Pixie asks for an interrupt, during the cycles before the picture.
+    pub fn interrupt(&self) -> bool {
+        let first_dma = FIRST_LINE * CYCLES_PER_LINE;
+        self.on && (first_dma - INTERRUPT_LEAD..first_dma).contains(&self.cycle)
+    }
+
+    /// Returns whether `EF1` is set, during the 4 lines before the picture and its last 4.
+    pub fn ef1(&self) -> bool {
+        let line = self.line();
+        let end = FIRST_LINE + HEIGHT as u32;
+        self.on
+            && ((FIRST_LINE - EF1_LINES..FIRST_LINE).contains(&line)
+                || (end - EF1_LINES..end).contains(&line))
+    }
+
+    /// Returns whether the pixel at `x` on line `y` of the last frame was lit.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.shown[y][x / 8] & 0x80 >> (x % 8) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing() {
+        let mut pixie = Pixie::default();
+        assert_eq!(pixie.dma(), None);
+        assert!(!pixie.interrupt());
+        pixie.on = true;
+
+        // The interrupt, 29 cycles before the first DMA, and EF1 4 lines before it.
+        pixie.advance(FIRST_LINE * CYCLES_PER_LINE - 30);
+        assert!(!pixie.interrupt());
+        assert!(pixie.ef1());
+        pixie.advance(1);
+        assert!(pixie.interrupt());
+        assert_eq!(pixie.dma(), None);
+        pixie.advance(29);
+        assert!(!pixie.interrupt());
+        assert!(!pixie.ef1());
+
+        // Every line of the picture is 8 bytes.
+        for value in 0..8 {
+            assert_eq!(pixie.dma(), Some(Dma::Out));
+            pixie.dma_out(0x80 >> value);
+            pixie.advance(1);
+        }
+        assert_eq!(pixie.dma(), None);
+        pixie.advance(CYCLES_PER_LINE - 8);
+        assert_eq!(pixie.dma(), Some(Dma::Out));
+
+        pixie.advance(CYCLES_PER_LINE * (HEIGHT as u32 - 6));
+        assert!(!pixie.ef1());
+        pixie.advance(CYCLES_PER_LINE);
+        assert!(pixie.ef1());
+        pixie.advance(CYCLES_PER_LINE * 4);
+        assert!(!pixie.ef1());
+        assert_eq!(pixie.dma(), None);
+
+        // The picture shows once the frame ends.
+        assert!(!pixie.pixel(0, 0));
+        let left = CYCLES_PER_FRAME - pixie.cycle;
+        assert!(!pixie.advance(left - 1));
+        assert!(pixie.advance(1));
+        assert_eq!(pixie.cycle, 0);
+        assert!((0..8).all(|x| pixie.pixel(x * 9, 0)));
+        assert!(!pixie.pixel(1, 0));
+        assert!(!pixie.pixel(0, 1));
+    }
+}
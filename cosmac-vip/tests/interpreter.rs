@@ -0,0 +1,46 @@
+//! Runs chip8-core's test ROMs on the original interpreter and on chip8-core, and checks that they
+//! leave the same screens.
+//!
+//! The interpreter is not part of this repository, so the test is ignored unless asked for, and then
+//! needs an image of it:
+//!
+//! ```text
+//! COSMAC_VIP_INTERPRETER=chip8.bin cargo test -p cosmac-vip --test interpreter -- --ignored
+//! ```
+
+use chip8_core::asm;
+use cosmac_vip::compare;
+use cosmac_vip::ROM_SIZE;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The image of the interpreter to run.
+const INTERPRETER: &str = "COSMAC_VIP_INTERPRETER";
+
+/// The ROMs whose screen settles: the others animate, or draw random numbers.
+const ROMS: [&str; 4] = ["collision", "font", "quirks", "wrap"];
+
+/// Frames each ROM runs for, long enough for both machines to finish drawing.
+const FRAMES: usize = 240;
+
+#[test]
+#[ignore = "needs $COSMAC_VIP_INTERPRETER"]
+fn matches_chip8_core() {
+    let path = env::var_os(INTERPRETER).unwrap_or_else(|| panic!("{} is not set", INTERPRETER));
+    let image = fs::read(&path).expect("the interpreter");
+    let interpreter = <[u8; ROM_SIZE]>::try_from(&image[..]).expect("a 512-byte interpreter");
+
+    let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../chip8-core/tests/roms");
+    let mut failures = Vec::new();
+    for name in &ROMS {
+        let source = fs::read_to_string(roms.join(format!("{}.8o", name))).unwrap();
+        let program = asm::assemble(&source).unwrap();
+        let comparison = compare::compare(interpreter, &program.bytes, FRAMES).unwrap();
+        if comparison.differences() != 0 {
+            failures.push(format!("{}:\n{}", name, comparison.text()));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
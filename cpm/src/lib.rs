@@ -0,0 +1,348 @@
+//! Just enough of CP/M to run the diagnostic programs of the 8080 and the Z80, such as CPUDIAG,
+//! 8080EXM and ZEXALL, on the processors of the [`i8080`] and [`z80`] crates, without a machine
+//! around them.
+//!
+//! CP/M loads a `.COM` program at 0x0100 and runs it with 64KB of RAM to itself. The program
+//! calls the BDOS, the operating system, at 0x0005 with a function number in `C`, and ends by
+//! jumping to 0x0000, the warm boot, or by returning to it. [`Machine`] gives it RAM, and of the
+//! BDOS only what the diagnostics call:
+//!
+//! | `C` | Function                                                  |
+//! |-----|-----------------------------------------------------------|
+//! | 0   | System reset, which ends the program as the warm boot does |
+//! | 2   | Console output of the character in `E`                    |
+//! | 9   | Console output of the string at `DE`, ending in `$`       |
+//!
+//! The address at 0x0006, the top of the memory programs may use, is [`TOP`], where the stack
+//! starts.
+//!
+//! ```
+//! use cpm::Machine;
+//!
+//! #[rustfmt::skip]
+//! let program = [
+//!     0x0E, 0x09,       // MVI C, 9
+//!     0x11, 0x08, 0x01, // LXI D, 0x0108
+//!     0xCD, 0x05, 0x00, // CALL 0x0005
+//!     b'O', b'K', b'$',
+//! ];
+//! let mut machine = Machine::<i8080::Cpu>::new(&program).unwrap();
+//! // Runs into the string after the call, whose bytes are harmless, then into zeros, NOPs, until
+//! // it wraps around to the warm boot.
+//! assert_eq!(machine.run().unwrap(), "OK");
+//! ```
+
+use thiserror::Error;
+
+/// Where CP/M loads programs.
+pub const START: u16 = 0x0100;
+
+/// Where programs call the BDOS.
+pub const BDOS: u16 = 0x0005;
+
+/// The top of the memory programs may use, below the BDOS, and where the stack starts.
+pub const TOP: u16 = 0xF000;
+
+/// The address of the warm boot, which ends the program.
+const WARM_BOOT: u16 = 0x0000;
+
+/// Why a program could not be run to its end.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum Error {
+    /// The program does not fit between [`START`] and [`TOP`].
+    #[error("a program of {0} bytes does not fit in the memory of CP/M")]
+    TooLarge(usize),
+    /// The program called a function of the BDOS that is not emulated.
+    #[error("BDOS function {0} is not emulated")]
+    Unsupported(u8),
+    /// The processor halted, which it would stay forever with nothing to interrupt it.
+    #[error("the processor halted at {0:04X}")]
+    Halted(u16),
+}
+
+/// The processors a program can run on: that of [`i8080`] and that of [`z80`].
+pub trait Processor {
+    /// Creates the processor as it is turned on.
+    fn new() -> Self;
+
+    /// Runs the next instruction and returns the clock cycles it took.
+    fn step(&mut self, ram: &mut Ram) -> u64;
+
+    /// Returns the address of the next instruction.
+    fn program_counter(&self) -> u16;
+
+    /// Sets the address of the next instruction.
+    fn set_program_counter(&mut self, address: u16);
+
+    /// Sets the stack pointer.
+    fn set_stack_pointer(&mut self, address: u16);
+
+    /// Returns `C`, which holds the function of a call to the BDOS.
+    fn c(&self) -> u8;
+
+    /// Returns `DE`, which holds the argument of a call to the BDOS.
+    fn de(&self) -> u16;
+
+    /// Returns whether the processor is halted.
+    fn halted(&self) -> bool;
+
+    /// Returns the clock cycles run since it was turned on.
+    fn cycles(&self) -> u64;
+}
+
+impl Processor for i8080::Cpu {
+    fn new() -> Self {
+        i8080::Cpu::new()
+    }
+
+    fn step(&mut self, ram: &mut Ram) -> u64 {
+        u64::from(i8080::Cpu::step(self, ram))
+    }
+
+    fn program_counter(&self) -> u16 {
+        i8080::Cpu::program_counter(self)
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        i8080::Cpu::set_program_counter(self, address);
+    }
+
+    fn set_stack_pointer(&mut self, address: u16) {
+        self.set_pair(i8080::Pair::SP, address);
+    }
+
+    fn c(&self) -> u8 {
+        self.register(i8080::Register::C)
+    }
+
+    fn de(&self) -> u16 {
+        self.pair(i8080::Pair::DE)
+    }
+
+    fn halted(&self) -> bool {
+        i8080::Cpu::halted(self)
+    }
+
+    fn cycles(&self) -> u64 {
+        i8080::Cpu::cycles(self)
+    }
+}
+
+impl Processor for z80::Cpu {
+    fn new() -> Self {
+        z80::Cpu::new()
+    }
+
+    fn step(&mut self, ram: &mut Ram) -> u64 {
+        u64::from(z80::Cpu::step(self, ram))
+    }
+
+    fn program_counter(&self) -> u16 {
+        z80::Cpu::program_counter(self)
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        z80::Cpu::set_program_counter(self, address);
+    }
+
+    fn set_stack_pointer(&mut self, address: u16) {
+        self.set_pair(z80::Pair::SP, address);
+    }
+
+    fn c(&self) -> u8 {
+        self.register(z80::Register::C)
+    }
+
+    fn de(&self) -> u16 {
+        self.pair(z80::Pair::DE)
+    }
+
+    fn halted(&self) -> bool {
+        z80::Cpu::halted(self)
+    }
+
+    fn cycles(&self) -> u64 {
+        z80::Cpu::cycles(self)
+    }
+}
+
+/// The 64KB of RAM of the machine, with no devices on the ports.
+#[derive(Clone)]
+pub struct Ram(pub Vec<u8>);
+
+impl i8080::bus::Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[usize::from(address)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[usize::from(address)] = value;
+    }
+}
+
+impl z80::bus::Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[usize::from(address)]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[usize::from(address)] = value;
+    }
+}
+
+/// A processor running a CP/M program.
+#[derive(Clone)]
+pub struct Machine<P> {
+    cpu: P,
+    ram: Ram,
+    /// What the program printed and was not yet taken.
+    output: String,
+    instructions: u64,
+}
+
+impl<P: Processor> Machine<P> {
+    /// Loads `program`, a `.COM` file, as CP/M does, ready to run.
+    pub fn new(program: &[u8]) -> Result<Self, Error> {
+        if program.len() > usize::from(TOP - START) {
+            return Err(Error::TooLarge(program.len()));
+        }
+        let mut ram = vec![0; 0x10000];
+        let start = usize::from(START);
+        ram[start..start + program.len()].copy_from_slice(program);
+        // RET from the BDOS, whose JMP would hold the top of memory at 0x0006.
+        ram[usize::from(BDOS)] = 0xC9;
+        ram[usize::from(BDOS) + 1..][..2].copy_from_slice(&TOP.to_le_bytes());
+        let mut cpu = P::new();
+        cpu.set_program_counter(START);
+        // The stack holds 0, for the program to return to the warm boot.
+        cpu.set_stack_pointer(TOP);
+        Ok(Machine {
+            cpu,
+            ram: Ram(ram),
+            output: String::new(),
+            instructions: 0,
+        })
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &P {
+        &self.cpu
+    }
+
+    /// Returns RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram.0
+    }
+
+    /// Returns the instructions run.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Returns and forgets what the program printed since the last call, to show it as it goes.
+    pub fn take_output(&mut self) -> String {
+        core::mem::take(&mut self.output)
+    }
+
+    /// Runs the next instruction, after the BDOS function the program calls if it calls one.
+    /// Returns `false` once the program has ended, without running anything.
+    pub fn step(&mut self) -> Result<bool, Error> {
+        let pc = self.cpu.program_counter();
+        if pc == WARM_BOOT {
+            return Ok(false);
+        }
+        if pc == BDOS {
+            match self.cpu.c() {
+                0 => return Ok(false),
+                2 => self.output.push(self.cpu.de() as u8 as char),
+                9 => {
+                    let text = self.ram.0[usize::from(self.cpu.de())..]
+                        .split(|&byte| byte == b'$')
+                        .next()
+                        .unwrap_or(&[]);
+                    self.output.extend(text.iter().map(|&byte| byte as char));
+                }
+                function => return Err(Error::Unsupported(function)),
+            }
+        }
+        if self.cpu.halted() {
+            return Err(Error::Halted(pc.wrapping_sub(1)));
+        }
+        self.cpu.step(&mut self.ram);
+        self.instructions += 1;
+        Ok(true)
+    }
+
+    /// Runs the program to its end, and returns what it printed since output was last taken.
+    pub fn run(&mut self) -> Result<String, Error> {
+        while self.step()? {}
+        Ok(self.take_output())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prints `H`, then `i!`, and returns: MVI C, 2; MVI E, 'H'; CALL 5; MVI C, 9; LXI D, 0x0110;
+    /// CALL 5; RET, then the string at 0x0110.
+    #[rustfmt::skip]
+    const HELLO: &[u8] = &[
+        0x0E, 0x02, 0x1E, b'H', 0xCD, 0x05, 0x00,
+        0x0E, 0x09, 0x11, 0x10, 0x01, 0xCD, 0x05, 0x00,
+        0xC9,
+        b'i', b'!', b'$', b'?',
+    ];
+
+    /// Checks the decimal adjustment both processors share, printing `OK` or `ERROR`: MVI A, 0x15;
+    /// ADI 0x27; DAA; CPI 0x42; MVI C, 9; LXI D, OK; JZ print; LXI D, ERROR; print: CALL 5; MVI C,
+    /// 0; CALL 5.
+    #[rustfmt::skip]
+    const DAA: &[u8] = &[
+        0x3E, 0x15, 0xC6, 0x27, 0x27, 0xFE, 0x42,
+        0x0E, 0x09, 0x11, 0x20, 0x01, 0xCA, 0x12, 0x01, 0x11, 0x23, 0x01,
+        0xCD, 0x05, 0x00, 0x0E, 0x00, 0xCD, 0x05, 0x00,
+        0, 0, 0, 0, 0, 0,
+        b'O', b'K', b'$', b'E', b'R', b'R', b'O', b'R', b'$',
+    ];
+
+    fn run<P: Processor>(program: &[u8]) -> Result<String, Error> {
+        Machine::<P>::new(program)?.run()
+    }
+
+    #[test]
+    fn prints_and_returns_to_the_warm_boot() {
+        assert_eq!(run::<i8080::Cpu>(HELLO).unwrap(), "Hi!");
+        assert_eq!(run::<z80::Cpu>(HELLO).unwrap(), "Hi!");
+    }
+
+    #[test]
+    fn checks_the_processors() {
+        assert_eq!(run::<i8080::Cpu>(DAA).unwrap(), "OK");
+        assert_eq!(run::<z80::Cpu>(DAA).unwrap(), "OK");
+    }
+
+    #[test]
+    fn takes_output_as_it_goes() {
+        let mut machine = Machine::<z80::Cpu>::new(HELLO).unwrap();
+        while machine.take_output().is_empty() {
+            assert!(machine.step().unwrap());
+        }
+        assert_eq!(machine.run().unwrap(), "i!");
+        assert!(!machine.step().unwrap());
+        assert_eq!(machine.instructions(), 9);
+    }
+
+    #[test]
+    fn stops_on_what_it_cannot_run() {
+        // MVI C, 7; CALL 5
+        let unsupported = [0x0E, 0x07, 0xCD, 0x05, 0x00];
+        assert_eq!(run::<i8080::Cpu>(&unsupported), Err(Error::Unsupported(7)));
+        // NOP; HLT
+        assert_eq!(run::<z80::Cpu>(&[0x00, 0x76]), Err(Error::Halted(0x0101)));
+        assert_eq!(
+            run::<i8080::Cpu>(&vec![0; 0xF000]),
+            Err(Error::TooLarge(0xF000))
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Running a CHIP-8 program on CHIPOS and on [`chip8_core`] side by side, and comparing the
+//! screens they leave, to tell which of a program's behaviors are those of the DREAM rather than
+//! of the VIP that [`chip8_core`] imitates.
+//!
+//! CHIPOS keeps its 64x32 screen in the display page, which is also the picture, so the DREAM's
+//! screen is read from there.
+
+use crate::{Config, Dream, CHIPOS_SIZE, DISPLAY};
+use chip8_core::display::{HEIGHT, WIDTH};
+use chip8_core::emulator::System;
+use emu_core::{Emulator, Error};
+
+/// A CHIP-8 screen.
+pub type Screen = [[bool; WIDTH]; HEIGHT];
+
+/// The screens a program left on both machines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comparison {
+    /// The screen of the DREAM, running CHIPOS.
+    pub dream: Screen,
+    /// The screen of [`chip8_core`].
+    pub chip8: Screen,
+}
+
+impl Comparison {
+    /// Returns how many pixels differ.
+    pub fn differences(&self) -> usize {
+        let rows = self.dream.iter().zip(&self.chip8);
+        rows.map(|(dream, chip8)| dream.iter().zip(chip8).filter(|(a, b)| a != b).count())
+            .sum()
+    }
+
+    /// Returns the screens as text, a line per row: `#` where both are lit and `.` where neither
+    /// is, and `D` or `C` where only the DREAM or only [`chip8_core`] is.
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity((WIDTH + 1) * HEIGHT);
+        for (dream, chip8) in self.dream.iter().zip(&self.chip8) {
+            text.extend(dream.iter().zip(chip8).map(|pixels| match pixels {
+                (true, true) => '#',
+                (false, false) => '.',
+                (true, false) => 'D',
+                (false, true) => 'C',
+            }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Returns the screen in the display page of `dream`.
+pub fn screen(dream: &Dream) -> Screen {
+    let page = &dream.ram()[DISPLAY..DISPLAY + WIDTH * HEIGHT / 8];
+    let mut screen = [[false; WIDTH]; HEIGHT];
+    for (y, row) in screen.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = page[y * WIDTH / 8 + x / 8] & 0x80 >> (x % 8) != 0;
+        }
+    }
+    screen
+}
+
+/// Runs `program` for `frames` frames on a 4KB DREAM with `chipos` and on the original machine
+/// of [`chip8_core`], and returns the screens it left. Fails if either machine cannot load or
+/// run it.
+///
+/// The DREAM's frames are a 50th of a second and [`chip8_core`]'s a 60th, so programs that time
+/// themselves should be given enough frames to finish on both.
+pub fn compare(
+    chipos: [u8; CHIPOS_SIZE],
+    program: &[u8],
+    frames: usize,
+) -> Result<Comparison, Error> {
+    let mut dream = Dream::new(Config {
+        chipos: Some(chipos),
+        ..Config::default()
+    });
+    let mut chip8 = System::new();
+    dream.load_rom(program)?;
+    chip8.load_rom(program)?;
+    for _ in 0..frames {
+        dream.step_frame()?;
+        chip8.step_frame()?;
+    }
+    Ok(Comparison {
+        dream: screen(&dream),
+        chip8: *chip8.chip().display().screen(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares() {
+        let mut dream = Dream::new(Config::default());
+        dream.ram_mut()[DISPLAY] = 0x81;
+        dream.ram_mut()[DISPLAY + 0xFF] = 0x01;
+        let mut chip8 = [[false; WIDTH]; HEIGHT];
+        chip8[0][0] = true;
+        chip8[0][1] = true;
+        let comparison = Comparison {
+            dream: screen(&dream),
+            chip8,
+        };
+        assert_eq!(comparison.differences(), 3);
+        let text = comparison.text();
+        assert!(text.starts_with("#C.....D."));
+        assert!(text.ends_with(".D\n"));
+        assert_eq!(text.lines().count(), HEIGHT);
+    }
+
+    #[test]
+    fn fails_to_load() {
+        let comparison = compare([0; CHIPOS_SIZE], &[0; 0xE01], 1);
+        assert!(matches!(comparison, Err(Error::Rom(_))));
+    }
+}
@@ -0,0 +1,651 @@
+//! The DREAM 6800, Michael Bauer's home computer of 1979 that brought CHIP-8 to the Motorola 6800,
+//! emulated so that CHIP-8 programs run on its interpreter, CHIPOS, as they did on the machine.
+//!
+//! The DREAM is a [6800](m6800) with RAM from address 0, CHIPOS in 1KB of ROM at 0xC000, and an
+//! [MC6821](pia) wiring it to a hexadecimal keypad, a tone and the TV. The picture, 64x32 dots, is
+//! read by DMA from the page at 0x0100 while the processor is held, and each frame ends with a
+//! pulse on `CB1`, from which CHIPOS counts its timers. CHIP-8 programs are loaded at 0x0200, and
+//! started at CHIPOS's interpreter at 0xC000 as the monitor would.
+//!
+//! CHIPOS does not come with this crate: a [`Config`] takes an image of it read from elsewhere.
+//! Without it, a ROM is a 6800 program loaded at address 0, which the machine starts at with the
+//! stack pointer at the end of RAM.
+//!
+//! [`compare`] runs a CHIP-8 program on CHIPOS and on [`chip8_core`] to check one against the
+//! other.
+//!
+//! ```
+//! use dream6800::{Config, Dream};
+//! use emu_core::Emulator;
+//!
+//! // 0x00: LDAA #$38; STAA $8013, setting CB2; WAI
+//! let mut dream = Dream::new(Config::default());
+//! dream.load_rom(&[0x86, 0x38, 0xB7, 0x80, 0x13, 0x3E]).unwrap();
+//! dream.step_frame().unwrap();
+//! assert!(dream.cpu().waiting());
+//! let mut samples = Vec::new();
+//! dream.pull_audio(&mut samples);
+//! assert!(samples.iter().any(|&sample| sample != 0));
+//! ```
+
+pub mod compare;
+pub mod pia;
+
+use crate::pia::Pia;
+use chip8_core::emulator::BUTTONS;
+use chip8_core::state::rom_hash;
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+use m6800::bus::Bus;
+use m6800::{Cpu, STATE_SIZE};
+
+/// Clock cycles per second of the processor.
+pub const CLOCK_HZ: u64 = 1_000_000;
+
+/// Clock cycles in a line of the picture, of 64µs.
+pub const CYCLES_PER_LINE: u32 = 64;
+
+/// Lines in a frame of the PAL picture.
+pub const LINES: u32 = 312;
+
+/// Clock cycles in a frame.
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES;
+
+/// Clock cycles of a frame during which DMA holds the processor, the 128 lines showing dots.
+pub const DMA_CYCLES: u32 = CYCLES_PER_LINE * 128;
+
+/// The time a frame of the picture takes, about a 50th of a second.
+pub const FRAME_PERIOD: Duration =
+    Duration::from_nanos(CYCLES_PER_FRAME as u64 * 1_000_000_000 / CLOCK_HZ);
+
+/// Dots across the picture.
+pub const WIDTH: usize = 64;
+
+/// Dots down the picture.
+pub const HEIGHT: usize = 32;
+
+/// Where the picture is read from, a bit per dot.
+pub const DISPLAY: usize = 0x0100;
+
+/// Where CHIPOS loads CHIP-8 programs.
+pub const PROGRAM_START: usize = 0x0200;
+
+/// Bytes in CHIPOS.
+pub const CHIPOS_SIZE: usize = 0x400;
+
+/// Where CHIPOS is, repeated up to 0xFFFF so that it holds the vectors, and where its CHIP-8
+/// interpreter starts.
+pub const CHIPOS_START: u16 = 0xC000;
+
+/// Where the PIA is, repeated every 4 bytes from 0x8000 to 0xBFFF.
+pub const PIA_START: u16 = 0x8010;
+
+/// Samples per second of the tone.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Pitch of the tone. The DREAM makes it with an oscillator gated by `CB2`, so this is only near
+/// the real one.
+pub const TONE_HZ: u32 = 1_000;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"DRM\x01";
+
+/// Loudness of the tone.
+const AMPLITUDE: i16 = 0x1000;
+
+/// The color of a lit dot.
+const ON: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+/// The color of a dark dot.
+const OFF: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// What a machine is built with.
+#[derive(Clone)]
+pub struct Config {
+    /// Bytes of RAM, a power of two from 1KB to 32KB. The DREAM came with 1KB and took more.
+    pub ram: usize,
+    /// CHIPOS, whose monitor runs on reset when present.
+    pub chipos: Option<[u8; CHIPOS_SIZE]>,
+}
+
+impl Default for Config {
+    /// 4KB of RAM, without CHIPOS.
+    fn default() -> Self {
+        Config {
+            ram: 0x1000,
+            chipos: None,
+        }
+    }
+}
+
+/// Everything on the board but the processor.
+#[derive(Clone)]
+struct Board {
+    ram: Vec<u8>,
+    chipos: Option<[u8; CHIPOS_SIZE]>,
+    pia: Pia,
+    keys: [bool; 16],
+}
+
+impl Board {
+    /// Returns the levels of the lines of port A, where the keypad is: the columns are lines 0 to
+    /// 3 and the rows lines 4 to 7, and a key held connects its row and its column, so that either
+    /// pulls the other low.
+    fn keypad(&self) -> u8 {
+        let mut lines = self.pia.port_a();
+        for key in (0..16).filter(|&key| self.keys[key]) {
+            let lines_of_key = 1 << (4 + key / 4) | 1 << (key % 4);
+            if lines & lines_of_key != lines_of_key {
+                lines &= !lines_of_key;
+            }
+        }
+        lines
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.ram[address as usize & (self.ram.len() - 1)],
+            0x8000..=0xBFFF => {
+                let lines_a = self.keypad();
+                let lines_b = self.pia.port_b();
+                self.pia.read(address, lines_a, lines_b)
+            }
+            _ => self
+                .chipos
+                .map_or(0xFF, |chipos| chipos[address as usize % CHIPOS_SIZE]),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                let offset = address as usize & (self.ram.len() - 1);
+                self.ram[offset] = value;
+            }
+            0x8000..=0xBFFF => self.pia.write(address, value),
+            _ => {}
+        }
+    }
+
+    fn irq(&mut self) -> bool {
+        self.pia.irq()
+    }
+}
+
+/// The tone, a square wave while `CB2` is high.
+#[derive(Clone, Default)]
+struct Tone {
+    /// Clock cycles since the last sample, times [`SAMPLE_RATE`].
+    clocks: u64,
+    /// Where the wave is within a cycle, in [`SAMPLE_RATE`]ths.
+    phase: u32,
+    samples: Vec<i16>,
+}
+
+impl Tone {
+    /// Plays `cycles` clock cycles of the tone, sounding if `on`.
+    fn play(&mut self, cycles: u32, on: bool) {
+        self.clocks += u64::from(cycles) * u64::from(SAMPLE_RATE);
+        while self.clocks >= CLOCK_HZ {
+            self.clocks -= CLOCK_HZ;
+            self.phase = (self.phase + TONE_HZ) % SAMPLE_RATE;
+            let sample = match (on, self.phase < SAMPLE_RATE / 2) {
+                (false, _) => 0,
+                (true, true) => AMPLITUDE,
+                (true, false) => -AMPLITUDE,
+            };
+            self.samples.push(sample);
+        }
+    }
+}
+
+/// A DREAM 6800, with the ROM it runs.
+#[derive(Clone)]
+pub struct Dream {
+    config: Config,
+    cpu: Cpu,
+    board: Board,
+    rom: Vec<u8>,
+    rom_hash: u64,
+    /// Clock cycles the processor has run past the end of the last frame.
+    overrun: u32,
+    tone: Tone,
+    framebuffer: Vec<u8>,
+}
+
+impl Dream {
+    /// Creates the machine `config` describes, with no ROM loaded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.ram` is not a power of two from 1KB to 32KB.
+    pub fn new(config: Config) -> Self {
+        assert!(
+            config.ram.is_power_of_two() && (0x400..=0x8000).contains(&config.ram),
+            "RAM must be a power of two from 1KB to 32KB, not {} bytes",
+            config.ram
+        );
+        let board = Board {
+            ram: vec![0; config.ram],
+            chipos: config.chipos,
+            pia: Pia::default(),
+            keys: [false; 16],
+        };
+        let mut dream = Dream {
+            config,
+            cpu: Cpu::new(),
+            board,
+            rom: Vec::new(),
+            rom_hash: rom_hash(&[]),
+            overrun: 0,
+            tone: Tone::default(),
+            framebuffer: vec![0; WIDTH * HEIGHT * 4],
+        };
+        dream.start();
+        dream
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns the processor, to change it directly.
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// Returns the PIA.
+    pub fn pia(&self) -> &Pia {
+        &self.board.pia
+    }
+
+    /// Returns RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.board.ram
+    }
+
+    /// Returns RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.board.ram
+    }
+
+    /// Returns whether the dot at `x` on row `y` is lit, with `x` below 64 and `y` below 32, as
+    /// the display page holds it now.
+    pub fn dot(&self, x: usize, y: usize) -> bool {
+        self.board.ram[DISPLAY + y * WIDTH / 8 + x / 8] & 0x80 >> (x % 8) != 0
+    }
+
+    /// Resets the processor and the PIA, keeping RAM, as the reset key does. With a ROM loaded
+    /// under CHIPOS, the processor then starts at its CHIP-8 interpreter, as the monitor's `GO`
+    /// at 0xC000 would.
+    fn start(&mut self) {
+        self.board.pia = Pia::default();
+        if self.board.chipos.is_some() {
+            self.cpu.reset(&mut self.board);
+            if !self.rom.is_empty() {
+                self.cpu.set_program_counter(CHIPOS_START);
+            }
+        } else {
+            self.cpu = Cpu::new();
+            self.cpu.set_stack_pointer(self.config.ram as u16 - 1);
+        }
+        self.overrun = 0;
+        self.tone = Tone::default();
+        self.draw();
+    }
+
+    /// Copies the display page into the framebuffer.
+    fn draw(&mut self) {
+        for (i, pixel) in self.framebuffer.chunks_exact_mut(4).enumerate() {
+            let (x, y) = (i % WIDTH, i / WIDTH);
+            let on = self.board.ram[DISPLAY + y * WIDTH / 8 + x / 8] & 0x80 >> (x % 8) != 0;
+            pixel.copy_from_slice(if on { &ON } else { &OFF });
+        }
+    }
+}
+
+impl Emulator for Dream {
+    fn name(&self) -> &'static str {
+        "DREAM 6800"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: WIDTH,
+            height: HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Loads a CHIP-8 program at 0x0200 under CHIPOS, or without it, a 6800 program at address 0,
+    /// and resets the machine.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let start = match &self.config.chipos {
+            Some(_) => PROGRAM_START,
+            None => 0,
+        };
+        let space = self.config.ram - start;
+        if rom.is_empty() {
+            return Err(Error::Rom("the ROM is empty".to_string()));
+        }
+        if rom.len() > space {
+            return Err(Error::Rom(format!(
+                "the ROM is {} bytes, but only {} fit",
+                rom.len(),
+                space
+            )));
+        }
+        let ram = &mut self.board.ram;
+        ram.iter_mut().for_each(|byte| *byte = 0);
+        ram[start..start + rom.len()].copy_from_slice(rom);
+        self.rom = rom.to_vec();
+        self.rom_hash = rom_hash(rom);
+        self.start();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        let rom = core::mem::take(&mut self.rom);
+        if rom.is_empty() {
+            self.board.ram.iter_mut().for_each(|byte| *byte = 0);
+            self.start();
+        } else if let Err(e) = self.load_rom(&rom) {
+            unreachable!("the ROM loaded before fails to load again: {}", e);
+        }
+    }
+
+    /// Runs a frame: the picture is read while DMA holds the processor, then `CB1` pulses and the
+    /// processor runs for the rest of the frame.
+    fn step_frame(&mut self) -> Result<(), Error> {
+        self.draw();
+        self.tone.play(DMA_CYCLES, self.board.pia.cb2());
+        self.board.pia.set_cb1(true);
+        self.board.pia.set_cb1(false);
+        let mut cycle = DMA_CYCLES + self.overrun;
+        while cycle < CYCLES_PER_FRAME {
+            let cycles = u32::from(self.cpu.step(&mut self.board));
+            self.tone.play(cycles, self.board.pia.cb2());
+            cycle += cycles;
+        }
+        self.overrun = cycle - CYCLES_PER_FRAME;
+        Ok(())
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.tone.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if let Some(key) = self.board.keys.get_mut(button) {
+            *key = pressed;
+        }
+    }
+
+    /// Saves the processor, RAM and the PIA, but not the picture, which is drawn again from RAM.
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(&self.board.ram);
+        state.extend_from_slice(&self.board.pia.save());
+        state.extend_from_slice(&self.overrun.to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let ram = self.config.ram;
+        let size = MAGIC.len() + 8 + STATE_SIZE + ram + pia::STATE_SIZE + 4;
+        if state.len() != size || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this machine".to_string()));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(STATE_SIZE);
+        let (memory, rest) = rest.split_at(ram);
+        let (pia, overrun) = rest.split_at(pia::STATE_SIZE);
+        let overrun = u32::from_le_bytes(<[u8; 4]>::try_from(overrun).unwrap());
+        if overrun >= CYCLES_PER_FRAME {
+            return Err(Error::State("the state is corrupt".to_string()));
+        }
+        self.cpu
+            .restore(<&[u8; STATE_SIZE]>::try_from(cpu).unwrap());
+        self.board.ram.copy_from_slice(memory);
+        self.board
+            .pia
+            .restore(<&[u8; pia::STATE_SIZE]>::try_from(pia).unwrap());
+        self.overrun = overrun;
+        self.draw();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stand-in for CHIPOS whose interpreter sets the stack pointer and jumps to the program at
+    /// 0x0200, whose monitor loops, and which counts frame interrupts at 0x0010.
+    fn chipos() -> [u8; CHIPOS_SIZE] {
+        let mut chipos = [0; CHIPOS_SIZE];
+        // 0xC000: LDS #$007F; JMP $0200
+        chipos[..6].copy_from_slice(&[0x8E, 0x00, 0x7F, 0x7E, 0x02, 0x00]);
+        // 0xC010: INC $0010; LDAA $8012, clearing the flag; RTI
+        chipos[0x10..0x17].copy_from_slice(&[0x7C, 0x00, 0x10, 0xB6, 0x80, 0x12, 0x3B]);
+        // 0xC020: BRA 0xC020
+        chipos[0x20..0x22].copy_from_slice(&[0x20, 0xFE]);
+        // The IRQ and reset vectors.
+        chipos[0x3F8..0x3FA].copy_from_slice(&[0xC0, 0x10]);
+        chipos[0x3FE..].copy_from_slice(&[0xC0, 0x20]);
+        chipos
+    }
+
+    /// Runs `program` at 0x0200 under the stand-in for CHIPOS.
+    fn running(program: &[u8]) -> Dream {
+        let mut dream = Dream::new(Config {
+            chipos: Some(chipos()),
+            ..Config::default()
+        });
+        // Without a program, the monitor runs.
+        assert_eq!(dream.cpu().program_counter(), 0xC020);
+        dream.load_rom(program).unwrap();
+        dream
+    }
+
+    /// Fills the display page with 0x81 and waits.
+    #[rustfmt::skip]
+    const FILL: [u8; 14] = [
+        0xCE, 0x01, 0x00, // LDX #$0100
+        0x86, 0x81, // LDAA #$81
+        0xA7, 0x00, // STAA $00,X
+        0x08, // INX
+        0x8C, 0x02, 0x00, // CPX #$0200
+        0x26, 0xF8, // BNE 0x0205
+        0x3E, // WAI
+    ];
+
+    /// Enables the frame interrupt on CB1 and loops.
+    #[rustfmt::skip]
+    const FRAMES: [u8; 8] = [
+        0x86, 0x05, // LDAA #$05
+        0xB7, 0x80, 0x13, // STAA $8013
+        0x0E, // CLI
+        0x20, 0xFE, // BRA 0x0206
+    ];
+
+    #[test]
+    fn displays() {
+        let mut dream = running(&FILL);
+        assert_eq!(dream.framebuffer().len(), dream.video().len());
+        dream.step_frame().unwrap();
+        assert!(dream.cpu().waiting());
+        dream.step_frame().unwrap();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let lit = x % 8 == 0 || x % 8 == 7;
+                assert_eq!(dream.dot(x, y), lit);
+                let pixel = &dream.framebuffer()[(y * WIDTH + x) * 4..][..4];
+                assert_eq!(pixel, if lit { ON } else { OFF });
+            }
+        }
+    }
+
+    #[test]
+    fn interrupts_every_frame() {
+        let mut dream = running(&FRAMES);
+        for _ in 0..3 {
+            dream.step_frame().unwrap();
+        }
+        assert_eq!(dream.ram()[0x10], 3);
+        // The processor ran for the frames but their picture.
+        let cycles = dream.cpu().cycles();
+        dream.step_frame().unwrap();
+        let frame = dream.cpu().cycles() - cycles;
+        assert!((frame as i64 - i64::from(CYCLES_PER_FRAME - DMA_CYCLES)).abs() < 12);
+        assert_eq!(FRAME_PERIOD.as_micros(), 19_968);
+    }
+
+    #[test]
+    fn reads_the_keypad() {
+        // Drives column 0 low, and copies port A to 0x20.
+        let mut dream = running(&[
+            0x86, 0x0F, 0xB7, 0x80, 0x10, // DDRA = 0x0F
+            0x86, 0x04, 0xB7, 0x80, 0x11, // CRA = 0x04
+            0x86, 0x0E, 0xB7, 0x80, 0x10, // PA = 0x0E
+            0xB6, 0x80, 0x10, // LDAA $8010
+            0x97, 0x20, // STAA $20
+            0x20, 0xF9, // BRA 0x020F
+        ]);
+        dream.step_frame().unwrap();
+        assert_eq!(dream.ram()[0x20], 0xFE);
+        dream.set_button(5, true);
+        dream.set_button(99, true);
+        dream.step_frame().unwrap();
+        assert_eq!(dream.ram()[0x20], 0xFE);
+        // Key 4 connects row 1 to column 0.
+        dream.set_button(5, false);
+        dream.set_button(4, true);
+        dream.step_frame().unwrap();
+        assert_eq!(dream.ram()[0x20], 0xDE);
+        // Through row 1, key 5 then pulls column 1 low too, as on the matrix.
+        dream.set_button(5, true);
+        dream.step_frame().unwrap();
+        assert_eq!(dream.ram()[0x20], 0xDC);
+    }
+
+    #[test]
+    fn sounds() {
+        // Sets CB2 high on the first key, and waits.
+        let mut dream = running(&[
+            0x86, 0x04, 0xB7, 0x80, 0x11, // CRA = 0x04
+            0x86, 0x30, 0xB7, 0x80, 0x13, // CB2 low
+            0xB6, 0x80, 0x10, // LDAA $8010
+            0x81, 0xFF, // CMPA #$FF
+            0x27, 0xF9, // BEQ 0x020A
+            0x86, 0x38, 0xB7, 0x80, 0x13, // CB2 high
+            0x3E, // WAI
+        ]);
+        let mut samples = Vec::new();
+        dream.step_frame().unwrap();
+        dream.pull_audio(&mut samples);
+        // 48000 samples a second, for 19968µs.
+        assert!((958..=959).contains(&samples.len()), "{}", samples.len());
+        assert!(samples.iter().all(|&sample| sample == 0));
+
+        // Port A is all inputs, pulled up, so a key pulls nothing low.
+        dream.set_button(0, true);
+        dream.step_frame().unwrap();
+        dream.pull_audio(&mut samples);
+        assert!(samples.iter().all(|&sample| sample == 0));
+        dream.ram_mut()[0x20F] = 0x26;
+        dream.step_frame().unwrap();
+        dream.pull_audio(&mut samples);
+        samples.clear();
+        dream.step_frame().unwrap();
+        dream.pull_audio(&mut samples);
+        assert!(samples.iter().all(|&sample| sample.abs() == AMPLITUDE));
+        // About 1000Hz: 20 cycles a frame.
+        let rises = samples.windows(2).filter(|w| w[0] < w[1]).count();
+        assert!((19..=21).contains(&rises), "{}", rises);
+    }
+
+    #[test]
+    fn saves_and_loads_states() {
+        let mut dream = running(&FRAMES);
+        dream.step_frame().unwrap();
+        let saved = dream.save_state();
+        let cpu = dream.cpu().clone();
+        dream.step_frame().unwrap();
+        assert_ne!(dream.cpu(), &cpu);
+        dream.load_state(&saved).unwrap();
+        assert_eq!(dream.cpu(), &cpu);
+        assert_eq!(dream.ram()[0x10], 1);
+        dream.step_frame().unwrap();
+        assert_eq!(dream.ram()[0x10], 2);
+
+        assert!(matches!(dream.load_state(&[1, 2, 3]), Err(Error::State(_))));
+        let mut corrupt = saved.clone();
+        *corrupt.last_mut().unwrap() = 0xFF;
+        assert!(matches!(dream.load_state(&corrupt), Err(Error::State(_))));
+        dream.load_rom(&FILL).unwrap();
+        assert!(matches!(dream.load_state(&saved), Err(Error::State(_))));
+    }
+
+    #[test]
+    fn loads_without_chipos() {
+        let mut dream = Dream::new(Config::default());
+        // 0x0000: CLRA; WAI
+        dream.load_rom(&[0x4F, 0x3E]).unwrap();
+        assert_eq!(dream.cpu().program_counter(), 0);
+        assert_eq!(dream.cpu().stack_pointer(), 0x0FFF);
+        dream.step_frame().unwrap();
+        assert!(dream.cpu().waiting());
+        // RAM repeats up to 0x7FFF, and there is nothing at CHIPOS's addresses.
+        assert_eq!(dream.board.read(0x1001), 0x3E);
+        assert_eq!(dream.board.read(0xFFFE), 0xFF);
+
+        dream.load_rom(&vec![0x01; 0x1000]).unwrap();
+        assert!(matches!(
+            dream.load_rom(&vec![0x01; 0x1001]),
+            Err(Error::Rom(_))
+        ));
+        assert!(matches!(dream.load_rom(&[]), Err(Error::Rom(_))));
+
+        let mut dream = running(&[0x01; 0x0E00]);
+        assert!(matches!(
+            dream.load_rom(&[0x01; 0x0E01]),
+            Err(Error::Rom(_))
+        ));
+        dream.reset();
+        assert_eq!(dream.cpu().program_counter(), CHIPOS_START);
+        assert_eq!(dream.ram()[PROGRAM_START..], [0x01; 0x0E00][..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn needs_ram() {
+        Dream::new(Config {
+            ram: 3000,
+            ..Config::default()
+        });
+    }
+}
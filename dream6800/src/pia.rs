@@ -0,0 +1,242 @@
+//! The MC6821 Peripheral Interface Adapter, which wires the DREAM 6800's keypad, frame interrupt
+//! and tone to the processor.
+//!
+//! The PIA has two sides, A and B, each with an 8-bit port whose lines are each an input or an
+//! output, and two control lines: `C1`, an input that sets a flag on its active edge and can
+//! interrupt the processor, and `C2`, here only used as an output. Each side has three registers
+//! at two addresses: the data direction register and the port share one, chosen by bit 2 of the
+//! control register at the other.
+//!
+//! Reading a port reads the levels of its lines, and clears the flags of its side. On side A,
+//! outputs read back as the lines are, as on the chip, and on side B as they were written.
+
+/// Length of the state written by [`Pia::save`].
+pub const STATE_SIZE: usize = 8;
+
+/// The bit of the control register that enables the interrupt of `C1`.
+const C1_ENABLE: u8 = 0x01;
+
+/// The bit of the control register that makes `C1` active on its rising edge rather than falling.
+const C1_RISING: u8 = 0x02;
+
+/// The bit of the control register that puts the port at its address rather than the data
+/// direction register.
+const PORT: u8 = 0x04;
+
+/// The bits of the control register that make `C2` an output following bit 3.
+const C2_OUTPUT: u8 = 0x30;
+
+/// The bit of the control register that `C2` follows as an output.
+const C2_LEVEL: u8 = 0x08;
+
+/// The flag of the control register set by the active edge of `C1`.
+const C1_FLAG: u8 = 0x80;
+
+/// The flags, which the processor cannot write.
+const FLAGS: u8 = 0xC0;
+
+/// A side of the PIA.
+#[derive(Clone, Default)]
+struct Side {
+    output: u8,
+    /// A bit set for each line that is an output.
+    direction: u8,
+    control: u8,
+    /// The level of `C1`.
+    c1: bool,
+}
+
+impl Side {
+    /// Returns the levels the side drives its lines to, with its inputs pulled up.
+    fn driven(&self) -> u8 {
+        self.output | !self.direction
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        let active = self.control & C1_RISING != 0;
+        if level != self.c1 && level == active {
+            self.control |= C1_FLAG;
+        }
+        self.c1 = level;
+    }
+
+    fn irq(&self) -> bool {
+        self.control & C1_FLAG != 0 && self.control & C1_ENABLE != 0
+    }
+
+    fn write(&mut self, control: bool, value: u8) {
+        if control {
+            self.control = self.control & FLAGS | value & !FLAGS;
+        } else if self.control & PORT != 0 {
+            self.output = value;
+        } else {
+            self.direction = value;
+        }
+    }
+
+    fn save(&self) -> [u8; 4] {
+        [self.output, self.direction, self.control, self.c1 as u8]
+    }
+
+    fn restore(&mut self, state: &[u8]) {
+        self.output = state[0];
+        self.direction = state[1];
+        self.control = state[2];
+        self.c1 = state[3] != 0;
+    }
+}
+
+/// The PIA.
+#[derive(Clone, Default)]
+pub struct Pia {
+    a: Side,
+    b: Side,
+}
+
+impl Pia {
+    /// Returns the levels port A drives its lines to, with its inputs pulled up, before the
+    /// devices on them pull any down.
+    pub fn port_a(&self) -> u8 {
+        self.a.driven()
+    }
+
+    /// Returns the levels port B drives its lines to, with its inputs pulled up.
+    pub fn port_b(&self) -> u8 {
+        self.b.driven()
+    }
+
+    /// Returns whether `CB2` is driven high, being an output set high. As an input, it drives
+    /// nothing.
+    pub fn cb2(&self) -> bool {
+        self.b.control & (C2_OUTPUT | C2_LEVEL) == C2_OUTPUT | C2_LEVEL
+    }
+
+    /// Sets the level of `CA1`.
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.set_c1(level);
+    }
+
+    /// Sets the level of `CB1`.
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.set_c1(level);
+    }
+
+    /// Returns whether either side pulls the IRQ line: a flag is set whose interrupt is enabled.
+    pub fn irq(&self) -> bool {
+        self.a.irq() || self.b.irq()
+    }
+
+    /// Reads the register numbered `register`, of which only the low two bits count, with the
+    /// lines of port A at `lines_a` and those of port B at `lines_b`.
+    pub fn read(&mut self, register: u16, lines_a: u8, lines_b: u8) -> u8 {
+        let side = if register & 2 == 0 {
+            &mut self.a
+        } else {
+            &mut self.b
+        };
+        if register & 1 != 0 {
+            side.control
+        } else if side.control & PORT == 0 {
+            side.direction
+        } else {
+            side.control &= !FLAGS;
+            if register & 2 == 0 {
+                lines_a
+            } else {
+                side.output & side.direction | lines_b & !side.direction
+            }
+        }
+    }
+
+    /// Writes `value` to the register numbered `register`, of which only the low two bits count.
+    pub fn write(&mut self, register: u16, value: u8) {
+        let side = if register & 2 == 0 {
+            &mut self.a
+        } else {
+            &mut self.b
+        };
+        side.write(register & 1 != 0, value);
+    }
+
+    /// Writes the whole PIA, for save states.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..4].copy_from_slice(&self.a.save());
+        state[4..].copy_from_slice(&self.b.save());
+        state
+    }
+
+    /// Restores the PIA from a state written by [`Pia::save`].
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        self.a.restore(&state[..4]);
+        self.b.restore(&state[4..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports() {
+        let mut pia = Pia::default();
+        // The data direction register is at the port's address until bit 2 is set.
+        pia.write(0, 0x0F);
+        assert_eq!(pia.read(0, 0x12, 0), 0x0F);
+        pia.write(1, PORT);
+        pia.write(0, 0x05);
+        assert_eq!(pia.port_a(), 0xF5);
+        assert_eq!(pia.read(0, 0x34, 0), 0x34);
+
+        // Port B reads its outputs as written.
+        pia.write(2, 0xF0);
+        pia.write(3, PORT | FLAGS);
+        assert_eq!(pia.read(3, 0, 0), PORT);
+        pia.write(2, 0xA5);
+        assert_eq!(pia.read(2, 0, 0x0F), 0xAF);
+        assert_eq!(pia.port_b(), 0xAF);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut pia = Pia::default();
+        pia.write(3, PORT | C1_RISING);
+        pia.set_cb1(true);
+        // The flag is set on the active edge, but only interrupts once enabled.
+        assert_eq!(pia.read(3, 0, 0), PORT | C1_RISING | C1_FLAG);
+        assert!(!pia.irq());
+        pia.write(3, PORT | C1_RISING | C1_ENABLE);
+        assert!(pia.irq());
+        pia.set_cb1(false);
+        pia.read(2, 0, 0);
+        assert!(!pia.irq());
+        pia.set_cb1(false);
+        assert!(!pia.irq());
+
+        // CA1, on its falling edge.
+        pia.write(1, C1_ENABLE);
+        pia.set_ca1(true);
+        assert!(!pia.irq());
+        pia.set_ca1(false);
+        assert!(pia.irq());
+        // Reading the data direction register leaves the flag.
+        pia.read(0, 0, 0);
+        assert!(pia.irq());
+    }
+
+    #[test]
+    fn cb2() {
+        let mut pia = Pia::default();
+        pia.write(3, C2_LEVEL);
+        assert!(!pia.cb2());
+        pia.write(3, C2_OUTPUT);
+        assert!(!pia.cb2());
+        pia.write(3, C2_OUTPUT | C2_LEVEL);
+        assert!(pia.cb2());
+
+        let mut restored = Pia::default();
+        restored.restore(&pia.save());
+        assert_eq!(restored.save(), pia.save());
+        assert!(restored.cb2());
+    }
+}
@@ -0,0 +1,46 @@
+//! Runs chip8-core's test ROMs on CHIPOS and on chip8-core, and checks that they leave the same
+//! screens.
+//!
+//! CHIPOS is not part of this repository, so the test is ignored unless asked for, and then needs an
+//! image of it:
+//!
+//! ```text
+//! DREAM6800_CHIPOS=chipos.bin cargo test -p dream6800 --test chipos -- --ignored
+//! ```
+
+use chip8_core::asm;
+use dream6800::compare;
+use dream6800::CHIPOS_SIZE;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The image of CHIPOS to run.
+const CHIPOS: &str = "DREAM6800_CHIPOS";
+
+/// The ROMs whose screen settles: the others animate, or draw random numbers.
+const ROMS: [&str; 4] = ["collision", "font", "quirks", "wrap"];
+
+/// Frames each ROM runs for, long enough for both machines to finish drawing.
+const FRAMES: usize = 240;
+
+#[test]
+#[ignore = "needs $DREAM6800_CHIPOS"]
+fn matches_chip8_core() {
+    let path = env::var_os(CHIPOS).unwrap_or_else(|| panic!("{} is not set", CHIPOS));
+    let image = fs::read(&path).expect("CHIPOS");
+    let chipos = <[u8; CHIPOS_SIZE]>::try_from(&image[..]).expect("a 1KB CHIPOS");
+
+    let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../chip8-core/tests/roms");
+    let mut failures = Vec::new();
+    for name in &ROMS {
+        let source = fs::read_to_string(roms.join(format!("{}.8o", name))).unwrap();
+        let program = asm::assemble(&source).unwrap();
+        let comparison = compare::compare(chipos, &program.bytes, FRAMES).unwrap();
+        if comparison.differences() != 0 {
+            failures.push(format!("{}:\n{}", name, comparison.text()));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,163 @@
+//! The interface every emulated system of this repository offers its frontends, so that a
+//! launcher, a debugger or a window can be written once for all of them.
+//!
+//! A system implements [`Emulator`]: it loads a ROM, runs a frame at a time, and between frames
+//! hands out its picture and the sound it made, takes the buttons held down, and saves and
+//! restores its whole state. What the picture and sound are like is described by [`VideoSpec`] and
+//! [`AudioSpec`], and the buttons by their names. Systems whose processor has a debugger adapter
+//! hand it out as a [`Target`] of [`emu_debug`], and those with a link port plug a [`link::Link`]
+//! into it to play with another.
+//!
+//! The crate is `no_std` without its default `std` feature, and only needs an allocator.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod link;
+
+use crate::link::Link;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+use emu_debug::Target;
+
+/// The picture of a system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VideoSpec {
+    /// The width of the framebuffer, in pixels.
+    pub width: usize,
+    /// The height of the framebuffer, in pixels.
+    pub height: usize,
+    /// How long a frame lasts on the real system, such as 1/60 second.
+    pub frame_period: Duration,
+}
+
+impl VideoSpec {
+    /// Returns the length of the framebuffer in bytes, at 4 bytes per pixel.
+    pub fn len(&self) -> usize {
+        self.width * self.height * 4
+    }
+
+    /// Returns `true` if the framebuffer has no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The sound of a system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioSpec {
+    /// Samples per second, of a single channel.
+    pub sample_rate: u32,
+}
+
+/// Why a system could not do what it was asked.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The ROM could not be loaded.
+    #[error("{0}")]
+    Rom(String),
+    /// The program stopped, and the system cannot run it further until it is reset.
+    #[error("{0}")]
+    Stopped(String),
+    /// The save state could not be restored, and the system was left as it was.
+    #[error("{0}")]
+    State(String),
+}
+
+/// An emulated system, as its frontends see it.
+///
+/// A frontend loads a ROM, then for every frame sets the buttons held down, calls
+/// [`step_frame`](Emulator::step_frame), shows the [`framebuffer`](Emulator::framebuffer) and
+/// plays the samples from [`pull_audio`](Emulator::pull_audio), waiting
+/// [`VideoSpec::frame_period`] between frames to run in real time.
+pub trait Emulator {
+    /// Returns the name of the system, such as `"CHIP-8"`.
+    fn name(&self) -> &'static str;
+
+    /// Returns what the framebuffer is like.
+    fn video(&self) -> VideoSpec;
+
+    /// Returns what the sound is like.
+    fn audio(&self) -> AudioSpec;
+
+    /// Returns the names of the buttons, in the order [`set_button`](Emulator::set_button)
+    /// numbers them.
+    fn buttons(&self) -> &'static [&'static str];
+
+    /// Powers on the system with `rom` inserted, in place of whatever ran before.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error>;
+
+    /// Powers the system off and on again, with the same ROM.
+    fn reset(&mut self);
+
+    /// Runs the system for one frame.
+    fn step_frame(&mut self) -> Result<(), Error>;
+
+    /// Returns the picture of the last frame, as 8-bit RGBA pixels row by row, of the size given
+    /// by [`video`](Emulator::video).
+    fn framebuffer(&self) -> &[u8];
+
+    /// Moves the samples made since the last call to the end of `samples`.
+    fn pull_audio(&mut self, samples: &mut Vec<i16>);
+
+    /// Presses or releases the button numbered `button`. Buttons that do not exist are ignored.
+    fn set_button(&mut self, button: usize, pressed: bool);
+
+    /// Returns the whole state of the system, for [`load_state`](Emulator::load_state).
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restores a state returned by [`save_state`](Emulator::save_state) for the same ROM.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error>;
+
+    /// Returns what a battery on the cartridge keeps while the system is off, such as the RAM a
+    /// game saves to, for [`load_battery`](Emulator::load_battery) when the ROM is next loaded.
+    /// Systems and cartridges without a battery return `None`.
+    fn battery(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores what [`battery`](Emulator::battery) returned for the same ROM, after it is
+    /// loaded.
+    fn load_battery(&mut self, _battery: &[u8]) -> Result<(), Error> {
+        Err(Error::State("the cartridge has no battery".into()))
+    }
+
+    /// Returns the processor of the system with its memory, for a debugger to look at and step
+    /// between frames, or `None` if it has no [`Target`] yet. Stepping the processor this way
+    /// runs it alone, with only the devices its bus clocks.
+    fn debug(&mut self) -> Option<Box<dyn Target + '_>> {
+        None
+    }
+
+    /// Plugs the cable `link` into the link port of the system, in place of the one plugged in
+    /// before, or unplugs it if `None`. Returns `false` if the system has no link port, dropping
+    /// `link`. The cable stays plugged in when a ROM is loaded or the system is reset.
+    fn set_link(&mut self, _link: Option<Box<dyn Link>>) -> bool {
+        false
+    }
+
+    /// Returns `true` if a cable is plugged into the link port and its other end is there.
+    fn is_linked(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffer_len() {
+        let video = VideoSpec {
+            width: 64,
+            height: 32,
+            frame_period: Duration::from_micros(16667),
+        };
+        assert_eq!(video.len(), 8192);
+        assert!(!video.is_empty());
+    }
+}
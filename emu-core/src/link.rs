@@ -0,0 +1,215 @@
+//! Cables between two systems, such as the Game Boy's link cable, carrying bytes both ways.
+//!
+//! A system plugs a [`Link`] into its port with [`Emulator::set_link`](crate::Emulator::set_link),
+//! and sends and receives bytes through it as it runs. With the `std` feature, two cables are
+//! offered: [`pair`] joins two systems of the same process, such as two run side by side or on two
+//! threads, and [`TcpLink`] joins systems of two processes, on one computer or over a network.
+//! Either end may run ahead of the other: bytes wait at the end they were sent to until it reads
+//! them.
+
+#[cfg(feature = "std")]
+pub use self::cables::{pair, Channel, TcpLink};
+
+/// An end of a cable.
+pub trait Link {
+    /// Sends `bytes` to the other end, in order. They are lost if it has gone.
+    fn send(&mut self, bytes: &[u8]);
+
+    /// Returns the next byte from the other end, or `None` if none has arrived yet.
+    fn receive(&mut self) -> Option<u8>;
+
+    /// Returns `false` once the other end has gone, such as when its system was closed.
+    fn is_connected(&self) -> bool;
+}
+
+#[cfg(feature = "std")]
+mod cables {
+    use super::Link;
+    use std::io::{self, Read, Write};
+    use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+    use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+    use std::thread;
+
+    /// Returns the two ends of a cable within the process.
+    pub fn pair() -> (Channel, Channel) {
+        let (first_sender, first_receiver) = mpsc::channel();
+        let (second_sender, second_receiver) = mpsc::channel();
+        let first = Channel {
+            sender: first_sender,
+            receiver: second_receiver,
+            connected: true,
+        };
+        let second = Channel {
+            sender: second_sender,
+            receiver: first_receiver,
+            connected: true,
+        };
+        (first, second)
+    }
+
+    /// An end of a cable returned by [`pair`].
+    #[derive(Debug)]
+    pub struct Channel {
+        sender: Sender<u8>,
+        receiver: Receiver<u8>,
+        connected: bool,
+    }
+
+    impl Link for Channel {
+        fn send(&mut self, bytes: &[u8]) {
+            for &byte in bytes {
+                if self.sender.send(byte).is_err() {
+                    self.connected = false;
+                }
+            }
+        }
+
+        fn receive(&mut self) -> Option<u8> {
+            match self.receiver.try_recv() {
+                Ok(byte) => Some(byte),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    None
+                }
+            }
+        }
+
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+    }
+
+    /// An end of a cable over TCP. A thread reads what the other end sends, so that
+    /// [`receive`](Link::receive) never waits for the network.
+    #[derive(Debug)]
+    pub struct TcpLink {
+        stream: TcpStream,
+        receiver: Receiver<u8>,
+        connected: bool,
+    }
+
+    impl TcpLink {
+        /// Connects to the other end listening at `address`.
+        pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<TcpLink> {
+            TcpLink::new(TcpStream::connect(address)?)
+        }
+
+        /// Makes an end of the connection `stream`, such as one a `TcpListener` accepted.
+        pub fn new(stream: TcpStream) -> io::Result<TcpLink> {
+            stream.set_nodelay(true)?;
+            stream.set_nonblocking(false)?;
+            let mut reader = stream.try_clone()?;
+            let (sender, receiver) = mpsc::channel();
+            thread::Builder::new()
+                .name("link".to_string())
+                .spawn(move || {
+                    let mut buffer = [0; 256];
+                    loop {
+                        let read = match reader.read(&mut buffer) {
+                            Ok(0) => return,
+                            Ok(read) => read,
+                            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                            Err(_) => return,
+                        };
+                        if buffer[..read]
+                            .iter()
+                            .any(|&byte| sender.send(byte).is_err())
+                        {
+                            return;
+                        }
+                    }
+                })?;
+            Ok(TcpLink {
+                stream,
+                receiver,
+                connected: true,
+            })
+        }
+
+        /// Returns the address of the other end.
+        pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+            self.stream.peer_addr()
+        }
+    }
+
+    impl Link for TcpLink {
+        fn send(&mut self, bytes: &[u8]) {
+            if self.connected && self.stream.write_all(bytes).is_err() {
+                self.connected = false;
+            }
+        }
+
+        fn receive(&mut self) -> Option<u8> {
+            match self.receiver.try_recv() {
+                Ok(byte) => Some(byte),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => {
+                    self.connected = false;
+                    None
+                }
+            }
+        }
+
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+    }
+
+    impl Drop for TcpLink {
+        /// Hangs up, which also ends the thread reading from the other end.
+        fn drop(&mut self) {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    /// Returns the bytes `link` receives within a second, up to `count`.
+    fn receive(link: &mut dyn Link, count: usize) -> Vec<u8> {
+        let start = Instant::now();
+        let mut bytes = Vec::new();
+        while bytes.len() < count && start.elapsed() < Duration::from_secs(1) {
+            match link.receive() {
+                Some(byte) => bytes.push(byte),
+                None => std::thread::yield_now(),
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn pairs() {
+        let (mut first, mut second) = pair();
+        assert_eq!(first.receive(), None);
+        first.send(&[1, 2]);
+        second.send(&[3]);
+        assert_eq!(receive(&mut second, 2), [1, 2]);
+        assert_eq!(receive(&mut first, 1), [3]);
+        drop(second);
+        first.send(&[4]);
+        assert_eq!(first.receive(), None);
+        assert!(!first.is_connected());
+    }
+
+    #[test]
+    fn connects_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpLink::connect(listener.local_addr().unwrap()).unwrap();
+        let mut server = TcpLink::new(listener.accept().unwrap().0).unwrap();
+        client.send(&[0x01, 0x42]);
+        server.send(&[0x02]);
+        assert_eq!(receive(&mut server, 2), [0x01, 0x42]);
+        assert_eq!(receive(&mut client, 1), [0x02]);
+        assert_eq!(client.peer_addr().unwrap(), listener.local_addr().unwrap());
+
+        drop(server);
+        assert!(receive(&mut client, 1).is_empty());
+        assert!(!client.is_connected());
+    }
+}
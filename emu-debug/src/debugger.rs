@@ -0,0 +1,303 @@
+//! Breakpoints, watchpoints and stepping on any [`Target`].
+
+use crate::{Access, AccessKind, Target};
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Which memory accesses trigger a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Both reads and writes.
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+/// Stops execution when a range of memory is accessed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    /// Number of bytes watched, starting at `address`.
+    pub len: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// Returns `true` if an access of `kind` to `address` should trigger this watchpoint.
+    pub fn triggers(&self, kind: AccessKind, address: u16) -> bool {
+        let end = self.address as u32 + self.len as u32;
+        self.kind.matches(kind) && (self.address as u32..end).contains(&(address as u32))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+        };
+        write!(f, "{:#06X}", self.address)?;
+        if self.len > 1 {
+            write!(f, "..{:#06X}", self.address as u32 + self.len as u32 - 1)?;
+        }
+        write!(f, " ({})", kind)
+    }
+}
+
+/// Why execution stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// A single instruction was executed.
+    Step,
+    /// A breakpoint was reached at the contained address.
+    Breakpoint(u16),
+    /// The instruction at `address` accessed watched memory.
+    Watchpoint { address: u16, access: Access },
+    /// The user interrupted execution.
+    Interrupted,
+    /// The program stopped, for the contained reason.
+    Error(String),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint(address) => write!(f, "breakpoint at {:#06X}", address),
+            StopReason::Watchpoint { address, access } => {
+                write!(f, "watchpoint: {:#06X} {}", address, access)
+            }
+            StopReason::Interrupted => write!(f, "interrupted"),
+            StopReason::Error(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+/// Controls the execution of a [`Target`].
+///
+/// The debugger does not own the target, so it can be inspected and modified directly between
+/// calls.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    /// Return address and depth to stop at when stepping over a call.
+    step_over: Option<(u16, usize)>,
+    /// The memory accessed by the last instruction.
+    accesses: Vec<Access>,
+}
+
+impl Debugger {
+    /// Creates a new `Debugger` with no breakpoints.
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    /// Sets a breakpoint at `address`. Returns `false` if one was already set.
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Clears the breakpoint at `address`. Returns `false` if there was none.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Sets a breakpoint at `address` if there is none, and clears it otherwise. Returns whether
+    /// one is set now.
+    pub fn toggle_breakpoint(&mut self, address: u16) -> bool {
+        !self.remove_breakpoint(address) && self.add_breakpoint(address)
+    }
+
+    /// Returns the addresses of all breakpoints in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Returns `true` if there is a breakpoint at `address`.
+    pub fn has_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    /// Sets a watchpoint, replacing any other watchpoint at the same address.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.remove_watchpoint(watchpoint.address);
+        self.watchpoints.push(watchpoint);
+        self.watchpoints
+            .sort_by_key(|watchpoint| watchpoint.address);
+    }
+
+    /// Clears the watchpoint at `address`. Returns `false` if there was none.
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.address != address);
+        self.watchpoints.len() != len
+    }
+
+    /// Returns all watchpoints in ascending order of address.
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> + '_ {
+        self.watchpoints.iter()
+    }
+
+    /// Returns the memory the last instruction executed read and wrote.
+    pub fn accesses(&self) -> &[Access] {
+        &self.accesses
+    }
+
+    /// Executes an instruction, recording what memory it accessed, and returns why to stop if it
+    /// touched a watchpoint or failed.
+    fn execute<T: Target + ?Sized>(&mut self, target: &mut T) -> Option<StopReason> {
+        let address = target.program_counter();
+        self.accesses.clear();
+        if let Err(err) = target.step(&mut self.accesses) {
+            return Some(StopReason::Error(err));
+        }
+        self.accesses
+            .iter()
+            .find(|access| {
+                self.watchpoints
+                    .iter()
+                    .any(|watchpoint| watchpoint.triggers(access.kind, access.address))
+            })
+            .map(|&access| StopReason::Watchpoint { address, access })
+    }
+
+    /// Executes a single instruction.
+    pub fn step<T: Target + ?Sized>(&mut self, target: &mut T) -> StopReason {
+        self.execute(target).unwrap_or(StopReason::Step)
+    }
+
+    /// Begins stepping over the instruction at the program counter.
+    ///
+    /// Ordinary instructions are executed right away and their result is returned. For a call,
+    /// `None` is returned and the caller should keep calling [`Debugger::run_step`] until it
+    /// reports that the subroutine has returned.
+    pub fn start_step_over<T: Target + ?Sized>(&mut self, target: &mut T) -> Option<StopReason> {
+        let address = target.program_counter();
+        if target.is_call(address) {
+            let len = target.disassemble(address).bytes.len() as u16;
+            self.step_over = Some((address.wrapping_add(len), target.depth()));
+            None
+        } else {
+            Some(self.step(target))
+        }
+    }
+
+    /// Executes one instruction of a continuous run.
+    ///
+    /// Returns why execution should stop, if it should.
+    pub fn run_step<T: Target + ?Sized>(&mut self, target: &mut T) -> Option<StopReason> {
+        let reason = self.execute(target).or_else(|| {
+            let pc = target.program_counter();
+            match self.step_over {
+                _ if self.breakpoints.contains(&pc) => Some(StopReason::Breakpoint(pc)),
+                Some((address, depth)) if pc == address && target.depth() <= depth => {
+                    Some(StopReason::Step)
+                }
+                _ => None,
+            }
+        });
+        if reason.is_some() {
+            self.step_over = None;
+        }
+        reason
+    }
+
+    /// Abandons a step over that is in progress, for when the user pauses execution.
+    pub fn interrupt(&mut self) -> StopReason {
+        self.step_over = None;
+        StopReason::Interrupted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Machine;
+
+    /// Runs until the debugger stops, without a way to interrupt it.
+    fn resume(debugger: &mut Debugger, machine: &mut Machine) -> StopReason {
+        loop {
+            if let Some(reason) = debugger.run_step(machine) {
+                return reason;
+            }
+        }
+    }
+
+    #[test]
+    fn breakpoints_and_step_over() {
+        // 0x00: CALL 0x08; 0x03: INC; 0x04: FF; 0x08: INC; 0x09: RET
+        let mut machine = Machine::new(&[0x01, 0x08, 0x00, 0x00, 0xFF, 0, 0, 0, 0x00, 0x02]);
+        let mut debugger = Debugger::new();
+
+        assert_eq!(debugger.start_step_over(&mut machine), None);
+        assert_eq!(resume(&mut debugger, &mut machine), StopReason::Step);
+        assert_eq!((machine.pc, machine.a, machine.sp), (0x03, 1, 0xFF));
+
+        assert!(debugger.add_breakpoint(0x04));
+        assert!(!debugger.toggle_breakpoint(0x04));
+        assert!(debugger.toggle_breakpoint(0x04));
+        assert_eq!(
+            resume(&mut debugger, &mut machine),
+            StopReason::Breakpoint(0x04)
+        );
+        assert_eq!(machine.a, 2);
+        assert_eq!(
+            debugger.start_step_over(&mut machine),
+            Some(StopReason::Error("illegal opcode FF".into()))
+        );
+    }
+
+    #[test]
+    fn watchpoints() {
+        // 0x00: INC; 0x01: ST 0x80; 0x04: ST 0x90; 0x07: INC
+        let mut machine = Machine::new(&[0x00, 0x03, 0x80, 0x00, 0x03, 0x90, 0x00, 0x00]);
+        let mut debugger = Debugger::new();
+        let watchpoint = Watchpoint {
+            address: 0x8F,
+            len: 2,
+            kind: WatchKind::Write,
+        };
+        debugger.add_watchpoint(watchpoint);
+        assert_eq!(watchpoint.to_string(), "0x008F..0x0090 (write)");
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x80,
+            len: 1,
+            kind: WatchKind::Read,
+        });
+
+        let reason = resume(&mut debugger, &mut machine);
+        let access = Access {
+            kind: AccessKind::Write,
+            address: 0x90,
+            value: 1,
+        };
+        assert_eq!(
+            reason,
+            StopReason::Watchpoint {
+                address: 0x04,
+                access
+            }
+        );
+        assert_eq!(debugger.accesses(), [access]);
+        assert_eq!(reason.to_string(), "watchpoint: 0x0004 wrote 0x0090 (0x01)");
+        assert!(debugger.remove_watchpoint(0x80));
+        assert!(!debugger.remove_watchpoint(0x80));
+        assert_eq!(debugger.watchpoints().count(), 1);
+    }
+}
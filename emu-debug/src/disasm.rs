@@ -0,0 +1,62 @@
+//! Listings of the instructions in memory, for disassembly panes.
+
+use crate::Target;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// An instruction, or data that is not one, at an address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    /// The bytes of the instruction, at least one.
+    pub bytes: Vec<u8>,
+    /// The mnemonic and operands in the syntax of the processor's maker.
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| alloc::format!("{:02X}", byte))
+            .collect();
+        write!(
+            f,
+            "{:#06X}  {:<12}{}",
+            self.address,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+/// Disassembles up to `count` instructions from `address` on, stopping at the end of memory.
+pub fn listing<T: Target + ?Sized>(target: &T, address: u16, count: usize) -> Vec<Line> {
+    let mut lines = Vec::with_capacity(count);
+    let mut address = usize::from(address);
+    while lines.len() < count && address < target.memory_size() {
+        let line = target.disassemble(address as u16);
+        address += line.bytes.len().max(1);
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Machine;
+    use alloc::string::ToString;
+
+    #[test]
+    fn listing() {
+        let mut machine = Machine::new(&[0x00, 0x01, 0x08, 0x00, 0x02]);
+        machine.memory.truncate(6);
+        let lines = super::listing(&machine, 0, 10);
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["INC", "CALL", "RET", "INC"]);
+        assert_eq!(lines[1].to_string(), "0x0001  01 08 00    CALL");
+    }
+}
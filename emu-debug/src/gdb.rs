@@ -0,0 +1,662 @@
+//! The GDB remote serial protocol, so programs on any [`Target`] can be debugged from GDB or an
+//! IDE.
+//!
+//! The protocol is handled without doing any I/O: [`next_input`] takes packets out of what was
+//! received, a [`Session`] answers each one, and [`frame`] wraps the answer to send. With the
+//! `std` feature, [`serve`] does all of that over TCP. Frontends that need more, such as the
+//! reverse execution of CHIP-8, build their own session from [`parse`] and the functions that
+//! read and write registers and memory.
+//!
+//! Registers are numbered as in [`Target::registers`], which [`target_xml`] describes to GDB, and
+//! are sent little-endian.
+
+use crate::debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+use crate::{AccessKind, Kind, Target};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+/// Byte sent by GDB to interrupt a running program.
+pub const INTERRUPT: u8 = 0x03;
+
+/// Largest packet GDB may send, in bytes.
+pub const PACKET_SIZE: usize = 0x1000;
+
+/// Wraps a packet body in `$...#xx` framing.
+pub fn frame(body: &str) -> String {
+    let checksum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", body, checksum)
+}
+
+/// Something received from GDB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    /// A packet with a valid checksum.
+    Packet(String),
+    /// A packet that must be sent again.
+    Corrupt,
+    Interrupt,
+}
+
+/// Removes the first complete input from the front of `buffer`.
+///
+/// Acknowledgements are skipped, since packets are never resent.
+pub fn next_input(buffer: &mut Vec<u8>) -> Option<Input> {
+    loop {
+        match buffer.first()? {
+            &INTERRUPT => {
+                buffer.remove(0);
+                return Some(Input::Interrupt);
+            }
+            b'$' => break,
+            _ => {
+                buffer.remove(0);
+            }
+        }
+    }
+
+    let end = buffer.iter().position(|&byte| byte == b'#')?;
+    if buffer.len() < end + 3 {
+        return None;
+    }
+    let packet: Vec<u8> = buffer.drain(..end + 3).collect();
+    let body = &packet[1..end];
+    let expected = core::str::from_utf8(&packet[end + 1..])
+        .ok()
+        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+    let checksum = body.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    match String::from_utf8(body.to_vec()) {
+        Ok(body) if expected == Some(checksum) => Some(Input::Packet(body)),
+        _ => Some(Input::Corrupt),
+    }
+}
+
+/// Encodes bytes as pairs of hex digits.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+/// Decodes a string of hex digit pairs.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses a hex number as sent by GDB.
+pub fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// Returns the target description of `target`, which tells GDB its registers.
+pub fn target_xml<T: Target + ?Sized>(target: &T) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n",
+    );
+    let _ = writeln!(
+        xml,
+        "  <feature name=\"org.{}.core\">",
+        target.architecture()
+    );
+    for (number, register) in target.registers().iter().enumerate() {
+        let bits = register.size * 8;
+        let kind = match register.kind {
+            Kind::Integer => format!("uint{}", bits),
+            Kind::Code => "code_ptr".to_string(),
+            Kind::Data => "data_ptr".to_string(),
+        };
+        let _ = writeln!(
+            xml,
+            "    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>",
+            register.name.to_ascii_lowercase(),
+            bits,
+            kind,
+            number
+        );
+    }
+    xml.push_str("  </feature>\n</target>\n");
+    xml
+}
+
+/// What a breakpoint packet sets or clears.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Point {
+    Breakpoint(u16),
+    Watchpoint(Watchpoint),
+    /// A kind of breakpoint that is not supported.
+    Unsupported,
+}
+
+/// A packet from GDB.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command<'a> {
+    /// `?`, asking why the program is stopped.
+    Halted,
+    ReadRegisters,
+    /// Every register, as hex.
+    WriteRegisters(&'a str),
+    ReadRegister(usize),
+    /// A register and its value, as hex.
+    WriteRegister(usize, &'a str),
+    ReadMemory {
+        address: usize,
+        len: usize,
+    },
+    WriteMemory {
+        address: usize,
+        data: Vec<u8>,
+    },
+    /// Runs the program, from the address if there is one.
+    Continue(Option<u16>),
+    /// Executes an instruction, at the address if there is one.
+    Step(Option<u16>),
+    ReverseStep,
+    ReverseContinue,
+    Insert(Point),
+    Remove(Point),
+    Detach,
+    Kill,
+    /// `H`, choosing a thread, of which there is only one.
+    SetThread,
+    /// Any other packet, such as a query.
+    Other(&'a str),
+}
+
+/// Parses `packet`, or returns `None` if its arguments are malformed.
+pub fn parse(packet: &str) -> Option<Command<'_>> {
+    let (command, args) = packet.split_at(packet.len().min(1));
+    let address = |args: &str| match args {
+        "" => Some(None),
+        _ => Some(Some(parse_hex(args)? as u16)),
+    };
+    let command = match command {
+        "?" => Command::Halted,
+        "g" => Command::ReadRegisters,
+        "G" => Command::WriteRegisters(args),
+        "p" => Command::ReadRegister(parse_hex(args)?),
+        "P" => {
+            let (number, hex) = args.split_once('=')?;
+            Command::WriteRegister(parse_hex(number)?, hex)
+        }
+        "m" => {
+            let (address, len) = args.split_once(',')?;
+            Command::ReadMemory {
+                address: parse_hex(address)?,
+                len: parse_hex(len)?,
+            }
+        }
+        "M" => {
+            let (location, hex) = args.split_once(':')?;
+            let (address, _) = location.split_once(',')?;
+            Command::WriteMemory {
+                address: parse_hex(address)?,
+                data: decode_hex(hex)?,
+            }
+        }
+        "c" => Command::Continue(address(args)?),
+        "s" => Command::Step(address(args)?),
+        "b" => match args {
+            "s" => Command::ReverseStep,
+            "c" => Command::ReverseContinue,
+            _ => Command::Other(packet),
+        },
+        "Z" => Command::Insert(point(args)?),
+        "z" => Command::Remove(point(args)?),
+        "D" => Command::Detach,
+        "k" => Command::Kill,
+        "H" => Command::SetThread,
+        _ => Command::Other(packet),
+    };
+    Some(command)
+}
+
+/// Parses the arguments of `Z` and `z` packets: the kind, the address and the length.
+fn point(args: &str) -> Option<Point> {
+    let parts: Vec<&str> = args.split(',').collect();
+    let (kind, address, len) = match parts[..] {
+        [kind, address, len] => (kind, parse_hex(address)? as u16, parse_hex(len)? as u16),
+        _ => return None,
+    };
+    let kind = match kind {
+        "0" | "1" => return Some(Point::Breakpoint(address)),
+        "2" => WatchKind::Write,
+        "3" => WatchKind::Read,
+        "4" => WatchKind::Access,
+        _ => return Some(Point::Unsupported),
+    };
+    Some(Point::Watchpoint(Watchpoint {
+        address,
+        len: len.max(1),
+        kind,
+    }))
+}
+
+/// Returns the value of register `number` as little-endian hex, or `None` if there is no such
+/// register.
+pub fn read_register<T: Target + ?Sized>(target: &T, number: usize) -> Option<String> {
+    let register = target.registers().get(number)?;
+    Some(encode_hex(
+        &target.register(number).to_le_bytes()[..register.size],
+    ))
+}
+
+/// Returns the values of every register.
+pub fn read_registers<T: Target + ?Sized>(target: &T) -> String {
+    (0..target.registers().len())
+        .filter_map(|number| read_register(target, number))
+        .collect()
+}
+
+/// Sets register `number` to the little-endian `hex`, returning `false` if there is no such
+/// register or `hex` is not a value of its size.
+pub fn write_register<T: Target + ?Sized>(target: &mut T, number: usize, hex: &str) -> bool {
+    let size = match target.registers().get(number) {
+        Some(register) => register.size,
+        None => return false,
+    };
+    match decode_hex(hex) {
+        Some(bytes) if bytes.len() == size => {
+            let mut value = [0; 2];
+            value[..size].copy_from_slice(&bytes);
+            target.set_register(number, u16::from_le_bytes(value));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Sets every register from `hex`, returning `false` and leaving them as they were if it does
+/// not hold a value for each.
+pub fn write_registers<T: Target + ?Sized>(target: &mut T, hex: &str) -> bool {
+    let total: usize = target
+        .registers()
+        .iter()
+        .map(|register| register.size)
+        .sum();
+    if hex.len() != total * 2 || decode_hex(hex).is_none() {
+        return false;
+    }
+    let mut rest = hex;
+    for number in 0..target.registers().len() {
+        let (value, tail) = rest.split_at(target.registers()[number].size * 2);
+        write_register(target, number, value);
+        rest = tail;
+    }
+    true
+}
+
+/// Returns `len` bytes of memory from `address` as hex, or `None` if `address` is past the end.
+/// Reads that run past the end of memory return what there is.
+pub fn read_memory<T: Target + ?Sized>(target: &T, address: usize, len: usize) -> Option<String> {
+    let available = target.memory_size().checked_sub(address)?;
+    if available == 0 {
+        return None;
+    }
+    let bytes: Vec<u8> = (address..address + len.min(available))
+        .map(|address| target.peek(address as u16))
+        .collect();
+    Some(encode_hex(&bytes))
+}
+
+/// Writes `data` to memory from `address`, returning `false` and writing nothing if it does not
+/// fit.
+pub fn write_memory<T: Target + ?Sized>(target: &mut T, address: usize, data: &[u8]) -> bool {
+    if address + data.len() > target.memory_size() {
+        return false;
+    }
+    for (offset, &byte) in data.iter().enumerate() {
+        target.poke((address + offset) as u16, byte);
+    }
+    true
+}
+
+/// Answers the general queries that are supported: `qSupported`, with `supported` added to the
+/// features offered, `QStartNoAckMode`, which clears `ack`, `qAttached`, and reading `xml` as the
+/// target description. Other packets get an empty reply, which tells GDB they are not supported.
+pub fn query(packet: &str, xml: &str, supported: &str, ack: &mut bool) -> String {
+    match packet {
+        _ if packet.starts_with("qSupported") => format!(
+            "PacketSize={:x};qXfer:features:read+;swbreak+;QStartNoAckMode+{}",
+            PACKET_SIZE, supported
+        ),
+        "QStartNoAckMode" => {
+            *ack = false;
+            "OK".to_string()
+        }
+        "qAttached" => "1".to_string(),
+        _ => match packet.strip_prefix("qXfer:features:read:target.xml:") {
+            Some(range) => {
+                let range = range
+                    .split_once(',')
+                    .and_then(|(offset, len)| Some((parse_hex(offset)?, parse_hex(len)?)));
+                match range {
+                    Some((offset, len)) => {
+                        let start = offset.min(xml.len());
+                        let end = (start + len).min(xml.len());
+                        let more = if end < xml.len() { 'm' } else { 'l' };
+                        format!("{}{}", more, &xml[start..end])
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            None => String::new(),
+        },
+    }
+}
+
+/// Returns the stop reply packet for `reason`.
+pub fn stop_reply(reason: &StopReason) -> String {
+    match reason {
+        StopReason::Step => "S05".to_string(),
+        StopReason::Breakpoint(_) => "T05swbreak:;".to_string(),
+        StopReason::Watchpoint { access, .. } => watch_reply(access.kind, access.address),
+        StopReason::Interrupted => "S02".to_string(),
+        // Report failures as an illegal instruction.
+        StopReason::Error(_) => "S04".to_string(),
+    }
+}
+
+/// Returns the stop reply packet for an access of `kind` to `address` that hit a watchpoint.
+pub fn watch_reply(kind: AccessKind, address: u16) -> String {
+    let kind = match kind {
+        AccessKind::Read => "rwatch",
+        AccessKind::Write => "watch",
+    };
+    format!("T05{}:{:x};", kind, address)
+}
+
+/// What to do after handling a packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Reply(String),
+    /// Run until something stops the program, then send a stop reply.
+    Continue,
+    /// Execute one instruction, then send a stop reply.
+    Step,
+    /// Close the connection, replying first if there is a reply.
+    Detach(Option<String>),
+}
+
+/// A debugging session with one GDB client.
+pub struct Session {
+    debugger: Debugger,
+    /// The target description.
+    xml: String,
+    /// Whether packets are acknowledged, which GDB may turn off.
+    ack: bool,
+}
+
+impl Session {
+    pub fn new<T: Target + ?Sized>(target: &T) -> Self {
+        Session {
+            debugger: Debugger::new(),
+            xml: target_xml(target),
+            ack: true,
+        }
+    }
+
+    /// Returns the debugger holding the breakpoints and watchpoints GDB set.
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Returns `true` if packets are acknowledged with `+`.
+    pub fn acknowledges(&self) -> bool {
+        self.ack
+    }
+
+    /// Handles one packet.
+    pub fn handle<T: Target + ?Sized>(&mut self, target: &mut T, packet: &str) -> Action {
+        let reply = |body: &str| Action::Reply(body.to_string());
+        let ok = |done: bool| reply(if done { "OK" } else { "E01" });
+        let command = match parse(packet) {
+            Some(command) => command,
+            None => return reply("E01"),
+        };
+        match command {
+            Command::Halted => reply("S05"),
+            Command::ReadRegisters => Action::Reply(read_registers(target)),
+            Command::WriteRegisters(hex) => ok(write_registers(target, hex)),
+            Command::ReadRegister(number) => {
+                Action::Reply(read_register(target, number).unwrap_or_else(|| "E01".into()))
+            }
+            Command::WriteRegister(number, hex) => ok(write_register(target, number, hex)),
+            Command::ReadMemory { address, len } => {
+                Action::Reply(read_memory(target, address, len).unwrap_or_else(|| "E01".into()))
+            }
+            Command::WriteMemory { address, data } => ok(write_memory(target, address, &data)),
+            Command::Continue(address) | Command::Step(address) => {
+                if let Some(address) = address {
+                    target.set_program_counter(address);
+                }
+                if matches!(command, Command::Continue(_)) {
+                    Action::Continue
+                } else {
+                    Action::Step
+                }
+            }
+            Command::Insert(Point::Breakpoint(address)) => {
+                self.debugger.add_breakpoint(address);
+                reply("OK")
+            }
+            Command::Insert(Point::Watchpoint(watchpoint)) => {
+                self.debugger.add_watchpoint(watchpoint);
+                reply("OK")
+            }
+            Command::Remove(Point::Breakpoint(address)) => {
+                self.debugger.remove_breakpoint(address);
+                reply("OK")
+            }
+            Command::Remove(Point::Watchpoint(watchpoint)) => {
+                self.debugger.remove_watchpoint(watchpoint.address);
+                reply("OK")
+            }
+            Command::Detach => Action::Detach(Some("OK".to_string())),
+            Command::Kill => Action::Detach(None),
+            Command::SetThread => reply("OK"),
+            Command::Other(packet) => Action::Reply(query(packet, &self.xml, "", &mut self.ack)),
+            Command::Insert(Point::Unsupported)
+            | Command::Remove(Point::Unsupported)
+            | Command::ReverseStep
+            | Command::ReverseContinue => reply(""),
+        }
+    }
+}
+
+/// Waits for GDB to connect on `port`, then lets it control `target` until it detaches.
+///
+/// The program runs as fast as it can when continued, checking for an interrupt from GDB every
+/// few thousand instructions.
+#[cfg(feature = "std")]
+pub fn serve<T: Target + ?Sized>(target: &mut T, port: u16) -> std::io::Result<()> {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpListener;
+
+    /// Instructions executed between checks for an interrupt.
+    const BATCH: usize = 4096;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    tracing::info!(
+        "waiting for gdb on port {}; use `target remote :{}`",
+        port,
+        port
+    );
+    let (mut stream, address) = listener.accept()?;
+    tracing::info!("connected to {}", address);
+    stream.set_nodelay(true)?;
+
+    let mut session = Session::new(target);
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; PACKET_SIZE];
+    loop {
+        let input = match next_input(&mut buffer) {
+            Some(input) => input,
+            None => {
+                let count = stream.read(&mut chunk)?;
+                if count == 0 {
+                    return Ok(());
+                }
+                buffer.extend_from_slice(&chunk[..count]);
+                continue;
+            }
+        };
+
+        let packet = match input {
+            Input::Packet(packet) => packet,
+            Input::Corrupt => {
+                stream.write_all(b"-")?;
+                continue;
+            }
+            // The program is already stopped.
+            Input::Interrupt => {
+                stream.write_all(frame("S02").as_bytes())?;
+                continue;
+            }
+        };
+        if session.acknowledges() {
+            stream.write_all(b"+")?;
+        }
+
+        let reason = match session.handle(target, &packet) {
+            Action::Reply(body) => {
+                stream.write_all(frame(&body).as_bytes())?;
+                continue;
+            }
+            Action::Step => session.debugger().step(target),
+            Action::Continue => {
+                stream.set_nonblocking(true)?;
+                let reason = loop {
+                    if let Some(reason) =
+                        (0..BATCH).find_map(|_| session.debugger().run_step(target))
+                    {
+                        break reason;
+                    }
+                    match stream.read(&mut chunk) {
+                        Ok(0) => return Ok(()),
+                        Ok(count) => {
+                            buffer.extend_from_slice(&chunk[..count]);
+                            if let Some(position) =
+                                buffer.iter().position(|&byte| byte == INTERRUPT)
+                            {
+                                buffer.remove(position);
+                                break session.debugger().interrupt();
+                            }
+                        }
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => (),
+                        Err(err) => return Err(err),
+                    }
+                };
+                stream.set_nonblocking(false)?;
+                reason
+            }
+            Action::Detach(reply) => {
+                if let Some(body) = reply {
+                    stream.write_all(frame(&body).as_bytes())?;
+                }
+                return Ok(());
+            }
+        };
+        stream.write_all(frame(&stop_reply(&reason)).as_bytes())?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Machine;
+
+    fn reply(session: &mut Session, machine: &mut Machine, packet: &str) -> String {
+        match session.handle(machine, packet) {
+            Action::Reply(body) => body,
+            action => panic!("unexpected {:?}", action),
+        }
+    }
+
+    #[test]
+    fn framing() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        let mut buffer = b"+$g#67\x03$m0,1#00$m".to_vec();
+        assert_eq!(
+            next_input(&mut buffer),
+            Some(Input::Packet("g".to_string()))
+        );
+        assert_eq!(next_input(&mut buffer), Some(Input::Interrupt));
+        assert_eq!(next_input(&mut buffer), Some(Input::Corrupt));
+        assert_eq!(next_input(&mut buffer), None);
+        assert_eq!(buffer, b"$m");
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        // 0x00: INC; 0x01: CALL 0x1234
+        let mut machine = Machine::new(&[0x00, 0x01, 0x34, 0x12]);
+        let mut session = Session::new(&machine);
+        machine.a = 0x2A;
+
+        assert_eq!(reply(&mut session, &mut machine, "g"), "2aff0000");
+        assert_eq!(reply(&mut session, &mut machine, "P2=3412"), "OK");
+        assert_eq!(machine.pc, 0x1234);
+        assert_eq!(reply(&mut session, &mut machine, "P1=3412"), "E01");
+        assert_eq!(reply(&mut session, &mut machine, "p0"), "2a");
+        assert_eq!(reply(&mut session, &mut machine, "p3"), "E01");
+        assert_eq!(reply(&mut session, &mut machine, "G01fe0100"), "OK");
+        assert_eq!((machine.a, machine.sp, machine.pc), (1, 0xFE, 1));
+        assert_eq!(reply(&mut session, &mut machine, "G01"), "E01");
+
+        assert_eq!(reply(&mut session, &mut machine, "m1,3"), "013412");
+        assert_eq!(reply(&mut session, &mut machine, "mfe,4"), "0000");
+        assert_eq!(reply(&mut session, &mut machine, "m100,1"), "E01");
+        assert_eq!(reply(&mut session, &mut machine, "M80,2:abcd"), "OK");
+        assert_eq!(machine.memory[0x81], 0xCD);
+        assert_eq!(reply(&mut session, &mut machine, "Mff,2:abcd"), "E01");
+
+        let xml = reply(
+            &mut session,
+            &mut machine,
+            "qXfer:features:read:target.xml:0,1000",
+        );
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains("<feature name=\"org.test.core\">"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"2\"/>"));
+        assert_eq!(reply(&mut session, &mut machine, "QStartNoAckMode"), "OK");
+        assert!(!session.acknowledges());
+        assert_eq!(reply(&mut session, &mut machine, "vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn breakpoints() {
+        // 0x00: INC; 0x01: INC; 0x02: ST 0x80; 0x05: INC
+        let mut machine = Machine::new(&[0x00, 0x00, 0x03, 0x80, 0x00, 0x00]);
+        let mut session = Session::new(&machine);
+        assert_eq!(reply(&mut session, &mut machine, "Z0,1,1"), "OK");
+        assert_eq!(reply(&mut session, &mut machine, "Z2,80,1"), "OK");
+        assert_eq!(reply(&mut session, &mut machine, "Z9,80,1"), "");
+        assert_eq!(reply(&mut session, &mut machine, "Z0,1"), "E01");
+
+        assert_eq!(session.handle(&mut machine, "c"), Action::Continue);
+        let reason = session.debugger().run_step(&mut machine);
+        assert_eq!(reason, Some(StopReason::Breakpoint(1)));
+        assert_eq!(stop_reply(&reason.unwrap()), "T05swbreak:;");
+        assert_eq!(reply(&mut session, &mut machine, "z0,1,1"), "OK");
+
+        assert_eq!(session.handle(&mut machine, "s"), Action::Step);
+        session.debugger().step(&mut machine);
+        let reason = session.debugger().step(&mut machine);
+        assert_eq!(stop_reply(&reason), "T05watch:80;");
+        assert_eq!(session.handle(&mut machine, "c0"), Action::Continue);
+        assert_eq!(machine.pc, 0);
+        assert_eq!(
+            session.handle(&mut machine, "D"),
+            Action::Detach(Some("OK".to_string()))
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! The Intel 8080 as a [`Target`].
+//!
+//! Its registers are, in order, `AF` (`PSW`), `BC`, `DE`, `HL`, `SP` and `PC`, as 8080 debuggers
+//! show them. Instruction fetches are reads, so watching code for reads stops when it runs.
+
+use crate::disasm::Line;
+use crate::{Access, AccessKind, Kind, Memory, Register, Target};
+use ::i8080::bus::Bus;
+use ::i8080::disasm::{self, Instruction};
+use ::i8080::{Cpu, Pair};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+const REGISTERS: [Register; 6] = [
+    Register::new("AF", 2, Kind::Integer),
+    Register::new("BC", 2, Kind::Integer),
+    Register::new("DE", 2, Kind::Integer),
+    Register::new("HL", 2, Kind::Integer),
+    Register::new("SP", 2, Kind::Data),
+    Register::new("PC", 2, Kind::Code),
+];
+
+/// The pairs of [`REGISTERS`] before the program counter.
+const PAIRS: [Pair; 5] = [Pair::PSW, Pair::BC, Pair::DE, Pair::HL, Pair::SP];
+
+/// An 8080 and the bus it runs against.
+pub struct I8080<'a, B> {
+    cpu: &'a mut Cpu,
+    bus: &'a mut B,
+}
+
+impl<'a, B: Bus + Memory> I8080<'a, B> {
+    pub fn new(cpu: &'a mut Cpu, bus: &'a mut B) -> Self {
+        I8080 { cpu, bus }
+    }
+}
+
+/// A bus that records what is read and written through it.
+struct Recorder<'a, B> {
+    bus: &'a mut B,
+    accesses: &'a mut Vec<Access>,
+}
+
+impl<B: Bus> Bus for Recorder<'_, B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.bus.read(address);
+        self.accesses.push(Access {
+            kind: AccessKind::Read,
+            address,
+            value,
+        });
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+        self.accesses.push(Access {
+            kind: AccessKind::Write,
+            address,
+            value,
+        });
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        self.bus.input(port)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.bus.output(port, value);
+    }
+
+    fn interrupt(&mut self) -> Option<u8> {
+        self.bus.interrupt()
+    }
+}
+
+impl<B: Bus + Memory> Target for I8080<'_, B> {
+    fn architecture(&self) -> &'static str {
+        "i8080"
+    }
+
+    fn registers(&self) -> &'static [Register] {
+        &REGISTERS
+    }
+
+    fn register(&self, index: usize) -> u16 {
+        match PAIRS.get(index) {
+            Some(&pair) => self.cpu.pair(pair),
+            None if index == PAIRS.len() => self.cpu.program_counter(),
+            None => panic!("no register {}", index),
+        }
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) {
+        match PAIRS.get(index) {
+            Some(&pair) => self.cpu.set_pair(pair, value),
+            None if index == PAIRS.len() => self.cpu.set_program_counter(value),
+            None => panic!("no register {}", index),
+        }
+    }
+
+    fn program_counter(&self) -> u16 {
+        self.cpu.program_counter()
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        self.cpu.set_program_counter(address);
+    }
+
+    /// The bytes pushed on the stack, which grows down from 0x0000.
+    fn depth(&self) -> usize {
+        usize::from(self.cpu.pair(Pair::SP).wrapping_neg())
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.bus.poke(address, value);
+    }
+
+    fn step(&mut self, accesses: &mut Vec<Access>) -> Result<(), String> {
+        self.cpu.step(&mut Recorder {
+            bus: &mut *self.bus,
+            accesses,
+        });
+        Ok(())
+    }
+
+    fn disassemble(&self, address: u16) -> Line {
+        let opcode = self.peek(address);
+        let bytes: Vec<u8> = (0..disasm::length(opcode) as u16)
+            .map(|offset| self.peek(address.wrapping_add(offset)))
+            .collect();
+        let text = match Instruction::decode(&bytes) {
+            Some(instruction) => instruction.to_string(),
+            None => format!("DB {:02X}", opcode),
+        };
+        Line {
+            address,
+            bytes,
+            text,
+        }
+    }
+
+    /// `CALL`, the conditional calls and `RST`.
+    fn is_call(&self, address: u16) -> bool {
+        match self.peek(address) {
+            0xCD | 0xDD | 0xED | 0xFD => true,
+            opcode => opcode & 0xC7 == 0xC4 || opcode & 0xC7 == 0xC7,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::{Debugger, StopReason};
+    use crate::disasm;
+    use ::i8080::bus::Memory as Ram;
+
+    #[test]
+    fn debugs() {
+        // 0x0000: LXI SP, 0100; CALL 0010; STA 2400; HLT; 0x0010: MVI A, 2A; RET
+        let program = [0x31, 0x00, 0x01, 0xCD, 0x10, 0x00, 0x32, 0x00, 0x24, 0x76];
+        let mut memory = Ram::with_program(0, &program).unwrap();
+        memory.0[0x10..0x13].copy_from_slice(&[0x3E, 0x2A, 0xC9]);
+        let mut cpu = Cpu::new();
+        let mut target = I8080::new(&mut cpu, &mut memory);
+        let mut debugger = Debugger::new();
+
+        assert_eq!(debugger.step(&mut target), StopReason::Step);
+        assert_eq!(target.register(4), 0x0100);
+        assert_eq!(debugger.start_step_over(&mut target), None);
+        while debugger.run_step(&mut target).is_none() {}
+        assert_eq!(target.program_counter(), 0x0006);
+        assert_eq!(target.register(0) >> 8, 0x2A);
+        assert_eq!(target.depth(), 0xFF00);
+
+        debugger.step(&mut target);
+        assert_eq!(target.peek(0x2400), 0x2A);
+        assert!(debugger.accesses().contains(&Access {
+            kind: AccessKind::Write,
+            address: 0x2400,
+            value: 0x2A
+        }));
+        let lines = disasm::listing(&target, 0, 3);
+        let texts: Vec<String> = lines.iter().map(|line| line.text.clone()).collect();
+        assert_eq!(texts, ["LXI SP, 0100", "CALL 0010", "STA 2400"]);
+        target.set_register(1, 0x1234);
+        assert_eq!(target.register(1), 0x1234);
+    }
+}
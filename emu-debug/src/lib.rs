@@ -0,0 +1,395 @@
+//! Debugging written once for every processor of this repository, so that the debugger of one
+//! system can be pointed at the others.
+//!
+//! A processor is debugged through [`Target`], which describes its registers, reads and writes
+//! them and its memory, executes an instruction at a time and disassembles one. On a target:
+//!
+//! * [`debugger`] stops at breakpoints and on watched memory, and steps into and over calls;
+//! * [`disasm`] lists the instructions from an address, for disassembly panes;
+//! * [`gdb`] speaks the GDB remote serial protocol, so GDB or an IDE can do all of that.
+//!
+//! With `std`, [`log`] logs what the emulators report through `tracing` for every frontend.
+//!
+//! The 8080, the 6502 and the Z80 are targets with the `i8080`, `mos6502` and `z80` features,
+//! through the adapters of the modules of the same names, given a core and the bus it runs
+//! against.
+//!
+//! ```
+//! use emu_debug::debugger::{Debugger, StopReason};
+//! # #[cfg(feature = "z80")]
+//! # {
+//! use emu_debug::z80::Z80;
+//! use emu_debug::Target;
+//! use z80::bus::Memory;
+//! use z80::Cpu;
+//!
+//! // 0x0000: LD A, 2A; INC A; JR $
+//! let mut memory = Memory::with_program(0, &[0x3E, 0x2A, 0x3C, 0x18, 0xFE])?;
+//! let mut cpu = Cpu::new();
+//! let mut target = Z80::new(&mut cpu, &mut memory);
+//! let mut debugger = Debugger::new();
+//! debugger.add_breakpoint(0x0003);
+//! while debugger.run_step(&mut target).is_none() {}
+//! assert_eq!(target.program_counter(), 0x0003);
+//! # }
+//! # Ok::<(), z80::bus::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` without its default `std` feature, which serves GDB over TCP and logs,
+//! and only needs an allocator.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
+#[cfg(feature = "i8080")]
+pub mod i8080;
+#[cfg(feature = "std")]
+pub mod log;
+#[cfg(feature = "mos6502")]
+pub mod mos6502;
+#[cfg(feature = "z80")]
+pub mod z80;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// What the value of a register is, which GDB shows it by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Integer,
+    /// An address of code, such as the program counter.
+    Code,
+    /// An address of data, such as a stack pointer.
+    Data,
+}
+
+/// A register of a processor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Register {
+    /// The name debuggers show, such as `A` or `PC`.
+    pub name: &'static str,
+    /// Its size in bytes, 1 or 2.
+    pub size: usize,
+    pub kind: Kind,
+}
+
+impl Register {
+    pub const fn new(name: &'static str, size: usize, kind: Kind) -> Self {
+        Register { name, size, kind }
+    }
+}
+
+/// Whether memory was read or written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A byte of memory accessed by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u16,
+    /// The byte read or written.
+    pub value: u8,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "wrote",
+        };
+        write!(f, "{} {:#06X} ({:#04X})", verb, self.address, self.value)
+    }
+}
+
+/// Memory a debugger can look at and change without the machine noticing, unlike reads and
+/// writes through its bus, which may clock devices or switch banks.
+pub trait Memory {
+    /// Returns the byte at `address`, or what the bus would most likely read there if reading it
+    /// does something, such as a register of a device.
+    fn peek(&self, address: u16) -> u8;
+
+    /// Writes `value` to `address` if it is RAM, and otherwise does nothing.
+    fn poke(&mut self, address: u16, value: u8);
+}
+
+impl Memory for emu_memory::Memory {
+    fn peek(&self, address: u16) -> u8 {
+        self.0[usize::from(address)]
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.0[usize::from(address)] = value;
+    }
+}
+
+/// A processor under a debugger, with its memory.
+pub trait Target {
+    /// The architecture, as named in GDB's target descriptions, such as `z80`.
+    fn architecture(&self) -> &'static str;
+
+    /// The registers, numbered as GDB numbers them.
+    fn registers(&self) -> &'static [Register];
+
+    /// Returns the value of register `index` of [`registers`](Target::registers).
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such register.
+    fn register(&self, index: usize) -> u16;
+
+    /// Sets register `index` to `value`, truncated to the size of the register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such register.
+    fn set_register(&mut self, index: usize, value: u16);
+
+    fn program_counter(&self) -> u16;
+
+    fn set_program_counter(&mut self, address: u16);
+
+    /// Returns how deep in calls the processor is, which grows with each call and shrinks with
+    /// each return, such as the bytes pushed on its stack.
+    fn depth(&self) -> usize;
+
+    /// The number of bytes of memory, which are at addresses from 0.
+    fn memory_size(&self) -> usize {
+        0x10000
+    }
+
+    /// Returns the byte at `address`, as [`Memory::peek`] does.
+    fn peek(&self, address: u16) -> u8;
+
+    /// Writes `value` to `address`, as [`Memory::poke`] does.
+    fn poke(&mut self, address: u16, value: u8);
+
+    /// Executes an instruction, adding the bytes of memory it read and wrote to `accesses`.
+    ///
+    /// Returns why it could not, if the program stopped.
+    fn step(&mut self, accesses: &mut Vec<Access>) -> Result<(), String>;
+
+    /// Disassembles the instruction at `address`.
+    fn disassemble(&self, address: u16) -> disasm::Line;
+
+    /// Returns `true` if the instruction at `address` calls a subroutine, which stepping over it
+    /// runs until it returns.
+    fn is_call(&self, address: u16) -> bool;
+}
+
+impl<T: Target + ?Sized> Target for &mut T {
+    fn architecture(&self) -> &'static str {
+        (**self).architecture()
+    }
+
+    fn registers(&self) -> &'static [Register] {
+        (**self).registers()
+    }
+
+    fn register(&self, index: usize) -> u16 {
+        (**self).register(index)
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) {
+        (**self).set_register(index, value);
+    }
+
+    fn program_counter(&self) -> u16 {
+        (**self).program_counter()
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        (**self).set_program_counter(address);
+    }
+
+    fn depth(&self) -> usize {
+        (**self).depth()
+    }
+
+    fn memory_size(&self) -> usize {
+        (**self).memory_size()
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        (**self).peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        (**self).poke(address, value);
+    }
+
+    fn step(&mut self, accesses: &mut Vec<Access>) -> Result<(), String> {
+        (**self).step(accesses)
+    }
+
+    fn disassemble(&self, address: u16) -> disasm::Line {
+        (**self).disassemble(address)
+    }
+
+    fn is_call(&self, address: u16) -> bool {
+        (**self).is_call(address)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::vec;
+
+    /// The registers of [`Machine`].
+    const REGISTERS: [Register; 3] = [
+        Register::new("A", 1, Kind::Integer),
+        Register::new("SP", 1, Kind::Data),
+        Register::new("PC", 2, Kind::Code),
+    ];
+
+    /// A processor of four instructions, for testing the debugger without a real one:
+    ///
+    /// * `00`: increments `A`;
+    /// * `01 nn nn`: calls `nnnn`, pushing the address after it on a stack from `0xFF` down;
+    /// * `02`: returns;
+    /// * `03 nn nn`: writes `A` to `nnnn`.
+    ///
+    /// Anything else stops the program.
+    pub(crate) struct Machine {
+        pub a: u8,
+        pub sp: u8,
+        pub pc: u16,
+        pub memory: Vec<u8>,
+    }
+
+    impl Machine {
+        pub fn new(program: &[u8]) -> Self {
+            let mut memory = vec![0; 0x100];
+            memory[..program.len()].copy_from_slice(program);
+            Machine {
+                a: 0,
+                sp: 0xFF,
+                pc: 0,
+                memory,
+            }
+        }
+
+        fn operand(&self) -> u16 {
+            u16::from_le_bytes([self.peek(self.pc + 1), self.peek(self.pc + 2)])
+        }
+    }
+
+    impl Target for Machine {
+        fn architecture(&self) -> &'static str {
+            "test"
+        }
+
+        fn registers(&self) -> &'static [Register] {
+            &REGISTERS
+        }
+
+        fn register(&self, index: usize) -> u16 {
+            [u16::from(self.a), u16::from(self.sp), self.pc][index]
+        }
+
+        fn set_register(&mut self, index: usize, value: u16) {
+            match index {
+                0 => self.a = value as u8,
+                1 => self.sp = value as u8,
+                _ => self.pc = value,
+            }
+        }
+
+        fn program_counter(&self) -> u16 {
+            self.pc
+        }
+
+        fn set_program_counter(&mut self, address: u16) {
+            self.pc = address;
+        }
+
+        fn depth(&self) -> usize {
+            usize::from(!self.sp)
+        }
+
+        fn memory_size(&self) -> usize {
+            self.memory.len()
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.memory[usize::from(address)]
+        }
+
+        fn poke(&mut self, address: u16, value: u8) {
+            self.memory[usize::from(address)] = value;
+        }
+
+        fn step(&mut self, accesses: &mut Vec<Access>) -> Result<(), String> {
+            match self.peek(self.pc) {
+                0x00 => {
+                    self.a = self.a.wrapping_add(1);
+                    self.pc += 1;
+                }
+                0x01 => {
+                    let [low, high] = (self.pc + 3).to_le_bytes();
+                    self.memory[usize::from(self.sp)] = high;
+                    self.memory[usize::from(self.sp - 1)] = low;
+                    self.sp -= 2;
+                    self.pc = self.operand();
+                }
+                0x02 => {
+                    self.sp += 2;
+                    let sp = usize::from(self.sp);
+                    self.pc = u16::from_le_bytes([self.memory[sp - 1], self.memory[sp]]);
+                }
+                0x03 => {
+                    let address = self.operand();
+                    self.poke(address, self.a);
+                    accesses.push(Access {
+                        kind: AccessKind::Write,
+                        address,
+                        value: self.a,
+                    });
+                    self.pc += 3;
+                }
+                opcode => return Err(format!("illegal opcode {:02X}", opcode)),
+            }
+            Ok(())
+        }
+
+        fn disassemble(&self, address: u16) -> disasm::Line {
+            let (len, text) = match self.peek(address) {
+                0x00 => (1, "INC".into()),
+                0x01 => (3, "CALL".into()),
+                0x02 => (1, "RET".into()),
+                0x03 => (3, "ST".into()),
+                _ => (1, "???".into()),
+            };
+            disasm::Line {
+                address,
+                bytes: (address..address + len).map(|a| self.peek(a)).collect(),
+                text,
+            }
+        }
+
+        fn is_call(&self, address: u16) -> bool {
+            self.peek(address) == 0x01
+        }
+    }
+
+    #[test]
+    fn accesses() {
+        let access = Access {
+            kind: AccessKind::Write,
+            address: 0x2400,
+            value: 0x2A,
+        };
+        assert_eq!(access.to_string(), "wrote 0x2400 (0x2A)");
+    }
+}
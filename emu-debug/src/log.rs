@@ -0,0 +1,676 @@
+//! Structured logging for the frontends: a [`tracing`](https://docs.rs/tracing) subscriber that
+//! writes the events it is asked for to stderr, as text or as JSON lines, and keeps the latest in
+//! a ring buffer for log views.
+//!
+//! Which events are written is set by a [`Filter`] in the syntax of `RUST_LOG`: a level for
+//! everything, and levels for targets, such as `warn,chip8_core=trace`. The events of a span,
+//! such as the frame and the instruction the machine was executing, are written with the span
+//! and its fields.
+//!
+//! A full-screen frontend [`Log::hold`]s the log while it draws, so that events do not write over
+//! the screen: they only go to the ring buffer, which the frontend shows, and are written once it
+//! lets go.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{self, Attributes, Id};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Level, Metadata, Subscriber};
+
+/// The environment variable holding the filter, unless one is given on the command line.
+pub const ENV: &str = "RUST_LOG";
+
+/// The filter when none is given: what the user should hear about, but not every frame.
+pub const DEFAULT_FILTER: &str = "info";
+
+/// Events kept by the ring buffer of a log.
+pub const CAPACITY: usize = 1000;
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static CURRENT: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Which events are logged: a level for everything, and levels for targets and the targets
+/// below them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    level: LevelFilter,
+    /// The levels of targets, such as `chip8_core`, which also apply to `chip8_core::remote`.
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    /// Returns the filter in `RUST_LOG`, or `default` if it is not set.
+    pub fn from_env(default: &str) -> Result<Self, String> {
+        match std::env::var(ENV) {
+            Ok(filter) => filter.parse().map_err(|err| format!("{}: {}", ENV, err)),
+            Err(_) => default.parse(),
+        }
+    }
+
+    /// Returns whether events at `level` from `target` are logged.
+    pub fn enabled(&self, target: &str, level: &Level) -> bool {
+        let within = |prefix: &str| {
+            target == prefix
+                || target
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with("::"))
+        };
+        let filter = self
+            .targets
+            .iter()
+            .filter(|(prefix, _)| within(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |&(_, filter)| filter);
+        *level <= filter
+    }
+
+    /// Returns the most verbose level anything is logged at.
+    pub fn max_level(&self) -> LevelFilter {
+        let targets = self.targets.iter().map(|&(_, filter)| filter);
+        targets.fold(self.level, LevelFilter::max)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        DEFAULT_FILTER.parse().expect("the default filter is valid")
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    /// Parses comma-separated levels, such as `warn,chip8_core=trace`: a level alone is for
+    /// everything, which is `error` if none is given.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let parse_level = |level: &str| {
+            level
+                .trim()
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("invalid log level `{}`", level.trim()))
+        };
+        let mut filter = Filter {
+            level: LevelFilter::ERROR,
+            targets: Vec::new(),
+        };
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => filter
+                    .targets
+                    .push((target.trim().to_string(), parse_level(level)?)),
+                None => filter.level = parse_level(directive)?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// How events are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Format {
+    /// A line of text per event, for people.
+    #[default]
+    Text,
+    /// A JSON object per line, for programs collecting logs.
+    Json,
+}
+
+/// Takes the logging options out of `args`: `--log <filter>`, which replaces `RUST_LOG`, and
+/// `--log-json`, which writes JSON lines.
+pub fn parse_args(args: &mut Vec<String>) -> Result<(Filter, Format), String> {
+    let mut filter = None;
+    let mut format = Format::Text;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--log" => {
+                let value = args.get(i + 1).ok_or("--log needs a value")?;
+                filter = Some(value.parse()?);
+                args.drain(i..i + 2);
+            }
+            "--log-json" => {
+                format = Format::Json;
+                args.remove(i);
+            }
+            _ => i += 1,
+        }
+    }
+    let filter = match filter {
+        Some(filter) => filter,
+        None => Filter::from_env(DEFAULT_FILTER)?,
+    };
+    Ok((filter, format))
+}
+
+/// The value of a field of an event or a span.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// An event that was logged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub time: SystemTime,
+    pub level: Level,
+    /// Where it comes from, usually the module path, such as `chip8::remote`.
+    pub target: String,
+    /// The spans it happened in, outermost first, as `name{field=value ...}`.
+    pub spans: Vec<String>,
+    pub message: String,
+    /// The fields other than the message.
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    /// Returns the record as a JSON object on one line.
+    pub fn json(&self) -> String {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let spans: Vec<String> = self.spans.iter().map(|span| quote(span)).collect();
+        let mut fields = format!("\"message\": {}", quote(&self.message));
+        for (name, value) in &self.fields {
+            let value = match value {
+                Value::Text(text) => quote(text),
+                Value::Float(value) if !value.is_finite() => quote(&value.to_string()),
+                value => value.to_string(),
+            };
+            let _ = write!(fields, ", {}: {}", quote(name), value);
+        }
+        format!(
+            "{{\"timestamp\": {}.{:03}, \"level\": \"{}\", \"target\": {}, \"spans\": [{}], \
+             \"fields\": {{{}}}}}",
+            time.as_secs(),
+            time.subsec_millis(),
+            self.level,
+            quote(&self.target),
+            spans.join(", "),
+            fields
+        )
+    }
+}
+
+/// Writes the record as a line of text, without the time: `LEVEL target: spans: message fields`.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5} {}: ", self.level, self.target)?;
+        for span in &self.spans {
+            write!(f, "{}: ", span)?;
+        }
+        write!(f, "{}", self.message)?;
+        for (name, value) in &self.fields {
+            write!(f, " {}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `text` as a JSON string.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Collects the fields of an event or a span.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    fields: Vec<(String, Value)>,
+}
+
+impl Fields {
+    fn push(&mut self, field: &Field, value: Value) {
+        match value {
+            Value::Text(text) if field.name() == "message" => self.message = Some(text),
+            value => self.fields.push((field.name().to_string(), value)),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, Value::Float(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Value::Integer(value.into()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Value::Integer(value.into()));
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.push(field, Value::Integer(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Value::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, Value::Text(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, Value::Text(format!("{:?}", value)));
+    }
+}
+
+/// The ring buffer of events, and where they are written.
+struct Shared {
+    records: VecDeque<Record>,
+    capacity: usize,
+    output: Box<dyn Write + Send>,
+    format: Format,
+    /// How many holds there are on the log, and the lines waiting for them to let go.
+    holds: usize,
+    held: Vec<String>,
+}
+
+impl Shared {
+    fn write(&mut self, line: &str) {
+        let _ = writeln!(self.output, "{}", line);
+        let _ = self.output.flush();
+    }
+}
+
+/// The events logged most recently, shared between the subscriber and the views of the log.
+#[derive(Clone)]
+pub struct Log {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Log {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns how many events are kept.
+    pub fn len(&self) -> usize {
+        self.lock().records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the events kept in `range`, oldest first.
+    pub fn range(&self, range: Range<usize>) -> Vec<Record> {
+        let shared = self.lock();
+        let end = range.end.min(shared.records.len());
+        let start = range.start.min(end);
+        shared.records.range(start..end).cloned().collect()
+    }
+
+    /// Forgets the events kept.
+    pub fn clear(&self) {
+        self.lock().records.clear();
+    }
+
+    /// Stops writing events until the returned hold is dropped, which writes those that came in
+    /// meanwhile. They are still kept.
+    pub fn hold(&self) -> Hold {
+        self.lock().holds += 1;
+        Hold { log: self.clone() }
+    }
+
+    fn push(&self, record: Record) {
+        let mut shared = self.lock();
+        let line = match shared.format {
+            Format::Text => record.to_string(),
+            Format::Json => record.json(),
+        };
+        if shared.holds > 0 {
+            shared.held.push(line);
+        } else {
+            shared.write(&line);
+        }
+        if shared.records.len() == shared.capacity {
+            shared.records.pop_front();
+        }
+        shared.records.push_back(record);
+    }
+}
+
+/// Holds a [`Log`] back from writing events while it lives.
+pub struct Hold {
+    log: Log,
+}
+
+impl Drop for Hold {
+    fn drop(&mut self) {
+        let mut shared = self.log.lock();
+        shared.holds -= 1;
+        if shared.holds == 0 {
+            for line in std::mem::take(&mut shared.held) {
+                shared.write(&line);
+            }
+        }
+    }
+}
+
+/// A span that is open, as shown with its events.
+struct Open {
+    name: &'static str,
+    fields: Vec<(String, Value)>,
+    references: usize,
+}
+
+impl fmt::Display for Open {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            let separator = if i == 0 { "{" } else { " " };
+            write!(f, "{}{}={}", separator, name, value)?;
+        }
+        if !self.fields.is_empty() {
+            write!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The subscriber, which logs the events its filter lets through into a [`Log`].
+pub struct Logger {
+    filter: Filter,
+    log: Log,
+    spans: Mutex<BTreeMap<u64, Open>>,
+    next_span: AtomicU64,
+}
+
+impl Logger {
+    /// Creates a logger writing the events `filter` lets through to `output` in `format`.
+    pub fn new(filter: Filter, format: Format, output: impl Write + Send + 'static) -> Self {
+        let shared = Shared {
+            records: VecDeque::new(),
+            capacity: CAPACITY,
+            output: Box::new(output),
+            format,
+            holds: 0,
+            held: Vec::new(),
+        };
+        Logger {
+            filter,
+            log: Log {
+                shared: Arc::new(Mutex::new(shared)),
+            },
+            spans: Mutex::new(BTreeMap::new()),
+            next_span: AtomicU64::new(1),
+        }
+    }
+
+    /// Returns the log the events go to.
+    pub fn log(&self) -> Log {
+        self.log.clone()
+    }
+
+    fn spans(&self) -> MutexGuard<'_, BTreeMap<u64, Open>> {
+        self.spans.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Subscriber for Logger {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if self.filter.enabled(metadata.target(), metadata.level()) {
+            Interest::always()
+        } else {
+            Interest::never()
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.filter.max_level())
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.enabled(metadata.target(), metadata.level())
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        let id = self.next_span.fetch_add(1, Ordering::Relaxed);
+        let open = Open {
+            name: attributes.metadata().name(),
+            fields: fields.fields,
+            references: 1,
+        };
+        self.spans().insert(id, open);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &span::Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some(open) = self.spans().get_mut(&span.into_u64()) {
+            open.fields.extend(fields.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let ids = match event.parent() {
+            Some(parent) => vec![parent.into_u64()],
+            None if event.is_contextual() => CURRENT.with(|current| current.borrow().clone()),
+            None => Vec::new(),
+        };
+        let spans = {
+            let open = self.spans();
+            ids.iter()
+                .filter_map(|id| open.get(id))
+                .map(Open::to_string)
+                .collect()
+        };
+        let metadata = event.metadata();
+        self.log.push(Record {
+            time: SystemTime::now(),
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            spans,
+            message: fields.message.unwrap_or_default(),
+            fields: fields.fields,
+        });
+    }
+
+    fn enter(&self, span: &Id) {
+        CURRENT.with(|current| current.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        let id = span.into_u64();
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            if let Some(i) = current.iter().rposition(|&entered| entered == id) {
+                current.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(open) = self.spans().get_mut(&span.into_u64()) {
+            open.references += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans();
+        let id = span.into_u64();
+        match spans.get_mut(&id) {
+            Some(open) if open.references > 1 => {
+                open.references -= 1;
+                false
+            }
+            Some(_) => {
+                spans.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Logs the events `filter` lets through to stderr in `format` for the rest of the program, and
+/// returns the log to show them. If the program already has a subscriber, it is kept and the log
+/// stays empty.
+pub fn init(filter: Filter, format: Format) -> Log {
+    let logger = Logger::new(filter, format, io::stderr());
+    let log = logger.log();
+    let _ = tracing::dispatcher::set_global_default(Dispatch::new(logger));
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes into a buffer the test can read.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            let text = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+            text.lines().map(str::to_string).collect()
+        }
+    }
+
+    #[test]
+    fn parses_filters() {
+        let filter: Filter = "warn, chip8_core=trace,chip8_core::remote=off"
+            .parse()
+            .unwrap();
+        assert!(filter.enabled("chip8", &Level::WARN));
+        assert!(!filter.enabled("chip8", &Level::INFO));
+        assert!(filter.enabled("chip8_core", &Level::TRACE));
+        assert!(filter.enabled("chip8_core::crowd", &Level::TRACE));
+        assert!(!filter.enabled("chip8_core::remote", &Level::ERROR));
+        // A prefix of a name is another target.
+        assert!(!filter.enabled("chip8_core_extra", &Level::INFO));
+        assert_eq!(filter.max_level(), LevelFilter::TRACE);
+
+        assert_eq!(
+            "".parse::<Filter>().unwrap().max_level(),
+            LevelFilter::ERROR
+        );
+        assert!("chip8=loud".parse::<Filter>().is_err());
+
+        let mut args: Vec<String> = ["run", "--log", "debug", "--log-json", "pong.ch8"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let (filter, format) = parse_args(&mut args).unwrap();
+        assert_eq!(args, ["run", "pong.ch8"]);
+        assert_eq!(filter.max_level(), LevelFilter::DEBUG);
+        assert_eq!(format, Format::Json);
+        assert!(parse_args(&mut vec!["--log".to_string()]).is_err());
+    }
+
+    #[test]
+    fn logs_events_in_spans() {
+        let buffer = Buffer::default();
+        let filter = "info,emu_debug::log=debug".parse().unwrap();
+        let logger = Logger::new(filter, Format::Text, buffer.clone());
+        let log = logger.log();
+        tracing::subscriber::with_default(logger, || {
+            let frame = tracing::info_span!("frame", number = 3);
+            let _frame = frame.enter();
+            tracing::debug_span!("instruction", address = "0x200").in_scope(|| {
+                tracing::warn!(key = 5, "stuck");
+            });
+            tracing::trace!("too verbose");
+            tracing::info!(target: "chip8", "outside");
+            tracing::debug!(target: "chip8", "filtered out");
+        });
+        assert_eq!(
+            buffer.lines(),
+            [
+                " WARN emu_debug::log::tests: frame{number=3}: instruction{address=0x200}: \
+                 stuck key=5",
+                " INFO chip8: frame{number=3}: outside",
+            ]
+        );
+        assert_eq!(log.len(), 2);
+        let record = &log.range(0..1)[0];
+        assert_eq!(record.message, "stuck");
+        assert_eq!(record.fields, [("key".to_string(), Value::Integer(5))]);
+    }
+
+    #[test]
+    fn holds_and_writes_json() {
+        let buffer = Buffer::default();
+        let logger = Logger::new(Filter::default(), Format::Json, buffer.clone());
+        let log = logger.log();
+        tracing::subscriber::with_default(logger, || {
+            let hold = log.hold();
+            tracing::info!(ok = true, name = "a \"quoted\"\nline", "held");
+            assert!(buffer.lines().is_empty());
+            drop(hold);
+        });
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 1);
+        let json = lines[0].split_once(", \"level\"").unwrap().1;
+        assert_eq!(
+            json,
+            ": \"INFO\", \"target\": \"emu_debug::log::tests\", \"spans\": [], \"fields\": \
+             {\"message\": \"held\", \"ok\": true, \"name\": \"a \\\"quoted\\\"\\nline\"}}"
+        );
+
+        log.clear();
+        assert!(log.is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! The MOS 6502 as a [`Target`].
+//!
+//! Its registers are, in order, `A`, `X`, `Y`, `S`, the flags `P` and `PC`. Instruction fetches,
+//! and the dummy reads the 6502 makes between them, are reads, so watching code for reads stops
+//! when it runs.
+
+use crate::disasm::Line;
+use crate::{Access, AccessKind, Kind, Memory, Register, Target};
+use ::mos6502::bus::Bus;
+use ::mos6502::disasm::Instruction;
+use ::mos6502::Cpu;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+const REGISTERS: [Register; 6] = [
+    Register::new("A", 1, Kind::Integer),
+    Register::new("X", 1, Kind::Integer),
+    Register::new("Y", 1, Kind::Integer),
+    Register::new("S", 1, Kind::Integer),
+    Register::new("P", 1, Kind::Integer),
+    Register::new("PC", 2, Kind::Code),
+];
+
+/// The registers of [`REGISTERS`] before the flags.
+const BYTES: [::mos6502::Register; 4] = [
+    ::mos6502::Register::A,
+    ::mos6502::Register::X,
+    ::mos6502::Register::Y,
+    ::mos6502::Register::S,
+];
+
+/// The opcode of `JSR`.
+const JSR: u8 = 0x20;
+
+/// A 6502 and the bus it runs against.
+pub struct Mos6502<'a, B> {
+    cpu: &'a mut Cpu,
+    bus: &'a mut B,
+}
+
+impl<'a, B: Bus + Memory> Mos6502<'a, B> {
+    pub fn new(cpu: &'a mut Cpu, bus: &'a mut B) -> Self {
+        Mos6502 { cpu, bus }
+    }
+}
+
+/// A bus that records what is read and written through it.
+struct Recorder<'a, B> {
+    bus: &'a mut B,
+    accesses: &'a mut Vec<Access>,
+}
+
+impl<B: Bus> Bus for Recorder<'_, B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.bus.read(address);
+        self.accesses.push(Access {
+            kind: AccessKind::Read,
+            address,
+            value,
+        });
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+        self.accesses.push(Access {
+            kind: AccessKind::Write,
+            address,
+            value,
+        });
+    }
+
+    fn nmi(&mut self) -> bool {
+        self.bus.nmi()
+    }
+
+    fn irq(&mut self) -> bool {
+        self.bus.irq()
+    }
+}
+
+impl<B: Bus + Memory> Target for Mos6502<'_, B> {
+    fn architecture(&self) -> &'static str {
+        "mos6502"
+    }
+
+    fn registers(&self) -> &'static [Register] {
+        &REGISTERS
+    }
+
+    fn register(&self, index: usize) -> u16 {
+        match index {
+            0..=3 => u16::from(self.cpu.register(BYTES[index])),
+            4 => u16::from(self.cpu.flags()),
+            5 => self.cpu.program_counter(),
+            _ => panic!("no register {}", index),
+        }
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) {
+        match index {
+            0..=3 => self.cpu.set_register(BYTES[index], value as u8),
+            4 => self.cpu.set_flags(value as u8),
+            5 => self.cpu.set_program_counter(value),
+            _ => panic!("no register {}", index),
+        }
+    }
+
+    fn program_counter(&self) -> u16 {
+        self.cpu.program_counter()
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        self.cpu.set_program_counter(address);
+    }
+
+    /// The bytes pushed on the stack, which grows down through page 1 from 0x01FF.
+    fn depth(&self) -> usize {
+        usize::from(!self.cpu.register(::mos6502::Register::S))
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.bus.poke(address, value);
+    }
+
+    /// Executes an instruction, or fails if a `JAM` has stopped the processor.
+    fn step(&mut self, accesses: &mut Vec<Access>) -> Result<(), String> {
+        if self.cpu.jammed() {
+            return Err(format!(
+                "the processor jammed before {:#06X}",
+                self.cpu.program_counter()
+            ));
+        }
+        self.cpu.step(&mut Recorder {
+            bus: &mut *self.bus,
+            accesses,
+        });
+        Ok(())
+    }
+
+    fn disassemble(&self, address: u16) -> Line {
+        let window: Vec<u8> = (0..3)
+            .map(|offset| self.peek(address.wrapping_add(offset)))
+            .collect();
+        match Instruction::decode(address, &window) {
+            Some(instruction) => Line {
+                address,
+                bytes: window[..instruction.len()].to_vec(),
+                text: instruction.to_string(),
+            },
+            None => Line {
+                address,
+                bytes: window[..1].to_vec(),
+                text: format!(".BYTE ${:02X}", window[0]),
+            },
+        }
+    }
+
+    fn is_call(&self, address: u16) -> bool {
+        self.peek(address) == JSR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::{Debugger, StopReason};
+    use crate::disasm;
+    use ::mos6502::bus::Memory as Ram;
+
+    #[test]
+    fn debugs() {
+        // 0x0200: LDX #$FF; TXS; JSR $0210; STA $0300; JAM; 0x0210: LDA #$2A; RTS
+        let program = [0xA2, 0xFF, 0x9A, 0x20, 0x10, 0x02, 0x8D, 0x00, 0x03, 0x02];
+        let mut memory = Ram::with_program(0x0200, &program).unwrap();
+        memory.0[0x0210..0x0213].copy_from_slice(&[0xA9, 0x2A, 0x60]);
+        let mut cpu = Cpu::new();
+        cpu.set_program_counter(0x0200);
+        let mut target = Mos6502::new(&mut cpu, &mut memory);
+        let mut debugger = Debugger::new();
+
+        debugger.step(&mut target);
+        debugger.step(&mut target);
+        assert_eq!(target.depth(), 0);
+        assert_eq!(debugger.start_step_over(&mut target), None);
+        while debugger.run_step(&mut target).is_none() {}
+        assert_eq!(
+            (target.program_counter(), target.register(0)),
+            (0x0206, 0x2A)
+        );
+
+        debugger.add_breakpoint(0x0209);
+        assert_eq!(
+            debugger.run_step(&mut target),
+            Some(StopReason::Breakpoint(0x0209))
+        );
+        assert_eq!(target.peek(0x0300), 0x2A);
+        debugger.step(&mut target);
+        assert!(matches!(debugger.step(&mut target), StopReason::Error(_)));
+
+        let lines = disasm::listing(&target, 0x0203, 2);
+        assert_eq!(lines[0].to_string(), "0x0203  20 10 02    JSR $0210");
+        assert_eq!(lines[1].text, "STA $0300");
+        target.set_register(4, 0x01);
+        assert_eq!(target.register(4) & 0x01, 0x01);
+    }
+}
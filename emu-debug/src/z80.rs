@@ -0,0 +1,243 @@
+//! The Zilog Z80 as a [`Target`].
+//!
+//! Its registers are, in order, `AF`, `BC`, `DE`, `HL`, `SP`, `PC`, `IX`, `IY`, the alternates
+//! `AF'` to `HL'`, and `I` and `R`, as GDB numbers them for the Z80. Instruction fetches are reads,
+//! so watching code for reads stops when it runs.
+
+use crate::disasm::Line;
+use crate::{Access, AccessKind, Kind, Memory, Register, Target};
+use ::z80::bus::Bus;
+use ::z80::disasm::Instruction;
+use ::z80::{Cpu, Pair};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+const REGISTERS: [Register; 14] = [
+    Register::new("AF", 2, Kind::Integer),
+    Register::new("BC", 2, Kind::Integer),
+    Register::new("DE", 2, Kind::Integer),
+    Register::new("HL", 2, Kind::Integer),
+    Register::new("SP", 2, Kind::Data),
+    Register::new("PC", 2, Kind::Code),
+    Register::new("IX", 2, Kind::Integer),
+    Register::new("IY", 2, Kind::Integer),
+    Register::new("AF'", 2, Kind::Integer),
+    Register::new("BC'", 2, Kind::Integer),
+    Register::new("DE'", 2, Kind::Integer),
+    Register::new("HL'", 2, Kind::Integer),
+    Register::new("I", 1, Kind::Integer),
+    Register::new("R", 1, Kind::Integer),
+];
+
+/// The pairs of the first eight of [`REGISTERS`], with `None` for the program counter.
+const PAIRS: [Option<Pair>; 8] = [
+    Some(Pair::AF),
+    Some(Pair::BC),
+    Some(Pair::DE),
+    Some(Pair::HL),
+    Some(Pair::SP),
+    None,
+    Some(Pair::IX),
+    Some(Pair::IY),
+];
+
+/// The pairs with alternates, in the order of [`REGISTERS`].
+const ALTERNATES: [Pair; 4] = [Pair::AF, Pair::BC, Pair::DE, Pair::HL];
+
+/// A Z80 and the bus it runs against.
+pub struct Z80<'a, B> {
+    cpu: &'a mut Cpu,
+    bus: &'a mut B,
+}
+
+impl<'a, B: Bus + Memory> Z80<'a, B> {
+    pub fn new(cpu: &'a mut Cpu, bus: &'a mut B) -> Self {
+        Z80 { cpu, bus }
+    }
+}
+
+/// A bus that records what is read and written through it.
+struct Recorder<'a, B> {
+    bus: &'a mut B,
+    accesses: &'a mut Vec<Access>,
+}
+
+impl<B: Bus> Bus for Recorder<'_, B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.bus.read(address);
+        self.accesses.push(Access {
+            kind: AccessKind::Read,
+            address,
+            value,
+        });
+        value
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+        self.accesses.push(Access {
+            kind: AccessKind::Write,
+            address,
+            value,
+        });
+    }
+
+    fn input(&mut self, port: u16) -> u8 {
+        self.bus.input(port)
+    }
+
+    fn output(&mut self, port: u16, value: u8) {
+        self.bus.output(port, value);
+    }
+
+    fn interrupt(&mut self) -> Option<u8> {
+        self.bus.interrupt()
+    }
+
+    fn nmi(&mut self) -> bool {
+        self.bus.nmi()
+    }
+}
+
+impl<B: Bus + Memory> Target for Z80<'_, B> {
+    fn architecture(&self) -> &'static str {
+        "z80"
+    }
+
+    fn registers(&self) -> &'static [Register] {
+        &REGISTERS
+    }
+
+    fn register(&self, index: usize) -> u16 {
+        match index {
+            5 => self.cpu.program_counter(),
+            0..=7 => self.cpu.pair(PAIRS[index].unwrap()),
+            8..=11 => self.cpu.alternate(ALTERNATES[index - 8]),
+            12 => u16::from(self.cpu.register(::z80::Register::I)),
+            13 => u16::from(self.cpu.register(::z80::Register::R)),
+            _ => panic!("no register {}", index),
+        }
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) {
+        match index {
+            5 => self.cpu.set_program_counter(value),
+            0..=7 => self.cpu.set_pair(PAIRS[index].unwrap(), value),
+            8..=11 => self.cpu.set_alternate(ALTERNATES[index - 8], value),
+            12 => self.cpu.set_register(::z80::Register::I, value as u8),
+            13 => self.cpu.set_register(::z80::Register::R, value as u8),
+            _ => panic!("no register {}", index),
+        }
+    }
+
+    fn program_counter(&self) -> u16 {
+        self.cpu.program_counter()
+    }
+
+    fn set_program_counter(&mut self, address: u16) {
+        self.cpu.set_program_counter(address);
+    }
+
+    /// The bytes pushed on the stack, which grows down from 0x0000.
+    fn depth(&self) -> usize {
+        usize::from(self.cpu.pair(Pair::SP).wrapping_neg())
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        self.bus.poke(address, value);
+    }
+
+    fn step(&mut self, accesses: &mut Vec<Access>) -> Result<(), String> {
+        self.cpu.step(&mut Recorder {
+            bus: &mut *self.bus,
+            accesses,
+        });
+        Ok(())
+    }
+
+    fn disassemble(&self, address: u16) -> Line {
+        let window: Vec<u8> = (0..4)
+            .map(|offset| self.peek(address.wrapping_add(offset)))
+            .collect();
+        match Instruction::decode(&window) {
+            Some(instruction) => Line {
+                address,
+                bytes: instruction.bytes().to_vec(),
+                text: instruction.to_string(),
+            },
+            None => Line {
+                address,
+                bytes: window[..1].to_vec(),
+                text: format!("DB {:02X}", window[0]),
+            },
+        }
+    }
+
+    /// `CALL`, the conditional calls and `RST`, with or without an index prefix.
+    fn is_call(&self, address: u16) -> bool {
+        let opcode = match self.peek(address) {
+            0xDD | 0xFD => self.peek(address.wrapping_add(1)),
+            opcode => opcode,
+        };
+        opcode == 0xCD || opcode & 0xC7 == 0xC4 || opcode & 0xC7 == 0xC7
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+    use crate::gdb;
+    use ::z80::bus::Memory as Ram;
+
+    #[test]
+    fn debugs() {
+        // 0x0000: LD SP, 0000; CALL 0010; LD (IX+05), 2A; HALT; 0x0010: EXX; RST 38; RET;
+        // 0x0038: RET
+        let program = [
+            0x31, 0x00, 0x00, 0xCD, 0x10, 0x00, 0xDD, 0x36, 0x05, 0x2A, 0x76,
+        ];
+        let mut memory = Ram::with_program(0, &program).unwrap();
+        memory.0[0x10..0x13].copy_from_slice(&[0xD9, 0xFF, 0xC9]);
+        memory.0[0x38] = 0xC9;
+        let mut cpu = Cpu::new();
+        let mut target = Z80::new(&mut cpu, &mut memory);
+        let mut debugger = Debugger::new();
+
+        debugger.step(&mut target);
+        assert_eq!(target.depth(), 0);
+        target.set_register(1, 0x1234);
+        target.set_register(6, 0x2400);
+        assert_eq!(debugger.start_step_over(&mut target), None);
+        while debugger.run_step(&mut target).is_none() {}
+        assert_eq!(target.program_counter(), 0x0006);
+        assert_eq!((target.register(1), target.register(9)), (0, 0x1234));
+
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x2405,
+            len: 1,
+            kind: WatchKind::Write,
+        });
+        assert_eq!(
+            debugger.run_step(&mut target),
+            Some(StopReason::Watchpoint {
+                address: 0x0006,
+                access: Access {
+                    kind: AccessKind::Write,
+                    address: 0x2405,
+                    value: 0x2A
+                }
+            })
+        );
+        assert_eq!(target.disassemble(0x0006).text, "LD (IX+05), 2A");
+        assert_eq!(target.disassemble(0x0006).bytes.len(), 4);
+        assert!(gdb::target_xml(&target)
+            .contains("<reg name=\"af'\" bitsize=\"16\" type=\"uint16\" regnum=\"8\"/>"));
+        assert_eq!(&gdb::read_registers(&target)[24..28], "0024");
+    }
+}
@@ -0,0 +1,18 @@
+// Lit pixels glow onto their neighbors.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(source, nearest_sampler, in.uv, 0.0).rgb;
+    // The glow reaches about half a pixel of the game around each one.
+    let step = 0.25 / uniforms.game_size;
+    var glow = vec3<f32>(0.0);
+    for (var x = -2; x <= 2; x++) {
+        for (var y = -2; y <= 2; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * step;
+            let weight = exp(-0.5 * f32(x * x + y * y));
+            glow += textureSampleLevel(source, linear_sampler, in.uv + offset, 0.0).rgb * weight;
+        }
+    }
+    // The weights add up to about 6.
+    return vec4<f32>(min(color + glow / 6.0 * 0.6, vec3<f32>(1.0)), 1.0);
+}
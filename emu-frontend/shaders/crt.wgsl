@@ -0,0 +1,18 @@
+// A CRT television: a slightly curved screen, dark gaps between the rows of the game, and
+// darker corners.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let centered = in.uv * 2.0 - 1.0;
+    let curved = centered * (1.0 + dot(centered, centered) * vec2<f32>(0.02, 0.03));
+    let uv = curved * 0.5 + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let color = textureSampleLevel(source, linear_sampler, uv, 0.0).rgb;
+    let row = fract(uv.y * uniforms.game_size.y);
+    let scanline = 0.6 + 0.4 * sin(row * 3.14159265);
+    let edges = uv * (1.0 - uv);
+    let vignette = clamp(pow(16.0 * edges.x * edges.y, 0.2), 0.0, 1.0);
+    return vec4<f32>(color * scanline * vignette, 1.0);
+}
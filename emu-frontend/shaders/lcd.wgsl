@@ -0,0 +1,11 @@
+// A handheld's LCD: each pixel of the game is a cell, with a faint grid between the cells.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(source, nearest_sampler, in.uv, 0.0).rgb;
+    let cell = fract(in.uv * uniforms.game_size);
+    // The grid lines are a tenth of a cell wide, and at least one pixel of the output.
+    let line = max(vec2<f32>(0.1), uniforms.game_size / uniforms.output_size);
+    let inside = step(line, cell);
+    return vec4<f32>(color * (0.8 + 0.2 * inside.x * inside.y), 1.0);
+}
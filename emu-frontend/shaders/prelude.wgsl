@@ -0,0 +1,35 @@
+// Declarations every post-processing shader of emu-frontend starts with. A shader defines
+// `fs_main`, which returns the color of the output pixel at `in.uv`, from (0, 0) at the top left
+// to (1, 1) at the bottom right, by reading `source`: the game for the first pass, and the output
+// of the previous pass for the others.
+
+struct Uniforms {
+    // Size of the game's display in pixels, such as 64x32.
+    game_size: vec2<f32>,
+    // Size of `source` in pixels: the game's for the first pass, the output's for the others.
+    source_size: vec2<f32>,
+    // Size of the output in pixels.
+    output_size: vec2<f32>,
+    // Seconds since the window opened.
+    time: f32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var nearest_sampler: sampler;
+@group(0) @binding(2) var linear_sampler: sampler;
+@group(0) @binding(3) var<uniform> uniforms: Uniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// Covers the output with a single triangle.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
@@ -0,0 +1,96 @@
+//! Where an emulator keeps its settings, and the values its settings files hold.
+//!
+//! The files are TOML, read with [`parse`] into a table of settings. Each emulator looks up its
+//! own keys, with the helpers here for the kinds of values they share, and writes them back with
+//! the helpers that write those values as TOML.
+
+use eframe::egui::Color32;
+use std::convert::TryFrom;
+use std::env;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Returns the directory holding the settings of the emulator `name`: `$XDG_CONFIG_HOME/<name>`,
+/// or `~/.config/<name>`.
+pub fn config_dir(name: &str) -> PathBuf {
+    match (env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) {
+        (Some(config), _) => Path::new(&config).join(name),
+        (None, Some(home)) => Path::new(&home).join(".config").join(name),
+        (None, None) => PathBuf::new(),
+    }
+}
+
+/// Parses a settings file, returning its settings by key.
+pub fn parse(text: &str) -> Result<Table, String> {
+    text.parse::<Table>().map_err(|err| {
+        let start = err.span().map_or(0, |span| span.start);
+        let line = text[..start].matches('\n').count() + 1;
+        format!("line {}: {}", line, err.message())
+    })
+}
+
+/// Returns the error for a setting whose key is unknown or whose value is invalid.
+pub fn invalid(key: &str, value: &Value) -> String {
+    format!("invalid setting `{} = {}`", key, value)
+}
+
+/// Parses a color written as `0xRRGGBB`.
+pub fn parse_color(value: &Value) -> Option<Color32> {
+    let rgb = u32::try_from(value.as_integer()?).ok()?;
+    let [_, r, g, b] = rgb.to_be_bytes();
+    (rgb <= 0xFF_FFFF).then_some(Color32::from_rgb(r, g, b))
+}
+
+/// Writes `color` as `0xRRGGBB`.
+pub fn color(color: Color32) -> String {
+    let [r, g, b, _] = color.to_array();
+    format!("0x{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// Parses an array of strings, such as `["X", "1"]`.
+pub fn parse_strings(value: &Value) -> Option<Vec<&str>> {
+    value.as_array()?.iter().map(Value::as_str).collect()
+}
+
+/// Writes `strings` as an array.
+pub fn strings<'a>(strings: impl Iterator<Item = &'a str>) -> String {
+    Value::Array(strings.map(Value::from).collect()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let table = parse(
+            "red = 0xFF0000 # a comment\nbig = 0x1000000\nbare = \"FF0000\"\n\
+             keys = [\"#\", \"1\"]\nnone = []\nmixed = [\"X\", 1]",
+        )
+        .unwrap();
+        assert_eq!(parse_color(&table["red"]), Some(Color32::RED));
+        assert_eq!(parse_color(&table["big"]), None);
+        assert_eq!(parse_color(&table["bare"]), None);
+        assert_eq!(color(Color32::from_rgb(0x0F, 0x38, 0x0F)), "0x0F380F");
+
+        assert_eq!(parse_strings(&table["keys"]), Some(vec!["#", "1"]));
+        assert_eq!(parse_strings(&table["none"]), Some(vec![]));
+        assert_eq!(parse_strings(&table["mixed"]), None);
+        assert_eq!(parse_strings(&table["bare"]), None);
+        assert_eq!(
+            strings(["crt", "lcd"].iter().copied()),
+            "[\"crt\", \"lcd\"]"
+        );
+        assert_eq!(strings(["a \"b\""].iter().copied()), "['a \"b\"']");
+        assert_eq!(strings(std::iter::empty()), "[]");
+
+        assert_eq!(
+            parse("speed = 700\nspeed = 800"),
+            Err("line 2: duplicate key `speed` in document root".to_string())
+        );
+        assert_eq!(
+            invalid("speed", &Value::from(0)),
+            "invalid setting `speed = 0`"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Views of a processor under the debugger, for any [`Target`].
+
+use eframe::egui::{self, RichText};
+use emu_debug::disasm;
+use emu_debug::Target;
+
+/// Instructions shown by [`disassembly`], from the program counter on.
+pub const DISASSEMBLY_LINES: usize = 32;
+
+/// Bytes of memory on each row of [`memory`].
+const ROW: usize = 16;
+
+/// Shows every register with its value in hex, as wide as the register.
+pub fn registers(ui: &mut egui::Ui, target: &dyn Target) {
+    egui::Grid::new("registers").striped(true).show(ui, |ui| {
+        for (index, register) in target.registers().iter().enumerate() {
+            ui.monospace(register.name);
+            ui.monospace(format!(
+                "{:0width$X}",
+                target.register(index),
+                width = register.size * 2
+            ));
+            ui.end_row();
+        }
+    });
+}
+
+/// Shows the instructions from the program counter on, the one about to run in bold.
+pub fn disassembly(ui: &mut egui::Ui, target: &dyn Target) {
+    let pc = target.program_counter();
+    egui::ScrollArea::vertical()
+        .id_salt("disassembly")
+        .show(ui, |ui| {
+            for line in disasm::listing(target, pc, DISASSEMBLY_LINES) {
+                let text = RichText::new(line.to_string()).monospace();
+                if line.address == pc {
+                    ui.label(text.strong());
+                } else {
+                    ui.label(text);
+                }
+            }
+        });
+}
+
+/// Shows all of memory in hex, a row of 16 bytes at a time, drawing only the rows in view.
+pub fn memory(ui: &mut egui::Ui, target: &dyn Target) {
+    let rows = target.memory_size().div_ceil(ROW);
+    let height = ui.text_style_height(&egui::TextStyle::Monospace);
+    egui::ScrollArea::vertical()
+        .id_salt("memory")
+        .show_rows(ui, height, rows, |ui, range| {
+            for row in range {
+                let start = row * ROW;
+                let end = (start + ROW).min(target.memory_size());
+                let bytes: Vec<String> = (start..end)
+                    .map(|address| format!("{:02X}", target.peek(address as u16)))
+                    .collect();
+                ui.monospace(format!("{:04X}  {}", start, bytes.join(" ")));
+            }
+        });
+}
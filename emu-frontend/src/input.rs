@@ -0,0 +1,69 @@
+//! Host keys bound to the buttons of an emulated machine, such as the 16 keys of a CHIP-8
+//! keypad. The bindings are a slice of keys indexed by button, so that no host key presses two
+//! buttons.
+
+use crate::config;
+use eframe::egui::{InputState, Key};
+
+/// Returns the button that the host key `key` presses.
+pub fn button_for(keys: &[Key], key: Key) -> Option<usize> {
+    keys.iter().position(|&bound| bound == key)
+}
+
+/// Binds the host key `key` to `button`. The button that had it before takes the old key of
+/// `button` instead, so that no host key presses two buttons.
+pub fn bind(keys: &mut [Key], button: usize, key: Key) {
+    if let Some(other) = button_for(keys, key) {
+        keys[other] = keys[button];
+    }
+    keys[button] = key;
+}
+
+/// Reads which buttons are held down from `input` into `held`. Keys held with Ctrl or Alt are
+/// for the shortcuts of the menus, and press no buttons.
+pub fn read(keys: &[Key], input: &InputState, held: &mut [bool]) {
+    let shortcut = input.modifiers.command || input.modifiers.alt;
+    for (held, &key) in held.iter_mut().zip(keys.iter()) {
+        *held = !shortcut && input.key_down(key);
+    }
+}
+
+/// Parses an array of the names egui gives keys, such as `["X", "ArrowUp"]`, which must be
+/// `count` long.
+pub fn parse_keys(value: &toml::Value, count: usize) -> Option<Vec<Key>> {
+    let names = config::parse_strings(value)?;
+    if names.len() != count {
+        return None;
+    }
+    names.into_iter().map(Key::from_name).collect()
+}
+
+/// Writes `keys` as an array of their names.
+pub fn key_names(keys: &[Key]) -> String {
+    config::strings(keys.iter().map(|key| key.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings() {
+        let mut keys = [Key::A, Key::B, Key::C];
+        assert_eq!(button_for(&keys, Key::B), Some(1));
+        assert_eq!(button_for(&keys, Key::D), None);
+
+        bind(&mut keys, 0, Key::D);
+        assert_eq!(keys, [Key::D, Key::B, Key::C]);
+        // Taking the key of another button swaps them.
+        bind(&mut keys, 0, Key::C);
+        assert_eq!(keys, [Key::C, Key::B, Key::D]);
+
+        let names = key_names(&keys);
+        assert_eq!(names, "[\"C\", \"B\", \"D\"]");
+        let table = config::parse(&format!("keys = {}\nnope = [\"C\", \"Nope\"]", names)).unwrap();
+        assert_eq!(parse_keys(&table["keys"], 3), Some(keys.to_vec()));
+        assert_eq!(parse_keys(&table["keys"], 4), None);
+        assert_eq!(parse_keys(&table["nope"], 2), None);
+    }
+}
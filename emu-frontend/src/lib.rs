@@ -0,0 +1,23 @@
+//! The desktop frontend shared by the emulators of this repository, built on egui and wgpu.
+//!
+//! An emulator brings its machine and its settings; this crate brings what every window around a
+//! machine needs, so that each emulator does not grow its own:
+//!
+//! * [`window`]: the native window, which reopens where it was left;
+//! * [`config`]: where settings are kept, and the values of their files;
+//! * [`input`]: host keys bound to the buttons of the machine;
+//! * [`osd`]: text and marks drawn over the game;
+//! * [`shaders`]: post-processing shaders in WGSL, reloaded as their files change;
+//! * [`states`]: where save states go, in slots for each ROM;
+//! * [`debug`]: the registers, disassembly and memory of a processor under the debugger;
+//! * [`video`]: recording the game and its sound with `ffmpeg`, with the `video` feature.
+
+pub mod config;
+pub mod debug;
+pub mod input;
+pub mod osd;
+pub mod shaders;
+pub mod states;
+#[cfg(feature = "video")]
+pub mod video;
+pub mod window;
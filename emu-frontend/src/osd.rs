@@ -0,0 +1,45 @@
+//! Text and marks drawn over the game and the window, with a larger size for those who need it.
+
+use eframe::egui::{self, Align2, Color32, FontId, Id, LayerId, Order, Painter, Pos2, Vec2};
+use std::time::Duration;
+
+/// Returns the size of the text over the game, larger if `large`.
+pub fn text_size(large: bool) -> f32 {
+    if large {
+        28.0
+    } else {
+        16.0
+    }
+}
+
+/// Marks a video being recorded with a red dot and its length, such as `REC 1:05`, in the top
+/// left corner of the game at `corner`.
+pub fn recording(painter: &Painter, corner: Pos2, duration: Duration, large: bool) {
+    let seconds = duration.as_secs();
+    let size = text_size(large);
+    let corner = corner + Vec2::splat(12.0);
+    let dot = size * 0.375;
+    painter.circle_filled(corner + Vec2::new(dot, size / 2.0), dot, Color32::RED);
+    painter.text(
+        corner + Vec2::new(dot * 3.0, 0.0),
+        Align2::LEFT_TOP,
+        format!("REC {}:{:02}", seconds / 60, seconds % 60),
+        FontId::monospace(size),
+        Color32::RED,
+    );
+}
+
+/// Darkens the whole window and shows `text` in the middle of it, above everything else, such as
+/// while a file is dragged over it.
+pub fn cover(ctx: &egui::Context, text: &str, large: bool) {
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("cover")));
+    let screen = ctx.screen_rect();
+    painter.rect_filled(screen, 0.0, Color32::from_black_alpha(192));
+    painter.text(
+        screen.center(),
+        Align2::CENTER_CENTER,
+        text,
+        FontId::proportional(text_size(large) * 1.5),
+        Color32::WHITE,
+    );
+}
@@ -0,0 +1,561 @@
+//! Post-processing shaders the game passes through on its way to the window, written in WGSL.
+//!
+//! Besides the built-in shaders, every `.wgsl` file in the directory given to the [`Library`] is
+//! one, named after the file, and takes the place of a built-in one of the same name. The
+//! directory is checked every second, and shaders are reloaded when their files change. Each
+//! shader is a fragment shader `fs_main` that follows the declarations of [`PRELUDE`], and is
+//! checked before it is used, so that a mistake only leaves the previous version of the shader in
+//! place.
+//!
+//! The selected shaders run one after the other: the first reads the game, at its own size, and
+//! each of the others the output of the one before, at the size it is shown at.
+
+use eframe::egui_wgpu::{self, CallbackResources, CallbackTrait, RenderState, ScreenDescriptor};
+use eframe::wgpu::{self, naga};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The declarations every shader starts with.
+pub const PRELUDE: &str = include_str!("../shaders/prelude.wgsl");
+
+/// The shaders that come with the emulator, by name.
+const BUILT_IN: [(&str, &str); 3] = [
+    ("bloom", include_str!("../shaders/bloom.wgsl")),
+    ("crt", include_str!("../shaders/crt.wgsl")),
+    ("lcd", include_str!("../shaders/lcd.wgsl")),
+];
+
+/// Time between two checks of the shaders directory for changes.
+pub const SCAN_PERIOD: Duration = Duration::from_secs(1);
+
+/// The format of the game as it is uploaded. Shaders work on the colors as they are stored, as
+/// egui does, rather than on linear ones.
+const GAME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Checks `shader` as wgpu would, after the prelude. Returns the whole source of the shader, or
+/// why it is wrong.
+pub fn check(shader: &str) -> Result<String, String> {
+    let source = format!("{}\n{}", PRELUDE, shader);
+    let module =
+        naga::front::wgsl::parse_str(&source).map_err(|err| err.emit_to_string(&source))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|err| err.emit_to_string(&source))?;
+    let fragment = module
+        .entry_points
+        .iter()
+        .any(|entry| entry.name == "fs_main" && entry.stage == naga::ShaderStage::Fragment);
+    if !fragment {
+        return Err("there is no @fragment function `fs_main`".to_string());
+    }
+    Ok(source)
+}
+
+/// A shader, compiled for the window.
+pub struct Pass {
+    pipeline: wgpu::RenderPipeline,
+}
+
+/// Where a shader comes from.
+enum Origin {
+    BuiltIn,
+    /// A file, last changed at the time given.
+    File(Option<SystemTime>),
+}
+
+/// A shader by name, and what became of it.
+struct Shader {
+    origin: Origin,
+    pass: Result<Arc<Pass>, String>,
+}
+
+/// Every shader there is, compiled for the window and kept up to date with its file.
+pub struct Library {
+    render: RenderState,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// The directory holding the user's shaders.
+    dir: PathBuf,
+    shaders: BTreeMap<String, Shader>,
+    next_scan: Instant,
+    /// When the library was created, from which the `time` of the shaders counts.
+    started: Instant,
+}
+
+impl Library {
+    /// Compiles the built-in shaders and those in `dir` for the window `render` draws.
+    pub fn new(render: &RenderState, dir: PathBuf) -> Self {
+        let device = &render.device;
+        let layout = Arc::new(
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("shader"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    sampler_entry(1),
+                    sampler_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            }),
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shader"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        render
+            .renderer
+            .write()
+            .callback_resources
+            .insert(Resources::new(device, layout, render.target_format));
+
+        let mut library = Library {
+            render: render.clone(),
+            pipeline_layout,
+            dir,
+            shaders: BTreeMap::new(),
+            next_scan: Instant::now(),
+            started: Instant::now(),
+        };
+        library.restore_built_in();
+        library
+    }
+
+    /// Compiles the built-in shaders that no file takes the place of.
+    fn restore_built_in(&mut self) {
+        for &(name, source) in BUILT_IN.iter() {
+            if self.shaders.contains_key(name) {
+                continue;
+            }
+            let pass = self.compile(source);
+            self.shaders.insert(
+                name.to_string(),
+                Shader {
+                    origin: Origin::BuiltIn,
+                    pass,
+                },
+            );
+        }
+    }
+
+    /// Checks and compiles `source`.
+    fn compile(&self, source: &str) -> Result<Arc<Pass>, String> {
+        let source = check(source)?;
+        let device = &self.render.device;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("shader"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(self.render.target_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        Ok(Arc::new(Pass { pipeline }))
+    }
+
+    /// Returns the names of every shader, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.shaders.keys().cloned().collect()
+    }
+
+    /// Returns the compiled shaders named `names`, leaving out those that are missing or broken.
+    pub fn passes(&self, names: &[String]) -> Vec<Arc<Pass>> {
+        names
+            .iter()
+            .filter_map(|name| self.shaders.get(name)?.pass.as_ref().ok().cloned())
+            .collect()
+    }
+
+    /// Returns the seconds since the library was created, for the `time` of the shaders.
+    pub fn time(&self) -> f32 {
+        self.started.elapsed().as_secs_f32()
+    }
+
+    /// Reloads the shaders whose files changed since the last time, if it is time to look.
+    /// Returns what happened, such as why a shader could not be loaded.
+    pub fn scan(&mut self, now: Instant) -> Vec<String> {
+        if now < self.next_scan {
+            return Vec::new();
+        }
+        self.next_scan = now + SCAN_PERIOD;
+        let files = files(&self.dir);
+        let mut notes = Vec::new();
+
+        let removed: Vec<String> = self
+            .shaders
+            .iter()
+            .filter(|(name, shader)| match &shader.origin {
+                Origin::File(..) => !files.contains_key(*name),
+                Origin::BuiltIn => false,
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in removed {
+            self.shaders.remove(&name);
+            notes.push(format!("shader {} was removed", name));
+        }
+        // A built-in shader whose replacement was removed comes back.
+        self.restore_built_in();
+
+        for (name, (path, modified)) in files {
+            let unchanged = match self.shaders.get(&name).map(|shader| &shader.origin) {
+                Some(Origin::File(loaded)) => *loaded == modified,
+                _ => false,
+            };
+            if unchanged {
+                continue;
+            }
+            let pass = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|source| self.compile(&source))
+                .map_err(|err| format!("{}: {}", path.display(), err));
+            match &pass {
+                Ok(_) => notes.push(format!("loaded shader {}", name)),
+                Err(err) => notes.push(err.clone()),
+            }
+            // A shader that fails to compile keeps the last version that did.
+            let pass = match (pass, self.shaders.remove(&name)) {
+                (Err(_), Some(Shader { pass: Ok(old), .. })) => Ok(old),
+                (pass, _) => pass,
+            };
+            self.shaders.insert(
+                name,
+                Shader {
+                    origin: Origin::File(modified),
+                    pass,
+                },
+            );
+        }
+        notes
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+/// Returns the shader files in `dir` by name, with when each was last changed.
+fn files(dir: &Path) -> BTreeMap<String, (PathBuf, Option<SystemTime>)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return BTreeMap::new(),
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "wgsl")
+        })
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            Some((name, (path, modified)))
+        })
+        .collect()
+}
+
+/// What the passes need on the GPU, kept with egui's renderer.
+struct Resources {
+    layout: Arc<wgpu::BindGroupLayout>,
+    /// The format of the window, which the passes draw in.
+    format: wgpu::TextureFormat,
+    nearest: wgpu::Sampler,
+    linear: wgpu::Sampler,
+    /// The game, as a texture of its own size.
+    game: Option<wgpu::Texture>,
+    /// The outputs of the passes before the last, used in turn.
+    between: Vec<wgpu::Texture>,
+    /// The uniforms and bind group of each pass of the frame being drawn.
+    bindings: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+}
+
+impl Resources {
+    fn new(
+        device: &wgpu::Device,
+        layout: Arc<wgpu::BindGroupLayout>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let sampler = |filter| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("shader"),
+                mag_filter: filter,
+                min_filter: filter,
+                ..Default::default()
+            })
+        };
+        Resources {
+            layout,
+            format,
+            nearest: sampler(wgpu::FilterMode::Nearest),
+            linear: sampler(wgpu::FilterMode::Linear),
+            game: None,
+            between: Vec::new(),
+            bindings: Vec::new(),
+        }
+    }
+}
+
+/// Returns a texture of `size` and `format` for the passes, reusing `texture` if it is one.
+fn texture(
+    device: &wgpu::Device,
+    texture: Option<wgpu::Texture>,
+    size: [u32; 2],
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    match texture {
+        Some(texture)
+            if texture.width() == size[0]
+                && texture.height() == size[1]
+                && texture.format() == format =>
+        {
+            texture
+        }
+        _ => device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shader"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: usage | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }),
+    }
+}
+
+/// Draws a frame of the game through shaders, as a paint callback of egui.
+pub struct Chain {
+    /// The game's pixels, as RGBA.
+    pub pixels: Vec<u8>,
+    /// The size of the game, in pixels.
+    pub game_size: [u32; 2],
+    /// The size it is shown at, in pixels of the window.
+    pub output_size: [u32; 2],
+    /// The shaders, at least one, in the order they run.
+    pub passes: Vec<Arc<Pass>>,
+    pub time: f32,
+}
+
+impl Chain {
+    /// Returns a paint callback that draws `self` in `rect`.
+    pub fn callback(self, rect: eframe::egui::Rect) -> eframe::egui::PaintCallback {
+        egui_wgpu::Callback::new_paint_callback(rect, self)
+    }
+}
+
+impl CallbackTrait for Chain {
+    /// Uploads the game and runs every pass but the last, each into a texture for the next.
+    fn prepare(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        _screen: &ScreenDescriptor,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &mut CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let resources: &mut Resources = match resources.get_mut() {
+            Some(resources) => resources,
+            None => return Vec::new(),
+        };
+        let game = texture(
+            device,
+            resources.game.take(),
+            self.game_size,
+            GAME_FORMAT,
+            wgpu::TextureUsages::COPY_DST,
+        );
+        queue.write_texture(
+            game.as_image_copy(),
+            &self.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * self.game_size[0]),
+                rows_per_image: None,
+            },
+            game.size(),
+        );
+        let between = self.passes.len().saturating_sub(1).min(2);
+        let mut old = std::mem::take(&mut resources.between).into_iter();
+        resources.between = (0..between)
+            .map(|_| {
+                texture(
+                    device,
+                    old.next(),
+                    self.output_size,
+                    resources.format,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT,
+                )
+            })
+            .collect();
+
+        resources.bindings.clear();
+        for index in 0..self.passes.len() {
+            let (source, source_size) = match index {
+                0 => (&game, self.game_size),
+                _ => (&resources.between[(index - 1) % 2], self.output_size),
+            };
+            let uniforms: [f32; 8] = [
+                self.game_size[0] as f32,
+                self.game_size[1] as f32,
+                source_size[0] as f32,
+                source_size[1] as f32,
+                self.output_size[0] as f32,
+                self.output_size[1] as f32,
+                self.time,
+                0.0,
+            ];
+            let bytes: Vec<u8> = uniforms
+                .iter()
+                .flat_map(|value| value.to_ne_bytes())
+                .collect();
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("shader"),
+                size: bytes.len() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            queue.write_buffer(&buffer, 0, &bytes);
+            let view = source.create_view(&Default::default());
+            let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("shader"),
+                layout: &resources.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&resources.nearest),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&resources.linear),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+            });
+            resources.bindings.push((buffer, group));
+        }
+
+        for (index, pass) in self.passes.iter().enumerate().rev().skip(1).rev() {
+            let target = resources.between[index % 2].create_view(&Default::default());
+            let mut render = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("shader"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render.set_pipeline(&pass.pipeline);
+            render.set_bind_group(0, &resources.bindings[index].1, &[]);
+            render.draw(0..3, 0..1);
+        }
+        resources.game = Some(game);
+        Vec::new()
+    }
+
+    /// Runs the last pass into the window.
+    fn paint(
+        &self,
+        _info: eframe::egui::PaintCallbackInfo,
+        render: &mut wgpu::RenderPass<'static>,
+        resources: &CallbackResources,
+    ) {
+        let resources: &Resources = match resources.get() {
+            Some(resources) => resources,
+            None => return,
+        };
+        if let (Some(pass), Some((_, group))) = (self.passes.last(), resources.bindings.last()) {
+            render.set_pipeline(&pass.pipeline);
+            render.set_bind_group(0, group, &[]);
+            render.draw(0..3, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in() {
+        for &(name, source) in BUILT_IN.iter() {
+            if let Err(err) = check(source) {
+                panic!("{}: {}", name, err);
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(check("fn fs_main() {").is_err());
+        assert!(check("@fragment fn fs_main() -> @location(0) vec4<f32> { return 1.0; }").is_err());
+        assert_eq!(
+            check("fn shade() -> f32 { return uniforms.time; }"),
+            Err("there is no @fragment function `fs_main`".to_string())
+        );
+        let files = files(Path::new("shaders"));
+        assert!(files.contains_key("crt") && !files.contains_key("prelude.wgsl"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Save states kept in numbered slots for each ROM, and an auto-save slot, whatever the system,
+//! with what the battery of its cartridge keeps.
+//!
+//! The states themselves are the system's own bytes, such as those of
+//! `emu_core::Emulator::save_state` and `emu_core::Emulator::battery`; this module only decides
+//! where they go. Each ROM has a directory named after its file and a hash of its contents, so
+//! that two ROMs of the same name do not share slots:
+//!
+//! ```text
+//! $XDG_DATA_HOME/<emulator>/states/<system>/pong-0123456789abcdef/slot0.state
+//! $XDG_DATA_HOME/<emulator>/states/<system>/pong-0123456789abcdef/auto.state
+//! $XDG_DATA_HOME/<emulator>/states/<system>/pong-0123456789abcdef/battery.sav
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the directory holding the data of the emulator `name`, such as its save states:
+/// `$XDG_DATA_HOME/<name>`, or `~/.local/share/<name>`.
+pub fn data_dir(name: &str) -> PathBuf {
+    match (env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+        (Some(data), _) => Path::new(&data).join(name),
+        (None, Some(home)) => Path::new(&home).join(".local/share").join(name),
+        (None, None) => PathBuf::from(format!("{}-data", name)),
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of `rom`, which names the directory of its slots.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    emu_state::hash(rom)
+}
+
+/// The save slots of one ROM, with one of them selected.
+pub struct Slots {
+    /// Directory holding the slots, named after the ROM and its hash.
+    dir: PathBuf,
+    /// The slot used when none is given.
+    pub selected: u8,
+}
+
+impl Slots {
+    /// Returns the slots in `root` of the ROM at `path`, whose contents are `rom`.
+    pub fn new(root: &Path, path: &str, rom: &[u8]) -> Self {
+        let stem = Path::new(path).file_stem().unwrap_or_default();
+        Slots {
+            dir: root.join(format!("{}-{:016x}", stem.to_string_lossy(), rom_hash(rom))),
+            selected: 0,
+        }
+    }
+
+    fn path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("slot{}.state", slot))
+    }
+
+    fn auto_path(&self) -> PathBuf {
+        self.dir.join("auto.state")
+    }
+
+    fn battery_path(&self) -> PathBuf {
+        self.dir.join("battery.sav")
+    }
+
+    /// Writes `contents` to `path` in the directory of the slots, creating it if needed.
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(path, contents))
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Reads the state at `path` and hands it to `restore`. `missing` is the error if there is
+    /// none.
+    fn read<F>(path: &Path, missing: String, restore: F) -> Result<(), String>
+    where
+        F: FnOnce(&[u8]) -> Result<(), String>,
+    {
+        let bytes = fs::read(path).map_err(|_| missing)?;
+        restore(&bytes).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Saves `state` to `slot`, or to the selected slot, and selects it.
+    pub fn save(&mut self, state: &[u8], slot: Option<u8>) -> Result<String, String> {
+        let slot = slot.unwrap_or(self.selected);
+        self.write(&self.path(slot), state)?;
+        self.selected = slot;
+        Ok(format!("saved state to slot {}", slot))
+    }
+
+    /// Hands the state in `slot`, or in the selected slot, to `restore`, and selects it.
+    pub fn load<F>(&mut self, slot: Option<u8>, restore: F) -> Result<String, String>
+    where
+        F: FnOnce(&[u8]) -> Result<(), String>,
+    {
+        let slot = slot.unwrap_or(self.selected);
+        Self::read(&self.path(slot), format!("slot {} is empty", slot), restore)?;
+        self.selected = slot;
+        Ok(format!("loaded state from slot {}", slot))
+    }
+
+    /// Saves `state` to the auto-save slot.
+    pub fn save_auto(&self, state: &[u8]) -> Result<(), String> {
+        self.write(&self.auto_path(), state)
+    }
+
+    /// Returns `true` if the ROM has an auto-save.
+    pub fn has_auto(&self) -> bool {
+        self.auto_path().is_file()
+    }
+
+    /// Hands the state in the auto-save slot to `restore`.
+    pub fn load_auto<F>(&self, restore: F) -> Result<(), String>
+    where
+        F: FnOnce(&[u8]) -> Result<(), String>,
+    {
+        Self::read(&self.auto_path(), "no auto-save".to_string(), restore)
+    }
+
+    /// Saves what the battery of the cartridge keeps.
+    pub fn save_battery(&self, battery: &[u8]) -> Result<(), String> {
+        self.write(&self.battery_path(), battery)
+    }
+
+    /// Returns `true` if the battery of the cartridge was saved.
+    pub fn has_battery(&self) -> bool {
+        self.battery_path().is_file()
+    }
+
+    /// Hands what the battery of the cartridge kept to `restore`.
+    pub fn load_battery<F>(&self, restore: F) -> Result<(), String>
+    where
+        F: FnOnce(&[u8]) -> Result<(), String>,
+    {
+        Self::read(&self.battery_path(), "no battery save".to_string(), restore)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots() {
+        let root = env::temp_dir().join(format!("emu-frontend-states-{}", std::process::id()));
+        let mut slots = Slots::new(&root, "roms/pong.ch8", &[1, 2, 3]);
+        assert!(slots
+            .dir
+            .ends_with(format!("pong-{:016x}", rom_hash(&[1, 2, 3]))));
+
+        let mut loaded = Vec::new();
+        assert_eq!(
+            slots.load(Some(1), |state| {
+                loaded = state.to_vec();
+                Ok(())
+            }),
+            Err("slot 1 is empty".to_string())
+        );
+        assert_eq!(
+            slots.save(&[4, 5], Some(1)),
+            Ok("saved state to slot 1".to_string())
+        );
+        assert_eq!(slots.selected, 1);
+        assert_eq!(
+            slots.load(None, |state| {
+                loaded = state.to_vec();
+                Ok(())
+            }),
+            Ok("loaded state from slot 1".to_string())
+        );
+        assert_eq!(loaded, [4, 5]);
+        let failed = slots.load(None, |_| Err("wrong ROM".to_string()));
+        assert!(failed.unwrap_err().ends_with("slot1.state: wrong ROM"));
+
+        assert!(!slots.has_auto());
+        slots.save_auto(&[6]).unwrap();
+        assert!(slots.has_auto());
+        slots
+            .load_auto(|state| {
+                loaded = state.to_vec();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(loaded, [6]);
+
+        assert!(!slots.has_battery());
+        assert_eq!(
+            slots.load_battery(|_| Ok(())),
+            Err("no battery save".to_string())
+        );
+        slots.save_battery(&[7, 8]).unwrap();
+        assert!(slots.has_battery());
+        slots
+            .load_battery(|battery| {
+                loaded = battery.to_vec();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(loaded, [7, 8]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(rom_hash(&[]), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(rom_hash(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_ne!(rom_hash(&[1, 2]), rom_hash(&[2, 1]));
+    }
+}
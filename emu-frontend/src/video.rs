@@ -0,0 +1,229 @@
+//! Recording the game as a video with `ffmpeg`, which must be installed.
+//!
+//! Every frame the machine runs becomes a frame of the video, with the sound it made during that
+//! frame alongside, so the video plays at the game's own speed however well the emulator kept up
+//! while recording it. `ffmpeg` encodes the frames as they come, while the sound is kept in a file
+//! beside them until the recording stops and the two are joined.
+
+use eframe::egui::ColorImage;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// The program that encodes the video.
+const FFMPEG: &str = "ffmpeg";
+
+/// The least width of the video. It is a whole multiple of the game's, so that players keep its
+/// pixels sharp.
+const MIN_WIDTH: usize = 640;
+
+/// The shape of what a machine gives the recorder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stream {
+    /// The width and height of the game's frames, in pixels.
+    pub size: [usize; 2],
+    /// Frames per second.
+    pub frame_rate: u32,
+    /// Samples of the sound track per second, which has one channel.
+    pub sample_rate: u32,
+}
+
+impl Stream {
+    /// Returns how many times larger than the game the video is.
+    fn scale(&self) -> usize {
+        (MIN_WIDTH + self.size[0] - 1) / self.size[0].max(1)
+    }
+}
+
+/// A kind of video file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Mp4,
+    WebM,
+}
+
+/// Every format, in the order the File menu lists them.
+pub const FORMATS: [Format; 2] = [Format::Mp4, Format::WebM];
+
+impl Format {
+    /// The extension of the file, from which `ffmpeg` picks the codecs.
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Mp4 => "mp4",
+            Format::WebM => "webm",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Mp4 => "MP4",
+            Format::WebM => "WebM",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A video being recorded.
+pub struct Recorder {
+    /// Where the video goes once it is finished.
+    path: PathBuf,
+    /// The frames encoded so far, without sound.
+    frames_path: PathBuf,
+    /// The sound track so far, as raw samples.
+    sound_path: PathBuf,
+    ffmpeg: Child,
+    sound: BufWriter<File>,
+    stream: Stream,
+    /// How loud the sound is, from 0 to 1.
+    volume: f32,
+    frames: u32,
+    /// The pixels of a frame, as RGBA.
+    pixels: Vec<u8>,
+    /// Why recording failed, if it did. Frames after it are dropped.
+    error: Option<String>,
+}
+
+impl Recorder {
+    /// Starts recording a video of `stream`, named after the ROM at `rom`, beside it, such as
+    /// `pong-1.mp4` for `pong.ch8`.
+    pub fn start(rom: &str, format: Format, stream: Stream) -> Result<Recorder, String> {
+        let rom = Path::new(rom);
+        let stem = rom.file_stem().unwrap_or(rom.as_os_str()).to_string_lossy();
+        let dir = rom.parent().unwrap_or_else(|| Path::new(""));
+        let extension = format.extension();
+        let path = (1..)
+            .map(|n| dir.join(format!("{}-{}.{}", stem, n, extension)))
+            .find(|path| !path.exists())
+            .expect("some number is free");
+        let frames_path = path.with_extension(format!("frames.{}", extension));
+        let sound_path = path.with_extension("pcm");
+
+        let sound = File::create(&sound_path)
+            .map_err(|err| format!("{}: {}", sound_path.display(), err))?;
+        let [width, height] = stream.size;
+        let size = format!("{}x{}", width, height);
+        let rate = stream.frame_rate.to_string();
+        let scale = stream.scale();
+        let scale = format!("scale=iw*{}:ih*{}:flags=neighbor", scale, scale);
+        let ffmpeg = Command::new(FFMPEG)
+            .args(["-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+            .args(["-video_size", &size, "-framerate", &rate, "-i", "-"])
+            .args(["-vf", &scale, "-pix_fmt", "yuv420p"])
+            .arg(&frames_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn();
+        let ffmpeg = match ffmpeg {
+            Ok(ffmpeg) => ffmpeg,
+            Err(err) => {
+                let _ = fs::remove_file(&sound_path);
+                return Err(format!("could not run {}: {}", FFMPEG, err));
+            }
+        };
+        Ok(Recorder {
+            path,
+            frames_path,
+            sound_path,
+            ffmpeg,
+            sound: BufWriter::new(sound),
+            stream,
+            volume: 1.0,
+            frames: 0,
+            pixels: Vec::with_capacity(width * height * 4),
+            error: None,
+        })
+    }
+
+    /// Returns how long the video is so far.
+    pub fn duration(&self) -> Duration {
+        let nanos = u64::from(self.frames) * 1_000_000_000 / u64::from(self.stream.frame_rate);
+        Duration::from_nanos(nanos)
+    }
+
+    /// Sets how loud the sound is from now on, in percent.
+    pub fn set_volume(&mut self, percent: u8) {
+        self.volume = f32::from(percent.min(100)) / 100.0;
+    }
+
+    /// Returns `true` if recording failed, and the recorder should be finished to find out why.
+    pub fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Adds a frame showing `image`, with the `samples` of sound made during it.
+    pub fn frame(&mut self, image: &ColorImage, samples: &[i16]) {
+        if self.error.is_none() {
+            if let Err(err) = self.write(image, samples) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    fn write(&mut self, image: &ColorImage, samples: &[i16]) -> Result<(), String> {
+        for &sample in samples {
+            let sample = (f32::from(sample) * self.volume) as i16;
+            self.sound
+                .write_all(&sample.to_le_bytes())
+                .map_err(|err| format!("{}: {}", self.sound_path.display(), err))?;
+        }
+        self.pixels.clear();
+        self.pixels
+            .extend(image.pixels.iter().flat_map(|color| color.to_array()));
+        let stdin = self.ffmpeg.stdin.as_mut().ok_or("ffmpeg has no input")?;
+        stdin
+            .write_all(&self.pixels)
+            .map_err(|err| format!("{} stopped: {}", FFMPEG, err))?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Stops recording and joins the frames with the sound track. Returns what happened, for
+    /// the status bar.
+    pub fn finish(mut self) -> Result<String, String> {
+        drop(self.ffmpeg.stdin.take());
+        let encoded = self.ffmpeg.wait();
+        let result = match (self.error.take(), encoded, self.sound.flush()) {
+            (Some(err), _, _) => Err(err),
+            (None, Err(err), _) => Err(format!("{} failed: {}", FFMPEG, err)),
+            (None, Ok(status), _) if !status.success() => Err(format!(
+                "{} failed to encode the video ({})",
+                FFMPEG, status
+            )),
+            (None, Ok(_), Err(err)) => Err(format!("{}: {}", self.sound_path.display(), err)),
+            (None, Ok(_), Ok(())) => self.join(),
+        };
+        let _ = fs::remove_file(&self.frames_path);
+        let _ = fs::remove_file(&self.sound_path);
+        result
+    }
+
+    /// Joins the encoded frames with the sound track, copying the frames as they are.
+    fn join(&self) -> Result<String, String> {
+        let rate = self.stream.sample_rate.to_string();
+        let status = Command::new(FFMPEG)
+            .args(["-loglevel", "error", "-y"])
+            .arg("-i")
+            .arg(&self.frames_path)
+            .args(["-f", "s16le", "-ar", &rate, "-ac", "1", "-i"])
+            .arg(&self.sound_path)
+            .args(["-c:v", "copy"])
+            .arg(&self.path)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|err| format!("could not run {}: {}", FFMPEG, err))?;
+        if !status.success() {
+            return Err(format!("{} failed to add the sound ({})", FFMPEG, status));
+        }
+        Ok(format!(
+            "recorded {:.1} seconds to {}",
+            self.duration().as_secs_f64(),
+            self.path.display()
+        ))
+    }
+}
@@ -0,0 +1,24 @@
+//! The native window, which keeps its size, position, monitor, full screen state and zoom in a
+//! file of its own, and reopens as it was left.
+
+use eframe::egui::{self, Vec2};
+use eframe::NativeOptions;
+use std::path::PathBuf;
+
+/// Returns the options of a window titled `title`, of `size` the first time it opens, and kept in
+/// the file at `state` after that.
+pub fn options(title: &str, size: impl Into<Vec2>, state: PathBuf) -> NativeOptions {
+    NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title(title)
+            .with_inner_size(size),
+        persistence_path: Some(state),
+        ..Default::default()
+    }
+}
+
+/// Switches the window to full screen, or back.
+pub fn toggle_fullscreen(ctx: &egui::Context) {
+    let fullscreen = ctx.input(|input| input.viewport().fullscreen == Some(true));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(!fullscreen));
+}
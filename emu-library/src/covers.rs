@@ -0,0 +1,88 @@
+//! Cover art, as PNG images. The cover of a ROM is the first of:
+//!
+//! * the one the user picked, kept in the covers directory as `<system>/<CRC-32>.png`;
+//! * an image beside the ROM named as its file, such as `tetris.png` for `tetris.gb`;
+//! * an image in the covers directory named as the game is in its database, as the libretro
+//!   thumbnails are, such as `gameboy/Tetris (World) (Rev 1).png`.
+
+use crate::Entry;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Returns the name of the cover of `name` in the libretro thumbnails, in which the characters
+/// some file systems forbid are underscores.
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if "&*/:`<>?\\|\"".contains(c) { '_' } else { c })
+        .collect();
+    format!("{}.png", name)
+}
+
+/// Returns the path the cover the user picks for `entry` is kept at in `covers`.
+fn picked(covers: &Path, entry: &Entry) -> PathBuf {
+    covers
+        .join(&entry.system)
+        .join(format!("{:08X}.png", entry.crc))
+}
+
+/// Returns the path of the cover of `entry`, looking in the covers directory `covers`, if it has
+/// one.
+pub fn find(covers: &Path, entry: &Entry) -> Option<PathBuf> {
+    let beside = entry.path.with_extension("png");
+    let named = entry
+        .game
+        .as_ref()
+        .map(|game| covers.join(&entry.system).join(file_name(&game.name)));
+    std::iter::once(picked(covers, entry))
+        .chain(Some(beside))
+        .chain(named)
+        .find(|path| path.is_file())
+}
+
+/// Makes the image at `image` the cover of `entry`, copying it into `covers`. Returns where it
+/// was copied to.
+pub fn set(covers: &Path, entry: &Entry, image: &Path) -> io::Result<PathBuf> {
+    let path = picked(covers, entry);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(image, &path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::Game;
+    use crate::tests::entry;
+
+    #[test]
+    fn finds_and_sets() {
+        let dir = std::env::temp_dir().join(format!("emu-library-covers-{}", std::process::id()));
+        let covers = dir.join("covers");
+        fs::create_dir_all(covers.join("gameboy")).unwrap();
+        let mut tetris = entry(
+            dir.join("tetris.gb").to_str().unwrap(),
+            "gameboy",
+            0x46DF_91AD,
+        );
+        tetris.game = Some(Game {
+            name: "Tetris: The Game?".to_string(),
+            ..Game::default()
+        });
+        assert_eq!(find(&covers, &tetris), None);
+
+        let named = covers.join("gameboy").join("Tetris_ The Game_.png");
+        fs::write(&named, "named").unwrap();
+        assert_eq!(find(&covers, &tetris), Some(named));
+        fs::write(dir.join("tetris.png"), "beside").unwrap();
+        assert_eq!(find(&covers, &tetris), Some(dir.join("tetris.png")));
+
+        let picked = set(&covers, &tetris, &dir.join("tetris.png")).unwrap();
+        assert_eq!(picked, covers.join("gameboy").join("46DF91AD.png"));
+        assert_eq!(find(&covers, &tetris), Some(picked));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
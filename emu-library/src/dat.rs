@@ -0,0 +1,301 @@
+//! Databases of known ROMs, in the clrmamepro DAT format No-Intro and others publish theirs in:
+//!
+//! ```text
+//! clrmamepro (
+//!     name "Nintendo - Game Boy"
+//! )
+//!
+//! game (
+//!     name "Tetris (World) (Rev 1)"
+//!     description "Tetris (World) (Rev 1)"
+//!     year "1989"
+//!     manufacturer "Nintendo"
+//!     rom ( name "Tetris (World) (Rev 1).gb" size 32768 crc 46DF91AD )
+//! )
+//! ```
+//!
+//! ROMs are told by the CRC-32 of their contents. Other fields, blocks and hashes are skipped.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// The magic number at the start of iNES files, which databases list without their header.
+const INES_MAGIC: &[u8] = b"NES\x1A";
+
+/// The size of the header of iNES files.
+const INES_HEADER_SIZE: usize = 16;
+
+/// Returns the CRC-32 of `data`, as zip files and ROM databases compute it.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Returns the CRC-32 of `rom` as databases list it: that of its contents, without the header of
+/// an iNES file.
+pub fn rom_crc(rom: &[u8]) -> u32 {
+    if rom.starts_with(INES_MAGIC) && rom.len() > INES_HEADER_SIZE {
+        crc32(&rom[INES_HEADER_SIZE..])
+    } else {
+        crc32(rom)
+    }
+}
+
+/// A game of a database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Game {
+    /// The name of the game, with its region and revision, such as `Tetris (World) (Rev 1)`.
+    pub name: String,
+    pub year: Option<String>,
+    /// Who published it.
+    pub manufacturer: Option<String>,
+}
+
+/// Why a database could not be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The games of a database, by the CRC-32 of their ROMs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Database {
+    games: HashMap<u32, Game>,
+}
+
+/// A value of a DAT file: a word, a quoted string or a block of pairs in parentheses.
+enum Value<'a> {
+    Text(&'a str),
+    Block(Vec<(&'a str, Value<'a>)>),
+}
+
+impl<'a> Value<'a> {
+    /// Returns the text of the first pair called `key` of a block.
+    fn get(&self, key: &str) -> Option<&'a str> {
+        match self {
+            Value::Block(pairs) => pairs.iter().find_map(|(name, value)| match value {
+                Value::Text(text) if *name == key => Some(*text),
+                _ => None,
+            }),
+            Value::Text(_) => None,
+        }
+    }
+}
+
+/// A word or quoted string of a DAT file, or a parenthesis.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    Text(&'a str),
+}
+
+/// Splits a DAT file into [`Token`]s, with the line each is on.
+struct Tokens<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Result<(usize, Token<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.text.len() - self.text.trim_start().len();
+        self.line += self.text[..start].matches('\n').count();
+        self.text = &self.text[start..];
+        let line = self.line;
+        let token = if let Some(rest) = self.text.strip_prefix('"') {
+            let end = match rest.find('"') {
+                Some(end) => end,
+                None => {
+                    self.text = "";
+                    return Some(Err(Error {
+                        line,
+                        message: "unterminated string",
+                    }));
+                }
+            };
+            self.line += rest[..end].matches('\n').count();
+            self.text = &rest[end + 1..];
+            Token::Text(&rest[..end])
+        } else {
+            let (token, end) = match self.text.chars().next()? {
+                '(' => (Token::Open, 1),
+                ')' => (Token::Close, 1),
+                _ => {
+                    let end = self
+                        .text
+                        .find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '"')
+                        .unwrap_or(self.text.len());
+                    (Token::Text(&self.text[..end]), end)
+                }
+            };
+            self.text = &self.text[end..];
+            token
+        };
+        Some(Ok((line, token)))
+    }
+}
+
+impl<'a> Tokens<'a> {
+    /// Reads the pairs of a block up to its closing parenthesis, or to the end of the file at
+    /// the top level.
+    fn block(&mut self, top: bool) -> Result<Vec<(&'a str, Value<'a>)>, Error> {
+        let mut pairs = Vec::new();
+        loop {
+            let key = match self.next().transpose()? {
+                Some((_, Token::Close)) if !top => return Ok(pairs),
+                Some((_, Token::Text(key))) => key,
+                Some((line, _)) => {
+                    return Err(Error {
+                        line,
+                        message: "expected a name",
+                    })
+                }
+                None if top => return Ok(pairs),
+                None => {
+                    return Err(Error {
+                        line: self.line,
+                        message: "unclosed block",
+                    })
+                }
+            };
+            let value = match self.next().transpose()? {
+                Some((_, Token::Open)) => Value::Block(self.block(false)?),
+                Some((line, Token::Close)) => {
+                    return Err(Error {
+                        line,
+                        message: "expected a value",
+                    })
+                }
+                Some((_, Token::Text(text))) => Value::Text(text),
+                None => {
+                    return Err(Error {
+                        line: self.line,
+                        message: "expected a value",
+                    })
+                }
+            };
+            pairs.push((key, value));
+        }
+    }
+}
+
+impl Database {
+    /// Parses a DAT file. Games are the `game`, `machine` and `resource` blocks, and each `rom`
+    /// block of them with a `crc` is a ROM of the game.
+    pub fn parse(text: &str) -> Result<Database, Error> {
+        let mut tokens = Tokens { text, line: 1 };
+        let mut games = HashMap::new();
+        for (kind, block) in tokens.block(true)? {
+            let pairs = match &block {
+                Value::Block(pairs) if matches!(kind, "game" | "machine" | "resource") => pairs,
+                _ => continue,
+            };
+            let game = Game {
+                name: block
+                    .get("description")
+                    .or_else(|| block.get("name"))
+                    .unwrap_or_default()
+                    .to_string(),
+                year: block.get("year").map(str::to_string),
+                manufacturer: block.get("manufacturer").map(str::to_string),
+            };
+            let crcs = pairs
+                .iter()
+                .filter(|(name, _)| *name == "rom")
+                .filter_map(|(_, rom)| u32::from_str_radix(rom.get("crc")?, 16).ok());
+            for crc in crcs {
+                games.entry(crc).or_insert_with(|| game.clone());
+            }
+        }
+        Ok(Database { games })
+    }
+
+    /// Returns the game whose ROM has the CRC-32 `crc`, as [`rom_crc`] computes it.
+    pub fn find(&self, crc: u32) -> Option<&Game> {
+        self.games.get(&crc)
+    }
+
+    /// Returns the number of ROMs known.
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.games.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAT: &str = r#"clrmamepro (
+	name "Nintendo - Game Boy"
+	version 20240101
+)
+
+game (
+	name "Tetris (World) (Rev 1)"
+	description "Tetris (World) (Rev 1)"
+	year "1989"
+	manufacturer "Nintendo"
+	rom ( name "Tetris (World) (Rev 1).gb" size 32768 crc 46DF91AD md5 982ED5D2B12A0377EB14BCDC4123744E )
+)
+
+game (
+	name "Blank (Demo)"
+	rom ( name "Blank.gb" size 0 crc 00000000 )
+	rom ( name "Blank (Alt).gb" size 1 crc d202ef8d )
+)
+"#;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let mut ines = b"NES\x1A".to_vec();
+        ines.resize(INES_HEADER_SIZE, 0xFF);
+        ines.extend_from_slice(b"123456789");
+        assert_eq!(rom_crc(&ines), 0xCBF4_3926);
+        assert_eq!(rom_crc(INES_MAGIC), crc32(INES_MAGIC));
+    }
+
+    #[test]
+    fn parses() {
+        let database = Database::parse(DAT).unwrap();
+        assert_eq!(database.len(), 3);
+        let tetris = database.find(0x46DF_91AD).unwrap();
+        assert_eq!(tetris.name, "Tetris (World) (Rev 1)");
+        assert_eq!(tetris.year.as_deref(), Some("1989"));
+        assert_eq!(tetris.manufacturer.as_deref(), Some("Nintendo"));
+        assert_eq!(database.find(0xD202_EF8D).unwrap().name, "Blank (Demo)");
+        assert_eq!(database.find(0).unwrap().year, None);
+        assert!(database.find(1).is_none());
+        assert!(Database::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects() {
+        let error = |text| Database::parse(text).unwrap_err();
+        assert_eq!(
+            error("game (\n\tname \"Tetris"),
+            Error {
+                line: 2,
+                message: "unterminated string"
+            }
+        );
+        assert_eq!(error("game (\n\tname x\n").line, 3);
+        assert_eq!(error("game ( name )").message, "expected a value");
+        assert_eq!(error("( x )").to_string(), "line 1: expected a name");
+    }
+}
@@ -0,0 +1,121 @@
+//! The file the library is kept in between runs: a line of tab-separated fields for each ROM,
+//! after a line naming the format. Here the tabs are drawn as arrows:
+//!
+//! ```text
+//! emu-library 1
+//! gameboy→46DF91AD→32768→1700000000→/roms/tetris.gb→Tetris (World) (Rev 1)→1989→Nintendo
+//! chip8→392D622C→2→1700000000→/roms/pong.ch8
+//! ```
+//!
+//! The fields are the system, the CRC-32, the size, the time of the last change in seconds since
+//! the Unix epoch and the path, then the name, the year and the publisher if the game is known.
+//! Files whose path has a tab or a line break in it, or is not UTF-8, are not kept.
+
+use crate::dat::Game;
+use crate::Entry;
+use std::path::PathBuf;
+
+/// The first line of the file, which names the format and its version.
+const HEADER: &str = "emu-library 1";
+
+/// Writes `entries` in the format of the index.
+pub fn write(entries: &[Entry]) -> String {
+    let mut text = format!("{}\n", HEADER);
+    for entry in entries {
+        let path = match entry.path.to_str() {
+            Some(path) if !path.contains(['\t', '\n', '\r']) => path,
+            _ => continue,
+        };
+        text += &format!(
+            "{}\t{:08X}\t{}\t{}\t{}",
+            entry.system, entry.crc, entry.size, entry.modified, path
+        );
+        if let Some(game) = &entry.game {
+            let field = |field: &Option<String>| field.clone().unwrap_or_default();
+            text += &format!(
+                "\t{}\t{}\t{}",
+                game.name,
+                field(&game.year),
+                field(&game.manufacturer)
+            );
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Reads the entries of an index written by [`write()`].
+pub fn read(text: &str) -> Result<Vec<Entry>, String> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line) != Some(HEADER) {
+        return Err("not a library index".to_string());
+    }
+    lines
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| {
+            let error = || format!("line {}: invalid entry", number + 1);
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (system, crc, size, modified, path, game) = match fields[..] {
+                [system, crc, size, modified, path] => (system, crc, size, modified, path, None),
+                [system, crc, size, modified, path, name, year, manufacturer] => {
+                    let field = |field: &str| Some(field.to_string()).filter(|f| !f.is_empty());
+                    let game = Game {
+                        name: name.to_string(),
+                        year: field(year),
+                        manufacturer: field(manufacturer),
+                    };
+                    (system, crc, size, modified, path, Some(game))
+                }
+                _ => return Err(error()),
+            };
+            Ok(Entry {
+                path: PathBuf::from(path),
+                system: system.to_string(),
+                size: size.parse().map_err(|_| error())?,
+                modified: modified.parse().map_err(|_| error())?,
+                crc: u32::from_str_radix(crc, 16).map_err(|_| error())?,
+                game,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::entry;
+
+    #[test]
+    fn round_trips() {
+        let mut tetris = entry("/roms/tetris.gb", "gameboy", 0x46DF_91AD);
+        tetris.game = Some(Game {
+            name: "Tetris (World)".to_string(),
+            year: None,
+            manufacturer: Some("Nintendo".to_string()),
+        });
+        let entries = vec![
+            tetris,
+            entry("/roms/pong.ch8", "chip8", 0x392D_622C),
+            entry("/roms/tab\there.ch8", "chip8", 0),
+        ];
+        let text = write(&entries);
+        assert_eq!(
+            text,
+            "emu-library 1\n\
+             gameboy\t46DF91AD\t2\t1700000000\t/roms/tetris.gb\tTetris (World)\t\tNintendo\n\
+             chip8\t392D622C\t2\t1700000000\t/roms/pong.ch8\n"
+        );
+        assert_eq!(read(&text).unwrap(), entries[..2]);
+    }
+
+    #[test]
+    fn rejects() {
+        assert_eq!(read(""), Err("not a library index".to_string()));
+        assert_eq!(
+            read("emu-library 1\nchip8\tXYZ\t2\t0\tpong.ch8\n"),
+            Err("line 2: invalid entry".to_string())
+        );
+        assert!(read("emu-library 1\nchip8\t0\t2\t0\n").is_err());
+        assert_eq!(read("emu-library 1\n\n"), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,280 @@
+//! A library of the ROMs of every system of this repository, found in directories of the user's
+//! choosing and told apart by the databases of each system.
+//!
+//! * [`scan`] walks the directories, asking the frontend which system each file is for, and reads
+//!   again only the files that changed since the last scan;
+//! * [`dat`] reads the databases No-Intro and others publish, which name each ROM by the CRC-32
+//!   of its contents, with its year and publisher;
+//! * [`index`] keeps what was found in a file, so that the library opens without scanning;
+//! * [`covers`] finds the cover art of each game, and keeps the covers the user picks.
+//!
+//! ```
+//! use emu_library::dat::Database;
+//! use emu_library::Library;
+//!
+//! let dir = std::env::temp_dir().join("emu-library-doc");
+//! std::fs::create_dir_all(&dir).unwrap();
+//! std::fs::write(dir.join("pong.ch8"), [0x12, 0x00]).unwrap();
+//! let database = Database::parse(r#"game ( name "Pong" rom ( crc 392D622C ) )"#).unwrap();
+//!
+//! let mut library = Library::new();
+//! library.add_database("chip8", database);
+//! library.scan(&[dir], |path, _| {
+//!     path.extension().filter(|&extension| extension == "ch8").map(|_| "chip8")
+//! });
+//! let found = library.search("pong", Some("chip8"));
+//! assert_eq!(found[0].title(), "Pong");
+//! ```
+
+pub mod covers;
+pub mod dat;
+pub mod index;
+pub mod scan;
+
+use crate::dat::{Database, Game};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use crate::scan::Scan;
+
+/// A ROM of the library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// The system it is for, such as `nes`.
+    pub system: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// When the file was last modified, in seconds since the Unix epoch, which with its size
+    /// tells whether it must be read again.
+    pub modified: u64,
+    /// The CRC-32 of the ROM, as [`dat::rom_crc`] computes it.
+    pub crc: u32,
+    /// The game, if the database of the system knows it.
+    pub game: Option<Game>,
+}
+
+impl Entry {
+    /// Returns the name of the game, or of its file without the extension if it is unknown.
+    pub fn title(&self) -> &str {
+        match &self.game {
+            Some(game) => &game.name,
+            None => self
+                .path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns `true` if every word of `query` is in the title, the file name, the year or the
+    /// publisher, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let mut text = self.title().to_lowercase();
+        if let Some(name) = self.path.file_name() {
+            text.push('\n');
+            text.push_str(&name.to_string_lossy().to_lowercase());
+        }
+        if let Some(game) = &self.game {
+            for field in game.year.iter().chain(game.manufacturer.iter()) {
+                text.push('\n');
+                text.push_str(&field.to_lowercase());
+            }
+        }
+        query
+            .split_whitespace()
+            .all(|word| text.contains(&word.to_lowercase()))
+    }
+}
+
+/// The ROMs found, and the databases of their systems.
+#[derive(Clone, Debug, Default)]
+pub struct Library {
+    /// Sorted by title, then by path.
+    entries: Vec<Entry>,
+    /// By the id of their system.
+    databases: BTreeMap<String, Database>,
+}
+
+impl Library {
+    /// Creates an empty library.
+    pub fn new() -> Self {
+        Library::default()
+    }
+
+    /// Returns every ROM, sorted by title.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Returns the ROMs of `system`, or of every system if `None`, that match `query` as
+    /// [`Entry::matches`] does, sorted by title.
+    pub fn search(&self, query: &str, system: Option<&str>) -> Vec<&Entry> {
+        self.entries
+            .iter()
+            .filter(|entry| system.is_none_or(|system| entry.system == system))
+            .filter(|entry| entry.matches(query))
+            .collect()
+    }
+
+    /// Identifies the ROMs of `system` with `database`, replacing its database if it had one.
+    pub fn add_database(&mut self, system: &str, database: Database) {
+        self.databases.insert(system.to_string(), database);
+        self.identify();
+    }
+
+    /// Reads the databases in `dir`, named after their systems, such as `nes.dat`. Returns why
+    /// any could not be read.
+    pub fn load_databases(&mut self, dir: &Path) -> Vec<String> {
+        let mut errors = Vec::new();
+        let files = match fs::read_dir(dir) {
+            Ok(files) => files,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return errors,
+            Err(err) => return vec![format!("{}: {}", dir.display(), err)],
+        };
+        for file in files.filter_map(Result::ok) {
+            let path = file.path();
+            let system = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(system) if path.extension().is_some_and(|extension| extension == "dat") => {
+                    system.to_string()
+                }
+                _ => continue,
+            };
+            let database = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| Database::parse(&text).map_err(|err| err.to_string()));
+            match database {
+                Ok(database) => {
+                    self.databases.insert(system, database);
+                }
+                Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+        self.identify();
+        errors
+    }
+
+    /// Looks every ROM up in the database of its system, keeping what is known of those without
+    /// one, and sorts them again.
+    fn identify(&mut self) {
+        for entry in &mut self.entries {
+            if let Some(database) = self.databases.get(&entry.system) {
+                entry.game = database.find(entry.crc).cloned();
+            }
+        }
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.entries
+            .sort_by_cached_key(|entry| (entry.title().to_lowercase(), entry.path.clone()));
+    }
+
+    /// Reads a library saved by [`Library::save`], or returns an empty one if there is none.
+    pub fn load(path: &Path) -> Result<Library, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Library::new()),
+            Err(err) => return Err(format!("{}: {}", path.display(), err)),
+        };
+        let entries = index::read(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let mut library = Library {
+            entries,
+            databases: BTreeMap::new(),
+        };
+        library.sort();
+        Ok(library)
+    }
+
+    /// Saves the ROMs to `path`, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let write = || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, index::write(&self.entries))
+        };
+        write().map_err(|err| format!("{}: {}", path.display(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an entry of `system` for the file `path`.
+    pub(crate) fn entry(path: &str, system: &str, crc: u32) -> Entry {
+        Entry {
+            path: PathBuf::from(path),
+            system: system.to_string(),
+            size: 2,
+            modified: 1_700_000_000,
+            crc,
+            game: None,
+        }
+    }
+
+    #[test]
+    fn identifies_and_searches() {
+        let mut library = Library::new();
+        library.entries = vec![
+            entry("roms/zelda.nes", "nes", 1),
+            entry("roms/Tetris.gb", "gameboy", 2),
+            entry("roms/pong.ch8", "chip8", 3),
+        ];
+        library.add_database(
+            "gameboy",
+            Database::parse(
+                r#"game ( name "Tetris (World)" year 1989 manufacturer Nintendo rom ( crc 2 ) )"#,
+            )
+            .unwrap(),
+        );
+        let titles: Vec<&str> = library.entries().iter().map(Entry::title).collect();
+        assert_eq!(titles, ["pong", "Tetris (World)", "zelda"]);
+
+        let titles = |query, system| -> Vec<&str> {
+            library
+                .search(query, system)
+                .into_iter()
+                .map(Entry::title)
+                .collect()
+        };
+        assert_eq!(titles("", None).len(), 3);
+        assert_eq!(titles("nintendo 1989", None), ["Tetris (World)"]);
+        assert_eq!(titles("TETRIS.GB", None), ["Tetris (World)"]);
+        assert_eq!(titles("o", Some("chip8")), ["pong"]);
+        assert!(titles("pong", Some("nes")).is_empty());
+    }
+
+    #[test]
+    fn loads_databases() {
+        let dir = std::env::temp_dir().join(format!("emu-library-dbs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("chip8.dat"),
+            r#"game ( name "Pong" rom ( crc 3 ) )"#,
+        )
+        .unwrap();
+        fs::write(dir.join("nes.dat"), "game (").unwrap();
+        fs::write(dir.join("readme.txt"), "not a database").unwrap();
+
+        let mut library = Library::new();
+        library.entries = vec![entry("pong.ch8", "chip8", 3)];
+        let errors = library.load_databases(&dir);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].ends_with("nes.dat: line 1: unclosed block"));
+        assert_eq!(library.entries()[0].title(), "Pong");
+        assert!(library.load_databases(&dir.join("none")).is_empty());
+
+        let path = dir.join("index").join("library.txt");
+        library.save(&path).unwrap();
+        assert_eq!(Library::load(&path).unwrap().entries(), library.entries());
+        assert!(Library::load(&dir.join("none"))
+            .unwrap()
+            .entries()
+            .is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Finding the ROMs in the library's directories.
+
+use crate::{dat, Entry, Library};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The largest file read as a ROM, larger than any cartridge of the systems of this repository.
+pub const MAX_SIZE: u64 = 16 << 20;
+
+/// How deep directories are searched, which also ends loops of symbolic links.
+const MAX_DEPTH: usize = 16;
+
+/// What a scan found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scan {
+    /// The number of ROMs found.
+    pub found: usize,
+    /// The number of them read, as they are new or changed since the last scan.
+    pub read: usize,
+    /// Why directories and files could not be read.
+    pub errors: Vec<String>,
+}
+
+impl fmt::Display for Scan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "found {} ROMs, {} new or changed", self.found, self.read)?;
+        match self.errors.first() {
+            Some(error) if self.errors.len() > 1 => {
+                write!(f, "; {} and {} more errors", error, self.errors.len() - 1)
+            }
+            Some(error) => write!(f, "; {}", error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Adds the files under `dir`, with their sizes and times of change, to `files`. Hidden files
+/// and directories, whose names start with a dot, are left out.
+fn walk(dir: &Path, depth: usize, files: &mut Vec<(PathBuf, u64, u64)>, errors: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => return errors.push(format!("{}: {}", dir.display(), err)),
+    };
+    for entry in entries.filter_map(Result::ok) {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                errors.push(format!("{}: {}", path.display(), err));
+                continue;
+            }
+        };
+        if metadata.is_dir() {
+            if depth < MAX_DEPTH {
+                walk(&path, depth + 1, files, errors);
+            }
+        } else if metadata.len() <= MAX_SIZE {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs());
+            files.push((path, metadata.len(), modified));
+        }
+    }
+}
+
+impl Library {
+    /// Finds the ROMs in `dirs` and the directories in them, in place of those found before.
+    ///
+    /// `detect` is given the path and the contents of each file, and returns the system it is for,
+    /// or `None` if it cannot tell. A file it cannot tell is for the system whose database knows
+    /// it, if any, which finds the ROMs of systems that have neither headers nor extensions of
+    /// their own. Files of the same size and time of change as when they were last found are not
+    /// read again.
+    pub fn scan<F>(&mut self, dirs: &[PathBuf], mut detect: F) -> Scan
+    where
+        F: FnMut(&Path, &[u8]) -> Option<&'static str>,
+    {
+        let mut files = Vec::new();
+        let mut scan = Scan::default();
+        for dir in dirs {
+            walk(dir, 0, &mut files, &mut scan.errors);
+        }
+        // The same file may be in two of the directories.
+        files.sort();
+        files.dedup_by(|a, b| a.0 == b.0);
+        let mut known: HashMap<PathBuf, Entry> = self
+            .entries
+            .drain(..)
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+        for (path, size, modified) in files {
+            match known.remove(&path) {
+                Some(entry) if entry.size == size && entry.modified == modified => {
+                    self.entries.push(entry);
+                    continue;
+                }
+                _ => {}
+            }
+            let rom = match fs::read(&path) {
+                Ok(rom) => rom,
+                Err(err) => {
+                    scan.errors.push(format!("{}: {}", path.display(), err));
+                    continue;
+                }
+            };
+            let crc = dat::rom_crc(&rom);
+            let system = detect(&path, &rom).map(str::to_string).or_else(|| {
+                self.databases
+                    .iter()
+                    .find(|(_, database)| database.find(crc).is_some())
+                    .map(|(system, _)| system.clone())
+            });
+            if let Some(system) = system {
+                scan.read += 1;
+                self.entries.push(Entry {
+                    path,
+                    system,
+                    size,
+                    modified,
+                    crc,
+                    game: None,
+                });
+            }
+        }
+        scan.found = self.entries.len();
+        self.identify();
+        scan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dat::Database;
+
+    #[test]
+    fn scans() {
+        let dir = std::env::temp_dir().join(format!("emu-library-scan-{}", std::process::id()));
+        let roms = dir.join("roms");
+        fs::create_dir_all(roms.join("nes")).unwrap();
+        fs::create_dir_all(roms.join(".hidden")).unwrap();
+        fs::write(roms.join("pong.ch8"), [0x12, 0x00]).unwrap();
+        fs::write(roms.join("nes").join("game.bin"), b"NES\x1A").unwrap();
+        fs::write(roms.join("readme.txt"), "not a ROM").unwrap();
+        fs::write(roms.join("invaders.rom"), [0xC3]).unwrap();
+        fs::write(roms.join(".hidden").join("secret.ch8"), [0x00]).unwrap();
+
+        let detect = |path: &Path, rom: &[u8]| {
+            if rom.starts_with(b"NES\x1A") {
+                Some("nes")
+            } else if path.extension()? == "ch8" {
+                Some("chip8")
+            } else {
+                None
+            }
+        };
+        let mut library = Library::new();
+        library.add_database(
+            "chip8",
+            Database::parse(r#"game ( name "Pong" rom ( crc 392D622C ) )"#).unwrap(),
+        );
+        library.add_database(
+            "invaders",
+            Database::parse(r#"game ( name "Space Invaders" rom ( crc D06F7C87 ) )"#).unwrap(),
+        );
+        let scan = library.scan(&[roms.clone(), roms.clone()], detect);
+        assert_eq!(scan.to_string(), "found 3 ROMs, 3 new or changed");
+        let titles: Vec<&str> = library.entries().iter().map(Entry::title).collect();
+        assert_eq!(titles, ["game", "Pong", "Space Invaders"]);
+        assert_eq!(library.entries()[0].system, "nes");
+        assert_eq!(library.entries()[2].system, "invaders");
+
+        let scan = library.scan(std::slice::from_ref(&roms), detect);
+        assert_eq!((scan.found, scan.read), (3, 0));
+        fs::remove_file(roms.join("pong.ch8")).unwrap();
+        let scan = library.scan(&[roms, dir.join("none")], detect);
+        assert_eq!((scan.found, scan.read, scan.errors.len()), (2, 0, 1));
+        assert!(scan
+            .to_string()
+            .starts_with("found 2 ROMs, 0 new or changed; "));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! A flat 64KB of RAM and nothing else, which the processors of this repository run tests and
+//! small programs in.
+//!
+//! Every processor crate has its own `Bus` trait and implements it for [`Memory`], so that one
+//! type serves them all:
+//!
+//! ```
+//! use emu_memory::Memory;
+//!
+//! let mut memory = Memory::with_program(0x0200, &[0xA9, 0x2A])?;
+//! assert_eq!(memory.0[0x0201], 0x2A);
+//! assert!(Memory::with_program(0xFFFF, &[0x00, 0x00]).is_err());
+//! # Ok::<(), emu_memory::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` and needs no allocator.
+
+#![no_std]
+
+use core::fmt;
+
+/// The size of memory, the whole of a 16-bit address space.
+pub const SIZE: usize = 0x10000;
+
+/// 64KB of RAM and nothing else, for tests and programs that need no devices.
+#[derive(Clone)]
+pub struct Memory(pub [u8; SIZE]);
+
+impl Memory {
+    /// Returns RAM holding `program` from `address`, and zeros around it, or an error if the
+    /// program runs past the end of memory.
+    pub fn with_program(address: u16, program: &[u8]) -> Result<Self, Overflow> {
+        let start = address as usize;
+        let overflow = Overflow {
+            address,
+            len: program.len(),
+        };
+        let end = start.checked_add(program.len()).ok_or(overflow)?;
+        let mut memory = Memory::default();
+        memory
+            .0
+            .get_mut(start..end)
+            .ok_or(overflow)?
+            .copy_from_slice(program);
+        Ok(memory)
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory([0; SIZE])
+    }
+}
+
+/// A program given to [`Memory::with_program`] that does not fit between its address and the end
+/// of memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overflow {
+    /// Where the program was to start.
+    pub address: u16,
+    /// The length of the program, in bytes.
+    pub len: usize,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a program of {} bytes at {:#06X} runs past the end of memory",
+            self.len, self.address
+        )
+    }
+}
+
+impl core::error::Error for Overflow {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_program() {
+        let memory = Memory::with_program(0xFFFE, &[1, 2]).unwrap();
+        assert_eq!(memory.0[0xFFFE..], [1, 2]);
+        assert_eq!(memory.0[..0xFFFE].iter().max(), Some(&0));
+
+        assert_eq!(
+            Memory::with_program(0xFFFF, &[1, 2]).err(),
+            Some(Overflow {
+                address: 0xFFFF,
+                len: 2
+            })
+        );
+        assert!(Memory::with_program(0, &[0; SIZE + 1]).is_err());
+        assert!(Memory::with_program(0, &[0; SIZE]).is_ok());
+    }
+}
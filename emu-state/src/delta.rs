@@ -0,0 +1,81 @@
+//! Differences between two states, which take a few bytes when little changed between them.
+//!
+//! A difference is the length of the new state, then runs of unchanged bytes and of changed
+//! bytes XORed with the old state, each run preceded by its length.
+
+use alloc::vec::Vec;
+
+/// Encodes how to turn `from` into `to`, for [`patch`].
+pub fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = (to.len() as u32).to_le_bytes().to_vec();
+    let xor: Vec<u8> = to
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ from.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut rest = &xor[..];
+    while !rest.is_empty() {
+        let same = rest.iter().take_while(|&&byte| byte == 0).count();
+        let changed = rest[same..].iter().take_while(|&&byte| byte != 0).count();
+        out.extend_from_slice(&(same as u32).to_le_bytes());
+        out.extend_from_slice(&(changed as u32).to_le_bytes());
+        out.extend_from_slice(&rest[same..same + changed]);
+        rest = &rest[same + changed..];
+    }
+    out
+}
+
+/// Reads a length written by [`diff`].
+fn next(diff: &mut &[u8]) -> usize {
+    let (value, rest) = diff.split_at(4);
+    *diff = rest;
+    u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as usize
+}
+
+/// Applies a difference made by [`diff`] to `from`, returning `to`.
+///
+/// # Panics
+///
+/// Panics if `diff` was not made by [`diff`] from a state as long as `from`.
+pub fn patch(from: &[u8], mut diff: &[u8]) -> Vec<u8> {
+    let len = next(&mut diff);
+    let mut to: Vec<u8> = (0..len)
+        .map(|i| from.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut offset = 0;
+    while !diff.is_empty() {
+        offset += next(&mut diff);
+        let changed = next(&mut diff);
+        for (byte, xor) in to[offset..offset + changed]
+            .iter_mut()
+            .zip(&diff[..changed])
+        {
+            *byte ^= xor;
+        }
+        diff = &diff[changed..];
+        offset += changed;
+    }
+    to
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn differences() {
+        let from = [1, 2, 3, 4, 5, 6];
+        for to in [
+            &[1, 2, 9, 4, 5, 7][..],
+            &[1, 2],
+            &[1, 2, 3, 4, 5, 6, 0, 8],
+            &[],
+        ] {
+            assert_eq!(patch(&from, &diff(&from, to)), to);
+        }
+        // Length, then 2 unchanged bytes and 1 changed one, then 2 and 1 again.
+        assert_eq!(diff(&from, &[1, 2, 9, 4, 5, 7]).len(), 4 + 2 * (8 + 1));
+        // Length, then 6 unchanged bytes.
+        assert_eq!(diff(&from, &from).len(), 4 + 8);
+    }
+}
@@ -0,0 +1,128 @@
+//! Rewinding, save state histories and replay movies, written once against the [`Emulator`]
+//! trait of [`emu_core`] so that every system implementing it has them.
+//!
+//! All of it is built on [`Emulator::save_state`] and [`Emulator::load_state`]:
+//!
+//! * [`delta`] encodes a state as its difference from another, which between two frames is
+//!   mostly unchanged bytes and so takes a few bytes;
+//! * [`rewind`] keeps the states of the last few seconds that way, to go back through them a
+//!   frame at a time;
+//! * [`movie`] records the buttons pressed from a state on, with checksums of the states along
+//!   the way, and plays them back to check that a run is reproduced.
+//!
+//! [`hash`] is the hash systems check their save states are of the same ROM with.
+//!
+//! The crate is `no_std` without its default `std` feature, and only needs an allocator.
+//!
+//! [`Emulator`]: emu_core::Emulator
+//! [`Emulator::save_state`]: emu_core::Emulator::save_state
+//! [`Emulator::load_state`]: emu_core::Emulator::load_state
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod delta;
+pub mod movie;
+pub mod rewind;
+
+/// Hashes `data` with 64-bit FNV-1a, to tell ROMs and states apart.
+pub fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+    use core::time::Duration;
+    use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+
+    /// A system whose state is a counter that each button held adds its number plus one to every
+    /// frame, and a frame count, for testing without a real one. It stops once the counter
+    /// passes 1000.
+    #[derive(Default)]
+    pub(crate) struct Counter {
+        pub(crate) count: u32,
+        pub(crate) frames: u32,
+        held: [bool; 4],
+    }
+
+    impl Emulator for Counter {
+        fn name(&self) -> &'static str {
+            "Counter"
+        }
+
+        fn video(&self) -> VideoSpec {
+            VideoSpec {
+                width: 0,
+                height: 0,
+                frame_period: Duration::from_millis(20),
+            }
+        }
+
+        fn audio(&self) -> AudioSpec {
+            AudioSpec { sample_rate: 1 }
+        }
+
+        fn buttons(&self) -> &'static [&'static str] {
+            &["1", "2", "3", "4"]
+        }
+
+        fn load_rom(&mut self, _rom: &[u8]) -> Result<(), Error> {
+            *self = Counter::default();
+            Ok(())
+        }
+
+        fn reset(&mut self) {
+            *self = Counter::default();
+        }
+
+        fn step_frame(&mut self) -> Result<(), Error> {
+            if self.count > 1000 {
+                return Err(Error::Stopped("the counter overflowed".to_string()));
+            }
+            let held = self.held.iter().enumerate().filter(|(_, &held)| held);
+            self.count += held.map(|(button, _)| button as u32 + 1).sum::<u32>();
+            self.frames += 1;
+            Ok(())
+        }
+
+        fn framebuffer(&self) -> &[u8] {
+            &[]
+        }
+
+        fn pull_audio(&mut self, _samples: &mut Vec<i16>) {}
+
+        fn set_button(&mut self, button: usize, pressed: bool) {
+            if let Some(held) = self.held.get_mut(button) {
+                *held = pressed;
+            }
+        }
+
+        fn save_state(&self) -> Vec<u8> {
+            let mut state = self.count.to_le_bytes().to_vec();
+            state.extend_from_slice(&self.frames.to_le_bytes());
+            state
+        }
+
+        fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+            if state.len() != 8 {
+                return Err(Error::State("not a save state of this machine".to_string()));
+            }
+            self.count = u32::from_le_bytes([state[0], state[1], state[2], state[3]]);
+            self.frames = u32::from_le_bytes([state[4], state[5], state[6], state[7]]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(hash(&[]), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(hash(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_ne!(hash(&[1, 2]), hash(&[2, 1]));
+    }
+}
@@ -0,0 +1,445 @@
+//! Replay movies of any system, for sharing runs and for regression tests that play a whole game.
+//!
+//! A movie is a text file describing a run from a state of the system, one item per line, with
+//! `#` starting a comment:
+//!
+//! ```text
+//! version 1
+//! system NES
+//! rom 3f2a9c0d11e4b7a5    # hash of the ROM
+//! length 900              # frames in the run
+//! state 4e45531a01000000  # the state the run starts from, in as many lines as it takes
+//! 120 press 4             # frame, then what happened at its start
+//! 131 release 4
+//! 180 checksum 8c1e0f7a2b9d3c46
+//! ```
+//!
+//! Buttons are numbered as [`Emulator::set_button`] numbers them. The run starts from a state
+//! rather than from power-on, as systems that seed their randomness from the host do not start
+//! the same way twice, and so that a run can start from a save state.
+//!
+//! Playback is deterministic because the system runs in whole frames, and buttons only change
+//! between frames. The checksums, the [`hash`] of the state at the end of a frame, show where a
+//! replay went out of step. Recording with a checksum every frame pins this down to the exact
+//! frame.
+
+use crate::hash;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+use emu_core::{Emulator, Error};
+
+/// Version of the format, increased whenever its meaning changes.
+const VERSION: u64 = 1;
+
+/// Bytes of the starting state written on each `state` line.
+const STATE_LINE: usize = 32;
+
+/// Frames between two checksums written while recording by default, about a second.
+pub const CHECKSUM_FRAMES: u64 = 60;
+
+/// Something that happens at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Press(usize),
+    Release(usize),
+    /// The checksum of the system at the end of the previous frame.
+    Checksum(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub frame: u64,
+    pub input: Input,
+}
+
+/// A recorded run of a ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// The name of the system, as [`Emulator::name`] gives it.
+    pub system: String,
+    pub rom_hash: u64,
+    /// The state the run starts from.
+    pub state: Vec<u8>,
+    /// Number of frames in the run.
+    pub length: u64,
+    /// Events in order of frame.
+    pub events: Vec<Event>,
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut version = None;
+        let mut system = None;
+        let mut rom_hash = None;
+        let mut length = None;
+        let mut state: Option<Vec<u8>> = None;
+        let mut events: Vec<Event> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+                .collect();
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let hex = |text: &str| {
+                u64::from_str_radix(text, 16)
+                    .map_err(|_| error(format!("invalid hexadecimal number `{}`", text)))
+            };
+            let decimal = |text: &str| {
+                text.parse::<u64>()
+                    .map_err(|_| error(format!("invalid number `{}`", text)))
+            };
+            match words.as_slice() {
+                [] => (),
+                ["version", value] => version = Some(decimal(value)?),
+                ["system", name @ ..] if !name.is_empty() => system = Some(name.join(" ")),
+                ["rom", value] => rom_hash = Some(hex(value)?),
+                ["length", value] => length = Some(decimal(value)?),
+                ["state", bytes] => {
+                    let invalid = || error(format!("invalid state `{}`", bytes));
+                    if bytes.len() % 2 != 0 {
+                        return Err(invalid());
+                    }
+                    let state = state.get_or_insert_with(Vec::new);
+                    for pair in bytes.as_bytes().chunks(2) {
+                        let pair = core::str::from_utf8(pair).map_err(|_| invalid())?;
+                        state.push(u8::from_str_radix(pair, 16).map_err(|_| invalid())?);
+                    }
+                }
+                [frame, kind, value] => {
+                    let frame = decimal(frame)?;
+                    let button = |text: &str| {
+                        text.parse::<usize>()
+                            .map_err(|_| error(format!("invalid button `{}`", text)))
+                    };
+                    let input = match *kind {
+                        "press" => Input::Press(button(value)?),
+                        "release" => Input::Release(button(value)?),
+                        "checksum" => Input::Checksum(hex(value)?),
+                        _ => return Err(error(format!("unknown event `{}`", kind))),
+                    };
+                    if events.last().is_some_and(|last| last.frame > frame) {
+                        return Err(error("events are out of order".to_string()));
+                    }
+                    events.push(Event { frame, input });
+                }
+                _ => return Err(error(format!("invalid line `{}`", line.trim()))),
+            }
+        }
+
+        match version {
+            Some(VERSION) => (),
+            Some(version) => return Err(format!("movie version {} is not supported", version)),
+            None => return Err("missing `version`".to_string()),
+        }
+        let missing = |name: &str| format!("missing `{}`", name);
+        Ok(Movie {
+            system: system.ok_or_else(|| missing("system"))?,
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            length: length.ok_or_else(|| missing("length"))?,
+            state: state.ok_or_else(|| missing("state"))?,
+            events,
+        })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version {}", VERSION)?;
+        writeln!(f, "system {}", self.system)?;
+        writeln!(f, "rom {:016x}", self.rom_hash)?;
+        writeln!(f, "length {}", self.length)?;
+        for line in self.state.chunks(STATE_LINE) {
+            write!(f, "state ")?;
+            for byte in line {
+                write!(f, "{:02x}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        for event in &self.events {
+            match event.input {
+                Input::Press(button) => writeln!(f, "{} press {}", event.frame, button)?,
+                Input::Release(button) => writeln!(f, "{} release {}", event.frame, button)?,
+                Input::Checksum(sum) => writeln!(f, "{} checksum {:016x}", event.frame, sum)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records a movie of a ROM as it is played, one frame at a time.
+pub struct Recorder {
+    movie: Movie,
+    /// The buttons held down during the last frame.
+    held: Vec<bool>,
+    /// Frames between two checksums.
+    checksum_frames: u64,
+}
+
+impl Recorder {
+    /// Starts recording `system`, running the ROM with hash `rom_hash`, from its current state
+    /// with no buttons held, with a checksum every `checksum_frames` frames.
+    pub fn new<E: Emulator + ?Sized>(system: &E, rom_hash: u64, checksum_frames: u64) -> Self {
+        Recorder {
+            movie: Movie {
+                system: system.name().to_string(),
+                rom_hash,
+                state: system.save_state(),
+                length: 0,
+                events: Vec::new(),
+            },
+            held: vec![false; system.buttons().len()],
+            checksum_frames: checksum_frames.max(1),
+        }
+    }
+
+    /// Records the buttons of `held` that changed since the last frame, then runs a frame of
+    /// `system` with them held. Buttons past those of the system are ignored.
+    pub fn frame<E: Emulator + ?Sized>(
+        &mut self,
+        system: &mut E,
+        held: &[bool],
+    ) -> Result<(), Error> {
+        let number = self.movie.length;
+        for (button, (was, &is)) in self.held.iter_mut().zip(held).enumerate() {
+            if *was != is {
+                *was = is;
+                let input = if is {
+                    Input::Press(button)
+                } else {
+                    Input::Release(button)
+                };
+                self.movie.events.push(Event {
+                    frame: number,
+                    input,
+                });
+            }
+        }
+        for (button, &held) in self.held.iter().enumerate() {
+            system.set_button(button, held);
+        }
+        system.step_frame()?;
+        self.movie.length += 1;
+        if self.movie.length.is_multiple_of(self.checksum_frames) {
+            self.movie.events.push(Event {
+                frame: self.movie.length,
+                input: Input::Checksum(hash(&system.save_state())),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Stops recording, returning the movie.
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Plays `movie` on `system`, which must have loaded the ROM with hash `rom_hash`, from the state
+/// the movie starts from. Returns the number of checksums that matched, or where the replay went
+/// out of step.
+pub fn play<E: Emulator + ?Sized>(
+    movie: &Movie,
+    system: &mut E,
+    rom_hash: u64,
+) -> Result<usize, String> {
+    if movie.system != system.name() {
+        return Err(format!(
+            "movie is for the {}, not the {}",
+            movie.system,
+            system.name()
+        ));
+    }
+    if movie.rom_hash != rom_hash {
+        return Err("movie is for a different ROM".to_string());
+    }
+    system
+        .load_state(&movie.state)
+        .map_err(|err| format!("the movie's state: {}", err))?;
+    let buttons = system.buttons().len();
+    let mut held = vec![false; buttons];
+    let mut events = movie.events.iter().peekable();
+    let mut matched = 0;
+    // The frame the last checksum that matched was taken at.
+    let mut last = 0;
+    for number in 0..=movie.length {
+        while let Some(event) = events.next_if(|event| event.frame == number) {
+            let error = |button| format!("frame {}: there is no button {}", number, button);
+            match event.input {
+                Input::Press(button) => *held.get_mut(button).ok_or_else(|| error(button))? = true,
+                Input::Release(button) => {
+                    *held.get_mut(button).ok_or_else(|| error(button))? = false
+                }
+                Input::Checksum(expected) => {
+                    let actual = hash(&system.save_state());
+                    if actual != expected {
+                        return Err(format!(
+                            "frame {}: checksum {:016x} does not match {:016x} from the \
+                             recording\nthe replay went out of step after frame {}",
+                            number, actual, expected, last
+                        ));
+                    }
+                    matched += 1;
+                    last = number;
+                }
+            }
+        }
+        if number < movie.length {
+            for (button, &held) in held.iter().enumerate() {
+                system.set_button(button, held);
+            }
+            system
+                .step_frame()
+                .map_err(|err| format!("frame {}: {}", number, err))?;
+        }
+    }
+    if let Some(event) = events.next() {
+        return Err(format!(
+            "event at frame {} is past the end of the movie",
+            event.frame
+        ));
+    }
+    Ok(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Counter;
+
+    /// Records 130 frames of a counter, holding button 2 from frame 10 to 69, and button 0 from
+    /// 20 on, with a checksum every `checksum_frames` frames.
+    fn record(checksum_frames: u64) -> (Counter, Movie) {
+        let mut counter = Counter::default();
+        let mut recorder = Recorder::new(&counter, 1, checksum_frames);
+        for number in 0..130 {
+            let held = [number >= 20, false, (10..70).contains(&number)];
+            recorder.frame(&mut counter, &held).unwrap();
+        }
+        (counter, recorder.finish())
+    }
+
+    #[test]
+    fn record_and_play() {
+        let (counter, movie) = record(CHECKSUM_FRAMES);
+        let text = movie.to_string();
+        assert!(text.contains("\nsystem Counter\n"), "{}", text);
+        assert!(text.contains("\nstate 0000000000000000\n"), "{}", text);
+        let movie: Movie = text.parse().unwrap();
+        assert_eq!(movie.length, 130);
+        assert_eq!(
+            movie.events[..2],
+            [
+                Event {
+                    frame: 10,
+                    input: Input::Press(2),
+                },
+                Event {
+                    frame: 20,
+                    input: Input::Press(0),
+                }
+            ]
+        );
+        assert!(matches!(movie.events[2].input, Input::Checksum(_)));
+        assert_eq!(movie.events[3].frame, 70);
+
+        // The replay starts from the movie's state, whatever the system was doing.
+        let mut replay = Counter::default();
+        replay.count = 5;
+        replay.set_button(3, true);
+        assert_eq!(play(&movie, &mut replay, 1), Ok(2));
+        assert_eq!((replay.count, replay.frames), (counter.count, 130));
+        assert_eq!(
+            play(&movie, &mut replay, 2),
+            Err("movie is for a different ROM".to_string())
+        );
+        let mut other = movie.clone();
+        other.system = "NES".to_string();
+        assert_eq!(
+            play(&other, &mut replay, 1),
+            Err("movie is for the NES, not the Counter".to_string())
+        );
+
+        // Holding a button a frame longer changes the count.
+        let mut desynced = movie.clone();
+        desynced.events[3].frame += 1;
+        let error = play(&desynced, &mut Counter::default(), 1).unwrap_err();
+        assert!(error.starts_with("frame 120: checksum"), "{}", error);
+        assert!(error.contains("out of step after frame 60"), "{}", error);
+
+        // With a checksum every frame, the replay stops on the frame it went out of step.
+        let (_, mut desynced) = record(1);
+        let release = desynced
+            .events
+            .iter_mut()
+            .find(|event| event.input == Input::Release(2))
+            .unwrap();
+        release.frame += 1;
+        let error = play(&desynced, &mut Counter::default(), 1).unwrap_err();
+        assert!(error.starts_with("frame 71: checksum"), "{}", error);
+        assert!(error.contains("out of step after frame 70"), "{}", error);
+    }
+
+    #[test]
+    fn stops() {
+        // Holding button 3 adds 4 a frame, which overflows the counter after 251 frames.
+        let mut counter = Counter::default();
+        let mut recorder = Recorder::new(&counter, 1, CHECKSUM_FRAMES);
+        let held = [false, false, false, true, true];
+        for _ in 0..251 {
+            recorder.frame(&mut counter, &held).unwrap();
+        }
+        assert!(recorder.frame(&mut counter, &held).is_err());
+        let mut movie = recorder.finish();
+        movie.length += 1;
+        let error = play(&movie, &mut Counter::default(), 1).unwrap_err();
+        assert_eq!(error, "frame 251: the counter overflowed");
+
+        movie.events.push(Event {
+            frame: 0,
+            input: Input::Press(4),
+        });
+        movie.events.rotate_right(1);
+        let error = play(&movie, &mut Counter::default(), 1).unwrap_err();
+        assert_eq!(error, "frame 0: there is no button 4");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let header = "version 1\nsystem COSMAC VIP\nrom 1\nlength 2\nstate 00ff\n";
+        let movie: Movie = header.parse().unwrap();
+        assert_eq!(movie.system, "COSMAC VIP");
+        assert_eq!(movie.state, [0x00, 0xFF]);
+        assert_eq!(
+            format!("{}2 jump 5", header).parse::<Movie>(),
+            Err("line 6: unknown event `jump`".to_string())
+        );
+        assert_eq!(
+            format!("{}2 press A", header).parse::<Movie>(),
+            Err("line 6: invalid button `A`".to_string())
+        );
+        assert_eq!(
+            "version 1\nstate 0f0".parse::<Movie>(),
+            Err("line 2: invalid state `0f0`".to_string())
+        );
+        assert_eq!(
+            "version 2".parse::<Movie>(),
+            Err("movie version 2 is not supported".to_string())
+        );
+        assert_eq!(
+            "version 1\nsystem Counter\nrom 1".parse::<Movie>(),
+            Err("missing `length`".to_string())
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Rewinding, for scrubbing back through the last few seconds of a game.
+//!
+//! A state is saved once per frame. Only the newest is kept in full; every older one is stored
+//! as its [difference](crate::delta) from the state after it, which is mostly unchanged bytes and
+//! so takes a few bytes, whatever the system.
+//!
+//! ```
+//! # use emu_core::Emulator;
+//! use emu_state::rewind::{Rewind, DEFAULT_FRAMES};
+//!
+//! fn frame(system: &mut dyn Emulator, rewind: &mut Rewind, rewinding: bool) {
+//!     if !rewinding || !rewind.rewind(system) {
+//!         rewind.record(system);
+//!         system.step_frame().ok();
+//!     }
+//! }
+//! # let _ = Rewind::new(DEFAULT_FRAMES);
+//! ```
+
+use crate::delta::{diff, patch};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use emu_core::Emulator;
+
+/// Number of frames remembered by default, ten seconds at 60 frames per second.
+pub const DEFAULT_FRAMES: usize = 600;
+
+/// The most recent states of a running game, newest last.
+#[derive(Clone, Debug, Default)]
+pub struct Rewind {
+    /// The newest state, in full.
+    latest: Option<Vec<u8>>,
+    /// Older states, oldest first, each as the difference from the state after it.
+    older: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    /// Creates an empty buffer remembering up to `capacity` states.
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            latest: None,
+            older: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Returns the number of states that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.older.len() + self.latest.is_some() as usize
+    }
+
+    /// Returns whether no states are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Returns the bytes the states take, as they are kept.
+    pub fn size(&self) -> usize {
+        let latest = self.latest.as_ref().map_or(0, Vec::len);
+        latest + self.older.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Forgets every state, such as when another ROM is loaded.
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.older.clear();
+    }
+
+    /// Remembers `state` as the newest, forgetting the oldest one if the buffer is full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(previous) = self.latest.replace(state) {
+            let latest = self.latest.as_deref().unwrap_or_default();
+            self.older.push_back(diff(latest, &previous));
+            if self.len() > self.capacity {
+                self.older.pop_front();
+            }
+        }
+    }
+
+    /// Returns the newest state and forgets it, or `None` if there are none left.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.latest.take()?;
+        self.latest = self.older.pop_back().map(|older| patch(&state, &older));
+        Some(state)
+    }
+
+    /// Remembers the current state of `system`.
+    pub fn record<E: Emulator + ?Sized>(&mut self, system: &E) {
+        self.push(system.save_state());
+    }
+
+    /// Restores `system` to the newest state and forgets it, returning `false` if there are no
+    /// states left.
+    pub fn rewind<E: Emulator + ?Sized>(&mut self, system: &mut E) -> bool {
+        match self.pop() {
+            Some(state) => {
+                // The states were saved by the system for the ROM it runs, unless it was
+                // changed without clearing the buffer, when there is nothing to go back to.
+                let _ = system.load_state(&state);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Counter;
+
+    #[test]
+    fn rewinds() {
+        let mut counter = Counter::default();
+        let mut rewind = Rewind::new(3);
+        counter.set_button(1, true);
+        for _ in 0..5 {
+            rewind.record(&counter);
+            counter.step_frame().unwrap();
+        }
+        assert_eq!(rewind.len(), 3);
+        // Only the low bytes of the counter and the frame count change between frames: the
+        // length, then 2 runs of unchanged bytes and a changed one, and the unchanged rest.
+        assert!(rewind
+            .older
+            .iter()
+            .all(|state| state.len() == 4 + 2 * 9 + 8));
+        assert_eq!(rewind.size(), 8 + 2 * 30);
+
+        for frames in [4, 3, 2] {
+            assert!(rewind.rewind(&mut counter));
+            assert_eq!((counter.frames, counter.count), (frames, frames * 2));
+        }
+        assert!(!rewind.rewind(&mut counter));
+        assert!(rewind.is_empty());
+
+        rewind.push(vec![1, 2, 3]);
+        rewind.clear();
+        assert_eq!(rewind.pop(), None);
+        let mut nothing = Rewind::new(0);
+        nothing.record(&counter);
+        assert!(nothing.is_empty());
+    }
+}
@@ -0,0 +1,812 @@
+//! The application: a menu bar, the game of whichever system the ROM is for, and a status bar.
+
+use crate::library::{Browser, Request};
+use crate::link::{self, Cable};
+use crate::settings::{self, Settings};
+use crate::systems::{self, System, SYSTEMS};
+use eframe::egui::{self, ColorImage, Key, KeyboardShortcut, Modifiers, Sense, TextureHandle};
+use eframe::egui::{TextureOptions, Vec2, ViewportCommand};
+use emu_core::Emulator;
+use emu_debug::debugger::{Debugger, StopReason};
+use emu_frontend::debug;
+use emu_frontend::states::{self, Slots};
+#[cfg(feature = "video")]
+use emu_frontend::video::{self, Format, Recorder, Stream};
+use emu_frontend::window::toggle_fullscreen;
+use emu_frontend::{input, osd};
+use emu_state::rewind::{Rewind, DEFAULT_FRAMES};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How far behind real time the system may fall before it skips ahead instead of catching up,
+/// such as after the window was hidden.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Number of save slots offered in the File menu.
+const SLOTS: u8 = 10;
+
+/// Keyboard shortcuts of the menu items, the same as in `chip8-gui`.
+const OPEN: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::O);
+const RESET: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::R);
+const SAVE_STATE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::S);
+const LOAD_STATE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::L);
+#[cfg(feature = "video")]
+const RECORD: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F9);
+const QUIT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Q);
+const PAUSE: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F5);
+const FULLSCREEN: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Enter);
+
+/// The key that moves the keyboard focus to the menu bar, as in most desktop applications.
+const MENU_KEY: Key = Key::F10;
+
+/// The key that takes the game back a frame at a time while it is held.
+const REWIND_KEY: Key = Key::Backspace;
+
+/// Instructions Step Over runs before giving up on the call returning, such as when it never
+/// does.
+const STEP_OVER_LIMIT: usize = 1_000_000;
+
+/// A ROM running on a system.
+struct Game {
+    system: &'static System,
+    emulator: Box<dyn Emulator>,
+    path: String,
+    settings: Settings,
+    slots: Slots,
+    /// The buttons held down when the window last saw the keyboard.
+    held: Vec<bool>,
+    /// The framebuffer of the last frame.
+    image: ColorImage,
+    /// The sound of the last frame. The window makes no sound of its own, but its video
+    /// recordings do.
+    samples: Vec<i16>,
+    /// Why the program stopped, if it failed.
+    error: Option<String>,
+    paused: bool,
+    /// The states of the last frames, to go back through.
+    rewind: Rewind,
+    /// Whether the rewind key is held, running the frames backwards.
+    rewinding: bool,
+    /// Steps the processor while the game is paused.
+    debugger: Debugger,
+    /// Why the debugger last stopped, if it has run.
+    stop: Option<StopReason>,
+    /// The video being recorded of every frame, if any.
+    #[cfg(feature = "video")]
+    recorder: Option<Recorder>,
+    /// When the next frame is due.
+    next_frame: Instant,
+}
+
+impl Game {
+    /// Loads the ROM at `path` into `system`, or into the system it is for if `None`. Returns
+    /// the game, and what went wrong that did not stop it from loading.
+    fn load(path: &str, system: Option<&'static System>) -> Result<(Game, Vec<String>), String> {
+        let rom = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        let system = system
+            .or_else(|| systems::detect(Path::new(path), &rom))
+            .ok_or_else(|| {
+                format!(
+                    "{}: unknown system; choose one in File → System and open it again",
+                    path
+                )
+            })?;
+        let mut emulator = system.create();
+        emulator
+            .load_rom(&rom)
+            .map_err(|err| format!("{}: {}", path, err))?;
+        let mut notes = Vec::new();
+        let settings = Settings::load(system).unwrap_or_else(|err| {
+            notes.push(err);
+            Settings::new(system)
+        });
+        let states = states::data_dir(settings::NAME).join("states");
+        let video = emulator.video();
+        let mut game = Game {
+            system,
+            path: path.to_string(),
+            slots: Slots::new(&states.join(system.id), path, &rom),
+            held: vec![false; settings.keys.len()],
+            settings,
+            image: ColorImage::new([video.width, video.height], egui::Color32::BLACK),
+            samples: Vec::new(),
+            emulator,
+            error: None,
+            paused: false,
+            rewind: Rewind::new(DEFAULT_FRAMES),
+            rewinding: false,
+            debugger: Debugger::new(),
+            stop: None,
+            #[cfg(feature = "video")]
+            recorder: None,
+            next_frame: Instant::now(),
+        };
+        game.redraw();
+        Ok((game, notes))
+    }
+
+    /// Restores what the battery of the cartridge kept, and resumes the game from its auto-save
+    /// if `resume` and it has one. Returns what happened, for the status bar.
+    fn start(&mut self, resume: bool, mut notes: Vec<String>) -> String {
+        notes.insert(0, format!("loaded {} ({})", self.path, self.system.name));
+        if self.slots.has_battery() {
+            let emulator = &mut self.emulator;
+            if let Err(err) = self.slots.load_battery(|battery| {
+                emulator
+                    .load_battery(battery)
+                    .map_err(|err| err.to_string())
+            }) {
+                notes.push(err);
+            }
+        }
+        if resume && self.slots.has_auto() {
+            let emulator = &mut self.emulator;
+            match self
+                .slots
+                .load_auto(|state| emulator.load_state(state).map_err(|err| err.to_string()))
+            {
+                Ok(()) => notes.push("resumed from the auto-save".to_string()),
+                Err(err) => notes.push(err),
+            }
+            self.redraw();
+        }
+        notes.join("; ")
+    }
+
+    /// Saves what the battery of the cartridge keeps, and auto-saves the game if `auto_save`,
+    /// before it is replaced or the window closes. A program that failed is not auto-saved, as
+    /// resuming into the failure would not help.
+    fn close(&self, auto_save: bool) {
+        if let Some(battery) = self.emulator.battery() {
+            if let Err(err) = self.slots.save_battery(&battery) {
+                tracing::warn!("{}", err);
+            }
+        }
+        if auto_save && self.error.is_none() {
+            if let Err(err) = self.slots.save_auto(&self.emulator.save_state()) {
+                tracing::warn!("{}", err);
+            }
+        }
+    }
+
+    /// Runs the frames due by `now`, unless the program is paused or has stopped, or takes them
+    /// back while rewinding, which also goes back from where a program stopped.
+    fn run(&mut self, now: Instant) {
+        if (self.error.is_some() && !self.rewinding) || self.paused {
+            return;
+        }
+        if now.duration_since(self.next_frame) > MAX_LAG {
+            self.next_frame = now;
+        }
+        let period = self.emulator.video().frame_period;
+        while self.next_frame <= now && (self.error.is_none() || self.rewinding) {
+            if self.rewinding {
+                self.back();
+            } else {
+                self.frame();
+            }
+            self.next_frame += period;
+        }
+    }
+
+    /// Restores the state before the last frame, if one is left.
+    fn back(&mut self) {
+        if self.rewind.rewind(&mut *self.emulator) {
+            self.error = None;
+            self.samples.clear();
+            self.redraw();
+            #[cfg(feature = "video")]
+            if let Some(recorder) = &mut self.recorder {
+                recorder.frame(&self.image, &self.samples);
+            }
+        }
+    }
+
+    /// Runs one frame with the buttons held down, remembering the state before it to rewind to.
+    fn frame(&mut self) {
+        self.rewind.record(&*self.emulator);
+        for (button, &held) in self.held.iter().enumerate() {
+            self.emulator.set_button(button, held);
+        }
+        if let Err(err) = self.emulator.step_frame() {
+            self.error = Some(err.to_string());
+        }
+        self.samples.clear();
+        self.emulator.pull_audio(&mut self.samples);
+        self.redraw();
+        #[cfg(feature = "video")]
+        if let Some(recorder) = &mut self.recorder {
+            recorder.frame(&self.image, &self.samples);
+        }
+    }
+
+    /// Executes an instruction of the paused game, or all of the subroutine it calls if `over`.
+    fn step(&mut self, over: bool) {
+        let debugger = &mut self.debugger;
+        let mut target = match self.emulator.debug() {
+            Some(target) => target,
+            None => return,
+        };
+        let reason = if over {
+            debugger
+                .start_step_over(target.as_mut())
+                .or_else(|| (0..STEP_OVER_LIMIT).find_map(|_| debugger.run_step(target.as_mut())))
+                .unwrap_or_else(|| debugger.interrupt())
+        } else {
+            debugger.step(target.as_mut())
+        };
+        drop(target);
+        self.stop = Some(reason);
+        self.redraw();
+    }
+
+    /// Shows the framebuffer again, for after it changed.
+    fn redraw(&mut self) {
+        let video = self.emulator.video();
+        self.image = ColorImage::from_rgba_unmultiplied(
+            [video.width, video.height],
+            self.emulator.framebuffer(),
+        );
+    }
+}
+
+/// The window and everything in it.
+pub struct App {
+    game: Option<Game>,
+    /// The system ROMs are opened in, or `None` to tell from each ROM.
+    system: Option<&'static System>,
+    /// The game as shown in the window.
+    texture: TextureHandle,
+    /// What last happened, shown in the status bar.
+    status: String,
+    /// The ROMs found in the directories of the library.
+    library: Browser,
+    /// Whether the library window is open.
+    browsing: bool,
+    /// The link cable to the game of another window.
+    cable: Cable,
+    /// Whether the link cable window is open.
+    linking: bool,
+    /// Whether the debugger window is open.
+    debugging: bool,
+    /// The kind of file videos are recorded to.
+    #[cfg(feature = "video")]
+    video_format: Format,
+}
+
+impl App {
+    /// Creates the application, running the ROM at `path` in `system` if one is given, or else
+    /// showing the library if it has any ROMs.
+    pub fn new(ctx: &egui::Context, system: Option<&'static System>, path: Option<&str>) -> Self {
+        let blank = ColorImage::new([1, 1], egui::Color32::BLACK);
+        let (library, notes) = Browser::load();
+        let status = if notes.is_empty() {
+            "Open a ROM, or drop one on the window".to_string()
+        } else {
+            notes.join("; ")
+        };
+        let mut app = App {
+            game: None,
+            system,
+            texture: ctx.load_texture("screen", blank, TextureOptions::NEAREST),
+            status,
+            browsing: path.is_none() && !library.is_empty(),
+            library,
+            cable: Cable::new(),
+            linking: false,
+            debugging: false,
+            #[cfg(feature = "video")]
+            video_format: Format::Mp4,
+        };
+        if let Some(path) = path {
+            app.open(path);
+        }
+        app
+    }
+
+    /// Runs the ROM at `path` in place of the current one, resuming it from its auto-save.
+    /// Shows why in the status bar if it could not be loaded, and keeps the current one.
+    fn open(&mut self, path: &str) {
+        self.replace(path, self.system, true);
+    }
+
+    /// Starts the current ROM over on the same system, without resuming from its auto-save.
+    fn reset(&mut self) {
+        if let Some((path, system)) = self
+            .game
+            .as_ref()
+            .map(|game| (game.path.clone(), game.system))
+        {
+            self.replace(&path, Some(system), false);
+        }
+    }
+
+    /// Replaces the game with the ROM at `path` running in `system`. The battery of the old
+    /// game is saved first, and unless it is started over, the game is auto-saved. A video being recorded of the same system goes on with
+    /// the new game.
+    fn replace(&mut self, path: &str, system: Option<&'static System>, resume: bool) {
+        match Game::load(path, system) {
+            Ok((mut game, notes)) => {
+                if let Some(old) = &self.game {
+                    old.close(resume);
+                }
+                #[cfg(feature = "video")]
+                if let Some(old) = &mut self.game {
+                    if std::ptr::eq(old.system, game.system) {
+                        game.recorder = old.recorder.take();
+                    } else if let Some(recorder) = old.recorder.take() {
+                        if let Err(err) = recorder.finish() {
+                            tracing::warn!("{}", err);
+                        }
+                    }
+                }
+                self.status = game.start(resume, notes);
+                self.game = Some(game);
+            }
+            Err(err) => self.status = err,
+        }
+    }
+
+    /// Asks for a ROM with the system's file dialog, and runs it.
+    fn choose(&mut self) {
+        let mut dialog = rfd::FileDialog::new().set_title("Open ROM");
+        for system in SYSTEMS.iter() {
+            dialog = dialog.add_filter(format!("{} ROMs", system.name), system.extensions);
+        }
+        dialog = dialog.add_filter("All files", &["*"]);
+        if let Some(dir) = self
+            .game
+            .as_ref()
+            .and_then(|game| Path::new(&game.path).parent())
+        {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(path) = dialog.pick_file() {
+            self.open(&path.to_string_lossy());
+        }
+    }
+
+    /// Runs a ROM dropped onto the window, and shows where it can be dropped while one is
+    /// dragged over it.
+    fn drop_target(&mut self, ctx: &egui::Context) {
+        let (hovering, dropped) = ctx.input(|input| {
+            let dropped = input
+                .raw
+                .dropped_files
+                .iter()
+                .find_map(|file| file.path.clone());
+            (!input.raw.hovered_files.is_empty(), dropped)
+        });
+        if let Some(path) = dropped {
+            self.open(&path.to_string_lossy());
+        }
+        if hovering {
+            osd::cover(ctx, "Drop a ROM to run it", false);
+        }
+    }
+
+    /// Saves the game to its selected slot if `save`, or else restores it from there.
+    fn state(&mut self, save: bool) {
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+        let result = if save {
+            game.slots.save(&game.emulator.save_state(), None)
+        } else {
+            let emulator = &mut game.emulator;
+            let loaded = game.slots.load(None, |state| {
+                emulator.load_state(state).map_err(|err| err.to_string())
+            });
+            if loaded.is_ok() {
+                game.error = None;
+                game.redraw();
+            }
+            loaded
+        };
+        self.status = result.unwrap_or_else(|err| err);
+    }
+
+    /// Pauses the running game, or continues the paused one from now on.
+    fn toggle_pause(&mut self) {
+        if let Some(game) = &mut self.game {
+            game.paused = !game.paused;
+            game.stop = None;
+            game.next_frame = Instant::now();
+        }
+    }
+
+    /// Starts recording a video of the game, or stops and saves the one being recorded.
+    #[cfg(feature = "video")]
+    fn toggle_recording(&mut self) {
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+        self.status = match game.recorder.take() {
+            Some(recorder) => recorder.finish().unwrap_or_else(|err| err),
+            None => {
+                let (video, audio) = (game.emulator.video(), game.emulator.audio());
+                let stream = Stream {
+                    size: [video.width, video.height],
+                    frame_rate: (1.0 / video.frame_period.as_secs_f64()).round() as u32,
+                    sample_rate: audio.sample_rate,
+                };
+                match Recorder::start(&game.path, self.video_format, stream) {
+                    Ok(recorder) => {
+                        game.recorder = Some(recorder);
+                        "recording a video; F9 stops".to_string()
+                    }
+                    Err(err) => err,
+                }
+            }
+        };
+    }
+
+    #[cfg(feature = "video")]
+    fn video_menu(&mut self, ui: &mut egui::Ui) {
+        let recording = self.game.as_ref().map(|game| game.recorder.is_some());
+        let label = if recording == Some(true) {
+            "Stop Recording"
+        } else {
+            "Record Video"
+        };
+        if ui
+            .add_enabled(recording.is_some(), item(ui, label, &RECORD))
+            .clicked()
+        {
+            self.toggle_recording();
+            ui.close_menu();
+        }
+        ui.menu_button("Video Format", |ui| {
+            for format in video::FORMATS.iter() {
+                ui.radio_value(&mut self.video_format, *format, format.to_string());
+            }
+        });
+    }
+
+    fn menu_bar(&mut self, ui: &mut egui::Ui) {
+        egui::menu::bar(ui, |ui| {
+            let file = ui.menu_button("File", |ui| {
+                if ui.add(item(ui, "Open ROM…", &OPEN)).clicked() {
+                    ui.close_menu();
+                    self.choose();
+                }
+                if ui.button("Library…").clicked() {
+                    self.browsing = true;
+                    ui.close_menu();
+                }
+                if ui.button("Link Cable…").clicked() {
+                    self.linking = true;
+                    ui.close_menu();
+                }
+                ui.menu_button("System", |ui| {
+                    ui.radio_value(&mut self.system, None, "Detect from the ROM");
+                    for system in SYSTEMS.iter() {
+                        ui.radio_value(&mut self.system, Some(system), system.name);
+                    }
+                });
+                let loaded = self.game.is_some();
+                if ui.add_enabled(loaded, item(ui, "Reset", &RESET)).clicked() {
+                    self.reset();
+                    ui.close_menu();
+                }
+                ui.separator();
+                if ui
+                    .add_enabled(loaded, item(ui, "Save State", &SAVE_STATE))
+                    .clicked()
+                {
+                    self.state(true);
+                    ui.close_menu();
+                }
+                if ui
+                    .add_enabled(loaded, item(ui, "Load State", &LOAD_STATE))
+                    .clicked()
+                {
+                    self.state(false);
+                    ui.close_menu();
+                }
+                if let Some(game) = &mut self.game {
+                    ui.menu_button("Slot", |ui| {
+                        for slot in 0..SLOTS {
+                            ui.radio_value(&mut game.slots.selected, slot, slot.to_string());
+                        }
+                    });
+                }
+                #[cfg(feature = "video")]
+                {
+                    ui.separator();
+                    self.video_menu(ui);
+                }
+                ui.separator();
+                if ui.add(item(ui, "Quit", &QUIT)).clicked() {
+                    ui.ctx().send_viewport_cmd(ViewportCommand::Close);
+                }
+            });
+            if ui.input_mut(|input| input.consume_key(Modifiers::NONE, MENU_KEY)) {
+                file.response.request_focus();
+            }
+            ui.menu_button("View", |ui| {
+                let paused = self.game.as_ref().map(|game| game.paused);
+                let label = if paused == Some(true) {
+                    "Continue"
+                } else {
+                    "Pause"
+                };
+                if ui
+                    .add_enabled(paused.is_some(), item(ui, label, &PAUSE))
+                    .clicked()
+                {
+                    self.toggle_pause();
+                    ui.close_menu();
+                }
+                ui.checkbox(&mut self.debugging, "Debugger");
+                ui.separator();
+                let fullscreen = ui.input(|input| input.viewport().fullscreen == Some(true));
+                let label = if fullscreen {
+                    "Leave Full Screen"
+                } else {
+                    "Full Screen"
+                };
+                if ui.add(item(ui, label, &FULLSCREEN)).clicked() {
+                    toggle_fullscreen(ui.ctx());
+                    ui.close_menu();
+                }
+                egui::gui_zoom::zoom_menu_buttons(ui);
+            });
+        });
+    }
+
+    fn status_bar(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| match &self.game {
+            Some(game) if game.rewinding => {
+                let period = game.emulator.video().frame_period;
+                ui.strong("rewinding");
+                ui.label(format!(
+                    "{:.1} s left",
+                    (period * game.rewind.len() as u32).as_secs_f64()
+                ));
+            }
+            Some(Game {
+                error: Some(err), ..
+            }) => {
+                ui.colored_label(ui.visuals().error_fg_color, format!("stopped: {}", err));
+            }
+            Some(Game { paused: true, .. }) => {
+                ui.strong("paused");
+                ui.label(&self.status);
+            }
+            _ => {
+                ui.label(&self.status);
+            }
+        });
+    }
+
+    /// Shows the game as large as fits, at a whole multiple of its size when it can be, and
+    /// marked while a video of it is recorded.
+    fn screen(&self, ui: &mut egui::Ui) {
+        let game = match &self.game {
+            Some(game) => game,
+            None => return,
+        };
+        let [width, height] = game.image.size;
+        let game_size = Vec2::new(width as f32, height as f32);
+        let available = ui.available_size();
+        let scale = (available.x / game_size.x).min(available.y / game_size.y);
+        let scale = if scale >= 1.0 { scale.floor() } else { scale };
+        let image = ui
+            .centered_and_justified(|ui| {
+                ui.add(
+                    egui::Image::new(&self.texture)
+                        .fit_to_exact_size(game_size * scale)
+                        .sense(Sense::hover()),
+                )
+            })
+            .inner;
+        #[cfg(feature = "video")]
+        let recording = game.recorder.as_ref().map(Recorder::duration);
+        #[cfg(not(feature = "video"))]
+        let recording = None;
+        if let Some(duration) = recording {
+            osd::recording(ui.painter(), image.rect.left_top(), duration, false);
+        }
+    }
+
+    /// Shows the library, and runs the ROM chosen in it.
+    fn library(&mut self, ctx: &egui::Context) {
+        let library = &mut self.library;
+        let request = egui::Window::new("Library")
+            .open(&mut self.browsing)
+            .default_size([720.0, 420.0])
+            .show(ctx, |ui| library.show(ui))
+            .and_then(|response| response.inner.flatten());
+        match request {
+            Some(Request::Open(path, system)) => self.replace(&path, Some(system), true),
+            Some(Request::Status(status)) => self.status = status,
+            None => {}
+        }
+    }
+
+    /// Shows the link cable, and plugs the game into the other window when it connects.
+    fn link(&mut self, ctx: &egui::Context) {
+        let emulator = self
+            .game
+            .as_mut()
+            .map(|game| &mut *game.emulator as &mut dyn Emulator);
+        if let Some(status) = self.cable.accept(emulator) {
+            self.status = status;
+        }
+        if self.cable.is_listening() {
+            ctx.request_repaint_after(link::ACCEPT_PERIOD);
+        }
+        let cable = &mut self.cable;
+        let game = &mut self.game;
+        let status = egui::Window::new("Link Cable")
+            .open(&mut self.linking)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let emulator = game
+                    .as_mut()
+                    .map(|game| &mut *game.emulator as &mut dyn Emulator);
+                cable.show(ui, emulator)
+            })
+            .and_then(|response| response.inner.flatten());
+        if let Some(status) = status {
+            self.status = status;
+        }
+    }
+
+    /// Shows the registers, instructions and memory of the game's processor, which can be stepped
+    /// through while the game is paused.
+    fn debugger(&mut self, ctx: &egui::Context) {
+        let game = match &mut self.game {
+            Some(game) => game,
+            None => return,
+        };
+        egui::Window::new("Debugger")
+            .open(&mut self.debugging)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                if game.emulator.debug().is_none() {
+                    ui.weak(format!("{} has no debugger", game.system.name));
+                    return;
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(game.paused, egui::Button::new("Step"))
+                        .clicked()
+                    {
+                        game.step(false);
+                    }
+                    if ui
+                        .add_enabled(game.paused, egui::Button::new("Step Over"))
+                        .clicked()
+                    {
+                        game.step(true);
+                    }
+                    match &game.stop {
+                        Some(reason) => ui.label(reason.to_string()),
+                        None if game.paused => ui.weak("paused"),
+                        None => ui.weak("pause the game to step through it"),
+                    };
+                });
+                ui.separator();
+                let target = match game.emulator.debug() {
+                    Some(target) => target,
+                    None => return,
+                };
+                ui.horizontal_top(|ui| {
+                    ui.vertical(|ui| debug::registers(ui, &*target));
+                    ui.separator();
+                    ui.vertical(|ui| {
+                        ui.set_max_height(240.0);
+                        debug::disassembly(ui, &*target);
+                    });
+                });
+                ui.separator();
+                debug::memory(ui, &*target);
+            });
+    }
+
+    /// Does what the keyboard shortcuts pressed since the last frame ask for.
+    fn shortcuts(&mut self, ctx: &egui::Context) {
+        let pressed =
+            |shortcut: &KeyboardShortcut| ctx.input_mut(|input| input.consume_shortcut(shortcut));
+        let loaded = self.game.is_some();
+        if pressed(&OPEN) {
+            self.choose();
+        }
+        if pressed(&RESET) {
+            self.reset();
+        }
+        if pressed(&SAVE_STATE) && loaded {
+            self.state(true);
+        }
+        if pressed(&LOAD_STATE) && loaded {
+            self.state(false);
+        }
+        #[cfg(feature = "video")]
+        if pressed(&RECORD) {
+            self.toggle_recording();
+        }
+        if pressed(&QUIT) {
+            ctx.send_viewport_cmd(ViewportCommand::Close);
+        }
+        if pressed(&PAUSE) {
+            self.toggle_pause();
+        }
+        if pressed(&FULLSCREEN) {
+            toggle_fullscreen(ctx);
+        }
+    }
+
+    /// Runs the frames that are due and shows everything.
+    fn show(&mut self, ctx: &egui::Context) {
+        self.drop_target(ctx);
+        if !ctx.wants_keyboard_input() {
+            self.shortcuts(ctx);
+        }
+        // The buttons only see the keyboard while no control has it, such as a menu reached
+        // with F10.
+        let typing = ctx.memory(|memory| memory.focused().is_some());
+
+        let now = Instant::now();
+        if let Some(game) = &mut self.game {
+            if typing {
+                game.held.iter_mut().for_each(|held| *held = false);
+                game.rewinding = false;
+            } else {
+                let keys = &game.settings.keys;
+                let held = &mut game.held;
+                ctx.input(|input| input::read(keys, input, held));
+                game.rewinding = ctx.input(|input| input.key_down(REWIND_KEY));
+            }
+            game.run(now);
+            #[cfg(feature = "video")]
+            if game.recorder.as_ref().is_some_and(Recorder::failed) {
+                if let Some(recorder) = game.recorder.take() {
+                    self.status = recorder.finish().unwrap_or_else(|err| err);
+                }
+            }
+            self.texture
+                .set(game.image.clone(), TextureOptions::NEAREST);
+            if (game.error.is_none() || game.rewinding) && !game.paused {
+                ctx.request_repaint_after(game.next_frame.saturating_duration_since(now));
+            }
+        }
+
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| self.menu_bar(ui));
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| self.status_bar(ui));
+        self.library(ctx);
+        self.link(ctx);
+        self.debugger(ctx);
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none().fill(egui::Color32::BLACK))
+            .show(ctx, |ui| self.screen(ui));
+    }
+}
+
+/// A menu item, showing its keyboard shortcut.
+fn item(ui: &egui::Ui, text: &str, shortcut: &KeyboardShortcut) -> egui::Button<'static> {
+    egui::Button::new(text.to_string()).shortcut_text(ui.ctx().format_shortcut(shortcut))
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.show(ctx);
+    }
+
+    fn on_exit(&mut self) {
+        if let Some(game) = &mut self.game {
+            game.close(true);
+            #[cfg(feature = "video")]
+            if let Some(recorder) = game.recorder.take() {
+                match recorder.finish() {
+                    Ok(status) => tracing::info!("{}", status),
+                    Err(err) => tracing::warn!("{}", err),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! The library window: the ROMs found in the directories of `library.toml` in the configuration
+//! directory, to search, filter by system and open.
+//!
+//! ```toml
+//! dirs = ["/home/me/roms", "/mnt/games"]
+//! ```
+//!
+//! The databases of each system are read from `databases/<system>.dat` beside it, such as
+//! `databases/nes.dat`. What was found is kept in `library.txt` in the data directory, and the
+//! covers picked in `covers`, where the libretro thumbnails of each system can go too.
+
+use crate::settings::NAME;
+use crate::systems::{self, System, SYSTEMS};
+use eframe::egui::{self, ColorImage, TextureHandle, TextureOptions};
+use emu_frontend::config;
+use emu_frontend::states::data_dir;
+use emu_library::{covers, Entry, Library};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The size covers are shown at, at most.
+const COVER_SIZE: f32 = 200.0;
+
+/// What the library window asks of the application.
+pub enum Request {
+    /// Run the ROM at the path on the system.
+    Open(String, &'static System),
+    /// Show the text in the status bar.
+    Status(String),
+}
+
+/// The library, and what the window shows of it.
+pub struct Browser {
+    library: Library,
+    /// The directories searched for ROMs.
+    dirs: Vec<PathBuf>,
+    /// The words the ROMs shown match.
+    query: String,
+    /// The system of the ROMs shown, or `None` for all of them.
+    system: Option<&'static System>,
+    /// The ROM selected, by its path.
+    selected: Option<PathBuf>,
+    details: Details,
+}
+
+/// The details of the selected ROM.
+#[derive(Default)]
+struct Details {
+    /// The cover last loaded, by the path of its image.
+    cover: Option<(PathBuf, TextureHandle)>,
+}
+
+impl Browser {
+    /// Opens the library as it was left, with the databases of its systems. Returns it, and what
+    /// went wrong that did not stop it from opening.
+    pub fn load() -> (Browser, Vec<String>) {
+        let mut notes = Vec::new();
+        let dirs = read_dirs().unwrap_or_else(|err| {
+            notes.push(err);
+            Vec::new()
+        });
+        let mut library = Library::load(&index_path()).unwrap_or_else(|err| {
+            notes.push(err);
+            Library::new()
+        });
+        notes.extend(library.load_databases(&config::config_dir(NAME).join("databases")));
+        let browser = Browser {
+            library,
+            dirs,
+            query: String::new(),
+            system: None,
+            selected: None,
+            details: Details::default(),
+        };
+        (browser, notes)
+    }
+
+    /// Returns `true` if no ROMs have been found.
+    pub fn is_empty(&self) -> bool {
+        self.library.entries().is_empty()
+    }
+
+    /// Searches the directories for ROMs again, and keeps what was found. Returns what happened,
+    /// for the status bar.
+    fn rescan(&mut self) -> String {
+        let scan = self.library.scan(&self.dirs, |path, rom| {
+            systems::detect(path, rom).map(|system| system.id)
+        });
+        match self.library.save(&index_path()) {
+            Ok(()) => format!("library: {}", scan),
+            Err(err) => format!("library: {}; {}", scan, err),
+        }
+    }
+
+    /// Asks for a directory of ROMs with the system's file dialog, adds it to the library and
+    /// searches it.
+    fn add_dir(&mut self) -> Option<String> {
+        let dir = rfd::FileDialog::new()
+            .set_title("Add Folder to Library")
+            .pick_folder()?;
+        if self.dirs.contains(&dir) {
+            return Some(self.rescan());
+        }
+        self.dirs.push(dir);
+        Some(match write_dirs(&self.dirs) {
+            Ok(()) => self.rescan(),
+            Err(err) => err,
+        })
+    }
+
+    /// Shows the library, and returns what the user asked of the application, if anything.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<Request> {
+        let mut request = None;
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.query).hint_text("Search"));
+            egui::ComboBox::from_id_salt("library system")
+                .selected_text(self.system.map_or("All systems", |system| system.name))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.system, None, "All systems");
+                    for system in SYSTEMS.iter() {
+                        ui.selectable_value(&mut self.system, Some(system), system.name);
+                    }
+                });
+            if ui.button("Add Folder…").clicked() {
+                request = self.add_dir().map(Request::Status);
+            }
+            if ui
+                .add_enabled(!self.dirs.is_empty(), egui::Button::new("Rescan"))
+                .clicked()
+            {
+                request = Some(Request::Status(self.rescan()));
+            }
+        });
+        ui.separator();
+        if self.is_empty() {
+            ui.weak("Add a folder of ROMs to fill the library.");
+            return request;
+        }
+
+        let entries = self
+            .library
+            .search(&self.query, self.system.map(|system| system.id));
+        let chosen = &mut self.selected;
+        let details = &mut self.details;
+        let selected = entries
+            .iter()
+            .find(|entry| Some(&entry.path) == chosen.as_ref())
+            .copied();
+        egui::SidePanel::right("library details")
+            .resizable(false)
+            .exact_width(COVER_SIZE + 16.0)
+            .show_inside(ui, |ui| {
+                if let Some(asked) = selected.and_then(|entry| details.show(ui, entry)) {
+                    request = Some(asked);
+                }
+            });
+        let mut open = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("library").striped(true).show(ui, |ui| {
+                for entry in &entries {
+                    let system = systems::find(&entry.system);
+                    let is_chosen = Some(&entry.path) == chosen.as_ref();
+                    let label = ui.selectable_label(is_chosen, entry.title());
+                    if label.clicked() {
+                        *chosen = Some(entry.path.clone());
+                    }
+                    if label.double_clicked() {
+                        open = system.map(|system| (entry.path.clone(), system));
+                    }
+                    ui.label(system.map_or(entry.system.as_str(), |system| system.name));
+                    let game = entry.game.as_ref();
+                    ui.label(
+                        game.and_then(|game| game.year.as_deref())
+                            .unwrap_or_default(),
+                    );
+                    ui.label(
+                        game.and_then(|game| game.manufacturer.as_deref())
+                            .unwrap_or_default(),
+                    );
+                    ui.end_row();
+                }
+            });
+        });
+        if let Some((path, system)) = open {
+            request = Some(Request::Open(path.to_string_lossy().into_owned(), system));
+        }
+        request
+    }
+}
+
+impl Details {
+    /// Shows the cover and the file of `entry`, with buttons to open it and to pick its cover.
+    fn show(&mut self, ui: &mut egui::Ui, entry: &Entry) -> Option<Request> {
+        let mut request = None;
+        if let Some(texture) = self.cover(ui.ctx(), entry) {
+            ui.add(egui::Image::new(&texture).max_size(egui::Vec2::splat(COVER_SIZE)));
+        }
+        ui.strong(entry.title());
+        ui.label(entry.path.display().to_string());
+        ui.monospace(format!("CRC32 {:08X}, {} bytes", entry.crc, entry.size));
+        ui.horizontal(|ui| {
+            if let Some(system) = systems::find(&entry.system) {
+                if ui.button("Open").clicked() {
+                    let path = entry.path.to_string_lossy().into_owned();
+                    request = Some(Request::Open(path, system));
+                }
+            }
+            if ui.button("Set Cover…").clicked() {
+                self.cover = None;
+                request = set_cover(entry).map(Request::Status);
+            }
+        });
+        request
+    }
+
+    /// Returns the cover of `entry`, loading it if it is not the one last shown.
+    fn cover(&mut self, ctx: &egui::Context, entry: &Entry) -> Option<TextureHandle> {
+        let path = covers::find(&covers_dir(), entry)?;
+        match &self.cover {
+            Some((shown, texture)) if *shown == path => return Some(texture.clone()),
+            _ => {}
+        }
+        let image = image::open(&path).ok()?.to_rgba8();
+        let size = [image.width() as usize, image.height() as usize];
+        let image = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+        let texture = ctx.load_texture("cover", image, TextureOptions::LINEAR);
+        self.cover = Some((path, texture.clone()));
+        Some(texture)
+    }
+}
+
+/// Asks for an image with the system's file dialog, and makes it the cover of `entry`.
+fn set_cover(entry: &Entry) -> Option<String> {
+    let image = rfd::FileDialog::new()
+        .set_title("Choose Cover")
+        .add_filter("PNG images", &["png"])
+        .pick_file()?;
+    Some(match covers::set(&covers_dir(), entry, &image) {
+        Ok(path) => format!("cover saved to {}", path.display()),
+        Err(err) => format!("{}: {}", image.display(), err),
+    })
+}
+
+/// Returns the path of the file the library is kept in.
+fn index_path() -> PathBuf {
+    data_dir(NAME).join("library.txt")
+}
+
+/// Returns the directory the covers are kept in.
+fn covers_dir() -> PathBuf {
+    data_dir(NAME).join("covers")
+}
+
+/// Returns the path of the file the directories of the library are listed in.
+fn dirs_path() -> PathBuf {
+    config::config_dir(NAME).join("library.toml")
+}
+
+/// Reads the directories of the library, or none if there is no `library.toml` yet.
+fn read_dirs() -> Result<Vec<PathBuf>, String> {
+    let path = dirs_path();
+    match fs::read_to_string(&path) {
+        Ok(text) => parse_dirs(&text).map_err(|err| format!("{}: {}", path.display(), err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+/// Parses `library.toml`.
+fn parse_dirs(text: &str) -> Result<Vec<PathBuf>, String> {
+    let mut dirs = Vec::new();
+    for (key, value) in &config::parse(text)? {
+        let error = || config::invalid(key, value);
+        match key.as_str() {
+            "dirs" => {
+                let strings = config::parse_strings(value).ok_or_else(error)?;
+                dirs = strings.into_iter().map(PathBuf::from).collect();
+            }
+            _ => return Err(error()),
+        }
+    }
+    Ok(dirs)
+}
+
+/// Writes the directories of the library to `library.toml`. Directories whose path is not
+/// Unicode cannot be kept there.
+fn write_dirs(dirs: &[PathBuf]) -> Result<(), String> {
+    let path = dirs_path();
+    let kept = dirs.iter().filter_map(|dir| dir.to_str());
+    let text = format!("dirs = {}\n", config::strings(kept));
+    let write = |path: &Path| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, &text)
+    };
+    write(&path).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dirs() {
+        assert_eq!(parse_dirs("# none yet\n"), Ok(Vec::new()));
+        assert_eq!(
+            parse_dirs("dirs = [\"/roms\", \"/mnt/games\"]  # both\n"),
+            Ok(vec![PathBuf::from("/roms"), PathBuf::from("/mnt/games")])
+        );
+        assert_eq!(
+            parse_dirs("dirs = [\"/roms, \\\"#1\\\"\"]"),
+            Ok(vec![PathBuf::from("/roms, \"#1\"")])
+        );
+        assert!(parse_dirs("dirs = /roms")
+            .unwrap_err()
+            .starts_with("line 1:"));
+        assert_eq!(
+            parse_dirs("dirs = \"/roms\""),
+            Err("invalid setting `dirs = \"/roms\"`".to_string())
+        );
+        assert!(parse_dirs("speed = 700").is_err());
+    }
+}
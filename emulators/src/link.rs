@@ -0,0 +1,170 @@
+//! The link cable window: plugs the game into the game of another `emulators` window over TCP, on
+//! this computer or another, so that two players can trade and battle. One window listens at an
+//! address, and the other connects to it.
+
+use eframe::egui;
+use emu_core::link::TcpLink;
+use emu_core::Emulator;
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// The address offered at first, for two windows on the same computer.
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7373";
+
+/// How long connecting may take before it is given up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the window looks for the other window connecting while it listens.
+pub const ACCEPT_PERIOD: Duration = Duration::from_millis(100);
+
+/// The link cable, and what the window shows of it.
+pub struct Cable {
+    /// The address to listen at or connect to.
+    address: String,
+    /// Listens for the other window, until it connects.
+    listener: Option<TcpListener>,
+    /// The address of the other window, once linked.
+    peer: Option<String>,
+}
+
+impl Cable {
+    pub fn new() -> Self {
+        Cable {
+            address: DEFAULT_ADDRESS.to_string(),
+            listener: None,
+            peer: None,
+        }
+    }
+
+    /// Returns `true` while the window waits for the other window to connect.
+    pub fn is_listening(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// Plugs the other window into `emulator` if it connected while listening. Returns what
+    /// happened, for the status bar.
+    pub fn accept(&mut self, emulator: Option<&mut dyn Emulator>) -> Option<String> {
+        let emulator = emulator?;
+        let (stream, peer) = match self.listener.as_ref()?.accept() {
+            Ok(accepted) => accepted,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return None,
+            Err(err) => {
+                self.listener = None;
+                return Some(format!("link: {}", err));
+            }
+        };
+        self.listener = None;
+        Some(self.plug(emulator, stream, peer.to_string()))
+    }
+
+    /// Plugs a cable over `stream` into `emulator`.
+    fn plug(&mut self, emulator: &mut dyn Emulator, stream: TcpStream, peer: String) -> String {
+        let link = match TcpLink::new(stream) {
+            Ok(link) => link,
+            Err(err) => return format!("link: {}", err),
+        };
+        if !emulator.set_link(Some(Box::new(link))) {
+            return format!("{} has no link port", emulator.name());
+        }
+        let status = format!("linked to {}", peer);
+        self.peer = Some(peer);
+        status
+    }
+
+    /// Listens at the address for the other window.
+    fn listen(&mut self) -> String {
+        let listener = TcpListener::bind(&self.address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener));
+        match listener {
+            Ok(listener) => {
+                self.listener = Some(listener);
+                self.peer = None;
+                format!("waiting for the other player at {}", self.address)
+            }
+            Err(err) => format!("{}: {}", self.address, err),
+        }
+    }
+
+    /// Connects to the other window listening at the address, and plugs the cable into
+    /// `emulator`.
+    fn connect(&mut self, emulator: &mut dyn Emulator) -> String {
+        let connect = || {
+            let mut last = io::Error::new(io::ErrorKind::InvalidInput, "no such address");
+            for address in self.address.to_socket_addrs()? {
+                match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => last = err,
+                }
+            }
+            Err(last)
+        };
+        match connect() {
+            Ok(stream) => {
+                let peer = self.address.clone();
+                self.plug(emulator, stream, peer)
+            }
+            Err(err) => format!("{}: {}", self.address, err),
+        }
+    }
+
+    /// Shows the cable plugged into `emulator`, with what is needed to link it to or unlink it
+    /// from another window. Returns what happened, for the status bar.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        emulator: Option<&mut dyn Emulator>,
+    ) -> Option<String> {
+        let mut status = None;
+        let emulator = match emulator {
+            Some(emulator) => emulator,
+            None => {
+                ui.weak("Open a game to link it to another window.");
+                return None;
+            }
+        };
+        if emulator.is_linked() {
+            ui.label(format!(
+                "Linked to {}.",
+                self.peer.as_deref().unwrap_or("the other player")
+            ));
+            if ui.button("Unplug").clicked() {
+                emulator.set_link(None);
+                self.peer = None;
+                status = Some("unplugged the link cable".to_string());
+            }
+            return status;
+        }
+        // Unplugs the cable whose other end is gone, which also tells if there is a port.
+        if !emulator.set_link(None) {
+            ui.weak(format!("{} has no link port.", emulator.name()));
+            return None;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            ui.add_enabled(
+                !self.is_listening(),
+                egui::TextEdit::singleline(&mut self.address),
+            );
+        });
+        ui.horizontal(|ui| {
+            if self.is_listening() {
+                if ui.button("Stop Listening").clicked() {
+                    self.listener = None;
+                }
+                ui.weak("waiting for the other player");
+                return;
+            }
+            if ui.button("Listen").clicked() {
+                status = Some(self.listen());
+            }
+            if ui.button("Connect").clicked() {
+                status = Some(self.connect(emulator));
+            }
+        });
+        if self.peer.is_some() {
+            ui.weak("The other player hung up.");
+        }
+        status
+    }
+}
@@ -0,0 +1,61 @@
+//! Every emulator of this repository in one desktop window, running each ROM on the system it is
+//! for.
+
+mod app;
+mod library;
+mod link;
+mod settings;
+mod systems;
+
+use crate::app::App;
+use emu_debug::log;
+use emu_frontend::{config, window};
+use std::env;
+use std::process;
+
+/// Exit status of a command line the launcher does not understand, as for `chip8`.
+const EXIT_USAGE: i32 = 2;
+
+/// Exit status when the window could not be opened.
+const EXIT_IO: i32 = 4;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: emulators [--system <system>] [--log <filter>] [--log-json] [<rom>]\nsystems: {}",
+        systems::ids()
+    );
+    process::exit(EXIT_USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let (filter, format) = log::parse_args(&mut args).unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        usage()
+    });
+    log::init(filter, format);
+    let mut system = None;
+    let mut path = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--system" => match args.next().as_deref().map(systems::find) {
+                Some(Some(found)) => system = Some(found),
+                _ => usage(),
+            },
+            _ if arg.starts_with('-') || path.is_some() => usage(),
+            _ => path = Some(arg),
+        }
+    }
+    let state = config::config_dir(settings::NAME).join("window.ron");
+    let options = window::options(settings::NAME, [960.0, 640.0], state);
+    let result = eframe::run_native(
+        settings::NAME,
+        options,
+        Box::new(move |cc| Ok(Box::new(App::new(&cc.egui_ctx, system, path.as_deref())))),
+    );
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(EXIT_IO);
+    }
+}
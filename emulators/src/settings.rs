@@ -0,0 +1,93 @@
+//! The settings of each system, kept in `<system>.toml` in the configuration directory,
+//! `$XDG_CONFIG_HOME/emulators` or `~/.config/emulators`, such as `chip8.toml`:
+//!
+//! ```toml
+//! keys = ["X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V"]
+//! ```
+//!
+//! `keys` holds the host key of each button of the system, in the order the system numbers them,
+//! by egui's names for them. Settings left out keep their defaults.
+
+use crate::systems::System;
+use eframe::egui::Key;
+use emu_frontend::config;
+use emu_frontend::input;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The name of the launcher's configuration and data directories.
+pub const NAME: &str = "emulators";
+
+/// The settings of one system.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// The host key of each button.
+    pub keys: Vec<Key>,
+}
+
+impl Settings {
+    /// Returns the default settings of `system`.
+    pub fn new(system: &System) -> Self {
+        Settings {
+            keys: system.default_keys(),
+        }
+    }
+
+    /// Loads the settings of `system`, or its defaults if it has none yet.
+    pub fn load(system: &System) -> Result<Settings, String> {
+        let path = config_path(system);
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                Settings::parse(system, &text).map_err(|err| format!("{}: {}", path.display(), err))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::new(system)),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    /// Parses `<system>.toml`.
+    fn parse(system: &System, text: &str) -> Result<Settings, String> {
+        let mut settings = Settings::new(system);
+        for (key, value) in &config::parse(text)? {
+            let error = || config::invalid(key, value);
+            match key.as_str() {
+                "keys" => {
+                    settings.keys =
+                        input::parse_keys(value, settings.keys.len()).ok_or_else(error)?
+                }
+                _ => return Err(error()),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+/// Returns the path of the settings file of `system`.
+fn config_path(system: &System) -> PathBuf {
+    config::config_dir(NAME).join(format!("{}.toml", system.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems;
+
+    #[test]
+    fn parses() {
+        let chip8 = systems::find("chip8").unwrap();
+        let defaults = Settings::new(chip8);
+        assert_eq!(Settings::parse(chip8, "# nothing\n"), Ok(defaults.clone()));
+
+        let mut keys = defaults.keys.clone();
+        keys.swap(0, 1);
+        let text = format!("keys = {}\n", input::key_names(&keys));
+        assert_eq!(Settings::parse(chip8, &text), Ok(Settings { keys }));
+
+        assert_eq!(
+            Settings::parse(chip8, "keys = [\"X\"]"),
+            Err("invalid setting `keys = [\"X\"]`".to_string())
+        );
+        assert!(Settings::parse(chip8, "speed = 700").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! The systems the launcher runs, and how it tells from a ROM which one it is for.
+//!
+//! A ROM is for the first system whose header it has, such as `NES\x1A` at the start of iNES
+//! files, or the Nintendo logo at 0x104 of Game Boy cartridges. ROMs without a header are told
+//! apart by the extension of their file, and those with neither need the system to be chosen.
+
+use crate::settings::NAME;
+use atari2600::Atari2600;
+use bytepusher::BytePusher;
+use chip8_core::emulator::System as Chip8;
+use chip8_core::keyboard::LAYOUT;
+use cosmac_vip::{Config, Vip, ROM_SIZE};
+use dream6800::{Dream, CHIPOS_SIZE};
+use eframe::egui::Key;
+use emu_core::Emulator;
+use emu_frontend::config;
+use gameboy::GameBoy;
+use nes::Nes;
+use pacman::PacMan;
+use space_invaders::sound::{Sample, SOUNDS};
+use space_invaders::Invaders;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+/// A system the launcher runs.
+pub struct System {
+    /// The name of the system on the command line and in file names, such as `chip8`.
+    pub id: &'static str,
+    /// The name of the system shown to the user, such as `CHIP-8`.
+    pub name: &'static str,
+    /// The extensions of its ROM files, in lowercase.
+    pub extensions: &'static [&'static str],
+    /// Returns `true` if the ROM has a header of the system.
+    header: fn(&[u8]) -> bool,
+    /// Creates the system, powered on with no ROM.
+    create: fn() -> Box<dyn Emulator>,
+    /// Returns the host keys bound to its buttons by default, indexed as its buttons are.
+    keys: fn() -> Vec<Key>,
+}
+
+impl System {
+    /// Creates the system, powered on with no ROM.
+    pub fn create(&self) -> Box<dyn Emulator> {
+        (self.create)()
+    }
+
+    /// Returns the host keys bound to its buttons by default.
+    pub fn default_keys(&self) -> Vec<Key> {
+        (self.keys)()
+    }
+}
+
+impl PartialEq for System {
+    fn eq(&self, other: &System) -> bool {
+        self.id == other.id
+    }
+}
+
+/// Every system, in the order they are offered.
+pub const SYSTEMS: [System; 9] = [
+    System {
+        id: "chip8",
+        name: "CHIP-8",
+        extensions: &["ch8", "c8"],
+        header: |_| false,
+        create: || Box::new(Chip8::new()),
+        keys: chip8_keys,
+    },
+    System {
+        id: "vip",
+        name: "COSMAC VIP",
+        extensions: &[],
+        header: |_| false,
+        create: || Box::new(vip()),
+        keys: chip8_keys,
+    },
+    System {
+        id: "dream",
+        name: "DREAM 6800",
+        extensions: &[],
+        header: |_| false,
+        create: || Box::new(dream()),
+        keys: chip8_keys,
+    },
+    System {
+        id: "bytepusher",
+        name: "BytePusher",
+        extensions: &["bytepusher"],
+        header: |_| false,
+        create: || Box::new(BytePusher::new()),
+        keys: chip8_keys,
+    },
+    System {
+        id: "invaders",
+        name: "Space Invaders",
+        extensions: &[],
+        header: |_| false,
+        create: || Box::new(invaders()),
+        keys: invaders_keys,
+    },
+    System {
+        id: "pacman",
+        name: "Pac-Man",
+        extensions: &[],
+        header: |_| false,
+        create: || Box::new(PacMan::new(pacman::Dips::default())),
+        keys: pacman_keys,
+    },
+    System {
+        id: "atari2600",
+        name: "Atari 2600",
+        extensions: &["a26"],
+        header: |_| false,
+        create: || Box::new(Atari2600::new()),
+        keys: atari2600_keys,
+    },
+    System {
+        id: "nes",
+        name: "NES",
+        extensions: &["nes"],
+        header: |rom| rom.starts_with(nes::cartridge::MAGIC),
+        create: || Box::new(Nes::new()),
+        keys: nes_keys,
+    },
+    System {
+        id: "gameboy",
+        name: "Game Boy",
+        extensions: &["gb"],
+        header: gameboy::cartridge::is_rom,
+        create: || Box::new(GameBoy::new()),
+        keys: gameboy_keys,
+    },
+];
+
+/// Creates a 4KB COSMAC VIP with the CHIP-8 interpreter and the monitor found in the `vip`
+/// directory of the configuration directory, as `chip8.bin` and `monitor.bin`. Without the
+/// interpreter, its ROMs are 1802 programs.
+fn vip() -> Vip {
+    let dir = config::config_dir(NAME).join("vip");
+    let image = |name: &str| {
+        let image = fs::read(dir.join(name)).ok()?;
+        <[u8; ROM_SIZE]>::try_from(&image[..]).ok()
+    };
+    Vip::new(Config {
+        interpreter: image("chip8.bin"),
+        monitor: image("monitor.bin"),
+        ..Config::default()
+    })
+}
+
+/// Creates a 4KB DREAM 6800 with CHIPOS found in the `dream` directory of the configuration
+/// directory, as `chipos.bin`. Without it, its ROMs are 6800 programs.
+fn dream() -> Dream {
+    let chipos = fs::read(config::config_dir(NAME).join("dream").join("chipos.bin")).ok();
+    Dream::new(dream6800::Config {
+        chipos: chipos.and_then(|image| <[u8; CHIPOS_SIZE]>::try_from(&image[..]).ok()),
+        ..dream6800::Config::default()
+    })
+}
+
+/// Creates a Space Invaders machine with the sounds found in the `invaders` directory of the
+/// configuration directory, as `0.wav` to `9.wav` in the order of [`Sound`]. Missing or unreadable
+/// sounds are silent.
+///
+/// [`Sound`]: space_invaders::sound::Sound
+fn invaders() -> Invaders {
+    let dir = config::config_dir(NAME).join("invaders");
+    let samples = (0..SOUNDS)
+        .map(|n| {
+            let wav = fs::read(dir.join(format!("{}.wav", n))).ok()?;
+            Sample::from_wav(&wav).ok()
+        })
+        .collect();
+    Invaders::new(space_invaders::Config {
+        samples,
+        ..space_invaders::Config::default()
+    })
+}
+
+/// The keys of arcade emulators: 5 for a coin, 1 and 2 to start, the arrows and space for the
+/// first player, A, D and W for the second, and T to tilt the cabinet.
+fn invaders_keys() -> Vec<Key> {
+    vec![
+        Key::Num5,
+        Key::Num1,
+        Key::Num2,
+        Key::Space,
+        Key::ArrowLeft,
+        Key::ArrowRight,
+        Key::W,
+        Key::A,
+        Key::D,
+        Key::T,
+    ]
+}
+
+/// The keys of Pac-Man: those of the other arcade machines for the coin and the start buttons,
+/// the arrows for the first player's joystick and W, S, A and D for the second's, and 9 for the
+/// service coin.
+fn pacman_keys() -> Vec<Key> {
+    vec![
+        Key::Num5,
+        Key::Num1,
+        Key::Num2,
+        Key::ArrowUp,
+        Key::ArrowDown,
+        Key::ArrowLeft,
+        Key::ArrowRight,
+        Key::W,
+        Key::S,
+        Key::A,
+        Key::D,
+        Key::Num9,
+    ]
+}
+
+/// The keys of the Atari 2600: the arrows and space for the first joystick, W, S, A, D and F for
+/// the second, and F2 and F1 for the Reset and Select switches, as in Stella.
+fn atari2600_keys() -> Vec<Key> {
+    vec![
+        Key::ArrowUp,
+        Key::ArrowDown,
+        Key::ArrowLeft,
+        Key::ArrowRight,
+        Key::Space,
+        Key::W,
+        Key::S,
+        Key::A,
+        Key::D,
+        Key::F,
+        Key::F2,
+        Key::F1,
+    ]
+}
+
+/// The keys of the NES controllers: X, Z, S, Enter and the arrows for A, B, Select, Start and the
+/// D-pad of the first, and the period, the comma, N, M, I, K, J and L for the second.
+fn nes_keys() -> Vec<Key> {
+    vec![
+        Key::X,
+        Key::Z,
+        Key::S,
+        Key::Enter,
+        Key::ArrowUp,
+        Key::ArrowDown,
+        Key::ArrowLeft,
+        Key::ArrowRight,
+        Key::Period,
+        Key::Comma,
+        Key::N,
+        Key::M,
+        Key::I,
+        Key::K,
+        Key::J,
+        Key::L,
+    ]
+}
+
+/// The keys of the Game Boy, those of the first NES controller.
+fn gameboy_keys() -> Vec<Key> {
+    nes_keys()[..gameboy::joypad::BUTTONS.len()].to_vec()
+}
+
+/// The left-hand side of a QWERTY keyboard, laid out as the CHIP-8 keypad, as in `chip8`, which is
+/// the keypad of the COSMAC VIP, of the DREAM 6800 and of BytePusher.
+fn chip8_keys() -> Vec<Key> {
+    const QWERTY: [[Key; 4]; 4] = [
+        [Key::Num1, Key::Num2, Key::Num3, Key::Num4],
+        [Key::Q, Key::W, Key::E, Key::R],
+        [Key::A, Key::S, Key::D, Key::F],
+        [Key::Z, Key::X, Key::C, Key::V],
+    ];
+    let mut keys = vec![Key::Num1; 16];
+    for (row, keypad) in QWERTY.iter().zip(LAYOUT.iter()) {
+        for (&key, &button) in row.iter().zip(keypad.iter()) {
+            keys[button as usize] = key;
+        }
+    }
+    keys
+}
+
+/// Returns the system called `id`.
+pub fn find(id: &str) -> Option<&'static System> {
+    SYSTEMS.iter().find(|system| system.id == id)
+}
+
+/// Returns the system of the ROM `rom`, read from the file at `path`: by its header, or else by
+/// the extension of the file.
+pub fn detect(path: &Path, rom: &[u8]) -> Option<&'static System> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    SYSTEMS
+        .iter()
+        .find(|system| (system.header)(rom))
+        .or_else(|| {
+            let extension = extension?;
+            SYSTEMS
+                .iter()
+                .find(|system| system.extensions.contains(&extension.as_str()))
+        })
+}
+
+/// Returns the ids of every system, such as `chip8`, separated by commas.
+pub fn ids() -> String {
+    let ids: Vec<&str> = SYSTEMS.iter().map(|system| system.id).collect();
+    ids.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects() {
+        let chip8 = find("chip8").unwrap();
+        assert!(find("nope").is_none());
+        assert_eq!(
+            detect(Path::new("roms/PONG.CH8"), &[0x12, 0x00]).map(|system| system.id),
+            Some("chip8")
+        );
+        assert!(detect(Path::new("pong.c8"), &[]).is_some());
+        assert!(detect(Path::new("pong.bin"), &[0x12, 0x00]).is_none());
+        assert!(detect(Path::new("pong"), &[0x12, 0x00]).is_none());
+        assert_eq!(
+            detect(Path::new("game.bin"), b"NES\x1A\x01").map(|system| system.id),
+            Some("nes")
+        );
+        assert_eq!(
+            detect(Path::new("Munching_Squares.BytePusher"), &[]).map(|system| system.id),
+            Some("bytepusher")
+        );
+        assert_eq!(
+            detect(Path::new("combat.a26"), &[0; 2048]).map(|system| system.id),
+            Some("atari2600")
+        );
+        assert_eq!(
+            detect(Path::new("GAME.NES"), &[]).map(|system| system.id),
+            Some("nes")
+        );
+        let mut rom = vec![0; 0x8000];
+        rom[0x104..0x134].copy_from_slice(&gameboy::cartridge::LOGO);
+        assert_eq!(
+            detect(Path::new("game.bin"), &rom).map(|system| system.id),
+            Some("gameboy")
+        );
+        assert_eq!(
+            detect(Path::new("game.gb"), &[]).map(|system| system.id),
+            Some("gameboy")
+        );
+        assert_eq!(
+            ids(),
+            "chip8, vip, dream, bytepusher, invaders, pacman, atari2600, nes, gameboy"
+        );
+
+        for system in &SYSTEMS {
+            let emulator = system.create();
+            assert_eq!(emulator.name(), system.name);
+            assert_eq!(system.default_keys().len(), emulator.buttons().len());
+        }
+        assert!(find("vip").unwrap() != chip8);
+    }
+
+    #[test]
+    fn chip8_keys_follow_the_keypad() {
+        let keys = find("chip8").unwrap().default_keys();
+        assert_eq!(keys[0x1], Key::Num1);
+        assert_eq!(keys[0xC], Key::Num4);
+        assert_eq!(keys[0x0], Key::X);
+        assert_eq!(keys[0xF], Key::V);
+    }
+}
@@ -0,0 +1,833 @@
+//! The audio processing unit, at 0xFF10 to 0xFF3F: two pulse channels, the first with a frequency
+//! sweep, a channel playing the 32 samples of wave RAM, and a noise channel.
+//!
+//! Each channel makes a 4-bit level, which its DAC turns into a voltage, and the mixer sends each
+//! channel to the left and right outputs as `NR51` says, at the volume of `NR50` for each side.
+//! The frame sequencer runs at 512Hz, clocked by the divider of the timer, and in turn clocks the
+//! length counters at 256Hz, the sweep at 128Hz and the volume envelopes at 64Hz.
+//!
+//! The quirks of the DMG that programs can see are emulated: the extra length clocks when the
+//! counter is enabled, the sweep that disables its channel on overflow, the length counters that
+//! keep running while the APU is powered off, and wave RAM that can only be reached while the wave
+//! channel plays on the cycle it reads it, as well as the corruption of wave RAM when the channel
+//! is triggered then.
+
+/// Length of the state written by [`Apu::save`].
+pub const STATE_SIZE: usize = REGISTERS + WAVE_SIZE + 4 * CHANNEL_SIZE + 12;
+
+/// Registers from `NR10` at 0xFF10 to `NR52` at 0xFF26.
+const REGISTERS: usize = 0x17;
+
+/// Bytes of wave RAM, at 0xFF30, of two 4-bit samples each.
+const WAVE_SIZE: usize = 0x10;
+
+/// Bytes of state of each channel.
+const CHANNEL_SIZE: usize = 10;
+
+/// What the registers read as ORed with, from `NR10`: bits that do not exist, and those that can
+/// only be written.
+const READ_MASKS: [u8; REGISTERS] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+/// Indexes of the registers that are not a channel's.
+const NR50: usize = 0x14;
+const NR51: usize = 0x15;
+const NR52: usize = 0x16;
+
+/// The pulse waveforms of 12.5%, 25%, 50% and 75% duty, from the first step in bit 0.
+const DUTIES: [u8; 4] = [0b1000_0000, 0b1000_0001, 0b1110_0001, 0b0111_1110];
+
+/// The shifts of the wave channel's samples for each volume of `NR32`: mute, 100%, 50% and 25%.
+const WAVE_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+/// The bit of the timer's counter whose falls clock the frame sequencer, 512 times a second.
+const SEQUENCER_BIT: u16 = 0x1000;
+
+/// How much the capacitor that removes the DC offset of the output keeps each machine cycle.
+const CHARGE: f32 = 0.999_832;
+
+/// The channels, as numbered in `NR51` and `NR52`.
+const PULSE1: usize = 0;
+const PULSE2: usize = 1;
+const WAVE: usize = 2;
+const NOISE: usize = 3;
+
+/// What a channel is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Channel {
+    /// Whether it is playing, as `NR52` shows it.
+    on: bool,
+    /// Length clocks left before it stops, if its length is enabled.
+    length: u16,
+    /// Machine cycles until the next step of its waveform, or for the wave channel, 2-clock
+    /// periods.
+    timer: u32,
+    /// The step of the pulse waveform, or the sample of wave RAM.
+    position: u8,
+    /// Volume of the envelope, from 0 to 15.
+    volume: u8,
+    /// Envelope clocks until its volume changes.
+    envelope: u8,
+}
+
+impl Channel {
+    fn save(&self) -> [u8; CHANNEL_SIZE] {
+        let [length_low, length_high] = self.length.to_le_bytes();
+        let timer = self.timer.to_le_bytes();
+        [
+            u8::from(self.on),
+            length_low,
+            length_high,
+            timer[0],
+            timer[1],
+            timer[2],
+            timer[3],
+            self.position,
+            self.volume,
+            self.envelope,
+        ]
+    }
+
+    fn restore(state: &[u8]) -> Self {
+        Channel {
+            on: state[0] != 0,
+            length: u16::from_le_bytes([state[1], state[2]]).min(256),
+            timer: u32::from_le_bytes([state[3], state[4], state[5], state[6]])
+                .clamp(1, noise_period(0x77)),
+            position: state[7] & 0x1F,
+            volume: state[8] & 0x0F,
+            envelope: state[9] & 0x07,
+        }
+    }
+}
+
+/// The audio processing unit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Apu {
+    registers: [u8; REGISTERS],
+    wave: [u8; WAVE_SIZE],
+    channels: [Channel; 4],
+    /// The frequency the sweep changes, copied from the first channel's at the trigger.
+    shadow: u16,
+    /// Sweep clocks until the next change of frequency.
+    sweep_timer: u8,
+    sweep_enabled: bool,
+    /// Whether the sweep has subtracted since the trigger, so that leaving negate mode in `NR10`
+    /// stops the channel.
+    negated: bool,
+    /// The shift register of the noise channel.
+    lfsr: u16,
+    /// The byte of wave RAM the wave channel read last.
+    sample: u8,
+    /// Whether the wave channel read wave RAM in the last machine cycle.
+    wave_read: bool,
+    /// The next step of the frame sequencer, from 0 to 7.
+    step: u8,
+    /// The bit of the timer's counter that clocks the frame sequencer, when it was last seen.
+    sequencer_bit: bool,
+    /// The sum of the output since the last sample, and the machine cycles of it.
+    sum: f32,
+    count: u32,
+    /// The voltage across the capacitor that removes the DC offset.
+    capacitor: f32,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    /// Creates the APU as the boot ROM leaves it: powered on, with the first pulse channel having
+    /// played its chime and both outputs at full volume.
+    pub fn new() -> Self {
+        let mut apu = Apu {
+            registers: [0; REGISTERS],
+            wave: [0; WAVE_SIZE],
+            channels: [Channel::default(); 4],
+            shadow: 0,
+            sweep_timer: 0,
+            sweep_enabled: false,
+            negated: false,
+            lfsr: 0,
+            sample: 0,
+            wave_read: false,
+            step: 0,
+            sequencer_bit: false,
+            sum: 0.0,
+            count: 0,
+            capacitor: 0.0,
+        };
+        for channel in &mut apu.channels {
+            channel.timer = 2048;
+        }
+        apu.registers[..REGISTERS].copy_from_slice(&[
+            0x80, 0xBF, 0xF3, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF,
+            0xBF, 0xFF, 0xFF, 0x00, 0x00, 0xBF, 0x77, 0xF3, 0x80,
+        ]);
+        apu.channels[PULSE1].on = true;
+        apu
+    }
+
+    /// Returns whether the APU is powered on, by bit 7 of `NR52`.
+    pub fn powered(&self) -> bool {
+        self.registers[NR52] & 0x80 != 0
+    }
+
+    /// Returns whether the channel `channel`, from 0 for the first pulse channel to 3 for noise,
+    /// is playing.
+    pub fn playing(&self, channel: usize) -> bool {
+        self.channels[channel].on
+    }
+
+    /// Returns wave RAM.
+    pub fn wave(&self) -> &[u8] {
+        &self.wave
+    }
+
+    /// Runs the APU for a machine cycle, given the counter of the timer, whose falling bit 12
+    /// clocks the frame sequencer.
+    pub fn tick(&mut self, counter: u16) {
+        let bit = counter & SEQUENCER_BIT != 0;
+        let falling = self.sequencer_bit && !bit;
+        self.sequencer_bit = bit;
+        self.wave_read = false;
+        if !self.powered() {
+            self.output(0.0, 0.0);
+            return;
+        }
+        if falling {
+            self.clock_sequencer();
+        }
+        for channel in PULSE1..=PULSE2 {
+            let period = self.period(channel);
+            let pulse = &mut self.channels[channel];
+            pulse.timer -= 1;
+            if pulse.timer == 0 {
+                pulse.timer = period;
+                pulse.position = (pulse.position + 1) & 7;
+            }
+        }
+        if self.channels[WAVE].on {
+            let period = self.period(WAVE);
+            for half in 0..2 {
+                let wave = &mut self.channels[WAVE];
+                wave.timer -= 1;
+                if wave.timer == 0 {
+                    wave.timer = period;
+                    wave.position = (wave.position + 1) & 31;
+                    self.sample = self.wave[usize::from(wave.position >> 1)];
+                    // The program reaches wave RAM only on the very clocks the channel does.
+                    self.wave_read = half == 1;
+                }
+            }
+        }
+        let noise = &mut self.channels[NOISE];
+        noise.timer -= 1;
+        if noise.timer == 0 {
+            let nr43 = self.registers[0x12];
+            noise.timer = noise_period(nr43);
+            if nr43 >> 4 < 14 {
+                let feedback = (self.lfsr ^ self.lfsr >> 1) & 1;
+                self.lfsr = self.lfsr >> 1 | feedback << 14;
+                if nr43 & 0x08 != 0 {
+                    self.lfsr = (self.lfsr & !0x40) | feedback << 6;
+                }
+            }
+        }
+        self.mix();
+    }
+
+    /// Returns the average of the output since the last call, mixed down to mono.
+    pub fn sample(&mut self) -> i16 {
+        let average = if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f32
+        };
+        self.sum = 0.0;
+        self.count = 0;
+        // Four channels at the loudest volume reach 32.
+        (average / 32.0 * f32::from(i16::MAX)) as i16
+    }
+
+    /// Reads the register or wave RAM at `address`, from 0xFF10 to 0xFF3F.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF30..=0xFF3F => {
+                if !self.channels[WAVE].on {
+                    self.wave[usize::from(address - 0xFF30)]
+                } else if self.wave_read {
+                    self.sample
+                } else {
+                    0xFF
+                }
+            }
+            0xFF26 => {
+                let playing = self
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .fold(0, |bits, (index, channel)| {
+                        bits | u8::from(channel.on) << index
+                    });
+                (self.registers[NR52] & 0x80) | READ_MASKS[NR52] | playing
+            }
+            0xFF10..=0xFF25 => {
+                let index = usize::from(address - 0xFF10);
+                self.registers[index] | READ_MASKS[index]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes the register or wave RAM at `address`, from 0xFF10 to 0xFF3F. While the APU is
+    /// powered off, only `NR52`, wave RAM and the lengths can be written.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF30..=0xFF3F => {
+                if !self.channels[WAVE].on {
+                    self.wave[usize::from(address - 0xFF30)] = value;
+                } else if self.wave_read {
+                    self.wave[usize::from(self.channels[WAVE].position >> 1)] = value;
+                }
+            }
+            0xFF26 => self.power(value & 0x80 != 0),
+            0xFF10..=0xFF25 if !self.powered() => match address {
+                0xFF11 | 0xFF16 | 0xFF20 => {
+                    self.load_length((address - 0xFF11) as usize / 5, value)
+                }
+                0xFF1B => self.load_length(WAVE, value),
+                _ => {}
+            },
+            0xFF10..=0xFF25 => {
+                let index = usize::from(address - 0xFF10);
+                let old = self.registers[index];
+                self.registers[index] = value;
+                let channel = index / 5;
+                match (index % 5, channel) {
+                    (_, 4) => {}
+                    (0, PULSE1) if self.negated && old & 0x08 != 0 && value & 0x08 == 0 => {
+                        self.channels[PULSE1].on = false
+                    }
+                    (0, WAVE) if value & 0x80 == 0 => self.channels[WAVE].on = false,
+                    (1, _) => self.load_length(channel, value),
+                    (2, channel) if channel != WAVE && value & 0xF8 == 0 => {
+                        self.channels[channel].on = false
+                    }
+                    (4, _) => self.write_control(channel, old, value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the state of the APU, for [`restore`](Apu::restore).
+    pub fn save(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(&self.registers);
+        state.extend_from_slice(&self.wave);
+        for channel in &self.channels {
+            state.extend_from_slice(&channel.save());
+        }
+        state.extend_from_slice(&self.shadow.to_le_bytes());
+        state.extend_from_slice(&self.lfsr.to_le_bytes());
+        state.extend_from_slice(&[
+            self.sweep_timer,
+            u8::from(self.sweep_enabled),
+            u8::from(self.negated),
+            self.sample,
+            u8::from(self.wave_read),
+            self.step,
+            u8::from(self.sequencer_bit),
+            0,
+        ]);
+        state
+    }
+
+    /// Restores a state returned by [`save`](Apu::save), of [`STATE_SIZE`] bytes.
+    pub fn restore(&mut self, state: &[u8]) {
+        let (registers, state) = state.split_at(REGISTERS);
+        let (wave, state) = state.split_at(WAVE_SIZE);
+        let (channels, state) = state.split_at(4 * CHANNEL_SIZE);
+        self.registers.copy_from_slice(registers);
+        self.wave.copy_from_slice(wave);
+        for (channel, state) in self.channels.iter_mut().zip(channels.chunks(CHANNEL_SIZE)) {
+            *channel = Channel::restore(state);
+        }
+        self.shadow = u16::from_le_bytes([state[0], state[1]]) & 0x7FF;
+        self.lfsr = u16::from_le_bytes([state[2], state[3]]) & 0x7FFF;
+        self.sweep_timer = state[4].min(8);
+        self.sweep_enabled = state[5] != 0;
+        self.negated = state[6] != 0;
+        self.sample = state[7];
+        self.wave_read = state[8] != 0;
+        self.step = state[9] & 7;
+        self.sequencer_bit = state[10] != 0;
+    }
+
+    /// Returns the 11-bit frequency of the channel `channel`, from its third and fifth registers.
+    fn frequency(&self, channel: usize) -> u16 {
+        let index = channel * 5;
+        u16::from_le_bytes([self.registers[index + 3], self.registers[index + 4] & 0x07])
+    }
+
+    /// Returns the period of the channel `channel`, in machine cycles for the pulse channels and
+    /// 2-clock periods for the wave channel.
+    fn period(&self, channel: usize) -> u32 {
+        2048 - u32::from(self.frequency(channel))
+    }
+
+    fn set_frequency(&mut self, frequency: u16) {
+        let [low, high] = frequency.to_le_bytes();
+        self.registers[3] = low;
+        self.registers[4] = (self.registers[4] & !0x07) | high;
+    }
+
+    /// Returns whether the DAC of the channel `channel` is on: for the wave channel by bit 7 of
+    /// `NR30`, and for the others by the initial volume and direction of the envelope.
+    fn dac_on(&self, channel: usize) -> bool {
+        if channel == WAVE {
+            self.registers[0x0A] & 0x80 != 0
+        } else {
+            self.registers[channel * 5 + 2] & 0xF8 != 0
+        }
+    }
+
+    /// Returns the most length clocks a channel plays for.
+    fn max_length(channel: usize) -> u16 {
+        if channel == WAVE {
+            256
+        } else {
+            64
+        }
+    }
+
+    fn length_enabled(&self, channel: usize) -> bool {
+        self.registers[channel * 5 + 4] & 0x40 != 0
+    }
+
+    /// Loads the length of the channel `channel` from the value written to its second register.
+    fn load_length(&mut self, channel: usize, value: u8) {
+        self.channels[channel].length = if channel == WAVE {
+            256 - u16::from(value)
+        } else {
+            64 - u16::from(value & 0x3F)
+        };
+    }
+
+    /// Writes the fifth register of the channel `channel`, which enables its length and triggers
+    /// it. Enabling the length counter when the next step of the frame sequencer does not clock it
+    /// clocks it once more.
+    fn write_control(&mut self, channel: usize, old: u8, value: u8) {
+        let extra_clock = self.step & 1 != 0;
+        let enabling = old & 0x40 == 0 && value & 0x40 != 0;
+        let state = &mut self.channels[channel];
+        if enabling && extra_clock && state.length > 0 {
+            state.length -= 1;
+            if state.length == 0 && value & 0x80 == 0 {
+                state.on = false;
+            }
+        }
+        if value & 0x80 != 0 {
+            self.trigger(channel, value & 0x40 != 0 && extra_clock);
+        }
+    }
+
+    /// Restarts the channel `channel`. `extra_clock` is whether the length counter, if it is
+    /// reloaded, is clocked at once.
+    fn trigger(&mut self, channel: usize, extra_clock: bool) {
+        let period = self.period(channel);
+        let envelope = self.registers[channel * 5 + 2];
+        let on = self.dac_on(channel);
+        if channel == WAVE && self.channels[WAVE].on && self.channels[WAVE].timer == 1 {
+            self.corrupt_wave();
+        }
+        let state = &mut self.channels[channel];
+        state.on = on;
+        if state.length == 0 {
+            state.length = Self::max_length(channel);
+            if extra_clock {
+                state.length -= 1;
+            }
+        }
+        state.volume = envelope >> 4;
+        state.envelope = envelope & 0x07;
+        match channel {
+            WAVE => {
+                // The first sample is read 6 clocks later than the period says.
+                state.timer = period + 3;
+                state.position = 0;
+            }
+            NOISE => {
+                state.timer = noise_period(self.registers[0x12]);
+                self.lfsr = 0x7FFF;
+            }
+            _ => state.timer = period,
+        }
+        if channel == PULSE1 {
+            let nr10 = self.registers[0];
+            self.shadow = self.frequency(PULSE1);
+            self.sweep_timer = sweep_period(nr10);
+            self.sweep_enabled = nr10 & 0x77 != 0;
+            self.negated = false;
+            if nr10 & 0x07 != 0 && self.sweep() > 0x7FF {
+                self.channels[PULSE1].on = false;
+            }
+        }
+    }
+
+    /// Rewrites the first bytes of wave RAM with those the wave channel reads, as triggering it on
+    /// the cycle it reads wave RAM does on the DMG: the first byte if it reads one of the first 4,
+    /// or else the 4 bytes around it.
+    fn corrupt_wave(&mut self) {
+        let next = usize::from((self.channels[WAVE].position + 1) & 31) >> 1;
+        if next < 4 {
+            self.wave[0] = self.wave[next];
+        } else {
+            let start = next & !3;
+            self.wave.copy_within(start..start + 4, 0);
+        }
+    }
+
+    /// Returns the next frequency of the sweep from the shadow frequency.
+    fn sweep(&mut self) -> u16 {
+        let nr10 = self.registers[0];
+        let change = self.shadow >> (nr10 & 0x07);
+        if nr10 & 0x08 != 0 {
+            self.negated = true;
+            self.shadow - change
+        } else {
+            self.shadow + change
+        }
+    }
+
+    /// Turns the APU on or off. Turning it off clears every register but the lengths, and turning
+    /// it on starts the frame sequencer over.
+    fn power(&mut self, on: bool) {
+        if on == self.powered() {
+            return;
+        }
+        if on {
+            self.registers[NR52] = 0x80;
+            self.step = 0;
+            for channel in &mut self.channels {
+                channel.position = 0;
+            }
+            self.sample = 0;
+        } else {
+            self.registers = [0; REGISTERS];
+            for channel in &mut self.channels {
+                *channel = Channel {
+                    length: channel.length,
+                    timer: channel.timer,
+                    ..Channel::default()
+                };
+            }
+            self.sweep_enabled = false;
+        }
+    }
+
+    /// Runs the step of the frame sequencer.
+    fn clock_sequencer(&mut self) {
+        if self.step & 1 == 0 {
+            for channel in 0..4 {
+                let enabled = self.length_enabled(channel);
+                let state = &mut self.channels[channel];
+                if enabled && state.length > 0 {
+                    state.length -= 1;
+                    if state.length == 0 {
+                        state.on = false;
+                    }
+                }
+            }
+        }
+        if self.step == 2 || self.step == 6 {
+            self.clock_sweep();
+        }
+        if self.step == 7 {
+            for channel in [PULSE1, PULSE2, NOISE].iter().copied() {
+                let envelope = self.registers[channel * 5 + 2];
+                let period = envelope & 0x07;
+                let state = &mut self.channels[channel];
+                if period == 0 {
+                    continue;
+                }
+                state.envelope = state.envelope.saturating_sub(1);
+                if state.envelope == 0 {
+                    state.envelope = period;
+                    if envelope & 0x08 != 0 && state.volume < 15 {
+                        state.volume += 1;
+                    } else if envelope & 0x08 == 0 && state.volume > 0 {
+                        state.volume -= 1;
+                    }
+                }
+            }
+        }
+        self.step = (self.step + 1) & 7;
+    }
+
+    fn clock_sweep(&mut self) {
+        self.sweep_timer = self.sweep_timer.saturating_sub(1);
+        if self.sweep_timer > 0 {
+            return;
+        }
+        let nr10 = self.registers[0];
+        self.sweep_timer = sweep_period(nr10);
+        if !self.sweep_enabled || nr10 & 0x70 == 0 {
+            return;
+        }
+        let frequency = self.sweep();
+        if frequency > 0x7FF {
+            self.channels[PULSE1].on = false;
+        } else if nr10 & 0x07 != 0 {
+            self.shadow = frequency;
+            self.set_frequency(frequency);
+            if self.sweep() > 0x7FF {
+                self.channels[PULSE1].on = false;
+            }
+        }
+    }
+
+    /// Adds the output of this machine cycle to the sample being made.
+    fn mix(&mut self) {
+        let nr51 = self.registers[NR51];
+        let (mut left, mut right) = (0.0, 0.0);
+        for channel in 0..4 {
+            if !self.dac_on(channel) {
+                continue;
+            }
+            let level = if self.channels[channel].on {
+                self.level(channel)
+            } else {
+                0
+            };
+            let voltage = 1.0 - f32::from(level) / 7.5;
+            if nr51 & 0x10 << channel != 0 {
+                left += voltage;
+            }
+            if nr51 & 1 << channel != 0 {
+                right += voltage;
+            }
+        }
+        let nr50 = self.registers[NR50];
+        left *= f32::from((nr50 >> 4 & 0x07) + 1);
+        right *= f32::from((nr50 & 0x07) + 1);
+        self.output(left, right);
+    }
+
+    /// Returns the 4-bit level the channel `channel` makes.
+    fn level(&self, channel: usize) -> u8 {
+        let state = &self.channels[channel];
+        match channel {
+            WAVE => {
+                let sample = if state.position & 1 == 0 {
+                    self.sample >> 4
+                } else {
+                    self.sample & 0x0F
+                };
+                sample >> WAVE_SHIFTS[usize::from(self.registers[0x0C] >> 5 & 0x03)]
+            }
+            NOISE => {
+                if self.lfsr & 1 == 0 {
+                    state.volume
+                } else {
+                    0
+                }
+            }
+            _ => {
+                let duty = DUTIES[usize::from(self.registers[channel * 5 + 1] >> 6)];
+                if duty >> state.position & 1 != 0 {
+                    state.volume
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// Adds the voltages of the outputs to the sample being made, through the capacitor that
+    /// removes their DC offset.
+    fn output(&mut self, left: f32, right: f32) {
+        let input = (left + right) / 2.0;
+        let output = input - self.capacitor;
+        self.capacitor = input - output * CHARGE;
+        self.sum += output;
+        self.count += 1;
+    }
+}
+
+/// Returns the machine cycles between the steps of the noise channel's shift register, for the
+/// value of `NR43`.
+fn noise_period(nr43: u8) -> u32 {
+    let divisor = match nr43 & 0x07 {
+        0 => 2,
+        divisor => 4 * u32::from(divisor),
+    };
+    divisor << (nr43 >> 4).min(13)
+}
+
+/// Returns the sweep clocks between changes of frequency, for the value of `NR10`, where a period
+/// of 0 counts as 8.
+fn sweep_period(nr10: u8) -> u8 {
+    match nr10 >> 4 & 0x07 {
+        0 => 8,
+        period => period,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the frame sequencer for `steps` steps, by making bit 12 of the counter fall.
+    fn run_sequencer(apu: &mut Apu, steps: usize) {
+        for _ in 0..steps {
+            apu.tick(SEQUENCER_BIT);
+            apu.tick(0);
+        }
+    }
+
+    /// Returns the APU powered off and on again, with the frame sequencer at its first step.
+    fn powered_on() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(0xFF26, 0x00);
+        apu.write(0xFF26, 0x80);
+        apu
+    }
+
+    #[test]
+    fn reads_registers_and_powers_off() {
+        let mut apu = Apu::new();
+        assert_eq!(apu.read(0xFF26), 0xF1);
+        apu.write(0xFF11, 0x45);
+        assert_eq!(apu.read(0xFF11), 0x7F);
+        apu.write(0xFF13, 0x12);
+        assert_eq!(apu.read(0xFF13), 0xFF);
+        assert_eq!(apu.read(0xFF27), 0xFF);
+        apu.write(0xFF30, 0x9A);
+        assert_eq!(apu.read(0xFF30), 0x9A);
+
+        // Powering off clears the registers and ignores writes, but keeps wave RAM.
+        apu.write(0xFF26, 0x00);
+        assert_eq!(apu.read(0xFF26), 0x70);
+        assert_eq!(apu.read(0xFF11), 0x3F);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF24, 0x77);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(0xFF24), 0x00);
+        assert_eq!(apu.read(0xFF30), 0x9A);
+        apu.write(0xFF26, 0x80);
+        assert_eq!(apu.read(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn counts_lengths() {
+        let mut apu = powered_on();
+        // Channel 2 at volume 8 for a length of 2, without enabling its length yet.
+        apu.write(0xFF17, 0x80);
+        apu.write(0xFF16, 62);
+        apu.write(0xFF19, 0x80);
+        assert!(apu.playing(PULSE2));
+        run_sequencer(&mut apu, 8);
+        assert!(apu.playing(PULSE2));
+
+        // Enabled, it is clocked every other step, and stops at 0.
+        apu.write(0xFF19, 0x40);
+        run_sequencer(&mut apu, 2);
+        assert!(apu.playing(PULSE2));
+        run_sequencer(&mut apu, 1);
+        assert!(!apu.playing(PULSE2));
+        assert_eq!(apu.read(0xFF26), 0xF0);
+
+        // Triggering reloads a length of 0 as 64, less the extra clock of enabling it on an odd
+        // step, and a DAC turned off stops the channel.
+        apu.write(0xFF19, 0xC0);
+        run_sequencer(&mut apu, 2 * 62);
+        assert!(apu.playing(PULSE2));
+        apu.write(0xFF19, 0xC0);
+        assert!(apu.playing(PULSE2));
+        apu.write(0xFF17, 0x00);
+        assert!(!apu.playing(PULSE2));
+    }
+
+    #[test]
+    fn sweeps_until_overflow() {
+        let mut apu = powered_on();
+        // A sweep adding half the frequency every sweep clock, from 0x400.
+        apu.write(0xFF10, 0x11);
+        apu.write(0xFF12, 0x80);
+        apu.write(0xFF13, 0x00);
+        apu.write(0xFF14, 0x84);
+        assert!(apu.playing(PULSE1));
+        run_sequencer(&mut apu, 3);
+        // 0x600 is written back, and the next frequency, 0x900, is too high.
+        assert!(!apu.playing(PULSE1));
+        assert_eq!(apu.frequency(PULSE1), 0x600);
+
+        // Overflowing on the trigger stops it at once.
+        apu.write(0xFF13, 0xFF);
+        apu.write(0xFF14, 0x87);
+        assert!(!apu.playing(PULSE1));
+    }
+
+    #[test]
+    fn mixes_the_channels() {
+        let mut apu = powered_on();
+        // Channel 2 at full volume and 50% duty, at 0x780, a period of 1024 machine cycles, to
+        // the left only.
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0x20);
+        apu.write(0xFF16, 0x80);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF18, 0x80);
+        apu.write(0xFF19, 0x87);
+        let mut samples = Vec::new();
+        for _ in 0..4096 {
+            apu.tick(0);
+            samples.push(apu.sample());
+        }
+        let (low, high) = (samples.iter().min().unwrap(), samples.iter().max().unwrap());
+        assert!(*low < -3000 && *high > 3000, "{} {}", low, high);
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count();
+        assert!((7..=9).contains(&crossings), "{}", crossings);
+
+        // Sent to neither output, the sound dies away.
+        apu.write(0xFF25, 0x00);
+        for _ in 0..65536 {
+            apu.tick(0);
+        }
+        assert!(apu.sample().abs() < 100);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut apu = powered_on();
+        apu.write(0xFF21, 0xF0);
+        apu.write(0xFF22, 0x55);
+        apu.write(0xFF23, 0x80);
+        apu.write(0xFF30, 0x12);
+        run_sequencer(&mut apu, 5);
+        let state = apu.save();
+        assert_eq!(state.len(), STATE_SIZE);
+        let mut restored = Apu::new();
+        restored.restore(&state);
+        assert_eq!(restored.save(), state);
+        for _ in 0..1000 {
+            apu.tick(0);
+            restored.tick(0);
+        }
+        assert_eq!(restored.save(), apu.save());
+        assert_eq!(restored.read(0xFF26), 0xF8);
+    }
+}
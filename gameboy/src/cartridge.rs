@@ -0,0 +1,691 @@
+//! Cartridges, and the memory bank controllers on them.
+//!
+//! A Game Boy ROM is the contents of the cartridge's ROM, with a header at 0x0100 telling its
+//! title, which controller the board has, and how much ROM and RAM it holds. The processor sees
+//! 32KB of ROM at 0x0000 and 8KB of RAM at 0xA000, and larger games switch banks into those
+//! windows by writing to the ROM, which the controller catches:
+//!
+//! * Without a controller, the cartridge is 32KB of ROM and maybe 8KB of RAM.
+//! * MBC1 switches 16KB banks of up to 2MB of ROM at 0x4000, and 8KB banks of up to 32KB of RAM.
+//! * MBC3 does the same for up to 4MB of ROM, and some add a real-time clock, whose registers are
+//!   switched in at 0xA000 in place of RAM.
+//! * MBC5 switches up to 8MB of ROM and 128KB of RAM.
+//!
+//! RAM on a cartridge with a battery keeps the game's saves, and its clock runs while the Game
+//! Boy is off. [`Cartridge::battery`] writes them in the layout other emulators use for `.sav`
+//! files: the RAM, then for a clock 48 bytes holding its registers and when they were saved.
+
+use emu_core::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The logo at 0x0104 of every cartridge, which the boot ROM checks.
+pub const LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Where the logo is.
+const LOGO_ADDRESS: usize = 0x0104;
+
+/// Bytes of a bank of ROM.
+pub const ROM_BANK: usize = 0x4000;
+
+/// Bytes of a bank of RAM.
+pub const RAM_BANK: usize = 0x2000;
+
+/// Bytes of the clock in a battery file: its registers and their latched copy as 32-bit numbers,
+/// and the Unix time they were saved at as a 64-bit one.
+pub const RTC_SIZE: usize = 48;
+
+/// Bytes of the state of the clock, as [`Cartridge::save`] writes it.
+const RTC_STATE_SIZE: usize = 5 + 5 + 4;
+
+/// Bytes of the state of the controller, before RAM.
+const REGISTERS_SIZE: usize = 6;
+
+/// Clock cycles a second, which the clock counts.
+const CLOCK_HZ: u32 = 4_194_304;
+
+/// The bit of the clock's high day register that stops it, and the one set when the day counter
+/// overflows.
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
+/// The controller of a cartridge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mapper {
+    /// No controller: 32KB of ROM.
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc5,
+}
+
+/// The real-time clock of MBC3 cartridges, counting seconds, minutes, hours and 512 days.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Rtc {
+    /// Seconds, minutes, hours, the low byte of the day, and the high day register: bit 0 of the
+    /// day, [`HALT`] and [`DAY_CARRY`].
+    registers: [u8; 5],
+    /// The registers as they were latched, which the program reads.
+    latched: [u8; 5],
+    /// Clock cycles into the current second.
+    cycles: u32,
+}
+
+impl Rtc {
+    fn halted(&self) -> bool {
+        self.registers[4] & HALT != 0
+    }
+
+    /// Runs the clock for a machine cycle.
+    fn tick(&mut self) {
+        if self.halted() {
+            return;
+        }
+        self.cycles += 4;
+        if self.cycles >= CLOCK_HZ {
+            self.cycles -= CLOCK_HZ;
+            self.second();
+        }
+    }
+
+    fn days(&self) -> u16 {
+        u16::from(self.registers[4] & 1) << 8 | u16::from(self.registers[3])
+    }
+
+    fn set_days(&mut self, days: u16) {
+        self.registers[3] = days as u8;
+        self.registers[4] = self.registers[4] & !1 | (days >> 8) as u8 & 1;
+    }
+
+    /// Counts a second. A register set out of its range counts up to the end of its bits before
+    /// wrapping to 0, without carrying.
+    fn second(&mut self) {
+        let registers = &mut self.registers;
+        registers[0] = (registers[0] + 1) & 0x3F;
+        if registers[0] != 60 {
+            return;
+        }
+        registers[0] = 0;
+        registers[1] = (registers[1] + 1) & 0x3F;
+        if registers[1] != 60 {
+            return;
+        }
+        registers[1] = 0;
+        registers[2] = (registers[2] + 1) & 0x1F;
+        if registers[2] != 24 {
+            return;
+        }
+        registers[2] = 0;
+        self.add_days(1);
+    }
+
+    fn add_days(&mut self, days: u64) {
+        let days = u64::from(self.days()) + days;
+        if days >= 512 {
+            self.registers[4] |= DAY_CARRY;
+        }
+        self.set_days((days % 512) as u16);
+    }
+
+    /// Counts `seconds` at once, as when the Game Boy was off.
+    fn advance(&mut self, mut seconds: u64) {
+        if self.halted() {
+            return;
+        }
+        let [second, minute, hour, ..] = self.registers;
+        if second < 60 && minute < 60 && hour < 24 {
+            let time = u64::from(second) + 60 * u64::from(minute) + 3600 * u64::from(hour);
+            self.add_days((time + seconds) / 86400);
+            seconds = (time + seconds) % 86400;
+            self.registers[0] = (seconds % 60) as u8;
+            self.registers[1] = (seconds / 60 % 60) as u8;
+            self.registers[2] = (seconds / 3600) as u8;
+        } else {
+            for _ in 0..seconds.min(86400) {
+                self.second();
+            }
+        }
+    }
+
+    /// Writes the register numbered `register`, 0 for seconds to 4 for the high day register.
+    fn write(&mut self, register: usize, value: u8) {
+        const MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+        if register == 0 {
+            self.cycles = 0;
+        }
+        self.registers[register] = value & MASKS[register];
+    }
+}
+
+/// A cartridge.
+#[derive(Clone, Debug)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mapper: Mapper,
+    battery: bool,
+    /// Whether the cartridge has a rumble motor, driven by bit 3 of the RAM bank.
+    rumble: bool,
+    rtc: Option<Rtc>,
+    ram_enabled: bool,
+    /// The ROM bank register: the low 5 bits of the bank for MBC1, 7 bits for MBC3 and 9 for
+    /// MBC5.
+    rom_bank: u16,
+    /// The RAM bank register, which for MBC1 also holds bits 5 and 6 of the ROM bank, and for
+    /// MBC3 selects a register of the clock from 0x08.
+    ram_bank: u8,
+    /// Whether MBC1 switches the RAM bank and the first 16KB of ROM too.
+    mode: bool,
+    /// The last value written to MBC3's latch register, which latches the clock when 0x00 then
+    /// 0x01 are written.
+    latch: u8,
+}
+
+impl Default for Cartridge {
+    /// No cartridge, which reads 0xFF.
+    fn default() -> Self {
+        Cartridge {
+            rom: vec![0xFF; 2 * ROM_BANK],
+            ram: Vec::new(),
+            mapper: Mapper::None,
+            battery: false,
+            rumble: false,
+            rtc: None,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: false,
+            latch: 0xFF,
+        }
+    }
+}
+
+/// Returns whether `rom` starts with the header of a Game Boy ROM, with the logo at 0x0104.
+pub fn is_rom(rom: &[u8]) -> bool {
+    rom.get(LOGO_ADDRESS..LOGO_ADDRESS + LOGO.len()) == Some(&LOGO[..])
+}
+
+impl Cartridge {
+    /// Reads the ROM `rom`, which is padded to a power of 2 banks if it is short of one.
+    pub fn from_rom(rom: &[u8]) -> Result<Self, Error> {
+        if rom.len() < 0x0150 {
+            return Err(Error::Rom("not a Game Boy ROM".to_string()));
+        }
+        // The type of cartridge: the controller, then whether it has RAM, a battery, a clock and
+        // a rumble motor.
+        let (mapper, ram, battery, rtc, rumble) = match rom[0x0147] {
+            0x00 => (Mapper::None, false, false, false, false),
+            0x08 => (Mapper::None, true, false, false, false),
+            0x09 => (Mapper::None, true, true, false, false),
+            0x01 => (Mapper::Mbc1, false, false, false, false),
+            0x02 => (Mapper::Mbc1, true, false, false, false),
+            0x03 => (Mapper::Mbc1, true, true, false, false),
+            0x0F => (Mapper::Mbc3, false, true, true, false),
+            0x10 => (Mapper::Mbc3, true, true, true, false),
+            0x11 => (Mapper::Mbc3, false, false, false, false),
+            0x12 => (Mapper::Mbc3, true, false, false, false),
+            0x13 => (Mapper::Mbc3, true, true, false, false),
+            0x19 => (Mapper::Mbc5, false, false, false, false),
+            0x1A => (Mapper::Mbc5, true, false, false, false),
+            0x1B => (Mapper::Mbc5, true, true, false, false),
+            0x1C => (Mapper::Mbc5, false, false, false, true),
+            0x1D => (Mapper::Mbc5, true, false, false, true),
+            0x1E => (Mapper::Mbc5, true, true, false, true),
+            kind => {
+                return Err(Error::Rom(format!(
+                    "the cartridge is of type 0x{:02X}, and only those without a controller, \
+                     with MBC1, MBC3 or MBC5 are supported",
+                    kind
+                )))
+            }
+        };
+        let ram_size = match rom[0x0149] {
+            _ if !ram => 0,
+            0x01 => 0x800,
+            0x02 => RAM_BANK,
+            0x03 => 4 * RAM_BANK,
+            0x04 => 16 * RAM_BANK,
+            0x05 => 8 * RAM_BANK,
+            _ => 0,
+        };
+        let banks = rom.len().div_ceil(ROM_BANK).next_power_of_two().max(2);
+        let mut rom = rom.to_vec();
+        rom.resize(banks * ROM_BANK, 0xFF);
+        Ok(Cartridge {
+            rom,
+            ram: vec![0; ram_size],
+            mapper,
+            battery,
+            rumble,
+            rtc: if rtc { Some(Rtc::default()) } else { None },
+            ..Cartridge::default()
+        })
+    }
+
+    /// Resets the controller's registers, as turning the Game Boy off and on does. RAM and the
+    /// clock are kept.
+    pub fn reset(&mut self) {
+        self.ram_enabled = false;
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.mode = false;
+        self.latch = 0xFF;
+    }
+
+    /// Returns the title in the header.
+    pub fn title(&self) -> String {
+        self.rom[0x0134..0x0144]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| char::from(byte))
+            .collect()
+    }
+
+    /// Returns the controller.
+    pub fn mapper(&self) -> Mapper {
+        self.mapper
+    }
+
+    /// Returns whether RAM and the clock are kept by a battery.
+    pub fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    /// Returns whether the cartridge has a real-time clock.
+    pub fn has_rtc(&self) -> bool {
+        self.rtc.is_some()
+    }
+
+    /// Returns whether the rumble motor is running.
+    pub fn rumbling(&self) -> bool {
+        self.rumble && self.ram_bank & 0x08 != 0
+    }
+
+    /// Returns the cartridge's RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Returns the cartridge's RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    /// Runs the clock, if there is one, for a machine cycle.
+    pub fn tick(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick();
+        }
+    }
+
+    /// Reads the processor's address `address`, in ROM from 0x0000 or in RAM from 0xA000.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[self.rom_offset(self.low_bank(), address)],
+            0x4000..=0x7FFF => self.rom[self.rom_offset(self.high_bank(), address)],
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled && self.mapper != Mapper::None {
+                    return 0xFF;
+                }
+                if let (Some(rtc), 0x08..=0x0C) = (&self.rtc, self.ram_bank) {
+                    return rtc.latched[usize::from(self.ram_bank - 0x08)];
+                }
+                match self.ram_offset(address) {
+                    Some(offset) => self.ram[offset],
+                    None => 0xFF,
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes the processor's address `address`: the controller's registers in ROM, or RAM.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match (self.mapper, address) {
+            (Mapper::None, 0x0000..=0x7FFF) => {}
+            (_, 0x0000..=0x1FFF) => self.ram_enabled = value & 0x0F == 0x0A,
+            (Mapper::Mbc1, 0x2000..=0x3FFF) => self.rom_bank = u16::from(value & 0x1F).max(1),
+            (Mapper::Mbc3, 0x2000..=0x3FFF) => self.rom_bank = u16::from(value & 0x7F).max(1),
+            (Mapper::Mbc5, 0x2000..=0x2FFF) => {
+                self.rom_bank = self.rom_bank & 0x100 | u16::from(value);
+            }
+            (Mapper::Mbc5, 0x3000..=0x3FFF) => {
+                self.rom_bank = self.rom_bank & 0xFF | u16::from(value & 1) << 8;
+            }
+            (Mapper::Mbc1, 0x4000..=0x5FFF) => self.ram_bank = value & 0x03,
+            (Mapper::Mbc3, 0x4000..=0x5FFF) => self.ram_bank = value & 0x0F,
+            (Mapper::Mbc5, 0x4000..=0x5FFF) => self.ram_bank = value & 0x0F,
+            (Mapper::Mbc1, 0x6000..=0x7FFF) => self.mode = value & 1 != 0,
+            (Mapper::Mbc3, 0x6000..=0x7FFF) => {
+                if let (Some(rtc), 0x00, 0x01) = (&mut self.rtc, self.latch, value) {
+                    rtc.latched = rtc.registers;
+                }
+                self.latch = value;
+            }
+            (_, 0xA000..=0xBFFF) => {
+                if !self.ram_enabled && self.mapper != Mapper::None {
+                    return;
+                }
+                if let (Some(rtc), 0x08..=0x0C) = (&mut self.rtc, self.ram_bank) {
+                    let register = usize::from(self.ram_bank - 0x08);
+                    rtc.write(register, value);
+                    rtc.latched[register] = rtc.registers[register];
+                    return;
+                }
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the bank of ROM at 0x0000, which is 0 unless MBC1 in its second mode switches it.
+    fn low_bank(&self) -> usize {
+        match self.mapper {
+            Mapper::Mbc1 if self.mode => usize::from(self.ram_bank) << 5,
+            _ => 0,
+        }
+    }
+
+    /// Returns the bank of ROM at 0x4000.
+    fn high_bank(&self) -> usize {
+        match self.mapper {
+            Mapper::None => 1,
+            Mapper::Mbc1 => usize::from(self.ram_bank) << 5 | usize::from(self.rom_bank),
+            Mapper::Mbc3 | Mapper::Mbc5 => usize::from(self.rom_bank),
+        }
+    }
+
+    fn rom_offset(&self, bank: usize, address: u16) -> usize {
+        ((bank * ROM_BANK) | (usize::from(address) & (ROM_BANK - 1))) & (self.rom.len() - 1)
+    }
+
+    /// Returns where in RAM the address `address`, from 0xA000, is, or `None` without RAM.
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let bank = match self.mapper {
+            Mapper::None => 0,
+            Mapper::Mbc1 if !self.mode => 0,
+            Mapper::Mbc5 if self.rumble => self.ram_bank & 0x07,
+            _ => self.ram_bank,
+        };
+        let offset = usize::from(bank) * RAM_BANK + usize::from(address - 0xA000);
+        Some(offset % self.ram.len())
+    }
+
+    /// Returns what the battery keeps, in the layout of `.sav` files, or `None` without a
+    /// battery.
+    pub fn battery(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        let mut battery = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            for &register in rtc.registers.iter().chain(&rtc.latched) {
+                battery.extend_from_slice(&u32::from(register).to_le_bytes());
+            }
+            battery.extend_from_slice(&unix_time().to_le_bytes());
+        }
+        Some(battery)
+    }
+
+    /// Restores what [`battery`](Cartridge::battery) returned. The clock counts the time that
+    /// has passed since, and may be missing, as in files of emulators without one.
+    pub fn load_battery(&mut self, battery: &[u8]) -> Result<(), Error> {
+        let ram = self.ram.len();
+        let rtc = battery.len() == ram + RTC_SIZE && self.rtc.is_some();
+        if !self.battery || battery.len() != ram && !rtc {
+            return Err(Error::State(
+                "not the battery RAM of this cartridge".to_string(),
+            ));
+        }
+        self.ram.copy_from_slice(&battery[..ram]);
+        if let (true, Some(clock)) = (rtc, &mut self.rtc) {
+            let word = |index: usize| {
+                let bytes = &battery[ram + index * 4..][..4];
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u8
+            };
+            for register in 0..5 {
+                clock.write(register, word(register));
+                clock.latched[register] = word(5 + register);
+            }
+            let mut saved = [0; 8];
+            saved.copy_from_slice(&battery[ram + 40..]);
+            clock.advance(unix_time().saturating_sub(u64::from_le_bytes(saved)));
+        }
+        Ok(())
+    }
+
+    /// Returns the length of the state written by [`save`](Cartridge::save).
+    pub fn state_size(&self) -> usize {
+        REGISTERS_SIZE
+            + self.ram.len()
+            + if self.rtc.is_some() {
+                RTC_STATE_SIZE
+            } else {
+                0
+            }
+    }
+
+    /// Returns the state of the controller, RAM and the clock, for
+    /// [`restore`](Cartridge::restore).
+    pub fn save(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.state_size());
+        let [bank_low, bank_high] = self.rom_bank.to_le_bytes();
+        state.extend_from_slice(&[
+            u8::from(self.ram_enabled),
+            bank_low,
+            bank_high,
+            self.ram_bank,
+            u8::from(self.mode),
+            self.latch,
+        ]);
+        state.extend_from_slice(&self.ram);
+        if let Some(rtc) = &self.rtc {
+            state.extend_from_slice(&rtc.registers);
+            state.extend_from_slice(&rtc.latched);
+            state.extend_from_slice(&rtc.cycles.to_le_bytes());
+        }
+        state
+    }
+
+    /// Restores a state returned by [`save`](Cartridge::save), which must be
+    /// [`state_size`](Cartridge::state_size) bytes.
+    pub fn restore(&mut self, state: &[u8]) {
+        let (registers, state) = state.split_at(REGISTERS_SIZE);
+        let (ram, state) = state.split_at(self.ram.len());
+        self.ram_enabled = registers[0] != 0;
+        self.rom_bank = u16::from_le_bytes([registers[1], registers[2]]) & 0x1FF;
+        self.ram_bank = registers[3] & 0x0F;
+        self.mode = registers[4] != 0;
+        self.latch = registers[5];
+        self.ram.copy_from_slice(ram);
+        if let Some(rtc) = &mut self.rtc {
+            for (register, &value) in state[..5].iter().enumerate() {
+                rtc.write(register, value);
+            }
+            rtc.latched.copy_from_slice(&state[5..10]);
+            let cycles = u32::from_le_bytes([state[10], state[11], state[12], state[13]]);
+            rtc.cycles = cycles.min(CLOCK_HZ - 1);
+        }
+    }
+}
+
+/// Returns the seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a ROM of `banks` 16KB banks, each filled with its number, and a header for
+    /// cartridge type `kind` with RAM of size code `ram`.
+    fn rom(kind: u8, banks: usize, ram: u8) -> Vec<u8> {
+        let mut rom: Vec<u8> = (0..banks * ROM_BANK)
+            .map(|offset| (offset / ROM_BANK) as u8)
+            .collect();
+        rom[LOGO_ADDRESS..LOGO_ADDRESS + LOGO.len()].copy_from_slice(&LOGO);
+        rom[0x0134..0x0139].copy_from_slice(b"TETRA");
+        rom[0x0139..0x0144].iter_mut().for_each(|byte| *byte = 0);
+        rom[0x0147] = kind;
+        rom[0x0149] = ram;
+        rom
+    }
+
+    #[test]
+    fn reads_the_header() {
+        let cartridge = Cartridge::from_rom(&rom(0x13, 4, 0x03)).unwrap();
+        assert!(is_rom(&rom(0x00, 2, 0)));
+        assert_eq!(cartridge.title(), "TETRA");
+        assert_eq!(cartridge.mapper(), Mapper::Mbc3);
+        assert!(cartridge.has_battery() && !cartridge.has_rtc());
+        assert_eq!(cartridge.ram().len(), 0x8000);
+
+        assert!(!is_rom(&[0; 0x8000]));
+        assert!(matches!(
+            Cartridge::from_rom(&[0; 0x100]),
+            Err(Error::Rom(_))
+        ));
+        assert!(matches!(
+            Cartridge::from_rom(&rom(0x05, 2, 0)),
+            Err(Error::Rom(_))
+        ));
+        // A short ROM is padded.
+        let cartridge = Cartridge::from_rom(&rom(0x00, 2, 0)[..0x6000]).unwrap();
+        assert_eq!(cartridge.read(0x7FFF), 0xFF);
+    }
+
+    #[test]
+    fn switches_mbc1_banks() {
+        let mut cartridge = Cartridge::from_rom(&rom(0x03, 64, 0x03)).unwrap();
+        assert_eq!(cartridge.read(0x4000), 1);
+        // Bank 0 selects bank 1.
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x4000), 1);
+        cartridge.write(0x2000, 0x25);
+        assert_eq!(cartridge.read(0x4000), 5);
+        cartridge.write(0x4000, 0x01);
+        assert_eq!(cartridge.read(0x4000), 0x25);
+        assert_eq!(cartridge.read(0x0000), 0);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0x0000), 0x20);
+
+        // RAM is closed until enabled, and banked in the second mode.
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.ram()[RAM_BANK], 0x12);
+        cartridge.write(0x6000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+    }
+
+    #[test]
+    fn switches_mbc3_and_mbc5_banks() {
+        let mut cartridge = Cartridge::from_rom(&rom(0x11, 128, 0)).unwrap();
+        cartridge.write(0x2000, 0x7F);
+        assert_eq!(cartridge.read(0x4000), 0x7F);
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x4000), 0x01);
+
+        let mut cartridge = Cartridge::from_rom(&rom(0x1A, 512, 0x04)).unwrap();
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x4000), 0x00);
+        cartridge.write(0x2000, 0x34);
+        cartridge.write(0x3000, 0x01);
+        assert_eq!(cartridge.read(0x4000), 0x34);
+        assert_eq!(cartridge.high_bank(), 0x134);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x0F);
+        cartridge.write(0xBFFF, 0x56);
+        assert_eq!(cartridge.ram()[16 * RAM_BANK - 1], 0x56);
+    }
+
+    #[test]
+    fn keeps_time() {
+        let mut cartridge = Cartridge::from_rom(&rom(0x10, 4, 0x03)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        // 23:59:59 on day 511.
+        for (register, value) in [(0x08, 59), (0x09, 59), (0x0A, 23), (0x0B, 0xFF), (0x0C, 1)] {
+            cartridge.write(0x4000, register);
+            cartridge.write(0xA000, value);
+        }
+        for _ in 0..CLOCK_HZ / 4 {
+            cartridge.tick();
+        }
+        // The latched registers hold until latched again.
+        cartridge.write(0x4000, 0x08);
+        assert_eq!(cartridge.read(0xA000), 59);
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        let registers: Vec<u8> = (0x08..=0x0C)
+            .map(|register| {
+                cartridge.write(0x4000, register);
+                cartridge.read(0xA000)
+            })
+            .collect();
+        assert_eq!(registers, [0, 0, 0, 0, DAY_CARRY]);
+
+        // The clock stops while halted.
+        cartridge.write(0x4000, 0x0C);
+        cartridge.write(0xA000, HALT);
+        for _ in 0..CLOCK_HZ / 4 {
+            cartridge.tick();
+        }
+        assert_eq!(cartridge.rtc.as_ref().unwrap().registers[0], 0);
+    }
+
+    #[test]
+    fn keeps_the_battery() {
+        let mut cartridge = Cartridge::from_rom(&rom(0x10, 4, 0x02)).unwrap();
+        cartridge.ram_mut()[0] = 0x42;
+        cartridge.rtc.as_mut().unwrap().registers = [10, 20, 3, 4, 0];
+        let mut battery = cartridge.battery().unwrap();
+        assert_eq!(battery.len(), RAM_BANK + RTC_SIZE);
+        // Saved an hour and a day ago.
+        let saved = unix_time() - 90000;
+        battery[RAM_BANK + 40..].copy_from_slice(&saved.to_le_bytes());
+
+        let mut loaded = Cartridge::from_rom(&rom(0x10, 4, 0x02)).unwrap();
+        loaded.load_battery(&battery).unwrap();
+        assert_eq!(loaded.ram()[0], 0x42);
+        let registers = loaded.rtc.as_ref().unwrap().registers;
+        assert!(registers[0] >= 10 && registers[0] < 15);
+        assert_eq!(registers[1..], [20, 4, 5, 0]);
+        // A file without the clock.
+        loaded.load_battery(&battery[..RAM_BANK]).unwrap();
+        assert!(loaded.load_battery(&battery[..100]).is_err());
+
+        let cartridge = Cartridge::from_rom(&rom(0x02, 4, 0x02)).unwrap();
+        assert_eq!(cartridge.battery(), None);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut cartridge = Cartridge::from_rom(&rom(0x10, 8, 0x03)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x2000, 0x05);
+        cartridge.write(0x4000, 0x02);
+        cartridge.write(0xA123, 0x77);
+        cartridge.write(0x4000, 0x09);
+        cartridge.write(0xA000, 30);
+        let state = cartridge.save();
+        assert_eq!(state.len(), cartridge.state_size());
+        let mut restored = Cartridge::from_rom(&rom(0x10, 8, 0x03)).unwrap();
+        restored.restore(&state);
+        assert_eq!(restored.save(), state);
+        assert_eq!(restored.read(0x4000), 5);
+        restored.write(0x4000, 0x02);
+        assert_eq!(restored.read(0xA123), 0x77);
+    }
+}
@@ -0,0 +1,1029 @@
+//! The Sharp SM83, the processor of the Game Boy.
+//!
+//! The SM83 is an Intel 8080 with some of the Z80's additions, such as the `CB`-prefixed bit
+//! instructions, and some of its own, such as `LDH` into the last page of memory and `SWAP`. It
+//! has seven 8-bit registers, which pair into `BC`, `DE` and `HL`, four flags in `F`, a stack
+//! pointer and a program counter.
+//!
+//! Its clock runs at 4.19MHz, but everything it does takes machine cycles of four clock cycles,
+//! each of which reads memory, writes it, or does neither. This module makes those cycles one by
+//! one, in the order the SM83 makes them, so that the timer, the PPU and the other devices of the
+//! Game Boy run in step with the program.
+//!
+//! Devices interrupt the program by setting bits of `IF` at 0xFF0F, each enabled by the same bit
+//! of `IE` at 0xFFFF. The processor takes the lowest pending one between instructions when its
+//! interrupt master enable is set, which `EI` sets after the instruction that follows it, `RETI`
+//! sets at once, and `DI` and taking an interrupt clear. `HALT` sleeps until one is pending,
+//! whether or not it is taken.
+
+/// Length of the state written by [`Cpu::save`].
+pub const STATE_SIZE: usize = 21;
+
+/// The zero flag, set when a result is 0.
+pub const ZERO: u8 = 0x80;
+
+/// The subtract flag, set by subtractions for `DAA`.
+pub const SUBTRACT: u8 = 0x40;
+
+/// The half-carry flag, set on a carry out of bit 3, or out of bit 11 for 16-bit additions.
+pub const HALF_CARRY: u8 = 0x20;
+
+/// The carry flag, set on a carry out of bit 7, or out of bit 15 for 16-bit additions, and when a
+/// subtraction borrows.
+pub const CARRY: u8 = 0x10;
+
+/// What the processor is wired to: memory, with the devices mapped into it, and the interrupts
+/// they request.
+///
+/// Every call to [`read`](Bus::read), [`write`](Bus::write) or [`tick`](Bus::tick) is one machine
+/// cycle, by which a machine runs its other devices.
+pub trait Bus {
+    /// Reads the byte at `address`, for a machine cycle.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes `value` at `address`, for a machine cycle.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Lets a machine cycle pass without accessing memory.
+    fn tick(&mut self);
+
+    /// Returns the interrupts both requested and enabled, `IF` AND `IE`, in bits 0 to 4.
+    fn pending(&mut self) -> u8 {
+        0
+    }
+
+    /// Clears the request of the interrupts in `interrupts` as one is taken.
+    fn acknowledge(&mut self, _interrupts: u8) {}
+
+    /// Tells the devices that a `STOP` has put the Game Boy to sleep.
+    fn stop(&mut self) {}
+}
+
+/// An 8-bit register, as [`Cpu::register`] names it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// The accumulator.
+    A,
+    /// The flags, [`ZERO`], [`SUBTRACT`], [`HALF_CARRY`] and [`CARRY`], in the high nibble.
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+/// The processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    a: u8,
+    f: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    h: u8,
+    l: u8,
+    sp: u16,
+    pc: u16,
+    /// The interrupt master enable.
+    ime: bool,
+    /// Whether an `EI` sets the interrupt master enable after the next instruction.
+    enabling: bool,
+    /// Whether a `HALT` or a `STOP` sleeps until an interrupt is pending.
+    halted: bool,
+    /// Whether the next opcode is read without moving past it, as after a `HALT` that found an
+    /// interrupt pending with interrupts disabled.
+    halt_bug: bool,
+    /// Whether an opcode that does not exist has locked the processor up until it is reset.
+    locked: bool,
+    /// Machine cycles run since the processor was created.
+    cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    /// Creates a processor as the boot ROM of the original Game Boy leaves it, about to run the
+    /// cartridge from 0x0100.
+    pub fn new() -> Self {
+        Cpu {
+            a: 0x01,
+            f: ZERO | HALF_CARRY | CARRY,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+            ime: false,
+            enabling: false,
+            halted: false,
+            halt_bug: false,
+            locked: false,
+            cycles: 0,
+        }
+    }
+
+    /// Returns `register`.
+    pub fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::F => self.f,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::E => self.e,
+            Register::H => self.h,
+            Register::L => self.l,
+        }
+    }
+
+    /// Sets `register` to `value`. The low nibble of `F` is always 0.
+    pub fn set_register(&mut self, register: Register, value: u8) {
+        match register {
+            Register::A => self.a = value,
+            Register::F => self.f = value & 0xF0,
+            Register::B => self.b = value,
+            Register::C => self.c = value,
+            Register::D => self.d = value,
+            Register::E => self.e = value,
+            Register::H => self.h = value,
+            Register::L => self.l = value,
+        }
+    }
+
+    /// Returns the stack pointer.
+    pub fn stack_pointer(&self) -> u16 {
+        self.sp
+    }
+
+    /// Sets the stack pointer to `address`.
+    pub fn set_stack_pointer(&mut self, address: u16) {
+        self.sp = address;
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    /// Jumps to `address`.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    /// Returns whether interrupts are taken, the interrupt master enable.
+    pub fn interrupts_enabled(&self) -> bool {
+        self.ime
+    }
+
+    /// Returns whether a `HALT` or a `STOP` is waiting for an interrupt.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns whether an opcode that does not exist has locked the processor up, until it is
+    /// reset.
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Returns the number of machine cycles run since the processor was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs the next instruction, or takes an interrupt, and returns how many machine cycles it
+    /// took. A halted or locked processor lets a machine cycle pass.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        let start = self.cycles;
+        if self.locked {
+            self.tick(bus);
+        } else if self.halted {
+            self.tick(bus);
+            self.halted = bus.pending() == 0;
+        } else if self.ime && bus.pending() != 0 {
+            self.interrupt(bus);
+        } else {
+            if self.enabling {
+                self.ime = true;
+                self.enabling = false;
+            }
+            let opcode = self.read(bus, self.pc);
+            if self.halt_bug {
+                self.halt_bug = false;
+            } else {
+                self.pc = self.pc.wrapping_add(1);
+            }
+            self.execute(opcode, bus);
+        }
+        (self.cycles - start) as u8
+    }
+
+    /// Returns the state of the processor, for [`restore`](Cpu::restore).
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..8].copy_from_slice(&[
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ]);
+        state[8..10].copy_from_slice(&self.sp.to_le_bytes());
+        state[10..12].copy_from_slice(&self.pc.to_le_bytes());
+        state[12] = u8::from(self.ime)
+            | u8::from(self.enabling) << 1
+            | u8::from(self.halted) << 2
+            | u8::from(self.halt_bug) << 3
+            | u8::from(self.locked) << 4;
+        state[13..].copy_from_slice(&self.cycles.to_le_bytes());
+        state
+    }
+
+    /// Restores a state returned by [`save`](Cpu::save).
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        let [a, f, b, c, d, e, h, l] = [
+            state[0], state[1], state[2], state[3], state[4], state[5], state[6], state[7],
+        ];
+        *self = Cpu {
+            a,
+            f: f & 0xF0,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            sp: u16::from_le_bytes([state[8], state[9]]),
+            pc: u16::from_le_bytes([state[10], state[11]]),
+            ime: state[12] & 0x01 != 0,
+            enabling: state[12] & 0x02 != 0,
+            halted: state[12] & 0x04 != 0,
+            halt_bug: state[12] & 0x08 != 0,
+            locked: state[12] & 0x10 != 0,
+            cycles: u64::from_le_bytes([
+                state[13], state[14], state[15], state[16], state[17], state[18], state[19],
+                state[20],
+            ]),
+        };
+    }
+
+    fn tick(&mut self, bus: &mut impl Bus) {
+        bus.tick();
+        self.cycles += 1;
+    }
+
+    fn read(&mut self, bus: &mut impl Bus, address: u16) -> u8 {
+        self.cycles += 1;
+        bus.read(address)
+    }
+
+    fn write(&mut self, bus: &mut impl Bus, address: u16, value: u8) {
+        self.cycles += 1;
+        bus.write(address, value);
+    }
+
+    /// Reads the byte at the program counter, and moves past it.
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = self.read(bus, self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = self.fetch(bus);
+        u16::from_le_bytes([low, self.fetch(bus)])
+    }
+
+    fn push(&mut self, bus: &mut impl Bus, value: u16) {
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(bus, self.sp, (value >> 8) as u8);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(bus, self.sp, value as u8);
+    }
+
+    fn pop(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = self.read(bus, self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = self.read(bus, self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Takes the lowest pending interrupt, in five machine cycles. The interrupt is chosen after
+    /// the high byte of the program counter is pushed, so a push that overwrites `IE` can cancel
+    /// it, and the program then goes on at 0x0000.
+    fn interrupt(&mut self, bus: &mut impl Bus) {
+        self.ime = false;
+        self.tick(bus);
+        self.tick(bus);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(bus, self.sp, (self.pc >> 8) as u8);
+        let pending = bus.pending();
+        self.sp = self.sp.wrapping_sub(1);
+        self.write(bus, self.sp, self.pc as u8);
+        self.pc = if pending == 0 {
+            0x0000
+        } else {
+            let interrupt = pending.trailing_zeros();
+            bus.acknowledge(1 << interrupt);
+            0x0040 + 8 * interrupt as u16
+        };
+        self.tick(bus);
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.f & flag != 0
+    }
+
+    fn set_flags(&mut self, zero: bool, subtract: bool, half_carry: bool, carry: bool) {
+        self.f = if zero { ZERO } else { 0 }
+            | if subtract { SUBTRACT } else { 0 }
+            | if half_carry { HALF_CARRY } else { 0 }
+            | if carry { CARRY } else { 0 };
+    }
+
+    fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.h, self.l])
+    }
+
+    fn set_hl(&mut self, value: u16) {
+        let [h, l] = value.to_be_bytes();
+        self.h = h;
+        self.l = l;
+    }
+
+    /// Returns the register pair numbered `pair` in opcodes: `BC`, `DE`, `HL` or `SP`.
+    fn pair(&self, pair: u8) -> u16 {
+        match pair {
+            0 => u16::from_be_bytes([self.b, self.c]),
+            1 => u16::from_be_bytes([self.d, self.e]),
+            2 => self.hl(),
+            _ => self.sp,
+        }
+    }
+
+    fn set_pair(&mut self, pair: u8, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        match pair {
+            0 => {
+                self.b = high;
+                self.c = low;
+            }
+            1 => {
+                self.d = high;
+                self.e = low;
+            }
+            2 => self.set_hl(value),
+            _ => self.sp = value,
+        }
+    }
+
+    /// Returns the register numbered `register` in opcodes, `B`, `C`, `D`, `E`, `H`, `L`, `(HL)`
+    /// or `A`, reading memory for `(HL)`.
+    fn get(&mut self, bus: &mut impl Bus, register: u8) -> u8 {
+        match register {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => self.h,
+            5 => self.l,
+            6 => self.read(bus, self.hl()),
+            _ => self.a,
+        }
+    }
+
+    fn set(&mut self, bus: &mut impl Bus, register: u8, value: u8) {
+        match register {
+            0 => self.b = value,
+            1 => self.c = value,
+            2 => self.d = value,
+            3 => self.e = value,
+            4 => self.h = value,
+            5 => self.l = value,
+            6 => self.write(bus, self.hl(), value),
+            _ => self.a = value,
+        }
+    }
+
+    /// Returns whether the condition numbered `condition` in opcodes holds: `NZ`, `Z`, `NC` or
+    /// `C`.
+    fn condition(&self, condition: u8) -> bool {
+        match condition {
+            0 => !self.flag(ZERO),
+            1 => self.flag(ZERO),
+            2 => !self.flag(CARRY),
+            _ => self.flag(CARRY),
+        }
+    }
+
+    fn execute(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        let pair = y >> 1;
+        match opcode {
+            // NOP
+            0x00 => {}
+            // LD (nn),SP
+            0x08 => {
+                let address = self.fetch16(bus);
+                let [high, low] = self.sp.to_be_bytes();
+                self.write(bus, address, low);
+                self.write(bus, address.wrapping_add(1), high);
+            }
+            // STOP, which is two bytes long.
+            0x10 => {
+                self.fetch(bus);
+                self.halted = true;
+                bus.stop();
+            }
+            // JR e
+            0x18 => self.jump_relative(bus, true),
+            // JR cc,e
+            0x20 | 0x28 | 0x30 | 0x38 => self.jump_relative(bus, self.condition(y - 4)),
+            // LD rr,nn
+            0x01 | 0x11 | 0x21 | 0x31 => {
+                let value = self.fetch16(bus);
+                self.set_pair(pair, value);
+            }
+            // ADD HL,rr
+            0x09 | 0x19 | 0x29 | 0x39 => {
+                let hl = self.hl();
+                let value = self.pair(pair);
+                let (sum, carry) = hl.overflowing_add(value);
+                let half_carry = (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF;
+                self.set_flags(self.flag(ZERO), false, half_carry, carry);
+                self.set_hl(sum);
+                self.tick(bus);
+            }
+            // LD (BC),A; LD (DE),A; LD (HL+),A; LD (HL-),A
+            0x02 | 0x12 | 0x22 | 0x32 => {
+                let address = self.indirect(pair);
+                self.write(bus, address, self.a);
+            }
+            // LD A,(BC); LD A,(DE); LD A,(HL+); LD A,(HL-)
+            0x0A | 0x1A | 0x2A | 0x3A => {
+                let address = self.indirect(pair);
+                self.a = self.read(bus, address);
+            }
+            // INC rr
+            0x03 | 0x13 | 0x23 | 0x33 => {
+                self.set_pair(pair, self.pair(pair).wrapping_add(1));
+                self.tick(bus);
+            }
+            // DEC rr
+            0x0B | 0x1B | 0x2B | 0x3B => {
+                self.set_pair(pair, self.pair(pair).wrapping_sub(1));
+                self.tick(bus);
+            }
+            // INC r
+            0x04 | 0x0C | 0x14 | 0x1C | 0x24 | 0x2C | 0x34 | 0x3C => {
+                let value = self.get(bus, y).wrapping_add(1);
+                self.set_flags(value == 0, false, value & 0x0F == 0, self.flag(CARRY));
+                self.set(bus, y, value);
+            }
+            // DEC r
+            0x05 | 0x0D | 0x15 | 0x1D | 0x25 | 0x2D | 0x35 | 0x3D => {
+                let value = self.get(bus, y).wrapping_sub(1);
+                self.set_flags(value == 0, true, value & 0x0F == 0x0F, self.flag(CARRY));
+                self.set(bus, y, value);
+            }
+            // LD r,n
+            0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => {
+                let value = self.fetch(bus);
+                self.set(bus, y, value);
+            }
+            // RLCA; RRCA; RLA; RRA, which unlike their CB forms always clear the zero flag.
+            0x07 | 0x0F | 0x17 | 0x1F => {
+                self.a = self.shift(y, self.a);
+                self.f &= !ZERO;
+            }
+            // DAA
+            0x27 => self.decimal_adjust(),
+            // CPL
+            0x2F => {
+                self.a = !self.a;
+                self.f |= SUBTRACT | HALF_CARRY;
+            }
+            // SCF
+            0x37 => self.set_flags(self.flag(ZERO), false, false, true),
+            // CCF
+            0x3F => self.set_flags(self.flag(ZERO), false, false, !self.flag(CARRY)),
+            // HALT, which goes on at once if an interrupt is pending, and with interrupts
+            // disabled reads the next opcode twice.
+            0x76 => {
+                if bus.pending() == 0 {
+                    self.halted = true;
+                } else if !self.ime {
+                    self.halt_bug = true;
+                }
+            }
+            // LD r,r
+            0x40..=0x7F => {
+                let value = self.get(bus, z);
+                self.set(bus, y, value);
+            }
+            // ADD, ADC, SUB, SBC, AND, XOR, OR and CP of A and r
+            0x80..=0xBF => {
+                let value = self.get(bus, z);
+                self.arithmetic(y, value);
+            }
+            // ... and of A and n
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+                let value = self.fetch(bus);
+                self.arithmetic(y, value);
+            }
+            // RET cc
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => {
+                self.tick(bus);
+                if self.condition(y) {
+                    self.pc = self.pop(bus);
+                    self.tick(bus);
+                }
+            }
+            // RET
+            0xC9 => {
+                self.pc = self.pop(bus);
+                self.tick(bus);
+            }
+            // RETI
+            0xD9 => {
+                self.pc = self.pop(bus);
+                self.tick(bus);
+                self.ime = true;
+            }
+            // LDH (n),A
+            0xE0 => {
+                let address = 0xFF00 | u16::from(self.fetch(bus));
+                self.write(bus, address, self.a);
+            }
+            // LDH A,(n)
+            0xF0 => {
+                let address = 0xFF00 | u16::from(self.fetch(bus));
+                self.a = self.read(bus, address);
+            }
+            // ADD SP,e
+            0xE8 => {
+                self.sp = self.add_offset(bus);
+                self.tick(bus);
+                self.tick(bus);
+            }
+            // LD HL,SP+e
+            0xF8 => {
+                let value = self.add_offset(bus);
+                self.set_hl(value);
+                self.tick(bus);
+            }
+            // POP rr
+            0xC1 | 0xD1 | 0xE1 | 0xF1 => {
+                let value = self.pop(bus);
+                if opcode == 0xF1 {
+                    let [a, f] = value.to_be_bytes();
+                    self.a = a;
+                    self.f = f & 0xF0;
+                } else {
+                    self.set_pair(pair, value);
+                }
+            }
+            // PUSH rr
+            0xC5 | 0xD5 | 0xE5 | 0xF5 => {
+                let value = if opcode == 0xF5 {
+                    u16::from_be_bytes([self.a, self.f])
+                } else {
+                    self.pair(pair)
+                };
+                self.tick(bus);
+                self.push(bus, value);
+            }
+            // JP HL
+            0xE9 => self.pc = self.hl(),
+            // LD SP,HL
+            0xF9 => {
+                self.sp = self.hl();
+                self.tick(bus);
+            }
+            // JP nn
+            0xC3 => self.jump(bus, true),
+            // JP cc,nn
+            0xC2 | 0xCA | 0xD2 | 0xDA => self.jump(bus, self.condition(y)),
+            // LD (C),A
+            0xE2 => self.write(bus, 0xFF00 | u16::from(self.c), self.a),
+            // LD A,(C)
+            0xF2 => self.a = self.read(bus, 0xFF00 | u16::from(self.c)),
+            // LD (nn),A
+            0xEA => {
+                let address = self.fetch16(bus);
+                self.write(bus, address, self.a);
+            }
+            // LD A,(nn)
+            0xFA => {
+                let address = self.fetch16(bus);
+                self.a = self.read(bus, address);
+            }
+            0xCB => {
+                let opcode = self.fetch(bus);
+                self.execute_cb(opcode, bus);
+            }
+            // DI
+            0xF3 => {
+                self.ime = false;
+                self.enabling = false;
+            }
+            // EI
+            0xFB => self.enabling = true,
+            // CALL nn
+            0xCD => self.call(bus, true),
+            // CALL cc,nn
+            0xC4 | 0xCC | 0xD4 | 0xDC => self.call(bus, self.condition(y)),
+            // RST n
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                self.tick(bus);
+                self.push(bus, self.pc);
+                self.pc = u16::from(opcode & 0x38);
+            }
+            // 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC and 0xFD, which lock the
+            // processor up.
+            _ => {
+                self.pc = self.pc.wrapping_sub(1);
+                self.locked = true;
+            }
+        }
+    }
+
+    /// Runs the `CB`-prefixed instruction `opcode`: a shift or rotation, `BIT`, `RES` or `SET`.
+    fn execute_cb(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        let value = self.get(bus, z);
+        match opcode >> 6 {
+            0 => {
+                let result = self.shift(y, value);
+                self.set(bus, z, result);
+            }
+            1 => {
+                let zero = value & 1 << y == 0;
+                self.set_flags(zero, false, true, self.flag(CARRY));
+            }
+            2 => self.set(bus, z, value & !(1 << y)),
+            _ => self.set(bus, z, value | 1 << y),
+        }
+    }
+
+    /// Returns the address `LD (BC),A` and its kin access with the pair numbered `pair`, moving
+    /// `HL` up or down after it.
+    fn indirect(&mut self, pair: u8) -> u16 {
+        match pair {
+            0 | 1 => self.pair(pair),
+            2 => {
+                let hl = self.hl();
+                self.set_hl(hl.wrapping_add(1));
+                hl
+            }
+            _ => {
+                let hl = self.hl();
+                self.set_hl(hl.wrapping_sub(1));
+                hl
+            }
+        }
+    }
+
+    /// Runs the arithmetic or logic operation numbered `operation` in opcodes on `A` and `value`.
+    fn arithmetic(&mut self, operation: u8, value: u8) {
+        let carry_in = u8::from((operation == 1 || operation == 3) && self.flag(CARRY));
+        match operation {
+            // ADD, ADC
+            0 | 1 => {
+                let sum = u16::from(self.a) + u16::from(value) + u16::from(carry_in);
+                let half_carry = (self.a & 0x0F) + (value & 0x0F) + carry_in > 0x0F;
+                self.set_flags(sum as u8 == 0, false, half_carry, sum > 0xFF);
+                self.a = sum as u8;
+            }
+            // SUB, SBC, CP
+            2 | 3 | 7 => {
+                let difference = i16::from(self.a) - i16::from(value) - i16::from(carry_in);
+                let half_carry =
+                    i16::from(self.a & 0x0F) - i16::from(value & 0x0F) - i16::from(carry_in) < 0;
+                self.set_flags(difference as u8 == 0, true, half_carry, difference < 0);
+                if operation != 7 {
+                    self.a = difference as u8;
+                }
+            }
+            // AND
+            4 => {
+                self.a &= value;
+                self.set_flags(self.a == 0, false, true, false);
+            }
+            // XOR
+            5 => {
+                self.a ^= value;
+                self.set_flags(self.a == 0, false, false, false);
+            }
+            // OR
+            _ => {
+                self.a |= value;
+                self.set_flags(self.a == 0, false, false, false);
+            }
+        }
+    }
+
+    /// Runs the shift or rotation numbered `operation` in `CB` opcodes on `value`, setting the
+    /// flags: `RLC`, `RRC`, `RL`, `RR`, `SLA`, `SRA`, `SWAP` or `SRL`.
+    fn shift(&mut self, operation: u8, value: u8) -> u8 {
+        let carry_in = u8::from(self.flag(CARRY));
+        let (result, carry) = match operation {
+            0 => (value.rotate_left(1), value & 0x80 != 0),
+            1 => (value.rotate_right(1), value & 0x01 != 0),
+            2 => (value << 1 | carry_in, value & 0x80 != 0),
+            3 => (value >> 1 | carry_in << 7, value & 0x01 != 0),
+            4 => (value << 1, value & 0x80 != 0),
+            5 => (value >> 1 | value & 0x80, value & 0x01 != 0),
+            6 => (value.rotate_left(4), false),
+            _ => (value >> 1, value & 0x01 != 0),
+        };
+        self.set_flags(result == 0, false, false, carry);
+        result
+    }
+
+    /// Adjusts `A` to binary-coded decimal after an addition or a subtraction of two such numbers.
+    fn decimal_adjust(&mut self) {
+        let subtract = self.flag(SUBTRACT);
+        let mut carry = self.flag(CARRY);
+        let mut adjust = 0;
+        if self.flag(HALF_CARRY) || !subtract && self.a & 0x0F > 0x09 {
+            adjust |= 0x06;
+        }
+        if carry || !subtract && self.a > 0x99 {
+            adjust |= 0x60;
+            carry = true;
+        }
+        self.a = if subtract {
+            self.a.wrapping_sub(adjust)
+        } else {
+            self.a.wrapping_add(adjust)
+        };
+        self.set_flags(self.a == 0, subtract, false, carry);
+    }
+
+    /// Returns the stack pointer plus the signed byte that follows the opcode, setting the
+    /// half-carry and carry flags from the addition of their low bytes, as `ADD SP,e` and
+    /// `LD HL,SP+e` do.
+    fn add_offset(&mut self, bus: &mut impl Bus) -> u16 {
+        let offset = self.fetch(bus);
+        let half_carry = (self.sp & 0x0F) + u16::from(offset & 0x0F) > 0x0F;
+        let carry = (self.sp & 0xFF) + u16::from(offset) > 0xFF;
+        self.set_flags(false, false, half_carry, carry);
+        self.sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn jump_relative(&mut self, bus: &mut impl Bus, taken: bool) {
+        let offset = self.fetch(bus) as i8;
+        if taken {
+            self.pc = self.pc.wrapping_add(offset as u16);
+            self.tick(bus);
+        }
+    }
+
+    fn jump(&mut self, bus: &mut impl Bus, taken: bool) {
+        let address = self.fetch16(bus);
+        if taken {
+            self.pc = address;
+            self.tick(bus);
+        }
+    }
+
+    fn call(&mut self, bus: &mut impl Bus, taken: bool) {
+        let address = self.fetch16(bus);
+        if taken {
+            self.tick(bus);
+            self.push(bus, self.pc);
+            self.pc = address;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 64KB of RAM, with `IF` and `IE` at 0xFF0F and 0xFFFF, counting the machine cycles that
+    /// pass.
+    struct Memory {
+        memory: Vec<u8>,
+        cycles: u64,
+    }
+
+    impl Memory {
+        fn with_program(program: &[u8]) -> Self {
+            let mut memory = vec![0; 0x10000];
+            memory[0x0100..0x0100 + program.len()].copy_from_slice(program);
+            Memory { memory, cycles: 0 }
+        }
+    }
+
+    impl Bus for Memory {
+        fn read(&mut self, address: u16) -> u8 {
+            self.cycles += 1;
+            self.memory[usize::from(address)]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.cycles += 1;
+            self.memory[usize::from(address)] = value;
+        }
+
+        fn tick(&mut self) {
+            self.cycles += 1;
+        }
+
+        fn pending(&mut self) -> u8 {
+            self.memory[0xFF0F] & self.memory[0xFFFF] & 0x1F
+        }
+
+        fn acknowledge(&mut self, interrupts: u8) {
+            self.memory[0xFF0F] &= !interrupts;
+        }
+    }
+
+    /// Runs `program` from 0x0100 until it locks up on 0xDD, and returns the processor.
+    fn run(program: &[u8]) -> (Cpu, Memory) {
+        let mut memory = Memory::with_program(program);
+        let mut cpu = Cpu::new();
+        while !cpu.locked() {
+            cpu.step(&mut memory);
+            assert!(cpu.cycles() < 10_000, "the program did not end");
+        }
+        (cpu, memory)
+    }
+
+    #[test]
+    fn counts_machine_cycles() {
+        #[rustfmt::skip]
+        let cycles = [
+            // NOP, LD BC,nn, LD (BC),A, INC BC, INC B, DEC B, LD B,n, RLCA, LD (nn),SP,
+            // ADD HL,BC, LD A,(BC), DEC BC, INC C, DEC C, LD C,n, RRCA
+            (&[0x00][..], 1), (&[0x01, 0, 0], 3), (&[0x02], 2), (&[0x03], 2), (&[0x04], 1),
+            (&[0x05], 1), (&[0x06, 0], 2), (&[0x07], 1), (&[0x08, 0, 0xC0], 5), (&[0x09], 2),
+            (&[0x0A], 2), (&[0x0B], 2), (&[0x0C], 1), (&[0x0D], 1), (&[0x0E, 0], 2),
+            (&[0x0F], 1),
+            // JR e, JR NZ,e taken and not, INC (HL), LD (HL),n, LD B,(HL), ADD A,(HL)
+            (&[0x18, 0], 3), (&[0xAF, 0x20, 0], 1 + 2), (&[0x37, 0x30, 0], 1 + 2),
+            (&[0x34], 3), (&[0x36, 0], 3), (&[0x46], 2), (&[0x86], 2),
+            // RET NZ not taken, POP BC, JP nn, CALL nn, PUSH BC, RST 0x08, LDH (n),A,
+            // LD (nn),A, ADD SP,e, LD HL,SP+e, LD SP,HL, JP HL
+            (&[0xAF, 0xC0], 1 + 2), (&[0xC1], 3), (&[0xC3, 0x03, 0x01], 4),
+            (&[0xCD, 0x03, 0x01], 6), (&[0xC5], 4), (&[0xCF], 4), (&[0xE0, 0], 3),
+            (&[0xEA, 0, 0xC0], 4), (&[0xE8, 0], 4), (&[0xF8, 0], 3), (&[0xF9], 2),
+            (&[0x21, 0x04, 0x01, 0xE9], 3 + 1),
+            // RLC B, RLC (HL), BIT 0,(HL), SET 0,(HL)
+            (&[0xCB, 0x00], 2), (&[0xCB, 0x06], 4), (&[0xCB, 0x46], 3), (&[0xCB, 0xC6], 4),
+        ];
+        for &(program, expected) in &cycles {
+            let mut memory = Memory::with_program(program);
+            let mut cpu = Cpu::new();
+            cpu.set_register(Register::H, 0xC0);
+            let mut taken = 0;
+            while cpu.program_counter() < 0x0100 + program.len() as u16
+                && cpu.program_counter() >= 0x0100
+            {
+                taken += u64::from(cpu.step(&mut memory));
+            }
+            assert_eq!(taken, expected, "{:02X?}", program);
+            assert_eq!(memory.cycles, expected, "{:02X?}", program);
+        }
+        // RET, RETI and CALL cc taken, from a call at 0x0100.
+        for &(routine, expected) in &[(0xC9, 4), (0xD9, 4)] {
+            let mut memory = Memory::with_program(&[0xCD, 0x00, 0x02]);
+            memory.memory[0x0200] = routine;
+            let mut cpu = Cpu::new();
+            cpu.step(&mut memory);
+            assert_eq!(cpu.step(&mut memory), expected);
+            assert_eq!(cpu.program_counter(), 0x0103);
+        }
+    }
+
+    #[test]
+    fn adds_and_subtracts() {
+        // LD A,0x3A; ADD A,0xC6
+        let (cpu, _) = run(&[0x3E, 0x3A, 0xC6, 0xC6, 0xDD]);
+        assert_eq!(cpu.register(Register::A), 0x00);
+        assert_eq!(cpu.register(Register::F), ZERO | HALF_CARRY | CARRY);
+        // LD A,0x3E; SCF; SBC A,0x3E
+        let (cpu, _) = run(&[0x3E, 0x3E, 0x37, 0xDE, 0x3E, 0xDD]);
+        assert_eq!(cpu.register(Register::A), 0xFF);
+        assert_eq!(cpu.register(Register::F), SUBTRACT | HALF_CARRY | CARRY);
+        // LD A,0x45; ADD A,0x38; DAA
+        let (cpu, _) = run(&[0x3E, 0x45, 0xC6, 0x38, 0x27, 0xDD]);
+        assert_eq!(cpu.register(Register::A), 0x83);
+        // LD A,0x83; SUB 0x38; DAA
+        let (cpu, _) = run(&[0x3E, 0x83, 0xD6, 0x38, 0x27, 0xDD]);
+        assert_eq!(cpu.register(Register::A), 0x45);
+        // LD HL,0x0FFF; LD BC,0x0001; ADD HL,BC, which keeps the zero flag.
+        let (cpu, _) = run(&[0x21, 0xFF, 0x0F, 0x01, 0x01, 0x00, 0x09, 0xDD]);
+        assert_eq!(cpu.register(Register::H), 0x10);
+        assert_eq!(cpu.register(Register::F), ZERO | HALF_CARRY);
+        // LD SP,0x00FF; ADD SP,-1
+        let (cpu, _) = run(&[0x31, 0xFF, 0x00, 0xE8, 0xFF, 0xDD]);
+        assert_eq!(cpu.stack_pointer(), 0x00FE);
+        assert_eq!(cpu.register(Register::F), HALF_CARRY | CARRY);
+    }
+
+    #[test]
+    fn shifts_and_tests_bits() {
+        // LD B,0x80; RLC B; SWAP B; BIT 3,B; SRA B
+        let (cpu, _) = run(&[
+            0x06, 0x80, 0xCB, 0x00, 0xCB, 0x30, 0xCB, 0x58, 0xCB, 0x28, 0xDD,
+        ]);
+        assert_eq!(cpu.register(Register::B), 0x08);
+        assert_eq!(cpu.register(Register::F), 0);
+        // XOR A; RLA; RLCA with A = 0 leaves the zero flag clear, unlike RLC A.
+        let (cpu, _) = run(&[0xAF, 0x17, 0x07, 0xDD]);
+        assert_eq!(cpu.register(Register::F), 0);
+        // LD HL,0xC000; SET 7,(HL); RES 7,(HL); SET 1,(HL)
+        let (_, memory) = run(&[0x21, 0x00, 0xC0, 0xCB, 0xFE, 0xCB, 0xBE, 0xCB, 0xCE, 0xDD]);
+        assert_eq!(memory.memory[0xC000], 0x02);
+    }
+
+    #[test]
+    fn calls_and_returns() {
+        // CALL 0x0200; LD B,A; lock. 0x0200: LD A,0x42; PUSH AF; POP DE; RET
+        let mut memory = Memory::with_program(&[0xCD, 0x00, 0x02, 0x47, 0xDD]);
+        memory.memory[0x0200..0x0206].copy_from_slice(&[0x3E, 0x42, 0xF5, 0xD1, 0xC9, 0x00]);
+        let mut cpu = Cpu::new();
+        while !cpu.locked() {
+            cpu.step(&mut memory);
+        }
+        assert_eq!(cpu.register(Register::B), 0x42);
+        assert_eq!(cpu.register(Register::D), 0x42);
+        assert_eq!(cpu.register(Register::E), ZERO | HALF_CARRY | CARRY);
+        assert_eq!(cpu.stack_pointer(), 0xFFFE);
+        assert_eq!(cpu.program_counter(), 0x0104);
+    }
+
+    #[test]
+    fn takes_interrupts() {
+        // EI; NOP; NOP, with the timer interrupt requested and enabled.
+        let mut memory = Memory::with_program(&[0xFB, 0x00, 0x00]);
+        memory.memory[0xFF0F] = 0x04;
+        memory.memory[0xFFFF] = 0x04;
+        let mut cpu = Cpu::new();
+        cpu.step(&mut memory);
+        assert!(!cpu.interrupts_enabled());
+        // The instruction after EI runs first.
+        cpu.step(&mut memory);
+        assert_eq!(cpu.program_counter(), 0x0102);
+        assert_eq!(cpu.step(&mut memory), 5);
+        assert_eq!(cpu.program_counter(), 0x0050);
+        assert_eq!(memory.memory[0xFF0F], 0);
+        assert_eq!(&memory.memory[0xFFFC..0xFFFE], [0x02, 0x01]);
+        assert!(!cpu.interrupts_enabled());
+
+        // HALT sleeps until an interrupt is pending, and with interrupts disabled goes on after
+        // it without taking it.
+        let mut memory = Memory::with_program(&[0x76, 0x04]);
+        memory.memory[0xFFFF] = 0x01;
+        let mut cpu = Cpu::new();
+        cpu.step(&mut memory);
+        for _ in 0..10 {
+            cpu.step(&mut memory);
+        }
+        assert!(cpu.halted());
+        memory.memory[0xFF0F] = 0x01;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert!(!cpu.halted());
+        assert_eq!(cpu.program_counter(), 0x0102);
+        assert_eq!(cpu.register(Register::B), 0x01);
+
+        // With one already pending and interrupts disabled, it reads the next opcode twice.
+        let mut memory = Memory::with_program(&[0x76, 0x04, 0xDD]);
+        memory.memory[0xFF0F] = 0x01;
+        memory.memory[0xFFFF] = 0x01;
+        let mut cpu = Cpu::new();
+        while !cpu.locked() {
+            cpu.step(&mut memory);
+        }
+        assert_eq!(cpu.register(Register::B), 0x02);
+    }
+
+    #[test]
+    fn locks_up_on_missing_opcodes() {
+        let (mut cpu, mut memory) = run(&[0x00, 0xD3]);
+        assert_eq!(cpu.program_counter(), 0x0101);
+        assert_eq!(cpu.step(&mut memory), 1);
+        assert_eq!(cpu.program_counter(), 0x0101);
+    }
+
+    #[test]
+    fn saves_state() {
+        let (cpu, _) = run(&[0x3E, 0x12, 0x06, 0x34, 0x31, 0x78, 0x56, 0xFB, 0xDD]);
+        let mut restored = Cpu::new();
+        restored.restore(&cpu.save());
+        assert_eq!(restored, cpu);
+    }
+}
@@ -0,0 +1,92 @@
+//! The joypad, read through `P1` at 0xFF00.
+//!
+//! The eight buttons are wired as a matrix of two rows of four: the program clears bit 4 of `P1`
+//! to read the D-pad, or bit 5 to read A, B, Select and Start, in bits 0 to 3, which read 0 for a
+//! button held down. A button pressed in a selected row requests the joypad interrupt.
+
+/// The buttons, numbered as [`Joypad::set`] numbers them.
+pub const BUTTONS: [&str; 8] = ["A", "B", "Select", "Start", "Up", "Down", "Left", "Right"];
+
+/// The joypad.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Joypad {
+    /// The buttons held down, in the bits numbered as in [`BUTTONS`].
+    pub buttons: u8,
+    /// Bits 4 and 5 of `P1`, which select the rows read.
+    select: u8,
+}
+
+impl Joypad {
+    /// Presses or releases the button numbered `button`, and returns whether that requests the
+    /// joypad interrupt.
+    pub fn set(&mut self, button: usize, pressed: bool) -> bool {
+        let before = self.lines();
+        if pressed {
+            self.buttons |= 1 << button;
+        } else {
+            self.buttons &= !(1 << button);
+        }
+        self.lines() & !before != 0
+    }
+
+    /// Reads `P1`.
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | !self.lines() & 0x0F
+    }
+
+    /// Writes `P1`, selecting the rows read, and returns whether that requests the joypad
+    /// interrupt.
+    pub fn write(&mut self, value: u8) -> bool {
+        let before = self.lines();
+        self.select = value & 0x30;
+        self.lines() & !before != 0
+    }
+
+    /// Returns the state of the joypad, for [`restore`](Joypad::restore).
+    pub fn save(&self) -> [u8; 2] {
+        [self.buttons, self.select]
+    }
+
+    /// Restores a state returned by [`save`](Joypad::save).
+    pub fn restore(&mut self, state: [u8; 2]) {
+        self.buttons = state[0];
+        self.select = state[1] & 0x30;
+    }
+
+    /// Returns the buttons held down in the selected rows, in bits 0 to 3, as 1s.
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & 0x10 == 0 {
+            // Right, Left, Up and Down.
+            let pad = self.buttons >> 4;
+            lines |= pad >> 3 & 1 | pad >> 1 & 2 | pad << 2 & 4 | pad << 2 & 8;
+        }
+        if self.select & 0x20 == 0 {
+            lines |= self.buttons & 0x0F;
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_selected_row() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x30);
+        // Start is pressed with neither row selected, which requests nothing.
+        assert!(!joypad.set(3, true));
+        assert_eq!(joypad.read(), 0xFF);
+        assert!(joypad.write(0x10));
+        assert_eq!(joypad.read(), 0xD7);
+        assert!(!joypad.write(0x20));
+        assert_eq!(joypad.read(), 0xEF);
+        for (button, bit) in [(4, 0x04), (5, 0x08), (6, 0x02), (7, 0x01)] {
+            assert!(joypad.set(button, true));
+            assert_eq!(!joypad.read() & 0x0F, bit);
+            joypad.set(button, false);
+        }
+    }
+}
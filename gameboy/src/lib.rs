@@ -0,0 +1,690 @@
+//! The original Game Boy, the DMG: a Sharp SM83 processor, 8KB of RAM, the PPU, a timer, the
+//! joypad, a serial port with a link cable to another Game Boy, and the cartridge.
+//!
+//! The processor makes an access to memory, or waits, every machine cycle of four clock cycles,
+//! and the devices run for a machine cycle on each, so that the program sees the timer and the PPU
+//! as they are on the cycle it reads them. The PPU requests an interrupt at the start of the
+//! vertical blank, when the program is free to change video memory, and a frame ends there.
+//!
+//! Cartridges without a controller or with MBC1, MBC3 or MBC5 are supported, with the RAM and
+//! clock their batteries keep. The boot ROM is not run: the Game Boy starts as it leaves it. The
+//! sound of the APU's two outputs is mixed down to mono.
+//!
+//! ```
+//! use emu_core::Emulator;
+//! use gameboy::GameBoy;
+//!
+//! let gameboy = GameBoy::new();
+//! assert_eq!(gameboy.video().width, 160);
+//! assert_eq!(gameboy.video().height, 144);
+//! assert_eq!(gameboy.buttons()[3], "Start");
+//! ```
+
+pub mod apu;
+pub mod cartridge;
+pub mod cpu;
+pub mod joypad;
+pub mod ppu;
+pub mod serial;
+pub mod timer;
+
+use crate::apu::Apu;
+use crate::cartridge::Cartridge;
+use crate::cpu::{Bus, Cpu};
+use crate::joypad::Joypad;
+use crate::ppu::Ppu;
+use crate::serial::{Cable, Serial};
+use crate::timer::Timer;
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::link::Link;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+
+/// Clock cycles per second.
+pub const CLOCK_HZ: u64 = 4_194_304;
+
+/// Machine cycles of a frame: 154 lines of 456 clock cycles.
+pub const FRAME_CYCLES: u64 = 154 * 456 / 4;
+
+/// The time a frame takes, a little under 1/59.7 second.
+pub const FRAME_PERIOD: Duration = Duration::from_nanos(16_742_706);
+
+/// Samples per second of the sound.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Bytes of work RAM, at 0xC000 and repeated from 0xE000.
+pub const WRAM_SIZE: usize = 0x2000;
+
+/// Bytes of high RAM, at 0xFF80.
+pub const HRAM_SIZE: usize = 0x7F;
+
+/// The interrupts, as bits of `IF` and `IE`, from the one taken first.
+pub const VBLANK: u8 = 0x01;
+pub const STAT: u8 = 0x02;
+pub const TIMER: u8 = 0x04;
+pub const SERIAL: u8 = 0x08;
+pub const JOYPAD: u8 = 0x10;
+
+/// Addresses of the registers that are not a device's.
+const IF: u16 = 0xFF0F;
+const DMA: u16 = 0xFF46;
+const IE: u16 = 0xFFFF;
+
+/// Machine cycles OAM DMA takes, copying a byte in each.
+const DMA_CYCLES: u8 = 160;
+
+/// Machine cycles between looks at what arrived over the link cable, the time of a bit on the
+/// Game Boy's own clock.
+const LINK_POLL_CYCLES: u64 = 128;
+
+/// The first bytes of a save state, with the version of its layout.
+const MAGIC: &[u8; 4] = b"DMG\x03";
+
+/// The Game Boy around the processor: memory and devices.
+#[derive(Clone)]
+struct Board {
+    cartridge: Cartridge,
+    wram: Vec<u8>,
+    hram: [u8; HRAM_SIZE],
+    ppu: Ppu,
+    timer: Timer,
+    joypad: Joypad,
+    serial: Serial,
+    cable: Cable,
+    apu: Apu,
+    interrupt_flag: u8,
+    interrupt_enable: u8,
+    /// The last value written to the DMA register, the page OAM is copied from.
+    dma: u8,
+    /// The byte OAM DMA copies next, [`DMA_CYCLES`] when it is not copying.
+    dma_index: u8,
+    /// Whether OAM DMA starts on the next machine cycle.
+    dma_starting: bool,
+    /// Machine cycles run since the Game Boy was turned on.
+    cycles: u64,
+    /// Machine cycles since the last sample of sound, times [`SAMPLE_RATE`].
+    clocks: u64,
+    samples: Vec<i16>,
+}
+
+impl Board {
+    /// Runs the devices for a machine cycle.
+    fn tick(&mut self) {
+        self.cycles += 1;
+        if self.timer.tick() {
+            self.interrupt_flag |= TIMER;
+        }
+        self.apu.tick(self.timer.counter());
+        self.clocks += 4 * u64::from(SAMPLE_RATE);
+        if self.clocks >= CLOCK_HZ {
+            self.clocks -= CLOCK_HZ;
+            self.samples.push(self.apu.sample());
+        }
+        if self.serial.tick() {
+            self.interrupt_flag |= SERIAL;
+        }
+        if self.cable.exchange(
+            &mut self.serial,
+            self.cycles.is_multiple_of(LINK_POLL_CYCLES),
+        ) {
+            self.interrupt_flag |= SERIAL;
+        }
+        self.ppu.tick();
+        self.interrupt_flag |= self.ppu.take_interrupts();
+        self.cartridge.tick();
+        if self.dma_index < DMA_CYCLES {
+            let source = u16::from(self.dma) << 8 | u16::from(self.dma_index);
+            let value = self.load(source);
+            self.ppu.write_dma(usize::from(self.dma_index), value);
+            self.dma_index += 1;
+        }
+        if self.dma_starting {
+            self.dma_starting = false;
+            self.dma_index = 0;
+        }
+    }
+
+    /// Reads `address` without running the devices.
+    fn load(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.read(address),
+            0x8000..=0x9FFF => self.ppu.read_vram(address),
+            0xC000..=0xFDFF => self.wram[usize::from(address) % WRAM_SIZE],
+            0xFE00..=0xFE9F => {
+                if self.dma_index < DMA_CYCLES {
+                    0xFF
+                } else {
+                    self.ppu.read_oam(address)
+                }
+            }
+            0xFEA0..=0xFEFF => 0x00,
+            0xFF00 => self.joypad.read(),
+            0xFF01..=0xFF02 => self.serial.read(address),
+            0xFF04..=0xFF07 => self.timer.read(address),
+            IF => self.interrupt_flag | 0xE0,
+            0xFF10..=0xFF3F => self.apu.read(address),
+            DMA => self.dma,
+            0xFF40..=0xFF4B => self.ppu.read_register(address),
+            0xFF80..=0xFFFE => self.hram[usize::from(address - 0xFF80)],
+            IE => self.interrupt_enable,
+            _ => 0xFF,
+        }
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.load(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(address, value),
+            0x8000..=0x9FFF => self.ppu.write_vram(address, value),
+            0xC000..=0xFDFF => self.wram[usize::from(address) % WRAM_SIZE] = value,
+            0xFE00..=0xFE9F if self.dma_index >= DMA_CYCLES => self.ppu.write_oam(address, value),
+            0xFF00 => {
+                let pressed = self.joypad.write(value);
+                self.interrupt_flag |= if pressed { JOYPAD } else { 0 };
+            }
+            0xFF01..=0xFF02 => self.serial.write(address, value),
+            0xFF04..=0xFF07 => self.timer.write(address, value),
+            IF => self.interrupt_flag = value & 0x1F,
+            0xFF10..=0xFF3F => self.apu.write(address, value),
+            DMA => {
+                self.dma = value;
+                self.dma_starting = true;
+            }
+            0xFF40..=0xFF4B => self.ppu.write_register(address, value),
+            0xFF80..=0xFFFE => self.hram[usize::from(address - 0xFF80)] = value,
+            IE => self.interrupt_enable = value,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        Board::tick(self);
+    }
+
+    fn pending(&mut self) -> u8 {
+        self.interrupt_flag & self.interrupt_enable & 0x1F
+    }
+
+    fn acknowledge(&mut self, interrupts: u8) {
+        self.interrupt_flag &= !interrupts;
+    }
+
+    /// Clears the divider, as `STOP` does.
+    fn stop(&mut self) {
+        self.timer.write(0xFF04, 0);
+    }
+}
+
+/// The Game Boy, with a cartridge.
+#[derive(Clone)]
+pub struct GameBoy {
+    cpu: Cpu,
+    board: Board,
+    rom_hash: u64,
+}
+
+impl Default for GameBoy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameBoy {
+    /// Creates a Game Boy without a cartridge, turned on.
+    pub fn new() -> Self {
+        let mut gameboy = GameBoy {
+            cpu: Cpu::new(),
+            board: Board {
+                cartridge: Cartridge::default(),
+                wram: vec![0; WRAM_SIZE],
+                hram: [0; HRAM_SIZE],
+                ppu: Ppu::new(),
+                timer: Timer::new(),
+                joypad: Joypad::default(),
+                serial: Serial::default(),
+                cable: Cable::default(),
+                apu: Apu::new(),
+                interrupt_flag: 0,
+                interrupt_enable: 0,
+                dma: 0,
+                dma_index: DMA_CYCLES,
+                dma_starting: false,
+                cycles: 0,
+                clocks: 0,
+                samples: Vec::new(),
+            },
+            rom_hash: emu_state::hash(&[]),
+        };
+        gameboy.reset();
+        gameboy
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns the PPU.
+    pub fn ppu(&self) -> &Ppu {
+        &self.board.ppu
+    }
+
+    /// Returns the cartridge.
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.board.cartridge
+    }
+
+    /// Returns the cartridge, to change its RAM directly.
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.board.cartridge
+    }
+
+    /// Returns work RAM.
+    pub fn wram(&self) -> &[u8] {
+        &self.board.wram
+    }
+
+    /// Returns the last bytes sent out of the serial port, up to 1KB, which test ROMs print their
+    /// results to.
+    pub fn serial_output(&self) -> &[u8] {
+        self.board.serial.output()
+    }
+
+    /// Returns the machine cycles run since the Game Boy was turned on.
+    pub fn cycles(&self) -> u64 {
+        self.board.cycles
+    }
+
+    /// Reads `address` as the processor would, without running the devices.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.board.load(address)
+    }
+}
+
+impl Emulator for GameBoy {
+    fn name(&self) -> &'static str {
+        "Game Boy"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: ppu::WIDTH,
+            height: ppu::HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &joypad::BUTTONS
+    }
+
+    /// Inserts the cartridge of the ROM `rom`, and turns the Game Boy on.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.board.cartridge = Cartridge::from_rom(rom)?;
+        self.rom_hash = emu_state::hash(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Turns the Game Boy off and on again. Its RAM is cleared, but the cartridge's is kept.
+    fn reset(&mut self) {
+        let board = &mut self.board;
+        board.wram.iter_mut().for_each(|byte| *byte = 0);
+        board.hram = [0; HRAM_SIZE];
+        board.ppu = Ppu::new();
+        board.timer = Timer::new();
+        board.joypad.restore([board.joypad.buttons, 0x30]);
+        board.serial = Serial::default();
+        board.apu = Apu::new();
+        board.interrupt_flag = VBLANK;
+        board.interrupt_enable = 0;
+        board.dma = 0xFF;
+        board.dma_index = DMA_CYCLES;
+        board.dma_starting = false;
+        board.cycles = 0;
+        board.clocks = 0;
+        board.samples.clear();
+        board.cartridge.reset();
+        self.cpu = Cpu::new();
+    }
+
+    /// Runs until the vertical blank, or for the time of a frame while the LCD is off.
+    fn step_frame(&mut self) -> Result<(), Error> {
+        let start = self.board.cycles;
+        loop {
+            if self.cpu.locked() {
+                return Err(Error::Stopped(format!(
+                    "the processor locked up on opcode {:02X} at {:04X}",
+                    self.peek(self.cpu.program_counter()),
+                    self.cpu.program_counter()
+                )));
+            }
+            self.cpu.step(&mut self.board);
+            if self.board.ppu.take_frame() || self.board.cycles - start >= FRAME_CYCLES {
+                return Ok(());
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        self.board.ppu.framebuffer()
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.board.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if button < joypad::BUTTONS.len() && self.board.joypad.set(button, pressed) {
+            self.board.interrupt_flag |= JOYPAD;
+        }
+    }
+
+    /// Saves the processor, RAM, the devices and the cartridge's controller, RAM and clock, but
+    /// not the picture, which is drawn again by the next frame.
+    fn save_state(&self) -> Vec<u8> {
+        let board = &self.board;
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(&board.wram);
+        state.extend_from_slice(&board.hram);
+        state.extend_from_slice(&board.ppu.save());
+        state.extend_from_slice(&board.timer.save());
+        state.extend_from_slice(&board.serial.save());
+        state.extend_from_slice(&board.apu.save());
+        state.extend_from_slice(&board.joypad.save());
+        state.extend_from_slice(&[
+            board.interrupt_flag,
+            board.interrupt_enable,
+            board.dma,
+            board.dma_index,
+            u8::from(board.dma_starting),
+        ]);
+        state.extend_from_slice(&board.cycles.to_le_bytes());
+        state.extend_from_slice(&board.cartridge.save());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let size = MAGIC.len()
+            + 8
+            + cpu::STATE_SIZE
+            + WRAM_SIZE
+            + HRAM_SIZE
+            + ppu::STATE_SIZE
+            + timer::STATE_SIZE
+            + serial::STATE_SIZE
+            + apu::STATE_SIZE
+            + 2
+            + 5
+            + 8
+            + self.board.cartridge.state_size();
+        if state.len() != size || !state.starts_with(MAGIC) {
+            return Err(Error::State(
+                "not a save state of this Game Boy".to_string(),
+            ));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(cpu::STATE_SIZE);
+        let (wram, rest) = rest.split_at(WRAM_SIZE);
+        let (hram, rest) = rest.split_at(HRAM_SIZE);
+        let (ppu, rest) = rest.split_at(ppu::STATE_SIZE);
+        let (timer, rest) = rest.split_at(timer::STATE_SIZE);
+        let (serial, rest) = rest.split_at(serial::STATE_SIZE);
+        let (apu, rest) = rest.split_at(apu::STATE_SIZE);
+        let (joypad, rest) = rest.split_at(2);
+        let (registers, rest) = rest.split_at(5);
+        let (cycles, cartridge) = rest.split_at(8);
+        self.cpu
+            .restore(<&[u8; cpu::STATE_SIZE]>::try_from(cpu).unwrap());
+        let board = &mut self.board;
+        board.wram.copy_from_slice(wram);
+        board.hram.copy_from_slice(hram);
+        board.ppu.restore(ppu);
+        board
+            .timer
+            .restore(<&[u8; timer::STATE_SIZE]>::try_from(timer).unwrap());
+        board
+            .serial
+            .restore(<&[u8; serial::STATE_SIZE]>::try_from(serial).unwrap());
+        board.apu.restore(apu);
+        board.joypad.restore([joypad[0], joypad[1]]);
+        board.interrupt_flag = registers[0] & 0x1F;
+        board.interrupt_enable = registers[1];
+        board.dma = registers[2];
+        board.dma_index = registers[3].min(DMA_CYCLES);
+        board.dma_starting = registers[4] != 0;
+        board.cycles = u64::from_le_bytes(<[u8; 8]>::try_from(cycles).unwrap());
+        board.cartridge.restore(cartridge);
+        Ok(())
+    }
+
+    fn battery(&self) -> Option<Vec<u8>> {
+        self.board.cartridge.battery()
+    }
+
+    fn load_battery(&mut self, battery: &[u8]) -> Result<(), Error> {
+        self.board.cartridge.load_battery(battery)
+    }
+
+    /// Plugs `link` into the serial port, to trade and battle with the Game Boy at its other end.
+    /// A copy of the Game Boy is not plugged in.
+    fn set_link(&mut self, link: Option<Box<dyn Link>>) -> bool {
+        self.board.cable.plug(link);
+        true
+    }
+
+    fn is_linked(&self) -> bool {
+        self.board.cable.is_linked()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a ROM of two banks for the cartridge type `kind`, with 8KB of RAM if it has any,
+    /// holding `code` at each address.
+    fn rom(kind: u8, code: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut rom = vec![0; 2 * cartridge::ROM_BANK];
+        rom[0x0104..0x0134].copy_from_slice(&cartridge::LOGO);
+        rom[0x0147] = kind;
+        rom[0x0149] = 0x02;
+        for &(address, bytes) in code {
+            rom[usize::from(address)..][..bytes.len()].copy_from_slice(bytes);
+        }
+        rom
+    }
+
+    fn machine(code: &[(u16, &[u8])]) -> GameBoy {
+        let mut gameboy = GameBoy::new();
+        gameboy.load_rom(&rom(0x00, code)).unwrap();
+        gameboy
+    }
+
+    /// A program that starts the timer at 262144Hz and counts its interrupts in `B`: LD A,5;
+    /// LDH (TAC),A; LD A,4; LDH (IE),A; EI; HALT; JR -3, and at the interrupt INC B; RETI.
+    const COUNT_TIMER: [(u16, &[u8]); 2] = [
+        (
+            0x0100,
+            &[
+                0x3E, 0x05, 0xE0, 0x07, 0x3E, 0x04, 0xE0, 0xFF, 0xFB, 0x76, 0x18, 0xFD,
+            ],
+        ),
+        (0x0050, &[0x04, 0xD9]),
+    ];
+
+    #[test]
+    fn maps_memory() {
+        let mut gameboy = machine(&[(0x4000, &[0x42])]);
+        let board = &mut gameboy.board;
+        board.write(0xE123, 0x12);
+        assert_eq!(board.wram[0x0123], 0x12);
+        assert_eq!(board.read(0xC123), 0x12);
+        assert_eq!(board.read(0x4000), 0x42);
+        assert_eq!(board.read(0xFEA0), 0x00);
+        assert_eq!(board.read(0xFF7F), 0xFF);
+        assert_eq!(board.read(0xFF10), 0x80);
+        board.write(IF, 0xFF);
+        assert_eq!(board.read(IF), 0xFF);
+        assert_eq!(board.interrupt_flag, 0x1F);
+        board.write(0xFFFE, 0x34);
+        assert_eq!(board.hram[0x7E], 0x34);
+        assert_eq!(gameboy.peek(0xFFFE), 0x34);
+        assert_eq!(gameboy.cycles(), 9);
+    }
+
+    #[test]
+    fn copies_oam_by_dma() {
+        let mut gameboy = machine(&[]);
+        let board = &mut gameboy.board;
+        for (index, byte) in board.wram[..0xA0].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        board.write(DMA, 0xC0);
+        board.tick();
+        // OAM reads as 0xFF while it is copied.
+        assert_eq!(board.read(0xFE00), 0xFF);
+        for _ in 0..DMA_CYCLES {
+            board.tick();
+        }
+        assert_eq!(board.read(0xFE9F), 0x9F);
+        assert_eq!(board.ppu.oam()[0x10], 0x10);
+    }
+
+    #[test]
+    fn runs_frames() {
+        let mut gameboy = machine(&COUNT_TIMER);
+        gameboy.step_frame().unwrap();
+        let start = gameboy.cycles();
+        let mut samples = Vec::new();
+        gameboy.pull_audio(&mut samples);
+        let interrupts = gameboy.cpu().register(cpu::Register::B);
+
+        gameboy.step_frame().unwrap();
+        let cycles = gameboy.cycles() - start;
+        assert!((FRAME_CYCLES..FRAME_CYCLES + 6).contains(&cycles));
+        samples.clear();
+        gameboy.pull_audio(&mut samples);
+        assert!((803..=804).contains(&samples.len()));
+        // The timer overflows every 1024 machine cycles.
+        let interrupts = gameboy.cpu().register(cpu::Register::B) - interrupts;
+        assert!((17..=18).contains(&interrupts));
+    }
+
+    #[test]
+    fn presses_buttons() {
+        // LD A,0x10; LDH (P1),A, selecting the buttons, then waits for the joypad interrupt.
+        let mut gameboy = machine(&[(0x0100, &[0x3E, 0x10, 0xE0, 0x00, 0x18, 0xFE])]);
+        gameboy.step_frame().unwrap();
+        assert_eq!(gameboy.peek(0xFF00) & 0x0F, 0x0F);
+        gameboy.set_button(3, true);
+        assert_eq!(gameboy.peek(0xFF00) & 0x0F, 0x07);
+        assert_ne!(gameboy.board.interrupt_flag & JOYPAD, 0);
+        gameboy.set_button(99, true);
+    }
+
+    #[test]
+    fn stops_on_missing_opcodes() {
+        let mut gameboy = machine(&[(0x0100, &[0x00, 0xD3])]);
+        assert_eq!(
+            gameboy.step_frame(),
+            Err(Error::Stopped(
+                "the processor locked up on opcode D3 at 0101".to_string()
+            ))
+        );
+        gameboy.reset();
+        assert!(!gameboy.cpu().locked());
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut gameboy = machine(&COUNT_TIMER);
+        gameboy.step_frame().unwrap();
+        let state = gameboy.save_state();
+        gameboy.step_frame().unwrap();
+        let frame = gameboy.framebuffer().to_vec();
+        let registers = gameboy.cpu().save();
+
+        let mut restored = machine(&COUNT_TIMER);
+        restored.load_state(&state).unwrap();
+        restored.step_frame().unwrap();
+        assert_eq!(restored.cpu().save(), registers);
+        assert_eq!(restored.framebuffer(), &frame[..]);
+        assert_eq!(restored.save_state(), gameboy.save_state());
+
+        assert!(matches!(
+            restored.load_state(&state[1..]),
+            Err(Error::State(_))
+        ));
+        let mut other = machine(&[]);
+        assert!(matches!(other.load_state(&state), Err(Error::State(_))));
+    }
+
+    #[test]
+    fn links_two_game_boys() {
+        // LD A,0x42; LDH (SB),A; LD A,0x80; LDH (SC),A, waiting for the other's clock.
+        let mut second = machine(&[(
+            0x0100,
+            &[0x3E, 0x42, 0xE0, 0x01, 0x3E, 0x80, 0xE0, 0x02, 0x18, 0xFE],
+        )]);
+        // The same sending 0x17 on its own clock.
+        let mut first = machine(&[(
+            0x0100,
+            &[0x3E, 0x17, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE],
+        )]);
+        let (first_end, second_end) = emu_core::link::pair();
+        assert!(first.set_link(Some(Box::new(first_end))));
+        assert!(second.set_link(Some(Box::new(second_end))));
+        assert!(first.is_linked());
+        for _ in 0..2 {
+            second.step_frame().unwrap();
+            first.step_frame().unwrap();
+        }
+        assert_eq!(first.peek(0xFF01), 0x42);
+        assert_eq!(second.peek(0xFF01), 0x17);
+        assert_eq!(second.peek(0xFF02), 0x7E);
+        assert_ne!(first.board.interrupt_flag & SERIAL, 0);
+        assert_ne!(second.board.interrupt_flag & SERIAL, 0);
+
+        // A copy is not linked, and neither is the Game Boy once the other is gone.
+        assert!(!first.clone().is_linked());
+        drop(second);
+        first.step_frame().unwrap();
+        assert!(!first.is_linked());
+    }
+
+    #[test]
+    fn keeps_the_battery() {
+        let mut gameboy = machine(&[]);
+        assert_eq!(gameboy.battery(), None);
+        assert!(gameboy.load_battery(&[0; 0x2000]).is_err());
+
+        // MBC1 with RAM and a battery.
+        let rom = rom(0x03, &[]);
+        gameboy.load_rom(&rom).unwrap();
+        gameboy.cartridge_mut().ram_mut()[0x123] = 0x45;
+        let battery = gameboy.battery().unwrap();
+        gameboy.load_rom(&rom).unwrap();
+        gameboy.load_battery(&battery).unwrap();
+        assert_eq!(gameboy.cartridge().ram()[0x123], 0x45);
+        assert!(gameboy.load_battery(&battery[1..]).is_err());
+    }
+}
@@ -0,0 +1,669 @@
+//! The PPU of the original Game Boy, which draws the 160×144 screen in four shades of green.
+//!
+//! The screen is drawn line by line, 154 lines of 456 dots a frame, a dot a clock cycle. Each of
+//! the 144 visible lines starts by searching OAM for the sprites on it (mode 2, 80 dots), then
+//! draws it (mode 3), then rests until the next line (mode 0). The 10 lines after them are the
+//! vertical blank (mode 1), when the program is free to change video memory, which it cannot
+//! read or write while the PPU uses it.
+//!
+//! The picture is a background of 8×8 tiles, scrolled by `SCX` and `SCY`, a window of tiles over
+//! it, placed by `WX` and `WY`, and up to 40 sprites of 8×8 or 8×16 pixels, 10 a line. Each line
+//! is drawn at once at the end of mode 3, from the registers as they are then, rather than pixel
+//! by pixel, and mode 3 always lasts 172 dots.
+
+/// The width of the screen, in pixels.
+pub const WIDTH: usize = 160;
+
+/// The height of the screen, in pixels.
+pub const HEIGHT: usize = 144;
+
+/// Dots, or clock cycles, of a line.
+pub const DOTS: u16 = 456;
+
+/// Lines of a frame, the vertical blank included.
+pub const LINES: u8 = 154;
+
+/// Length of the state written by [`Ppu::save`].
+pub const STATE_SIZE: usize = VRAM_SIZE + OAM_SIZE + 11 + 6;
+
+/// The four shades, from the lightest, as RGB.
+pub const SHADES: [[u8; 3]; 4] = [
+    [0xE0, 0xF8, 0xD0],
+    [0x88, 0xC0, 0x70],
+    [0x34, 0x68, 0x56],
+    [0x08, 0x18, 0x20],
+];
+
+/// Bytes of video RAM, at 0x8000.
+pub const VRAM_SIZE: usize = 0x2000;
+
+/// Bytes of OAM, the attributes of the 40 sprites, at 0xFE00.
+pub const OAM_SIZE: usize = 0xA0;
+
+/// The interrupts the PPU requests: the vertical blank, and `STAT`.
+pub const VBLANK: u8 = 0x01;
+pub const STAT: u8 = 0x02;
+
+/// Dots of modes 2 and 3.
+const SEARCH_DOTS: u16 = 80;
+const DRAW_DOTS: u16 = 172;
+
+/// Bits of `LCDC`.
+const LCD_ON: u8 = 0x80;
+const WINDOW_MAP: u8 = 0x40;
+const WINDOW_ON: u8 = 0x20;
+const TILE_DATA: u8 = 0x10;
+const BG_MAP: u8 = 0x08;
+const TALL_SPRITES: u8 = 0x04;
+const SPRITES_ON: u8 = 0x02;
+const BG_ON: u8 = 0x01;
+
+/// Bits of `STAT` that make the modes and `LY` = `LYC` request the `STAT` interrupt.
+const LYC_INTERRUPT: u8 = 0x40;
+const SEARCH_INTERRUPT: u8 = 0x20;
+const VBLANK_INTERRUPT: u8 = 0x10;
+const HBLANK_INTERRUPT: u8 = 0x08;
+
+/// Bits of a sprite's attributes.
+const BEHIND: u8 = 0x80;
+const FLIP_Y: u8 = 0x40;
+const FLIP_X: u8 = 0x20;
+const PALETTE_1: u8 = 0x10;
+
+/// Sprites drawn on a line at most.
+const LINE_SPRITES: usize = 10;
+
+/// The modes of the PPU, as `STAT` tells them.
+const HBLANK: u8 = 0;
+const VBLANK_MODE: u8 = 1;
+const SEARCH: u8 = 2;
+const DRAW: u8 = 3;
+
+/// The PPU.
+#[derive(Clone, Debug)]
+pub struct Ppu {
+    vram: Vec<u8>,
+    oam: Vec<u8>,
+    lcdc: u8,
+    /// The bits of `STAT` that enable its interrupts.
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    dot: u16,
+    mode: u8,
+    /// The line of the window drawn next, which only moves on lines that show it.
+    window_line: u8,
+    /// Whether the `STAT` interrupt line is high, which requests the interrupt as it rises.
+    stat_line: bool,
+    /// Interrupts requested since they were last taken.
+    requests: u8,
+    /// Whether a frame has ended since [`take_frame`](Ppu::take_frame) was last called.
+    frame: bool,
+    framebuffer: Vec<u8>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    /// Creates the PPU as the boot ROM leaves it: on, with the background shown, at the start of
+    /// the vertical blank.
+    pub fn new() -> Self {
+        let mut ppu = Ppu {
+            vram: vec![0; VRAM_SIZE],
+            oam: vec![0; OAM_SIZE],
+            lcdc: LCD_ON | TILE_DATA | BG_ON,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: HEIGHT as u8,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            dot: 0,
+            mode: VBLANK_MODE,
+            window_line: 0,
+            stat_line: false,
+            requests: 0,
+            frame: false,
+            framebuffer: vec![0; WIDTH * HEIGHT * 4],
+        };
+        ppu.blank();
+        ppu
+    }
+
+    /// Returns the picture of the last frame, as RGBA pixels.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Returns the line being drawn, `LY`, and the dot on it.
+    pub fn position(&self) -> (u8, u16) {
+        (self.ly, self.dot)
+    }
+
+    /// Returns video RAM.
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    /// Returns OAM.
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
+
+    /// Returns whether a frame has ended since the last call.
+    pub fn take_frame(&mut self) -> bool {
+        std::mem::replace(&mut self.frame, false)
+    }
+
+    /// Returns the interrupts requested since the last call, [`VBLANK`] and [`STAT`].
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::replace(&mut self.requests, 0)
+    }
+
+    /// Runs the PPU for a machine cycle, four dots.
+    pub fn tick(&mut self) {
+        if self.lcdc & LCD_ON == 0 {
+            return;
+        }
+        self.dot += 4;
+        if self.dot == DOTS {
+            self.dot = 0;
+            self.ly += 1;
+            if self.ly == LINES {
+                self.ly = 0;
+                self.window_line = 0;
+            }
+            if self.ly == HEIGHT as u8 {
+                self.mode = VBLANK_MODE;
+                self.requests |= VBLANK;
+                self.frame = true;
+            } else if self.ly < HEIGHT as u8 {
+                self.mode = SEARCH;
+            }
+        } else if self.ly < HEIGHT as u8 {
+            if self.dot == SEARCH_DOTS {
+                self.mode = DRAW;
+            } else if self.dot == SEARCH_DOTS + DRAW_DOTS {
+                self.draw_line();
+                self.mode = HBLANK;
+            }
+        }
+        self.update_stat();
+    }
+
+    /// Reads video RAM at `address`, from 0x8000, which reads 0xFF while the PPU draws.
+    pub fn read_vram(&self, address: u16) -> u8 {
+        if self.mode == DRAW {
+            0xFF
+        } else {
+            self.vram[usize::from(address & 0x1FFF)]
+        }
+    }
+
+    /// Writes video RAM at `address`, from 0x8000, which is ignored while the PPU draws.
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        if self.mode != DRAW {
+            self.vram[usize::from(address & 0x1FFF)] = value;
+        }
+    }
+
+    /// Reads OAM at `address`, from 0xFE00, which reads 0xFF while the PPU searches or draws.
+    pub fn read_oam(&self, address: u16) -> u8 {
+        match self.mode {
+            SEARCH | DRAW => 0xFF,
+            _ => self.oam[usize::from(address & 0xFF)],
+        }
+    }
+
+    /// Writes OAM at `address`, from 0xFE00, which is ignored while the PPU searches or draws.
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        if let HBLANK | VBLANK_MODE = self.mode {
+            self.oam[usize::from(address & 0xFF)] = value;
+        }
+    }
+
+    /// Writes byte `index` of OAM, as OAM DMA does whatever the PPU is doing.
+    pub fn write_dma(&mut self, index: usize, value: u8) {
+        self.oam[index] = value;
+    }
+
+    /// Reads the register at `address`, 0xFF40 to 0xFF4B but not 0xFF46.
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            0xFF40 => self.lcdc,
+            0xFF41 => 0x80 | self.stat | u8::from(self.ly == self.lyc) << 2 | self.mode,
+            0xFF42 => self.scy,
+            0xFF43 => self.scx,
+            0xFF44 => self.ly,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgp,
+            0xFF48 => self.obp0,
+            0xFF49 => self.obp1,
+            0xFF4A => self.wy,
+            0xFF4B => self.wx,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes the register at `address`, 0xFF40 to 0xFF4B but not 0xFF46. Turning the LCD off
+    /// stops the PPU at the top of the screen and blanks it.
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF40 => {
+                if self.lcdc & LCD_ON != 0 && value & LCD_ON == 0 {
+                    self.ly = 0;
+                    self.dot = 0;
+                    self.mode = HBLANK;
+                    self.window_line = 0;
+                    self.blank();
+                } else if self.lcdc & LCD_ON == 0 && value & LCD_ON != 0 {
+                    self.mode = SEARCH;
+                }
+                self.lcdc = value;
+            }
+            0xFF41 => self.stat = value & 0x78,
+            0xFF42 => self.scy = value,
+            0xFF43 => self.scx = value,
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgp = value,
+            0xFF48 => self.obp0 = value,
+            0xFF49 => self.obp1 = value,
+            0xFF4A => self.wy = value,
+            0xFF4B => self.wx = value,
+            _ => {}
+        }
+        self.update_stat();
+    }
+
+    /// Returns the state of the PPU, for [`restore`](Ppu::restore). The picture is not part of
+    /// it.
+    pub fn save(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(&self.vram);
+        state.extend_from_slice(&self.oam);
+        state.extend_from_slice(&[
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.bgp, self.obp0,
+            self.obp1, self.wy, self.wx,
+        ]);
+        state.extend_from_slice(&self.dot.to_le_bytes());
+        state.extend_from_slice(&[
+            self.mode,
+            self.window_line,
+            u8::from(self.stat_line),
+            self.requests,
+        ]);
+        state
+    }
+
+    /// Restores a state returned by [`save`](Ppu::save), which must be [`STATE_SIZE`] bytes.
+    pub fn restore(&mut self, state: &[u8]) {
+        let (vram, state) = state.split_at(VRAM_SIZE);
+        let (oam, state) = state.split_at(OAM_SIZE);
+        self.vram.copy_from_slice(vram);
+        self.oam.copy_from_slice(oam);
+        self.lcdc = state[0];
+        self.stat = state[1] & 0x78;
+        self.scy = state[2];
+        self.scx = state[3];
+        self.ly = state[4] % LINES;
+        self.lyc = state[5];
+        self.bgp = state[6];
+        self.obp0 = state[7];
+        self.obp1 = state[8];
+        self.wy = state[9];
+        self.wx = state[10];
+        self.dot = (u16::from_le_bytes([state[11], state[12]]) % DOTS) & !3;
+        self.mode = state[13] & 3;
+        self.window_line = state[14];
+        self.stat_line = state[15] != 0;
+        self.requests = state[16] & (VBLANK | STAT);
+        self.frame = false;
+    }
+
+    /// Requests the `STAT` interrupt if its line has just risen.
+    fn update_stat(&mut self) {
+        let on = |bit: u8| self.stat & bit != 0;
+        let line = self.lcdc & LCD_ON != 0
+            && (on(LYC_INTERRUPT) && self.ly == self.lyc
+                || on(HBLANK_INTERRUPT) && self.mode == HBLANK
+                || on(VBLANK_INTERRUPT) && self.mode == VBLANK_MODE
+                || on(SEARCH_INTERRUPT) && self.mode == SEARCH
+                // The mode 2 interrupt also comes at the start of the vertical blank.
+                || on(SEARCH_INTERRUPT) && self.ly == HEIGHT as u8 && self.dot == 0);
+        if line && !self.stat_line {
+            self.requests |= STAT;
+        }
+        self.stat_line = line;
+    }
+
+    /// Fills the picture with the lightest shade, as the LCD shows when off.
+    fn blank(&mut self) {
+        for pixel in self.framebuffer.chunks_exact_mut(4) {
+            pixel[..3].copy_from_slice(&SHADES[0]);
+            pixel[3] = 0xFF;
+        }
+    }
+
+    /// Returns color `column` of row `row` of tile `tile`, in the tile data `LCDC` selects for
+    /// the background and the window: from 0x8000 for tiles 0 to 255, or from 0x9000 for tiles
+    /// -128 to 127.
+    fn background_pixel(&self, tile: u8, row: usize, column: usize) -> u8 {
+        let address = if self.lcdc & TILE_DATA != 0 {
+            usize::from(tile) * 16
+        } else {
+            (0x1000 + isize::from(tile as i8) * 16) as usize
+        };
+        self.pixel(address + row * 2, column)
+    }
+
+    /// Returns color `column`, 0 for the leftmost pixel, of the row of a tile at `address`.
+    fn pixel(&self, address: usize, column: usize) -> u8 {
+        let bit = 7 - column;
+        (self.vram[address + 1] >> bit & 1) << 1 | self.vram[address] >> bit & 1
+    }
+
+    /// Draws line `LY` into the picture.
+    fn draw_line(&mut self) {
+        let y = usize::from(self.ly);
+        let mut colors = [0; WIDTH];
+        let window = self.lcdc & WINDOW_ON != 0 && self.ly >= self.wy && self.wx <= 166;
+        let mut window_shown = false;
+        if self.lcdc & BG_ON != 0 {
+            for (x, color) in colors.iter_mut().enumerate() {
+                let (map, column, row) = if window && x + 7 >= usize::from(self.wx) {
+                    window_shown = true;
+                    let map = if self.lcdc & WINDOW_MAP != 0 {
+                        0x1C00
+                    } else {
+                        0x1800
+                    };
+                    (
+                        map,
+                        x + 7 - usize::from(self.wx),
+                        usize::from(self.window_line),
+                    )
+                } else {
+                    let map = if self.lcdc & BG_MAP != 0 {
+                        0x1C00
+                    } else {
+                        0x1800
+                    };
+                    let column = (x + usize::from(self.scx)) & 0xFF;
+                    (map, column, (y + usize::from(self.scy)) & 0xFF)
+                };
+                let tile = self.vram[map + row / 8 * 32 + column / 8];
+                *color = self.background_pixel(tile, row % 8, column % 8);
+            }
+        }
+        if window_shown {
+            self.window_line += 1;
+        }
+
+        let mut shades = [0; WIDTH];
+        for (shade, &color) in shades.iter_mut().zip(&colors) {
+            *shade = self.bgp >> (color * 2) & 3;
+        }
+        if self.lcdc & SPRITES_ON != 0 {
+            self.draw_sprites(y, &colors, &mut shades);
+        }
+
+        let row = &mut self.framebuffer[y * WIDTH * 4..][..WIDTH * 4];
+        for (pixel, &shade) in row.chunks_exact_mut(4).zip(&shades) {
+            pixel[..3].copy_from_slice(&SHADES[usize::from(shade)]);
+            pixel[3] = 0xFF;
+        }
+    }
+
+    /// Draws the sprites on line `y` over the background of colors `colors`, into `shades`. The
+    /// first 10 sprites in OAM that are on the line are drawn, and where they overlap the one
+    /// furthest left wins, or else the first in OAM, even if it is behind the background there.
+    fn draw_sprites(&self, y: usize, colors: &[u8; WIDTH], shades: &mut [u8; WIDTH]) {
+        let height = if self.lcdc & TALL_SPRITES != 0 { 16 } else { 8 };
+        let mut sprites = [0; LINE_SPRITES];
+        let mut count = 0;
+        for (index, sprite) in self.oam.chunks_exact(4).enumerate() {
+            let top = usize::from(sprite[0]);
+            if y + 16 >= top && y + 16 < top + height {
+                sprites[count] = index;
+                count += 1;
+                if count == LINE_SPRITES {
+                    break;
+                }
+            }
+        }
+        let sprites = &mut sprites[..count];
+        sprites.sort_by_key(|&index| self.oam[index * 4 + 1]);
+
+        for (x, shade) in shades.iter_mut().enumerate() {
+            for &index in sprites.iter() {
+                let sprite = &self.oam[index * 4..][..4];
+                let left = usize::from(sprite[1]);
+                if x + 8 < left || x >= left {
+                    continue;
+                }
+                let attributes = sprite[3];
+                let mut column = x + 8 - left;
+                if attributes & FLIP_X != 0 {
+                    column = 7 - column;
+                }
+                let mut row = y + 16 - usize::from(sprite[0]);
+                if attributes & FLIP_Y != 0 {
+                    row = height - 1 - row;
+                }
+                let tile = if height == 16 {
+                    sprite[2] & 0xFE
+                } else {
+                    sprite[2]
+                };
+                let color = self.pixel(usize::from(tile) * 16 + row * 2, column);
+                if color == 0 {
+                    continue;
+                }
+                if attributes & BEHIND == 0 || colors[x] == 0 {
+                    let palette = if attributes & PALETTE_1 != 0 {
+                        self.obp1
+                    } else {
+                        self.obp0
+                    };
+                    *shade = palette >> (color * 2) & 3;
+                }
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a PPU with the LCD off, and tile 1 a solid block of `color`.
+    fn ppu(color: u8) -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.write_register(0xFF40, 0);
+        for row in 0..8 {
+            ppu.vram[16 + row * 2] = if color & 1 != 0 { 0xFF } else { 0 };
+            ppu.vram[16 + row * 2 + 1] = if color & 2 != 0 { 0xFF } else { 0 };
+        }
+        ppu.write_register(0xFF47, 0xE4);
+        ppu.write_register(0xFF48, 0xE4);
+        ppu.write_register(0xFF49, 0x1B);
+        ppu
+    }
+
+    /// Runs `ppu` from the LCD being turned on with `lcdc` until the end of the frame.
+    fn frame(ppu: &mut Ppu, lcdc: u8) {
+        ppu.write_register(0xFF40, LCD_ON | lcdc);
+        while !ppu.take_frame() {
+            ppu.tick();
+        }
+    }
+
+    fn shade(ppu: &Ppu, x: usize, y: usize) -> usize {
+        let pixel = &ppu.framebuffer()[(y * WIDTH + x) * 4..][..3];
+        SHADES.iter().position(|shade| shade == pixel).unwrap()
+    }
+
+    #[test]
+    fn times_lines_and_modes() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0xFF41, LYC_INTERRUPT | HBLANK_INTERRUPT);
+        ppu.write_register(0xFF45, 2);
+        ppu.write_register(0xFF40, LCD_ON);
+        let mut modes = Vec::new();
+        for _ in 0..DOTS / 4 {
+            modes.push(ppu.read_register(0xFF41) & 3);
+            ppu.tick();
+        }
+        assert_eq!(modes.iter().filter(|&&mode| mode == SEARCH).count(), 20);
+        assert_eq!(modes.iter().filter(|&&mode| mode == DRAW).count(), 43);
+        assert_eq!(modes.iter().filter(|&&mode| mode == HBLANK).count(), 51);
+        assert_eq!(ppu.position(), (1, 0));
+        assert_eq!(ppu.take_interrupts(), STAT);
+        for _ in 0..DOTS / 4 {
+            ppu.tick();
+        }
+        // LY = LYC from the start of line 2 keeps the line high through its mode 0.
+        assert_eq!(ppu.read_register(0xFF41) & 0x04, 0x04);
+        assert_eq!(ppu.take_interrupts(), STAT);
+        for _ in 0..DOTS / 4 {
+            ppu.tick();
+        }
+        assert_eq!(ppu.take_interrupts(), 0);
+
+        let mut cycles = 3 * u32::from(DOTS) / 4;
+        while !ppu.take_frame() {
+            ppu.tick();
+            cycles += 1;
+        }
+        assert_eq!(cycles, 144 * u32::from(DOTS) / 4);
+        assert_eq!(ppu.take_interrupts() & VBLANK, VBLANK);
+        assert_eq!(ppu.read_register(0xFF41) & 3, VBLANK_MODE);
+        // Video memory is closed while the line is drawn.
+        ppu.write_register(0xFF40, 0);
+        ppu.write_register(0xFF40, LCD_ON);
+        for _ in 0..SEARCH_DOTS / 4 {
+            ppu.tick();
+        }
+        ppu.write_vram(0x8000, 0x12);
+        assert_eq!(ppu.read_vram(0x8000), 0xFF);
+        assert_eq!(ppu.vram()[0], 0);
+    }
+
+    #[test]
+    fn draws_the_background_and_window() {
+        let mut ppu = ppu(3);
+        // Tile 1 at column 1, row 0 of the first map, and at the top left of the second.
+        ppu.vram[0x1801] = 1;
+        ppu.vram[0x1C00] = 1;
+        ppu.write_register(0xFF43, 4);
+        frame(&mut ppu, TILE_DATA | BG_ON);
+        assert_eq!(shade(&ppu, 3, 0), 0);
+        assert_eq!(shade(&ppu, 4, 0), 3);
+        assert_eq!(shade(&ppu, 11, 7), 3);
+        assert_eq!(shade(&ppu, 12, 0), 0);
+        assert_eq!(shade(&ppu, 4, 8), 0);
+
+        // The window, from (20, 100), over the same map.
+        ppu.write_register(0xFF4A, 100);
+        ppu.write_register(0xFF4B, 27);
+        frame(&mut ppu, TILE_DATA | BG_ON | WINDOW_ON | WINDOW_MAP);
+        assert_eq!(shade(&ppu, 19, 100), 0);
+        assert_eq!(shade(&ppu, 20, 100), 3);
+        assert_eq!(shade(&ppu, 27, 107), 3);
+        assert_eq!(shade(&ppu, 28, 100), 0);
+        assert_eq!(shade(&ppu, 20, 99), 0);
+
+        // Signed tile numbers from 0x9000, where tile 1 is blank.
+        frame(&mut ppu, BG_ON);
+        assert_eq!(shade(&ppu, 4, 0), 0);
+        // The background off, which is blank.
+        frame(&mut ppu, TILE_DATA);
+        assert_eq!(shade(&ppu, 4, 0), 0);
+    }
+
+    #[test]
+    fn draws_sprites() {
+        let mut ppu = ppu(1);
+        // Tile 2 is solid color 2, and tile 4 has color 3 on its left half.
+        for row in 0..8 {
+            ppu.vram[32 + row * 2 + 1] = 0xFF;
+            ppu.vram[64 + row * 2] = 0xF0;
+            ppu.vram[64 + row * 2 + 1] = 0xF0;
+        }
+        ppu.vram[0x1800] = 1;
+        #[rustfmt::skip]
+        ppu.oam[..28].copy_from_slice(&[
+            // At (16, 0), and at (20, 0) with the second palette, under the first.
+            16, 24, 2, 0,
+            16, 28, 2, PALETTE_1,
+            // At (0, 0) behind the background, and at (40, 0) behind its color 0.
+            16, 8, 2, BEHIND,
+            16, 48, 2, BEHIND,
+            // At (60, 0), flipped.
+            16, 68, 4, FLIP_X,
+            // At (100, 20), and at (120, 20) flipped, which differ in 8×16.
+            36, 108, 3, 0,
+            36, 128, 2, FLIP_Y,
+        ]);
+        frame(&mut ppu, TILE_DATA | BG_ON | SPRITES_ON);
+        assert_eq!(shade(&ppu, 16, 0), 2);
+        assert_eq!(shade(&ppu, 23, 7), 2);
+        assert_eq!(shade(&ppu, 24, 0), 1);
+        assert_eq!(shade(&ppu, 27, 0), 1);
+        assert_eq!(shade(&ppu, 28, 0), 0);
+        assert_eq!(shade(&ppu, 0, 0), 1);
+        assert_eq!(shade(&ppu, 40, 0), 2);
+        assert_eq!(shade(&ppu, 60, 0), 0);
+        assert_eq!(shade(&ppu, 64, 0), 3);
+        assert_eq!(shade(&ppu, 100, 20), 0);
+        assert_eq!(shade(&ppu, 120, 20), 2);
+
+        frame(&mut ppu, TILE_DATA | BG_ON | SPRITES_ON | TALL_SPRITES);
+        assert_eq!(shade(&ppu, 100, 20), 2);
+        assert_eq!(shade(&ppu, 100, 28), 0);
+        assert_eq!(shade(&ppu, 120, 20), 0);
+        assert_eq!(shade(&ppu, 120, 28), 2);
+
+        // Only 10 sprites are drawn on a line.
+        for (index, sprite) in ppu.oam.chunks_exact_mut(4).take(11).enumerate() {
+            sprite.copy_from_slice(&[100, 8 + 8 * index as u8, 2, 0]);
+        }
+        frame(&mut ppu, TILE_DATA | SPRITES_ON);
+        assert_eq!(shade(&ppu, 72, 84), 2);
+        assert_eq!(shade(&ppu, 80, 84), 0);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut ppu = ppu(2);
+        ppu.vram[0x1800] = 1;
+        frame(&mut ppu, TILE_DATA | BG_ON);
+        for _ in 0..1000 {
+            ppu.tick();
+        }
+        let state = ppu.save();
+        assert_eq!(state.len(), STATE_SIZE);
+        let mut restored = Ppu::new();
+        restored.restore(&state);
+        assert_eq!(restored.save(), state);
+        frame(&mut ppu, TILE_DATA | BG_ON);
+        frame(&mut restored, TILE_DATA | BG_ON);
+        assert_eq!(restored.framebuffer(), ppu.framebuffer());
+    }
+}
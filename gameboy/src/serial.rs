@@ -0,0 +1,297 @@
+//! The serial port: `SB` at 0xFF01 and `SC` at 0xFF02, and the link cable plugged into it.
+//!
+//! A transfer shifts the byte in `SB` out, a bit at a time, while shifting in the other Game
+//! Boy's. With the Game Boy's own clock, selected by bit 0 of `SC`, it sends 8192 bits a second;
+//! with the other's, it waits for it. With nothing connected, a transfer on the Game Boy's own
+//! clock shifts in 1s, and one on the other's never ends. The bytes sent are kept, since test
+//! ROMs print their results there.
+//!
+//! Over a [`Link`], whose other end may be a frame or more behind, the two Game Boys exchange
+//! [`Message`]s instead of bits. A Game Boy that starts a transfer on the other's clock tells it
+//! the byte it is ready to send. A transfer on the Game Boy's own clock takes that byte when it
+//! ends, and sends its own in return, which ends the other's transfer; if the other was not
+//! ready, it shifts in 1s, as when nothing is connected, and the other never sees it. Games wait
+//! for the other Game Boy and try again, as they do on hardware when it is late, so a byte
+//! crosses the cable about once a frame, slower than on hardware but fast enough to trade and
+//! battle.
+
+use emu_core::link::Link;
+
+/// Length of the state written by [`Serial::save`].
+pub const STATE_SIZE: usize = 5;
+
+/// Bytes sent that are kept, the oldest dropped first.
+const OUTPUT_SIZE: usize = 1024;
+
+/// Machine cycles a bit takes on the Game Boy's own clock.
+const BIT_CYCLES: u8 = 128;
+
+/// The bit of `SC` that starts a transfer, and stays set until it ends.
+const START: u8 = 0x80;
+
+/// The bit of `SC` that selects the Game Boy's own clock.
+const INTERNAL_CLOCK: u8 = 0x01;
+
+/// What a Game Boy tells the other over a link cable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// It started a transfer on the other's clock, and will send the byte.
+    Ready(u8),
+    /// It ended a transfer on its own clock, sending the byte.
+    Transfer(u8),
+}
+
+impl Message {
+    /// Returns the message as sent over the cable: its kind, then its byte.
+    pub fn encode(self) -> [u8; 2] {
+        match self {
+            Message::Ready(byte) => [0x01, byte],
+            Message::Transfer(byte) => [0x02, byte],
+        }
+    }
+
+    /// Reads a message sent as [`encode`](Message::encode) sends it, or returns `None` if `kind`
+    /// is of none.
+    pub fn decode(kind: u8, byte: u8) -> Option<Message> {
+        match kind {
+            0x01 => Some(Message::Ready(byte)),
+            0x02 => Some(Message::Transfer(byte)),
+            _ => None,
+        }
+    }
+}
+
+/// The serial port.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Serial {
+    data: u8,
+    control: u8,
+    /// Bits left to shift.
+    bits: u8,
+    /// Machine cycles into the bit being shifted.
+    cycles: u8,
+    /// The byte being sent.
+    sent: u8,
+    /// The byte the other Game Boy is ready to send, if it said so.
+    peer: Option<u8>,
+    /// The messages for the other Game Boy, until they are sent.
+    outbox: Vec<Message>,
+    output: Vec<u8>,
+}
+
+impl Serial {
+    /// Runs the port for a machine cycle, and returns whether it requests the serial interrupt.
+    pub fn tick(&mut self) -> bool {
+        if self.control & (START | INTERNAL_CLOCK) != START | INTERNAL_CLOCK {
+            return false;
+        }
+        self.cycles += 1;
+        if self.cycles < BIT_CYCLES {
+            return false;
+        }
+        self.cycles = 0;
+        self.data = self.data << 1 | 1;
+        self.bits -= 1;
+        if self.bits > 0 {
+            return false;
+        }
+        if let Some(byte) = self.peer.take() {
+            self.data = byte;
+            self.outbox.push(Message::Transfer(self.sent));
+        }
+        self.control &= !START;
+        true
+    }
+
+    /// Takes a message from the other Game Boy, and returns whether it requests the serial
+    /// interrupt, as it does when it ends a transfer on its clock.
+    pub fn receive(&mut self, message: Message) -> bool {
+        match message {
+            Message::Ready(byte) => {
+                self.peer = Some(byte);
+                false
+            }
+            Message::Transfer(byte) if self.control == START => {
+                self.data = byte;
+                self.bits = 0;
+                self.control = 0;
+                true
+            }
+            Message::Transfer(_) => false,
+        }
+    }
+
+    /// Moves the messages for the other Game Boy to the end of `messages`.
+    pub fn take_messages(&mut self, messages: &mut Vec<Message>) {
+        messages.append(&mut self.outbox);
+    }
+
+    /// Reads the register at `address`, 0xFF01 or 0xFF02.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            _ => self.control | 0x7E,
+        }
+    }
+
+    /// Writes the register at `address`, 0xFF01 or 0xFF02.
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.data = value,
+            _ => {
+                self.control = value & (START | INTERNAL_CLOCK);
+                if value & START != 0 {
+                    self.bits = 8;
+                    self.cycles = 0;
+                    self.sent = self.data;
+                    if self.output.len() == OUTPUT_SIZE {
+                        self.output.remove(0);
+                    }
+                    self.output.push(self.data);
+                }
+            }
+        }
+        if self.control == START {
+            self.outbox.push(Message::Ready(self.data));
+        }
+    }
+
+    /// Returns the last bytes sent, up to 1KB.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Returns the state of the port, for [`restore`](Serial::restore). The bytes sent are not
+    /// part of it, nor what the other Game Boy said.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        [self.data, self.control, self.bits, self.cycles, self.sent]
+    }
+
+    /// Restores a state returned by [`save`](Serial::save).
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        self.data = state[0];
+        self.control = state[1] & (START | INTERNAL_CLOCK);
+        self.bits = state[2].min(8);
+        self.cycles = state[3].min(BIT_CYCLES - 1);
+        self.sent = state[4];
+    }
+}
+
+/// The link cable plugged into the serial port, if any.
+#[derive(Default)]
+pub struct Cable {
+    link: Option<Box<dyn Link>>,
+    /// The kind of the message being received, when only it has arrived.
+    kind: Option<u8>,
+    messages: Vec<Message>,
+}
+
+impl Clone for Cable {
+    /// Returns an unplugged cable: a copy of a Game Boy is not linked to the other.
+    fn clone(&self) -> Self {
+        Cable::default()
+    }
+}
+
+impl Cable {
+    /// Plugs `link` in, in place of what was plugged in, or unplugs the cable if `None`.
+    pub fn plug(&mut self, link: Option<Box<dyn Link>>) {
+        self.link = link;
+        self.kind = None;
+    }
+
+    /// Returns `true` if a cable is plugged in and its other end is there.
+    pub fn is_linked(&self) -> bool {
+        self.link.as_ref().is_some_and(|link| link.is_connected())
+    }
+
+    /// Sends the messages of `serial` to the other Game Boy, and gives it those that arrived if
+    /// `poll`. Returns whether they request the serial interrupt.
+    pub fn exchange(&mut self, serial: &mut Serial, poll: bool) -> bool {
+        serial.take_messages(&mut self.messages);
+        let link = match &mut self.link {
+            Some(link) => link,
+            None => {
+                self.messages.clear();
+                return false;
+            }
+        };
+        for message in self.messages.drain(..) {
+            link.send(&message.encode());
+        }
+        if !poll {
+            return false;
+        }
+        let mut interrupt = false;
+        while let Some(byte) = link.receive() {
+            match self.kind.take() {
+                None => self.kind = Some(byte),
+                Some(kind) => {
+                    if let Some(message) = Message::decode(kind, byte) {
+                        interrupt |= serial.receive(message);
+                    }
+                }
+            }
+        }
+        interrupt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_with_nothing_connected() {
+        let mut serial = Serial::default();
+        serial.write(0xFF01, b'A');
+        serial.write(0xFF02, START | INTERNAL_CLOCK);
+        assert_eq!(serial.read(0xFF02), 0xFF);
+        let cycles = (1..).find(|_| serial.tick()).unwrap();
+        assert_eq!(cycles, 8 * u32::from(BIT_CYCLES));
+        assert_eq!(serial.read(0xFF01), 0xFF);
+        assert_eq!(serial.read(0xFF02), 0x7F);
+        assert_eq!(serial.output(), b"A");
+
+        // On the other Game Boy's clock, the transfer waits forever.
+        serial.write(0xFF02, START);
+        assert!((0..10_000).all(|_| !serial.tick()));
+    }
+
+    #[test]
+    fn exchanges_messages() {
+        let mut first = Serial::default();
+        let mut second = Serial::default();
+        let mut messages = Vec::new();
+        second.write(0xFF01, 0x42);
+        second.write(0xFF02, START);
+        second.take_messages(&mut messages);
+        assert_eq!(messages, [Message::Ready(0x42)]);
+        assert!(!first.receive(messages[0]));
+
+        messages.clear();
+        first.write(0xFF01, 0x17);
+        first.write(0xFF02, START | INTERNAL_CLOCK);
+        assert!((1..8 * u32::from(BIT_CYCLES)).all(|_| !first.tick()));
+        assert!(first.tick());
+        assert_eq!(first.read(0xFF01), 0x42);
+        first.take_messages(&mut messages);
+        assert_eq!(messages, [Message::Transfer(0x17)]);
+        assert!(second.receive(messages[0]));
+        assert_eq!(second.read(0xFF01), 0x17);
+        assert_eq!(second.read(0xFF02), 0x7E);
+
+        // A Game Boy not waiting for a transfer ignores one, and the other shifts in 1s.
+        assert!(!second.receive(Message::Transfer(0x99)));
+        assert_eq!(second.read(0xFF01), 0x17);
+        first.write(0xFF02, START | INTERNAL_CLOCK);
+        assert!((0..8 * u32::from(BIT_CYCLES)).any(|_| first.tick()));
+        assert_eq!(first.read(0xFF01), 0xFF);
+
+        for message in [Message::Ready(0x12), Message::Transfer(0x34)] {
+            let [kind, byte] = message.encode();
+            assert_eq!(Message::decode(kind, byte), Some(message));
+        }
+        assert_eq!(Message::decode(0x00, 0x12), None);
+    }
+}
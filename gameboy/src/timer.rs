@@ -0,0 +1,208 @@
+//! The timer: `DIV` at 0xFF04, `TIMA` at 0xFF05, `TMA` at 0xFF06 and `TAC` at 0xFF07.
+//!
+//! A 16-bit counter runs at the clock's 4.19MHz, and `DIV` is its high byte. `TIMA` counts up
+//! when the bit of the counter `TAC` selects falls from 1 to 0, so writing `DIV`, which clears the
+//! counter, or changing `TAC` can count it up as well. When `TIMA` overflows it reads 0 for a
+//! machine cycle, then is loaded from `TMA` and requests the timer interrupt.
+
+/// Length of the state written by [`Timer::save`].
+pub const STATE_SIZE: usize = 7;
+
+/// The bit of the counter that clocks `TIMA`, for each frequency `TAC` selects: 4096Hz, 262144Hz,
+/// 65536Hz and 16384Hz.
+const TAP: [u16; 4] = [1 << 9, 1 << 3, 1 << 5, 1 << 7];
+
+/// The bit of `TAC` that turns `TIMA` on.
+const ENABLE: u8 = 0x04;
+
+/// The timer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    /// Whether `TIMA` overflowed on the last machine cycle, and is loaded from `TMA` on this one.
+    overflow: bool,
+    /// Whether `TIMA` was loaded from `TMA` on this machine cycle, so that writes to it are lost.
+    reloaded: bool,
+}
+
+impl Timer {
+    /// Creates the timer as the boot ROM leaves it.
+    pub fn new() -> Self {
+        Timer {
+            counter: 0xABCC,
+            tac: 0xF8,
+            ..Timer::default()
+        }
+    }
+
+    /// Runs the timer for a machine cycle, and returns whether it requests its interrupt.
+    pub fn tick(&mut self) -> bool {
+        self.reloaded = false;
+        let interrupt = self.overflow;
+        if self.overflow {
+            self.overflow = false;
+            self.tima = self.tma;
+            self.reloaded = true;
+        }
+        let before = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        if before && !self.signal() {
+            self.increment();
+        }
+        interrupt
+    }
+
+    /// Returns the counter the divider is the high byte of.
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    /// Reads the register at `address`, 0xFF04 to 0xFF07.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            0xFF04 => (self.counter >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            _ => self.tac | 0xF8,
+        }
+    }
+
+    /// Writes the register at `address`, 0xFF04 to 0xFF07.
+    pub fn write(&mut self, address: u16, value: u8) {
+        let before = self.signal();
+        match address {
+            0xFF04 => self.counter = 0,
+            0xFF05 => {
+                if !self.reloaded {
+                    self.tima = value;
+                    self.overflow = false;
+                }
+            }
+            0xFF06 => {
+                self.tma = value;
+                if self.reloaded {
+                    self.tima = value;
+                }
+            }
+            _ => self.tac = value & 0x07,
+        }
+        if before && !self.signal() {
+            self.increment();
+        }
+    }
+
+    /// Returns the state of the timer, for [`restore`](Timer::restore).
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let [low, high] = self.counter.to_le_bytes();
+        [
+            low,
+            high,
+            self.tima,
+            self.tma,
+            self.tac,
+            u8::from(self.overflow),
+            u8::from(self.reloaded),
+        ]
+    }
+
+    /// Restores a state returned by [`save`](Timer::save).
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        *self = Timer {
+            counter: u16::from_le_bytes([state[0], state[1]]),
+            tima: state[2],
+            tma: state[3],
+            tac: state[4] & 0x07,
+            overflow: state[5] != 0,
+            reloaded: state[6] != 0,
+        };
+    }
+
+    /// Returns whether `TIMA` is on and the bit of the counter that clocks it is set.
+    fn signal(&self) -> bool {
+        self.tac & ENABLE != 0 && self.counter & TAP[usize::from(self.tac & 3)] != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.overflow = overflow;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divides_the_clock() {
+        let mut timer = Timer::default();
+        for _ in 0..64 {
+            timer.tick();
+        }
+        assert_eq!(timer.read(0xFF04), 1);
+        timer.write(0xFF04, 0x55);
+        assert_eq!(timer.read(0xFF04), 0);
+        assert_eq!(timer.read(0xFF07), 0xF8);
+    }
+
+    #[test]
+    fn counts_and_overflows() {
+        let mut timer = Timer::default();
+        // 262144Hz, every 4 machine cycles, reloaded with 0xF0.
+        timer.write(0xFF07, 0x05);
+        timer.write(0xFF06, 0xF0);
+        timer.write(0xFF05, 0xFE);
+        for _ in 0..8 {
+            assert!(!timer.tick());
+        }
+        // TIMA reads 0 for a machine cycle before it is reloaded and interrupts.
+        assert_eq!(timer.read(0xFF05), 0x00);
+        assert!(timer.tick());
+        assert_eq!(timer.read(0xFF05), 0xF0);
+
+        // Writing TIMA in the cycle it is reloaded is lost, and in the cycle it reads 0 cancels
+        // the reload.
+        timer.write(0xFF05, 0xFF);
+        assert_eq!(timer.read(0xFF05), 0xF0);
+        timer.tick();
+        timer.write(0xFF05, 0xFF);
+        timer.tick();
+        timer.tick();
+        assert_eq!(timer.read(0xFF05), 0x00);
+        timer.write(0xFF05, 0x12);
+        assert!(!timer.tick());
+        assert_eq!(timer.read(0xFF05), 0x12);
+    }
+
+    #[test]
+    fn counts_on_falling_edges() {
+        let mut timer = Timer::default();
+        timer.write(0xFF07, 0x05);
+        timer.tick();
+        timer.tick();
+        // Bit 3 of the counter is set, so clearing it counts TIMA up.
+        timer.write(0xFF04, 0);
+        assert_eq!(timer.read(0xFF05), 1);
+        timer.tick();
+        timer.tick();
+        // So does turning TIMA off.
+        timer.write(0xFF07, 0x01);
+        assert_eq!(timer.read(0xFF05), 2);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut timer = Timer::new();
+        timer.write(0xFF07, 0x06);
+        timer.write(0xFF06, 0x80);
+        for _ in 0..100 {
+            timer.tick();
+        }
+        let mut restored = Timer::default();
+        restored.restore(&timer.save());
+        assert_eq!(restored, timer);
+    }
+}
@@ -0,0 +1,149 @@
+//! Runs Blargg's test ROMs for the processor and the APU, which check the Game Boy against what it
+//! does on hardware and print whether it passed, to the serial port, cartridge RAM or only to the
+//! screen.
+//!
+//! The ROMs are not part of this repository, so the tests are ignored unless asked for, and then
+//! need a directory holding them, named as in Blargg's archives: `cpu_instrs.gb` and its singles
+//! `01-special.gb` to `11-op a,(hl).gb`, `instr_timing.gb`, `mem_timing.gb` and its singles
+//! `01-read_timing.gb` to `03-modify_timing.gb`, `halt_bug.gb`, and `dmg_sound.gb` and its singles
+//! `01-registers.gb` to `12-wave write while on.gb`. Missing files are skipped:
+//!
+//! ```text
+//! GAMEBOY_TEST_ROMS=roms/gameboy cargo test -p gameboy --release --test test_roms -- --ignored --nocapture
+//! ```
+
+use emu_core::Emulator;
+use gameboy::GameBoy;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The directory of the ROMs to run.
+const TEST_ROMS: &str = "GAMEBOY_TEST_ROMS";
+
+/// What the newer ROMs write at 0xA001 of cartridge RAM to tell that they print from 0xA004.
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+/// How many frames a ROM may run before it is taken to be lost. The whole of `cpu_instrs.gb`
+/// takes about a minute.
+const FRAMES: usize = 6000;
+
+/// Returns the Game Boy with the ROM `name` from the directory of the ROMs, or `None` if it is
+/// not there.
+fn game_boy(name: &str) -> Option<GameBoy> {
+    let dir = env::var_os(TEST_ROMS).unwrap_or_else(|| panic!("{} is not set", TEST_ROMS));
+    let rom = match fs::read(PathBuf::from(dir).join(name)) {
+        Ok(rom) => rom,
+        Err(_) => {
+            eprintln!("{} is missing, skipping", name);
+            return None;
+        }
+    };
+    let mut game_boy = GameBoy::new();
+    game_boy.load_rom(&rom).unwrap();
+    Some(game_boy)
+}
+
+/// Returns what the ROM printed to the serial port or to cartridge RAM, or if nothing, the text on
+/// the screen. The ROMs draw characters with the tiles of their ASCII codes, in the background map
+/// at 0x9800.
+fn output(game_boy: &GameBoy) -> String {
+    if !game_boy.serial_output().is_empty() {
+        return String::from_utf8_lossy(game_boy.serial_output()).into_owned();
+    }
+    let ram = game_boy.cartridge().ram();
+    if ram.len() > 4 && ram[1..4] == SIGNATURE {
+        return ram[4..]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| char::from(byte))
+            .collect();
+    }
+    let map = &game_boy.ppu().vram()[0x1800..0x1C00];
+    map.chunks(32)
+        .map(|row| {
+            let line: String = row[..20].iter().map(|&tile| char::from(tile)).collect();
+            line.trim_end().to_string() + "\n"
+        })
+        .collect()
+}
+
+/// Runs the ROMs until they print `Passed` or `Failed`.
+fn run(names: &[&str]) {
+    for name in names {
+        let mut game_boy = match game_boy(name) {
+            Some(game_boy) => game_boy,
+            None => continue,
+        };
+        let mut frames = 0;
+        let output = loop {
+            game_boy
+                .step_frame()
+                .unwrap_or_else(|error| panic!("{}: {}", name, error));
+            frames += 1;
+            let output = output(&game_boy);
+            if output.contains("Passed") || output.contains("Failed") {
+                break output;
+            }
+            assert!(frames < FRAMES, "{} did not finish: {}", name, output);
+        };
+        assert!(!output.contains("Failed"), "{} failed: {}", name, output);
+    }
+}
+
+#[test]
+#[ignore = "needs $GAMEBOY_TEST_ROMS"]
+fn cpu_instrs() {
+    run(&[
+        "01-special.gb",
+        "02-interrupts.gb",
+        "03-op sp,hl.gb",
+        "04-op r,imm.gb",
+        "05-op rp.gb",
+        "06-ld r,r.gb",
+        "07-jr,jp,call,ret,rst.gb",
+        "08-misc instrs.gb",
+        "09-op r,r.gb",
+        "10-bit ops.gb",
+        "11-op a,(hl).gb",
+        "cpu_instrs.gb",
+    ]);
+}
+
+#[test]
+#[ignore = "needs $GAMEBOY_TEST_ROMS"]
+fn timing() {
+    run(&[
+        "instr_timing.gb",
+        "01-read_timing.gb",
+        "02-write_timing.gb",
+        "03-modify_timing.gb",
+        "mem_timing.gb",
+    ]);
+}
+
+#[test]
+#[ignore = "needs $GAMEBOY_TEST_ROMS"]
+fn halt_bug() {
+    run(&["halt_bug.gb"]);
+}
+
+#[test]
+#[ignore = "needs $GAMEBOY_TEST_ROMS"]
+fn dmg_sound() {
+    run(&[
+        "01-registers.gb",
+        "02-len ctr.gb",
+        "03-trigger.gb",
+        "04-sweep.gb",
+        "05-sweep details.gb",
+        "06-overflow on trigger.gb",
+        "07-len sweep period sync.gb",
+        "08-len ctr during power.gb",
+        "09-wave read while on.gb",
+        "10-wave trigger while on.gb",
+        "11-regs after power.gb",
+        "12-wave write while on.gb",
+        "dmg_sound.gb",
+    ]);
+}
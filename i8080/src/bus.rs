@@ -0,0 +1,41 @@
+//! What the processor is wired to: memory, the 256 input and output ports, and the interrupt
+//! line.
+
+pub use emu_memory::{Memory, Overflow};
+
+/// The system around the processor.
+///
+/// Only memory has to be provided. The other methods default to a system with no devices: inputs
+/// read 0, outputs go nowhere and no interrupt is ever asked for.
+pub trait Bus {
+    /// Reads the byte at `address`.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes `value` at `address`.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Reads the input port `port`, for `IN`.
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    /// Writes `value` to the output port `port`, for `OUT`.
+    fn output(&mut self, _port: u8, _value: u8) {}
+
+    /// Returns the instruction a device puts on the data bus to interrupt the program, usually
+    /// an `RST`. Only asked while interrupts are enabled, so the interrupt is taken when one is
+    /// returned.
+    fn interrupt(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[address as usize] = value;
+    }
+}
@@ -0,0 +1,205 @@
+//! Disassembling instructions into Intel's mnemonics, such as `MVI A, 2A`, `LXI H, 2400` and
+//! `MOV M, A`, for debuggers and traces.
+//!
+//! Opcodes Intel left undefined are shown as the instructions they run as: `NOP`, `JMP`, `RET` and
+//! `CALL`.
+//!
+//! ```
+//! use i8080::disasm::Instruction;
+//!
+//! let instruction = Instruction::decode(&[0x21, 0x00, 0x24]).unwrap();
+//! assert_eq!(instruction.to_string(), "LXI H, 2400");
+//! assert_eq!(instruction.len(), 3);
+//! ```
+
+use core::fmt;
+
+/// The registers as instructions number them, with memory at `HL` as `M`.
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+
+/// The pairs of `LXI`, `DAD`, `INX` and `DCX`, named by their first register, and the stack
+/// pointer.
+const PAIRS: [&str; 4] = ["B", "D", "H", "SP"];
+
+/// The pairs of `PUSH` and `POP`.
+const STACK_PAIRS: [&str; 4] = ["B", "D", "H", "PSW"];
+
+/// The arithmetic and logic operations on a register, `80` to `BF`.
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBB", "ANA", "XRA", "ORA", "CMP"];
+
+/// The same operations on an immediate byte.
+const ALU_IMMEDIATE: [&str; 8] = ["ADI", "ACI", "SUI", "SBI", "ANI", "XRI", "ORI", "CPI"];
+
+/// The rotations and the other operations on the accumulator and flags, `07` to `3F`.
+const ACCUMULATOR: [&str; 8] = ["RLC", "RRC", "RAL", "RAR", "DAA", "CMA", "STC", "CMC"];
+
+/// The conditional returns, jumps and calls, by their condition.
+const RETURNS: [&str; 8] = ["RNZ", "RZ", "RNC", "RC", "RPO", "RPE", "RP", "RM"];
+const JUMPS: [&str; 8] = ["JNZ", "JZ", "JNC", "JC", "JPO", "JPE", "JP", "JM"];
+const CALLS: [&str; 8] = ["CNZ", "CZ", "CNC", "CC", "CPO", "CPE", "CP", "CM"];
+
+/// Returns the length in bytes of the instruction starting with `opcode`: 1, 2 for an immediate
+/// byte or a port, or 3 for an address or an immediate 16-bit value.
+pub fn length(opcode: u8) -> usize {
+    match opcode {
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => 2,
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE | 0xD3 | 0xDB => 2,
+        0x01 | 0x11 | 0x21 | 0x31 | 0x22 | 0x2A | 0x32 | 0x3A => 3,
+        0xC3 | 0xCB | 0xCD | 0xDD | 0xED | 0xFD => 3,
+        _ if opcode & 0xC7 == 0xC2 || opcode & 0xC7 == 0xC4 => 3,
+        _ => 1,
+    }
+}
+
+/// An instruction with its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u8,
+    /// The byte or the 16-bit value following the opcode.
+    pub operand: u16,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, or returns `None` if they end before it
+    /// does.
+    pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+        let opcode = *bytes.first()?;
+        let operand = match length(opcode) {
+            1 => 0,
+            2 => u16::from(*bytes.get(1)?),
+            _ => u16::from_le_bytes([*bytes.get(1)?, *bytes.get(2)?]),
+        };
+        Some(Instruction { opcode, operand })
+    }
+
+    /// Returns the length of the instruction in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        length(self.opcode)
+    }
+
+    /// Returns the mnemonic of the instruction, such as `MVI`.
+    pub fn mnemonic(&self) -> &'static str {
+        let opcode = self.opcode;
+        let n = (opcode >> 3 & 7) as usize;
+        match opcode {
+            0x76 => "HLT",
+            0x40..=0x7F => "MOV",
+            0x80..=0xBF => ALU[n],
+            _ if opcode & 0xC7 == 0x00 => "NOP",
+            _ if opcode & 0xCF == 0x01 => "LXI",
+            0x02 | 0x12 => "STAX",
+            0x0A | 0x1A => "LDAX",
+            0x22 => "SHLD",
+            0x2A => "LHLD",
+            0x32 => "STA",
+            0x3A => "LDA",
+            _ if opcode & 0xCF == 0x03 => "INX",
+            _ if opcode & 0xCF == 0x0B => "DCX",
+            _ if opcode & 0xC7 == 0x04 => "INR",
+            _ if opcode & 0xC7 == 0x05 => "DCR",
+            _ if opcode & 0xC7 == 0x06 => "MVI",
+            _ if opcode & 0xC7 == 0x07 => ACCUMULATOR[n],
+            _ if opcode & 0xCF == 0x09 => "DAD",
+            _ if opcode & 0xC7 == 0xC0 => RETURNS[n],
+            _ if opcode & 0xCF == 0xC1 => "POP",
+            _ if opcode & 0xC7 == 0xC2 => JUMPS[n],
+            _ if opcode & 0xC7 == 0xC4 => CALLS[n],
+            _ if opcode & 0xCF == 0xC5 => "PUSH",
+            _ if opcode & 0xC7 == 0xC6 => ALU_IMMEDIATE[n],
+            _ if opcode & 0xC7 == 0xC7 => "RST",
+            0xC3 | 0xCB => "JMP",
+            0xC9 | 0xD9 => "RET",
+            0xCD | 0xDD | 0xED | 0xFD => "CALL",
+            0xD3 => "OUT",
+            0xDB => "IN",
+            0xE3 => "XTHL",
+            0xE9 => "PCHL",
+            0xEB => "XCHG",
+            0xF3 => "DI",
+            0xF9 => "SPHL",
+            _ => "EI",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+        let opcode = self.opcode;
+        let destination = REGISTERS[(opcode >> 3 & 7) as usize];
+        let source = REGISTERS[(opcode & 7) as usize];
+        let pair = (opcode >> 4 & 3) as usize;
+        match mnemonic {
+            "MOV" => write!(f, "MOV {}, {}", destination, source),
+            "MVI" => write!(f, "MVI {}, {:02X}", destination, self.operand),
+            "INR" | "DCR" => write!(f, "{} {}", mnemonic, destination),
+            "LXI" => write!(f, "LXI {}, {:04X}", PAIRS[pair], self.operand),
+            "INX" | "DCX" | "DAD" | "STAX" | "LDAX" => write!(f, "{} {}", mnemonic, PAIRS[pair]),
+            "PUSH" | "POP" => write!(f, "{} {}", mnemonic, STACK_PAIRS[pair]),
+            "RST" => write!(f, "RST {}", opcode >> 3 & 7),
+            _ if (0x80..=0xBF).contains(&opcode) => write!(f, "{} {}", mnemonic, source),
+            _ => match self.len() {
+                2 => write!(f, "{} {:02X}", mnemonic, self.operand),
+                3 => write!(f, "{} {:04X}", mnemonic, self.operand),
+                _ => f.write_str(mnemonic),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    fn disassemble(bytes: &[u8]) -> std::string::String {
+        Instruction::decode(bytes).unwrap().to_string()
+    }
+
+    #[test]
+    fn disassembles() {
+        assert_eq!(disassemble(&[0x00]), "NOP");
+        assert_eq!(disassemble(&[0x01, 0x34, 0x12]), "LXI B, 1234");
+        assert_eq!(disassemble(&[0x31, 0x00, 0x24]), "LXI SP, 2400");
+        assert_eq!(disassemble(&[0x12]), "STAX D");
+        assert_eq!(disassemble(&[0x2A, 0x00, 0x20]), "LHLD 2000");
+        assert_eq!(disassemble(&[0x3B]), "DCX SP");
+        assert_eq!(disassemble(&[0x34]), "INR M");
+        assert_eq!(disassemble(&[0x3E, 0x2A]), "MVI A, 2A");
+        assert_eq!(disassemble(&[0x1F]), "RAR");
+        assert_eq!(disassemble(&[0x27]), "DAA");
+        assert_eq!(disassemble(&[0x29]), "DAD H");
+        assert_eq!(disassemble(&[0x77]), "MOV M, A");
+        assert_eq!(disassemble(&[0x76]), "HLT");
+        assert_eq!(disassemble(&[0x96]), "SUB M");
+        assert_eq!(disassemble(&[0xBF]), "CMP A");
+        assert_eq!(disassemble(&[0xC0]), "RNZ");
+        assert_eq!(disassemble(&[0xF1]), "POP PSW");
+        assert_eq!(disassemble(&[0xDA, 0x00, 0x01]), "JC 0100");
+        assert_eq!(disassemble(&[0xFC, 0x00, 0x01]), "CM 0100");
+        assert_eq!(disassemble(&[0xE5]), "PUSH H");
+        assert_eq!(disassemble(&[0xFE, 0x0A]), "CPI 0A");
+        assert_eq!(disassemble(&[0xCF]), "RST 1");
+        assert_eq!(disassemble(&[0xCD, 0x05, 0x00]), "CALL 0005");
+        assert_eq!(disassemble(&[0xD3, 0x03]), "OUT 03");
+        assert_eq!(disassemble(&[0xDB, 0x01]), "IN 01");
+        assert_eq!(disassemble(&[0xEB]), "XCHG");
+        assert_eq!(disassemble(&[0xFB]), "EI");
+        assert_eq!(disassemble(&[0x08]), "NOP");
+        assert_eq!(disassemble(&[0xCB, 0x00, 0x00]), "JMP 0000");
+        assert_eq!(disassemble(&[0xD9]), "RET");
+        assert_eq!(Instruction::decode(&[0xC3, 0x01]), None);
+        assert_eq!(Instruction::decode(&[]), None);
+    }
+
+    #[test]
+    fn lengths() {
+        let lengths: usize = (0..=255u8).map(length).sum();
+        // 8 MVI, 8 immediate operations, IN and OUT; 4 LXI, 4 direct loads and stores, 2 JMP,
+        // 4 CALL and 8 each of the conditional jumps and calls.
+        assert_eq!(lengths, 256 + 18 + (4 + 4 + 2 + 4 + 16) * 2);
+    }
+}
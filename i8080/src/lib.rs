@@ -0,0 +1,917 @@
+//! The Intel 8080 microprocessor, the processor of Space Invaders and of the other arcade
+//! machines of the late 1970s, and of the first CP/M computers.
+//!
+//! The processor has seven 8-bit registers, `A` to `L`, which pair up as `BC`, `DE` and `HL` for
+//! 16-bit operations, a stack pointer and five flags. Instructions take 4 to 18 clock cycles,
+//! which Intel calls states. Between instructions, while interrupts are enabled, a device can
+//! interrupt the program by putting an instruction on the data bus, usually an `RST` calling one
+//! of eight addresses at the bottom of memory.
+//!
+//! A [`Cpu`] runs against a [`Bus`], which is the memory and devices of a machine:
+//!
+//! ```
+//! use i8080::bus::Memory;
+//! use i8080::{Cpu, Register};
+//!
+//! // 0x0000: MVI A, 2A; INR A; HLT
+//! let mut memory = Memory::with_program(0, &[0x3E, 0x2A, 0x3C, 0x76])?;
+//! let mut cpu = Cpu::new();
+//! while !cpu.halted() {
+//!     cpu.step(&mut memory);
+//! }
+//! assert_eq!(cpu.register(Register::A), 0x2B);
+//! assert_eq!(cpu.cycles(), 7 + 5 + 7);
+//! # Ok::<(), i8080::bus::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` and needs no allocator.
+
+#![no_std]
+
+pub mod bus;
+pub mod disasm;
+
+use crate::bus::Bus;
+
+/// Length of the state written by [`Cpu::save`].
+pub const STATE_SIZE: usize = 21;
+
+/// The sign flag, set when bit 7 of a result is.
+pub const SIGN: u8 = 0x80;
+
+/// The zero flag, set when a result is 0.
+pub const ZERO: u8 = 0x40;
+
+/// The auxiliary carry flag, set on a carry out of bit 3, for `DAA`.
+pub const AUX_CARRY: u8 = 0x10;
+
+/// The parity flag, set when a result has an even number of bits set.
+pub const PARITY: u8 = 0x04;
+
+/// The carry flag, set on a carry out of bit 7 or on a borrow.
+pub const CARRY: u8 = 0x01;
+
+/// The bit of the flags that is always set.
+const FLAGS_SET: u8 = 0x02;
+
+/// The flags that exist, with [`FLAGS_SET`].
+const FLAGS_MASK: u8 = SIGN | ZERO | AUX_CARRY | PARITY | CARRY;
+
+/// Where the flags are kept among the registers, in place of `M`, which is memory.
+const F: usize = 6;
+
+/// The accumulator's place among the registers.
+const A: usize = 7;
+
+/// Clock cycles of each instruction, or of a conditional call or return that is not taken.
+const CYCLES: [u8; 256] = [
+    4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4, // 00
+    4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4, // 10
+    4, 10, 16, 5, 5, 5, 7, 4, 4, 10, 16, 5, 5, 5, 7, 4, // 20
+    4, 10, 13, 5, 10, 10, 10, 4, 4, 10, 13, 5, 5, 5, 7, 4, // 30
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5, // 40
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5, // 50
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5, // 60
+    7, 7, 7, 7, 7, 7, 7, 7, 5, 5, 5, 5, 5, 5, 7, 5, // 70
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 80
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 90
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // A0
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // B0
+    5, 10, 10, 10, 11, 11, 7, 11, 5, 10, 10, 10, 11, 17, 7, 11, // C0
+    5, 10, 10, 10, 11, 11, 7, 11, 5, 10, 10, 10, 11, 17, 7, 11, // D0
+    5, 10, 10, 18, 11, 11, 7, 11, 5, 5, 10, 4, 11, 17, 7, 11, // E0
+    5, 10, 10, 4, 11, 11, 7, 11, 5, 5, 10, 4, 11, 17, 7, 11, // F0
+];
+
+/// Clock cycles a conditional call or return takes on top of [`CYCLES`] when it is taken.
+const TAKEN: u8 = 6;
+
+/// An 8-bit register, numbered as instructions number them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    B = 0,
+    C = 1,
+    D = 2,
+    E = 3,
+    H = 4,
+    L = 5,
+    /// The accumulator.
+    A = 7,
+}
+
+/// A pair of registers, or the stack pointer, used as a 16-bit register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pair {
+    BC,
+    DE,
+    HL,
+    SP,
+    /// The accumulator and the flags, as `PUSH PSW` and `POP PSW` move them.
+    PSW,
+}
+
+/// The processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    /// `B`, `C`, `D`, `E`, `H`, `L`, the flags and `A`, in the order instructions number them.
+    registers: [u8; 8],
+    sp: u16,
+    pc: u16,
+    /// Whether interrupts are taken.
+    inte: bool,
+    /// Whether interrupts stay off for one more instruction, after `EI`.
+    ei_delay: bool,
+    /// Whether the processor is waiting in `HLT` for an interrupt.
+    halted: bool,
+    /// Clock cycles run since the processor was created.
+    cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    /// Creates a processor just reset, with every register 0.
+    pub fn new() -> Self {
+        let mut registers = [0; 8];
+        registers[F] = FLAGS_SET;
+        Cpu {
+            registers,
+            sp: 0,
+            pc: 0,
+            inte: false,
+            ei_delay: false,
+            halted: false,
+            cycles: 0,
+        }
+    }
+
+    /// Resets the processor as its `RESET` line does: the program starts over at address 0 with
+    /// interrupts disabled. The other registers keep their values.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.inte = false;
+        self.ei_delay = false;
+        self.halted = false;
+    }
+
+    /// Returns an 8-bit register.
+    pub fn register(&self, register: Register) -> u8 {
+        self.registers[register as usize]
+    }
+
+    /// Sets an 8-bit register.
+    pub fn set_register(&mut self, register: Register, value: u8) {
+        self.registers[register as usize] = value;
+    }
+
+    /// Returns a pair of registers, or the stack pointer.
+    pub fn pair(&self, pair: Pair) -> u16 {
+        let (high, low) = match pair {
+            Pair::BC => (0, 1),
+            Pair::DE => (2, 3),
+            Pair::HL => (4, 5),
+            Pair::SP => return self.sp,
+            Pair::PSW => (A, F),
+        };
+        u16::from_be_bytes([self.registers[high], self.registers[low]])
+    }
+
+    /// Sets a pair of registers, or the stack pointer. Setting `PSW` leaves the flag bits that
+    /// do not exist as they always are.
+    pub fn set_pair(&mut self, pair: Pair, value: u16) {
+        let (high, low) = match pair {
+            Pair::BC => (0, 1),
+            Pair::DE => (2, 3),
+            Pair::HL => (4, 5),
+            Pair::SP => {
+                self.sp = value;
+                return;
+            }
+            Pair::PSW => (A, F),
+        };
+        let [high_byte, low_byte] = value.to_be_bytes();
+        self.registers[high] = high_byte;
+        self.registers[low] = low_byte;
+        self.registers[F] = self.registers[F] & FLAGS_MASK | FLAGS_SET;
+    }
+
+    /// Returns the flags, as [`SIGN`], [`ZERO`], [`AUX_CARRY`], [`PARITY`] and [`CARRY`].
+    pub fn flags(&self) -> u8 {
+        self.registers[F]
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    /// Continues the program at `address`.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    /// Returns whether interrupts are enabled.
+    pub fn inte(&self) -> bool {
+        self.inte
+    }
+
+    /// Returns whether the processor is waiting in `HLT` for an interrupt.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns the clock cycles run since the processor was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs the next instruction, or the one a device interrupts with, and returns the clock
+    /// cycles it took. While halted with no interrupt, 4 clock cycles pass, as for a `NOP`.
+    ///
+    /// Interrupts are only taken once the instruction following `EI` has run, so that a routine
+    /// can end with `EI` and `RET` without being interrupted in between.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        let enabled = self.inte && !self.ei_delay;
+        self.ei_delay = false;
+        let interrupt = if enabled { bus.interrupt() } else { None };
+        let cycles = match interrupt {
+            Some(opcode) => {
+                self.inte = false;
+                self.halted = false;
+                self.execute(opcode, bus)
+            }
+            None if self.halted => 4,
+            None => {
+                let opcode = self.immediate(bus);
+                self.execute(opcode, bus)
+            }
+        };
+        self.cycles += u64::from(cycles);
+        cycles
+    }
+
+    /// Reads the byte at the program counter and moves past it.
+    fn immediate(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    /// Reads the address or 16-bit value at the program counter and moves past it.
+    fn immediate16(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = self.immediate(bus);
+        let high = self.immediate(bus);
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Reads the register numbered `n` from 0 to 7, or memory at `HL` for 6.
+    fn read(&mut self, n: u8, bus: &mut impl Bus) -> u8 {
+        match n as usize {
+            F => bus.read(self.pair(Pair::HL)),
+            r => self.registers[r],
+        }
+    }
+
+    /// Writes the register numbered `n` from 0 to 7, or memory at `HL` for 6.
+    fn write(&mut self, n: u8, value: u8, bus: &mut impl Bus) {
+        match n as usize {
+            F => bus.write(self.pair(Pair::HL), value),
+            r => self.registers[r] = value,
+        }
+    }
+
+    /// Returns the pair numbered `n` from 0 to 3 in `LXI`, `DAD`, `INX` and `DCX`.
+    fn pair_sp(n: u8) -> Pair {
+        [Pair::BC, Pair::DE, Pair::HL, Pair::SP][n as usize & 3]
+    }
+
+    /// Returns the pair numbered `n` from 0 to 3 in `PUSH` and `POP`.
+    fn pair_psw(n: u8) -> Pair {
+        [Pair::BC, Pair::DE, Pair::HL, Pair::PSW][n as usize & 3]
+    }
+
+    fn push(&mut self, value: u16, bus: &mut impl Bus) {
+        let [high, low] = value.to_be_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, high);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, low);
+    }
+
+    fn pop(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
+    }
+
+    fn set_flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.registers[F] |= flag;
+        } else {
+            self.registers[F] &= !flag;
+        }
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.registers[F] & flag != 0
+    }
+
+    /// Sets the sign, zero and parity flags from `result`.
+    fn set_szp(&mut self, result: u8) {
+        self.set_flag(SIGN, result & 0x80 != 0);
+        self.set_flag(ZERO, result == 0);
+        self.set_flag(PARITY, result.count_ones() & 1 == 0);
+    }
+
+    /// Returns the condition numbered `n` from 0 to 7: not zero, zero, no carry, carry, parity
+    /// odd, parity even, plus and minus.
+    fn condition(&self, n: u8) -> bool {
+        let flag = [ZERO, CARRY, PARITY, SIGN][n as usize >> 1 & 3];
+        self.flag(flag) == (n & 1 != 0)
+    }
+
+    /// Executes `opcode`, whose operands follow at the program counter, and returns its clock
+    /// cycles.
+    fn execute(&mut self, opcode: u8, bus: &mut impl Bus) -> u8 {
+        let mut cycles = CYCLES[opcode as usize];
+        let destination = opcode >> 3 & 7;
+        let source = opcode & 7;
+        match opcode {
+            0x76 => self.halted = true,
+            0x40..=0x7F => {
+                let value = self.read(source, bus);
+                self.write(destination, value, bus);
+            }
+            0x80..=0xBF => {
+                let value = self.read(source, bus);
+                self.alu(destination, value);
+            }
+            0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE => {
+                let value = self.immediate(bus);
+                self.alu(destination, value);
+            }
+            _ if opcode & 0xC7 == 0x00 => {}
+            _ if opcode & 0xCF == 0x01 => {
+                let value = self.immediate16(bus);
+                self.set_pair(Self::pair_sp(opcode >> 4), value);
+            }
+            0x02 | 0x12 => bus.write(self.pair(Self::pair_sp(opcode >> 4)), self.registers[A]),
+            0x0A | 0x1A => self.registers[A] = bus.read(self.pair(Self::pair_sp(opcode >> 4))),
+            0x22 => {
+                let address = self.immediate16(bus);
+                bus.write(address, self.registers[5]);
+                bus.write(address.wrapping_add(1), self.registers[4]);
+            }
+            0x2A => {
+                let address = self.immediate16(bus);
+                self.registers[5] = bus.read(address);
+                self.registers[4] = bus.read(address.wrapping_add(1));
+            }
+            0x32 => {
+                let address = self.immediate16(bus);
+                bus.write(address, self.registers[A]);
+            }
+            0x3A => {
+                let address = self.immediate16(bus);
+                self.registers[A] = bus.read(address);
+            }
+            _ if opcode & 0xC7 == 0x03 => {
+                let pair = Self::pair_sp(opcode >> 4);
+                let step = if opcode & 8 == 0 { 1 } else { 0xFFFF };
+                self.set_pair(pair, self.pair(pair).wrapping_add(step));
+            }
+            _ if opcode & 0xC7 == 0x04 => {
+                let result = self.read(destination, bus).wrapping_add(1);
+                self.write(destination, result, bus);
+                self.set_szp(result);
+                self.set_flag(AUX_CARRY, result & 0xF == 0);
+            }
+            _ if opcode & 0xC7 == 0x05 => {
+                let result = self.read(destination, bus).wrapping_sub(1);
+                self.write(destination, result, bus);
+                self.set_szp(result);
+                self.set_flag(AUX_CARRY, result & 0xF != 0xF);
+            }
+            _ if opcode & 0xC7 == 0x06 => {
+                let value = self.immediate(bus);
+                self.write(destination, value, bus);
+            }
+            _ if opcode & 0xCF == 0x09 => {
+                let sum = u32::from(self.pair(Pair::HL))
+                    + u32::from(self.pair(Self::pair_sp(opcode >> 4)));
+                self.set_pair(Pair::HL, sum as u16);
+                self.set_flag(CARRY, sum > 0xFFFF);
+            }
+            0x07 | 0x0F | 0x17 | 0x1F => self.rotate(opcode >> 3),
+            0x27 => self.daa(),
+            0x2F => self.registers[A] = !self.registers[A],
+            0x37 => self.set_flag(CARRY, true),
+            0x3F => self.set_flag(CARRY, !self.flag(CARRY)),
+            _ if opcode & 0xC7 == 0xC0 => {
+                if self.condition(destination) {
+                    self.pc = self.pop(bus);
+                    cycles += TAKEN;
+                }
+            }
+            _ if opcode & 0xCF == 0xC1 => {
+                let value = self.pop(bus);
+                self.set_pair(Self::pair_psw(opcode >> 4), value);
+            }
+            _ if opcode & 0xC7 == 0xC2 => {
+                let address = self.immediate16(bus);
+                if self.condition(destination) {
+                    self.pc = address;
+                }
+            }
+            0xC3 | 0xCB => self.pc = self.immediate16(bus),
+            _ if opcode & 0xC7 == 0xC4 => {
+                let address = self.immediate16(bus);
+                if self.condition(destination) {
+                    self.push(self.pc, bus);
+                    self.pc = address;
+                    cycles += TAKEN;
+                }
+            }
+            _ if opcode & 0xCF == 0xC5 => {
+                self.push(self.pair(Self::pair_psw(opcode >> 4)), bus);
+            }
+            _ if opcode & 0xC7 == 0xC7 => {
+                self.push(self.pc, bus);
+                self.pc = u16::from(opcode & 0x38);
+            }
+            0xC9 | 0xD9 => self.pc = self.pop(bus),
+            0xCD | 0xDD | 0xED | 0xFD => {
+                let address = self.immediate16(bus);
+                self.push(self.pc, bus);
+                self.pc = address;
+            }
+            0xD3 => {
+                let port = self.immediate(bus);
+                bus.output(port, self.registers[A]);
+            }
+            0xDB => {
+                let port = self.immediate(bus);
+                self.registers[A] = bus.input(port);
+            }
+            0xE3 => {
+                let value = self.pop(bus);
+                self.push(self.pair(Pair::HL), bus);
+                self.set_pair(Pair::HL, value);
+            }
+            0xE9 => self.pc = self.pair(Pair::HL),
+            0xEB => {
+                let (de, hl) = (self.pair(Pair::DE), self.pair(Pair::HL));
+                self.set_pair(Pair::DE, hl);
+                self.set_pair(Pair::HL, de);
+            }
+            0xF3 => self.inte = false,
+            0xF9 => self.sp = self.pair(Pair::HL),
+            0xFB => {
+                self.inte = true;
+                self.ei_delay = true;
+            }
+            _ => unreachable!("every opcode is decoded"),
+        }
+        cycles
+    }
+
+    /// Adds `a`, `b` and `carry`, setting every flag, and returns the sum.
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let sum = u16::from(a) + u16::from(b) + u16::from(carry);
+        let result = sum as u8;
+        self.set_szp(result);
+        self.set_flag(AUX_CARRY, (a & 0xF) + (b & 0xF) + carry as u8 > 0xF);
+        self.set_flag(CARRY, sum > 0xFF);
+        result
+    }
+
+    /// Subtracts `b` and `borrow` from `a`, setting every flag, and returns the difference. The
+    /// 8080 adds the complement, so the auxiliary carry is the carry of that addition, while the
+    /// carry is the borrow.
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) -> u8 {
+        let result = self.add(a, !b, !borrow);
+        self.set_flag(CARRY, !self.flag(CARRY));
+        result
+    }
+
+    /// Runs the arithmetic or logic operation numbered `n` from 0 to 7 on the accumulator and
+    /// `value`: `ADD`, `ADC`, `SUB`, `SBB`, `ANA`, `XRA`, `ORA` and `CMP`.
+    fn alu(&mut self, n: u8, value: u8) {
+        let a = self.registers[A];
+        let carry = self.flag(CARRY);
+        self.registers[A] = match n {
+            0 => self.add(a, value, false),
+            1 => self.add(a, value, carry),
+            2 => self.subtract(a, value, false),
+            3 => self.subtract(a, value, carry),
+            7 => {
+                self.subtract(a, value, false);
+                a
+            }
+            _ => {
+                let result = match n {
+                    4 => a & value,
+                    5 => a ^ value,
+                    _ => a | value,
+                };
+                self.set_szp(result);
+                // AND sets the auxiliary carry to bit 3 of either operand, a quirk of the 8080.
+                self.set_flag(AUX_CARRY, n == 4 && (a | value) & 0x08 != 0);
+                self.set_flag(CARRY, false);
+                result
+            }
+        };
+    }
+
+    /// Runs the rotation numbered `n` from 0 to 3: `RLC`, `RRC`, `RAL` and `RAR`.
+    fn rotate(&mut self, n: u8) {
+        let a = self.registers[A];
+        let carry = self.flag(CARRY) as u8;
+        let (result, out) = match n {
+            0 => (a.rotate_left(1), a & 0x80 != 0),
+            1 => (a.rotate_right(1), a & 1 != 0),
+            2 => (a << 1 | carry, a & 0x80 != 0),
+            _ => (a >> 1 | carry << 7, a & 1 != 0),
+        };
+        self.registers[A] = result;
+        self.set_flag(CARRY, out);
+    }
+
+    /// Adjusts the accumulator to two decimal digits after adding two of them.
+    fn daa(&mut self) {
+        let a = self.registers[A];
+        let mut correction = 0;
+        let mut carry = self.flag(CARRY);
+        if self.flag(AUX_CARRY) || a & 0xF > 9 {
+            correction |= 0x06;
+        }
+        if carry || a >> 4 > 9 || (a >> 4 == 9 && a & 0xF > 9) {
+            correction |= 0x60;
+            carry = true;
+        }
+        self.registers[A] = self.add(a, correction, false);
+        self.set_flag(CARRY, carry);
+    }
+
+    /// Writes the whole processor, for save states.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..8].copy_from_slice(&self.registers);
+        state[8..10].copy_from_slice(&self.sp.to_be_bytes());
+        state[10..12].copy_from_slice(&self.pc.to_be_bytes());
+        state[12] = self.inte as u8 | (self.ei_delay as u8) << 1 | (self.halted as u8) << 2;
+        state[13..].copy_from_slice(&self.cycles.to_be_bytes());
+        state
+    }
+
+    /// Restores the processor from a state written by [`Cpu::save`].
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        self.registers.copy_from_slice(&state[..8]);
+        self.registers[F] = self.registers[F] & FLAGS_MASK | FLAGS_SET;
+        self.sp = u16::from_be_bytes([state[8], state[9]]);
+        self.pc = u16::from_be_bytes([state[10], state[11]]);
+        let flags = state[12];
+        self.inte = flags & 1 != 0;
+        self.ei_delay = flags & 2 != 0;
+        self.halted = flags & 4 != 0;
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&state[13..]);
+        self.cycles = u64::from_be_bytes(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Memory;
+
+    /// Memory, with devices driven by the test.
+    struct Machine {
+        memory: Memory,
+        inputs: [u8; 256],
+        outputs: [Option<u8>; 256],
+        interrupt: Option<u8>,
+    }
+
+    impl Machine {
+        fn new(program: &[u8]) -> Self {
+            Machine {
+                memory: Memory::with_program(0, program).unwrap(),
+                inputs: [0; 256],
+                outputs: [None; 256],
+                interrupt: None,
+            }
+        }
+    }
+
+    impl Bus for Machine {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory.write(address, value);
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            self.inputs[port as usize]
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs[port as usize] = Some(value);
+        }
+
+        fn interrupt(&mut self) -> Option<u8> {
+            self.interrupt.take()
+        }
+    }
+
+    /// Runs the processor until it halts, and returns it.
+    fn run(machine: &mut Machine) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::SP, 0x8000);
+        while !cpu.halted() {
+            cpu.step(machine);
+            assert!(cpu.cycles() < 1_000_000, "the program never halts");
+        }
+        cpu
+    }
+
+    /// Runs `opcode` with the accumulator `a`, the operand `value` in `B` and the carry `carry`,
+    /// and returns the accumulator and the flags.
+    fn alu(opcode: u8, a: u8, value: u8, carry: bool) -> (u8, u8) {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::with_program(0, &[opcode]).unwrap();
+        cpu.set_register(Register::A, a);
+        cpu.set_register(Register::B, value);
+        cpu.set_pair(Pair::PSW, u16::from(a) << 8 | u16::from(carry));
+        cpu.step(&mut memory);
+        (cpu.register(Register::A), cpu.flags())
+    }
+
+    /// Returns the flags a result sets, other than the carries.
+    fn szp(result: u8) -> u8 {
+        let mut flags = FLAGS_SET;
+        if result & 0x80 != 0 {
+            flags |= SIGN;
+        }
+        if result == 0 {
+            flags |= ZERO;
+        }
+        if result.count_ones() & 1 == 0 {
+            flags |= PARITY;
+        }
+        flags
+    }
+
+    #[test]
+    fn arithmetic() {
+        for a in 0..=255u8 {
+            for value in 0..=255u8 {
+                for &carry in &[false, true] {
+                    // ADC B and SBB B.
+                    let sum = u16::from(a) + u16::from(value) + u16::from(carry);
+                    let mut flags = szp(sum as u8);
+                    if sum > 0xFF {
+                        flags |= CARRY;
+                    }
+                    if (a ^ value ^ sum as u8) & 0x10 != 0 {
+                        flags |= AUX_CARRY;
+                    }
+                    assert_eq!(alu(0x88, a, value, carry), (sum as u8, flags));
+
+                    let difference = i16::from(a) - i16::from(value) - i16::from(carry);
+                    let mut flags = szp(difference as u8);
+                    if difference < 0 {
+                        flags |= CARRY;
+                    }
+                    // The carry out of bit 3 of A plus the complement of the operand.
+                    if (a ^ value ^ difference as u8) & 0x10 == 0 {
+                        flags |= AUX_CARRY;
+                    }
+                    assert_eq!(alu(0x98, a, value, carry), (difference as u8, flags));
+                    if !carry {
+                        // CMP B only sets the flags of SUB B.
+                        assert_eq!(alu(0xB8, a, value, carry), (a, flags));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decimal() {
+        // MVI A, tens and units; ADI, another; DAA; HLT
+        for x in 0..100u8 {
+            for y in 0..100u8 {
+                let bcd = |n: u8| ((n / 10) << 4) | (n % 10);
+                let mut machine = Machine::new(&[0x3E, bcd(x), 0xC6, bcd(y), 0x27, 0x76]);
+                let cpu = run(&mut machine);
+                let sum = x + y;
+                assert_eq!(cpu.register(Register::A), bcd(sum % 100), "{} + {}", x, y);
+                assert_eq!(cpu.flags() & CARRY != 0, sum >= 100, "{} + {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn logic_and_rotations() {
+        // ANA sets the auxiliary carry from bit 3 of either operand; XRA and ORA clear it.
+        assert_eq!(alu(0xA0, 0x08, 0x00, true), (0x00, szp(0) | AUX_CARRY));
+        assert_eq!(alu(0xA0, 0xF0, 0x0F, false), (0x00, szp(0) | AUX_CARRY));
+        assert_eq!(alu(0xA0, 0xF0, 0xF0, false), (0xF0, szp(0xF0)));
+        assert_eq!(alu(0xA8, 0xFF, 0x0F, true), (0xF0, szp(0xF0)));
+        assert_eq!(alu(0xB0, 0x01, 0x02, true), (0x03, szp(0x03)));
+
+        // RLC, RRC, RAL and RAR only change the carry.
+        assert_eq!(alu(0x07, 0x81, 0, false), (0x03, FLAGS_SET | CARRY));
+        assert_eq!(alu(0x0F, 0x01, 0, false), (0x80, FLAGS_SET | CARRY));
+        assert_eq!(alu(0x17, 0x40, 0, true), (0x81, FLAGS_SET));
+        assert_eq!(alu(0x1F, 0x01, 0, false), (0x00, FLAGS_SET | CARRY));
+        // CMA, STC and CMC.
+        assert_eq!(alu(0x2F, 0x55, 0, false), (0xAA, FLAGS_SET));
+        assert_eq!(alu(0x37, 0, 0, false), (0, FLAGS_SET | CARRY));
+        assert_eq!(alu(0x3F, 0, 0, true), (0, FLAGS_SET));
+    }
+
+    #[test]
+    fn increments() {
+        // MVI B, 0F; INR B; MVI C, 00; DCR C; LXI H, FFFF; INX H; DAD SP; HLT
+        let mut machine = Machine::new(&[
+            0x06, 0x0F, 0x04, 0x0E, 0x00, 0x0D, 0x21, 0xFF, 0xFF, 0x23, 0x39, 0x76,
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.register(Register::B), 0x10);
+        assert_eq!(cpu.register(Register::C), 0xFF);
+        // DCR left the flags, with the carry untouched, and DAD set the carry only.
+        assert_eq!(cpu.flags(), szp(0xFF));
+        assert_eq!(cpu.pair(Pair::HL), 0x8000);
+
+        // INR M, with the carry kept.
+        let mut machine = Machine::new(&[0x37, 0x21, 0x00, 0x10, 0x34, 0x76]);
+        machine.memory.0[0x1000] = 0xFF;
+        let cpu = run(&mut machine);
+        assert_eq!(machine.memory.0[0x1000], 0x00);
+        assert_eq!(cpu.flags(), szp(0) | AUX_CARRY | CARRY);
+    }
+
+    #[test]
+    fn memory_and_registers() {
+        let mut machine = Machine::new(&[
+            0x21, 0x34, 0x12, // LXI H, 1234
+            0x22, 0x00, 0x20, // SHLD 2000
+            0x2A, 0x00, 0x20, // LHLD 2000
+            0x11, 0x78, 0x56, // LXI D, 5678
+            0xEB, // XCHG
+            0x3E, 0x9A, // MVI A, 9A
+            0x32, 0x02, 0x20, // STA 2002
+            0x01, 0x02, 0x20, // LXI B, 2002
+            0x0A, // LDAX B
+            0x47, // MOV B, A
+            0x36, 0x77, // MVI M, 77
+            0x4E, // MOV C, M
+            0xE3, // XTHL
+            0x76, // HLT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(machine.memory.0[0x2000..0x2003], [0x34, 0x12, 0x9A]);
+        assert_eq!(cpu.pair(Pair::DE), 0x1234);
+        assert_eq!(machine.memory.0[0x5678], 0x77);
+        assert_eq!(cpu.pair(Pair::BC), 0x9A77);
+        // XTHL swapped HL with the empty top of the stack.
+        assert_eq!(cpu.pair(Pair::HL), 0x0000);
+        assert_eq!(machine.memory.0[0x8000..0x8002], [0x78, 0x56]);
+    }
+
+    #[test]
+    fn stack_and_flags() {
+        // LXI B, FFFF; PUSH B; POP PSW; PUSH PSW; POP D; HLT
+        let mut machine = Machine::new(&[0x01, 0xFF, 0xFF, 0xC5, 0xF1, 0xF5, 0xD1, 0x76]);
+        let cpu = run(&mut machine);
+        // Bits 1, 3 and 5 of the flags are 1, 0 and 0 whatever is popped.
+        assert_eq!(cpu.pair(Pair::PSW), 0xFFD7);
+        assert_eq!(cpu.pair(Pair::DE), 0xFFD7);
+        assert_eq!(cpu.pair(Pair::SP), 0x8000);
+    }
+
+    #[test]
+    fn calls_and_jumps() {
+        let mut machine = Machine::new(&[
+            0xCD, 0x10, 0x00, // 0x00: CALL 0010
+            0xAF, // XRA A, setting zero
+            0xC4, 0x10, 0x00, // CNZ 0010, not taken
+            0xCC, 0x18, 0x00, // CZ 0018
+            0xCA, 0x00, 0x00, // JZ 0000, not taken
+            0x76, // 0x0D: HLT
+            0, 0,    //
+            0x0C, // 0x10: INR C
+            0xC9, // RET
+            0, 0, 0, 0, 0, 0,    //
+            0x0C, // 0x18: INR C, clearing zero
+            0xC8, // RZ, not taken
+            0x21, 0x00, 0x01, // LXI H, 0100
+            0xE9, // PCHL
+        ]);
+        // 0x100: MVI D, 1; RNZ
+        machine.memory.0[0x100..0x103].copy_from_slice(&[0x16, 0x01, 0xC0]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.register(Register::C), 2);
+        assert_eq!(cpu.register(Register::D), 1);
+        assert_eq!(cpu.program_counter(), 0x0E);
+        assert_eq!(cpu.pair(Pair::SP), 0x8000);
+        // CALL, INR, RET; XRA; CNZ; CZ, INR, RZ, LXI, PCHL, MVI, RNZ; JZ; HLT
+        let cycles = 17 + 5 + 10 + 4 + 11 + 17 + 5 + 5 + 10 + 5 + 7 + 11 + 10 + 7;
+        assert_eq!(cpu.cycles(), cycles);
+    }
+
+    #[test]
+    fn input_and_output() {
+        // IN 07; OUT FE; RST 1; 0x08: HLT
+        let mut machine = Machine::new(&[0xDB, 0x07, 0xD3, 0xFE, 0xCF, 0, 0, 0, 0x76]);
+        machine.inputs[7] = 0x5A;
+        let cpu = run(&mut machine);
+        assert_eq!(machine.outputs[0xFE], Some(0x5A));
+        assert_eq!(machine.outputs[0x07], None);
+        assert_eq!(cpu.program_counter(), 0x09);
+        assert_eq!(machine.memory.0[0x7FFE..0x8000], [0x05, 0x00]);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut machine = Machine::new(&[
+            0x3C, // 0x00: INR A
+            0xFB, // EI
+            0x04, // INR B, before which no interrupt is taken
+            0x76, // HLT
+            0x76, // HLT, with interrupts off
+            0, 0, 0,    //
+            0x0C, // 0x08: INR C
+            0xFB, // EI, and later DI
+            0xC9, // RET
+        ]);
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::SP, 0x8000);
+        // Not taken while interrupts are off.
+        machine.interrupt = Some(0xCF);
+        cpu.step(&mut machine);
+        assert_eq!(machine.interrupt, Some(0xCF));
+        cpu.step(&mut machine);
+        assert!(cpu.inte());
+        cpu.step(&mut machine);
+        assert_eq!(cpu.register(Register::B), 1);
+        // RST 1, taken after the instruction following EI.
+        assert_eq!(cpu.step(&mut machine), 11);
+        assert_eq!(machine.interrupt, None);
+        assert!(!cpu.inte());
+        assert_eq!(cpu.program_counter(), 0x08);
+        while cpu.program_counter() != 0x03 {
+            cpu.step(&mut machine);
+        }
+        assert_eq!(cpu.register(Register::C), 1);
+        // HLT waits for an interrupt, which continues after it, this time returning with DI.
+        cpu.step(&mut machine);
+        assert!(cpu.halted());
+        assert_eq!(cpu.step(&mut machine), 4);
+        assert!(cpu.halted());
+        machine.memory.0[0x09] = 0xF3;
+        machine.interrupt = Some(0xCF);
+        cpu.step(&mut machine);
+        assert!(!cpu.halted());
+        while cpu.program_counter() != 0x04 {
+            cpu.step(&mut machine);
+        }
+        assert_eq!(cpu.register(Register::C), 2);
+        // The second HLT, with interrupts disabled, waits for good.
+        cpu.step(&mut machine);
+        assert!(cpu.halted());
+        machine.interrupt = Some(0xCF);
+        cpu.step(&mut machine);
+        assert!(cpu.halted());
+        assert_eq!(machine.interrupt, Some(0xCF));
+    }
+
+    #[test]
+    fn resets_and_saves() {
+        // MVI A, 2A; LXI SP, 1234; EI; HLT
+        let mut machine = Machine::new(&[0x3E, 0x2A, 0x31, 0x34, 0x12, 0xFB, 0x76]);
+        let cpu = run(&mut machine);
+        let state = cpu.save();
+        let mut restored = Cpu::new();
+        restored.restore(&state);
+        assert_eq!(restored, cpu);
+        assert_eq!(restored.save(), state);
+
+        restored.reset();
+        assert_eq!(restored.program_counter(), 0);
+        assert!(!restored.inte());
+        assert!(!restored.halted());
+        assert_eq!(restored.register(Register::A), 0x2A);
+        assert_eq!(restored.pair(Pair::SP), 0x1234);
+    }
+}
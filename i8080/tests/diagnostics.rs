@@ -0,0 +1,49 @@
+//! Runs the CP/M diagnostic programs written for the 8080, such as Supersoft's CPUTEST,
+//! Microcosm's CPUDIAG and the 8080EXM instruction exerciser, which check every instruction and
+//! flag against the results of a real processor.
+//!
+//! The programs are not part of this repository, so the test is ignored unless asked for, and then
+//! needs a directory holding them as `.COM` files. The exerciser runs for billions of instructions,
+//! so build the test with optimizations:
+//!
+//! ```text
+//! I8080_DIAGNOSTICS=roms/8080 cargo test -p i8080 --release --test diagnostics -- --ignored --nocapture
+//! ```
+
+use cpm::Machine;
+use i8080::Cpu;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The directory of the programs to run.
+const DIAGNOSTICS: &str = "I8080_DIAGNOSTICS";
+
+#[test]
+#[ignore = "needs $I8080_DIAGNOSTICS"]
+fn diagnostics() {
+    let dir = env::var_os(DIAGNOSTICS).unwrap_or_else(|| panic!("{} is not set", DIAGNOSTICS));
+    let mut programs: Vec<_> = fs::read_dir(Path::new(&dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("com"))
+        })
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "no .COM files");
+
+    let mut failures = Vec::new();
+    for path in &programs {
+        let output = Machine::<Cpu>::new(&fs::read(path).unwrap())
+            .and_then(|mut machine| machine.run())
+            .unwrap_or_else(|error| format!("ERROR: {}", error));
+        println!("{}:\n{}", path.display(), output);
+        let passed = output.contains("OK") || output.contains("CPU IS OPERATIONAL");
+        if output.contains("ERROR") || output.contains("FAIL") || !passed {
+            failures.push(path.display().to_string());
+        }
+    }
+    assert!(failures.is_empty(), "failed: {}", failures.join(", "));
+}
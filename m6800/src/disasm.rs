@@ -0,0 +1,127 @@
+//! Disassembling instructions into Motorola's syntax, such as `LDAA #$2A`, `STX $10,X` and
+//! `JSR $C000`, for debuggers and traces. Undefined opcodes show as the bytes they are, with
+//! `FCB`.
+//!
+//! Branches show the address they go to, so an instruction is decoded at its address:
+//!
+//! ```
+//! use m6800::disasm::Instruction;
+//!
+//! let instruction = Instruction::decode(0xC000, &[0x26, 0xFE]).unwrap();
+//! assert_eq!(instruction.to_string(), "BNE $C000");
+//! assert_eq!(instruction.len(), 2);
+//! ```
+
+use crate::opcode::{self, Mode};
+use core::fmt;
+
+/// An instruction with its operand, at its address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    /// The byte or the 16-bit value following the opcode.
+    pub operand: u16,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, which are at `address`, or returns `None`
+    /// if they end before it does.
+    pub fn decode(address: u16, bytes: &[u8]) -> Option<Instruction> {
+        let opcode = *bytes.first()?;
+        let operand = match opcode::mode(opcode).len() {
+            1 => 0,
+            2 => u16::from(*bytes.get(1)?),
+            _ => u16::from_be_bytes([*bytes.get(1)?, *bytes.get(2)?]),
+        };
+        Some(Instruction {
+            address,
+            opcode,
+            operand,
+        })
+    }
+
+    /// Returns the length of the instruction in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.mode().len()
+    }
+
+    /// Returns the mnemonic of the instruction, such as `LDAA`, or `FCB` if it is undefined.
+    pub fn mnemonic(&self) -> &'static str {
+        opcode::decode(self.opcode).map_or("FCB", |(mnemonic, _)| mnemonic)
+    }
+
+    /// Returns how the instruction addresses its operand.
+    pub fn mode(&self) -> Mode {
+        opcode::mode(self.opcode)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+        let operand = self.operand;
+        match self.mode() {
+            Mode::Inh if opcode::decode(self.opcode).is_none() => {
+                write!(f, "{} ${:02X}", mnemonic, self.opcode)
+            }
+            Mode::Inh => f.write_str(mnemonic),
+            Mode::Imm => write!(f, "{} #${:02X}", mnemonic, operand),
+            Mode::Imm16 => write!(f, "{} #${:04X}", mnemonic, operand),
+            Mode::Dir => write!(f, "{} ${:02X}", mnemonic, operand),
+            Mode::Idx => write!(f, "{} ${:02X},X", mnemonic, operand),
+            Mode::Ext => write!(f, "{} ${:04X}", mnemonic, operand),
+            Mode::Rel => {
+                let next = self.address.wrapping_add(2);
+                let target = next.wrapping_add(operand as u8 as i8 as u16);
+                write!(f, "{} ${:04X}", mnemonic, target)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    fn disassemble(bytes: &[u8]) -> std::string::String {
+        Instruction::decode(0x8000, bytes).unwrap().to_string()
+    }
+
+    #[test]
+    fn disassembles() {
+        assert_eq!(disassemble(&[0x01]), "NOP");
+        assert_eq!(disassemble(&[0x4F]), "CLRA");
+        assert_eq!(disassemble(&[0x86, 0x2A]), "LDAA #$2A");
+        assert_eq!(disassemble(&[0xCE, 0x12, 0x34]), "LDX #$1234");
+        assert_eq!(disassemble(&[0xD6, 0x10]), "LDAB $10");
+        assert_eq!(disassemble(&[0xEF, 0x10]), "STX $10,X");
+        assert_eq!(disassemble(&[0x7C, 0x02, 0x00]), "INC $0200");
+        assert_eq!(disassemble(&[0xBD, 0xC0, 0x00]), "JSR $C000");
+        assert_eq!(disassemble(&[0x6E, 0x00]), "JMP $00,X");
+        assert_eq!(disassemble(&[0x26, 0x10]), "BNE $8012");
+        assert_eq!(disassemble(&[0x8D, 0xFC]), "BSR $7FFE");
+        assert_eq!(disassemble(&[0x87, 0x10]), "FCB $87");
+        assert_eq!(Instruction::decode(0, &[0x87]).unwrap().len(), 1);
+        assert_eq!(Instruction::decode(0, &[0xBD, 0x01]), None);
+        assert_eq!(Instruction::decode(0, &[]), None);
+    }
+
+    #[test]
+    fn lengths() {
+        let lengths: usize = (0..=255u8).map(|opcode| opcode::mode(opcode).len()).sum();
+        // 110 of one byte, the 51 inherent and the 59 undefined, and 43 of three, the 40 extended
+        // and the immediate CPX, LDS and LDX.
+        assert_eq!(lengths, 110 + 103 * 2 + 43 * 3);
+        assert_eq!(
+            (0..=255)
+                .filter(|&opcode| opcode::decode(opcode).is_some())
+                .count(),
+            197
+        );
+    }
+}
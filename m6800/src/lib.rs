@@ -0,0 +1,1064 @@
+//! The Motorola 6800 microprocessor, the processor of the DREAM 6800, the SWTPC 6800 and the
+//! Altair 680, and of the early arcade machines and pinball tables that followed them.
+//!
+//! The processor has two accumulators, `A` and `B`, a 16-bit index register `X`, a stack pointer
+//! anywhere in memory and six flags. Instructions take 2 to 12 clock cycles, and reach memory by
+//! an address in the first page, one of 16 bits, or `X` plus an offset. Devices interrupt the
+//! program through two lines, NMI and IRQ, which are taken between instructions, and `WAI` stacks
+//! the registers ahead of an interrupt and waits for it.
+//!
+//! The opcodes Motorola left undefined, some of which hang a real 6800, run as `NOP`s of one byte.
+//!
+//! A [`Cpu`] runs against a [`Bus`], which is the memory and devices of a machine:
+//!
+//! ```
+//! use m6800::bus::Memory;
+//! use m6800::{Cpu, Register};
+//!
+//! // 0x0200: LDAA #$2A; ADDA #$01; WAI
+//! let mut memory = Memory::with_program(0x0200, &[0x86, 0x2A, 0x8B, 0x01, 0x3E])?;
+//! let mut cpu = Cpu::new();
+//! cpu.set_program_counter(0x0200);
+//! cpu.set_stack_pointer(0x01FF);
+//! while !cpu.waiting() {
+//!     cpu.step(&mut memory);
+//! }
+//! assert_eq!(cpu.register(Register::A), 0x2B);
+//! assert_eq!(cpu.cycles(), 2 + 2 + 9);
+//! # Ok::<(), m6800::bus::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` and needs no allocator.
+
+#![no_std]
+
+pub mod bus;
+pub mod disasm;
+pub mod opcode;
+
+use crate::bus::Bus;
+
+/// Length of the state written by [`Cpu::save`].
+pub const STATE_SIZE: usize = 18;
+
+/// The half carry flag, set on a carry out of bit 3 by additions, for `DAA`.
+pub const HALF_CARRY: u8 = 0x20;
+
+/// The interrupt mask, which masks IRQ but not NMI.
+pub const INTERRUPT: u8 = 0x10;
+
+/// The negative flag, set when bit 7 of a result is.
+pub const NEGATIVE: u8 = 0x08;
+
+/// The zero flag, set when a result is 0.
+pub const ZERO: u8 = 0x04;
+
+/// The overflow flag, set when a signed result is out of range.
+pub const OVERFLOW: u8 = 0x02;
+
+/// The carry flag, set on a carry out of bit 7, or when a subtraction borrows.
+pub const CARRY: u8 = 0x01;
+
+/// The bits of the flags that are always set.
+const FLAGS_SET: u8 = 0xC0;
+
+/// Where the address of the IRQ handler is.
+pub const IRQ_VECTOR: u16 = 0xFFF8;
+
+/// Where the address of the `SWI` handler is.
+pub const SWI_VECTOR: u16 = 0xFFFA;
+
+/// Where the address of the NMI handler is.
+pub const NMI_VECTOR: u16 = 0xFFFC;
+
+/// Where the address the program starts at is.
+pub const RESET_VECTOR: u16 = 0xFFFE;
+
+/// Clock cycles of taking an interrupt, stacking the registers and fetching the vector.
+const INTERRUPT_CYCLES: u8 = 12;
+
+/// Clock cycles of taking an interrupt in `WAI`, which has stacked the registers already.
+const WAKE_CYCLES: u8 = 4;
+
+/// An accumulator, as [`Cpu::register`] names it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+}
+
+/// The processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    a: u8,
+    b: u8,
+    x: u16,
+    sp: u16,
+    pc: u16,
+    /// The flags, with [`FLAGS_SET`].
+    cc: u8,
+    /// Whether `WAI` has stacked the registers and waits for an interrupt.
+    waiting: bool,
+    /// Whether the NMI line was pulled before the last instruction.
+    nmi_line: bool,
+    /// Whether the NMI line has been pulled since the last NMI was taken.
+    nmi_edge: bool,
+    /// Clock cycles run since the processor was created.
+    cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    /// Creates a processor as it is powered on, with interrupts masked. It starts the program once
+    /// [`reset`](Cpu::reset).
+    pub fn new() -> Self {
+        Cpu {
+            a: 0,
+            b: 0,
+            x: 0,
+            sp: 0,
+            pc: 0,
+            cc: FLAGS_SET | INTERRUPT,
+            waiting: false,
+            nmi_line: false,
+            nmi_edge: false,
+            cycles: 0,
+        }
+    }
+
+    /// Resets the processor, as pulling its RESET line does: it masks interrupts and jumps to the
+    /// address at [`RESET_VECTOR`]. The other registers are kept.
+    pub fn reset(&mut self, bus: &mut impl Bus) {
+        self.waiting = false;
+        self.nmi_edge = false;
+        self.cc |= INTERRUPT;
+        self.pc = self.read16(bus, RESET_VECTOR);
+    }
+
+    /// Returns the accumulator `register`.
+    pub fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+        }
+    }
+
+    /// Sets the accumulator `register` to `value`.
+    pub fn set_register(&mut self, register: Register, value: u8) {
+        match register {
+            Register::A => self.a = value,
+            Register::B => self.b = value,
+        }
+    }
+
+    /// Returns the index register `X`.
+    pub fn index(&self) -> u16 {
+        self.x
+    }
+
+    /// Sets the index register `X`.
+    pub fn set_index(&mut self, value: u16) {
+        self.x = value;
+    }
+
+    /// Returns the stack pointer, the address the next byte pushed goes to.
+    pub fn stack_pointer(&self) -> u16 {
+        self.sp
+    }
+
+    /// Sets the stack pointer.
+    pub fn set_stack_pointer(&mut self, value: u16) {
+        self.sp = value;
+    }
+
+    /// Returns the flags, as `TPA` would, with the two top bits set.
+    pub fn flags(&self) -> u8 {
+        self.cc
+    }
+
+    /// Sets the flags, as `TAP` does.
+    pub fn set_flags(&mut self, flags: u8) {
+        self.cc = flags | FLAGS_SET;
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    /// Jumps to `address`.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    /// Returns whether `WAI` is waiting for an interrupt.
+    pub fn waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Returns the number of clock cycles run since the processor was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs the next instruction, or takes an interrupt, and returns how many clock cycles it
+    /// took. While `WAI` waits, a step is a clock cycle.
+    ///
+    /// NMI is taken before IRQ, which is only taken while the interrupt mask is clear.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        let nmi = bus.nmi();
+        self.nmi_edge |= nmi && !self.nmi_line;
+        self.nmi_line = nmi;
+        let cycles = if self.nmi_edge || self.cc & INTERRUPT == 0 && bus.irq() {
+            let vector = if self.nmi_edge {
+                self.nmi_edge = false;
+                NMI_VECTOR
+            } else {
+                IRQ_VECTOR
+            };
+            let cycles = if self.waiting {
+                WAKE_CYCLES
+            } else {
+                self.push_registers(bus);
+                INTERRUPT_CYCLES
+            };
+            self.waiting = false;
+            self.cc |= INTERRUPT;
+            self.pc = self.read16(bus, vector);
+            cycles
+        } else if self.waiting {
+            1
+        } else {
+            let opcode = self.fetch(bus);
+            if opcode::decode(opcode).is_some() {
+                self.execute(opcode, bus);
+            }
+            opcode::cycles(opcode)
+        };
+        self.cycles += u64::from(cycles);
+        cycles
+    }
+
+    fn read16(&mut self, bus: &mut impl Bus, address: u16) -> u16 {
+        u16::from_be_bytes([bus.read(address), bus.read(address.wrapping_add(1))])
+    }
+
+    fn write16(&mut self, bus: &mut impl Bus, address: u16, value: u16) {
+        let [high, low] = value.to_be_bytes();
+        bus.write(address, high);
+        bus.write(address.wrapping_add(1), low);
+    }
+
+    /// Reads the byte at the program counter, and moves past it.
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16(&mut self, bus: &mut impl Bus) -> u16 {
+        let high = self.fetch(bus);
+        u16::from_be_bytes([high, self.fetch(bus)])
+    }
+
+    fn push(&mut self, bus: &mut impl Bus, value: u8) {
+        bus.write(self.sp, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    fn pull(&mut self, bus: &mut impl Bus) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        bus.read(self.sp)
+    }
+
+    /// Pushes `value` low byte first, so that it is in memory high byte first.
+    fn push16(&mut self, bus: &mut impl Bus, value: u16) {
+        self.push(bus, value as u8);
+        self.push(bus, (value >> 8) as u8);
+    }
+
+    fn pull16(&mut self, bus: &mut impl Bus) -> u16 {
+        let high = self.pull(bus);
+        u16::from_be_bytes([high, self.pull(bus)])
+    }
+
+    /// Pushes every register, as interrupts, `SWI` and `WAI` do: the program counter, `X`, `A`,
+    /// `B` and the flags.
+    fn push_registers(&mut self, bus: &mut impl Bus) {
+        self.push16(bus, self.pc);
+        self.push16(bus, self.x);
+        self.push(bus, self.a);
+        self.push(bus, self.b);
+        self.push(bus, self.cc);
+    }
+
+    fn set_flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.cc |= flag;
+        } else {
+            self.cc &= !flag;
+        }
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.cc & flag != 0
+    }
+
+    /// Sets the negative and zero flags from `value`.
+    fn set_nz(&mut self, value: u8) {
+        self.set_flag(NEGATIVE, value & 0x80 != 0);
+        self.set_flag(ZERO, value == 0);
+    }
+
+    /// Sets the negative and zero flags from the 16-bit `value`, and clears overflow, as the loads
+    /// and stores of `X` and the stack pointer do.
+    fn set_nz16(&mut self, value: u16) {
+        self.set_flag(NEGATIVE, value & 0x8000 != 0);
+        self.set_flag(ZERO, value == 0);
+        self.set_flag(OVERFLOW, false);
+    }
+
+    /// Sets the negative and zero flags from `value` and clears overflow, as the loads, stores and
+    /// logic do, and returns it.
+    fn logic(&mut self, value: u8) -> u8 {
+        self.set_nz(value);
+        self.set_flag(OVERFLOW, false);
+        value
+    }
+
+    fn accumulator(&mut self, b: bool) -> &mut u8 {
+        if b {
+            &mut self.b
+        } else {
+            &mut self.a
+        }
+    }
+
+    /// Executes a defined opcode, after its fetch.
+    fn execute(&mut self, opcode: u8, bus: &mut impl Bus) {
+        match opcode {
+            0x00..=0x3F => self.inherent(opcode, bus),
+            0x40..=0x5F => {
+                let value = *self.accumulator(opcode >= 0x50);
+                if let Some(result) = self.unary(opcode & 0xF, value) {
+                    *self.accumulator(opcode >= 0x50) = result;
+                }
+            }
+            0x6E => self.pc = self.indexed(bus),
+            0x7E => self.pc = self.fetch16(bus),
+            0x60..=0x7F => {
+                let address = if opcode < 0x70 {
+                    self.indexed(bus)
+                } else {
+                    self.fetch16(bus)
+                };
+                let value = bus.read(address);
+                if let Some(result) = self.unary(opcode & 0xF, value) {
+                    bus.write(address, result);
+                }
+            }
+            0x8D => {
+                let offset = self.fetch(bus);
+                self.push16(bus, self.pc);
+                self.branch(offset);
+            }
+            _ => self.binary(opcode, bus),
+        }
+    }
+
+    /// Returns the address of an indexed operand, `X` plus the unsigned byte that follows.
+    fn indexed(&mut self, bus: &mut impl Bus) -> u16 {
+        let offset = self.fetch(bus);
+        self.x.wrapping_add(u16::from(offset))
+    }
+
+    /// Moves the program counter by the signed `offset`.
+    fn branch(&mut self, offset: u8) {
+        self.pc = self.pc.wrapping_add(offset as i8 as u16);
+    }
+
+    /// Executes `00` to `3F`: the flags, the transfers between registers, the branches and the
+    /// stack.
+    fn inherent(&mut self, opcode: u8, bus: &mut impl Bus) {
+        match opcode {
+            0x06 => self.set_flags(self.a),
+            0x07 => self.a = self.cc,
+            0x08 => {
+                self.x = self.x.wrapping_add(1);
+                self.set_flag(ZERO, self.x == 0);
+            }
+            0x09 => {
+                self.x = self.x.wrapping_sub(1);
+                self.set_flag(ZERO, self.x == 0);
+            }
+            0x0A => self.set_flag(OVERFLOW, false),
+            0x0B => self.set_flag(OVERFLOW, true),
+            0x0C => self.set_flag(CARRY, false),
+            0x0D => self.set_flag(CARRY, true),
+            0x0E => self.set_flag(INTERRUPT, false),
+            0x0F => self.set_flag(INTERRUPT, true),
+            0x10 => self.a = self.subtract(self.a, self.b, false),
+            0x11 => {
+                self.subtract(self.a, self.b, false);
+            }
+            0x16 => self.b = self.logic(self.a),
+            0x17 => self.a = self.logic(self.b),
+            0x19 => self.daa(),
+            0x1B => self.a = self.add(self.a, self.b, false),
+            0x20..=0x2F => {
+                let offset = self.fetch(bus);
+                if self.condition(opcode & 0xF) {
+                    self.branch(offset);
+                }
+            }
+            0x30 => self.x = self.sp.wrapping_add(1),
+            0x31 => self.sp = self.sp.wrapping_add(1),
+            0x32 => self.a = self.pull(bus),
+            0x33 => self.b = self.pull(bus),
+            0x34 => self.sp = self.sp.wrapping_sub(1),
+            0x35 => self.sp = self.x.wrapping_sub(1),
+            0x36 => self.push(bus, self.a),
+            0x37 => self.push(bus, self.b),
+            0x39 => self.pc = self.pull16(bus),
+            0x3B => {
+                let flags = self.pull(bus);
+                self.set_flags(flags);
+                self.b = self.pull(bus);
+                self.a = self.pull(bus);
+                self.x = self.pull16(bus);
+                self.pc = self.pull16(bus);
+            }
+            0x3E => {
+                self.push_registers(bus);
+                self.waiting = true;
+            }
+            0x3F => {
+                self.push_registers(bus);
+                self.cc |= INTERRUPT;
+                self.pc = self.read16(bus, SWI_VECTOR);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns whether the branch `2n` is taken, `BRA` to `BLE`.
+    fn condition(&self, n: u8) -> bool {
+        let c = self.flag(CARRY);
+        let z = self.flag(ZERO);
+        let v = self.flag(OVERFLOW);
+        let n_flag = self.flag(NEGATIVE);
+        match n {
+            0x0 => true,
+            0x2 => !(c || z),
+            0x3 => c || z,
+            0x4 => !c,
+            0x5 => c,
+            0x6 => !z,
+            0x7 => z,
+            0x8 => !v,
+            0x9 => v,
+            0xA => !n_flag,
+            0xB => n_flag,
+            0xC => n_flag == v,
+            0xD => n_flag != v,
+            0xE => !z && n_flag == v,
+            _ => z || n_flag != v,
+        }
+    }
+
+    /// Executes the operation numbered `n` of `40` to `7F` on `value`, and returns the result to
+    /// write back, or `None` for `TST`, which only tests it.
+    fn unary(&mut self, n: u8, value: u8) -> Option<u8> {
+        let carry = self.flag(CARRY) as u8;
+        let result = match n {
+            0x0 => {
+                let result = 0u8.wrapping_sub(value);
+                self.set_flag(OVERFLOW, result == 0x80);
+                self.set_flag(CARRY, result != 0);
+                result
+            }
+            0x3 => {
+                self.set_flag(CARRY, true);
+                self.logic(!value)
+            }
+            0x4 => self.shift(value >> 1, value & 1 != 0),
+            0x6 => self.shift(value >> 1 | carry << 7, value & 1 != 0),
+            0x7 => self.shift((value as i8 >> 1) as u8, value & 1 != 0),
+            0x8 => self.shift(value << 1, value & 0x80 != 0),
+            0x9 => self.shift(value << 1 | carry, value & 0x80 != 0),
+            0xA => {
+                self.set_flag(OVERFLOW, value == 0x80);
+                value.wrapping_sub(1)
+            }
+            0xC => {
+                self.set_flag(OVERFLOW, value == 0x7F);
+                value.wrapping_add(1)
+            }
+            0xD => {
+                self.logic(value);
+                self.set_flag(CARRY, false);
+                return None;
+            }
+            _ => {
+                self.set_flag(CARRY, false);
+                self.logic(0)
+            }
+        };
+        self.set_nz(result);
+        Some(result)
+    }
+
+    /// Sets the flags after a shift or rotation to `result` that shifted `carry` out, with
+    /// overflow set when the carry and the sign differ, and returns the result.
+    fn shift(&mut self, result: u8, carry: bool) -> u8 {
+        self.set_flag(CARRY, carry);
+        self.set_flag(OVERFLOW, carry != (result & 0x80 != 0));
+        result
+    }
+
+    /// Executes `80` to `FF`: the operations of an accumulator with memory, and those of `X` and
+    /// the stack pointer, for `A` in the first half and `B` or `X` in the second.
+    fn binary(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let b = opcode >= 0xC0;
+        let n = opcode & 0xF;
+        let address = match opcode >> 4 & 3 {
+            0 => {
+                let address = self.pc;
+                let wide = matches!(n, 0xC | 0xE);
+                self.pc = self.pc.wrapping_add(if wide { 2 } else { 1 });
+                address
+            }
+            1 => u16::from(self.fetch(bus)),
+            2 => self.indexed(bus),
+            _ => self.fetch16(bus),
+        };
+        match n {
+            0x7 => {
+                let value = *self.accumulator(b);
+                bus.write(address, self.logic(value));
+            }
+            0xC => {
+                let value = self.read16(bus, address);
+                self.compare_index(value);
+            }
+            0xD => {
+                self.push16(bus, self.pc);
+                self.pc = address;
+            }
+            0xE => {
+                let value = self.read16(bus, address);
+                self.set_nz16(value);
+                if b {
+                    self.x = value;
+                } else {
+                    self.sp = value;
+                }
+            }
+            0xF => {
+                let value = if b { self.x } else { self.sp };
+                self.set_nz16(value);
+                self.write16(bus, address, value);
+            }
+            _ => {
+                let m = bus.read(address);
+                let a = *self.accumulator(b);
+                let result = match n {
+                    0x0 => self.subtract(a, m, false),
+                    0x1 => {
+                        self.subtract(a, m, false);
+                        return;
+                    }
+                    0x2 => self.subtract(a, m, self.flag(CARRY)),
+                    0x4 => self.logic(a & m),
+                    0x5 => {
+                        self.logic(a & m);
+                        return;
+                    }
+                    0x6 => self.logic(m),
+                    0x8 => self.logic(a ^ m),
+                    0x9 => self.add(a, m, self.flag(CARRY)),
+                    0xA => self.logic(a | m),
+                    _ => self.add(a, m, false),
+                };
+                *self.accumulator(b) = result;
+            }
+        }
+    }
+
+    /// Returns `a` plus `m` plus `carry`, setting every flag but the interrupt mask.
+    fn add(&mut self, a: u8, m: u8, carry: bool) -> u8 {
+        let sum = u16::from(a) + u16::from(m) + u16::from(carry);
+        let result = sum as u8;
+        self.set_flag(HALF_CARRY, (a ^ m ^ result) & 0x10 != 0);
+        self.set_flag(OVERFLOW, (a ^ result) & (m ^ result) & 0x80 != 0);
+        self.set_flag(CARRY, sum > 0xFF);
+        self.set_nz(result);
+        result
+    }
+
+    /// Returns `a` minus `m` minus `borrow`, setting the flags but the half carry, which
+    /// subtractions leave alone.
+    fn subtract(&mut self, a: u8, m: u8, borrow: bool) -> u8 {
+        let difference = u16::from(a)
+            .wrapping_sub(u16::from(m))
+            .wrapping_sub(u16::from(borrow));
+        let result = difference as u8;
+        self.set_flag(OVERFLOW, (a ^ m) & (a ^ result) & 0x80 != 0);
+        self.set_flag(CARRY, difference > 0xFF);
+        self.set_nz(result);
+        result
+    }
+
+    /// Compares `X` with `value`, as `CPX` does on the 6800: zero is set from all 16 bits, but
+    /// negative and overflow only from subtracting the high bytes, and the carry is left alone.
+    fn compare_index(&mut self, value: u16) {
+        let [x, m] = [(self.x >> 8) as u8, (value >> 8) as u8];
+        let high = x.wrapping_sub(m);
+        self.set_flag(NEGATIVE, high & 0x80 != 0);
+        self.set_flag(OVERFLOW, (x ^ m) & (x ^ high) & 0x80 != 0);
+        self.set_flag(ZERO, self.x == value);
+    }
+
+    /// Adjusts `A` after adding two binary-coded decimal numbers into it, from the carry and
+    /// half carry. Motorola leaves overflow undefined, and it is left alone.
+    fn daa(&mut self) {
+        let (low, high) = (self.a & 0xF, self.a >> 4);
+        let mut correction = 0;
+        let mut carry = self.flag(CARRY);
+        if self.flag(HALF_CARRY) || low > 9 {
+            correction |= 0x06;
+        }
+        if carry || high > 9 || high > 8 && low > 9 {
+            correction |= 0x60;
+            carry = true;
+        }
+        self.a = self.a.wrapping_add(correction);
+        self.set_nz(self.a);
+        self.set_flag(CARRY, carry);
+    }
+
+    /// Writes the whole processor, for save states.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[0] = self.a;
+        state[1] = self.b;
+        state[2..4].copy_from_slice(&self.x.to_be_bytes());
+        state[4..6].copy_from_slice(&self.sp.to_be_bytes());
+        state[6..8].copy_from_slice(&self.pc.to_be_bytes());
+        state[8] = self.cc;
+        state[9] = self.waiting as u8 | (self.nmi_line as u8) << 1 | (self.nmi_edge as u8) << 2;
+        state[10..].copy_from_slice(&self.cycles.to_be_bytes());
+        state
+    }
+
+    /// Restores the processor from a state written by [`Cpu::save`].
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        self.a = state[0];
+        self.b = state[1];
+        self.x = u16::from_be_bytes([state[2], state[3]]);
+        self.sp = u16::from_be_bytes([state[4], state[5]]);
+        self.pc = u16::from_be_bytes([state[6], state[7]]);
+        self.set_flags(state[8]);
+        self.waiting = state[9] & 1 != 0;
+        self.nmi_line = state[9] & 2 != 0;
+        self.nmi_edge = state[9] & 4 != 0;
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&state[10..]);
+        self.cycles = u64::from_be_bytes(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Memory;
+
+    /// Memory, with interrupt lines driven by the test.
+    struct Machine {
+        memory: Memory,
+        nmi: bool,
+        irq: bool,
+    }
+
+    impl Machine {
+        fn new(program: &[u8]) -> Self {
+            let mut memory = Memory::with_program(0x0100, program).unwrap();
+            memory.0[RESET_VECTOR as usize..].copy_from_slice(&[0x01, 0x00]);
+            Machine {
+                memory,
+                nmi: false,
+                irq: false,
+            }
+        }
+    }
+
+    impl Bus for Machine {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory.write(address, value);
+        }
+
+        fn nmi(&mut self) -> bool {
+            self.nmi
+        }
+
+        fn irq(&mut self) -> bool {
+            self.irq
+        }
+    }
+
+    /// Resets a processor into the program at 0x0100, with the stack below it.
+    fn start(machine: &mut Machine) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.reset(machine);
+        cpu.set_stack_pointer(0x00FF);
+        cpu
+    }
+
+    /// Runs until `WAI`.
+    fn run(cpu: &mut Cpu, machine: &mut Machine) {
+        while !cpu.waiting() {
+            cpu.step(machine);
+        }
+    }
+
+    #[test]
+    fn multiplies() {
+        // 0x0100: multiplies 13 by 11 into A by adding B to A, X times.
+        let mut machine = Machine::new(&[
+            0x4F, // CLRA
+            0xC6, 0x0D, // LDAB #13
+            0xCE, 0x00, 0x0B, // LDX #11
+            0x1B, // ABA
+            0x09, // DEX
+            0x26, 0xFC, // BNE 0x0106
+            0x97, 0x10, // STAA $10
+            0x3E, // WAI
+        ]);
+        let mut cpu = start(&mut machine);
+        run(&mut cpu, &mut machine);
+        assert_eq!(cpu.register(Register::A), 143);
+        assert_eq!(machine.memory.0[0x10], 143);
+        assert_eq!(cpu.index(), 0);
+        assert_eq!(cpu.cycles(), 2 + 2 + 3 + (2 + 4 + 4) * 11 + 4 + 9);
+    }
+
+    #[test]
+    fn arithmetic() {
+        // ADCA and SBCA for every accumulator, operand and carry.
+        let mut memory = Memory::default();
+        let mut cpu = Cpu::new();
+        for &opcode in &[0x89, 0x82] {
+            for a in 0..=255u8 {
+                for m in 0..=255u8 {
+                    for &carry in &[false, true] {
+                        memory.0[..2].copy_from_slice(&[opcode, m]);
+                        cpu.set_program_counter(0);
+                        cpu.set_register(Register::A, a);
+                        cpu.set_flags(carry as u8);
+                        cpu.step(&mut memory);
+                        let (a, m, c) = (i32::from(a), i32::from(m), carry as i32);
+                        let (sum, signed) = if opcode == 0x89 {
+                            (
+                                a + m + c,
+                                i32::from(a as u8 as i8) + i32::from(m as u8 as i8) + c,
+                            )
+                        } else {
+                            (
+                                a - m - c,
+                                i32::from(a as u8 as i8) - i32::from(m as u8 as i8) - c,
+                            )
+                        };
+                        let result = sum as u8;
+                        let mut flags = FLAGS_SET;
+                        flags |= if result & 0x80 != 0 { NEGATIVE } else { 0 };
+                        flags |= if result == 0 { ZERO } else { 0 };
+                        flags |= if !(-128..128).contains(&signed) {
+                            OVERFLOW
+                        } else {
+                            0
+                        };
+                        flags |= if !(0..256).contains(&sum) { CARRY } else { 0 };
+                        if opcode == 0x89 && (a & 0xF) + (m & 0xF) + c > 0xF {
+                            flags |= HALF_CARRY;
+                        }
+                        assert_eq!(
+                            (cpu.register(Register::A), cpu.flags()),
+                            (result, flags),
+                            "{:02X} with A={:02X}, M={:02X}, C={}",
+                            opcode,
+                            a,
+                            m,
+                            c
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decimal() {
+        // ADDA then DAA for every pair of two-digit numbers.
+        let mut memory = Memory::default();
+        let mut cpu = Cpu::new();
+        let bcd = |n: u32| (((n / 10) << 4) | (n % 10)) as u8;
+        for x in 0..100 {
+            for y in 0..100 {
+                memory.0[..3].copy_from_slice(&[0x8B, bcd(y), 0x19]);
+                cpu.set_program_counter(0);
+                cpu.set_register(Register::A, bcd(x));
+                cpu.step(&mut memory);
+                cpu.step(&mut memory);
+                let sum = x + y;
+                assert_eq!(cpu.register(Register::A), bcd(sum % 100), "{} + {}", x, y);
+                assert_eq!(cpu.flags() & CARRY != 0, sum >= 100, "{} + {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn shifts_and_unary() {
+        let mut machine = Machine::new(&[
+            0x86,
+            0b1000_0001, // LDAA
+            0x44,        // LSRA -> 0100_0000, C = 1
+            0x46,        // RORA -> 1010_0000, C = 0
+            0x47,        // ASRA -> 1101_0000, C = 0
+            0x48,        // ASLA -> 1010_0000, C = 1
+            0x49,        // ROLA -> 0100_0001, C = 1
+            0x40,        // NEGA -> 1011_1111, C = 1
+            0x43,        // COMA -> 0100_0000, C = 1
+            0x4A,        // DECA -> 0011_1111
+            0x4C,        // INCA -> 0100_0000
+            0x4F,        // CLRA
+            0x3E,
+        ]);
+        let mut cpu = start(&mut machine);
+        let mut trace = [(0, false); 10];
+        cpu.step(&mut machine);
+        for step in trace.iter_mut() {
+            cpu.step(&mut machine);
+            *step = (cpu.register(Register::A), cpu.flags() & CARRY != 0);
+        }
+        assert_eq!(
+            trace,
+            [
+                (0b0100_0000, true),
+                (0b1010_0000, false),
+                (0b1101_0000, false),
+                (0b1010_0000, true),
+                (0b0100_0001, true),
+                (0b1011_1111, true),
+                (0b0100_0000, true),
+                (0b0011_1111, true),
+                (0b0100_0000, true),
+                (0, false),
+            ]
+        );
+        assert_eq!(cpu.flags(), FLAGS_SET | INTERRUPT | ZERO);
+
+        // INC and DEC overflow at the signed limits, and leave the carry alone.
+        let mut memory = Memory::with_program(0, &[0x7C, 0x00, 0x10, 0x7A, 0x00, 0x11]).unwrap();
+        memory.0[0x10..0x12].copy_from_slice(&[0x7F, 0x80]);
+        let mut cpu = Cpu::new();
+        cpu.step(&mut memory);
+        assert_eq!(memory.0[0x10], 0x80);
+        assert_eq!(cpu.flags(), FLAGS_SET | INTERRUPT | NEGATIVE | OVERFLOW);
+        cpu.step(&mut memory);
+        assert_eq!(memory.0[0x11], 0x7F);
+        assert_eq!(cpu.flags(), FLAGS_SET | INTERRUPT | OVERFLOW);
+    }
+
+    #[test]
+    fn branches() {
+        let mut memory = Memory::default();
+        let mut branch = |opcode: u8, flags: u8| {
+            memory.0[0x80..0x82].copy_from_slice(&[opcode, 0x10]);
+            let mut cpu = Cpu::new();
+            cpu.set_program_counter(0x80);
+            cpu.set_flags(flags);
+            assert_eq!(cpu.step(&mut memory), 4);
+            cpu.program_counter() == 0x92
+        };
+        assert!(branch(0x20, 0));
+        assert!(branch(0x22, 0) && !branch(0x22, CARRY) && !branch(0x22, ZERO));
+        assert!(branch(0x23, ZERO) && !branch(0x23, 0));
+        assert!(branch(0x24, 0) && branch(0x25, CARRY));
+        assert!(branch(0x26, 0) && branch(0x27, ZERO));
+        assert!(branch(0x28, 0) && branch(0x29, OVERFLOW));
+        assert!(branch(0x2A, 0) && branch(0x2B, NEGATIVE));
+        assert!(branch(0x2C, NEGATIVE | OVERFLOW) && !branch(0x2C, NEGATIVE));
+        assert!(branch(0x2D, OVERFLOW) && !branch(0x2D, 0));
+        assert!(branch(0x2E, 0) && !branch(0x2E, ZERO) && !branch(0x2E, NEGATIVE));
+        assert!(branch(0x2F, ZERO) && branch(0x2F, NEGATIVE) && !branch(0x2F, 0));
+
+        // Backwards, across a page.
+        memory.0[0x100..0x102].copy_from_slice(&[0x20, 0xFC]);
+        let mut cpu = Cpu::new();
+        cpu.set_program_counter(0x100);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.program_counter(), 0xFE);
+    }
+
+    #[test]
+    fn index_and_stack() {
+        let mut machine = Machine::new(&[
+            0xCE, 0x12, 0x34, // LDX #$1234
+            0xDF, 0x20, // STX $20
+            0x8C, 0x12, 0x35, // CPX #$1235
+            0x07, // TPA
+            0x97, 0x30, // STAA $30
+            0xBD, 0x01, 0x20, // JSR $0120
+            0x36, // PSHA
+            0x33, // PULB
+            0x3E, // WAI
+        ]);
+        machine.memory.0[0x120..0x126].copy_from_slice(&[
+            0x30, // TSX
+            0xEE, 0x00, // LDX $00,X, the return address
+            0x86, 0x5A, // LDAA #$5A
+            0x39, // RTS
+        ]);
+        let mut cpu = start(&mut machine);
+        run(&mut cpu, &mut machine);
+        assert_eq!(machine.memory.0[0x20..0x22], [0x12, 0x34]);
+        // CPX set neither zero nor negative, as the high bytes are the same.
+        assert_eq!(machine.memory.0[0x30], FLAGS_SET | INTERRUPT);
+        assert_eq!(cpu.index(), 0x010E);
+        assert_eq!(cpu.register(Register::B), 0x5A);
+        // WAI stacked 7 bytes.
+        assert_eq!(cpu.stack_pointer(), 0x00FF - 7);
+        assert_eq!(machine.memory.0[0xFE..0x100], [0x01, 0x11]);
+    }
+
+    #[test]
+    fn compares_the_index() {
+        let mut memory = Memory::default();
+        let mut compare = |x: u16, m: u16| {
+            memory.0[..3].copy_from_slice(&[0x8C, (m >> 8) as u8, m as u8]);
+            let mut cpu = Cpu::new();
+            cpu.set_flags(CARRY);
+            cpu.set_index(x);
+            cpu.step(&mut memory);
+            cpu.flags() & (NEGATIVE | ZERO | OVERFLOW | CARRY)
+        };
+        assert_eq!(compare(0x1234, 0x1234), ZERO | CARRY);
+        assert_eq!(compare(0x1234, 0x1235), CARRY);
+        assert_eq!(compare(0x1234, 0x1334), NEGATIVE | CARRY);
+        assert_eq!(compare(0x8000, 0x0100), OVERFLOW | CARRY);
+    }
+
+    #[test]
+    fn interrupts() {
+        // 0x0100: CLI; then counts in B at 0x0101 forever. The IRQ handler at 0x0200 counts at
+        // 0x0010 and returns; the NMI handler at 0x0300 waits.
+        let mut machine = Machine::new(&[0x0E, 0x5C, 0x20, 0xFD]);
+        machine.memory.0[0x200..0x204].copy_from_slice(&[0x7C, 0x00, 0x10, 0x3B]);
+        machine.memory.0[0x300] = 0x3E;
+        machine.memory.0[IRQ_VECTOR as usize..][..2].copy_from_slice(&[0x02, 0x00]);
+        machine.memory.0[NMI_VECTOR as usize..][..2].copy_from_slice(&[0x03, 0x00]);
+        machine.irq = true;
+        let mut cpu = start(&mut machine);
+        // IRQ is masked after a reset.
+        cpu.step(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x0101);
+        assert_eq!(cpu.step(&mut machine), INTERRUPT_CYCLES);
+        assert_eq!(cpu.program_counter(), 0x0200);
+        assert_eq!(cpu.stack_pointer(), 0x00FF - 7);
+        assert_ne!(cpu.flags() & INTERRUPT, 0);
+        machine.irq = false;
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x0101);
+        assert_eq!(machine.memory.0[0x10], 1);
+        assert_eq!(cpu.flags() & INTERRUPT, 0);
+
+        // NMI is taken on the edge, masked or not, and held, it is taken once.
+        machine.nmi = true;
+        cpu.set_flags(INTERRUPT);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x0300);
+        cpu.step(&mut machine);
+        assert!(cpu.waiting());
+        assert_eq!(cpu.step(&mut machine), 1);
+        assert!(cpu.waiting());
+
+        // SWI stacks the registers and jumps through its vector.
+        let mut machine = Machine::new(&[0x3F]);
+        machine.memory.0[SWI_VECTOR as usize..][..2].copy_from_slice(&[0x12, 0x34]);
+        let mut cpu = start(&mut machine);
+        assert_eq!(cpu.step(&mut machine), 12);
+        assert_eq!(cpu.program_counter(), 0x1234);
+        assert_eq!(machine.memory.0[0xFE..0x100], [0x01, 0x01]);
+    }
+
+    #[test]
+    fn wakes_from_wai() {
+        // WAI, then an IRQ taken without stacking again, and RTI back after it.
+        let mut machine = Machine::new(&[0x0E, 0x3E, 0x01]);
+        machine.memory.0[0x200] = 0x3B;
+        machine.memory.0[IRQ_VECTOR as usize..][..2].copy_from_slice(&[0x02, 0x00]);
+        let mut cpu = start(&mut machine);
+        run(&mut cpu, &mut machine);
+        assert_eq!(cpu.step(&mut machine), 1);
+        machine.irq = true;
+        assert_eq!(cpu.step(&mut machine), WAKE_CYCLES);
+        assert_eq!(cpu.program_counter(), 0x0200);
+        machine.irq = false;
+        cpu.step(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x0102);
+        assert_eq!(cpu.stack_pointer(), 0x00FF);
+    }
+
+    #[test]
+    fn cycles() {
+        let mut memory = Memory::default();
+        let mut cpu = Cpu::new();
+        let mut cycles = |bytes: &[u8]| {
+            memory.0[0x1000..0x1000 + bytes.len()].copy_from_slice(bytes);
+            cpu.set_program_counter(0x1000);
+            cpu.set_stack_pointer(0x0FFF);
+            cpu.step(&mut memory)
+        };
+        assert_eq!(cycles(&[0x01]), 2);
+        assert_eq!(cycles(&[0x08]), 4);
+        assert_eq!(cycles(&[0x86, 0x00]), 2);
+        assert_eq!(cycles(&[0x96, 0x00]), 3);
+        assert_eq!(cycles(&[0xA6, 0x00]), 5);
+        assert_eq!(cycles(&[0xB6, 0x00, 0x00]), 4);
+        assert_eq!(cycles(&[0xB7, 0x00, 0x00]), 5);
+        assert_eq!(cycles(&[0x6C, 0x00]), 7);
+        assert_eq!(cycles(&[0xFF, 0x00, 0x00]), 6);
+        assert_eq!(cycles(&[0xBD, 0x00, 0x00]), 9);
+        assert_eq!(cycles(&[0x39]), 5);
+        // Undefined opcodes are NOPs of one byte.
+        assert_eq!(cycles(&[0x87, 0x00]), 2);
+        assert_eq!(cpu.program_counter(), 0x1001);
+    }
+
+    #[test]
+    fn resets_and_saves() {
+        let mut machine = Machine::new(&[0x86, 0x12, 0xCE, 0xAB, 0xCD, 0x0D, 0x3E]);
+        let mut cpu = start(&mut machine);
+        run(&mut cpu, &mut machine);
+        let mut restored = Cpu::new();
+        restored.restore(&cpu.save());
+        assert_eq!(restored, cpu);
+
+        cpu.set_flags(0);
+        cpu.reset(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x0100);
+        assert!(!cpu.waiting());
+        assert_eq!(cpu.flags(), FLAGS_SET | INTERRUPT);
+        assert_eq!(cpu.index(), 0xABCD);
+    }
+}
@@ -0,0 +1,141 @@
+//! The instruction set: the mnemonic of each of the 197 opcodes Motorola defined, how it addresses
+//! its operand, and the clock cycles it takes, from Motorola's data sheet.
+
+/// How an instruction finds its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// No operand, or one implied by the instruction, such as `INX` or `NEGA`.
+    Inh,
+    /// The byte following the opcode, as in `LDAA #$2A`.
+    Imm,
+    /// The 16-bit value following the opcode, as in `LDX #$1234`.
+    Imm16,
+    /// A byte of the first page, as in `LDAA $10`.
+    Dir,
+    /// The byte at `X` plus an unsigned offset, as in `LDAA $10,X`.
+    Idx,
+    /// A 16-bit address, as in `LDAA $1234`.
+    Ext,
+    /// A signed offset from the next instruction, for branches.
+    Rel,
+}
+
+impl Mode {
+    /// Returns the length in bytes of an instruction with this mode, with its opcode.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(self) -> usize {
+        match self {
+            Mode::Inh => 1,
+            Mode::Imm16 | Mode::Ext => 3,
+            _ => 2,
+        }
+    }
+}
+
+use Mode::*;
+
+/// The mnemonic and mode of each opcode, or `None` for those Motorola left undefined.
+#[rustfmt::skip]
+const INSTRUCTIONS: [Option<(&str, Mode)>; 256] = [
+    None, Some(("NOP", Inh)), None, None, // 00
+    None, None, Some(("TAP", Inh)), Some(("TPA", Inh)),
+    Some(("INX", Inh)), Some(("DEX", Inh)), Some(("CLV", Inh)), Some(("SEV", Inh)),
+    Some(("CLC", Inh)), Some(("SEC", Inh)), Some(("CLI", Inh)), Some(("SEI", Inh)),
+    Some(("SBA", Inh)), Some(("CBA", Inh)), None, None, // 10
+    None, None, Some(("TAB", Inh)), Some(("TBA", Inh)),
+    None, Some(("DAA", Inh)), None, Some(("ABA", Inh)),
+    None, None, None, None,
+    Some(("BRA", Rel)), None, Some(("BHI", Rel)), Some(("BLS", Rel)), // 20
+    Some(("BCC", Rel)), Some(("BCS", Rel)), Some(("BNE", Rel)), Some(("BEQ", Rel)),
+    Some(("BVC", Rel)), Some(("BVS", Rel)), Some(("BPL", Rel)), Some(("BMI", Rel)),
+    Some(("BGE", Rel)), Some(("BLT", Rel)), Some(("BGT", Rel)), Some(("BLE", Rel)),
+    Some(("TSX", Inh)), Some(("INS", Inh)), Some(("PULA", Inh)), Some(("PULB", Inh)), // 30
+    Some(("DES", Inh)), Some(("TXS", Inh)), Some(("PSHA", Inh)), Some(("PSHB", Inh)),
+    None, Some(("RTS", Inh)), None, Some(("RTI", Inh)),
+    None, None, Some(("WAI", Inh)), Some(("SWI", Inh)),
+    Some(("NEGA", Inh)), None, None, Some(("COMA", Inh)), // 40
+    Some(("LSRA", Inh)), None, Some(("RORA", Inh)), Some(("ASRA", Inh)),
+    Some(("ASLA", Inh)), Some(("ROLA", Inh)), Some(("DECA", Inh)), None,
+    Some(("INCA", Inh)), Some(("TSTA", Inh)), None, Some(("CLRA", Inh)),
+    Some(("NEGB", Inh)), None, None, Some(("COMB", Inh)), // 50
+    Some(("LSRB", Inh)), None, Some(("RORB", Inh)), Some(("ASRB", Inh)),
+    Some(("ASLB", Inh)), Some(("ROLB", Inh)), Some(("DECB", Inh)), None,
+    Some(("INCB", Inh)), Some(("TSTB", Inh)), None, Some(("CLRB", Inh)),
+    Some(("NEG", Idx)), None, None, Some(("COM", Idx)), // 60
+    Some(("LSR", Idx)), None, Some(("ROR", Idx)), Some(("ASR", Idx)),
+    Some(("ASL", Idx)), Some(("ROL", Idx)), Some(("DEC", Idx)), None,
+    Some(("INC", Idx)), Some(("TST", Idx)), Some(("JMP", Idx)), Some(("CLR", Idx)),
+    Some(("NEG", Ext)), None, None, Some(("COM", Ext)), // 70
+    Some(("LSR", Ext)), None, Some(("ROR", Ext)), Some(("ASR", Ext)),
+    Some(("ASL", Ext)), Some(("ROL", Ext)), Some(("DEC", Ext)), None,
+    Some(("INC", Ext)), Some(("TST", Ext)), Some(("JMP", Ext)), Some(("CLR", Ext)),
+    Some(("SUBA", Imm)), Some(("CMPA", Imm)), Some(("SBCA", Imm)), None, // 80
+    Some(("ANDA", Imm)), Some(("BITA", Imm)), Some(("LDAA", Imm)), None,
+    Some(("EORA", Imm)), Some(("ADCA", Imm)), Some(("ORAA", Imm)), Some(("ADDA", Imm)),
+    Some(("CPX", Imm16)), Some(("BSR", Rel)), Some(("LDS", Imm16)), None,
+    Some(("SUBA", Dir)), Some(("CMPA", Dir)), Some(("SBCA", Dir)), None, // 90
+    Some(("ANDA", Dir)), Some(("BITA", Dir)), Some(("LDAA", Dir)), Some(("STAA", Dir)),
+    Some(("EORA", Dir)), Some(("ADCA", Dir)), Some(("ORAA", Dir)), Some(("ADDA", Dir)),
+    Some(("CPX", Dir)), None, Some(("LDS", Dir)), Some(("STS", Dir)),
+    Some(("SUBA", Idx)), Some(("CMPA", Idx)), Some(("SBCA", Idx)), None, // A0
+    Some(("ANDA", Idx)), Some(("BITA", Idx)), Some(("LDAA", Idx)), Some(("STAA", Idx)),
+    Some(("EORA", Idx)), Some(("ADCA", Idx)), Some(("ORAA", Idx)), Some(("ADDA", Idx)),
+    Some(("CPX", Idx)), Some(("JSR", Idx)), Some(("LDS", Idx)), Some(("STS", Idx)),
+    Some(("SUBA", Ext)), Some(("CMPA", Ext)), Some(("SBCA", Ext)), None, // B0
+    Some(("ANDA", Ext)), Some(("BITA", Ext)), Some(("LDAA", Ext)), Some(("STAA", Ext)),
+    Some(("EORA", Ext)), Some(("ADCA", Ext)), Some(("ORAA", Ext)), Some(("ADDA", Ext)),
+    Some(("CPX", Ext)), Some(("JSR", Ext)), Some(("LDS", Ext)), Some(("STS", Ext)),
+    Some(("SUBB", Imm)), Some(("CMPB", Imm)), Some(("SBCB", Imm)), None, // C0
+    Some(("ANDB", Imm)), Some(("BITB", Imm)), Some(("LDAB", Imm)), None,
+    Some(("EORB", Imm)), Some(("ADCB", Imm)), Some(("ORAB", Imm)), Some(("ADDB", Imm)),
+    None, None, Some(("LDX", Imm16)), None,
+    Some(("SUBB", Dir)), Some(("CMPB", Dir)), Some(("SBCB", Dir)), None, // D0
+    Some(("ANDB", Dir)), Some(("BITB", Dir)), Some(("LDAB", Dir)), Some(("STAB", Dir)),
+    Some(("EORB", Dir)), Some(("ADCB", Dir)), Some(("ORAB", Dir)), Some(("ADDB", Dir)),
+    None, None, Some(("LDX", Dir)), Some(("STX", Dir)),
+    Some(("SUBB", Idx)), Some(("CMPB", Idx)), Some(("SBCB", Idx)), None, // E0
+    Some(("ANDB", Idx)), Some(("BITB", Idx)), Some(("LDAB", Idx)), Some(("STAB", Idx)),
+    Some(("EORB", Idx)), Some(("ADCB", Idx)), Some(("ORAB", Idx)), Some(("ADDB", Idx)),
+    None, None, Some(("LDX", Idx)), Some(("STX", Idx)),
+    Some(("SUBB", Ext)), Some(("CMPB", Ext)), Some(("SBCB", Ext)), None, // F0
+    Some(("ANDB", Ext)), Some(("BITB", Ext)), Some(("LDAB", Ext)), Some(("STAB", Ext)),
+    Some(("EORB", Ext)), Some(("ADCB", Ext)), Some(("ORAB", Ext)), Some(("ADDB", Ext)),
+    None, None, Some(("LDX", Ext)), Some(("STX", Ext)),
+];
+
+/// Clock cycles of each opcode, taking a branch or not. The undefined opcodes run as `NOP`.
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+    2, 2, 2, 2, 2, 2, 2, 2, 4, 4, 2, 2, 2, 2, 2, 2, // 00
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, // 10
+    4, 2, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, // 20
+    4, 4, 4, 4, 4, 4, 4, 4, 2, 5, 2, 10, 2, 2, 9, 12, // 30
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, // 40
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, // 50
+    7, 2, 2, 7, 7, 2, 7, 7, 7, 7, 7, 2, 7, 7, 4, 7, // 60
+    6, 2, 2, 6, 6, 2, 6, 6, 6, 6, 6, 2, 6, 6, 3, 6, // 70
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 8, 3, 2, // 80
+    3, 3, 3, 2, 3, 3, 3, 4, 3, 3, 3, 3, 4, 2, 4, 5, // 90
+    5, 5, 5, 2, 5, 5, 5, 6, 5, 5, 5, 5, 6, 8, 6, 7, // A0
+    4, 4, 4, 2, 4, 4, 4, 5, 4, 4, 4, 4, 5, 9, 5, 6, // B0
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, // C0
+    3, 3, 3, 2, 3, 3, 3, 4, 3, 3, 3, 3, 2, 2, 4, 5, // D0
+    5, 5, 5, 2, 5, 5, 5, 6, 5, 5, 5, 5, 2, 2, 6, 7, // E0
+    4, 4, 4, 2, 4, 4, 4, 5, 4, 4, 4, 4, 2, 2, 5, 6, // F0
+];
+
+/// Returns the mnemonic of `opcode`, such as `LDAA`, and how it addresses its operand, or `None`
+/// if Motorola left it undefined.
+pub fn decode(opcode: u8) -> Option<(&'static str, Mode)> {
+    INSTRUCTIONS[opcode as usize]
+}
+
+/// Returns how many clock cycles `opcode` takes.
+pub fn cycles(opcode: u8) -> u8 {
+    CYCLES[opcode as usize]
+}
+
+/// Returns how `opcode` addresses its operand, with the undefined opcodes taking none.
+pub fn mode(opcode: u8) -> Mode {
+    decode(opcode).map_or(Inh, |(_, mode)| mode)
+}
@@ -0,0 +1,42 @@
+//! What the processor is wired to: memory, with the devices mapped into it, and the two interrupt
+//! lines.
+
+pub use emu_memory::{Memory, Overflow};
+
+/// The system around the processor.
+///
+/// The 6502 reads or writes memory on every clock cycle, even when an instruction has no use for
+/// the byte, so each call to [`read`](Bus::read) or [`write`](Bus::write) is one cycle, and a
+/// machine can run its other chips by a cycle in each. The dummy accesses are made as the 6502
+/// makes them, since reading some devices changes them.
+///
+/// Only memory has to be provided. The interrupt lines default to never being pulled.
+pub trait Bus {
+    /// Reads the byte at `address`, for a clock cycle.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes `value` at `address`, for a clock cycle.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Returns whether a device is pulling the NMI line, which interrupts the program when it is
+    /// first pulled.
+    fn nmi(&mut self) -> bool {
+        false
+    }
+
+    /// Returns whether a device is pulling the IRQ line, which interrupts the program for as long
+    /// as it is pulled and interrupts are not disabled.
+    fn irq(&mut self) -> bool {
+        false
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[address as usize] = value;
+    }
+}
@@ -0,0 +1,141 @@
+//! Disassembling instructions into MOS's syntax, such as `LDA #$2A`, `STA $0200,X` and
+//! `JMP ($FFFC)`, for debuggers and traces.
+//!
+//! Branches show the address they go to, so an instruction is decoded at its address:
+//!
+//! ```
+//! use mos6502::disasm::Instruction;
+//!
+//! let instruction = Instruction::decode(0xC000, &[0xD0, 0xFE]).unwrap();
+//! assert_eq!(instruction.to_string(), "BNE $C000");
+//! assert_eq!(instruction.len(), 2);
+//! ```
+
+use crate::opcode::{self, Mode};
+use core::fmt;
+
+/// An instruction with its operand, at its address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    /// The byte or the 16-bit value following the opcode.
+    pub operand: u16,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, which are at `address`, or returns `None`
+    /// if they end before it does.
+    pub fn decode(address: u16, bytes: &[u8]) -> Option<Instruction> {
+        let opcode = *bytes.first()?;
+        let operand = match opcode::decode(opcode).1.len() {
+            1 => 0,
+            2 => u16::from(*bytes.get(1)?),
+            _ => u16::from_le_bytes([*bytes.get(1)?, *bytes.get(2)?]),
+        };
+        Some(Instruction {
+            address,
+            opcode,
+            operand,
+        })
+    }
+
+    /// Returns the length of the instruction in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.mode().len()
+    }
+
+    /// Returns the mnemonic of the instruction, such as `LDA`.
+    pub fn mnemonic(&self) -> &'static str {
+        opcode::decode(self.opcode).0.mnemonic()
+    }
+
+    /// Returns how the instruction addresses its operand.
+    pub fn mode(&self) -> Mode {
+        opcode::decode(self.opcode).1
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = self.mnemonic();
+        let operand = self.operand;
+        match self.mode() {
+            Mode::Imp => f.write_str(mnemonic),
+            Mode::Acc => write!(f, "{} A", mnemonic),
+            Mode::Imm => write!(f, "{} #${:02X}", mnemonic, operand),
+            Mode::Zp => write!(f, "{} ${:02X}", mnemonic, operand),
+            Mode::Zpx => write!(f, "{} ${:02X},X", mnemonic, operand),
+            Mode::Zpy => write!(f, "{} ${:02X},Y", mnemonic, operand),
+            Mode::Abs => write!(f, "{} ${:04X}", mnemonic, operand),
+            Mode::Abx => write!(f, "{} ${:04X},X", mnemonic, operand),
+            Mode::Aby => write!(f, "{} ${:04X},Y", mnemonic, operand),
+            Mode::Izx => write!(f, "{} (${:02X},X)", mnemonic, operand),
+            Mode::Izy => write!(f, "{} (${:02X}),Y", mnemonic, operand),
+            Mode::Ind => write!(f, "{} (${:04X})", mnemonic, operand),
+            Mode::Rel => {
+                let next = self.address.wrapping_add(2);
+                let target = next.wrapping_add(operand as u8 as i8 as u16);
+                write!(f, "{} ${:04X}", mnemonic, target)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    fn disassemble(bytes: &[u8]) -> std::string::String {
+        Instruction::decode(0x8000, bytes).unwrap().to_string()
+    }
+
+    #[test]
+    fn disassembles() {
+        assert_eq!(disassemble(&[0x00]), "BRK");
+        assert_eq!(disassemble(&[0xEA]), "NOP");
+        assert_eq!(disassemble(&[0x0A]), "ASL A");
+        assert_eq!(disassemble(&[0xA9, 0x2A]), "LDA #$2A");
+        assert_eq!(disassemble(&[0xA5, 0x10]), "LDA $10");
+        assert_eq!(disassemble(&[0xB5, 0x10]), "LDA $10,X");
+        assert_eq!(disassemble(&[0xB6, 0x10]), "LDX $10,Y");
+        assert_eq!(disassemble(&[0x8D, 0x00, 0x02]), "STA $0200");
+        assert_eq!(disassemble(&[0x9D, 0x00, 0x02]), "STA $0200,X");
+        assert_eq!(disassemble(&[0x99, 0x00, 0x02]), "STA $0200,Y");
+        assert_eq!(disassemble(&[0xA1, 0x10]), "LDA ($10,X)");
+        assert_eq!(disassemble(&[0xB1, 0x10]), "LDA ($10),Y");
+        assert_eq!(disassemble(&[0x6C, 0xFC, 0xFF]), "JMP ($FFFC)");
+        assert_eq!(disassemble(&[0x20, 0x34, 0x12]), "JSR $1234");
+        assert_eq!(disassemble(&[0xD0, 0x10]), "BNE $8012");
+        assert_eq!(disassemble(&[0x10, 0xFC]), "BPL $7FFE");
+        assert_eq!(disassemble(&[0xA7, 0x10]), "LAX $10");
+        assert_eq!(disassemble(&[0xEB, 0x01]), "SBC #$01");
+        assert_eq!(disassemble(&[0x1C, 0x00, 0x02]), "NOP $0200,X");
+        assert_eq!(disassemble(&[0x9E, 0x00, 0x02]), "SHX $0200,Y");
+        assert_eq!(disassemble(&[0x02]), "JAM");
+        assert_eq!(Instruction::decode(0, &[0x4C, 0x01]), None);
+        assert_eq!(Instruction::decode(0, &[]), None);
+    }
+
+    #[test]
+    fn lengths() {
+        let lengths: usize = (0..=255u8)
+            .map(|opcode| opcode::decode(opcode).1.len())
+            .sum();
+        // 47 of one byte: 25 implied, 4 on the accumulator, 12 JAM and 6 NOP. 81 of three: the
+        // last four columns, the odd rows of columns 9 and B, and JSR.
+        assert_eq!(lengths, 47 + 128 * 2 + 81 * 3);
+        assert!(opcode::documented(0xEA));
+        assert!(!opcode::documented(0xEB));
+        assert_eq!(
+            (0..=255)
+                .filter(|&opcode| opcode::documented(opcode))
+                .count(),
+            151
+        );
+    }
+}
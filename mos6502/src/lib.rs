@@ -0,0 +1,1263 @@
+//! The MOS Technology 6502 microprocessor, the processor of the NES, the Atari 2600 and 8-bit
+//! computers, the Apple II and the Commodore 64 among many others.
+//!
+//! The processor has an accumulator, two index registers, `X` and `Y`, a stack pointer into page
+//! 1 of memory and seven flags. It reads or writes memory on every clock cycle, so instructions
+//! take as many cycles as they make accesses, 2 to 8, and this crate makes those accesses one by
+//! one, dummy ones included, so that a machine can run its other chips in step. Devices interrupt
+//! the program through two lines, NMI and IRQ, which are polled as the 6502 polls them, before the
+//! last cycle of an instruction.
+//!
+//! The opcodes MOS left undocumented run as they do on NMOS 6502s, down to the unstable ones, and
+//! decimal mode can be turned off for the Ricoh 2A03 of the NES, which has none.
+//!
+//! A [`Cpu`] runs against a [`Bus`], which is the memory and devices of a machine:
+//!
+//! ```
+//! use mos6502::bus::Memory;
+//! use mos6502::{Cpu, Register};
+//!
+//! // 0x0200: LDA #$2A; ADC #$01; JAM
+//! let mut memory = Memory::with_program(0x0200, &[0xA9, 0x2A, 0x69, 0x01, 0x02])?;
+//! let mut cpu = Cpu::new();
+//! cpu.set_program_counter(0x0200);
+//! while !cpu.jammed() {
+//!     cpu.step(&mut memory);
+//! }
+//! assert_eq!(cpu.register(Register::A), 0x2B);
+//! assert_eq!(cpu.cycles(), 2 + 2 + 2);
+//! # Ok::<(), mos6502::bus::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` and needs no allocator.
+
+#![no_std]
+
+pub mod bus;
+pub mod disasm;
+pub mod opcode;
+
+use crate::bus::Bus;
+use crate::opcode::{Mode, Op};
+
+/// Length of the state written by [`Cpu::save`].
+pub const STATE_SIZE: usize = 16;
+
+/// The negative flag, set when bit 7 of a result is.
+pub const NEGATIVE: u8 = 0x80;
+
+/// The overflow flag, set when a signed result is out of range.
+pub const OVERFLOW: u8 = 0x40;
+
+/// The break flag, which only exists in the flags pushed on the stack, where it tells `BRK` and
+/// `PHP` from interrupts.
+pub const BREAK: u8 = 0x10;
+
+/// The decimal flag, which makes `ADC` and `SBC` work on binary-coded decimal.
+pub const DECIMAL: u8 = 0x08;
+
+/// The interrupt disable flag, which masks IRQ but not NMI.
+pub const INTERRUPT: u8 = 0x04;
+
+/// The zero flag, set when a result is 0.
+pub const ZERO: u8 = 0x02;
+
+/// The carry flag, set on a carry out of bit 7, or when a subtraction does not borrow.
+pub const CARRY: u8 = 0x01;
+
+/// The bit of the flags that is always set.
+const FLAGS_SET: u8 = 0x20;
+
+/// Where the address of the NMI handler is.
+pub const NMI_VECTOR: u16 = 0xFFFA;
+
+/// Where the address the program starts at is.
+pub const RESET_VECTOR: u16 = 0xFFFC;
+
+/// Where the address of the IRQ and `BRK` handler is.
+pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// What the unstable `ANE` and `LXA` OR the accumulator with, which depends on the chip and its
+/// temperature. 0xEE is the value most 6502s settle on.
+const MAGIC: u8 = 0xEE;
+
+/// A register, as [`Cpu::register`] names it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// The accumulator.
+    A,
+    X,
+    Y,
+    /// The stack pointer, into page 1.
+    S,
+}
+
+/// How an instruction uses the byte it addresses, which decides its dummy accesses.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    /// Read, written back unchanged, then written changed.
+    Modify,
+}
+
+/// The processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    a: u8,
+    x: u8,
+    y: u8,
+    s: u8,
+    /// The flags, without [`BREAK`] and with [`FLAGS_SET`].
+    p: u8,
+    pc: u16,
+    /// Whether the decimal flag makes `ADC` and `SBC` work in decimal.
+    decimal_mode: bool,
+    /// Whether a `JAM` has stopped the processor until it is reset.
+    jammed: bool,
+    /// Whether an interrupt is taken before the next instruction.
+    interrupt: bool,
+    /// Whether the NMI line was pulled on the last cycle.
+    nmi_line: bool,
+    /// Whether the NMI line has been pulled since the last NMI was taken.
+    nmi_edge: bool,
+    /// Whether an interrupt was asked for on the last cycle, and on the one before.
+    poll: bool,
+    previous_poll: bool,
+    /// Clock cycles run since the processor was created.
+    cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    /// Creates a processor as it is powered on, with decimal mode on. It starts the program once
+    /// [`reset`](Cpu::reset).
+    pub fn new() -> Self {
+        Cpu {
+            a: 0,
+            x: 0,
+            y: 0,
+            s: 0,
+            p: FLAGS_SET | INTERRUPT,
+            pc: 0,
+            decimal_mode: true,
+            jammed: false,
+            interrupt: false,
+            nmi_line: false,
+            nmi_edge: false,
+            poll: false,
+            previous_poll: false,
+            cycles: 0,
+        }
+    }
+
+    /// Resets the processor, as pulling its RES line does: in 7 clock cycles, it moves the stack
+    /// pointer down by 3 without writing, disables interrupts, and jumps to the address at
+    /// [`RESET_VECTOR`]. The other registers are kept.
+    pub fn reset(&mut self, bus: &mut impl Bus) {
+        self.jammed = false;
+        self.interrupt = false;
+        self.nmi_edge = false;
+        self.read(bus, self.pc);
+        self.read(bus, self.pc);
+        for _ in 0..3 {
+            self.read(bus, 0x100 | u16::from(self.s));
+            self.s = self.s.wrapping_sub(1);
+        }
+        self.p |= INTERRUPT;
+        self.pc = self.read_vector(bus, RESET_VECTOR);
+        self.poll = false;
+        self.previous_poll = false;
+    }
+
+    /// Returns `register`.
+    pub fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::X => self.x,
+            Register::Y => self.y,
+            Register::S => self.s,
+        }
+    }
+
+    /// Sets `register` to `value`.
+    pub fn set_register(&mut self, register: Register, value: u8) {
+        match register {
+            Register::A => self.a = value,
+            Register::X => self.x = value,
+            Register::Y => self.y = value,
+            Register::S => self.s = value,
+        }
+    }
+
+    /// Returns the flags, as `PHP` would push them but without [`BREAK`].
+    pub fn flags(&self) -> u8 {
+        self.p
+    }
+
+    /// Sets the flags, as `PLP` does: [`BREAK`] is ignored.
+    pub fn set_flags(&mut self, flags: u8) {
+        self.p = flags & !BREAK | FLAGS_SET;
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    /// Jumps to `address`.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    /// Returns whether the decimal flag makes `ADC` and `SBC` work in decimal.
+    pub fn decimal_mode(&self) -> bool {
+        self.decimal_mode
+    }
+
+    /// Turns decimal mode on or off. With it off, as on the Ricoh 2A03, the decimal flag can still
+    /// be set and cleared but changes nothing.
+    pub fn set_decimal_mode(&mut self, on: bool) {
+        self.decimal_mode = on;
+    }
+
+    /// Returns whether a `JAM` has stopped the processor, until it is reset.
+    pub fn jammed(&self) -> bool {
+        self.jammed
+    }
+
+    /// Returns the number of clock cycles run since the processor was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs the next instruction, or takes an interrupt, and returns how many clock cycles it
+    /// took. A jammed processor only reads 0xFFFF, for a cycle.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        let start = self.cycles;
+        if self.jammed {
+            self.read(bus, 0xFFFF);
+        } else if self.interrupt {
+            self.read(bus, self.pc);
+            self.read(bus, self.pc);
+            self.interrupt(bus, false);
+        } else {
+            let opcode = self.fetch(bus);
+            self.execute(opcode, bus);
+            self.interrupt = self.previous_poll;
+        }
+        (self.cycles - start) as u8
+    }
+
+    /// Ends a clock cycle: the interrupt lines are sampled, and the NMI line's edge detected.
+    fn cycle(&mut self, bus: &mut impl Bus) {
+        self.cycles += 1;
+        let nmi = bus.nmi();
+        self.nmi_edge |= nmi && !self.nmi_line;
+        self.nmi_line = nmi;
+        self.previous_poll = self.poll;
+        self.poll = self.nmi_edge || self.p & INTERRUPT == 0 && bus.irq();
+    }
+
+    fn read(&mut self, bus: &mut impl Bus, address: u16) -> u8 {
+        let value = bus.read(address);
+        self.cycle(bus);
+        value
+    }
+
+    fn write(&mut self, bus: &mut impl Bus, address: u16, value: u8) {
+        bus.write(address, value);
+        self.cycle(bus);
+    }
+
+    /// Reads the byte at the program counter, and moves past it.
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = self.read(bus, self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = self.fetch(bus);
+        u16::from_le_bytes([low, self.fetch(bus)])
+    }
+
+    fn read_vector(&mut self, bus: &mut impl Bus, vector: u16) -> u16 {
+        let low = self.read(bus, vector);
+        u16::from_le_bytes([low, self.read(bus, vector + 1)])
+    }
+
+    fn push(&mut self, bus: &mut impl Bus, value: u8) {
+        self.write(bus, 0x100 | u16::from(self.s), value);
+        self.s = self.s.wrapping_sub(1);
+    }
+
+    fn pull(&mut self, bus: &mut impl Bus) -> u8 {
+        self.s = self.s.wrapping_add(1);
+        self.read(bus, 0x100 | u16::from(self.s))
+    }
+
+    /// Reads the top of the stack without pulling it, as instructions that pull do first.
+    fn peek(&mut self, bus: &mut impl Bus) {
+        self.read(bus, 0x100 | u16::from(self.s));
+    }
+
+    /// Pushes the program counter and the flags, and jumps to the handler of NMI if one is
+    /// pending, or else of IRQ and `BRK`. An NMI that comes while an IRQ or a `BRK` is pushing
+    /// takes its place, as on the 6502.
+    fn interrupt(&mut self, bus: &mut impl Bus, brk: bool) {
+        self.push(bus, (self.pc >> 8) as u8);
+        self.push(bus, self.pc as u8);
+        self.push(bus, self.p | if brk { BREAK } else { 0 });
+        let vector = if self.nmi_edge {
+            self.nmi_edge = false;
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        };
+        self.p |= INTERRUPT;
+        self.pc = self.read_vector(bus, vector);
+        // The first instruction of the handler always runs.
+        self.interrupt = false;
+        self.poll = false;
+        self.previous_poll = false;
+    }
+
+    fn set_flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.p |= flag;
+        } else {
+            self.p &= !flag;
+        }
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.p & flag != 0
+    }
+
+    /// Sets the negative and zero flags from `value`, and returns it.
+    fn set_nz(&mut self, value: u8) -> u8 {
+        self.p = self.p & !(NEGATIVE | ZERO) | value & NEGATIVE;
+        self.set_flag(ZERO, value == 0);
+        value
+    }
+
+    /// Returns the index register of `mode`.
+    fn index(&self, mode: Mode) -> u8 {
+        match mode {
+            Mode::Zpy | Mode::Aby | Mode::Izy => self.y,
+            _ => self.x,
+        }
+    }
+
+    /// Fetches the address an indexed mode starts from, in the instruction or, for `(zp),Y`, in
+    /// the zero page.
+    fn base(&mut self, bus: &mut impl Bus, mode: Mode) -> u16 {
+        if mode == Mode::Izy {
+            let pointer = self.fetch(bus);
+            let low = self.read(bus, u16::from(pointer));
+            u16::from_le_bytes([low, self.read(bus, u16::from(pointer.wrapping_add(1)))])
+        } else {
+            self.fetch16(bus)
+        }
+    }
+
+    /// Fetches the operand of `mode` and returns the address it is at. Indexing across a page
+    /// first reads from the address before its high byte is fixed, which instructions that write
+    /// do even when it needs no fixing.
+    fn address(&mut self, bus: &mut impl Bus, mode: Mode, access: Access) -> u16 {
+        match mode {
+            Mode::Zp => u16::from(self.fetch(bus)),
+            Mode::Zpx | Mode::Zpy => {
+                let base = self.fetch(bus);
+                self.read(bus, u16::from(base));
+                u16::from(base.wrapping_add(self.index(mode)))
+            }
+            Mode::Abs => self.fetch16(bus),
+            Mode::Izx => {
+                let pointer = self.fetch(bus);
+                self.read(bus, u16::from(pointer));
+                let pointer = pointer.wrapping_add(self.x);
+                let low = self.read(bus, u16::from(pointer));
+                u16::from_le_bytes([low, self.read(bus, u16::from(pointer.wrapping_add(1)))])
+            }
+            _ => {
+                let base = self.base(bus, mode);
+                let address = base.wrapping_add(u16::from(self.index(mode)));
+                if address & 0xFF00 != base & 0xFF00 || access != Access::Read {
+                    self.read(bus, base & 0xFF00 | address & 0x00FF);
+                }
+                address
+            }
+        }
+    }
+
+    /// Returns the operand of an instruction that reads it.
+    fn operand(&mut self, bus: &mut impl Bus, mode: Mode) -> u8 {
+        if mode == Mode::Imm {
+            self.fetch(bus)
+        } else {
+            let address = self.address(bus, mode, Access::Read);
+            self.read(bus, address)
+        }
+    }
+
+    fn execute(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let (op, mode) = opcode::decode(opcode);
+        match op {
+            Op::Adc
+            | Op::Alr
+            | Op::Anc
+            | Op::And
+            | Op::Ane
+            | Op::Arr
+            | Op::Bit
+            | Op::Cmp
+            | Op::Cpx
+            | Op::Cpy
+            | Op::Eor
+            | Op::Las
+            | Op::Lax
+            | Op::Lda
+            | Op::Ldx
+            | Op::Ldy
+            | Op::Lxa
+            | Op::Ora
+            | Op::Sbc
+            | Op::Sbx
+                if mode != Mode::Imp =>
+            {
+                let value = self.operand(bus, mode);
+                self.read_operation(op, value);
+            }
+            Op::Nop if mode != Mode::Imp => {
+                self.operand(bus, mode);
+            }
+            Op::Sta | Op::Stx | Op::Sty | Op::Sax => {
+                let address = self.address(bus, mode, Access::Write);
+                let value = match op {
+                    Op::Sta => self.a,
+                    Op::Stx => self.x,
+                    Op::Sty => self.y,
+                    _ => self.a & self.x,
+                };
+                self.write(bus, address, value);
+            }
+            Op::Sha | Op::Shx | Op::Shy | Op::Tas => {
+                let value = match op {
+                    Op::Sha => self.a & self.x,
+                    Op::Shx => self.x,
+                    Op::Shy => self.y,
+                    _ => {
+                        self.s = self.a & self.x;
+                        self.s
+                    }
+                };
+                self.unstable_store(bus, mode, value);
+            }
+            Op::Asl | Op::Lsr | Op::Rol | Op::Ror if mode == Mode::Acc => {
+                self.read(bus, self.pc);
+                self.a = self.modify(op, self.a);
+            }
+            Op::Asl | Op::Lsr | Op::Rol | Op::Ror | Op::Inc | Op::Dec => {
+                let address = self.address(bus, mode, Access::Modify);
+                let value = self.read(bus, address);
+                self.write(bus, address, value);
+                let result = self.modify(op, value);
+                self.write(bus, address, result);
+            }
+            Op::Slo | Op::Rla | Op::Sre | Op::Rra | Op::Dcp | Op::Isc => {
+                let address = self.address(bus, mode, Access::Modify);
+                let value = self.read(bus, address);
+                self.write(bus, address, value);
+                let result = self.modify(op, value);
+                self.write(bus, address, result);
+                let then = match op {
+                    Op::Slo => Op::Ora,
+                    Op::Rla => Op::And,
+                    Op::Sre => Op::Eor,
+                    Op::Rra => Op::Adc,
+                    Op::Dcp => Op::Cmp,
+                    _ => Op::Sbc,
+                };
+                self.read_operation(then, result);
+            }
+            Op::Bpl => self.branch(bus, !self.flag(NEGATIVE)),
+            Op::Bmi => self.branch(bus, self.flag(NEGATIVE)),
+            Op::Bvc => self.branch(bus, !self.flag(OVERFLOW)),
+            Op::Bvs => self.branch(bus, self.flag(OVERFLOW)),
+            Op::Bcc => self.branch(bus, !self.flag(CARRY)),
+            Op::Bcs => self.branch(bus, self.flag(CARRY)),
+            Op::Bne => self.branch(bus, !self.flag(ZERO)),
+            Op::Beq => self.branch(bus, self.flag(ZERO)),
+            Op::Brk => {
+                self.fetch(bus);
+                self.interrupt(bus, true);
+            }
+            Op::Jmp if mode == Mode::Abs => self.pc = self.fetch16(bus),
+            Op::Jmp => {
+                // The high byte of the address is read from the same page as the low byte.
+                let pointer = self.fetch16(bus);
+                let low = self.read(bus, pointer);
+                let high = self.read(bus, pointer & 0xFF00 | pointer.wrapping_add(1) & 0x00FF);
+                self.pc = u16::from_le_bytes([low, high]);
+            }
+            Op::Jsr => {
+                let low = self.fetch(bus);
+                self.peek(bus);
+                self.push(bus, (self.pc >> 8) as u8);
+                self.push(bus, self.pc as u8);
+                let high = self.read(bus, self.pc);
+                self.pc = u16::from_le_bytes([low, high]);
+            }
+            Op::Rts => {
+                self.read(bus, self.pc);
+                self.peek(bus);
+                let low = self.pull(bus);
+                self.pc = u16::from_le_bytes([low, self.pull(bus)]);
+                self.fetch(bus);
+            }
+            Op::Rti => {
+                self.read(bus, self.pc);
+                self.peek(bus);
+                let flags = self.pull(bus);
+                self.set_flags(flags);
+                let low = self.pull(bus);
+                self.pc = u16::from_le_bytes([low, self.pull(bus)]);
+            }
+            Op::Pha | Op::Php => {
+                self.read(bus, self.pc);
+                let value = if op == Op::Pha {
+                    self.a
+                } else {
+                    self.p | BREAK
+                };
+                self.push(bus, value);
+            }
+            Op::Pla | Op::Plp => {
+                self.read(bus, self.pc);
+                self.peek(bus);
+                let value = self.pull(bus);
+                if op == Op::Pla {
+                    self.a = self.set_nz(value);
+                } else {
+                    self.set_flags(value);
+                }
+            }
+            Op::Jam => {
+                self.read(bus, self.pc);
+                self.jammed = true;
+            }
+            _ => {
+                self.read(bus, self.pc);
+                self.implied(op);
+            }
+        }
+    }
+
+    /// Runs an instruction of two cycles that only works on registers and flags.
+    fn implied(&mut self, op: Op) {
+        match op {
+            Op::Clc => self.set_flag(CARRY, false),
+            Op::Cld => self.set_flag(DECIMAL, false),
+            Op::Cli => self.set_flag(INTERRUPT, false),
+            Op::Clv => self.set_flag(OVERFLOW, false),
+            Op::Sec => self.set_flag(CARRY, true),
+            Op::Sed => self.set_flag(DECIMAL, true),
+            Op::Sei => self.set_flag(INTERRUPT, true),
+            Op::Tax => self.x = self.set_nz(self.a),
+            Op::Tay => self.y = self.set_nz(self.a),
+            Op::Tsx => self.x = self.set_nz(self.s),
+            Op::Txa => self.a = self.set_nz(self.x),
+            Op::Txs => self.s = self.x,
+            Op::Tya => self.a = self.set_nz(self.y),
+            Op::Inx => self.x = self.set_nz(self.x.wrapping_add(1)),
+            Op::Iny => self.y = self.set_nz(self.y.wrapping_add(1)),
+            Op::Dex => self.x = self.set_nz(self.x.wrapping_sub(1)),
+            Op::Dey => self.y = self.set_nz(self.y.wrapping_sub(1)),
+            _ => {}
+        }
+    }
+
+    /// Branches if `taken`, a cycle later, and another if the branch crosses a page. Interrupts
+    /// are not polled again in the cycle a branch takes within the page, so one asked for then
+    /// waits for the next instruction.
+    fn branch(&mut self, bus: &mut impl Bus, taken: bool) {
+        let offset = self.fetch(bus) as i8;
+        if !taken {
+            return;
+        }
+        let poll = self.previous_poll;
+        self.read(bus, self.pc);
+        let target = self.pc.wrapping_add(offset as u16);
+        if target & 0xFF00 == self.pc & 0xFF00 {
+            self.previous_poll = poll;
+        } else {
+            self.read(bus, self.pc & 0xFF00 | target & 0x00FF);
+        }
+        self.pc = target;
+    }
+
+    /// Stores `value` ANDed with the high byte of the base address plus 1, for `SHA`, `SHX`,
+    /// `SHY` and `TAS`. When indexing crosses a page, the high byte of the address is replaced by
+    /// the value stored.
+    fn unstable_store(&mut self, bus: &mut impl Bus, mode: Mode, value: u8) {
+        let base = self.base(bus, mode);
+        let address = base.wrapping_add(u16::from(self.index(mode)));
+        self.read(bus, base & 0xFF00 | address & 0x00FF);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let address = if address & 0xFF00 != base & 0xFF00 {
+            u16::from(value) << 8 | address & 0x00FF
+        } else {
+            address
+        };
+        self.write(bus, address, value);
+    }
+
+    /// Runs an operation that reads `value`.
+    fn read_operation(&mut self, op: Op, value: u8) {
+        match op {
+            Op::Lda => self.a = self.set_nz(value),
+            Op::Ldx => self.x = self.set_nz(value),
+            Op::Ldy => self.y = self.set_nz(value),
+            Op::Lax => {
+                self.a = self.set_nz(value);
+                self.x = value;
+            }
+            Op::And => self.a = self.set_nz(self.a & value),
+            Op::Ora => self.a = self.set_nz(self.a | value),
+            Op::Eor => self.a = self.set_nz(self.a ^ value),
+            Op::Adc => self.adc(value),
+            Op::Sbc => self.sbc(value),
+            Op::Cmp => self.compare(self.a, value),
+            Op::Cpx => self.compare(self.x, value),
+            Op::Cpy => self.compare(self.y, value),
+            Op::Bit => {
+                self.p = self.p & !(NEGATIVE | OVERFLOW) | value & (NEGATIVE | OVERFLOW);
+                self.set_flag(ZERO, self.a & value == 0);
+            }
+            Op::Las => {
+                self.s &= value;
+                self.a = self.set_nz(self.s);
+                self.x = self.s;
+            }
+            Op::Anc => {
+                self.a = self.set_nz(self.a & value);
+                self.set_flag(CARRY, self.a & 0x80 != 0);
+            }
+            Op::Alr => self.a = self.modify(Op::Lsr, self.a & value),
+            Op::Arr => self.arr(value),
+            Op::Ane => self.a = self.set_nz((self.a | MAGIC) & self.x & value),
+            Op::Lxa => {
+                self.a = self.set_nz((self.a | MAGIC) & value);
+                self.x = self.a;
+            }
+            _ => {
+                let and = self.a & self.x;
+                self.set_flag(CARRY, and >= value);
+                self.x = self.set_nz(and.wrapping_sub(value));
+            }
+        }
+    }
+
+    /// Returns `value` shifted, rotated, incremented or decremented by `op`, setting the flags.
+    fn modify(&mut self, op: Op, value: u8) -> u8 {
+        let carry = self.flag(CARRY) as u8;
+        let result = match op {
+            Op::Asl | Op::Slo => value << 1,
+            Op::Lsr | Op::Sre => value >> 1,
+            Op::Rol | Op::Rla => value << 1 | carry,
+            Op::Ror | Op::Rra => value >> 1 | carry << 7,
+            Op::Inc | Op::Isc => value.wrapping_add(1),
+            _ => value.wrapping_sub(1),
+        };
+        match op {
+            Op::Asl | Op::Slo | Op::Rol | Op::Rla => self.set_flag(CARRY, value & 0x80 != 0),
+            Op::Lsr | Op::Sre | Op::Ror | Op::Rra => self.set_flag(CARRY, value & 0x01 != 0),
+            _ => {}
+        }
+        self.set_nz(result)
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.set_flag(CARRY, register >= value);
+        self.set_nz(register.wrapping_sub(value));
+    }
+
+    /// Adds `value` and the carry to the accumulator in binary, setting every flag.
+    fn add(&mut self, value: u8) {
+        let sum = u16::from(self.a) + u16::from(value) + u16::from(self.flag(CARRY));
+        let result = sum as u8;
+        self.set_flag(OVERFLOW, (self.a ^ result) & (value ^ result) & 0x80 != 0);
+        self.set_flag(CARRY, sum > 0xFF);
+        self.a = self.set_nz(result);
+    }
+
+    /// Adds with carry. In decimal, the 6502 sets the zero flag from the binary sum, and the
+    /// negative and overflow flags from the sum before its high digit is adjusted.
+    fn adc(&mut self, value: u8) {
+        if !(self.decimal_mode && self.flag(DECIMAL)) {
+            return self.add(value);
+        }
+        let a = self.a;
+        let carry = i16::from(self.flag(CARRY));
+        let mut low = i16::from(a & 0x0F) + i16::from(value & 0x0F) + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let signed = i16::from((a & 0xF0) as i8) + i16::from((value & 0xF0) as i8) + low;
+        let mut sum = i16::from(a & 0xF0) + i16::from(value & 0xF0) + low;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.set_nz(signed as u8);
+        self.set_flag(ZERO, a.wrapping_add(value).wrapping_add(carry as u8) == 0);
+        self.set_flag(OVERFLOW, !(-128..=127).contains(&signed));
+        self.set_flag(CARRY, sum >= 0x100);
+        self.a = sum as u8;
+    }
+
+    /// Subtracts with borrow. In decimal, the 6502 sets every flag from the binary difference.
+    fn sbc(&mut self, value: u8) {
+        let a = self.a;
+        let carry = i16::from(self.flag(CARRY));
+        self.add(!value);
+        if self.decimal_mode && self.flag(DECIMAL) {
+            let mut low = i16::from(a & 0x0F) - i16::from(value & 0x0F) + carry - 1;
+            if low < 0 {
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
+            let mut difference = i16::from(a & 0xF0) - i16::from(value & 0xF0) + low;
+            if difference < 0 {
+                difference -= 0x60;
+            }
+            self.a = difference as u8;
+        }
+    }
+
+    /// ANDs the accumulator with `value` and rotates it right. The carry comes from bit 6 of the
+    /// result and the overflow flag from bits 6 and 5, except in decimal, where the 6502 also
+    /// adjusts the result as a decimal addition would.
+    fn arr(&mut self, value: u8) {
+        let and = self.a & value;
+        let carry = self.flag(CARRY);
+        let result = and >> 1 | (carry as u8) << 7;
+        if !(self.decimal_mode && self.flag(DECIMAL)) {
+            self.a = self.set_nz(result);
+            self.set_flag(CARRY, result & 0x40 != 0);
+            self.set_flag(OVERFLOW, (result ^ result << 1) & 0x40 != 0);
+            return;
+        }
+        self.set_flag(NEGATIVE, carry);
+        self.set_flag(ZERO, result == 0);
+        self.set_flag(OVERFLOW, (and ^ result) & 0x40 != 0);
+        let mut result = result;
+        if (and & 0x0F) + (and & 0x01) > 0x05 {
+            result = result & 0xF0 | result.wrapping_add(0x06) & 0x0F;
+        }
+        let high = u16::from(and & 0xF0) + u16::from(and & 0x10) > 0x50;
+        if high {
+            result = result.wrapping_add(0x60);
+        }
+        self.set_flag(CARRY, high);
+        self.a = result;
+    }
+
+    /// Writes the whole processor, for save states.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..5].copy_from_slice(&[self.a, self.x, self.y, self.s, self.p]);
+        state[5..7].copy_from_slice(&self.pc.to_be_bytes());
+        let bits = [
+            self.decimal_mode,
+            self.jammed,
+            self.interrupt,
+            self.nmi_line,
+            self.nmi_edge,
+            self.poll,
+            self.previous_poll,
+        ];
+        state[7] = bits
+            .iter()
+            .enumerate()
+            .fold(0, |byte, (n, &bit)| byte | (bit as u8) << n);
+        state[8..].copy_from_slice(&self.cycles.to_be_bytes());
+        state
+    }
+
+    /// Restores the processor from a state written by [`Cpu::save`].
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        self.a = state[0];
+        self.x = state[1];
+        self.y = state[2];
+        self.s = state[3];
+        self.set_flags(state[4]);
+        self.pc = u16::from_be_bytes([state[5], state[6]]);
+        let bit = |n: u8| state[7] & 1 << n != 0;
+        self.decimal_mode = bit(0);
+        self.jammed = bit(1);
+        self.interrupt = bit(2);
+        self.nmi_line = bit(3);
+        self.nmi_edge = bit(4);
+        self.poll = bit(5);
+        self.previous_poll = bit(6);
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&state[8..]);
+        self.cycles = u64::from_be_bytes(cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::bus::Memory;
+    use std::vec::Vec;
+
+    /// Clock cycles of each opcode, without crossing a page or taking a branch, from MOS's data
+    /// sheets and, for the undocumented opcodes, from the "NMOS 6510 Unintended Opcodes" document.
+    #[rustfmt::skip]
+    const CYCLES: [u8; 256] = [
+        7, 6, 0, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 00
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 10
+        6, 6, 0, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 20
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 30
+        6, 6, 0, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 40
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 50
+        6, 6, 0, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 60
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 70
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 80
+        2, 6, 0, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 90
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // A0
+        2, 5, 0, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // B0
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // C0
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // D0
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // E0
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // F0
+    ];
+
+    /// Memory that records every access, with interrupt lines driven by the test.
+    struct Machine {
+        memory: Memory,
+        /// The address of each access, and the value of each write.
+        accesses: Vec<(u16, Option<u8>)>,
+        /// The access from which the IRQ line is pulled.
+        irq_from: Option<usize>,
+        /// The accesses during which the NMI line is pulled.
+        nmi: core::ops::Range<usize>,
+    }
+
+    impl Machine {
+        /// Returns a machine with `program` at 0x0200, where the processor starts, and handlers of
+        /// NMI at 0x0300 and of IRQ at 0x0400.
+        fn new(program: &[u8]) -> (Cpu, Self) {
+            let mut memory = Memory::with_program(0x0200, program).unwrap();
+            memory.0[0xFFFA..].copy_from_slice(&[0x00, 0x03, 0x00, 0x02, 0x00, 0x04]);
+            let mut machine = Machine {
+                memory,
+                accesses: Vec::new(),
+                irq_from: None,
+                nmi: 0..0,
+            };
+            let mut cpu = Cpu::new();
+            cpu.reset(&mut machine);
+            machine.accesses.clear();
+            (cpu, machine)
+        }
+    }
+
+    impl Bus for Machine {
+        fn read(&mut self, address: u16) -> u8 {
+            self.accesses.push((address, None));
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.accesses.push((address, Some(value)));
+            self.memory.write(address, value);
+        }
+
+        fn nmi(&mut self) -> bool {
+            self.nmi.contains(&(self.accesses.len() - 1))
+        }
+
+        fn irq(&mut self) -> bool {
+            self.irq_from.is_some_and(|from| self.accesses.len() > from)
+        }
+    }
+
+    /// Runs `cpu` until the program counter reaches `address`.
+    fn run_to(cpu: &mut Cpu, machine: &mut Machine, address: u16) {
+        while cpu.program_counter() != address {
+            cpu.step(machine);
+        }
+    }
+
+    #[test]
+    fn takes_every_opcodes_cycles() {
+        for opcode in 0..=255 {
+            let (op, mode) = opcode::decode(opcode);
+            if mode == Mode::Rel || op == Op::Jam {
+                continue;
+            }
+            // Operands of 0x0310, or 0x10 in the zero page, which points to 0x0300.
+            let (mut cpu, mut machine) = Machine::new(&[opcode, 0x10, 0x03]);
+            machine.memory.0[0x10..0x12].copy_from_slice(&[0x00, 0x03]);
+            let cycles = cpu.step(&mut machine);
+            assert_eq!(cycles, CYCLES[opcode as usize], "{:02X}", opcode);
+            assert_eq!(machine.accesses.len(), cycles as usize, "{:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn crosses_pages_and_branches() {
+        // LDX #$FF; LDY #$01; LDA $0210,X; LDA $0210,Y; STA $0200,Y; LDA ($10),Y
+        let program = [
+            0xA2, 0xFF, 0xA0, 0x01, 0xBD, 0x10, 0x02, 0xB9, 0x10, 0x02, 0x99, 0x00, 0x02, 0xB1,
+            0x10,
+        ];
+        let (mut cpu, mut machine) = Machine::new(&program);
+        machine.memory.0[0x10..0x12].copy_from_slice(&[0xFF, 0x02]);
+        let cycles: Vec<u8> = (0..6).map(|_| cpu.step(&mut machine)).collect();
+        assert_eq!(cycles, [2, 2, 5, 4, 5, 6]);
+
+        // CLC; BCS +2; BCC +0; BCC -8, to 0x01FF
+        let (mut cpu, mut machine) = Machine::new(&[0x18, 0xB0, 0x02, 0x90, 0x00, 0x90, 0xF8]);
+        let cycles: Vec<u8> = (0..4).map(|_| cpu.step(&mut machine)).collect();
+        assert_eq!(cycles, [2, 2, 3, 4]);
+        assert_eq!(cpu.program_counter(), 0x01FF);
+        // The branch reads the next opcode, then the target with its old high byte.
+        let last = &machine.accesses[machine.accesses.len() - 2..];
+        assert_eq!(last, [(0x0207, None), (0x02FF, None)]);
+    }
+
+    #[test]
+    fn makes_dummy_accesses() {
+        // LDX #$01; INC $0310,X; LDA $02F0,X; ASL A; PHA; PLA; RTS
+        let program = [
+            0xA2, 0x01, 0xFE, 0x10, 0x03, 0xBD, 0xFF, 0x02, 0x0A, 0x48, 0x68,
+        ];
+        let (mut cpu, mut machine) = Machine::new(&program);
+        machine.memory.0[0x0311] = 0x41;
+        cpu.step(&mut machine);
+        machine.accesses.clear();
+        cpu.step(&mut machine);
+        let inc = [
+            (0x0202, None),
+            (0x0203, None),
+            (0x0204, None),
+            (0x0311, None),
+            (0x0311, None),
+            (0x0311, Some(0x41)),
+            (0x0311, Some(0x42)),
+        ];
+        assert_eq!(machine.accesses, inc);
+        machine.accesses.clear();
+        cpu.step(&mut machine);
+        let lda = [
+            (0x0205, None),
+            (0x0206, None),
+            (0x0207, None),
+            (0x0200, None),
+            (0x0300, None),
+        ];
+        assert_eq!(machine.accesses, lda);
+        machine.accesses.clear();
+        for _ in 0..3 {
+            cpu.step(&mut machine);
+        }
+        let stack = [
+            (0x0208, None),
+            (0x0209, None),
+            (0x0209, None),
+            (0x020A, None),
+            (0x01FD, Some(0x00)),
+            (0x020A, None),
+            (0x020B, None),
+            (0x01FC, None),
+            (0x01FD, None),
+        ];
+        assert_eq!(machine.accesses, stack);
+    }
+
+    /// Returns the accumulator and flags after `op` on `a` and `value` with the carry in.
+    fn arithmetic(op: Op, a: u8, value: u8, carry: bool, decimal: bool) -> (u8, u8) {
+        let mut cpu = Cpu::new();
+        cpu.a = a;
+        cpu.set_flag(CARRY, carry);
+        cpu.set_flag(DECIMAL, decimal);
+        cpu.read_operation(op, value);
+        (cpu.a, cpu.p & (NEGATIVE | OVERFLOW | ZERO | CARRY))
+    }
+
+    /// Returns the negative and zero flags of `value`.
+    fn nz(value: u8) -> u8 {
+        value & NEGATIVE | if value == 0 { ZERO } else { 0 }
+    }
+
+    #[test]
+    fn adds_and_subtracts() {
+        for a in 0..=255u8 {
+            for value in 0..=255u8 {
+                for &carry in &[false, true] {
+                    let sum = i16::from(a as i8) + i16::from(value as i8) + carry as i16;
+                    let result = a.wrapping_add(value).wrapping_add(carry as u8);
+                    let mut flags = nz(result);
+                    if !(-128..=127).contains(&sum) {
+                        flags |= OVERFLOW;
+                    }
+                    if u16::from(a) + u16::from(value) + carry as u16 > 0xFF {
+                        flags |= CARRY;
+                    }
+                    assert_eq!(arithmetic(Op::Adc, a, value, carry, false), (result, flags));
+
+                    let difference = i16::from(a as i8) - i16::from(value as i8) - !carry as i16;
+                    let result = a.wrapping_sub(value).wrapping_sub(!carry as u8);
+                    let mut flags = nz(result);
+                    if !(-128..=127).contains(&difference) {
+                        flags |= OVERFLOW;
+                    }
+                    if u16::from(a) >= u16::from(value) + !carry as u16 {
+                        flags |= CARRY;
+                    }
+                    assert_eq!(arithmetic(Op::Sbc, a, value, carry, false), (result, flags));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adds_and_subtracts_in_decimal() {
+        let bcd = |n: u8| ((n / 10) << 4) | (n % 10);
+        for a in 0..100 {
+            for value in 0..100 {
+                for &carry in &[false, true] {
+                    let (sum, flags) = arithmetic(Op::Adc, bcd(a), bcd(value), carry, true);
+                    let expected = a + value + carry as u8;
+                    assert_eq!(sum, bcd(expected % 100));
+                    assert_eq!(flags & CARRY != 0, expected >= 100);
+
+                    let (difference, flags) = arithmetic(Op::Sbc, bcd(a), bcd(value), carry, true);
+                    let expected = i16::from(a) - i16::from(value) - !carry as i16;
+                    assert_eq!(difference, bcd(expected.rem_euclid(100) as u8));
+                    assert_eq!(flags & CARRY != 0, expected >= 0);
+                }
+            }
+        }
+        // The zero flag comes from the binary sum, and the negative and overflow flags from the
+        // sum before its high digit is adjusted.
+        assert_eq!(
+            arithmetic(Op::Adc, 0x99, 0x01, false, true),
+            (0x00, NEGATIVE | CARRY)
+        );
+        let flags = NEGATIVE | OVERFLOW | CARRY;
+        assert_eq!(arithmetic(Op::Adc, 0x50, 0x50, false, true), (0x00, flags));
+        // The Ricoh 2A03 has no decimal mode.
+        let mut cpu = Cpu::new();
+        cpu.set_decimal_mode(false);
+        cpu.set_flag(DECIMAL, true);
+        cpu.a = 0x09;
+        cpu.adc(0x01);
+        assert_eq!(cpu.a, 0x0A);
+    }
+
+    #[test]
+    fn runs_undocumented_opcodes() {
+        assert_eq!(
+            arithmetic(Op::Anc, 0x81, 0xFF, false, false),
+            (0x81, NEGATIVE | CARRY)
+        );
+        assert_eq!(arithmetic(Op::Alr, 0x03, 0xFF, false, false), (0x01, CARRY));
+        assert_eq!(
+            arithmetic(Op::Arr, 0xFF, 0xC0, true, false),
+            (0xE0, NEGATIVE | CARRY)
+        );
+        assert_eq!(
+            arithmetic(Op::Arr, 0xFF, 0x40, false, false),
+            (0x20, OVERFLOW)
+        );
+        assert_eq!(
+            arithmetic(Op::Arr, 0xFF, 0x5F, false, true),
+            (0x85, OVERFLOW | CARRY)
+        );
+        assert_eq!(arithmetic(Op::Ane, 0x00, 0xFF, false, false), (0x00, ZERO));
+        assert_eq!(arithmetic(Op::Lxa, 0x01, 0x0F, false, false), (0x0F, 0));
+
+        // LDA #$F0; LDX #$3C; SAX $10; LAX $10; SBX #$31; DCP $10; ISC $10; SLO $10; RLA $10;
+        // SRE $10; RRA $10; LAS $0210,Y
+        let program = [
+            0xA9, 0xF0, 0xA2, 0x3C, 0x87, 0x10, 0xA7, 0x10, 0xCB, 0x31, 0xC7, 0x10, 0xE7, 0x10,
+            0x07, 0x10, 0x27, 0x10, 0x47, 0x10, 0x67, 0x10, 0xBB, 0x10, 0x02,
+        ];
+        let (mut cpu, mut machine) = Machine::new(&program);
+        let mut step = |expected: [u8; 3]| {
+            cpu.step(&mut machine);
+            let registers = [cpu.a, cpu.x, machine.memory.0[0x10]];
+            assert_eq!(registers, expected, "{:04X}", cpu.pc);
+            cpu.p
+        };
+        step([0xF0, 0x00, 0x00]);
+        step([0xF0, 0x3C, 0x00]);
+        step([0xF0, 0x3C, 0x30]);
+        step([0x30, 0x30, 0x30]);
+        assert_eq!(step([0x30, 0xFF, 0x30]) & (CARRY | NEGATIVE), NEGATIVE);
+        assert_eq!(step([0x30, 0xFF, 0x2F]) & (CARRY | ZERO), CARRY);
+        step([0x00, 0xFF, 0x30]);
+        step([0x60, 0xFF, 0x60]);
+        step([0x40, 0xFF, 0xC0]);
+        step([0x20, 0xFF, 0x60]);
+        step([0x50, 0xFF, 0x30]);
+        // 0x0210 holds RLA's 0x27, ANDed with the stack pointer.
+        step([0x25, 0x25, 0x30]);
+        assert_eq!(cpu.s, 0x25);
+    }
+
+    #[test]
+    fn stores_unstably_across_pages() {
+        // LDX #$FF; LDY #$11; SHY $02F0,X; SHY $0300,X
+        let program = [0xA2, 0xFF, 0xA0, 0x11, 0x9C, 0xF0, 0x02, 0x9C, 0x00, 0x03];
+        let (mut cpu, mut machine) = Machine::new(&program);
+        for _ in 0..4 {
+            cpu.step(&mut machine);
+        }
+        // 0x11 & 0x03, at 0x03EF with the high byte of the address replaced by the value.
+        assert_eq!(
+            machine.accesses[machine.accesses.len() - 6],
+            (0x01EF, Some(0x01))
+        );
+        // Without crossing, 0x11 & 0x04 at 0x03FF.
+        assert_eq!(machine.accesses.last(), Some(&(0x03FF, Some(0x00))));
+    }
+
+    #[test]
+    fn jumps_and_calls() {
+        // JSR $0210; JMP ($02FF); at 0x0210: PHP; PLA; RTS
+        let (mut cpu, mut machine) = Machine::new(&[0x20, 0x10, 0x02, 0x6C, 0xFF, 0x02]);
+        machine.memory.0[0x0210..0x0213].copy_from_slice(&[0x08, 0x68, 0x60]);
+        // The high byte of the address is read from 0x0200, not 0x0300.
+        machine.memory.0[0x02FF] = 0x34;
+        machine.memory.0[0x0300] = 0x56;
+        cpu.step(&mut machine);
+        assert_eq!((cpu.pc, cpu.s), (0x0210, 0xFB));
+        assert_eq!(machine.memory.0[0x01FC..0x01FE], [0x02, 0x02]);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        // PHP pushes the break flag.
+        assert_eq!(cpu.a, FLAGS_SET | BREAK | INTERRUPT);
+        cpu.step(&mut machine);
+        assert_eq!((cpu.pc, cpu.s), (0x0203, 0xFD));
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x2034);
+    }
+
+    #[test]
+    fn takes_interrupts() {
+        // CLI; NOP; NOP, with IRQ pulled from the start.
+        let (mut cpu, mut machine) = Machine::new(&[0x58, 0xEA, 0xEA]);
+        machine.irq_from = Some(0);
+        cpu.step(&mut machine);
+        // The interrupt waits for the instruction after CLI.
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0202);
+        assert_eq!(cpu.step(&mut machine), 7);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(machine.memory.0[0x01FB..0x01FE], [FLAGS_SET, 0x02, 0x02]);
+        assert!(cpu.flag(INTERRUPT));
+
+        // SEI is too late to stop an IRQ pulled before it, which pushes the flags SEI set.
+        let (mut cpu, mut machine) = Machine::new(&[0x58, 0xEA, 0x78, 0xEA]);
+        machine.memory.0[0x0400] = 0x40;
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        machine.irq_from = Some(machine.accesses.len());
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0400);
+        machine.irq_from = None;
+        cpu.step(&mut machine);
+        assert_eq!((cpu.pc, cpu.flag(INTERRUPT)), (0x0203, true));
+
+        // An IRQ pulled on the second cycle of LDA $10 is taken after it, but one pulled on the
+        // second cycle of a branch taken within its page waits for another instruction.
+        let (mut cpu, mut machine) = Machine::new(&[0x58, 0xA5, 0x10, 0xD0, 0x00, 0xEA]);
+        cpu.step(&mut machine);
+        machine.irq_from = Some(machine.accesses.len() + 1);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0400);
+        let (mut cpu, mut machine) = Machine::new(&[0x58, 0xA5, 0x10, 0xD0, 0x00, 0xEA]);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        machine.irq_from = Some(machine.accesses.len() + 1);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0206);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0400);
+    }
+
+    #[test]
+    fn takes_nmis_on_their_edge() {
+        // NOP; NOP; BRK, with NMI pulled for the whole program, and RTI at 0x0300.
+        let (mut cpu, mut machine) = Machine::new(&[0xEA, 0xEA, 0x00, 0x00, 0xEA]);
+        machine.memory.0[0x0300] = 0x40;
+        machine.nmi = 0..usize::MAX;
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0300);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0201);
+        run_to(&mut cpu, &mut machine, 0x0202);
+        // BRK skips a byte, and pushes the break flag.
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0400);
+        assert_eq!(
+            machine.memory.0[0x01FB..0x01FE],
+            [FLAGS_SET | BREAK | INTERRUPT, 0x04, 0x02]
+        );
+
+        // An NMI pulled while BRK pushes takes its place, and still pushes the break flag.
+        let (mut cpu, mut machine) = Machine::new(&[0x00, 0x00]);
+        machine.nmi = 3..4;
+        cpu.step(&mut machine);
+        assert_eq!(cpu.pc, 0x0300);
+        assert_eq!(machine.memory.0[0x01FB], FLAGS_SET | BREAK | INTERRUPT);
+        assert!(!cpu.interrupt && !cpu.nmi_edge);
+    }
+
+    #[test]
+    fn resets_jams_and_saves() {
+        let (mut cpu, mut machine) = Machine::new(&[0xA9, 0x2A, 0x02, 0xEA]);
+        assert_eq!((cpu.pc, cpu.s, cpu.cycles), (0x0200, 0xFD, 7));
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert!(cpu.jammed());
+        assert_eq!(cpu.step(&mut machine), 1);
+        assert_eq!(
+            (cpu.pc, machine.accesses.last()),
+            (0x0203, Some(&(0xFFFF, None)))
+        );
+
+        let state = cpu.save();
+        let mut restored = Cpu::new();
+        restored.restore(&state);
+        assert_eq!(restored, cpu);
+
+        cpu.reset(&mut machine);
+        assert!(!cpu.jammed());
+        assert_eq!((cpu.pc, cpu.s, cpu.a), (0x0200, 0xFA, 0x2A));
+        assert!(cpu.flag(INTERRUPT));
+        machine.nmi = machine.accesses.len()..usize::MAX;
+        cpu.step(&mut machine);
+        let state = cpu.save();
+        restored.restore(&state);
+        assert_eq!(restored, cpu);
+        assert!(restored.interrupt);
+    }
+}
@@ -0,0 +1,199 @@
+//! The instruction set: what each of the 256 opcodes does and how it addresses its operand,
+//! including the opcodes MOS left undocumented, which are named as in the "NMOS 6510 Unintended
+//! Opcodes" document.
+
+/// How an instruction finds its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// No operand, or one implied by the instruction, such as `INX`.
+    Imp,
+    /// The accumulator, as in `ASL A`.
+    Acc,
+    /// The byte following the opcode, as in `LDA #$2A`.
+    Imm,
+    /// A byte of the zero page, as in `LDA $10`.
+    Zp,
+    /// A byte of the zero page indexed by `X`, wrapping within it, as in `LDA $10,X`.
+    Zpx,
+    /// A byte of the zero page indexed by `Y`, as in `LDX $10,Y`.
+    Zpy,
+    /// A 16-bit address, as in `LDA $1234`.
+    Abs,
+    /// A 16-bit address indexed by `X`, as in `LDA $1234,X`.
+    Abx,
+    /// A 16-bit address indexed by `Y`, as in `LDA $1234,Y`.
+    Aby,
+    /// The address in the zero page at the byte indexed by `X`, as in `LDA ($10,X)`.
+    Izx,
+    /// The address in the zero page at the byte, indexed by `Y`, as in `LDA ($10),Y`.
+    Izy,
+    /// A signed offset from the next instruction, for branches.
+    Rel,
+    /// The address at a 16-bit address, for `JMP ($1234)`.
+    Ind,
+}
+
+impl Mode {
+    /// Returns the length in bytes of an instruction with this mode, with its opcode.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(self) -> usize {
+        match self {
+            Mode::Imp | Mode::Acc => 1,
+            Mode::Abs | Mode::Abx | Mode::Aby | Mode::Ind => 3,
+            _ => 2,
+        }
+    }
+}
+
+/// An operation, by its mnemonic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[rustfmt::skip]
+pub enum Op {
+    Adc, Alr, Anc, And, Ane, Arr, Asl, Bcc, Bcs, Beq, Bit, Bmi,
+    Bne, Bpl, Brk, Bvc, Bvs, Clc, Cld, Cli, Clv, Cmp, Cpx, Cpy,
+    Dcp, Dec, Dex, Dey, Eor, Inc, Inx, Iny, Isc, Jam, Jmp, Jsr,
+    Las, Lax, Lda, Ldx, Ldy, Lsr, Lxa, Nop, Ora, Pha, Php, Pla,
+    Plp, Rla, Rol, Ror, Rra, Rti, Rts, Sax, Sbc, Sbx, Sec, Sed,
+    Sei, Sha, Shx, Shy, Slo, Sre, Sta, Stx, Sty, Tas, Tax, Tay,
+    Tsx, Txa, Txs, Tya,
+}
+
+use Mode::*;
+use Op::*;
+
+/// The operation and mode of each opcode.
+#[rustfmt::skip]
+const INSTRUCTIONS: [(Op, Mode); 256] = [
+    (Brk, Imp), (Ora, Izx), (Jam, Imp), (Slo, Izx), (Nop, Zp), (Ora, Zp), (Asl, Zp), (Slo, Zp), // 00
+    (Php, Imp), (Ora, Imm), (Asl, Acc), (Anc, Imm), (Nop, Abs), (Ora, Abs), (Asl, Abs), (Slo, Abs),
+    (Bpl, Rel), (Ora, Izy), (Jam, Imp), (Slo, Izy), (Nop, Zpx), (Ora, Zpx), (Asl, Zpx), (Slo, Zpx), // 10
+    (Clc, Imp), (Ora, Aby), (Nop, Imp), (Slo, Aby), (Nop, Abx), (Ora, Abx), (Asl, Abx), (Slo, Abx),
+    (Jsr, Abs), (And, Izx), (Jam, Imp), (Rla, Izx), (Bit, Zp), (And, Zp), (Rol, Zp), (Rla, Zp), // 20
+    (Plp, Imp), (And, Imm), (Rol, Acc), (Anc, Imm), (Bit, Abs), (And, Abs), (Rol, Abs), (Rla, Abs),
+    (Bmi, Rel), (And, Izy), (Jam, Imp), (Rla, Izy), (Nop, Zpx), (And, Zpx), (Rol, Zpx), (Rla, Zpx), // 30
+    (Sec, Imp), (And, Aby), (Nop, Imp), (Rla, Aby), (Nop, Abx), (And, Abx), (Rol, Abx), (Rla, Abx),
+    (Rti, Imp), (Eor, Izx), (Jam, Imp), (Sre, Izx), (Nop, Zp), (Eor, Zp), (Lsr, Zp), (Sre, Zp), // 40
+    (Pha, Imp), (Eor, Imm), (Lsr, Acc), (Alr, Imm), (Jmp, Abs), (Eor, Abs), (Lsr, Abs), (Sre, Abs),
+    (Bvc, Rel), (Eor, Izy), (Jam, Imp), (Sre, Izy), (Nop, Zpx), (Eor, Zpx), (Lsr, Zpx), (Sre, Zpx), // 50
+    (Cli, Imp), (Eor, Aby), (Nop, Imp), (Sre, Aby), (Nop, Abx), (Eor, Abx), (Lsr, Abx), (Sre, Abx),
+    (Rts, Imp), (Adc, Izx), (Jam, Imp), (Rra, Izx), (Nop, Zp), (Adc, Zp), (Ror, Zp), (Rra, Zp), // 60
+    (Pla, Imp), (Adc, Imm), (Ror, Acc), (Arr, Imm), (Jmp, Ind), (Adc, Abs), (Ror, Abs), (Rra, Abs),
+    (Bvs, Rel), (Adc, Izy), (Jam, Imp), (Rra, Izy), (Nop, Zpx), (Adc, Zpx), (Ror, Zpx), (Rra, Zpx), // 70
+    (Sei, Imp), (Adc, Aby), (Nop, Imp), (Rra, Aby), (Nop, Abx), (Adc, Abx), (Ror, Abx), (Rra, Abx),
+    (Nop, Imm), (Sta, Izx), (Nop, Imm), (Sax, Izx), (Sty, Zp), (Sta, Zp), (Stx, Zp), (Sax, Zp), // 80
+    (Dey, Imp), (Nop, Imm), (Txa, Imp), (Ane, Imm), (Sty, Abs), (Sta, Abs), (Stx, Abs), (Sax, Abs),
+    (Bcc, Rel), (Sta, Izy), (Jam, Imp), (Sha, Izy), (Sty, Zpx), (Sta, Zpx), (Stx, Zpy), (Sax, Zpy), // 90
+    (Tya, Imp), (Sta, Aby), (Txs, Imp), (Tas, Aby), (Shy, Abx), (Sta, Abx), (Shx, Aby), (Sha, Aby),
+    (Ldy, Imm), (Lda, Izx), (Ldx, Imm), (Lax, Izx), (Ldy, Zp), (Lda, Zp), (Ldx, Zp), (Lax, Zp), // A0
+    (Tay, Imp), (Lda, Imm), (Tax, Imp), (Lxa, Imm), (Ldy, Abs), (Lda, Abs), (Ldx, Abs), (Lax, Abs),
+    (Bcs, Rel), (Lda, Izy), (Jam, Imp), (Lax, Izy), (Ldy, Zpx), (Lda, Zpx), (Ldx, Zpy), (Lax, Zpy), // B0
+    (Clv, Imp), (Lda, Aby), (Tsx, Imp), (Las, Aby), (Ldy, Abx), (Lda, Abx), (Ldx, Aby), (Lax, Aby),
+    (Cpy, Imm), (Cmp, Izx), (Nop, Imm), (Dcp, Izx), (Cpy, Zp), (Cmp, Zp), (Dec, Zp), (Dcp, Zp), // C0
+    (Iny, Imp), (Cmp, Imm), (Dex, Imp), (Sbx, Imm), (Cpy, Abs), (Cmp, Abs), (Dec, Abs), (Dcp, Abs),
+    (Bne, Rel), (Cmp, Izy), (Jam, Imp), (Dcp, Izy), (Nop, Zpx), (Cmp, Zpx), (Dec, Zpx), (Dcp, Zpx), // D0
+    (Cld, Imp), (Cmp, Aby), (Nop, Imp), (Dcp, Aby), (Nop, Abx), (Cmp, Abx), (Dec, Abx), (Dcp, Abx),
+    (Cpx, Imm), (Sbc, Izx), (Nop, Imm), (Isc, Izx), (Cpx, Zp), (Sbc, Zp), (Inc, Zp), (Isc, Zp), // E0
+    (Inx, Imp), (Sbc, Imm), (Nop, Imp), (Sbc, Imm), (Cpx, Abs), (Sbc, Abs), (Inc, Abs), (Isc, Abs),
+    (Beq, Rel), (Sbc, Izy), (Jam, Imp), (Isc, Izy), (Nop, Zpx), (Sbc, Zpx), (Inc, Zpx), (Isc, Zpx), // F0
+    (Sed, Imp), (Sbc, Aby), (Nop, Imp), (Isc, Aby), (Nop, Abx), (Sbc, Abx), (Inc, Abx), (Isc, Abx),
+];
+
+/// Returns the operation of `opcode` and how it addresses its operand.
+pub fn decode(opcode: u8) -> (Op, Mode) {
+    INSTRUCTIONS[opcode as usize]
+}
+
+/// Returns whether `opcode` is one of the 151 that MOS documented.
+pub fn documented(opcode: u8) -> bool {
+    match decode(opcode).0 {
+        Alr | Anc | Ane | Arr | Dcp | Isc | Jam | Las | Lax | Lxa | Rla | Rra | Sax | Sbx | Sha
+        | Shx | Shy | Slo | Sre | Tas => false,
+        Nop => opcode == 0xEA,
+        Sbc => opcode != 0xEB,
+        _ => true,
+    }
+}
+
+impl Op {
+    /// Returns the mnemonic of the operation, such as `LDA`.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Adc => "ADC",
+            Alr => "ALR",
+            Anc => "ANC",
+            And => "AND",
+            Ane => "ANE",
+            Arr => "ARR",
+            Asl => "ASL",
+            Bcc => "BCC",
+            Bcs => "BCS",
+            Beq => "BEQ",
+            Bit => "BIT",
+            Bmi => "BMI",
+            Bne => "BNE",
+            Bpl => "BPL",
+            Brk => "BRK",
+            Bvc => "BVC",
+            Bvs => "BVS",
+            Clc => "CLC",
+            Cld => "CLD",
+            Cli => "CLI",
+            Clv => "CLV",
+            Cmp => "CMP",
+            Cpx => "CPX",
+            Cpy => "CPY",
+            Dcp => "DCP",
+            Dec => "DEC",
+            Dex => "DEX",
+            Dey => "DEY",
+            Eor => "EOR",
+            Inc => "INC",
+            Inx => "INX",
+            Iny => "INY",
+            Isc => "ISC",
+            Jam => "JAM",
+            Jmp => "JMP",
+            Jsr => "JSR",
+            Las => "LAS",
+            Lax => "LAX",
+            Lda => "LDA",
+            Ldx => "LDX",
+            Ldy => "LDY",
+            Lsr => "LSR",
+            Lxa => "LXA",
+            Nop => "NOP",
+            Ora => "ORA",
+            Pha => "PHA",
+            Php => "PHP",
+            Pla => "PLA",
+            Plp => "PLP",
+            Rla => "RLA",
+            Rol => "ROL",
+            Ror => "ROR",
+            Rra => "RRA",
+            Rti => "RTI",
+            Rts => "RTS",
+            Sax => "SAX",
+            Sbc => "SBC",
+            Sbx => "SBX",
+            Sec => "SEC",
+            Sed => "SED",
+            Sei => "SEI",
+            Sha => "SHA",
+            Shx => "SHX",
+            Shy => "SHY",
+            Slo => "SLO",
+            Sre => "SRE",
+            Sta => "STA",
+            Stx => "STX",
+            Sty => "STY",
+            Tas => "TAS",
+            Tax => "TAX",
+            Tay => "TAY",
+            Tsx => "TSX",
+            Txa => "TXA",
+            Txs => "TXS",
+            Tya => "TYA",
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Runs the test programs written for the 6502: Kevin Horton's nestest, which logs the registers
+//! and clock cycle of every instruction, undocumented ones included, on a real NES, and Klaus
+//! Dormann's functional, decimal and interrupt tests.
+//!
+//! The programs are not part of this repository, so the tests are ignored unless asked for, and
+//! then need a directory holding them: `nestest.nes` with its `nestest.log`, and `6502_functional_test.bin`,
+//! `6502_decimal_test.bin` and `6502_interrupt_test.bin`. Missing files are skipped. Build the tests
+//! with optimizations, as the functional test runs for millions of instructions:
+//!
+//! ```text
+//! MOS6502_TEST_ROMS=roms/6502 cargo test -p mos6502 --release --test test_roms -- --ignored --nocapture
+//! ```
+
+use mos6502::bus::{Bus, Memory};
+use mos6502::disasm::Instruction;
+use mos6502::{Cpu, Register};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The directory of the programs to run.
+const TEST_ROMS: &str = "MOS6502_TEST_ROMS";
+
+/// How many instructions a test may run before it is taken to be lost.
+const LIMIT: u64 = 200_000_000;
+
+/// Where the functional test passes, in the binary Klaus Dormann's repository builds.
+const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+
+/// Where the interrupt test passes, in the binary of the ca65 port of Klaus Dormann's tests.
+const INTERRUPT_SUCCESS: u16 = 0x06E8;
+
+/// The interrupt test's port, whose bit 0 pulls IRQ and bit 1 NMI.
+const FEEDBACK: u16 = 0xBFFC;
+
+/// Returns the program `name` from the directory of the programs, or `None` if it is not there.
+fn program(name: &str) -> Option<Vec<u8>> {
+    let dir = env::var_os(TEST_ROMS).unwrap_or_else(|| panic!("{} is not set", TEST_ROMS));
+    match fs::read(PathBuf::from(dir).join(name)) {
+        Ok(program) => Some(program),
+        Err(_) => {
+            eprintln!("{} is missing, skipping", name);
+            None
+        }
+    }
+}
+
+/// Runs `cpu` until it jumps or branches to itself, and returns where.
+fn run_to_trap(cpu: &mut Cpu, bus: &mut impl Bus) -> u16 {
+    for _ in 0..LIMIT {
+        let pc = cpu.program_counter();
+        cpu.step(bus);
+        if cpu.program_counter() == pc {
+            return pc;
+        }
+    }
+    panic!("lost at {:04X}", cpu.program_counter());
+}
+
+/// Returns the value after `label` in a line of nestest's log, such as `A:` or `CYC:`.
+fn field<'a>(line: &'a str, label: &str) -> &'a str {
+    let start = line.find(label).unwrap() + label.len();
+    line[start..].split_whitespace().next().unwrap()
+}
+
+#[test]
+#[ignore = "needs $MOS6502_TEST_ROMS"]
+fn nestest() {
+    let (rom, log) = match (program("nestest.nes"), program("nestest.log")) {
+        (Some(rom), Some(log)) => (rom, String::from_utf8(log).unwrap()),
+        _ => return,
+    };
+    // The 16KB of the program, after the iNES header, at both 0x8000 and 0xC000.
+    let mut memory = Memory::default();
+    memory.0[0x8000..0xC000].copy_from_slice(&rom[16..0x4010]);
+    memory.0[0xC000..].copy_from_slice(&rom[16..0x4010]);
+    let mut cpu = Cpu::new();
+    cpu.set_decimal_mode(false);
+    cpu.reset(&mut memory);
+    // The automated test starts at 0xC000 rather than at the reset vector.
+    cpu.set_program_counter(0xC000);
+    for (n, line) in log.lines().enumerate() {
+        let state = format!(
+            "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            cpu.program_counter(),
+            cpu.register(Register::A),
+            cpu.register(Register::X),
+            cpu.register(Register::Y),
+            cpu.flags(),
+            cpu.register(Register::S),
+            cpu.cycles()
+        );
+        let expected = format!(
+            "{} A:{} X:{} Y:{} P:{} SP:{} CYC:{}",
+            &line[..4],
+            field(line, "A:"),
+            field(line, "X:"),
+            field(line, "Y:"),
+            field(line, "P:"),
+            field(line, "SP:"),
+            field(line, "CYC:")
+        );
+        let pc = cpu.program_counter() as usize;
+        let instruction = Instruction::decode(pc as u16, &memory.0[pc..]).unwrap();
+        assert_eq!(state, expected, "line {}: {}", n + 1, instruction);
+        cpu.step(&mut memory);
+    }
+    // The test leaves the number of the first failure of documented and undocumented opcodes.
+    assert_eq!(memory.0[0x02..0x04], [0, 0]);
+}
+
+#[test]
+#[ignore = "needs $MOS6502_TEST_ROMS"]
+fn functional() {
+    let program = match program("6502_functional_test.bin") {
+        Some(program) => program,
+        None => return,
+    };
+    let mut memory = Memory::with_program(0, &program).unwrap();
+    let mut cpu = Cpu::new();
+    cpu.set_program_counter(0x0400);
+    let trap = run_to_trap(&mut cpu, &mut memory);
+    assert_eq!(trap, FUNCTIONAL_SUCCESS, "failed at {:04X}", trap);
+}
+
+#[test]
+#[ignore = "needs $MOS6502_TEST_ROMS"]
+fn decimal() {
+    let program = match program("6502_decimal_test.bin") {
+        Some(program) => program,
+        None => return,
+    };
+    let mut memory = Memory::with_program(0x0200, &program).unwrap();
+    let mut cpu = Cpu::new();
+    cpu.set_program_counter(0x0200);
+    // The test ends in the 65C02's STP, which the program does not otherwise use.
+    while memory.0[cpu.program_counter() as usize] != 0xDB {
+        cpu.step(&mut memory);
+    }
+    assert_eq!(memory.0[0x000B], 0, "ERROR is set");
+}
+
+/// Memory with the interrupt test's port, through which the program interrupts itself.
+struct Feedback {
+    memory: Memory,
+    port: u8,
+}
+
+impl Bus for Feedback {
+    fn read(&mut self, address: u16) -> u8 {
+        match address {
+            FEEDBACK => self.port,
+            _ => self.memory.read(address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            FEEDBACK => self.port = value & 0x7F,
+            _ => self.memory.write(address, value),
+        }
+    }
+
+    fn nmi(&mut self) -> bool {
+        self.port & 0x02 != 0
+    }
+
+    fn irq(&mut self) -> bool {
+        self.port & 0x01 != 0
+    }
+}
+
+#[test]
+#[ignore = "needs $MOS6502_TEST_ROMS"]
+fn interrupts() {
+    let program = match program("6502_interrupt_test.bin") {
+        Some(program) => program,
+        None => return,
+    };
+    let mut bus = Feedback {
+        memory: Memory::with_program(0, &program).unwrap(),
+        port: 0,
+    };
+    let mut cpu = Cpu::new();
+    cpu.set_program_counter(0x0400);
+    let trap = run_to_trap(&mut cpu, &mut bus);
+    assert_eq!(trap, INTERRUPT_SUCCESS, "failed at {:04X}", trap);
+}
@@ -0,0 +1,284 @@
+//! Cartridges, loaded from iNES files.
+//!
+//! An iNES file is a 16-byte header followed by the cartridge's ROMs: an optional 512-byte
+//! trainer, the program ROM in 16KB banks, and the character ROM the PPU draws from in 8KB banks.
+//! The header tells how many banks there are, how the nametables are mirrored, and which mapper,
+//! the circuit that switches banks, the board has. Only NROM, mapper 0, which switches nothing, is
+//! supported.
+
+use emu_core::Error;
+
+/// The first bytes of an iNES file.
+pub const MAGIC: &[u8; 4] = b"NES\x1A";
+
+/// Bytes of the header.
+const HEADER_SIZE: usize = 16;
+
+/// Bytes of the trainer, which the file holds before the program if bit 2 of byte 6 is set.
+const TRAINER_SIZE: usize = 512;
+
+/// Bytes of a bank of program ROM.
+pub const PRG_BANK: usize = 0x4000;
+
+/// Bytes of a bank of character ROM, and of the character RAM of boards without ROM.
+pub const CHR_BANK: usize = 0x2000;
+
+/// Bytes of program RAM at 0x6000, which a few NROM games such as Family Basic have.
+pub const PRG_RAM_SIZE: usize = 0x2000;
+
+/// How the 2KB of nametable RAM in the console fill the PPU's four nametables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    /// The top two nametables are the first 2KB, and the bottom two the second, for games that
+    /// scroll vertically.
+    Horizontal,
+    /// The left two nametables are the first 2KB, and the right two the second, for games that
+    /// scroll horizontally.
+    Vertical,
+    /// Four separate nametables, with 2KB of RAM on the cartridge.
+    FourScreen,
+}
+
+impl Mirroring {
+    /// Returns where in 4KB of nametables the PPU address `address` is.
+    pub fn nametable(self, address: u16) -> usize {
+        let table = (address >> 10) & 3;
+        let table = match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 1,
+            Mirroring::FourScreen => table,
+        };
+        usize::from(table) << 10 | usize::from(address & 0x3FF)
+    }
+}
+
+/// An NROM cartridge.
+#[derive(Clone, Debug)]
+pub struct Cartridge {
+    /// 16KB or 32KB of program ROM, at 0x8000 with 16KB repeated at 0xC000.
+    prg: Vec<u8>,
+    /// 8KB of character ROM, or of character RAM.
+    chr: Vec<u8>,
+    /// Whether `chr` is RAM, which the program fills in.
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    mirroring: Mirroring,
+    /// Whether the program RAM is kept by a battery.
+    battery: bool,
+}
+
+impl Default for Cartridge {
+    /// A cartridge of blank ROM, with character RAM, as the console starts with before one is
+    /// inserted.
+    fn default() -> Self {
+        Cartridge {
+            prg: vec![0; 2 * PRG_BANK],
+            chr: vec![0; CHR_BANK],
+            chr_ram: true,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+        }
+    }
+}
+
+impl Cartridge {
+    /// Reads the iNES file `rom`.
+    pub fn from_ines(rom: &[u8]) -> Result<Self, Error> {
+        let error = |message: &str| Err(Error::Rom(message.to_string()));
+        if rom.len() < HEADER_SIZE || !rom.starts_with(MAGIC) {
+            return error("not an iNES file");
+        }
+        let header = &rom[..HEADER_SIZE];
+        // Byte 7 is only trusted in files of NES 2.0, or with the end of the header clear, as
+        // old tools wrote their names there.
+        let nes2 = header[7] & 0x0C == 0x08;
+        let high = if nes2 || header[12..].iter().all(|&byte| byte == 0) {
+            header[7] & 0xF0
+        } else {
+            0
+        };
+        let mapper = high | header[6] >> 4;
+        if mapper != 0 {
+            return Err(Error::Rom(format!(
+                "the cartridge has mapper {}, and only NROM (mapper 0) is supported",
+                mapper
+            )));
+        }
+        let prg_size = usize::from(header[4]) * PRG_BANK;
+        let chr_size = usize::from(header[5]) * CHR_BANK;
+        if prg_size != PRG_BANK && prg_size != 2 * PRG_BANK {
+            return error("an NROM cartridge has 16KB or 32KB of program ROM");
+        }
+        if chr_size > CHR_BANK {
+            return error("an NROM cartridge has at most 8KB of character ROM");
+        }
+        let start = HEADER_SIZE
+            + if header[6] & 0x04 != 0 {
+                TRAINER_SIZE
+            } else {
+                0
+            };
+        if rom.len() < start + prg_size + chr_size {
+            return error("the file ends before the ROMs its header tells of");
+        }
+        let prg = rom[start..start + prg_size].to_vec();
+        let chr_ram = chr_size == 0;
+        let chr = if chr_ram {
+            vec![0; CHR_BANK]
+        } else {
+            rom[start + prg_size..start + prg_size + chr_size].to_vec()
+        };
+        let mut prg_ram = vec![0; PRG_RAM_SIZE];
+        if header[6] & 0x04 != 0 {
+            // The trainer is loaded at 0x7000.
+            prg_ram[0x1000..0x1200].copy_from_slice(&rom[HEADER_SIZE..start]);
+        }
+        let mirroring = if header[6] & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if header[6] & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        Ok(Cartridge {
+            prg,
+            chr,
+            chr_ram,
+            prg_ram,
+            mirroring,
+            battery: header[6] & 0x02 != 0,
+        })
+    }
+
+    /// Returns how the nametables are mirrored.
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    /// Returns whether the program RAM is kept by a battery.
+    pub fn battery(&self) -> bool {
+        self.battery
+    }
+
+    /// Returns whether the character memory is RAM.
+    pub fn chr_ram(&self) -> bool {
+        self.chr_ram
+    }
+
+    /// Returns the program RAM.
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    /// Returns the program RAM, to change it directly.
+    pub fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    /// Returns the character memory.
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    /// Returns the character memory, to change it directly. Only RAM is restored from a state.
+    pub(crate) fn chr_mut(&mut self) -> &mut [u8] {
+        &mut self.chr
+    }
+
+    /// Reads the processor's address `address`, or returns `None` below 0x6000, where nothing on
+    /// the cartridge answers.
+    pub fn read_prg(&self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => Some(self.prg_ram[usize::from(address - 0x6000)]),
+            0x8000..=0xFFFF => Some(self.prg[usize::from(address - 0x8000) % self.prg.len()]),
+            _ => None,
+        }
+    }
+
+    /// Writes the processor's address `address`, which only changes program RAM.
+    pub fn write_prg(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            self.prg_ram[usize::from(address - 0x6000)] = value;
+        }
+    }
+
+    /// Reads the PPU's address `address`, below 0x2000.
+    pub fn read_chr(&self, address: u16) -> u8 {
+        self.chr[usize::from(address & 0x1FFF)]
+    }
+
+    /// Writes the PPU's address `address`, below 0x2000, which only changes character RAM.
+    pub fn write_chr(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            self.chr[usize::from(address & 0x1FFF)] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an iNES file with `prg` 16KB banks, `chr` 8KB banks and the flags of byte 6.
+    fn ines(prg: u8, chr: u8, flags: u8) -> Vec<u8> {
+        let mut rom = MAGIC.to_vec();
+        rom.extend_from_slice(&[prg, chr, flags, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        if flags & 0x04 != 0 {
+            rom.extend(vec![0x77; TRAINER_SIZE]);
+        }
+        rom.extend((0..usize::from(prg) * PRG_BANK).map(|n| (n >> 8) as u8));
+        rom.extend(vec![0xCC; usize::from(chr) * CHR_BANK]);
+        rom
+    }
+
+    #[test]
+    fn loads_nrom() {
+        let cartridge = Cartridge::from_ines(&ines(1, 1, 0x01)).unwrap();
+        assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+        assert!(!cartridge.battery() && !cartridge.chr_ram());
+        assert_eq!(cartridge.read_prg(0x8123), Some(0x01));
+        assert_eq!(cartridge.read_prg(0xC123), Some(0x01));
+        assert_eq!(cartridge.read_prg(0x4020), None);
+        assert_eq!(cartridge.read_chr(0x1FFF), 0xCC);
+
+        let mut cartridge = Cartridge::from_ines(&ines(2, 0, 0x06)).unwrap();
+        assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+        assert!(cartridge.battery() && cartridge.chr_ram());
+        assert_eq!(cartridge.read_prg(0xC000), Some(0x40));
+        assert_eq!(cartridge.read_prg(0x7000), Some(0x77));
+        cartridge.write_prg(0x6000, 0x12);
+        cartridge.write_prg(0x8000, 0x34);
+        assert_eq!(cartridge.read_prg(0x6000), Some(0x12));
+        assert_eq!(cartridge.read_prg(0x8000), Some(0x00));
+        cartridge.write_chr(0x0010, 0x56);
+        assert_eq!(cartridge.read_chr(0x0010), 0x56);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_run() {
+        let rejects = |rom: &[u8]| matches!(Cartridge::from_ines(rom), Err(Error::Rom(_)));
+        assert!(rejects(b"NES"));
+        assert!(rejects(&[0; 0x6010]));
+        assert!(rejects(&ines(1, 1, 0x10)));
+        assert!(rejects(&ines(3, 1, 0)));
+        assert!(rejects(&ines(1, 2, 0)));
+        assert!(rejects(&ines(1, 1, 0)[..0x5000]));
+        // Byte 7 with junk at the end of the header is ignored.
+        let mut rom = ines(1, 1, 0);
+        rom[7] = 0x40;
+        assert!(rejects(&rom));
+        rom[12] = b'D';
+        assert!(Cartridge::from_ines(&rom).is_ok());
+    }
+
+    #[test]
+    fn mirrors() {
+        assert_eq!(Mirroring::Horizontal.nametable(0x2400), 0x000);
+        assert_eq!(Mirroring::Horizontal.nametable(0x2C05), 0x405);
+        assert_eq!(Mirroring::Vertical.nametable(0x2800), 0x000);
+        assert_eq!(Mirroring::Vertical.nametable(0x2FFF), 0x7FF);
+        assert_eq!(Mirroring::Vertical.nametable(0x3400), 0x400);
+        assert_eq!(Mirroring::FourScreen.nametable(0x2C00), 0xC00);
+    }
+}
@@ -0,0 +1,89 @@
+//! The standard controller: a D-pad, A, B, Select and Start, read a bit at a time through a shift
+//! register.
+//!
+//! Writing 1 to bit 0 of 0x4016 holds the register loading the buttons, and writing 0 lets it be
+//! shifted: each read of 0x4016, for the first controller, or 0x4017, for the second, then returns
+//! the next button in bit 0, in the order of [`BUTTONS`], and 1 once all eight have been read.
+
+/// The buttons, in the order they are read.
+pub const BUTTONS: [&str; 8] = ["A", "B", "Select", "Start", "Up", "Down", "Left", "Right"];
+
+/// A standard controller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Controller {
+    /// The buttons held down, a bit for each in the order of [`BUTTONS`] from bit 0.
+    pub buttons: u8,
+    /// The buttons not yet read, from bit 0, shifting 1s in behind them.
+    shift: u8,
+    /// Whether the register is held loading the buttons.
+    strobe: bool,
+}
+
+impl Controller {
+    /// Presses or releases the button numbered `button` in [`BUTTONS`].
+    pub fn set(&mut self, button: usize, pressed: bool) {
+        let bit = 1 << button;
+        if pressed {
+            self.buttons |= bit;
+        } else {
+            self.buttons &= !bit;
+        }
+    }
+
+    /// Holds the register loading the buttons, or lets it go, as bit 0 of 0x4016 does.
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    /// Returns whether the next button is held down, as bit 0 of a read does.
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 1;
+        }
+        let bit = self.shift & 1;
+        self.shift = self.shift >> 1 | 0x80;
+        bit
+    }
+
+    /// Returns the shift register and whether it is held loading, for a save state.
+    pub(crate) fn save(&self) -> [u8; 2] {
+        [self.shift, self.strobe as u8]
+    }
+
+    /// Restores what [`save`](Controller::save) returned.
+    pub(crate) fn restore(&mut self, state: [u8; 2]) {
+        self.shift = state[0];
+        self.strobe = state[1] != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_out_the_buttons() {
+        let mut controller = Controller::default();
+        controller.set(0, true);
+        controller.set(3, true);
+        controller.set(7, true);
+        controller.write(1);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+        controller.set(0, false);
+        assert_eq!(controller.read(), 0);
+        controller.set(0, true);
+        controller.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| controller.read()).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+        // Buttons pressed after the register was loaded wait for the next load.
+        controller.set(1, true);
+        controller.write(1);
+        controller.write(0);
+        assert_eq!(controller.read(), 1);
+        assert_eq!(controller.read(), 1);
+    }
+}
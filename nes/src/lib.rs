@@ -0,0 +1,660 @@
+//! The Nintendo Entertainment System: a Ricoh 2A03, which is a 6502 without decimal mode, 2KB of
+//! RAM, the 2C02 picture processing unit, two controllers, and the cartridge.
+//!
+//! The processor and the PPU run in step: the PPU draws three dots for every clock cycle of the
+//! processor, on each of its reads and writes, so that the program sees the PPU as it is on the
+//! cycle it reads it, and mid-frame changes to scrolling or palettes land on the dot they are made
+//! on. The PPU raises NMI at the start of the vertical blank, when the program is free to change
+//! video memory, and a frame ends there.
+//!
+//! Cartridges are loaded from iNES files, and only those of NROM boards, which switch no banks, are
+//! supported. The sound unit is not emulated, so the console is silent.
+//!
+//! ```
+//! use emu_core::Emulator;
+//! use nes::Nes;
+//!
+//! let nes = Nes::new();
+//! assert_eq!(nes.video().width, 256);
+//! assert_eq!(nes.video().height, 240);
+//! assert_eq!(nes.buttons()[3], "1P Start");
+//! ```
+
+pub mod cartridge;
+pub mod controller;
+pub mod ppu;
+
+use crate::cartridge::Cartridge;
+use crate::controller::Controller;
+use crate::ppu::Ppu;
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+use emu_debug::mos6502::Mos6502;
+use emu_debug::{Memory, Target};
+use mos6502::bus::Bus;
+use mos6502::Cpu;
+
+/// Clock cycles per second of the processor, from the 21.477MHz crystal divided by 12.
+pub const CLOCK_HZ: u64 = 1_789_773;
+
+/// The time a frame takes: 262 lines of 341 dots, a dot shorter every other frame, at 3 dots a
+/// clock cycle, a little over 1/60 second.
+pub const FRAME_PERIOD: Duration = Duration::from_nanos(16_639_267);
+
+/// Samples per second of the silence the console makes.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Bytes of RAM in the console, repeated up to 0x2000.
+pub const RAM_SIZE: usize = 0x800;
+
+/// The buttons of both controllers, numbered as [`Nes`] numbers them: those of the first, in the
+/// order of [`controller::BUTTONS`], then those of the second.
+pub const BUTTONS: [&str; 16] = [
+    "1P A",
+    "1P B",
+    "1P Select",
+    "1P Start",
+    "1P Up",
+    "1P Down",
+    "1P Left",
+    "1P Right",
+    "2P A",
+    "2P B",
+    "2P Select",
+    "2P Start",
+    "2P Up",
+    "2P Down",
+    "2P Left",
+    "2P Right",
+];
+
+/// The register that copies a page of memory to OAM.
+const OAM_DMA: u16 = 0x4014;
+
+/// The registers of the controllers: writing the first strobes both, and reading each reads one.
+const JOYPAD_1: u16 = 0x4016;
+const JOYPAD_2: u16 = 0x4017;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"NES\x01";
+
+/// Everything in the console but the processor.
+#[derive(Clone)]
+struct Board {
+    ram: [u8; RAM_SIZE],
+    ppu: Ppu,
+    cartridge: Cartridge,
+    controllers: [Controller; 2],
+    /// The last value on the data bus, which addresses nothing answers read back.
+    open_bus: u8,
+    /// Clock cycles run since the console was turned on.
+    cycles: u64,
+}
+
+impl Board {
+    /// Runs the PPU for a clock cycle of the processor.
+    fn tick(&mut self) {
+        self.cycles += 1;
+        for _ in 0..3 {
+            self.ppu.tick(&mut self.cartridge);
+        }
+    }
+
+    /// Reads `address` without running a cycle.
+    fn load(&mut self, address: u16) -> u8 {
+        let value = match address {
+            0x0000..=0x1FFF => self.ram[usize::from(address) % RAM_SIZE],
+            0x2000..=0x3FFF => self.ppu.read_register(address, &self.cartridge),
+            JOYPAD_1 | JOYPAD_2 => {
+                let controller = &mut self.controllers[usize::from(address - JOYPAD_1)];
+                self.open_bus & 0xE0 | controller.read()
+            }
+            0x4000..=0x401F => self.open_bus,
+            _ => self.cartridge.read_prg(address).unwrap_or(self.open_bus),
+        };
+        self.open_bus = value;
+        value
+    }
+
+    /// Copies the page `page` to OAM, halting the processor for 513 clock cycles, or 514 if the
+    /// copy has to wait for an even cycle to start on.
+    fn dma(&mut self, page: u8) {
+        self.tick();
+        if self.cycles & 1 != 0 {
+            self.tick();
+        }
+        for offset in 0..=0xFF {
+            self.tick();
+            let value = self.load(u16::from(page) << 8 | offset);
+            self.tick();
+            self.ppu.write_register(4, value, &mut self.cartridge);
+        }
+    }
+}
+
+/// Reading the registers of the picture unit and the controllers changes them, so the debugger
+/// sees the last value on the bus there instead.
+impl Memory for Board {
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[usize::from(address) % RAM_SIZE],
+            0x2000..=0x401F => self.open_bus,
+            _ => self.cartridge.read_prg(address).unwrap_or(self.open_bus),
+        }
+    }
+
+    /// Writes RAM and the cartridge's RAM.
+    fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram[usize::from(address) % RAM_SIZE] = value,
+            0x6000..=0x7FFF => self.cartridge.write_prg(address, value),
+            _ => {}
+        }
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        self.tick();
+        self.load(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.tick();
+        self.open_bus = value;
+        match address {
+            0x0000..=0x1FFF => self.ram[usize::from(address) % RAM_SIZE] = value,
+            0x2000..=0x3FFF => self.ppu.write_register(address, value, &mut self.cartridge),
+            OAM_DMA => self.dma(value),
+            JOYPAD_1 => {
+                for controller in &mut self.controllers {
+                    controller.write(value);
+                }
+            }
+            // The sound unit, which is not emulated.
+            0x4000..=0x401F => {}
+            _ => self.cartridge.write_prg(address, value),
+        }
+    }
+
+    fn nmi(&mut self) -> bool {
+        self.ppu.nmi()
+    }
+}
+
+/// The console, with a cartridge.
+#[derive(Clone)]
+pub struct Nes {
+    cpu: Cpu,
+    board: Board,
+    rom_hash: u64,
+    /// Clock cycles since the last sample of sound, times [`SAMPLE_RATE`].
+    clocks: u64,
+    samples: Vec<i16>,
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Nes {
+    /// Creates a console with a blank cartridge, turned on.
+    pub fn new() -> Self {
+        let mut nes = Nes {
+            cpu: Cpu::new(),
+            board: Board {
+                ram: [0; RAM_SIZE],
+                ppu: Ppu::new(),
+                cartridge: Cartridge::default(),
+                controllers: [Controller::default(); 2],
+                open_bus: 0,
+                cycles: 0,
+            },
+            rom_hash: emu_state::hash(&[]),
+            clocks: 0,
+            samples: Vec::new(),
+        };
+        nes.reset();
+        nes
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns the PPU.
+    pub fn ppu(&self) -> &Ppu {
+        &self.board.ppu
+    }
+
+    /// Returns the cartridge.
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.board.cartridge
+    }
+
+    /// Returns the cartridge, to change its RAM directly.
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.board.cartridge
+    }
+
+    /// Returns the console's RAM.
+    pub fn ram(&self) -> &[u8; RAM_SIZE] {
+        &self.board.ram
+    }
+
+    /// Returns the console's RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8; RAM_SIZE] {
+        &mut self.board.ram
+    }
+
+    /// Returns the clock cycles run since the console was turned on.
+    pub fn cycles(&self) -> u64 {
+        self.board.cycles
+    }
+
+    /// Adds the silence of `cycles` clock cycles to the samples.
+    fn mix(&mut self, cycles: u64) {
+        self.clocks += cycles * u64::from(SAMPLE_RATE);
+        let samples = self.samples.len() + (self.clocks / CLOCK_HZ) as usize;
+        self.samples.resize(samples, 0);
+        self.clocks %= CLOCK_HZ;
+    }
+
+    /// Returns the error of a jammed processor.
+    fn jammed(&self) -> Error {
+        Error::Stopped(format!(
+            "the processor jammed at {:04X}",
+            self.cpu.program_counter().wrapping_sub(1)
+        ))
+    }
+}
+
+impl Emulator for Nes {
+    fn name(&self) -> &'static str {
+        "NES"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: ppu::WIDTH,
+            height: ppu::HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Inserts the cartridge of the iNES file `rom`, and turns the console on.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        self.board.cartridge = Cartridge::from_ines(rom)?;
+        self.rom_hash = emu_state::hash(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Turns the console off and on again. The console's RAM is cleared, but the cartridge's is
+    /// kept.
+    fn reset(&mut self) {
+        let board = &mut self.board;
+        board.ram = [0; RAM_SIZE];
+        board.ppu = Ppu::new();
+        board.open_bus = 0;
+        board.cycles = 0;
+        for controller in &mut board.controllers {
+            controller.restore([0, 0]);
+        }
+        self.cpu = Cpu::new();
+        self.cpu.set_decimal_mode(false);
+        self.cpu.reset(board);
+        self.clocks = 0;
+        self.samples.clear();
+    }
+
+    fn step_frame(&mut self) -> Result<(), Error> {
+        loop {
+            if self.cpu.jammed() {
+                return Err(self.jammed());
+            }
+            let start = self.board.cycles;
+            self.cpu.step(&mut self.board);
+            self.mix(self.board.cycles - start);
+            if self.board.ppu.take_frame() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        self.board.ppu.framebuffer()
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if button < BUTTONS.len() {
+            self.board.controllers[button / 8].set(button % 8, pressed);
+        }
+    }
+
+    /// Saves the processor, RAM, the PPU, the cartridge's RAM and the controllers, but not the
+    /// picture, which is drawn again by the next frame.
+    fn save_state(&self) -> Vec<u8> {
+        let board = &self.board;
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(&board.ram);
+        state.extend_from_slice(&board.ppu.save());
+        state.extend_from_slice(board.cartridge.prg_ram());
+        if board.cartridge.chr_ram() {
+            state.extend_from_slice(board.cartridge.chr());
+        }
+        for controller in &board.controllers {
+            state.extend_from_slice(&controller.save());
+        }
+        state.push(board.open_bus);
+        state.extend_from_slice(&board.cycles.to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let cartridge = &self.board.cartridge;
+        let chr = if cartridge.chr_ram() {
+            cartridge.chr().len()
+        } else {
+            0
+        };
+        let prg_ram = cartridge.prg_ram().len();
+        let size = MAGIC.len()
+            + 8
+            + mos6502::STATE_SIZE
+            + RAM_SIZE
+            + ppu::STATE_SIZE
+            + prg_ram
+            + chr
+            + 2 * 2
+            + 1
+            + 8;
+        if state.len() != size || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this console".to_string()));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(mos6502::STATE_SIZE);
+        let (ram, rest) = rest.split_at(RAM_SIZE);
+        let (ppu, rest) = rest.split_at(ppu::STATE_SIZE);
+        let (prg_ram, rest) = rest.split_at(prg_ram);
+        let (chr, rest) = rest.split_at(chr);
+        self.board.ppu.restore(ppu)?;
+        self.cpu
+            .restore(<&[u8; mos6502::STATE_SIZE]>::try_from(cpu).unwrap());
+        let board = &mut self.board;
+        board.ram.copy_from_slice(ram);
+        board.cartridge.prg_ram_mut().copy_from_slice(prg_ram);
+        if !chr.is_empty() {
+            board.cartridge.chr_mut().copy_from_slice(chr);
+        }
+        board.controllers[0].restore([rest[0], rest[1]]);
+        board.controllers[1].restore([rest[2], rest[3]]);
+        board.open_bus = rest[4];
+        board.cycles = u64::from_le_bytes(<[u8; 8]>::try_from(&rest[5..]).unwrap());
+        Ok(())
+    }
+
+    /// Returns the program RAM, if the cartridge keeps it with a battery.
+    fn battery(&self) -> Option<Vec<u8>> {
+        let cartridge = &self.board.cartridge;
+        if cartridge.battery() {
+            Some(cartridge.prg_ram().to_vec())
+        } else {
+            None
+        }
+    }
+
+    fn load_battery(&mut self, battery: &[u8]) -> Result<(), Error> {
+        let cartridge = &mut self.board.cartridge;
+        if !cartridge.battery() || battery.len() != cartridge.prg_ram().len() {
+            return Err(Error::State(
+                "not the battery RAM of this cartridge".to_string(),
+            ));
+        }
+        cartridge.prg_ram_mut().copy_from_slice(battery);
+        Ok(())
+    }
+
+    fn debug(&mut self) -> Option<Box<dyn Target + '_>> {
+        Some(Box::new(Mos6502::new(&mut self.cpu, &mut self.board)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an iNES file of 16KB of program, holding `code` at each address from 0x8000, with
+    /// NMI handled at 0x8100 and IRQ at 0x8200, and character RAM.
+    fn rom(code: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut rom = cartridge::MAGIC.to_vec();
+        rom.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mut prg = vec![0; cartridge::PRG_BANK];
+        prg[0x3FFA..].copy_from_slice(&[0x00, 0x81, 0x00, 0x80, 0x00, 0x82]);
+        for &(address, bytes) in code {
+            prg[usize::from(address - 0x8000)..][..bytes.len()].copy_from_slice(bytes);
+        }
+        rom.extend(prg);
+        rom
+    }
+
+    fn machine(code: &[(u16, &[u8])]) -> Nes {
+        let mut nes = Nes::new();
+        nes.load_rom(&rom(code)).unwrap();
+        nes
+    }
+
+    /// A program that loops, with NMI enabled: LDA #$80; STA $2000; BNE *
+    const NMI_LOOP: (u16, &[u8]) = (0x8000, &[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xD0, 0xFE]);
+
+    /// An NMI handler that counts the frames at 0x0010: INC $10; RTI
+    const COUNT: (u16, &[u8]) = (0x8100, &[0xE6, 0x10, 0x40]);
+
+    #[test]
+    fn maps_memory() {
+        let mut nes = machine(&[(0x8000, &[0x42])]);
+        let board = &mut nes.board;
+        board.write(0x0800, 0x12);
+        assert_eq!(board.ram[0], 0x12);
+        assert_eq!(board.read(0x1800), 0x12);
+        // Nothing answers at 0x5000, so the last value read is read again.
+        assert_eq!(board.read(0x5000), 0x12);
+        assert_eq!(board.read(0xC000), 0x42);
+        board.write(0x6000, 0x34);
+        assert_eq!(board.read(0x6000), 0x34);
+        board.write(0x2006, 0x3F);
+        board.write(0x3FFE, 0x00);
+        board.write(0x2007, 0x21);
+        board.write(0x2006, 0x3F);
+        board.write(0x2006, 0x00);
+        assert_eq!(board.read(0x2007), 0x21);
+    }
+
+    #[test]
+    fn debugs() {
+        use emu_debug::debugger::{Debugger, StopReason};
+
+        let mut nes = machine(&[NMI_LOOP]);
+        let mut target = nes.debug().unwrap();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x8005);
+        let reason = loop {
+            if let Some(reason) = debugger.run_step(target.as_mut()) {
+                break reason;
+            }
+        };
+        assert_eq!(reason, StopReason::Breakpoint(0x8005));
+        assert_eq!(target.disassemble(0x8002).text, "STA $2000");
+        // The picture unit's registers are not read, so as not to change them.
+        assert_eq!(target.peek(0x2002), target.peek(0x4000));
+        target.poke(0x0800, 0x12);
+        target.poke(0x8000, 0x34);
+        assert_eq!(target.peek(0x8000), 0xA9);
+        drop(target);
+        assert_eq!(nes.board.ram[0], 0x12);
+    }
+
+    #[test]
+    fn reads_controllers() {
+        let mut nes = machine(&[]);
+        nes.set_button(3, true);
+        nes.set_button(8, true);
+        nes.set_button(BUTTONS.len(), true);
+        let board = &mut nes.board;
+        board.write(JOYPAD_1, 1);
+        board.write(JOYPAD_1, 0);
+        let first: Vec<u8> = (0..9).map(|_| board.read(JOYPAD_1) & 1).collect();
+        assert_eq!(first, [0, 0, 0, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(board.read(JOYPAD_2) & 1, 1);
+        assert_eq!(board.read(JOYPAD_2) & 1, 0);
+    }
+
+    #[test]
+    fn runs_frames_and_takes_nmi() {
+        let mut nes = machine(&[NMI_LOOP, COUNT]);
+        let mut ends = Vec::new();
+        for _ in 0..4 {
+            nes.step_frame().unwrap();
+            ends.push(nes.cycles());
+        }
+        // The NMI of each frame is taken in the next.
+        assert_eq!(nes.ram()[0x10], 3);
+        // Without rendering, frames are 89342 dots, or 29780 2/3 clock cycles.
+        for frame in ends.windows(2) {
+            assert!((29780..=29781).contains(&(frame[1] - frame[0])));
+        }
+        let mut samples = Vec::new();
+        nes.pull_audio(&mut samples);
+        let expected = nes.cycles() * u64::from(SAMPLE_RATE) / CLOCK_HZ;
+        assert!((samples.len() as u64).abs_diff(expected) <= 1);
+        assert!(samples.iter().all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn draws_what_the_program_writes() {
+        #[rustfmt::skip]
+        let program: &[u8] = &[
+            // LDA $2002, then the backdrop and color 1 of the first palette.
+            0xAD, 0x02, 0x20,
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20,
+            0xA9, 0x0F, 0x8D, 0x07, 0x20, 0xA9, 0x30, 0x8D, 0x07, 0x20,
+            // Tile 1, of color 1, in character RAM.
+            0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA9, 0x10, 0x8D, 0x06, 0x20,
+            0xA2, 0x08, 0xA9, 0xFF, 0x8D, 0x07, 0x20, 0xCA, 0xD0, 0xFA,
+            // Tile 1 at the top left.
+            0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20,
+            0xA9, 0x01, 0x8D, 0x07, 0x20,
+            // No scrolling, the background shown, and BNE *.
+            0xA9, 0x00, 0x8D, 0x00, 0x20, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20,
+            0xA9, 0x0A, 0x8D, 0x01, 0x20, 0xD0, 0xFE,
+        ];
+        let mut nes = machine(&[(0x8000, program)]);
+        nes.step_frame().unwrap();
+        nes.step_frame().unwrap();
+        let pixel = |x: usize, y: usize| &nes.framebuffer()[(y * ppu::WIDTH + x) * 4..][..3];
+        assert_eq!(pixel(0, 0), ppu::PALETTE[0x30]);
+        assert_eq!(pixel(7, 7), ppu::PALETTE[0x30]);
+        assert_eq!(pixel(8, 0), ppu::PALETTE[0x0F]);
+        assert_eq!(pixel(0, 8), ppu::PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn copies_oam_by_dma() {
+        let mut nes = machine(&[]);
+        for (n, byte) in nes.ram_mut()[0x200..0x300].iter_mut().enumerate() {
+            *byte = n as u8;
+        }
+        let board = &mut nes.board;
+        let start = board.cycles;
+        board.write(OAM_DMA, 0x02);
+        assert!((514..=515).contains(&(board.cycles - start)));
+        assert_eq!(board.ppu.oam()[..], board.ram[0x200..0x300]);
+    }
+
+    #[test]
+    fn stops_when_jammed() {
+        let mut nes = machine(&[(0x8000, &[0xEA, 0x02])]);
+        match nes.step_frame() {
+            Err(Error::Stopped(message)) => assert!(message.ends_with("8001")),
+            result => panic!("{:?}", result),
+        }
+        assert!(nes.step_frame().is_err());
+        nes.reset();
+        assert!(!nes.cpu().jammed());
+    }
+
+    #[test]
+    fn loads_only_ines_files() {
+        let mut nes = Nes::new();
+        let error = nes.load_rom(&[0; 0x4010]).unwrap_err();
+        assert!(matches!(error, Error::Rom(_)));
+        assert!(nes.load_rom(&rom(&[])).is_ok());
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut nes = machine(&[NMI_LOOP, COUNT]);
+        nes.step_frame().unwrap();
+        nes.step_frame().unwrap();
+        nes.cartridge_mut().prg_ram_mut()[0] = 0x55;
+        nes.set_button(0, true);
+        nes.board.write(JOYPAD_1, 1);
+        let state = nes.save_state();
+
+        let mut restored = machine(&[NMI_LOOP, COUNT]);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.cycles(), nes.cycles());
+        nes.step_frame().unwrap();
+        restored.step_frame().unwrap();
+        assert_eq!(restored.save_state(), nes.save_state());
+        assert_eq!(restored.ram()[0x10], 2);
+
+        let mut other = machine(&[NMI_LOOP]);
+        assert!(matches!(other.load_state(&state), Err(Error::State(_))));
+        assert!(matches!(
+            restored.load_state(&state[1..]),
+            Err(Error::State(_))
+        ));
+    }
+
+    #[test]
+    fn keeps_the_battery() {
+        let mut nes = machine(&[]);
+        assert_eq!(nes.battery(), None);
+        assert!(nes.load_battery(&[0; cartridge::PRG_RAM_SIZE]).is_err());
+
+        let mut rom = rom(&[]);
+        rom[6] = 0x02;
+        nes.load_rom(&rom).unwrap();
+        nes.cartridge_mut().prg_ram_mut()[0x123] = 0x45;
+        let battery = nes.battery().unwrap();
+        nes.load_rom(&rom).unwrap();
+        nes.load_battery(&battery).unwrap();
+        assert_eq!(nes.cartridge().prg_ram()[0x123], 0x45);
+        assert!(nes.load_battery(&battery[1..]).is_err());
+    }
+}
@@ -0,0 +1,970 @@
+//! The Ricoh 2C02, the picture processing unit of the NTSC NES.
+//!
+//! The PPU draws a 256x240 picture a dot at a time, three dots for every clock cycle of the
+//! processor, on 262 lines of 341 dots: 240 visible lines, a line after them, 20 lines of vertical
+//! blank in which the program may change video memory, and a line before the next picture in which
+//! the PPU fetches its first tiles. As on the 2C02, each dot does the work the chip does on it:
+//!
+//! * The background is fetched a tile ahead, 4 reads of 2 dots each, into shift registers that
+//!   give a dot on each cycle, scrolled through the `v` and `t` addresses and fine `x` as the
+//!   registers at 0x2005 and 0x2006 set them, mid-frame changes included.
+//! * The sprites of the next line, up to 8, are found among the 64 of OAM at the end of each line,
+//!   with the overflow flag set by the 2C02's faulty search, and drawn over or behind the
+//!   background by priority, with the sprite 0 hit of the first sprite.
+//! * The vertical blank flag is set on the second dot of line 241, raising NMI if enabled, and
+//!   cleared on the second dot of the line before the picture, which is a dot shorter on odd frames
+//!   while rendering.
+//!
+//! Video memory is the cartridge's character memory below 0x2000, the 2KB of nametables in the
+//! console above it, mirrored as the cartridge wires them, and the 32 bytes of palette at 0x3F00.
+
+use crate::cartridge::Cartridge;
+use emu_core::Error;
+
+/// The width of the picture, in dots.
+pub const WIDTH: usize = 256;
+
+/// The height of the picture, in lines.
+pub const HEIGHT: usize = 240;
+
+/// Dots in a line.
+pub const DOTS: u16 = 341;
+
+/// Lines in a frame.
+pub const LINES: u16 = 262;
+
+/// The line the vertical blank starts on.
+pub const VBLANK_LINE: u16 = 241;
+
+/// The line before the picture, on which the first tiles are fetched.
+pub const PRE_RENDER: u16 = LINES - 1;
+
+/// Length of the state written by [`Ppu::save`].
+pub const STATE_SIZE: usize = 4448;
+
+/// The bits of 0x2000, `PPUCTRL`.
+const INCREMENT_32: u8 = 0x04;
+const SPRITE_TABLE: u8 = 0x08;
+const BACKGROUND_TABLE: u8 = 0x10;
+const TALL_SPRITES: u8 = 0x20;
+const GENERATE_NMI: u8 = 0x80;
+
+/// The bits of 0x2001, `PPUMASK`.
+const GRAYSCALE: u8 = 0x01;
+const BACKGROUND_LEFT: u8 = 0x02;
+const SPRITES_LEFT: u8 = 0x04;
+const SHOW_BACKGROUND: u8 = 0x08;
+const SHOW_SPRITES: u8 = 0x10;
+
+/// The bits of 0x2002, `PPUSTATUS`.
+const OVERFLOW: u8 = 0x20;
+const SPRITE_ZERO_HIT: u8 = 0x40;
+const VBLANK: u8 = 0x80;
+
+/// The sprite attribute bits.
+const BEHIND: u8 = 0x20;
+const FLIP_X: u8 = 0x40;
+const FLIP_Y: u8 = 0x80;
+
+/// The colors of the 64 palette entries, as the 2C02 shows them on an NTSC television.
+#[rustfmt::skip]
+pub const PALETTE: [[u8; 3]; 64] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136],
+    [68, 0, 100], [92, 0, 48], [84, 4, 0], [60, 24, 0],
+    [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0],
+    [0, 50, 60], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228],
+    [136, 20, 176], [160, 20, 100], [152, 34, 32], [120, 60, 0],
+    [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40],
+    [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236],
+    [228, 84, 236], [236, 88, 180], [236, 106, 100], [212, 136, 32],
+    [160, 170, 0], [116, 196, 0], [76, 208, 32], [56, 204, 108],
+    [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236],
+    [236, 174, 236], [236, 174, 212], [236, 180, 176], [228, 196, 144],
+    [204, 210, 120], [180, 222, 120], [168, 226, 144], [152, 226, 180],
+    [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+/// The picture processing unit.
+#[derive(Clone)]
+pub struct Ppu {
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_address: u8,
+    oam: [u8; 256],
+    palette: [u8; 32],
+    /// 4KB of nametables, of which the console has the first 2KB.
+    nametables: Vec<u8>,
+    /// The address of the tile being fetched, or of the next access through 0x2007: coarse x in
+    /// bits 0-4, coarse y in bits 5-9, the nametable in bits 10-11 and fine y in bits 12-14.
+    v: u16,
+    /// The address `v` is reloaded from, at the start of each line and of each frame.
+    t: u16,
+    /// Fine x, the dot within a tile the picture starts at.
+    x: u8,
+    /// Whether the next write to 0x2005 or 0x2006 is the second.
+    w: bool,
+    /// What the last read of 0x2007 below the palette read, returned by the next.
+    buffer: u8,
+    /// The last value on the data bus between the processor and the PPU, read back from the bits
+    /// registers do not drive.
+    latch: u8,
+    scanline: u16,
+    dot: u16,
+    odd_frame: bool,
+    /// Whether a read of 0x2002 just before the vertical blank has stopped it being flagged.
+    suppress_vblank: bool,
+    /// The tile, attribute and pattern bytes fetched for the tile after the next.
+    tile: u8,
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    /// The pattern and attribute bits of the next 16 dots, from bit 15, low bits first.
+    pattern_shift: [u16; 2],
+    attribute_shift: [u16; 2],
+    /// How many sprites are on the line, with their patterns, flipped, attributes and x.
+    sprites: usize,
+    sprite_patterns: [[u8; 2]; 8],
+    sprite_attributes: [u8; 8],
+    sprite_x: [u8; 8],
+    /// Whether the first sprite on the line is sprite 0.
+    sprite_zero: bool,
+    /// Whether a picture has been finished since [`take_frame`](Ppu::take_frame).
+    frame: bool,
+    framebuffer: Vec<u8>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    /// Creates a PPU as it is powered on, at the start of the line before the picture.
+    pub fn new() -> Self {
+        let mut framebuffer = vec![0; WIDTH * HEIGHT * 4];
+        for pixel in framebuffer.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[0, 0, 0, 0xFF]);
+        }
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_address: 0,
+            oam: [0; 256],
+            palette: [0; 32],
+            nametables: vec![0; 0x1000],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            buffer: 0,
+            latch: 0,
+            scanline: PRE_RENDER,
+            dot: 0,
+            odd_frame: false,
+            suppress_vblank: false,
+            tile: 0,
+            attribute: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+            pattern_shift: [0; 2],
+            attribute_shift: [0; 2],
+            sprites: 0,
+            sprite_patterns: [[0; 2]; 8],
+            sprite_attributes: [0; 8],
+            sprite_x: [0; 8],
+            sprite_zero: false,
+            frame: false,
+            framebuffer,
+        }
+    }
+
+    /// Returns the picture, as 8-bit RGBA dots row by row.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Returns the line and the dot the PPU is about to draw.
+    pub fn position(&self) -> (u16, u16) {
+        (self.scanline, self.dot)
+    }
+
+    /// Returns OAM, the 64 sprites of 4 bytes: y, tile, attributes and x.
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    /// Returns whether the PPU pulls the processor's NMI line, which it does through the vertical
+    /// blank if bit 7 of 0x2000 is set.
+    pub fn nmi(&self) -> bool {
+        self.ctrl & GENERATE_NMI != 0 && self.status & VBLANK != 0
+    }
+
+    /// Returns whether a picture has been finished since the last call.
+    pub fn take_frame(&mut self) -> bool {
+        core::mem::replace(&mut self.frame, false)
+    }
+
+    /// Returns whether the background or the sprites are shown, which makes the PPU fetch.
+    fn rendering(&self) -> bool {
+        self.mask & (SHOW_BACKGROUND | SHOW_SPRITES) != 0
+    }
+
+    /// Reads the register at `register`, from 0 for 0x2000 to 7 for 0x2007.
+    pub fn read_register(&mut self, register: u16, cartridge: &Cartridge) -> u8 {
+        let value = match register & 7 {
+            2 => {
+                let value = self.status & 0xE0 | self.latch & 0x1F;
+                self.status &= !VBLANK;
+                self.w = false;
+                if (self.scanline, self.dot) == (VBLANK_LINE, 1) {
+                    self.suppress_vblank = true;
+                }
+                value
+            }
+            4 => {
+                let value = self.oam[usize::from(self.oam_address)];
+                // The attribute bits 2 to 4 do not exist.
+                if self.oam_address & 3 == 2 {
+                    value & 0xE3
+                } else {
+                    value
+                }
+            }
+            7 => {
+                let address = self.v & 0x3FFF;
+                let value = if address >= 0x3F00 {
+                    // The buffer is filled from the nametable under the palette.
+                    self.buffer = self.read(address - 0x1000, cartridge);
+                    self.read(address, cartridge) | self.latch & 0xC0
+                } else {
+                    let value = self.read(address, cartridge);
+                    core::mem::replace(&mut self.buffer, value)
+                };
+                self.increment_v();
+                value
+            }
+            _ => self.latch,
+        };
+        self.latch = value;
+        value
+    }
+
+    /// Writes `value` to the register at `register`, from 0 for 0x2000 to 7 for 0x2007.
+    pub fn write_register(&mut self, register: u16, value: u8, cartridge: &mut Cartridge) {
+        self.latch = value;
+        match register & 7 {
+            0 => {
+                self.ctrl = value;
+                self.t = self.t & !0x0C00 | u16::from(value & 3) << 10;
+            }
+            1 => self.mask = value,
+            3 => self.oam_address = value,
+            4 => {
+                self.oam[usize::from(self.oam_address)] = value;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            5 if !self.w => {
+                self.t = self.t & !0x001F | u16::from(value >> 3);
+                self.x = value & 7;
+                self.w = true;
+            }
+            5 => {
+                self.t = self.t & !0x73E0 | u16::from(value & 7) << 12 | u16::from(value >> 3) << 5;
+                self.w = false;
+            }
+            6 if !self.w => {
+                self.t = self.t & 0x00FF | u16::from(value & 0x3F) << 8;
+                self.w = true;
+            }
+            6 => {
+                self.t = self.t & 0xFF00 | u16::from(value);
+                self.v = self.t;
+                self.w = false;
+            }
+            7 => {
+                self.write(self.v & 0x3FFF, value, cartridge);
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves `v` on after an access through 0x2007, by 1 or 32, or while rendering, as the
+    /// fetches move it, to the next tile and line at once.
+    fn increment_v(&mut self) {
+        if self.rendering() && (self.scanline < HEIGHT as u16 || self.scanline == PRE_RENDER) {
+            self.increment_x();
+            self.increment_y();
+        } else {
+            let step = if self.ctrl & INCREMENT_32 != 0 { 32 } else { 1 };
+            self.v = self.v.wrapping_add(step) & 0x7FFF;
+        }
+    }
+
+    /// Reads video memory at `address`, below 0x4000.
+    fn read(&self, address: u16, cartridge: &Cartridge) -> u8 {
+        match address {
+            0x0000..=0x1FFF => cartridge.read_chr(address),
+            0x2000..=0x3EFF => self.nametables[cartridge.mirroring().nametable(address)],
+            _ => self.palette[palette_index(address)],
+        }
+    }
+
+    /// Writes `value` to video memory at `address`, below 0x4000.
+    fn write(&mut self, address: u16, value: u8, cartridge: &mut Cartridge) {
+        match address {
+            0x0000..=0x1FFF => cartridge.write_chr(address, value),
+            0x2000..=0x3EFF => self.nametables[cartridge.mirroring().nametable(address)] = value,
+            _ => self.palette[palette_index(address)] = value & 0x3F,
+        }
+    }
+
+    /// Runs a dot.
+    pub fn tick(&mut self, cartridge: &mut Cartridge) {
+        let (line, dot) = (self.scanline, self.dot);
+        if line < HEIGHT as u16 || line == PRE_RENDER {
+            if line == PRE_RENDER && dot == 1 {
+                self.status &= !(VBLANK | SPRITE_ZERO_HIT | OVERFLOW);
+            }
+            if self.rendering() {
+                self.render(cartridge);
+            }
+            if line < HEIGHT as u16 && (1..=WIDTH as u16).contains(&dot) {
+                self.draw(usize::from(dot - 1), usize::from(line));
+            }
+        } else if line == VBLANK_LINE && dot == 1 {
+            if !self.suppress_vblank {
+                self.status |= VBLANK;
+            }
+            self.suppress_vblank = false;
+            self.frame = true;
+        }
+
+        self.dot += 1;
+        if line == PRE_RENDER && self.dot == DOTS - 1 && self.odd_frame && self.rendering() {
+            self.dot = DOTS;
+        }
+        if self.dot == DOTS {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == LINES {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    /// Does the fetches and the scrolling of a dot on a line that is drawn, while rendering.
+    fn render(&mut self, cartridge: &mut Cartridge) {
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+            self.shift();
+            match (dot - 1) % 8 {
+                0 => {
+                    self.load_shifters();
+                    self.tile = self.read(0x2000 | self.v & 0x0FFF, cartridge);
+                }
+                2 => {
+                    let v = self.v;
+                    let address = 0x23C0 | v & 0x0C00 | v >> 4 & 0x38 | v >> 2 & 0x07;
+                    let shift = v >> 4 & 4 | v & 2;
+                    self.attribute = self.read(address, cartridge) >> shift & 3;
+                }
+                4 => self.pattern_low = self.read(self.pattern_address(), cartridge),
+                6 => self.pattern_high = self.read(self.pattern_address() + 8, cartridge),
+                7 => self.increment_x(),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.load_shifters();
+                self.v = self.v & !0x041F | self.t & 0x041F;
+                self.evaluate_sprites(cartridge);
+            }
+            280..=304 if self.scanline == PRE_RENDER => {
+                self.v = self.v & !0x7BE0 | self.t & 0x7BE0;
+            }
+            _ => {}
+        }
+        if (257..=320).contains(&dot) {
+            self.oam_address = 0;
+        }
+    }
+
+    /// Returns the address of the low pattern byte of the background tile being fetched.
+    fn pattern_address(&self) -> u16 {
+        let table = if self.ctrl & BACKGROUND_TABLE != 0 {
+            0x1000
+        } else {
+            0
+        };
+        table | u16::from(self.tile) << 4 | self.v >> 12
+    }
+
+    /// Moves the background shift registers on by a dot.
+    fn shift(&mut self) {
+        if self.mask & SHOW_BACKGROUND != 0 {
+            for shift in self
+                .pattern_shift
+                .iter_mut()
+                .chain(&mut self.attribute_shift)
+            {
+                *shift <<= 1;
+            }
+        }
+    }
+
+    /// Loads the tile fetched into the low bytes of the shift registers.
+    fn load_shifters(&mut self) {
+        let patterns = [self.pattern_low, self.pattern_high];
+        for (bit, (pattern, attribute)) in self
+            .pattern_shift
+            .iter_mut()
+            .zip(&mut self.attribute_shift)
+            .enumerate()
+        {
+            *pattern = *pattern & 0xFF00 | u16::from(patterns[bit]);
+            let fill = if self.attribute >> bit & 1 != 0 {
+                0xFF
+            } else {
+                0
+            };
+            *attribute = *attribute & 0xFF00 | fill;
+        }
+    }
+
+    /// Moves `v` to the next tile to the right, into the next nametable after the 32nd.
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v = self.v & !0x001F ^ 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// Moves `v` down a line, into the next nametable after the 30th row of tiles. Rows 30 and 31
+    /// hold attributes, but can be scrolled to, and wrap without changing the nametable.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let y = match self.v >> 5 & 0x1F {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            y => y + 1,
+        };
+        self.v = self.v & !0x03E0 | y << 5;
+    }
+
+    /// Finds the sprites of the next line, and fetches their patterns. The sprites of a line are
+    /// those whose y, which is one less than the line they start on, is within 8 or 16 lines above
+    /// it. After the eighth, the 2C02 looks for a ninth to set the overflow flag with, but wrongly
+    /// moves on to the next byte of each sprite as well as to the next sprite, so it is both set
+    /// and missed by mistake.
+    fn evaluate_sprites(&mut self, cartridge: &Cartridge) {
+        self.sprites = 0;
+        self.sprite_zero = false;
+        if self.scanline == PRE_RENDER {
+            return;
+        }
+        let height = if self.ctrl & TALL_SPRITES != 0 { 16 } else { 8 };
+        let line = self.scanline;
+        let mut n = 0;
+        while n < 64 && self.sprites < 8 {
+            let [y, tile, attributes, x] = [0, 1, 2, 3].map(|byte| self.oam[n * 4 + byte]);
+            let mut row = line.wrapping_sub(u16::from(y));
+            if row < height {
+                if attributes & FLIP_Y != 0 {
+                    row = height - 1 - row;
+                }
+                let address = if height == 16 {
+                    u16::from(tile & 1) << 12 | (u16::from(tile & 0xFE) + row / 8) << 4 | row & 7
+                } else {
+                    let table = if self.ctrl & SPRITE_TABLE != 0 {
+                        0x1000
+                    } else {
+                        0
+                    };
+                    table | u16::from(tile) << 4 | row
+                };
+                let mut pattern = [cartridge.read_chr(address), cartridge.read_chr(address + 8)];
+                if attributes & FLIP_X != 0 {
+                    pattern = [pattern[0].reverse_bits(), pattern[1].reverse_bits()];
+                }
+                self.sprite_patterns[self.sprites] = pattern;
+                self.sprite_attributes[self.sprites] = attributes;
+                self.sprite_x[self.sprites] = x;
+                self.sprite_zero |= n == 0;
+                self.sprites += 1;
+            }
+            n += 1;
+        }
+        let mut m = 0;
+        while n < 64 {
+            if line.wrapping_sub(u16::from(self.oam[n * 4 + m])) < height {
+                self.status |= OVERFLOW;
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 3;
+        }
+    }
+
+    /// Draws the dot at `x` on `line`.
+    fn draw(&mut self, x: usize, line: usize) {
+        let mut background = 0;
+        if self.mask & SHOW_BACKGROUND != 0 && (x >= 8 || self.mask & BACKGROUND_LEFT != 0) {
+            let bit = 15 - self.x;
+            let pixel = |shift: &[u16; 2]| (shift[1] >> bit & 1) << 1 | shift[0] >> bit & 1;
+            let pattern = pixel(&self.pattern_shift);
+            if pattern != 0 {
+                background = (pixel(&self.attribute_shift) << 2 | pattern) as u8;
+            }
+        }
+
+        let mut sprite = None;
+        if self.mask & SHOW_SPRITES != 0 && (x >= 8 || self.mask & SPRITES_LEFT != 0) {
+            sprite = (0..self.sprites).find_map(|n| {
+                let offset = x.checked_sub(usize::from(self.sprite_x[n]))?;
+                if offset >= 8 {
+                    return None;
+                }
+                let [low, high] = self.sprite_patterns[n];
+                let pattern = (high << offset >> 7) << 1 | low << offset >> 7;
+                if pattern == 0 {
+                    return None;
+                }
+                Some((n, (self.sprite_attributes[n] & 3) << 2 | pattern))
+            });
+        }
+
+        let index = match sprite {
+            None => background,
+            Some((n, pixel)) => {
+                if background != 0 && n == 0 && self.sprite_zero && x != 255 {
+                    self.status |= SPRITE_ZERO_HIT;
+                }
+                if background != 0 && self.sprite_attributes[n] & BEHIND != 0 {
+                    background
+                } else {
+                    0x10 | pixel
+                }
+            }
+        };
+        // With rendering off, the backdrop is shown, or the color `v` points to in the palette.
+        let address = if !self.rendering() && self.v & 0x3F00 == 0x3F00 {
+            self.v
+        } else {
+            0x3F00 | u16::from(index)
+        };
+        let mut color = self.palette[palette_index(address)];
+        if self.mask & GRAYSCALE != 0 {
+            color &= 0x30;
+        }
+        let [mut red, mut green, mut blue] = PALETTE[usize::from(color)];
+        // Emphasizing a color darkens the other two.
+        let emphasis = self.mask >> 5;
+        if emphasis != 0 {
+            let dim = |channel: &mut u8, bit: u8| {
+                if emphasis & bit == 0 {
+                    *channel = (u16::from(*channel) * 3 / 4) as u8;
+                }
+            };
+            dim(&mut red, 1);
+            dim(&mut green, 2);
+            dim(&mut blue, 4);
+        }
+        self.framebuffer[(line * WIDTH + x) * 4..][..4].copy_from_slice(&[red, green, blue, 0xFF]);
+    }
+
+    /// Returns the state of the PPU but the picture, for [`restore`](Ppu::restore).
+    pub fn save(&self) -> Vec<u8> {
+        let mut state = vec![
+            self.ctrl,
+            self.mask,
+            self.status,
+            self.oam_address,
+            self.latch,
+            self.buffer,
+            self.x,
+            self.w as u8,
+            self.odd_frame as u8,
+            self.suppress_vblank as u8,
+            self.tile,
+            self.attribute,
+            self.pattern_low,
+            self.pattern_high,
+            self.sprites as u8,
+            self.sprite_zero as u8,
+        ];
+        for value in [self.v, self.t, self.scanline, self.dot]
+            .iter()
+            .chain(&self.pattern_shift)
+            .chain(&self.attribute_shift)
+        {
+            state.extend_from_slice(&value.to_le_bytes());
+        }
+        for pattern in &self.sprite_patterns {
+            state.extend_from_slice(pattern);
+        }
+        state.extend_from_slice(&self.sprite_attributes);
+        state.extend_from_slice(&self.sprite_x);
+        state.extend_from_slice(&self.oam);
+        state.extend_from_slice(&self.palette);
+        state.extend_from_slice(&self.nametables);
+        state
+    }
+
+    /// Restores a state returned by [`save`](Ppu::save), or leaves the PPU as it was if it is
+    /// not one.
+    pub fn restore(&mut self, state: &[u8]) -> Result<(), Error> {
+        let corrupt = || Error::State("the state of the PPU is corrupt".to_string());
+        if state.len() != STATE_SIZE {
+            return Err(corrupt());
+        }
+        let (bytes, rest) = state.split_at(16);
+        let (words, rest) = rest.split_at(16);
+        let words: Vec<u16> = words
+            .chunks(2)
+            .map(|word| u16::from_le_bytes([word[0], word[1]]))
+            .collect();
+        if words[2] >= LINES || words[3] >= DOTS || bytes[14] > 8 || bytes[6] > 7 {
+            return Err(corrupt());
+        }
+        let (patterns, rest) = rest.split_at(16);
+        let (attributes, rest) = rest.split_at(8);
+        let (x, rest) = rest.split_at(8);
+        let (oam, rest) = rest.split_at(256);
+        let (palette, nametables) = rest.split_at(32);
+
+        self.ctrl = bytes[0];
+        self.mask = bytes[1];
+        self.status = bytes[2];
+        self.oam_address = bytes[3];
+        self.latch = bytes[4];
+        self.buffer = bytes[5];
+        self.x = bytes[6];
+        self.w = bytes[7] != 0;
+        self.odd_frame = bytes[8] != 0;
+        self.suppress_vblank = bytes[9] != 0;
+        self.tile = bytes[10];
+        self.attribute = bytes[11];
+        self.pattern_low = bytes[12];
+        self.pattern_high = bytes[13];
+        self.sprites = usize::from(bytes[14]);
+        self.sprite_zero = bytes[15] != 0;
+        self.v = words[0];
+        self.t = words[1];
+        self.scanline = words[2];
+        self.dot = words[3];
+        self.pattern_shift = [words[4], words[5]];
+        self.attribute_shift = [words[6], words[7]];
+        for (pattern, bytes) in self.sprite_patterns.iter_mut().zip(patterns.chunks(2)) {
+            *pattern = [bytes[0], bytes[1]];
+        }
+        self.sprite_attributes.copy_from_slice(attributes);
+        self.sprite_x.copy_from_slice(x);
+        self.oam.copy_from_slice(oam);
+        self.palette.copy_from_slice(palette);
+        self.nametables.copy_from_slice(nametables);
+        self.frame = false;
+        Ok(())
+    }
+}
+
+/// Returns where the palette address `address` is in the 32 bytes of palette. The backdrop of the
+/// sprite palettes, at 0x3F10, 0x3F14, 0x3F18 and 0x3F1C, is that of the background palettes.
+fn palette_index(address: u16) -> usize {
+    let index = usize::from(address & 0x1F);
+    if index & 0x13 == 0x10 {
+        index & 0x0F
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `bytes` to video memory from `address`, through 0x2006 and 0x2007.
+    fn poke(ppu: &mut Ppu, cartridge: &mut Cartridge, address: u16, bytes: &[u8]) {
+        ppu.write_register(6, (address >> 8) as u8, cartridge);
+        ppu.write_register(6, address as u8, cartridge);
+        for &byte in bytes {
+            ppu.write_register(7, byte, cartridge);
+        }
+    }
+
+    /// Runs the PPU to the end of the next picture, and returns how many dots it took.
+    fn frame(ppu: &mut Ppu, cartridge: &mut Cartridge) -> u32 {
+        let mut dots = 1;
+        ppu.tick(cartridge);
+        while !ppu.take_frame() {
+            ppu.tick(cartridge);
+            dots += 1;
+        }
+        dots
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> [u8; 3] {
+        let mut color = [0; 3];
+        color.copy_from_slice(&ppu.framebuffer()[(y * WIDTH + x) * 4..][..3]);
+        color
+    }
+
+    /// Returns a PPU whose first two tiles are of colors 1 and 3, with black, white and red in
+    /// the palettes, tile 1 at the top left of the first nametable and the one below, and the
+    /// background and sprites shown everywhere.
+    fn screen() -> (Ppu, Cartridge) {
+        let (mut ppu, mut cartridge) = (Ppu::new(), Cartridge::default());
+        poke(&mut ppu, &mut cartridge, 0x0010, &[0xFF; 8]);
+        poke(&mut ppu, &mut cartridge, 0x0020, &[0xFF; 16]);
+        poke(&mut ppu, &mut cartridge, 0x2000, &[1]);
+        poke(&mut ppu, &mut cartridge, 0x2020, &[1]);
+        poke(&mut ppu, &mut cartridge, 0x3F00, &[0x0F, 0x30]);
+        poke(&mut ppu, &mut cartridge, 0x3F13, &[0x16]);
+        ppu.write_register(6, 0, &mut cartridge);
+        ppu.write_register(6, 0, &mut cartridge);
+        ppu.write_register(1, 0x1E, &mut cartridge);
+        (ppu, cartridge)
+    }
+
+    const BLACK: [u8; 3] = PALETTE[0x0F];
+    const WHITE: [u8; 3] = PALETTE[0x30];
+    const RED: [u8; 3] = PALETTE[0x16];
+
+    #[test]
+    fn scrolls_through_t_and_v() {
+        let (mut ppu, mut cartridge) = (Ppu::new(), Cartridge::default());
+        ppu.write_register(0, 0x02, &mut cartridge);
+        assert_eq!(ppu.t, 0x0800);
+        ppu.write_register(5, 0x7D, &mut cartridge);
+        assert_eq!((ppu.t, ppu.x, ppu.w), (0x080F, 5, true));
+        ppu.write_register(5, 0x5E, &mut cartridge);
+        assert_eq!((ppu.t, ppu.w), (0x696F, false));
+        ppu.write_register(6, 0x3D, &mut cartridge);
+        assert_eq!(ppu.t, 0x3D6F);
+        // Reading the status starts the pairs of writes over.
+        ppu.read_register(2, &cartridge);
+        ppu.write_register(6, 0x3D, &mut cartridge);
+        ppu.write_register(6, 0xF0, &mut cartridge);
+        assert_eq!((ppu.t, ppu.v), (0x3DF0, 0x3DF0));
+
+        ppu.v = 0x73BF;
+        ppu.increment_x();
+        assert_eq!(ppu.v, 0x77A0);
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0x0400 | 0x0800);
+        ppu.v = 0x73E0;
+        ppu.increment_y();
+        assert_eq!(ppu.v, 0x0000);
+    }
+
+    #[test]
+    fn reads_through_a_buffer() {
+        let (mut ppu, mut cartridge) = (Ppu::new(), Cartridge::default());
+        poke(&mut ppu, &mut cartridge, 0x2400, &[0x11, 0x22]);
+        poke(&mut ppu, &mut cartridge, 0x3F10, &[0x2A]);
+        poke(&mut ppu, &mut cartridge, 0x2000, &[]);
+        ppu.read_register(7, &cartridge);
+        // The first nametable mirrors the second, horizontally.
+        assert_eq!(ppu.read_register(7, &cartridge), 0x11);
+        assert_eq!(ppu.read_register(7, &cartridge), 0x22);
+        // Palette reads are not buffered, and 0x3F10 is 0x3F00.
+        poke(&mut ppu, &mut cartridge, 0x3F00, &[]);
+        assert_eq!(ppu.read_register(7, &cartridge) & 0x3F, 0x2A);
+        ppu.write_register(0, INCREMENT_32, &mut cartridge);
+        poke(&mut ppu, &mut cartridge, 0x2000, &[0x33, 0x44]);
+        assert_eq!(ppu.nametables[0x20..0x21], [0x44]);
+        // Write-only registers read back the last value written.
+        assert_eq!(ppu.read_register(0, &cartridge), 0x44);
+        ppu.write_register(3, 0x02, &mut cartridge);
+        ppu.write_register(4, 0xFF, &mut cartridge);
+        ppu.write_register(3, 0x02, &mut cartridge);
+        assert_eq!(ppu.read_register(4, &cartridge), 0xE3);
+    }
+
+    #[test]
+    fn flags_the_vertical_blank() {
+        let (mut ppu, mut cartridge) = (Ppu::new(), Cartridge::default());
+        ppu.write_register(0, GENERATE_NMI, &mut cartridge);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(ppu.position(), (VBLANK_LINE, 2));
+        assert!(ppu.nmi());
+        assert_eq!(ppu.read_register(2, &cartridge) & VBLANK, VBLANK);
+        assert!(!ppu.nmi());
+        assert_eq!(ppu.read_register(2, &cartridge) & VBLANK, 0);
+
+        // Without rendering, every frame is 262 lines of 341 dots.
+        assert_eq!(frame(&mut ppu, &mut cartridge), 89342);
+        assert_eq!(frame(&mut ppu, &mut cartridge), 89342);
+        ppu.write_register(1, SHOW_BACKGROUND, &mut cartridge);
+        let frames = [
+            frame(&mut ppu, &mut cartridge),
+            frame(&mut ppu, &mut cartridge),
+        ];
+        assert!(frames.contains(&89341) && frames.contains(&89342));
+
+        // Reading the status just before the flag is set keeps it and NMI from being raised.
+        while ppu.position() != (VBLANK_LINE, 1) {
+            ppu.tick(&mut cartridge);
+        }
+        ppu.read_register(2, &cartridge);
+        ppu.tick(&mut cartridge);
+        assert!(ppu.take_frame());
+        assert_eq!(ppu.status & VBLANK, 0);
+        while ppu.position() != (PRE_RENDER, 2) {
+            ppu.tick(&mut cartridge);
+        }
+        assert!(!ppu.suppress_vblank);
+    }
+
+    #[test]
+    fn draws_the_background() {
+        let (mut ppu, mut cartridge) = screen();
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 0, 0), WHITE);
+        assert_eq!(pixel(&ppu, 7, 15), WHITE);
+        assert_eq!(pixel(&ppu, 8, 0), BLACK);
+        assert_eq!(pixel(&ppu, 0, 16), BLACK);
+
+        // Scrolled 3 dots right and 2 lines down.
+        ppu.write_register(5, 3, &mut cartridge);
+        ppu.write_register(5, 2, &mut cartridge);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 4, 13), WHITE);
+        assert_eq!(pixel(&ppu, 5, 0), BLACK);
+        assert_eq!(pixel(&ppu, 0, 14), BLACK);
+
+        // Hiding the leftmost 8 dots, and showing in gray.
+        ppu.write_register(5, 0, &mut cartridge);
+        ppu.write_register(5, 0, &mut cartridge);
+        ppu.write_register(1, SHOW_BACKGROUND | GRAYSCALE, &mut cartridge);
+        poke(&mut ppu, &mut cartridge, 0x3F01, &[0x16]);
+        ppu.write_register(6, 0, &mut cartridge);
+        ppu.write_register(6, 0, &mut cartridge);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 0, 0), PALETTE[0x00]);
+        poke(&mut ppu, &mut cartridge, 0x2001, &[1]);
+        ppu.write_register(6, 0, &mut cartridge);
+        ppu.write_register(6, 0, &mut cartridge);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 8, 0), PALETTE[0x10]);
+    }
+
+    #[test]
+    fn draws_sprites() {
+        let (mut ppu, mut cartridge) = screen();
+        // Sprite 0, of tile 2, from (4, 10) over the background's second tile.
+        ppu.oam[..4].copy_from_slice(&[9, 2, 0, 4]);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 4, 10), RED);
+        assert_eq!(pixel(&ppu, 11, 17), RED);
+        assert_eq!(pixel(&ppu, 3, 10), WHITE);
+        assert_eq!(pixel(&ppu, 12, 10), BLACK);
+        assert_eq!(pixel(&ppu, 4, 9), WHITE);
+        assert_ne!(ppu.read_register(2, &cartridge) & SPRITE_ZERO_HIT, 0);
+
+        // Behind the background, and where there is none.
+        ppu.oam[2] = BEHIND;
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 4, 10), WHITE);
+        assert_eq!(pixel(&ppu, 8, 10), RED);
+
+        // Sprite 0 over no background hits nothing.
+        ppu.oam[..4].copy_from_slice(&[99, 2, 0, 100]);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 100, 100), RED);
+        assert_eq!(ppu.read_register(2, &cartridge) & SPRITE_ZERO_HIT, 0);
+    }
+
+    #[test]
+    fn shows_eight_sprites_a_line() {
+        let (mut ppu, mut cartridge) = screen();
+        for (n, sprite) in ppu.oam.chunks_mut(4).enumerate() {
+            sprite.copy_from_slice(&[0xF0, 2, 0, 0]);
+            if n < 8 {
+                sprite.copy_from_slice(&[99, 2, 0, n as u8 * 16]);
+            }
+        }
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 112, 100), RED);
+        assert_eq!(ppu.read_register(2, &cartridge) & OVERFLOW, 0);
+
+        ppu.oam[8 * 4..][..4].copy_from_slice(&[99, 2, 0, 128]);
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(pixel(&ppu, 128, 100), BLACK);
+        assert_ne!(ppu.read_register(2, &cartridge) & OVERFLOW, 0);
+
+        // The faulty search reads the ninth sprite's tile as its y, and misses it.
+        ppu.oam[8 * 4..][..2].copy_from_slice(&[0xF0, 99]);
+        ppu.oam[9 * 4 + 1] = 0xF0;
+        frame(&mut ppu, &mut cartridge);
+        assert_eq!(ppu.read_register(2, &cartridge) & OVERFLOW, 0);
+        ppu.oam[9 * 4 + 1] = 99;
+        frame(&mut ppu, &mut cartridge);
+        assert_ne!(ppu.read_register(2, &cartridge) & OVERFLOW, 0);
+    }
+
+    #[test]
+    fn flips_and_stacks_sprites() {
+        let (mut ppu, mut cartridge) = screen();
+        // Tile 4 has its top left dot in color 1, and tile 5 its bottom right in color 2. While
+        // rendering, 0x2007 moves `v` as the fetches do, so it is turned off.
+        ppu.write_register(1, 0, &mut cartridge);
+        poke(&mut ppu, &mut cartridge, 0x0040, &[0x80]);
+        poke(&mut ppu, &mut cartridge, 0x005F, &[0x01]);
+        poke(&mut ppu, &mut cartridge, 0x3F11, &[0x30, 0x16]);
+        ppu.write_register(0, TALL_SPRITES, &mut cartridge);
+        ppu.write_register(6, 0, &mut cartridge);
+        ppu.write_register(6, 0, &mut cartridge);
+        ppu.write_register(1, 0x1E, &mut cartridge);
+        ppu.oam[..8].copy_from_slice(&[49, 4, 0, 50, 99, 4, FLIP_X | FLIP_Y, 100]);
+        frame(&mut ppu, &mut cartridge);
+        // 16 lines high, tile 5 is drawn below tile 4.
+        assert_eq!(pixel(&ppu, 50, 50), WHITE);
+        assert_eq!(pixel(&ppu, 51, 50), BLACK);
+        assert_eq!(pixel(&ppu, 57, 65), RED);
+        // Flipped both ways, tile 5's bottom right dot is at the top left.
+        assert_eq!(pixel(&ppu, 100, 100), RED);
+        assert_eq!(pixel(&ppu, 107, 115), WHITE);
+    }
+
+    #[test]
+    fn saves_state() {
+        let (mut ppu, mut cartridge) = screen();
+        ppu.oam[..4].copy_from_slice(&[9, 2, 0, 4]);
+        for _ in 0..50_000 {
+            ppu.tick(&mut cartridge);
+        }
+        let state = ppu.save();
+        assert_eq!(state.len(), STATE_SIZE);
+        let mut restored = Ppu::new();
+        restored.restore(&state).unwrap();
+        assert_eq!(restored.save(), state);
+        for _ in 0..2 {
+            frame(&mut ppu, &mut cartridge);
+            frame(&mut restored, &mut cartridge);
+        }
+        assert!(restored.framebuffer() == ppu.framebuffer());
+
+        let mut corrupt = state.clone();
+        corrupt[16 + 7] = 0xFF;
+        assert!(restored.restore(&corrupt).is_err());
+        assert!(restored.restore(&state[1..]).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Runs Blargg's test ROMs for the PPU, and the one for the dummy writes of the processor to it,
+//! which check the console against what it does on hardware and report whether it passed.
+//!
+//! The ROMs are not part of this repository, so the tests are ignored unless asked for, and then
+//! need a directory holding them, named as in Blargg's archives: `vram_access.nes`,
+//! `palette_ram.nes`, `sprite_ram.nes` and `vbl_clear_time.nes` of the PPU tests, `01.basics.nes`
+//! to `08.double_height.nes` of the sprite 0 hit tests, `1.Basics.nes` and `2.Details.nes` of the
+//! sprite overflow tests, and `oam_read.nes` and `cpu_dummy_writes_ppumem.nes`. Missing files are
+//! skipped:
+//!
+//! ```text
+//! NES_TEST_ROMS=roms/nes cargo test -p nes --release --test test_roms -- --ignored --nocapture
+//! ```
+
+use emu_core::Emulator;
+use nes::Nes;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The directory of the ROMs to run.
+const TEST_ROMS: &str = "NES_TEST_ROMS";
+
+/// How many frames a ROM may run before it is taken to have finished, or to be lost.
+const FRAMES: usize = 1200;
+
+/// What the newer ROMs write at 0x6001 to tell that they report at 0x6000.
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+/// Returns the console with the ROM `name` from the directory of the ROMs, or `None` if it is
+/// not there.
+fn console(name: &str) -> Option<Nes> {
+    let dir = env::var_os(TEST_ROMS).unwrap_or_else(|| panic!("{} is not set", TEST_ROMS));
+    let rom = match fs::read(PathBuf::from(dir).join(name)) {
+        Ok(rom) => rom,
+        Err(_) => {
+            eprintln!("{} is missing, skipping", name);
+            return None;
+        }
+    };
+    let mut nes = Nes::new();
+    nes.load_rom(&rom).unwrap();
+    Some(nes)
+}
+
+/// Runs the older ROMs, which write their result at `address` in RAM: 1 if they passed, or the
+/// number of the check that failed.
+fn run_to_result(names: &[&str], address: usize) {
+    for name in names {
+        let mut nes = match console(name) {
+            Some(nes) => nes,
+            None => continue,
+        };
+        for _ in 0..FRAMES {
+            nes.step_frame().unwrap();
+        }
+        assert_eq!(nes.ram()[address], 1, "{} failed", name);
+    }
+}
+
+/// Runs the newer ROMs, which write 0x80 at 0x6000 while running and their result when done, 0 if
+/// they passed, with a message from 0x6004.
+fn run_to_status(names: &[&str]) {
+    for name in names {
+        let mut nes = match console(name) {
+            Some(nes) => nes,
+            None => continue,
+        };
+        let mut frames = 0;
+        loop {
+            nes.step_frame().unwrap();
+            frames += 1;
+            let ram = nes.cartridge().prg_ram();
+            if ram[1..4] == SIGNATURE && ram[0] < 0x80 {
+                break;
+            }
+            assert!(frames < FRAMES, "{} did not finish", name);
+        }
+        let ram = nes.cartridge().prg_ram();
+        let message: String = ram[4..]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| char::from(byte))
+            .collect();
+        assert_eq!(ram[0], 0, "{} failed: {}", name, message);
+    }
+}
+
+#[test]
+#[ignore = "needs $NES_TEST_ROMS"]
+fn ppu() {
+    run_to_result(
+        &["vram_access.nes", "palette_ram.nes", "sprite_ram.nes"],
+        0xF0,
+    );
+    run_to_result(&["vbl_clear_time.nes"], 0xF8);
+    run_to_status(&["oam_read.nes", "cpu_dummy_writes_ppumem.nes"]);
+}
+
+#[test]
+#[ignore = "needs $NES_TEST_ROMS"]
+fn sprite_zero_hit() {
+    run_to_result(
+        &[
+            "01.basics.nes",
+            "02.alignment.nes",
+            "03.corners.nes",
+            "04.flip.nes",
+            "05.left_clip.nes",
+            "06.right_edge.nes",
+            "07.screen_bottom.nes",
+            "08.double_height.nes",
+        ],
+        0xF8,
+    );
+}
+
+#[test]
+#[ignore = "needs $NES_TEST_ROMS"]
+fn sprite_overflow() {
+    run_to_result(&["1.Basics.nes", "2.Details.nes"], 0xF8);
+}
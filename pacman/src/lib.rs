@@ -0,0 +1,787 @@
+//! The Pac-Man arcade machine of 1980: a Zilog Z80, 16KB of ROM, tiles and sprites colored through
+//! two PROMs, and Namco's waveform sound generator.
+//!
+//! The program sees the screen as 1KB of video RAM and 1KB of color RAM at 0x4000, followed by 1KB
+//! of RAM at 0x4C00 whose last 16 bytes choose the sprites. The buttons, the DIP switches, the
+//! sound chip and the places of the sprites are at 0x5000. Once a frame, as the beam leaves the
+//! picture, the board interrupts the program if it is allowed to, and puts on the data bus the
+//! vector the program last wrote to output port 0, for interrupt mode 2. A watchdog resets the
+//! machine if the program goes 16 frames without writing to 0x50C0.
+//!
+//! The ROMs are not part of this crate: [`Emulator::load_rom`] takes the chips of the machine one
+//! after another: the program in `pacman.6e`, `pacman.6f`, `pacman.6h` and `pacman.6j`, the tiles
+//! in `pacman.5e` and the sprites in `pacman.5f`, then the PROMs of the colors, `82s123.7f`, of
+//! the lookup table, `82s126.4a`, and of the waveforms, `82s126.1m`.
+//!
+//! ```
+//! use emu_core::Emulator;
+//! use pacman::{Dips, PacMan};
+//!
+//! let pacman = PacMan::new(Dips::default());
+//! assert_eq!(pacman.video().width, 224);
+//! assert_eq!(pacman.video().height, 288);
+//! ```
+
+pub mod video;
+pub mod wsg;
+
+use crate::video::{
+    Video, HEIGHT, LOOKUP_SIZE, PALETTE_SIZE, SPRITES, SPRITES_SIZE, TILES_SIZE, WIDTH,
+};
+use crate::wsg::{Wsg, SAMPLE_RATE, WAVES_SIZE};
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+use emu_debug::z80::Z80;
+use emu_debug::{Memory, Target};
+use z80::bus::Bus;
+use z80::{Cpu, STATE_SIZE};
+
+/// Clock cycles per second of the processor, from the 18.432MHz crystal divided by 6.
+pub const CLOCK_HZ: u64 = 3_072_000;
+
+/// Clock cycles of the processor in a line of the picture.
+pub const CYCLES_PER_LINE: u32 = 192;
+
+/// Lines in a frame, with those of the vertical blank.
+pub const LINES: u32 = 264;
+
+/// Clock cycles of the processor in a frame.
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES;
+
+/// The time a frame takes, about 1/60.6 second.
+pub const FRAME_PERIOD: Duration =
+    Duration::from_nanos(CYCLES_PER_FRAME as u64 * 1_000_000_000 / CLOCK_HZ);
+
+/// Bytes of the program, in the four 4KB ROM chips.
+pub const PROGRAM_SIZE: usize = 0x4000;
+
+/// Bytes of all the ROMs and PROMs, one after another.
+pub const ROM_SIZE: usize =
+    PROGRAM_SIZE + TILES_SIZE + SPRITES_SIZE + PALETTE_SIZE + LOOKUP_SIZE + WAVES_SIZE;
+
+/// The buttons of the cabinet, numbered as [`PacMan`] numbers them.
+pub const BUTTONS: [&str; 12] = [
+    "Coin", "1P Start", "2P Start", "1P Up", "1P Down", "1P Left", "1P Right", "2P Up", "2P Down",
+    "2P Left", "2P Right", "Service",
+];
+
+/// Frames the watchdog waits for the program before resetting the machine.
+const WATCHDOG_FRAMES: u8 = 16;
+
+/// Bytes of RAM from 0x4000, with the 1KB at 0x4800 where there is none.
+const RAM_SIZE: usize = 0x1000;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"PAC\x01";
+
+/// What a coin buys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coinage {
+    FreePlay,
+    OneCoinOneCredit,
+    OneCoinTwoCredits,
+    TwoCoinsOneCredit,
+}
+
+/// The DIP switches on the board, and the switches inside the cabinet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dips {
+    pub coinage: Coinage,
+    /// Lives a game starts with: 1, 2, 3 or 5.
+    pub lives: u8,
+    /// The points an extra life is given at, 10000, 15000 or 20000, or `None` for no extra life.
+    pub bonus_life: Option<u32>,
+    /// Whether the game is harder.
+    pub hard: bool,
+    /// Whether the ghosts are given their alternate names.
+    pub alternate_names: bool,
+    /// Whether the cabinet is a cocktail table, on which the screen turns around for the second
+    /// player.
+    pub cocktail: bool,
+    /// Whether the rack test switch is on, which clears each maze as it starts.
+    pub rack_test: bool,
+    /// Whether the service switch is on, which runs the game's test of the board instead.
+    pub service: bool,
+}
+
+impl Default for Dips {
+    /// A credit a coin, 3 lives, an extra one at 10000 points, the normal difficulty and names, in
+    /// an upright cabinet with the test switches off.
+    fn default() -> Self {
+        Dips {
+            coinage: Coinage::OneCoinOneCredit,
+            lives: 3,
+            bonus_life: Some(10_000),
+            hard: false,
+            alternate_names: false,
+            cocktail: false,
+            rack_test: false,
+            service: false,
+        }
+    }
+}
+
+/// Everything on the board but the processor.
+#[derive(Clone)]
+struct Board {
+    /// The program.
+    rom: Vec<u8>,
+    /// RAM from 0x4000.
+    ram: Vec<u8>,
+    /// The places of the sprites, written at 0x5060.
+    positions: [u8; 2 * SPRITES],
+    /// The bits written at 0x5000 to 0x5007: the interrupt enable, sound enable, an unused bit,
+    /// the flip of the screen, the two start lamps, the coin lockout and the coin counter.
+    latches: u8,
+    /// The vector written to port 0.
+    vector: u8,
+    /// Whether the interrupt of the last vertical blank has not been taken yet.
+    interrupt: bool,
+    /// Frames since the program last wrote to the watchdog.
+    watchdog: u8,
+    buttons: [bool; BUTTONS.len()],
+    dips: Dips,
+    wsg: Wsg,
+}
+
+impl Board {
+    /// Returns the bits of `buttons` from `bit` on.
+    fn buttons(&self, buttons: &[usize], bit: u8) -> u8 {
+        buttons
+            .iter()
+            .enumerate()
+            .map(|(n, &button)| (self.buttons[button] as u8) << (bit + n as u8))
+            .fold(0, |bits, bit| bits | bit)
+    }
+
+    /// Returns the DIP switches as 0x5080 reads them.
+    fn dip_switches(&self) -> u8 {
+        let dips = self.dips;
+        let lives = match dips.lives {
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            _ => 3,
+        };
+        let bonus_life = match dips.bonus_life {
+            Some(points) if points <= 10_000 => 0,
+            Some(points) if points <= 15_000 => 1,
+            Some(_) => 2,
+            None => 3,
+        };
+        dips.coinage as u8
+            | lives << 2
+            | bonus_life << 4
+            | (!dips.hard as u8) << 6
+            | (!dips.alternate_names as u8) << 7
+    }
+
+    /// Sets bit `bit` of the latches at 0x5000 to `on`.
+    fn latch(&mut self, bit: usize, on: bool) {
+        self.latches = self.latches & !(1 << bit) | (on as u8) << bit;
+        match bit {
+            0 if !on => self.interrupt = false,
+            1 => self.wsg.enabled = on,
+            _ => {}
+        }
+    }
+}
+
+/// Reading the inputs does nothing else on this machine, so the debugger sees what the processor
+/// does, but writes above RAM set latches and play sounds, which the debugger leaves alone.
+impl Memory for Board {
+    /// Reads the program below 0x4000, and RAM and the inputs above it. A15 is not decoded, nor
+    /// A13 above 0x4000, so each is repeated.
+    fn peek(&self, address: u16) -> u8 {
+        let address = usize::from(address & 0x7FFF);
+        if address < PROGRAM_SIZE {
+            return self.rom[address];
+        }
+        match address & 0x1FFF {
+            offset @ 0x0000..=0x07FF | offset @ 0x0C00..=0x0FFF => self.ram[offset],
+            0x0800..=0x0BFF => 0xBF,
+            offset => match offset & 0xC0 {
+                0x00 => {
+                    !(self.buttons(&[3, 5, 6, 4], 0)
+                        | (self.dips.rack_test as u8) << 4
+                        | self.buttons(&[0], 5)
+                        | self.buttons(&[11], 7))
+                }
+                0x40 => {
+                    !(self.buttons(&[7, 9, 10, 8], 0)
+                        | (self.dips.service as u8) << 4
+                        | self.buttons(&[1, 2], 5)
+                        | (self.dips.cocktail as u8) << 7)
+                }
+                0x80 => self.dip_switches(),
+                _ => 0xFF,
+            },
+        }
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        let address = usize::from(address & 0x7FFF);
+        if let offset @ 0x0000..=0x07FF | offset @ 0x0C00..=0x0FFF = address & 0x1FFF {
+            if address >= PROGRAM_SIZE {
+                self.ram[offset] = value;
+            }
+        }
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    /// Writes RAM, and from 0x5000 the latches, the sound chip, the places of the sprites and the
+    /// watchdog.
+    fn write(&mut self, address: u16, value: u8) {
+        let address = usize::from(address & 0x7FFF);
+        if address < PROGRAM_SIZE {
+            return;
+        }
+        match address & 0x1FFF {
+            offset @ 0x0000..=0x07FF | offset @ 0x0C00..=0x0FFF => self.ram[offset] = value,
+            0x0800..=0x0BFF => {}
+            offset => match offset & 0xFF {
+                latch @ 0x00..=0x3F => self.latch(latch & 7, value & 1 != 0),
+                register @ 0x40..=0x5F => self.wsg.write(register as u8 - 0x40, value),
+                position @ 0x60..=0x6F => self.positions[position - 0x60] = value,
+                0xC0..=0xFF => self.watchdog = 0,
+                _ => {}
+            },
+        }
+    }
+
+    /// Sets the vector of the interrupt from port 0.
+    fn output(&mut self, port: u16, value: u8) {
+        if port & 0xFF == 0 {
+            self.vector = value;
+        }
+    }
+
+    fn interrupt(&mut self) -> Option<u8> {
+        if self.interrupt {
+            self.interrupt = false;
+            Some(self.vector)
+        } else {
+            None
+        }
+    }
+}
+
+/// The arcade machine, with its ROMs.
+#[derive(Clone)]
+pub struct PacMan {
+    cpu: Cpu,
+    board: Board,
+    video: Video,
+    rom_hash: u64,
+    /// The clock cycle within the frame, from the start of the vertical blank.
+    cycle: u32,
+    samples: Vec<i16>,
+    framebuffer: Vec<u8>,
+}
+
+impl PacMan {
+    /// Creates the machine with the DIP switches `dips`, with no ROM loaded.
+    pub fn new(dips: Dips) -> Self {
+        let rom = vec![0; ROM_SIZE];
+        let board = Board {
+            rom: vec![0; PROGRAM_SIZE],
+            ram: vec![0; RAM_SIZE],
+            positions: [0; 2 * SPRITES],
+            latches: 0,
+            vector: 0,
+            interrupt: false,
+            watchdog: 0,
+            buttons: [false; BUTTONS.len()],
+            dips,
+            wsg: Wsg::new(&rom[ROM_SIZE - WAVES_SIZE..]),
+        };
+        let mut pacman = PacMan {
+            cpu: Cpu::new(),
+            board,
+            video: video(&rom),
+            rom_hash: emu_state::hash(&[]),
+            cycle: 0,
+            samples: Vec::new(),
+            framebuffer: vec![0; WIDTH * HEIGHT * 4],
+        };
+        pacman.draw();
+        pacman
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns RAM, from 0x4000: video RAM, color RAM, 1KB where there is none, and RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.board.ram
+    }
+
+    /// Returns RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.board.ram
+    }
+
+    /// Returns the DIP switches, to change them. The game reads most of them as it starts.
+    pub fn dips_mut(&mut self) -> &mut Dips {
+        &mut self.board.dips
+    }
+
+    /// Draws the screen from video RAM and the sprites.
+    fn draw(&mut self) {
+        let board = &self.board;
+        let flip = board.latches & 0x08 != 0;
+        self.video
+            .draw(&board.ram, &board.positions, flip, &mut self.framebuffer);
+    }
+
+    /// Resets the processor and the latches, as the watchdog does, leaving RAM as it is.
+    fn restart(&mut self) {
+        self.cpu = Cpu::new();
+        let board = &mut self.board;
+        board.latches = 0;
+        board.vector = 0;
+        board.interrupt = false;
+        board.watchdog = 0;
+        board.wsg.enabled = false;
+    }
+
+    /// Moves the beam on by `cycles`. As it leaves the picture, which ends the frame, draws the
+    /// screen, asks for the interrupt if it is enabled, and counts a frame of the watchdog.
+    /// Returns `true` if the frame ended.
+    fn advance(&mut self, cycles: u32) -> bool {
+        self.cycle += cycles;
+        if self.cycle < CYCLES_PER_FRAME {
+            return false;
+        }
+        self.cycle -= CYCLES_PER_FRAME;
+        self.draw();
+        let board = &mut self.board;
+        if board.latches & 0x01 != 0 {
+            board.interrupt = true;
+        }
+        board.watchdog += 1;
+        if board.watchdog >= WATCHDOG_FRAMES {
+            self.restart();
+        }
+        true
+    }
+}
+
+impl Emulator for PacMan {
+    fn name(&self) -> &'static str {
+        "Pac-Man"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: WIDTH,
+            height: HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Loads the ROMs and PROMs one after another, and turns the machine on.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        if rom.len() != ROM_SIZE {
+            return Err(Error::Rom(format!(
+                "the ROM is {} bytes, not the {} of pacman.6e, .6f, .6h, .6j, .5e and .5f, \
+                 82s123.7f, 82s126.4a and 82s126.1m one after another",
+                rom.len(),
+                ROM_SIZE
+            )));
+        }
+        self.board.rom.copy_from_slice(&rom[..PROGRAM_SIZE]);
+        self.board.wsg = Wsg::new(&rom[ROM_SIZE - WAVES_SIZE..]);
+        self.video = video(rom);
+        self.rom_hash = emu_state::hash(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Turns the machine off and on again, clearing RAM as the game's own test does.
+    fn reset(&mut self) {
+        self.restart();
+        let board = &mut self.board;
+        board.ram.iter_mut().for_each(|byte| *byte = 0);
+        board.positions = [0; 2 * SPRITES];
+        board.wsg.reset();
+        self.cycle = 0;
+        self.samples.clear();
+        self.draw();
+    }
+
+    fn step_frame(&mut self) -> Result<(), Error> {
+        loop {
+            let cycles = u32::from(self.cpu.step(&mut self.board));
+            self.board.wsg.run(cycles, &mut self.samples);
+            if self.advance(cycles) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if let Some(held) = self.board.buttons.get_mut(button) {
+            *held = pressed;
+        }
+    }
+
+    /// Saves the processor, RAM, the places of the sprites, the latches, the interrupt, the
+    /// watchdog, the sound chip and where the beam is.
+    fn save_state(&self) -> Vec<u8> {
+        let board = &self.board;
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(&board.ram);
+        state.extend_from_slice(&board.positions);
+        state.extend_from_slice(&[
+            board.latches,
+            board.vector,
+            u8::from(board.interrupt),
+            board.watchdog,
+        ]);
+        state.extend_from_slice(&board.wsg.save());
+        state.extend_from_slice(&self.cycle.to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let length =
+            MAGIC.len() + 8 + STATE_SIZE + RAM_SIZE + 2 * SPRITES + 4 + wsg::STATE_SIZE + 4;
+        if state.len() != length || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this machine".to_string()));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(STATE_SIZE);
+        let (ram, rest) = rest.split_at(RAM_SIZE);
+        let (positions, rest) = rest.split_at(2 * SPRITES);
+        let (latches, rest) = rest.split_at(4);
+        let (wsg, rest) = rest.split_at(wsg::STATE_SIZE);
+        let cycle = u32::from_le_bytes(<[u8; 4]>::try_from(rest).unwrap());
+        if cycle >= CYCLES_PER_FRAME || latches[3] >= WATCHDOG_FRAMES {
+            return Err(Error::State("the state is corrupt".to_string()));
+        }
+        self.cpu
+            .restore(<&[u8; STATE_SIZE]>::try_from(cpu).unwrap());
+        let board = &mut self.board;
+        board.ram.copy_from_slice(ram);
+        board.positions.copy_from_slice(positions);
+        board.latches = latches[0];
+        board.vector = latches[1];
+        board.interrupt = latches[2] != 0;
+        board.watchdog = latches[3];
+        board
+            .wsg
+            .restore(<&[u8; wsg::STATE_SIZE]>::try_from(wsg).unwrap());
+        self.cycle = cycle;
+        self.draw();
+        Ok(())
+    }
+
+    fn debug(&mut self) -> Option<Box<dyn Target + '_>> {
+        Some(Box::new(Z80::new(&mut self.cpu, &mut self.board)))
+    }
+}
+
+/// Decodes the graphics and colors of the ROMs `rom`, one after another as [`Emulator::load_rom`]
+/// takes them.
+fn video(rom: &[u8]) -> Video {
+    let (tiles, rest) = rom[PROGRAM_SIZE..].split_at(TILES_SIZE);
+    let (sprites, rest) = rest.split_at(SPRITES_SIZE);
+    let (palette, rest) = rest.split_at(PALETTE_SIZE);
+    Video::new(tiles, sprites, palette, &rest[..LOOKUP_SIZE])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where the color PROM is among the ROMs.
+    const PALETTE: usize = PROGRAM_SIZE + TILES_SIZE + SPRITES_SIZE;
+
+    /// Where the lookup PROM is among the ROMs.
+    const LOOKUP: usize = PALETTE + PALETTE_SIZE;
+
+    /// Returns a machine whose ROMs, one after another, hold `bytes` at each offset.
+    fn machine(bytes: &[(usize, &[u8])]) -> PacMan {
+        let mut rom = vec![0; ROM_SIZE];
+        for &(offset, bytes) in bytes {
+            rom[offset..][..bytes.len()].copy_from_slice(bytes);
+        }
+        let mut pacman = PacMan::new(Dips::default());
+        pacman.load_rom(&rom).unwrap();
+        pacman
+    }
+
+    /// A program that writes to the watchdog and loops with interrupts off.
+    const LOOP: (usize, &[u8]) = (0, &[0x32, 0xC0, 0x50, 0x18, 0xFB]);
+
+    fn pixel(pacman: &PacMan, x: usize, y: usize) -> [u8; 4] {
+        let mut color = [0; 4];
+        color.copy_from_slice(&pacman.framebuffer()[(y * WIDTH + x) * 4..][..4]);
+        color
+    }
+
+    #[test]
+    fn maps_memory() {
+        let mut pacman = machine(&[(0x3FFF, &[0x42])]);
+        let board = &mut pacman.board;
+        board.write(0x0000, 0x11);
+        assert_eq!(board.read(0x0000), 0);
+        assert_eq!(board.read(0xBFFF), 0x42);
+        board.write(0x4000, 0x99);
+        assert_eq!(board.read(0x6000), 0x99);
+        assert_eq!(board.read(0xC000), 0x99);
+        board.write(0x4800, 0x55);
+        assert_eq!(board.read(0x4800), 0xBF);
+        board.write(0xCFFF, 0x77);
+        assert_eq!(pacman.ram()[0xFFF], 0x77);
+
+        let board = &mut pacman.board;
+        board.write(0x5065, 0x12);
+        board.write(0x506F, 0x34);
+        assert_eq!(board.positions[5], 0x12);
+        assert_eq!(board.positions[15], 0x34);
+        board.write(0x5055, 0x0F);
+        assert_eq!(board.wsg.volume(0), 0x0F);
+        board.write(0x5003, 0x01);
+        board.write(0x503D, 0xFF);
+        assert_eq!(board.latches, 0x28);
+        board.write(0x5001, 0x01);
+        assert!(board.wsg.enabled);
+    }
+
+    #[test]
+    fn debugs() {
+        use emu_debug::debugger::{Debugger, StopReason};
+
+        // 0x0000: LD SP, 4FF0; CALL 0010; JR $; 0x0010: LD A, 2A; LD (4000), A; RET
+        let call: &[u8] = &[0x31, 0xF0, 0x4F, 0xCD, 0x10, 0x00, 0x18, 0xFE];
+        let subroutine: &[u8] = &[0x3E, 0x2A, 0x32, 0x00, 0x40, 0xC9];
+        let mut pacman = machine(&[(0, call), (0x10, subroutine)]);
+        let mut target = pacman.debug().unwrap();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.step(target.as_mut()), StopReason::Step);
+        assert_eq!(debugger.start_step_over(target.as_mut()), None);
+        let reason = loop {
+            if let Some(reason) = debugger.run_step(target.as_mut()) {
+                break reason;
+            }
+        };
+        assert_eq!(reason, StopReason::Step);
+        assert_eq!(target.program_counter(), 0x0006);
+        assert_eq!(target.peek(0xC000), 0x2A);
+        target.poke(0x4001, 0x99);
+        target.poke(0x5003, 0x01);
+        drop(target);
+        assert_eq!(pacman.ram()[..2], [0x2A, 0x99]);
+        assert_eq!(pacman.board.latches, 0);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut pacman = machine(&[
+            // LD SP, 4FC0; LD A, 10; LD I, A; IM 2; LD A, 20; OUT (00), A
+            (
+                0x00,
+                &[
+                    0x31, 0xC0, 0x4F, 0x3E, 0x10, 0xED, 0x47, 0xED, 0x5E, 0x3E, 0x20, 0xD3, 0x00,
+                ],
+            ),
+            // LD A, 01; LD (5000), A; EI; LD (50C0), A; JR -5
+            (
+                0x0D,
+                &[
+                    0x3E, 0x01, 0x32, 0x00, 0x50, 0xFB, 0x32, 0xC0, 0x50, 0x18, 0xFB,
+                ],
+            ),
+            (0x1020, &[0x00, 0x01]),
+            // LD HL, 4C00; INC (HL); EI; RETI
+            (0x100, &[0x21, 0x00, 0x4C, 0x34, 0xFB, 0xED, 0x4D]),
+        ]);
+        pacman.step_frame().unwrap();
+        assert!(pacman.board.interrupt);
+        pacman.step_frame().unwrap();
+        pacman.step_frame().unwrap();
+        assert_eq!(pacman.ram()[0xC00], 2);
+        assert_eq!(pacman.cpu().interrupt_mode(), 2);
+
+        pacman.board.write(0x5000, 0);
+        assert!(!pacman.board.interrupt);
+        for _ in 0..20 {
+            pacman.step_frame().unwrap();
+        }
+        assert_eq!(pacman.ram()[0xC00], 2);
+    }
+
+    #[test]
+    fn watchdog_resets() {
+        // LD HL, 4C00; INC (HL); JR $
+        let mut pacman = machine(&[(0x00, &[0x21, 0x00, 0x4C, 0x34, 0x18, 0xFE])]);
+        pacman.board.latches = 0x01;
+        for _ in 0..WATCHDOG_FRAMES {
+            pacman.step_frame().unwrap();
+        }
+        assert_eq!(pacman.ram()[0xC00], 1);
+        assert_eq!(pacman.board.latches, 0);
+        pacman.step_frame().unwrap();
+        assert_eq!(pacman.ram()[0xC00], 2);
+
+        let mut pacman = machine(&[LOOP]);
+        for _ in 0..2 * WATCHDOG_FRAMES {
+            pacman.step_frame().unwrap();
+        }
+        assert!(pacman.cpu().cycles() > u64::from(CYCLES_PER_FRAME) * 31);
+    }
+
+    #[test]
+    fn reads_buttons_and_dips() {
+        let mut pacman = PacMan::new(Dips::default());
+        assert_eq!(pacman.board.read(0x5000), 0xFF);
+        assert_eq!(pacman.board.read(0x5040), 0xFF);
+        assert_eq!(pacman.board.read(0x5080), 0xC9);
+        for button in 0..BUTTONS.len() {
+            pacman.set_button(button, true);
+        }
+        pacman.set_button(BUTTONS.len(), true);
+        assert_eq!(pacman.board.read(0x5000), 0x50);
+        assert_eq!(pacman.board.read(0x503F), 0x50);
+        assert_eq!(pacman.board.read(0x5040), 0x90);
+        pacman.set_button(5, false);
+        pacman.set_button(2, false);
+        assert_eq!(pacman.board.read(0x5000), 0x52);
+        assert_eq!(pacman.board.read(0x5040), 0xD0);
+
+        *pacman.dips_mut() = Dips {
+            coinage: Coinage::FreePlay,
+            lives: 5,
+            bonus_life: None,
+            hard: true,
+            alternate_names: true,
+            cocktail: true,
+            rack_test: true,
+            service: true,
+        };
+        for button in 0..BUTTONS.len() {
+            pacman.set_button(button, false);
+        }
+        assert_eq!(pacman.board.read(0x5080), 0x3C);
+        assert_eq!(pacman.board.read(0x5000), 0xEF);
+        assert_eq!(pacman.board.read(0x5040), 0x6F);
+        pacman.dips_mut().lives = 2;
+        pacman.dips_mut().bonus_life = Some(15_000);
+        pacman.dips_mut().coinage = Coinage::TwoCoinsOneCredit;
+        assert_eq!(pacman.board.read(0x5080), 0x17);
+    }
+
+    #[test]
+    fn draws() {
+        let mut pacman = machine(&[
+            LOOP,
+            (PROGRAM_SIZE + 16, &[0x0F; 16]),
+            (PALETTE + 1, &[0x07]),
+            (LOOKUP + 4, &[0, 1]),
+        ]);
+        // Tile 1 in palette 1 at the left of the third row, where the maze starts.
+        pacman.ram_mut()[0x3A0] = 1;
+        pacman.ram_mut()[0x7A0] = 1;
+        pacman.step_frame().unwrap();
+        let red = [0xFF, 0x00, 0x00, 0xFF];
+        assert_eq!(pixel(&pacman, 0, 16), red);
+        assert_eq!(pixel(&pacman, 7, 23), red);
+        assert_eq!(pixel(&pacman, 8, 16), [0x00, 0x00, 0x00, 0xFF]);
+
+        pacman.board.write(0x5003, 1);
+        pacman.step_frame().unwrap();
+        assert_eq!(pixel(&pacman, WIDTH - 1, HEIGHT - 17), red);
+    }
+
+    #[test]
+    fn plays_sound() {
+        let mut pacman = machine(&[LOOP]);
+        pacman.board.write(0x5055, 0x0F);
+        pacman.step_frame().unwrap();
+        let mut samples = Vec::new();
+        pacman.pull_audio(&mut samples);
+        assert_eq!(samples.len() as u32, CYCLES_PER_FRAME / 64);
+        assert!(samples.iter().all(|&sample| sample == 0));
+
+        // The waveforms are all 0, the lowest sample.
+        pacman.board.write(0x5001, 0x01);
+        pacman.step_frame().unwrap();
+        samples.clear();
+        pacman.pull_audio(&mut samples);
+        assert!(samples.iter().all(|&sample| sample == -8 * 15 * 64));
+    }
+
+    #[test]
+    fn saves_state() {
+        let code: &[(usize, &[u8])] = &[LOOP];
+        let mut pacman = machine(code);
+        pacman.board.write(0x5000, 1);
+        pacman.board.write(0x5001, 1);
+        pacman.board.write(0x5050, 7);
+        pacman.board.write(0x5062, 0x40);
+        pacman.board.output(0, 0xFA);
+        pacman.ram_mut()[0x400] = 0x1F;
+        pacman.step_frame().unwrap();
+        pacman.advance(1000);
+        let state = pacman.save_state();
+
+        let mut restored = machine(code);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert!(restored.board.interrupt);
+        assert_eq!(restored.board.vector, 0xFA);
+        assert_eq!(restored.board.positions[2], 0x40);
+        assert_eq!(restored.board.wsg.frequency(0), 7);
+        assert!(restored.board.wsg.enabled);
+        assert_eq!(restored.cycle, pacman.cycle);
+        assert_eq!(restored.framebuffer(), pacman.framebuffer());
+
+        let mut other = machine(&[]);
+        assert!(matches!(other.load_state(&state), Err(Error::State(_))));
+        assert!(matches!(
+            restored.load_state(&state[1..]),
+            Err(Error::State(_))
+        ));
+    }
+
+    #[test]
+    fn loads_only_the_whole_rom() {
+        let mut pacman = PacMan::new(Dips::default());
+        let error = pacman.load_rom(&[0; PROGRAM_SIZE]).unwrap_err();
+        assert!(matches!(error, Error::Rom(_)));
+        assert!(pacman.load_rom(&[0; ROM_SIZE]).is_ok());
+    }
+}
@@ -0,0 +1,320 @@
+//! The video hardware: 28x36 tiles of 8x8 dots, and 8 sprites of 16x16 dots over them, each in 4
+//! colors out of 16 chosen by a palette number.
+//!
+//! Each dot is 2 bits of a graphics ROM, `5E` for the tiles and `5F` for the sprites. With the 5-bit
+//! palette number of its tile or sprite, they choose one of the entries of the lookup PROM, `4A`,
+//! whose low 4 bits choose one of the colors of the color PROM, `7F`. Each color is a byte: 3 bits
+//! of red, 3 of green and 2 of blue, through resistors of 1kΩ, 470Ω and 220Ω. Sprite dots whose
+//! entry is the first color are transparent.
+//!
+//! The monitor is on its side: the beam draws 224 lines of 288 dots, which the player sees as 224
+//! dots wide and 288 high. The graphics ROMs are laid out for the beam, and are turned as they are
+//! decoded, so that everything else here is as the player sees it.
+
+/// The width of the screen as the player sees it.
+pub const WIDTH: usize = 224;
+
+/// The height of the screen as the player sees it.
+pub const HEIGHT: usize = 288;
+
+/// Bytes of the tile ROM, `5E`: 256 tiles of 16 bytes.
+pub const TILES_SIZE: usize = 0x1000;
+
+/// Bytes of the sprite ROM, `5F`: 64 sprites of 64 bytes.
+pub const SPRITES_SIZE: usize = 0x1000;
+
+/// Bytes of the color PROM, `7F`.
+pub const PALETTE_SIZE: usize = 32;
+
+/// Bytes of the lookup PROM, `4A`.
+pub const LOOKUP_SIZE: usize = 256;
+
+/// Sprites on the screen at once.
+pub const SPRITES: usize = 8;
+
+/// Tiles across the screen.
+const COLUMNS: usize = WIDTH / 8;
+
+/// Tiles down the screen.
+const ROWS: usize = HEIGHT / 8;
+
+/// Where the sprites' numbers and palettes are in memory, from 0x4000.
+const SPRITE_ATTRIBUTES: usize = 0xFF0;
+
+/// The lines of the screen sprites are drawn on: not on the two rows of tiles at the top and the
+/// bottom, which hold the scores and the lives.
+const SPRITE_LINES: core::ops::Range<i32> = 16..272;
+
+/// The graphics and colors of the machine, decoded from its ROMs.
+#[derive(Clone)]
+pub struct Video {
+    /// The dots of each tile, row by row.
+    tiles: Vec<[u8; 64]>,
+    /// The dots of each sprite, row by row.
+    sprites: Vec<[u8; 256]>,
+    /// The color of each entry of the lookup PROM, in RGBA.
+    colors: Vec<[u8; 4]>,
+    /// Whether each entry of the lookup PROM is transparent in sprites.
+    transparent: Vec<bool>,
+}
+
+impl Video {
+    /// Decodes the tile ROM `tiles`, the sprite ROM `sprites`, the color PROM `palette` and the
+    /// lookup PROM `lookup`, of [`TILES_SIZE`], [`SPRITES_SIZE`], [`PALETTE_SIZE`] and
+    /// [`LOOKUP_SIZE`] bytes.
+    pub fn new(tiles: &[u8], sprites: &[u8], palette: &[u8], lookup: &[u8]) -> Self {
+        Video {
+            tiles: tiles.chunks_exact(16).map(decode_tile).collect(),
+            sprites: sprites.chunks_exact(64).map(decode_sprite).collect(),
+            colors: lookup
+                .iter()
+                .map(|&entry| color(palette[usize::from(entry & 0x0F)]))
+                .collect(),
+            transparent: lookup.iter().map(|&entry| entry & 0x0F == 0).collect(),
+        }
+    }
+
+    /// Draws the screen into `framebuffer`, in RGBA: the tiles of video RAM, `memory[..0x400]`, in
+    /// the palettes of color RAM, `memory[0x400..0x800]`, and over them the sprites numbered,
+    /// flipped and colored by `memory[0xFF0..0x1000]`, at the places of `positions`, written at
+    /// 0x5060. `memory` is what is mapped from 0x4000. With `flip`, the screen is turned upside
+    /// down, for the second player of a cocktail table.
+    pub fn draw(
+        &self,
+        memory: &[u8],
+        positions: &[u8; 2 * SPRITES],
+        flip: bool,
+        framebuffer: &mut [u8],
+    ) {
+        let mut plot = |x: usize, y: usize, color: &[u8; 4]| {
+            let (x, y) = if flip {
+                (WIDTH - 1 - x, HEIGHT - 1 - y)
+            } else {
+                (x, y)
+            };
+            framebuffer[(y * WIDTH + x) * 4..][..4].copy_from_slice(color);
+        };
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let offset = tile_offset(column, row);
+                let tile = &self.tiles[usize::from(memory[offset])];
+                let palette = usize::from(memory[0x400 + offset] & 0x1F) * 4;
+                for (dot, &value) in tile.iter().enumerate() {
+                    let color = &self.colors[palette + usize::from(value)];
+                    plot(column * 8 + dot % 8, row * 8 + dot / 8, color);
+                }
+            }
+        }
+        // The first sprite is drawn last, over the others, and the first three are a dot to the
+        // left of where the others would be.
+        for sprite in (0..SPRITES).rev() {
+            let attributes = memory[SPRITE_ATTRIBUTES + 2 * sprite];
+            let palette = usize::from(memory[SPRITE_ATTRIBUTES + 2 * sprite + 1] & 0x1F) * 4;
+            let left = 239 - i32::from(positions[2 * sprite]) - i32::from(sprite < 3);
+            let top = 272 - i32::from(positions[2 * sprite + 1]);
+            for (dot, &value) in self.sprites[usize::from(attributes >> 2)]
+                .iter()
+                .enumerate()
+            {
+                let entry = palette + usize::from(value);
+                if self.transparent[entry] {
+                    continue;
+                }
+                let (mut dx, mut dy) = ((dot % 16) as i32, (dot / 16) as i32);
+                if attributes & 0x02 != 0 {
+                    dx = 15 - dx;
+                }
+                if attributes & 0x01 != 0 {
+                    dy = 15 - dy;
+                }
+                let x = left + dx;
+                // A sprite past the bottom of the screen comes back at the top.
+                for &y in &[top + dy, top + dy - 256] {
+                    if (0..WIDTH as i32).contains(&x) && SPRITE_LINES.contains(&y) {
+                        plot(x as usize, y as usize, &self.colors[entry]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns where in video RAM the tile at `column` and `row` of the screen is.
+///
+/// The 28x32 tiles of the maze are stored a column at a time, from the top right to the bottom
+/// left, after the two rows at the bottom of the screen and before the two at the top. Those rows
+/// are stored from right to left, and are 32 tiles long, of which the first and last two are off
+/// the screen.
+fn tile_offset(column: usize, row: usize) -> usize {
+    let from_right = COLUMNS - 1 - column;
+    match row {
+        0 | 1 => 0x3C2 + row * 0x20 + from_right,
+        34 | 35 => 0x002 + (row - 34) * 0x20 + from_right,
+        _ => 0x040 + from_right * 0x20 + row - 2,
+    }
+}
+
+/// Returns the color of a byte of the color PROM, in RGBA.
+fn color(byte: u8) -> [u8; 4] {
+    let bit = |n: u8| u16::from(byte >> n & 1);
+    let level = |first: u8| 0x21 * bit(first) + 0x47 * bit(first + 1) + 0x97 * bit(first + 2);
+    let blue = 0x51 * bit(6) + 0xAE * bit(7);
+    [level(0) as u8, level(3) as u8, blue as u8, 0xFF]
+}
+
+/// Returns the dot numbered `n`, from 0 to 3, of the four a byte of graphics holds: its high bit
+/// is in the high nibble, and its low bit in the low nibble.
+fn dot(byte: u8, n: usize) -> u8 {
+    (byte >> (7 - n) & 1) << 1 | byte >> (3 - n) & 1
+}
+
+/// Decodes the 16 bytes of a tile. Each byte is a strip of 4 dots down the screen: the first 8 are
+/// the lower half of the tile from right to left, and the next 8 the upper half.
+fn decode_tile(bytes: &[u8]) -> [u8; 64] {
+    let mut dots = [0; 64];
+    for (n, value) in dots.iter_mut().enumerate() {
+        let (x, y) = (n % 8, n / 8);
+        let half = if y < 4 { 8 } else { 0 };
+        *value = dot(bytes[half + 7 - x], y % 4);
+    }
+    dots
+}
+
+/// Decodes the 64 bytes of a sprite, strips of 4 dots down the screen as in tiles. The four
+/// quarters of the sprite from top to bottom are 8 bytes apart, the last at the start, and the
+/// right half comes first.
+fn decode_sprite(bytes: &[u8]) -> [u8; 256] {
+    const QUARTERS: [usize; 4] = [8, 16, 24, 0];
+    let mut dots = [0; 256];
+    for (n, value) in dots.iter_mut().enumerate() {
+        let (x, y) = (n % 16, n / 16);
+        let strip = 15 - x;
+        let half = if strip < 8 { strip } else { strip + 24 };
+        *value = dot(bytes[QUARTERS[y / 4] + half], y % 4);
+    }
+    dots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+    const RED: [u8; 4] = [0xFF, 0x00, 0x00, 0xFF];
+    const BLUE: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+    /// Returns the video of a tile 1 that is all dots of value 1, a tile 2 with only its top left
+    /// dot of value 3, and a sprite 1 with only its top left dot of value 2, with palette 1 in
+    /// black, red, blue and white.
+    fn video() -> Video {
+        let mut tiles = vec![0; TILES_SIZE];
+        tiles[16..32].iter_mut().for_each(|byte| *byte = 0x0F);
+        tiles[32 + 15] = 0x88;
+        let mut sprites = vec![0; SPRITES_SIZE];
+        sprites[64 + 8 + 39] = 0x80;
+        let mut palette = [0; PALETTE_SIZE];
+        palette[1..4].copy_from_slice(&[0x07, 0xC0, 0xFF]);
+        let mut lookup = [0; LOOKUP_SIZE];
+        lookup[4..8].copy_from_slice(&[0, 1, 2, 3]);
+        Video::new(&tiles, &sprites, &palette, &lookup)
+    }
+
+    fn pixel(framebuffer: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let mut color = [0; 4];
+        color.copy_from_slice(&framebuffer[(y * WIDTH + x) * 4..][..4]);
+        color
+    }
+
+    #[test]
+    fn decodes_colors() {
+        assert_eq!(color(0x00), BLACK);
+        assert_eq!(color(0x07), RED);
+        assert_eq!(color(0xC0), BLUE);
+        assert_eq!(color(0xFF), WHITE);
+        assert_eq!(color(0x01), [0x21, 0, 0, 0xFF]);
+        assert_eq!(color(0x10), [0, 0x47, 0, 0xFF]);
+        assert_eq!(color(0x40), [0, 0, 0x51, 0xFF]);
+    }
+
+    #[test]
+    fn lays_out_tiles() {
+        assert_eq!(tile_offset(27, 0), 0x3C2);
+        assert_eq!(tile_offset(0, 0), 0x3DD);
+        assert_eq!(tile_offset(0, 1), 0x3FD);
+        assert_eq!(tile_offset(27, 2), 0x040);
+        assert_eq!(tile_offset(27, 3), 0x041);
+        assert_eq!(tile_offset(26, 2), 0x060);
+        assert_eq!(tile_offset(0, 33), 0x3BF);
+        assert_eq!(tile_offset(27, 34), 0x002);
+        assert_eq!(tile_offset(0, 35), 0x03D);
+    }
+
+    #[test]
+    fn decodes_graphics() {
+        let mut tile = [0; 16];
+        tile[15] = 0x80;
+        tile[0] = 0x01;
+        let dots = decode_tile(&tile);
+        assert_eq!(dots[0], 2);
+        assert_eq!(dots[7 * 8 + 7], 1);
+        assert_eq!(dots.iter().filter(|&&dot| dot != 0).count(), 2);
+
+        let mut sprite = [0; 64];
+        sprite[8 + 39] = 0x80;
+        sprite[7] = 0x11;
+        let dots = decode_sprite(&sprite);
+        assert_eq!(dots[0], 2);
+        assert_eq!(dots[15 * 16 + 8], 3);
+        assert_eq!(dots.iter().filter(|&&dot| dot != 0).count(), 2);
+    }
+
+    #[test]
+    fn draws_tiles() {
+        let video = video();
+        let mut memory = vec![0; 0x1000];
+        memory[tile_offset(3, 5)] = 1;
+        memory[0x400 + tile_offset(3, 5)] = 1;
+        memory[tile_offset(0, 35)] = 2;
+        memory[0x400 + tile_offset(0, 35)] = 0x21;
+        let mut framebuffer = vec![0; WIDTH * HEIGHT * 4];
+        video.draw(&memory, &[0; 16], false, &mut framebuffer);
+        assert_eq!(pixel(&framebuffer, 24, 40), RED);
+        assert_eq!(pixel(&framebuffer, 31, 47), RED);
+        assert_eq!(pixel(&framebuffer, 32, 47), BLACK);
+        assert_eq!(pixel(&framebuffer, 0, 280), WHITE);
+        assert_eq!(pixel(&framebuffer, 1, 280), BLACK);
+
+        video.draw(&memory, &[0; 16], true, &mut framebuffer);
+        assert_eq!(pixel(&framebuffer, WIDTH - 1, HEIGHT - 1 - 280), WHITE);
+    }
+
+    #[test]
+    fn draws_sprites() {
+        let video = video();
+        let mut memory = vec![0; 0x1000];
+        let mut positions = [0; 16];
+        // Sprite 3, sprite 1 with palette 1, at 100 from the left and 50 from the top.
+        memory[0xFF6] = 1 << 2;
+        memory[0xFF7] = 1;
+        positions[6] = 139;
+        positions[7] = 222;
+        let mut framebuffer = vec![0; WIDTH * HEIGHT * 4];
+        video.draw(&memory, &positions, false, &mut framebuffer);
+        assert_eq!(pixel(&framebuffer, 100, 50), BLUE);
+        assert_eq!(framebuffer.chunks(4).filter(|&p| p != BLACK).count(), 1);
+
+        // Flipped both ways, and moved a dot left as the first sprite.
+        memory[0xFF0] = 1 << 2 | 0x03;
+        memory[0xFF1] = 1;
+        positions[..2].copy_from_slice(&[139, 222]);
+        video.draw(&memory, &positions, false, &mut framebuffer);
+        assert_eq!(pixel(&framebuffer, 99 + 15, 50 + 15), BLUE);
+
+        // Past the bottom of the screen, over the lives, it comes back at the top.
+        positions[1] = 0;
+        video.draw(&memory, &positions, false, &mut framebuffer);
+        assert_eq!(pixel(&framebuffer, 99 + 15, 16 + 15), BLUE);
+        assert_eq!(framebuffer.chunks(4).filter(|&p| p != BLACK).count(), 2);
+    }
+}
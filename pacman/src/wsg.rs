@@ -0,0 +1,260 @@
+//! The Namco WSG, the waveform sound generator: three voices, each playing one of 8 waveforms of
+//! 32 4-bit samples from the sound PROM, `1M`, at a frequency and volume of its own.
+//!
+//! Its 32 registers, at 0x5040 to 0x505F, hold 4 bits each, low nibbles first. 96,000 times a
+//! second, the 3.072MHz clock divided by 32, each voice adds its frequency to its accumulator,
+//! whose top 5 bits of 20 choose the sample of its waveform. The first voice has 20 bits of
+//! frequency and accumulator, and the others the top 16, their lowest 4 bits always 0:
+//!
+//! | Registers   | Voice 1     | Voice 2     | Voice 3     |
+//! |-------------|-------------|-------------|-------------|
+//! | Accumulator | 0x00 - 0x04 | 0x06 - 0x09 | 0x0B - 0x0E |
+//! | Waveform    | 0x05        | 0x0A        | 0x0F        |
+//! | Frequency   | 0x10 - 0x14 | 0x16 - 0x19 | 0x1B - 0x1E |
+//! | Volume      | 0x15        | 0x1A        | 0x1F        |
+
+/// Bytes of the sound PROM, `1M`: 8 waveforms of 32 samples, in their low nibbles.
+pub const WAVES_SIZE: usize = 256;
+
+/// Samples per second of the sound, half those of the chip.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Voices of the chip.
+pub const VOICES: usize = 3;
+
+/// Length of the state written by [`Wsg::save`].
+pub const STATE_SIZE: usize = REGISTERS + 4 * VOICES + 2;
+
+/// Registers of the chip.
+const REGISTERS: usize = 32;
+
+/// Clock cycles of the processor in a sample of the sound: two of the chip's.
+const CYCLES_PER_SAMPLE: u32 = 64;
+
+/// The loudness of a step of a sample at the highest volume.
+const GAIN: i32 = 64;
+
+/// The sound chip.
+#[derive(Clone)]
+pub struct Wsg {
+    waves: Vec<u8>,
+    registers: [u8; REGISTERS],
+    accumulators: [u32; VOICES],
+    /// Whether sound is on, from bit 0 of 0x5001. The voices stop and are silent while it is off.
+    pub enabled: bool,
+    /// Clock cycles of the processor since the last sample.
+    cycles: u32,
+}
+
+impl Wsg {
+    /// Creates the chip with the sound PROM `waves`, of [`WAVES_SIZE`] bytes, silent.
+    pub fn new(waves: &[u8]) -> Self {
+        Wsg {
+            waves: waves.iter().map(|&sample| sample & 0x0F).collect(),
+            registers: [0; REGISTERS],
+            accumulators: [0; VOICES],
+            enabled: false,
+            cycles: 0,
+        }
+    }
+
+    /// Clears the registers and turns sound off, as when the machine is turned on.
+    pub fn reset(&mut self) {
+        self.registers = [0; REGISTERS];
+        self.accumulators = [0; VOICES];
+        self.enabled = false;
+        self.cycles = 0;
+    }
+
+    /// Writes the low nibble of `value` to the register numbered `register`, from 0 to 31.
+    pub fn write(&mut self, register: u8, value: u8) {
+        let register = usize::from(register) % REGISTERS;
+        let value = value & 0x0F;
+        self.registers[register] = value;
+        if register < 0x10 {
+            let (voice, nibble) = (register / 5, register % 5);
+            if voice < VOICES && (nibble != 0 || voice == 0) {
+                let shift = 4 * nibble;
+                let accumulator = &mut self.accumulators[voice];
+                *accumulator = *accumulator & !(0x0F << shift) | u32::from(value) << shift;
+            }
+        }
+    }
+
+    /// Returns the frequency of the voice `voice`, added to its accumulator at each clock.
+    pub fn frequency(&self, voice: usize) -> u32 {
+        let first = if voice == 0 { 0 } else { 1 };
+        (first..5).fold(0, |frequency, nibble| {
+            frequency | u32::from(self.registers[0x10 + 5 * voice + nibble]) << (4 * nibble)
+        })
+    }
+
+    /// Returns the volume of the voice `voice`, from 0 to 15.
+    pub fn volume(&self, voice: usize) -> u8 {
+        self.registers[0x15 + 5 * voice]
+    }
+
+    /// Returns the waveform the voice `voice` plays, from 0 to 7.
+    pub fn waveform(&self, voice: usize) -> u8 {
+        self.registers[0x05 + 5 * voice] & 7
+    }
+
+    /// Runs the chip for `cycles` clock cycles of the processor, adding the samples it finishes to
+    /// `samples`.
+    pub fn run(&mut self, cycles: u32, samples: &mut Vec<i16>) {
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_SAMPLE {
+            self.cycles -= CYCLES_PER_SAMPLE;
+            let sample = if self.enabled {
+                (self.clock() + self.clock()) * GAIN / 2
+            } else {
+                0
+            };
+            samples.push(sample as i16);
+        }
+    }
+
+    /// Moves each voice on by a clock of the chip, and returns their samples added together, from
+    /// -8 to 7 times their volume.
+    fn clock(&mut self) -> i32 {
+        (0..VOICES)
+            .map(|voice| {
+                let accumulator = (self.accumulators[voice] + self.frequency(voice)) & 0xF_FFFF;
+                self.accumulators[voice] = accumulator;
+                let index = usize::from(self.waveform(voice)) * 32 + (accumulator >> 15) as usize;
+                (i32::from(self.waves[index]) - 8) * i32::from(self.volume(voice))
+            })
+            .sum()
+    }
+
+    /// Returns the state of the chip, for [`restore`](Wsg::restore).
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..REGISTERS].copy_from_slice(&self.registers);
+        for (voice, accumulator) in self.accumulators.iter().enumerate() {
+            state[REGISTERS + 4 * voice..][..4].copy_from_slice(&accumulator.to_le_bytes());
+        }
+        state[STATE_SIZE - 2] = u8::from(self.enabled);
+        state[STATE_SIZE - 1] = self.cycles as u8;
+        state
+    }
+
+    /// Restores a state returned by [`save`](Wsg::save).
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        for (register, &value) in self.registers.iter_mut().zip(state.iter()) {
+            *register = value & 0x0F;
+        }
+        for (voice, accumulator) in self.accumulators.iter_mut().enumerate() {
+            let bytes = [
+                state[REGISTERS + 4 * voice],
+                state[REGISTERS + 4 * voice + 1],
+                state[REGISTERS + 4 * voice + 2],
+                state[REGISTERS + 4 * voice + 3],
+            ];
+            *accumulator = u32::from_le_bytes(bytes) & 0xF_FFFF;
+        }
+        self.enabled = state[STATE_SIZE - 2] != 0;
+        self.cycles = u32::from(state[STATE_SIZE - 1]) % CYCLES_PER_SAMPLE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the chip with a square wave, high then low, as waveform 1, and the others silent.
+    fn wsg() -> Wsg {
+        let mut waves = [8; WAVES_SIZE];
+        waves[32..48].iter_mut().for_each(|sample| *sample = 15);
+        waves[48..64].iter_mut().for_each(|sample| *sample = 1);
+        let mut wsg = Wsg::new(&waves);
+        wsg.enabled = true;
+        wsg
+    }
+
+    /// Writes `value` a nibble at a time to the registers from `first`.
+    fn write(wsg: &mut Wsg, first: u8, value: u32, nibbles: u8) {
+        for nibble in 0..nibbles {
+            wsg.write(first + nibble, (value >> (4 * nibble)) as u8);
+        }
+    }
+
+    #[test]
+    fn reads_registers() {
+        let mut wsg = wsg();
+        write(&mut wsg, 0x10, 0x12345, 5);
+        write(&mut wsg, 0x16, 0x6789, 4);
+        wsg.write(0x1F, 0xFA);
+        wsg.write(0x0A, 0x0F);
+        assert_eq!(wsg.frequency(0), 0x12345);
+        assert_eq!(wsg.frequency(1), 0x67890);
+        assert_eq!(wsg.frequency(2), 0);
+        assert_eq!(wsg.volume(2), 0x0A);
+        assert_eq!(wsg.waveform(1), 7);
+
+        write(&mut wsg, 0x00, 0xABCDE, 5);
+        write(&mut wsg, 0x06, 0x1234, 4);
+        wsg.write(0x05, 3);
+        assert_eq!(wsg.accumulators, [0xABCDE, 0x12340, 0]);
+        assert_eq!(wsg.waveform(0), 3);
+    }
+
+    #[test]
+    fn plays_a_voice() {
+        let mut wsg = wsg();
+        // 1/64 of the waveform a clock: 1500Hz.
+        write(&mut wsg, 0x1B, 0x400, 4);
+        wsg.write(0x0F, 1);
+        wsg.write(0x1F, 15);
+        let mut samples = Vec::new();
+        wsg.run(64 * SAMPLE_RATE / 100, &mut samples);
+        assert_eq!(samples.len(), 480);
+        assert_eq!(samples[0], (7 * 15 * GAIN) as i16);
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| (pair[0] > 0) != (pair[1] > 0))
+            .count();
+        assert_eq!(crossings, 29);
+
+        wsg.write(0x1F, 1);
+        samples.clear();
+        wsg.run(64 * 32, &mut samples);
+        assert!(samples
+            .iter()
+            .all(|&sample| sample.abs() <= (7 * GAIN) as i16));
+        assert!(samples.contains(&((-7 * GAIN) as i16)));
+
+        wsg.enabled = false;
+        samples.clear();
+        wsg.run(64 * 10 + 32, &mut samples);
+        assert_eq!(samples, vec![0; 10]);
+        assert_eq!(wsg.cycles, 32);
+    }
+
+    #[test]
+    fn mixes_the_voices() {
+        let mut wsg = wsg();
+        for voice in 0..VOICES as u8 {
+            wsg.write(0x05 + 5 * voice, 1);
+            wsg.write(0x15 + 5 * voice, 15);
+        }
+        let mut samples = Vec::new();
+        wsg.run(64, &mut samples);
+        assert_eq!(samples, vec![(3 * 7 * 15 * GAIN) as i16]);
+    }
+
+    #[test]
+    fn saves_state() {
+        let mut wsg = wsg();
+        write(&mut wsg, 0x10, 0x54321, 5);
+        wsg.write(0x15, 9);
+        wsg.run(100, &mut Vec::new());
+        let state = wsg.save();
+        let mut restored = Wsg::new(&[0; WAVES_SIZE]);
+        restored.restore(&state);
+        assert_eq!(restored.save(), state);
+        assert_eq!(restored.frequency(0), 0x54321);
+        assert!(restored.enabled);
+        assert_eq!(restored.cycles, 36);
+    }
+}
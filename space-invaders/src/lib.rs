@@ -0,0 +1,757 @@
+//! The Space Invaders arcade machine of 1978: an Intel 8080, 8KB of ROM, 8KB of RAM of which 7KB
+//! is the picture, and the shift register the game draws its sprites with.
+//!
+//! The picture is 256 dots wide and 224 lines high in memory, a bit per dot, but the monitor is
+//! turned on its side in the cabinet, so the player sees it 224 wide and 256 high. The beam
+//! interrupts the program twice a frame, with `RST 1` when it reaches line 96 and `RST 2` when it
+//! leaves the picture, so that the game can redraw whichever half it is not on. Colors come from
+//! strips of cellophane stuck on the screen, which [`Config::overlay`] imitates.
+//!
+//! The ROM is not part of this crate: [`Emulator::load_rom`] takes the four 2KB chips of the
+//! machine, `invaders.h`, `invaders.g`, `invaders.f` and `invaders.e`, one after another.
+//!
+//! ```
+//! use emu_core::Emulator;
+//! use space_invaders::{Config, Invaders};
+//!
+//! let invaders = Invaders::new(Config::default());
+//! assert_eq!(invaders.video().width, 224);
+//! assert_eq!(invaders.video().height, 256);
+//! ```
+
+pub mod sound;
+
+use crate::sound::{Mixer, Sample, Sound, SAMPLE_RATE};
+use core::convert::TryFrom;
+use core::time::Duration;
+use emu_core::{AudioSpec, Emulator, Error, VideoSpec};
+use emu_debug::i8080::I8080;
+use emu_debug::{Memory, Target};
+use i8080::bus::Bus;
+use i8080::{Cpu, STATE_SIZE};
+
+/// Clock cycles per second of the processor, from the 19.968MHz crystal divided by 10.
+pub const CLOCK_HZ: u64 = 1_996_800;
+
+/// Clock cycles of the processor in a line of the picture.
+pub const CYCLES_PER_LINE: u32 = 128;
+
+/// Lines in a frame, with those of the vertical blank.
+pub const LINES: u32 = 262;
+
+/// Clock cycles of the processor in a frame.
+pub const CYCLES_PER_FRAME: u32 = CYCLES_PER_LINE * LINES;
+
+/// The time a frame takes, a little over 1/60 second.
+pub const FRAME_PERIOD: Duration =
+    Duration::from_nanos(CYCLES_PER_FRAME as u64 * 1_000_000_000 / CLOCK_HZ);
+
+/// Lines of the picture in memory, which are its columns on the screen.
+pub const LINES_SHOWN: usize = 224;
+
+/// Dots in a line of the picture in memory.
+pub const DOTS: usize = 256;
+
+/// The width of the screen as the player sees it.
+pub const WIDTH: usize = LINES_SHOWN;
+
+/// The height of the screen as the player sees it.
+pub const HEIGHT: usize = DOTS;
+
+/// Bytes of the ROM.
+pub const ROM_SIZE: usize = 0x2000;
+
+/// Where the picture starts in memory.
+pub const VIDEO_RAM: u16 = 0x2400;
+
+/// The buttons of the cabinet, numbered as [`Invaders`] numbers them.
+pub const BUTTONS: [&str; 10] = [
+    "Coin", "1P Start", "2P Start", "1P Fire", "1P Left", "1P Right", "2P Fire", "2P Left",
+    "2P Right", "Tilt",
+];
+
+/// The line at which `RST 1` interrupts the program.
+const MIDDLE: u32 = 96;
+
+/// `RST 1`.
+const RST_1: u8 = 0xCF;
+
+/// `RST 2`.
+const RST_2: u8 = 0xD7;
+
+/// Identifies a save state of this crate, with its version.
+const MAGIC: &[u8; 4] = b"INV\x01";
+
+/// The color of a lit dot without the overlay.
+const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+/// The color of the overlay's red strip, across the flying saucer.
+const RED: [u8; 4] = [0xFF, 0x30, 0x30, 0xFF];
+
+/// The color of the overlay's green strips, across the shields and the player's bases.
+const GREEN: [u8; 4] = [0x30, 0xFF, 0x30, 0xFF];
+
+/// The color of a dark dot.
+const BLACK: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// The DIP switches on the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dips {
+    /// Bases a game starts with, from 3 to 6.
+    pub bases: u8,
+    /// Whether the extra base comes at 1000 points rather than 1500.
+    pub extra_base_at_1000: bool,
+    /// Whether the demonstration shows what a coin buys.
+    pub coin_info: bool,
+}
+
+impl Default for Dips {
+    /// 3 bases, an extra one at 1500 points, and the coin information shown.
+    fn default() -> Self {
+        Dips {
+            bases: 3,
+            extra_base_at_1000: false,
+            coin_info: true,
+        }
+    }
+}
+
+/// What a machine is built with.
+#[derive(Clone)]
+pub struct Config {
+    pub dips: Dips,
+    /// Whether the screen is colored as the cellophane on the cabinet's monitor colors it, or
+    /// left white.
+    pub overlay: bool,
+    /// The recording of each sound, indexed as [`Sound`] numbers them. Sounds without one are
+    /// silent.
+    pub samples: Vec<Option<Sample>>,
+}
+
+impl Default for Config {
+    /// The default DIP switches and the overlay, with no sounds.
+    fn default() -> Self {
+        Config {
+            dips: Dips::default(),
+            overlay: true,
+            samples: Vec::new(),
+        }
+    }
+}
+
+/// Everything on the board but the processor.
+#[derive(Clone)]
+struct Board {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    /// The shift register, of the last two bytes written to port 4, the last in the high byte.
+    shift: u16,
+    /// How many bits the shift register's result is shifted left by, from port 2.
+    offset: u8,
+    buttons: [bool; BUTTONS.len()],
+    dips: Dips,
+    /// The `RST` the beam asks for, until it is taken.
+    interrupt: Option<u8>,
+    /// What was last written to the sound ports, 3 and 5.
+    sound_ports: [u8; 2],
+    mixer: Mixer,
+}
+
+impl Board {
+    /// Returns the bits of `buttons` from `bit` on.
+    fn buttons(&self, buttons: &[usize], bit: u8) -> u8 {
+        buttons
+            .iter()
+            .enumerate()
+            .map(|(n, &button)| (self.buttons[button] as u8) << (bit + n as u8))
+            .fold(0, |bits, bit| bits | bit)
+    }
+
+    /// Starts the sounds of `sounds` whose bit in `value` has just been set, and stops the
+    /// flying saucer if its bit has been cleared.
+    fn play(&mut self, port: usize, value: u8, sounds: &[Sound; 5]) {
+        let started = value & !self.sound_ports[port];
+        for (bit, &sound) in sounds.iter().enumerate() {
+            if started & 1 << bit != 0 {
+                self.mixer.start(sound);
+            }
+        }
+        if sounds[0] == Sound::Ufo && value & 1 == 0 {
+            self.mixer.stop(Sound::Ufo);
+        }
+        self.sound_ports[port] = value;
+    }
+}
+
+/// Reading memory does nothing else on this machine, so the debugger sees what the processor does.
+impl Memory for Board {
+    /// Reads the ROM below 0x2000 and RAM above it, both repeated every 16KB.
+    fn peek(&self, address: u16) -> u8 {
+        let address = address as usize & 0x3FFF;
+        match address.checked_sub(ROM_SIZE) {
+            Some(offset) => self.ram[offset],
+            None => self.rom[address],
+        }
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        if let Some(offset) = (address as usize & 0x3FFF).checked_sub(ROM_SIZE) {
+            self.ram[offset] = value;
+        }
+    }
+}
+
+impl Bus for Board {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.poke(address, value);
+    }
+
+    /// Reads the buttons from ports 0 to 2, with the DIP switches on port 2, and the result of the
+    /// shift register from port 3.
+    fn input(&mut self, port: u8) -> u8 {
+        let dips = self.dips;
+        match port {
+            0 => 0x0E | self.buttons(&[3, 4, 5], 4),
+            1 => 0x08 | self.buttons(&[0, 2, 1], 0) | self.buttons(&[3, 4, 5], 4),
+            2 => {
+                (dips.bases.clamp(3, 6) - 3)
+                    | self.buttons(&[9], 2)
+                    | (dips.extra_base_at_1000 as u8) << 3
+                    | self.buttons(&[6, 7, 8], 4)
+                    | (!dips.coin_info as u8) << 7
+            }
+            3 => (self.shift >> (8 - self.offset)) as u8,
+            _ => 0,
+        }
+    }
+
+    /// Sets the shift register's offset from port 2 and shifts a byte into it from port 4, and
+    /// plays the sounds of ports 3 and 5. Port 6 is the watchdog, which this machine never lets
+    /// reset the game.
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            2 => self.offset = value & 7,
+            3 => {
+                self.mixer.amplifier = value & 0x20 != 0;
+                self.play(0, value, &Sound::PORT_3);
+            }
+            4 => self.shift = u16::from(value) << 8 | self.shift >> 8,
+            5 => self.play(1, value, &Sound::PORT_5),
+            _ => {}
+        }
+    }
+
+    fn interrupt(&mut self) -> Option<u8> {
+        self.interrupt.take()
+    }
+}
+
+/// The arcade machine, with its ROM.
+#[derive(Clone)]
+pub struct Invaders {
+    cpu: Cpu,
+    board: Board,
+    overlay: bool,
+    rom_hash: u64,
+    /// The clock cycle within the frame.
+    cycle: u32,
+    /// Clock cycles since the last sample of sound, times [`SAMPLE_RATE`].
+    clocks: u64,
+    samples: Vec<i16>,
+    framebuffer: Vec<u8>,
+}
+
+impl Invaders {
+    /// Creates the machine `config` describes, with no ROM loaded.
+    pub fn new(config: Config) -> Self {
+        let board = Board {
+            rom: vec![0; ROM_SIZE],
+            ram: vec![0; 0x2000],
+            shift: 0,
+            offset: 0,
+            buttons: [false; BUTTONS.len()],
+            dips: config.dips,
+            interrupt: None,
+            sound_ports: [0; 2],
+            mixer: Mixer::new(config.samples),
+        };
+        let mut framebuffer = vec![0; WIDTH * HEIGHT * 4];
+        for pixel in framebuffer.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&BLACK);
+        }
+        Invaders {
+            cpu: Cpu::new(),
+            board,
+            overlay: config.overlay,
+            rom_hash: emu_state::hash(&[]),
+            cycle: 0,
+            clocks: 0,
+            samples: Vec::new(),
+            framebuffer,
+        }
+    }
+
+    /// Returns the processor.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Returns RAM, from 0x2000.
+    pub fn ram(&self) -> &[u8] {
+        &self.board.ram
+    }
+
+    /// Returns RAM, to change it directly.
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.board.ram
+    }
+
+    /// Returns the DIP switches, to change them. The game reads them as it starts.
+    pub fn dips_mut(&mut self) -> &mut Dips {
+        &mut self.board.dips
+    }
+
+    /// Returns the color of a lit dot at `x` and `y` on the screen, as the player sees it.
+    fn color(&self, x: usize, y: usize) -> [u8; 4] {
+        if !self.overlay {
+            WHITE
+        } else if (32..64).contains(&y) {
+            RED
+        } else if (184..240).contains(&y) || (y >= 240 && (16..134).contains(&x)) {
+            GREEN
+        } else {
+            WHITE
+        }
+    }
+
+    /// Draws the line of the picture numbered `line` in memory, which is a column of the screen.
+    fn draw_line(&mut self, line: usize) {
+        let start = (VIDEO_RAM as usize - ROM_SIZE) + line * DOTS / 8;
+        for dot in 0..DOTS {
+            let lit = self.board.ram[start + dot / 8] & 1 << (dot % 8) != 0;
+            let (x, y) = (line, HEIGHT - 1 - dot);
+            let color = if lit { self.color(x, y) } else { BLACK };
+            self.framebuffer[(y * WIDTH + x) * 4..][..4].copy_from_slice(&color);
+        }
+    }
+
+    /// Moves the beam on by `cycles`, drawing the lines it finishes and asking for the
+    /// interrupts of those it starts. Returns `true` if the frame ended.
+    fn advance(&mut self, cycles: u32) -> bool {
+        let line = self.cycle / CYCLES_PER_LINE;
+        self.cycle += cycles;
+        for finished in line..self.cycle / CYCLES_PER_LINE {
+            if (finished as usize) < LINES_SHOWN {
+                self.draw_line(finished as usize);
+            }
+            match finished + 1 {
+                MIDDLE => self.board.interrupt = Some(RST_1),
+                started if started as usize == LINES_SHOWN => self.board.interrupt = Some(RST_2),
+                _ => {}
+            }
+        }
+        let ended = self.cycle >= CYCLES_PER_FRAME;
+        if ended {
+            self.cycle -= CYCLES_PER_FRAME;
+        }
+        ended
+    }
+
+    /// Mixes the sound of `cycles` clock cycles.
+    fn mix(&mut self, cycles: u8) {
+        self.clocks += u64::from(cycles) * u64::from(SAMPLE_RATE);
+        while self.clocks >= CLOCK_HZ {
+            self.clocks -= CLOCK_HZ;
+            self.samples.push(self.board.mixer.sample());
+        }
+    }
+}
+
+impl Emulator for Invaders {
+    fn name(&self) -> &'static str {
+        "Space Invaders"
+    }
+
+    fn video(&self) -> VideoSpec {
+        VideoSpec {
+            width: WIDTH,
+            height: HEIGHT,
+            frame_period: FRAME_PERIOD,
+        }
+    }
+
+    fn audio(&self) -> AudioSpec {
+        AudioSpec {
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    fn buttons(&self) -> &'static [&'static str] {
+        &BUTTONS
+    }
+
+    /// Loads the 8KB of the four ROM chips, and turns the machine on.
+    fn load_rom(&mut self, rom: &[u8]) -> Result<(), Error> {
+        if rom.len() != ROM_SIZE {
+            return Err(Error::Rom(format!(
+                "the ROM is {} bytes, not the 8192 of invaders.h, .g, .f and .e one after another",
+                rom.len()
+            )));
+        }
+        self.board.rom.copy_from_slice(rom);
+        self.rom_hash = emu_state::hash(rom);
+        self.reset();
+        Ok(())
+    }
+
+    /// Turns the machine off and on again, clearing RAM as the game's own test does.
+    fn reset(&mut self) {
+        self.cpu = Cpu::new();
+        let board = &mut self.board;
+        board.ram.iter_mut().for_each(|byte| *byte = 0);
+        board.shift = 0;
+        board.offset = 0;
+        board.interrupt = None;
+        board.mixer.silence();
+        board.sound_ports = [0; 2];
+        self.cycle = 0;
+        self.clocks = 0;
+        self.samples.clear();
+        for line in 0..LINES_SHOWN {
+            self.draw_line(line);
+        }
+    }
+
+    fn step_frame(&mut self) -> Result<(), Error> {
+        loop {
+            let cycles = self.cpu.step(&mut self.board);
+            self.mix(cycles);
+            if self.advance(u32::from(cycles)) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    fn pull_audio(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.samples);
+    }
+
+    fn set_button(&mut self, button: usize, pressed: bool) {
+        if let Some(held) = self.board.buttons.get_mut(button) {
+            *held = pressed;
+        }
+    }
+
+    /// Saves the processor, RAM, the shift register, the sound ports and where the beam is, but
+    /// not the sounds playing, which start again with the next one the game plays.
+    fn save_state(&self) -> Vec<u8> {
+        let board = &self.board;
+        let mut state = MAGIC.to_vec();
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+        state.extend_from_slice(&self.cpu.save());
+        state.extend_from_slice(&board.ram);
+        state.extend_from_slice(&board.shift.to_le_bytes());
+        state.extend_from_slice(&[board.offset, board.interrupt.unwrap_or(0)]);
+        state.extend_from_slice(&board.sound_ports);
+        state.extend_from_slice(&self.cycle.to_le_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let ram = self.board.ram.len();
+        if state.len() != MAGIC.len() + 8 + STATE_SIZE + ram + 10 || !state.starts_with(MAGIC) {
+            return Err(Error::State("not a save state of this machine".to_string()));
+        }
+        let (hash, rest) = state[MAGIC.len()..].split_at(8);
+        if u64::from_le_bytes(<[u8; 8]>::try_from(hash).unwrap()) != self.rom_hash {
+            return Err(Error::State("the state is of another ROM".to_string()));
+        }
+        let (cpu, rest) = rest.split_at(STATE_SIZE);
+        let (memory, rest) = rest.split_at(ram);
+        let cycle = u32::from_le_bytes(<[u8; 4]>::try_from(&rest[6..]).unwrap());
+        let interrupt = match rest[3] {
+            0 => None,
+            RST_1 => Some(RST_1),
+            RST_2 => Some(RST_2),
+            _ => return Err(Error::State("the state is corrupt".to_string())),
+        };
+        if cycle >= CYCLES_PER_FRAME || rest[2] > 7 {
+            return Err(Error::State("the state is corrupt".to_string()));
+        }
+        self.cpu
+            .restore(<&[u8; STATE_SIZE]>::try_from(cpu).unwrap());
+        let board = &mut self.board;
+        board.ram.copy_from_slice(memory);
+        board.shift = u16::from_le_bytes([rest[0], rest[1]]);
+        board.offset = rest[2];
+        board.interrupt = interrupt;
+        board.sound_ports = [rest[4], rest[5]];
+        board.mixer.amplifier = rest[4] & 0x20 != 0;
+        if rest[4] & 1 != 0 {
+            board.mixer.start(Sound::Ufo);
+        } else {
+            board.mixer.stop(Sound::Ufo);
+        }
+        self.cycle = cycle;
+        for line in 0..LINES_SHOWN {
+            self.draw_line(line);
+        }
+        Ok(())
+    }
+
+    fn debug(&mut self) -> Option<Box<dyn Target + '_>> {
+        Some(Box::new(I8080::new(&mut self.cpu, &mut self.board)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a machine whose ROM holds `code` at each address.
+    fn machine(config: Config, code: &[(u16, &[u8])]) -> Invaders {
+        let mut rom = vec![0; ROM_SIZE];
+        for &(address, bytes) in code {
+            rom[address as usize..][..bytes.len()].copy_from_slice(bytes);
+        }
+        let mut invaders = Invaders::new(config);
+        invaders.load_rom(&rom).unwrap();
+        invaders
+    }
+
+    /// A program that loops with interrupts off.
+    const LOOP: (u16, &[u8]) = (0, &[0xC3, 0x00, 0x00]);
+
+    fn pixel(invaders: &Invaders, x: usize, y: usize) -> [u8; 4] {
+        let mut color = [0; 4];
+        color.copy_from_slice(&invaders.framebuffer()[(y * WIDTH + x) * 4..][..4]);
+        color
+    }
+
+    #[test]
+    fn shifts() {
+        let board = &mut Invaders::new(Config::default()).board;
+        board.output(4, 0xAB);
+        board.output(4, 0xCD);
+        assert_eq!(board.input(3), 0xCD);
+        board.output(2, 4);
+        assert_eq!(board.input(3), 0xDA);
+        board.output(2, 0x0F);
+        assert_eq!(board.input(3), 0xD5);
+    }
+
+    #[test]
+    fn maps_memory() {
+        let mut invaders = machine(Config::default(), &[(0x1FFF, &[0x42])]);
+        let board = &mut invaders.board;
+        board.write(0x0000, 0x11);
+        assert_eq!(board.read(0x0000), 0);
+        assert_eq!(board.read(0x5FFF), 0x42);
+        board.write(0x2400, 0x99);
+        assert_eq!(board.read(0x6400), 0x99);
+        board.write(0xFFFF, 0x77);
+        assert_eq!(invaders.ram()[0x1FFF], 0x77);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut invaders = machine(
+            Config::default(),
+            &[
+                // LXI SP, 2400; EI; JMP 0004
+                (0x00, &[0x31, 0x00, 0x24, 0xFB, 0xC3, 0x04, 0x00]),
+                // LXI H, 2000; INR M; EI; RET
+                (0x08, &[0x21, 0x00, 0x20, 0x34, 0xFB, 0xC9]),
+                // LXI H, 2001; INR M; EI; RET
+                (0x10, &[0x21, 0x01, 0x20, 0x34, 0xFB, 0xC9]),
+            ],
+        );
+        invaders.step_frame().unwrap();
+        assert_eq!(&invaders.ram()[..2], &[1, 1]);
+        invaders.step_frame().unwrap();
+        invaders.step_frame().unwrap();
+        assert_eq!(&invaders.ram()[..2], &[3, 3]);
+    }
+
+    #[test]
+    fn draws_on_its_side() {
+        let mut invaders = machine(Config::default(), &[LOOP]);
+        let picture = (VIDEO_RAM as usize) - ROM_SIZE;
+        invaders.ram_mut()[picture] = 0x01;
+        invaders.ram_mut()[picture + 32 * 223 + 31] = 0x80;
+        // The dot at y 40, under the red strip, and at y 200, under the green one.
+        invaders.ram_mut()[picture + 32 * 10 + 26] = 0x80;
+        invaders.ram_mut()[picture + 32 * 10 + 6] = 0x80;
+        invaders.step_frame().unwrap();
+        assert_eq!(pixel(&invaders, 0, 255), WHITE);
+        assert_eq!(pixel(&invaders, 223, 0), WHITE);
+        assert_eq!(pixel(&invaders, 10, 40), RED);
+        assert_eq!(pixel(&invaders, 10, 200), GREEN);
+        assert_eq!(pixel(&invaders, 1, 255), BLACK);
+        let lit = invaders
+            .framebuffer()
+            .chunks(4)
+            .filter(|&p| p != BLACK)
+            .count();
+        assert_eq!(lit, 4);
+
+        let config = Config {
+            overlay: false,
+            ..Config::default()
+        };
+        let mut invaders = machine(config, &[LOOP]);
+        invaders.ram_mut()[picture + 32 * 10 + 26] = 0x80;
+        invaders.step_frame().unwrap();
+        assert_eq!(pixel(&invaders, 10, 40), WHITE);
+    }
+
+    #[test]
+    fn overlays() {
+        let invaders = Invaders::new(Config::default());
+        assert_eq!(invaders.color(100, 10), WHITE);
+        assert_eq!(invaders.color(100, 32), RED);
+        assert_eq!(invaders.color(100, 64), WHITE);
+        assert_eq!(invaders.color(0, 184), GREEN);
+        assert_eq!(invaders.color(0, 250), WHITE);
+        assert_eq!(invaders.color(16, 250), GREEN);
+        assert_eq!(invaders.color(134, 250), WHITE);
+    }
+
+    #[test]
+    fn reads_buttons_and_dips() {
+        let mut invaders = Invaders::new(Config {
+            dips: Dips {
+                bases: 5,
+                extra_base_at_1000: true,
+                coin_info: false,
+            },
+            ..Config::default()
+        });
+        assert_eq!(invaders.board.input(0), 0x0E);
+        assert_eq!(invaders.board.input(1), 0x08);
+        assert_eq!(invaders.board.input(2), 0x8A);
+        for button in 0..BUTTONS.len() {
+            invaders.set_button(button, true);
+        }
+        invaders.set_button(BUTTONS.len(), true);
+        assert_eq!(invaders.board.input(0), 0x7E);
+        assert_eq!(invaders.board.input(1), 0x7F);
+        assert_eq!(invaders.board.input(2), 0xFE);
+        invaders.set_button(1, false);
+        invaders.set_button(7, false);
+        assert_eq!(invaders.board.input(1), 0x7B);
+        assert_eq!(invaders.board.input(2), 0xDE);
+        invaders.dips_mut().coin_info = true;
+        invaders.dips_mut().bases = 9;
+        assert_eq!(invaders.board.input(2) & 0x83, 0x03);
+    }
+
+    #[test]
+    fn plays_sounds() {
+        let tone = Sample {
+            rate: SAMPLE_RATE,
+            data: vec![1000; 100],
+        };
+        let mut invaders = Invaders::new(Config {
+            samples: vec![Some(tone.clone()), Some(tone)],
+            ..Config::default()
+        });
+        let board = &mut invaders.board;
+        board.output(3, 0x03);
+        assert!(board.mixer.playing(Sound::Ufo));
+        assert!(board.mixer.playing(Sound::Shot));
+        assert_eq!(board.mixer.sample(), 0);
+        board.output(3, 0x23);
+        assert_eq!(board.mixer.sample(), 2000);
+        board.output(3, 0x22);
+        assert!(!board.mixer.playing(Sound::Ufo));
+        assert!(board.mixer.playing(Sound::Shot));
+        board.output(5, 0x01);
+        assert!(board.mixer.playing(Sound::Fleet1));
+        assert_eq!(board.sound_ports, [0x22, 0x01]);
+    }
+
+    #[test]
+    fn mixes_at_the_sample_rate() {
+        let mut invaders = machine(Config::default(), &[LOOP]);
+        let mut samples = Vec::new();
+        for _ in 0..60 {
+            invaders.step_frame().unwrap();
+        }
+        invaders.pull_audio(&mut samples);
+        let expected = 60 * u64::from(CYCLES_PER_FRAME) * u64::from(SAMPLE_RATE) / CLOCK_HZ;
+        assert!((samples.len() as u64).abs_diff(expected) <= 1);
+        assert!(samples.iter().all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn saves_state() {
+        let code: &[(u16, &[u8])] = &[(0x00, &[0x31, 0x00, 0x24, 0xFB, 0xC3, 0x04, 0x00])];
+        let mut invaders = machine(Config::default(), code);
+        invaders.board.output(4, 0x12);
+        invaders.board.output(2, 3);
+        invaders.board.output(3, 0x21);
+        invaders.ram_mut()[0x400] = 0xFF;
+        invaders.advance(MIDDLE * CYCLES_PER_LINE);
+        let state = invaders.save_state();
+
+        let mut restored = machine(Config::default(), code);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.board.shift, 0x1200);
+        assert_eq!(restored.board.interrupt, Some(RST_1));
+        assert!(restored.board.mixer.amplifier);
+        assert!(restored.board.mixer.playing(Sound::Ufo));
+        assert_eq!(pixel(&restored, 0, 255), WHITE);
+
+        let mut other = machine(Config::default(), &[LOOP]);
+        assert!(matches!(other.load_state(&state), Err(Error::State(_))));
+        assert!(matches!(
+            restored.load_state(&state[1..]),
+            Err(Error::State(_))
+        ));
+    }
+
+    #[test]
+    fn debugs() {
+        use emu_debug::debugger::{Debugger, StopReason, WatchKind, Watchpoint};
+
+        // 0x0000: MVI A, 2A; STA 2400; JMP 0
+        let code: &[u8] = &[0x3E, 0x2A, 0x32, 0x00, 0x24, 0xC3, 0x00, 0x00];
+        let mut invaders = machine(Config::default(), &[(0, code)]);
+        let mut target = invaders.debug().unwrap();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint {
+            address: 0x2400,
+            len: 1,
+            kind: WatchKind::Write,
+        });
+        let reason = loop {
+            if let Some(reason) = debugger.run_step(target.as_mut()) {
+                break reason;
+            }
+        };
+        assert!(matches!(reason, StopReason::Watchpoint { address: 2, .. }));
+        assert_eq!(target.peek(0x6400), 0x2A);
+        target.poke(0x0000, 0xFF);
+        assert_eq!(target.peek(0x0000), 0x3E);
+        drop(target);
+        assert_eq!(invaders.ram()[0x400], 0x2A);
+    }
+
+    #[test]
+    fn loads_only_the_whole_rom() {
+        let mut invaders = Invaders::new(Config::default());
+        let error = invaders.load_rom(&[0; 0x800]).unwrap_err();
+        assert!(matches!(error, Error::Rom(_)));
+        assert!(invaders.load_rom(&[0; ROM_SIZE]).is_ok());
+    }
+}
@@ -0,0 +1,272 @@
+//! The sounds of the cabinet, played from recordings.
+//!
+//! The machine made its sounds with analog circuits, each started by a bit of output port 3 or 5.
+//! Like other emulators, this one plays a recording of each instead, from the WAV files of the
+//! usual sample set, which do not come with it: `0.wav` to `9.wav`, numbered as [`Sound`] numbers
+//! them.
+
+use core::convert::TryFrom;
+
+/// Samples per second of the mix.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// A sound of the cabinet, numbered as its recording is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sound {
+    /// The flying saucer, repeated as long as it flies.
+    Ufo = 0,
+    Shot = 1,
+    /// The player's base exploding.
+    BaseHit = 2,
+    InvaderHit = 3,
+    /// The four notes of the invaders marching, in turn.
+    Fleet1 = 4,
+    Fleet2 = 5,
+    Fleet3 = 6,
+    Fleet4 = 7,
+    UfoHit = 8,
+    /// An extra base, for reaching the score set by the DIP switches.
+    ExtraBase = 9,
+}
+
+/// Number of sounds.
+pub const SOUNDS: usize = 10;
+
+impl Sound {
+    /// The sounds started by the bits of output port 3, from bit 0.
+    pub const PORT_3: [Sound; 5] = [
+        Sound::Ufo,
+        Sound::Shot,
+        Sound::BaseHit,
+        Sound::InvaderHit,
+        Sound::ExtraBase,
+    ];
+
+    /// The sounds started by the bits of output port 5, from bit 0.
+    pub const PORT_5: [Sound; 5] = [
+        Sound::Fleet1,
+        Sound::Fleet2,
+        Sound::Fleet3,
+        Sound::Fleet4,
+        Sound::UfoHit,
+    ];
+}
+
+/// A recording, in mono.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sample {
+    /// Samples per second.
+    pub rate: u32,
+    pub data: Vec<i16>,
+}
+
+impl Sample {
+    /// Reads a WAV file of 8-bit or 16-bit PCM, mixing stereo down to mono.
+    pub fn from_wav(bytes: &[u8]) -> Result<Sample, String> {
+        if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err("not a WAV file".to_string());
+        }
+        let mut format = None;
+        let mut chunks = &bytes[12..];
+        while chunks.len() >= 8 {
+            let id = &chunks[..4];
+            let len = u32::from_le_bytes(<[u8; 4]>::try_from(&chunks[4..8]).unwrap()) as usize;
+            let body = chunks
+                .get(8..8 + len)
+                .ok_or_else(|| "the WAV file is cut short".to_string())?;
+            match id {
+                b"fmt " if body.len() >= 16 => {
+                    let field = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]);
+                    let rate = u32::from_le_bytes(<[u8; 4]>::try_from(&body[4..8]).unwrap());
+                    format = Some((field(0), field(2), rate, field(14)));
+                }
+                b"data" => {
+                    let (encoding, channels, rate, bits) =
+                        format.ok_or_else(|| "the WAV file has no format".to_string())?;
+                    if encoding != 1 || !(bits == 8 || bits == 16) || channels == 0 || rate == 0 {
+                        return Err(format!(
+                            "only 8-bit and 16-bit PCM is supported, not format {} of {} bits",
+                            encoding, bits
+                        ));
+                    }
+                    let samples: Vec<i32> = if bits == 8 {
+                        body.iter()
+                            .map(|&byte| (i32::from(byte) - 0x80) << 8)
+                            .collect()
+                    } else {
+                        let pairs = body.chunks_exact(2);
+                        pairs
+                            .map(|pair| i32::from(i16::from_le_bytes([pair[0], pair[1]])))
+                            .collect()
+                    };
+                    let channels = channels as usize;
+                    let data = samples
+                        .chunks_exact(channels)
+                        .map(|frame| (frame.iter().sum::<i32>() / channels as i32) as i16)
+                        .collect();
+                    return Ok(Sample { rate, data });
+                }
+                _ => {}
+            }
+            // Chunks are padded to an even length.
+            chunks = chunks.get(8 + len + len % 2..).unwrap_or(&[]);
+        }
+        Err("the WAV file has no data".to_string())
+    }
+}
+
+/// A sound being played, at a position in its recording counted in [`SAMPLE_RATE`]ths of a
+/// sample.
+#[derive(Clone, Copy, Debug, Default)]
+struct Voice {
+    playing: bool,
+    position: u64,
+}
+
+/// The sounds being played, mixed at [`SAMPLE_RATE`].
+#[derive(Clone, Default)]
+pub struct Mixer {
+    samples: Vec<Option<Sample>>,
+    voices: [Voice; SOUNDS],
+    /// Whether the amplifier is on, which the game turns off during its demonstration.
+    pub amplifier: bool,
+}
+
+impl Mixer {
+    /// Creates a mixer playing `samples`, indexed as [`Sound`] numbers them. Sounds without a
+    /// recording are silent.
+    pub fn new(samples: Vec<Option<Sample>>) -> Self {
+        Mixer {
+            samples,
+            voices: [Voice::default(); SOUNDS],
+            amplifier: false,
+        }
+    }
+
+    /// Starts `sound` from the beginning.
+    pub fn start(&mut self, sound: Sound) {
+        self.voices[sound as usize] = Voice {
+            playing: true,
+            position: 0,
+        };
+    }
+
+    /// Stops `sound`.
+    pub fn stop(&mut self, sound: Sound) {
+        self.voices[sound as usize].playing = false;
+    }
+
+    /// Stops every sound and turns the amplifier off, as when the machine is turned on.
+    pub fn silence(&mut self) {
+        self.voices = [Voice::default(); SOUNDS];
+        self.amplifier = false;
+    }
+
+    /// Returns whether `sound` is playing.
+    pub fn playing(&self, sound: Sound) -> bool {
+        self.voices[sound as usize].playing
+    }
+
+    /// Mixes the next sample. The flying saucer repeats until stopped; the other sounds stop at
+    /// the end of their recording.
+    pub fn sample(&mut self) -> i16 {
+        let mut mix = 0;
+        for (n, voice) in self.voices.iter_mut().enumerate() {
+            let sample = match self.samples.get(n) {
+                Some(Some(sample)) if voice.playing && !sample.data.is_empty() => sample,
+                _ => {
+                    voice.playing = false;
+                    continue;
+                }
+            };
+            let mut index = (voice.position / u64::from(SAMPLE_RATE)) as usize;
+            if index >= sample.data.len() {
+                if n == Sound::Ufo as usize {
+                    voice.position = 0;
+                    index = 0;
+                } else {
+                    voice.playing = false;
+                    continue;
+                }
+            }
+            mix += i32::from(sample.data[index]);
+            voice.position += u64::from(sample.rate);
+        }
+        if self.amplifier {
+            mix.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a WAV file of `data` with `channels` and `bits`.
+    fn wav(channels: u16, bits: u16, rate: u32, data: &[u8]) -> Vec<u8> {
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        // A chunk the reader skips, of an odd length.
+        wav.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        wav.extend_from_slice(b"fmt \x10\0\0\0\x01\0");
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&rate.to_le_bytes());
+        wav.extend_from_slice(&(rate * u32::from(channels * bits / 8)).to_le_bytes());
+        wav.extend_from_slice(&(channels * bits / 8).to_le_bytes());
+        wav.extend_from_slice(&bits.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(data);
+        wav
+    }
+
+    #[test]
+    fn reads_wav_files() {
+        let sample = Sample::from_wav(&wav(1, 8, 11_025, &[0x80, 0xFF, 0x00])).unwrap();
+        assert_eq!(sample.rate, 11_025);
+        assert_eq!(sample.data, [0, 0x7F00, -0x8000]);
+        let stereo = [0x00, 0x10, 0x00, 0x30, 0xFF, 0xFF, 0x01, 0x00];
+        let sample = Sample::from_wav(&wav(2, 16, 48_000, &stereo)).unwrap();
+        assert_eq!(sample.data, [0x2000, 0]);
+
+        assert!(Sample::from_wav(b"RIFF").is_err());
+        assert!(Sample::from_wav(&wav(1, 24, 48_000, &[0; 3])).is_err());
+        let mut cut = wav(1, 8, 11_025, &[0x80; 4]);
+        cut.truncate(cut.len() - 1);
+        assert!(Sample::from_wav(&cut).is_err());
+    }
+
+    #[test]
+    fn mixes() {
+        let sample = |rate, data: &[i16]| {
+            Some(Sample {
+                rate,
+                data: data.to_vec(),
+            })
+        };
+        let mut samples = vec![None; SOUNDS];
+        samples[Sound::Ufo as usize] = sample(SAMPLE_RATE, &[1, 2]);
+        samples[Sound::Shot as usize] = sample(SAMPLE_RATE / 2, &[10, 20]);
+        let mut mixer = Mixer::new(samples);
+        mixer.amplifier = true;
+        assert_eq!(mixer.sample(), 0);
+
+        mixer.start(Sound::Ufo);
+        mixer.start(Sound::Shot);
+        mixer.start(Sound::InvaderHit);
+        // A sound without a recording stops at once.
+        assert_eq!(mixer.sample(), 11);
+        assert!(!mixer.playing(Sound::InvaderHit));
+        let mixed: Vec<i16> = (0..5).map(|_| mixer.sample()).collect();
+        // The shot, at half the rate, lasts 4 samples; the saucer repeats.
+        assert_eq!(mixed, [12, 21, 22, 1, 2]);
+        assert!(!mixer.playing(Sound::Shot));
+        mixer.stop(Sound::Ufo);
+        assert_eq!(mixer.sample(), 0);
+
+        mixer.start(Sound::Ufo);
+        mixer.amplifier = false;
+        assert_eq!(mixer.sample(), 0);
+    }
+}
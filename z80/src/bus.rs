@@ -0,0 +1,50 @@
+//! What the processor is wired to: memory, the input and output ports, and the two interrupt
+//! lines.
+
+pub use emu_memory::{Memory, Overflow};
+
+/// The system around the processor.
+///
+/// Only memory has to be provided. The other methods default to a system with no devices: inputs
+/// read 0xFF, as a data bus nothing drives does, outputs go nowhere and no interrupt is ever asked
+/// for.
+pub trait Bus {
+    /// Reads the byte at `address`.
+    fn read(&mut self, address: u16) -> u8;
+
+    /// Writes `value` at `address`.
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Reads the input port at `port`, for `IN`. The Z80 puts 16 bits on the address bus: the port
+    /// in the low byte, and `A` or `B` in the high one, which some machines decode too.
+    fn input(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    /// Writes `value` to the output port at `port`, for `OUT`.
+    fn output(&mut self, _port: u16, _value: u8) {}
+
+    /// Returns the byte a device puts on the data bus while it holds the `INT` line low: the
+    /// instruction run in interrupt mode 0, usually an `RST`, or the low byte of the vector in
+    /// mode 2, and ignored in mode 1. Only asked while interrupts are enabled, so the interrupt
+    /// is taken when one is returned.
+    fn interrupt(&mut self) -> Option<u8> {
+        None
+    }
+
+    /// Returns whether the `NMI` line has fallen since it was last asked, before every
+    /// instruction. The non-maskable interrupt is taken whether interrupts are enabled or not.
+    fn nmi(&mut self) -> bool {
+        false
+    }
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0[address as usize] = value;
+    }
+}
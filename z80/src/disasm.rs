@@ -0,0 +1,458 @@
+//! Disassembling instructions into Zilog's mnemonics, such as `LD A, 2A`, `LD (IX+05), B` and
+//! `JR NZ, $-04`, for debuggers and traces.
+//!
+//! Relative jumps are shown as `$` plus or minus the distance from the start of the instruction.
+//! The undocumented opcodes are shown as they run: `SLL`, the halves of the index registers as
+//! `IXH` and `IXL`, the copy to a register of `DD CB` opcodes as in `RLC (IX+05), B`, and the
+//! opcodes Zilog left out of `ED`, or a prefix followed by another, as `NOP`.
+//!
+//! ```
+//! use z80::disasm::Instruction;
+//!
+//! let instruction = Instruction::decode(&[0xDD, 0x36, 0x05, 0x2A]).unwrap();
+//! assert_eq!(instruction.to_string(), "LD (IX+05), 2A");
+//! assert_eq!(instruction.len(), 4);
+//! ```
+
+use core::fmt;
+
+/// The registers as instructions number them, with memory at `HL` as `(HL)`.
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+
+/// The pairs of `LD rr, nn`, `ADD`, `INC` and `DEC`, with `HL` given apart.
+const PAIRS: [&str; 4] = ["BC", "DE", "HL", "SP"];
+
+/// The pairs of `PUSH` and `POP`.
+const STACK_PAIRS: [&str; 4] = ["BC", "DE", "HL", "AF"];
+
+/// The conditions of jumps, calls and returns.
+const CONDITIONS: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+
+/// The arithmetic and logic operations, `80` to `BF`, with the accumulator they work on.
+const ALU: [&str; 8] = [
+    "ADD A, ", "ADC A, ", "SUB ", "SBC A, ", "AND ", "XOR ", "OR ", "CP ",
+];
+
+/// The operations on the accumulator and flags, `07` to `3F`.
+const ACCUMULATOR: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+/// The rotations and shifts of `CB`.
+const SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SLL", "SRL"];
+
+/// The bit operations of `CB`, after the shifts.
+const BIT_OPERATIONS: [&str; 3] = ["BIT", "RES", "SET"];
+
+/// The block instructions of `ED A0` to `ED BB`.
+const BLOCKS: [[&str; 4]; 4] = [
+    ["LDI", "CPI", "INI", "OUTI"],
+    ["LDD", "CPD", "IND", "OUTD"],
+    ["LDIR", "CPIR", "INIR", "OTIR"],
+    ["LDDR", "CPDR", "INDR", "OTDR"],
+];
+
+/// Returns the length in bytes of the instruction without a prefix starting with `opcode`, with
+/// its operands.
+fn base_length(opcode: u8) -> usize {
+    match opcode {
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xCB | 0xD3 | 0xDB => 2,
+        0x01 | 0x11 | 0x21 | 0x31 | 0x22 | 0x2A | 0x32 | 0x3A | 0xC3 | 0xCD => 3,
+        _ if opcode & 0xC7 == 0x06 || opcode & 0xC7 == 0xC6 => 2,
+        _ if opcode & 0xC7 == 0xC2 || opcode & 0xC7 == 0xC4 => 3,
+        _ => 1,
+    }
+}
+
+/// Returns whether the opcode works on memory at `HL`, which becomes `(IX+d)` after a prefix,
+/// with a displacement following it.
+fn uses_memory(opcode: u8) -> bool {
+    match opcode {
+        0x34..=0x36 => true,
+        0x76 => false,
+        0x40..=0x7F => opcode & 7 == 6 || opcode & 0x38 == 0x30,
+        0x80..=0xBF => opcode & 7 == 6,
+        _ => false,
+    }
+}
+
+/// Returns the length in bytes of the instruction at the start of `bytes`, or `None` if they
+/// end before its opcode does.
+pub fn length(bytes: &[u8]) -> Option<usize> {
+    let opcode = *bytes.first()?;
+    Some(match opcode {
+        0xED => match *bytes.get(1)? {
+            0x43 | 0x4B | 0x53 | 0x5B | 0x63 | 0x6B | 0x73 | 0x7B => 4,
+            _ => 2,
+        },
+        0xDD | 0xFD => match *bytes.get(1)? {
+            0xDD | 0xED | 0xFD => 1,
+            0xCB => 4,
+            opcode => 1 + base_length(opcode) + uses_memory(opcode) as usize,
+        },
+        _ => base_length(opcode),
+    })
+}
+
+/// An instruction with its prefixes and operands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    bytes: [u8; 4],
+    length: u8,
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, or returns `None` if they end before it
+    /// does.
+    pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+        let length = length(bytes)?;
+        let mut instruction = Instruction {
+            bytes: [0; 4],
+            length: length as u8,
+        };
+        instruction.bytes[..length].copy_from_slice(bytes.get(..length)?);
+        Some(instruction)
+    }
+
+    /// Returns the bytes of the instruction.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len()]
+    }
+
+    /// Returns the length of the instruction in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        usize::from(self.length)
+    }
+}
+
+/// The names an instruction gives `HL`, `H`, `L` and `(HL)`: themselves, or after a prefix, an
+/// index register, its halves and memory at a displacement from it.
+#[derive(Clone, Copy)]
+struct Names {
+    pair: &'static str,
+    displacement: i8,
+}
+
+impl Names {
+    const HL: Names = Names {
+        pair: "HL",
+        displacement: 0,
+    };
+
+    /// Returns the register numbered `n` from 0 to 7, with these names.
+    fn register(self, n: u8) -> Register {
+        Register { n, names: self }
+    }
+}
+
+/// A register or memory operand, as [`Names::register`] returns it.
+struct Register {
+    n: u8,
+    names: Names,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Names { pair, displacement } = self.names;
+        match (self.n, pair) {
+            (_, "HL") => f.write_str(REGISTERS[usize::from(self.n)]),
+            (4, _) => write!(f, "{}H", pair),
+            (5, _) => write!(f, "{}L", pair),
+            (6, _) if displacement < 0 => {
+                write!(f, "({}-{:02X})", pair, -i16::from(displacement))
+            }
+            (6, _) => write!(f, "({}+{:02X})", pair, displacement),
+            (n, _) => f.write_str(REGISTERS[usize::from(n)]),
+        }
+    }
+}
+
+/// A relative jump, `$` plus or minus the distance from the start of the instruction.
+struct Relative(u8);
+
+impl fmt::Display for Relative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let distance = i16::from(self.0 as i8) + 2;
+        if distance < 0 {
+            write!(f, "$-{:02X}", -distance)
+        } else {
+            write!(f, "$+{:02X}", distance)
+        }
+    }
+}
+
+impl Instruction {
+    /// Writes the instruction without a prefix starting at `bytes`, or with the prefix whose
+    /// names are `names`.
+    fn fmt_main(f: &mut fmt::Formatter<'_>, bytes: &[u8], names: Names) -> fmt::Result {
+        let opcode = bytes[0];
+        let indexed = names.pair != "HL";
+        let memory = indexed && uses_memory(opcode);
+        let names = Names {
+            displacement: if memory { bytes[1] as i8 } else { 0 },
+            ..names
+        };
+        let operands = &bytes[1 + memory as usize..];
+        let n = operands.first().copied().unwrap_or(0);
+        let nn = u16::from_le_bytes([n, operands.get(1).copied().unwrap_or(0)]);
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        let pair = |p: u8| match p & 3 {
+            2 => names.pair,
+            p => PAIRS[usize::from(p)],
+        };
+        match opcode {
+            0x76 => f.write_str("HALT"),
+            // With (IX+d), H and L stay themselves.
+            0x40..=0x7F if memory => {
+                let (destination, source) = if z == 6 {
+                    (Names::HL.register(y), names.register(z))
+                } else {
+                    (names.register(y), Names::HL.register(z))
+                };
+                write!(f, "LD {}, {}", destination, source)
+            }
+            0x40..=0x7F => write!(f, "LD {}, {}", names.register(y), names.register(z)),
+            0x80..=0xBF => write!(f, "{}{}", ALU[usize::from(y)], names.register(z)),
+            _ if opcode & 0xC7 == 0xC6 => write!(f, "{}{:02X}", ALU[usize::from(y)], n),
+            0x00 => f.write_str("NOP"),
+            0x08 => f.write_str("EX AF, AF'"),
+            0x10 => write!(f, "DJNZ {}", Relative(n)),
+            0x18 => write!(f, "JR {}", Relative(n)),
+            0x20 | 0x28 | 0x30 | 0x38 => {
+                write!(f, "JR {}, {}", CONDITIONS[usize::from(y - 4)], Relative(n))
+            }
+            _ if opcode & 0xCF == 0x01 => write!(f, "LD {}, {:04X}", pair(opcode >> 4), nn),
+            _ if opcode & 0xCF == 0x09 => {
+                write!(f, "ADD {}, {}", names.pair, pair(opcode >> 4))
+            }
+            0x02 | 0x12 => write!(f, "LD ({}), A", PAIRS[usize::from(opcode >> 4)]),
+            0x0A | 0x1A => write!(f, "LD A, ({})", PAIRS[usize::from(opcode >> 4)]),
+            0x22 => write!(f, "LD ({:04X}), {}", nn, names.pair),
+            0x2A => write!(f, "LD {}, ({:04X})", names.pair, nn),
+            0x32 => write!(f, "LD ({:04X}), A", nn),
+            0x3A => write!(f, "LD A, ({:04X})", nn),
+            _ if opcode & 0xCF == 0x03 => write!(f, "INC {}", pair(opcode >> 4)),
+            _ if opcode & 0xCF == 0x0B => write!(f, "DEC {}", pair(opcode >> 4)),
+            _ if opcode & 0xC7 == 0x04 => write!(f, "INC {}", names.register(y)),
+            _ if opcode & 0xC7 == 0x05 => write!(f, "DEC {}", names.register(y)),
+            _ if opcode & 0xC7 == 0x06 => write!(f, "LD {}, {:02X}", names.register(y), n),
+            _ if opcode & 0xC7 == 0x07 => f.write_str(ACCUMULATOR[usize::from(y)]),
+            _ if opcode & 0xC7 == 0xC0 => write!(f, "RET {}", CONDITIONS[usize::from(y)]),
+            _ if opcode & 0xC7 == 0xC2 => {
+                write!(f, "JP {}, {:04X}", CONDITIONS[usize::from(y)], nn)
+            }
+            _ if opcode & 0xC7 == 0xC4 => {
+                write!(f, "CALL {}, {:04X}", CONDITIONS[usize::from(y)], nn)
+            }
+            _ if opcode & 0xCF == 0xC1 || opcode & 0xCF == 0xC5 => {
+                let mnemonic = if opcode & 4 == 0 { "POP" } else { "PUSH" };
+                match opcode >> 4 & 3 {
+                    2 => write!(f, "{} {}", mnemonic, names.pair),
+                    p => write!(f, "{} {}", mnemonic, STACK_PAIRS[usize::from(p)]),
+                }
+            }
+            _ if opcode & 0xC7 == 0xC7 => write!(f, "RST {:02X}", opcode & 0x38),
+            0xC3 => write!(f, "JP {:04X}", nn),
+            0xC9 => f.write_str("RET"),
+            0xCB => Self::fmt_cb(f, n, Names::HL),
+            0xCD => write!(f, "CALL {:04X}", nn),
+            0xD3 => write!(f, "OUT ({:02X}), A", n),
+            0xD9 => f.write_str("EXX"),
+            0xDB => write!(f, "IN A, ({:02X})", n),
+            0xE3 => write!(f, "EX (SP), {}", names.pair),
+            0xE9 => write!(f, "JP ({})", names.pair),
+            0xEB => f.write_str("EX DE, HL"),
+            0xF3 => f.write_str("DI"),
+            0xF9 => write!(f, "LD SP, {}", names.pair),
+            0xFB => f.write_str("EI"),
+            _ => f.write_str("NOP"),
+        }
+    }
+
+    /// Writes the opcode following `CB`, on the register it names or on memory at `names`.
+    fn fmt_cb(f: &mut fmt::Formatter<'_>, opcode: u8, names: Names) -> fmt::Result {
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        let indexed = names.pair != "HL";
+        let operand = if indexed { 6 } else { z };
+        match opcode >> 6 {
+            0 => write!(f, "{} {}", SHIFTS[usize::from(y)], names.register(operand))?,
+            x => {
+                let mnemonic = BIT_OPERATIONS[usize::from(x - 1)];
+                write!(f, "{} {}, {}", mnemonic, y, names.register(operand))?;
+                if x == 1 {
+                    return Ok(());
+                }
+            }
+        }
+        if indexed && z != 6 {
+            write!(f, ", {}", REGISTERS[usize::from(z)])?;
+        }
+        Ok(())
+    }
+
+    /// Writes the opcode following `ED`, whose operands are `operands`.
+    fn fmt_ed(f: &mut fmt::Formatter<'_>, opcode: u8, operands: &[u8]) -> fmt::Result {
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        let register = Names::HL.register(y);
+        let pair = PAIRS[usize::from(y >> 1)];
+        match opcode {
+            0x70 => f.write_str("IN (C)"),
+            0x71 => f.write_str("OUT (C), 0"),
+            0x40..=0x7F => match z {
+                0 => write!(f, "IN {}, (C)", register),
+                1 => write!(f, "OUT (C), {}", register),
+                2 if y & 1 == 0 => write!(f, "SBC HL, {}", pair),
+                2 => write!(f, "ADC HL, {}", pair),
+                3 => {
+                    let nn = u16::from_le_bytes([operands[0], operands[1]]);
+                    if y & 1 == 0 {
+                        write!(f, "LD ({:04X}), {}", nn, pair)
+                    } else {
+                        write!(f, "LD {}, ({:04X})", pair, nn)
+                    }
+                }
+                4 => f.write_str("NEG"),
+                5 if y == 1 => f.write_str("RETI"),
+                5 => f.write_str("RETN"),
+                6 => write!(f, "IM {}", [0, 0, 1, 2][usize::from(y & 3)]),
+                _ => f.write_str(
+                    [
+                        "LD I, A", "LD R, A", "LD A, I", "LD A, R", "RRD", "RLD", "NOP", "NOP",
+                    ][usize::from(y)],
+                ),
+            },
+            0xA0..=0xBF if z < 4 => f.write_str(BLOCKS[usize::from(y - 4)][usize::from(z)]),
+            _ => f.write_str("NOP"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.bytes();
+        match bytes[0] {
+            0xED => Self::fmt_ed(f, bytes[1], &bytes[2..]),
+            0xDD | 0xFD if bytes.len() == 1 => f.write_str("NOP"),
+            prefix @ 0xDD | prefix @ 0xFD => {
+                let pair = if prefix == 0xDD { "IX" } else { "IY" };
+                if bytes[1] == 0xCB {
+                    let names = Names {
+                        pair,
+                        displacement: bytes[2] as i8,
+                    };
+                    Self::fmt_cb(f, bytes[3], names)
+                } else {
+                    let names = Names {
+                        pair,
+                        displacement: 0,
+                    };
+                    Self::fmt_main(f, &bytes[1..], names)
+                }
+            }
+            _ => Self::fmt_main(f, bytes, Names::HL),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    fn disassemble(bytes: &[u8]) -> std::string::String {
+        let instruction = Instruction::decode(bytes).unwrap();
+        assert_eq!(instruction.len(), bytes.len(), "{:02X?}", bytes);
+        instruction.to_string()
+    }
+
+    #[test]
+    fn disassembles() {
+        assert_eq!(disassemble(&[0x00]), "NOP");
+        assert_eq!(disassemble(&[0x01, 0x34, 0x12]), "LD BC, 1234");
+        assert_eq!(disassemble(&[0x08]), "EX AF, AF'");
+        assert_eq!(disassemble(&[0x10, 0xFE]), "DJNZ $+00");
+        assert_eq!(disassemble(&[0x20, 0xFA]), "JR NZ, $-04");
+        assert_eq!(disassemble(&[0x38, 0x10]), "JR C, $+12");
+        assert_eq!(disassemble(&[0x12]), "LD (DE), A");
+        assert_eq!(disassemble(&[0x2A, 0x00, 0x20]), "LD HL, (2000)");
+        assert_eq!(disassemble(&[0x34]), "INC (HL)");
+        assert_eq!(disassemble(&[0x3E, 0x2A]), "LD A, 2A");
+        assert_eq!(disassemble(&[0x1F]), "RRA");
+        assert_eq!(disassemble(&[0x39]), "ADD HL, SP");
+        assert_eq!(disassemble(&[0x77]), "LD (HL), A");
+        assert_eq!(disassemble(&[0x76]), "HALT");
+        assert_eq!(disassemble(&[0x96]), "SUB (HL)");
+        assert_eq!(disassemble(&[0x8F]), "ADC A, A");
+        assert_eq!(disassemble(&[0xFE, 0x0A]), "CP 0A");
+        assert_eq!(disassemble(&[0xC0]), "RET NZ");
+        assert_eq!(disassemble(&[0xF1]), "POP AF");
+        assert_eq!(disassemble(&[0xDA, 0x00, 0x01]), "JP C, 0100");
+        assert_eq!(disassemble(&[0xFC, 0x00, 0x01]), "CALL M, 0100");
+        assert_eq!(disassemble(&[0xFF]), "RST 38");
+        assert_eq!(disassemble(&[0xD3, 0xFE]), "OUT (FE), A");
+        assert_eq!(disassemble(&[0xDB, 0x01]), "IN A, (01)");
+        assert_eq!(disassemble(&[0xE3]), "EX (SP), HL");
+        assert_eq!(disassemble(&[0xE9]), "JP (HL)");
+        assert_eq!(disassemble(&[0xD9]), "EXX");
+    }
+
+    #[test]
+    fn disassembles_prefixes() {
+        assert_eq!(disassemble(&[0xCB, 0x00]), "RLC B");
+        assert_eq!(disassemble(&[0xCB, 0x36]), "SLL (HL)");
+        assert_eq!(disassemble(&[0xCB, 0x7E]), "BIT 7, (HL)");
+        assert_eq!(disassemble(&[0xCB, 0xC7]), "SET 0, A");
+        assert_eq!(disassemble(&[0xED, 0x44]), "NEG");
+        assert_eq!(disassemble(&[0xED, 0x4D]), "RETI");
+        assert_eq!(disassemble(&[0xED, 0x5E]), "IM 2");
+        assert_eq!(disassemble(&[0xED, 0x78]), "IN A, (C)");
+        assert_eq!(disassemble(&[0xED, 0x70]), "IN (C)");
+        assert_eq!(disassemble(&[0xED, 0x71]), "OUT (C), 0");
+        assert_eq!(disassemble(&[0xED, 0x52]), "SBC HL, DE");
+        assert_eq!(disassemble(&[0xED, 0x73, 0x00, 0xF0]), "LD (F000), SP");
+        assert_eq!(disassemble(&[0xED, 0x57]), "LD A, I");
+        assert_eq!(disassemble(&[0xED, 0x6F]), "RLD");
+        assert_eq!(disassemble(&[0xED, 0xB0]), "LDIR");
+        assert_eq!(disassemble(&[0xED, 0xBB]), "OTDR");
+        assert_eq!(disassemble(&[0xED, 0x00]), "NOP");
+
+        assert_eq!(disassemble(&[0xDD, 0x21, 0x00, 0x40]), "LD IX, 4000");
+        assert_eq!(disassemble(&[0xFD, 0x7E, 0xFD]), "LD A, (IY-03)");
+        assert_eq!(disassemble(&[0xDD, 0x66, 0x01]), "LD H, (IX+01)");
+        assert_eq!(disassemble(&[0xDD, 0x74, 0x01]), "LD (IX+01), H");
+        assert_eq!(disassemble(&[0xDD, 0x65]), "LD IXH, IXL");
+        assert_eq!(disassemble(&[0xFD, 0x2E, 0x10]), "LD IYL, 10");
+        assert_eq!(disassemble(&[0xDD, 0x86, 0x7F]), "ADD A, (IX+7F)");
+        assert_eq!(disassemble(&[0xDD, 0x35, 0x80]), "DEC (IX-80)");
+        assert_eq!(disassemble(&[0xDD, 0x29]), "ADD IX, IX");
+        assert_eq!(disassemble(&[0xFD, 0xE5]), "PUSH IY");
+        assert_eq!(disassemble(&[0xDD, 0xE9]), "JP (IX)");
+        assert_eq!(disassemble(&[0xDD, 0xEB]), "EX DE, HL");
+        assert_eq!(disassemble(&[0xDD, 0xCB, 0x05, 0x46]), "BIT 0, (IX+05)");
+        assert_eq!(disassemble(&[0xFD, 0xCB, 0xFF, 0x16]), "RL (IY-01)");
+        assert_eq!(disassemble(&[0xDD, 0xCB, 0x05, 0x00]), "RLC (IX+05), B");
+        assert_eq!(disassemble(&[0xDD, 0xCB, 0x05, 0xFF]), "SET 7, (IX+05), A");
+        let prefix = Instruction::decode(&[0xDD, 0xFD, 0x00]).unwrap();
+        assert_eq!((prefix.to_string().as_str(), prefix.len()), ("NOP", 1));
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(length(&[]), None);
+        assert_eq!(length(&[0xED]), None);
+        assert_eq!(length(&[0xDD, 0x36]), Some(4));
+        assert_eq!(Instruction::decode(&[0xDD, 0x36, 0x05]), None);
+        assert_eq!(Instruction::decode(&[0xC3, 0x01]), None);
+        let lengths: usize = (0..=255u8)
+            .map(|opcode| length(&[opcode, 0]).unwrap())
+            .sum();
+        // JR, DJNZ, CB, IN, OUT, 8 LD r, n and 8 immediate operations; 4 LD rr, nn, 4 direct
+        // loads and stores, JP, CALL and 8 each of the conditional jumps and calls; and
+        // the prefixes DD, ED and FD with a NOP after them.
+        assert_eq!(lengths, 256 + 9 + 16 + (4 + 4 + 2 + 16) * 2 + 1 + 1 + 1);
+    }
+}
@@ -0,0 +1,1871 @@
+//! The Zilog Z80 microprocessor, the processor of Pac-Man and the arcade machines of the early
+//! 1980s, of the Sega Master System, and of the ZX Spectrum and most CP/M computers.
+//!
+//! The Z80 runs the programs of the Intel 8080 and adds to it: a second set of registers, which
+//! `EX AF, AF'` and `EXX` swap in, the index registers `IX` and `IY`, relative jumps, block moves
+//! and searches, operations on single bits, and three modes of interrupts besides the
+//! non-maskable one. Instructions take 4 to 23 clock cycles, which Zilog calls T-states. Prefixes
+//! select the opcodes beyond the first 256: `CB` the bit operations, `ED` the others, and `DD`
+//! and `FD` put `IX` and `IY` in place of `HL`.
+//!
+//! A [`Cpu`] runs against a [`Bus`], which is the memory and devices of a machine:
+//!
+//! ```
+//! use z80::bus::Memory;
+//! use z80::{Cpu, Register};
+//!
+//! // 0x0000: LD A, 2A; INC A; HALT
+//! let mut memory = Memory::with_program(0, &[0x3E, 0x2A, 0x3C, 0x76])?;
+//! let mut cpu = Cpu::new();
+//! while !cpu.halted() {
+//!     cpu.step(&mut memory);
+//! }
+//! assert_eq!(cpu.register(Register::A), 0x2B);
+//! assert_eq!(cpu.cycles(), 7 + 4 + 4);
+//! # Ok::<(), z80::bus::Overflow>(())
+//! ```
+//!
+//! The crate is `no_std` and needs no allocator.
+
+#![no_std]
+
+pub mod bus;
+pub mod disasm;
+
+use crate::bus::Bus;
+
+/// Length of the state written by [`Cpu::save`].
+pub const STATE_SIZE: usize = 39;
+
+/// The sign flag, set when bit 7 of a result is.
+pub const SIGN: u8 = 0x80;
+
+/// The zero flag, set when a result is 0.
+pub const ZERO: u8 = 0x40;
+
+/// The undocumented flag that usually holds bit 5 of a result.
+pub const BIT5: u8 = 0x20;
+
+/// The half carry flag, set on a carry out of bit 3 or a borrow into it, for `DAA`.
+pub const HALF_CARRY: u8 = 0x10;
+
+/// The undocumented flag that usually holds bit 3 of a result.
+pub const BIT3: u8 = 0x08;
+
+/// The parity or overflow flag: set when a logical result has an even number of bits set, or
+/// when an arithmetic one overflows as a signed number.
+pub const PARITY: u8 = 0x04;
+
+/// The subtract flag, set by subtractions, for `DAA`.
+pub const SUBTRACT: u8 = 0x02;
+
+/// The carry flag, set on a carry out of bit 7 or on a borrow.
+pub const CARRY: u8 = 0x01;
+
+/// Both undocumented flags.
+const UNDOCUMENTED: u8 = BIT5 | BIT3;
+
+/// Where the flags are kept among the registers, in place of `(HL)`, which is memory.
+const F: usize = 6;
+
+/// The accumulator's place among the registers.
+const A: usize = 7;
+
+/// Clock cycles of each instruction without a prefix, or of a conditional jump, call or return
+/// that is not taken. The prefixes count for nothing here.
+const CYCLES: [u8; 256] = [
+    4, 10, 7, 6, 4, 4, 7, 4, 4, 11, 7, 6, 4, 4, 7, 4, // 00
+    8, 10, 7, 6, 4, 4, 7, 4, 12, 11, 7, 6, 4, 4, 7, 4, // 10
+    7, 10, 16, 6, 4, 4, 7, 4, 7, 11, 16, 6, 4, 4, 7, 4, // 20
+    7, 10, 13, 6, 11, 11, 10, 4, 7, 11, 13, 6, 4, 4, 7, 4, // 30
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 40
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 50
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 60
+    7, 7, 7, 7, 7, 7, 4, 7, 4, 4, 4, 4, 4, 4, 7, 4, // 70
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 80
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 90
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // A0
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // B0
+    5, 10, 10, 10, 10, 11, 7, 11, 5, 10, 10, 0, 10, 17, 7, 11, // C0
+    5, 10, 10, 11, 10, 11, 7, 11, 5, 4, 10, 11, 10, 0, 7, 11, // D0
+    5, 10, 10, 19, 10, 11, 7, 11, 5, 4, 10, 4, 10, 0, 7, 11, // E0
+    5, 10, 10, 4, 10, 11, 7, 11, 5, 6, 10, 4, 10, 0, 7, 11, // F0
+];
+
+/// Clock cycles a relative jump or `DJNZ` takes on top of [`CYCLES`] when it jumps.
+const JUMP_TAKEN: u8 = 5;
+
+/// Clock cycles a conditional return takes on top of [`CYCLES`] when it is taken.
+const RETURN_TAKEN: u8 = 6;
+
+/// Clock cycles a conditional call takes on top of [`CYCLES`] when it is taken.
+const CALL_TAKEN: u8 = 7;
+
+/// Clock cycles a block instruction takes on top of one that ends when it repeats.
+const REPEAT: u8 = 5;
+
+/// An 8-bit register, numbered as instructions number them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    B = 0,
+    C = 1,
+    D = 2,
+    E = 3,
+    H = 4,
+    L = 5,
+    /// The accumulator.
+    A = 7,
+    /// The high byte of the vectors of interrupt mode 2.
+    I = 8,
+    /// The refresh counter, whose 7 low bits count the opcodes fetched.
+    R = 9,
+}
+
+/// A pair of registers, the stack pointer or an index register, used as a 16-bit register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pair {
+    BC,
+    DE,
+    HL,
+    SP,
+    /// The accumulator and the flags.
+    AF,
+    IX,
+    IY,
+}
+
+/// Which register instructions use for `HL`, as the prefixes `DD` and `FD` choose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Index {
+    HL,
+    IX,
+    IY,
+}
+
+/// The processor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cpu {
+    /// `B`, `C`, `D`, `E`, `H`, `L`, the flags and `A`, in the order instructions number them.
+    registers: [u8; 8],
+    /// The alternate registers, in the same order.
+    alternates: [u8; 8],
+    ix: u16,
+    iy: u16,
+    sp: u16,
+    pc: u16,
+    i: u8,
+    r: u8,
+    /// The internal register Zilog calls `WZ` and others `MEMPTR`, holding addresses the
+    /// processor works out, which shows through the undocumented flags of `BIT n, (HL)`.
+    wz: u16,
+    /// Whether interrupts are taken.
+    iff1: bool,
+    /// Where `IFF1` is kept while a non-maskable interrupt runs, for `RETN`.
+    iff2: bool,
+    /// Whether interrupts stay off for one more instruction, after `EI`.
+    ei_delay: bool,
+    /// The interrupt mode, 0, 1 or 2.
+    im: u8,
+    /// Whether the processor is waiting in `HALT` for an interrupt.
+    halted: bool,
+    /// The flags the last instruction set, or 0 if it left them, which shows through the
+    /// undocumented flags of `SCF` and `CCF`.
+    q: u8,
+    /// Whether the instruction running has set the flags.
+    flags_set: bool,
+    /// Clock cycles run since the processor was created.
+    cycles: u64,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu {
+    /// Creates a processor just powered on, with `AF` and `SP` at 0xFFFF as on most Z80s, and the
+    /// other registers 0.
+    pub fn new() -> Self {
+        let mut registers = [0; 8];
+        registers[F] = 0xFF;
+        registers[A] = 0xFF;
+        Cpu {
+            registers,
+            alternates: [0; 8],
+            ix: 0,
+            iy: 0,
+            sp: 0xFFFF,
+            pc: 0,
+            i: 0,
+            r: 0,
+            wz: 0,
+            iff1: false,
+            iff2: false,
+            ei_delay: false,
+            im: 0,
+            halted: false,
+            q: 0,
+            flags_set: false,
+            cycles: 0,
+        }
+    }
+
+    /// Resets the processor as its `RESET` line does: the program starts over at address 0 with
+    /// interrupts disabled, in mode 0, and `I` and `R` are cleared. The other registers keep
+    /// their values.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.i = 0;
+        self.r = 0;
+        self.iff1 = false;
+        self.iff2 = false;
+        self.ei_delay = false;
+        self.im = 0;
+        self.halted = false;
+    }
+
+    /// Returns an 8-bit register.
+    pub fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::I => self.i,
+            Register::R => self.r,
+            _ => self.registers[register as usize],
+        }
+    }
+
+    /// Sets an 8-bit register.
+    pub fn set_register(&mut self, register: Register, value: u8) {
+        match register {
+            Register::I => self.i = value,
+            Register::R => self.r = value,
+            _ => self.registers[register as usize] = value,
+        }
+    }
+
+    /// Returns a pair of registers, the stack pointer or an index register.
+    pub fn pair(&self, pair: Pair) -> u16 {
+        let (high, low) = match pair {
+            Pair::BC => (0, 1),
+            Pair::DE => (2, 3),
+            Pair::HL => (4, 5),
+            Pair::AF => (A, F),
+            Pair::SP => return self.sp,
+            Pair::IX => return self.ix,
+            Pair::IY => return self.iy,
+        };
+        u16::from_be_bytes([self.registers[high], self.registers[low]])
+    }
+
+    /// Sets a pair of registers, the stack pointer or an index register.
+    pub fn set_pair(&mut self, pair: Pair, value: u16) {
+        let (high, low) = match pair {
+            Pair::BC => (0, 1),
+            Pair::DE => (2, 3),
+            Pair::HL => (4, 5),
+            Pair::AF => (A, F),
+            Pair::SP => return self.sp = value,
+            Pair::IX => return self.ix = value,
+            Pair::IY => return self.iy = value,
+        };
+        let [high_byte, low_byte] = value.to_be_bytes();
+        self.registers[high] = high_byte;
+        self.registers[low] = low_byte;
+    }
+
+    /// Returns the alternate of `AF`, `BC`, `DE` or `HL`, which `EX AF, AF'` and `EXX` swap in.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the other pairs, which have no alternates.
+    pub fn alternate(&self, pair: Pair) -> u16 {
+        let (high, low) = Self::alternate_indexes(pair);
+        u16::from_be_bytes([self.alternates[high], self.alternates[low]])
+    }
+
+    /// Sets the alternate of `AF`, `BC`, `DE` or `HL`.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the other pairs, which have no alternates.
+    pub fn set_alternate(&mut self, pair: Pair, value: u16) {
+        let (high, low) = Self::alternate_indexes(pair);
+        let [high_byte, low_byte] = value.to_be_bytes();
+        self.alternates[high] = high_byte;
+        self.alternates[low] = low_byte;
+    }
+
+    fn alternate_indexes(pair: Pair) -> (usize, usize) {
+        match pair {
+            Pair::BC => (0, 1),
+            Pair::DE => (2, 3),
+            Pair::HL => (4, 5),
+            Pair::AF => (A, F),
+            _ => panic!("{:?} has no alternate", pair),
+        }
+    }
+
+    /// Returns the flags, as [`SIGN`], [`ZERO`], [`HALF_CARRY`], [`PARITY`], [`SUBTRACT`] and
+    /// [`CARRY`], with the undocumented [`BIT5`] and [`BIT3`].
+    pub fn flags(&self) -> u8 {
+        self.registers[F]
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    /// Continues the program at `address`.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.pc = address;
+    }
+
+    /// Returns whether interrupts are enabled, which is `IFF1`.
+    pub fn iff1(&self) -> bool {
+        self.iff1
+    }
+
+    /// Returns `IFF2`, where `IFF1` is kept while a non-maskable interrupt runs.
+    pub fn iff2(&self) -> bool {
+        self.iff2
+    }
+
+    /// Returns the interrupt mode, 0, 1 or 2, as `IM` sets it.
+    pub fn interrupt_mode(&self) -> u8 {
+        self.im
+    }
+
+    /// Returns whether the processor is waiting in `HALT` for an interrupt.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns the clock cycles run since the processor was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs the next instruction, or takes an interrupt, and returns the clock cycles it took.
+    /// While halted with no interrupt, 4 clock cycles pass, as for a `NOP`.
+    ///
+    /// Interrupts are only taken once the instruction following `EI` has run, so that a routine
+    /// can end with `EI` and `RET` without being interrupted in between. The non-maskable
+    /// interrupt is taken between any two instructions.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        let enabled = self.iff1 && !self.ei_delay;
+        self.ei_delay = false;
+        self.flags_set = false;
+        let cycles = if bus.nmi() {
+            self.nmi(bus)
+        } else if let Some(data) = if enabled { bus.interrupt() } else { None } {
+            self.interrupt(data, bus)
+        } else if self.halted {
+            self.refresh();
+            4
+        } else {
+            let opcode = self.fetch(bus);
+            self.execute(opcode, Index::HL, bus)
+        };
+        self.q = if self.flags_set { self.registers[F] } else { 0 };
+        self.cycles += u64::from(cycles);
+        cycles
+    }
+
+    /// Takes the non-maskable interrupt, calling 0x0066 with interrupts disabled and their state
+    /// kept in `IFF2`.
+    fn nmi(&mut self, bus: &mut impl Bus) -> u8 {
+        self.refresh();
+        self.halted = false;
+        self.iff1 = false;
+        self.push(self.pc, bus);
+        self.pc = 0x0066;
+        self.wz = self.pc;
+        11
+    }
+
+    /// Takes an interrupt with `data` on the data bus, in the interrupt mode.
+    fn interrupt(&mut self, data: u8, bus: &mut impl Bus) -> u8 {
+        self.refresh();
+        self.halted = false;
+        self.iff1 = false;
+        self.iff2 = false;
+        let cycles = match self.im {
+            // The instruction runs with two more clock cycles for acknowledging the interrupt.
+            0 => return self.execute(data, Index::HL, bus) + 2,
+            1 => {
+                self.push(self.pc, bus);
+                self.pc = 0x0038;
+                13
+            }
+            _ => {
+                self.push(self.pc, bus);
+                let vector = u16::from_be_bytes([self.i, data]);
+                self.pc = self.read16(vector, bus);
+                19
+            }
+        };
+        self.wz = self.pc;
+        cycles
+    }
+
+    /// Counts an opcode fetch in the 7 low bits of `R`.
+    fn refresh(&mut self) {
+        self.r = self.r & 0x80 | self.r.wrapping_add(1) & 0x7F;
+    }
+
+    /// Fetches an opcode or a prefix, counting it in `R`.
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        self.refresh();
+        self.immediate(bus)
+    }
+
+    /// Reads the byte at the program counter and moves past it.
+    fn immediate(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    /// Reads the address or 16-bit value at the program counter and moves past it.
+    fn immediate16(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = self.immediate(bus);
+        let high = self.immediate(bus);
+        u16::from_le_bytes([low, high])
+    }
+
+    fn read16(&mut self, address: u16, bus: &mut impl Bus) -> u16 {
+        let low = bus.read(address);
+        let high = bus.read(address.wrapping_add(1));
+        u16::from_le_bytes([low, high])
+    }
+
+    fn write16(&mut self, address: u16, value: u16, bus: &mut impl Bus) {
+        let [low, high] = value.to_le_bytes();
+        bus.write(address, low);
+        bus.write(address.wrapping_add(1), high);
+    }
+
+    fn push(&mut self, value: u16, bus: &mut impl Bus) {
+        let [high, low] = value.to_be_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, high);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, low);
+    }
+
+    fn pop(&mut self, bus: &mut impl Bus) -> u16 {
+        let low = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Returns `HL`, `IX` or `IY`.
+    fn index(&self, index: Index) -> u16 {
+        match index {
+            Index::HL => self.pair(Pair::HL),
+            Index::IX => self.ix,
+            Index::IY => self.iy,
+        }
+    }
+
+    fn set_index(&mut self, index: Index, value: u16) {
+        match index {
+            Index::HL => self.set_pair(Pair::HL, value),
+            Index::IX => self.ix = value,
+            Index::IY => self.iy = value,
+        }
+    }
+
+    /// Returns the address of `(HL)`, or of `(IX+d)` or `(IY+d)` reading the displacement `d`
+    /// and adding the 8 clock cycles it takes to `cycles`.
+    fn address(&mut self, index: Index, bus: &mut impl Bus, cycles: &mut u8) -> u16 {
+        if index == Index::HL {
+            return self.pair(Pair::HL);
+        }
+        let displacement = self.immediate(bus) as i8;
+        self.wz = self.index(index).wrapping_add(displacement as u16);
+        *cycles += 8;
+        self.wz
+    }
+
+    /// Reads the register numbered `n` from 0 to 7 other than 6, with `H` and `L` the halves of
+    /// `IX` or `IY` after their prefixes.
+    fn get(&self, n: u8, index: Index) -> u8 {
+        match (n, index) {
+            (4, Index::IX) | (4, Index::IY) => (self.index(index) >> 8) as u8,
+            (5, Index::IX) | (5, Index::IY) => self.index(index) as u8,
+            _ => self.registers[usize::from(n)],
+        }
+    }
+
+    /// Writes the register numbered `n` from 0 to 7 other than 6, as [`Cpu::get`] reads it.
+    fn set(&mut self, n: u8, value: u8, index: Index) {
+        let [high, low] = self.index(index).to_be_bytes();
+        match (n, index) {
+            (4, Index::IX) | (4, Index::IY) => {
+                self.set_index(index, u16::from_be_bytes([value, low]))
+            }
+            (5, Index::IX) | (5, Index::IY) => {
+                self.set_index(index, u16::from_be_bytes([high, value]))
+            }
+            _ => self.registers[usize::from(n)] = value,
+        }
+    }
+
+    /// Reads the register numbered `n` from 0 to 7, or memory at `(HL)` for 6.
+    fn operand(&mut self, n: u8, index: Index, bus: &mut impl Bus, cycles: &mut u8) -> u8 {
+        if n == 6 {
+            let address = self.address(index, bus, cycles);
+            bus.read(address)
+        } else {
+            self.get(n, index)
+        }
+    }
+
+    /// Returns the pair numbered `n` from 0 to 3 in `LD rr, nn`, `ADD`, `INC` and `DEC`: `BC`,
+    /// `DE`, `HL` and `SP`.
+    fn pair_sp(&self, n: u8, index: Index) -> u16 {
+        match n & 3 {
+            0 => self.pair(Pair::BC),
+            1 => self.pair(Pair::DE),
+            2 => self.index(index),
+            _ => self.sp,
+        }
+    }
+
+    fn set_pair_sp(&mut self, n: u8, value: u16, index: Index) {
+        match n & 3 {
+            0 => self.set_pair(Pair::BC, value),
+            1 => self.set_pair(Pair::DE, value),
+            2 => self.set_index(index, value),
+            _ => self.sp = value,
+        }
+    }
+
+    /// Sets every flag to `flags`.
+    fn set_flags(&mut self, flags: u8) {
+        self.registers[F] = flags;
+        self.flags_set = true;
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        self.registers[F] & flag != 0
+    }
+
+    /// Returns the condition numbered `n` from 0 to 7: not zero, zero, no carry, carry, parity
+    /// odd, parity even, plus and minus.
+    fn condition(&self, n: u8) -> bool {
+        let flag = [ZERO, CARRY, PARITY, SIGN][usize::from(n >> 1 & 3)];
+        self.flag(flag) == (n & 1 != 0)
+    }
+
+    /// Moves the program counter by the displacement at it, for `JR` and `DJNZ`.
+    fn jump_relative(&mut self, displacement: u8) {
+        self.pc = self.pc.wrapping_add(displacement as i8 as u16);
+        self.wz = self.pc;
+    }
+
+    /// Executes `opcode`, whose operands follow at the program counter, with `HL` or the index
+    /// register of its prefix, and returns its clock cycles.
+    fn execute(&mut self, opcode: u8, index: Index, bus: &mut impl Bus) -> u8 {
+        let mut cycles = CYCLES[usize::from(opcode)];
+        if index != Index::HL {
+            cycles += 4;
+        }
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        match opcode {
+            0x76 => self.halted = true,
+            // With (IX+d), H and L stay themselves.
+            0x40..=0x7F if z == 6 => {
+                let address = self.address(index, bus, &mut cycles);
+                self.registers[usize::from(y)] = bus.read(address);
+            }
+            0x40..=0x7F if y == 6 => {
+                let address = self.address(index, bus, &mut cycles);
+                bus.write(address, self.registers[usize::from(z)]);
+            }
+            0x40..=0x7F => {
+                let value = self.get(z, index);
+                self.set(y, value, index);
+            }
+            0x80..=0xBF => {
+                let value = self.operand(z, index, bus, &mut cycles);
+                self.alu(y, value);
+            }
+            _ if opcode & 0xC7 == 0xC6 => {
+                let value = self.immediate(bus);
+                self.alu(y, value);
+            }
+            0x00 => {}
+            0x08 => {
+                self.registers[F..].swap_with_slice(&mut self.alternates[F..]);
+            }
+            0x10 => {
+                let displacement = self.immediate(bus);
+                self.registers[0] = self.registers[0].wrapping_sub(1);
+                if self.registers[0] != 0 {
+                    self.jump_relative(displacement);
+                    cycles += JUMP_TAKEN;
+                }
+            }
+            0x18 => {
+                let displacement = self.immediate(bus);
+                self.jump_relative(displacement);
+            }
+            0x20 | 0x28 | 0x30 | 0x38 => {
+                let displacement = self.immediate(bus);
+                if self.condition(y - 4) {
+                    self.jump_relative(displacement);
+                    cycles += JUMP_TAKEN;
+                }
+            }
+            _ if opcode & 0xCF == 0x01 => {
+                let value = self.immediate16(bus);
+                self.set_pair_sp(opcode >> 4, value, index);
+            }
+            _ if opcode & 0xCF == 0x09 => {
+                let (a, b) = (self.index(index), self.pair_sp(opcode >> 4, index));
+                let result = self.add16(a, b);
+                self.set_index(index, result);
+            }
+            0x02 | 0x12 => {
+                let address = self.pair_sp(opcode >> 4, index);
+                let a = self.registers[A];
+                bus.write(address, a);
+                self.wz = u16::from_be_bytes([a, address.wrapping_add(1) as u8]);
+            }
+            0x0A | 0x1A => {
+                let address = self.pair_sp(opcode >> 4, index);
+                self.registers[A] = bus.read(address);
+                self.wz = address.wrapping_add(1);
+            }
+            0x22 => {
+                let address = self.immediate16(bus);
+                let value = self.index(index);
+                self.write16(address, value, bus);
+                self.wz = address.wrapping_add(1);
+            }
+            0x2A => {
+                let address = self.immediate16(bus);
+                let value = self.read16(address, bus);
+                self.set_index(index, value);
+                self.wz = address.wrapping_add(1);
+            }
+            0x32 => {
+                let address = self.immediate16(bus);
+                let a = self.registers[A];
+                bus.write(address, a);
+                self.wz = u16::from_be_bytes([a, address.wrapping_add(1) as u8]);
+            }
+            0x3A => {
+                let address = self.immediate16(bus);
+                self.registers[A] = bus.read(address);
+                self.wz = address.wrapping_add(1);
+            }
+            _ if opcode & 0xC7 == 0x03 => {
+                let n = opcode >> 4;
+                let step = if opcode & 8 == 0 { 1 } else { 0xFFFF };
+                let value = self.pair_sp(n, index).wrapping_add(step);
+                self.set_pair_sp(n, value, index);
+            }
+            0x34 | 0x35 => {
+                let address = self.address(index, bus, &mut cycles);
+                let value = bus.read(address);
+                let result = if opcode == 0x34 {
+                    self.increment(value)
+                } else {
+                    self.decrement(value)
+                };
+                bus.write(address, result);
+            }
+            _ if opcode & 0xC7 == 0x04 => {
+                let result = self.increment(self.get(y, index));
+                self.set(y, result, index);
+            }
+            _ if opcode & 0xC7 == 0x05 => {
+                let result = self.decrement(self.get(y, index));
+                self.set(y, result, index);
+            }
+            0x36 => {
+                let address = self.address(index, bus, &mut cycles);
+                if index != Index::HL {
+                    // The byte is read while the displacement is added.
+                    cycles -= 3;
+                }
+                let value = self.immediate(bus);
+                bus.write(address, value);
+            }
+            _ if opcode & 0xC7 == 0x06 => {
+                let value = self.immediate(bus);
+                self.set(y, value, index);
+            }
+            0x07 | 0x0F | 0x17 | 0x1F => {
+                let a = self.registers[A];
+                let carry = self.flag(CARRY) as u8;
+                let (result, out) = match y {
+                    0 => (a.rotate_left(1), a >> 7),
+                    1 => (a.rotate_right(1), a & 1),
+                    2 => (a << 1 | carry, a >> 7),
+                    _ => (a >> 1 | carry << 7, a & 1),
+                };
+                self.registers[A] = result;
+                let kept = self.registers[F] & (SIGN | ZERO | PARITY);
+                self.set_flags(kept | result & UNDOCUMENTED | out);
+            }
+            0x27 => self.daa(),
+            0x2F => {
+                let result = !self.registers[A];
+                self.registers[A] = result;
+                let kept = self.registers[F] & (SIGN | ZERO | PARITY | CARRY);
+                self.set_flags(kept | HALF_CARRY | SUBTRACT | result & UNDOCUMENTED);
+            }
+            0x37 | 0x3F => {
+                // The undocumented flags come from A, or from A and the flags when the last
+                // instruction left them.
+                let f = self.registers[F];
+                let undocumented = ((self.q ^ f) | self.registers[A]) & UNDOCUMENTED;
+                let kept = f & (SIGN | ZERO | PARITY) | undocumented;
+                if opcode == 0x37 {
+                    self.set_flags(kept | CARRY);
+                } else if f & CARRY != 0 {
+                    self.set_flags(kept | HALF_CARRY);
+                } else {
+                    self.set_flags(kept | CARRY);
+                }
+            }
+            _ if opcode & 0xC7 == 0xC0 => {
+                if self.condition(y) {
+                    self.pc = self.pop(bus);
+                    self.wz = self.pc;
+                    cycles += RETURN_TAKEN;
+                }
+            }
+            _ if opcode & 0xCF == 0xC1 => {
+                let value = self.pop(bus);
+                match opcode >> 4 & 3 {
+                    3 => self.set_pair(Pair::AF, value),
+                    n => self.set_pair_sp(n, value, index),
+                }
+            }
+            _ if opcode & 0xC7 == 0xC2 => {
+                self.wz = self.immediate16(bus);
+                if self.condition(y) {
+                    self.pc = self.wz;
+                }
+            }
+            0xC3 => {
+                self.wz = self.immediate16(bus);
+                self.pc = self.wz;
+            }
+            _ if opcode & 0xC7 == 0xC4 => {
+                self.wz = self.immediate16(bus);
+                if self.condition(y) {
+                    self.push(self.pc, bus);
+                    self.pc = self.wz;
+                    cycles += CALL_TAKEN;
+                }
+            }
+            _ if opcode & 0xCF == 0xC5 => {
+                let value = match opcode >> 4 & 3 {
+                    3 => self.pair(Pair::AF),
+                    n => self.pair_sp(n, index),
+                };
+                self.push(value, bus);
+            }
+            _ if opcode & 0xC7 == 0xC7 => {
+                self.push(self.pc, bus);
+                self.pc = u16::from(opcode & 0x38);
+                self.wz = self.pc;
+            }
+            0xC9 => {
+                self.pc = self.pop(bus);
+                self.wz = self.pc;
+            }
+            0xCB if index == Index::HL => {
+                let opcode = self.fetch(bus);
+                cycles += self.execute_cb(opcode, bus);
+            }
+            0xCB => {
+                // The displacement comes before the opcode, which is not counted in R.
+                let address = self.address(index, bus, &mut cycles);
+                let opcode = self.immediate(bus);
+                cycles += self.execute_indexed_cb(opcode, address, bus) - 8;
+            }
+            0xCD => {
+                self.wz = self.immediate16(bus);
+                self.push(self.pc, bus);
+                self.pc = self.wz;
+            }
+            0xD3 => {
+                let port = self.immediate(bus);
+                let a = self.registers[A];
+                bus.output(u16::from_be_bytes([a, port]), a);
+                self.wz = u16::from_be_bytes([a, port.wrapping_add(1)]);
+            }
+            0xD9 => {
+                self.registers[..F].swap_with_slice(&mut self.alternates[..F]);
+            }
+            0xDB => {
+                let port = u16::from_be_bytes([self.registers[A], self.immediate(bus)]);
+                self.registers[A] = bus.input(port);
+                self.wz = port.wrapping_add(1);
+            }
+            // A prefix after another takes its place, the first having cost 4 clock cycles.
+            0xDD => {
+                let opcode = self.fetch(bus);
+                cycles += self.execute(opcode, Index::IX, bus);
+            }
+            0xFD => {
+                let opcode = self.fetch(bus);
+                cycles += self.execute(opcode, Index::IY, bus);
+            }
+            0xED => {
+                let opcode = self.fetch(bus);
+                cycles += self.execute_ed(opcode, bus);
+            }
+            0xE3 => {
+                let value = self.read16(self.sp, bus);
+                self.write16(self.sp, self.index(index), bus);
+                self.set_index(index, value);
+                self.wz = value;
+            }
+            0xE9 => self.pc = self.index(index),
+            0xEB => {
+                let (de, hl) = (self.pair(Pair::DE), self.pair(Pair::HL));
+                self.set_pair(Pair::DE, hl);
+                self.set_pair(Pair::HL, de);
+            }
+            0xF3 => {
+                self.iff1 = false;
+                self.iff2 = false;
+            }
+            0xF9 => self.sp = self.index(index),
+            0xFB => {
+                self.iff1 = true;
+                self.iff2 = true;
+                self.ei_delay = true;
+            }
+            _ => unreachable!("every opcode is decoded"),
+        }
+        cycles
+    }
+
+    /// Executes the opcode following `CB`: the rotations, shifts and bit operations, and returns
+    /// its clock cycles with the prefix's.
+    fn execute_cb(&mut self, opcode: u8, bus: &mut impl Bus) -> u8 {
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        if z == 6 {
+            let address = self.pair(Pair::HL);
+            let value = bus.read(address);
+            if opcode >> 6 == 1 {
+                // The undocumented flags come from the high byte of WZ.
+                self.bit(y, value, (self.wz >> 8) as u8);
+                return 12;
+            }
+            let result = self.bit_operation(opcode, value);
+            bus.write(address, result);
+            return 15;
+        }
+        let value = self.registers[usize::from(z)];
+        if opcode >> 6 == 1 {
+            self.bit(y, value, value);
+        } else {
+            self.registers[usize::from(z)] = self.bit_operation(opcode, value);
+        }
+        8
+    }
+
+    /// Executes the opcode of `DD CB d` or `FD CB d` on the byte at `address`, and returns its
+    /// clock cycles without the first prefix. Except for `BIT`, the result is also written to the
+    /// register the opcode names, which is undocumented.
+    fn execute_indexed_cb(&mut self, opcode: u8, address: u16, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(address);
+        if opcode >> 6 == 1 {
+            self.bit(opcode >> 3 & 7, value, (address >> 8) as u8);
+            return 16;
+        }
+        let result = self.bit_operation(opcode, value);
+        bus.write(address, result);
+        let z = opcode & 7;
+        if z != 6 {
+            self.registers[usize::from(z)] = result;
+        }
+        19
+    }
+
+    /// Runs the rotation, shift, `RES` or `SET` of a `CB` opcode on `value`, and returns the
+    /// result.
+    fn bit_operation(&mut self, opcode: u8, value: u8) -> u8 {
+        let y = opcode >> 3 & 7;
+        match opcode >> 6 {
+            0 => self.shift(y, value),
+            2 => value & !(1 << y),
+            _ => value | 1 << y,
+        }
+    }
+
+    /// Runs the rotation or shift numbered `n` from 0 to 7 on `value`: `RLC`, `RRC`, `RL`, `RR`,
+    /// `SLA`, `SRA`, the undocumented `SLL`, which shifts a 1 in, and `SRL`.
+    fn shift(&mut self, n: u8, value: u8) -> u8 {
+        let carry = self.flag(CARRY) as u8;
+        let (result, out) = match n {
+            0 => (value.rotate_left(1), value >> 7),
+            1 => (value.rotate_right(1), value & 1),
+            2 => (value << 1 | carry, value >> 7),
+            3 => (value >> 1 | carry << 7, value & 1),
+            4 => (value << 1, value >> 7),
+            5 => (value >> 1 | value & 0x80, value & 1),
+            6 => (value << 1 | 1, value >> 7),
+            _ => (value >> 1, value & 1),
+        };
+        self.set_flags(szp(result) | result & UNDOCUMENTED | out);
+        result
+    }
+
+    /// Tests bit `n` of `value` for `BIT`, with the undocumented flags from `undocumented`.
+    fn bit(&mut self, n: u8, value: u8, undocumented: u8) {
+        let bit = value & 1 << n;
+        let mut flags = self.registers[F] & CARRY | HALF_CARRY | bit & SIGN;
+        if bit == 0 {
+            flags |= ZERO | PARITY;
+        }
+        self.set_flags(flags | undocumented & UNDOCUMENTED);
+    }
+
+    /// Executes the opcode following `ED`, and returns its clock cycles with the prefix's.
+    /// Opcodes Zilog left out run as two `NOP`s.
+    fn execute_ed(&mut self, opcode: u8, bus: &mut impl Bus) -> u8 {
+        let y = opcode >> 3 & 7;
+        let z = opcode & 7;
+        match opcode {
+            0x40..=0x7F => match z {
+                0 => {
+                    // IN F, (C) only sets the flags.
+                    let bc = self.pair(Pair::BC);
+                    let value = bus.input(bc);
+                    if y != 6 {
+                        self.registers[usize::from(y)] = value;
+                    }
+                    let kept = self.registers[F] & CARRY;
+                    self.set_flags(kept | szp(value) | value & UNDOCUMENTED);
+                    self.wz = bc.wrapping_add(1);
+                    12
+                }
+                1 => {
+                    // OUT (C), 0 in place of (HL).
+                    let bc = self.pair(Pair::BC);
+                    let value = if y == 6 {
+                        0
+                    } else {
+                        self.registers[usize::from(y)]
+                    };
+                    bus.output(bc, value);
+                    self.wz = bc.wrapping_add(1);
+                    12
+                }
+                2 => {
+                    let (hl, value) = (self.pair(Pair::HL), self.pair_sp(y >> 1, Index::HL));
+                    let result = if y & 1 == 0 {
+                        self.subtract16(hl, value)
+                    } else {
+                        self.add16_carry(hl, value)
+                    };
+                    self.set_pair(Pair::HL, result);
+                    15
+                }
+                3 => {
+                    let address = self.immediate16(bus);
+                    if y & 1 == 0 {
+                        let value = self.pair_sp(y >> 1, Index::HL);
+                        self.write16(address, value, bus);
+                    } else {
+                        let value = self.read16(address, bus);
+                        self.set_pair_sp(y >> 1, value, Index::HL);
+                    }
+                    self.wz = address.wrapping_add(1);
+                    20
+                }
+                4 => {
+                    self.registers[A] = self.subtract(0, self.registers[A], false);
+                    8
+                }
+                5 => {
+                    // RETI and RETN, which both restore IFF1.
+                    self.iff1 = self.iff2;
+                    self.pc = self.pop(bus);
+                    self.wz = self.pc;
+                    14
+                }
+                6 => {
+                    self.im = [0, 0, 1, 2][usize::from(y & 3)];
+                    8
+                }
+                _ => match y {
+                    0 => {
+                        self.i = self.registers[A];
+                        9
+                    }
+                    1 => {
+                        self.r = self.registers[A];
+                        9
+                    }
+                    2 | 3 => {
+                        let value = if y == 2 { self.i } else { self.r };
+                        self.registers[A] = value;
+                        let mut flags = self.registers[F] & CARRY | sz(value);
+                        if self.iff2 {
+                            flags |= PARITY;
+                        }
+                        self.set_flags(flags | value & UNDOCUMENTED);
+                        9
+                    }
+                    4 | 5 => {
+                        self.rotate_digit(y == 5, bus);
+                        18
+                    }
+                    _ => 8,
+                },
+            },
+            0xA0..=0xBF if z < 4 => self.block(opcode, bus),
+            _ => 8,
+        }
+    }
+
+    /// Runs `RRD`, or `RLD` if `left`, rotating the digits of `A` and `(HL)` through each other.
+    fn rotate_digit(&mut self, left: bool, bus: &mut impl Bus) {
+        let address = self.pair(Pair::HL);
+        let value = bus.read(address);
+        let a = self.registers[A];
+        let (result, digit) = if left {
+            (value << 4 | a & 0x0F, value >> 4)
+        } else {
+            (a << 4 | value >> 4, value & 0x0F)
+        };
+        bus.write(address, result);
+        let a = a & 0xF0 | digit;
+        self.registers[A] = a;
+        let kept = self.registers[F] & CARRY;
+        self.set_flags(kept | szp(a) | a & UNDOCUMENTED);
+        self.wz = address.wrapping_add(1);
+    }
+
+    /// Runs a block instruction, from `ED A0` to `ED BB`: one step of a move, a comparison, an
+    /// input or an output, going up or down and repeating until `BC`, or `B`, runs out. A
+    /// repeating instruction moves the program counter back to itself, so that interrupts can be
+    /// taken between its steps.
+    fn block(&mut self, opcode: u8, bus: &mut impl Bus) -> u8 {
+        let step = if opcode & 0x08 == 0 { 1 } else { 0xFFFF };
+        let repeat = opcode & 0x10 != 0;
+        let hl = self.pair(Pair::HL);
+        let a = self.registers[A];
+        let again = match opcode & 3 {
+            0 => {
+                let value = bus.read(hl);
+                let de = self.pair(Pair::DE);
+                bus.write(de, value);
+                self.set_pair(Pair::DE, de.wrapping_add(step));
+                self.set_pair(Pair::HL, hl.wrapping_add(step));
+                let bc = self.pair(Pair::BC).wrapping_sub(1);
+                self.set_pair(Pair::BC, bc);
+                // The undocumented flags are bits 1 and 3 of the byte plus A.
+                let n = value.wrapping_add(a);
+                let mut flags =
+                    self.registers[F] & (SIGN | ZERO | CARRY) | n & BIT3 | n << 4 & BIT5;
+                if bc != 0 {
+                    flags |= PARITY;
+                }
+                self.set_flags(flags);
+                bc != 0
+            }
+            1 => {
+                let value = bus.read(hl);
+                let result = a.wrapping_sub(value);
+                self.set_pair(Pair::HL, hl.wrapping_add(step));
+                let bc = self.pair(Pair::BC).wrapping_sub(1);
+                self.set_pair(Pair::BC, bc);
+                self.wz = self.wz.wrapping_add(step);
+                let half = (a ^ value ^ result) & HALF_CARRY;
+                // The undocumented flags are bits 1 and 3 of the difference less the half carry.
+                let n = result.wrapping_sub(half >> 4);
+                let mut flags = self.registers[F] & CARRY | SUBTRACT | sz(result) | half;
+                flags |= n & BIT3 | n << 4 & BIT5;
+                if bc != 0 {
+                    flags |= PARITY;
+                }
+                self.set_flags(flags);
+                bc != 0 && result != 0
+            }
+            n => {
+                let (value, k) = if n == 2 {
+                    let bc = self.pair(Pair::BC);
+                    let value = bus.input(bc);
+                    bus.write(hl, value);
+                    self.wz = bc.wrapping_add(step);
+                    self.registers[0] = self.registers[0].wrapping_sub(1);
+                    let c = self.registers[1].wrapping_add(step as u8);
+                    (value, u16::from(value) + u16::from(c))
+                } else {
+                    let value = bus.read(hl);
+                    self.registers[0] = self.registers[0].wrapping_sub(1);
+                    let bc = self.pair(Pair::BC);
+                    self.wz = bc.wrapping_add(step);
+                    bus.output(bc, value);
+                    let l = hl.wrapping_add(step) as u8;
+                    (value, u16::from(value) + u16::from(l))
+                };
+                self.set_pair(Pair::HL, hl.wrapping_add(step));
+                let b = self.registers[0];
+                let mut flags = sz(b) | b & UNDOCUMENTED | (value >> 6 & SUBTRACT);
+                if k > 0xFF {
+                    flags |= HALF_CARRY | CARRY;
+                }
+                flags |= szp((k as u8 & 7) ^ b) & PARITY;
+                self.set_flags(flags);
+                b != 0
+            }
+        };
+        if repeat && again {
+            self.pc = self.pc.wrapping_sub(2);
+            self.wz = self.pc.wrapping_add(1);
+            16 + REPEAT
+        } else {
+            16
+        }
+    }
+
+    /// Adds `a`, `b` and `carry`, setting every flag, and returns the sum.
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let sum = u16::from(a) + u16::from(b) + u16::from(carry);
+        let result = sum as u8;
+        let mut flags = sz(result) | result & UNDOCUMENTED | (a ^ b ^ result) & HALF_CARRY;
+        if (a ^ result) & (b ^ result) & 0x80 != 0 {
+            flags |= PARITY;
+        }
+        if sum > 0xFF {
+            flags |= CARRY;
+        }
+        self.set_flags(flags);
+        result
+    }
+
+    /// Subtracts `b` and `borrow` from `a`, setting every flag, and returns the difference.
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) -> u8 {
+        let result = a.wrapping_sub(b).wrapping_sub(borrow as u8);
+        let mut flags = sz(result) | result & UNDOCUMENTED | (a ^ b ^ result) & HALF_CARRY;
+        flags |= SUBTRACT;
+        if (a ^ b) & (a ^ result) & 0x80 != 0 {
+            flags |= PARITY;
+        }
+        if u16::from(a) < u16::from(b) + u16::from(borrow) {
+            flags |= CARRY;
+        }
+        self.set_flags(flags);
+        result
+    }
+
+    /// Runs the arithmetic or logic operation numbered `n` from 0 to 7 on the accumulator and
+    /// `value`: `ADD`, `ADC`, `SUB`, `SBC`, `AND`, `XOR`, `OR` and `CP`.
+    fn alu(&mut self, n: u8, value: u8) {
+        let a = self.registers[A];
+        let carry = self.flag(CARRY);
+        self.registers[A] = match n {
+            0 => self.add(a, value, false),
+            1 => self.add(a, value, carry),
+            2 => self.subtract(a, value, false),
+            3 => self.subtract(a, value, carry),
+            7 => {
+                self.subtract(a, value, false);
+                // CP takes the undocumented flags from the operand.
+                let flags = self.registers[F] & !UNDOCUMENTED | value & UNDOCUMENTED;
+                self.set_flags(flags);
+                a
+            }
+            _ => {
+                let result = match n {
+                    4 => a & value,
+                    5 => a ^ value,
+                    _ => a | value,
+                };
+                let half = if n == 4 { HALF_CARRY } else { 0 };
+                self.set_flags(szp(result) | result & UNDOCUMENTED | half);
+                result
+            }
+        };
+    }
+
+    /// Adds 1 to `value` for `INC`, setting every flag but the carry.
+    fn increment(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        let mut flags = self.registers[F] & CARRY | sz(result) | result & UNDOCUMENTED;
+        if result & 0x0F == 0 {
+            flags |= HALF_CARRY;
+        }
+        if result == 0x80 {
+            flags |= PARITY;
+        }
+        self.set_flags(flags);
+        result
+    }
+
+    /// Subtracts 1 from `value` for `DEC`, setting every flag but the carry.
+    fn decrement(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        let mut flags = self.registers[F] & CARRY | sz(result) | result & UNDOCUMENTED | SUBTRACT;
+        if value & 0x0F == 0 {
+            flags |= HALF_CARRY;
+        }
+        if result == 0x7F {
+            flags |= PARITY;
+        }
+        self.set_flags(flags);
+        result
+    }
+
+    /// Adds `b` to `a` for `ADD HL, rr`, which leaves the sign, zero and parity flags, and
+    /// returns the sum.
+    fn add16(&mut self, a: u16, b: u16) -> u16 {
+        let sum = u32::from(a) + u32::from(b);
+        let result = sum as u16;
+        let mut flags = self.registers[F] & (SIGN | ZERO | PARITY);
+        flags |= (result >> 8) as u8 & UNDOCUMENTED | ((a ^ b ^ result) >> 8) as u8 & HALF_CARRY;
+        if sum > 0xFFFF {
+            flags |= CARRY;
+        }
+        self.set_flags(flags);
+        self.wz = a.wrapping_add(1);
+        result
+    }
+
+    /// Adds `b` and the carry to `a` for `ADC HL, rr`, setting every flag, and returns the sum.
+    fn add16_carry(&mut self, a: u16, b: u16) -> u16 {
+        let sum = u32::from(a) + u32::from(b) + u32::from(self.flag(CARRY));
+        let result = sum as u16;
+        let mut flags = sz16(result) | ((a ^ b ^ result) >> 8) as u8 & HALF_CARRY;
+        if (a ^ result) & (b ^ result) & 0x8000 != 0 {
+            flags |= PARITY;
+        }
+        if sum > 0xFFFF {
+            flags |= CARRY;
+        }
+        self.set_flags(flags);
+        self.wz = a.wrapping_add(1);
+        result
+    }
+
+    /// Subtracts `b` and the carry from `a` for `SBC HL, rr`, setting every flag, and returns
+    /// the difference.
+    fn subtract16(&mut self, a: u16, b: u16) -> u16 {
+        let borrow = self.flag(CARRY);
+        let result = a.wrapping_sub(b).wrapping_sub(u16::from(borrow));
+        let mut flags = sz16(result) | ((a ^ b ^ result) >> 8) as u8 & HALF_CARRY | SUBTRACT;
+        if (a ^ b) & (a ^ result) & 0x8000 != 0 {
+            flags |= PARITY;
+        }
+        if u32::from(a) < u32::from(b) + u32::from(borrow) {
+            flags |= CARRY;
+        }
+        self.set_flags(flags);
+        self.wz = a.wrapping_add(1);
+        result
+    }
+
+    /// Adjusts the accumulator to two decimal digits after adding or subtracting two of them.
+    fn daa(&mut self) {
+        let a = self.registers[A];
+        let f = self.registers[F];
+        let mut correction = 0;
+        let mut carry = f & CARRY;
+        if f & HALF_CARRY != 0 || a & 0x0F > 9 {
+            correction |= 0x06;
+        }
+        if carry != 0 || a > 0x99 {
+            correction |= 0x60;
+            carry = CARRY;
+        }
+        let (result, half) = if f & SUBTRACT != 0 {
+            (
+                a.wrapping_sub(correction),
+                f & HALF_CARRY != 0 && a & 0x0F < 6,
+            )
+        } else {
+            (a.wrapping_add(correction), a & 0x0F > 9)
+        };
+        self.registers[A] = result;
+        let mut flags = f & SUBTRACT | szp(result) | result & UNDOCUMENTED | carry;
+        if half {
+            flags |= HALF_CARRY;
+        }
+        self.set_flags(flags);
+    }
+
+    /// Writes the whole processor, for save states.
+    pub fn save(&self) -> [u8; STATE_SIZE] {
+        let mut state = [0; STATE_SIZE];
+        state[..8].copy_from_slice(&self.registers);
+        state[8..16].copy_from_slice(&self.alternates);
+        state[16..18].copy_from_slice(&self.ix.to_be_bytes());
+        state[18..20].copy_from_slice(&self.iy.to_be_bytes());
+        state[20..22].copy_from_slice(&self.sp.to_be_bytes());
+        state[22..24].copy_from_slice(&self.pc.to_be_bytes());
+        state[24] = self.i;
+        state[25] = self.r;
+        state[26..28].copy_from_slice(&self.wz.to_be_bytes());
+        state[28] = self.iff1 as u8
+            | (self.iff2 as u8) << 1
+            | (self.ei_delay as u8) << 2
+            | (self.halted as u8) << 3;
+        state[29] = self.im;
+        state[30] = self.q;
+        state[31..].copy_from_slice(&self.cycles.to_be_bytes());
+        state
+    }
+
+    /// Restores the processor from a state written by [`Cpu::save`].
+    pub fn restore(&mut self, state: &[u8; STATE_SIZE]) {
+        self.registers.copy_from_slice(&state[..8]);
+        self.alternates.copy_from_slice(&state[8..16]);
+        self.ix = u16::from_be_bytes([state[16], state[17]]);
+        self.iy = u16::from_be_bytes([state[18], state[19]]);
+        self.sp = u16::from_be_bytes([state[20], state[21]]);
+        self.pc = u16::from_be_bytes([state[22], state[23]]);
+        self.i = state[24];
+        self.r = state[25];
+        self.wz = u16::from_be_bytes([state[26], state[27]]);
+        let flags = state[28];
+        self.iff1 = flags & 1 != 0;
+        self.iff2 = flags & 2 != 0;
+        self.ei_delay = flags & 4 != 0;
+        self.halted = flags & 8 != 0;
+        self.im = state[29].min(2);
+        self.q = state[30];
+        let mut cycles = [0; 8];
+        cycles.copy_from_slice(&state[31..]);
+        self.cycles = u64::from_be_bytes(cycles);
+    }
+}
+
+/// Returns the sign and zero flags of `result`.
+fn sz(result: u8) -> u8 {
+    let zero = if result == 0 { ZERO } else { 0 };
+    result & SIGN | zero
+}
+
+/// Returns the sign, zero and parity flags of `result`.
+fn szp(result: u8) -> u8 {
+    let parity = if result.count_ones() & 1 == 0 {
+        PARITY
+    } else {
+        0
+    };
+    sz(result) | parity
+}
+
+/// Returns the sign, zero and undocumented flags of a 16-bit `result`, from its high byte.
+fn sz16(result: u16) -> u8 {
+    let zero = if result == 0 { ZERO } else { 0 };
+    (result >> 8) as u8 & (SIGN | UNDOCUMENTED) | zero
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Memory;
+
+    /// Memory, with devices driven by the test.
+    struct Machine {
+        memory: Memory,
+        /// The inputs, by the low byte of their port.
+        inputs: [u8; 256],
+        /// The last port written and its value.
+        output: Option<(u16, u8)>,
+        interrupt: Option<u8>,
+        nmi: bool,
+    }
+
+    impl Machine {
+        fn new(program: &[u8]) -> Self {
+            Machine {
+                memory: Memory::with_program(0, program).unwrap(),
+                inputs: [0; 256],
+                output: None,
+                interrupt: None,
+                nmi: false,
+            }
+        }
+    }
+
+    impl Bus for Machine {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory.read(address)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory.write(address, value);
+        }
+
+        fn input(&mut self, port: u16) -> u8 {
+            self.inputs[usize::from(port & 0xFF)]
+        }
+
+        fn output(&mut self, port: u16, value: u8) {
+            self.output = Some((port, value));
+        }
+
+        fn interrupt(&mut self) -> Option<u8> {
+            self.interrupt.take()
+        }
+
+        fn nmi(&mut self) -> bool {
+            core::mem::take(&mut self.nmi)
+        }
+    }
+
+    /// Runs the processor until it halts, and returns it.
+    fn run(machine: &mut Machine) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::SP, 0x8000);
+        while !cpu.halted() {
+            cpu.step(machine);
+            assert!(cpu.cycles() < 1_000_000, "the program never halts");
+        }
+        cpu
+    }
+
+    /// Runs `opcode` with the accumulator `a`, the operand `value` in `B` and the flags `flags`,
+    /// and returns the accumulator and the flags.
+    fn alu(opcode: u8, a: u8, value: u8, flags: u8) -> (u8, u8) {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::with_program(0, &[opcode]).unwrap();
+        cpu.set_register(Register::B, value);
+        cpu.set_pair(Pair::AF, u16::from_be_bytes([a, flags]));
+        cpu.step(&mut memory);
+        (cpu.register(Register::A), cpu.flags())
+    }
+
+    /// Returns the flags a result sets, other than the carries and overflow.
+    fn sz53(result: u8) -> u8 {
+        let mut flags = result & (SIGN | BIT5 | BIT3);
+        if result == 0 {
+            flags |= ZERO;
+        }
+        flags
+    }
+
+    #[test]
+    fn arithmetic() {
+        for a in 0..=255u8 {
+            for value in 0..=255u8 {
+                for &carry in &[false, true] {
+                    // ADC A, B and SBC A, B.
+                    let sum = i16::from(a as i8) + i16::from(value as i8) + i16::from(carry);
+                    let unsigned = u16::from(a) + u16::from(value) + u16::from(carry);
+                    let mut flags = sz53(unsigned as u8);
+                    if unsigned > 0xFF {
+                        flags |= CARRY;
+                    }
+                    if (a & 0xF) + (value & 0xF) + carry as u8 > 0xF {
+                        flags |= HALF_CARRY;
+                    }
+                    if !(-128..=127).contains(&sum) {
+                        flags |= PARITY;
+                    }
+                    assert_eq!(alu(0x88, a, value, carry as u8), (unsigned as u8, flags));
+
+                    let difference = i16::from(a as i8) - i16::from(value as i8) - i16::from(carry);
+                    let unsigned = i16::from(a) - i16::from(value) - i16::from(carry);
+                    let mut flags = sz53(unsigned as u8) | SUBTRACT;
+                    if unsigned < 0 {
+                        flags |= CARRY;
+                    }
+                    if (a & 0xF) < (value & 0xF) + carry as u8 {
+                        flags |= HALF_CARRY;
+                    }
+                    if !(-128..=127).contains(&difference) {
+                        flags |= PARITY;
+                    }
+                    assert_eq!(alu(0x98, a, value, carry as u8), (unsigned as u8, flags));
+                    if !carry {
+                        // CP B sets the flags of SUB B, but bits 5 and 3 of the operand.
+                        let flags = flags & !(BIT5 | BIT3) | value & (BIT5 | BIT3);
+                        assert_eq!(alu(0xB8, a, value, 0), (a, flags));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decimal() {
+        let bcd = |n: u8| ((n / 10) << 4) | (n % 10);
+        for x in 0..100u8 {
+            for y in 0..100u8 {
+                // LD A, x; ADD A, y; DAA; HALT
+                let mut machine = Machine::new(&[0x3E, bcd(x), 0xC6, bcd(y), 0x27, 0x76]);
+                let cpu = run(&mut machine);
+                let sum = x + y;
+                assert_eq!(cpu.register(Register::A), bcd(sum % 100), "{} + {}", x, y);
+                assert_eq!(cpu.flags() & CARRY != 0, sum >= 100, "{} + {}", x, y);
+
+                // LD A, x; SUB y; DAA; HALT
+                let mut machine = Machine::new(&[0x3E, bcd(x), 0xD6, bcd(y), 0x27, 0x76]);
+                let cpu = run(&mut machine);
+                let difference = (100 + x - y) % 100;
+                assert_eq!(cpu.register(Register::A), bcd(difference), "{} - {}", x, y);
+                assert_eq!(cpu.flags() & CARRY != 0, x < y, "{} - {}", x, y);
+                assert_ne!(cpu.flags() & SUBTRACT, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn sixteen_bits() {
+        let mut machine = Machine::new(&[
+            0x21, 0xFF, 0x7F, // LD HL, 7FFF
+            0x11, 0x01, 0x00, // LD DE, 0001
+            0xB7, // OR A, clearing the carry
+            0xED, 0x5A, // ADC HL, DE
+            0x76, // HALT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.pair(Pair::HL), 0x8000);
+        assert_eq!(cpu.flags(), SIGN | HALF_CARRY | PARITY);
+
+        let mut machine = Machine::new(&[
+            0x21, 0x00, 0x00, // LD HL, 0000
+            0x11, 0x01, 0x00, // LD DE, 0001
+            0x37, // SCF
+            0xED, 0x52, // SBC HL, DE
+            0x76, // HALT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.pair(Pair::HL), 0xFFFE);
+        assert_eq!(
+            cpu.flags(),
+            SIGN | BIT5 | HALF_CARRY | BIT3 | SUBTRACT | CARRY
+        );
+
+        // ADD HL, BC keeps the sign, zero and parity flags.
+        let mut machine = Machine::new(&[
+            0xAF, // XOR A, setting zero and parity
+            0x21, 0x00, 0xF0, // LD HL, F000
+            0x01, 0x00, 0x18, // LD BC, 1800
+            0x09, // ADD HL, BC
+            0x76, // HALT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.pair(Pair::HL), 0x0800);
+        assert_eq!(cpu.flags(), ZERO | BIT3 | PARITY | CARRY);
+    }
+
+    #[test]
+    fn bits_and_shifts() {
+        let mut machine = Machine::new(&[
+            0x06, 0x81, // LD B, 81
+            0xCB, 0x00, // RLC B
+            0x0E, 0x40, // LD C, 40
+            0xCB, 0x31, // SLL C
+            0x3E, 0x80, // LD A, 80
+            0xCB, 0x2F, // SRA A
+            0xCB, 0xF2, // SET 6, D
+            0xCB, 0x97, // RES 2, A
+            0x76, // HALT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.register(Register::B), 0x03);
+        assert_eq!(cpu.register(Register::C), 0x81);
+        assert_eq!(cpu.register(Register::A), 0xC0);
+        assert_eq!(cpu.register(Register::D), 0x40);
+        // RES left the flags of SRA, which shifted a 0 out.
+        assert_eq!(cpu.flags(), SIGN | PARITY);
+
+        // BIT 7, A and BIT 0, A set zero and parity together, and the sign only for bit 7.
+        let mut memory = Memory::with_program(0, &[0xCB, 0x7F, 0xCB, 0x47]).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::AF, 0xA801);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.flags(), SIGN | BIT5 | HALF_CARRY | BIT3 | CARRY);
+        cpu.step(&mut memory);
+        assert_eq!(
+            cpu.flags(),
+            ZERO | BIT5 | HALF_CARRY | BIT3 | PARITY | CARRY
+        );
+
+        // SCF takes bits 5 and 3 of A, with those of the flags when the instruction before it
+        // left them.
+        assert_eq!(alu(0x37, 0x00, 0, BIT5 | BIT3).1, BIT5 | BIT3 | CARRY);
+        let mut memory = Memory::with_program(0, &[0xAF, 0x37, 0x3F]).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::AF, 0x28FF);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.flags(), ZERO | PARITY | CARRY);
+        cpu.step(&mut memory);
+        assert_eq!(cpu.flags(), ZERO | HALF_CARRY | PARITY);
+    }
+
+    #[test]
+    fn index_registers() {
+        let mut machine = Machine::new(&[
+            0xDD, 0x21, 0x00, 0x20, // LD IX, 2000
+            0xFD, 0x21, 0x10, 0x20, // LD IY, 2010
+            0xDD, 0x36, 0x05, 0x2A, // LD (IX+05), 2A
+            0xDD, 0x34, 0x05, // INC (IX+05)
+            0xFD, 0x7E, 0xF5, // LD A, (IY-0B)
+            0xDD, 0x26, 0x12, // LD IXH, 12
+            0xDD, 0x6C, // LD IXL, IXH
+            0xDD, 0x84, // ADD A, IXH
+            0x21, 0x00, 0x30, // LD HL, 3000
+            0xFD, 0x66, 0xF5, // LD H, (IY-0B)
+            0xFD, 0xCB, 0xF5, 0x00, // RLC (IY-0B), B
+            0xFD, 0xE5, // PUSH IY
+            0xDD, 0xE1, // POP IX
+            0x76, // HALT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(machine.memory.0[0x2005], 0x56);
+        assert_eq!(cpu.register(Register::A), 0x2B + 0x12);
+        assert_eq!(cpu.register(Register::H), 0x2B);
+        assert_eq!(cpu.register(Register::L), 0x00);
+        assert_eq!(cpu.register(Register::B), 0x56);
+        assert_eq!(cpu.pair(Pair::IX), 0x2010);
+        assert_eq!(cpu.pair(Pair::IY), 0x2010);
+        let cycles = 14 + 14 + 19 + 23 + 19 + 11 + 8 + 8 + 10 + 19 + 23 + 15 + 14 + 4;
+        assert_eq!(cpu.cycles(), cycles);
+        // Every prefix counts in R, but not the opcode after the displacement.
+        assert_eq!(cpu.register(Register::R), 26);
+    }
+
+    #[test]
+    fn exchanges() {
+        let mut machine = Machine::new(&[
+            0x01, 0x34, 0x12, // LD BC, 1234
+            0x3E, 0x56, // LD A, 56
+            0x08, // EX AF, AF'
+            0xD9, // EXX
+            0x01, 0x78, 0x56, // LD BC, 5678
+            0x21, 0x11, 0x11, // LD HL, 1111
+            0x11, 0x22, 0x22, // LD DE, 2222
+            0xEB, // EX DE, HL
+            0xE5, // PUSH HL
+            0xDD, 0x21, 0x33, 0x33, // LD IX, 3333
+            0xDD, 0xE3, // EX (SP), IX
+            0x76, // HALT
+        ]);
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.pair(Pair::BC), 0x5678);
+        assert_eq!(cpu.alternate(Pair::BC), 0x1234);
+        assert_eq!(cpu.alternate(Pair::AF) >> 8, 0x56);
+        assert_eq!(cpu.register(Register::A), 0x00);
+        assert_eq!(cpu.pair(Pair::DE), 0x1111);
+        assert_eq!(cpu.pair(Pair::IX), 0x2222);
+        assert_eq!(machine.memory.0[0x7FFE..0x8000], [0x33, 0x33]);
+    }
+
+    #[test]
+    fn block_instructions() {
+        let mut machine = Machine::new(&[
+            0x21, 0x00, 0x10, // LD HL, 1000
+            0x11, 0x00, 0x20, // LD DE, 2000
+            0x01, 0x04, 0x00, // LD BC, 0004
+            0xED, 0xB0, // LDIR
+            0x21, 0x00, 0x10, // LD HL, 1000
+            0x01, 0x10, 0x00, // LD BC, 0010
+            0x3E, 0x33, // LD A, 33
+            0xED, 0xB1, // CPIR
+            0x76, // HALT
+        ]);
+        machine.memory.0[0x1000..0x1004].copy_from_slice(&[0x11, 0x22, 0x33, 0x44]);
+        let cpu = run(&mut machine);
+        assert_eq!(
+            machine.memory.0[0x2000..0x2005],
+            [0x11, 0x22, 0x33, 0x44, 0x00]
+        );
+        assert_eq!(cpu.pair(Pair::DE), 0x2004);
+        // CPIR stopped after the match, with 13 to go.
+        assert_eq!(cpu.pair(Pair::HL), 0x1003);
+        assert_eq!(cpu.pair(Pair::BC), 0x000D);
+        assert_eq!(
+            cpu.flags() & (ZERO | PARITY | SUBTRACT),
+            ZERO | PARITY | SUBTRACT
+        );
+        let cycles = 10 * 3 + 3 * 21 + 16 + 10 + 10 + 7 + 2 * 21 + 16 + 4;
+        assert_eq!(cpu.cycles(), cycles);
+    }
+
+    #[test]
+    fn input_and_output() {
+        let mut machine = Machine::new(&[
+            0x3E, 0x12, // LD A, 12
+            0xDB, 0x07, // IN A, (07)
+            0xD3, 0xFE, // OUT (FE), A
+            0x01, 0x07, 0x02, // LD BC, 0207
+            0xED, 0x50, // IN D, (C)
+            0x21, 0x00, 0x30, // LD HL, 3000
+            0xED, 0xB2, // INIR
+            0x76, // HALT
+        ]);
+        machine.inputs[7] = 0x5A;
+        let cpu = run(&mut machine);
+        assert_eq!(cpu.register(Register::A), 0x5A);
+        assert_eq!(cpu.register(Register::D), 0x5A);
+        assert_eq!(machine.memory.0[0x3000..0x3003], [0x5A, 0x5A, 0x00]);
+        assert_eq!(cpu.pair(Pair::BC), 0x0007);
+        assert_ne!(cpu.flags() & ZERO, 0);
+        // A was on the high byte of the port.
+        assert_eq!(machine.output, Some((0x5AFE, 0x5A)));
+    }
+
+    #[test]
+    fn calls_and_jumps() {
+        let mut machine = Machine::new(&[
+            0xCD, 0x20, 0x00, // 0x00: CALL 0020
+            0xAF, // XOR A, setting zero
+            0xC4, 0x20, 0x00, // CALL NZ, 0020, not taken
+            0xCC, 0x18, 0x00, // CALL Z, 0018
+            0x18, 0x02, // JR $+04
+            0x76, 0x76, // HALT, jumped over
+            0x10, 0xFE, // 0x0E: DJNZ $+00, until B is 0
+            0x76, // HALT
+        ]);
+        // 0x18: INC C, clearing zero; RET Z, not taken; INC C; RET
+        machine.memory.0[0x18..0x1C].copy_from_slice(&[0x0C, 0xC8, 0x0C, 0xC9]);
+        // 0x20: INC C; RET
+        machine.memory.0[0x20..0x22].copy_from_slice(&[0x0C, 0xC9]);
+        let mut cpu = Cpu::new();
+        cpu.set_register(Register::B, 3);
+        cpu.set_pair(Pair::SP, 0x8000);
+        while !cpu.halted() {
+            cpu.step(&mut machine);
+        }
+        assert_eq!(cpu.register(Register::C), 3);
+        assert_eq!(cpu.register(Register::B), 0);
+        assert_eq!(cpu.program_counter(), 0x11);
+        assert_eq!(cpu.pair(Pair::SP), 0x8000);
+        // CALL, INC, RET; XOR; CALL NZ; CALL Z, INC, RET Z, INC, RET; JR; DJNZ taken twice and
+        // once not; HALT
+        let cycles = 17 + 4 + 10 + 4 + 10 + 17 + 4 + 5 + 4 + 10 + 12 + 13 * 2 + 8 + 4;
+        assert_eq!(cpu.cycles(), cycles);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut machine = Machine::new(&[
+            0xED, 0x56, // 0x00: IM 1
+            0xFB, // EI
+            0x04, // INC B, before which no interrupt is taken
+            0xED, 0x5E, // 0x04: IM 2
+            0x76, // HALT
+            0xF3, // 0x07: DI
+            0x76, // HALT, with interrupts off
+        ]);
+        // 0x38: INC C; EI; RET
+        machine.memory.0[0x38..0x3B].copy_from_slice(&[0x0C, 0xFB, 0xC9]);
+        // The vector at 0x1240 points to 0x0040: INC D; EI; RETI
+        machine.memory.0[0x1240..0x1242].copy_from_slice(&[0x40, 0x00]);
+        machine.memory.0[0x40..0x44].copy_from_slice(&[0x14, 0xFB, 0xED, 0x4D]);
+        // 0x66: INC E; RETN
+        machine.memory.0[0x66..0x69].copy_from_slice(&[0x1C, 0xED, 0x45]);
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::SP, 0x8000);
+        cpu.set_register(Register::I, 0x12);
+
+        // Not taken while interrupts are off, nor right after EI.
+        machine.interrupt = Some(0xFF);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert!(cpu.iff1() && cpu.iff2());
+        assert_eq!(cpu.interrupt_mode(), 1);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.register(Register::B), 1);
+        assert_eq!(machine.interrupt, Some(0xFF));
+        // Mode 1 calls 0x0038 whatever is on the bus.
+        assert_eq!(cpu.step(&mut machine), 13);
+        assert!(!cpu.iff1() && !cpu.iff2());
+        assert_eq!(cpu.program_counter(), 0x38);
+        while cpu.program_counter() != 0x04 {
+            cpu.step(&mut machine);
+        }
+        assert_eq!(cpu.register(Register::C), 1);
+
+        // HALT waits for an interrupt, which continues after it, here through the vector of
+        // mode 2.
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert!(cpu.halted());
+        assert_eq!(cpu.step(&mut machine), 4);
+        machine.interrupt = Some(0x40);
+        assert_eq!(cpu.step(&mut machine), 19);
+        assert!(!cpu.halted());
+        assert_eq!(cpu.program_counter(), 0x40);
+        while cpu.program_counter() != 0x07 {
+            cpu.step(&mut machine);
+        }
+        assert_eq!(cpu.register(Register::D), 1);
+
+        // The non-maskable interrupt is taken with interrupts off, and RETN turns them back on
+        // as they were.
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert!(cpu.halted());
+        machine.interrupt = Some(0x40);
+        cpu.step(&mut machine);
+        assert!(cpu.halted());
+        machine.nmi = true;
+        assert_eq!(cpu.step(&mut machine), 11);
+        assert_eq!(cpu.program_counter(), 0x66);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.register(Register::E), 1);
+        assert_eq!(cpu.program_counter(), 0x09);
+        assert!(!cpu.iff1());
+        assert_eq!(cpu.pair(Pair::SP), 0x8000);
+    }
+
+    #[test]
+    fn interrupt_mode_0() {
+        // EI; NOP; HALT, interrupted by RST 18: LD A, I; RET
+        let mut machine = Machine::new(&[0xFB, 0x00, 0x76]);
+        machine.memory.0[0x18..0x1B].copy_from_slice(&[0xED, 0x57, 0xC9]);
+        let mut cpu = Cpu::new();
+        cpu.set_pair(Pair::SP, 0x8000);
+        cpu.set_register(Register::I, 0x80);
+        cpu.step(&mut machine);
+        cpu.step(&mut machine);
+        machine.interrupt = Some(0xDF);
+        assert_eq!(cpu.step(&mut machine), 13);
+        assert_eq!(cpu.program_counter(), 0x18);
+        cpu.step(&mut machine);
+        // LD A, I copies IFF2 into the parity flag.
+        assert_eq!(cpu.register(Register::A), 0x80);
+        assert_eq!(cpu.flags() & (SIGN | PARITY), SIGN);
+        cpu.step(&mut machine);
+        assert_eq!(cpu.program_counter(), 0x02);
+    }
+
+    #[test]
+    fn resets_and_saves() {
+        // LD A, 2A; LD SP, 1234; EXX; IM 2; EI; HALT
+        let mut machine =
+            Machine::new(&[0x3E, 0x2A, 0x31, 0x34, 0x12, 0xD9, 0xED, 0x5E, 0xFB, 0x76]);
+        let cpu = run(&mut machine);
+        let state = cpu.save();
+        let mut restored = Cpu::new();
+        restored.restore(&state);
+        assert_eq!(restored, cpu);
+        assert_eq!(restored.save(), state);
+
+        restored.reset();
+        assert_eq!(restored.program_counter(), 0);
+        assert!(!restored.iff1());
+        assert!(!restored.halted());
+        assert_eq!(restored.interrupt_mode(), 0);
+        assert_eq!(restored.register(Register::R), 0);
+        assert_eq!(restored.register(Register::A), 0x2A);
+        assert_eq!(restored.pair(Pair::SP), 0x1234);
+    }
+}
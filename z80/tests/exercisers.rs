@@ -0,0 +1,72 @@
+//! Runs Frank Cringle's instruction exercisers, ZEXDOC and ZEXALL, as CP/M programs. They run
+//! every instruction on many operands and compare a checksum of the results with that of a real
+//! Z80: ZEXDOC the documented flags only, and ZEXALL the undocumented ones too.
+//!
+//! The programs are not part of this repository, so the test is ignored unless asked for, and then
+//! needs a directory holding them as `.COM` files. Each runs for billions of instructions, so build
+//! the test with optimizations:
+//!
+//! ```text
+//! Z80_EXERCISERS=roms/z80 cargo test -p z80 --release --test exercisers -- --ignored --nocapture
+//! ```
+
+use cpm::Machine;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use z80::Cpu;
+
+/// The directory of the programs to run.
+const EXERCISERS: &str = "Z80_EXERCISERS";
+
+/// Runs the CP/M program `program`, printing what it prints as it goes, and returns it, or the
+/// error that stopped it.
+fn run(program: &[u8]) -> String {
+    let mut machine = match Machine::<Cpu>::new(program) {
+        Ok(machine) => machine,
+        Err(error) => return format!("ERROR: {}", error),
+    };
+    let mut output = String::new();
+    loop {
+        let running = machine.step();
+        let text = machine.take_output();
+        if !text.is_empty() {
+            print!("{}", text);
+            io::stdout().flush().unwrap();
+            output.push_str(&text);
+        }
+        match running {
+            Ok(true) => {}
+            Ok(false) => return output,
+            Err(error) => return output + &format!("ERROR: {}", error),
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs $Z80_EXERCISERS"]
+fn exercisers() {
+    let dir = env::var_os(EXERCISERS).unwrap_or_else(|| panic!("{} is not set", EXERCISERS));
+    let mut programs: Vec<_> = fs::read_dir(Path::new(&dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("com"))
+        })
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "no .COM files");
+
+    let mut failures = Vec::new();
+    for path in &programs {
+        println!("{}:", path.display());
+        let output = run(&fs::read(path).unwrap());
+        println!();
+        if output.contains("ERROR") || !output.contains("Tests complete") {
+            failures.push(path.display().to_string());
+        }
+    }
+    assert!(failures.is_empty(), "failed: {}", failures.join(", "));
+}